@@ -0,0 +1,298 @@
+mod music;
+mod spatial;
+
+use crate::cg_support::node;
+use cgmath::*;
+use rodio::Source;
+use spatial::Attenuation;
+pub use spatial::Listener;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::BufReader;
+use std::rc::{Rc, Weak};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// A sound effect decoded into memory, which can be cheaply cloned to play
+/// it many times at once
+type SoundClip = rodio::source::Buffered<rodio::Decoder<BufReader<File>>>;
+
+/// How often the channel volumes of a playing sound are updated from its
+/// position relative to the listener
+const GAIN_UPDATE_PERIOD: Duration = Duration::from_millis(10);
+
+/// Where a sound is played from
+enum SoundPosition {
+    Fixed(Point3<f64>),
+    /// Follows a node. The sound is stopped once the node is dropped
+    Attached(Weak<RefCell<node::Node>>, Point3<f64>),
+}
+
+/// A positional sound source
+///
+/// A sound emitter only describes how and where a sound is played. Sounds
+/// are started with `AudioEngine::play`
+pub struct SoundEmitter {
+    position: SoundPosition,
+    attenuation: Attenuation,
+    volume: f32,
+    looping: bool,
+}
+
+impl SoundEmitter {
+    /// Creates a sound emitter which follows `attachment`
+    pub fn new(attachment: &Rc<RefCell<node::Node>>) -> Self {
+        Self {
+            position: SoundPosition::Attached(
+                Rc::downgrade(attachment),
+                Point3::origin(),
+            ),
+            attenuation: Attenuation::default(),
+            volume: 1.,
+            looping: false,
+        }
+    }
+
+    /// Creates a sound emitter at a fixed position in world space
+    pub fn at(pos: Point3<f64>) -> Self {
+        Self {
+            position: SoundPosition::Fixed(pos),
+            attenuation: Attenuation::default(),
+            volume: 1.,
+            looping: false,
+        }
+    }
+
+    /// Sets the point, relative to the attachment, the sound is played from.
+    /// Does nothing for sounds at a fixed position
+    #[must_use]
+    #[allow(dead_code)]
+    pub fn offset(mut self, offset: Point3<f64>) -> Self {
+        if let SoundPosition::Attached(_, pos) = &mut self.position {
+            *pos = offset;
+        }
+        self
+    }
+
+    /// Sets the distance the sound is played at full volume within, and the
+    /// distance it can no longer be heard beyond
+    #[must_use]
+    pub const fn range(mut self, ref_dist: f64, max_dist: f64) -> Self {
+        self.attenuation.ref_dist = ref_dist;
+        self.attenuation.max_dist = max_dist;
+        self
+    }
+
+    /// Sets the volume of the sound at the reference distance
+    #[must_use]
+    pub const fn volume(mut self, volume: f32) -> Self {
+        self.volume = volume;
+        self
+    }
+
+    /// Repeats the sound until its attachment is dropped
+    #[must_use]
+    #[allow(dead_code)]
+    pub const fn looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    /// Gets the world space position of the sound, or `None` if the node it
+    /// is attached to no longer exists
+    fn pos(&self) -> Option<Point3<f64>> {
+        match &self.position {
+            SoundPosition::Fixed(pos) => Some(*pos),
+            SoundPosition::Attached(node, offset) => node
+                .upgrade()
+                .map(|node| node.borrow().mat().transform_point(*offset)),
+        }
+    }
+
+    /// Gets the left and right channel volumes of the sound as heard by
+    /// `listener`
+    fn gains(&self, listener: &Listener) -> Option<[f32; 2]> {
+        self.pos().map(|pos| {
+            let [l, r] =
+                spatial::stereo_gains(listener, pos, &self.attenuation);
+            [l * self.volume, r * self.volume]
+        })
+    }
+}
+
+/// A sound that is currently playing
+struct PlayingSound {
+    emitter: SoundEmitter,
+    sink: rodio::Sink,
+    /// Channel volumes shared with the audio thread
+    gains: Arc<Mutex<[f32; 2]>>,
+}
+
+/// Wraps `source` so that it's mixed down to stereo with the channel volumes
+/// in `gains`, which can be changed while the source plays
+fn spatial_source<S>(
+    source: S,
+    gains: Arc<Mutex<[f32; 2]>>,
+) -> impl Source<Item = S::Item> + Send
+where
+    S: Source + Send + 'static,
+    S::Item: rodio::Sample + Send,
+{
+    let initial = gains.lock().map_or([0., 0.], |g| *g);
+    rodio::source::ChannelVolume::new(source, initial.to_vec()).periodic_access(
+        GAIN_UPDATE_PERIOD,
+        move |src| {
+            if let Ok(gains) = gains.lock() {
+                src.set_volume(0, gains[0]);
+                src.set_volume(1, gains[1]);
+            }
+        },
+    )
+}
+
+/// Plays positional sound effects and background music
+///
+/// Sound effects are panned and attenuated according to their position
+/// relative to the listener, which should be updated every frame with
+/// `update`
+pub struct AudioEngine {
+    _stream: rodio::OutputStream,
+    handle: rodio::OutputStreamHandle,
+    /// Decoded sound effects keyed by path. `None` if the sound failed to
+    /// load, so that the failure is only reported once
+    clips: HashMap<String, Option<SoundClip>>,
+    sounds: Vec<PlayingSound>,
+    music: music::MusicPlayer,
+    listener: Listener,
+    /// Maximum amount of sound effects that can play at once
+    max_sounds: usize,
+    /// Speed, and pitch, sound effects are played at
+    speed: f32,
+}
+
+impl AudioEngine {
+    /// Opens the default audio output device
+    ///
+    /// # Errors
+    /// Returns an error if there is no audio device that can be played to
+    pub fn new() -> Result<Self, Box<dyn Error>> {
+        let (stream, handle) = rodio::OutputStream::try_default()?;
+        Ok(Self {
+            _stream: stream,
+            handle,
+            clips: HashMap::new(),
+            sounds: Vec::new(),
+            music: music::MusicPlayer::new(),
+            listener: Listener::default(),
+            max_sounds: 32,
+            speed: 1.,
+        })
+    }
+
+    /// Gets the sound effect at `path`, loading it if it isn't loaded
+    fn get_clip(&mut self, path: &str) -> Option<SoundClip> {
+        self.clips
+            .entry(path.to_owned())
+            .or_insert_with(|| {
+                File::open(path)
+                    .map_err(Box::<dyn Error>::from)
+                    .and_then(|file| {
+                        rodio::Decoder::new(BufReader::new(file))
+                            .map_err(Box::<dyn Error>::from)
+                    })
+                    .map(Source::buffered)
+                    .map_err(|e| {
+                        warn!("Could not load sound '{}': {}", path, e);
+                    })
+                    .ok()
+            })
+            .clone()
+    }
+
+    /// Plays the sound effect at `path` from `emitter`
+    ///
+    /// If the maximum amount of sounds are already playing, or the sound
+    /// can't be loaded, the sound isn't played
+    pub fn play(&mut self, path: &str, emitter: SoundEmitter) {
+        if self.sounds.len() >= self.max_sounds {
+            return;
+        }
+        let gains = match emitter.gains(&self.listener) {
+            Some(gains) => Arc::new(Mutex::new(gains)),
+            None => return,
+        };
+        let clip = match self.get_clip(path) {
+            Some(clip) => clip,
+            None => return,
+        };
+        let sink = match rodio::Sink::try_new(&self.handle) {
+            Ok(sink) => sink,
+            Err(e) => {
+                warn!("Could not play sound '{}': {}", path, e);
+                return;
+            }
+        };
+        sink.set_speed(self.speed);
+        if emitter.looping {
+            sink.append(spatial_source(clip.repeat_infinite(), gains.clone()));
+        } else {
+            sink.append(spatial_source(clip, gains.clone()));
+        }
+        self.sounds.push(PlayingSound {
+            emitter,
+            sink,
+            gains,
+        });
+    }
+
+    /// Sets the speed sound effects are played at, which also shifts their
+    /// pitch, so that they slow down with the game. Music is unaffected
+    pub fn set_speed(&mut self, speed: f32) {
+        if (speed - self.speed).abs() < f32::EPSILON {
+            return;
+        }
+        self.speed = speed;
+        for sound in &self.sounds {
+            sound.sink.set_speed(speed);
+        }
+    }
+
+    /// Crossfades the background music to the track at `path`, which is
+    /// streamed from disk and looped
+    ///
+    /// # Errors
+    /// Returns an error if the track can't be opened or decoded
+    pub fn play_music(&mut self, path: &str) -> Result<(), Box<dyn Error>> {
+        self.music.play(path, &self.handle)
+    }
+
+    /// Updates the position of the listener, which is used to spatialize
+    /// every playing sound. Should be called once per frame
+    ///
+    /// Sounds which have finished or whose attachment no longer exists are
+    /// removed
+    pub fn update(&mut self, listener: Listener) {
+        self.listener = listener;
+        self.sounds.retain(|sound| {
+            if sound.sink.empty() {
+                return false;
+            }
+            match sound.emitter.gains(&listener) {
+                Some(new_gains) => {
+                    if let Ok(mut gains) = sound.gains.lock() {
+                        *gains = new_gains;
+                    }
+                    true
+                }
+                None => {
+                    sound.sink.stop();
+                    false
+                }
+            }
+        });
+        self.music.update(Instant::now());
+    }
+}
@@ -0,0 +1,151 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::BufReader;
+use std::time::{Duration, Instant};
+
+/// Gets the volumes of the outgoing and incoming tracks `t` of the way
+/// through a crossfade
+///
+/// Uses an equal power crossfade so the overall loudness stays constant
+fn crossfade_gains(t: f32) -> (f32, f32) {
+    let angle = t.clamp(0., 1.) * std::f32::consts::FRAC_PI_2;
+    (angle.cos(), angle.sin())
+}
+
+/// Gets how far through a fade of length `fade` that started at `start` is
+fn fade_progress(start: Instant, fade: Duration, now: Instant) -> f32 {
+    if fade.is_zero() {
+        1.
+    } else {
+        (now.saturating_duration_since(start).as_secs_f32()
+            / fade.as_secs_f32())
+        .min(1.)
+    }
+}
+
+/// A music track which is streamed from disk
+struct Track {
+    sink: rodio::Sink,
+    fade_in_start: Instant,
+    fade_out_start: Option<Instant>,
+}
+
+/// Plays looping background music, crossfading between tracks
+pub struct MusicPlayer {
+    tracks: Vec<Track>,
+    fade: Duration,
+    volume: f32,
+}
+
+impl MusicPlayer {
+    pub fn new() -> Self {
+        Self {
+            tracks: Vec::new(),
+            fade: Duration::from_secs(3),
+            volume: 0.5,
+        }
+    }
+
+    /// Sets the length of the crossfade between tracks
+    #[allow(dead_code)]
+    pub fn set_fade(&mut self, fade: Duration) {
+        self.fade = fade;
+    }
+
+    /// Sets the volume of the music
+    #[allow(dead_code)]
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume;
+    }
+
+    /// Starts looping the track at `path`, fading out the current track
+    ///
+    /// # Errors
+    /// Returns an error if the track could not be opened or decoded, in which
+    /// case the current track keeps playing
+    pub fn play(
+        &mut self,
+        path: &str,
+        handle: &rodio::OutputStreamHandle,
+    ) -> Result<(), Box<dyn Error>> {
+        let source =
+            rodio::Decoder::new_looped(BufReader::new(File::open(path)?))?;
+        let sink = rodio::Sink::try_new(handle)?;
+        sink.set_volume(0.);
+        sink.append(source);
+        let now = Instant::now();
+        for track in &mut self.tracks {
+            track.fade_out_start.get_or_insert(now);
+        }
+        self.tracks.push(Track {
+            sink,
+            fade_in_start: now,
+            fade_out_start: None,
+        });
+        Ok(())
+    }
+
+    /// Fades out the current track
+    #[allow(dead_code)]
+    pub fn stop(&mut self) {
+        let now = Instant::now();
+        for track in &mut self.tracks {
+            track.fade_out_start.get_or_insert(now);
+        }
+    }
+
+    /// Updates the volumes of fading tracks and stops the tracks that have
+    /// finished fading out
+    pub fn update(&mut self, now: Instant) {
+        let (fade, volume) = (self.fade, self.volume);
+        self.tracks.retain(|track| {
+            let fade_in =
+                crossfade_gains(fade_progress(track.fade_in_start, fade, now))
+                    .1;
+            let fade_out = track.fade_out_start.map_or(1., |start| {
+                crossfade_gains(fade_progress(start, fade, now)).0
+            });
+            if fade_out <= f32::EPSILON {
+                track.sink.stop();
+                false
+            } else {
+                track.sink.set_volume(volume * fade_in * fade_out);
+                true
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn crossfade_keeps_constant_power() {
+        for i in 0..=10u8 {
+            let (out, inc) = crossfade_gains(f32::from(i) / 10.);
+            assert_approx_eq!(out * out + inc * inc, 1.);
+        }
+        assert_eq!(crossfade_gains(0.), (1., 0.));
+        let (out, inc) = crossfade_gains(2.);
+        assert_approx_eq!(out, 0.);
+        assert_approx_eq!(inc, 1.);
+    }
+
+    #[test]
+    fn fade_progress_is_clamped() {
+        let start = Instant::now();
+        let fade = Duration::from_secs(2);
+        assert_approx_eq!(fade_progress(start, fade, start), 0.);
+        assert_approx_eq!(
+            fade_progress(start, fade, start + Duration::from_secs(1)),
+            0.5
+        );
+        assert_approx_eq!(
+            fade_progress(start, fade, start + Duration::from_secs(5)),
+            1.
+        );
+        assert_approx_eq!(fade_progress(start, Duration::ZERO, start), 1.);
+    }
+}
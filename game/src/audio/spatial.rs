@@ -0,0 +1,153 @@
+use cgmath::*;
+
+/// The point sounds are heard from, which is usually the player camera
+#[derive(Copy, Clone, Debug)]
+pub struct Listener {
+    pub pos: Point3<f64>,
+    /// Direction pointing out of the listener's right ear
+    pub right: Vector3<f64>,
+}
+
+impl Listener {
+    /// Creates a listener from a camera's view matrix
+    pub fn from_view(view: Matrix4<f32>) -> Self {
+        let view: Matrix4<f64> = view.cast().unwrap();
+        // the first row of the view matrix is the camera's right vector in
+        // world space
+        let right = vec3(view.x.x, view.y.x, view.z.x);
+        let pos = view.invert().map_or_else(Point3::origin, |inv| {
+            inv.transform_point(Point3::origin())
+        });
+        Self { pos, right }
+    }
+}
+
+impl Default for Listener {
+    fn default() -> Self {
+        Self {
+            pos: Point3::origin(),
+            right: vec3(1., 0., 0.),
+        }
+    }
+}
+
+/// How the volume of a sound decreases with its distance from the listener
+#[derive(Copy, Clone, Debug)]
+pub struct Attenuation {
+    /// Distance the sound is played at full volume within
+    pub ref_dist: f64,
+    /// Distance the sound is inaudible beyond
+    pub max_dist: f64,
+    /// How quickly the volume falls off past the reference distance
+    pub rolloff: f64,
+}
+
+impl Default for Attenuation {
+    fn default() -> Self {
+        Self {
+            ref_dist: 10.,
+            max_dist: 800.,
+            rolloff: 1.,
+        }
+    }
+}
+
+impl Attenuation {
+    /// Gets the volume multiplier of a sound `dist` away from the listener
+    ///
+    /// The volume falls off with the inverse of the distance, and is faded
+    /// to zero by the maximum distance so that sounds don't suddenly cut out
+    pub fn gain(&self, dist: f64) -> f32 {
+        if dist <= self.ref_dist {
+            1.
+        } else if dist >= self.max_dist {
+            0.
+        } else {
+            let inverse = self.ref_dist
+                / self.rolloff.mul_add(dist - self.ref_dist, self.ref_dist);
+            let fade =
+                1. - (dist - self.ref_dist) / (self.max_dist - self.ref_dist);
+            (inverse * fade) as f32
+        }
+    }
+}
+
+/// Gets the left and right channel volumes of a sound at `pos`
+///
+/// Sounds are panned with a constant power pan law so a sound moving around
+/// the listener doesn't change in loudness
+pub fn stereo_gains(
+    listener: &Listener,
+    pos: Point3<f64>,
+    attenuation: &Attenuation,
+) -> [f32; 2] {
+    let to_sound = pos - listener.pos;
+    let dist = to_sound.magnitude();
+    let pan =
+        if dist > f64::EPSILON && listener.right.magnitude2() > f64::EPSILON {
+            to_sound.dot(listener.right.normalize()) / dist
+        } else {
+            0.
+        };
+    let angle = (pan.clamp(-1., 1.) + 1.) * std::f64::consts::FRAC_PI_4;
+    let gain = attenuation.gain(dist);
+    [angle.cos() as f32 * gain, angle.sin() as f32 * gain]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn attenuation_falls_off_with_distance() {
+        let atten = Attenuation {
+            ref_dist: 10.,
+            max_dist: 110.,
+            rolloff: 1.,
+        };
+        assert_approx_eq!(atten.gain(0.), 1.);
+        assert_approx_eq!(atten.gain(10.), 1.);
+        assert_approx_eq!(atten.gain(60.), 0.5 / 6.);
+        assert_approx_eq!(atten.gain(110.), 0.);
+        assert_approx_eq!(atten.gain(1000.), 0.);
+        assert!(atten.gain(20.) > atten.gain(30.));
+    }
+
+    #[test]
+    fn sounds_are_panned_towards_their_side() {
+        let listener = Listener::default();
+        let atten = Attenuation::default();
+        let [l, r] = stereo_gains(&listener, point3(5., 0., 0.), &atten);
+        assert_approx_eq!(l, 0.);
+        assert_approx_eq!(r, 1.);
+        let [l, r] = stereo_gains(&listener, point3(-5., 0., 0.), &atten);
+        assert_approx_eq!(l, 1.);
+        assert_approx_eq!(r, 0.);
+        let [l, r] = stereo_gains(&listener, point3(0., 0., 5.), &atten);
+        assert_approx_eq!(l, r);
+        assert_approx_eq!(l * l + r * r, 1.);
+        // a sound on top of the listener is centered
+        let [l, r] = stereo_gains(&listener, listener.pos, &atten);
+        assert_approx_eq!(l, r);
+    }
+
+    #[test]
+    fn listener_from_view_matrix() {
+        let view = Matrix4::look_at_rh(
+            point3(0f32, 0., 10.),
+            point3(0., 0., 0.),
+            vec3(0., 1., 0.),
+        );
+        let listener = Listener::from_view(view);
+        assert_approx_eq!(listener.pos.z, 10.);
+        assert_approx_eq!(listener.right.x, 1.);
+        let view = Matrix4::look_at_rh(
+            point3(0f32, 0., -10.),
+            point3(0., 0., 0.),
+            vec3(0., 1., 0.),
+        );
+        let listener = Listener::from_view(view);
+        assert_approx_eq!(listener.right.x, -1.);
+    }
+}
@@ -0,0 +1,294 @@
+use crate::cg_support::node::{self, Node};
+use crate::controls::{self, MovementControl, PlayerIteratorHolder};
+use crate::{collisions, game, physics};
+use cgmath::*;
+use shared_types::game_controller::{GameController, RemoteGameController};
+use shared_types::id_list::IdList;
+use shared_types::{HitClaim, ObjectId, ObjectType, RemoteObject};
+use std::cell::RefCell;
+use std::error::Error;
+use std::net::IpAddr;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+
+/// Port the server listens on by default
+pub const DEFAULT_SERVER_PORT: u16 = 33200;
+/// Time between the updates of a bot
+const TICK: Duration = Duration::from_millis(16);
+/// Shortest time between two shots of a bot
+const FIRE_INTERVAL: Duration = Duration::from_millis(250);
+/// Time a laser flies for before it's removed
+const LASER_LIFETIME: Duration = Duration::from_secs(3);
+/// Distance from the center of a ship that a laser hits it within
+const HIT_RADIUS: f64 = 10.;
+/// Half the width of the region the bots' AI plans in
+const SCENE_SIZE: f64 = 2000.;
+/// Time between bots connecting, so the server isn't flooded with logins
+const CONNECT_STAGGER: Duration = Duration::from_millis(50);
+
+/// The settings of a group of bots run from one process
+#[derive(Clone, Debug)]
+pub struct BotConfig {
+    pub count: usize,
+    pub server: (IpAddr, u16),
+    /// Prefix of the bots' usernames
+    pub name: String,
+    /// How long the bots play for, or `None` to play until the process is
+    /// killed
+    pub duration: Option<Duration>,
+}
+
+/// A laser in flight which was fired by a bot
+struct Laser {
+    id: ObjectId,
+    node: Node,
+    velocity: Vector3<f64>,
+    fired: Instant,
+}
+
+/// A headless player which is flown by the AI and plays on a server, for load
+/// and soak testing the server
+///
+/// The bot's ship moves by its velocity without collisions, since the scene's
+/// meshes aren't loaded. Its lasers hit the ships they pass close to, which
+/// the bot claims as hits
+pub struct Bot {
+    controller: RemoteGameController,
+    ai: Rc<RefCell<dyn MovementControl>>,
+    body: physics::RigidBody<()>,
+    scene: collisions::CollisionTree,
+    ids: IdList,
+    lasers: Vec<Laser>,
+    last_shot: Option<Instant>,
+}
+
+impl Bot {
+    /// Connects a bot named `name` which behaves according to `profile` to
+    /// the server at `server`
+    ///
+    /// # Errors
+    /// Fails if the bot cannot connect to the server
+    pub fn connect(
+        name: &str,
+        server: (IpAddr, u16),
+        profile: controls::AiProfile,
+    ) -> Result<Self, Box<dyn Error>> {
+        let controller = RemoteGameController::new(name, server)?;
+        let spawn_pos = controller.get_player_stats().spawn_pos;
+        let body = physics::RigidBody::new(
+            Rc::new(RefCell::new(Node::default().pos(spawn_pos))),
+            None,
+            physics::BodyType::Controlled,
+            (),
+        );
+        Ok(Self {
+            controller,
+            ai: controls::get_ai_controller(profile),
+            body,
+            scene: collisions::CollisionTree::new(spawn_pos, SCENE_SIZE),
+            ids: IdList::new(),
+            lasers: Vec::new(),
+            last_shot: None,
+        })
+    }
+
+    fn pid(&self) -> ObjectId {
+        self.controller.get_player_stats().pid
+    }
+
+    fn forward(&self) -> Vector3<f64> {
+        self.body
+            .base
+            .transform
+            .borrow()
+            .transform_vec(vec3(0., 0., 1.))
+    }
+
+    /// Gets the ids and positions of the other ships the server sent
+    fn other_ships(&self) -> Vec<(ObjectId, Rc<RefCell<Node>>)> {
+        let pid = self.pid();
+        self.controller
+            .server_objects()
+            .iter()
+            .filter(|obj| obj.typ == ObjectType::Ship && obj.id != pid)
+            .map(|obj| {
+                let (node, ..) = node::from_remote_object(obj);
+                (obj.id, Rc::new(RefCell::new(node)))
+            })
+            .collect()
+    }
+
+    /// Fires a laser if the bot can fire again and has an id for it
+    fn fire(&mut self, now: Instant) {
+        if self
+            .last_shot
+            .map_or(false, |last| now.duration_since(last) < FIRE_INTERVAL)
+        {
+            return;
+        }
+        if let Some(id) = self.ids.next_id() {
+            let mut node = self
+                .body
+                .base
+                .transform
+                .borrow()
+                .clone()
+                .scale(vec3(0.3, 0.3, 1.));
+            node.translate(self.forward() * game::MUZZLE_DIST);
+            self.lasers.push(Laser {
+                id,
+                node,
+                velocity: self.forward() * game::LASER_SPEED,
+                fired: now,
+            });
+            self.last_shot = Some(now);
+        }
+    }
+
+    /// Moves the lasers, claiming a hit for each laser that passes close to
+    /// one of `ships` and removing the lasers that hit or expired
+    fn update_lasers(
+        &mut self,
+        now: Instant,
+        dt: Duration,
+        ships: &[(ObjectId, Rc<RefCell<Node>>)],
+    ) {
+        let pid = self.pid();
+        let mut claims = Vec::new();
+        self.lasers.retain_mut(|laser| {
+            laser.node.translate(laser.velocity * dt.as_secs_f64());
+            let pos = laser.node.get_pos();
+            let target = ships.iter().find(|(_, ship)| {
+                ship.borrow().get_pos().distance(pos) < HIT_RADIUS
+            });
+            if let Some((target, _)) = target {
+                claims.push(HitClaim {
+                    shooter: pid,
+                    target: *target,
+                    hit_point: pos.into(),
+                });
+                return false;
+            }
+            now.duration_since(laser.fired) < LASER_LIFETIME
+        });
+        for claim in claims {
+            self.controller.claim_hit(claim);
+        }
+    }
+
+    /// Gets the ship and lasers of the bot to send to the server
+    fn objects(&self) -> Vec<RemoteObject> {
+        let ship = node::to_remote_object(
+            &*self.body.base.transform.borrow(),
+            &self.body.base.velocity,
+            &self.body.base.rot_vel,
+            ObjectType::Ship,
+            self.pid(),
+        );
+        std::iter::once(ship)
+            .chain(self.lasers.iter().map(|laser| {
+                node::to_remote_object(
+                    &laser.node,
+                    &laser.velocity,
+                    &vec3(0., 0., 0.),
+                    ObjectType::Laser,
+                    laser.id,
+                )
+            }))
+            .collect()
+    }
+
+    /// Lets the AI fly and fire the ship for `dt`, and syncs with the server
+    pub fn tick(&mut self, dt: Duration) {
+        let now = Instant::now();
+        self.controller.refill_ids(&mut self.ids);
+        let ships = self.other_ships();
+        let others: Vec<_> =
+            ships.iter().map(|(_, ship)| ship.clone()).collect();
+        let action = self.ai.borrow_mut().on_frame_update(
+            &self.scene,
+            &self.body.base,
+            dt,
+            &PlayerIteratorHolder(others.into_iter()),
+        );
+        if let Some(rot) = self.ai.borrow().get_snapped_rot() {
+            self.body.base.transform.borrow_mut().set_rot(rot.into());
+        }
+        if let Some(action) = action {
+            self.body.base.velocity = action.velocity;
+            if action.fire {
+                self.fire(now);
+            }
+        }
+        let velocity = self.body.base.velocity;
+        self.body
+            .base
+            .transform
+            .borrow_mut()
+            .translate(velocity * dt.as_secs_f64());
+        self.update_lasers(now, dt, &ships);
+        // bots don't apply damage, so confirmed hits are only load
+        self.controller.take_confirmed_hits();
+        self.controller.set_objects(&self.objects());
+        self.controller.sync();
+    }
+
+    /// Plays until `deadline`, or forever if there is none
+    pub fn run(&mut self, deadline: Option<Instant>) {
+        let mut last_tick = Instant::now();
+        while deadline.map_or(true, |deadline| Instant::now() < deadline) {
+            let now = Instant::now();
+            self.tick(now - last_tick);
+            last_tick = now;
+            if let Some(rest) = TICK.checked_sub(last_tick.elapsed()) {
+                std::thread::sleep(rest);
+            }
+        }
+    }
+
+    /// Gets a summary of the bot's connection to the server
+    #[must_use]
+    pub fn net_summary(&self) -> Option<String> {
+        self.controller
+            .get_net_stats()
+            .map(|summary| summary.to_string())
+    }
+}
+
+/// Runs `config.count` bots, each on its own thread, until their time is up.
+/// The bots cycle through the AI profiles, so the server sees a mix of
+/// behaviors
+pub fn run_bots(config: &BotConfig) {
+    let deadline = config.duration.map(|duration| Instant::now() + duration);
+    let profiles = [
+        controls::AiProfile::easy(),
+        controls::AiProfile::normal(),
+        controls::AiProfile::ace(),
+    ];
+    let bots: Vec<_> = (0..config.count)
+        .map(|i| {
+            let server = config.server;
+            let prefix = config.name.clone();
+            let profile = profiles[i % profiles.len()];
+            std::thread::spawn(move || {
+                std::thread::sleep(CONNECT_STAGGER * i as u32);
+                let name = format!("{}-bot{}", prefix, i);
+                match Bot::connect(&name, server, profile) {
+                    Ok(mut bot) => {
+                        bot.run(deadline);
+                        if let Some(summary) = bot.net_summary() {
+                            info!("{}: {}", name, summary);
+                        }
+                    }
+                    Err(e) => warn!("{} could not connect: {}", name, e),
+                }
+            })
+        })
+        .collect();
+    for bot in bots {
+        if bot.join().is_err() {
+            error!("A bot panicked");
+        }
+    }
+}
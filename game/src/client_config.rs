@@ -0,0 +1,690 @@
+use crate::bot;
+use crate::controls::{Action, Button, InputMap};
+use crate::graphics_engine::capture::{VideoOutput, VideoSettings};
+use crate::localization;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt::Display;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Default location of the client configuration file
+pub const CLIENT_CONFIG_PATH: &str = "client.toml";
+
+/// Window sizes that can be chosen in the settings menu
+pub const RESOLUTIONS: [(u32, u32); 5] = [
+    (1280, 720),
+    (1600, 900),
+    (1920, 1080),
+    (2560, 1440),
+    (3840, 2160),
+];
+
+/// Gets the resolution after `width` by `height` in `RESOLUTIONS`, wrapping
+/// around to the first. Sizes which aren't in the list are followed by the
+/// first larger resolution
+pub fn next_resolution(width: u32, height: u32) -> (u32, u32) {
+    RESOLUTIONS
+        .iter()
+        .find(|(w, h)| (*w, *h) > (width, height))
+        .copied()
+        .unwrap_or(RESOLUTIONS[0])
+}
+
+/// Configuration of the game client, loaded from a TOML file and overridden
+/// by command line arguments
+#[derive(Clone, PartialEq, Debug)]
+pub struct ClientConfig {
+    /// Width of the window and the main render targets
+    pub width: u32,
+    /// Height of the window and the main render targets
+    pub height: u32,
+    pub fullscreen: bool,
+    pub vsync: bool,
+    /// Address of the server that multiplayer clients connect to
+    pub server: SocketAddr,
+    /// Username sent to the server
+    pub player_name: String,
+    /// Language of the UI text, which is the name of a file in the language
+    /// directory
+    pub language: String,
+    /// Seconds of frames a recording captures
+    pub video_seconds: u32,
+    /// Frames per second of recordings
+    pub video_fps: u32,
+    /// Command line of the encoder recorded frames are piped to, or empty to
+    /// save them as an image sequence. See `VideoOutput::Encoder`
+    pub video_encoder: String,
+    /// Levels logged for each module, such as `warn,oort_client::bot=debug`.
+    /// See `logging::LogFilter::set`
+    pub log_filter: String,
+    /// Shows recent warnings and errors on the HUD
+    pub log_overlay: bool,
+    /// Keys and mouse buttons bound to each action. Actions which aren't
+    /// listed keep their default buttons
+    pub bindings: Vec<(Action, Vec<Button>)>,
+    /// Modes chosen on the command line
+    pub launch: LaunchOptions,
+}
+
+/// Options chosen on the command line for a single run of the game, which
+/// aren't saved to the config file
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct LaunchOptions {
+    /// Sends waves of enemies at the player instead of playing the mission.
+    /// Set by `--survival`
+    pub survival: bool,
+    /// Adds player 2, who plays against player 1 with the gamepad on the
+    /// right half of the screen. Set by `--split-screen`
+    pub split_screen: bool,
+    /// Turns the profiler on from the start. Set by `--profile`
+    pub profile: bool,
+    /// The map to play instead of the asteroid map. Set by `--seed <seed>`
+    /// or `--map <name>`
+    pub map: Option<MapChoice>,
+    /// Number of extra laser lights the light culling is benchmarked with.
+    /// Set by `--light-benchmark <count>`
+    pub light_benchmark: usize,
+}
+
+/// A map chosen on the command line
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum MapChoice {
+    /// The procedural map generated from the seed
+    Procedural(u64),
+    /// The map file with the name
+    File(String),
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        let input_map = InputMap::default();
+        let bindings = Action::ALL
+            .iter()
+            .map(|action| {
+                let mut buttons: Vec<_> = input_map
+                    .buttons_for(*action)
+                    .filter(|button| !matches!(button, Button::Gamepad(_)))
+                    .collect();
+                buttons.sort_by_key(ToString::to_string);
+                (*action, buttons)
+            })
+            .collect();
+        Self {
+            width: 1920,
+            height: 1080,
+            fullscreen: false,
+            vsync: true,
+            server: (Ipv4Addr::LOCALHOST, bot::DEFAULT_SERVER_PORT).into(),
+            player_name: "player".to_owned(),
+            language: localization::DEFAULT_LANGUAGE.to_owned(),
+            video_seconds: 10,
+            video_fps: 30,
+            video_encoder: String::new(),
+            log_filter: "info".to_owned(),
+            log_overlay: true,
+            bindings,
+            launch: LaunchOptions::default(),
+        }
+    }
+}
+
+/// Parses a quoted TOML string. Escape sequences aren't supported
+fn parse_string(val: &str) -> Result<&str, String> {
+    val.strip_prefix('"')
+        .and_then(|val| val.strip_suffix('"'))
+        .filter(|val| !val.contains('"') && !val.contains('\\'))
+        .ok_or_else(|| format!("Invalid string: {}", val))
+}
+
+/// Parses a TOML array of button names, such as `["W", "Up"]`
+fn parse_buttons(val: &str) -> Result<Vec<Button>, String> {
+    val.strip_prefix('[')
+        .and_then(|val| val.strip_suffix(']'))
+        .ok_or_else(|| format!("Invalid list of buttons: {}", val))?
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| parse_string(name).and_then(Button::try_from))
+        .collect()
+}
+
+impl ClientConfig {
+    /// Parses a configuration from the contents of a config file
+    ///
+    /// The file is a subset of TOML: `[window]`, `[network]`, `[interface]`,
+    /// `[capture]`, `[logging]` and `[controls]` tables of `key = value`
+    /// lines, where values are integers, booleans, strings without escapes,
+    /// or arrays of strings. Empty lines and lines beginning with `#` are ignored. Missing
+    /// keys keep their defaults
+    ///
+    /// # Errors
+    /// Fails if there is an unknown table or key, a line is malformed, or a
+    /// value cannot be parsed
+    pub fn parse(config: &str) -> Result<Self, Box<dyn Error>> {
+        let mut result = Self::default();
+        let mut table = "";
+        for line in config
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        {
+            if let Some(name) =
+                line.strip_prefix('[').and_then(|l| l.strip_suffix(']'))
+            {
+                table = name.trim();
+                if ![
+                    "window",
+                    "network",
+                    "interface",
+                    "capture",
+                    "logging",
+                    "controls",
+                ]
+                .contains(&table)
+                {
+                    return Err(format!("Unknown table [{}]", table).into());
+                }
+                continue;
+            }
+            let (key, val) = line
+                .split_once('=')
+                .map(|(k, v)| (k.trim(), v.trim()))
+                .ok_or_else(|| format!("Malformed setting: \"{}\"", line))?;
+            match (table, key) {
+                ("window", "width") => result.width = val.parse()?,
+                ("window", "height") => result.height = val.parse()?,
+                ("window", "fullscreen") => result.fullscreen = val.parse()?,
+                ("window", "vsync") => result.vsync = val.parse()?,
+                ("network", "server") => {
+                    result.server = parse_string(val)?.parse()?;
+                }
+                ("network", "player_name") => {
+                    result.player_name = parse_string(val)?.to_owned();
+                }
+                ("interface", "language") => {
+                    result.language = parse_string(val)?.to_owned();
+                }
+                ("capture", "video_seconds") => {
+                    result.video_seconds = val.parse()?;
+                }
+                ("capture", "video_fps") => result.video_fps = val.parse()?,
+                ("capture", "video_encoder") => {
+                    result.video_encoder = parse_string(val)?.to_owned();
+                }
+                ("logging", "filter") => {
+                    result.log_filter = parse_string(val)?.to_owned();
+                }
+                ("logging", "overlay") => result.log_overlay = val.parse()?,
+                ("controls", action) => result.set_buttons(
+                    Action::try_from(action)?,
+                    parse_buttons(val)?,
+                ),
+                _ => {
+                    return Err(format!(
+                        "Unknown setting \"{}\" in [{}]",
+                        key, table
+                    )
+                    .into())
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Loads the configuration from the file at `path`
+    ///
+    /// # Errors
+    /// Fails if the file cannot be read or parsed
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn Error>> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Loads the configuration from `path`. If the file does not exist, the
+    /// default configuration is written to it so it can be edited. If it
+    /// cannot be read or parsed, the defaults are used
+    pub fn load_or_default(path: &str) -> Self {
+        if !std::path::Path::new(path).exists() {
+            let config = Self::default();
+            match config.save(path) {
+                Ok(()) => {
+                    info!("Wrote default client configuration to {}", path)
+                }
+                Err(e) => warn!("Could not write {}: {}", path, e),
+            }
+            return config;
+        }
+        Self::from_file(path).unwrap_or_else(|e| {
+            warn!("Using default client configuration: {}", e);
+            Self::default()
+        })
+    }
+
+    /// Saves the configuration to `path`
+    ///
+    /// # Errors
+    /// Fails if the file cannot be written
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        std::fs::write(path, self.to_string())
+    }
+
+    /// Binds `buttons` to `action` instead of the action's default buttons
+    pub fn set_buttons(&mut self, action: Action, buttons: Vec<Button>) {
+        match self.bindings.iter_mut().find(|(a, _)| *a == action) {
+            Some((_, old)) => *old = buttons,
+            None => self.bindings.push((action, buttons)),
+        }
+    }
+
+    /// Gets how recordings are captured
+    pub fn video_settings(&self) -> VideoSettings {
+        VideoSettings {
+            duration: Duration::from_secs(self.video_seconds.into()),
+            fps: self.video_fps,
+            output: if self.video_encoder.trim().is_empty() {
+                VideoOutput::ImageSequence
+            } else {
+                VideoOutput::Encoder(self.video_encoder.clone())
+            },
+        }
+    }
+
+    /// Gets the default input map with the configured key and mouse
+    /// bindings. Gamepad bindings are unchanged
+    pub fn input_map(&self) -> InputMap {
+        let mut map = InputMap::default();
+        for (action, buttons) in &self.bindings {
+            map.rebind(*action, buttons);
+        }
+        map
+    }
+}
+
+impl Display for ClientConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "# Client configuration\n\
+            [window]\n\
+            width = {}\n\
+            height = {}\n\
+            fullscreen = {}\n\
+            vsync = {}\n\
+            \n\
+            [network]\n\
+            server = \"{}\"\n\
+            player_name = \"{}\"\n\
+            \n\
+            [interface]\n\
+            language = \"{}\"\n\
+            \n\
+            # Recordings are saved as pngs, unless an encoder such as\n\
+            # \"ffmpeg -y -f rawvideo -pix_fmt rgba -s {{width}}x{{height}} \
+            -r {{fps}} -i - capture.mp4\"\n\
+            # is given to pipe the frames to\n\
+            [capture]\n\
+            video_seconds = {}\n\
+            video_fps = {}\n\
+            video_encoder = \"{}\"\n\
+            \n\
+            # Levels logged for each module, such as\n\
+            # \"warn,oort_client::bot=debug\". RUST_LOG takes precedence\n\
+            [logging]\n\
+            filter = \"{}\"\n\
+            overlay = {}\n\
+            \n\
+            # Keys and mouse buttons bound to each action\n\
+            [controls]\n",
+            self.width,
+            self.height,
+            self.fullscreen,
+            self.vsync,
+            self.server,
+            self.player_name,
+            self.language,
+            self.video_seconds,
+            self.video_fps,
+            self.video_encoder,
+            self.log_filter,
+            self.log_overlay
+        )?;
+        for (action, buttons) in &self.bindings {
+            let names: Vec<_> =
+                buttons.iter().map(|b| format!("\"{}\"", b)).collect();
+            writeln!(f, "{} = [{}]", action, names.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Arguments which take a value and are parsed by the game itself
+const MAIN_ARGS: [&str; 4] = ["--record", "--replay", "--bots", "--duration"];
+
+fn parse_args_helper<I: Iterator<Item = String>>(
+    mut args: I,
+    mut config: ClientConfig,
+) -> Result<ClientConfig, Box<dyn Error>> {
+    match args.next() {
+        None => Ok(config),
+        Some(x) if x == "--width" => {
+            let width = args.next().ok_or("--width requires an argument")?;
+            config.width = width.parse::<u32>()?;
+            parse_args_helper(args, config)
+        }
+        Some(x) if x == "--height" => {
+            let height = args.next().ok_or("--height requires an argument")?;
+            config.height = height.parse::<u32>()?;
+            parse_args_helper(args, config)
+        }
+        Some(x) if x == "--fullscreen" => {
+            config.fullscreen = true;
+            parse_args_helper(args, config)
+        }
+        Some(x) if x == "--windowed" => {
+            config.fullscreen = false;
+            parse_args_helper(args, config)
+        }
+        Some(x) if x == "--vsync" => {
+            config.vsync = true;
+            parse_args_helper(args, config)
+        }
+        Some(x) if x == "--no-vsync" => {
+            config.vsync = false;
+            parse_args_helper(args, config)
+        }
+        Some(x) if x == "--server" => {
+            let addr = args.next().ok_or("--server requires an argument")?;
+            config.server = addr.parse::<SocketAddr>()?;
+            parse_args_helper(args, config)
+        }
+        Some(x) if x == "--name" => {
+            let name = args.next().ok_or("--name requires an argument")?;
+            if name.is_empty() || name.contains('"') || name.contains('\\') {
+                return Err(format!("Invalid player name \"{}\"", name).into());
+            }
+            config.player_name = name;
+            parse_args_helper(args, config)
+        }
+        Some(x) if x == "--language" => {
+            let language =
+                args.next().ok_or("--language requires an argument")?;
+            if language.is_empty() || language.contains(['"', '\\', '/']) {
+                return Err(format!("Invalid language \"{}\"", language).into());
+            }
+            config.language = language;
+            parse_args_helper(args, config)
+        }
+        Some(x) if x == "--log" => {
+            let filter = args.next().ok_or("--log requires an argument")?;
+            if filter.contains(['"', '\\']) {
+                return Err(format!("Invalid log filter \"{}\"", filter).into());
+            }
+            config.log_filter = filter;
+            parse_args_helper(args, config)
+        }
+        Some(x) if x == "--config" => {
+            // the file was loaded before the other arguments were parsed
+            args.next().ok_or("--config requires an argument")?;
+            parse_args_helper(args, config)
+        }
+        Some(x) if x == "--survival" => {
+            config.launch.survival = true;
+            parse_args_helper(args, config)
+        }
+        Some(x) if x == "--split-screen" => {
+            config.launch.split_screen = true;
+            parse_args_helper(args, config)
+        }
+        Some(x) if x == "--profile" => {
+            config.launch.profile = true;
+            parse_args_helper(args, config)
+        }
+        Some(x) if x == "--seed" => {
+            let seed = args.next().ok_or("--seed requires an argument")?;
+            config.launch.map =
+                Some(MapChoice::Procedural(seed.parse::<u64>()?));
+            parse_args_helper(args, config)
+        }
+        Some(x) if x == "--map" => {
+            let name = args.next().ok_or("--map requires an argument")?;
+            config.launch.map = Some(MapChoice::File(name));
+            parse_args_helper(args, config)
+        }
+        Some(x) if x == "--light-benchmark" => {
+            let count = args
+                .next()
+                .ok_or("--light-benchmark requires an argument")?;
+            config.launch.light_benchmark = count.parse::<usize>()?;
+            parse_args_helper(args, config)
+        }
+        // the values of these are parsed where they're used
+        Some(x) if MAIN_ARGS.contains(&x.as_str()) => {
+            args.next()
+                .ok_or_else(|| format!("{} requires an argument", x))?;
+            parse_args_helper(args, config)
+        }
+        Some(x) => Err(format!("Unknown argument \"{}\"", x).into()),
+    }
+}
+
+/// Loads the configuration from the file given by `--config <path>`, or the
+/// default config file, and then overrides it with the other arguments
+///
+/// # Errors
+/// Fails if an argument is unknown, is missing its value or its value cannot
+/// be parsed
+pub fn parse_args<I: Iterator<Item = String>>(
+    mut args: I,
+) -> Result<ClientConfig, Box<dyn Error>> {
+    args.next(); // skip the program name
+    let args: Vec<_> = args.collect();
+    let config = ClientConfig::load_or_default(config_path(&args));
+    parse_args_helper(args.into_iter(), config)
+}
+
+/// Gets the path of the config file given by `--config <path>`, or the
+/// default path
+pub fn config_path(args: &[String]) -> &str {
+    args.windows(2)
+        .find(|arg| arg[0] == "--config")
+        .map_or(CLIENT_CONFIG_PATH, |arg| arg[1].as_str())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use glutin::event::VirtualKeyCode;
+
+    fn args(args: &[&str]) -> std::vec::IntoIter<String> {
+        args.iter()
+            .map(|arg| (*arg).to_owned())
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn client_config_round_trip() {
+        let mut config = ClientConfig {
+            width: 800,
+            height: 600,
+            fullscreen: true,
+            vsync: false,
+            server: "10.0.0.2:4000".parse().unwrap(),
+            player_name: "ace".to_owned(),
+            language: "fr".to_owned(),
+            video_seconds: 5,
+            video_fps: 60,
+            video_encoder: "ffmpeg -s {width}x{height} -i - out.mp4".to_owned(),
+            log_filter: "warn,oort_client::bot=debug".to_owned(),
+            log_overlay: false,
+            ..ClientConfig::default()
+        };
+        config.set_buttons(Action::Fire, vec![Button::Key(VirtualKeyCode::J)]);
+        let parsed = ClientConfig::parse(&config.to_string()).unwrap();
+        assert_eq!(config, parsed);
+        assert_eq!(
+            parsed.video_settings().output,
+            VideoOutput::Encoder(config.video_encoder)
+        );
+        assert_eq!(
+            ClientConfig::default().video_settings().output,
+            VideoOutput::ImageSequence
+        );
+    }
+
+    #[test]
+    fn client_config_rebinds_keys() {
+        let config = ClientConfig::parse(
+            "# comment\n\
+            [controls]\n\
+            forward = [\"Up\", \"W\"]\n\
+            fire = []\n\
+            \n\
+            [window]\n\
+            width = 1280",
+        )
+        .unwrap();
+        assert_eq!(config.width, 1280);
+        assert_eq!(config.height, ClientConfig::default().height);
+        let map = config.input_map();
+        assert_eq!(
+            map.action(Button::Key(VirtualKeyCode::Up)),
+            Some(Action::Forward)
+        );
+        assert_eq!(
+            map.action(Button::Key(VirtualKeyCode::W)),
+            Some(Action::Forward)
+        );
+        assert_eq!(map.action(Button::Mouse(1)), None);
+        assert_eq!(map.action(Button::Mouse(3)), Some(Action::FireRope));
+    }
+
+    #[test]
+    fn client_config_invalid() {
+        assert!(ClientConfig::parse("width = 800").is_err());
+        assert!(ClientConfig::parse("[audio]").is_err());
+        assert!(ClientConfig::parse("[window]\nwidth 800").is_err());
+        assert!(ClientConfig::parse("[window]\nvsync = yes").is_err());
+        assert!(ClientConfig::parse("[network]\nserver = 1.2.3.4").is_err());
+        assert!(ClientConfig::parse("[controls]\njump = [\"Space\"]").is_err());
+        assert!(ClientConfig::parse("[controls]\nfire = [\"Hyper\"]").is_err());
+    }
+
+    #[test]
+    fn resolutions_cycle() {
+        assert_eq!(next_resolution(1280, 720), (1600, 900));
+        assert_eq!(next_resolution(1700, 1000), (1920, 1080));
+        assert_eq!(next_resolution(3840, 2160), (1280, 720));
+        assert_eq!(next_resolution(5000, 3000), (1280, 720));
+    }
+
+    #[test]
+    fn args_override_config() {
+        let config = parse_args_helper(
+            args(&[
+                "--seed",
+                "4",
+                "--width",
+                "640",
+                "--fullscreen",
+                "--no-vsync",
+                "--name",
+                "ace",
+                "--language",
+                "fr",
+                "--log",
+                "debug",
+            ]),
+            ClientConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(config.width, 640);
+        assert!(config.fullscreen);
+        assert!(!config.vsync);
+        assert_eq!(config.player_name, "ace");
+        assert_eq!(config.language, "fr");
+        assert_eq!(config.log_filter, "debug");
+        assert!(parse_args_helper(
+            args(&["--server", "localhost"]),
+            ClientConfig::default()
+        )
+        .is_err());
+        assert!(parse_args_helper(
+            args(&["--height"]),
+            ClientConfig::default()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn args_choose_modes() {
+        let default = ClientConfig::default();
+        let config = parse_args_helper(
+            args(&["--survival", "--record", "inputs.rec", "--split-screen"]),
+            ClientConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            config.launch,
+            LaunchOptions {
+                survival: true,
+                split_screen: true,
+                ..LaunchOptions::default()
+            }
+        );
+        assert!(
+            parse_args_helper(args(&["--profile"]), ClientConfig::default())
+                .unwrap()
+                .launch
+                .profile
+        );
+        // the modes aren't saved
+        assert_eq!(ClientConfig::parse(&config.to_string()).unwrap(), default);
+    }
+
+    #[test]
+    fn args_choose_map() {
+        let config = parse_args_helper(
+            args(&["--seed", "42", "--light-benchmark", "200"]),
+            ClientConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(config.launch.map, Some(MapChoice::Procedural(42)));
+        assert_eq!(config.launch.light_benchmark, 200);
+        // the map chosen last is played
+        let config = parse_args_helper(
+            args(&["--seed", "42", "--map", "arena"]),
+            ClientConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(config.launch.map, Some(MapChoice::File("arena".into())));
+        assert_eq!(
+            ClientConfig::parse(&config.to_string()).unwrap(),
+            ClientConfig::default()
+        );
+        assert!(parse_args_helper(
+            args(&["--seed", "forty-two"]),
+            ClientConfig::default()
+        )
+        .is_err());
+        assert!(parse_args_helper(
+            args(&["--light-benchmark", "-1"]),
+            ClientConfig::default()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn args_reject_unknown() {
+        let err =
+            parse_args_helper(args(&["--survivl"]), ClientConfig::default())
+                .unwrap_err();
+        assert_eq!(err.to_string(), "Unknown argument \"--survivl\"");
+        assert!(
+            parse_args_helper(args(&["4"]), ClientConfig::default()).is_err()
+        );
+        assert!(parse_args_helper(args(&["--map"]), ClientConfig::default())
+            .is_err());
+    }
+}
@@ -0,0 +1,149 @@
+//! Benchmarks of the broad phase, narrow phase and octree. They're ignored
+//! by default, run them with
+//! `cargo test --release bench -- --ignored --nocapture --test-threads=1`
+//!
+//! Criterion keeps the results of the last run in `target/criterion`, and
+//! reports any regression against them
+
+use super::*;
+use crate::object::col_data_of_obj_type;
+use cgmath::*;
+use criterion::{black_box, BenchmarkId, Criterion};
+use rand::{Rng, SeedableRng};
+use serial_test::serial;
+use shared_types::ObjectType;
+use std::time::Duration;
+
+/// Half width of the scene the bodies are scattered in, which is about the
+/// size of a map
+const SCENE_SIZE: f64 = 1500.;
+
+fn criterion() -> Criterion {
+    Criterion::default()
+        .sample_size(20)
+        .warm_up_time(Duration::from_secs(1))
+}
+
+/// Loads the collision mesh of objects of type `typ`
+fn prototype(typ: ObjectType) -> CollisionObject {
+    let (path, stop, _) = col_data_of_obj_type(typ).unwrap();
+    CollisionObject::prototype(path, stop)
+}
+
+/// Gets a random position in the scene
+fn random_pos(rng: &mut impl Rng) -> Point3<f64> {
+    point3(
+        rng.gen_range(-SCENE_SIZE..SCENE_SIZE),
+        rng.gen_range(-SCENE_SIZE..SCENE_SIZE),
+        rng.gen_range(-SCENE_SIZE..SCENE_SIZE),
+    )
+}
+
+/// Creates `count` asteroids of random sizes at random positions
+fn scatter(count: usize, rng: &mut impl Rng) -> Vec<CollisionObject> {
+    let asteroid = prototype(ObjectType::Asteroid);
+    (0..count)
+        .map(|_| {
+            let node = node::Node::default()
+                .pos(random_pos(rng))
+                .u_scale(rng.gen_range(1. ..20.));
+            CollisionObject::from(Rc::new(RefCell::new(node)), &asteroid)
+        })
+        .collect()
+}
+
+#[test]
+#[ignore]
+#[serial]
+fn broad_phase_bench() {
+    let mut c = criterion();
+    let mut group = c.benchmark_group("broad_phase");
+    for count in [1000, 5000] {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(count as u64);
+        let objects = scatter(count, &mut rng);
+        let mut tree = CollisionTree::new(point3(0., 0., 0.), SCENE_SIZE);
+        for obj in &objects {
+            tree.insert(obj);
+        }
+        group.bench_with_input(
+            BenchmarkId::from_parameter(count),
+            &objects,
+            |b, objects| {
+                b.iter(|| {
+                    for obj in objects {
+                        black_box(CollisionTree::get_colliders(obj));
+                    }
+                });
+            },
+        );
+    }
+    group.finish();
+    c.final_summary();
+}
+
+#[test]
+#[ignore]
+#[serial]
+fn narrow_phase_bench() {
+    let mut c = criterion();
+    let mut group = c.benchmark_group("narrow_phase");
+    let method = TriangleTriangleCPU {};
+    // pairs which collide in a typical game, overlapping so the bvhs have to
+    // be traversed to their leaves
+    let pairs = [
+        (ObjectType::Asteroid, ObjectType::Asteroid, 1.),
+        (ObjectType::Ship, ObjectType::Asteroid, 1.),
+        (ObjectType::Laser, ObjectType::Ship, 0.2),
+        (ObjectType::Ship, ObjectType::Station, 5.),
+    ];
+    for (a, b, offset) in pairs {
+        let node_a = node::Node::default();
+        let node_b = node::Node::default()
+            .pos(point3(offset, offset / 2., 0.))
+            .rot(Quaternion::from_angle_y(Deg(30.)));
+        let obj_a =
+            CollisionObject::from(Rc::new(RefCell::new(node_a)), &prototype(a));
+        let obj_b =
+            CollisionObject::from(Rc::new(RefCell::new(node_b)), &prototype(b));
+        group.bench_function(format!("{:?}_{:?}", a, b), |bench| {
+            bench.iter(|| black_box(obj_a.collision(&obj_b, &method)));
+        });
+    }
+    group.finish();
+    c.final_summary();
+}
+
+#[test]
+#[ignore]
+#[serial]
+fn octree_churn_bench() {
+    let mut c = criterion();
+    let mut group = c.benchmark_group("octree_churn");
+    for count in [1000, 5000] {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(count as u64);
+        let objects = scatter(count, &mut rng);
+        let mut tree = CollisionTree::new(point3(0., 0., 0.), SCENE_SIZE);
+        for obj in &objects {
+            tree.insert(obj);
+        }
+        // every body moves to a new cell each iteration, the worst case
+        // for an update
+        let positions: Vec<_> = objects
+            .iter()
+            .map(|_| (random_pos(&mut rng), random_pos(&mut rng)))
+            .collect();
+        let mut flip = false;
+        group.bench_function(BenchmarkId::from_parameter(count), |b| {
+            b.iter(|| {
+                flip = !flip;
+                for (obj, (first, second)) in objects.iter().zip(&positions) {
+                    let pos = if flip { *first } else { *second };
+                    obj.get_transformation().borrow_mut().set_pos(pos);
+                    black_box(CollisionTree::update(obj));
+                }
+            });
+        });
+    }
+    group.finish();
+    c.final_summary();
+}
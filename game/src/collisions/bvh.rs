@@ -4,6 +4,7 @@ use std::collections::{HashSet, VecDeque};
 use std::hash::{Hash, Hasher};
 use std::marker::PhantomPinned;
 use std::pin::Pin;
+use tracing::trace;
 
 /// The criteria for stopping the growth of a BVH tree
 #[derive(PartialEq, Eq, Hash, Clone, Copy)]
@@ -11,7 +12,6 @@ pub enum TreeStopCriteria {
     MaxPrimitivesPerLeaf(usize),
     #[allow(dead_code)]
     MaxDepth(u32),
-    #[allow(dead_code)]
     AlwaysStop,
 }
 
@@ -42,6 +42,82 @@ impl TreeStopCriteria {
     }
 }
 
+/// Named levels of detail of a BVH
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum CollisionLod {
+    /// A single leaf holding every triangle, for meshes with so few
+    /// triangles that splitting them isn't worth it
+    Hull,
+    /// Large leaves, for meshes whose hits are rare or don't need to be
+    /// narrowed down quickly
+    Coarse,
+    /// Small leaves, for large meshes that are hit often
+    Fine,
+}
+
+impl CollisionLod {
+    /// Gets the criteria for stopping the growth of a BVH of this level of
+    /// detail
+    pub const fn stop_criteria(self) -> TreeStopCriteria {
+        match self {
+            Self::Hull => TreeStopCriteria::AlwaysStop,
+            Self::Coarse => TreeStopCriteria::MaxPrimitivesPerLeaf(1024),
+            Self::Fine => TreeStopCriteria::MaxPrimitivesPerLeaf(128),
+        }
+    }
+}
+
+impl From<CollisionLod> for TreeStopCriteria {
+    fn from(lod: CollisionLod) -> Self {
+        lod.stop_criteria()
+    }
+}
+
+/// The shape of a BVH, to tune its stop criteria with
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct BvhStats {
+    pub nodes: usize,
+    pub leaves: usize,
+    /// Depth of the deepest leaf, where the root has a depth of `0`
+    pub max_depth: u32,
+    pub triangles: usize,
+}
+
+impl BvhStats {
+    /// Gets the average number of triangles in a leaf
+    pub fn avg_tris_per_leaf(&self) -> f64 {
+        if self.leaves == 0 {
+            0.
+        } else {
+            self.triangles as f64 / self.leaves as f64
+        }
+    }
+
+    /// Combines the stats of two separate trees
+    #[must_use]
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            nodes: self.nodes + other.nodes,
+            leaves: self.leaves + other.leaves,
+            max_depth: self.max_depth.max(other.max_depth),
+            triangles: self.triangles + other.triangles,
+        }
+    }
+}
+
+impl std::fmt::Display for BvhStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} nodes, {} leaves, max depth {}, {:.1} triangles per leaf",
+            self.nodes,
+            self.leaves,
+            self.max_depth,
+            self.avg_tris_per_leaf()
+        )
+    }
+}
+
 #[derive(Clone)]
 pub struct CollisionVertex<T: BaseFloat> {
     pub pos: Point3<T>,
@@ -182,7 +258,7 @@ impl<T: BaseFloat> BVHNode<T> {
                 triangles: Some(left),
             }
         } else {
-            println!("Splitting {} and {}", left.len(), right.len());
+            trace!("Splitting {} and {}", left.len(), right.len());
             Self {
                 left: Some(Box::new(Self::new(left, rec_depth + 1, stop))),
                 right: Some(Box::new(Self::new(right, rec_depth + 1, stop))),
@@ -310,6 +386,20 @@ impl<T: BaseFloat> BVHNode<T> {
     /// get's all bounding boxes of leaves
     /// Testing purposes
     #[allow(dead_code)]
+    /// Adds the nodes of the subtree rooted at this node, which is at a
+    /// depth of `depth`, to `stats`
+    fn add_stats(&self, depth: u32, stats: &mut BvhStats) {
+        stats.nodes += 1;
+        if self.is_leaf() {
+            stats.leaves += 1;
+            stats.max_depth = stats.max_depth.max(depth);
+            stats.triangles += self.triangles.as_ref().map_or(0, Vec::len);
+        }
+        for child in self.left.iter().chain(self.right.iter()) {
+            child.add_stats(depth + 1, stats);
+        }
+    }
+
     fn get_leaf_boxes(&self, boxes: &mut Vec<BoundingVolume>) {
         if self.is_leaf() {
             boxes.push(self.volume.clone());
@@ -444,6 +534,13 @@ impl<T: BaseFloat> OBBTree<T> {
         )
     }
 
+    /// Gets the shape of this bvh
+    pub fn stats(&self) -> BvhStats {
+        let mut stats = BvhStats::default();
+        self.root.add_stats(0, &mut stats);
+        stats
+    }
+
     /// Iterates over all the vertices of this bvh
     pub fn forall_verts<F: FnMut(&CollisionVertex<T>)>(&self, func: &mut F) {
         for v in &self.vertices.as_ref().vertices {
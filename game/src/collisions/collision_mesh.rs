@@ -1,8 +1,9 @@
 #![allow(clippy::unreadable_literal)]
-use super::bvh::{CollisionVertex, OBBTree, TreeStopCriteria};
+use super::bvh::{BvhStats, CollisionVertex, OBBTree, TreeStopCriteria};
 use super::highp_col::{HighPCollision, Hit};
 use super::obb::{self, BoundingVolume};
 use cgmath::*;
+use tracing::debug;
 
 pub struct CollisionMesh {
     sub_meshes: Vec<OBBTree<f32>>,
@@ -48,12 +49,20 @@ impl CollisionMesh {
             .into_iter()
             .map(|x| {
                 let (verts, indices) = get_mesh_data(&x.mesh);
-                println!("{} triangles", indices.len() / 3);
                 OBBTree::from(indices, verts, stop_method)
             })
             .collect();
-        println!("Created mesh");
-        Self { sub_meshes: meshes }
+        let mesh = Self { sub_meshes: meshes };
+        debug!("Created mesh {}: {}", file, mesh.stats());
+        mesh
+    }
+
+    /// Gets the combined shape of the bvhs of every sub mesh
+    pub fn stats(&self) -> BvhStats {
+        self.sub_meshes
+            .iter()
+            .map(OBBTree::stats)
+            .fold(BvhStats::default(), BvhStats::merge)
     }
 
     /// See `HighPStrat::collision`
@@ -225,6 +234,29 @@ mod test {
         (shader::ShaderManager::init(&wnd_ctx), wnd_ctx)
     }
 
+    #[test]
+    fn lod_presets_shape_the_bvh() {
+        use bvh::CollisionLod;
+        let stats = |lod: CollisionLod| {
+            CollisionMesh::new("assets/asteroid1/Asteroid.obj", lod.into())
+                .stats()
+        };
+        let (hull, coarse, fine) = (
+            stats(CollisionLod::Hull),
+            stats(CollisionLod::Coarse),
+            stats(CollisionLod::Fine),
+        );
+        assert_eq!(hull.max_depth, 0);
+        assert_eq!(hull.nodes, hull.leaves);
+        assert_eq!(hull.triangles, coarse.triangles);
+        assert_eq!(coarse.triangles, fine.triangles);
+        assert!(fine.leaves >= coarse.leaves);
+        assert!(fine.max_depth >= coarse.max_depth);
+        assert!(fine.avg_tris_per_leaf() <= coarse.avg_tris_per_leaf());
+        // every internal node has two children
+        assert_eq!(fine.nodes, 2 * fine.leaves - hull.leaves);
+    }
+
     #[test]
     #[serial]
     fn bvh_collisions() {
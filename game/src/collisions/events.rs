@@ -0,0 +1,204 @@
+use cgmath::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+/// How a body in a collision tree changed
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ObstacleChange {
+    /// The body was inserted into the tree, or moved for the first time since
+    /// the subscription was made
+    Inserted,
+    Moved,
+    /// The body was removed from the tree. The event holds the body's last
+    /// reported position
+    Removed,
+}
+
+/// A change to a large body in a collision tree
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct ObstacleEvent {
+    pub change: ObstacleChange,
+    /// Id of the body's transformation node
+    pub node_id: usize,
+    /// Center of the body's bounding sphere after the change
+    pub center: Point3<f64>,
+    pub radius: f64,
+    /// Center of the body's bounding sphere when it was last reported, if
+    /// it was reported before
+    pub previous: Option<Point3<f64>>,
+}
+
+/// Collects the changes to bodies in a collision tree that are at least a
+/// minimum size
+///
+/// Moves are only reported once a body has moved further than the move
+/// tolerance away from the position it was last reported at
+pub struct ObstacleSubscription {
+    min_radius: f64,
+    move_tolerance: f64,
+    /// Last reported center of every body this subscription has seen
+    last_known: HashMap<usize, Point3<f64>>,
+    events: Vec<ObstacleEvent>,
+    /// Most events held until they're drained
+    max_events: usize,
+    /// `true` if events were dropped because there were too many
+    overflowed: bool,
+}
+
+impl ObstacleSubscription {
+    /// Takes every event that occurred since the last call, in the order they
+    /// occurred
+    pub fn drain(&mut self) -> Vec<ObstacleEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Limits the events held until they're drained to `max_events`, so a
+    /// subscription that's rarely drained doesn't grow without bound. Once
+    /// the limit is reached, events are dropped
+    pub fn set_max_events(&mut self, max_events: usize) {
+        self.max_events = max_events;
+    }
+
+    /// Returns `true` if events were dropped since the last call
+    pub fn take_overflowed(&mut self) -> bool {
+        std::mem::take(&mut self.overflowed)
+    }
+
+    fn notify(&mut self, event: ObstacleEvent) {
+        if event.radius < self.min_radius {
+            return;
+        }
+        let previous = self.last_known.get(&event.node_id).copied();
+        let change = match (event.change, previous) {
+            (ObstacleChange::Removed, None) => return,
+            (ObstacleChange::Removed, Some(_)) => {
+                self.last_known.remove(&event.node_id);
+                self.push(ObstacleEvent { previous, ..event });
+                return;
+            }
+            (ObstacleChange::Moved, Some(last))
+                if last.distance(event.center) < self.move_tolerance =>
+            {
+                return
+            }
+            (ObstacleChange::Moved, Some(_)) => ObstacleChange::Moved,
+            (_, _) => ObstacleChange::Inserted,
+        };
+        self.last_known.insert(event.node_id, event.center);
+        self.push(ObstacleEvent {
+            change,
+            previous,
+            ..event
+        });
+    }
+
+    fn push(&mut self, event: ObstacleEvent) {
+        if self.events.len() < self.max_events {
+            self.events.push(event);
+        } else {
+            self.overflowed = true;
+        }
+    }
+}
+
+/// Broadcasts the changes to the bodies of a collision tree to its
+/// subscriptions
+///
+/// Subscriptions are held weakly, so a subscription is cancelled by dropping
+/// it
+#[derive(Default)]
+pub(super) struct ObstacleEvents {
+    subscriptions: Vec<Weak<RefCell<ObstacleSubscription>>>,
+}
+
+impl ObstacleEvents {
+    pub(super) fn subscribe(
+        &mut self,
+        min_radius: f64,
+        move_tolerance: f64,
+    ) -> Rc<RefCell<ObstacleSubscription>> {
+        let sub = Rc::new(RefCell::new(ObstacleSubscription {
+            min_radius,
+            move_tolerance,
+            last_known: HashMap::new(),
+            events: Vec::new(),
+            max_events: usize::MAX,
+            overflowed: false,
+        }));
+        self.subscriptions.push(Rc::downgrade(&sub));
+        sub
+    }
+
+    pub(super) fn publish(&mut self, event: ObstacleEvent) {
+        self.subscriptions.retain(|sub| {
+            sub.upgrade().map_or(false, |sub| {
+                sub.borrow_mut().notify(event);
+                true
+            })
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn event(change: ObstacleChange, x: f64, radius: f64) -> ObstacleEvent {
+        ObstacleEvent {
+            change,
+            node_id: 1,
+            center: point3(x, 0., 0.),
+            radius,
+            previous: None,
+        }
+    }
+
+    #[test]
+    fn subscriptions_filter_small_moves_and_bodies() {
+        let mut events = ObstacleEvents::default();
+        let sub = events.subscribe(2., 1.);
+        events.publish(event(ObstacleChange::Moved, 0., 1.));
+        assert!(sub.borrow_mut().drain().is_empty());
+        events.publish(event(ObstacleChange::Moved, 0., 3.));
+        events.publish(event(ObstacleChange::Moved, 0.5, 3.));
+        events.publish(event(ObstacleChange::Moved, 2., 3.));
+        events.publish(event(ObstacleChange::Removed, 2., 3.));
+        events.publish(event(ObstacleChange::Removed, 2., 3.));
+        let changes: Vec<_> =
+            sub.borrow_mut().drain().iter().map(|e| e.change).collect();
+        assert_eq!(
+            changes,
+            vec![
+                ObstacleChange::Inserted,
+                ObstacleChange::Moved,
+                ObstacleChange::Removed
+            ]
+        );
+        drop(sub);
+        events.publish(event(ObstacleChange::Inserted, 0., 3.));
+        assert!(events.subscriptions.is_empty());
+    }
+
+    #[test]
+    fn subscriptions_report_previous_centers_until_full() {
+        let mut events = ObstacleEvents::default();
+        let sub = events.subscribe(0., 1.);
+        sub.borrow_mut().set_max_events(2);
+        events.publish(event(ObstacleChange::Inserted, 0., 1.));
+        events.publish(event(ObstacleChange::Moved, 2., 1.));
+        events.publish(event(ObstacleChange::Moved, 4., 1.));
+        let previous: Vec<_> = sub
+            .borrow_mut()
+            .drain()
+            .iter()
+            .map(|e| e.previous)
+            .collect();
+        assert_eq!(previous, vec![None, Some(point3(0., 0., 0.))]);
+        assert!(sub.borrow_mut().take_overflowed());
+        assert!(!sub.borrow_mut().take_overflowed());
+        events.publish(event(ObstacleChange::Removed, 4., 1.));
+        let removed = sub.borrow_mut().drain();
+        assert_eq!(removed[0].previous, Some(point3(4., 0., 0.)));
+    }
+}
@@ -1,11 +1,16 @@
+#[cfg(test)]
+mod bench;
 mod bvh;
 mod collision_mesh;
+mod events;
 mod highp_col;
 mod obb;
 mod object;
 mod octree;
 use crate::cg_support::node;
-pub use bvh::TreeStopCriteria;
+pub use bvh::{BvhStats, CollisionLod, TreeStopCriteria};
+use events::ObstacleEvents;
+pub use events::{ObstacleChange, ObstacleEvent, ObstacleSubscription};
 pub use highp_col::*;
 pub use obb::{Aabb, BoundingVolume, Obb};
 use octree::Octree;
@@ -16,34 +21,55 @@ use std::rc::Rc;
 type MeshIdType = (String, TreeStopCriteria);
 type LoadedMeshMap = HashMap<MeshIdType, Rc<collision_mesh::CollisionMesh>>;
 
-static mut LOADED_MESHES: Option<LoadedMeshMap> = None;
-
-static mut MESH_MAP_INIT: bool = false;
-
-struct MeshMap {
-    loaded_meshes: Option<LoadedMeshMap>,
+std::thread_local! {
+    /// Collision meshes shared between all objects with the same geometry
+    ///
+    /// Meshes are shared by `Rc`, so each thread has its own cache. Objects
+    /// loaded on different threads never share a mesh
+    static LOADED_MESHES: RefCell<LoadedMeshMap> =
+        RefCell::new(LoadedMeshMap::new());
 }
 
-fn get_loaded_meshes() -> MeshMap {
-    let meshes = if unsafe { MESH_MAP_INIT } {
-        unsafe { LOADED_MESHES.take() }
-    } else {
-        unsafe { MESH_MAP_INIT = true };
-        Some(LoadedMeshMap::new())
-    };
-    MeshMap {
-        loaded_meshes: Some(meshes.expect("Singleton already borrowed")),
+/// Gets the cached collision mesh at `mesh_path` built with `bvh_stop`,
+/// loading it if it isn't cached
+fn load_mesh(
+    mesh_path: &str,
+    bvh_stop: TreeStopCriteria,
+) -> Rc<collision_mesh::CollisionMesh> {
+    let key = (mesh_path.to_owned(), bvh_stop);
+    if let Some(mesh) = LOADED_MESHES.with(|m| m.borrow().get(&key).cloned()) {
+        return mesh;
     }
+    // the cache isn't borrowed while loading, since it can take a while
+    let mesh = Rc::new(collision_mesh::CollisionMesh::new(mesh_path, bvh_stop));
+    LOADED_MESHES.with(|m| m.borrow_mut().insert(key, mesh.clone()));
+    mesh
 }
 
-impl Drop for MeshMap {
-    fn drop(&mut self) {
-        unsafe {
-            LOADED_MESHES = std::mem::replace(&mut self.loaded_meshes, None);
-        }
+/// Loads the collision meshes of `meshes`, as pairs of mesh path and bvh
+/// stop criteria, so that objects created with them later don't have to
+pub fn preload_meshes<'a, I>(meshes: I)
+where
+    I: IntoIterator<Item = (&'a str, TreeStopCriteria)>,
+{
+    for (path, bvh_stop) in meshes {
+        load_mesh(path, bvh_stop);
     }
 }
 
+/// Removes every cached collision mesh which isn't used by an object, such
+/// as the meshes of a level that was left
+///
+/// Returns the amount of evicted meshes
+pub fn evict_unused_meshes() -> usize {
+    LOADED_MESHES.with(|m| {
+        let mut meshes = m.borrow_mut();
+        let start_len = meshes.len();
+        meshes.retain(|_, mesh| Rc::strong_count(mesh) > 1);
+        start_len - meshes.len()
+    })
+}
+
 #[derive(Clone)]
 pub struct CollisionObject {
     obj: Rc<RefCell<object::Object>>, //shared with octree, which holds weak pointers
@@ -60,33 +86,10 @@ impl CollisionObject {
         mesh_path: &str,
         bvh_stop: bvh::TreeStopCriteria,
     ) -> Self {
-        let mut mmap = get_loaded_meshes();
-        if let Some(mesh) = mmap
-            .loaded_meshes
-            .as_ref()
-            .unwrap()
-            .get(&(mesh_path.to_string(), bvh_stop))
-        {
-            let obj = Rc::new(RefCell::new(object::Object::with_mesh(
-                transform, mesh,
-            )));
-            Self {
-                obj,
-                mesh: mesh.clone(),
-            }
-        } else {
-            let mesh = Rc::new(collision_mesh::CollisionMesh::new(
-                mesh_path, bvh_stop,
-            ));
-            mmap.loaded_meshes
-                .as_mut()
-                .unwrap()
-                .insert((mesh_path.to_owned(), bvh_stop), mesh.clone());
-            let obj = Rc::new(RefCell::new(object::Object::with_mesh(
-                transform, &mesh,
-            )));
-            Self { obj, mesh }
-        }
+        let mesh = load_mesh(mesh_path, bvh_stop);
+        let obj =
+            Rc::new(RefCell::new(object::Object::with_mesh(transform, &mesh)));
+        Self { obj, mesh }
     }
 
     /// Creates a new collision object that's meant to serve as a prototype to make
@@ -107,6 +110,7 @@ impl CollisionObject {
             local_radius: prototype.obj.borrow().local_radius,
             octree_cell: std::rc::Weak::new(),
             mesh: prototype.obj.borrow().mesh.clone(),
+            events: std::rc::Weak::new(),
         }));
         Self {
             obj,
@@ -130,6 +134,12 @@ impl CollisionObject {
         )
     }
 
+    /// Gets the shape of the bvh of this object's collision mesh
+    #[allow(dead_code)]
+    pub fn bvh_stats(&self) -> BvhStats {
+        self.mesh.stats()
+    }
+
     /// Gets transformation matrices transforming a -1 to 1 cube to each bounding box
     #[allow(dead_code)]
     pub fn get_main_and_leaf_cube_transformations(
@@ -209,7 +219,7 @@ impl CollisionObject {
 
     #[inline]
     pub fn update_in_collision_tree(&self) {
-        Octree::update(&self.obj);
+        CollisionTree::update(self);
     }
 
     #[inline]
@@ -293,6 +303,7 @@ impl PartialEq<Rc<RefCell<object::Object>>> for CollisionObject {
 
 pub struct CollisionTree {
     tree: Octree,
+    events: Rc<RefCell<ObstacleEvents>>,
 }
 
 impl CollisionTree {
@@ -300,6 +311,7 @@ impl CollisionTree {
     pub fn new(center: cgmath::Point3<f64>, half_width: f64) -> Self {
         Self {
             tree: Octree::new(center, half_width),
+            events: Rc::default(),
         }
     }
 
@@ -311,6 +323,8 @@ impl CollisionTree {
             return;
         }
         self.tree.insert(&obj.obj);
+        obj.obj.borrow_mut().events = Rc::downgrade(&self.events);
+        Self::publish(obj, ObstacleChange::Inserted);
     }
 
     /// Updates the position of the given object in the collision tree
@@ -319,13 +333,53 @@ impl CollisionTree {
     /// Returns true if the object was in the tree and updated, false otherwise
     #[inline]
     pub fn update(obj: &CollisionObject) -> bool {
-        Octree::update(&obj.obj)
+        let updated = Octree::update(&obj.obj);
+        if updated {
+            Self::publish(obj, ObstacleChange::Moved);
+        }
+        updated
     }
 
     #[inline]
-    #[allow(dead_code)]
     pub fn remove(obj: &CollisionObject) {
+        Self::publish(obj, ObstacleChange::Removed);
         Octree::remove(&obj.obj);
+        obj.obj.borrow_mut().events = std::rc::Weak::new();
+    }
+
+    /// Notifies the subscriptions of the tree `obj` is in of a change to
+    /// `obj`
+    fn publish(obj: &CollisionObject, change: ObstacleChange) {
+        let events = obj.obj.borrow().events.upgrade();
+        if let Some(events) = events {
+            let (center, radius) = {
+                let o = obj.obj.borrow();
+                (o.center(), o.radius())
+            };
+            events.borrow_mut().publish(ObstacleEvent {
+                change,
+                node_id: obj.node_id(),
+                center,
+                radius,
+                previous: None,
+            });
+        }
+    }
+
+    /// Subscribes to changes to bodies in this tree with a bounding sphere
+    /// radius of at least `min_radius`
+    ///
+    /// Moves are reported once a body has moved at least `move_tolerance`
+    /// since it was last reported. The subscription is cancelled when the
+    /// returned subscription is dropped
+    pub fn subscribe(
+        &self,
+        min_radius: f64,
+        move_tolerance: f64,
+    ) -> Rc<RefCell<ObstacleSubscription>> {
+        self.events
+            .borrow_mut()
+            .subscribe(min_radius, move_tolerance)
     }
 
     /// Gets objects that collide with the given object which is part of this
@@ -359,6 +413,72 @@ impl CollisionTree {
             .collect()
     }
 
+    /// Gets all objects in this collision tree whose bounding spheres are at
+    /// least partially inside the cone starting at `apex` and opening along
+    /// `dir`
+    ///
+    /// `half_angle` - the angle between the axis and the edge of the cone
+    ///
+    /// `range` - the distance from the apex objects must be within
+    pub fn objects_in_cone(
+        &self,
+        apex: cgmath::Point3<f64>,
+        dir: cgmath::Vector3<f64>,
+        half_angle: cgmath::Rad<f64>,
+        range: f64,
+    ) -> Vec<CollisionObject> {
+        use cgmath::InnerSpace;
+        let mut objects = self.test_for_collisions(apex, range);
+        objects.retain(|obj| {
+            let (center, radius) = obj.bounding_sphere();
+            let to_center = center - apex;
+            let dist = to_center.magnitude();
+            // the bounding sphere contains the apex
+            dist <= radius
+                || to_center.angle(dir).0
+                    <= half_angle.0 + (radius / dist).asin()
+        });
+        objects
+    }
+
+    /// Moves a sphere of `radius` from `start` to `end` and finds where it
+    /// first touches an object in this collision tree
+    ///
+    /// `ignore` - objects the sphere passes through, such as the object the
+    /// cast starts from
+    ///
+    /// Returns the fraction of the way from `start` to `end` that the sphere
+    /// can travel without touching anything, or `None` if it reaches `end`
+    pub fn sphere_cast(
+        &self,
+        start: cgmath::Point3<f64>,
+        end: cgmath::Point3<f64>,
+        radius: f64,
+        ignore: &[&CollisionObject],
+    ) -> Option<f64> {
+        use cgmath::{InnerSpace, SquareMatrix};
+        /// Maximum amount of spheres tested along the path
+        const MAX_STEPS: usize = 64;
+        let path = end - start;
+        // steps of half the radius so thin objects aren't skipped over
+        let steps = ((path.magnitude() / (radius * 0.5).max(f64::EPSILON))
+            .ceil() as usize)
+            .clamp(1, MAX_STEPS);
+        let touches = |center: cgmath::Point3<f64>| {
+            let probe = BoundingVolume::Aabb(Aabb {
+                center,
+                extents: cgmath::vec3(radius, radius, radius),
+            });
+            self.test_for_collisions(center, radius).iter().any(|obj| {
+                !ignore.iter().any(|ignored| *ignored == obj)
+                    && obj.collision_simple(probe, &cgmath::Matrix4::identity())
+            })
+        };
+        (0..=steps)
+            .find(|step| touches(start + path * (*step as f64 / steps as f64)))
+            .map(|step| step.saturating_sub(1) as f64 / steps as f64)
+    }
+
     /// Gets all objects in the tree
     pub fn get_all_objects(&self) -> Vec<CollisionObject> {
         self.tree
@@ -380,6 +500,21 @@ mod test {
     use cgmath::*;
     use serial_test::serial;
 
+    #[test]
+    fn mesh_cache_shares_and_evicts_meshes() {
+        let path = "assets/default_cube.obj";
+        preload_meshes([(path, TreeStopCriteria::default())]);
+        let a = CollisionObject::prototype(path, TreeStopCriteria::default());
+        let b = CollisionObject::prototype(path, TreeStopCriteria::default());
+        assert!(Rc::ptr_eq(&a.mesh, &b.mesh));
+        assert_eq!(evict_unused_meshes(), 0);
+        drop(a);
+        drop(b);
+        assert_eq!(evict_unused_meshes(), 1);
+        let c = CollisionObject::prototype(path, TreeStopCriteria::default());
+        assert_eq!(Rc::strong_count(&c.mesh), 2);
+    }
+
     #[serial]
     #[test]
     fn collision_tree_test() {
@@ -491,4 +626,132 @@ mod test {
             }
         );
     }
+
+    #[serial]
+    #[test]
+    fn cone_query_test() {
+        let mut tree = CollisionTree::new(point3(0., 0., 0.), 100.);
+        let ahead = Rc::new(RefCell::new(
+            node::Node::default().pos(point3(0., 0., 20.)),
+        ));
+        let beside = Rc::new(RefCell::new(
+            node::Node::default().pos(point3(20., 0., 0.)),
+        ));
+        let far = Rc::new(RefCell::new(
+            node::Node::default().pos(point3(0., 0., 80.)),
+        ));
+        let objs: Vec<_> = [&ahead, &beside, &far]
+            .iter()
+            .map(|n| {
+                CollisionObject::new(
+                    (*n).clone(),
+                    "assets/default_cube.obj",
+                    TreeStopCriteria::default(),
+                )
+            })
+            .collect();
+        for obj in &objs {
+            tree.insert(obj);
+        }
+        let in_cone = tree.objects_in_cone(
+            point3(0., 0., 0.),
+            vec3(0., 0., 1.),
+            Deg(20.).into(),
+            50.,
+        );
+        assert_eq!(in_cone.len(), 1);
+        assert!(in_cone[0] == objs[0]);
+        let in_cone = tree.objects_in_cone(
+            point3(0., 0., 0.),
+            vec3(1., 0., 1.),
+            Deg(50.).into(),
+            50.,
+        );
+        assert_eq!(in_cone.len(), 2);
+        assert!(!in_cone.contains(&objs[2]));
+    }
+
+    #[serial]
+    #[test]
+    fn sphere_cast_test() {
+        let mut tree = CollisionTree::new(point3(0., 0., 0.), 100.);
+        let start = Rc::new(RefCell::new(node::Node::default()));
+        let wall = Rc::new(RefCell::new(
+            node::Node::default()
+                .pos(point3(0., 0., 10.))
+                .scale(vec3(5., 5., 1.)),
+        ));
+        let objs: Vec<_> = [&start, &wall]
+            .iter()
+            .map(|n| {
+                CollisionObject::new(
+                    (*n).clone(),
+                    "assets/default_cube.obj",
+                    TreeStopCriteria::default(),
+                )
+            })
+            .collect();
+        for obj in &objs {
+            tree.insert(obj);
+        }
+        let hit = tree
+            .sphere_cast(
+                point3(0., 0., 0.),
+                point3(0., 0., 20.),
+                1.,
+                &[&objs[0]],
+            )
+            .unwrap();
+        // the sphere stops before the front face of the wall at z = 9
+        assert_le!(hit * 20., 8.);
+        assert_gt!(hit * 20., 6.);
+        // the sphere starts inside the ignored object
+        assert!(tree
+            .sphere_cast(point3(0., 0., 0.), point3(0., 0., 20.), 1., &[])
+            .map_or(false, |t| t < f64::EPSILON));
+        // the sphere passes beside the wall
+        assert!(tree
+            .sphere_cast(
+                point3(10., 0., 0.),
+                point3(10., 0., 20.),
+                1.,
+                &[&objs[0]]
+            )
+            .is_none());
+    }
+
+    #[serial]
+    #[test]
+    fn tree_publishes_obstacle_events() {
+        let mut tree = CollisionTree::new(point3(0., 0., 0.), 100.);
+        let node = Rc::new(RefCell::new(node::Node::default()));
+        let obj = CollisionObject::new(
+            node.clone(),
+            "assets/default_cube.obj",
+            TreeStopCriteria::default(),
+        );
+        let sub = tree.subscribe(0.5, 1.);
+        let large_only = tree.subscribe(100., 1.);
+        tree.insert(&obj);
+        node.borrow_mut().set_pos(point3(0.5, 0., 0.));
+        obj.update_in_collision_tree();
+        node.borrow_mut().set_pos(point3(5., 0., 0.));
+        obj.update_in_collision_tree();
+        CollisionTree::remove(&obj);
+        // objects outside of a tree don't publish events
+        CollisionTree::update(&obj);
+        let events = sub.borrow_mut().drain();
+        let changes: Vec<_> = events.iter().map(|e| e.change).collect();
+        assert_eq!(
+            changes,
+            vec![
+                ObstacleChange::Inserted,
+                ObstacleChange::Moved,
+                ObstacleChange::Removed
+            ]
+        );
+        assert!(events.iter().all(|e| e.node_id == obj.node_id()));
+        assert_relative_eq!(events[1].center, point3(5., 0., 0.));
+        assert!(large_only.borrow_mut().drain().is_empty());
+    }
 }
@@ -1,4 +1,5 @@
 use super::collision_mesh;
+use super::events::ObstacleEvents;
 use super::octree::ONode;
 use crate::cg_support::node;
 use cgmath::*;
@@ -11,6 +12,8 @@ pub(super) struct Object {
     pub(super) local_radius: f64,
     pub(super) octree_cell: Weak<RefCell<ONode>>,
     pub(super) mesh: Weak<collision_mesh::CollisionMesh>,
+    /// Events of the collision tree this object is in
+    pub(super) events: Weak<RefCell<ObstacleEvents>>,
 }
 
 impl Object {
@@ -39,6 +42,7 @@ impl Object {
             local_radius: radius,
             octree_cell: Weak::new(),
             mesh: Weak::new(),
+            events: Weak::new(),
         }
     }
 
@@ -55,6 +59,7 @@ impl Object {
             local_radius,
             octree_cell: Weak::new(),
             mesh: Rc::downgrade(mesh),
+            events: Weak::new(),
         }
         // local_radius was previously set as radius + center.x.max(center.y.max(center.z)),
         // when `radius` was incorrectly the maximum of the extents
@@ -245,6 +245,7 @@ impl ONode {
             local_radius: radius,
             octree_cell: Weak::new(),
             mesh: Weak::new(),
+            events: Weak::new(),
         }));
         let mut v = Self::get_self_colliders(node, &test_obj);
         v.append(&mut Self::get_subtree_colliders(node, &test_obj));
@@ -768,6 +769,7 @@ mod test {
             local_radius: 1.,
             octree_cell: Weak::new(),
             mesh: Weak::new(),
+            events: Weak::new(),
         }));
         assert_eq!(
             ONode::get_octant_index(&point3(0., 0., 0.), 10., &obj),
@@ -0,0 +1,388 @@
+use glutin::event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
+use std::collections::{BTreeMap, VecDeque};
+
+/// Character which opens and closes the console
+pub const TOGGLE_CHAR: char = '`';
+/// Lines of output the console keeps
+const MAX_OUTPUT: usize = 200;
+/// Commands the console remembers
+const MAX_HISTORY: usize = 50;
+
+/// The text a command prints if it succeeds, or the reason it failed
+pub type CommandResult = Result<String, String>;
+
+/// A command which can be run from the console
+struct Command<'a> {
+    /// Arguments the command takes, shown by `help`
+    usage: String,
+    handler: Box<dyn FnMut(&[&str]) -> CommandResult + 'a>,
+}
+
+/// A drop-down developer console which runs registered commands
+///
+/// A line is run by splitting it into words. The first word names the
+/// command, and the rest are passed to its handler. `help` and `clear` are
+/// always available
+pub struct Console<'a> {
+    commands: BTreeMap<String, Command<'a>>,
+    open: bool,
+    input: String,
+    /// Lines which were run, oldest first
+    history: VecDeque<String>,
+    /// Index of the line of the history being edited, or `None` when editing
+    /// a new line
+    browsing: Option<usize>,
+    /// Lines printed by commands, oldest first
+    output: VecDeque<String>,
+}
+
+impl<'a> Default for Console<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Console<'a> {
+    pub fn new() -> Self {
+        Self {
+            commands: BTreeMap::new(),
+            open: false,
+            input: String::new(),
+            history: VecDeque::new(),
+            browsing: None,
+            output: VecDeque::new(),
+        }
+    }
+
+    /// Registers `handler` to run the command `name`, replacing any command
+    /// of the same name
+    ///
+    /// `usage` - the arguments of the command, such as `<scale> [secs]`
+    ///
+    /// `handler` - called with the arguments of the command. Its result is
+    /// printed to the console
+    pub fn register<F>(&mut self, name: &str, usage: &str, handler: F)
+    where
+        F: FnMut(&[&str]) -> CommandResult + 'a,
+    {
+        self.commands.insert(
+            name.to_owned(),
+            Command {
+                usage: usage.to_owned(),
+                handler: Box::new(handler),
+            },
+        );
+    }
+
+    #[must_use]
+    pub const fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Opens the console if it's closed, and closes it if it's open
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+    }
+
+    /// Gets the line being typed
+    #[must_use]
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
+    /// Gets the last `count` lines of output, oldest first
+    pub fn output(&self, count: usize) -> impl Iterator<Item = &str> {
+        self.output
+            .iter()
+            .skip(self.output.len().saturating_sub(count))
+            .map(String::as_str)
+    }
+
+    /// Prints `text` to the console, a line at a time
+    pub fn print(&mut self, text: &str) {
+        for line in text.lines() {
+            if self.output.len() == MAX_OUTPUT {
+                self.output.pop_front();
+            }
+            self.output.push_back(line.to_owned());
+        }
+    }
+
+    /// Runs the command `line`, printing it and the result of the command
+    pub fn execute(&mut self, line: &str) {
+        self.print(&format!("> {}", line));
+        let words: Vec<_> = line.split_whitespace().collect();
+        let result = match words.split_first() {
+            None => return,
+            Some((&"help", _)) => Ok(self.help()),
+            Some((&"clear", _)) => {
+                self.output.clear();
+                return;
+            }
+            Some((name, args)) => match self.commands.get_mut(*name) {
+                Some(command) => (command.handler)(args),
+                None => Err(format!("Unknown command \"{}\"", name)),
+            },
+        };
+        match result {
+            Ok(text) => self.print(&text),
+            Err(e) => self.print(&format!("Error: {}", e)),
+        }
+    }
+
+    /// Lists the commands and their arguments
+    fn help(&self) -> String {
+        let mut lines = vec!["clear".to_owned(), "help".to_owned()];
+        lines.extend(self.commands.iter().map(|(name, cmd)| {
+            format!("{} {}", name, cmd.usage).trim_end().to_owned()
+        }));
+        lines.sort();
+        lines.join("\n")
+    }
+
+    /// Runs the line being typed and remembers it in the history
+    pub fn submit(&mut self) {
+        let line = std::mem::take(&mut self.input);
+        self.browsing = None;
+        if line.trim().is_empty() {
+            return;
+        }
+        if self.history.back() != Some(&line) {
+            if self.history.len() == MAX_HISTORY {
+                self.history.pop_front();
+            }
+            self.history.push_back(line.clone());
+        }
+        self.execute(&line);
+    }
+
+    /// Replaces the line being typed with the line before it in the history
+    pub fn history_back(&mut self) {
+        let idx = match self.browsing {
+            Some(idx) => idx.saturating_sub(1),
+            None if self.history.is_empty() => return,
+            None => self.history.len() - 1,
+        };
+        self.browsing = Some(idx);
+        self.input = self.history[idx].clone();
+    }
+
+    /// Replaces the line being typed with the line after it in the history,
+    /// or an empty line after the last one
+    pub fn history_forward(&mut self) {
+        match self.browsing {
+            Some(idx) if idx + 1 < self.history.len() => {
+                self.browsing = Some(idx + 1);
+                self.input = self.history[idx + 1].clone();
+            }
+            Some(_) => {
+                self.browsing = None;
+                self.input.clear();
+            }
+            None => (),
+        }
+    }
+
+    /// Completes the name of the command being typed as far as the commands
+    /// it could be agree. If it could still be several of them, they're
+    /// printed
+    pub fn complete(&mut self) {
+        if self.input.contains(char::is_whitespace) {
+            return;
+        }
+        let matches: Vec<_> = ["clear", "help"]
+            .iter()
+            .copied()
+            .chain(self.commands.keys().map(String::as_str))
+            .filter(|name| name.starts_with(self.input.as_str()))
+            .collect();
+        let prefix = matches.iter().skip(1).fold(
+            matches.first().copied().unwrap_or_default(),
+            |prefix, name| common_prefix(prefix, name),
+        );
+        if matches.len() == 1 {
+            self.input = format!("{} ", prefix);
+        } else if prefix.len() > self.input.len() {
+            self.input = prefix.to_owned();
+        } else if matches.len() > 1 {
+            let mut names = matches.join("  ");
+            names.insert_str(0, "  ");
+            self.print(&names);
+        }
+    }
+
+    /// Handles the typing of the console, and the key which toggles it
+    ///
+    /// Returns `true` if the event was consumed by the console
+    pub fn on_window_event(&mut self, ev: &WindowEvent) -> bool {
+        match ev {
+            WindowEvent::ReceivedCharacter(TOGGLE_CHAR) => {
+                self.toggle();
+                true
+            }
+            _ if !self.open => false,
+            // keys which edit the line arrive as control characters too
+            WindowEvent::ReceivedCharacter(c) if !c.is_control() => {
+                self.input.push(*c);
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(key),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                match key {
+                    VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => {
+                        self.submit();
+                    }
+                    VirtualKeyCode::Back => {
+                        self.input.pop();
+                    }
+                    VirtualKeyCode::Tab => self.complete(),
+                    VirtualKeyCode::Up => self.history_back(),
+                    VirtualKeyCode::Down => self.history_forward(),
+                    VirtualKeyCode::Escape => self.open = false,
+                    _ => (),
+                }
+                true
+            }
+            WindowEvent::ReceivedCharacter(_)
+            | WindowEvent::KeyboardInput { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+/// Gets the longest prefix `a` and `b` share
+fn common_prefix<'b>(a: &'b str, b: &str) -> &'b str {
+    let len = a
+        .char_indices()
+        .zip(b.chars())
+        .find(|((_, a), b)| a != b)
+        .map_or_else(|| a.len().min(b.len()), |((idx, _), _)| idx);
+    &a[..len]
+}
+
+/// Parses `on`, `off`, or nothing as the new state of a setting which is
+/// currently `current`. Nothing toggles it
+///
+/// # Errors
+/// Fails if the argument is something else
+pub fn parse_toggle(args: &[&str], current: bool) -> Result<bool, String> {
+    match args {
+        [] => Ok(!current),
+        ["on"] => Ok(true),
+        ["off"] => Ok(false),
+        _ => Err("Expected on, off, or nothing to toggle".to_owned()),
+    }
+}
+
+/// Parses the argument at `idx` of `args`, or gets `default` if there are
+/// fewer arguments
+///
+/// # Errors
+/// Fails if the argument cannot be parsed
+pub fn parse_arg<T: std::str::FromStr>(
+    args: &[&str],
+    idx: usize,
+    default: T,
+) -> Result<T, String> {
+    args.get(idx).map_or(Ok(default), |arg| {
+        arg.parse()
+            .map_err(|_| format!("Invalid argument \"{}\"", arg))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn commands_run_with_their_arguments() {
+        let scale = Cell::new(1.);
+        let mut console = Console::new();
+        console.register("time_scale", "<scale>", |args| match args {
+            [scale_arg] => {
+                scale.set(scale_arg.parse().map_err(|_| "Not a number")?);
+                Ok(format!("Time scale is {}", scale.get()))
+            }
+            _ => Err("Expected a scale".to_owned()),
+        });
+        console.execute("time_scale  0.5 ");
+        console.execute("time_scale");
+        console.execute("spawn");
+        assert!((scale.get() - 0.5).abs() < f64::EPSILON);
+        assert_eq!(
+            console.output(5).collect::<Vec<_>>(),
+            vec![
+                "Time scale is 0.5",
+                "> time_scale",
+                "Error: Expected a scale",
+                "> spawn",
+                "Error: Unknown command \"spawn\"",
+            ]
+        );
+        console.execute("help");
+        assert_eq!(
+            console.output(3).collect::<Vec<_>>(),
+            vec!["clear", "help", "time_scale <scale>"]
+        );
+        console.execute("clear");
+        assert_eq!(console.output(5).count(), 0);
+    }
+
+    #[test]
+    fn history_is_browsed() {
+        let mut console = Console::new();
+        for line in ["help", "clear", "clear", " "] {
+            console.input = line.to_owned();
+            console.submit();
+        }
+        assert_eq!(console.history, vec!["help", "clear"]);
+        console.history_back();
+        assert_eq!(console.input(), "clear");
+        console.history_back();
+        console.history_back();
+        assert_eq!(console.input(), "help");
+        console.history_forward();
+        assert_eq!(console.input(), "clear");
+        console.history_forward();
+        assert_eq!(console.input(), "");
+    }
+
+    #[test]
+    fn command_names_are_completed() {
+        let mut console = Console::new();
+        console.register("spawn_asteroid", "", |_| Ok(String::new()));
+        console.register("spawn_pickup", "", |_| Ok(String::new()));
+        console.input = "sp".to_owned();
+        console.complete();
+        assert_eq!(console.input(), "spawn_");
+        console.complete();
+        assert_eq!(
+            console.output(1).collect::<Vec<_>>(),
+            vec!["  spawn_asteroid  spawn_pickup"]
+        );
+        console.input.push('a');
+        console.complete();
+        assert_eq!(console.input(), "spawn_asteroid ");
+        assert_eq!(common_prefix("hello", "help"), "hel");
+        assert_eq!(common_prefix("he", "help"), "he");
+    }
+
+    #[test]
+    fn arguments_are_parsed() {
+        assert_eq!(parse_toggle(&[], true), Ok(false));
+        assert_eq!(parse_toggle(&["on"], true), Ok(true));
+        assert_eq!(parse_toggle(&["off"], false), Ok(false));
+        assert!(parse_toggle(&["yes"], false).is_err());
+        assert_eq!(parse_arg(&["2", "x"], 0, 1), Ok(2));
+        assert_eq!(parse_arg(&["2"], 1, 1), Ok(1));
+        assert!(parse_arg(&["2", "x"], 1, 1).is_err());
+    }
+}
@@ -1,12 +1,20 @@
 use std::cell::RefCell;
 use std::rc::Rc;
+use std::time::{Duration, Instant};
+use tracing::trace;
 
-use cgmath::{vec3, InnerSpace, Matrix3, Rad, SquareMatrix};
+use cgmath::{vec3, InnerSpace, Matrix3, Point3, Rad, SquareMatrix, Vector3};
+use rand::Rng;
 
+use super::evasion::{Maneuver, Threat};
 use super::pathfinding::ComputedPath;
-use super::{Movement, MovementControl, PlayerActionState, PlayerIterator};
+use super::patrol::Waypoints;
+use super::{
+    Movement, MovementControl, PlayerActionState, PlayerIterator, Projectile,
+};
 use crate::cg_support::node;
 use crate::collisions::CollisionTree;
+use crate::graphics_engine::clock;
 use crate::physics;
 
 #[derive(Clone)]
@@ -30,23 +38,289 @@ pub trait BTNode {
     ) -> ActionResult;
 }
 
+/// Tunable parameters of an AI's behavior, which make it an easier or
+/// harder opponent
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct AiProfile {
+    /// How eager the AI is to attack, from `0` to `1`. More aggressive AIs
+    /// fire more often and open fire from further away
+    pub aggression: f64,
+    /// Maximum angle a shot can deviate from the target
+    pub accuracy_spread: Rad<f64>,
+    /// How long the AI must be aimed at a target before it starts shooting
+    pub reaction_delay: Duration,
+    /// Distance from the target the AI stops approaching it at
+    pub engagement_range: f64,
+}
+
+impl AiProfile {
+    /// An inaccurate, slow to react opponent that keeps its distance
+    pub const fn easy() -> Self {
+        Self {
+            aggression: 0.25,
+            accuracy_spread: Rad(0.12),
+            reaction_delay: Duration::from_millis(800),
+            engagement_range: 140.,
+        }
+    }
+
+    pub const fn normal() -> Self {
+        Self {
+            aggression: 0.5,
+            accuracy_spread: Rad(0.04),
+            reaction_delay: Duration::from_millis(350),
+            engagement_range: 100.,
+        }
+    }
+
+    /// An accurate opponent that reacts quickly and fights up close
+    pub const fn ace() -> Self {
+        Self {
+            aggression: 0.85,
+            accuracy_spread: Rad(0.01),
+            reaction_delay: Duration::from_millis(100),
+            engagement_range: 70.,
+        }
+    }
+
+    /// Minimum time between shots
+    pub fn fire_interval(&self) -> Duration {
+        Duration::from_secs_f64((1. - self.aggression).max(0.15))
+    }
+
+    /// Distance within which the AI will shoot at a target
+    pub fn fire_range(&self) -> f64 {
+        self.aggression.mul_add(300., self.engagement_range)
+    }
+}
+
+impl Default for AiProfile {
+    fn default() -> Self {
+        Self::normal()
+    }
+}
+
+/// The part a ship plays in its squad
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SquadRole {
+    /// Picks the squad's target, and is always free to attack it
+    Leader,
+    /// Holds a formation slot next to the leader until it's the wingman's
+    /// turn to make an attack run. Wingmen are numbered from `0`
+    Wingman(usize),
+}
+
+/// The last known state of a squad member
+struct SquadMemberState {
+    /// `None` while the member is dead
+    pos: Option<Point3<f64>>,
+    forward: Vector3<f64>,
+}
+
+/// Coordinates a group of AI ships so that they don't dogpile their target
+/// or collide with each other
+///
+/// The first living member of the squad is its leader, whose target is
+/// shared with every member. Wingmen fly in a V formation behind the leader
+/// and take turns making attack runs, so that only one wingman at a time
+/// leaves the formation to attack
+pub struct Squad {
+    members: Vec<SquadMemberState>,
+    /// Node id and last known location of the squad's target
+    target: Option<(usize, Point3<f64>)>,
+    /// When the squad started attacking its current target
+    engaged_at: Option<Instant>,
+    spacing: f64,
+    attack_run: Duration,
+}
+
+impl Squad {
+    /// Creates a squad without any members whose wingmen are spaced
+    /// `spacing` apart in formation
+    pub const fn new(spacing: f64) -> Self {
+        Self {
+            members: Vec::new(),
+            target: None,
+            engaged_at: None,
+            spacing,
+            attack_run: Duration::from_secs(4),
+        }
+    }
+
+    /// Sets how long each wingman's attack run lasts
+    #[must_use]
+    #[allow(dead_code)]
+    pub const fn attack_run(mut self, duration: Duration) -> Self {
+        self.attack_run = duration;
+        self
+    }
+
+    /// Adds a member to the squad, returning its member id
+    ///
+    /// The member doesn't have a role until its position is reported
+    pub(super) fn join(&mut self) -> usize {
+        self.members.push(SquadMemberState {
+            pos: None,
+            forward: vec3(0., 0., 1.),
+        });
+        self.members.len() - 1
+    }
+
+    /// Updates the position and facing direction of `member`
+    pub(super) fn report(
+        &mut self,
+        member: usize,
+        pos: Point3<f64>,
+        forward: Vector3<f64>,
+    ) {
+        let state = &mut self.members[member];
+        state.pos = Some(pos);
+        if forward.magnitude2() > f64::EPSILON {
+            state.forward = forward.normalize();
+        }
+    }
+
+    /// Removes `member` from the formation until its position is reported
+    /// again
+    pub(super) fn report_death(&mut self, member: usize) {
+        self.members[member].pos = None;
+    }
+
+    fn living_members(&self) -> impl Iterator<Item = usize> + '_ {
+        self.members
+            .iter()
+            .enumerate()
+            .filter(|(_, state)| state.pos.is_some())
+            .map(|(idx, _)| idx)
+    }
+
+    /// Gets the role of `member`, or `None` if it's dead
+    pub fn role(&self, member: usize) -> Option<SquadRole> {
+        self.living_members()
+            .position(|idx| idx == member)
+            .map(|pos| {
+                if pos == 0 {
+                    SquadRole::Leader
+                } else {
+                    SquadRole::Wingman(pos - 1)
+                }
+            })
+    }
+
+    /// Reports that `member` sees the target `target_id` at `location`
+    ///
+    /// The leader changes the squad's target, but other members can only
+    /// update the location of the squad's target, or pick a target if the
+    /// squad doesn't have one
+    pub(super) fn share_target(
+        &mut self,
+        member: usize,
+        target_id: usize,
+        location: Point3<f64>,
+        now: Instant,
+    ) {
+        match self.target {
+            Some((id, _)) if id == target_id => {
+                self.target = Some((id, location));
+            }
+            Some(_) if self.role(member) != Some(SquadRole::Leader) => (),
+            _ => {
+                self.target = Some((target_id, location));
+                self.engaged_at = Some(now);
+            }
+        }
+    }
+
+    /// Gets the node id of the squad's target
+    pub fn target(&self) -> Option<usize> {
+        self.target.map(|(id, _)| id)
+    }
+
+    /// Gets the point wingman `wingman` should hold in formation, or `None` if
+    /// the squad has no leader
+    ///
+    /// Wingmen alternate between the leader's right and left, with each pair
+    /// further behind the leader than the last
+    pub fn formation_slot(&self, wingman: usize) -> Option<Point3<f64>> {
+        let leader = &self.members[self.living_members().next()?];
+        let up = vec3(0., 1., 0.);
+        let dir = leader.forward;
+        let side = if up.cross(dir).magnitude2() > f64::EPSILON {
+            up.cross(dir).normalize()
+        } else {
+            vec3(1., 0., 0.)
+        };
+        let rank = (wingman / 2 + 1) as f64 * self.spacing;
+        let side_sign = if wingman % 2 == 0 { -1. } else { 1. };
+        leader
+            .pos
+            .map(|pos| pos + side * side_sign * rank - dir * rank)
+    }
+
+    /// Determines if a member with `role` should attack the squad's target
+    /// at time `now`
+    ///
+    /// The leader is always cleared to attack. Wingmen are cleared one at a
+    /// time, in order, for an attack run each
+    pub fn cleared_to_attack(&self, role: SquadRole, now: Instant) -> bool {
+        match (role, self.engaged_at) {
+            (SquadRole::Leader, _) => true,
+            (SquadRole::Wingman(_), None) => false,
+            (SquadRole::Wingman(wingman), Some(start)) => {
+                let wingmen = self.living_members().count().saturating_sub(1);
+                let runs = now.saturating_duration_since(start).as_secs_f64()
+                    / self.attack_run.as_secs_f64().max(f64::EPSILON);
+                wingmen > 0 && runs as usize % wingmen == wingman
+            }
+        }
+    }
+}
+
 pub struct Blackboard {
     pub(super) target_location: Option<cgmath::Point3<f64>>,
     pub(super) computed_path: Option<ComputedPath>,
     pub(super) target_id: Option<usize>,
     pub(super) path_target_location: Option<cgmath::Point3<f64>>,
     pub(super) rot: Option<Matrix3<f64>>,
+    /// Rotation, relative to looking straight at the target, that the AI
+    /// aims its next shot with
+    pub(super) aim_error: Option<Matrix3<f64>>,
+    pub(super) profile: AiProfile,
+    /// The squad the player belongs to and its member id
+    pub(super) squad: Option<(Rc<RefCell<Squad>>, usize)>,
+    /// `false` if the player is holding formation and shouldn't attack
+    pub(super) weapons_free: bool,
+    /// The route the player patrols while it isn't engaging a target
+    pub(super) waypoints: Waypoints,
+    /// `false` if the player is patrolling and shouldn't attack its target
+    pub(super) engaging: bool,
+    /// Lasers in flight as of the last frame
+    pub(super) projectiles: Vec<Projectile>,
+    /// The most imminent laser heading toward the player
+    pub(super) threat: Option<Threat>,
+    /// The evasive maneuver the player is making
+    pub(super) maneuver: Option<Maneuver>,
 }
 
 impl Blackboard {
-    /// Creates a new blackboard for the player
-    pub const fn new() -> Self {
+    /// Creates a new blackboard for a player which behaves according to
+    /// `profile`
+    pub const fn new(profile: AiProfile) -> Self {
         Self {
             target_location: None,
             computed_path: None,
             target_id: None,
             path_target_location: None,
             rot: None,
+            aim_error: None,
+            profile,
+            squad: None,
+            weapons_free: true,
+            waypoints: Waypoints::new(Vec::new()),
+            engaging: true,
+            projectiles: Vec::new(),
+            threat: None,
+            maneuver: None,
         }
     }
 }
@@ -163,22 +437,43 @@ impl BTNode for ParallelSequence {
 /// A node that succeeds if the player is looking near
 /// another player
 ///
-/// On success, returns a controller action to fire a laser
-/// if the last time this node fired was more than 0.5 seconds ago
+/// On success, returns a controller action to fire a laser once the player
+/// has been aimed at the target for the profile's reaction delay, and the
+/// last time this node fired was more than the profile's fire interval ago
 pub struct ShootIfAble {
     last_time: std::time::Instant,
+    /// How long the player has been aimed at a target
+    aimed_time: Duration,
 }
 
 impl ShootIfAble {
+    /// Angle between the player's direction and a target that the player is
+    /// still considered to be aiming at the target within, not counting the
+    /// profile's accuracy spread
+    const AIM_TOLERANCE: Rad<f64> = Rad(0.1);
+
     pub fn new() -> Self {
         Self {
-            last_time: std::time::Instant::now(),
+            last_time: clock::now(),
+            aimed_time: Duration::default(),
         }
     }
 
+    /// Gets a random rotation of at most `spread` to aim a shot with
+    fn random_aim_error(spread: Rad<f64>) -> Matrix3<f64> {
+        let mut rng = rand::thread_rng();
+        let angle = spread * rng.gen::<f64>();
+        let axis_angle: f64 = rng.gen_range(0. ..std::f64::consts::TAU);
+        Matrix3::from_axis_angle(
+            vec3(axis_angle.cos(), axis_angle.sin(), 0.),
+            angle,
+        )
+    }
+
     fn is_aimed_at(
         controlled: &physics::BaseRigidBody,
         target: &Rc<RefCell<node::Node>>,
+        profile: &AiProfile,
     ) -> bool {
         let controlled_pos = controlled.transform.borrow().get_pos();
         let target_pos = target.borrow().get_pos();
@@ -190,8 +485,9 @@ impl ShootIfAble {
         let controlled_to_target_dir = controlled_to_target.normalize();
         let controlled_to_target_angle =
             controlled_dir.angle(controlled_to_target_dir);
-        controlled_to_target_angle < cgmath::Rad(0.1)
-            && (controlled_pos - target_pos).magnitude() < 250.
+        controlled_to_target_angle
+            < Self::AIM_TOLERANCE + profile.accuracy_spread
+            && (controlled_pos - target_pos).magnitude() < profile.fire_range()
     }
 }
 
@@ -199,23 +495,32 @@ impl BTNode for ShootIfAble {
     fn tick<'a>(
         &mut self,
         _children: &mut [BehaviorTree],
-        _blackboard: &mut Blackboard,
+        blackboard: &mut Blackboard,
         _scene: &CollisionTree,
         player: &physics::BaseRigidBody,
-        _dt: std::time::Duration,
+        dt: std::time::Duration,
         other_players: PlayerIterator<'a>,
     ) -> ActionResult {
+        let profile = blackboard.profile;
         let is_aiming = other_players
             .copy()
-            .any(|other| Self::is_aimed_at(player, &other));
-        if !is_aiming {
+            .any(|other| Self::is_aimed_at(player, &other, &profile));
+        if !is_aiming || !blackboard.weapons_free || !blackboard.engaging {
+            self.aimed_time = Duration::default();
             return ActionResult::Failure;
         }
-        if self.last_time.elapsed().as_secs_f32() > 0.5 {
-            self.last_time = std::time::Instant::now();
+        self.aimed_time += dt;
+        if self.aimed_time >= profile.reaction_delay
+            && clock::now().duration_since(self.last_time)
+                > profile.fire_interval()
+        {
+            self.last_time = clock::now();
+            blackboard.aim_error =
+                Some(Self::random_aim_error(profile.accuracy_spread));
             ActionResult::Success(Some(super::ControllerAction {
                 fire: true,
                 velocity: vec3(0., 0., 0.),
+                afterburner: false,
             }))
         } else {
             ActionResult::Success(None)
@@ -288,21 +593,93 @@ impl BTNode for TriggerRecomputePath {
     }
 }
 
+/// A node that coordinates the player with its squad, if it's in one
+///
+/// Shares the player's target with the squad, and adopts the squad's
+/// target. Wingmen that aren't cleared to attack have their target location
+/// replaced with their formation slot and hold their fire
+///
+/// Always succeeds
+pub struct CoordinateWithSquad {}
+
+impl BTNode for CoordinateWithSquad {
+    fn tick<'a>(
+        &mut self,
+        _children: &mut [BehaviorTree],
+        blackboard: &mut Blackboard,
+        _scene: &CollisionTree,
+        player: &physics::BaseRigidBody,
+        _dt: std::time::Duration,
+        _other_players: PlayerIterator<'a>,
+    ) -> ActionResult {
+        let (squad, member) = match &blackboard.squad {
+            Some((squad, member)) => (squad.clone(), *member),
+            None => return ActionResult::Success(None),
+        };
+        let mut squad = squad.borrow_mut();
+        let now = clock::now();
+        {
+            let transform = player.transform.borrow();
+            squad.report(
+                member,
+                transform.get_pos(),
+                transform.transform_vec(vec3(0., 0., 1.)),
+            );
+        }
+        if let (Some(id), Some(location)) =
+            (blackboard.target_id, blackboard.target_location)
+        {
+            squad.share_target(member, id, location, now);
+        }
+        if let Some(target) = squad.target() {
+            blackboard.target_id = Some(target);
+        }
+        let role = squad.role(member);
+        blackboard.weapons_free =
+            role.map_or(true, |role| squad.cleared_to_attack(role, now));
+        if let (false, Some(SquadRole::Wingman(wingman))) =
+            (blackboard.weapons_free, role)
+        {
+            if let Some(slot) = squad.formation_slot(wingman) {
+                blackboard.target_location = Some(slot);
+            }
+        }
+        ActionResult::Success(None)
+    }
+}
+
 pub struct AIController {
     pub(super) behavior_tree: BehaviorTree,
     pub(super) blackboard: Blackboard,
     last_action_state: PlayerActionState,
+    /// `true` if the last action used the afterburner
+    afterburning: bool,
 }
 
 impl AIController {
-    /// Creates a new AI controller for the player
-    pub const fn new(behavior_tree: BehaviorTree) -> Self {
+    /// Creates a new AI controller for the player which behaves according
+    /// to `profile`
+    pub const fn new(behavior_tree: BehaviorTree, profile: AiProfile) -> Self {
         Self {
             behavior_tree,
-            blackboard: Blackboard::new(),
+            blackboard: Blackboard::new(profile),
             last_action_state: PlayerActionState::Idle,
+            afterburning: false,
         }
     }
+
+    /// Adds the player to `squad`
+    pub fn join_squad(&mut self, squad: &Rc<RefCell<Squad>>) {
+        let member = squad.borrow_mut().join();
+        self.blackboard.squad = Some((squad.clone(), member));
+    }
+
+    /// Sets the route the player patrols while it isn't engaging a target
+    #[must_use]
+    pub fn with_waypoints(mut self, waypoints: Vec<Point3<f64>>) -> Self {
+        self.blackboard.waypoints = Waypoints::new(waypoints);
+        self
+    }
 }
 
 impl MovementControl for AIController {
@@ -334,12 +711,29 @@ impl MovementControl for AIController {
         0.
     }
 
+    fn is_afterburning(&self) -> bool {
+        self.afterburning
+    }
+
     fn transition_action_state(&mut self) {
         // TODO
     }
 
+    fn observe_projectiles(&mut self, projectiles: &[Projectile]) {
+        self.blackboard.projectiles.clear();
+        self.blackboard.projectiles.extend_from_slice(projectiles);
+    }
+
     fn on_death(&mut self) {
-        self.blackboard = Blackboard::new();
+        let squad = self.blackboard.squad.take();
+        if let Some((squad, member)) = &squad {
+            squad.borrow_mut().report_death(*member);
+        }
+        let mut waypoints = std::mem::take(&mut self.blackboard.waypoints);
+        waypoints.restart();
+        self.blackboard = Blackboard::new(self.blackboard.profile);
+        self.blackboard.squad = squad;
+        self.blackboard.waypoints = waypoints;
     }
 
     fn on_frame_update<'a>(
@@ -357,9 +751,11 @@ impl MovementControl for AIController {
             other_players,
         ) {
             ActionResult::Running(action) | ActionResult::Success(action) => {
+                self.afterburning =
+                    action.as_ref().map_or(false, |x| x.afterburner);
                 if action.as_ref().map_or(false, |x| x.fire) {
                     self.last_action_state = PlayerActionState::Fire;
-                    println!("Fire state");
+                    trace!("Fire state");
                 } else {
                     self.last_action_state = PlayerActionState::Idle;
                 }
@@ -367,8 +763,85 @@ impl MovementControl for AIController {
             }
             ActionResult::Failure => {
                 self.last_action_state = PlayerActionState::Idle;
+                self.afterburning = false;
                 None
             }
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn harder_profiles_are_more_dangerous() {
+        let (easy, normal, ace) =
+            (AiProfile::easy(), AiProfile::normal(), AiProfile::ace());
+        assert_eq!(AiProfile::default(), normal);
+        assert_eq!(normal.fire_interval(), Duration::from_millis(500));
+        assert!(easy.fire_interval() > normal.fire_interval());
+        assert!(normal.fire_interval() > ace.fire_interval());
+        assert!(easy.accuracy_spread > ace.accuracy_spread);
+        assert!(easy.reaction_delay > ace.reaction_delay);
+        assert!(ace.fire_range() > easy.fire_range());
+    }
+
+    #[test]
+    fn squad_assigns_roles_and_formation() {
+        let mut squad = Squad::new(10.);
+        let (a, b, c) = (squad.join(), squad.join(), squad.join());
+        assert_eq!(squad.role(a), None);
+        squad.report(b, Point3::new(0., 0., 0.), vec3(0., 0., 1.));
+        squad.report(c, Point3::new(5., 0., 0.), vec3(0., 0., 1.));
+        assert_eq!(squad.role(b), Some(SquadRole::Leader));
+        assert_eq!(squad.role(c), Some(SquadRole::Wingman(0)));
+        squad.report(a, Point3::new(0., 0., 0.), vec3(0., 0., 1.));
+        assert_eq!(squad.role(a), Some(SquadRole::Leader));
+        assert_eq!(squad.role(c), Some(SquadRole::Wingman(1)));
+        // wingmen are behind the leader on alternating sides
+        let first = squad.formation_slot(0).unwrap();
+        let second = squad.formation_slot(1).unwrap();
+        assert!(first.z < 0. && second.z < 0.);
+        assert!(first.x * second.x < 0.);
+        assert!((first - Point3::new(0., 0., 0.)).magnitude() > 10.);
+        squad.report_death(a);
+        assert_eq!(squad.role(a), None);
+        assert_eq!(squad.role(b), Some(SquadRole::Leader));
+    }
+
+    #[test]
+    fn squad_shares_target_and_staggers_attacks() {
+        let mut squad = Squad::new(10.).attack_run(Duration::from_secs(1));
+        let members: Vec<_> = (0..3).map(|_| squad.join()).collect();
+        for member in &members {
+            squad.report(*member, Point3::new(0., 0., 0.), vec3(0., 0., 1.));
+        }
+        let start = Instant::now();
+        assert!(!squad.cleared_to_attack(SquadRole::Wingman(0), start));
+        squad.share_target(members[2], 7, Point3::new(0., 0., 50.), start);
+        assert_eq!(squad.target(), Some(7));
+        // a wingman can't change the squad's target, but the leader can
+        squad.share_target(members[1], 8, Point3::new(0., 0., 50.), start);
+        assert_eq!(squad.target(), Some(7));
+        squad.share_target(members[0], 8, Point3::new(0., 0., 50.), start);
+        assert_eq!(squad.target(), Some(8));
+        let at = |secs| start + Duration::from_millis(secs);
+        assert!(squad.cleared_to_attack(SquadRole::Leader, at(0)));
+        assert!(squad.cleared_to_attack(SquadRole::Wingman(0), at(500)));
+        assert!(!squad.cleared_to_attack(SquadRole::Wingman(1), at(500)));
+        assert!(!squad.cleared_to_attack(SquadRole::Wingman(0), at(1500)));
+        assert!(squad.cleared_to_attack(SquadRole::Wingman(1), at(1500)));
+        assert!(squad.cleared_to_attack(SquadRole::Wingman(0), at(2500)));
+    }
+
+    #[test]
+    fn aim_error_is_within_spread() {
+        let spread = AiProfile::easy().accuracy_spread;
+        for _ in 0..100 {
+            let error = ShootIfAble::random_aim_error(spread);
+            let dir = error * vec3(0., 0., 1.);
+            assert!(dir.angle(vec3(0., 0., 1.)) <= spread + Rad(1e-6));
+        }
+    }
+}
@@ -0,0 +1,347 @@
+use super::ai::{ActionResult, AiProfile, BehaviorTree, Blackboard};
+use super::avoidance;
+use super::pathfinding::player_ids;
+use super::patrol::WAYPOINT_ARRIVAL_DIST;
+use super::{
+    ControllerAction, Movement, MovementControl, PlayerActionState,
+    PlayerIterator,
+};
+use crate::cg_support::{self, node};
+use crate::collisions::CollisionTree;
+use crate::physics;
+use crate::station;
+use cgmath::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Fastest the autopilot flies
+const CRUISE_SPEED: f64 = 60.;
+/// Rate the autopilot speeds up at
+const ACCELERATION: f64 = 20.;
+/// Rate the autopilot slows down at as it nears the end of a leg
+const DECELERATION: f64 = 15.;
+/// Slowest the autopilot flies before it arrives, so it doesn't creep up on
+/// the end of a leg forever
+const MIN_SPEED: f64 = 3.;
+/// Distance from a target the autopilot stops at
+const TARGET_STANDOFF: f64 = 60.;
+/// Speed the autopilot flies into a station's approach corridor at, which
+/// is slow enough to dock
+const DOCKING_APPROACH_SPEED: f64 = station::MAX_DOCKING_SPEED * 0.6;
+
+/// What the player asked the autopilot to fly to, which the game turns into
+/// a `Destination`
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum AutopilotRequest {
+    /// The character the player is locked on to
+    LockedTarget,
+    /// The docking bay of the map's station
+    Dock,
+}
+
+/// Where the autopilot flies to
+pub enum Destination {
+    /// A fixed point
+    Waypoint(Point3<f64>),
+    /// A body, which the autopilot follows until it's `TARGET_STANDOFF`
+    /// away from it
+    Target(Rc<RefCell<node::Node>>),
+    /// The points a station's approach corridor is flown through, in order.
+    /// The autopilot flies the last leg slowly enough for the station to
+    /// take over and dock the player
+    Docking(Vec<Point3<f64>>),
+}
+
+/// Flies a player to a destination with the same navigation AI controllers
+/// use, so the player is steered around obstacles
+///
+/// The autopilot speeds up and slows down gradually, facing the way it
+/// flies. It disengages once it arrives, and never fires
+pub struct Autopilot {
+    destination: Destination,
+    /// Index of the docking approach point being flown to
+    leg: usize,
+    /// Speed of the last action, or `None` before the first one
+    speed: Option<f64>,
+    engaged: bool,
+    tree: BehaviorTree,
+    blackboard: Blackboard,
+}
+
+impl Autopilot {
+    /// Creates an engaged autopilot which flies to `destination`
+    pub fn new(destination: Destination) -> Self {
+        let mut blackboard = Blackboard::new(AiProfile::default());
+        blackboard.engaging = false;
+        Self {
+            destination,
+            leg: 0,
+            speed: None,
+            engaged: true,
+            tree: super::get_navigate_tree(),
+            blackboard,
+        }
+    }
+
+    /// Returns `false` once the autopilot arrived or was disengaged
+    pub const fn is_engaged(&self) -> bool {
+        self.engaged
+    }
+
+    /// Gets the point the autopilot is currently flying to
+    pub fn waypoint(&self) -> Point3<f64> {
+        match &self.destination {
+            Destination::Waypoint(pt) => *pt,
+            Destination::Target(node) => node.borrow().get_pos(),
+            Destination::Docking(route) => route[self.leg.min(route.len() - 1)],
+        }
+    }
+
+    /// `true` if the autopilot is flying the last leg of its route
+    fn on_last_leg(&self) -> bool {
+        match &self.destination {
+            Destination::Docking(route) => self.leg + 1 >= route.len(),
+            _ => true,
+        }
+    }
+
+    /// Distance from the waypoint the current leg ends at
+    fn arrival_dist(&self) -> f64 {
+        match self.destination {
+            Destination::Target(_) => TARGET_STANDOFF,
+            _ => WAYPOINT_ARRIVAL_DIST,
+        }
+    }
+
+    /// Gets the fastest the autopilot can fly during the current leg, and
+    /// the speed it should be flying at when the leg ends
+    fn leg_speeds(&self) -> (f64, f64) {
+        match self.destination {
+            Destination::Docking(_) if self.on_last_leg() => {
+                (DOCKING_APPROACH_SPEED, DOCKING_APPROACH_SPEED)
+            }
+            Destination::Docking(_) => (CRUISE_SPEED, DOCKING_APPROACH_SPEED),
+            _ => (CRUISE_SPEED, 0.),
+        }
+    }
+}
+
+/// Gets the speed to fly at `dt` after flying at `speed`, which speeds up
+/// towards `cruise` and slows down in time to be flying at `end_speed` once
+/// it has travelled `dist`
+fn profile_speed(
+    speed: f64,
+    dist: f64,
+    cruise: f64,
+    end_speed: f64,
+    dt: std::time::Duration,
+) -> f64 {
+    let braking = (2. * DECELERATION).mul_add(dist, end_speed * end_speed);
+    ACCELERATION
+        .mul_add(dt.as_secs_f64(), speed)
+        .min(cruise)
+        .min(braking.sqrt())
+        .max(MIN_SPEED.min(cruise))
+}
+
+impl MovementControl for Autopilot {
+    fn get_movement(&self) -> Movement {
+        // the velocity is set by the actions, like an AI controller
+        Movement::Stopped
+    }
+
+    fn get_roll(&self) -> f64 {
+        0.
+    }
+
+    fn get_pitch(&self) -> f64 {
+        0.
+    }
+
+    fn get_action_state(&self) -> PlayerActionState {
+        PlayerActionState::Idle
+    }
+
+    fn get_transparency_fac(&mut self) -> f32 {
+        0.
+    }
+
+    fn transition_action_state(&mut self) {}
+
+    /// Steers `player` along a path to the waypoint, moving on to the next
+    /// leg of the route when it arrives
+    ///
+    /// Returns the action moving the player, a stopping action once it
+    /// arrives at its destination, or `None` if the autopilot is disengaged
+    /// or there is no path to the waypoint
+    fn on_frame_update(
+        &mut self,
+        scene: &CollisionTree,
+        player: &physics::BaseRigidBody,
+        dt: std::time::Duration,
+        other_players: PlayerIterator,
+    ) -> Option<ControllerAction> {
+        if !self.engaged {
+            return None;
+        }
+        let pos = player.transform.borrow().get_pos();
+        if pos.distance(self.waypoint()) < self.arrival_dist() {
+            if self.on_last_leg() {
+                self.engaged = false;
+                return Some(ControllerAction {
+                    velocity: vec3(0., 0., 0.),
+                    fire: false,
+                    afterburner: false,
+                });
+            }
+            self.leg += 1;
+        }
+        let waypoint = self.waypoint();
+        self.blackboard.target_location = Some(waypoint);
+        let action = match self.tree.tick(
+            &mut self.blackboard,
+            scene,
+            player,
+            dt,
+            other_players,
+        ) {
+            ActionResult::Running(action) | ActionResult::Success(action) => {
+                action
+            }
+            ActionResult::Failure => None,
+        }?;
+        let (cruise, end_speed) = self.leg_speeds();
+        let speed = profile_speed(
+            self.speed.unwrap_or_else(|| player.velocity.magnitude()),
+            (pos.distance(waypoint) - self.arrival_dist()).max(0.),
+            cruise,
+            end_speed,
+            dt,
+        );
+        self.speed = Some(speed);
+        // the navigation only looks out for obstacles at its own speed
+        let dir = avoidance::steer(
+            scene,
+            player,
+            if action.velocity.magnitude2() > f64::EPSILON {
+                action.velocity
+            } else {
+                waypoint - pos
+            },
+            speed,
+            &player_ids(player, other_players),
+        );
+        if let Some(rot) = cg_support::look_at(dir, &vec3(0., 1., 0.)) {
+            self.blackboard.rot = Some(rot);
+        }
+        Some(ControllerAction {
+            velocity: dir * speed,
+            fire: false,
+            afterburner: false,
+        })
+    }
+
+    fn on_death(&mut self) {
+        self.engaged = false;
+    }
+
+    /// Gets the rotation which faces the player the way it's flying, or
+    /// `None` if the autopilot hasn't flown yet
+    fn get_snapped_rot(&self) -> Option<Matrix3<f64>> {
+        self.blackboard.rot
+    }
+
+    fn is_ai(&self) -> bool {
+        false
+    }
+
+    fn on_docking(&mut self) {
+        self.engaged = false;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::controls::PlayerIteratorHolder;
+    use assert_approx_eq::assert_approx_eq;
+    use std::time::Duration;
+
+    /// Ticks `autopilot` once, flying `body` from where it is
+    fn tick(
+        autopilot: &mut Autopilot,
+        scene: &CollisionTree,
+        body: &physics::BaseRigidBody,
+    ) -> Option<ControllerAction> {
+        let others =
+            PlayerIteratorHolder(std::iter::empty::<Rc<RefCell<node::Node>>>());
+        autopilot.on_frame_update(
+            scene,
+            body,
+            Duration::from_millis(16),
+            &others,
+        )
+    }
+
+    #[test]
+    fn autopilot_flies_to_waypoint() {
+        let scene = CollisionTree::new(point3(0., 0., 0.), 200.);
+        let body = physics::BaseRigidBody::new(Rc::new(RefCell::new(
+            node::Node::default(),
+        )));
+        let mut autopilot =
+            Autopilot::new(Destination::Waypoint(point3(100., 0., 0.)));
+        let action = tick(&mut autopilot, &scene, &body).unwrap();
+        assert!(!action.fire);
+        assert!(action.velocity.normalize().x > 0.9);
+        // starts from rest
+        assert_approx_eq!(action.velocity.magnitude(), MIN_SPEED);
+        let forward = autopilot.get_snapped_rot().unwrap() * vec3(0., 0., 1.);
+        assert!(forward.x > 0.9);
+        assert!(autopilot.is_engaged());
+
+        body.transform.borrow_mut().set_pos(point3(95., 0., 0.));
+        let action = tick(&mut autopilot, &scene, &body).unwrap();
+        assert!(action.velocity.magnitude() < f64::EPSILON);
+        assert!(!autopilot.is_engaged());
+        assert!(tick(&mut autopilot, &scene, &body).is_none());
+    }
+
+    #[test]
+    fn speed_ramps_up_and_down() {
+        let dt = Duration::from_millis(500);
+        assert_approx_eq!(profile_speed(0., 1000., 60., 0., dt), 10.);
+        assert_approx_eq!(profile_speed(55., 1000., 60., 0., dt), 60.);
+        // slows down to stop at the end of the leg
+        let near = profile_speed(60., 30., 60., 0., dt);
+        assert_approx_eq!(near, (2. * DECELERATION * 30.).sqrt());
+        assert_approx_eq!(profile_speed(60., 0., 60., 0., dt), MIN_SPEED);
+        // or to fly into a docking corridor
+        assert_approx_eq!(
+            profile_speed(60., 0., 60., DOCKING_APPROACH_SPEED, dt),
+            DOCKING_APPROACH_SPEED
+        );
+        assert!(DOCKING_APPROACH_SPEED < station::MAX_DOCKING_SPEED);
+    }
+
+    #[test]
+    fn docking_approach_flies_each_leg() {
+        let scene = CollisionTree::new(point3(0., 0., 0.), 400.);
+        let body = physics::BaseRigidBody::new(Rc::new(RefCell::new(
+            node::Node::default(),
+        )));
+        let route = vec![point3(0., 0., 200.), point3(0., 0., 100.)];
+        let mut autopilot = Autopilot::new(Destination::Docking(route));
+        tick(&mut autopilot, &scene, &body).unwrap();
+        assert_approx_eq!(autopilot.waypoint().z, 200.);
+
+        body.transform.borrow_mut().set_pos(point3(0., 0., 195.));
+        let action = tick(&mut autopilot, &scene, &body).unwrap();
+        assert_approx_eq!(autopilot.waypoint().z, 100.);
+        assert!(action.velocity.z < 0.);
+        assert!(action.velocity.magnitude() <= DOCKING_APPROACH_SPEED);
+        assert!(autopilot.is_engaged());
+        autopilot.on_docking();
+        assert!(!autopilot.is_engaged());
+    }
+}
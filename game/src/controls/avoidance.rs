@@ -0,0 +1,195 @@
+use crate::collisions::CollisionTree;
+use crate::physics::BaseRigidBody;
+use cgmath::*;
+use std::collections::HashSet;
+
+/// Angle between the forward whisker and the whiskers around it
+const WHISKER_ANGLE: Deg<f64> = Deg(35.);
+/// Seconds of flight at the ship's speed that the whiskers reach ahead
+const LOOKAHEAD_TIME: f64 = 2.;
+/// Shortest the whiskers are, so slow ships still see obstacles coming
+const MIN_LOOKAHEAD: f64 = 30.;
+/// Radius swept by the whiskers of a ship without a collider
+const DEFAULT_RADIUS: f64 = 3.;
+
+/// Gets the unit directions of the whiskers of a ship flying along the unit
+/// direction `dir`. The forward whisker is first, followed by the whiskers
+/// tilted up, down, left, and right of it
+fn whiskers(dir: Vector3<f64>) -> [Vector3<f64>; 5] {
+    let side = if dir.cross(vec3(0., 1., 0.)).magnitude2() > 1e-6 {
+        dir.cross(vec3(0., 1., 0.)).normalize()
+    } else {
+        dir.cross(vec3(1., 0., 0.)).normalize()
+    };
+    let up = side.cross(dir);
+    let (sin, cos) = WHISKER_ANGLE.sin_cos();
+    [
+        dir,
+        dir * cos + up * sin,
+        dir * cos - up * sin,
+        dir * cos - side * sin,
+        dir * cos + side * sin,
+    ]
+}
+
+/// Sweeps a sphere of `radius` from `start` along the unit direction `dir`
+/// for `length`, against the bounding sphere at `center` of `bound_radius`
+///
+/// Returns the fraction of `length` the sphere travels before touching the
+/// bounding sphere, or `None` if it doesn't touch it
+fn sweep_sphere(
+    start: Point3<f64>,
+    dir: Vector3<f64>,
+    length: f64,
+    radius: f64,
+    center: Point3<f64>,
+    bound_radius: f64,
+) -> Option<f64> {
+    let offset = start - center;
+    let reach = radius + bound_radius;
+    let c = offset.magnitude2() - reach * reach;
+    if c <= 0. {
+        return Some(0.);
+    }
+    let b = offset.dot(dir);
+    let discriminant = b.mul_add(b, -c);
+    if b > 0. || discriminant < 0. {
+        return None;
+    }
+    let t = -b - discriminant.sqrt();
+    if t <= length {
+        Some(t / length)
+    } else {
+        None
+    }
+}
+
+/// Steers `ship`, flying along `dir` at `speed`, around the obstacles in
+/// `scene` ahead of it
+///
+/// Whiskers are swept from the ship along `dir` and tilted around it. The
+/// closer the nearest obstacle they touch, the more the ship is turned
+/// towards the whisker which is clear the furthest, preferring to keep
+/// going straight
+///
+/// `ignore` - node ids of the bodies which aren't obstacles, such as the
+/// ship itself
+///
+/// Returns the unit direction to fly along, which is `dir` if nothing is in
+/// the way
+pub fn steer(
+    scene: &CollisionTree,
+    ship: &BaseRigidBody,
+    dir: Vector3<f64>,
+    speed: f64,
+    ignore: &HashSet<usize>,
+) -> Vector3<f64> {
+    if dir.magnitude2() < f64::EPSILON {
+        return dir;
+    }
+    let dir = dir.normalize();
+    let pos = ship.center();
+    let radius = ship.extents().unwrap_or(DEFAULT_RADIUS);
+    let length = (speed * LOOKAHEAD_TIME).max(MIN_LOOKAHEAD);
+    let obstacles: Vec<_> = scene
+        .test_for_collisions(pos, length + radius)
+        .into_iter()
+        .filter(|obj| !ignore.contains(&obj.node_id()))
+        .map(|obj| obj.bounding_sphere())
+        .collect();
+    if obstacles.is_empty() {
+        return dir;
+    }
+    let clearances = whiskers(dir).map(|whisker| {
+        let clear = obstacles
+            .iter()
+            .filter_map(|(center, bound_radius)| {
+                sweep_sphere(
+                    pos,
+                    whisker,
+                    length,
+                    radius,
+                    *center,
+                    *bound_radius,
+                )
+            })
+            .fold(1., f64::min);
+        (whisker, clear)
+    });
+    let nearest = clearances
+        .iter()
+        .map(|(_, clear)| *clear)
+        .fold(1., f64::min);
+    // the first whisker wins ties, which keeps the ship going straight
+    let (escape, _) = clearances.iter().fold(clearances[0], |best, whisker| {
+        if whisker.1 > best.1 {
+            *whisker
+        } else {
+            best
+        }
+    });
+    let danger = 1. - nearest;
+    let steered = dir * (1. - danger) + escape * danger;
+    if steered.magnitude2() < f64::EPSILON {
+        escape
+    } else {
+        steered.normalize()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cg_support::node;
+    use crate::collisions::{CollisionObject, TreeStopCriteria};
+    use serial_test::serial;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn spheres_sweep_into_bounds_ahead() {
+        let start = point3(0., 0., 0.);
+        let fwd = vec3(0., 0., 1.);
+        let hit = sweep_sphere(start, fwd, 20., 1., point3(0., 0., 10.), 4.);
+        assert!((hit.unwrap() - 0.25).abs() < 1e-9);
+        // behind, beside, and beyond the sweep
+        assert!(sweep_sphere(start, fwd, 20., 1., point3(0., 0., -10.), 4.)
+            .is_none());
+        assert!(sweep_sphere(start, fwd, 20., 1., point3(6., 0., 10.), 4.)
+            .is_none());
+        assert!(sweep_sphere(start, fwd, 20., 1., point3(0., 0., 30.), 4.)
+            .is_none());
+        assert_eq!(
+            sweep_sphere(start, fwd, 20., 1., point3(1., 0., 0.), 4.),
+            Some(0.)
+        );
+    }
+
+    #[serial]
+    #[test]
+    fn ships_steer_around_obstacles_ahead() {
+        let mut tree = CollisionTree::new(point3(0., 0., 0.), 100.);
+        let obstacle = CollisionObject::new(
+            Rc::new(RefCell::new(
+                node::Node::default().pos(point3(0., 0., 12.)).u_scale(3.),
+            )),
+            "assets/default_cube.obj",
+            TreeStopCriteria::default(),
+        );
+        tree.insert(&obstacle);
+        let ship =
+            BaseRigidBody::new(Rc::new(RefCell::new(node::Node::default())));
+        let ignore = HashSet::new();
+        let fwd = vec3(0., 0., 1.);
+        let steered = steer(&tree, &ship, fwd, 10., &ignore);
+        assert!((steered.magnitude() - 1.).abs() < 1e-9);
+        assert!(steered.z < 0.99);
+        assert!(steered.z > 0.);
+        // nothing is in the way of other directions
+        let side = vec3(1., 0., 0.);
+        assert!(steer(&tree, &ship, side, 10., &ignore).distance(side) < 1e-9);
+        // or of ships the obstacle is ignored by
+        let ignore = std::iter::once(obstacle.node_id()).collect();
+        assert!(steer(&tree, &ship, fwd, 10., &ignore).distance(fwd) < 1e-9);
+    }
+}
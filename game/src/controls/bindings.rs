@@ -0,0 +1,636 @@
+use glutin::event::VirtualKeyCode;
+use shared_types::survival::ShopItem;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::Display;
+
+/// A game action triggered by pressing and releasing a button
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Action {
+    Forward,
+    Backward,
+    Fire,
+    FireRope,
+    /// While held, shortens the grappling line
+    ReelIn,
+    /// While held, lengthens the grappling line
+    ReelOut,
+    ToggleVisibility,
+    ToggleMouseCapture,
+    /// Locks on to the next target in front of the player
+    CycleTarget,
+    /// Turns the camera's soft tracking of the locked target on or off
+    ToggleCameraTracking,
+    /// Switches to the next distribution of power between the ship's
+    /// weapons, engines, and shields
+    CyclePowerDistribution,
+    /// Buys an item from the shop between waves
+    Buy(ShopItem),
+    /// While held, mouse movement orbits the camera instead of turning the
+    /// player
+    FreeLook,
+    ZoomMinimapIn,
+    ZoomMinimapOut,
+    /// Switches between the flat minimap and the 3D radar
+    ToggleRadar,
+    /// While held, tows the asteroid in front of the player with a tractor
+    /// beam
+    TractorBeam,
+    /// While held with forward, boosts the engines' thrust at a high energy
+    /// cost
+    Afterburner,
+    /// Switches between assisted flight, which damps the ship's drift
+    /// towards its heading, and newtonian drift
+    ToggleFlightAssist,
+    /// Shows or hides the top-down tactical map, where clicking sets a
+    /// waypoint for the autopilot
+    ToggleTacticalMap,
+    /// Flies the player on autopilot to the target it's locked on to
+    AutopilotToTarget,
+    /// Flies the player on autopilot down the station's approach corridor
+    /// to dock with it
+    AutopilotDock,
+}
+
+impl Action {
+    /// Every action, in the order they're listed in the client configuration
+    pub const ALL: [Self; 24] = [
+        Self::Forward,
+        Self::Backward,
+        Self::Afterburner,
+        Self::Fire,
+        Self::FireRope,
+        Self::ReelIn,
+        Self::ReelOut,
+        Self::TractorBeam,
+        Self::CycleTarget,
+        Self::CyclePowerDistribution,
+        Self::ToggleFlightAssist,
+        Self::FreeLook,
+        Self::ToggleCameraTracking,
+        Self::ToggleVisibility,
+        Self::ToggleMouseCapture,
+        Self::ZoomMinimapIn,
+        Self::ZoomMinimapOut,
+        Self::ToggleRadar,
+        Self::ToggleTacticalMap,
+        Self::AutopilotToTarget,
+        Self::AutopilotDock,
+        Self::Buy(ShopItem::Repair),
+        Self::Buy(ShopItem::ExtraLife),
+        Self::Buy(ShopItem::FireBoost),
+    ];
+}
+
+impl Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Forward => write!(f, "forward"),
+            Self::Backward => write!(f, "backward"),
+            Self::Fire => write!(f, "fire"),
+            Self::FireRope => write!(f, "fire_rope"),
+            Self::ReelIn => write!(f, "reel_in"),
+            Self::ReelOut => write!(f, "reel_out"),
+            Self::ToggleVisibility => write!(f, "toggle_visibility"),
+            Self::ToggleMouseCapture => write!(f, "toggle_mouse_capture"),
+            Self::CycleTarget => write!(f, "cycle_target"),
+            Self::ToggleCameraTracking => write!(f, "toggle_camera_tracking"),
+            Self::CyclePowerDistribution => write!(f, "cycle_power"),
+            Self::Buy(ShopItem::Repair) => write!(f, "buy_repair"),
+            Self::Buy(ShopItem::ExtraLife) => write!(f, "buy_extra_life"),
+            Self::Buy(ShopItem::FireBoost) => write!(f, "buy_fire_boost"),
+            Self::FreeLook => write!(f, "free_look"),
+            Self::ZoomMinimapIn => write!(f, "zoom_minimap_in"),
+            Self::ZoomMinimapOut => write!(f, "zoom_minimap_out"),
+            Self::ToggleRadar => write!(f, "toggle_radar"),
+            Self::TractorBeam => write!(f, "tractor_beam"),
+            Self::Afterburner => write!(f, "afterburner"),
+            Self::ToggleFlightAssist => write!(f, "toggle_flight_assist"),
+            Self::ToggleTacticalMap => write!(f, "toggle_tactical_map"),
+            Self::AutopilotToTarget => write!(f, "autopilot_to_target"),
+            Self::AutopilotDock => write!(f, "autopilot_dock"),
+        }
+    }
+}
+
+impl TryFrom<&str> for Action {
+    type Error = String;
+    fn try_from(val: &str) -> Result<Self, Self::Error> {
+        match val {
+            "forward" => Ok(Self::Forward),
+            "backward" => Ok(Self::Backward),
+            "fire" => Ok(Self::Fire),
+            "fire_rope" => Ok(Self::FireRope),
+            "reel_in" => Ok(Self::ReelIn),
+            "reel_out" => Ok(Self::ReelOut),
+            "toggle_visibility" => Ok(Self::ToggleVisibility),
+            "toggle_mouse_capture" => Ok(Self::ToggleMouseCapture),
+            "cycle_target" => Ok(Self::CycleTarget),
+            "toggle_camera_tracking" => Ok(Self::ToggleCameraTracking),
+            "cycle_power" => Ok(Self::CyclePowerDistribution),
+            "buy_repair" => Ok(Self::Buy(ShopItem::Repair)),
+            "buy_extra_life" => Ok(Self::Buy(ShopItem::ExtraLife)),
+            "buy_fire_boost" => Ok(Self::Buy(ShopItem::FireBoost)),
+            "free_look" => Ok(Self::FreeLook),
+            "zoom_minimap_in" => Ok(Self::ZoomMinimapIn),
+            "zoom_minimap_out" => Ok(Self::ZoomMinimapOut),
+            "toggle_radar" => Ok(Self::ToggleRadar),
+            "tractor_beam" => Ok(Self::TractorBeam),
+            "afterburner" => Ok(Self::Afterburner),
+            "toggle_flight_assist" => Ok(Self::ToggleFlightAssist),
+            "toggle_tactical_map" => Ok(Self::ToggleTacticalMap),
+            "autopilot_to_target" => Ok(Self::AutopilotToTarget),
+            "autopilot_dock" => Ok(Self::AutopilotDock),
+            _ => Err(format!("Invalid action: {}", val)),
+        }
+    }
+}
+
+/// A game control driven by an analog input
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Axis {
+    Pitch,
+    Roll,
+    /// Positive values move forward and negative values move backwards
+    Thrust,
+}
+
+impl Display for Axis {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Pitch => write!(f, "pitch"),
+            Self::Roll => write!(f, "roll"),
+            Self::Thrust => write!(f, "thrust"),
+        }
+    }
+}
+
+impl TryFrom<&str> for Axis {
+    type Error = String;
+    fn try_from(val: &str) -> Result<Self, Self::Error> {
+        match val {
+            "pitch" => Ok(Self::Pitch),
+            "roll" => Ok(Self::Roll),
+            "thrust" => Ok(Self::Thrust),
+            _ => Err(format!("Invalid axis: {}", val)),
+        }
+    }
+}
+
+/// A digital input from any device
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Button {
+    Key(VirtualKeyCode),
+    /// Mouse button id. `1` is the left button, `2` is the middle button and
+    /// `3` is the right button
+    Mouse(u32),
+    Gamepad(gilrs::Button),
+}
+
+/// Keys which can be bound by name, such as in the client configuration.
+/// Their names are the names of their `VirtualKeyCode`s
+const NAMED_KEYS: &[VirtualKeyCode] = {
+    use VirtualKeyCode as K;
+    &[
+        K::A,
+        K::B,
+        K::C,
+        K::D,
+        K::E,
+        K::F,
+        K::G,
+        K::H,
+        K::I,
+        K::J,
+        K::K,
+        K::L,
+        K::M,
+        K::N,
+        K::O,
+        K::P,
+        K::Q,
+        K::R,
+        K::S,
+        K::T,
+        K::U,
+        K::V,
+        K::W,
+        K::X,
+        K::Y,
+        K::Z,
+        K::Key0,
+        K::Key1,
+        K::Key2,
+        K::Key3,
+        K::Key4,
+        K::Key5,
+        K::Key6,
+        K::Key7,
+        K::Key8,
+        K::Key9,
+        K::F1,
+        K::F2,
+        K::F3,
+        K::F4,
+        K::F5,
+        K::F6,
+        K::F7,
+        K::F8,
+        K::F9,
+        K::F10,
+        K::F11,
+        K::F12,
+        K::Escape,
+        K::Tab,
+        K::Space,
+        K::Return,
+        K::Back,
+        K::Insert,
+        K::Delete,
+        K::Home,
+        K::End,
+        K::PageUp,
+        K::PageDown,
+        K::Up,
+        K::Down,
+        K::Left,
+        K::Right,
+        K::LShift,
+        K::RShift,
+        K::LControl,
+        K::RControl,
+        K::LAlt,
+        K::RAlt,
+        K::Minus,
+        K::Equals,
+        K::Comma,
+        K::Period,
+        K::Slash,
+        K::Backslash,
+        K::Semicolon,
+        K::Apostrophe,
+        K::LBracket,
+        K::RBracket,
+        K::Grave,
+        K::Capital,
+    ]
+};
+
+impl Display for Button {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Key(key) => write!(f, "{:?}", key),
+            Self::Mouse(id) => write!(f, "Mouse{}", id),
+            Self::Gamepad(button) => write!(f, "Gamepad{:?}", button),
+        }
+    }
+}
+
+/// Parses the name of a key or mouse button, such as `W`, `LShift` or
+/// `Mouse1`. Gamepad buttons can't be parsed
+impl TryFrom<&str> for Button {
+    type Error = String;
+    fn try_from(val: &str) -> Result<Self, Self::Error> {
+        if let Some(id) = val.strip_prefix("Mouse") {
+            return id
+                .parse()
+                .map(Self::Mouse)
+                .map_err(|_| format!("Invalid mouse button: {}", val));
+        }
+        NAMED_KEYS
+            .iter()
+            .find(|key| format!("{:?}", key).eq_ignore_ascii_case(val))
+            .map(|key| Self::Key(*key))
+            .ok_or_else(|| format!("Invalid key: {}", val))
+    }
+}
+
+/// An analog input from any device
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum AnalogInput {
+    /// Horizontal mouse movement since the last mouse event
+    MouseX,
+    /// Vertical mouse movement since the last mouse event
+    MouseY,
+    Gamepad(gilrs::Axis),
+}
+
+/// How an analog input controls an axis
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct AxisBinding {
+    pub axis: Axis,
+    /// Factor the input is multiplied by. Negative scales invert the input
+    pub scale: f64,
+    /// Magnitude of input below which the input is ignored
+    pub deadzone: f64,
+}
+
+/// Removes small inputs caused by a stick not resting exactly at its center
+///
+/// Inputs with a magnitude less than `deadzone` become `0`, and the remaining
+/// range is rescaled so that the output still starts at `0` and reaches `1`
+/// at full deflection
+pub fn apply_deadzone(value: f64, deadzone: f64) -> f64 {
+    let deadzone = deadzone.clamp(0., 1.);
+    if value.abs() <= deadzone || deadzone >= 1. {
+        0.
+    } else {
+        value.signum() * (value.abs() - deadzone) / (1. - deadzone)
+    }
+}
+
+/// Maps the inputs of the keyboard, mouse and gamepads to game actions
+///
+/// Each input can be bound to one action or axis, but an action or axis can
+/// have multiple inputs bound to it
+#[derive(Clone, Debug)]
+pub struct InputMap {
+    buttons: HashMap<Button, Action>,
+    axes: HashMap<AnalogInput, AxisBinding>,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        use gilrs::Axis as Stick;
+        use gilrs::Button as Pad;
+        const STICK_SENSITIVITY: f64 = 15.;
+        const STICK_DEADZONE: f64 = 0.15;
+        let mut map = Self::new();
+        map.bind(Button::Key(VirtualKeyCode::W), Action::Forward);
+        map.bind(Button::Key(VirtualKeyCode::S), Action::Backward);
+        map.bind(Button::Key(VirtualKeyCode::T), Action::ToggleVisibility);
+        map.bind(Button::Key(VirtualKeyCode::E), Action::ReelIn);
+        map.bind(Button::Key(VirtualKeyCode::F), Action::ReelOut);
+        map.bind(
+            Button::Key(VirtualKeyCode::Escape),
+            Action::ToggleMouseCapture,
+        );
+        map.bind(Button::Key(VirtualKeyCode::Tab), Action::CycleTarget);
+        map.bind(Button::Key(VirtualKeyCode::C), Action::ToggleCameraTracking);
+        map.bind(
+            Button::Key(VirtualKeyCode::R),
+            Action::CyclePowerDistribution,
+        );
+        map.bind(
+            Button::Key(VirtualKeyCode::Key1),
+            Action::Buy(ShopItem::Repair),
+        );
+        map.bind(
+            Button::Key(VirtualKeyCode::Key2),
+            Action::Buy(ShopItem::ExtraLife),
+        );
+        map.bind(
+            Button::Key(VirtualKeyCode::Key3),
+            Action::Buy(ShopItem::FireBoost),
+        );
+        map.bind(Button::Key(VirtualKeyCode::LAlt), Action::FreeLook);
+        map.bind(Button::Key(VirtualKeyCode::Equals), Action::ZoomMinimapIn);
+        map.bind(Button::Key(VirtualKeyCode::Minus), Action::ZoomMinimapOut);
+        map.bind(Button::Key(VirtualKeyCode::M), Action::ToggleRadar);
+        map.bind(Button::Key(VirtualKeyCode::N), Action::ToggleTacticalMap);
+        map.bind(Button::Key(VirtualKeyCode::G), Action::AutopilotToTarget);
+        map.bind(Button::Key(VirtualKeyCode::H), Action::AutopilotDock);
+        map.bind(Button::Key(VirtualKeyCode::Q), Action::TractorBeam);
+        map.bind(Button::Key(VirtualKeyCode::LShift), Action::Afterburner);
+        map.bind(Button::Key(VirtualKeyCode::V), Action::ToggleFlightAssist);
+        map.bind(Button::Mouse(2), Action::FreeLook);
+        map.bind(Button::Mouse(1), Action::Fire);
+        map.bind(Button::Mouse(3), Action::FireRope);
+        map.bind(Button::Gamepad(Pad::RightTrigger2), Action::Fire);
+        map.bind(Button::Gamepad(Pad::LeftTrigger2), Action::FireRope);
+        map.bind(Button::Gamepad(Pad::LeftTrigger), Action::ReelIn);
+        map.bind(Button::Gamepad(Pad::RightTrigger), Action::ReelOut);
+        map.bind(Button::Gamepad(Pad::South), Action::TractorBeam);
+        map.bind(Button::Gamepad(Pad::LeftThumb), Action::Afterburner);
+        map.bind(Button::Gamepad(Pad::RightThumb), Action::ToggleFlightAssist);
+        map.bind(Button::Gamepad(Pad::North), Action::ToggleVisibility);
+        map.bind(Button::Gamepad(Pad::East), Action::CycleTarget);
+        map.bind(Button::Gamepad(Pad::West), Action::ToggleCameraTracking);
+        map.bind(Button::Gamepad(Pad::DPadUp), Action::CyclePowerDistribution);
+        map.bind(
+            Button::Gamepad(Pad::DPadLeft),
+            Action::Buy(ShopItem::Repair),
+        );
+        map.bind(
+            Button::Gamepad(Pad::DPadDown),
+            Action::Buy(ShopItem::ExtraLife),
+        );
+        map.bind(
+            Button::Gamepad(Pad::DPadRight),
+            Action::Buy(ShopItem::FireBoost),
+        );
+
+        map.bind_axis(AnalogInput::MouseX, Axis::Roll, 1.);
+        map.bind_axis(AnalogInput::MouseY, Axis::Pitch, 1.);
+        map.bind_axis(
+            AnalogInput::Gamepad(Stick::RightStickX),
+            Axis::Roll,
+            STICK_SENSITIVITY,
+        );
+        // pushing the stick up pitches down, like moving the mouse up
+        map.bind_axis(
+            AnalogInput::Gamepad(Stick::RightStickY),
+            Axis::Pitch,
+            -STICK_SENSITIVITY,
+        );
+        map.bind_axis(
+            AnalogInput::Gamepad(Stick::LeftStickY),
+            Axis::Thrust,
+            1.,
+        );
+        for stick in [Stick::RightStickX, Stick::RightStickY, Stick::LeftStickY]
+        {
+            map.set_deadzone(AnalogInput::Gamepad(stick), STICK_DEADZONE);
+        }
+        map
+    }
+}
+
+impl InputMap {
+    /// Creates a map without any bindings
+    pub fn new() -> Self {
+        Self {
+            buttons: HashMap::new(),
+            axes: HashMap::new(),
+        }
+    }
+
+    /// Binds `button` to `action`, returning the action that was previously
+    /// bound to `button`
+    pub fn bind(&mut self, button: Button, action: Action) -> Option<Action> {
+        self.buttons.insert(button, action)
+    }
+
+    /// Removes the binding of `button`
+    #[allow(dead_code)]
+    pub fn unbind(&mut self, button: Button) -> Option<Action> {
+        self.buttons.remove(&button)
+    }
+
+    /// Replaces the keys and mouse buttons bound to `action` with `buttons`.
+    /// Gamepad bindings are kept
+    pub fn rebind(&mut self, action: Action, buttons: &[Button]) {
+        self.buttons.retain(|button, bound| {
+            *bound != action || matches!(button, Button::Gamepad(_))
+        });
+        for button in buttons {
+            self.bind(*button, action);
+        }
+    }
+
+    /// Binds `input` to `axis` without a deadzone. The value of the axis is
+    /// the input multiplied by `scale`
+    ///
+    /// Returns the binding previously bound to `input`
+    pub fn bind_axis(
+        &mut self,
+        input: AnalogInput,
+        axis: Axis,
+        scale: f64,
+    ) -> Option<AxisBinding> {
+        self.axes.insert(
+            input,
+            AxisBinding {
+                axis,
+                scale,
+                deadzone: 0.,
+            },
+        )
+    }
+
+    /// Removes the binding of `input`
+    #[allow(dead_code)]
+    pub fn unbind_axis(&mut self, input: AnalogInput) -> Option<AxisBinding> {
+        self.axes.remove(&input)
+    }
+
+    /// Sets the deadzone of `input`. Does nothing if `input` is not bound
+    pub fn set_deadzone(&mut self, input: AnalogInput, deadzone: f64) {
+        if let Some(binding) = self.axes.get_mut(&input) {
+            binding.deadzone = deadzone;
+        }
+    }
+
+    /// Gets the action bound to `button`
+    pub fn action(&self, button: Button) -> Option<Action> {
+        self.buttons.get(&button).copied()
+    }
+
+    /// Gets all buttons bound to `action`
+    #[allow(dead_code)]
+    pub fn buttons_for(
+        &self,
+        action: Action,
+    ) -> impl Iterator<Item = Button> + '_ {
+        self.buttons
+            .iter()
+            .filter(move |(_, a)| **a == action)
+            .map(|(b, _)| *b)
+    }
+
+    /// Converts the raw `value` of `input` to the value of the axis it's
+    /// bound to, applying the binding's deadzone and scale
+    ///
+    /// Returns `None` if `input` is not bound
+    pub fn axis(&self, input: AnalogInput, value: f64) -> Option<(Axis, f64)> {
+        self.axes.get(&input).map(|binding| {
+            (
+                binding.axis,
+                apply_deadzone(value, binding.deadzone) * binding.scale,
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn deadzone_rescales_input() {
+        assert_approx_eq!(apply_deadzone(0.1, 0.2), 0.);
+        assert_approx_eq!(apply_deadzone(-0.2, 0.2), 0.);
+        assert_approx_eq!(apply_deadzone(0.6, 0.2), 0.5);
+        assert_approx_eq!(apply_deadzone(-1., 0.2), -1.);
+        assert_approx_eq!(apply_deadzone(0.3, 0.), 0.3);
+        assert_approx_eq!(apply_deadzone(0.9, 1.), 0.);
+    }
+
+    #[test]
+    fn keyboard_and_gamepad_share_actions() {
+        let map = InputMap::default();
+        assert_eq!(
+            map.action(Button::Key(VirtualKeyCode::W)),
+            Some(Action::Forward)
+        );
+        assert_eq!(map.action(Button::Mouse(1)), Some(Action::Fire));
+        assert_eq!(
+            map.action(Button::Gamepad(gilrs::Button::RightTrigger2)),
+            Some(Action::Fire)
+        );
+        assert_eq!(map.buttons_for(Action::Fire).count(), 2);
+        assert_eq!(map.action(Button::Key(VirtualKeyCode::Q)), None);
+    }
+
+    #[test]
+    fn buttons_can_be_rebound() {
+        let mut map = InputMap::default();
+        let w = Button::Key(VirtualKeyCode::W);
+        let up = Button::Key(VirtualKeyCode::Up);
+        assert_eq!(map.bind(w, Action::Fire), Some(Action::Forward));
+        assert_eq!(map.bind(up, Action::Forward), None);
+        assert_eq!(map.action(w), Some(Action::Fire));
+        assert_eq!(map.action(up), Some(Action::Forward));
+        assert_eq!(map.unbind(w), Some(Action::Fire));
+        assert_eq!(map.action(w), None);
+    }
+
+    #[test]
+    fn rebind_keeps_gamepad() {
+        let mut map = InputMap::default();
+        let up = Button::Key(VirtualKeyCode::Up);
+        map.rebind(Action::Fire, &[up]);
+        assert_eq!(map.action(up), Some(Action::Fire));
+        assert_eq!(map.action(Button::Mouse(1)), None);
+        assert_eq!(map.buttons_for(Action::Fire).count(), 2);
+    }
+
+    #[test]
+    fn button_names_round_trip() {
+        for button in [
+            Button::Key(VirtualKeyCode::W),
+            Button::Key(VirtualKeyCode::LShift),
+            Button::Key(VirtualKeyCode::Key1),
+            Button::Mouse(3),
+        ] {
+            assert_eq!(
+                Button::try_from(button.to_string().as_str()),
+                Ok(button)
+            );
+        }
+        assert_eq!(
+            Button::try_from("tab"),
+            Ok(Button::Key(VirtualKeyCode::Tab))
+        );
+        assert!(Button::try_from("Mouse").is_err());
+        assert!(Button::try_from("Hyper").is_err());
+    }
+
+    #[test]
+    fn axes_apply_deadzone_and_scale() {
+        let mut map = InputMap::new();
+        let stick = AnalogInput::Gamepad(gilrs::Axis::LeftStickX);
+        assert!(map.axis(stick, 1.).is_none());
+        map.bind_axis(stick, Axis::Roll, -2.);
+        map.set_deadzone(stick, 0.5);
+        let (axis, val) = map.axis(stick, 0.75).unwrap();
+        assert_eq!(axis, Axis::Roll);
+        assert_approx_eq!(val, -1.);
+        assert_approx_eq!(map.axis(stick, 0.25).unwrap().1, 0.);
+        assert_eq!(
+            map.bind_axis(stick, Axis::Thrust, 1.).map(|b| b.axis),
+            Some(Axis::Roll)
+        );
+    }
+}
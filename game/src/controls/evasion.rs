@@ -0,0 +1,407 @@
+use super::ai::{ActionResult, BTNode, BehaviorTree, Blackboard};
+use super::{ControllerAction, PlayerIterator, Projectile};
+use crate::cg_support;
+use crate::collisions::CollisionTree;
+use crate::graphics_engine::clock;
+use crate::physics;
+use cgmath::*;
+use std::time::{Duration, Instant};
+
+/// How far ahead, in seconds, incoming fire is looked for
+const THREAT_HORIZON: f64 = 1.5;
+
+/// Distance a laser must pass within to be a threat
+const THREAT_RADIUS: f64 = 8.;
+
+/// A laser that will pass close to the controlled player
+#[derive(Copy, Clone, Debug)]
+pub(super) struct Threat {
+    /// Seconds until the laser is closest to the player
+    pub(super) time_to_impact: f64,
+    /// Direction the laser is travelling, relative to the player
+    pub(super) heading: Vector3<f64>,
+    /// Direction the player should move to get out of the laser's way
+    pub(super) dodge: Vector3<f64>,
+}
+
+/// Gets a unit vector perpendicular to `v`
+fn perpendicular(v: Vector3<f64>) -> Vector3<f64> {
+    let other = if v.x.abs() < 0.9 {
+        vec3(1., 0., 0.)
+    } else {
+        vec3(0., 1., 0.)
+    };
+    v.cross(other).normalize()
+}
+
+/// Determines if `projectile` will pass within `radius` of a body at `pos`
+/// moving at `velocity` in the next `horizon` seconds
+///
+/// The projectile's path is swept relative to the body, so that the closest
+/// point of approach is found even if the body is moving
+fn incoming_threat(
+    projectile: &Projectile,
+    pos: Point3<f64>,
+    velocity: Vector3<f64>,
+    radius: f64,
+    horizon: f64,
+) -> Option<Threat> {
+    let rel_vel = projectile.velocity - velocity;
+    let speed2 = rel_vel.magnitude2();
+    let to_body = pos - projectile.pos;
+    if speed2 <= f64::EPSILON || to_body.dot(rel_vel) <= 0. {
+        // the projectile is stationary or moving away
+        return None;
+    }
+    let time_to_impact = (to_body.dot(rel_vel) / speed2).min(horizon);
+    let miss = to_body - rel_vel * time_to_impact;
+    if miss.magnitude() > radius {
+        return None;
+    }
+    let heading = rel_vel.normalize();
+    let dodge = if miss.magnitude2() > f64::EPSILON {
+        miss.normalize()
+    } else {
+        perpendicular(heading)
+    };
+    Some(Threat {
+        time_to_impact,
+        heading,
+        dodge,
+    })
+}
+
+/// A Behavior Tree condition node that looks for lasers heading toward the
+/// controlled player
+///
+/// Succeeds if a laser will pass close to the player, storing the most
+/// imminent threat in the blackboard, or if the player is already making an
+/// evasive maneuver. Fails otherwise
+pub struct DetectIncomingFire {}
+
+impl BTNode for DetectIncomingFire {
+    fn tick(
+        &mut self,
+        _children: &mut [BehaviorTree],
+        blackboard: &mut Blackboard,
+        _scene: &CollisionTree,
+        player: &physics::BaseRigidBody,
+        _dt: std::time::Duration,
+        _other_players: PlayerIterator,
+    ) -> ActionResult {
+        let pos = player.transform.borrow().get_pos();
+        blackboard.threat = blackboard
+            .projectiles
+            .iter()
+            .filter_map(|projectile| {
+                incoming_threat(
+                    projectile,
+                    pos,
+                    player.velocity,
+                    THREAT_RADIUS,
+                    THREAT_HORIZON,
+                )
+            })
+            .min_by(|a, b| {
+                a.time_to_impact
+                    .partial_cmp(&b.time_to_impact)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            });
+        if blackboard.threat.is_some() || blackboard.maneuver.is_some() {
+            ActionResult::Success(None)
+        } else {
+            ActionResult::Failure
+        }
+    }
+}
+
+/// The kinds of evasive maneuvers
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(super) enum ManeuverKind {
+    /// A corkscrew around the player's direction of travel
+    BarrelRoll,
+    /// A short, sharp sidestep
+    Jink,
+    /// A hard turn out of the line of fire
+    BreakTurn,
+}
+
+impl ManeuverKind {
+    /// Picks a maneuver to evade `threat` with
+    ///
+    /// There's only time to jink out of the way of imminent threats. Threats
+    /// from behind are evaded with a break turn, and all others with a
+    /// barrel roll
+    fn against(threat: &Threat, forward: Vector3<f64>) -> Self {
+        if threat.time_to_impact < 0.4 {
+            Self::Jink
+        } else if threat.heading.dot(forward) > 0.5 {
+            Self::BreakTurn
+        } else {
+            Self::BarrelRoll
+        }
+    }
+
+    /// Gets how long the maneuver takes
+    const fn duration(self) -> Duration {
+        match self {
+            Self::BarrelRoll => Duration::from_millis(1200),
+            Self::Jink => Duration::from_millis(350),
+            Self::BreakTurn => Duration::from_millis(800),
+        }
+    }
+}
+
+/// An evasive maneuver in progress
+#[derive(Copy, Clone, Debug)]
+pub(super) struct Maneuver {
+    kind: ManeuverKind,
+    elapsed: Duration,
+    /// Direction the player was facing when the maneuver started
+    forward: Vector3<f64>,
+    /// Direction out of the line of fire
+    dodge: Vector3<f64>,
+}
+
+impl Maneuver {
+    /// Speed of jinks and break turns
+    const DODGE_SPEED: f64 = 45.;
+    /// Forward speed during barrel rolls
+    const ROLL_FORWARD_SPEED: f64 = 20.;
+    /// Speed around the corkscrew of barrel rolls
+    const ROLL_SPEED: f64 = 30.;
+
+    fn new(threat: &Threat, forward: Vector3<f64>) -> Self {
+        let forward = if forward.magnitude2() > f64::EPSILON {
+            forward.normalize()
+        } else {
+            vec3(0., 0., 1.)
+        };
+        Self {
+            kind: ManeuverKind::against(threat, forward),
+            elapsed: Duration::default(),
+            forward,
+            dodge: threat.dodge,
+        }
+    }
+
+    /// Fraction of the maneuver that has been completed
+    fn progress(&self) -> f64 {
+        (self.elapsed.as_secs_f64() / self.kind.duration().as_secs_f64())
+            .min(1.)
+    }
+
+    fn is_finished(&self) -> bool {
+        self.elapsed >= self.kind.duration()
+    }
+
+    /// Gets the velocity of the player at the current point of the maneuver
+    fn velocity(&self) -> Vector3<f64> {
+        match self.kind {
+            ManeuverKind::Jink | ManeuverKind::BreakTurn => {
+                self.dodge * Self::DODGE_SPEED
+            }
+            ManeuverKind::BarrelRoll => {
+                let angle = Rad::full_turn() * self.progress();
+                let up = self.forward.cross(self.dodge);
+                self.forward * Self::ROLL_FORWARD_SPEED
+                    + (self.dodge * angle.cos() + up * angle.sin())
+                        * Self::ROLL_SPEED
+            }
+        }
+    }
+
+    /// `true` if the player flees the line of fire on its afterburner,
+    /// rather than rolling around it
+    const fn afterburner(&self) -> bool {
+        matches!(self.kind, ManeuverKind::Jink | ManeuverKind::BreakTurn)
+    }
+
+    /// Gets the rotation of the player at the current point of the maneuver,
+    /// or `None` if the maneuver doesn't change the player's rotation
+    fn rot(&self) -> Option<Matrix3<f64>> {
+        match self.kind {
+            ManeuverKind::Jink => None,
+            ManeuverKind::BreakTurn => {
+                let dir =
+                    self.forward.lerp(self.dodge, self.progress()).normalize();
+                cg_support::look_at(dir, &vec3(0., 1., 0.))
+            }
+            ManeuverKind::BarrelRoll => cg_support::look_at(
+                self.forward,
+                &vec3(0., 1., 0.),
+            )
+            .map(|rot| {
+                rot * Matrix3::from_angle_z(Rad::full_turn() * self.progress())
+            }),
+        }
+    }
+}
+
+/// A Behavior Tree action node that evades the threat found by
+/// `DetectIncomingFire` with an evasive maneuver
+///
+/// A maneuver is started if there's a threat and the last maneuver ended at
+/// least the cooldown ago. The node is running while the maneuver is in
+/// progress, and fails once the maneuver finishes or if no maneuver can be
+/// started
+pub struct EvasiveManeuver {
+    cooldown: Duration,
+    last_finished: Option<Instant>,
+}
+
+impl EvasiveManeuver {
+    /// Creates a node that waits `cooldown` between maneuvers
+    pub const fn new(cooldown: Duration) -> Self {
+        Self {
+            cooldown,
+            last_finished: None,
+        }
+    }
+
+    fn cooled_down(&self) -> bool {
+        self.last_finished.map_or(true, |time| {
+            clock::now().duration_since(time) >= self.cooldown
+        })
+    }
+}
+
+impl BTNode for EvasiveManeuver {
+    fn tick(
+        &mut self,
+        _children: &mut [BehaviorTree],
+        blackboard: &mut Blackboard,
+        _scene: &CollisionTree,
+        player: &physics::BaseRigidBody,
+        dt: std::time::Duration,
+        _other_players: PlayerIterator,
+    ) -> ActionResult {
+        if blackboard.maneuver.is_none() && self.cooled_down() {
+            let forward =
+                player.transform.borrow().transform_vec(vec3(0., 0., 1.));
+            blackboard.maneuver = blackboard
+                .threat
+                .map(|threat| Maneuver::new(&threat, forward));
+        }
+        let maneuver = match blackboard.maneuver.as_mut() {
+            Some(maneuver) => maneuver,
+            None => return ActionResult::Failure,
+        };
+        maneuver.elapsed += dt;
+        if maneuver.is_finished() {
+            blackboard.maneuver = None;
+            self.last_finished = Some(clock::now());
+            return ActionResult::Failure;
+        }
+        if let Some(rot) = maneuver.rot() {
+            blackboard.rot = Some(rot);
+        }
+        ActionResult::Running(Some(ControllerAction {
+            velocity: maneuver.velocity(),
+            fire: false,
+            afterburner: maneuver.afterburner(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    fn laser(pos: Point3<f64>, velocity: Vector3<f64>) -> Projectile {
+        Projectile { pos, velocity }
+    }
+
+    #[test]
+    fn detects_lasers_heading_toward_body() {
+        let body = point3(0., 0., 0.);
+        let still = vec3(0., 0., 0.);
+        let threat = incoming_threat(
+            &laser(point3(2., 0., -100.), vec3(0., 0., 100.)),
+            body,
+            still,
+            THREAT_RADIUS,
+            THREAT_HORIZON,
+        )
+        .unwrap();
+        assert_approx_eq!(threat.time_to_impact, 1.);
+        // dodge away from the side the laser passes on
+        assert_approx_eq!(threat.dodge.x, -1.);
+        assert_approx_eq!(threat.heading.z, 1.);
+        // passing laser
+        assert!(incoming_threat(
+            &laser(point3(0., 0., 10.), vec3(0., 0., 100.)),
+            body,
+            still,
+            THREAT_RADIUS,
+            THREAT_HORIZON
+        )
+        .is_none());
+        // wide miss
+        assert!(incoming_threat(
+            &laser(point3(20., 0., -100.), vec3(0., 0., 100.)),
+            body,
+            still,
+            THREAT_RADIUS,
+            THREAT_HORIZON
+        )
+        .is_none());
+        // too far away to worry about yet
+        assert!(incoming_threat(
+            &laser(point3(0., 0., -1000.), vec3(0., 0., 100.)),
+            body,
+            still,
+            THREAT_RADIUS,
+            THREAT_HORIZON
+        )
+        .is_none());
+        // a laser fired in the direction the body is moving still catches up
+        let threat = incoming_threat(
+            &laser(point3(0., 0., -50.), vec3(0., 0., 100.)),
+            body,
+            vec3(0., 0., 50.),
+            THREAT_RADIUS,
+            THREAT_HORIZON,
+        )
+        .unwrap();
+        assert_approx_eq!(threat.time_to_impact, 1.);
+        assert_approx_eq!(threat.dodge.magnitude(), 1.);
+    }
+
+    #[test]
+    fn maneuvers_fit_the_threat() {
+        let forward = vec3(0., 0., 1.);
+        let threat = |time_to_impact, heading| Threat {
+            time_to_impact,
+            heading,
+            dodge: vec3(1., 0., 0.),
+        };
+        let head_on = threat(1., -forward);
+        assert_eq!(
+            ManeuverKind::against(&threat(0.2, -forward), forward),
+            ManeuverKind::Jink
+        );
+        assert_eq!(
+            ManeuverKind::against(&threat(1., forward), forward),
+            ManeuverKind::BreakTurn
+        );
+        // fleeing from behind uses the afterburner, rolling doesn't
+        assert!(Maneuver::new(&threat(1., forward), forward).afterburner());
+        let mut roll = Maneuver::new(&head_on, forward);
+        assert_eq!(roll.kind, ManeuverKind::BarrelRoll);
+        assert!(!roll.afterburner());
+        let start = roll.velocity();
+        assert_approx_eq!(start.x, Maneuver::ROLL_SPEED);
+        roll.elapsed = roll.kind.duration() / 4;
+        assert_approx_eq!(roll.velocity().x, 0.);
+        assert_approx_eq!(roll.velocity().y.abs(), Maneuver::ROLL_SPEED);
+        assert!(!roll.is_finished());
+        roll.elapsed = roll.kind.duration();
+        assert!(roll.is_finished());
+        // the roll ends facing forward again
+        let rot = roll.rot().unwrap();
+        assert_approx_eq!((rot * vec3(0., 0., 1.)).dot(forward), 1.);
+        assert_approx_eq!((rot * vec3(0., 1., 0.)).y, 1.);
+    }
+}
@@ -1,10 +1,19 @@
 mod ai;
+mod autopilot;
+mod avoidance;
+mod bindings;
+mod evasion;
 mod pathfinding;
+mod patrol;
+mod recording;
 mod user_input;
 use crate::node;
 use crate::{collisions, physics};
 use ai::*;
+use evasion::*;
 use pathfinding::*;
+use patrol::*;
+use shared_types::survival::ShopItem;
 use std::{cell::RefCell, rc::Rc};
 
 /// A cloneable iterator type for players
@@ -46,6 +55,16 @@ pub enum Movement {
     Backwards,
 }
 
+/// How a character is changing the length of its grappling line
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Reel {
+    Hold,
+    /// Shortening the line, pulling the character and the hooked object
+    /// together
+    In,
+    Out,
+}
+
 #[derive(PartialEq, Eq, Copy, Clone, Debug)]
 pub enum PlayerActionState {
     Idle,
@@ -54,6 +73,13 @@ pub enum PlayerActionState {
     CutRope,
 }
 
+/// A laser in flight
+#[derive(Copy, Clone, Debug)]
+pub struct Projectile {
+    pub pos: cgmath::Point3<f64>,
+    pub velocity: cgmath::Vector3<f64>,
+}
+
 /// The action that the controller will take as a result of performing an
 /// update
 #[derive(Clone)]
@@ -62,6 +88,8 @@ pub struct ControllerAction {
     pub velocity: cgmath::Vector3<f64>,
     // TODO: add rotational control, firing control, etc.
     pub fire: bool,
+    /// `true` if the character boosts its velocity with its afterburner
+    pub afterburner: bool,
 }
 
 /// `MovementControl` is a trait that controls the movement of a character
@@ -108,59 +136,235 @@ pub trait MovementControl {
 
     /// Returns whether this controller is an AI controller
     fn is_ai(&self) -> bool;
+
+    /// Returns `true` if the character requested to lock on to the next
+    /// target since the last call
+    fn take_cycle_target(&mut self) -> bool {
+        false
+    }
+
+    /// Gets how the character is changing the length of its grappling line
+    fn get_reel(&self) -> Reel {
+        Reel::Hold
+    }
+
+    /// Returns `true` while the character holds its tractor beam on
+    fn is_beam_held(&self) -> bool {
+        false
+    }
+
+    /// Returns `true` while the character wants its afterburner on
+    fn is_afterburning(&self) -> bool {
+        false
+    }
+
+    /// Returns `true` if the character's drift is damped towards its
+    /// heading, or `false` if it flies with newtonian drift
+    fn is_flight_assisted(&self) -> bool {
+        true
+    }
+
+    /// Returns `true` if the character requested to switch to the next
+    /// power distribution since the last call
+    fn take_cycle_power(&mut self) -> bool {
+        false
+    }
+
+    /// Gets the item the character asked to buy from the shop between waves
+    /// since the last call, if any
+    fn take_purchase(&mut self) -> Option<ShopItem> {
+        None
+    }
+
+    /// Called when the character's ship runs out of energy to keep its
+    /// cloak up, so the character should become visible
+    fn on_cloak_depleted(&mut self) {}
+
+    /// Called when a station takes the character's ship out of its control
+    /// to dock it
+    fn on_docking(&mut self) {}
+
+    /// Returns whether the character's camera should softly track the
+    /// target it's locked on to
+    fn is_camera_tracking(&self) -> bool {
+        false
+    }
+
+    /// Gets the horizontal and vertical amount the character wants to orbit
+    /// its camera by since the last call, or `None` if the camera isn't in
+    /// free look
+    fn take_free_look(&mut self) -> Option<(f64, f64)> {
+        None
+    }
+
+    /// Informs the controller of the lasers that are in flight. Should be
+    /// called every frame before `on_frame_update`
+    fn observe_projectiles(&mut self, _projectiles: &[Projectile]) {}
 }
 
+pub use ai::{AiProfile, Squad};
+pub use autopilot::{AutopilotRequest, Destination};
+pub use bindings::{Action, Button, InputMap};
 pub use user_input::PlayerControls;
 
-/// Returns a standard behavior tree for the AI controller
-fn get_std_behavior_tree() -> BehaviorTree {
-    let root = Box::new(Sequence {});
+/// Size of the tiles AI controllers divide space into to find paths
+const PATH_TILE_DIM: f64 = 12.;
+
+/// Minimum time between the evasive maneuvers of AI controllers
+const EVADE_COOLDOWN: std::time::Duration = std::time::Duration::from_secs(3);
+
+/// Returns a subtree which follows a path to the target location,
+/// recomputing the path when it's blocked or the target location moves
+fn get_navigate_tree() -> BehaviorTree {
     let compute_path_if_necessary = BehaviorTree::new(
         Box::new(Fallback {}),
         vec![
             BehaviorTree::new(Box::new(ShouldRecomputePath {}), vec![]),
-            BehaviorTree::new(Box::new(ComputePath::new(12.)), vec![]),
+            BehaviorTree::new(
+                Box::new(ComputePath::new(PATH_TILE_DIM)),
+                vec![],
+            ),
+        ],
+    );
+    BehaviorTree::new(
+        Box::new(Sequence {}),
+        vec![
+            BehaviorTree::new(
+                Box::new(InvalidateBlockedPath::new(PATH_TILE_DIM)),
+                vec![],
+            ),
+            compute_path_if_necessary,
+            BehaviorTree::new(
+                Box::new(Fallback {}),
+                vec![
+                    BehaviorTree::new(
+                        Box::new(StraightLineNav::default()),
+                        vec![],
+                    ),
+                    BehaviorTree::new(
+                        Box::new(TriggerRecomputePath {}),
+                        vec![],
+                    ),
+                ],
+            ),
+        ],
+    )
+}
+
+/// Returns a subtree which chases and shoots at the target
+fn get_engage_tree() -> BehaviorTree {
+    BehaviorTree::new(
+        Box::new(ParallelSequence {}),
+        vec![
+            get_navigate_tree(),
+            BehaviorTree::new(Box::new(ShootIfAble::new()), vec![]),
+        ],
+    )
+}
+
+/// Returns a subtree which dodges incoming fire
+fn get_evade_tree() -> BehaviorTree {
+    BehaviorTree::new(
+        Box::new(Sequence {}),
+        vec![
+            BehaviorTree::new(Box::new(DetectIncomingFire {}), vec![]),
+            BehaviorTree::new(
+                Box::new(EvasiveManeuver::new(EVADE_COOLDOWN)),
+                vec![],
+            ),
+        ],
+    )
+}
+
+/// Returns a subtree which finds a target
+fn get_find_target_tree() -> BehaviorTree {
+    BehaviorTree::new(
+        Box::new(Fallback {}),
+        vec![
+            BehaviorTree::new(Box::new(SearchForIDedTarget {}), vec![]),
+            BehaviorTree::new(Box::new(IdentifyTarget {}), vec![]),
+        ],
+    )
+}
+
+/// Returns a standard behavior tree for the AI controller
+fn get_std_behavior_tree() -> BehaviorTree {
+    let children = vec![
+        get_find_target_tree(),
+        BehaviorTree::new(Box::new(CoordinateWithSquad {}), vec![]),
+        // dodge incoming fire before anything else
+        BehaviorTree::new(
+            Box::new(Fallback {}),
+            vec![get_evade_tree(), get_engage_tree()],
+        ),
+    ];
+    BehaviorTree::new(Box::new(Sequence {}), children)
+}
+
+/// Returns a behavior tree for an AI controller which patrols its waypoints
+/// and only engages targets within `radius` of `center`
+fn get_guard_behavior_tree(
+    center: cgmath::Point3<f64>,
+    radius: f64,
+) -> BehaviorTree {
+    let guard = BehaviorTree::new(
+        Box::new(Sequence {}),
+        vec![
+            get_find_target_tree(),
+            BehaviorTree::new(Box::new(GuardArea::new(center, radius)), vec![]),
         ],
     );
     let children = vec![
         BehaviorTree::new(
             Box::new(Fallback {}),
-            vec![
-                BehaviorTree::new(Box::new(SearchForIDedTarget {}), vec![]),
-                BehaviorTree::new(Box::new(IdentifyTarget {}), vec![]),
-            ],
+            vec![guard, BehaviorTree::new(Box::new(PatrolRoute {}), vec![])],
         ),
+        BehaviorTree::new(Box::new(CoordinateWithSquad {}), vec![]),
         BehaviorTree::new(
-            Box::new(ParallelSequence {}),
-            vec![
-                BehaviorTree::new(
-                    Box::new(Sequence {}),
-                    vec![
-                        compute_path_if_necessary,
-                        BehaviorTree::new(
-                            Box::new(Fallback {}),
-                            vec![
-                                BehaviorTree::new(
-                                    Box::new(StraightLineNav::default()),
-                                    vec![],
-                                ),
-                                BehaviorTree::new(
-                                    Box::new(TriggerRecomputePath {}),
-                                    vec![],
-                                ),
-                            ],
-                        ),
-                    ],
-                ),
-                BehaviorTree::new(Box::new(ShootIfAble::new()), vec![]),
-            ],
+            Box::new(Fallback {}),
+            vec![get_evade_tree(), get_engage_tree()],
         ),
     ];
-    BehaviorTree::new(root, children)
+    BehaviorTree::new(Box::new(Sequence {}), children)
 }
 
-/// Returns a standard AI controller
-pub fn get_std_ai_controller() -> Rc<RefCell<dyn MovementControl>> {
+/// Returns an AI controller which behaves according to `profile`
+pub fn get_ai_controller(
+    profile: AiProfile,
+) -> Rc<RefCell<dyn MovementControl>> {
     let behavior_tree = get_std_behavior_tree();
-    Rc::new(RefCell::new(AIController::new(behavior_tree)))
+    Rc::new(RefCell::new(AIController::new(behavior_tree, profile)))
+}
+
+/// Returns an AI controller which behaves according to `profile` and
+/// coordinates with the other members of `squad`
+pub fn get_squad_ai_controller(
+    profile: AiProfile,
+    squad: &Rc<RefCell<Squad>>,
+) -> Rc<RefCell<dyn MovementControl>> {
+    let mut controller = AIController::new(get_std_behavior_tree(), profile);
+    controller.join_squad(squad);
+    Rc::new(RefCell::new(controller))
+}
+
+/// Returns an AI controller which behaves according to `profile`, patrolling
+/// `waypoints` and guarding the area within `radius` of `center`
+///
+/// Without any waypoints, the player holds its position at `center`
+pub fn get_guard_ai_controller(
+    profile: AiProfile,
+    center: cgmath::Point3<f64>,
+    radius: f64,
+    waypoints: Vec<cgmath::Point3<f64>>,
+) -> Rc<RefCell<dyn MovementControl>> {
+    let behavior_tree = get_guard_behavior_tree(center, radius);
+    Rc::new(RefCell::new(
+        AIController::new(behavior_tree, profile).with_waypoints(waypoints),
+    ))
+}
+
+/// Returns a standard AI controller of normal difficulty
+#[allow(dead_code)]
+pub fn get_std_ai_controller() -> Rc<RefCell<dyn MovementControl>> {
+    get_ai_controller(AiProfile::normal())
 }
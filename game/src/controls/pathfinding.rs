@@ -1,12 +1,15 @@
 use super::ai::{ActionResult, BTNode, BehaviorTree, Blackboard};
+use super::avoidance;
 use super::{ControllerAction, PlayerIterator};
 use crate::cg_support;
-use crate::collisions::CollisionTree;
+use crate::collisions::{CollisionTree, ObstacleChange, ObstacleSubscription};
 use crate::physics::{self, BaseRigidBody};
 use cgmath::*;
 use priority_queue::PriorityQueue;
+use std::cell::RefCell;
 use std::collections::{HashSet, VecDeque};
 use std::rc::Rc;
+use tracing::debug;
 
 /// A linked list in order to backtrack the shortest path
 #[derive(Debug, Clone, Eq)]
@@ -52,6 +55,48 @@ pub(super) struct ComputedPath {
     tile_dim: f64,
 }
 
+/// Gets the indices of the tiles on the path ending at `terminal_node`,
+/// not including the tile the path starts from
+fn backtrack(terminal_node: Rc<PathNode>) -> VecDeque<Point3<i32>> {
+    let mut path = VecDeque::new();
+    let mut cur_node = terminal_node;
+    while let Some(parent) = cur_node.parent.clone() {
+        path.push_front(cur_node.index);
+        cur_node = parent;
+    }
+    path
+}
+
+/// Gets the distance from `p` to the closest point on the line segment from
+/// `a` to `b`
+fn distance_to_segment(a: Point3<f64>, b: Point3<f64>, p: Point3<f64>) -> f64 {
+    let ab = b - a;
+    let len2 = ab.magnitude2();
+    let t = if len2 <= f64::EPSILON {
+        0.
+    } else {
+        ((p - a).dot(ab) / len2).clamp(0., 1.)
+    };
+    (a + ab * t).distance(p)
+}
+
+/// Returns true if a tile of `tile_dim` can move in a straight line from `a`
+/// to `b` without colliding with anything in `scene` besides `players`
+fn is_line_clear(
+    a: Point3<f64>,
+    b: Point3<f64>,
+    tile_dim: f64,
+    scene: &CollisionTree,
+    players: &HashSet<usize>,
+) -> bool {
+    // steps of half a tile so that every point along the line is covered
+    let steps = ((b - a).magnitude() / (tile_dim * 0.5)).ceil().max(1.) as u32;
+    (0..=steps).all(|step| {
+        let pt = a + (b - a) * (f64::from(step) / f64::from(steps));
+        tile_cost(pt, tile_dim, scene, players) == 0
+    })
+}
+
 impl ComputedPath {
     /// Constructs a computed path by backtracking from the terminal node
     ///
@@ -62,18 +107,8 @@ impl ComputedPath {
         origin: Point3<f64>,
         tile_dim: f64,
     ) -> Self {
-        let mut path = VecDeque::new();
-        let mut cur_node = terminal_node;
-        while let Some(parent) = cur_node.parent.clone() {
-            path.push_front(cur_node.index);
-            // println!(
-            //     "{:?}",
-            //     origin + cur_node.index.to_vec().cast().unwrap() * tile_dim
-            // );
-            cur_node = parent;
-        }
         Self {
-            path,
+            path: backtrack(terminal_node),
             target,
             origin,
             tile_dim,
@@ -86,12 +121,123 @@ impl ComputedPath {
     fn index_to_point(&self, index: &Point3<i32>) -> Point3<f64> {
         self.origin + index.to_vec().cast().unwrap() * self.tile_dim
     }
+
+    /// Removes waypoints, starting at the waypoint at index `from`, that can
+    /// be skipped by flying straight to a later waypoint. This is known as
+    /// string-pulling, and makes the path less jagged than the tile grid
+    ///
+    /// `start` - the point the path is followed from to the waypoint at
+    /// `from`
+    fn smooth(
+        &mut self,
+        start: Point3<f64>,
+        from: usize,
+        scene: &CollisionTree,
+        players: &HashSet<usize>,
+    ) {
+        let rest: Vec<_> =
+            self.path.drain(from.min(self.path.len())..).collect();
+        let mut anchor = start;
+        let mut i = 0;
+        while i < rest.len() {
+            let mut furthest = i;
+            for (j, idx) in rest.iter().enumerate().skip(i + 1) {
+                if !is_line_clear(
+                    anchor,
+                    self.index_to_point(idx),
+                    self.tile_dim,
+                    scene,
+                    players,
+                ) {
+                    break;
+                }
+                furthest = j;
+            }
+            self.path.push_back(rest[furthest]);
+            anchor = self.index_to_point(&rest[furthest]);
+            i = furthest + 1;
+        }
+    }
+
+    /// Gets the index of the first waypoint whose incoming segment is
+    /// obstructed by a body with a bounding sphere at `center` of `radius`
+    ///
+    /// `start` - the position the path is being followed from
+    fn first_blocked(
+        &self,
+        start: Point3<f64>,
+        center: Point3<f64>,
+        radius: f64,
+        scene: &CollisionTree,
+        players: &HashSet<usize>,
+    ) -> Option<usize> {
+        /// Distance between the center and corner of a unit cube
+        const HALF_DIAGONAL_FAC: f64 = 0.866_025_403_78;
+        let clearance = HALF_DIAGONAL_FAC.mul_add(self.tile_dim, radius);
+        let mut prev = start;
+        for (k, idx) in self.path.iter().enumerate() {
+            let pt = self.index_to_point(idx);
+            if distance_to_segment(prev, pt, center) <= clearance
+                && !is_line_clear(prev, pt, self.tile_dim, scene, players)
+            {
+                return Some(k);
+            }
+            prev = pt;
+        }
+        None
+    }
+
+    /// Replans the path from the waypoint before the waypoint at index
+    /// `blocked`, keeping the waypoints before it
+    ///
+    /// Returns false if the path must be recomputed from the start because no
+    /// path from the waypoint exists, or the first waypoint is blocked
+    fn repair(
+        &mut self,
+        blocked: usize,
+        scene: &CollisionTree,
+        players: &HashSet<usize>,
+    ) -> bool {
+        if blocked == 0 || blocked > self.path.len() {
+            return false;
+        }
+        let from = self.path[blocked - 1];
+        let from_pt = self.index_to_point(&from);
+        ComputePath::get_path(
+            &from_pt,
+            self.tile_dim,
+            &self.target,
+            scene,
+            players,
+        )
+        .map_or(false, |terminal| {
+            self.path.truncate(blocked);
+            self.path.extend(
+                backtrack(terminal).into_iter().map(|i| i + from.to_vec()),
+            );
+            self.smooth(from_pt, blocked, scene, players);
+            true
+        })
+    }
+}
+
+/// Gets the node ids of the controlled player and `other_players`, which
+/// should not be treated as obstacles
+pub(super) fn player_ids(
+    player: &physics::BaseRigidBody,
+    other_players: PlayerIterator,
+) -> HashSet<usize> {
+    let mut ids: HashSet<usize> =
+        other_players.copy().map(|p| p.as_ptr() as usize).collect();
+    ids.insert(player.transform.as_ptr() as usize);
+    ids
 }
 
 /// A Behavior Tree action node that moves the AI along a path in relatively straight lines
 ///
 /// This is designed to be used to navigate on a precomputed path
-/// relatively small spacing between points in the path
+/// relatively small spacing between points in the path. Obstacles that
+/// drift into the path are steered around until the path is recomputed
 #[derive(Default)]
 pub struct StraightLineNav {
     last_pos: Option<Point3<f64>>,
@@ -165,7 +311,7 @@ impl StraightLineNav {
         self.last_velocity.map_or(false, |v| {
             let hit = v.normalize().dot(npc.velocity.normalize()) < 0.0;
             if hit {
-                println!("Hit an obstacle during path following");
+                debug!("Hit an obstacle during path following");
             }
             hit
             // less than 0 means > 90 degrees
@@ -177,11 +323,26 @@ impl StraightLineNav {
         self.last_velocity = None;
     }
 
+    /// Returns true if `npc` is within `range` of `target`, and should stop
+    /// approaching it
+    fn in_engagement_range(
+        npc: &BaseRigidBody,
+        target: Option<Point3<f64>>,
+        range: f64,
+    ) -> bool {
+        target.map_or(false, |target| {
+            (target - npc.transform.borrow().get_pos()).magnitude() <= range
+        })
+    }
+
+    /// Moves `npc` towards the next point in `path`, steering around the
+    /// obstacles in `scene` that aren't in `ignore`
     fn follow_path(
         &mut self,
         path: &mut ComputedPath,
         npc: &physics::BaseRigidBody,
-        _dt: std::time::Duration,
+        scene: &CollisionTree,
+        ignore: &HashSet<usize>,
     ) -> ActionResult {
         if self.did_hit_obstacle(npc) {
             self.reset();
@@ -196,7 +357,8 @@ impl StraightLineNav {
             let velocity = if dir.is_zero() {
                 vec3(0., 0., 0.)
             } else {
-                dir.normalize() * SLN_FOLLOW_VELOCITY
+                avoidance::steer(scene, npc, dir, SLN_FOLLOW_VELOCITY, ignore)
+                    * SLN_FOLLOW_VELOCITY
             };
             self.last_velocity = Some(velocity);
             self.last_pos = Some(npc.transform.borrow().get_pos());
@@ -204,13 +366,15 @@ impl StraightLineNav {
             ActionResult::Running(Some(ControllerAction {
                 velocity,
                 fire: false,
+                afterburner: false,
             }))
         } else {
             self.reset();
-            println!("No next point");
+            debug!("No next point");
             ActionResult::Success(Some(ControllerAction {
                 velocity: vec3(0., 0., 0.),
                 fire: false,
+                afterburner: false,
             }))
         }
     }
@@ -221,17 +385,33 @@ impl BTNode for StraightLineNav {
         &mut self,
         _children: &mut [BehaviorTree],
         blackboard: &mut Blackboard,
-        _scene: &CollisionTree,
+        scene: &CollisionTree,
         player: &physics::BaseRigidBody,
-        dt: std::time::Duration,
-        _other_players: PlayerIterator,
+        _dt: std::time::Duration,
+        other_players: PlayerIterator,
     ) -> ActionResult {
-        blackboard
-            .computed_path
-            .as_mut()
-            .map_or(ActionResult::Failure, |path| {
-                self.follow_path(path, player, dt)
-            })
+        if blackboard.weapons_free
+            && blackboard.engaging
+            && Self::in_engagement_range(
+                player,
+                blackboard.target_location,
+                blackboard.profile.engagement_range,
+            )
+        {
+            self.reset();
+            return ActionResult::Success(Some(ControllerAction {
+                velocity: vec3(0., 0., 0.),
+                fire: false,
+                afterburner: false,
+            }));
+        }
+        blackboard.computed_path.as_mut().map_or(
+            ActionResult::Failure,
+            |path| {
+                let ignore = player_ids(player, other_players);
+                self.follow_path(path, player, scene, &ignore)
+            },
+        )
     }
 }
 
@@ -466,9 +646,7 @@ impl BTNode for ComputePath {
     ) -> ActionResult {
         // println!("Ticking ComputePath");
         if let Some(target_location) = &blackboard.target_location {
-            let mut player_map: HashSet<usize> =
-                other_players.copy().map(|p| p.as_ptr() as usize).collect();
-            player_map.insert(player.transform.as_ptr() as usize);
+            let player_map = player_ids(player, other_players);
             let cur_pos = player.transform.borrow().get_pos();
             blackboard.computed_path = Self::get_path(
                 &cur_pos,
@@ -479,12 +657,14 @@ impl BTNode for ComputePath {
             )
             .map(|path| {
                 // println!("Got path to {:?}", target_location);
-                ComputedPath::new(
+                let mut path = ComputedPath::new(
                     path,
                     *target_location,
                     cur_pos,
                     self.tile_dim,
-                )
+                );
+                path.smooth(cur_pos, 0, scene, &player_map);
+                path
             });
             blackboard.path_target_location = blackboard.target_location;
             blackboard
@@ -492,12 +672,81 @@ impl BTNode for ComputePath {
                 .as_ref()
                 .map_or(ActionResult::Failure, |_| ActionResult::Success(None))
         } else {
-            println!("No target location to compute path to");
+            debug!("No target location to compute path to");
             ActionResult::Failure
         }
     }
 }
 
+/// A Behavior Tree action node that repairs the computed path when a large
+/// body moves into its way
+///
+/// Subscribes to changes in the scene, and only replans the part of the path
+/// after the first obstructed segment. If the path can't be repaired, it's
+/// removed so it will be recomputed
+///
+/// Always succeeds
+pub struct InvalidateBlockedPath {
+    tile_dim: f64,
+    subscription: Option<Rc<RefCell<ObstacleSubscription>>>,
+}
+
+impl InvalidateBlockedPath {
+    /// Creates a node that repairs paths computed with tiles of `tile_dim`
+    ///
+    /// Only bodies at least half a tile in radius, which have moved at least
+    /// half a tile, are considered
+    pub const fn new(tile_dim: f64) -> Self {
+        Self {
+            tile_dim,
+            subscription: None,
+        }
+    }
+}
+
+impl BTNode for InvalidateBlockedPath {
+    fn tick(
+        &mut self,
+        _children: &mut [BehaviorTree],
+        blackboard: &mut Blackboard,
+        scene: &CollisionTree,
+        player: &physics::BaseRigidBody,
+        _dt: std::time::Duration,
+        other_players: PlayerIterator,
+    ) -> ActionResult {
+        let tile_dim = self.tile_dim;
+        let events = self
+            .subscription
+            .get_or_insert_with(|| {
+                scene.subscribe(tile_dim * 0.5, tile_dim * 0.5)
+            })
+            .borrow_mut()
+            .drain();
+        if let Some(path) = blackboard.computed_path.as_mut() {
+            let players = player_ids(player, other_players);
+            let pos = player.transform.borrow().get_pos();
+            let blocked = events
+                .iter()
+                .filter(|e| {
+                    e.change != ObstacleChange::Removed
+                        && !players.contains(&e.node_id)
+                })
+                .filter_map(|e| {
+                    path.first_blocked(pos, e.center, e.radius, scene, &players)
+                })
+                .min();
+            if let Some(blocked) = blocked {
+                if !path.repair(blocked, scene, &players) {
+                    debug!("Could not repair blocked path");
+                    blackboard.computed_path = None;
+                    blackboard.path_target_location = None;
+                }
+            }
+        }
+        ActionResult::Success(None)
+    }
+}
+
 /// A Behavior Tree action node that identifies a target to follow
 /// This will succeed if there is a target already identified or if a new target is identified
 /// otherwise it will be running
@@ -521,7 +770,7 @@ impl BTNode for IdentifyTarget {
             if let Some(player) = other_players.copy().next() {
                 blackboard.target_id = Some(player.as_ptr() as usize);
             }
-            println!("Identified target");
+            debug!("Identified target");
         }
         ActionResult::Success(None)
     }
@@ -566,8 +815,11 @@ impl BTNode for SearchForIDedTarget {
                         - player.transform.borrow().get_pos();
                     blackboard.target_location =
                         Some(obj.get_transformation().borrow().get_pos());
+                    let aim_error =
+                        blackboard.aim_error.unwrap_or_else(Matrix3::identity);
                     blackboard.rot =
-                        cg_support::look_at(dir.normalize(), &vec3(0., 1., 0.));
+                        cg_support::look_at(dir.normalize(), &vec3(0., 1., 0.))
+                            .map(|rot| rot * aim_error);
                     return ActionResult::Success(None);
                 }
             }
@@ -655,12 +907,9 @@ mod test {
         let body = physics::BaseRigidBody::new(Rc::new(RefCell::new(
             node::Node::default().pos(start_pos),
         )));
+        let scene = CollisionTree::new(point3(0., 0., 0.), 100.);
         loop {
-            match sln.follow_path(
-                &mut path,
-                &body,
-                std::time::Duration::from_secs(1),
-            ) {
+            match sln.follow_path(&mut path, &body, &scene, &HashSet::new()) {
                 ActionResult::Failure => unreachable!("Shouldn't fail"),
                 ActionResult::Success(maybe_action) => {
                     assert_lt!(
@@ -886,4 +1135,87 @@ mod test {
             cp_mine_field();
         }
     }
+
+    #[serial]
+    #[test]
+    fn cp_smoothed_path_is_clear() {
+        let mut tree = CollisionTree::new(point3(0., 0., 0.), 20.0);
+        let obstacle = CollisionObject::new(
+            Rc::new(RefCell::new(node::Node::default())),
+            "assets/default_cube.obj",
+            TreeStopCriteria::default(),
+        );
+        tree.insert(&obstacle);
+        let tile_dim = 1.0;
+        let start = point3(-8f64, 0., 0.);
+        let target = point3(8f64, 3., 0.);
+        let players = HashSet::new();
+        let path =
+            ComputePath::get_path(&start, tile_dim, &target, &tree, &players)
+                .unwrap();
+        let mut path = ComputedPath::new(path, target, start, tile_dim);
+        let unsmoothed_len = path.path.len();
+        let last = *path.path.back().unwrap();
+        path.smooth(start, 0, &tree, &players);
+        assert_lt!(path.path.len(), unsmoothed_len);
+        assert_eq!(*path.path.back().unwrap(), last);
+        let mut prev = start;
+        for idx in &path.path {
+            let pt = path.index_to_point(idx);
+            assert!(is_line_clear(prev, pt, tile_dim, &tree, &players));
+            prev = pt;
+        }
+    }
+
+    #[serial]
+    #[test]
+    fn cp_repairs_only_blocked_segments() {
+        let mut tree = CollisionTree::new(point3(0., 0., 0.), 20.0);
+        let events = tree.subscribe(0.5, 0.5);
+        let tile_dim = 1.0;
+        let start = point3(-10f64, 0., 0.);
+        let target = point3(10f64, 0., 0.);
+        let players = HashSet::new();
+        let path =
+            ComputePath::get_path(&start, tile_dim, &target, &tree, &players)
+                .unwrap();
+        let mut path = ComputedPath::new(path, target, start, tile_dim);
+        let original = path.path.clone();
+        let obstacle = CollisionObject::new(
+            Rc::new(RefCell::new(node::Node::default())),
+            "assets/default_cube.obj",
+            TreeStopCriteria::default(),
+        );
+        tree.insert(&obstacle);
+        let event = events.borrow_mut().drain()[0];
+        assert_eq!(event.change, ObstacleChange::Inserted);
+        let blocked = path
+            .first_blocked(start, event.center, event.radius, &tree, &players)
+            .unwrap();
+        assert_gt!(blocked, 0);
+        assert!(path.repair(blocked, &tree, &players));
+        assert!(path
+            .path
+            .iter()
+            .take(blocked)
+            .eq(original.iter().take(blocked)));
+        let mut prev = start;
+        for idx in &path.path {
+            let pt = path.index_to_point(idx);
+            assert!(is_line_clear(prev, pt, tile_dim, &tree, &players));
+            prev = pt;
+        }
+        assert_relative_eq!(
+            path.index_to_point(path.path.back().unwrap()),
+            target
+        );
+    }
+
+    #[test]
+    fn segment_distance() {
+        let (a, b) = (point3(0., 0., 0.), point3(10., 0., 0.));
+        assert_relative_eq!(distance_to_segment(a, b, point3(5., 3., 0.)), 3.);
+        assert_relative_eq!(distance_to_segment(a, b, point3(-4., 3., 0.)), 5.);
+        assert_relative_eq!(distance_to_segment(a, a, point3(0., 0., 2.)), 2.);
+    }
 }
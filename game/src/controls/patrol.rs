@@ -0,0 +1,206 @@
+use super::ai::{ActionResult, BTNode, BehaviorTree, Blackboard};
+use super::PlayerIterator;
+use crate::cg_support;
+use crate::collisions::CollisionTree;
+use crate::physics;
+use cgmath::*;
+
+/// Distance from a waypoint the player must be within to have reached it
+pub(super) const WAYPOINT_ARRIVAL_DIST: f64 = 15.;
+
+/// A looping list of points an AI controlled player patrols between
+#[derive(Clone, Debug, Default)]
+pub struct Waypoints {
+    points: Vec<Point3<f64>>,
+    next: usize,
+}
+
+impl Waypoints {
+    /// Creates a route that visits `points` in order, returning to the first
+    /// point after the last
+    pub const fn new(points: Vec<Point3<f64>>) -> Self {
+        Self { points, next: 0 }
+    }
+
+    /// Gets the waypoint the player is heading to, or `None` if the route is
+    /// empty
+    pub fn current(&self) -> Option<Point3<f64>> {
+        self.points.get(self.next).copied()
+    }
+
+    /// Moves on to the next waypoint in the route
+    pub fn advance(&mut self) {
+        if !self.points.is_empty() {
+            self.next = (self.next + 1) % self.points.len();
+        }
+    }
+
+    /// Heads back to the first waypoint
+    pub fn restart(&mut self) {
+        self.next = 0;
+    }
+}
+
+/// A Behavior Tree action node that moves the player through its waypoints
+///
+/// Sets the target location to the current waypoint, advancing to the next
+/// waypoint once the player reaches it, and faces the player towards it.
+/// While patrolling, the player doesn't engage any targets
+///
+/// Always succeeds. If the player has no waypoints, the target location is
+/// left as is
+pub struct PatrolRoute {}
+
+impl BTNode for PatrolRoute {
+    fn tick(
+        &mut self,
+        _children: &mut [BehaviorTree],
+        blackboard: &mut Blackboard,
+        _scene: &CollisionTree,
+        player: &physics::BaseRigidBody,
+        _dt: std::time::Duration,
+        _other_players: PlayerIterator,
+    ) -> ActionResult {
+        blackboard.engaging = false;
+        let pos = player.transform.borrow().get_pos();
+        if blackboard
+            .waypoints
+            .current()
+            .map_or(false, |pt| pt.distance(pos) < WAYPOINT_ARRIVAL_DIST)
+        {
+            blackboard.waypoints.advance();
+        }
+        if let Some(waypoint) = blackboard.waypoints.current() {
+            blackboard.target_location = Some(waypoint);
+            if let Some(rot) =
+                cg_support::look_at(waypoint - pos, &vec3(0., 1., 0.))
+            {
+                blackboard.rot = Some(rot);
+            }
+        }
+        ActionResult::Success(None)
+    }
+}
+
+/// A Behavior Tree condition node that only lets the player engage targets
+/// within an area
+///
+/// Succeeds if the player's target is within `radius` of `center`. Otherwise,
+/// fails and sets the target location to `center` so that a player without
+/// waypoints returns to and holds its post
+pub struct GuardArea {
+    center: Point3<f64>,
+    radius: f64,
+}
+
+impl GuardArea {
+    pub const fn new(center: Point3<f64>, radius: f64) -> Self {
+        Self { center, radius }
+    }
+}
+
+impl BTNode for GuardArea {
+    fn tick(
+        &mut self,
+        _children: &mut [BehaviorTree],
+        blackboard: &mut Blackboard,
+        _scene: &CollisionTree,
+        _player: &physics::BaseRigidBody,
+        _dt: std::time::Duration,
+        _other_players: PlayerIterator,
+    ) -> ActionResult {
+        match blackboard.target_location {
+            Some(target) if target.distance(self.center) <= self.radius => {
+                blackboard.engaging = true;
+                ActionResult::Success(None)
+            }
+            _ => {
+                blackboard.target_location = Some(self.center);
+                ActionResult::Failure
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cg_support::node;
+    use crate::controls::ai::AiProfile;
+    use crate::controls::PlayerIteratorHolder;
+    use assert_approx_eq::assert_approx_eq;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn tick<N: BTNode>(
+        node: &mut N,
+        blackboard: &mut Blackboard,
+        pos: Point3<f64>,
+    ) -> ActionResult {
+        let body = physics::BaseRigidBody::new(Rc::new(RefCell::new(
+            node::Node::default().pos(pos),
+        )));
+        node.tick(
+            &mut [],
+            blackboard,
+            &CollisionTree::new(point3(0., 0., 0.), 10.),
+            &body,
+            std::time::Duration::from_millis(16),
+            &PlayerIteratorHolder(
+                std::iter::empty::<Rc<RefCell<node::Node>>>(),
+            ),
+        )
+    }
+
+    #[test]
+    fn waypoints_loop() {
+        let mut route =
+            Waypoints::new(vec![point3(0., 0., 0.), point3(1., 0., 0.)]);
+        assert_eq!(route.current(), Some(point3(0., 0., 0.)));
+        route.advance();
+        assert_eq!(route.current(), Some(point3(1., 0., 0.)));
+        route.advance();
+        assert_eq!(route.current(), Some(point3(0., 0., 0.)));
+        let mut empty = Waypoints::default();
+        empty.advance();
+        assert_eq!(empty.current(), None);
+    }
+
+    #[test]
+    fn patrol_advances_at_waypoints() {
+        let mut blackboard = Blackboard::new(AiProfile::normal());
+        blackboard.waypoints =
+            Waypoints::new(vec![point3(100., 0., 0.), point3(0., 0., 100.)]);
+        let mut patrol = PatrolRoute {};
+        assert!(matches!(
+            tick(&mut patrol, &mut blackboard, point3(0., 0., 0.)),
+            ActionResult::Success(None)
+        ));
+        assert_eq!(blackboard.target_location, Some(point3(100., 0., 0.)));
+        assert!(!blackboard.engaging);
+        // faces the waypoint
+        let forward = blackboard.rot.unwrap() * vec3(0., 0., 1.);
+        assert_approx_eq!(forward.x, 1.);
+        tick(&mut patrol, &mut blackboard, point3(95., 0., 0.));
+        assert_eq!(blackboard.target_location, Some(point3(0., 0., 100.)));
+    }
+
+    #[test]
+    fn guard_only_engages_targets_in_area() {
+        let mut blackboard = Blackboard::new(AiProfile::normal());
+        let mut guard = GuardArea::new(point3(0., 0., 0.), 50.);
+        blackboard.engaging = false;
+        blackboard.target_location = Some(point3(0., 40., 0.));
+        assert!(matches!(
+            tick(&mut guard, &mut blackboard, point3(0., 0., 0.)),
+            ActionResult::Success(_)
+        ));
+        assert!(blackboard.engaging);
+        blackboard.target_location = Some(point3(0., 60., 0.));
+        assert!(matches!(
+            tick(&mut guard, &mut blackboard, point3(0., 0., 0.)),
+            ActionResult::Failure
+        ));
+        assert_eq!(blackboard.target_location, Some(point3(0., 0., 0.)));
+    }
+}
@@ -0,0 +1,231 @@
+use super::bindings::{Action, Axis};
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::time::Duration;
+
+/// The kind of device that moved an axis
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum AxisSource {
+    /// Mouse movement is relative, and only lasts for the frame it
+    /// occurred in
+    Mouse,
+    /// Gamepad sticks are absolute, and hold their value until they move
+    Gamepad,
+}
+
+/// An input after it was mapped to a game action
+///
+/// Inputs are recorded after they are mapped so that a recording can be
+/// played back with different bindings
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum InputEvent {
+    /// An action was started (`true`) or stopped (`false`)
+    Action(Action, bool),
+    /// An axis moved to a value
+    Axis(AxisSource, Axis, f64),
+}
+
+impl Display for InputEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Action(action, true) => write!(f, "press {}", action),
+            Self::Action(action, false) => write!(f, "release {}", action),
+            Self::Axis(AxisSource::Mouse, axis, val) => {
+                write!(f, "mouse {} {}", axis, val)
+            }
+            Self::Axis(AxisSource::Gamepad, axis, val) => {
+                write!(f, "gamepad {} {}", axis, val)
+            }
+        }
+    }
+}
+
+impl TryFrom<&str> for InputEvent {
+    type Error = Box<dyn Error>;
+    fn try_from(line: &str) -> Result<Self, Self::Error> {
+        let words: Vec<_> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["press", action] => {
+                Ok(Self::Action(Action::try_from(*action)?, true))
+            }
+            ["release", action] => {
+                Ok(Self::Action(Action::try_from(*action)?, false))
+            }
+            [source @ ("mouse" | "gamepad"), axis, val] => Ok(Self::Axis(
+                if *source == "mouse" {
+                    AxisSource::Mouse
+                } else {
+                    AxisSource::Gamepad
+                },
+                Axis::try_from(*axis)?,
+                val.parse()?,
+            )),
+            _ => Err(format!("Malformed input event: \"{}\"", line).into()),
+        }
+    }
+}
+
+/// The inputs that occurred before a frame, and the frame's time step
+#[derive(Clone, PartialEq, Debug)]
+pub struct RecordedFrame {
+    pub dt: Duration,
+    pub events: Vec<InputEvent>,
+}
+
+impl Display for RecordedFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "frame {}", self.dt.as_nanos())?;
+        for event in &self.events {
+            writeln!(f, "{}", event)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses an input recording
+///
+/// A recording is a list of frames. Each frame starts with a
+/// `frame <dt in nanoseconds>` line, followed by one line per input event
+/// that occurred before the frame. Empty lines and lines beginning with `#`
+/// are ignored
+///
+/// # Errors
+/// Fails if a line is malformed, or an event occurs before the first frame
+pub fn parse_recording(
+    recording: &str,
+) -> Result<Vec<RecordedFrame>, Box<dyn Error>> {
+    let mut frames = Vec::new();
+    for line in recording
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+    {
+        if let Some(dt) = line.strip_prefix("frame ") {
+            frames.push(RecordedFrame {
+                dt: Duration::from_nanos(dt.trim().parse()?),
+                events: Vec::new(),
+            });
+        } else {
+            frames
+                .last_mut()
+                .ok_or_else(|| {
+                    format!("Event before first frame: \"{}\"", line)
+                })?
+                .events
+                .push(InputEvent::try_from(line)?);
+        }
+    }
+    Ok(frames)
+}
+
+/// Writes every frame of input to a file as it happens, so that the recording
+/// is kept even if the game crashes
+pub struct InputRecorder {
+    writer: BufWriter<File>,
+    pending: Vec<InputEvent>,
+}
+
+impl InputRecorder {
+    /// Starts a new recording at `path`, overwriting any existing file
+    ///
+    /// # Errors
+    /// Fails if the file cannot be created
+    pub fn new(path: &str) -> std::io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "# Oort input recording")?;
+        Ok(Self {
+            writer,
+            pending: Vec::new(),
+        })
+    }
+
+    /// Records an event for the upcoming frame
+    pub fn record(&mut self, event: InputEvent) {
+        self.pending.push(event);
+    }
+
+    /// Writes the events since the last frame along with the time step of the
+    /// frame that's being started
+    ///
+    /// # Errors
+    /// Fails if the recording cannot be written
+    pub fn end_frame(&mut self, dt: Duration) -> std::io::Result<()> {
+        let frame = RecordedFrame {
+            dt,
+            events: std::mem::take(&mut self.pending),
+        };
+        write!(self.writer, "{}", frame)?;
+        self.writer.flush()
+    }
+}
+
+/// Plays back a recording frame by frame
+pub struct InputPlayback {
+    frames: VecDeque<RecordedFrame>,
+}
+
+impl InputPlayback {
+    /// Loads the recording at `path`
+    ///
+    /// # Errors
+    /// Fails if the file cannot be read or parsed
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            frames: parse_recording(&std::fs::read_to_string(path)?)?.into(),
+        })
+    }
+
+    /// Gets the next recorded frame, or `None` if the playback is finished
+    pub fn next_frame(&mut self) -> Option<RecordedFrame> {
+        self.frames.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn recording_round_trips() {
+        let frames = vec![
+            RecordedFrame {
+                dt: Duration::from_nanos(16_666_667),
+                events: vec![
+                    InputEvent::Action(Action::Forward, true),
+                    InputEvent::Axis(AxisSource::Mouse, Axis::Pitch, -3.25),
+                ],
+            },
+            RecordedFrame {
+                dt: Duration::from_millis(17),
+                events: Vec::new(),
+            },
+            RecordedFrame {
+                dt: Duration::from_millis(15),
+                events: vec![
+                    InputEvent::Axis(AxisSource::Gamepad, Axis::Thrust, 0.1),
+                    InputEvent::Action(Action::FireRope, false),
+                ],
+            },
+        ];
+        let text: String = frames.iter().map(ToString::to_string).collect();
+        assert_eq!(parse_recording(&text).unwrap(), frames);
+    }
+
+    #[test]
+    fn malformed_recordings_are_rejected() {
+        assert!(parse_recording("press fire\nframe 10").is_err());
+        assert!(parse_recording("frame 10\npress jump").is_err());
+        assert!(parse_recording("frame 10\nmouse pitch").is_err());
+        assert!(parse_recording("frame ten").is_err());
+        let frames =
+            parse_recording("# comment\n\nframe 10\n  release fire  ").unwrap();
+        assert_eq!(
+            frames[0].events,
+            vec![InputEvent::Action(Action::Fire, false)]
+        );
+    }
+}
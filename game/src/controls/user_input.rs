@@ -1,14 +1,78 @@
-use super::{Movement, MovementControl, PlayerActionState, PlayerIterator};
+use super::autopilot::{Autopilot, AutopilotRequest, Destination};
+use super::bindings::*;
+use super::recording::*;
+use super::{
+    Movement, MovementControl, PlayerActionState, PlayerIterator, Reel,
+};
 use crate::{collisions, physics};
 use glutin::event::*;
+use shared_types::survival::ShopItem;
+use std::collections::HashMap;
+use std::error::Error;
+use tracing::{info, warn};
+
+/// Where the inputs of the player come from
+enum InputMode {
+    Live,
+    /// Inputs come from the devices and are written to a recording
+    Recording(InputRecorder),
+    /// Inputs come from a recording. Device inputs are ignored
+    Playback(InputPlayback),
+}
+
 // `PlayerControls` converts device inputs to higher level
 /// game controls
+///
+/// Inputs from the keyboard, mouse, and gamepads are converted to actions by
+/// an `InputMap`, so every device controls the player the same way
+///
+/// The mapped inputs can be recorded to a file and played back later. When
+/// the time step of every frame is taken from `begin_frame`, playing back a
+/// recording repeats the same inputs at the same frames with the same time
+/// steps
 pub struct PlayerControls {
-    movement: Movement,
-    pitch: f64,
-    roll: f64,
+    forward_held: bool,
+    backward_held: bool,
+    free_look_held: bool,
+    reel_in_held: bool,
+    reel_out_held: bool,
+    beam_held: bool,
+    afterburner_held: bool,
+    /// `false` while the ship drifts without flight assist
+    flight_assist: bool,
+    /// Mouse movement while free look is held, since the camera last took it
+    orbit: (f64, f64),
+    /// Movement of the mouse along each axis during the current frame
+    mouse_axes: HashMap<Axis, f64>,
+    /// Position of the gamepad inputs along each axis
+    gamepad_axes: HashMap<Axis, f64>,
+    bindings: InputMap,
+    gamepad: Option<gilrs::Gilrs>,
+    mode: InputMode,
     state: PlayerActionState,
     mouse_capture: bool,
+    /// Set when the player asks to lock on to the next target, until the
+    /// request is taken by the game
+    cycle_target: bool,
+    /// Set when the player asks to switch to the next power distribution,
+    /// until the request is taken by the game
+    cycle_power: bool,
+    /// Item the player asked to buy, until the request is taken by the game
+    purchase: Option<ShopItem>,
+    /// Zoom levels the player asked to zoom the minimap in by, until the
+    /// request is taken by the HUD
+    minimap_zoom: i32,
+    /// `true` if the 3D radar is shown instead of the minimap
+    radar_shown: bool,
+    tactical_map_shown: bool,
+    /// Flies the player to the waypoint set on the tactical map, or the
+    /// destination the player asked for, until the player arrives, thrusts,
+    /// or steers
+    autopilot: Option<Autopilot>,
+    /// Where the player asked the autopilot to fly, until the request is
+    /// taken by the game
+    autopilot_request: Option<AutopilotRequest>,
+    camera_tracking: bool,
     visible: bool,
     vis_transition_start: std::time::Instant,
     inv_fac: f32,
@@ -18,10 +82,30 @@ pub struct PlayerControls {
 impl PlayerControls {
     pub fn new() -> Self {
         Self {
-            movement: Movement::Stopped,
+            forward_held: false,
+            backward_held: false,
+            free_look_held: false,
+            reel_in_held: false,
+            reel_out_held: false,
+            beam_held: false,
+            afterburner_held: false,
+            flight_assist: true,
+            orbit: (0., 0.),
             mouse_capture: false,
-            pitch: 0.,
-            roll: 0.,
+            cycle_target: false,
+            cycle_power: false,
+            purchase: None,
+            minimap_zoom: 0,
+            radar_shown: false,
+            tactical_map_shown: false,
+            autopilot: None,
+            autopilot_request: None,
+            camera_tracking: true,
+            mouse_axes: HashMap::new(),
+            gamepad_axes: HashMap::new(),
+            bindings: InputMap::default(),
+            gamepad: None,
+            mode: InputMode::Live,
             state: PlayerActionState::Idle,
             visible: true,
             vis_transition_start: std::time::Instant::now(),
@@ -29,6 +113,95 @@ impl PlayerControls {
             inv_trans_fac_start: 0.,
         }
     }
+
+    /// Enables gamepad input. If gamepads aren't supported on this platform,
+    /// only the keyboard and mouse are used
+    #[must_use]
+    pub fn with_gamepad(mut self) -> Self {
+        // deadzones are applied by the input map
+        match gilrs::GilrsBuilder::new()
+            .with_default_filters(false)
+            .build()
+        {
+            Ok(gamepad) => self.gamepad = Some(gamepad),
+            Err(e) => warn!("Gamepad input disabled: {}", e),
+        }
+        self
+    }
+
+    /// Sets the mapping of inputs to actions
+    #[must_use]
+    pub fn with_bindings(mut self, bindings: InputMap) -> Self {
+        self.bindings = bindings;
+        self
+    }
+
+    /// Gets the mapping of inputs to actions, which can be used to rebind
+    /// inputs while playing
+    #[allow(dead_code)]
+    pub fn bindings_mut(&mut self) -> &mut InputMap {
+        &mut self.bindings
+    }
+
+    /// Starts writing every input to a new recording at `path`
+    ///
+    /// # Errors
+    /// Fails if the recording file cannot be created
+    pub fn start_recording(&mut self, path: &str) -> std::io::Result<()> {
+        self.mode = InputMode::Recording(InputRecorder::new(path)?);
+        Ok(())
+    }
+
+    /// Starts playing back the recording at `path`. Device inputs are ignored
+    /// until the playback is finished
+    ///
+    /// # Errors
+    /// Fails if the recording cannot be read or parsed
+    pub fn start_playback(&mut self, path: &str) -> Result<(), Box<dyn Error>> {
+        self.mode = InputMode::Playback(InputPlayback::from_file(path)?);
+        self.release_mouse();
+        Ok(())
+    }
+
+    /// Should be called at the start of every frame with the time since the
+    /// last frame. Returns the time step the frame should be simulated with
+    ///
+    /// During playback, this applies the recorded inputs of the frame and
+    /// returns the recorded time step. While recording, this writes the
+    /// inputs since the last frame along with `dt`
+    pub fn begin_frame(
+        &mut self,
+        dt: std::time::Duration,
+    ) -> std::time::Duration {
+        match &mut self.mode {
+            InputMode::Live => dt,
+            InputMode::Recording(recorder) => {
+                if let Err(e) = recorder.end_frame(dt) {
+                    warn!("Stopping input recording: {}", e);
+                    self.mode = InputMode::Live;
+                }
+                dt
+            }
+            InputMode::Playback(playback) => {
+                if let Some(frame) = playback.next_frame() {
+                    for event in frame.events {
+                        self.apply_event(event);
+                    }
+                    frame.dt
+                } else {
+                    info!("Input playback finished");
+                    self.mode = InputMode::Live;
+                    dt
+                }
+            }
+        }
+    }
+
+    /// `true` if inputs from the devices should be ignored
+    const fn is_playing_back(&self) -> bool {
+        matches!(self.mode, InputMode::Playback(_))
+    }
+
     /// Changes the mouse capture mode and returns the new value
     fn change_mouse_mode(
         mouse_capture: bool,
@@ -40,10 +213,268 @@ impl PlayerControls {
         rev
     }
 
+    /// Releases the mouse if it is captured and stops any movement
+    /// that was started by a held key
+    pub fn release_mouse(&mut self) {
+        if self.mouse_capture {
+            let ctx = crate::graphics_engine::get_active_ctx();
+            self.mouse_capture = Self::change_mouse_mode(
+                self.mouse_capture,
+                &*ctx.ctx.borrow().gl_window().window(),
+            );
+        }
+        for action in [
+            Action::Forward,
+            Action::Backward,
+            Action::FreeLook,
+            Action::Afterburner,
+        ] {
+            self.on_event(InputEvent::Action(action, false));
+        }
+        self.release_gamepad();
+    }
+
+    /// Gets the number of levels the player asked to zoom the minimap in by
+    /// since the last call. Negative values zoom out
+    pub fn take_minimap_zoom(&mut self) -> i32 {
+        std::mem::take(&mut self.minimap_zoom)
+    }
+
+    /// Returns `true` if the player chose to show the 3D radar instead of
+    /// the minimap
+    pub const fn is_radar_shown(&self) -> bool {
+        self.radar_shown
+    }
+
+    /// Returns `true` if the player chose to show the tactical map
+    pub const fn is_tactical_map_shown(&self) -> bool {
+        self.tactical_map_shown
+    }
+
+    /// Hides the tactical map
+    pub fn close_tactical_map(&mut self) {
+        self.tactical_map_shown = false;
+    }
+
+    /// Flies the player to `waypoint` on autopilot, or stops the autopilot
+    /// if `waypoint` is `None`
+    pub fn set_waypoint(&mut self, waypoint: Option<cgmath::Point3<f64>>) {
+        self.set_destination(waypoint.map(Destination::Waypoint));
+    }
+
+    /// Flies the player to `destination` on autopilot, or stops the
+    /// autopilot if `destination` is `None`
+    pub fn set_destination(&mut self, destination: Option<Destination>) {
+        self.autopilot = destination.map(Autopilot::new);
+    }
+
+    /// Gets the point the autopilot is flying the player to, if it's on
+    pub fn waypoint(&self) -> Option<cgmath::Point3<f64>> {
+        self.autopilot.as_ref().map(Autopilot::waypoint)
+    }
+
+    /// Gets where the player asked the autopilot to fly since the last
+    /// call, if anywhere
+    pub fn take_autopilot_request(&mut self) -> Option<AutopilotRequest> {
+        self.autopilot_request.take()
+    }
+
+    /// Returns `true` if the player is turning or thrusting with the mouse
+    /// or a gamepad
+    fn is_steering(&self) -> bool {
+        [Axis::Pitch, Axis::Roll, Axis::Thrust]
+            .iter()
+            .any(|axis| self.axis(*axis).abs() > f64::EPSILON)
+    }
+
+    /// Resets the gamepad axes to their resting position
+    fn release_gamepad(&mut self) {
+        for axis in [Axis::Pitch, Axis::Roll, Axis::Thrust] {
+            if self.gamepad_axes.contains_key(&axis) {
+                self.on_event(InputEvent::Axis(AxisSource::Gamepad, axis, 0.));
+            }
+        }
+    }
+
+    /// Records `event` if recording, then applies it
+    fn on_event(&mut self, event: InputEvent) {
+        if let InputMode::Recording(recorder) = &mut self.mode {
+            // toggling the mouse capture doesn't affect the game
+            if !matches!(
+                event,
+                InputEvent::Action(Action::ToggleMouseCapture, _)
+            ) {
+                recorder.record(event);
+            }
+        }
+        self.apply_event(event);
+    }
+
+    /// Performs the action bound to `button` when it is pressed or released
+    fn on_button(&mut self, button: Button, state: ElementState) {
+        if let Some(action) = self.bindings.action(button) {
+            self.on_event(InputEvent::Action(
+                action,
+                state == ElementState::Pressed,
+            ));
+        }
+    }
+
+    /// Moves the axis bound to `input`
+    fn on_analog(&mut self, input: AnalogInput, value: f64) {
+        if let Some((axis, val)) = self.bindings.axis(input, value) {
+            let source = match input {
+                AnalogInput::MouseX | AnalogInput::MouseY => AxisSource::Mouse,
+                AnalogInput::Gamepad(_) => AxisSource::Gamepad,
+            };
+            self.on_event(InputEvent::Axis(source, axis, val));
+        }
+    }
+
+    /// Updates the controls from a mapped input
+    fn apply_event(&mut self, event: InputEvent) {
+        match event {
+            InputEvent::Action(action, pressed) => {
+                self.apply_action(action, pressed);
+            }
+            InputEvent::Axis(AxisSource::Mouse, axis, val)
+                if self.free_look_held =>
+            {
+                match axis {
+                    Axis::Roll => self.orbit.0 += val,
+                    Axis::Pitch => self.orbit.1 += val,
+                    Axis::Thrust => (),
+                }
+            }
+            InputEvent::Axis(AxisSource::Mouse, axis, val) => {
+                self.mouse_axes.insert(axis, val);
+            }
+            InputEvent::Axis(AxisSource::Gamepad, axis, val) => {
+                self.gamepad_axes.insert(axis, val);
+            }
+        }
+    }
+
+    /// Starts (`pressed == true`) or stops an action
+    fn apply_action(&mut self, action: Action, pressed: bool) {
+        match action {
+            Action::Forward | Action::Backward => {
+                if action == Action::Forward {
+                    self.forward_held = pressed;
+                } else {
+                    self.backward_held = pressed;
+                }
+                // thrusting takes the controls back from the autopilot
+                if pressed {
+                    self.autopilot = None;
+                }
+            }
+            Action::ReelIn => self.reel_in_held = pressed,
+            Action::ReelOut => self.reel_out_held = pressed,
+            Action::TractorBeam => self.beam_held = pressed,
+            Action::Afterburner => self.afterburner_held = pressed,
+            Action::FreeLook => {
+                self.free_look_held = pressed;
+                self.orbit = (0., 0.);
+            }
+            Action::Fire if pressed => {
+                self.state = PlayerActionState::Fire;
+            }
+            Action::FireRope => {
+                self.state = if pressed {
+                    PlayerActionState::FireRope
+                } else {
+                    PlayerActionState::CutRope
+                };
+            }
+            Action::ToggleVisibility if pressed => {
+                self.set_visible(!self.visible);
+            }
+            Action::CycleTarget if pressed => self.cycle_target = true,
+            Action::CyclePowerDistribution if pressed => {
+                self.cycle_power = true;
+            }
+            Action::Buy(item) if pressed => self.purchase = Some(item),
+            Action::ZoomMinimapIn if pressed => self.minimap_zoom += 1,
+            Action::ZoomMinimapOut if pressed => self.minimap_zoom -= 1,
+            Action::ToggleRadar if pressed => {
+                self.radar_shown = !self.radar_shown;
+            }
+            Action::ToggleTacticalMap if pressed => {
+                self.tactical_map_shown = !self.tactical_map_shown;
+                // the cursor is needed to pick waypoints
+                if self.tactical_map_shown {
+                    self.release_mouse();
+                }
+            }
+            Action::AutopilotToTarget if pressed => {
+                self.autopilot_request = Some(AutopilotRequest::LockedTarget);
+            }
+            Action::AutopilotDock if pressed => {
+                self.autopilot_request = Some(AutopilotRequest::Dock);
+            }
+            Action::ToggleFlightAssist if pressed => {
+                self.flight_assist = !self.flight_assist;
+            }
+            Action::ToggleCameraTracking if pressed => {
+                self.camera_tracking = !self.camera_tracking;
+            }
+            Action::ToggleMouseCapture if pressed => {
+                let ctx = crate::graphics_engine::get_active_ctx();
+                self.mouse_capture = Self::change_mouse_mode(
+                    self.mouse_capture,
+                    &*ctx.ctx.borrow().gl_window().window(),
+                );
+            }
+            _ => (),
+        }
+    }
+
+    /// Starts fading the player in if `visible` is `true`, or out otherwise
+    fn set_visible(&mut self, visible: bool) {
+        self.inv_trans_fac_start = self.inv_fac;
+        self.vis_transition_start = std::time::Instant::now();
+        self.visible = visible;
+    }
+
+    /// Handles the events of every connected gamepad since the last poll
+    fn poll_gamepad(&mut self) {
+        use gilrs::EventType;
+        if self.is_playing_back() {
+            return;
+        }
+        let mut events = Vec::new();
+        if let Some(gamepad) = &mut self.gamepad {
+            while let Some(ev) = gamepad.next_event() {
+                events.push(ev.event);
+            }
+        }
+        for ev in events {
+            match ev {
+                EventType::ButtonPressed(button, _) => self
+                    .on_button(Button::Gamepad(button), ElementState::Pressed),
+                EventType::ButtonReleased(button, _) => self
+                    .on_button(Button::Gamepad(button), ElementState::Released),
+                EventType::AxisChanged(axis, val, _) => {
+                    self.on_analog(AnalogInput::Gamepad(axis), f64::from(val))
+                }
+                EventType::Disconnected => self.release_gamepad(),
+                _ => (),
+            }
+        }
+    }
+
+    /// Gets the combined value of all inputs bound to `axis`
+    fn axis(&self, axis: Axis) -> f64 {
+        self.mouse_axes.get(&axis).copied().unwrap_or(0.)
+            + self.gamepad_axes.get(&axis).copied().unwrap_or(0.)
+    }
+
     /// Callback to handle input events from the window to control the player
-    #[allow(clippy::too_many_lines)]
     pub fn on_input(&mut self, ev: &DeviceEvent) {
-        let ctx = crate::graphics_engine::get_active_ctx();
+        if self.is_playing_back() {
+            return;
+        }
         match ev {
             #[allow(deprecated)]
             DeviceEvent::Key(KeyboardInput {
@@ -51,47 +482,15 @@ impl PlayerControls {
                 state,
                 virtual_keycode: Some(vk),
                 modifiers: _,
-            }) => match (vk, state) {
-                (VirtualKeyCode::W, ElementState::Pressed) => {
-                    self.movement = Movement::Forward;
-                }
-                (
-                    VirtualKeyCode::W | VirtualKeyCode::S,
-                    ElementState::Released,
-                ) => {
-                    self.movement = Movement::Stopped;
-                }
-                (VirtualKeyCode::S, ElementState::Pressed) => {
-                    self.movement = Movement::Backwards;
-                }
-                (VirtualKeyCode::T, ElementState::Pressed) => {
-                    self.inv_trans_fac_start = self.inv_fac;
-                    self.vis_transition_start = std::time::Instant::now();
-                    self.visible = !self.visible;
-                }
-                (VirtualKeyCode::Escape, ElementState::Pressed) => {
-                    self.mouse_capture = Self::change_mouse_mode(
-                        self.mouse_capture,
-                        &*ctx.ctx.borrow().gl_window().window(),
-                    );
-                }
-                _ => (),
-            },
+            }) => self.on_button(Button::Key(*vk), *state),
             DeviceEvent::MouseMotion { delta: (dx, dy) }
                 if self.mouse_capture =>
             {
-                self.pitch = *dy;
-                self.roll = *dx;
+                self.on_analog(AnalogInput::MouseX, *dx);
+                self.on_analog(AnalogInput::MouseY, *dy);
             }
             DeviceEvent::Button { button, state } if self.mouse_capture => {
-                // button 1 is lmouse, 3 is rmouse, 2 is middle mouse
-                if *button == 1 && *state == ElementState::Pressed {
-                    self.state = PlayerActionState::Fire;
-                } else if *button == 3 && *state == ElementState::Pressed {
-                    self.state = PlayerActionState::FireRope;
-                } else if *button == 3 && *state == ElementState::Released {
-                    self.state = PlayerActionState::CutRope;
-                }
+                self.on_button(Button::Mouse(*button), *state);
             }
             _ => (),
         }
@@ -100,11 +499,21 @@ impl PlayerControls {
 
 impl MovementControl for PlayerControls {
     fn get_movement(&self) -> Movement {
-        self.movement
+        if self.forward_held {
+            Movement::Forward
+        } else if self.backward_held {
+            Movement::Backwards
+        } else {
+            match self.axis(Axis::Thrust) {
+                t if t > f64::EPSILON => Movement::Forward,
+                t if t < -f64::EPSILON => Movement::Backwards,
+                _ => Movement::Stopped,
+            }
+        }
     }
 
     fn get_snapped_rot(&self) -> Option<cgmath::Matrix3<f64>> {
-        None
+        self.autopilot.as_ref().and_then(Autopilot::get_snapped_rot)
     }
 
     fn is_ai(&self) -> bool {
@@ -112,11 +521,11 @@ impl MovementControl for PlayerControls {
     }
 
     fn get_roll(&self) -> f64 {
-        self.roll
+        self.axis(Axis::Roll)
     }
 
     fn get_pitch(&self) -> f64 {
-        self.pitch
+        self.axis(Axis::Pitch)
     }
 
     fn get_action_state(&self) -> PlayerActionState {
@@ -145,19 +554,319 @@ impl MovementControl for PlayerControls {
 
     fn on_frame_update(
         &mut self,
-        _scene: &collisions::CollisionTree,
-        _player: &physics::BaseRigidBody,
-        _dt: std::time::Duration,
-        _other_players: PlayerIterator,
+        scene: &collisions::CollisionTree,
+        player: &physics::BaseRigidBody,
+        dt: std::time::Duration,
+        other_players: PlayerIterator,
     ) -> Option<super::ControllerAction> {
-        self.pitch = 0.;
-        self.roll = 0.;
-        None
+        // steering takes the controls back from the autopilot
+        if self.is_steering() {
+            self.autopilot = None;
+        }
+        self.mouse_axes.clear();
+        self.poll_gamepad();
+        let autopilot = self.autopilot.as_mut()?;
+        let action =
+            autopilot.on_frame_update(scene, player, dt, other_players);
+        if !autopilot.is_engaged() {
+            self.autopilot = None;
+        }
+        action
     }
 
     fn on_death(&mut self) {
+        self.autopilot = None;
         self.state = PlayerActionState::Idle;
         self.visible = true;
         self.inv_fac = 0.0;
     }
+
+    fn take_cycle_target(&mut self) -> bool {
+        std::mem::take(&mut self.cycle_target)
+    }
+
+    fn get_reel(&self) -> Reel {
+        if self.reel_in_held {
+            Reel::In
+        } else if self.reel_out_held {
+            Reel::Out
+        } else {
+            Reel::Hold
+        }
+    }
+
+    fn is_beam_held(&self) -> bool {
+        self.beam_held
+    }
+
+    fn take_cycle_power(&mut self) -> bool {
+        std::mem::take(&mut self.cycle_power)
+    }
+
+    fn is_afterburning(&self) -> bool {
+        self.afterburner_held
+    }
+
+    fn is_flight_assisted(&self) -> bool {
+        self.flight_assist
+    }
+
+    fn take_purchase(&mut self) -> Option<ShopItem> {
+        self.purchase.take()
+    }
+
+    fn on_cloak_depleted(&mut self) {
+        if !self.visible {
+            self.set_visible(true);
+        }
+    }
+
+    fn on_docking(&mut self) {
+        self.autopilot = None;
+    }
+
+    fn is_camera_tracking(&self) -> bool {
+        self.camera_tracking
+    }
+
+    fn take_free_look(&mut self) -> Option<(f64, f64)> {
+        if self.free_look_held {
+            Some(std::mem::take(&mut self.orbit))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keyboard_and_gamepad_control_movement() {
+        let mut controls = PlayerControls::new();
+        assert!(controls.get_movement() == Movement::Stopped);
+        controls
+            .on_button(Button::Key(VirtualKeyCode::S), ElementState::Pressed);
+        assert!(controls.get_movement() == Movement::Backwards);
+        controls
+            .on_button(Button::Key(VirtualKeyCode::S), ElementState::Released);
+        assert!(controls.get_movement() == Movement::Stopped);
+
+        controls.gamepad_axes.insert(Axis::Thrust, 0.5);
+        assert!(controls.get_movement() == Movement::Forward);
+        controls.gamepad_axes.insert(Axis::Roll, 2.);
+        controls.mouse_axes.insert(Axis::Roll, 3.);
+        assert!((controls.get_roll() - 5.).abs() < f64::EPSILON);
+
+        controls.on_button(
+            Button::Gamepad(gilrs::Button::LeftTrigger2),
+            ElementState::Pressed,
+        );
+        assert_eq!(controls.get_action_state(), PlayerActionState::FireRope);
+        controls.on_button(
+            Button::Gamepad(gilrs::Button::LeftTrigger2),
+            ElementState::Released,
+        );
+        assert_eq!(controls.get_action_state(), PlayerActionState::CutRope);
+
+        assert_eq!(controls.get_reel(), Reel::Hold);
+        controls
+            .on_button(Button::Key(VirtualKeyCode::E), ElementState::Pressed);
+        assert_eq!(controls.get_reel(), Reel::In);
+        controls
+            .on_button(Button::Key(VirtualKeyCode::E), ElementState::Released);
+        assert_eq!(controls.get_reel(), Reel::Hold);
+    }
+
+    #[test]
+    fn target_cycling_is_taken_once_per_press() {
+        let mut controls = PlayerControls::new();
+        assert!(!controls.take_cycle_target());
+        controls
+            .on_button(Button::Key(VirtualKeyCode::Tab), ElementState::Pressed);
+        controls.on_button(
+            Button::Key(VirtualKeyCode::Tab),
+            ElementState::Released,
+        );
+        assert!(controls.take_cycle_target());
+        assert!(!controls.take_cycle_target());
+
+        assert!(controls.is_camera_tracking());
+        controls.on_button(
+            Button::Gamepad(gilrs::Button::West),
+            ElementState::Pressed,
+        );
+        assert!(!controls.is_camera_tracking());
+
+        assert!(!controls.take_cycle_power());
+        controls
+            .on_button(Button::Key(VirtualKeyCode::R), ElementState::Pressed);
+        assert!(controls.take_cycle_power());
+        assert!(!controls.take_cycle_power());
+    }
+
+    #[test]
+    fn afterburner_is_held_and_flight_assist_toggles() {
+        let mut controls = PlayerControls::new();
+        assert!(!controls.is_afterburning());
+        controls.on_button(
+            Button::Key(VirtualKeyCode::LShift),
+            ElementState::Pressed,
+        );
+        assert!(controls.is_afterburning());
+        controls.on_button(
+            Button::Key(VirtualKeyCode::LShift),
+            ElementState::Released,
+        );
+        assert!(!controls.is_afterburning());
+
+        assert!(controls.is_flight_assisted());
+        for assisted in [false, true] {
+            controls.on_button(
+                Button::Key(VirtualKeyCode::V),
+                ElementState::Pressed,
+            );
+            controls.on_button(
+                Button::Key(VirtualKeyCode::V),
+                ElementState::Released,
+            );
+            assert_eq!(controls.is_flight_assisted(), assisted);
+        }
+    }
+
+    #[test]
+    fn map_controls_zoom_and_switch_to_radar() {
+        let mut controls = PlayerControls::new();
+        assert_eq!(controls.take_minimap_zoom(), 0);
+        for _ in 0..2 {
+            controls.on_button(
+                Button::Key(VirtualKeyCode::Equals),
+                ElementState::Pressed,
+            );
+        }
+        controls.on_button(
+            Button::Key(VirtualKeyCode::Minus),
+            ElementState::Pressed,
+        );
+        assert_eq!(controls.take_minimap_zoom(), 1);
+        assert_eq!(controls.take_minimap_zoom(), 0);
+        assert!(!controls.is_radar_shown());
+        controls
+            .on_button(Button::Key(VirtualKeyCode::M), ElementState::Pressed);
+        assert!(controls.is_radar_shown());
+    }
+
+    #[test]
+    fn thrusting_cancels_the_autopilot() {
+        let mut controls = PlayerControls::new();
+        assert!(!controls.is_tactical_map_shown());
+        controls
+            .on_button(Button::Key(VirtualKeyCode::N), ElementState::Pressed);
+        assert!(controls.is_tactical_map_shown());
+        let waypoint = cgmath::point3(100., 0., 50.);
+        controls.set_waypoint(Some(waypoint));
+        assert_eq!(controls.waypoint(), Some(waypoint));
+        // the autopilot only turns the player once it's flying
+        assert!(controls.get_snapped_rot().is_none());
+        controls
+            .on_button(Button::Key(VirtualKeyCode::W), ElementState::Pressed);
+        assert_eq!(controls.waypoint(), None);
+        assert!(controls.get_movement() == Movement::Forward);
+    }
+
+    #[test]
+    fn steering_cancels_the_autopilot() {
+        use crate::cg_support::node;
+        use crate::controls::PlayerIteratorHolder;
+        use std::{cell::RefCell, rc::Rc, time::Duration};
+        let mut controls = PlayerControls::new();
+        controls
+            .on_button(Button::Key(VirtualKeyCode::H), ElementState::Pressed);
+        assert_eq!(
+            controls.take_autopilot_request(),
+            Some(AutopilotRequest::Dock)
+        );
+        assert_eq!(controls.take_autopilot_request(), None);
+
+        let scene =
+            collisions::CollisionTree::new(cgmath::point3(0., 0., 0.), 200.);
+        let body = physics::BaseRigidBody::new(Rc::new(RefCell::new(
+            node::Node::default(),
+        )));
+        let others =
+            PlayerIteratorHolder(std::iter::empty::<Rc<RefCell<node::Node>>>());
+        let waypoint = cgmath::point3(100., 0., 50.);
+        controls.set_waypoint(Some(waypoint));
+        let dt = Duration::from_millis(16);
+        assert!(controls
+            .on_frame_update(&scene, &body, dt, &others)
+            .is_some());
+        assert!(controls.get_snapped_rot().is_some());
+        controls.on_analog(AnalogInput::MouseX, 2.);
+        assert!(controls
+            .on_frame_update(&scene, &body, dt, &others)
+            .is_none());
+        assert_eq!(controls.waypoint(), None);
+        assert!(controls.get_snapped_rot().is_none());
+    }
+
+    #[test]
+    fn free_look_takes_mouse_movement() {
+        let mut controls = PlayerControls::new();
+        controls.on_analog(AnalogInput::MouseX, 2.);
+        assert!(controls.take_free_look().is_none());
+        assert!((controls.get_roll() - 2.).abs() < f64::EPSILON);
+
+        controls.on_button(Button::Mouse(2), ElementState::Pressed);
+        controls.mouse_axes.clear();
+        controls.on_analog(AnalogInput::MouseX, 3.);
+        controls.on_analog(AnalogInput::MouseY, -1.);
+        controls.on_analog(AnalogInput::MouseX, 1.);
+        // the player doesn't turn while looking around
+        assert!(controls.get_roll().abs() < f64::EPSILON);
+        assert_eq!(controls.take_free_look(), Some((4., -1.)));
+        assert_eq!(controls.take_free_look(), Some((0., 0.)));
+
+        controls.on_button(Button::Mouse(2), ElementState::Released);
+        assert!(controls.take_free_look().is_none());
+    }
+
+    #[test]
+    fn recorded_inputs_play_back_at_the_same_frames() {
+        use std::time::Duration;
+        let path = std::env::temp_dir().join("oort_test_recording.txt");
+        let path = path.to_str().unwrap();
+        let mut recorded = PlayerControls::new();
+        recorded.start_recording(path).unwrap();
+        recorded
+            .on_button(Button::Key(VirtualKeyCode::W), ElementState::Pressed);
+        recorded.on_analog(AnalogInput::MouseY, 4.);
+        recorded.begin_frame(Duration::from_millis(10));
+        recorded
+            .on_button(Button::Key(VirtualKeyCode::W), ElementState::Released);
+        recorded.begin_frame(Duration::from_millis(20));
+
+        let mut replayed = PlayerControls::new();
+        replayed.start_playback(path).unwrap();
+        // device inputs are ignored during playback
+        replayed
+            .on_button(Button::Key(VirtualKeyCode::S), ElementState::Pressed);
+        assert_eq!(
+            replayed.begin_frame(Duration::from_secs(1)),
+            Duration::from_millis(10)
+        );
+        assert!(replayed.get_movement() == Movement::Forward);
+        assert!((replayed.get_pitch() - 4.).abs() < f64::EPSILON);
+        assert_eq!(
+            replayed.begin_frame(Duration::from_secs(1)),
+            Duration::from_millis(20)
+        );
+        assert!(replayed.get_movement() == Movement::Stopped);
+        assert_eq!(
+            replayed.begin_frame(Duration::from_secs(1)),
+            Duration::from_secs(1)
+        );
+        std::fs::remove_file(path).unwrap();
+    }
 }
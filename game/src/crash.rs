@@ -0,0 +1,155 @@
+use crate::logging;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Mutex, PoisonError};
+use std::time::SystemTime;
+use tracing::error;
+
+/// Directory crash reports are written to
+pub const CRASH_DIR: &str = "crashes";
+/// Title of the message box shown when the game crashes
+const MESSAGE_TITLE: &str = "Space Fight";
+
+lazy_static! {
+    /// Titled descriptions of the state of the game which are included in
+    /// crash reports, such as the graphics settings
+    static ref CONTEXT: Mutex<Vec<(&'static str, String)>> =
+        Mutex::new(Vec::new());
+}
+
+/// Includes `contents` in crash reports under `title`, replacing whatever
+/// was there before
+pub fn set_context(title: &'static str, contents: String) {
+    let mut context = CONTEXT.lock().unwrap_or_else(PoisonError::into_inner);
+    match context.iter_mut().find(|(t, _)| *t == title) {
+        Some((_, old)) => *old = contents,
+        None => context.push((title, contents)),
+    }
+}
+
+/// Writes a crash report to `CRASH_DIR` when any thread panics. The report
+/// has the backtrace, the recent log, and the context set with
+/// `set_context`. Panics of the main thread, which end the game, also show
+/// a message box where possible
+///
+/// Panics are still printed as they were before the hook was installed
+pub fn install_hook() {
+    let print_panic = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        print_panic(info);
+        let report = {
+            let context =
+                CONTEXT.lock().unwrap_or_else(PoisonError::into_inner);
+            let log = logging::recent_lines().join("\n");
+            let mut sections: Vec<_> = context
+                .iter()
+                .map(|(title, contents)| (*title, contents.as_str()))
+                .collect();
+            sections.push(("Recent log", &log));
+            shared_types::crash::report(info, &sections)
+        };
+        match write_report(Path::new(CRASH_DIR), &report, SystemTime::now()) {
+            Ok(path) => {
+                error!("Wrote crash report to {}", path.display());
+                if std::thread::current().name() == Some("main") {
+                    show_message(&format!(
+                        "The game crashed: {}\n\nA report was saved to {}",
+                        shared_types::crash::panic_message(info.payload()),
+                        path.display()
+                    ));
+                }
+            }
+            Err(e) => error!("Failed to write crash report: {}", e),
+        }
+    }));
+}
+
+/// Writes `report` to a file in `dir` named after `time`, such as
+/// `crash-<ms>.txt` where `<ms>` is the milliseconds since the Unix epoch
+///
+/// Returns the path of the file
+fn write_report(
+    dir: &Path,
+    report: &str,
+    time: SystemTime,
+) -> std::io::Result<PathBuf> {
+    let millis = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(format!("crash-{}.txt", millis));
+    std::fs::write(&path, report)?;
+    Ok(path)
+}
+
+/// Gets the command which shows a message box of the text in the
+/// environment variable `OORT_CRASH`. Other platforms pass `text` as an
+/// argument
+fn message_command(text: &str) -> Command {
+    if cfg!(target_os = "windows") {
+        let mut cmd = Command::new("powershell");
+        cmd.arg("-NoProfile").arg("-Command").arg(format!(
+            "Add-Type -AssemblyName PresentationFramework; \
+            [System.Windows.MessageBox]::Show($env:OORT_CRASH, '{}')",
+            MESSAGE_TITLE
+        ));
+        cmd
+    } else if cfg!(target_os = "macos") {
+        let mut cmd = Command::new("osascript");
+        cmd.arg("-e").arg(format!(
+            "display alert \"{}\" message (system attribute \"OORT_CRASH\")",
+            MESSAGE_TITLE
+        ));
+        cmd
+    } else {
+        let mut cmd = Command::new("zenity");
+        cmd.arg("--error")
+            .arg("--no-markup")
+            .arg("--title")
+            .arg(MESSAGE_TITLE)
+            .arg("--text")
+            .arg(text);
+        cmd
+    }
+}
+
+/// Shows `text` in a message box until it's closed. Nothing is shown if
+/// the message box cannot be opened
+fn show_message(text: &str) {
+    // the text is passed in the environment so it doesn't need quoting
+    let _ = message_command(text).env("OORT_CRASH", text).status();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn reports_are_written() {
+        let dir = std::env::temp_dir().join("oort-crash-test");
+        let time = SystemTime::UNIX_EPOCH + Duration::from_millis(1234);
+        let path = write_report(&dir, "Thread 'main' panicked", time).unwrap();
+        assert_eq!(path, dir.join("crash-1234.txt"));
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "Thread 'main' panicked"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn context_is_replaced() {
+        set_context("GPU", "Software renderer".to_owned());
+        set_context("GPU", "Test GPU".to_owned());
+        let context = CONTEXT.lock().unwrap();
+        assert_eq!(
+            context
+                .iter()
+                .filter(|(title, _)| *title == "GPU")
+                .collect::<Vec<_>>(),
+            vec![&("GPU", "Test GPU".to_owned())]
+        );
+    }
+}
@@ -0,0 +1,337 @@
+use crate::object::{ObjectData, ObjectId, ObjectType};
+use crate::physics::RigidBody;
+use cgmath::*;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Hull points of an asteroid per unit of its radius
+const ASTEROID_TOUGHNESS: f64 = 4.;
+
+/// How much damage an object can take before it's destroyed, and how it
+/// recovers from damage
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Armor {
+    pub max_shield: f64,
+    pub max_hull: f64,
+    /// Time the object must go without taking damage before its shield
+    /// starts recharging
+    pub recharge_delay: Duration,
+    /// Shield points recharged per second
+    pub recharge_rate: f64,
+}
+
+impl Armor {
+    /// Gets the armor of an object of type `typ` whose bounding sphere has
+    /// the radius `radius`, or `None` if the object can't be damaged
+    pub fn of_obj_type(typ: ObjectType, radius: f64) -> Option<Self> {
+        match typ {
+            ObjectType::Ship => Some(Self {
+                max_shield: 100.,
+                max_hull: 100.,
+                recharge_delay: Duration::from_secs(3),
+                recharge_rate: 15.,
+            }),
+            ObjectType::Asteroid => Some(Self {
+                max_shield: 0.,
+                max_hull: radius * ASTEROID_TOUGHNESS,
+                recharge_delay: Duration::default(),
+                recharge_rate: 0.,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Gets the factor that the damage an object of type `source` deals to an
+/// object of type `target` is multiplied by
+pub const fn damage_multiplier(target: ObjectType, source: ObjectType) -> f64 {
+    use ObjectType::*;
+    match (target, source) {
+        (Ship, Asteroid | Station) => 1.5,
+        (Ship, Planet) => 3.,
+        (Ship | Asteroid, Laser) | (Ship, Ship) => 1.,
+        // asteroids are only worn down by lasers
+        _ => 0.,
+    }
+}
+
+/// The shield and hull of an object that can be damaged
+///
+/// Damage is taken by the shield until it's depleted, and then by the
+/// hull. The object is destroyed once its hull is depleted
+#[derive(Clone, Debug)]
+pub struct Health {
+    armor: Armor,
+    shield: f64,
+    hull: f64,
+    /// Time since the object last took damage
+    since_damaged: Duration,
+}
+
+impl Health {
+    /// Creates the health of an undamaged object with the armor `armor`
+    pub const fn new(armor: Armor) -> Self {
+        Self {
+            armor,
+            shield: armor.max_shield,
+            hull: armor.max_hull,
+            since_damaged: armor.recharge_delay,
+        }
+    }
+
+    pub const fn shield(&self) -> f64 {
+        self.shield
+    }
+
+    pub const fn hull(&self) -> f64 {
+        self.hull
+    }
+
+    pub fn is_destroyed(&self) -> bool {
+        self.hull <= f64::EPSILON
+    }
+
+    /// Deals `damage` to the shield and then the hull, and restarts the
+    /// shield's recharge delay
+    ///
+    /// Returns `true` if the damage destroyed the object
+    pub fn apply_damage(&mut self, damage: f64) -> bool {
+        if damage <= 0. || self.is_destroyed() {
+            return false;
+        }
+        self.since_damaged = Duration::default();
+        let absorbed = damage.min(self.shield);
+        self.shield -= absorbed;
+        self.hull = (self.hull - (damage - absorbed)).max(0.);
+        self.is_destroyed()
+    }
+
+    /// Restores up to `amount` of the shield
+    pub fn restore_shield(&mut self, amount: f64) {
+        self.shield = (self.shield + amount).min(self.armor.max_shield);
+    }
+
+    /// Sets the shield and hull, without exceeding the armor's maximums
+    pub fn set(&mut self, shield: f64, hull: f64) {
+        self.shield = shield.clamp(0., self.armor.max_shield);
+        self.hull = hull.clamp(0., self.armor.max_hull);
+    }
+
+    /// Restores the shield and hull to full
+    pub fn reset(&mut self) {
+        *self = Self::new(self.armor);
+    }
+
+    /// Recharges the shield if the object hasn't taken damage for at least
+    /// the recharge delay
+    pub fn update(&mut self, dt: Duration) {
+        self.update_powered(dt, 1., f64::INFINITY);
+    }
+
+    /// Recharges the shield like `update`, with the recharge rate multiplied
+    /// by `power` and at most `limit` shield points recharged
+    ///
+    /// Returns the shield points recharged
+    pub fn update_powered(
+        &mut self,
+        dt: Duration,
+        power: f64,
+        limit: f64,
+    ) -> f64 {
+        self.since_damaged += dt;
+        if self.since_damaged < self.armor.recharge_delay || self.is_destroyed()
+        {
+            return 0.;
+        }
+        let before = self.shield;
+        self.restore_shield(
+            (self.armor.recharge_rate * power * dt.as_secs_f64()).min(limit),
+        );
+        self.shield - before
+    }
+}
+
+/// Damage dealt to an object
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Damage {
+    /// Type and id of the damaged object
+    pub target: ObjectData,
+    /// Type and id of the object that dealt the damage
+    pub source: ObjectData,
+    /// Damage before the target's damage multiplier is applied
+    pub amount: f64,
+    /// Where the target was hit
+    pub pos: Point3<f64>,
+    /// Momentum the source transferred to the target
+    pub impulse: Vector3<f64>,
+}
+
+impl Damage {
+    /// Gets the damage the target takes
+    pub fn multiplied(&self) -> f64 {
+        self.amount * damage_multiplier(self.target.0, self.source.0)
+    }
+}
+
+/// Notice that an object was destroyed
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct DestructionEvent {
+    pub id: ObjectId,
+    pub typ: ObjectType,
+    /// Type and id of the object that dealt the final blow
+    pub source: ObjectData,
+    /// Where the object was dealt the final blow
+    pub pos: Point3<f64>,
+    /// Momentum transferred to the object by the final blow
+    pub impulse: Vector3<f64>,
+}
+
+impl DestructionEvent {
+    /// Creates the event for the target of `damage` being destroyed by it
+    pub const fn from_damage(damage: &Damage) -> Self {
+        Self {
+            id: damage.target.1,
+            typ: damage.target.0,
+            source: damage.source,
+            pos: damage.pos,
+            impulse: damage.impulse,
+        }
+    }
+}
+
+/// Collects the damage dealt to objects during a frame, and tracks the
+/// health of every damaged object that isn't a player
+///
+/// Damage is dealt during collision callbacks, when the damaged objects may
+/// be borrowed, so it's queued and applied once the simulation step is over
+#[derive(Default)]
+pub struct DamageModel {
+    health: HashMap<ObjectId, Health>,
+    /// Damage yet to be applied, and the radius of each target
+    pending: Vec<(Damage, f64)>,
+}
+
+impl DamageModel {
+    /// Queues `amount` damage from `source` hitting `target` at `pos` to be
+    /// dealt to `target`. Does nothing if the target takes no damage from
+    /// the source
+    pub fn deal(
+        &mut self,
+        target: &RigidBody<ObjectData>,
+        source: &RigidBody<ObjectData>,
+        amount: f64,
+        pos: Point3<f64>,
+    ) {
+        let damage = Damage {
+            target: target.metadata,
+            source: source.metadata,
+            amount,
+            pos,
+            impulse: source.base.mass
+                * (source.base.velocity - target.base.velocity),
+        };
+        if damage.multiplied() > 0. {
+            let radius = target
+                .base
+                .collider
+                .as_ref()
+                .map_or(1., |c| c.bounding_sphere().1);
+            self.pending.push((damage, radius));
+        }
+    }
+
+    /// Takes the damage dealt since the last call, in the order it was dealt
+    pub fn take_pending(&mut self) -> Vec<(Damage, f64)> {
+        std::mem::take(&mut self.pending)
+    }
+
+    /// Applies `damage` to its target, whose bounding sphere has the radius
+    /// `radius`. The target's health is created the first time it's damaged
+    ///
+    /// Returns the target's destruction, if the damage destroyed it. A
+    /// destroyed object's health is no longer tracked
+    pub fn apply(
+        &mut self,
+        damage: &Damage,
+        radius: f64,
+    ) -> Option<DestructionEvent> {
+        let (typ, id) = damage.target;
+        let armor = Armor::of_obj_type(typ, radius)?;
+        let health =
+            self.health.entry(id).or_insert_with(|| Health::new(armor));
+        if health.apply_damage(damage.multiplied()) {
+            self.health.remove(&id);
+            Some(DestructionEvent::from_damage(damage))
+        } else {
+            None
+        }
+    }
+
+    /// Recharges the shields of every damaged object
+    pub fn update(&mut self, dt: Duration) {
+        for health in self.health.values_mut() {
+            health.update(dt);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn shield_absorbs_damage_and_recharges() {
+        let armor = Armor::of_obj_type(ObjectType::Ship, 1.).unwrap();
+        let mut health = Health::new(armor);
+        assert!(!health.apply_damage(120.));
+        assert_approx_eq!(health.shield(), 0.);
+        assert_approx_eq!(health.hull(), 80.);
+        // the shield waits for the recharge delay
+        health.update(Duration::from_secs(2));
+        assert_approx_eq!(health.shield(), 0.);
+        health.update(Duration::from_secs(2));
+        assert_approx_eq!(health.shield(), armor.recharge_rate * 2.);
+        health.update(Duration::from_secs(60));
+        assert_approx_eq!(health.shield(), armor.max_shield);
+        // the hull doesn't recharge
+        assert_approx_eq!(health.hull(), 80.);
+        assert!(health.apply_damage(180.));
+        assert!(health.is_destroyed());
+        assert!(!health.apply_damage(10.));
+    }
+
+    #[test]
+    fn damage_depends_on_object_types() {
+        let mut model = DamageModel::default();
+        let asteroid = (ObjectType::Asteroid, ObjectId::new(4));
+        let hit = |target, source| Damage {
+            target,
+            source: (source, ObjectId::new(9)),
+            amount: 10.,
+            pos: point3(0., 0., 0.),
+            impulse: vec3(0., 0., 0.),
+        };
+        // asteroids aren't damaged by collisions with ships
+        assert_approx_eq!(hit(asteroid, ObjectType::Ship).multiplied(), 0.);
+        assert!(
+            hit((ObjectType::Ship, ObjectId::new(1)), ObjectType::Asteroid)
+                .multiplied()
+                > 10.
+        );
+        // an asteroid with a radius of 5 has 20 hull points
+        let laser = hit(asteroid, ObjectType::Laser);
+        assert_eq!(model.apply(&laser, 5.), None);
+        let destroyed = model.apply(&laser, 5.).unwrap();
+        assert_eq!(destroyed.id, ObjectId::new(4));
+        assert_eq!(destroyed.source.0, ObjectType::Laser);
+        assert!(model.health.is_empty());
+        assert_eq!(
+            model.apply(
+                &hit((ObjectType::Planet, asteroid.1), ObjectType::Laser),
+                5.
+            ),
+            None
+        );
+    }
+}
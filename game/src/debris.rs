@@ -0,0 +1,243 @@
+use crate::cg_support::node::Node;
+use crate::game_mediator::GameMediator;
+use crate::object::{ObjectId, ObjectType};
+use cgmath::*;
+use rand::Rng;
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+/// Maximum number of chunks of debris in the world at once. The oldest
+/// chunks are removed to make room for new ones
+const MAX_DEBRIS: usize = 80;
+/// How long a chunk of debris lasts before it's removed
+const DEBRIS_LIFETIME: Duration = Duration::from_secs(30);
+/// How long a chunk of debris takes to shrink away at the end of its life
+const FADE_TIME: Duration = Duration::from_secs(3);
+/// Minimum and maximum number of chunks a destroyed ship breaks into
+const CHUNK_COUNT: (usize, usize) = (6, 10);
+/// Minimum and maximum speeds chunks fly away from the wreck at
+const SCATTER_SPEED: (f64, f64) = (4., 18.);
+/// Largest rotational velocity of a chunk about each axis
+const MAX_TUMBLE: f64 = 0.0006;
+/// Mass of a wreck used to turn the impulse of the final blow into the
+/// velocity the chunks inherit
+const WRECK_MASS: f64 = 400.;
+/// Change in speed of a chunk of debris hit by a laser
+const LASER_KICK: f64 = 6.;
+/// Shapes of the pieces of a wreck, as scales of the debris cube: hull
+/// plates, struts, and blocks of machinery
+const SHAPES: [Vector3<f64>; 3] = [
+    Vector3::new(1.2, 0.15, 0.8),
+    Vector3::new(0.2, 0.2, 1.5),
+    Vector3::new(0.6, 0.5, 0.6),
+];
+
+/// A piece of a wreck
+#[derive(Clone, Debug)]
+struct Chunk {
+    /// Scale of the chunk before it starts shrinking away
+    scale: Vector3<f64>,
+    /// Position of the chunk relative to the wreck
+    offset: Vector3<f64>,
+    velocity: Vector3<f64>,
+    rot_vel: Vector3<f64>,
+}
+
+/// Gets a vector whose components are each between `-max` and `max`
+fn random_vec<R: Rng>(max: f64, rng: &mut R) -> Vector3<f64> {
+    vec3(
+        rng.gen_range(-max..max),
+        rng.gen_range(-max..max),
+        rng.gen_range(-max..max),
+    )
+}
+
+/// Breaks a wreck into `count` chunks of random shapes, which tumble away
+/// from it in random directions
+///
+/// `velocity` - the velocity the chunks share
+fn break_up<R: Rng>(
+    velocity: Vector3<f64>,
+    count: usize,
+    rng: &mut R,
+) -> Vec<Chunk> {
+    (0..count)
+        .map(|i| {
+            let dir = random_vec(1., rng);
+            let dir = if dir.magnitude2() > f64::EPSILON {
+                dir.normalize()
+            } else {
+                vec3(0., 1., 0.)
+            };
+            let speed = rng.gen_range(SCATTER_SPEED.0..=SCATTER_SPEED.1);
+            Chunk {
+                scale: SHAPES[i % SHAPES.len()] * rng.gen_range(0.6..1.2),
+                offset: dir * 2.,
+                velocity: velocity + dir * speed,
+                rot_vel: random_vec(MAX_TUMBLE, rng),
+            }
+        })
+        .collect()
+}
+
+/// Gets the scale factor of a chunk of debris that's `age` old, which
+/// shrinks from `1` to `0` over the end of its lifetime
+fn fade(age: Duration) -> f64 {
+    let left = DEBRIS_LIFETIME.saturating_sub(age);
+    (left.as_secs_f64() / FADE_TIME.as_secs_f64()).min(1.)
+}
+
+/// The debris left behind by destroyed ships
+///
+/// Every chunk of debris is an instance of the debris object, so they share
+/// its collision prototype and are stretched into one of a few shapes.
+/// Chunks are light, so lasers and ships knock them around. They shrink away
+/// and are removed once they're old or there are too many of them
+#[derive(Default)]
+pub struct DebrisField {
+    /// Ids of the chunks in the world, from oldest to newest, and their ages
+    chunks: VecDeque<(ObjectId, Duration)>,
+    /// Scale of each chunk before it starts shrinking away
+    scales: HashMap<ObjectId, Vector3<f64>>,
+    /// Changes in velocity to apply to chunks on the next update
+    kicks: Vec<(ObjectId, Vector3<f64>)>,
+}
+
+impl DebrisField {
+    /// Breaks a ship destroyed at `pos` into chunks of debris
+    ///
+    /// `impulse` - the momentum of the final blow, which pushes the chunks
+    pub fn scatter<M: GameMediator, R: Rng>(
+        &mut self,
+        pos: Point3<f64>,
+        impulse: Vector3<f64>,
+        mediator: &mut M,
+        rng: &mut R,
+    ) {
+        let count = rng.gen_range(CHUNK_COUNT.0..=CHUNK_COUNT.1);
+        for chunk in break_up(impulse / WRECK_MASS, count, rng) {
+            let transform =
+                Node::default().pos(pos + chunk.offset).scale(chunk.scale);
+            if let Some(id) =
+                mediator.add_debris(transform, chunk.velocity, chunk.rot_vel)
+            {
+                self.chunks.push_back((id, Duration::default()));
+                self.scales.insert(id, chunk.scale);
+            }
+        }
+    }
+
+    /// Knocks the chunk of debris with the id `id` away from a laser moving
+    /// with `velocity` relative to it
+    pub fn kick(&mut self, id: ObjectId, velocity: Vector3<f64>) {
+        if velocity.magnitude2() > f64::EPSILON {
+            self.kicks.push((id, velocity.normalize() * LASER_KICK));
+        }
+    }
+
+    /// Applies the kicks since the last update, shrinks chunks at the end of
+    /// their lives, and removes the chunks that are too old or too many
+    pub fn update<M: GameMediator>(&mut self, dt: Duration, mediator: &mut M) {
+        let removed = self.tick(dt);
+        for id in &removed {
+            self.scales.remove(id);
+        }
+        mediator.remove_objects(ObjectType::Debris, &removed);
+        let kicks: HashMap<_, _> = self.kicks.drain(..).collect();
+        let ages: HashMap<_, _> = self.chunks.iter().copied().collect();
+        let scales = &self.scales;
+        mediator.update_bodies(|bodies| {
+            for body in bodies.filter(|b| b.metadata.0 == ObjectType::Debris) {
+                let id = body.metadata.1;
+                if let Some(kick) = kicks.get(&id) {
+                    body.base.velocity += *kick;
+                }
+                if let (Some(age), Some(scale)) =
+                    (ages.get(&id), scales.get(&id))
+                {
+                    body.base
+                        .transform
+                        .borrow_mut()
+                        .set_scale(*scale * fade(*age).max(0.05));
+                }
+            }
+        });
+    }
+
+    /// Ages the chunks by `dt`
+    ///
+    /// Returns the ids of the chunks that have outlived their lifetime, and
+    /// of the oldest chunks past the limit on the number of chunks
+    fn tick(&mut self, dt: Duration) -> Vec<ObjectId> {
+        for (_, age) in &mut self.chunks {
+            *age += dt;
+        }
+        let mut removed = Vec::new();
+        while let Some((id, age)) = self.chunks.front().copied() {
+            if age < DEBRIS_LIFETIME && self.chunks.len() <= MAX_DEBRIS {
+                break;
+            }
+            removed.push(id);
+            self.chunks.pop_front();
+        }
+        removed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn chunks_tumble_away_from_wreck() {
+        let velocity = vec3(0., 0., 5.);
+        let chunks = break_up(velocity, 8, &mut rand::thread_rng());
+        assert_eq!(chunks.len(), 8);
+        for chunk in &chunks {
+            let speed = (chunk.velocity - velocity).magnitude();
+            assert!(speed >= SCATTER_SPEED.0 - 0.0001);
+            assert!(speed <= SCATTER_SPEED.1 + 0.0001);
+            assert!(chunk.offset.dot(chunk.velocity - velocity) > 0.);
+            assert!(chunk.rot_vel.x.abs() <= MAX_TUMBLE);
+        }
+        // the chunks come in every shape
+        assert!(SHAPES.iter().all(|shape| chunks.iter().any(|c| {
+            (c.scale.x / c.scale.y - shape.x / shape.y).abs() < 0.0001
+        })));
+    }
+
+    #[test]
+    fn debris_is_removed_when_old_or_too_many() {
+        let mut field = DebrisField::default();
+        for id in 0..4 {
+            field
+                .chunks
+                .push_back((ObjectId::new(id), Duration::default()));
+        }
+        assert!(field.tick(DEBRIS_LIFETIME / 2).is_empty());
+        field
+            .chunks
+            .push_back((ObjectId::new(4), Duration::default()));
+        assert_eq!(
+            field.tick(DEBRIS_LIFETIME / 2),
+            (0..4).map(ObjectId::new).collect::<Vec<_>>()
+        );
+        for id in 5..MAX_DEBRIS as u32 + 5 {
+            field
+                .chunks
+                .push_back((ObjectId::new(id), Duration::default()));
+        }
+        // the oldest chunk makes room for the newest
+        assert_eq!(field.tick(Duration::default()), vec![ObjectId::new(4)]);
+        assert_eq!(field.chunks.len(), MAX_DEBRIS);
+    }
+
+    #[test]
+    fn debris_shrinks_away_at_end_of_life() {
+        assert_approx_eq!(fade(Duration::default()), 1.);
+        assert_approx_eq!(fade(DEBRIS_LIFETIME - FADE_TIME), 1.);
+        assert_approx_eq!(fade(DEBRIS_LIFETIME - FADE_TIME / 2), 0.5);
+        assert_approx_eq!(fade(DEBRIS_LIFETIME), 0.);
+    }
+}
@@ -0,0 +1,197 @@
+use std::fmt::Display;
+use std::time::Duration;
+
+/// Energy used per second of thrust
+pub const THRUST_COST: f64 = 10.;
+/// Energy used per laser or hook fired
+pub const SHOT_COST: f64 = 1.;
+/// Energy used per shield point recharged
+pub const SHIELD_COST: f64 = 0.25;
+/// Energy used per second while cloaked
+pub const CLOAK_COST: f64 = 4.;
+/// Energy used per second while the tractor beam holds a target
+pub const BEAM_COST: f64 = 6.;
+/// Energy used per second while the afterburner is on, on top of the cost
+/// of thrust
+pub const AFTERBURNER_COST: f64 = 20.;
+/// Performance of the systems which are given priority
+const PRIORITY_FAC: f64 = 1.5;
+/// Performance of the systems which aren't given priority when another
+/// system is
+const DEPRIORITIZED_FAC: f64 = 0.75;
+
+/// A system of a ship whose performance depends on how power is distributed
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Subsystem {
+    /// How quickly the ship can accelerate
+    Engines,
+    /// How quickly the ship can fire
+    Weapons,
+    /// How quickly the ship's shield recharges
+    Shields,
+}
+
+/// How a ship's power is divided between its systems
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PowerDistribution {
+    Balanced,
+    /// Favors a system at the expense of the others
+    Priority(Subsystem),
+}
+
+impl PowerDistribution {
+    /// Gets the next distribution, cycling from balanced through each
+    /// system's priority
+    pub const fn next(self) -> Self {
+        match self {
+            Self::Balanced => Self::Priority(Subsystem::Weapons),
+            Self::Priority(Subsystem::Weapons) => {
+                Self::Priority(Subsystem::Engines)
+            }
+            Self::Priority(Subsystem::Engines) => {
+                Self::Priority(Subsystem::Shields)
+            }
+            Self::Priority(Subsystem::Shields) => Self::Balanced,
+        }
+    }
+
+    /// Gets the factor the performance of `system` is multiplied by
+    pub fn factor(self, system: Subsystem) -> f64 {
+        match self {
+            Self::Balanced => 1.,
+            Self::Priority(priority) if priority == system => PRIORITY_FAC,
+            Self::Priority(_) => DEPRIORITIZED_FAC,
+        }
+    }
+}
+
+impl Display for PowerDistribution {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Balanced => write!(f, "BAL"),
+            Self::Priority(Subsystem::Weapons) => write!(f, "WPN"),
+            Self::Priority(Subsystem::Engines) => write!(f, "ENG"),
+            Self::Priority(Subsystem::Shields) => write!(f, "SHD"),
+        }
+    }
+}
+
+/// A ship's capacitor, which is charged by its generator and drained by its
+/// systems
+#[derive(Clone, Debug)]
+pub struct EnergySystem {
+    charge: f64,
+    capacity: f64,
+    /// Energy generated per second
+    generation_rate: f64,
+    distribution: PowerDistribution,
+}
+
+impl EnergySystem {
+    /// Creates a fully charged capacitor with a balanced power distribution
+    pub const fn new(capacity: f64, generation_rate: f64) -> Self {
+        Self {
+            charge: capacity,
+            capacity,
+            generation_rate,
+            distribution: PowerDistribution::Balanced,
+        }
+    }
+
+    pub const fn charge(&self) -> f64 {
+        self.charge
+    }
+
+    #[allow(dead_code)]
+    pub const fn capacity(&self) -> f64 {
+        self.capacity
+    }
+
+    pub const fn distribution(&self) -> PowerDistribution {
+        self.distribution
+    }
+
+    /// Switches to the next power distribution
+    pub fn cycle_distribution(&mut self) {
+        self.distribution = self.distribution.next();
+    }
+
+    /// Gets the factor the performance of `system` is multiplied by under
+    /// the current power distribution
+    pub fn factor(&self, system: Subsystem) -> f64 {
+        self.distribution.factor(system)
+    }
+
+    /// Takes `amount` energy if the capacitor holds at least that much
+    ///
+    /// Returns `true` if the energy was taken
+    pub fn draw(&mut self, amount: f64) -> bool {
+        if self.charge >= amount {
+            self.charge -= amount;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Takes up to `amount` energy
+    ///
+    /// Returns the energy taken
+    pub fn drain(&mut self, amount: f64) -> f64 {
+        let drained = amount.clamp(0., self.charge);
+        self.charge -= drained;
+        drained
+    }
+
+    /// Adds up to `amount` energy, without exceeding the capacity
+    pub fn restore(&mut self, amount: f64) {
+        self.charge = (self.charge + amount).clamp(0., self.capacity);
+    }
+
+    /// Fully charges the capacitor
+    pub fn refill(&mut self) {
+        self.charge = self.capacity;
+    }
+
+    /// Charges the capacitor by the energy generated over `dt`
+    pub fn update(&mut self, dt: Duration) {
+        self.restore(self.generation_rate * dt.as_secs_f64());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn capacitor_charges_and_drains() {
+        let mut energy = EnergySystem::new(10., 2.);
+        assert!(energy.draw(4.));
+        assert!(!energy.draw(7.));
+        assert_approx_eq!(energy.charge(), 6.);
+        assert_approx_eq!(energy.drain(8.), 6.);
+        assert_approx_eq!(energy.charge(), 0.);
+        energy.update(Duration::from_secs(2));
+        assert_approx_eq!(energy.charge(), 4.);
+        energy.update(Duration::from_secs(60));
+        assert_approx_eq!(energy.charge(), energy.capacity());
+    }
+
+    #[test]
+    fn distribution_favors_priority_system() {
+        let mut energy = EnergySystem::new(10., 2.);
+        assert_approx_eq!(energy.factor(Subsystem::Engines), 1.);
+        energy.cycle_distribution();
+        assert_eq!(
+            energy.distribution(),
+            PowerDistribution::Priority(Subsystem::Weapons)
+        );
+        assert!(energy.factor(Subsystem::Weapons) > 1.);
+        assert!(energy.factor(Subsystem::Shields) < 1.);
+        for _ in 0..3 {
+            energy.cycle_distribution();
+        }
+        assert_eq!(energy.distribution(), PowerDistribution::Balanced);
+    }
+}
@@ -0,0 +1,296 @@
+use crate::cg_support::node::Node;
+use crate::collisions::HitData;
+use crate::damage::{Damage, DestructionEvent};
+use crate::object::{ObjectData, ObjectType};
+use crate::physics::RigidBody;
+use cgmath::*;
+use shared_types::pickup::PickupKind;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// One of the bodies of a collision
+#[derive(Clone)]
+pub struct CollidingBody {
+    pub data: ObjectData,
+    pub transform: Rc<RefCell<Node>>,
+    pub velocity: Vector3<f64>,
+    /// Where the body was hit
+    pub pos: Point3<f64>,
+    /// The impact normal on the body's mesh
+    pub normal: Vector3<f64>,
+}
+
+impl CollidingBody {
+    fn new(
+        body: &RigidBody<ObjectData>,
+        (pos, normal): (Point3<f64>, Vector3<f64>),
+    ) -> Self {
+        Self {
+            data: body.metadata,
+            transform: body.base.transform.clone(),
+            velocity: body.base.velocity,
+            pos,
+            normal,
+        }
+    }
+}
+
+/// Notice that two bodies collided during the simulation step
+#[derive(Clone)]
+pub struct CollisionEvent {
+    pub a: CollidingBody,
+    pub b: CollidingBody,
+}
+
+impl CollisionEvent {
+    pub fn new(
+        a: &RigidBody<ObjectData>,
+        b: &RigidBody<ObjectData>,
+        hit: &HitData,
+    ) -> Self {
+        Self {
+            a: CollidingBody::new(a, hit.pos_norm_a),
+            b: CollidingBody::new(b, hit.pos_norm_b),
+        }
+    }
+
+    /// Gets the body of type `typ` and the body it collided with, if either
+    /// body is of type `typ`
+    pub fn involving(
+        &self,
+        typ: ObjectType,
+    ) -> Option<(&CollidingBody, &CollidingBody)> {
+        if self.a.data.0 == typ {
+            Some((&self.a, &self.b))
+        } else if self.b.data.0 == typ {
+            Some((&self.b, &self.a))
+        } else {
+            None
+        }
+    }
+
+    /// Gets the body of type `first` and the body of type `second`, if the
+    /// collision was between bodies of those types
+    pub fn between(
+        &self,
+        first: ObjectType,
+        second: ObjectType,
+    ) -> Option<(&CollidingBody, &CollidingBody)> {
+        self.involving(first)
+            .filter(|(_, other)| other.data.0 == second)
+    }
+}
+
+/// Notice that damage was applied to an object
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct DamageEvent {
+    pub damage: Damage,
+    /// `true` if the damage destroyed the object
+    pub fatal: bool,
+}
+
+/// Notice that a ship collected a pickup
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct PickupEvent {
+    pub id: shared_types::ObjectId,
+    pub kind: PickupKind,
+    /// Type and id of the ship that collected the pickup
+    pub collector: ObjectData,
+}
+
+/// A function called with the context events are dispatched with and every
+/// event of type `E`
+type Handler<C, E> = Box<dyn Fn(&C, &E)>;
+
+/// Events of a single type waiting to be dispatched, and the handlers
+/// subscribed to them
+pub struct Channel<C, E> {
+    pending: RefCell<Vec<E>>,
+    handlers: Vec<Handler<C, E>>,
+}
+
+impl<C, E> Default for Channel<C, E> {
+    fn default() -> Self {
+        Self {
+            pending: RefCell::default(),
+            handlers: Vec::new(),
+        }
+    }
+}
+
+impl<C, E> Channel<C, E> {
+    /// Passes the pending events to every handler, in the order they were
+    /// published and then the order the handlers were subscribed in
+    ///
+    /// Returns `true` if there were any pending events
+    fn dispatch(&self, ctx: &C) -> bool {
+        let events = self.pending.take();
+        for event in &events {
+            for handler in &self.handlers {
+                handler(ctx, event);
+            }
+        }
+        !events.is_empty()
+    }
+}
+
+/// An event which can be published to an `EventBus`
+pub trait Event: Sized + 'static {
+    fn channel<C>(bus: &EventBus<C>) -> &Channel<C, Self>;
+
+    fn channel_mut<C>(bus: &mut EventBus<C>) -> &mut Channel<C, Self>;
+}
+
+macro_rules! impl_event {
+    ($event:ty, $channel:ident) => {
+        impl Event for $event {
+            fn channel<C>(bus: &EventBus<C>) -> &Channel<C, Self> {
+                &bus.$channel
+            }
+
+            fn channel_mut<C>(bus: &mut EventBus<C>) -> &mut Channel<C, Self> {
+                &mut bus.$channel
+            }
+        }
+    };
+}
+
+impl_event!(CollisionEvent, collisions);
+impl_event!(DamageEvent, damage);
+impl_event!(DestructionEvent, destructions);
+impl_event!(PickupEvent, pickups);
+
+/// Queues the events that happen during a frame, so the systems that react
+/// to them can subscribe to the events instead of being called by whatever
+/// caused them
+///
+/// Events are held until `dispatch` is called, which passes them to their
+/// handlers along with the context `C`. Events published by handlers are
+/// dispatched during the same call
+pub struct EventBus<C> {
+    collisions: Channel<C, CollisionEvent>,
+    damage: Channel<C, DamageEvent>,
+    destructions: Channel<C, DestructionEvent>,
+    pickups: Channel<C, PickupEvent>,
+}
+
+impl<C> Default for EventBus<C> {
+    fn default() -> Self {
+        Self {
+            collisions: Channel::default(),
+            damage: Channel::default(),
+            destructions: Channel::default(),
+            pickups: Channel::default(),
+        }
+    }
+}
+
+impl<C> EventBus<C> {
+    /// Calls `handler` with every dispatched event of type `E`
+    pub fn subscribe<E: Event, F: Fn(&C, &E) + 'static>(
+        &mut self,
+        handler: F,
+    ) -> &mut Self {
+        E::channel_mut(self).handlers.push(Box::new(handler));
+        self
+    }
+
+    /// Queues `event` to be dispatched
+    pub fn publish<E: Event>(&self, event: E) {
+        E::channel(self).pending.borrow_mut().push(event);
+    }
+
+    /// Dispatches the queued events, one type at a time, until there are no
+    /// more events
+    pub fn dispatch(&self, ctx: &C) {
+        // every channel is dispatched each round, so no short circuiting
+        while self.collisions.dispatch(ctx)
+            | self.damage.dispatch(ctx)
+            | self.destructions.dispatch(ctx)
+            | self.pickups.dispatch(ctx)
+        {}
+    }
+
+    /// Drops the queued events without dispatching them
+    pub fn clear(&self) {
+        self.collisions.pending.borrow_mut().clear();
+        self.damage.pending.borrow_mut().clear();
+        self.destructions.pending.borrow_mut().clear();
+        self.pickups.pending.borrow_mut().clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use shared_types::ObjectId;
+
+    /// Context which records the events that were handled
+    #[derive(Default)]
+    struct Log {
+        bus: EventBus<Log>,
+        handled: RefCell<Vec<(&'static str, ObjectId)>>,
+    }
+
+    fn damage(id: u32, fatal: bool) -> DamageEvent {
+        DamageEvent {
+            damage: Damage {
+                target: (ObjectType::Asteroid, ObjectId::new(id)),
+                source: (ObjectType::Laser, ObjectId::new(0)),
+                amount: 10.,
+                pos: point3(0., 0., 0.),
+                impulse: vec3(0., 0., 0.),
+            },
+            fatal,
+        }
+    }
+
+    #[test]
+    fn dispatches_events_published_by_handlers() {
+        let mut log = Log::default();
+        log.bus
+            .subscribe(|log: &Log, event: &DamageEvent| {
+                log.handled
+                    .borrow_mut()
+                    .push(("damage", event.damage.target.1));
+                if event.fatal {
+                    log.bus
+                        .publish(DestructionEvent::from_damage(&event.damage));
+                }
+            })
+            .subscribe(|log: &Log, event: &DestructionEvent| {
+                log.handled.borrow_mut().push(("first", event.id));
+            })
+            .subscribe(|log: &Log, event: &DestructionEvent| {
+                log.handled.borrow_mut().push(("second", event.id));
+            });
+        log.bus.publish(damage(1, true));
+        log.bus.publish(damage(2, false));
+        log.bus.publish(damage(3, true));
+        log.bus.dispatch(&log);
+        let ids = |ids: &[(&'static str, u32)]| -> Vec<_> {
+            ids.iter()
+                .map(|(name, id)| (*name, ObjectId::new(*id)))
+                .collect()
+        };
+        assert_eq!(
+            log.handled.take(),
+            ids(&[
+                ("damage", 1),
+                ("damage", 2),
+                ("damage", 3),
+                ("first", 1),
+                ("second", 1),
+                ("first", 3),
+                ("second", 3)
+            ])
+        );
+        // events are only dispatched once
+        log.bus.dispatch(&log);
+        assert!(log.handled.borrow().is_empty());
+        log.bus.publish(damage(4, true));
+        log.bus.clear();
+        log.bus.dispatch(&log);
+        assert!(log.handled.borrow().is_empty());
+    }
+}
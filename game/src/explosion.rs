@@ -0,0 +1,99 @@
+use crate::game_mediator::GameMediator;
+use crate::graphics_engine::particles::{
+    explosion_emitter, Fireball, Shockwave,
+};
+use crate::object::ObjectData;
+use crate::physics::{ForceManipulator, RadialImpulse};
+use cgmath::*;
+use std::time::Duration;
+
+/// Index of the particle system's billboard of sparks
+const SPARK_BILLBOARD: usize = 1;
+/// Index of the particle system's billboard of fire
+const FIRE_BILLBOARD: usize = 2;
+/// Index of the particle system's billboard of a ring
+const RING_BILLBOARD: usize = 3;
+/// How long the blast of an explosion pushes bodies for
+const BLAST_DURATION: Duration = Duration::from_millis(250);
+/// Radius of the smallest asteroid that explodes when destroyed
+const LARGE_ASTEROID_RADIUS: f64 = 8.;
+
+/// How big an explosion is
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Blast {
+    /// Size the fireball grows to
+    pub fireball: f64,
+    /// Distance the shockwave reaches, and pushes bodies within
+    pub radius: f64,
+    /// Momentum given to a body at the center
+    pub impulse: f64,
+    /// Largest change in speed given to a body at the center
+    pub max_speed: f64,
+}
+
+/// The explosion of a destroyed ship
+pub const SHIP_BLAST: Blast = Blast {
+    fireball: 6.,
+    radius: 60.,
+    impulse: 8000.,
+    max_speed: 30.,
+};
+
+/// Gets the explosion of a destroyed asteroid of radius `radius`, or `None`
+/// if it's too small to explode
+pub fn asteroid_blast(radius: f64) -> Option<Blast> {
+    if radius < LARGE_ASTEROID_RADIUS {
+        return None;
+    }
+    Some(Blast {
+        fireball: radius * 0.6,
+        radius: radius * 5.,
+        impulse: radius * 1000.,
+        max_speed: 20.,
+    })
+}
+
+/// Sets off an explosion of `blast` at `pos`: a fireball whose flash lights
+/// its surroundings, a shockwave, and a spray of sparks
+///
+/// Returns the manipulator of the blast's push on nearby bodies, which must
+/// be added to the simulation
+pub fn explode<M: GameMediator, F: glium::backend::Facade>(
+    pos: Point3<f64>,
+    blast: &Blast,
+    mediator: &mut M,
+    facade: &F,
+) -> ForceManipulator<ObjectData> {
+    mediator.add_particle_emitter(
+        Box::new(Fireball::new(pos, blast.fireball, facade)),
+        FIRE_BILLBOARD,
+    );
+    mediator.add_particle_emitter(
+        Box::new(Shockwave::new(pos, blast.radius, facade)),
+        RING_BILLBOARD,
+    );
+    mediator.add_particle_emitter(explosion_emitter(pos), SPARK_BILLBOARD);
+    ForceManipulator::new_single(Box::new(RadialImpulse {
+        center: pos,
+        impulse: blast.impulse,
+        max_speed: blast.max_speed,
+        radius: blast.radius,
+        duration: BLAST_DURATION,
+    }))
+    .lifetime(BLAST_DURATION)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn only_large_asteroids_explode() {
+        assert!(asteroid_blast(LARGE_ASTEROID_RADIUS / 2.).is_none());
+        let small = asteroid_blast(LARGE_ASTEROID_RADIUS).unwrap();
+        let big = asteroid_blast(LARGE_ASTEROID_RADIUS * 3.).unwrap();
+        assert!(big.radius > small.radius);
+        assert!(big.fireball > small.fireball);
+        assert!(big.impulse > small.impulse);
+    }
+}
@@ -0,0 +1,158 @@
+use crate::object::ObjectId;
+use cgmath::*;
+use rand::Rng;
+use std::collections::HashSet;
+
+/// Maximum number of fragments in the world at once
+const MAX_FRAGMENTS: usize = 60;
+/// Radius of the smallest fragment a body can be split into
+const MIN_FRAGMENT_RADIUS: f64 = 1.5;
+/// Speed fragments fly apart at per unit of the impact's change in velocity
+/// of the destroyed body
+const SCATTER_FAC: f64 = 2.;
+/// Minimum and maximum speeds fragments fly apart at
+const SCATTER_SPEED: (f64, f64) = (2., 25.);
+
+/// A piece of a destroyed body
+#[derive(Clone, Debug)]
+pub struct Fragment {
+    /// Position of the fragment relative to the destroyed body
+    pub offset: Vector3<f64>,
+    pub velocity: Vector3<f64>,
+    /// Scale factor of the fragment relative to the destroyed body
+    pub scale: f64,
+}
+
+/// Splits a body into `count` equally sized fragments whose total volume,
+/// and therefore mass, is the body's
+///
+/// The fragments fly apart in random directions, and their total momentum is
+/// the body's momentum plus the impulse of the impact that destroyed it
+///
+/// `radius` - radius of the body's bounding sphere
+pub fn split<R: Rng>(
+    radius: f64,
+    mass: f64,
+    velocity: Vector3<f64>,
+    impulse: Vector3<f64>,
+    count: usize,
+    rng: &mut R,
+) -> Vec<Fragment> {
+    if count == 0 {
+        return Vec::new();
+    }
+    let delta_v = if mass > f64::EPSILON {
+        impulse / mass
+    } else {
+        vec3(0., 0., 0.)
+    };
+    let speed = (delta_v.magnitude() * SCATTER_FAC)
+        .clamp(SCATTER_SPEED.0, SCATTER_SPEED.1);
+    let dirs: Vec<_> = (0..count)
+        .map(|_| {
+            vec3(
+                rng.gen_range(-1. ..1.),
+                rng.gen_range(-1. ..1.),
+                rng.gen_range(-1. ..1.),
+            )
+        })
+        .collect();
+    // the fragments have equal masses, so their scatter velocities cancel out
+    // once the mean direction is removed
+    let mean = dirs.iter().sum::<Vector3<f64>>() / count as f64;
+    let scale = (1. / count as f64).cbrt();
+    dirs.into_iter()
+        .map(|dir| {
+            let dir = dir - mean;
+            Fragment {
+                offset: dir * radius * (1. - scale),
+                velocity: velocity + delta_v + dir * speed,
+                scale,
+            }
+        })
+        .collect()
+}
+
+/// Decides how many fragments destroyed bodies split into, keeping the number
+/// of fragments in the world bounded
+#[derive(Default)]
+pub struct Fragmenter {
+    /// Ids of the fragments in the world
+    fragments: HashSet<ObjectId>,
+}
+
+impl Fragmenter {
+    /// Gets how many fragments a body with a bounding sphere of radius
+    /// `radius` should split into. Bodies split into 2 to 4 fragments unless
+    /// the fragments would be too small or there's no room for them
+    pub fn fragment_count<R: Rng>(&self, radius: f64, rng: &mut R) -> usize {
+        let room = MAX_FRAGMENTS.saturating_sub(self.fragments.len());
+        let count = rng.gen_range(2..=4).min(room);
+        let fits = |count: usize| {
+            radius * (1. / count as f64).cbrt() >= MIN_FRAGMENT_RADIUS
+        };
+        (2..=count).rev().find(|count| fits(*count)).unwrap_or(0)
+    }
+
+    /// Starts counting the fragment with the id `id` towards the limit
+    pub fn add(&mut self, id: ObjectId) {
+        self.fragments.insert(id);
+    }
+
+    /// Stops counting the object with the id `id` towards the limit, if it's
+    /// a fragment
+    pub fn remove(&mut self, id: ObjectId) {
+        self.fragments.remove(&id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn fragments_conserve_mass_and_momentum() {
+        let (mass, velocity, impulse) =
+            (12., vec3(1., 2., 0.), vec3(0., 0., 24.));
+        for count in 2..=4 {
+            let fragments = split(
+                5.,
+                mass,
+                velocity,
+                impulse,
+                count,
+                &mut rand::thread_rng(),
+            );
+            assert_eq!(fragments.len(), count);
+            let volume: f64 = fragments.iter().map(|f| f.scale.powi(3)).sum();
+            assert_approx_eq!(volume, 1.);
+            let momentum: Vector3<f64> = fragments
+                .iter()
+                .map(|f| f.velocity * mass / count as f64)
+                .sum();
+            let expected = velocity * mass + impulse;
+            assert_approx_eq!(momentum.x, expected.x);
+            assert_approx_eq!(momentum.y, expected.y);
+            assert_approx_eq!(momentum.z, expected.z);
+        }
+    }
+
+    #[test]
+    fn fragment_count_is_bounded() {
+        let mut rng = rand::thread_rng();
+        let mut fragmenter = Fragmenter::default();
+        let count = fragmenter.fragment_count(100., &mut rng);
+        assert!((2..=4).contains(&count));
+        // too small to split
+        assert_eq!(fragmenter.fragment_count(1., &mut rng), 0);
+        // only fits 2 fragments
+        assert_eq!(fragmenter.fragment_count(2., &mut rng), 2);
+        for id in 0..MAX_FRAGMENTS as u32 - 1 {
+            fragmenter.add(ObjectId::new(id));
+        }
+        assert_eq!(fragmenter.fragment_count(100., &mut rng), 0);
+        fragmenter.remove(ObjectId::new(0));
+        assert_eq!(fragmenter.fragment_count(100., &mut rng), 2);
+    }
+}
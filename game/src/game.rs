@@ -1,9 +1,30 @@
+use super::audio;
 use super::controls;
+use super::damage::{DamageModel, DestructionEvent};
+use super::debris::DebrisField;
+use super::energy;
+use super::event_bus::*;
+use super::explosion;
+use super::fragmentation::{self, Fragmenter};
 use super::game_mediator::*;
+use super::game_state::{GameState, GameStateMachine};
+use super::grapple::{self, Grapple};
+use super::mission::{Mission, MissionStatus};
+use super::pickup::{self, Pickups};
 use super::player;
+use super::profiler;
+use super::respawn;
+use super::scoring::Score;
+use super::station::{Docking, DockingEvent, DockingPort, Service};
+use super::time_scale::TimeScale;
+use super::tractor::{self, TractorBeam};
+use super::wormhole::Wormholes;
 use crate::cg_support::node;
 use crate::collisions::*;
 use crate::entity::AbstractEntity;
+use crate::graphics_engine::clock;
+use crate::graphics_engine::drawable::Viewer;
+use crate::graphics_engine::lights::{self, LightManager};
 use crate::graphics_engine::particles::*;
 use crate::graphics_engine::scene;
 use crate::object;
@@ -11,23 +32,164 @@ use crate::physics::*;
 use crate::player::Player;
 use cgmath::*;
 use controls::{ControllerAction, PlayerActionState};
+use shared_types::save::SaveGame;
+use shared_types::survival::{
+    Difficulty, ShopItem, Wave, WaveEvent, WavePhase,
+};
+use shared_types::wormhole::TeleportClaim;
 use std::cell::Cell;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
+use tracing::debug;
 
 /// Encapsulates the game map and handles the logic for base game mechanics
 pub struct Game<M: GameMediator> {
     mediator: RefCell<M>,
     characters: Vec<Rc<RefCell<player::Player>>>,
-    // to access some character data during hit callback when characters
-    // are already borrowed
-    health_deltas: RefCell<HashMap<object::ObjectData, f64>>,
+    damage: RefCell<DamageModel>,
     player_1_base: Cell<Option<BaseRigidBody>>,
 
     forces: RefCell<Vec<Box<dyn Manipulator<object::ObjectData>>>>,
     dead_lasers: RefCell<Vec<shared_types::ObjectId>>,
     new_forces: RefCell<Vec<Box<dyn Manipulator<object::ObjectData>>>>,
+    audio: RefCell<Option<audio::AudioEngine>>,
+    /// Index of the character player 1 is locked on to
+    target: Cell<Option<usize>>,
+    state: RefCell<GameStateMachine>,
+    mission: RefCell<Mission>,
+    /// Objects destroyed during the current frame
+    destroyed: RefCell<Vec<DestructionEvent>>,
+    fragmenter: RefCell<Fragmenter>,
+    /// Debris left behind by destroyed ships
+    debris: RefCell<DebrisField>,
+    /// How fast the game runs relative to real time
+    time_scale: RefCell<TimeScale>,
+    /// `true` while the game is slowed down for the tactical map
+    tactical_slowed: Cell<bool>,
+    /// Points and statistics of player 1
+    score: Rc<RefCell<Score>>,
+    /// Times player 1 can die before the game is over
+    lives: Cell<u32>,
+    pickups: RefCell<Pickups>,
+    /// Line from player 1 to the object its hook is attached to
+    grapple: RefCell<Option<Grapple>>,
+    /// Player 1's tractor beam, while it holds a target
+    tractor: RefCell<Option<TractorBeam>>,
+    /// Effect drawn along player 1's tractor beam
+    beam: Rc<RefCell<Beam>>,
+    /// Indices of the benched characters spawned in waves, and their
+    /// difficulties
+    reserves: Vec<(usize, Difficulty)>,
+    /// Wave events that happened since they were last taken
+    wave_events: RefCell<Vec<WaveEvent>>,
+    wormholes: RefCell<Wormholes>,
+    /// Player 1's docking with the map's station
+    docking: RefCell<Docking>,
+    /// Lights passed to the scene each frame
+    lights: RefCell<LightManager>,
+    /// Events of the current frame, which are dispatched once the
+    /// simulation has been stepped
+    events: EventBus<Self>,
+}
+
+const LASER_FIRE_SOUND: &str = "assets/sounds/laser_fire.wav";
+const LASER_HIT_SOUND: &str = "assets/sounds/laser_hit.wav";
+/// How long the flash of a laser hit lights its surroundings
+const LASER_HIT_FLASH: std::time::Duration =
+    std::time::Duration::from_millis(120);
+const ASTEROID_HIT_SOUND: &str = "assets/sounds/asteroid_hit.wav";
+/// Background music, which is looped
+pub const MUSIC_TRACK: &str = "assets/sounds/music.wav";
+
+/// Times player 1 can die before the game is over
+const PLAYER_LIVES: u32 = 3;
+/// Speed of the lasers fired by players
+pub const LASER_SPEED: f64 = 120.;
+/// Damage dealt by a laser to whatever it hits
+const LASER_DAMAGE: f64 = 10.;
+/// Damage dealt by a collision per unit of the relative speed of the
+/// colliding objects
+const COLLISION_DAMAGE_FAC: f64 = 0.1;
+/// Speed of the hooks fired by players
+const HOOK_SPEED: f64 = 200.;
+/// Distance in front of a bot's ship that its lasers are fired from
+pub const MUZZLE_DIST: f64 = 10.;
+/// Time the fire rate bought from the shop is boosted for
+const SHOP_FIRE_BOOST: std::time::Duration = std::time::Duration::from_secs(45);
+/// Angular velocity, in radians per second, player 1's camera is kicked up
+/// by when it fires a laser
+const SHOT_KICK: f64 = 0.6;
+/// Damage from a single hit that fully shakes player 1's camera
+const FULL_SHAKE_DAMAGE: f64 = 40.;
+/// Trauma added to player 1's camera by an explosion right next to it
+const EXPLOSION_TRAUMA: f64 = 0.6;
+/// Time scale the game slows down to when player 1 dies
+const DEATH_TIME_SCALE: f64 = 0.25;
+/// Real time the game stays slowed down for when player 1 dies
+const DEATH_SLOW_MOTION: std::time::Duration =
+    std::time::Duration::from_millis(1200);
+/// Time scale the game slows down to while player 1 is on the tactical map
+const TACTICAL_TIME_SCALE: f64 = 0.1;
+/// Real time it takes to slow down when the tactical map is shown, and to
+/// speed back up once it's hidden
+const TACTICAL_RAMP: std::time::Duration =
+    std::time::Duration::from_millis(300);
+/// Distance from player 1 beyond which explosions don't shake its camera
+const EXPLOSION_SHAKE_RANGE: f64 = 250.;
+/// Maximum distance of a target that can be locked on to
+const LOCK_RANGE: f64 = 800.;
+/// Maximum angle between a ship's forward direction and the direction to a
+/// target that can be locked on to
+const LOCK_ANGLE: Deg<f64> = Deg(25.);
+
+/// Gets the target after `current` in `candidates`, wrapping around to the
+/// first candidate. If `current` is not a candidate, the first candidate is
+/// chosen
+fn next_target(current: Option<usize>, candidates: &[usize]) -> Option<usize> {
+    let next = current
+        .and_then(|cur| candidates.iter().position(|c| *c == cur))
+        .map_or(0, |idx| idx + 1);
+    candidates.get(next).or_else(|| candidates.first()).copied()
+}
+
+/// Gets the time it takes a projectile fired from `origin` at `speed` to hit
+/// a target at `target_pos` moving at constant velocity `target_vel`
+///
+/// Returns `None` if the projectile can never catch the target
+fn intercept_time(
+    origin: Point3<f64>,
+    target_pos: Point3<f64>,
+    target_vel: Vector3<f64>,
+    speed: f64,
+) -> Option<f64> {
+    // solve |target_pos - origin + target_vel * t| = speed * t for t
+    let to_target = target_pos - origin;
+    let a = target_vel.magnitude2() - speed * speed;
+    let b = 2. * to_target.dot(target_vel);
+    let c = to_target.magnitude2();
+    if a.abs() < f64::EPSILON {
+        // the target moves as fast as the projectile
+        return if b.abs() < f64::EPSILON {
+            None
+        } else {
+            Some(-c / b).filter(|t| *t >= 0.)
+        };
+    }
+    let discriminant = b.mul_add(b, -4. * a * c);
+    if discriminant < 0. {
+        return None;
+    }
+    let sqrt_d = discriminant.sqrt();
+    let (t1, t2) = ((-b - sqrt_d) / (2. * a), (-b + sqrt_d) / (2. * a));
+    let (t1, t2) = (t1.min(t2), t1.max(t2));
+    if t1 >= 0. {
+        Some(t1)
+    } else if t2 >= 0. {
+        Some(t2)
+    } else {
+        None
+    }
 }
 
 impl<M: GameMediator> Game<M> {
@@ -35,9 +197,7 @@ impl<M: GameMediator> Game<M> {
     fn create_emitter<Func>(
         &self,
         emitter_factory: Func,
-        a: &RigidBody<object::ObjectData>,
-        b: &RigidBody<object::ObjectData>,
-        hit: &HitData,
+        event: &CollisionEvent,
         emitter_id: usize,
     ) where
         Func: Fn(
@@ -48,14 +208,14 @@ impl<M: GameMediator> Game<M> {
         ) -> Box<dyn Emitter>,
     {
         use crate::graphics_engine;
-        let relative_vel = a.base.velocity - b.base.velocity;
+        let relative_vel = event.a.velocity - event.b.velocity;
         if relative_vel.magnitude() > 1. {
             let ctx = graphics_engine::get_active_ctx();
             let facade = ctx.ctx.borrow();
             self.mediator.borrow_mut().add_particle_emitter(
                 emitter_factory(
-                    hit.pos_norm_b.0,
-                    hit.pos_norm_b.1,
+                    event.b.pos,
+                    event.b.normal,
                     relative_vel,
                     &*facade,
                 ),
@@ -65,134 +225,285 @@ impl<M: GameMediator> Game<M> {
     }
 
     /// Callback function for when a tether shot collides with
-    /// an object
-    fn on_hook(
-        &self,
-        a: &RigidBody<object::ObjectData>,
-        b: &RigidBody<object::ObjectData>,
-        hit: &HitData,
-        player: &BaseRigidBody,
-    ) {
+    /// an object. Attaches a grappling line from `player` to the point the
+    /// hook hit on `target`, replacing the line it was already attached with
+    fn on_hook(&self, target: &CollidingBody, player: &BaseRigidBody) {
         use crate::graphics_engine::primitives;
-        use crate::physics;
 
-        let target = if a.metadata.0 == object::ObjectType::Hook {
-            b
-        } else {
-            a
-        };
-        let hit_point = if a.metadata.0 == object::ObjectType::Hook {
-            hit.pos_norm_b.0
-        } else {
-            hit.pos_norm_a.0
+        if Rc::ptr_eq(&target.transform, &player.transform) {
+            return;
+        }
+        self.release_grapple();
+        // lines anchored to immobile bodies are held stiff, while bodies
+        // being towed are eased along by the line
+        let mode = match target.data.0 {
+            object::ObjectType::Planet | object::ObjectType::Station => {
+                TetherMode::Rigid
+            }
+            _ => TetherMode::Spring,
         };
-        let ship_front = player
-            .transform
-            .borrow()
-            .transform_point(point3(0., 0., 8.));
-        let hit_local = target
-            .base
-            .transform
-            .borrow()
-            .mat()
-            .invert()
-            .unwrap()
-            .transform_point(hit_point);
+        let (grapple, tether) = Grapple::attach(
+            &player.transform,
+            player.mass,
+            &target.transform,
+            target.pos,
+            mode,
+        );
         self.mediator.borrow_mut().add_line(
             0,
             primitives::LineData {
                 color: [1., 0., 0., 1.],
                 start: node::Node::default()
                     .parent(player.transform.clone())
-                    .pos(point3(0., 0., 10.)),
+                    .pos(grapple::SHIP_ATTACH),
                 end: node::Node::default()
-                    .parent(target.base.transform.clone())
-                    .pos(hit_local),
+                    .parent(target.transform.clone())
+                    .pos(grapple.target_attach()),
+                length: Some(grapple.rope_length()),
             },
         );
-        self.new_forces
+        self.new_forces.borrow_mut().push(Box::new(tether));
+        self.grapple.replace(Some(grapple));
+    }
+
+    /// Detaches player 1's grappling line, if it's attached. Both objects keep
+    /// the velocities they had when the line was released
+    fn release_grapple(&self) {
+        if let Some(grapple) = self.grapple.take() {
+            grapple.release();
+            self.mediator.borrow_mut().remove_line(0);
+        }
+    }
+
+    /// Reels player 1's grappling line in or out, and releases it if player
+    /// 1 died
+    fn reel_grapple(&self, dt: std::time::Duration) {
+        let player = self.player_1();
+        let player = player.borrow();
+        if player.is_dead() {
+            self.release_grapple();
+        } else if let Some(grapple) = self.grapple.borrow().as_ref() {
+            grapple.reel(player.get_reel(), dt);
+        }
+    }
+
+    /// Gets the node and center of the closest asteroid in player 1's
+    /// tractor beam, if any
+    fn tractor_target(
+        &self,
+        origin: Point3<f64>,
+        forward: Vector3<f64>,
+    ) -> Option<(Rc<RefCell<node::Node>>, Point3<f64>)> {
+        let mut closest: Option<(Rc<RefCell<node::Node>>, Point3<f64>)> = None;
+        self.mediator.borrow().iter_bodies(|bodies| {
+            for body in bodies
+                .filter(|body| body.metadata.0 == object::ObjectType::Asteroid)
+            {
+                let center = body.base.center();
+                if tractor::in_beam(origin, forward, center)
+                    && closest.as_ref().map_or(true, |(_, pos)| {
+                        origin.distance2(center) < origin.distance2(*pos)
+                    })
+                {
+                    closest = Some((body.base.transform.clone(), center));
+                }
+            }
+        });
+        closest
+    }
+
+    /// Locks player 1's tractor beam on to the closest asteroid in front of
+    /// it while the beam is held, and spends energy while the beam holds its
+    /// target. The target is released once the beam is let go, the target
+    /// leaves the beam's range, or player 1 runs out of energy
+    fn update_tractor(&self, dt: std::time::Duration) {
+        let player = self.player_1();
+        let mut player = player.borrow_mut();
+        let held = player.is_beam_held()
+            && !player.is_dead()
+            && self.docking.borrow().is_flying();
+        let cost = energy::BEAM_COST * dt.as_secs_f64();
+        let mut tractor = self.tractor.borrow_mut();
+        if let Some(beam) = tractor.as_ref() {
+            if !held || !beam.is_attached() || !player.draw_energy(cost) {
+                beam.release();
+                *tractor = None;
+            }
+        } else if held && player.energy() > cost {
+            let ship = player.get_node();
+            let origin = player.beam_origin();
+            if let Some((target, center)) =
+                self.tractor_target(origin, player.forward())
+            {
+                let (beam, joint) = TractorBeam::lock(
+                    &ship,
+                    player.ship().beam,
+                    &target,
+                    center,
+                );
+                self.new_forces.borrow_mut().push(Box::new(joint));
+                *tractor = Some(beam);
+            }
+        }
+        self.beam
             .borrow_mut()
-            .push(Box::new(physics::Tether::new(physics::TetherData {
-                attach_a: hit_local,
-                a: Rc::downgrade(&target.base.transform),
-                attach_b: ship_front,
-                b: Rc::downgrade(&player.transform),
-                length: (ship_front - hit_point).magnitude(),
-            })));
-    }
-
-    /// Checks if a `check` is a character that was involved in a collision, and if so,
-    /// reduces the player's health accordingly
-    fn check_player_hit(
+            .set_ends(tractor.as_ref().and_then(TractorBeam::ends));
+    }
+
+    /// Snaps player 1's grappling line if the last step put it under too
+    /// much tension, and removes the constraints that are no longer active
+    fn check_grapple(&self) {
+        let (overloaded, detached) = self
+            .grapple
+            .borrow()
+            .as_ref()
+            .map_or((false, false), |grapple| {
+                (grapple.is_overloaded(), !grapple.is_attached())
+            });
+        if overloaded {
+            debug!("Grappling line snapped");
+        }
+        if overloaded || detached {
+            self.release_grapple();
+        }
+        self.forces.borrow_mut().retain(|f| f.is_active());
+    }
+
+    /// Deals the damage `source` does to `target` by hitting it at `pos`
+    fn deal_hit_damage(
         &self,
-        check: &RigidBody<object::ObjectData>,
-        collider: &RigidBody<object::ObjectData>,
+        target: &RigidBody<object::ObjectData>,
+        source: &RigidBody<object::ObjectData>,
+        pos: Point3<f64>,
     ) {
-        const SHIELD_DAMAGE_FAC: f64 = -0.01;
-        if let Some((_, shield_delta)) = self
-            .health_deltas
-            .borrow_mut()
-            .iter_mut()
-            .find(|(key, _)| **key == collider.metadata)
-        {
-            if check.metadata.0 == object::ObjectType::Laser {
-                *shield_delta -= 10.;
-            } else if check.metadata.0 != object::ObjectType::Hook {
-                *shield_delta -= SHIELD_DAMAGE_FAC
-                    * (check.base.velocity - collider.base.velocity)
-                        .magnitude();
+        let amount = match source.metadata.0 {
+            object::ObjectType::Laser => LASER_DAMAGE,
+            object::ObjectType::Hook => return,
+            _ => {
+                COLLISION_DAMAGE_FAC
+                    * (source.base.velocity - target.base.velocity).magnitude()
             }
-        }
+        };
+        self.damage.borrow_mut().deal(target, source, amount, pos);
     }
 
     /// Callback function for when two objects collide
+    ///
+    /// Damage is dealt during the simulation step, everything else that
+    /// reacts to the collision subscribes to its event
     pub fn on_hit(
         &self,
         a: &RigidBody<object::ObjectData>,
         b: &RigidBody<object::ObjectData>,
         hit: &HitData,
     ) {
+        use object::ObjectType::Pickup;
+        self.events.publish(CollisionEvent::new(a, b, hit));
+        // pickups are sensors which only interact with the ships that
+        // collect them
+        if a.metadata.0 != Pickup && b.metadata.0 != Pickup {
+            self.deal_hit_damage(a, b, hit.pos_norm_a.0);
+            self.deal_hit_damage(b, a, hit.pos_norm_b.0);
+        }
+    }
+
+    /// Subscribes the game's own systems to the events of a frame
+    fn system_events() -> EventBus<Self> {
+        let mut events = EventBus::default();
+        events
+            .subscribe(Self::on_laser_hit)
+            .subscribe(Self::on_asteroid_hit)
+            .subscribe(Self::on_hook_hit)
+            .subscribe(Self::on_pickup_touched)
+            .subscribe(Self::on_damage)
+            .subscribe(Self::on_destroyed)
+            .subscribe(Self::on_pickup);
+        events
+    }
+
+    /// Sprays sparks where a laser hit, and removes the laser. Lasers knock
+    /// debris around and leave decals on asteroids
+    fn on_laser_hit(&self, event: &CollisionEvent) {
         use object::ObjectType::*;
-        if a.metadata.0 == Laser || b.metadata.0 == Laser {
-            self.create_emitter(
-                laser_hit_emitter::<glium::Display>,
-                a,
-                b,
-                hit,
-                0,
-            );
-            let lt = if a.metadata.0 == Laser {
-                a.metadata.1
-            } else {
-                b.metadata.1
-            };
-            self.dead_lasers.borrow_mut().push(lt);
+        let (laser, target) = match event.involving(Laser) {
+            Some((_, target)) if target.data.0 == Pickup => return,
+            Some(bodies) => bodies,
+            None => return,
+        };
+        self.create_emitter(laser_hit_emitter::<glium::Display>, event, 0);
+        self.lights.borrow_mut().flash(
+            super::shader::LightData::point_light(
+                target.pos.cast().unwrap(),
+                120.,
+                vec3(0.5451, 0., 0.5451),
+            ),
+            LASER_HIT_FLASH,
+        );
+        self.dead_lasers.borrow_mut().push(laser.data.1);
+        self.play_sound(
+            LASER_HIT_SOUND,
+            audio::SoundEmitter::at(event.a.pos).range(20., 600.),
+        );
+        // lasers pass through debris, so they knock it around themselves
+        match target.data.0 {
+            Debris => self
+                .debris
+                .borrow_mut()
+                .kick(target.data.1, laser.velocity - target.velocity),
+            Asteroid => self.mediator.borrow_mut().add_decal(
+                &target.transform,
+                target.pos,
+                target.normal,
+            ),
+            _ => (),
         }
-        if a.metadata.0 == Ship && b.metadata.0 == Asteroid
-            || a.metadata.0 == Asteroid && b.metadata.0 == Ship
-        {
+    }
+
+    /// Sprays dust where a ship hit an asteroid
+    fn on_asteroid_hit(&self, event: &CollisionEvent) {
+        use object::ObjectType::*;
+        if event.between(Ship, Asteroid).is_some() {
             self.create_emitter(
                 asteroid_hit_emitter::<glium::Display>,
-                a,
-                b,
-                hit,
+                event,
                 1,
             );
+            self.play_sound(
+                ASTEROID_HIT_SOUND,
+                audio::SoundEmitter::at(event.a.pos).range(20., 800.),
+            );
         }
-        if a.metadata.0 == Hook || b.metadata.0 == Hook {
-            // TODO: allow multiple hooks
-            let p1_base = self.player_1_base.take();
-            self.on_hook(a, b, hit, p1_base.as_ref().unwrap());
-            self.player_1_base.replace(p1_base);
-            let lt = if a.metadata.0 == Hook {
-                a.metadata.1
-            } else {
-                b.metadata.1
-            };
-            self.dead_lasers.borrow_mut().push(lt);
+    }
+
+    /// Attaches player 1's grappling line to whatever its hook hit, and
+    /// removes the hook
+    fn on_hook_hit(&self, event: &CollisionEvent) {
+        use object::ObjectType::*;
+        let (hook, target) = match event.involving(Hook) {
+            Some((_, target)) if target.data.0 == Pickup => return,
+            Some(bodies) => bodies,
+            None => return,
+        };
+        // TODO: allow multiple hooks
+        let p1_base = self.player_1_base.take();
+        self.on_hook(target, p1_base.as_ref().unwrap());
+        self.player_1_base.replace(p1_base);
+        self.dead_lasers.borrow_mut().push(hook.data.1);
+    }
+
+    /// Collects the pickups touched by ships
+    fn on_pickup_touched(&self, event: &CollisionEvent) {
+        use object::ObjectType::*;
+        if let Some((pickup, ship)) = event.between(Pickup, Ship) {
+            let kind = self.pickups.borrow_mut().collect(pickup.data.1);
+            if let Some(kind) = kind {
+                self.events.publish(PickupEvent {
+                    id: pickup.data.1,
+                    kind,
+                    collector: ship.data,
+                });
+            }
         }
-        self.check_player_hit(a, b);
-        self.check_player_hit(b, a);
     }
 
     /// Callback function for physics simulation
@@ -208,7 +519,12 @@ impl<M: GameMediator> Game<M> {
         #[allow(clippy::unnested_or_patterns)]
         !matches!(
             (a.metadata.0, b.metadata.0),
-            (Hook, _) | (_, Hook) | (Laser, _) | (_, Laser)
+            (Hook, _)
+                | (_, Hook)
+                | (Laser, _)
+                | (_, Laser)
+                | (Pickup, _)
+                | (_, Pickup)
         )
     }
 
@@ -224,14 +540,6 @@ impl<M: GameMediator> Game<M> {
         let mut characters: Vec<_> =
             self.characters.iter().map(|p| p.borrow_mut()).collect();
 
-        // Reset health deltas
-        self.health_deltas.borrow_mut().clear();
-        for c in &characters {
-            self.health_deltas
-                .borrow_mut()
-                .insert(c.get_rigid_body().metadata, 0.);
-        }
-
         self.player_1_base
             .set(Some(characters[0].get_rigid_body().base.clone()));
 
@@ -258,47 +566,245 @@ impl<M: GameMediator> Game<M> {
         };
         Simulation::apply_resolvers(&mut bodies, &resolvers, dt);
 
-        // Updates players' health
-        for c in &mut characters {
-            let index = c.get_rigid_body().metadata;
-            // println!("Updating {:?}", index);
-            c.change_shield(self.health_deltas.borrow()[&index]);
-            if c.shield() <= f64::EPSILON {
-                c.get_node().borrow_mut().set_pos(
-                    point3(
-                        rand::random::<f64>() - 0.5,
-                        rand::random::<f64>() - 0.5,
-                        rand::random::<f64>() - 0.5,
-                    ) * 200.0,
-                );
+        // Applies the damage dealt during the step, the game is over once
+        // player 1 runs out of lives
+        let mut damage = self.damage.borrow_mut();
+        let mut died = Vec::new();
+        for (dmg, radius) in damage.take_pending() {
+            let destroyed = if let Some((c, idx)) = characters
+                .iter_mut()
+                .zip(0..)
+                .find(|(c, _)| c.get_rigid_body().metadata == dmg.target)
+            {
+                let killed = c.apply_damage(dmg.multiplied());
+                if killed {
+                    died.push(idx);
+                }
+                if killed && idx == 0 {
+                    self.lives.set(self.lives.get().saturating_sub(1));
+                    if self.lives.get() == 0 {
+                        self.state.borrow_mut().request(GameState::GameOver);
+                    }
+                }
+                killed.then(|| DestructionEvent::from_damage(&dmg))
+            } else {
+                damage.apply(&dmg, radius)
+            };
+            self.events.publish(DamageEvent {
+                damage: dmg,
+                fatal: destroyed.is_some(),
+            });
+            if let Some(destroyed) = destroyed {
+                self.events.publish(destroyed);
+            }
+        }
+        damage.update(dt);
+
+        // dead ships wait at their respawn points until they respawn
+        let tree = sim.get_collision_tree();
+        let mut rng = rand::thread_rng();
+        for idx in died {
+            if characters[idx].is_benched() {
+                self.on_enemy_destroyed(idx);
+                continue;
+            }
+            let pos = {
+                let ship: Vec<_> = characters[idx]
+                    .get_rigid_body()
+                    .base
+                    .collider
+                    .iter()
+                    .collect();
+                respawn::find_spawn_point(
+                    tree,
+                    respawn::SPAWN_CLEARANCE,
+                    &ship,
+                    &mut rng,
+                )
+            };
+            characters[idx].set_respawn_point(pos);
+        }
+    }
+
+    /// Records the damage dealt to and by player 1, and shakes player 1's
+    /// camera when it's hit
+    fn on_damage(&self, event: &DamageEvent) {
+        let dmg = &event.damage;
+        let p1 = self.player_1();
+        let mut p1 = p1.borrow_mut();
+        let p1_data = p1.get_rigid_body().metadata;
+        let mut score = self.score.borrow_mut();
+        if dmg.target == p1_data {
+            score.record_damage_taken(dmg.multiplied());
+            p1.camera_effects()
+                .add_trauma(dmg.multiplied() / FULL_SHAKE_DAMAGE);
+        } else if dmg.source == p1_data || score.record_hit(dmg.source.1) {
+            score.record_damage_dealt(dmg.multiplied());
+            if event.fatal {
+                score.record_kill(dmg.target.0);
+            }
+        }
+    }
+
+    /// Records an object being destroyed during the frame, and shakes player
+    /// 1's camera by how close it was to the object
+    fn on_destroyed(&self, event: &DestructionEvent) {
+        self.destroyed.borrow_mut().push(*event);
+        let p1 = self.player_1();
+        let mut p1 = p1.borrow_mut();
+        let closeness = 1.
+            - event.pos.distance(p1.get_rigid_body().base.center())
+                / EXPLOSION_SHAKE_RANGE;
+        if closeness > 0. {
+            p1.camera_effects().add_trauma(EXPLOSION_TRAUMA * closeness);
+        }
+        self.play_sound(
+            ASTEROID_HIT_SOUND,
+            audio::SoundEmitter::at(event.pos).range(20., 800.),
+        );
+    }
+
+    /// Applies the effect of a collected pickup to the ship that collected
+    /// it, and removes the pickup
+    fn on_pickup(&self, event: &PickupEvent) {
+        if let Some(character) = self
+            .characters
+            .iter()
+            .find(|c| c.borrow().get_rigid_body().metadata == event.collector)
+        {
+            pickup::apply_effect(event.kind, &mut *character.borrow_mut());
+        }
+        self.mediator
+            .borrow_mut()
+            .remove_objects(object::ObjectType::Pickup, &[event.id]);
+    }
+
+    /// Records the benched character at index `idx` being destroyed, if it
+    /// was part of a wave
+    fn on_enemy_destroyed(&self, idx: usize) {
+        let difficulty = self
+            .reserves
+            .iter()
+            .find(|(reserve, _)| *reserve == idx)
+            .map(|(_, difficulty)| *difficulty);
+        if let (Some(difficulty), Some(waves)) =
+            (difficulty, self.mediator.borrow_mut().waves())
+        {
+            self.wave_events
+                .borrow_mut()
+                .extend(waves.enemy_destroyed(difficulty));
+        }
+    }
+
+    /// Sends the waves of a survival game at player 1, and applies the items
+    /// player 1 buys from the shop between waves
+    fn update_waves(&self, tree: &CollisionTree, dt: std::time::Duration) {
+        let purchase = self.player_1().borrow().take_purchase();
+        let mut mediator = self.mediator.borrow_mut();
+        let waves = match mediator.waves() {
+            Some(waves) => waves,
+            None => return,
+        };
+        if let Some(event) = waves.update(dt) {
+            if let WaveEvent::Started(wave) = event {
+                waves.enemies_spawned(self.spawn_wave(&wave, tree));
+            }
+            self.wave_events.borrow_mut().push(event);
+        }
+        if let Some(item) = purchase.filter(|item| waves.buy(*item)) {
+            let player = self.player_1();
+            match item {
+                ShopItem::Repair => player.borrow_mut().repair(),
+                ShopItem::ExtraLife => self.lives.set(self.lives.get() + 1),
+                ShopItem::FireBoost => {
+                    player.borrow_mut().boost_fire_rate(SHOP_FIRE_BOOST);
+                }
             }
         }
     }
 
+    /// Spawns the benched characters of each difficulty in `wave` at clear
+    /// points. There may be fewer benched characters than the wave has
+    /// enemies
+    ///
+    /// Returns the number of characters spawned
+    fn spawn_wave(&self, wave: &Wave, tree: &CollisionTree) -> u32 {
+        let mut rng = rand::thread_rng();
+        let mut spawned = 0;
+        for difficulty in
+            [Difficulty::Easy, Difficulty::Normal, Difficulty::Ace]
+        {
+            for (idx, _) in self
+                .reserves
+                .iter()
+                .filter(|(idx, reserve_difficulty)| {
+                    *reserve_difficulty == difficulty
+                        && self.characters[*idx].borrow().is_benched()
+                })
+                .take(wave.count(difficulty) as usize)
+            {
+                let mut enemy = self.characters[*idx].borrow_mut();
+                let pos = {
+                    let ship: Vec<_> =
+                        enemy.get_rigid_body().base.collider.iter().collect();
+                    respawn::find_spawn_point(
+                        tree,
+                        respawn::SPAWN_CLEARANCE,
+                        &ship,
+                        &mut rng,
+                    )
+                };
+                enemy.spawn_at(pos);
+                spawned += 1;
+            }
+        }
+        spawned
+    }
+
+    /// Plays a sound effect if audio is enabled
+    fn play_sound(&self, path: &str, emitter: audio::SoundEmitter) {
+        if let Some(audio) = self.audio.borrow_mut().as_mut() {
+            audio.play(path, emitter);
+        }
+    }
+
     /// Function thet should be called every frame to handle shooting lasers
-    fn handle_shots(user: &mut player::Player, mediator: &mut M) {
-        const ENERGY_PER_SHOT: f64 = 1.;
-        if matches!(
+    ///
+    /// Returns the type and id of the laser or hook `user` fired, if any
+    fn handle_shots(
+        user: &mut player::Player,
+        mediator: &mut M,
+    ) -> Option<object::ObjectData> {
+        let mut fired = None;
+        let requested = matches!(
             user.get_action_state(),
             PlayerActionState::Fire | PlayerActionState::FireRope
-        ) && user.energy() > ENERGY_PER_SHOT
-        {
+        );
+        if requested && user.energy() > energy::SHOT_COST && user.can_fire() {
             let mut transform = user
                 .root()
                 .borrow()
                 .clone()
                 .scale(cgmath::vec3(0.3, 0.3, 1.));
-            transform.translate(user.forward() * 10.);
+            transform.set_pos(user.next_muzzle());
             let (typ, speed) =
                 if user.get_action_state() == PlayerActionState::FireRope {
-                    (object::ObjectType::Hook, 200.)
+                    (object::ObjectType::Hook, HOOK_SPEED)
                 } else {
-                    (object::ObjectType::Laser, 120.)
+                    (object::ObjectType::Laser, LASER_SPEED)
                 };
-            mediator.add_laser(transform, user.forward() * speed, typ);
-            user.change_energy(-ENERGY_PER_SHOT);
+            fired = mediator
+                .add_laser(transform, user.forward() * speed, typ)
+                .map(|id| (typ, id));
+            user.change_energy(-energy::SHOT_COST);
+            user.start_shot_cooldown();
+        }
+        // a shot requested too soon after the last one is fired once the
+        // player can fire again
+        if fired.is_some() || !requested || user.can_fire() {
+            user.transition_action_state();
         }
-        user.transition_action_state();
+        fired
     }
 
     /// Updates the players' rigid body states from the controller actions
@@ -313,11 +819,17 @@ impl<M: GameMediator> Game<M> {
 
         // then update velocity once rotations are sufficiently close
         for (idx, action) in actions {
-            self.characters[idx]
-                .borrow_mut()
-                .get_rigid_body_mut()
-                .base
-                .velocity = action.velocity;
+            let mut character = self.characters[idx].borrow_mut();
+            if character.is_dead() {
+                continue;
+            }
+            let boost = if action.afterburner {
+                player::AFTERBURNER_FAC
+            } else {
+                1.
+            };
+            character.get_rigid_body_mut().base.velocity =
+                action.velocity * boost;
             // if action.velocity.magnitude() > 0.001 {
             //     let rot = cg_support::look_at(
             //         action.velocity.normalize(),
@@ -336,6 +848,29 @@ impl<M: GameMediator> Game<M> {
         }
     }
 
+    /// Adds exhaust particle emitters for the thruster nozzles of the
+    /// characters that don't have them yet
+    fn add_thruster_emitters(&self) {
+        use crate::graphics_engine;
+        let nozzles: Vec<_> = self
+            .characters
+            .iter()
+            .flat_map(|c| c.borrow_mut().take_unemitted_nozzles())
+            .collect();
+        if nozzles.is_empty() {
+            return;
+        }
+        let ctx = graphics_engine::get_active_ctx();
+        let facade = ctx.ctx.borrow();
+        let mut mediator = self.mediator.borrow_mut();
+        for nozzle in &nozzles {
+            mediator.add_particle_emitter(
+                Box::new(ExhaustEmitter::new(nozzle, &*facade)),
+                1,
+            );
+        }
+    }
+
     /// Callback function for when a frame is drawn
     pub fn on_draw<'a, 'b>(
         &self,
@@ -344,27 +879,87 @@ impl<M: GameMediator> Game<M> {
         scene: &mut dyn scene::AbstractScene,
     ) {
         use controls::PlayerIteratorHolder;
+        if !self.state.borrow().is_simulating() {
+            return;
+        }
+        // everything in the game runs on game time, which may be slowed
+        let dt = self.update_time_scale(dt);
+        self.lights.borrow_mut().next_frame(dt);
         self.mediator.borrow_mut().sync();
         self.dead_lasers.borrow_mut().clear();
-        for player in &self.characters {
+        self.destroyed.borrow_mut().clear();
+        for (player, idx) in self.characters.iter().zip(0..) {
+            if idx == 0 && !self.docking.borrow().is_flying() {
+                continue;
+            }
             let mut u = player.borrow_mut();
             if u.get_action_state() == PlayerActionState::CutRope {
-                self.forces.borrow_mut().clear();
-                self.mediator.borrow_mut().remove_line(0);
+                self.release_grapple();
+            }
+            if let Some((typ, id)) =
+                Self::handle_shots(&mut *u, &mut self.mediator.borrow_mut())
+            {
+                if idx == 0 && typ == object::ObjectType::Laser {
+                    self.score.borrow_mut().record_shot(id);
+                    u.camera_effects().kick(SHOT_KICK);
+                }
+                self.play_sound(
+                    LASER_FIRE_SOUND,
+                    audio::SoundEmitter::new(&u.get_node())
+                        .range(30., 600.)
+                        .volume(0.6),
+                );
             }
-            Self::handle_shots(&mut *u, &mut self.mediator.borrow_mut());
         }
-        self.step_sim(sim, dt);
+        self.add_thruster_emitters();
+        self.reel_grapple(dt);
+        self.update_tractor(dt);
+        {
+            let _timer = profiler::scope("simulation");
+            self.step_sim(sim, dt);
+        }
+        self.events.dispatch(self);
+        self.check_grapple();
+        self.slow_down_on_death();
+        self.update_wormholes(dt);
+        self.update_docking(dt);
+        self.update_target(sim.get_collision_tree(), dt);
+        self.update_waves(sim.get_collision_tree(), dt);
 
         self.mediator
             .borrow_mut()
             .remove_lasers(&self.dead_lasers.borrow());
-        self.mediator.borrow_mut().emit_particles(dt);
-        scene.set_lights(&self.mediator.borrow().get_lights());
+        self.remove_destroyed();
+        self.update_mission(dt);
+        self.score.borrow_mut().update(dt);
+        self.pickups
+            .borrow_mut()
+            .update(dt, &mut *self.mediator.borrow_mut());
+        self.debris
+            .borrow_mut()
+            .update(dt, &mut *self.mediator.borrow_mut());
+        let view_pos = self.player_1().borrow().cam_pos();
+        {
+            let _timer = profiler::scope("particles");
+            self.mediator.borrow_mut().emit_particles(dt, view_pos);
+        }
+        if let Some(audio) = self.audio.borrow_mut().as_mut() {
+            audio.update(audio::Listener::from_view(
+                self.player_1().borrow().view_mat(),
+            ));
+        }
+        {
+            let mut lights = self.lights.borrow_mut();
+            lights.extend(self.mediator.borrow().get_lights());
+            scene.set_lights(lights.finish(view_pos));
+        }
 
+        let _timer = profiler::scope("ai");
+        let projectiles = self.projectiles();
         let it = self.characters.iter();
         let mut actions = HashMap::new();
         for (player, idx) in self.characters.iter().zip(0..) {
+            player.borrow().observe_projectiles(&projectiles);
             if let Some(action) = player.borrow_mut().on_controller_tick(
                 sim.get_collision_tree(),
                 dt,
@@ -380,10 +975,514 @@ impl<M: GameMediator> Game<M> {
         self.update_bodies_from_actions(actions);
     }
 
+    /// Advances the time scale by the real time `dt` and slows the effects
+    /// and sounds of the game to match it
+    ///
+    /// Returns the game time that passed during `dt`
+    fn update_time_scale(
+        &self,
+        dt: std::time::Duration,
+    ) -> std::time::Duration {
+        let game_dt = self.time_scale.borrow_mut().update(dt);
+        self.apply_time_scale();
+        game_dt
+    }
+
+    fn apply_time_scale(&self) {
+        let scale = self.time_scale.borrow().scale();
+        clock::set_scale(scale);
+        if let Some(audio) = self.audio.borrow_mut().as_mut() {
+            audio.set_speed(scale as f32);
+        }
+    }
+
+    /// Smoothly changes how fast the game runs relative to real time to
+    /// `target` over the real time `over`. The UI is unaffected
+    pub fn set_time_scale(&self, target: f64, over: std::time::Duration) {
+        self.time_scale.borrow_mut().set_time_scale(target, over);
+        self.apply_time_scale();
+    }
+
+    /// Slows the game down while player 1 is on the tactical map, and
+    /// speeds it back up to real time once `slowed` is `false`. Networked
+    /// games aren't slowed down, since every client must run at the same
+    /// rate
+    pub fn slow_for_tactical_map(&self, slowed: bool) {
+        if self.tactical_slowed.replace(slowed) != slowed
+            && self.mediator.borrow().net_stats().is_none()
+        {
+            let target = if slowed { TACTICAL_TIME_SCALE } else { 1. };
+            self.set_time_scale(target, TACTICAL_RAMP);
+        }
+    }
+
+    /// Briefly slows the game down when player 1 dies. Networked games
+    /// aren't slowed down, since every client must run at the same rate
+    fn slow_down_on_death(&self) {
+        let p1 = self.player_1().borrow().get_rigid_body().metadata;
+        let died = self
+            .destroyed
+            .borrow()
+            .iter()
+            .any(|event| (event.typ, event.id) == p1);
+        if died && self.mediator.borrow().net_stats().is_none() {
+            self.time_scale
+                .borrow_mut()
+                .slow_motion(DEATH_TIME_SCALE, DEATH_SLOW_MOTION);
+        }
+    }
+
+    /// Gets the teleport claims of the live characters and asteroids
+    /// entering wormholes
+    fn wormhole_claims(&self, wormholes: &mut Wormholes) -> Vec<TeleportClaim> {
+        let mut claims = Vec::new();
+        for player in &self.characters {
+            let player = player.borrow();
+            if !player.is_dead() {
+                let body = player.get_rigid_body();
+                claims.extend(wormholes.check(
+                    body.metadata.1,
+                    body.base.center(),
+                    body.base.velocity,
+                ));
+            }
+        }
+        self.mediator.borrow().iter_bodies(|bodies| {
+            for body in bodies
+                .filter(|body| body.metadata.0 == object::ObjectType::Asteroid)
+            {
+                claims.extend(wormholes.check(
+                    body.metadata.1,
+                    body.base.center(),
+                    body.base.velocity,
+                ));
+            }
+        });
+        claims
+    }
+
+    /// Claims teleports for the bodies entering wormholes, and moves the
+    /// bodies whose teleports were confirmed
+    fn update_wormholes(&self, dt: std::time::Duration) {
+        let mut wormholes = self.wormholes.borrow_mut();
+        wormholes.update(dt);
+        let claims = self.wormhole_claims(&mut wormholes);
+        let confirmed = {
+            let mut mediator = self.mediator.borrow_mut();
+            for claim in claims {
+                mediator.claim_teleport(claim);
+            }
+            mediator.take_confirmed_teleports()
+        };
+        for claim in confirmed {
+            if let Some(idx) = self.characters.iter().position(|player| {
+                player.borrow().get_rigid_body().metadata.1 == claim.body
+            }) {
+                let mut player = self.characters[idx].borrow_mut();
+                wormholes
+                    .teleport(&claim, &mut player.get_rigid_body_mut().base);
+                player.snap_camera();
+                if idx == 0 {
+                    // the line and beam can't follow the ship through the
+                    // wormhole
+                    self.release_grapple();
+                    if let Some(beam) = self.tractor.take() {
+                        beam.release();
+                    }
+                }
+            } else {
+                self.mediator.borrow_mut().update_bodies(|bodies| {
+                    for body in bodies.filter(|b| b.metadata.1 == claim.body) {
+                        wormholes.teleport(&claim, &mut body.base);
+                    }
+                });
+            }
+        }
+    }
+
+    /// Docks player 1 with the station once it flies slowly down the
+    /// station's approach corridor, and opens the station's menu once it
+    /// reaches the berth. A ship destroyed while docking is returned to
+    /// flight
+    fn update_docking(&self, dt: std::time::Duration) {
+        let player = self.player_1();
+        let mut player = player.borrow_mut();
+        if player.is_dead() {
+            let mut docking = self.docking.borrow_mut();
+            if !docking.is_flying() {
+                docking.abort();
+                // the game is over if it was player 1's last life
+                if self.lives.get() > 0 {
+                    self.state.borrow_mut().request(GameState::Playing);
+                }
+            }
+            return;
+        }
+        let event = self
+            .docking
+            .borrow_mut()
+            .update(dt, &mut player.get_rigid_body_mut().base);
+        match event {
+            Some(DockingEvent::Started) => {
+                player.on_docking();
+                // the line and beam can't follow the ship into the berth
+                self.release_grapple();
+                if let Some(beam) = self.tractor.take() {
+                    beam.release();
+                }
+            }
+            Some(DockingEvent::Docked) => {
+                self.state.borrow_mut().request(GameState::Docked);
+            }
+            _ => (),
+        }
+    }
+
+    /// Gets the route player 1 flies down the station's approach corridor
+    /// to dock, or `None` if it can't dock
+    pub fn docking_approach(&self) -> Option<Vec<Point3<f64>>> {
+        self.docking.borrow().approach_route()
+    }
+
+    /// Provides `service` to player 1 if it's docked with the station
+    pub fn service_player_1(&self, service: Service) {
+        if !self.docking.borrow().is_docked() {
+            return;
+        }
+        let player = self.player_1();
+        let mut player = player.borrow_mut();
+        match service {
+            Service::Repair => player.repair(),
+            Service::Rearm => player.rearm(),
+        }
+    }
+
+    /// Undocks player 1 from the station and returns it to flight
+    pub fn undock(&self) {
+        if self.docking.borrow_mut().undock() {
+            self.state.borrow_mut().request(GameState::Playing);
+        }
+    }
+
+    /// Removes the asteroids destroyed during the frame, splitting them into
+    /// fragments, breaks the ships destroyed during the frame into debris,
+    /// and plays the destruction effects of every object destroyed during the
+    /// frame
+    fn remove_destroyed(&self) {
+        use object::ObjectType::Asteroid;
+        let destroyed = self.destroyed.borrow();
+        let asteroids: Vec<_> = destroyed
+            .iter()
+            .filter(|event| event.typ == Asteroid)
+            .map(|event| event.id)
+            .collect();
+        let mut mediator = self.mediator.borrow_mut();
+        // transform, radius, mass, velocity, rotational velocity, and impact
+        // impulse of every destroyed asteroid
+        let mut bodies = Vec::new();
+        let mut radii = HashMap::new();
+        mediator.iter_bodies(|it| {
+            for body in it.filter(|b| asteroids.contains(&b.metadata.1)) {
+                let impulse = destroyed
+                    .iter()
+                    .find(|event| event.id == body.metadata.1)
+                    .map(|event| event.impulse);
+                let radius = body.base.extents().unwrap_or(0.);
+                radii.insert(body.metadata.1, radius);
+                bodies.push((
+                    body.base.transform.borrow().clone(),
+                    radius,
+                    body.base.mass,
+                    body.base.velocity,
+                    body.base.rot_vel,
+                    impulse.unwrap_or_else(Vector3::zero),
+                ));
+            }
+        });
+        mediator.remove_objects(Asteroid, &asteroids);
+        let mut fragmenter = self.fragmenter.borrow_mut();
+        for id in &asteroids {
+            fragmenter.remove(*id);
+        }
+        let mut rng = rand::thread_rng();
+        for (transform, radius, mass, vel, rot_vel, impulse) in bodies {
+            let count = fragmenter.fragment_count(radius, &mut rng);
+            for fragment in fragmentation::split(
+                radius, mass, vel, impulse, count, &mut rng,
+            ) {
+                let mut node = transform.clone();
+                node.set_u_scale(transform.local_scale().x * fragment.scale);
+                node.translate(fragment.offset);
+                if let Some(id) =
+                    mediator.add_asteroid(node, fragment.velocity, rot_vel)
+                {
+                    fragmenter.add(id);
+                }
+            }
+        }
+        let mut debris = self.debris.borrow_mut();
+        for event in destroyed
+            .iter()
+            .filter(|e| e.typ == object::ObjectType::Ship)
+        {
+            debris.scatter(event.pos, event.impulse, &mut *mediator, &mut rng);
+        }
+        self.explode(&destroyed, &radii, &mut *mediator);
+    }
+
+    /// Sets off the explosions of the `destroyed` objects. Ships and large
+    /// asteroids explode, while everything else just bursts into sparks
+    ///
+    /// `radii` - the radius of every destroyed asteroid
+    fn explode(
+        &self,
+        destroyed: &[DestructionEvent],
+        radii: &HashMap<shared_types::ObjectId, f64>,
+        mediator: &mut M,
+    ) {
+        use crate::graphics_engine;
+        if destroyed.is_empty() {
+            return;
+        }
+        let ctx = graphics_engine::get_active_ctx();
+        let facade = ctx.ctx.borrow();
+        for event in destroyed {
+            let blast = match event.typ {
+                object::ObjectType::Ship => Some(explosion::SHIP_BLAST),
+                object::ObjectType::Asteroid => radii
+                    .get(&event.id)
+                    .and_then(|radius| explosion::asteroid_blast(*radius)),
+                _ => None,
+            };
+            if let Some(blast) = blast {
+                let push =
+                    explosion::explode(event.pos, &blast, mediator, &*facade);
+                self.new_forces.borrow_mut().push(Box::new(push));
+            } else {
+                mediator.add_particle_emitter(explosion_emitter(event.pos), 1);
+            }
+        }
+    }
+
+    /// Advances the mission, ending the game if the mission failed
+    fn update_mission(&self, dt: std::time::Duration) {
+        let destroyed: Vec<_> = self
+            .destroyed
+            .borrow()
+            .iter()
+            .map(|event| (event.id, event.typ))
+            .collect();
+        let player_pos =
+            self.characters[0].borrow().get_rigid_body().base.center();
+        let mut mission = self.mission.borrow_mut();
+        mission.update(dt, player_pos, &destroyed);
+        if mission.status() == MissionStatus::Failed {
+            self.state.borrow_mut().request(GameState::GameOver);
+        }
+    }
+
+    /// Gets the lasers that are in flight
+    fn projectiles(&self) -> Vec<controls::Projectile> {
+        self.mediator
+            .borrow()
+            .get_lasers()
+            .bodies_slice()
+            .iter()
+            .filter(|body| body.metadata.0 == object::ObjectType::Laser)
+            .map(|body| controls::Projectile {
+                pos: body.base.transform.borrow().get_pos(),
+                velocity: body.base.velocity,
+            })
+            .collect()
+    }
+
     pub fn get_mediator(&self) -> std::cell::Ref<M> {
         self.mediator.borrow()
     }
 
+    /// Gets the state machine which controls whether the game is being
+    /// played
+    pub fn state_machine(&self) -> std::cell::RefMut<GameStateMachine> {
+        self.state.borrow_mut()
+    }
+
+    /// Gets the current state of the game
+    pub fn state(&self) -> GameState {
+        self.state.borrow().state()
+    }
+
+    /// Gets the objects destroyed during the last frame, in the order they
+    /// were destroyed
+    pub fn destroyed(&self) -> std::cell::Ref<[DestructionEvent]> {
+        std::cell::Ref::map(self.destroyed.borrow(), Vec::as_slice)
+    }
+
+    /// Gets the mission being played
+    pub fn mission(&self) -> std::cell::RefMut<Mission> {
+        self.mission.borrow_mut()
+    }
+
+    /// Gets the points and statistics of player 1 during the current session
+    pub fn score(&self) -> Rc<RefCell<Score>> {
+        self.score.clone()
+    }
+
+    /// Gets the times player 1 can die before the game is over
+    pub fn lives(&self) -> u32 {
+        self.lives.get()
+    }
+
+    /// Gives player 1 back all of its lives
+    pub fn reset_lives(&self) {
+        self.lives.set(PLAYER_LIVES);
+    }
+
+    /// Takes a snapshot of the session to save, which is played on the map
+    /// with the seed `map_seed`
+    pub fn save_game(&self, map_seed: Option<u64>) -> SaveGame {
+        SaveGame {
+            map_seed,
+            bodies: self.mediator.borrow().saved_objects(),
+            ships: self
+                .characters
+                .iter()
+                .map(|character| character.borrow().save_state())
+                .collect(),
+            lives: self.lives.get(),
+            score: self.score.borrow().save_state(),
+            mission: self.mission.borrow().save_state(),
+        }
+    }
+
+    /// Puts the session back in the state it was in when `save` was taken
+    ///
+    /// The saved bodies replace every body in the world, and the saved ships
+    /// are matched to the characters by id. Projectiles, pickups, debris and
+    /// anything holding on to a body, such as the grappling line, are
+    /// cleared. Damage to asteroids isn't saved, so they're restored at full
+    /// health
+    pub fn load_game(&self, save: &SaveGame) {
+        self.release_grapple();
+        if let Some(beam) = self.tractor.take() {
+            beam.release();
+        }
+        self.beam.borrow_mut().set_ends(None);
+        self.forces.borrow_mut().clear();
+        self.new_forces.borrow_mut().clear();
+        self.dead_lasers.borrow_mut().clear();
+        self.events.clear();
+        self.destroyed.borrow_mut().clear();
+        self.damage.take();
+        self.fragmenter.take();
+        self.debris.take();
+        self.pickups.borrow_mut().reset();
+        self.mediator.borrow_mut().restore_objects(&save.bodies);
+        for character in &self.characters {
+            let mut character = character.borrow_mut();
+            let id = character.get_rigid_body().metadata.1;
+            if let Some(state) =
+                save.ships.iter().find(|ship| ship.body.id == id)
+            {
+                character.load_state(state);
+            }
+        }
+        self.target.set(None);
+        self.lives.set(save.lives);
+        self.score.borrow_mut().load_state(&save.score);
+        self.mission.borrow_mut().load_state(&save.mission);
+        self.set_time_scale(1., std::time::Duration::default());
+    }
+
+    /// Gets the text showing the round-trip time, packet loss and bandwidth
+    /// of the connection to the server. Empty if the game isn't networked
+    pub fn net_hud_text(&self) -> String {
+        self.mediator
+            .borrow()
+            .net_stats()
+            .map_or_else(String::new, |stats| stats.to_string())
+    }
+
+    /// Gets the text showing the current wave, the enemies left and the time
+    /// before the next wave, or the credits and what the shop sells between
+    /// waves. Empty if the game mode doesn't have waves
+    pub fn wave_hud_text(&self) -> String {
+        let mut mediator = self.mediator.borrow_mut();
+        mediator
+            .waves()
+            .map_or_else(String::new, |waves| match waves.phase() {
+                WavePhase::Intermission(left) => tr!(
+                    "hud.wave_intermission",
+                    waves.wave() + 1,
+                    left.as_secs_f64().ceil() as u64,
+                    waves.credits(),
+                    ShopItem::Repair.cost(),
+                    ShopItem::ExtraLife.cost(),
+                    ShopItem::FireBoost.cost()
+                ),
+                WavePhase::Attack {
+                    remaining,
+                    time_left,
+                } => tr!(
+                    "hud.wave_attack",
+                    waves.wave(),
+                    remaining,
+                    time_left.as_secs_f64().ceil() as u64
+                ),
+            })
+    }
+
+    /// Takes the wave events that happened since the last call
+    pub fn take_wave_events(&self) -> Vec<WaveEvent> {
+        std::mem::take(&mut *self.wave_events.borrow_mut())
+    }
+
+    /// Sets the mission being played. By default, there is no mission
+    pub fn set_mission(&mut self, mission: Mission) {
+        self.mission = RefCell::new(mission);
+    }
+
+    /// Gets the location of the waypoint or protected object of the
+    /// current objective
+    pub fn objective_marker(&self) -> Option<Point3<f64>> {
+        let mission = self.mission.borrow();
+        mission.waypoint().or_else(|| {
+            let target = mission.protected()?;
+            let mut pos = None;
+            self.mediator.borrow().iter_bodies(|bodies| {
+                pos = bodies
+                    .find(|body| body.metadata.1 == target)
+                    .map(|body| body.base.center());
+            });
+            pos.or_else(|| {
+                self.characters.iter().find_map(|c| {
+                    let c = c.borrow();
+                    let body = c.get_rigid_body();
+                    (body.metadata.1 == target).then(|| body.base.center())
+                })
+            })
+        })
+    }
+
+    /// Sets the pickups spawned in the game. By default, there are no pickups
+    pub fn set_pickups(&mut self, pickups: Pickups) {
+        self.pickups = RefCell::new(pickups);
+    }
+
+    /// Sets the state machine which controls whether the game is being
+    /// played. By default, the game is always being played
+    pub fn set_state_machine(&mut self, state: GameStateMachine) {
+        self.state = RefCell::new(state);
+    }
+
+    /// Calls `handler` with every event of type `E` once the frame the event
+    /// happened in has been simulated
+    pub fn subscribe<E: Event, F: Fn(&Self, &E) + 'static>(
+        &mut self,
+        handler: F,
+    ) {
+        self.events.subscribe(handler);
+    }
+
     // #[inline]
     // pub fn get_entities(&self) -> Vec<Rc<RefCell<dyn AbstractEntity>>> {
     //     self.mediator.borrow().get_entities()
@@ -394,25 +1493,192 @@ impl<M: GameMediator> Game<M> {
         self.characters[0].clone()
     }
 
+    /// Gets the point lasers are fired from by player 1
+    fn muzzle_pos(&self) -> Point3<f64> {
+        self.characters[0].borrow().muzzle()
+    }
+
+    /// Gets the point `dist` in front of player 1 that its lasers travel
+    /// through
+    pub fn aim_point(&self, dist: f64) -> Point3<f64> {
+        self.muzzle_pos() + self.characters[0].borrow().forward() * dist
+    }
+
+    /// Adds an asteroid of the scale `scale` `dist` in front of player 1,
+    /// drifting along with it
+    ///
+    /// Returns the id of the asteroid, or `None` if there are no ids left
+    pub fn spawn_asteroid(
+        &self,
+        scale: f64,
+        dist: f64,
+    ) -> Option<shared_types::ObjectId> {
+        let transform = node::Node::default()
+            .pos(self.aim_point(dist))
+            .u_scale(scale);
+        let velocity = self.player_1().borrow().get_rigid_body().base.velocity;
+        self.mediator.borrow_mut().add_asteroid(
+            transform,
+            velocity,
+            Vector3::zero(),
+        )
+    }
+
+    /// Calls `func` with the body of every character other than player 1
+    /// that is in play
+    pub fn iter_opponents<F: FnMut(&RigidBody<object::ObjectData>)>(
+        &self,
+        mut func: F,
+    ) {
+        for c in self.characters.iter().skip(1) {
+            let c = c.borrow();
+            if !c.is_benched() {
+                func(c.get_rigid_body());
+            }
+        }
+    }
+
+    /// Gets the character player 1 is locked on to
+    pub fn locked_target(&self) -> Option<Rc<RefCell<Player>>> {
+        self.target
+            .get()
+            .and_then(|idx| self.characters.get(idx))
+            .cloned()
+    }
+
+    /// Locks player 1 on to the next character within the lock on range and
+    /// angle. Targets are cycled through in order of how close they are to
+    /// the direction player 1 is facing
+    fn cycle_target(&self, tree: &CollisionTree) {
+        let (pos, forward) = {
+            let p1 = self.characters[0].borrow();
+            (p1.get_rigid_body().base.center(), p1.forward())
+        };
+        let in_cone =
+            tree.objects_in_cone(pos, forward, LOCK_ANGLE.into(), LOCK_RANGE);
+        let mut candidates: Vec<_> = self
+            .characters
+            .iter()
+            .enumerate()
+            .skip(1)
+            .filter(|(_, c)| !c.borrow().is_dead())
+            .filter_map(|(idx, c)| {
+                let c = c.borrow();
+                let body = &c.get_rigid_body().base;
+                body.collider
+                    .as_ref()
+                    .filter(|collider| in_cone.contains(collider))
+                    .map(|_| {
+                        let to_target = body.center() - pos;
+                        (idx, to_target.dot(forward) / to_target.magnitude())
+                    })
+            })
+            .collect();
+        candidates.sort_by(|(_, a), (_, b)| {
+            b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal)
+        });
+        let candidates: Vec<_> =
+            candidates.into_iter().map(|(idx, _)| idx).collect();
+        self.target.set(next_target(self.target.get(), &candidates));
+    }
+
+    /// Cycles player 1's target if requested, drops the lock if the target
+    /// moved out of range, and updates player 1's camera, softly turning it
+    /// towards the target
+    fn update_target(&self, tree: &CollisionTree, dt: std::time::Duration) {
+        let p1 = self.player_1();
+        if p1.borrow().take_cycle_target() {
+            self.cycle_target(tree);
+        }
+        let target_pos = self
+            .locked_target()
+            .filter(|target| !target.borrow().is_dead())
+            .map(|target| target.borrow().get_rigid_body().base.center());
+        let target_pos = target_pos.filter(|target| {
+            target.distance(p1.borrow().get_rigid_body().base.center())
+                <= LOCK_RANGE
+        });
+        if target_pos.is_none() {
+            self.target.set(None);
+        }
+        let mut p1 = p1.borrow_mut();
+        p1.set_look_target(target_pos);
+        p1.update_camera(tree, dt);
+    }
+
+    /// Gets the point player 1 should aim at for its lasers to hit the
+    /// locked target, assuming the target keeps its current velocity
+    ///
+    /// Returns `None` if there is no locked target or the target is too fast
+    /// to be hit
+    pub fn target_lead(&self) -> Option<Point3<f64>> {
+        let target = self.locked_target()?;
+        let target = target.borrow();
+        let body = &target.get_rigid_body().base;
+        let origin = self.muzzle_pos();
+        intercept_time(origin, body.center(), body.velocity, LASER_SPEED)
+            .map(|t| body.center() + body.velocity * t)
+    }
+
     /// Gets all of the players' entity representations
     pub fn get_player_entities(&self) -> Vec<Rc<RefCell<dyn AbstractEntity>>> {
         self.characters
             .iter()
-            .map(|p| p.borrow().as_entity() as Rc<RefCell<dyn AbstractEntity>>)
+            .flat_map(|p| {
+                let p = p.borrow();
+                [
+                    p.as_entity() as Rc<RefCell<dyn AbstractEntity>>,
+                    p.engine_trail() as Rc<RefCell<dyn AbstractEntity>>,
+                    p.thruster_cones() as Rc<RefCell<dyn AbstractEntity>>,
+                ]
+            })
+            .chain(std::iter::once(
+                self.beam.clone() as Rc<RefCell<dyn AbstractEntity>>
+            ))
             .collect()
     }
 }
 
 impl<M: GameMediatorLightingAvailable> Game<M> {
     pub fn new(mediator: M, player: player::Player) -> Self {
+        let wormholes = Wormholes::new(mediator.wormholes().to_vec());
+        let mut station = None;
+        mediator.iter_bodies(|bodies| {
+            station = bodies
+                .find(|body| body.metadata.0 == object::ObjectType::Station)
+                .map(|body| {
+                    DockingPort::new(body.base.transform.borrow().clone())
+                });
+        });
         Self {
             mediator: RefCell::new(mediator),
             characters: vec![Rc::new(RefCell::new(player))],
             forces: RefCell::default(),
             dead_lasers: RefCell::new(Vec::new()),
             new_forces: RefCell::new(Vec::new()),
-            health_deltas: RefCell::new(HashMap::new()),
+            damage: RefCell::default(),
             player_1_base: Cell::default(),
+            audio: RefCell::new(None),
+            target: Cell::new(None),
+            state: RefCell::new(GameStateMachine::new(GameState::Playing)),
+            mission: RefCell::new(Mission::new(Vec::new())),
+            destroyed: RefCell::new(Vec::new()),
+            fragmenter: RefCell::default(),
+            debris: RefCell::default(),
+            time_scale: RefCell::default(),
+            tactical_slowed: Cell::new(false),
+            score: Rc::default(),
+            lives: Cell::new(PLAYER_LIVES),
+            pickups: RefCell::default(),
+            grapple: RefCell::new(None),
+            tractor: RefCell::new(None),
+            beam: Rc::default(),
+            reserves: Vec::new(),
+            wave_events: RefCell::new(Vec::new()),
+            wormholes: RefCell::new(wormholes),
+            docking: RefCell::new(Docking::new(station)),
+            lights: RefCell::new(LightManager::new(lights::MAX_LIGHTS)),
+            events: Self::system_events(),
         }
     }
 
@@ -420,6 +1686,27 @@ impl<M: GameMediatorLightingAvailable> Game<M> {
         self.characters.push(player);
     }
 
+    /// Adds a character of difficulty `difficulty` which is benched until a
+    /// wave with enemies of its difficulty arrives
+    pub fn add_reserve(
+        &mut self,
+        player: Rc<RefCell<player::Player>>,
+        difficulty: Difficulty,
+    ) {
+        player.borrow_mut().bench();
+        self.reserves.push((self.characters.len(), difficulty));
+        self.characters.push(player);
+    }
+
+    /// Sets the audio engine sound effects are played with
+    pub fn set_audio(&mut self, audio: audio::AudioEngine) {
+        self.audio = RefCell::new(Some(audio));
+    }
+
+    /// Takes the lighting information of the map out of the mediator
+    ///
+    /// Only the game's own systems are subscribed to the events of the
+    /// returned game, so other handlers must be subscribed afterwards
     pub fn get_lighting(
         self,
     ) -> (super::shader::PbrMaps, Vector3<f32>, Game<M::ReturnType>)
@@ -436,9 +1723,102 @@ impl<M: GameMediatorLightingAvailable> Game<M> {
                 forces: self.forces,
                 dead_lasers: self.dead_lasers,
                 new_forces: self.new_forces,
-                health_deltas: self.health_deltas,
+                damage: self.damage,
                 player_1_base: self.player_1_base,
+                audio: self.audio,
+                target: self.target,
+                state: self.state,
+                mission: self.mission,
+                destroyed: self.destroyed,
+                fragmenter: self.fragmenter,
+                debris: self.debris,
+                time_scale: self.time_scale,
+                tactical_slowed: self.tactical_slowed,
+                score: self.score,
+                lives: self.lives,
+                pickups: self.pickups,
+                grapple: self.grapple,
+                tractor: self.tractor,
+                beam: self.beam,
+                reserves: self.reserves,
+                wave_events: self.wave_events,
+                wormholes: self.wormholes,
+                docking: self.docking,
+                lights: self.lights,
+                events: Game::system_events(),
             },
         )
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn sounds_exist() {
+        for path in &[
+            LASER_FIRE_SOUND,
+            LASER_HIT_SOUND,
+            ASTEROID_HIT_SOUND,
+            MUSIC_TRACK,
+        ] {
+            assert!(
+                std::path::Path::new(path).is_file(),
+                "{} is missing",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn targets_cycle_in_order() {
+        let candidates = [3, 1, 2];
+        assert_eq!(next_target(None, &candidates), Some(3));
+        assert_eq!(next_target(Some(3), &candidates), Some(1));
+        assert_eq!(next_target(Some(2), &candidates), Some(3));
+        // a target that left the cone is replaced by the best candidate
+        assert_eq!(next_target(Some(5), &candidates), Some(3));
+        assert_eq!(next_target(Some(3), &[]), None);
+        assert_eq!(next_target(Some(3), &[3]), Some(3));
+    }
+
+    #[test]
+    fn intercept_stationary_target() {
+        let t = intercept_time(
+            point3(0., 0., 0.),
+            point3(0., 0., 100.),
+            vec3(0., 0., 0.),
+            50.,
+        )
+        .unwrap();
+        assert_approx_eq!(t, 2.);
+    }
+
+    #[test]
+    fn intercept_moving_target() {
+        let (origin, target, vel, speed) = (
+            point3(0., 0., 0.),
+            point3(0., 0., 100.),
+            vec3(30., 0., 0.),
+            50.,
+        );
+        let t = intercept_time(origin, target, vel, speed).unwrap();
+        // the projectile and target meet at the lead point
+        let lead = target + vel * t;
+        assert_approx_eq!((lead - origin).magnitude(), speed * t);
+        assert_approx_eq!(t, 2.5);
+
+        // a target moving directly away faster than the projectile can't
+        // be hit
+        assert!(
+            intercept_time(origin, target, vec3(0., 0., 60.), speed).is_none()
+        );
+        // a target moving as fast as the projectile can be hit if it's
+        // approaching
+        let t =
+            intercept_time(origin, target, vec3(0., 0., -50.), speed).unwrap();
+        assert_approx_eq!(t, 1.);
+    }
+}
@@ -1,20 +1,27 @@
 use super::object;
 use super::object::*;
+use super::wormhole;
 use crate::cg_support::node::*;
-use crate::collisions;
 use crate::graphics_engine::entity::*;
 use crate::graphics_engine::{
-    cubes, entity, model, particles, primitives, scene, shader,
+    atmosphere, cubes, decals, model, particles, portal, primitives, scene,
+    shader, sky, volume,
 };
 use crate::physics::{self, RigidBody};
 use cgmath::*;
-use shared_types::{game_controller::*, id_list::IdList};
+use shared_types::wormhole::{TeleportClaim, Wormhole};
+use shared_types::{
+    game_controller::*, id_list::IdList, remote, survival, RemoteObject,
+};
 use std::cell::{Cell, Ref, RefCell};
 use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::rc::Rc;
+use tracing::warn;
 
-pub trait GameMediator {
+/// The game's event handlers are boxed with the game's type, so a mediator
+/// can't hold any borrows
+pub trait GameMediator: 'static {
     fn get_entities(&self) -> Vec<Rc<RefCell<dyn AbstractEntity>>>;
 
     /// Gets the lights in this map
@@ -46,22 +53,85 @@ pub trait GameMediator {
         emitter_id: usize,
     );
 
+    /// See `DecalSystem::add_decal`
+    fn add_decal(
+        &mut self,
+        target: &Rc<RefCell<Node>>,
+        pos: Point3<f64>,
+        normal: Vector3<f64>,
+    );
+
+    /// Adds a new laser or hook of type `typ` at `transform`
+    ///
+    /// Returns the id of the projectile, or `None` if there are no ids left
     fn add_laser(
         &mut self,
         transform: Node,
         vel: Vector3<f64>,
         typ: ObjectType,
-    );
+    ) -> Option<ObjectId>;
 
     fn remove_lasers(&mut self, ids: &[ObjectId]);
 
+    /// Adds a new pickup at `transform`
+    ///
+    /// Returns the id of the pickup, or `None` if there are no ids left
+    fn add_pickup(&mut self, transform: Node) -> Option<ObjectId>;
+
+    /// Adds a new asteroid at `transform`
+    ///
+    /// Returns the id of the asteroid, or `None` if there are no ids left
+    fn add_asteroid(
+        &mut self,
+        transform: Node,
+        vel: Vector3<f64>,
+        rot_vel: Vector3<f64>,
+    ) -> Option<ObjectId>;
+
+    /// Adds a new chunk of debris at `transform`
+    ///
+    /// Returns the id of the debris, or `None` if there are no ids left
+    fn add_debris(
+        &mut self,
+        transform: Node,
+        vel: Vector3<f64>,
+        rot_vel: Vector3<f64>,
+    ) -> Option<ObjectId>;
+
+    /// Removes the objects of type `typ` with the given ids
+    fn remove_objects(&mut self, typ: ObjectType, ids: &[ObjectId]);
+
+    /// Gets the bodies that are saved with the game, see `SAVED_TYPES`
+    fn saved_objects(&self) -> Vec<RemoteObject>;
+
+    /// Replaces every body that isn't a ship with `objs`, which are recreated
+    /// from the prototypes of their types with the ids they were saved with.
+    /// Bodies of types that aren't saved, such as lasers, are removed
+    fn restore_objects(&mut self, objs: &[RemoteObject]);
+
     fn sync(&mut self);
 
-    fn emit_particles(&self, dt: std::time::Duration);
+    /// Moves the particles and sorts them back to front from `view_pos`
+    fn emit_particles(&self, dt: std::time::Duration, view_pos: Point3<f32>);
 
     fn game_objects<'a>(
         &'a self,
     ) -> Box<dyn Iterator<Item = Rc<RefCell<GameObject>>> + 'a>;
+
+    /// See `GameController::get_waves`
+    fn waves(&mut self) -> Option<&mut survival::Waves>;
+
+    /// See `GameController::get_net_stats`
+    fn net_stats(&self) -> Option<remote::NetSummary>;
+
+    /// See `GameController::get_wormholes`
+    fn wormholes(&self) -> &[Wormhole];
+
+    /// See `GameController::claim_teleport`
+    fn claim_teleport(&mut self, claim: TeleportClaim);
+
+    /// See `GameController::take_confirmed_teleports`
+    fn take_confirmed_teleports(&mut self) -> Vec<TeleportClaim>;
 }
 
 pub trait GameMediatorLightingAvailable: GameMediator {
@@ -80,32 +150,50 @@ struct GameMediatorBase<State> {
     entity: HashMap<ObjectType, Rc<RefCell<dyn AbstractEntity>>>,
     lines: Rc<RefCell<primitives::Lines>>,
     particles: Rc<RefCell<particles::ParticleSystem>>,
+    decals: Rc<RefCell<decals::DecalSystem>>,
+    /// Faces of the wormholes' portals
+    portals: Rc<RefCell<Entity>>,
     ids: IdList,
     ibl_maps: Cell<Option<shader::PbrMaps>>,
     light_dir: Vector3<f32>,
     _state: std::marker::PhantomData<State>,
 }
 
+const ASTEROID_MODEL: &str = "assets/asteroid1/Asteroid.obj";
+const PLANET_MODEL: &str = "assets/planet/planet1.obj";
+const STATION_MODEL: &str = "assets/station/station.obj";
+/// The model of pickups and debris
+const CUBE_MODEL: &str = "assets/default_cube.obj";
+const LASER_MODEL: &str = "assets/laser2.obj";
+/// The models of every type of object
+pub const OBJECT_MODELS: [&str; 5] = [
+    ASTEROID_MODEL,
+    PLANET_MODEL,
+    STATION_MODEL,
+    CUBE_MODEL,
+    LASER_MODEL,
+];
+
 #[allow(clippy::too_many_lines)]
 fn init_objs<F: glium::backend::Facade, C: GameController>(
     _sm: &shader::ShaderManager,
     controller: &C,
     ctx: &F,
 ) -> HashMap<ObjectType, Rc<RefCell<GameObject>>> {
-    use collisions::TreeStopCriteria;
     let mut objs = HashMap::new();
     objs.insert(
         ObjectType::Asteroid,
         Rc::new(RefCell::new(
             object::GameObject::new(
-                model::Model::new("assets/asteroid1/Asteroid.obj", ctx)
-                    .with_instancing(),
+                model::Model::new(ASTEROID_MODEL, ctx)
+                    .with_instancing()
+                    .with_impostors(ctx),
                 object::ObjectType::Asteroid,
             )
             .with_depth()
             .with_collisions(
                 "assets/asteroid1/Asteroid.obj",
-                collisions::TreeStopCriteria::default(),
+                object::collision_lod(ObjectType::Asteroid).into(),
             )
             .density(2.71),
         )),
@@ -114,28 +202,78 @@ fn init_objs<F: glium::backend::Facade, C: GameController>(
         ObjectType::Planet,
         Rc::new(RefCell::new(
             object::GameObject::new(
-                model::Model::new("assets/planet/planet1.obj", ctx),
+                model::Model::new(PLANET_MODEL, ctx),
                 object::ObjectType::Planet,
             )
             .with_depth()
             .with_collisions(
                 "assets/planet/planet1.obj",
-                TreeStopCriteria::default(),
+                object::collision_lod(ObjectType::Planet).into(),
+            )
+            .immobile()
+            .density(10.),
+        )),
+    );
+    // the bay of the station is closed off in its collision hull, so the AI
+    // plans its paths around the station instead of into the bay
+    objs.insert(
+        ObjectType::Station,
+        Rc::new(RefCell::new(
+            object::GameObject::new(
+                model::Model::new(STATION_MODEL, ctx),
+                object::ObjectType::Station,
+            )
+            .with_depth()
+            .with_collisions(
+                "assets/station/station_hull.obj",
+                object::collision_lod(ObjectType::Station).into(),
             )
             .immobile()
             .density(10.),
         )),
     );
+    objs.insert(
+        ObjectType::Pickup,
+        Rc::new(RefCell::new(
+            object::GameObject::new(
+                model::Model::new(CUBE_MODEL, ctx).with_instancing(),
+                object::ObjectType::Pickup,
+            )
+            .with_depth()
+            .with_collisions(
+                "assets/default_cube.obj",
+                object::collision_lod(ObjectType::Pickup).into(),
+            )
+            .immobile(),
+        )),
+    );
+    // every chunk of debris is the cube stretched into the shape of a piece
+    // of a wreck
+    objs.insert(
+        ObjectType::Debris,
+        Rc::new(RefCell::new(
+            object::GameObject::new(
+                model::Model::new(CUBE_MODEL, ctx).with_instancing(),
+                object::ObjectType::Debris,
+            )
+            .with_depth()
+            .with_collisions(
+                "assets/default_cube.obj",
+                object::collision_lod(ObjectType::Debris).into(),
+            )
+            .density(0.3),
+        )),
+    );
     objs.insert(
         ObjectType::Laser,
         Rc::new(RefCell::new(
             object::GameObject::new(
-                model::Model::new("assets/laser2.obj", ctx).with_instancing(),
+                model::Model::new(LASER_MODEL, ctx).with_instancing(),
                 object::ObjectType::Laser,
             )
             .with_collisions(
                 "assets/laser2.obj",
-                collisions::TreeStopCriteria::default(),
+                object::collision_lod(ObjectType::Laser).into(),
             ),
         )),
     );
@@ -154,45 +292,89 @@ fn init_objs<F: glium::backend::Facade, C: GameController>(
     objs
 }
 
-fn init_entities<F: glium::backend::Facade, C: GameController>(
-    _sm: &shader::ShaderManager,
+/// Average radius of the surface of the planet model, in model space
+const PLANET_RADIUS: f32 = 18.5;
+/// Depth of the atmosphere of the planet model, in model space
+const ATMOSPHERE_DEPTH: f32 = 5.;
+
+/// Gets the transforms of all objects of type `typ` in the game
+fn locations_of<C: GameController>(
     controller: &C,
-    ctx: &F,
-) -> HashMap<ObjectType, Rc<RefCell<dyn AbstractEntity>>> {
-    let clouds: Vec<_> = controller
+    typ: ObjectType,
+) -> Vec<Node> {
+    controller
         .get_game_objects()
         .iter()
         .map(shared_types::node::from_remote_object)
-        .filter(|(_, _, _, typ, _)| *typ == ObjectType::Cloud)
+        .filter(|(_, _, _, obj_typ, _)| *obj_typ == typ)
         .map(|(transform, _, _, _, _)| transform)
-        .collect();
+        .collect()
+}
+
+fn init_entities<F: glium::backend::Facade, C: GameController>(
+    _sm: &shader::ShaderManager,
+    controller: &C,
+    ctx: &F,
+) -> HashMap<ObjectType, Rc<RefCell<dyn AbstractEntity>>> {
+    let clouds = locations_of(controller, ObjectType::Cloud);
+    // planets are immobile, so their atmospheres can be placed once
+    let planets = locations_of(controller, ObjectType::Planet);
     let mut entities: HashMap<_, Rc<RefCell<dyn AbstractEntity>>> =
         HashMap::new();
     entities.insert(
         ObjectType::Cloud,
         Rc::new(RefCell::new(
-            entity::EntityBuilder::new(cubes::Volumetric::cloud(128, ctx))
-                .with_pass(shader::RenderPassType::Visual)
-                .render_order(entity::RenderOrder::Last)
+            cubes::CloudVolume::new(volume::VolumeGenerator::nebula(128))
+                .spin(vec3(0.2, 1., 0.), 1.5)
                 .at_all(&clouds)
-                .build(),
+                .build(ctx),
+        )),
+    );
+    entities.insert(
+        ObjectType::Planet,
+        Rc::new(RefCell::new(
+            atmosphere::Atmosphere::new(PLANET_RADIUS, ATMOSPHERE_DEPTH, ctx)
+                .into_entity(&planets),
         )),
     );
     entities
 }
 
 /// Gets the skybox and ibl map
+///
+/// If the map's skybox or lighting images aren't installed, a procedural
+/// sky is generated instead, and its ibl is generated from it
 fn init_lighting<F: glium::backend::Facade>(
     sm: &shader::ShaderManager,
     ctx: &F,
     lighting: &GlobalLightingInfo,
 ) -> (Entity, shader::PbrMaps) {
-    let mut skybox =
-        cubes::Skybox::cvt_from_sphere(&lighting.skybox, 2048, sm, ctx);
-    let ibl = scene::gen_ibl_from_hdr(&lighting.hdr, &mut skybox, sm, ctx);
-    (skybox.into_entity(), ibl)
+    use std::path::Path;
+    if Path::new(&lighting.skybox).exists() && Path::new(&lighting.hdr).exists()
+    {
+        let mut skybox =
+            cubes::Skybox::cvt_from_sphere(&lighting.skybox, 2048, sm, ctx);
+        let ibl = scene::gen_ibl_from_hdr(&lighting.hdr, &mut skybox, sm, ctx);
+        (skybox.into_entity(), ibl)
+    } else {
+        warn!(
+            "Skybox '{}' not found, generating a procedural sky",
+            lighting.skybox
+        );
+        let mut skybox = sky::ProceduralSky::space(0).generate(sm, ctx);
+        let ibl = scene::gen_ibl_from_sky(&mut skybox, sm, ctx);
+        (skybox.into_entity(), ibl)
+    }
 }
 
+/// Types of the bodies that are saved with the game. Projectiles, pickups
+/// and debris are short lived, so they're left out of saves
+const SAVED_TYPES: [ObjectType; 3] = [
+    ObjectType::Asteroid,
+    ObjectType::Planet,
+    ObjectType::Station,
+];
+
 /// Converts a remote object into a rigid body
 #[allow(unused)]
 fn remote_obj_to_body(
@@ -220,7 +402,6 @@ fn remote_obj_to_body(
 }
 
 /// Converts a rigid body to a remote object
-#[allow(unused)]
 fn body_to_remote_obj(
     body: &RigidBody<ObjectData>,
 ) -> shared_types::RemoteObject {
@@ -243,8 +424,20 @@ impl<State> GameMediatorBase<State> {
         let particles = Rc::new(RefCell::new(
             particles::ParticleSystem::new()
                 .with_billboard("assets/particles/smoke_01.png", 0.4)
-                .with_billboard("assets/particles/circle_05.png", 0.4),
+                .with_billboard("assets/particles/circle_05.png", 0.4)
+                .with_billboard("assets/particles/fire_01.png", 0.4)
+                .with_billboard("assets/particles/circle_03.png", 0.4),
         ));
+        let decals = Rc::new(RefCell::new(
+            decals::DecalSystem::new("assets/particles/scorch_01.png", ctx)
+                .with_size(3.)
+                .with_lifetime(std::time::Duration::from_secs(30)),
+        ));
+        // portals never move, so they can be placed once
+        let portals =
+            Rc::new(RefCell::new(portal::Portal::new(ctx).into_entity(
+                &wormhole::portal_locations(controller.get_wormholes()),
+            )));
         let mut entity = init_entities(sm, controller, ctx);
         let (skybox, ibl_maps) =
             init_lighting(sm, ctx, controller.get_lighting_info());
@@ -254,6 +447,8 @@ impl<State> GameMediatorBase<State> {
             entity,
             lines,
             particles,
+            decals,
+            portals,
             ids: IdList::new(),
             ibl_maps: Cell::new(Some(ibl_maps)),
             light_dir: controller.get_lighting_info().dir_light,
@@ -302,6 +497,12 @@ impl<State> GameMediatorBase<State> {
             .chain(std::iter::once(
                 self.particles.clone() as Rc<RefCell<dyn AbstractEntity>>
             ))
+            .chain(std::iter::once(
+                self.decals.clone() as Rc<RefCell<dyn AbstractEntity>>
+            ))
+            .chain(std::iter::once(
+                self.portals.clone() as Rc<RefCell<dyn AbstractEntity>>
+            ))
             .collect()
     }
 
@@ -332,34 +533,133 @@ impl<State> GameMediatorBase<State> {
         transform: Node,
         vel: Vector3<f64>,
         typ: ObjectType,
-    ) {
-        if let Some(id) = self.ids.next() {
+    ) -> Option<ObjectId> {
+        let id = self.ids.next();
+        if let Some(id) = id {
             self.objs[&ObjectType::Laser]
                 .borrow_mut()
                 .new_instance(transform, Some(vel), id)
                 .metadata
                 .0 = typ;
         } else {
-            println!("No more IDs!");
+            warn!("No more IDs!");
         }
+        id
     }
 
     fn remove_lasers(&mut self, ids: &[ObjectId]) {
-        let bad_ptrs = self.objs[&ObjectType::Laser]
-            .borrow()
-            .bodies_slice()
+        self.remove_objects(ObjectType::Laser, ids);
+    }
+
+    /// Adds a new pickup to pickups
+    fn add_pickup(&mut self, transform: Node) -> Option<ObjectId> {
+        let id = self.ids.next();
+        if let Some(id) = id {
+            self.objs[&ObjectType::Pickup]
+                .borrow_mut()
+                .new_instance(transform, None, id);
+        } else {
+            warn!("No more IDs!");
+        }
+        id
+    }
+
+    /// Adds a new asteroid to asteroids
+    fn add_asteroid(
+        &mut self,
+        transform: Node,
+        vel: Vector3<f64>,
+        rot_vel: Vector3<f64>,
+    ) -> Option<ObjectId> {
+        let id = self.ids.next();
+        if let Some(id) = id {
+            self.objs[&ObjectType::Asteroid]
+                .borrow_mut()
+                .new_instance(transform, Some(vel), id)
+                .base
+                .rot_vel = rot_vel;
+        } else {
+            warn!("No more IDs!");
+        }
+        id
+    }
+
+    /// Adds a new chunk of debris to debris
+    fn add_debris(
+        &mut self,
+        transform: Node,
+        vel: Vector3<f64>,
+        rot_vel: Vector3<f64>,
+    ) -> Option<ObjectId> {
+        let id = self.ids.next();
+        if let Some(id) = id {
+            self.objs[&ObjectType::Debris]
+                .borrow_mut()
+                .new_instance(transform, Some(vel), id)
+                .base
+                .rot_vel = rot_vel;
+        } else {
+            warn!("No more IDs!");
+        }
+        id
+    }
+
+    fn remove_objects(&mut self, typ: ObjectType, ids: &[ObjectId]) {
+        if let Some(obj) = self.objs.get(&typ) {
+            let bad_ptrs = obj
+                .borrow()
+                .bodies_slice()
+                .iter()
+                .filter_map(|body| {
+                    if ids.contains(&body.metadata.1) {
+                        Some(body.base.transform.as_ptr() as *const ())
+                    } else {
+                        None
+                    }
+                })
+                .collect::<Vec<_>>();
+            obj.borrow_mut().retain(|ptr| !bad_ptrs.contains(&ptr));
+        }
+    }
+
+    fn saved_objects(&self) -> Vec<RemoteObject> {
+        SAVED_TYPES
             .iter()
-            .filter_map(|body| {
-                if ids.contains(&body.metadata.1) {
-                    Some(body.base.transform.as_ptr() as *const ())
-                } else {
-                    None
-                }
+            .filter_map(|typ| self.objs.get(typ))
+            .flat_map(|obj| {
+                obj.borrow()
+                    .bodies_slice()
+                    .iter()
+                    .map(body_to_remote_obj)
+                    .collect::<Vec<_>>()
             })
-            .collect::<Vec<_>>();
-        self.objs[&ObjectType::Laser]
-            .borrow_mut()
-            .retain(|ptr| !bad_ptrs.contains(&ptr));
+            .collect()
+    }
+
+    /// Replaces the bodies with `objs`
+    ///
+    /// Returns the newest id of the restored bodies, if any
+    fn restore_objects(&mut self, objs: &[RemoteObject]) -> Option<ObjectId> {
+        for obj in self.objs.values() {
+            obj.borrow_mut().retain(|_| false);
+        }
+        for (transform, vel, rot_vel, typ, id) in objs
+            .iter()
+            .map(shared_types::node::from_remote_object)
+            .filter(|(_, _, _, typ, _)| SAVED_TYPES.contains(typ))
+        {
+            self.objs[&typ]
+                .borrow_mut()
+                .new_instance(transform, Some(vel), id)
+                .base
+                .rot_vel = rot_vel;
+        }
+        let newest = objs
+            .iter()
+            .map(|obj| obj.id)
+            .max_by_key(|id| id.as_underlying_type())?;
+        self.ids.skip_through(newest);
+        Some(newest)
     }
 
     #[inline]
@@ -373,8 +673,10 @@ impl<State> GameMediatorBase<State> {
     }
 
     #[inline]
-    fn emit_particles(&self, dt: std::time::Duration) {
-        self.particles.borrow_mut().emit(dt);
+    fn emit_particles(&self, dt: std::time::Duration, view_pos: Point3<f32>) {
+        let mut particles = self.particles.borrow_mut();
+        particles.emit(dt);
+        particles.sort_particles(view_pos);
     }
 
     fn game_objects<'a>(
@@ -398,6 +700,8 @@ impl GameMediatorBase<HasLightingAvailable> {
                 entity: self.entity,
                 lines: self.lines,
                 particles: self.particles,
+                decals: self.decals,
+                portals: self.portals,
                 ids: self.ids,
                 ibl_maps: Cell::new(None),
                 light_dir: self.light_dir,
@@ -438,14 +742,9 @@ impl<State> LocalGameMediator<State> {
     }
 }
 
-impl<State> GameMediator for LocalGameMediator<State> {
+impl<State: 'static> GameMediator for LocalGameMediator<State> {
     fn sync(&mut self) {
-        if self.base.ids.remaining() < 64 {
-            self.controller.request_n_ids(1024);
-        }
-        if let Some(ids) = self.controller.get_requested_ids() {
-            self.base.ids.add_ids(ids);
-        }
+        self.controller.refill_ids(&mut self.base.ids);
     }
 
     fn get_entities(&self) -> Vec<Rc<RefCell<dyn AbstractEntity>>> {
@@ -480,9 +779,9 @@ impl<State> GameMediator for LocalGameMediator<State> {
         transform: Node,
         vel: Vector3<f64>,
         typ: ObjectType,
-    ) {
+    ) -> Option<ObjectId> {
         assert!(matches!(typ, ObjectType::Laser | ObjectType::Hook));
-        self.base.add_laser(transform, vel, typ);
+        self.base.add_laser(transform, vel, typ)
     }
 
     fn update_bodies<F>(&mut self, func: F)
@@ -503,10 +802,60 @@ impl<State> GameMediator for LocalGameMediator<State> {
             .new_emitter(emitter, emitter_id);
     }
 
+    fn add_decal(
+        &mut self,
+        target: &Rc<RefCell<Node>>,
+        pos: Point3<f64>,
+        normal: Vector3<f64>,
+    ) {
+        self.base.decals.borrow_mut().add_decal(target, pos, normal);
+    }
+
     fn remove_lasers(&mut self, ids: &[ObjectId]) {
         self.base.remove_lasers(ids);
     }
 
+    fn add_pickup(&mut self, transform: Node) -> Option<ObjectId> {
+        self.base.add_pickup(transform)
+    }
+
+    fn add_asteroid(
+        &mut self,
+        transform: Node,
+        vel: Vector3<f64>,
+        rot_vel: Vector3<f64>,
+    ) -> Option<ObjectId> {
+        self.base.add_asteroid(transform, vel, rot_vel)
+    }
+
+    fn add_debris(
+        &mut self,
+        transform: Node,
+        vel: Vector3<f64>,
+        rot_vel: Vector3<f64>,
+    ) -> Option<ObjectId> {
+        self.base.add_debris(transform, vel, rot_vel)
+    }
+
+    fn remove_objects(&mut self, typ: ObjectType, ids: &[ObjectId]) {
+        self.base.remove_objects(typ, ids);
+    }
+
+    fn saved_objects(&self) -> Vec<RemoteObject> {
+        self.base.saved_objects()
+    }
+
+    fn restore_objects(&mut self, objs: &[RemoteObject]) {
+        // ids requested before the save was loaded may already be used by
+        // the loaded bodies
+        while let Some(ids) = self.controller.get_requested_ids() {
+            self.base.ids.add_ids(ids);
+        }
+        if let Some(newest) = self.base.restore_objects(objs) {
+            self.controller.reserve_ids_through(newest);
+        }
+    }
+
     fn add_line(&mut self, line_id: u32, line: primitives::LineData) {
         self.base.add_line(line_id, line);
     }
@@ -515,8 +864,8 @@ impl<State> GameMediator for LocalGameMediator<State> {
         self.base.remove_line(line_id);
     }
 
-    fn emit_particles(&self, dt: std::time::Duration) {
-        self.base.emit_particles(dt);
+    fn emit_particles(&self, dt: std::time::Duration, view_pos: Point3<f32>) {
+        self.base.emit_particles(dt, view_pos);
     }
 
     fn game_objects<'a>(
@@ -524,6 +873,26 @@ impl<State> GameMediator for LocalGameMediator<State> {
     ) -> Box<dyn Iterator<Item = Rc<RefCell<GameObject>>> + 'a> {
         self.base.game_objects()
     }
+
+    fn waves(&mut self) -> Option<&mut survival::Waves> {
+        self.controller.get_waves()
+    }
+
+    fn net_stats(&self) -> Option<remote::NetSummary> {
+        self.controller.get_net_stats()
+    }
+
+    fn wormholes(&self) -> &[Wormhole] {
+        self.controller.get_wormholes()
+    }
+
+    fn claim_teleport(&mut self, claim: TeleportClaim) {
+        self.controller.claim_teleport(claim);
+    }
+
+    fn take_confirmed_teleports(&mut self) -> Vec<TeleportClaim> {
+        self.controller.take_confirmed_teleports()
+    }
 }
 
 impl GameMediatorLightingAvailable for LocalGameMediator<HasLightingAvailable> {
@@ -0,0 +1,270 @@
+use crate::graphics_engine::window::SceneManager;
+use std::collections::HashMap;
+
+/// A stage of the game's lifecycle
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum GameState {
+    MainMenu,
+    /// Choosing the ship player 1 flies in the next match
+    ShipSelect,
+    /// Waiting for the assets of a match to finish loading
+    Loading,
+    Playing,
+    Paused,
+    /// Changing the graphics and display settings from the pause menu
+    Settings,
+    /// Player 1 is docked with a station, which shows the station's menu
+    /// while the game carries on around it
+    Docked,
+    /// Player 1 ran out of lives or failed the mission
+    GameOver,
+}
+
+impl GameState {
+    /// `true` if the simulation and the players are updated in this state
+    pub const fn is_simulating(self) -> bool {
+        matches!(self, Self::Playing | Self::Docked)
+    }
+
+    /// `true` if the game can move from this state to `next`
+    pub const fn can_transition_to(self, next: Self) -> bool {
+        use GameState::*;
+        matches!(
+            (self, next),
+            (MainMenu, ShipSelect)
+                | (ShipSelect, Loading | MainMenu)
+                | (Loading, Playing)
+                | (Playing, Paused | GameOver | Docked)
+                | (Docked, Playing | GameOver)
+                | (Paused, Playing | MainMenu | Settings)
+                | (Settings, Paused)
+                | (GameOver, Loading | MainMenu)
+        )
+    }
+}
+
+/// The scenes shown while in a state
+#[derive(Copy, Clone)]
+struct StateScenes {
+    /// Name of the active scene
+    scene: &'static str,
+    /// Name of the scene pushed on top of `scene`, if any
+    overlay: Option<&'static str>,
+}
+
+/// A callback for entering or leaving a state. It's called with the state
+/// being left when entering a state, and the state being entered when
+/// leaving a state
+type StateHook = Box<dyn FnMut(GameState)>;
+
+/// Moves the game between its states, switching the displayed scenes and
+/// calling the enter and exit hooks of the states it moves between
+///
+/// Transitions are requested at any time, and take place the next time the
+/// state machine is updated so that they can be requested while the scene
+/// manager is borrowed
+pub struct GameStateMachine {
+    state: GameState,
+    pending: Option<GameState>,
+    scenes: HashMap<GameState, StateScenes>,
+    enter_hooks: HashMap<GameState, Vec<StateHook>>,
+    exit_hooks: HashMap<GameState, Vec<StateHook>>,
+}
+
+impl GameStateMachine {
+    /// Creates a state machine starting in `initial`, which doesn't switch
+    /// scenes or call any hooks
+    pub fn new(initial: GameState) -> Self {
+        Self {
+            state: initial,
+            pending: None,
+            scenes: HashMap::new(),
+            enter_hooks: HashMap::new(),
+            exit_hooks: HashMap::new(),
+        }
+    }
+
+    /// Shows the scene named `scene` while in `state`
+    ///
+    /// Requires `scene` is a name of a scene in the scene manager the state
+    /// machine is updated with
+    #[must_use]
+    pub fn with_scene(mut self, state: GameState, scene: &'static str) -> Self {
+        self.scenes.insert(
+            state,
+            StateScenes {
+                scene,
+                overlay: None,
+            },
+        );
+        self
+    }
+
+    /// Shows the scene named `overlay` on top of the scene named `scene`
+    /// while in `state`
+    ///
+    /// Requires both scenes are in the scene manager the state machine is
+    /// updated with
+    #[must_use]
+    pub fn with_overlay(
+        mut self,
+        state: GameState,
+        scene: &'static str,
+        overlay: &'static str,
+    ) -> Self {
+        self.scenes.insert(
+            state,
+            StateScenes {
+                scene,
+                overlay: Some(overlay),
+            },
+        );
+        self
+    }
+
+    /// Calls `hook` with the previous state whenever `state` is entered
+    ///
+    /// Hooks are called while the state machine is mutably borrowed
+    #[must_use]
+    pub fn on_enter<F: FnMut(GameState) + 'static>(
+        mut self,
+        state: GameState,
+        hook: F,
+    ) -> Self {
+        self.enter_hooks
+            .entry(state)
+            .or_default()
+            .push(Box::new(hook));
+        self
+    }
+
+    /// Calls `hook` with the next state whenever `state` is left
+    ///
+    /// Hooks are called while the state machine is mutably borrowed
+    #[must_use]
+    pub fn on_exit<F: FnMut(GameState) + 'static>(
+        mut self,
+        state: GameState,
+        hook: F,
+    ) -> Self {
+        self.exit_hooks
+            .entry(state)
+            .or_default()
+            .push(Box::new(hook));
+        self
+    }
+
+    /// Gets the current state
+    pub const fn state(&self) -> GameState {
+        self.state
+    }
+
+    /// `true` if the simulation should be stepped
+    pub const fn is_simulating(&self) -> bool {
+        self.state.is_simulating()
+    }
+
+    /// Requests a transition to `next`, which takes place the next time the
+    /// state machine is updated. Replaces any requested transition
+    ///
+    /// Returns `false` and does nothing if the current state can't move to
+    /// `next`
+    pub fn request(&mut self, next: GameState) -> bool {
+        if self.state.can_transition_to(next) {
+            self.pending = Some(next);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Shows the scenes of the current state
+    pub fn show(&self, scenes: &mut SceneManager) {
+        if let Some(state_scenes) = self.scenes.get(&self.state) {
+            scenes.change_scene(state_scenes.scene);
+            if let Some(overlay) = state_scenes.overlay {
+                scenes.push_scene(overlay);
+            }
+        }
+    }
+
+    /// Performs the requested transition, if any, by calling the exit hooks
+    /// of the current state, showing the scenes of the next state, and then
+    /// calling the enter hooks of the next state
+    ///
+    /// Should be called once per frame
+    pub fn update(&mut self, scenes: &mut SceneManager) {
+        if self.advance().is_some() {
+            self.show(scenes);
+        }
+    }
+
+    /// Performs the requested transition without switching scenes
+    ///
+    /// Returns the state that was left, or `None` if no transition was
+    /// requested
+    fn advance(&mut self) -> Option<GameState> {
+        let next = self.pending.take()?;
+        let prev = self.state;
+        for hook in self.exit_hooks.get_mut(&prev).into_iter().flatten() {
+            hook(next);
+        }
+        self.state = next;
+        for hook in self.enter_hooks.get_mut(&next).into_iter().flatten() {
+            hook(prev);
+        }
+        Some(prev)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn only_simulates_while_playing_or_docked() {
+        use GameState::*;
+        for state in [MainMenu, ShipSelect, Loading, Paused, Settings, GameOver]
+        {
+            assert!(!state.is_simulating());
+        }
+        assert!(Playing.is_simulating());
+        assert!(Docked.is_simulating());
+        assert!(!Docked.can_transition_to(Paused));
+        assert!(Playing.can_transition_to(Paused));
+        assert!(Paused.can_transition_to(Settings));
+        assert!(!Settings.can_transition_to(Playing));
+        assert!(!Playing.can_transition_to(Playing));
+        assert!(!MainMenu.can_transition_to(Playing));
+        assert!(!MainMenu.can_transition_to(Loading));
+        assert!(ShipSelect.can_transition_to(Loading));
+    }
+
+    #[test]
+    fn transitions_wait_for_update_and_call_hooks() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let (enter_log, exit_log) = (log.clone(), log.clone());
+        let mut states = GameStateMachine::new(GameState::Playing)
+            .on_exit(GameState::Playing, move |next| {
+                exit_log.borrow_mut().push(("exit", next));
+            })
+            .on_enter(GameState::Paused, move |prev| {
+                enter_log.borrow_mut().push(("enter", prev));
+            });
+        assert!(!states.request(GameState::Loading));
+        assert!(states.request(GameState::Paused));
+        assert_eq!(states.state(), GameState::Playing);
+        states.update(&mut SceneManager::new());
+        assert_eq!(states.state(), GameState::Paused);
+        assert!(!states.is_simulating());
+        assert_eq!(
+            *log.borrow(),
+            vec![("exit", GameState::Paused), ("enter", GameState::Playing)]
+        );
+        // nothing happens without a request
+        states.update(&mut SceneManager::new());
+        assert_eq!(log.borrow().len(), 2);
+    }
+}
@@ -0,0 +1,269 @@
+use crate::settings::GraphicsSettings;
+use std::fmt::Display;
+use std::time::Duration;
+
+/// Frames whose times are averaged before the tier can change
+const WINDOW: u32 = 60;
+/// Frames ignored after the tier changes, while the render targets are
+/// rebuilt
+const SETTLE_FRAMES: u32 = 10;
+/// Fraction of the budget an average frame can take before the quality is
+/// lowered
+const OVER_BUDGET: f64 = 1.1;
+/// Fraction of the budget an average frame must take less than for the
+/// quality to be raised
+const HEADROOM: f64 = 0.7;
+/// Windows in a row with headroom needed to raise the quality, so a brief
+/// lull doesn't raise it just to lower it again
+const RAISE_WINDOWS: u32 = 3;
+/// Smallest size shadow maps are scaled down to
+const MIN_SHADOW_MAP_SIZE: u32 = 256;
+
+/// How far the quality is lowered from the graphics settings to hold the
+/// target frame rate
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum QualityTier {
+    Full,
+    Reduced,
+    Low,
+    Minimal,
+}
+
+impl QualityTier {
+    const fn lower(self) -> Self {
+        match self {
+            Self::Full => Self::Reduced,
+            Self::Reduced => Self::Low,
+            Self::Low | Self::Minimal => Self::Minimal,
+        }
+    }
+
+    const fn raise(self) -> Self {
+        match self {
+            Self::Full | Self::Reduced => Self::Full,
+            Self::Low => Self::Reduced,
+            Self::Minimal => Self::Low,
+        }
+    }
+
+    /// Scales the costly settings of `settings` down to this tier
+    pub fn apply(self, settings: &GraphicsSettings) -> GraphicsSettings {
+        // particle density, frames between translucency renders, and the
+        // divisor of the shadow map size and bloom iterations
+        let (density, refresh, divisor) = match self {
+            Self::Full => (1., 1, 1),
+            Self::Reduced => (0.5, 2, 1),
+            Self::Low => (0.25, 4, 2),
+            Self::Minimal => (0.1, 8, 4),
+        };
+        GraphicsSettings {
+            particle_density: settings.particle_density * density,
+            translucency_refresh: settings.translucency_refresh * refresh,
+            shadow_map_size: settings.shadow_map_size.min(
+                (settings.shadow_map_size / divisor).max(MIN_SHADOW_MAP_SIZE),
+            ),
+            // a convolution is split into two iterations
+            bloom_iterations: settings
+                .bloom_iterations
+                .min((settings.bloom_iterations / divisor as usize).max(2)),
+            ..settings.clone()
+        }
+    }
+}
+
+impl Display for QualityTier {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Full => write!(f, "full"),
+            Self::Reduced => write!(f, "reduced"),
+            Self::Low => write!(f, "low"),
+            Self::Minimal => write!(f, "minimal"),
+        }
+    }
+}
+
+/// Lowers the quality when recent frames take longer than the frame time
+/// budget, and raises it again once there's headroom
+///
+/// The tier only changes after a full window of frames, and is raised only
+/// after several windows in a row with headroom, so it doesn't flip back
+/// and forth when the frame time is near the budget
+pub struct FrameGovernor {
+    /// Time a frame should take, or `None` if the quality is never changed
+    budget: Option<Duration>,
+    tier: QualityTier,
+    window_time: Duration,
+    window_frames: u32,
+    /// Frames left to ignore
+    settle: u32,
+    /// Windows in a row with headroom
+    headroom_windows: u32,
+    /// Average frame time of the last window
+    last_average: Option<Duration>,
+    changed: bool,
+}
+
+impl FrameGovernor {
+    /// Creates a governor which holds `target_fps`, or never changes the
+    /// quality if `target_fps` is `0`
+    pub fn new(target_fps: u32) -> Self {
+        Self {
+            budget: if target_fps == 0 {
+                None
+            } else {
+                Some(Duration::from_secs(1) / target_fps)
+            },
+            tier: QualityTier::Full,
+            window_time: Duration::default(),
+            window_frames: 0,
+            settle: 0,
+            headroom_windows: 0,
+            last_average: None,
+            changed: false,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub const fn tier(&self) -> QualityTier {
+        self.tier
+    }
+
+    /// Records that a frame took `frame_time`, which changes the tier if it
+    /// completes a window that was over budget or had headroom
+    pub fn record(&mut self, frame_time: Duration) {
+        let budget = match self.budget {
+            Some(budget) => budget,
+            None => return,
+        };
+        if self.settle > 0 {
+            self.settle -= 1;
+            return;
+        }
+        self.window_time += frame_time;
+        self.window_frames += 1;
+        if self.window_frames < WINDOW {
+            return;
+        }
+        let average = self.window_time / self.window_frames;
+        self.window_time = Duration::default();
+        self.window_frames = 0;
+        self.last_average = Some(average);
+        let load = average.as_secs_f64() / budget.as_secs_f64();
+        self.headroom_windows = if load < HEADROOM {
+            self.headroom_windows + 1
+        } else {
+            0
+        };
+        let tier = if load > OVER_BUDGET {
+            self.tier.lower()
+        } else if self.headroom_windows >= RAISE_WINDOWS {
+            self.headroom_windows = 0;
+            self.tier.raise()
+        } else {
+            self.tier
+        };
+        if tier != self.tier {
+            self.tier = tier;
+            self.changed = true;
+            self.settle = SETTLE_FRAMES;
+        }
+    }
+
+    /// Returns `true` if the tier changed since this was last called
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::take(&mut self.changed)
+    }
+
+    /// Gets `settings` scaled down to the current tier
+    pub fn apply(&self, settings: &GraphicsSettings) -> GraphicsSettings {
+        self.tier.apply(settings)
+    }
+
+    /// Gets the text of the quality indicator, which is empty if the
+    /// quality is never changed
+    pub fn hud_text(&self) -> String {
+        match (self.budget, self.last_average) {
+            (None, _) => String::new(),
+            (Some(_), None) => {
+                tr!("hud.quality", tr!(&format!("tier.{}", self.tier)))
+            }
+            (Some(_), Some(average)) => tr!(
+                "hud.quality_fps",
+                tr!(&format!("tier.{}", self.tier)),
+                format!("{:.0}", 1. / average.as_secs_f64())
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::settings::Quality;
+    use assert_approx_eq::assert_approx_eq;
+
+    /// Records a window of frames which each take `frame_time`
+    fn window(governor: &mut FrameGovernor, frame_time: Duration) {
+        for _ in 0..WINDOW {
+            governor.record(frame_time);
+        }
+    }
+
+    #[test]
+    fn governor_lowers_and_raises_quality() {
+        let (slow, ok, fast) = (
+            Duration::from_millis(25),
+            Duration::from_millis(15),
+            Duration::from_millis(5),
+        );
+        let mut governor = FrameGovernor::new(60);
+        window(&mut governor, slow);
+        assert_eq!(governor.tier(), QualityTier::Reduced);
+        assert!(governor.take_changed());
+        assert!(!governor.take_changed());
+        // the frames after a change are ignored
+        for _ in 0..SETTLE_FRAMES {
+            governor.record(Duration::from_secs(1));
+        }
+        window(&mut governor, slow);
+        assert_eq!(governor.tier(), QualityTier::Low);
+        for _ in 0..SETTLE_FRAMES {
+            governor.record(ok);
+        }
+        // headroom has to last several windows in a row
+        window(&mut governor, fast);
+        window(&mut governor, fast);
+        window(&mut governor, ok);
+        window(&mut governor, fast);
+        window(&mut governor, fast);
+        assert_eq!(governor.tier(), QualityTier::Low);
+        window(&mut governor, fast);
+        assert_eq!(governor.tier(), QualityTier::Reduced);
+        assert!(governor.hud_text().starts_with("Quality: reduced"));
+
+        let mut disabled = FrameGovernor::new(0);
+        window(&mut disabled, Duration::from_secs(1));
+        assert_eq!(disabled.tier(), QualityTier::Full);
+        assert!(disabled.hud_text().is_empty());
+    }
+
+    #[test]
+    fn tiers_scale_costly_settings() {
+        let settings = GraphicsSettings::preset(Quality::High, 800, 600);
+        assert_eq!(QualityTier::Full.apply(&settings), settings);
+        let low = QualityTier::Low.apply(&settings);
+        assert_eq!(low.shadow_map_size, 1024);
+        assert_eq!(low.bloom_iterations, 5);
+        assert_eq!(low.translucency_refresh, 4);
+        assert_approx_eq!(low.particle_density, 0.25);
+        assert_eq!(low.msaa_samples, settings.msaa_samples);
+        let small = GraphicsSettings {
+            shadow_map_size: 128,
+            bloom_iterations: 2,
+            ..settings
+        };
+        let minimal = QualityTier::Minimal.apply(&small);
+        assert_eq!(minimal.shadow_map_size, 128);
+        assert_eq!(minimal.bloom_iterations, 2);
+    }
+}
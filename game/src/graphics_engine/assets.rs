@@ -0,0 +1,391 @@
+use super::model::{Model, ModelSource};
+use super::sdf_font;
+use super::text::{Font, FontDesc};
+use super::textures::DecodedImage;
+use glium::texture::{RawImage2d, SrgbTexture2d};
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::rc::Rc;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use tracing::error;
+
+/// Most decoded bytes uploaded to the GPU each frame
+const UPLOAD_BUDGET: usize = 8 * 1024 * 1024;
+
+/// The loading state of an asset
+pub enum AssetState<T> {
+    Loading,
+    Loaded(Rc<T>),
+    Failed(String),
+}
+
+/// A shared handle to an asset which may still be loading
+///
+/// Entities hold onto the handle and poll it each frame. Until the asset is
+/// loaded, the handle returns its placeholder, if it has one
+pub struct AssetHandle<T> {
+    state: Rc<RefCell<AssetState<T>>>,
+    placeholder: Option<Rc<T>>,
+}
+
+impl<T> Clone for AssetHandle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+            placeholder: self.placeholder.clone(),
+        }
+    }
+}
+
+impl<T> AssetHandle<T> {
+    fn new(placeholder: Option<Rc<T>>) -> Self {
+        Self {
+            state: Rc::new(RefCell::new(AssetState::Loading)),
+            placeholder,
+        }
+    }
+
+    /// Creates a handle to an asset which is already loaded
+    #[allow(dead_code)]
+    pub fn loaded(asset: T) -> Self {
+        Self {
+            state: Rc::new(RefCell::new(AssetState::Loaded(Rc::new(asset)))),
+            placeholder: None,
+        }
+    }
+
+    /// Gets the loaded asset, or the placeholder if the asset is still loading
+    /// or failed to load
+    pub fn get(&self) -> Option<Rc<T>> {
+        match &*self.state.borrow() {
+            AssetState::Loaded(asset) => Some(asset.clone()),
+            _ => self.placeholder.clone(),
+        }
+    }
+
+    /// `true` if the asset has finished loading successfully
+    pub fn is_loaded(&self) -> bool {
+        matches!(&*self.state.borrow(), AssetState::Loaded(_))
+    }
+
+    /// Gets the reason the asset failed to load, or `None` if it has not failed
+    pub fn error(&self) -> Option<String> {
+        match &*self.state.borrow() {
+            AssetState::Failed(err) => Some(err.clone()),
+            _ => None,
+        }
+    }
+
+    fn finish(&self, result: Result<T, String>) {
+        *self.state.borrow_mut() = match result {
+            Ok(asset) => AssetState::Loaded(Rc::new(asset)),
+            Err(err) => {
+                error!("Failed to load asset: {}", err);
+                AssetState::Failed(err)
+            }
+        };
+    }
+}
+
+/// The kind of work a worker thread performs for a job
+enum JobKind {
+    Image,
+    /// A font and the paths of its fallbacks
+    Font(Vec<String>),
+    Model,
+}
+
+struct Job {
+    id: u32,
+    path: String,
+    kind: JobKind,
+}
+
+/// Asset data which has been read and decoded on a worker thread and is
+/// ready to be uploaded on the main thread
+enum Decoded {
+    Image(DecodedImage),
+    /// A font followed by its fallbacks
+    Font(Vec<(FontDesc, DecodedImage)>),
+    Model(Box<ModelSource>),
+}
+
+impl Decoded {
+    /// Gets the amount of bytes that will be uploaded to the GPU
+    fn upload_size(&self) -> usize {
+        match self {
+            Self::Image(image) => image.data.len(),
+            Self::Font(fonts) => {
+                fonts.iter().map(|(_, sdf)| sdf.data.len()).sum()
+            }
+            Self::Model(model) => model.upload_size(),
+        }
+    }
+}
+
+type JobResult = (u32, Result<Decoded, String>);
+
+/// Where the result of a job is stored once it is uploaded
+enum UploadTarget {
+    SrgbTexture(AssetHandle<SrgbTexture2d>),
+    Font(AssetHandle<Font>),
+    /// The model cache, under the path of the model
+    Model(String),
+}
+
+/// Reads the font at `path`. Atlases of font outlines are generated if they
+/// aren't cached
+fn decode_font(path: &str) -> Result<(FontDesc, DecodedImage), String> {
+    let path = if sdf_font::is_outline_font(path) {
+        sdf_font::cached_atlas(path)?
+    } else {
+        path.to_owned()
+    };
+    let data = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Could not open font file '{}': {}", path, e))?;
+    let desc = FontDesc::parse(&data, &path);
+    let sdf = DecodedImage::load(&desc.tex_path)?;
+    Ok((desc, sdf))
+}
+
+/// Performs a job on a worker thread
+fn decode(job: &Job) -> Result<Decoded, String> {
+    match &job.kind {
+        JobKind::Image => DecodedImage::load(&job.path).map(Decoded::Image),
+        JobKind::Font(fallbacks) => std::iter::once(&job.path)
+            .chain(fallbacks)
+            .map(|path| decode_font(path))
+            .collect::<Result<_, _>>()
+            .map(Decoded::Font),
+        JobKind::Model => ModelSource::load(&job.path)
+            .map(|model| Decoded::Model(model.into())),
+    }
+}
+
+/// Runs jobs until the job queue is closed
+fn worker_loop(
+    jobs: &Arc<Mutex<mpsc::Receiver<Job>>>,
+    results: &mpsc::Sender<JobResult>,
+) {
+    loop {
+        let job = match jobs.lock().unwrap().recv() {
+            Ok(job) => job,
+            Err(_) => return,
+        };
+        let result = catch_unwind(AssertUnwindSafe(|| decode(&job)))
+            .unwrap_or_else(|_| Err(format!("Panic loading '{}'", job.path)));
+        if results.send((job.id, result)).is_err() {
+            return;
+        }
+    }
+}
+
+/// Loads assets without blocking the main thread
+///
+/// File IO, decoding and the parsing of models is performed on a pool of
+/// worker threads. Since the GL context can only be used from the main
+/// thread, decoded assets are uploaded in `process_uploads`, which uploads at
+/// most `UPLOAD_BUDGET` bytes each call so that loading is spread out
+/// over multiple frames.
+pub struct AssetManager {
+    jobs: Option<mpsc::Sender<Job>>,
+    results: mpsc::Receiver<JobResult>,
+    workers: Vec<thread::JoinHandle<()>>,
+    targets: HashMap<u32, UploadTarget>,
+    ready: VecDeque<JobResult>,
+    next_id: u32,
+    placeholder_srgb: Rc<SrgbTexture2d>,
+}
+
+impl AssetManager {
+    /// Creates a new asset manager with `worker_count` worker threads
+    pub fn new<F: glium::backend::Facade>(
+        worker_count: usize,
+        facade: &F,
+    ) -> Self {
+        let (job_sender, job_receiver) = mpsc::channel::<Job>();
+        let (result_sender, result_receiver) = mpsc::channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let workers = (0..worker_count.max(1))
+            .map(|i| {
+                let jobs = job_receiver.clone();
+                let results = result_sender.clone();
+                thread::Builder::new()
+                    .name(format!("asset-worker-{}", i))
+                    .spawn(move || worker_loop(&jobs, &results))
+                    .expect("Could not spawn asset worker")
+            })
+            .collect();
+        // magenta makes missing textures easy to spot
+        let placeholder_pixel =
+            || RawImage2d::from_raw_rgba(vec![255u8, 0, 255, 255], (1, 1));
+        Self {
+            jobs: Some(job_sender),
+            results: result_receiver,
+            workers,
+            targets: HashMap::new(),
+            ready: VecDeque::new(),
+            next_id: 0,
+            placeholder_srgb: Rc::new(
+                SrgbTexture2d::new(facade, placeholder_pixel()).unwrap(),
+            ),
+        }
+    }
+
+    fn submit(&mut self, path: &str, kind: JobKind, target: UploadTarget) {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.targets.insert(id, target);
+        self.jobs
+            .as_ref()
+            .unwrap()
+            .send(Job {
+                id,
+                path: path.to_owned(),
+                kind,
+            })
+            .expect("Asset workers stopped");
+    }
+
+    /// Loads an `sRGB` texture as 8bit RGBA with mipmaps. The image is
+    /// reversed on load. The handle holds a placeholder texture until the
+    /// texture is loaded
+    pub fn load_texture_srgb(
+        &mut self,
+        path: &str,
+    ) -> AssetHandle<SrgbTexture2d> {
+        let handle = AssetHandle::new(Some(self.placeholder_srgb.clone()));
+        self.submit(
+            path,
+            JobKind::Image,
+            UploadTarget::SrgbTexture(handle.clone()),
+        );
+        handle
+    }
+
+    /// Loads a font from its `fnt` file, or from a `ttf` or `otf` font whose
+    /// signed distance field atlas is generated if it isn't cached. Glyphs
+    /// the font is missing are drawn with the fonts at `fallbacks`, which
+    /// are loaded the same way. Fonts have no placeholder
+    pub fn load_font<S: AsRef<str>>(
+        &mut self,
+        path: &str,
+        fallbacks: &[S],
+    ) -> AssetHandle<Font> {
+        let handle = AssetHandle::new(None);
+        let fallbacks = fallbacks
+            .iter()
+            .map(|path| path.as_ref().to_owned())
+            .collect();
+        self.submit(
+            path,
+            JobKind::Font(fallbacks),
+            UploadTarget::Font(handle.clone()),
+        );
+        handle
+    }
+
+    /// Loads the model at `path` into the model cache, so that creating
+    /// models of it with `Model::new` doesn't read the file. A model that
+    /// fails to load is loaded again by `Model::new`
+    pub fn preload_model(&mut self, path: &str) {
+        self.submit(path, JobKind::Model, UploadTarget::Model(path.to_owned()));
+    }
+
+    /// `true` if there are no assets still loading
+    pub fn is_idle(&self) -> bool {
+        self.targets.is_empty()
+    }
+
+    /// Uploads decoded assets to the GPU, stopping once the upload budget
+    /// has been used up. At least one asset is uploaded if one is ready
+    ///
+    /// Must be called from the main thread, once per frame
+    pub fn process_uploads<F: glium::backend::Facade>(&mut self, facade: &F) {
+        self.ready.extend(self.results.try_iter());
+        let mut uploaded = 0;
+        while uploaded < UPLOAD_BUDGET {
+            let (id, result) = match self.ready.pop_front() {
+                Some(res) => res,
+                None => break,
+            };
+            if let Ok(decoded) = &result {
+                uploaded += decoded.upload_size();
+            }
+            if let Some(target) = self.targets.remove(&id) {
+                Self::upload(target, result, facade);
+            }
+        }
+    }
+
+    /// Waits for every asset that's loading, uploading each as soon as it's
+    /// decoded. The workers decode the assets at the same time, so this is
+    /// quicker than loading them one after another on the main thread
+    ///
+    /// Must be called from the main thread
+    pub fn finish_loading<F: glium::backend::Facade>(&mut self, facade: &F) {
+        while !self.targets.is_empty() {
+            let next = self
+                .ready
+                .pop_front()
+                .map_or_else(|| self.results.recv().ok(), Some);
+            let (id, result) = match next {
+                Some(res) => res,
+                // the workers have stopped
+                None => return,
+            };
+            if let Some(target) = self.targets.remove(&id) {
+                Self::upload(target, result, facade);
+            }
+        }
+    }
+
+    /// Uploads a decoded asset and stores it in `target`
+    fn upload<F: glium::backend::Facade>(
+        target: UploadTarget,
+        result: Result<Decoded, String>,
+        facade: &F,
+    ) {
+        match (target, result) {
+            (UploadTarget::SrgbTexture(handle), Ok(Decoded::Image(image))) => {
+                handle.finish(image.upload_srgb(facade));
+            }
+            (UploadTarget::Font(handle), Ok(Decoded::Font(fonts))) => {
+                let mut fonts = fonts.into_iter().map(|(desc, sdf)| {
+                    sdf.upload_2d(facade).map(|sdf| Font::from_desc(desc, sdf))
+                });
+                let font = fonts.next().unwrap().and_then(|font| {
+                    fonts.try_fold(font, |font, fallback| {
+                        Ok(font.with_fallback(Rc::new(fallback?)))
+                    })
+                });
+                handle.finish(font);
+            }
+            (UploadTarget::Model(path), Ok(Decoded::Model(model))) => {
+                if let Err(e) = Model::cache_source(&path, *model, facade) {
+                    error!("Failed to load asset: {}", e);
+                }
+            }
+            (UploadTarget::SrgbTexture(handle), Err(e)) => {
+                handle.finish(Err(e));
+            }
+            (UploadTarget::Font(handle), Err(e)) => handle.finish(Err(e)),
+            (UploadTarget::Model(_), Err(e)) => {
+                error!("Failed to load asset: {}", e);
+            }
+            _ => unreachable!("Decoded asset does not match its target"),
+        }
+    }
+}
+
+impl Drop for AssetManager {
+    fn drop(&mut self) {
+        // closing the job queue stops the workers
+        self.jobs.take();
+        for worker in self.workers.drain(..) {
+            worker.join().ok();
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use super::cubes::{CUBE_INDICES, CUBE_VERTS};
+use super::drawable::*;
+use super::shader;
+
+/// The atmosphere of a planet, which scatters the light of the scene's
+/// directional light
+///
+/// Single Rayleigh and Mie scattering is ray marched through a shell around
+/// the planet, stopping at the scene's depth, so the atmosphere is composited
+/// over the planet and anything inside of it. The scattering is scaled to the
+/// depth of the atmosphere, so atmospheres look alike no matter their size.
+/// Requires the camera's depth to be in the pipeline cache
+pub struct Atmosphere {
+    vbo: glium::VertexBuffer<VertexPos>,
+    ebo: glium::IndexBuffer<u16>,
+    planet_radius: f32,
+    depth: f32,
+    density: f32,
+    sun_intensity: f32,
+}
+
+impl Atmosphere {
+    /// `planet_radius` - the radius of the surface of the planet, in the
+    /// model space of the planet
+    ///
+    /// `depth` - the distance from the surface of the planet to the top of
+    /// the atmosphere, in the model space of the planet
+    pub fn new<F: glium::backend::Facade>(
+        planet_radius: f32,
+        depth: f32,
+        facade: &F,
+    ) -> Self {
+        Self {
+            vbo: glium::VertexBuffer::new(facade, &CUBE_VERTS).unwrap(),
+            ebo: glium::IndexBuffer::new(
+                facade,
+                glium::index::PrimitiveType::TrianglesList,
+                &CUBE_INDICES,
+            )
+            .unwrap(),
+            planet_radius,
+            depth,
+            density: 1.,
+            sun_intensity: 20.,
+        }
+    }
+
+    /// Scales how much light the atmosphere scatters and absorbs. `1` is
+    /// about as much as earth's atmosphere
+    #[must_use]
+    #[allow(dead_code)]
+    pub const fn density(mut self, density: f32) -> Self {
+        self.density = density;
+        self
+    }
+
+    /// Sets the brightness of the light scattered by the atmosphere
+    #[must_use]
+    #[allow(dead_code)]
+    pub const fn sun_intensity(mut self, intensity: f32) -> Self {
+        self.sun_intensity = intensity;
+        self
+    }
+
+    /// Creates the entity of this atmosphere around every planet at
+    /// `locations`
+    pub fn into_entity(
+        self,
+        locations: &[crate::cg_support::node::Node],
+    ) -> super::entity::Entity {
+        use super::entity;
+        entity::EntityBuilder::new(self)
+            .with_pass(shader::RenderPassType::Visual)
+            .render_order(entity::RenderOrder::Last)
+            .at_all(locations)
+            .build()
+    }
+}
+
+impl Drawable for Atmosphere {
+    fn render_args<'a>(
+        &'a mut self,
+        positions: &[[[f32; 4]; 4]],
+    ) -> Vec<(
+        shader::UniformInfo,
+        VertexHolder<'a>,
+        glium::index::IndicesSource<'a>,
+    )> {
+        let mut out = Vec::new();
+        for model in positions {
+            let arg = shader::UniformInfo::Atmosphere(shader::AtmosphereData {
+                model: *model,
+                planet_radius: self.planet_radius,
+                atmosphere_radius: self.planet_radius + self.depth,
+                density: self.density,
+                sun_intensity: self.sun_intensity,
+            });
+            out.push((
+                arg,
+                VertexHolder::new(VertexSourceData::Single(From::from(
+                    &self.vbo,
+                ))),
+                From::from(&self.ebo),
+            ));
+        }
+        out
+    }
+
+    fn transparency(&self) -> Option<f32> {
+        None
+    }
+}
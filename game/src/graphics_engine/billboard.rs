@@ -3,7 +3,7 @@ use super::shader;
 use glium::*;
 use VertexSimple as Vertex;
 
-const RECT_VERTS: [Vertex; 4] = [
+pub(super) const RECT_VERTS: [Vertex; 4] = [
     Vertex {
         pos: [1., 1., 0.],
         tex_coords: [1., 1.],
@@ -22,7 +22,7 @@ const RECT_VERTS: [Vertex; 4] = [
     },
 ];
 
-const RECT_INDICES: [u32; 6] = [0, 1, 3, 3, 1, 2];
+pub(super) const RECT_INDICES: [u32; 6] = [0, 1, 3, 3, 1, 2];
 
 /// A textured 2D quad in 3D that provides billboarding support
 ///
@@ -67,8 +67,11 @@ impl Drawable for Rect3D {
         VertexHolder<'a>,
         glium::index::IndicesSource<'a>,
     )> {
-        let info =
-            shader::UniformInfo::Billboard(&self.tex, self.spherical_density);
+        let info = shader::UniformInfo::Billboard(
+            &self.tex,
+            self.spherical_density,
+            shader::BlendMode::Alpha,
+        );
         vec![(
             info,
             VertexHolder::new(VertexSourceData::Single(From::from(
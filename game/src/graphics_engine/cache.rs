@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// Default time an asset can go unused before it is evicted
+pub const DEFAULT_MAX_IDLE: Duration = Duration::from_secs(30);
+
+struct CacheEntry<T> {
+    asset: Rc<T>,
+    /// The last time the asset was known to be in use
+    last_used: Instant,
+}
+
+/// A cache of assets keyed by their path, which shares one copy of each asset
+/// between all of its users
+///
+/// Users are tracked by the reference count of the shared asset. Once an
+/// asset has no users for longer than the maximum idle time, it is evicted
+/// by `evict_unused`
+pub struct AssetCache<T> {
+    entries: HashMap<String, CacheEntry<T>>,
+    max_idle: Duration,
+}
+
+impl<T> AssetCache<T> {
+    pub fn new() -> Self {
+        Self {
+            entries: HashMap::new(),
+            max_idle: DEFAULT_MAX_IDLE,
+        }
+    }
+
+    /// Sets the time an asset can go unused before it is evicted
+    pub fn set_max_idle(&mut self, max_idle: Duration) {
+        self.max_idle = max_idle;
+    }
+
+    /// Gets the asset stored at `path`, calling `load` to load it if it is
+    /// not in the cache
+    pub fn get_or_load<L: FnOnce() -> T>(
+        &mut self,
+        path: &str,
+        load: L,
+    ) -> Rc<T> {
+        let entry =
+            self.entries
+                .entry(path.to_owned())
+                .or_insert_with(|| CacheEntry {
+                    asset: Rc::new(load()),
+                    last_used: Instant::now(),
+                });
+        entry.last_used = Instant::now();
+        entry.asset.clone()
+    }
+
+    /// `true` if the asset at `path` is in the cache
+    pub fn contains(&self, path: &str) -> bool {
+        self.entries.contains_key(path)
+    }
+
+    /// Gets the amount of users of the asset at `path`, or `None` if the
+    /// asset is not in the cache
+    #[allow(dead_code)]
+    pub fn ref_count(&self, path: &str) -> Option<usize> {
+        self.entries
+            .get(path)
+            .map(|entry| Rc::strong_count(&entry.asset) - 1)
+    }
+
+    /// Gets the amount of assets in the cache
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// `true` if there are no assets in the cache
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Removes every asset which has not had any users for at least the
+    /// maximum idle time
+    ///
+    /// `now` - the current time
+    ///
+    /// Returns the amount of evicted assets
+    pub fn evict_unused(&mut self, now: Instant) -> usize {
+        let max_idle = self.max_idle;
+        let start_len = self.entries.len();
+        self.entries.retain(|_, entry| {
+            if Rc::strong_count(&entry.asset) > 1 {
+                entry.last_used = now;
+                true
+            } else {
+                now.saturating_duration_since(entry.last_used) < max_idle
+            }
+        });
+        start_len - self.entries.len()
+    }
+}
+
+impl<T> Default for AssetCache<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cache_shares_assets() {
+        let mut cache = AssetCache::new();
+        let mut loads = 0;
+        let a = cache.get_or_load("a", || {
+            loads += 1;
+            5
+        });
+        let b = cache.get_or_load("a", || {
+            loads += 1;
+            6
+        });
+        assert_eq!(loads, 1);
+        assert!(Rc::ptr_eq(&a, &b));
+        assert_eq!(cache.ref_count("a"), Some(2));
+        drop(b);
+        assert_eq!(cache.ref_count("a"), Some(1));
+        assert_eq!(cache.ref_count("b"), None);
+        assert!(cache.contains("a") && !cache.contains("b"));
+    }
+
+    #[test]
+    fn cache_evicts_idle_assets() {
+        let mut cache = AssetCache::new();
+        cache.set_max_idle(Duration::from_secs(10));
+        let start = Instant::now();
+        let used = cache.get_or_load("used", || 0);
+        cache.get_or_load("unused", || 1);
+        assert_eq!(cache.evict_unused(start + Duration::from_secs(5)), 0);
+        assert_eq!(cache.evict_unused(start + Duration::from_secs(11)), 1);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.ref_count("used"), Some(1));
+
+        drop(used);
+        let last_used = start + Duration::from_secs(11);
+        assert_eq!(cache.evict_unused(last_used + Duration::from_secs(9)), 0);
+        assert_eq!(cache.evict_unused(last_used + Duration::from_secs(10)), 1);
+        assert!(cache.is_empty());
+    }
+}
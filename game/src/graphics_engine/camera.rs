@@ -1,5 +1,6 @@
 #![allow(clippy::module_name_repetitions)]
 use super::drawable;
+use crate::collisions::{CollisionObject, CollisionTree};
 use cgmath::*;
 use drawable::Viewer;
 
@@ -26,84 +27,6 @@ impl PerspectiveCamera {
             up: vec3(0., 1., 0.),
         }
     }
-
-    /// Gets a viewer for a cascade that spans the perspective frustum from `near` to `far` along the view space z axis
-    ///
-    /// `light_dir` - direction of light/angle to view the cascade at
-    ///
-    /// `map_size` - shadow map size for texel snapping
-    pub fn get_cascade(
-        &self,
-        light_dir: Vector3<f32>,
-        near: f32,
-        far: f32,
-        map_size: u32,
-    ) -> StaticCamera {
-        let mut f = self.clone();
-        f.near = near;
-        f.far = far;
-
-        let (frustum, mut center) = get_frustum_world(&f);
-        let mut radius = f32::MIN;
-        for pt in &frustum {
-            radius = radius.max((pt - center).magnitude());
-        }
-
-        let texels_per_unit = map_size as f32 / (radius * 2.0);
-        let lookat = Matrix4::look_at_rh(
-            point3(light_dir.x, light_dir.y, light_dir.z),
-            point3(0., 0., 0.),
-            vec3(0., 1., 0.),
-        ) * Matrix4::from_scale(texels_per_unit);
-        let lookat_inv = lookat.invert().unwrap();
-        center = lookat.transform_point(center);
-        center.x = center.x.floor();
-        center.y = center.y.floor(); // move the center in texel-sized increments
-        center.z = center.z.floor();
-        center = lookat_inv.transform_point(center);
-
-        let view =
-            Matrix4::look_at_rh(center + light_dir, center, vec3(0., 1., 0.));
-        //right-handed system, positive z facing towards the camera (ortho expects positize z facing away)
-
-        let z_factor = 6f32; // expand in the z-direction to include objects that might cast a shadow into the map
-        StaticCamera {
-            view,
-            near: f.near,
-            far: f.far,
-            proj: ortho(
-                -radius,
-                radius,
-                -radius,
-                radius,
-                -radius * z_factor,
-                radius * z_factor,
-            ),
-            cam_pos: center + light_dir,
-        }
-    }
-
-    /// Gets the cameras for cascade splits of this frustum
-    ///
-    /// `splits` - a vector of `(far_plane, tex_square_size)` tuples for each cascade
-    /// Each subsequenct cascade has a near plane of the previous cascade's far plane
-    /// Requires `splits` to be ordered closest to farthest cascade
-    ///
-    /// Returns the cameras specified from the first split to the last one
-    #[allow(dead_code)]
-    pub fn get_cascades(
-        &self,
-        splits: Vec<(f32, u32)>,
-        light_dir: Vector3<f32>,
-    ) -> Vec<StaticCamera> {
-        let mut last_depth = self.near;
-        let mut cams = Vec::<_>::new();
-        for (split, map_size) in splits {
-            cams.push(self.get_cascade(light_dir, last_depth, split, map_size));
-            last_depth = split;
-        }
-        cams
-    }
 }
 
 impl Viewer for PerspectiveCamera {
@@ -148,7 +71,6 @@ pub struct OrthoCamera {
 }
 
 impl OrthoCamera {
-    #[allow(dead_code)]
     #[allow(clippy::too_many_arguments)]
     pub fn new(
         width: f32,
@@ -194,6 +116,22 @@ impl Viewer for OrthoCamera {
     fn view_dist(&self) -> (f32, f32) {
         (self.near, self.far)
     }
+
+    fn frustum_corners(&self) -> [Point3<f32>; 8] {
+        // the frustum is a box, so the far plane is the near plane pushed back
+        let face = |depth: f32| {
+            [
+                point3(self.left, self.top, -depth),
+                point3(self.right, self.top, -depth),
+                point3(self.right, self.btm, -depth),
+                point3(self.left, self.btm, -depth),
+            ]
+        };
+        let (near, far) = (face(self.near), face(self.far));
+        [
+            near[0], near[1], near[2], near[3], far[0], far[1], far[2], far[3],
+        ]
+    }
 }
 
 impl std::default::Default for OrthoCamera {
@@ -212,33 +150,232 @@ impl std::default::Default for OrthoCamera {
     }
 }
 
+/// A perspective camera whose frustum may be off center, such as the view
+/// of one eye of a stereo pair, or one tile of a capture split into tiles
+#[derive(Clone)]
+#[allow(dead_code)]
+pub struct FrustumCamera {
+    /// Edges of the near plane, in view space
+    pub left: f32,
+    pub right: f32,
+    pub btm: f32,
+    pub top: f32,
+    pub near: f32,
+    pub far: f32,
+    pub cam_pos: cgmath::Point3<f32>,
+    pub target: cgmath::Point3<f32>,
+    pub up: cgmath::Vector3<f32>,
+}
+
+impl Viewer for FrustumCamera {
+    fn proj_mat(&self) -> Matrix4<f32> {
+        frustum(
+            self.left, self.right, self.btm, self.top, self.near, self.far,
+        )
+    }
+
+    fn cam_pos(&self) -> Point3<f32> {
+        self.cam_pos
+    }
+
+    fn view_mat(&self) -> Matrix4<f32> {
+        Matrix4::look_at_rh(self.cam_pos, self.target, self.up)
+    }
+
+    fn view_dist(&self) -> (f32, f32) {
+        (self.near, self.far)
+    }
+
+    fn frustum_corners(&self) -> [Point3<f32>; 8] {
+        // the far plane is the near plane scaled away from the camera
+        let face = |depth: f32| {
+            let scale = depth / self.near;
+            [
+                point3(self.left * scale, self.top * scale, -depth),
+                point3(self.right * scale, self.top * scale, -depth),
+                point3(self.right * scale, self.btm * scale, -depth),
+                point3(self.left * scale, self.btm * scale, -depth),
+            ]
+        };
+        let (near, far) = (face(self.near), face(self.far));
+        [
+            near[0], near[1], near[2], near[3], far[0], far[1], far[2], far[3],
+        ]
+    }
+}
+
 /// Gets the world coordinates of a viewer's frustum, and the center point of that frustum
 /// Points are ordered top left, top right, bottom right, bottom left, near plane then far plane
+#[allow(dead_code)]
 pub fn get_frustum_world(
     viewer: &dyn Viewer,
 ) -> (Vec<Point3<f32>>, Point3<f32>) {
-    //v_ndc = M_proj * M_view * v_world
-    let cube = [
-        point3(-1f32, 1., -1.),
-        point3(1., 1., -1.),
-        point3(1., -1., -1.),
-        point3(-1., -1., -1.),
-        point3(-1., 1., 1.),
-        point3(1., 1., 1.),
-        point3(1., -1., 1.),
-        point3(-1., -1., 1.),
-    ];
-    let inv = (viewer.proj_mat() * viewer.view_mat()).invert().unwrap();
-    let mut out = Vec::<Point3<f32>>::new();
-    let mut center = vec3(0f32, 0., 0.);
-    for pt in cube {
-        let mut r = inv * vec4(pt.x, pt.y, pt.z, 1.0);
-        r /= r.w;
-        center += vec3(r.x, r.y, r.z);
-        out.push(point3(r.x, r.y, r.z));
-    }
-    center /= out.len() as f32;
-    (out, point3(center.x, center.y, center.z))
+    let to_world = viewer.view_mat().invert().unwrap();
+    let out: Vec<_> = viewer
+        .frustum_corners()
+        .iter()
+        .map(|pt| to_world.transform_point(*pt))
+        .collect();
+    let center = Point3::centroid(&out);
+    (out, center)
+}
+
+/// Gets the corners of the slice of `viewer`'s frustum between the view
+/// space depths `near` and `far`, in view space and in the order of
+/// `Viewer::frustum_corners`
+fn frustum_slice(viewer: &dyn Viewer, near: f32, far: f32) -> [Point3<f32>; 8] {
+    let corners = viewer.frustum_corners();
+    let (near_face, far_face) = corners.split_at(4);
+    let mut slice = corners;
+    for (i, (a, b)) in near_face.iter().zip(far_face).enumerate() {
+        // depth changes linearly along an edge of the frustum
+        let at = |depth: f32| a + (b - a) * ((depth + a.z) / (a.z - b.z));
+        slice[i] = at(near);
+        slice[i + 4] = at(far);
+    }
+    slice
+}
+
+/// Gets the bounding sphere of the slice of `viewer`'s frustum between the
+/// view space depths `near` and `far`, in world space
+///
+/// The sphere is found in view space, so it only depends on the shape of
+/// the frustum. Unlike the sphere around the centroid of the frustum's
+/// corners, its radius doesn't change as the viewer rotates
+fn bounding_sphere(
+    viewer: &dyn Viewer,
+    near: f32,
+    far: f32,
+) -> (Point3<f32>, f32) {
+    let corners = frustum_slice(viewer, near, far);
+    let near_center = Point3::centroid(&corners[..4]);
+    let far_center = Point3::centroid(&corners[4..]);
+    let axis2 = near_center.distance2(far_center);
+    // the point between the centers of the faces which is as far from the
+    // corners of the near face as from those of the far face, unless the
+    // frustum is so wide that the far face alone bounds it
+    let t = if axis2 > 0. {
+        ((axis2 + corners[4].distance2(far_center)
+            - corners[0].distance2(near_center))
+            / (2. * axis2))
+            .clamp(0., 1.)
+    } else {
+        0.5
+    };
+    let center = near_center + (far_center - near_center) * t;
+    let radius = corners
+        .iter()
+        .map(|corner| corner.distance(center))
+        .fold(0., f32::max);
+    let to_world = viewer.view_mat().invert().unwrap();
+    (to_world.transform_point(center), radius)
+}
+
+/// Gets a viewer for a cascade that spans `viewer`'s frustum from `near` to
+/// `far` along the view space z axis
+///
+/// `light_dir` - direction of light/angle to view the cascade at
+///
+/// `map_size` - shadow map size for texel snapping
+pub fn get_cascade(
+    viewer: &dyn Viewer,
+    light_dir: Vector3<f32>,
+    near: f32,
+    far: f32,
+    map_size: u32,
+) -> StaticCamera {
+    let (center, radius) = bounding_sphere(viewer, near, far);
+    let center =
+        snap_to_texels(center, light_dir, radius * 2. / map_size as f32);
+
+    let view =
+        Matrix4::look_at_rh(center + light_dir, center, vec3(0., 1., 0.));
+    //right-handed system, positive z facing towards the camera (ortho expects positize z facing away)
+
+    let z_factor = 6f32; // expand in the z-direction to include objects that might cast a shadow into the map
+    StaticCamera {
+        view,
+        near,
+        far,
+        proj: ortho(
+            -radius,
+            radius,
+            -radius,
+            radius,
+            -radius * z_factor,
+            radius * z_factor,
+        ),
+        cam_pos: center + light_dir,
+    }
+}
+
+/// Gets the cameras for cascade splits of `viewer`'s frustum
+///
+/// `splits` - a vector of `(far_plane, tex_square_size)` tuples for each cascade
+/// Each subsequenct cascade has a near plane of the previous cascade's far plane
+/// Requires `splits` to be ordered closest to farthest cascade
+///
+/// Returns the cameras specified from the first split to the last one
+#[allow(dead_code)]
+pub fn get_cascades(
+    viewer: &dyn Viewer,
+    splits: Vec<(f32, u32)>,
+    light_dir: Vector3<f32>,
+) -> Vec<StaticCamera> {
+    let mut last_depth = viewer.view_dist().0;
+    let mut cams = Vec::<_>::new();
+    for (split, map_size) in splits {
+        cams.push(get_cascade(viewer, light_dir, last_depth, split, map_size));
+        last_depth = split;
+    }
+    cams
+}
+
+/// Moves `center` to the closest corner of a shadow map texel
+///
+/// The texels of a shadow map centered on the snapped point stay over the
+/// same world positions as the camera moves, so shadow edges don't shimmer
+///
+/// `light_dir` - direction of the light the shadow map is rendered from
+///
+/// `texel_size` - width of a texel of the shadow map in world units
+fn snap_to_texels(
+    center: Point3<f32>,
+    light_dir: Vector3<f32>,
+    texel_size: f32,
+) -> Point3<f32> {
+    let light_view = Matrix4::look_at_rh(
+        point3(light_dir.x, light_dir.y, light_dir.z),
+        point3(0., 0., 0.),
+        vec3(0., 1., 0.),
+    );
+    let mut light_center = light_view.transform_point(center);
+    light_center.x = (light_center.x / texel_size).floor() * texel_size;
+    light_center.y = (light_center.y / texel_size).floor() * texel_size;
+    light_view.invert().unwrap().transform_point(light_center)
+}
+
+/// Gets the far planes of `count` cascades splitting the frustum between
+/// `near` and `far` with the practical split scheme, which blends
+/// logarithmic and uniform splits
+///
+/// `lambda` - weight of the logarithmic splits, from `0` for uniform splits
+/// to `1` for logarithmic splits. Logarithmic splits give every cascade the
+/// same texel density on screen, but make the closest cascades tiny
+pub fn practical_splits(
+    near: f32,
+    far: f32,
+    count: usize,
+    lambda: f32,
+) -> Vec<f32> {
+    (1..=count)
+        .map(|i| {
+            let t = i as f32 / count as f32;
+            let log = near * (far / near).powf(t);
+            let uniform = (far - near).mul_add(t, near);
+            lambda.mul_add(log, (1. - lambda) * uniform)
+        })
+        .collect()
 }
 
 /// A Camera that isn't easy to move as it just stores the prebuilt view and project matrices
@@ -287,6 +424,11 @@ impl Camera2D {
             proj: ortho(0f32, width as f32, height as f32, 0., 0., 1.),
         }
     }
+
+    /// Changes the size of the screen the camera projects onto
+    pub fn resize(&mut self, width: u32, height: u32) {
+        *self = Self::new(width, height);
+    }
 }
 
 impl Viewer for Camera2D {
@@ -303,3 +445,307 @@ impl Viewer for Camera2D {
         (0., 1.)
     }
 }
+
+/// A camera which follows a target from behind
+///
+/// The camera is pulled towards its resting position behind the target by a
+/// damped spring, so it lags behind sudden movements of the target. While
+/// free look is enabled, the camera can be orbited around the target, and
+/// it swings back behind the target once free look is released. The camera
+/// is pulled in towards the target whenever an object comes between them
+pub struct ChaseCamera {
+    /// Resting position of the camera in the target's local space
+    offset: Vector3<f64>,
+    stiffness: f64,
+    damping: f64,
+    /// Radius of the sphere kept clear around the camera
+    probe_radius: f64,
+    /// Radians the camera orbits per unit of free look input
+    orbit_sensitivity: f64,
+    /// Position of the spring, which ignores obstacles
+    spring_pos: Option<Point3<f64>>,
+    velocity: Vector3<f64>,
+    /// Position of the camera after being pulled in front of obstacles
+    pos: Point3<f64>,
+    up: Vector3<f64>,
+    yaw: Rad<f64>,
+    pitch: Rad<f64>,
+    free_look: bool,
+}
+
+impl ChaseCamera {
+    /// Creates a new chase camera which rests at `offset` from the target, in
+    /// the target's local space
+    pub fn new(offset: Vector3<f64>) -> Self {
+        Self {
+            offset,
+            stiffness: 60.,
+            damping: 14.,
+            probe_radius: 2.,
+            orbit_sensitivity: 0.005,
+            spring_pos: None,
+            velocity: vec3(0., 0., 0.),
+            pos: Point3::from_vec(offset),
+            up: vec3(0., 1., 0.),
+            yaw: Rad(0.),
+            pitch: Rad(0.),
+            free_look: false,
+        }
+    }
+
+    /// Sets the stiffness of the spring pulling the camera to its resting
+    /// position, and the damping which slows the camera down. A damping of
+    /// `2 * sqrt(stiffness)` stops the camera without overshooting
+    #[must_use]
+    #[allow(dead_code)]
+    pub const fn spring(mut self, stiffness: f64, damping: f64) -> Self {
+        self.stiffness = stiffness;
+        self.damping = damping;
+        self
+    }
+
+    /// Sets the radius of the sphere around the camera which is kept clear
+    /// of obstacles
+    #[must_use]
+    #[allow(dead_code)]
+    pub const fn probe_radius(mut self, radius: f64) -> Self {
+        self.probe_radius = radius;
+        self
+    }
+
+    /// Sets how many radians the camera orbits per unit of free look input
+    #[must_use]
+    #[allow(dead_code)]
+    pub const fn orbit_sensitivity(mut self, sensitivity: f64) -> Self {
+        self.orbit_sensitivity = sensitivity;
+        self
+    }
+
+    /// Orbits the camera around the target by `dx` horizontally and `dy`
+    /// vertically if `input` is `Some`. If `input` is `None`, free look is
+    /// disabled and the camera returns behind the target
+    pub fn free_look(&mut self, input: Option<(f64, f64)>) {
+        /// Keeps the camera from flipping over the top of the target
+        const MAX_PITCH: Rad<f64> = Rad(1.4);
+        self.free_look = input.is_some();
+        if let Some((dx, dy)) = input {
+            self.yaw -= Rad(dx * self.orbit_sensitivity);
+            self.pitch = Rad((self.pitch.0 + dy * self.orbit_sensitivity)
+                .clamp(-MAX_PITCH.0, MAX_PITCH.0));
+        }
+    }
+
+    /// Moves the camera towards its resting position behind the target
+    ///
+    /// `target` - the target's model matrix
+    ///
+    /// `obstacles` - objects the camera is kept out of
+    ///
+    /// `ignore` - objects that may come between the camera and the target,
+    /// such as the target itself
+    pub fn update(
+        &mut self,
+        target: &Matrix4<f64>,
+        dt: std::time::Duration,
+        obstacles: &CollisionTree,
+        ignore: &[&CollisionObject],
+    ) {
+        /// Longest time step the spring is simulated with, so that stiff
+        /// springs stay stable during long frames
+        const MAX_STEP: f64 = 1. / 120.;
+        /// How quickly the orbit returns behind the target, per second
+        const ORBIT_RETURN_RATE: f64 = 4.;
+        let dt = dt.as_secs_f64();
+        if !self.free_look {
+            let decay = (-ORBIT_RETURN_RATE * dt).exp();
+            self.yaw *= decay;
+            self.pitch *= decay;
+        }
+        let orbit =
+            Basis3::from_angle_y(self.yaw) * Basis3::from_angle_x(self.pitch);
+        let rest = target.transform_point(Point3::from_vec(
+            orbit.rotate_vector(self.offset),
+        ));
+        let center = target.transform_point(point3(0., 0., 0.));
+        self.up = target.transform_vector(vec3(0., 1., 0.)).normalize();
+
+        let mut spring_pos = self.spring_pos.unwrap_or(rest);
+        let steps = (dt / MAX_STEP).ceil().max(1.);
+        let step = dt / steps;
+        for _ in 0..steps as u32 {
+            let accel = (rest - spring_pos) * self.stiffness
+                - self.velocity * self.damping;
+            self.velocity += accel * step;
+            spring_pos += self.velocity * step;
+        }
+        self.spring_pos = Some(spring_pos);
+
+        self.pos = obstacles
+            .sphere_cast(center, spring_pos, self.probe_radius, ignore)
+            .map_or(spring_pos, |t| center + (spring_pos - center) * t);
+    }
+
+    /// Places the camera at its resting position on the next update instead
+    /// of springing towards it, such as after the target teleports
+    pub fn snap(&mut self) {
+        self.spring_pos = None;
+        self.velocity = vec3(0., 0., 0.);
+    }
+
+    /// Gets the position of the camera
+    pub const fn pos(&self) -> Point3<f64> {
+        self.pos
+    }
+
+    /// Gets the up direction of the camera
+    pub const fn up(&self) -> Vector3<f64> {
+        self.up
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cg_support::node::Node;
+    use serial_test::serial;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    #[serial]
+    #[test]
+    fn chase_camera_follows_and_avoids_obstacles() {
+        let mut tree = CollisionTree::new(point3(0., 0., 0.), 100.);
+        let mut cam = ChaseCamera::new(vec3(0., 0., -20.)).probe_radius(1.);
+        let target = Matrix4::from_translation(vec3(0., 0., 10.));
+        for _ in 0..120 {
+            cam.update(&target, Duration::from_millis(16), &tree, &[]);
+        }
+        assert!(cam.pos().distance(point3(0., 0., -10.)) < 0.1);
+
+        // the camera lags behind a moving target
+        let moved = Matrix4::from_translation(vec3(0., 0., 30.));
+        cam.update(&moved, Duration::from_millis(16), &tree, &[]);
+        assert!(cam.pos().z < 9.);
+        for _ in 0..240 {
+            cam.update(&moved, Duration::from_millis(16), &tree, &[]);
+        }
+        assert!(cam.pos().distance(point3(0., 0., 10.)) < 0.1);
+
+        // a wall between the target and the camera pulls the camera in
+        let wall = CollisionObject::new(
+            Rc::new(RefCell::new(
+                Node::default()
+                    .pos(point3(0., 0., 20.))
+                    .scale(vec3(5., 5., 1.)),
+            )),
+            "assets/default_cube.obj",
+            crate::collisions::TreeStopCriteria::default(),
+        );
+        tree.insert(&wall);
+        cam.update(&moved, Duration::from_millis(16), &tree, &[]);
+        assert!(cam.pos().z > 21.);
+        assert!(cam.pos().z < 30.);
+    }
+
+    #[test]
+    fn cascade_bounds_are_stable() {
+        let light_dir = vec3(-120., 120., 0.);
+        let mut cam = PerspectiveCamera::default(16. / 9.);
+        cam.far = 600.;
+        let (center, radius) = bounding_sphere(&cam, 40., 200.);
+        let mut slice = cam.clone();
+        slice.near = 40.;
+        slice.far = 200.;
+        for corner in get_frustum_world(&slice).0 {
+            assert!(corner.distance(center) <= radius * 1.0001);
+        }
+
+        // rotating the camera doesn't change the size of the cascade
+        cam.target = point3(0.3, -0.2, 1.);
+        let (_, rotated_radius) = bounding_sphere(&cam, 40., 200.);
+        assert!((rotated_radius - radius).abs() < 1e-3);
+
+        // the cascade moves in whole texels
+        let texel = radius * 2. / 2048.;
+        let light_view = Matrix4::look_at_rh(
+            point3(light_dir.x, light_dir.y, light_dir.z),
+            point3(0., 0., 0.),
+            vec3(0., 1., 0.),
+        );
+        let a = light_view
+            .transform_point(snap_to_texels(center, light_dir, texel));
+        let b = light_view.transform_point(snap_to_texels(
+            center + vec3(0.37, 1.1, -0.6),
+            light_dir,
+            texel,
+        ));
+        for offset in [(b.x - a.x) / texel, (b.y - a.y) / texel] {
+            assert!((offset - offset.round()).abs() < 1e-2);
+        }
+    }
+
+    #[test]
+    fn custom_frusta_match_their_projections() {
+        let ortho_cam = OrthoCamera {
+            left: -4.,
+            right: 6.,
+            top: 3.,
+            btm: -1.,
+            near: 0.5,
+            far: 50.,
+            ..OrthoCamera::default()
+        };
+        let off_axis = FrustumCamera {
+            left: -0.02,
+            right: 0.06,
+            btm: -0.03,
+            top: 0.05,
+            near: 0.1,
+            far: 80.,
+            cam_pos: point3(1., 2., 3.),
+            target: point3(0., 0., 30.),
+            up: vec3(0., 1., 0.),
+        };
+        let viewers: [&dyn Viewer; 2] = [&ortho_cam, &off_axis];
+        for viewer in viewers {
+            // a static camera finds its corners from the projection matrix
+            let projected = StaticCamera::from(viewer).frustum_corners();
+            for (corner, expected) in
+                viewer.frustum_corners().iter().zip(&projected)
+            {
+                let tolerance = 1e-3 * expected.to_vec().magnitude().max(1.);
+                assert!(corner.distance(*expected) < tolerance);
+            }
+            let (corners, center) = get_frustum_world(viewer);
+            for plane in viewer.frustum_planes() {
+                assert!(plane.dot(center.to_homogeneous()) > 0.);
+                for corner in &corners {
+                    assert!(plane.dot(corner.to_homogeneous()) > -1e-2);
+                }
+            }
+            let (center, radius) = bounding_sphere(viewer, 2., 20.);
+            let to_world = viewer.view_mat().invert().unwrap();
+            for corner in frustum_slice(viewer, 2., 20.) {
+                let corner = to_world.transform_point(corner);
+                assert!(corner.distance(center) <= radius * 1.0001);
+            }
+        }
+    }
+
+    #[test]
+    fn practical_splits_blend_log_and_uniform() {
+        let uniform = practical_splits(1., 100., 4, 0.);
+        let log = practical_splits(1., 1000., 3, 1.);
+        let practical = practical_splits(0.1, 600., 3, 0.75);
+        for (split, expected) in uniform.iter().zip([25.75, 50.5, 75.25, 100.])
+        {
+            assert!((split - expected).abs() < 1e-3);
+        }
+        for (split, expected) in log.iter().zip([10., 100., 1000.]) {
+            assert!((split - expected).abs() < 1e-2);
+        }
+        assert!(practical.windows(2).all(|w| w[0] < w[1]));
+        assert!((practical[2] - 600.).abs() < 1e-2);
+    }
+}
@@ -0,0 +1,251 @@
+use cgmath::*;
+use noise::{NoiseFn, Perlin};
+use std::time::Duration;
+
+/// Largest rotational offset of the shake, reached at full trauma
+const MAX_SHAKE_ANGLE: Deg<f64> = Deg(4.);
+/// Roll is kept smaller than yaw and pitch so the horizon doesn't swing
+const ROLL_FAC: f64 = 0.5;
+/// Speed the shake noise is sampled at, in noise units per second
+const SHAKE_FREQUENCY: f64 = 12.;
+/// Trauma lost per second
+const TRAUMA_DECAY: f64 = 1.2;
+/// Spring constant pulling a kicked camera back to rest
+const KICK_STIFFNESS: f64 = 400.;
+/// Damping of the kick spring, critically damped for `KICK_STIFFNESS`
+const KICK_DAMPING: f64 = 40.;
+/// Longest step the kick spring is integrated over, to keep it stable when
+/// frames are slow
+const MAX_KICK_STEP: f64 = 0.05;
+/// Speed at which the FOV is widened by the full amount
+const FULL_FOV_SPEED: f64 = 80.;
+/// How quickly the FOV blends towards its goal, per second
+const FOV_RATE: f64 = 4.;
+/// Fraction of the full speed widening the FOV is further widened by while
+/// boosting
+const BOOST_FOV_FAC: f64 = 0.6;
+
+/// Effects applied on top of a camera's view to give physical feedback
+///
+/// Trauma is added by impacts and explosions and shakes the camera with
+/// smooth, noisy rotations whose strength is the square of the trauma, so
+/// small hits barely shake the camera and big ones shake it a lot. Kicks
+/// pitch the camera up and spring it back, and moving quickly or boosting
+/// widens the field of view
+pub struct CameraEffects {
+    noise: Perlin,
+    /// Amount of shake, from `0` to `1`
+    trauma: f64,
+    /// Seconds the effects have been running, which the shake noise is
+    /// sampled at
+    time: f64,
+    /// Pitch offset of the kick in radians
+    kick: f64,
+    kick_vel: f64,
+    /// Degrees the FOV is currently widened by
+    fov_offset: f64,
+    /// Multiplier of the shake and kick strength
+    intensity: f64,
+    /// Degrees the FOV is widened by at full speed
+    max_fov_offset: f64,
+    /// `true` while the FOV is further widened by a boost
+    boosting: bool,
+}
+
+impl CameraEffects {
+    pub fn new() -> Self {
+        Self {
+            noise: Perlin::new(),
+            trauma: 0.,
+            time: 0.,
+            kick: 0.,
+            kick_vel: 0.,
+            fov_offset: 0.,
+            intensity: 1.,
+            max_fov_offset: 10.,
+            boosting: false,
+        }
+    }
+
+    /// Sets the multiplier of the shake and kick strength, where `0`
+    /// disables them
+    #[must_use]
+    pub const fn intensity(mut self, intensity: f64) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    /// Sets the degrees the FOV is widened by at full speed, where `0`
+    /// disables the widening
+    #[must_use]
+    pub const fn max_fov_offset(mut self, degrees: f64) -> Self {
+        self.max_fov_offset = degrees;
+        self
+    }
+
+    /// Adds `amount` of trauma, clamping the total trauma to `1`
+    pub fn add_trauma(&mut self, amount: f64) {
+        self.trauma = (self.trauma + amount).clamp(0., 1.);
+    }
+
+    /// Gets the current amount of trauma
+    pub const fn trauma(&self) -> f64 {
+        self.trauma
+    }
+
+    /// Kicks the camera upwards with an angular velocity of `strength`
+    /// radians per second
+    pub fn kick(&mut self, strength: f64) {
+        self.kick_vel += strength;
+    }
+
+    /// Further widens the FOV while `boosting`, such as while the
+    /// afterburner is on
+    pub fn set_boosting(&mut self, boosting: bool) {
+        self.boosting = boosting;
+    }
+
+    /// Advances the effects by `dt` while the camera moves at `speed`
+    pub fn update(&mut self, dt: Duration, speed: f64) {
+        let dt = dt.as_secs_f64();
+        self.time += dt;
+        self.trauma = (self.trauma - TRAUMA_DECAY * dt).max(0.);
+
+        let mut left = dt;
+        while left > 0. {
+            let step = left.min(MAX_KICK_STEP);
+            let acc =
+                -KICK_STIFFNESS * self.kick - KICK_DAMPING * self.kick_vel;
+            self.kick_vel += acc * step;
+            self.kick += self.kick_vel * step;
+            left -= step;
+        }
+
+        let boost = if self.boosting { BOOST_FOV_FAC } else { 0. };
+        let goal =
+            self.max_fov_offset * ((speed / FULL_FOV_SPEED).min(1.) + boost);
+        let t = 1. - (-FOV_RATE * dt).exp();
+        self.fov_offset += (goal - self.fov_offset) * t;
+    }
+
+    /// Gets the field of view with the speed widening applied to `base_fov`
+    pub fn fov(&self, base_fov: f32) -> f32 {
+        base_fov + self.fov_offset as f32
+    }
+
+    /// Gets the yaw, pitch, and roll offsets of the shake and kick, in
+    /// radians
+    fn offsets(&self) -> (f64, f64, f64) {
+        let shake = self.trauma * self.trauma * self.intensity;
+        let max = Rad::from(MAX_SHAKE_ANGLE).0 * shake;
+        let t = self.time * SHAKE_FREQUENCY;
+        // each axis samples a different row of the noise so they're
+        // uncorrelated
+        let (yaw, pitch, roll) = (
+            self.noise.get([t, 0.5]),
+            self.noise.get([t, 10.5]),
+            self.noise.get([t, 20.5]),
+        );
+        (
+            yaw * max,
+            pitch.mul_add(max, self.kick * self.intensity),
+            roll * max * ROLL_FAC,
+        )
+    }
+
+    /// Applies the shake and kick to a camera at `pos` looking at `target`
+    /// with an up vector of `up`
+    ///
+    /// Returns the new target and up vector
+    pub fn apply(
+        &self,
+        pos: Point3<f64>,
+        target: Point3<f64>,
+        up: Vector3<f64>,
+    ) -> (Point3<f64>, Vector3<f64>) {
+        let view = target - pos;
+        if view.magnitude2() <= f64::EPSILON {
+            return (target, up);
+        }
+        let forward = view.normalize();
+        let right = forward.cross(up);
+        if right.magnitude2() <= f64::EPSILON {
+            return (target, up);
+        }
+        let right = right.normalize();
+        let cam_up = right.cross(forward);
+        let (yaw, pitch, roll) = self.offsets();
+        let rot = Quaternion::from_axis_angle(cam_up, Rad(yaw))
+            * Quaternion::from_axis_angle(right, Rad(pitch))
+            * Quaternion::from_axis_angle(forward, Rad(roll));
+        (pos + rot.rotate_vector(view), rot.rotate_vector(up))
+    }
+}
+
+impl Default for CameraEffects {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn trauma_decays_and_clamps() {
+        let mut effects = CameraEffects::new();
+        effects.add_trauma(0.7);
+        effects.add_trauma(0.7);
+        assert_approx_eq!(effects.trauma(), 1.);
+        effects.update(Duration::from_millis(500), 0.);
+        assert!(effects.trauma() < 1.);
+        effects.update(Duration::from_secs(2), 0.);
+        assert_approx_eq!(effects.trauma(), 0.);
+    }
+
+    #[test]
+    fn calm_camera_is_unchanged() {
+        let effects = CameraEffects::new();
+        let (target, up) = effects.apply(
+            point3(0., 0., 0.),
+            point3(0., 0., -5.),
+            vec3(0., 1., 0.),
+        );
+        assert_approx_eq!(target.z, -5.);
+        assert_approx_eq!(target.x, 0.);
+        assert_approx_eq!(up.y, 1.);
+    }
+
+    #[test]
+    fn kick_springs_back() {
+        let mut effects = CameraEffects::new();
+        effects.kick(2.);
+        effects.update(Duration::from_millis(30), 0.);
+        let (target, _) = effects.apply(
+            point3(0., 0., 0.),
+            point3(0., 0., -5.),
+            vec3(0., 1., 0.),
+        );
+        assert!(target.y > 0.);
+        for _ in 0..60 {
+            effects.update(Duration::from_millis(16), 0.);
+        }
+        assert!(effects.kick.abs() < 1e-3);
+    }
+
+    #[test]
+    fn fov_widens_with_speed() {
+        let mut effects = CameraEffects::new().max_fov_offset(10.);
+        for _ in 0..100 {
+            effects.update(Duration::from_millis(50), FULL_FOV_SPEED * 2.);
+        }
+        assert_approx_eq!(effects.fov(60.), 70., 0.01);
+        effects.set_boosting(true);
+        for _ in 0..100 {
+            effects.update(Duration::from_millis(50), FULL_FOV_SPEED * 2.);
+        }
+        assert_approx_eq!(effects.fov(60.), 76., 0.01);
+    }
+}
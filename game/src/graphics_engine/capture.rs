@@ -0,0 +1,568 @@
+use crate::profiler;
+use glium::texture::pixel_buffer::PixelBuffer;
+use glium::texture::{RawImage2d, Texture2d};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{error, info, warn};
+
+/// Directory screenshots and recorded image sequences are saved in
+pub const CAPTURE_DIR: &str = "captures";
+/// Frames a readback waits before its pixels are mapped, so the GPU has
+/// finished copying them by then and mapping them doesn't stall the frame
+const READBACK_DELAY: u32 = 2;
+
+/// Where the frames of a recording go
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum VideoOutput {
+    /// Numbered pngs in a new directory of `CAPTURE_DIR`
+    ImageSequence,
+    /// The standard input of an encoder, started by a command line such as
+    /// `ffmpeg -f rawvideo -pix_fmt rgba -s {width}x{height} -r {fps} -i -
+    /// out.mp4`. The raw RGBA frames are piped top row first, and `{width}`,
+    /// `{height}` and `{fps}` are replaced by the size and rate of the frames
+    Encoder(String),
+}
+
+/// How long and how a recording is captured
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct VideoSettings {
+    /// Length of the recording
+    pub duration: Duration,
+    /// Frames of the recording per second. Rendered frames are dropped or
+    /// repeated to keep this rate
+    pub fps: u32,
+    pub output: VideoOutput,
+}
+
+/// A composited frame read back from the GPU. Rows are stored bottom row
+/// first, like the texture
+struct Frame {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Frame {
+    /// Gets the frame as an image, which stores the top row first
+    fn into_image(self) -> Option<image::RgbaImage> {
+        image::RgbaImage::from_raw(self.width, self.height, self.pixels)
+            .map(|image| image::imageops::flip_vertical(&image))
+    }
+}
+
+/// Work done by the writer thread, in the order it was queued
+enum Job {
+    /// Saves the frame to the first path, and copies the file to the others
+    Save(Frame, Vec<PathBuf>),
+    /// Sets the encoder frames are piped to
+    StartEncoder(Child),
+    /// Pipes the frame to the encoder the given number of times
+    Encode(Frame, u32),
+    /// Closes the encoder's input and waits for it to finish
+    FinishEncoder,
+}
+
+/// Saves and encodes frames until the sending half of `jobs` is dropped
+fn write_frames(jobs: &mpsc::Receiver<Job>) {
+    let mut encoder = None;
+    for job in jobs {
+        match job {
+            Job::Save(frame, paths) => save_frame(frame, &paths),
+            Job::StartEncoder(child) => encoder = Some(child),
+            Job::Encode(frame, copies) => {
+                if let Some(Err(e)) = encoder
+                    .as_mut()
+                    .map(|child| encode_frame(child, frame, copies))
+                {
+                    warn!("Stopped piping frames to the encoder: {}", e);
+                    encoder.take().into_iter().for_each(finish_encoder);
+                }
+            }
+            Job::FinishEncoder => {
+                encoder.take().into_iter().for_each(finish_encoder);
+            }
+        }
+    }
+    encoder.into_iter().for_each(finish_encoder);
+}
+
+/// Pipes `frame` to the input of `encoder` `copies` times
+fn encode_frame(
+    encoder: &mut Child,
+    frame: Frame,
+    copies: u32,
+) -> Result<(), Box<dyn Error>> {
+    let image = frame.into_image().ok_or("Invalid frame")?;
+    let input = encoder.stdin.as_mut().ok_or("The encoder has no input")?;
+    for _ in 0..copies {
+        input.write_all(image.as_raw())?;
+    }
+    Ok(())
+}
+
+/// Closes the input of `encoder` and waits for it to exit
+fn finish_encoder(mut encoder: Child) {
+    drop(encoder.stdin.take());
+    match encoder.wait() {
+        Ok(status) if status.success() => info!("Finished encoding video"),
+        Ok(status) => error!("Video encoder failed: {}", status),
+        Err(e) => error!("Failed to wait for the video encoder: {}", e),
+    }
+}
+
+/// Saves `frame` as a png to the first of `paths`, and copies it to the
+/// others
+fn save_frame(frame: Frame, paths: &[PathBuf]) {
+    let saved = frame
+        .into_image()
+        .ok_or_else(|| "Invalid frame".to_owned())
+        .and_then(|image| {
+            let path = paths.first().ok_or("No path to save to")?;
+            image.save(path).map_err(|e| e.to_string())?;
+            for copy in &paths[1..] {
+                std::fs::copy(path, copy).map_err(|e| e.to_string())?;
+            }
+            Ok(path)
+        });
+    match saved {
+        Ok(path) if paths.len() == 1 => info!("Saved {}", path.display()),
+        Ok(_) => (),
+        Err(e) => error!("Failed to save capture: {}", e),
+    }
+}
+
+/// Gets the name of a capture made at `time`, such as `screenshot-<ms>`
+/// where `<ms>` is the milliseconds since the Unix epoch
+fn capture_name(kind: &str, time: SystemTime) -> String {
+    let millis = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    format!("{}-{}", kind, millis)
+}
+
+/// Splits the encoder command line `template` into its program and
+/// arguments, filling in the size and rate of the frames
+fn encoder_args(
+    template: &str,
+    width: u32,
+    height: u32,
+    fps: u32,
+) -> Vec<String> {
+    template
+        .split_whitespace()
+        .map(|arg| {
+            arg.replace("{width}", &width.to_string())
+                .replace("{height}", &height.to_string())
+                .replace("{fps}", &fps.to_string())
+        })
+        .collect()
+}
+
+/// Starts the encoder of the command line `template` with a piped input
+fn spawn_encoder(
+    template: &str,
+    width: u32,
+    height: u32,
+    fps: u32,
+) -> std::io::Result<Child> {
+    let args = encoder_args(template, width, height, fps);
+    let (program, args) = args.split_first().ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, "No encoder")
+    })?;
+    Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .spawn()
+}
+
+/// Where the frames of a recording are sent
+enum Sink {
+    /// Frames are saved as pngs in the directory
+    Sequence(PathBuf),
+    /// Frames are piped to an encoder started with the command line, once
+    /// the size of the frames is known
+    Encoder { template: String, started: bool },
+}
+
+/// A recording in progress
+struct Recording {
+    sink: Sink,
+    fps: u32,
+    /// Time between the frames of the recording
+    period: Duration,
+    /// Time passed since the last frame of the recording
+    owed: Duration,
+    /// Time the last frame was rendered, or `None` before the first
+    last_frame: Option<Instant>,
+    /// Index of the next frame of the recording, which is the number of
+    /// frames recorded so far
+    next_frame: u32,
+    frames_left: u32,
+}
+
+impl Recording {
+    fn new(settings: &VideoSettings, sink: Sink) -> Self {
+        let fps = settings.fps.max(1);
+        let period = Duration::from_secs(1) / fps;
+        Self {
+            sink,
+            fps,
+            period,
+            // the first rendered frame is always captured
+            owed: period,
+            last_frame: None,
+            next_frame: 0,
+            frames_left: (settings.duration.as_secs_f64() * f64::from(fps))
+                .ceil() as u32,
+        }
+    }
+
+    /// Gets the number of frames of the recording that a frame rendered `dt`
+    /// after the last one stands in for. Rendering slower than the
+    /// recording's rate repeats frames, and rendering faster drops them
+    fn frames_due(&mut self, dt: Duration) -> u32 {
+        self.owed += dt;
+        let due = (self.owed.as_nanos() / self.period.as_nanos()) as u32;
+        self.owed -= self.period * due;
+        let due = due.min(self.frames_left);
+        self.frames_left -= due;
+        due
+    }
+}
+
+/// Gets the paths of the `count` frames of the image sequence in `dir`
+/// starting at the frame with index `first`
+fn sequence_paths(dir: &Path, first: u32, count: u32) -> Vec<PathBuf> {
+    (first..first + count)
+        .map(|idx| dir.join(format!("frame-{:05}.png", idx)))
+        .collect()
+}
+
+/// What is done with a frame once it's read back
+enum Destination {
+    Save(Vec<PathBuf>),
+    Encode(u32),
+    /// Ends the recording of an encoder after the frames queued before it
+    FinishEncoder,
+}
+
+/// A frame being copied into a pixel buffer
+struct Readback {
+    /// `None` for destinations which don't need the frame
+    pixels: Option<PixelBuffer<(u8, u8, u8, u8)>>,
+    /// Frames rendered since the copy was started
+    age: u32,
+    destination: Destination,
+}
+
+#[derive(Default)]
+struct Capture {
+    screenshot_requested: bool,
+    recording: Option<Recording>,
+    /// Readbacks in the order they were started
+    readbacks: VecDeque<Readback>,
+    /// Queue of the writer thread, which is started by the first capture
+    writer: Option<mpsc::Sender<Job>>,
+}
+
+impl Capture {
+    /// Sends `job` to the writer thread, starting it if needed
+    fn send(&mut self, job: Job) {
+        let writer = self.writer.get_or_insert_with(|| {
+            let (sender, jobs) = mpsc::channel();
+            std::thread::Builder::new()
+                .name("capture".to_owned())
+                .spawn(move || write_frames(&jobs))
+                .expect("Failed to start the capture thread");
+            sender
+        });
+        if writer.send(job).is_err() {
+            error!("The capture thread stopped");
+            self.writer = None;
+        }
+    }
+
+    /// Maps the pixels of the readbacks old enough that the GPU has
+    /// finished copying them, and hands them to the writer thread
+    fn finish_readbacks(&mut self) {
+        while self
+            .readbacks
+            .front()
+            .map_or(false, |readback| readback.age >= READBACK_DELAY)
+        {
+            let readback = self.readbacks.pop_front().unwrap();
+            let frame = readback.pixels.and_then(|pixels| {
+                pixels
+                    .read_as_texture_2d::<RawImage2d<u8>>()
+                    .map(|raw| Frame {
+                        width: raw.width,
+                        height: raw.height,
+                        pixels: raw.data.into_owned(),
+                    })
+                    .map_err(|e| error!("Failed to read frame: {:?}", e))
+                    .ok()
+            });
+            match (readback.destination, frame) {
+                (Destination::Save(paths), Some(frame)) => {
+                    self.send(Job::Save(frame, paths));
+                }
+                (Destination::Encode(copies), Some(frame)) => {
+                    self.send(Job::Encode(frame, copies));
+                }
+                (Destination::FinishEncoder, _) => {
+                    self.send(Job::FinishEncoder);
+                }
+                _ => (),
+            }
+        }
+        for readback in &mut self.readbacks {
+            readback.age += 1;
+        }
+    }
+
+    /// Starts copying `frame` into a pixel buffer, to be sent to
+    /// `destination` a few frames later
+    fn start_readback(&mut self, frame: &Texture2d, destination: Destination) {
+        self.readbacks.push_back(Readback {
+            pixels: Some(frame.read_to_pixel_buffer()),
+            age: 0,
+            destination,
+        });
+    }
+
+    /// Reads back `frame` for the frames of the recording it stands in for,
+    /// and ends the recording after its last frame
+    fn record(&mut self, frame: &Texture2d, now: Instant) {
+        let mut recording = match self.recording.take() {
+            Some(recording) => recording,
+            None => return,
+        };
+        let dt = recording
+            .last_frame
+            .map_or(Duration::ZERO, |last| now - last);
+        recording.last_frame = Some(now);
+        let due = recording.frames_due(dt);
+        let destination = match &mut recording.sink {
+            _ if due == 0 => None,
+            Sink::Sequence(dir) => Some(Destination::Save(sequence_paths(
+                dir,
+                recording.next_frame,
+                due,
+            ))),
+            Sink::Encoder { template, started } => {
+                if !*started {
+                    match spawn_encoder(
+                        template,
+                        frame.width(),
+                        frame.height(),
+                        recording.fps,
+                    ) {
+                        Ok(child) => self.send(Job::StartEncoder(child)),
+                        Err(e) => {
+                            // the recording is dropped
+                            error!("Failed to start the encoder: {}", e);
+                            return;
+                        }
+                    }
+                    *started = true;
+                }
+                Some(Destination::Encode(due))
+            }
+        };
+        if let Some(destination) = destination {
+            self.start_readback(frame, destination);
+        }
+        recording.next_frame += due;
+        if recording.frames_left == 0 {
+            self.end_recording(recording);
+        } else {
+            self.recording = Some(recording);
+        }
+    }
+
+    /// Finishes `recording` once its frames have been written
+    fn end_recording(&mut self, recording: Recording) {
+        match recording.sink {
+            Sink::Sequence(dir) => {
+                info!(
+                    "Recorded {} frames to {}",
+                    recording.next_frame,
+                    dir.display()
+                );
+            }
+            Sink::Encoder { started: true, .. } => {
+                self.readbacks.push_back(Readback {
+                    pixels: None,
+                    age: 0,
+                    destination: Destination::FinishEncoder,
+                });
+            }
+            Sink::Encoder { .. } => (),
+        }
+    }
+}
+
+thread_local! {
+    static CAPTURE: RefCell<Capture> = RefCell::new(Capture::default());
+}
+
+/// Saves the next composited frame as a png in `CAPTURE_DIR`
+pub fn request_screenshot() {
+    CAPTURE.with(|c| c.borrow_mut().screenshot_requested = true);
+}
+
+/// Starts recording the composited frames, replacing any recording in
+/// progress
+///
+/// # Errors
+/// Fails if the directory of an image sequence cannot be created
+pub fn start_recording(settings: &VideoSettings) -> std::io::Result<()> {
+    let sink = match &settings.output {
+        VideoOutput::ImageSequence => {
+            let dir = Path::new(CAPTURE_DIR)
+                .join(capture_name("video", SystemTime::now()));
+            std::fs::create_dir_all(&dir)?;
+            Sink::Sequence(dir)
+        }
+        VideoOutput::Encoder(template) => Sink::Encoder {
+            template: template.clone(),
+            started: false,
+        },
+    };
+    stop_recording();
+    CAPTURE.with(|c| {
+        c.borrow_mut().recording = Some(Recording::new(settings, sink));
+    });
+    Ok(())
+}
+
+/// Ends the recording in progress, keeping the frames recorded so far
+pub fn stop_recording() {
+    CAPTURE.with(|c| {
+        let mut capture = c.borrow_mut();
+        if let Some(recording) = capture.recording.take() {
+            capture.end_recording(recording);
+        }
+    });
+}
+
+/// `true` if the composited frames are being recorded
+#[must_use]
+pub fn is_recording() -> bool {
+    CAPTURE.with(|c| c.borrow().recording.is_some())
+}
+
+/// Captures `frame`, the final composited frame, if a screenshot was
+/// requested or it's being recorded
+///
+/// Frames are copied into pixel buffers asynchronously and read a few
+/// frames later, so capturing doesn't wait on the GPU. They're saved and
+/// encoded on another thread
+pub fn on_frame(frame: &Texture2d) {
+    CAPTURE.with(|c| {
+        let mut capture = c.borrow_mut();
+        if capture.readbacks.is_empty()
+            && capture.recording.is_none()
+            && !capture.screenshot_requested
+        {
+            return;
+        }
+        let _timer = profiler::scope("capture");
+        capture.finish_readbacks();
+        if capture.screenshot_requested {
+            capture.screenshot_requested = false;
+            let path = Path::new(CAPTURE_DIR).join(format!(
+                "{}.png",
+                capture_name("screenshot", SystemTime::now())
+            ));
+            match std::fs::create_dir_all(CAPTURE_DIR) {
+                Ok(()) => {
+                    capture
+                        .start_readback(frame, Destination::Save(vec![path]));
+                }
+                Err(e) => error!("Failed to save screenshot: {}", e),
+            }
+        }
+        capture.record(frame, Instant::now());
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn recording(fps: u32, secs: u64) -> Recording {
+        Recording::new(
+            &VideoSettings {
+                duration: Duration::from_secs(secs),
+                fps,
+                output: VideoOutput::ImageSequence,
+            },
+            Sink::Sequence(PathBuf::from(CAPTURE_DIR)),
+        )
+    }
+
+    #[test]
+    fn recordings_keep_their_frame_rate() {
+        let mut rec = recording(30, 1);
+        let frame_60 = Duration::from_millis(17);
+        assert_eq!(rec.frames_due(Duration::ZERO), 1);
+        // rendering twice as fast as the recording drops every other frame
+        let due: Vec<_> = (0..4).map(|_| rec.frames_due(frame_60)).collect();
+        assert_eq!(due, vec![0, 1, 0, 1]);
+        // and rendering slower repeats frames
+        assert_eq!(rec.frames_due(Duration::from_millis(100)), 3);
+        assert_eq!(rec.frames_left, 24);
+        assert_eq!(rec.frames_due(Duration::from_secs(5)), 24);
+        assert_eq!(rec.frames_left, 0);
+        assert_eq!(rec.frames_due(frame_60), 0);
+    }
+
+    #[test]
+    fn image_sequences_are_numbered() {
+        let dir = PathBuf::from("video");
+        assert_eq!(
+            sequence_paths(&dir, 0, 1),
+            vec![dir.join("frame-00000.png")]
+        );
+        assert_eq!(
+            sequence_paths(&dir, 9, 2),
+            vec![dir.join("frame-00009.png"), dir.join("frame-00010.png")]
+        );
+    }
+
+    #[test]
+    fn captures_are_named_and_encoders_filled_in() {
+        let time = SystemTime::UNIX_EPOCH + Duration::from_millis(1234);
+        assert_eq!(capture_name("screenshot", time), "screenshot-1234");
+        assert_eq!(
+            encoder_args(
+                "ffmpeg -s {width}x{height}  -r {fps} -i -",
+                640,
+                480,
+                30
+            ),
+            vec!["ffmpeg", "-s", "640x480", "-r", "30", "-i", "-"]
+        );
+    }
+
+    #[test]
+    fn frames_are_flipped_to_images() {
+        let frame = Frame {
+            width: 1,
+            height: 2,
+            pixels: vec![1, 2, 3, 4, 5, 6, 7, 8],
+        };
+        assert_eq!(
+            frame.into_image().unwrap().as_raw(),
+            &vec![5, 6, 7, 8, 1, 2, 3, 4]
+        );
+    }
+}
@@ -0,0 +1,374 @@
+use super::drawable::Viewer;
+use super::scene::Scene;
+use cgmath::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// How the playback speed of a camera path changes over its duration
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[allow(dead_code)]
+pub enum Easing {
+    Linear,
+    /// Starts slowly and speeds up
+    EaseIn,
+    /// Starts quickly and slows down
+    EaseOut,
+    /// Starts and ends slowly
+    EaseInOut,
+}
+
+impl Easing {
+    /// Maps the fraction of the playback time that has passed, `t`, to the
+    /// fraction of the path that should be travelled
+    pub fn apply(self, t: f64) -> f64 {
+        let t = t.clamp(0., 1.);
+        match self {
+            Self::Linear => t,
+            Self::EaseIn => t * t,
+            Self::EaseOut => (1. - t).mul_add(t - 1., 1.),
+            Self::EaseInOut => t * t * 2.0f64.mul_add(-t, 3.),
+        }
+    }
+}
+
+/// Gets the point `t` of the way between `p1` and `p2` on the uniform
+/// Catmull-Rom spline through `p0`, `p1`, `p2` and `p3`
+fn catmull_rom(
+    p0: Point3<f64>,
+    p1: Point3<f64>,
+    p2: Point3<f64>,
+    p3: Point3<f64>,
+    t: f64,
+) -> Point3<f64> {
+    let (t2, t3) = (t * t, t * t * t);
+    let (p0, p1, p2, p3) = (p0.to_vec(), p1.to_vec(), p2.to_vec(), p3.to_vec());
+    Point3::from_vec(
+        (p1 * 2.
+            + (p2 - p0) * t
+            + (p0 * 2. - p1 * 5. + p2 * 4. - p3) * t2
+            + (p1 * 3. - p0 - p2 * 3. + p3) * t3)
+            * 0.5,
+    )
+}
+
+/// A camera position and orientation at a point in time
+#[derive(Copy, Clone, Debug)]
+pub struct Keyframe {
+    /// Time since the start of the path, in seconds
+    pub time: f64,
+    pub pos: Point3<f64>,
+    /// Rotation of the camera. The camera looks along the rotated `+z` axis
+    /// with the rotated `+y` axis pointing up
+    pub rot: Quaternion<f64>,
+}
+
+impl Keyframe {
+    /// Creates a keyframe of a camera at `pos` looking at `target`
+    pub fn looking_at(
+        time: f64,
+        pos: Point3<f64>,
+        target: Point3<f64>,
+        up: Vector3<f64>,
+    ) -> Self {
+        let dir = (target - pos).normalize();
+        let side = up.cross(dir).normalize();
+        let up = dir.cross(side);
+        Self {
+            time,
+            pos,
+            rot: Matrix3::from_cols(side, up, dir).into(),
+        }
+    }
+}
+
+/// A smooth camera path through a sequence of keyframes
+///
+/// Positions are interpolated with a Catmull-Rom spline, which passes
+/// through every keyframe, and orientations are spherically interpolated
+pub struct CameraPath {
+    keys: Vec<Keyframe>,
+    easing: Easing,
+}
+
+impl CameraPath {
+    /// Creates a path through `keys`. The keyframes are sorted by time
+    ///
+    /// Requires there is at least one keyframe
+    pub fn new(mut keys: Vec<Keyframe>) -> Self {
+        assert!(!keys.is_empty(), "A camera path needs a keyframe");
+        keys.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap());
+        Self {
+            keys,
+            easing: Easing::EaseInOut,
+        }
+    }
+
+    /// Creates a path which circles `center` `turns` times over `duration`
+    /// seconds, looking at `center` from `radius` away and `height` above
+    /// it. Useful for kill cams
+    #[allow(dead_code)]
+    pub fn orbit(
+        center: Point3<f64>,
+        radius: f64,
+        height: f64,
+        turns: f64,
+        duration: f64,
+    ) -> Self {
+        /// Keyframes per full turn
+        const KEYS_PER_TURN: f64 = 8.;
+        let count = (turns.abs() * KEYS_PER_TURN).ceil().max(1.) as u32;
+        let keys = (0..=count)
+            .map(|i| {
+                let t = f64::from(i) / f64::from(count);
+                let angle = Rad::full_turn() * turns * t;
+                let pos = center
+                    + vec3(angle.cos() * radius, height, angle.sin() * radius);
+                Keyframe::looking_at(
+                    t * duration,
+                    pos,
+                    center,
+                    vec3(0., 1., 0.),
+                )
+            })
+            .collect();
+        Self::new(keys).easing(Easing::Linear)
+    }
+
+    /// Sets how the playback speed changes over the path. Paths ease in and
+    /// out by default
+    #[must_use]
+    pub const fn easing(mut self, easing: Easing) -> Self {
+        self.easing = easing;
+        self
+    }
+
+    /// Gets the length of the path in seconds
+    pub fn duration(&self) -> f64 {
+        self.keys.last().unwrap().time - self.keys[0].time
+    }
+
+    /// Gets the camera position and rotation `time` seconds after the start
+    /// of the path
+    pub fn sample(&self, time: f64) -> (Point3<f64>, Quaternion<f64>) {
+        let duration = self.duration();
+        let time = if duration > f64::EPSILON {
+            self.easing
+                .apply(time / duration)
+                .mul_add(duration, self.keys[0].time)
+        } else {
+            self.keys[0].time
+        };
+        let last = self.keys.len() - 1;
+        let next = self
+            .keys
+            .iter()
+            .position(|key| key.time > time)
+            .unwrap_or(last + 1);
+        if next == 0 || next > last {
+            let key = &self.keys[next.min(last)];
+            return (key.pos, key.rot);
+        }
+        let (a, b) = (&self.keys[next - 1], &self.keys[next]);
+        let t = (time - a.time) / (b.time - a.time);
+        let before = self.keys[next.saturating_sub(2)].pos;
+        let after = self.keys[(next + 1).min(last)].pos;
+        // take the shortest way around between orientations
+        let b_rot = if a.rot.dot(b.rot) < 0. { -b.rot } else { b.rot };
+        (
+            catmull_rom(before, a.pos, b.pos, after, t),
+            a.rot.slerp(b_rot, t),
+        )
+    }
+}
+
+/// A camera which plays camera paths as one shot sequences, such as an
+/// intro flyover or a kill cam
+///
+/// A sequence is played in place of a scene's viewer with `play_in_scene`.
+/// When the sequence finishes, the scene's viewer is restored and the
+/// sequence's completion callback is called so control can be returned to
+/// the player
+pub struct CinematicCamera {
+    path: Option<CameraPath>,
+    elapsed: f64,
+    on_complete: Option<Box<dyn FnOnce()>>,
+    pos: Point3<f64>,
+    rot: Quaternion<f64>,
+    pub fov_deg: f32,
+    pub aspect: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl CinematicCamera {
+    pub fn new(aspect: f32, far: f32) -> Self {
+        Self {
+            path: None,
+            elapsed: 0.,
+            on_complete: None,
+            pos: point3(0., 0., 0.),
+            rot: Quaternion::one(),
+            fov_deg: 60.,
+            aspect,
+            near: 0.1,
+            far,
+        }
+    }
+
+    /// Starts playing `path` from the beginning, calling `on_complete` when
+    /// it finishes. If a sequence is already playing, it's replaced without
+    /// calling its completion callback
+    ///
+    /// `on_complete` is called while the camera is mutably borrowed
+    pub fn play<F: FnOnce() + 'static>(
+        &mut self,
+        path: CameraPath,
+        on_complete: F,
+    ) {
+        let (pos, rot) = path.sample(0.);
+        self.pos = pos;
+        self.rot = rot;
+        self.path = Some(path);
+        self.elapsed = 0.;
+        self.on_complete = Some(Box::new(on_complete));
+    }
+
+    /// Plays `path` in place of the viewer of `scene`, restoring the scene's
+    /// viewer and then calling `on_complete` once the path finishes
+    pub fn play_in_scene<F: FnOnce() + 'static>(
+        camera: &Rc<RefCell<Self>>,
+        scene: &Rc<RefCell<Scene>>,
+        path: CameraPath,
+        on_complete: F,
+    ) {
+        let prev = scene.borrow_mut().set_viewer(camera.clone());
+        let scene = scene.clone();
+        camera.borrow_mut().play(path, move || {
+            scene.borrow_mut().set_viewer(prev);
+            on_complete();
+        });
+    }
+
+    /// `true` if a sequence is playing
+    pub const fn is_playing(&self) -> bool {
+        self.path.is_some()
+    }
+
+    /// Advances the playing sequence by `dt`, finishing it if it reached the
+    /// end of its path
+    pub fn update(&mut self, dt: std::time::Duration) {
+        let finished = self.path.as_ref().map_or(false, |path| {
+            self.elapsed += dt.as_secs_f64();
+            let (pos, rot) = path.sample(self.elapsed);
+            self.pos = pos;
+            self.rot = rot;
+            self.elapsed >= path.duration()
+        });
+        if finished {
+            self.finish();
+        }
+    }
+
+    /// Stops the playing sequence and calls its completion callback
+    pub fn skip(&mut self) {
+        if self.is_playing() {
+            self.finish();
+        }
+    }
+
+    fn finish(&mut self) {
+        self.path = None;
+        if let Some(on_complete) = self.on_complete.take() {
+            on_complete();
+        }
+    }
+}
+
+impl Viewer for CinematicCamera {
+    fn proj_mat(&self) -> Matrix4<f32> {
+        perspective(Deg(self.fov_deg), self.aspect, self.near, self.far)
+    }
+
+    fn cam_pos(&self) -> Point3<f32> {
+        self.pos.cast().unwrap()
+    }
+
+    fn view_mat(&self) -> Matrix4<f32> {
+        let forward = self.rot.rotate_vector(vec3(0., 0., 1.));
+        let up = self.rot.rotate_vector(vec3(0., 1., 0.));
+        Matrix4::look_at_rh(self.pos, self.pos + forward, up)
+            .cast()
+            .unwrap()
+    }
+
+    fn view_dist(&self) -> (f32, f32) {
+        (self.near, self.far)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+    use std::cell::Cell;
+    use std::time::Duration;
+
+    #[test]
+    fn easing_keeps_endpoints() {
+        for easing in [
+            Easing::Linear,
+            Easing::EaseIn,
+            Easing::EaseOut,
+            Easing::EaseInOut,
+        ] {
+            assert_approx_eq!(easing.apply(0.), 0.);
+            assert_approx_eq!(easing.apply(1.), 1.);
+            assert_approx_eq!(easing.apply(2.), 1.);
+        }
+        assert_approx_eq!(Easing::EaseInOut.apply(0.5), 0.5);
+        assert!(Easing::EaseIn.apply(0.25) < 0.25);
+        assert!(Easing::EaseOut.apply(0.25) > 0.25);
+    }
+
+    #[test]
+    fn path_passes_through_keyframes() {
+        let up = vec3(0., 1., 0.);
+        let target = point3(0., 0., 0.);
+        let path = CameraPath::new(vec![
+            Keyframe::looking_at(2., point3(0., 0., 10.), target, up),
+            Keyframe::looking_at(0., point3(10., 0., 0.), target, up),
+            Keyframe::looking_at(1., point3(10., 5., 10.), target, up),
+        ])
+        .easing(Easing::Linear);
+        assert_approx_eq!(path.duration(), 2.);
+        let (pos, _) = path.sample(0.);
+        assert_approx_eq!(pos.x, 10.);
+        let (pos, rot) = path.sample(1.);
+        assert_approx_eq!(pos.y, 5.);
+        // the camera looks at the target
+        let forward = rot.rotate_vector(vec3(0., 0., 1.));
+        assert_approx_eq!(forward.dot((target - pos).normalize()), 1.);
+        let (pos, _) = path.sample(5.);
+        assert_approx_eq!(pos.z, 10.);
+        assert_approx_eq!(pos.x, 0.);
+    }
+
+    #[test]
+    fn sequence_calls_completion_once() {
+        let done = Rc::new(Cell::new(0));
+        let mut cam = CinematicCamera::new(1., 100.);
+        let counter = done.clone();
+        cam.play(
+            CameraPath::orbit(point3(0., 0., 0.), 10., 2., 1., 1.),
+            move || counter.set(counter.get() + 1),
+        );
+        cam.update(Duration::from_millis(500));
+        assert!(cam.is_playing());
+        assert_approx_eq!(cam.cam_pos().x, -10.);
+        cam.update(Duration::from_millis(600));
+        assert!(!cam.is_playing());
+        cam.update(Duration::from_millis(600));
+        cam.skip();
+        assert_eq!(done.get(), 1);
+    }
+}
@@ -0,0 +1,88 @@
+use std::cell::Cell;
+use std::time::Instant;
+
+/// The game clock, which runs at `scale` times the speed of real time
+///
+/// It stores the real and game time when the scale last changed, so game
+/// time is continuous when the scale changes
+#[derive(Clone, Copy)]
+struct Clock {
+    real: Instant,
+    game: Instant,
+    scale: f64,
+}
+
+impl Clock {
+    /// Gets the game time at the real time `real`
+    fn game_time(&self, real: Instant) -> Instant {
+        self.game
+            + real
+                .saturating_duration_since(self.real)
+                .mul_f64(self.scale)
+    }
+}
+
+std::thread_local! {
+    static CLOCK: Cell<Option<Clock>> = Cell::new(None);
+}
+
+/// Gets the `thread_local` game clock, starting it at the current time if it
+/// hasn't started
+fn clock() -> Clock {
+    CLOCK.with(|c| {
+        c.get().unwrap_or_else(|| {
+            let now = Instant::now();
+            let clock = Clock {
+                real: now,
+                game: now,
+                scale: 1.,
+            };
+            c.set(Some(clock));
+            clock
+        })
+    })
+}
+
+/// Gets the current game time
+///
+/// Game time runs slower or faster than real time by the time scale, so
+/// effects timed with it, such as particles and animations, slow down with
+/// the simulation
+pub fn now() -> Instant {
+    clock().game_time(Instant::now())
+}
+
+/// Sets how fast game time passes relative to real time. Negative scales are
+/// treated as `0`
+pub fn set_scale(scale: f64) {
+    let real = Instant::now();
+    let game = clock().game_time(real);
+    CLOCK.with(|c| {
+        c.set(Some(Clock {
+            real,
+            game,
+            scale: scale.max(0.),
+        }));
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn game_time_is_scaled_real_time() {
+        let start = Instant::now();
+        let clock = Clock {
+            real: start,
+            game: start + Duration::from_secs(1),
+            scale: 0.5,
+        };
+        assert_eq!(
+            clock.game_time(start + Duration::from_millis(200)),
+            start + Duration::from_millis(1100)
+        );
+        assert_eq!(clock.game_time(start), start + Duration::from_secs(1));
+    }
+}
@@ -1,8 +1,9 @@
 use super::drawable::*;
-use super::shader;
+use super::{entity, shader, volume};
+use crate::cg_support::node;
 use VertexPos as Vertex;
 
-const CUBE_VERTS: [Vertex; 8] = [
+pub(super) const CUBE_VERTS: [Vertex; 8] = [
     Vertex {
         pos: [-1.0, -1.0, 1.0],
     },
@@ -29,7 +30,7 @@ const CUBE_VERTS: [Vertex; 8] = [
     },
 ];
 
-const CUBE_INDICES: [u16; 36] = [
+pub(super) const CUBE_INDICES: [u16; 36] = [
     0, 1, 2, 2, 3, 0, 1, 5, 6, 6, 2, 1, 7, 6, 5, 5, 4, 7, 4, 0, 3, 3, 7, 4, 4,
     5, 1, 1, 0, 4, 3, 2, 6, 6, 7, 3,
 ];
@@ -192,11 +193,12 @@ pub fn gen_cubemap_from_sphere<F: glium::backend::Facade>(
         None,
         None,
     ));
-    let mut gen_sky_pass = RenderPass::new(
-        vec![gen_sky],
-        vec![cp],
-        Pipeline::new(vec![0], vec![(0, (1, 0))]),
-    );
+    let mut builder = PipelineBuilder::new("sky");
+    let gen_sky = builder.add_target("gen_sky", gen_sky);
+    let cp = builder.add_process("cp", cp);
+    builder.connect(gen_sky, cp, 0);
+    let mut gen_sky_pass =
+        builder.build().expect("Invalid sky generation pipeline");
     let sd = Rc::new(RefCell::new(shader::SceneData {
         viewer: viewer_data_from(&cam),
         pass_type: shader::RenderPassType::LayeredVisual,
@@ -236,7 +238,6 @@ pub struct DebugCube {
 }
 
 impl DebugCube {
-    #[allow(dead_code)]
     pub fn new<F: glium::backend::Facade>(facade: &F) -> Self {
         Self {
             vbo: glium::VertexBuffer::new(facade, &CUBE_VERTS).unwrap(),
@@ -282,12 +283,22 @@ pub struct Volumetric {
     vbo: glium::VertexBuffer<Vertex>,
     ebo: glium::IndexBuffer<u16>,
     vol: glium::texture::Texture3d,
+    /// Axis and speed, in degrees per second, the density rotates around
+    spin: (cgmath::Vector3<f32>, f32),
+    start: std::time::Instant,
 }
 
 impl Volumetric {
     /// Constructs a new cloud volume
     pub fn cloud<F: glium::backend::Facade>(tex_size: u32, facade: &F) -> Self {
-        use super::textures;
+        Self::from_generator(&volume::VolumeGenerator::nebula(tex_size), facade)
+    }
+
+    /// Constructs a volume whose density is generated by `generator`
+    pub fn from_generator<F: glium::backend::Facade>(
+        generator: &volume::VolumeGenerator,
+        facade: &F,
+    ) -> Self {
         Self {
             vbo: glium::VertexBuffer::new(facade, &CUBE_VERTS).unwrap(),
             ebo: glium::IndexBuffer::new(
@@ -296,11 +307,87 @@ impl Volumetric {
                 &CUBE_INDICES,
             )
             .unwrap(),
-            vol: textures::gen_cloud_noise_vol(
-                tex_size, tex_size, tex_size, facade,
-            ),
+            vol: generator.generate(facade),
+            spin: (cgmath::vec3(0., 1., 0.), 0.),
+            start: std::time::Instant::now(),
         }
     }
+
+    /// Slowly rotates the density of the volume around its center
+    ///
+    /// `axis` - the axis of rotation
+    ///
+    /// `deg_per_sec` - the speed of the rotation
+    pub fn with_spin(
+        mut self,
+        axis: cgmath::Vector3<f32>,
+        deg_per_sec: f32,
+    ) -> Self {
+        use cgmath::InnerSpace;
+        self.spin = (axis.normalize(), deg_per_sec);
+        self
+    }
+
+    /// Gets the current rotation of the volume's density
+    fn anim_rot(&self) -> [[f32; 3]; 3] {
+        let (axis, speed) = self.spin;
+        let angle = self.start.elapsed().as_secs_f32() * speed;
+        cgmath::Matrix3::from_axis_angle(axis, cgmath::Deg(angle)).into()
+    }
+}
+
+/// Constructs entities of animated volumetric clouds, such as nebulas
+pub struct CloudVolume {
+    generator: volume::VolumeGenerator,
+    spin: (cgmath::Vector3<f32>, f32),
+    locations: Vec<node::Node>,
+}
+
+impl CloudVolume {
+    /// `generator` - the generator of the density of each cloud
+    pub const fn new(generator: volume::VolumeGenerator) -> Self {
+        Self {
+            generator,
+            spin: (cgmath::vec3(0., 1., 0.), 0.),
+            locations: Vec::new(),
+        }
+    }
+
+    /// Slowly rotates the density of each cloud around its center
+    /// See `Volumetric::with_spin`
+    #[must_use]
+    pub const fn spin(
+        mut self,
+        axis: cgmath::Vector3<f32>,
+        deg_per_sec: f32,
+    ) -> Self {
+        self.spin = (axis, deg_per_sec);
+        self
+    }
+
+    /// Places a cloud at each location. The cloud fills the unit cube
+    /// of each location
+    #[must_use]
+    pub fn at_all(mut self, locations: &[node::Node]) -> Self {
+        self.locations.extend_from_slice(locations);
+        self
+    }
+
+    /// Generates the cloud volume and builds the entity of all clouds
+    pub fn build<F: glium::backend::Facade>(
+        self,
+        facade: &F,
+    ) -> entity::Entity {
+        let (axis, speed) = self.spin;
+        entity::EntityBuilder::new(
+            Volumetric::from_generator(&self.generator, facade)
+                .with_spin(axis, speed),
+        )
+        .with_pass(shader::RenderPassType::Visual)
+        .render_order(entity::RenderOrder::Last)
+        .at_all(&self.locations)
+        .build()
+    }
 }
 
 impl Drawable for Volumetric {
@@ -317,6 +404,7 @@ impl Drawable for Volumetric {
             let arg = shader::UniformInfo::Cloud(shader::CloudData {
                 model: *p,
                 volume: &self.vol,
+                anim_rot: self.anim_rot(),
             });
             out.push((
                 arg,
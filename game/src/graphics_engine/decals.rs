@@ -0,0 +1,352 @@
+use super::drawable::*;
+use super::entity::{AbstractEntity, RenderOrder};
+use super::{cubes, instancing, shader, textures};
+use crate::cg_support::{node, Transformation};
+use cgmath::*;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// Fraction of a decal's lifetime spent fading out
+const FADE_FRACTION: f64 = 0.2;
+
+/// A decal stored relative to the object it was placed on
+struct Decal {
+    /// Transformation from decal space to the local space of the target
+    local: Matrix4<f64>,
+    birth: Instant,
+}
+
+/// All decals placed on a single object, drawn with one instanced draw call
+struct DecalBatch {
+    target: Rc<RefCell<node::Node>>,
+    /// Decals ordered from oldest to newest
+    decals: VecDeque<Decal>,
+    instances: instancing::InstanceBuffer<instancing::DecalAttributes>,
+}
+
+/// The decals of every object, which ages out decals after a lifetime or
+/// once there are too many of them
+struct DecalBatches {
+    batches: Vec<DecalBatch>,
+    lifetime: Duration,
+    max_decals: usize,
+}
+
+impl DecalBatches {
+    const fn new() -> Self {
+        Self {
+            batches: Vec::new(),
+            lifetime: Duration::from_secs(20),
+            max_decals: 128,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.batches.iter().map(|b| b.decals.len()).sum()
+    }
+
+    /// Adds a decal to the batch of `target`, removing the oldest decal if
+    /// there are too many
+    fn add(
+        &mut self,
+        target: &Rc<RefCell<node::Node>>,
+        world: Matrix4<f64>,
+        now: Instant,
+    ) {
+        let local = target
+            .borrow()
+            .mat()
+            .invert()
+            .map_or(world, |inv_target| inv_target * world);
+        let decal = Decal { local, birth: now };
+        if let Some(batch) = self
+            .batches
+            .iter_mut()
+            .find(|b| Rc::ptr_eq(&b.target, target))
+        {
+            batch.decals.push_back(decal);
+        } else {
+            self.batches.push(DecalBatch {
+                target: target.clone(),
+                decals: std::iter::once(decal).collect(),
+                instances: instancing::InstanceBuffer::new(),
+            });
+        }
+        while self.len() > self.max_decals {
+            let oldest = self
+                .batches
+                .iter_mut()
+                .filter(|b| !b.decals.is_empty())
+                .min_by_key(|b| b.decals[0].birth);
+            if let Some(batch) = oldest {
+                batch.decals.pop_front();
+            }
+        }
+        self.batches.retain(|b| !b.decals.is_empty());
+    }
+
+    /// Removes all decals that have outlived their lifetime
+    fn expire(&mut self, now: Instant) {
+        let lifetime = self.lifetime;
+        for batch in &mut self.batches {
+            batch.decals.retain(|decal| {
+                now.saturating_duration_since(decal.birth) < lifetime
+            });
+        }
+        self.batches.retain(|b| !b.decals.is_empty());
+    }
+
+    /// Gets the opacity of a decal of age `age`. Decals are opaque until
+    /// they start fading out at the end of their lifetime
+    fn alpha(&self, age: Duration) -> f32 {
+        let t = age.as_secs_f64() / self.lifetime.as_secs_f64();
+        ((1. - t) / FADE_FRACTION).clamp(0., 1.) as f32
+    }
+}
+
+/// Textures projected onto the surfaces of objects, such as laser scorch
+/// marks. Decals follow the object they were placed on
+///
+/// Decals are rendered after opaque geometry and reconstruct the surface
+/// they are projected onto from the depth buffer
+pub struct DecalSystem {
+    decals: DecalBatches,
+    texture: glium::texture::SrgbTexture2d,
+    color: [f32; 4],
+    size: f64,
+    vbo: glium::VertexBuffer<VertexPos>,
+    ebo: glium::IndexBuffer<u16>,
+}
+
+impl DecalSystem {
+    /// Creates a new decal system
+    ///
+    /// `tex_path` - path to the decal texture. The texture's alpha is used as a
+    /// mask for the decal's color
+    pub fn new<F: glium::backend::Facade>(tex_path: &str, facade: &F) -> Self {
+        Self {
+            decals: DecalBatches::new(),
+            texture: textures::load_texture_srgb(tex_path, facade),
+            color: [0.02, 0.015, 0.01, 0.9],
+            size: 2.,
+            vbo: glium::VertexBuffer::new(facade, &cubes::CUBE_VERTS).unwrap(),
+            ebo: glium::IndexBuffer::new(
+                facade,
+                glium::index::PrimitiveType::TrianglesList,
+                &cubes::CUBE_INDICES,
+            )
+            .unwrap(),
+        }
+    }
+
+    /// Sets how long a decal lasts before it is removed
+    #[must_use]
+    pub const fn with_lifetime(mut self, lifetime: Duration) -> Self {
+        self.decals.lifetime = lifetime;
+        self
+    }
+
+    /// Sets the maximum amount of decals. Once exceeded, the oldest decals are
+    /// removed first
+    #[must_use]
+    #[allow(dead_code)]
+    pub const fn with_max_decals(mut self, max_decals: usize) -> Self {
+        self.decals.max_decals = max_decals;
+        self
+    }
+
+    /// Sets the width of each decal in world units
+    #[must_use]
+    pub const fn with_size(mut self, size: f64) -> Self {
+        self.size = size;
+        self
+    }
+
+    /// Sets the color, in linear space, the decal texture is tinted with
+    #[must_use]
+    #[allow(dead_code)]
+    pub const fn with_color(mut self, color: [f32; 4]) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Places a decal on `target`
+    ///
+    /// `pos` - world space point on the surface of `target`
+    ///
+    /// `normal` - world space surface normal at `pos`, which the decal is
+    /// projected along
+    pub fn add_decal(
+        &mut self,
+        target: &Rc<RefCell<node::Node>>,
+        pos: Point3<f64>,
+        normal: Vector3<f64>,
+    ) {
+        use rand::Rng;
+        if normal.magnitude2() < f64::EPSILON {
+            return;
+        }
+        let roll =
+            Rad(rand::thread_rng().gen_range(0. ..std::f64::consts::TAU));
+        let rot =
+            Quaternion::from_arc(vec3(0., 0., 1.), normal.normalize(), None)
+                * Quaternion::from_angle_z(roll);
+        let half_size = self.size * 0.5;
+        let world = Matrix4::from_translation(pos.to_vec())
+            * Matrix4::from(rot)
+            * Matrix4::from_nonuniform_scale(
+                half_size,
+                half_size,
+                half_size * 0.5,
+            );
+        self.decals.add(target, world, Instant::now());
+    }
+}
+
+impl Drawable for DecalSystem {
+    fn render_args<'a>(
+        &'a mut self,
+        _: &[[[f32; 4]; 4]],
+    ) -> Vec<(
+        shader::UniformInfo,
+        VertexHolder<'a>,
+        glium::index::IndicesSource<'a>,
+    )> {
+        let now = Instant::now();
+        self.decals.expire(now);
+        let ctx = super::get_active_ctx();
+        let facade = ctx.ctx.borrow();
+        for idx in 0..self.decals.batches.len() {
+            let batch = &self.decals.batches[idx];
+            let target = batch.target.borrow().mat();
+            let data: Vec<_> = batch
+                .decals
+                .iter()
+                .map(|decal| {
+                    let model: [[f32; 4]; 4] =
+                        (target * decal.local).cast::<f32>().unwrap().into();
+                    instancing::DecalAttributes {
+                        instance_model_col0: model[0],
+                        instance_model_col1: model[1],
+                        instance_model_col2: model[2],
+                        instance_model_col3: model[3],
+                        instance_alpha: self
+                            .decals
+                            .alpha(now.saturating_duration_since(decal.birth)),
+                    }
+                })
+                .collect();
+            self.decals.batches[idx]
+                .instances
+                .update_buffer(&data, &*facade);
+        }
+        self.decals
+            .batches
+            .iter()
+            .filter_map(|batch| batch.instances.get_stored_buffer())
+            .map(|instances| {
+                (
+                    shader::UniformInfo::Decal(&self.texture, self.color),
+                    VertexHolder::new(VertexSourceData::Single(From::from(
+                        &self.vbo,
+                    )))
+                    .append(From::from(instances.per_instance().unwrap())),
+                    From::from(&self.ebo),
+                )
+            })
+            .collect()
+    }
+
+    fn transparency(&self) -> Option<f32> {
+        None
+    }
+}
+
+impl AbstractEntity for DecalSystem {
+    fn transformations(&self) -> Option<&[Rc<RefCell<dyn Transformation>>]> {
+        None
+    }
+
+    fn drawable(&mut self) -> &mut dyn Drawable {
+        self
+    }
+
+    fn should_render(&self, pass: shader::RenderPassType) -> bool {
+        pass == shader::RenderPassType::Visual
+    }
+
+    fn render_order(&self) -> RenderOrder {
+        RenderOrder::Last
+    }
+
+    fn get_id(&self) -> usize {
+        self as *const Self as usize
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decals_batch_per_target() {
+        let mut decals = DecalBatches::new();
+        let a = Rc::new(RefCell::new(node::Node::default()));
+        let b = Rc::new(RefCell::new(node::Node::default()));
+        let now = Instant::now();
+        decals.add(&a, Matrix4::from_scale(1.), now);
+        decals.add(&b, Matrix4::from_scale(1.), now);
+        decals.add(&a, Matrix4::from_scale(1.), now);
+        assert_eq!(decals.batches.len(), 2);
+        assert_eq!(decals.len(), 3);
+    }
+
+    #[test]
+    fn decals_are_stored_relative_to_target() {
+        let mut decals = DecalBatches::new();
+        let target = Rc::new(RefCell::new(
+            node::Node::default().pos(point3(10., 0., 0.)),
+        ));
+        decals.add(
+            &target,
+            Matrix4::from_translation(vec3(10., 5., 0.)),
+            Instant::now(),
+        );
+        let local = decals.batches[0].decals[0]
+            .local
+            .transform_point(point3(0., 0., 0.));
+        assert_eq!(local, point3(0., 5., 0.));
+    }
+
+    #[test]
+    fn decals_age_out() {
+        let mut decals = DecalBatches::new();
+        decals.lifetime = Duration::from_secs(10);
+        decals.max_decals = 2;
+        let target = Rc::new(RefCell::new(node::Node::default()));
+        let start = Instant::now();
+        for i in 0..3 {
+            decals.add(
+                &target,
+                Matrix4::from_scale(1.),
+                start + Duration::from_secs(i),
+            );
+        }
+        assert_eq!(decals.len(), 2);
+        assert_eq!(
+            decals.batches[0].decals[0].birth,
+            start + Duration::from_secs(1)
+        );
+
+        decals.expire(start + Duration::from_secs(11));
+        assert_eq!(decals.len(), 1);
+        decals.expire(start + Duration::from_secs(12));
+        assert!(decals.batches.is_empty());
+
+        assert!((decals.alpha(Duration::from_secs(1)) - 1.).abs() < 0.0001);
+        assert!((decals.alpha(Duration::from_secs(9)) - 0.5).abs() < 0.0001);
+        assert!(decals.alpha(Duration::from_secs(10)).abs() < 0.0001);
+    }
+}
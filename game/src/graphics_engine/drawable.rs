@@ -175,6 +175,26 @@ pub trait Drawable {
     /// Gets the transparency of the Drawable from `0` indicating opaque to `1` indicating transparent
     /// or `None` if the drawable is opaque
     fn transparency(&self) -> Option<f32>;
+
+    /// Chooses the level of detail to render the drawable with at each of `positions` when seen by
+    /// `viewer`. Drawables without levels of detail ignore this
+    fn select_lod(
+        &mut self,
+        _positions: &[[[f32; 4]; 4]],
+        _viewer: &shader::ViewerData,
+    ) {
+    }
+
+    /// Renders what the drawable needs before it's drawn at `positions` during a visual pass, such as the
+    /// images of its impostors
+    fn prepare(
+        &mut self,
+        _positions: &[[[f32; 4]; 4]],
+        _scene_data: &shader::SceneData,
+        _cache: &shader::PipelineCache,
+        _shader: &shader::ShaderManager,
+    ) {
+    }
 }
 
 /// Something that encapsulates control of a view of the scene
@@ -190,8 +210,54 @@ pub trait Viewer {
 
     /// Gets the viewer's near and far plane as a tuple
     fn view_dist(&self) -> (f32, f32);
+
+    /// Gets the corners of the viewer's frustum in view space, ordered top
+    /// left, top right, bottom right, bottom left on the near plane then on
+    /// the far plane
+    ///
+    /// By default the corners are found by inverting the projection matrix,
+    /// which works for any projection, including orthographic and
+    /// asymmetric ones
+    fn frustum_corners(&self) -> [cgmath::Point3<f32>; 8] {
+        use cgmath::{SquareMatrix, Transform};
+        let inv = self.proj_mat().invert().unwrap();
+        NDC_CORNERS.map(|pt| inv.transform_point(pt))
+    }
+
+    /// Gets the planes bounding the viewer's frustum in world space, ordered
+    /// left, right, bottom, top, near, far. Each plane is `(a, b, c, d)`
+    /// such that `ax + by + cz + d` is the distance of a point in front of
+    /// the plane, and the normal `(a, b, c)` points into the frustum
+    #[allow(dead_code)]
+    fn frustum_planes(&self) -> [cgmath::Vector4<f32>; 6] {
+        use cgmath::{InnerSpace, Matrix};
+        // the rows of the view projection matrix
+        let rows = (self.proj_mat() * self.view_mat()).transpose();
+        [
+            rows.w + rows.x,
+            rows.w - rows.x,
+            rows.w + rows.y,
+            rows.w - rows.y,
+            rows.w + rows.z,
+            rows.w - rows.z,
+        ]
+        .map(|plane| plane / plane.truncate().magnitude())
+    }
 }
 
+/// Corners of the normalized device coordinate cube, in the order of
+/// `Viewer::frustum_corners`
+const NDC_CORNERS: [cgmath::Point3<f32>; 8] = [
+    cgmath::Point3::new(-1., 1., -1.),
+    cgmath::Point3::new(1., 1., -1.),
+    cgmath::Point3::new(1., -1., -1.),
+    cgmath::Point3::new(-1., -1., -1.),
+    cgmath::Point3::new(-1., 1., 1.),
+    cgmath::Point3::new(1., 1., 1.),
+    cgmath::Point3::new(1., -1., 1.),
+    cgmath::Point3::new(-1., -1., 1.),
+];
+
 /// Constructs shader viewer matrices from a viewer
 pub fn viewer_data_from(viewer: &dyn Viewer) -> shader::ViewerData {
     let view = viewer.view_mat();
@@ -204,6 +270,24 @@ pub fn viewer_data_from(viewer: &dyn Viewer) -> shader::ViewerData {
     }
 }
 
+/// Projects the world space point `pt` onto the screen of `viewer`
+///
+/// Returns the point in normalized device coordinates, or `None` if the
+/// point is behind the viewer
+pub fn project_to_ndc(
+    viewer: &dyn Viewer,
+    pt: cgmath::Point3<f64>,
+) -> Option<cgmath::Point2<f64>> {
+    use cgmath::EuclideanSpace;
+    let clip = (viewer.proj_mat() * viewer.view_mat()).cast::<f64>()?
+        * pt.to_homogeneous();
+    if clip.w > f64::EPSILON {
+        Some(cgmath::point2(clip.x / clip.w, clip.y / clip.w))
+    } else {
+        None
+    }
+}
+
 /// Gets the default scene data filled with the relevant matrices according to
 /// `viewer` and the aspect ratio `aspect`.
 ///
@@ -232,7 +316,20 @@ pub fn render_drawable<S: glium::Surface>(
     shader: &shader::ShaderManager,
 ) {
     let v = vec![cgmath::Matrix4::from_scale(1f32).into()];
-    for (args, vbo, ebo) in drawable.render_args(matrices.unwrap_or(&v)) {
+    let positions = matrices.unwrap_or(&v);
+    // other passes reuse the level of detail chosen for the camera, so an
+    // object's shadow matches what's seen
+    if scene_data.pass_type == shader::RenderPassType::Visual {
+        drawable.select_lod(positions, &scene_data.viewer);
+        drawable.prepare(positions, scene_data, cache, shader);
+    }
+    for (args, vbo, ebo) in drawable.render_args(positions) {
+        // impostors only stand in for geometry as the camera sees it
+        if matches!(args, shader::UniformInfo::Impostor(_))
+            && scene_data.pass_type != shader::RenderPassType::Visual
+        {
+            continue;
+        }
         let (shader, params, uniform) =
             shader.use_shader(&args, Some(scene_data), Some(cache));
         match uniform {
@@ -260,11 +357,15 @@ pub fn render_drawable<S: glium::Surface>(
             shader::UniformType::ExtractBright(uniform) => {
                 surface.draw(vbo, ebo, shader, &uniform, &params)
             }
+            shader::UniformType::ProceduralSky(uniform) => {
+                surface.draw(vbo, ebo, shader, &uniform, &params)
+            }
             shader::UniformType::PrefilterHdrEnv(uniform) => {
                 surface.draw(vbo, ebo, shader, &uniform, &params)
             }
             shader::UniformType::BrdfLut(uniform)
-            | shader::UniformType::Line(uniform) => {
+            | shader::UniformType::Line(uniform)
+            | shader::UniformType::Radar(uniform) => {
                 surface.draw(vbo, ebo, shader, &uniform, &params)
             }
             shader::UniformType::Billboard(uniform) => {
@@ -282,9 +383,27 @@ pub fn render_drawable<S: glium::Surface>(
             shader::UniformType::Minimap(uniform) => {
                 surface.draw(vbo, ebo, shader, &uniform, &params)
             }
+            shader::UniformType::HitIndicator(uniform) => {
+                surface.draw(vbo, ebo, shader, &uniform, &params)
+            }
             shader::UniformType::Icon(uniform) => {
                 surface.draw(vbo, ebo, shader, &uniform, &params)
             }
+            shader::UniformType::Decal(uniform) => {
+                surface.draw(vbo, ebo, shader, &uniform, &params)
+            }
+            shader::UniformType::Trail(uniform) => {
+                surface.draw(vbo, ebo, shader, &uniform, &params)
+            }
+            shader::UniformType::Portal(uniform) => {
+                surface.draw(vbo, ebo, shader, &uniform, &params)
+            }
+            shader::UniformType::Impostor(uniform) => {
+                surface.draw(vbo, ebo, shader, &uniform, &params)
+            }
+            shader::UniformType::Atmosphere(uniform) => {
+                surface.draw(vbo, ebo, shader, &uniform, &params)
+            }
         }
         .unwrap();
     }
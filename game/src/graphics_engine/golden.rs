@@ -0,0 +1,483 @@
+// the harness is only used by the GL tests, which are behind a feature
+#![cfg_attr(not(feature = "gl-tests"), allow(dead_code))]
+use image::{Rgba, RgbaImage};
+use std::path::{Path, PathBuf};
+
+/// Directory of the golden images, relative to the crate
+const GOLDEN_DIR: &str = "tests/golden";
+/// Set to rewrite the golden images with the images that are rendered
+const UPDATE_VAR: &str = "UPDATE_GOLDEN";
+/// Perceived difference above which two pixels are different, from `0` to
+/// `1`. Small enough to notice a changed material, but large enough to
+/// ignore differences in precision between drivers
+const PIXEL_THRESHOLD: f64 = 0.1;
+/// Fraction of pixels which may differ before an image doesn't match
+const MAX_MISMATCHED: f64 = 0.005;
+
+/// How much a rendered image differs from its golden image
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct ImageDiff {
+    /// Pixels whose difference is above the threshold
+    pub mismatched: usize,
+    pub pixels: usize,
+    /// Largest difference of any pixel, from `0` to `1`
+    pub max_delta: f64,
+}
+
+impl ImageDiff {
+    pub fn mismatched_fraction(&self) -> f64 {
+        if self.pixels == 0 {
+            0.
+        } else {
+            self.mismatched as f64 / self.pixels as f64
+        }
+    }
+}
+
+/// Converts `color`, blended over black, to the YIQ color space, whose
+/// luma and chroma are closer to how people perceive color than RGB
+fn yiq(color: Rgba<u8>) -> (f64, f64, f64) {
+    let [r, g, b, a] = color.0.map(f64::from);
+    let (r, g, b) = (r * a / 255., g * a / 255., b * a / 255.);
+    (
+        0.298_895_31_f64
+            .mul_add(r, 0.586_622_47_f64.mul_add(g, 0.114_482_23 * b)),
+        0.595_977_99_f64
+            .mul_add(r, (-0.274_176_1_f64).mul_add(g, -0.321_801_89 * b)),
+        0.211_470_17_f64
+            .mul_add(r, (-0.522_617_11_f64).mul_add(g, 0.311_146_94 * b)),
+    )
+}
+
+/// Perceived difference between two colors, from `0` to `1`, weighing
+/// brightness more than hue, like pixelmatch
+fn color_delta(a: Rgba<u8>, b: Rgba<u8>) -> f64 {
+    /// Largest weighted distance between two YIQ colors
+    const MAX_DELTA: f64 = 35_215.;
+    let ((ya, ia, qa), (yb, ib, qb)) = (yiq(a), yiq(b));
+    let (y, i, q) = (ya - yb, ia - ib, qa - qb);
+    let delta =
+        0.5053_f64.mul_add(y * y, 0.299_f64.mul_add(i * i, 0.1957 * q * q));
+    (delta / MAX_DELTA).sqrt()
+}
+
+/// Compares `actual` to `expected`, counting the pixels whose perceived
+/// difference is above `threshold`
+///
+/// Returns the difference and an image of it, which shows the mismatched
+/// pixels in red over a faded copy of `expected`, or `None` if the images
+/// aren't the same size
+pub fn diff(
+    expected: &RgbaImage,
+    actual: &RgbaImage,
+    threshold: f64,
+) -> Option<(ImageDiff, RgbaImage)> {
+    if expected.dimensions() != actual.dimensions() {
+        return None;
+    }
+    let mut result = ImageDiff {
+        mismatched: 0,
+        pixels: (expected.width() * expected.height()) as usize,
+        max_delta: 0.,
+    };
+    let mut image = RgbaImage::new(expected.width(), expected.height());
+    for ((x, y, expected), actual) in
+        expected.enumerate_pixels().zip(actual.pixels())
+    {
+        let delta = color_delta(*expected, *actual);
+        result.max_delta = result.max_delta.max(delta);
+        let pixel = if delta > threshold {
+            result.mismatched += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            let (luma, _, _) = yiq(*expected);
+            let faded = (luma - 255.).mul_add(0.1, 255.) as u8;
+            Rgba([faded, faded, faded, 255])
+        };
+        image.put_pixel(x, y, pixel);
+    }
+    Some((result, image))
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    Path::new(GOLDEN_DIR).join(format!("{}.png", name))
+}
+
+/// Checks that `actual` matches the golden image `name`. If it doesn't, the
+/// rendered image and an image of the difference are written to the temp
+/// directory
+///
+/// When the `UPDATE_GOLDEN` environment variable is set, `actual` replaces
+/// the golden image instead
+///
+/// Panics if the images don't match or there's no golden image
+pub fn assert_matches_golden(name: &str, actual: &RgbaImage) {
+    let path = golden_path(name);
+    if std::env::var_os(UPDATE_VAR).is_some() {
+        std::fs::create_dir_all(GOLDEN_DIR).unwrap();
+        actual.save(&path).unwrap();
+        eprintln!("Updated golden image {}", path.display());
+        return;
+    }
+    let expected = image::open(&path)
+        .unwrap_or_else(|e| {
+            panic!(
+                "No golden image {} ({}). Run with {}=1 to create it",
+                path.display(),
+                e,
+                UPDATE_VAR
+            )
+        })
+        .to_rgba8();
+    let out_dir = std::env::temp_dir().join("oort-golden");
+    let save_failure = |diff_image: Option<&RgbaImage>| {
+        std::fs::create_dir_all(&out_dir).unwrap();
+        actual.save(out_dir.join(format!("{}.png", name))).unwrap();
+        if let Some(diff_image) = diff_image {
+            diff_image
+                .save(out_dir.join(format!("{}.diff.png", name)))
+                .unwrap();
+        }
+    };
+    match diff(&expected, actual, PIXEL_THRESHOLD) {
+        None => {
+            save_failure(None);
+            panic!(
+                "{} is {:?}, but its golden image is {:?}. Output written to {}",
+                name,
+                actual.dimensions(),
+                expected.dimensions(),
+                out_dir.display()
+            );
+        }
+        Some((diff, image)) if diff.mismatched_fraction() > MAX_MISMATCHED => {
+            save_failure(Some(&image));
+            panic!(
+                "{} differs from its golden image in {} of {} pixels \
+                (max difference {:.3}). Output written to {}",
+                name,
+                diff.mismatched,
+                diff.pixels,
+                diff.max_delta,
+                out_dir.display()
+            );
+        }
+        Some(_) => (),
+    }
+}
+
+mod test {
+    use super::*;
+
+    fn filled(color: [u8; 4]) -> RgbaImage {
+        RgbaImage::from_pixel(10, 10, Rgba(color))
+    }
+
+    #[test]
+    fn perceived_difference_is_normalized() {
+        let (black, white) = (Rgba([0, 0, 0, 255]), Rgba([255; 4]));
+        assert!(color_delta(black, black).abs() < 1e-9);
+        assert!((color_delta(black, white) - 1.).abs() < 1e-3);
+        // brightness matters more than hue
+        let (gray, red) =
+            (Rgba([100, 100, 100, 255]), Rgba([110, 100, 100, 255]));
+        let lighter = Rgba([110, 110, 110, 255]);
+        assert!(color_delta(gray, lighter) > color_delta(gray, red));
+    }
+
+    #[test]
+    fn diff_counts_mismatched_pixels() {
+        let expected = filled([40, 80, 120, 255]);
+        let mut actual = expected.clone();
+        // too small a change to notice
+        actual.put_pixel(0, 0, Rgba([41, 80, 121, 255]));
+        actual.put_pixel(5, 5, Rgba([255, 255, 255, 255]));
+        let (diff, image) = diff(&expected, &actual, PIXEL_THRESHOLD).unwrap();
+        assert_eq!(diff.mismatched, 1);
+        assert_eq!(diff.pixels, 100);
+        assert!((diff.mismatched_fraction() - 0.01).abs() < 1e-9);
+        assert!(diff.max_delta > PIXEL_THRESHOLD);
+        assert_eq!(*image.get_pixel(5, 5), Rgba([255, 0, 0, 255]));
+        assert_ne!(*image.get_pixel(0, 0), Rgba([255, 0, 0, 255]));
+
+        assert!(super::diff(&expected, &RgbaImage::new(5, 5), 0.1).is_none());
+    }
+}
+
+/// Renders canned scenes through the main pipelines offscreen and compares
+/// them to their golden images. Needs a GL 4.3 context, so they only run
+/// with `cargo test --features gl-tests`
+#[cfg(feature = "gl-tests")]
+mod gl_test {
+    use super::super::pipeline::*;
+    use super::super::*;
+    use super::*;
+    use crate::cg_support::node::Node;
+    use cgmath::*;
+    use serial_test::serial;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    const WIDTH: u32 = 128;
+    const HEIGHT: u32 = 128;
+
+    #[cfg(unix)]
+    fn get_event_loop() -> glutin::event_loop::EventLoop<()> {
+        use glutin::platform::unix::EventLoopExtUnix;
+        glutin::event_loop::EventLoop::new_any_thread()
+    }
+
+    #[cfg(windows)]
+    fn get_event_loop() -> glutin::event_loop::EventLoop<()> {
+        use glutin::platform::windows::EventLoopExtWindows;
+        glutin::event_loop::EventLoop::new_any_thread()
+    }
+
+    /// Creates a context whose window is never shown, since everything is
+    /// rendered to textures, and makes it the active context
+    fn init() -> (Rc<RefCell<glium::Display>>, Rc<shader::ShaderManager>) {
+        use glutin::window::WindowBuilder;
+        use glutin::ContextBuilder;
+        let e_loop = get_event_loop();
+        let window_builder = WindowBuilder::new()
+            .with_visible(false)
+            .with_inner_size(glium::glutin::dpi::PhysicalSize::<u32> {
+                width: WIDTH,
+                height: HEIGHT,
+            });
+        let wnd_ctx =
+            glium::Display::new(window_builder, ContextBuilder::new(), &e_loop)
+                .unwrap();
+        gl::load_with(|s| wnd_ctx.gl_window().get_proc_address(s));
+        let shader = Rc::new(shader::ShaderManager::init(&wnd_ctx));
+        let ctx = Rc::new(RefCell::new(wnd_ctx));
+        set_active_ctx(ctx.clone(), shader.clone());
+        (ctx, shader)
+    }
+
+    /// Adds the stages every lit pass starts with: the depth prepass, the
+    /// light culling which needs it, and the render target which shades
+    /// the scene with both
+    ///
+    /// Returns the builder, the render target and the light culling
+    fn lit_pass(
+        name: &'static str,
+        ctx: &glium::Display,
+    ) -> (PipelineBuilder, StageHandle, StageHandle) {
+        let mut builder = PipelineBuilder::new(name);
+        let depth = builder.add_target(
+            "depth",
+            Box::new(render_target::DepthRenderTarget::new(
+                WIDTH, HEIGHT, false,
+            )),
+        );
+        let msaa = builder.add_target(
+            "msaa",
+            Box::new(render_target::MsaaRenderTarget::new(
+                4, WIDTH, HEIGHT, ctx,
+            )),
+        );
+        let cull_lights = builder.add_process(
+            "cull_lights",
+            Box::new(texture_processor::CullLightProcessor::new(
+                WIDTH, HEIGHT, 16,
+            )),
+        );
+        let cam_depth = builder.add_process(
+            "cam_depth_to_cache",
+            Box::new(texture_processor::ToCacheProcessor::for_slot(
+                shader::CAM_DEPTH,
+            )),
+        );
+        builder
+            .connect(depth, cull_lights, 0)
+            .connect(depth, cam_depth, 0)
+            .connect(cam_depth, msaa, 0)
+            .connect(cull_lights, msaa, 1);
+        (builder, msaa, cull_lights)
+    }
+
+    /// Adds bloom after `msaa`, whose output is composited with the blurred
+    /// bright parts of its output
+    fn add_bloom(
+        builder: &mut PipelineBuilder,
+        msaa: StageHandle,
+        ctx: &glium::Display,
+    ) {
+        let extract = builder.add_process(
+            "extract_bright",
+            Box::new(texture_processor::ExtractBrightProcessor::new(
+                ctx, WIDTH, HEIGHT,
+            )),
+        );
+        let blur = builder.add_process(
+            "blur",
+            Box::new(texture_processor::SepConvProcessor::new(
+                WIDTH, HEIGHT, 10, ctx,
+            )),
+        );
+        let compose = builder.add_process(
+            "compose",
+            Box::new(texture_processor::CompositorProcessor::new(
+                WIDTH,
+                HEIGHT,
+                shader::BlendFn::Add,
+                ctx,
+            )),
+        );
+        builder
+            .connect(msaa, extract, 0)
+            .connect(extract, blur, 0)
+            .connect(msaa, compose, 0)
+            .connect(blur, compose, 1);
+    }
+
+    /// Adds three shadow cascades of `cam`'s frustum, which are cached
+    /// before `msaa` renders
+    fn add_cascades(
+        builder: &mut PipelineBuilder,
+        msaa: StageHandle,
+        cull_lights: StageHandle,
+        cam: &camera::PerspectiveCamera,
+    ) {
+        let to_cache = builder.add_process(
+            "cascades_to_cache",
+            Box::new(texture_processor::ToCacheProcessor::for_slot(
+                shader::CASCADES,
+            )),
+        );
+        let splits = camera::practical_splits(cam.near, 40., 3, 0.5);
+        let mut near = cam.near;
+        for (slot, (far, name)) in splits
+            .iter()
+            .zip(["cascade_1", "cascade_2", "cascade_3"])
+            .enumerate()
+        {
+            let (far, cam) = (*far, cam.clone());
+            let cascade = builder.add_target(
+                name,
+                Box::new(render_target::CustomViewRenderTargetDecorator::new(
+                    render_target::DepthRenderTarget::new_cascade(
+                        512, 512, true,
+                    ),
+                    move |_| {
+                        camera::get_cascade(&cam, light_dir(), near, far, 512)
+                    },
+                )),
+            );
+            builder
+                .connect(cull_lights, cascade, 0)
+                .connect(cascade, to_cache, slot);
+            near = far;
+        }
+        builder.connect(to_cache, msaa, 2);
+    }
+
+    fn light_dir() -> Vector3<f32> {
+        vec3(-1., 1., -0.5)
+    }
+
+    fn canned_camera() -> camera::PerspectiveCamera {
+        camera::PerspectiveCamera {
+            cam: point3(0., 3., -8.),
+            target: point3(0., 0., 0.),
+            far: 100.,
+            ..camera::PerspectiveCamera::default(WIDTH as f32 / HEIGHT as f32)
+        }
+    }
+
+    /// A seeded sky lighting an asteroid, which casts a shadow onto a
+    /// second asteroid behind it
+    fn canned_scene(
+        pass: RenderPass,
+        ctx: &glium::Display,
+        shader: &shader::ShaderManager,
+    ) -> scene::Scene {
+        let mut sky = sky::ProceduralSky::space(7)
+            .size(64, 3)
+            .generate(shader, ctx);
+        let ibl = scene::gen_ibl_from_sky(&mut sky, shader, ctx);
+        let asteroids = entity::EntityBuilder::new(model::Model::new(
+            "assets/asteroid1/Asteroid.obj",
+            ctx,
+        ))
+        .at(Node::default().u_scale(0.5))
+        .at(Node::default().pos(point3(1.5, -1., 4.)).u_scale(0.8))
+        .with_pass(shader::RenderPassType::Visual)
+        .with_pass(shader::RenderPassType::Depth)
+        .build();
+        let mut scene =
+            scene::Scene::new(pass, Rc::new(RefCell::new(canned_camera())));
+        scene.set_ibl_maps(Rc::new(ibl));
+        scene.set_light_dir(light_dir());
+        scene.set_entities(vec![
+            Rc::new(RefCell::new(sky.into_entity())),
+            Rc::new(RefCell::new(asteroids)),
+        ]);
+        scene
+    }
+
+    /// Renders the canned scene through the pass `make_pass` builds, and
+    /// reads back its output
+    fn render(
+        make_pass: impl FnOnce(&glium::Display) -> RenderPass,
+    ) -> RgbaImage {
+        use scene::AbstractScene;
+        let (ctx, shader) = init();
+        let image = {
+            let display = ctx.borrow();
+            let mut scene =
+                canned_scene(make_pass(&*display), &*display, &*shader);
+            match scene.render(None, &*shader) {
+                Some(TextureType::Tex2d(tex)) => {
+                    let raw: glium::texture::RawImage2d<u8> =
+                        tex.to_ref().read();
+                    let image = RgbaImage::from_raw(
+                        raw.width,
+                        raw.height,
+                        raw.data.into_owned(),
+                    )
+                    .unwrap();
+                    // textures start at the bottom row, images at the top
+                    image::imageops::flip_vertical(&image)
+                }
+                _ => panic!("The pass didn't output a 2d texture"),
+            }
+        };
+        remove_ctx_if_active(&ctx);
+        image
+    }
+
+    #[test]
+    #[serial]
+    fn pbr_matches_golden() {
+        let image = render(|ctx| {
+            let (builder, _, _) = lit_pass("pbr", ctx);
+            builder.build().unwrap()
+        });
+        assert_matches_golden("pbr", &image);
+    }
+
+    #[test]
+    #[serial]
+    fn pbr_bloom_matches_golden() {
+        let image = render(|ctx| {
+            let (mut builder, msaa, _) = lit_pass("pbr_bloom", ctx);
+            add_bloom(&mut builder, msaa, ctx);
+            builder.build().unwrap()
+        });
+        assert_matches_golden("pbr_bloom", &image);
+    }
+
+    #[test]
+    #[serial]
+    fn shadow_cascades_match_golden() {
+        let image = render(|ctx| {
+            let (mut builder, msaa, cull_lights) =
+                lit_pass("shadow_cascades", ctx);
+            add_cascades(&mut builder, msaa, cull_lights, &canned_camera());
+            builder.build().unwrap()
+        });
+        assert_matches_golden("shadow_cascades", &image);
+    }
+}
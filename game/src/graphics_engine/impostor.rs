@@ -0,0 +1,542 @@
+use super::billboard::{RECT_INDICES, RECT_VERTS};
+use super::drawable::*;
+use super::instancing::{BillboardAttributes, InstanceBuffer};
+use super::shader;
+use cgmath::*;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Width and height of the image of each impostor, in pixels
+const IMPOSTOR_SIZE: u32 = 64;
+/// Most impostors which can have an image at once
+const MAX_IMPOSTORS: u32 = 256;
+/// Width of the cubes of the world space grid that far away copies of a
+/// model are clustered by
+const CLUSTER_SIZE: f32 = 100.;
+/// Angle between the directions an impostor is seen from and its image was
+/// captured from past which its image is captured again
+const REFRESH_ANGLE: Deg<f32> = Deg(3.);
+/// Longest time the image of an impostor is shown before it's captured
+/// again, so the image keeps up with the objects moving and rotating in it
+const MAX_IMAGE_AGE: Duration = Duration::from_secs(1);
+/// Most images captured in one frame
+const MAX_CAPTURES_PER_FRAME: usize = 16;
+
+/// A sphere in world space
+#[derive(Copy, Clone, Debug)]
+pub struct Sphere {
+    pub center: Point3<f32>,
+    pub radius: f32,
+}
+
+impl Sphere {
+    /// Gets the sphere bounding all of `spheres`, or `None` if there are none
+    fn bounding(spheres: impl Iterator<Item = Self> + Clone) -> Option<Self> {
+        let (sum, count) = spheres
+            .clone()
+            .fold((vec3(0., 0., 0.), 0.), |(sum, count), s| {
+                (sum + s.center.to_vec(), count + 1.)
+            });
+        if count < 1. {
+            return None;
+        }
+        let center = Point3::from_vec(sum / count);
+        let radius = spheres
+            .map(|s| s.center.distance(center) + s.radius)
+            .fold(0f32, f32::max);
+        Some(Self { center, radius })
+    }
+}
+
+/// An image of a cluster which should be captured
+pub struct Capture {
+    /// The layer of the impostor texture array to capture the image on
+    pub layer: u32,
+    pub viewer: shader::ViewerData,
+    /// Indices of the far away copies in the cluster
+    pub members: Vec<usize>,
+}
+
+/// Far away copies of a model in the same cell of the cluster grid
+struct Cluster {
+    /// The layer of the cluster's image, or `None` if it has none
+    layer: Option<u32>,
+    /// Direction from the viewer to the cluster when its image was captured
+    view_dir: Vector3<f32>,
+    captured: Instant,
+    /// Number of copies in the cluster when its image was captured
+    captured_members: usize,
+    /// Indices of the far away copies in the cluster
+    members: Vec<usize>,
+    bounds: Sphere,
+    /// `false` if the viewer is inside the bounds of the cluster, where an
+    /// image of it can't be shown
+    framed: bool,
+}
+
+/// Gets the cell of the cluster grid containing `pt`
+fn cluster_key(pt: Point3<f32>) -> [i32; 3] {
+    let cell = pt / CLUSTER_SIZE;
+    [
+        cell.x.floor() as i32,
+        cell.y.floor() as i32,
+        cell.z.floor() as i32,
+    ]
+}
+
+/// Gets the world space up direction of `viewer`
+fn viewer_up(viewer: &shader::ViewerData) -> Vector3<f32> {
+    let view = viewer.view;
+    vec3(view[0][1], view[1][1], view[2][1])
+}
+
+/// Gets the viewer at `cam_pos` whose view is filled by `bounds`, which
+/// captures the image of a cluster, or `None` if `cam_pos` is inside of
+/// `bounds`
+///
+/// `up` - the up direction of the camera
+fn capture_viewer(
+    cam_pos: Point3<f32>,
+    up: Vector3<f32>,
+    bounds: Sphere,
+) -> Option<shader::ViewerData> {
+    let offset = bounds.center - cam_pos;
+    let dist = offset.magnitude();
+    if dist <= bounds.radius {
+        return None;
+    }
+    let up = if offset.cross(up).magnitude2() > f32::EPSILON {
+        up
+    } else {
+        offset.cross(Vector3::unit_x()) + offset.cross(Vector3::unit_y())
+    };
+    let view = Matrix4::look_at_rh(cam_pos, bounds.center, up);
+    let proj = perspective(
+        Rad(2. * (bounds.radius / dist).asin()),
+        1.,
+        (dist - bounds.radius).max(0.1),
+        dist + bounds.radius,
+    );
+    Some(shader::ViewerData {
+        viewproj: (proj * view).into(),
+        view: view.into(),
+        proj: proj.into(),
+        cam_pos: cam_pos.into(),
+    })
+}
+
+/// Gets the half width of a billboard at the center of `bounds` which covers
+/// the same part of the screen as `bounds` does, when seen from `cam_pos`
+fn billboard_half_size(cam_pos: Point3<f32>, bounds: Sphere) -> f32 {
+    let dist = bounds.center.distance(cam_pos);
+    let r = bounds.radius;
+    r * dist / r.mul_add(-r, dist * dist).max(f32::EPSILON).sqrt()
+}
+
+/// Groups the far away copies of a model into clusters, and keeps track of
+/// when the image of each cluster has to be captured
+pub struct ImpostorClusters {
+    clusters: HashMap<[i32; 3], Cluster>,
+    free_layers: Vec<u32>,
+    /// `true` for each far away copy which is drawn by the image of its
+    /// cluster
+    covered: Vec<bool>,
+    cam_pos: Point3<f32>,
+}
+
+impl ImpostorClusters {
+    /// Creates clusters which use up to `layers` images
+    pub fn new(layers: u32) -> Self {
+        Self {
+            clusters: HashMap::new(),
+            free_layers: (0..layers).rev().collect(),
+            covered: Vec::new(),
+            cam_pos: point3(0., 0., 0.),
+        }
+    }
+
+    /// Clusters the copies of a model which are too far away from `viewer`
+    /// to draw their geometry
+    ///
+    /// `far` - the bounds of each far away copy
+    ///
+    /// Returns the images to capture this frame. New clusters are captured
+    /// first, then the clusters whose images are the oldest, up to
+    /// `MAX_CAPTURES_PER_FRAME`
+    pub fn update(
+        &mut self,
+        far: &[Sphere],
+        viewer: &shader::ViewerData,
+        now: Instant,
+    ) -> Vec<Capture> {
+        for cluster in self.clusters.values_mut() {
+            cluster.members.clear();
+        }
+        for (i, sphere) in far.iter().enumerate() {
+            self.clusters
+                .entry(cluster_key(sphere.center))
+                .or_insert_with(|| Cluster {
+                    layer: None,
+                    view_dir: vec3(0., 0., 0.),
+                    captured: now,
+                    captured_members: 0,
+                    members: Vec::new(),
+                    bounds: *sphere,
+                    framed: false,
+                })
+                .members
+                .push(i);
+        }
+        let free_layers = &mut self.free_layers;
+        self.clusters.retain(|_, cluster| {
+            if cluster.members.is_empty() {
+                free_layers.extend(cluster.layer);
+            }
+            !cluster.members.is_empty()
+        });
+
+        self.cam_pos = Point3::from(viewer.cam_pos);
+        let min_cos = REFRESH_ANGLE.cos();
+        let mut stale = Vec::new();
+        for (key, cluster) in &mut self.clusters {
+            if let Some(bounds) =
+                Sphere::bounding(cluster.members.iter().map(|i| far[*i]))
+            {
+                cluster.bounds = bounds;
+            }
+            let offset = cluster.bounds.center - self.cam_pos;
+            cluster.framed = offset.magnitude() > cluster.bounds.radius;
+            let age = now.saturating_duration_since(cluster.captured);
+            let priority = match cluster.layer {
+                _ if !cluster.framed => None,
+                None => Some(Duration::MAX),
+                Some(_)
+                    if offset.normalize().dot(cluster.view_dir) < min_cos
+                        || cluster.members.len()
+                            != cluster.captured_members
+                        || age > MAX_IMAGE_AGE =>
+                {
+                    Some(age)
+                }
+                Some(_) => None,
+            };
+            if let Some(priority) = priority {
+                stale.push((priority, *key));
+            }
+        }
+        stale.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+        let up = viewer_up(viewer);
+        let free_layers = &mut self.free_layers;
+        let mut captures = Vec::new();
+        for (_, key) in stale {
+            if captures.len() >= MAX_CAPTURES_PER_FRAME {
+                break;
+            }
+            let cluster = self.clusters.get_mut(&key).unwrap();
+            let layer = match cluster.layer.or_else(|| free_layers.pop()) {
+                Some(layer) => layer,
+                None => continue,
+            };
+            if let Some(viewer) =
+                capture_viewer(self.cam_pos, up, cluster.bounds)
+            {
+                cluster.layer = Some(layer);
+                cluster.view_dir =
+                    (cluster.bounds.center - self.cam_pos).normalize();
+                cluster.captured = now;
+                cluster.captured_members = cluster.members.len();
+                captures.push(Capture {
+                    layer,
+                    viewer,
+                    members: cluster.members.clone(),
+                });
+            } else if cluster.layer.is_none() {
+                free_layers.push(layer);
+            }
+        }
+
+        self.covered = vec![false; far.len()];
+        for cluster in self.clusters.values() {
+            if cluster.layer.is_some() && cluster.framed {
+                for i in &cluster.members {
+                    self.covered[*i] = true;
+                }
+            }
+        }
+        captures
+    }
+
+    /// `true` if the far away copy at index `idx` of the last update is
+    /// drawn by the image of its cluster. The other far away copies have
+    /// to be drawn with geometry
+    pub fn covers(&self, idx: usize) -> bool {
+        self.covered.get(idx).copied().unwrap_or(false)
+    }
+
+    /// Gets the billboard of each cluster with an image
+    ///
+    /// The color of each billboard tints the image, and its alpha is the layer
+    /// of the image
+    pub fn billboards(&self) -> Vec<BillboardAttributes> {
+        self.clusters
+            .values()
+            .filter(|cluster| cluster.framed)
+            .filter_map(|cluster| {
+                cluster.layer.map(|layer| {
+                    let half_size =
+                        billboard_half_size(self.cam_pos, cluster.bounds);
+                    let center = cluster.bounds.center;
+                    BillboardAttributes {
+                        instance_pos_rot: [center.x, center.y, center.z, 0.],
+                        instance_scale: [half_size, half_size],
+                        instance_color: [1., 1., 1., layer as f32],
+                    }
+                })
+            })
+            .collect()
+    }
+}
+
+/// Draws the far away copies of a model as billboards showing images of
+/// the clusters they're grouped into, so a far away cluster is a single
+/// quad in one instanced draw call
+///
+/// The images are captured from the viewer's position, and are captured
+/// again when the viewer looks at them from a different direction
+pub struct Impostors {
+    clusters: ImpostorClusters,
+    images: glium::texture::Texture2dArray,
+    depth: glium::texture::DepthTexture2d,
+    vertices: glium::VertexBuffer<VertexSimple>,
+    indices: glium::IndexBuffer<u32>,
+    billboards: InstanceBuffer<BillboardAttributes>,
+    billboard_count: usize,
+}
+
+impl Impostors {
+    pub fn new<F: glium::backend::Facade>(facade: &F) -> Self {
+        use glium::texture::{MipmapsOption, UncompressedFloatFormat};
+        Self {
+            clusters: ImpostorClusters::new(MAX_IMPOSTORS),
+            images: glium::texture::Texture2dArray::empty_with_format(
+                facade,
+                UncompressedFloatFormat::F16F16F16F16,
+                MipmapsOption::NoMipmap,
+                IMPOSTOR_SIZE,
+                IMPOSTOR_SIZE,
+                MAX_IMPOSTORS,
+            )
+            .unwrap(),
+            depth: glium::texture::DepthTexture2d::empty(
+                facade,
+                IMPOSTOR_SIZE,
+                IMPOSTOR_SIZE,
+            )
+            .unwrap(),
+            vertices: glium::VertexBuffer::immutable(facade, &RECT_VERTS)
+                .unwrap(),
+            indices: glium::IndexBuffer::immutable(
+                facade,
+                glium::index::PrimitiveType::TrianglesList,
+                &RECT_INDICES,
+            )
+            .unwrap(),
+            billboards: InstanceBuffer::new(),
+            billboard_count: 0,
+        }
+    }
+
+    /// See `ImpostorClusters::update`
+    pub fn update(
+        &mut self,
+        far: &[Sphere],
+        viewer: &shader::ViewerData,
+        now: Instant,
+    ) -> Vec<Capture> {
+        self.clusters.update(far, viewer, now)
+    }
+
+    /// See `ImpostorClusters::covers`
+    pub fn covers(&self, idx: usize) -> bool {
+        self.clusters.covers(idx)
+    }
+
+    /// Captures the image `capture` by drawing `drawable` at `positions`
+    ///
+    /// `scene_data` - the scene data of the visual pass the impostors are
+    /// drawn in
+    pub fn capture(
+        &self,
+        capture: &Capture,
+        drawable: &mut dyn Drawable,
+        positions: &[[[f32; 4]; 4]],
+        scene_data: &shader::SceneData,
+        cache: &shader::PipelineCache,
+        shader: &shader::ShaderManager,
+    ) {
+        use glium::Surface;
+        let mut fbo = {
+            let ctx = super::get_active_ctx();
+            let ctx = ctx.ctx.borrow();
+            glium::framebuffer::SimpleFrameBuffer::with_depth_buffer(
+                &*ctx,
+                self.images.main_level().layer(capture.layer).unwrap(),
+                &self.depth,
+            )
+            .unwrap()
+        };
+        fbo.clear_color_and_depth((0., 0., 0., 0.), 1.);
+        let sd = shader::SceneData {
+            viewer: capture.viewer,
+            ibl_maps: scene_data.ibl_maps,
+            lights: scene_data.lights,
+            pass_type: shader::RenderPassType::Visual,
+            light_pos: scene_data.light_pos,
+        };
+        render_drawable(
+            drawable,
+            Some(positions),
+            &mut fbo,
+            &sd,
+            cache,
+            shader,
+        );
+    }
+
+    /// Uploads the billboards of the clusters with images
+    pub fn update_billboards(&mut self) {
+        let billboards = self.clusters.billboards();
+        self.billboard_count = billboards.len();
+        if !billboards.is_empty() {
+            let ctx = super::get_active_ctx();
+            let ctx = ctx.ctx.borrow();
+            self.billboards.update_buffer(&billboards, &*ctx);
+        }
+    }
+
+    /// Gets the arguments to draw the billboards of every image, or `None`
+    /// if there are none
+    pub fn render_args(
+        &self,
+    ) -> Option<(
+        shader::UniformInfo,
+        VertexHolder,
+        glium::index::IndicesSource,
+    )> {
+        if self.billboard_count == 0 {
+            return None;
+        }
+        let instances = self.billboards.get_stored_buffer()?;
+        Some((
+            shader::UniformInfo::Impostor(&self.images),
+            VertexHolder::new(VertexSourceData::Single(From::from(
+                &self.vertices,
+            )))
+            .append(From::from(instances.per_instance().unwrap())),
+            From::from(&self.indices),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    fn viewer_at(cam_pos: Point3<f32>) -> shader::ViewerData {
+        let view =
+            Matrix4::look_at_rh(cam_pos, point3(0., 0., 0.), Vector3::unit_y());
+        let proj = perspective(Deg(60f32), 1., 0.1, 1000.);
+        shader::ViewerData {
+            viewproj: (proj * view).into(),
+            view: view.into(),
+            proj: proj.into(),
+            cam_pos: cam_pos.into(),
+        }
+    }
+
+    fn sphere(x: f32, y: f32, z: f32) -> Sphere {
+        Sphere {
+            center: point3(x, y, z),
+            radius: 2.,
+        }
+    }
+
+    #[test]
+    fn clusters_are_captured_when_the_view_changes() {
+        let mut clusters = ImpostorClusters::new(8);
+        let now = Instant::now();
+        let far = [
+            sphere(10., 10., 10.),
+            sphere(20., 20., 20.),
+            sphere(250., 0., 0.),
+        ];
+        let viewer = viewer_at(point3(0., 0., 900.));
+        let captures = clusters.update(&far, &viewer, now);
+        assert_eq!(captures.len(), 2);
+        assert!(captures.iter().any(|c| c.members == vec![0, 1]));
+        assert!((0..3).all(|i| clusters.covers(i)));
+        assert_eq!(clusters.billboards().len(), 2);
+
+        // nothing changed
+        let later = now + Duration::from_millis(100);
+        assert!(clusters.update(&far, &viewer, later).is_empty());
+
+        // a small step doesn't change the view direction enough
+        let viewer = viewer_at(point3(10., 0., 900.));
+        assert!(clusters.update(&far, &viewer, later).is_empty());
+        let viewer = viewer_at(point3(200., 0., 600.));
+        assert_eq!(clusters.update(&far, &viewer, later).len(), 2);
+
+        // images are refreshed once they're too old
+        let much_later = later + MAX_IMAGE_AGE * 2;
+        assert_eq!(clusters.update(&far, &viewer, much_later).len(), 2);
+
+        // the layer of an empty cluster is reused
+        let far = [sphere(250., 0., 0.), sphere(-300., 0., 0.)];
+        let captures = clusters.update(&far, &viewer, much_later);
+        assert_eq!(captures.len(), 1);
+        assert_eq!(captures[0].members, vec![1]);
+        assert_eq!(clusters.free_layers.len(), 6);
+    }
+
+    #[test]
+    fn clusters_without_an_image_are_drawn_with_geometry() {
+        let mut clusters = ImpostorClusters::new(1);
+        let far = [sphere(10., 10., 10.), sphere(250., 0., 0.)];
+        let viewer = viewer_at(point3(0., 0., 900.));
+        assert_eq!(clusters.update(&far, &viewer, Instant::now()).len(), 1);
+        assert_ne!(clusters.covers(0), clusters.covers(1));
+        assert!(!clusters.covers(2));
+
+        // the viewer is inside of the cluster
+        let mut clusters = ImpostorClusters::new(1);
+        let viewer = viewer_at(point3(12., 12., 12.));
+        let far = [sphere(10., 10., 10.), sphere(20., 20., 20.)];
+        assert!(clusters.update(&far, &viewer, Instant::now()).is_empty());
+        assert!(!clusters.covers(0));
+        assert!(clusters.billboards().is_empty());
+    }
+
+    #[test]
+    fn capture_viewer_frames_the_cluster() {
+        let bounds = sphere(0., 0., -100.);
+        let cam_pos = point3(0., 0., 0.);
+        let viewer =
+            capture_viewer(cam_pos, Vector3::unit_y(), bounds).unwrap();
+        let viewproj = Matrix4::from(viewer.viewproj);
+        // the sphere's silhouette touches the edges of the image
+        let half_size = billboard_half_size(cam_pos, bounds);
+        let edge = viewproj * vec4(half_size, 0., -100., 1.);
+        assert_approx_eq!(edge.x / edge.w, 1., 1e-4);
+        let center = viewproj * vec4(0., 0., -100., 1.);
+        assert_approx_eq!(center.x / center.w, 0., 1e-4);
+        assert!(capture_viewer(
+            point3(0., 0., -99.),
+            Vector3::unit_y(),
+            bounds
+        )
+        .is_none());
+    }
+}
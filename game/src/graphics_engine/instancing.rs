@@ -29,6 +29,40 @@ glium::implement_vertex!(
     instance_color
 );
 
+/// The state of a particle simulated on the GPU, laid out to match the
+/// `std430` particle buffer
+#[derive(Copy, Clone, Default)]
+#[repr(C)]
+pub struct GpuParticle {
+    /// xyz - world position, w - rotation about the camera z axis
+    pub pos_rot: [f32; 4],
+    /// xyz - velocity, w - rotational velocity in radians per second
+    pub vel_rot_vel: [f32; 4],
+    pub color: [f32; 4],
+    /// xy - scale, z - age in seconds, w - lifetime in seconds
+    ///
+    /// A particle is dead once its age reaches its lifetime
+    pub scale_age_life: [f32; 4],
+}
+
+#[derive(Copy, Clone)]
+pub struct DecalAttributes {
+    pub instance_model_col0: [f32; 4],
+    pub instance_model_col1: [f32; 4],
+    pub instance_model_col2: [f32; 4],
+    pub instance_model_col3: [f32; 4],
+    pub instance_alpha: f32,
+}
+
+glium::implement_vertex!(
+    DecalAttributes,
+    instance_model_col0,
+    instance_model_col1,
+    instance_model_col2,
+    instance_model_col3,
+    instance_alpha
+);
+
 #[derive(Copy, Clone)]
 pub struct LineAttributes {
     pub start_pos: [f32; 4],
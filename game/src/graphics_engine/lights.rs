@@ -0,0 +1,149 @@
+use super::shader::LightData;
+use crate::profiler;
+use cgmath::*;
+use std::time::Duration;
+
+/// Most lights the light culling can handle. Must match
+/// `MAX_LIGHTS_PER_TILE` in the light culling shader, since every light
+/// could be in the same tile
+pub const MAX_LIGHTS: usize = 1024;
+
+/// A light which is shown for a while after it's added, fading out over
+/// its lifetime
+struct TimedLight {
+    light: LightData,
+    lifetime: Duration,
+    age: Duration,
+}
+
+/// Collects the lights of each frame into a buffer which is reused by the
+/// next frame, so the light buffer isn't reallocated every frame
+///
+/// Lights are either added for a single frame, like the lights which follow
+/// lasers around, or added once with a lifetime after which they expire.
+/// When there are more lights than the budget, the lights which contribute
+/// the least to the view are dropped
+pub struct LightManager {
+    budget: usize,
+    /// Lights of the current frame
+    lights: Vec<LightData>,
+    timed: Vec<TimedLight>,
+}
+
+impl LightManager {
+    /// Creates a manager which passes at most `budget` lights to the scene
+    pub fn new(budget: usize) -> Self {
+        Self {
+            budget,
+            lights: Vec::with_capacity(budget),
+            timed: Vec::new(),
+        }
+    }
+
+    /// Starts a frame `dt` after the last one, which removes the lights of
+    /// the last frame and the timed lights which expired
+    pub fn next_frame(&mut self, dt: Duration) {
+        self.lights.clear();
+        for light in &mut self.timed {
+            light.age += dt;
+        }
+        self.timed.retain(|light| light.age < light.lifetime);
+    }
+
+    /// Adds lights shown during the current frame only
+    pub fn extend(&mut self, lights: impl IntoIterator<Item = LightData>) {
+        self.lights.extend(lights);
+    }
+
+    /// Adds a light shown for `lifetime`, whose luminance fades to nothing
+    pub fn flash(&mut self, light: LightData, lifetime: Duration) {
+        self.timed.push(TimedLight {
+            light,
+            lifetime,
+            age: Duration::default(),
+        });
+    }
+
+    /// Gets the lights of the current frame. If there are more than the
+    /// budget, only the lights which contribute the most to a viewer at
+    /// `view_pos` are kept
+    ///
+    /// Must be called once per frame, after the lights of the frame were
+    /// added
+    pub fn finish(&mut self, view_pos: Point3<f32>) -> &[LightData] {
+        let Self {
+            lights,
+            timed,
+            budget,
+        } = self;
+        lights.extend(timed.iter().map(|timed| {
+            timed.light.scaled(
+                1. - timed.age.as_secs_f32() / timed.lifetime.as_secs_f32(),
+            )
+        }));
+        let total = lights.len();
+        if total > *budget {
+            lights.select_nth_unstable_by(*budget, |a, b| {
+                priority(b, view_pos).total_cmp(&priority(a, view_pos))
+            });
+            lights.truncate(*budget);
+        }
+        profiler::count("lights", lights.len() as u64);
+        profiler::count("lights dropped", (total - lights.len()) as u64);
+        profiler::count("timed lights", timed.len() as u64);
+        lights
+    }
+}
+
+/// How much `light` contributes to a viewer at `view_pos`
+fn priority(light: &LightData, view_pos: Point3<f32>) -> f32 {
+    light.luminance() / light.center().distance2(view_pos).max(1.)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    fn light(pos: Point3<f32>, luminance: f32) -> LightData {
+        LightData::point_light(pos, luminance, vec3(1., 1., 1.))
+    }
+
+    #[test]
+    fn timed_lights_fade_and_expire() {
+        let mut lights = LightManager::new(MAX_LIGHTS);
+        let origin = point3(0., 0., 0.);
+        lights.flash(light(origin, 100.), Duration::from_millis(100));
+        lights.next_frame(Duration::from_millis(25));
+        lights.extend([light(origin, 10.)]);
+        let frame = lights.finish(origin);
+        assert_eq!(frame.len(), 2);
+        assert_approx_eq!(frame[1].luminance(), 75., 0.01);
+        // lights added for a frame are gone the next frame
+        lights.next_frame(Duration::from_millis(50));
+        assert_eq!(lights.finish(origin).len(), 1);
+        lights.next_frame(Duration::from_millis(25));
+        assert!(lights.finish(origin).is_empty());
+    }
+
+    #[test]
+    fn lights_over_budget_are_dropped_by_priority() {
+        let mut lights = LightManager::new(2);
+        let view_pos = point3(0., 0., 0.);
+        lights.extend([
+            light(point3(100., 0., 0.), 50.),
+            light(point3(10., 0., 0.), 50.),
+            light(point3(100., 0., 0.), 10_000.),
+            light(point3(0., 20., 0.), 1.),
+        ]);
+        let mut kept: Vec<_> = lights
+            .finish(view_pos)
+            .iter()
+            .map(LightData::luminance)
+            .collect();
+        kept.sort_by(f32::total_cmp);
+        assert_eq!(kept.len(), 2);
+        assert_approx_eq!(kept[0], 50.);
+        assert_approx_eq!(kept[1], 10_000.);
+    }
+}
@@ -1,30 +1,63 @@
+pub mod assets;
+pub mod atmosphere;
+mod cache;
 pub mod camera;
+pub mod camera_effects;
+pub mod capture;
+pub mod cinematic;
+pub mod clock;
 pub mod model;
 #[macro_use]
 pub mod pipeline;
 mod billboard;
 pub mod cubes;
+pub mod decals;
 pub mod drawable;
 pub mod entity;
+#[cfg(test)]
+mod golden;
+pub mod impostor;
 pub mod instancing;
+pub mod lights;
 pub mod particles;
+pub mod portal;
 pub mod primitives;
 pub mod scene;
+mod sdf_font;
 pub mod shader;
+pub mod sky;
 pub mod text;
 pub mod textures;
+pub mod ui;
+pub mod volume;
 pub mod window;
 use std::cell::{Cell, RefCell};
 use std::mem::MaybeUninit;
 use std::rc::Rc;
 
+/// Evicts cached models and textures which have gone unused for longer
+/// than the maximum idle time
+pub fn evict_unused_assets() {
+    let now = std::time::Instant::now();
+    model::Model::evict_unused(now);
+    textures::evict_unused(now);
+}
+
+/// Sets the time cached models and textures can go unused before they
+/// are evicted
+#[allow(dead_code)]
+pub fn set_asset_idle_time(idle_time: std::time::Duration) {
+    model::Model::set_cache_idle_time(idle_time);
+    textures::set_cache_idle_time(idle_time);
+}
+
 std::thread_local! {
     static ACTIVE_CTX: Cell<Option<Rc<RefCell<glium::Display>>>> = Cell::new(None);
     static ACTIVE_MANAGER: Cell<Option<Rc<shader::ShaderManager>>> = Cell::new(None);
 }
 
 /// Sets the thread local active window context and shader
-fn set_active_ctx(
+pub fn set_active_ctx(
     ctx: Rc<RefCell<glium::Display>>,
     shader: Rc<shader::ShaderManager>,
 ) {
@@ -1,3 +1,4 @@
+use super::super::clock;
 use super::{to_m4, Lerp};
 use assimp::*;
 use cgmath::Quaternion;
@@ -5,6 +6,9 @@ use cgmath::*;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
+use std::sync::Arc;
+use tracing::debug;
+
 pub struct Bone {
     pub id: i32,
     /// Matrix to transform a vector into bone space
@@ -148,20 +152,71 @@ impl BoneAnim {
         )
     }
 
-    /// Gets the current transformation matrix for the bone at the time
-    /// `anim_ticks`
-    pub fn get_bone_matrix(&self, anim_ticks: f64) -> Matrix4<f64> {
-        let scale = self.get_cur_scale(anim_ticks);
-        Matrix4::from_translation(self.get_cur_pos(anim_ticks))
-            * Matrix4::from(self.get_cur_rot(anim_ticks))
-            * Matrix4::from_nonuniform_scale(scale.x, scale.y, scale.z)
+    /// Gets the current local pose of the bone at the time `anim_ticks`
+    fn get_pose(&self, anim_ticks: f64) -> NodePose {
+        NodePose {
+            pos: self.get_cur_pos(anim_ticks),
+            rot: self.get_cur_rot(anim_ticks),
+            scale: self.get_cur_scale(anim_ticks),
+        }
+    }
+}
+
+/// The local translation, rotation, and scale of a node in the scene graph
+#[derive(Copy, Clone)]
+struct NodePose {
+    pos: Vector3<f64>,
+    rot: Quaternion<f64>,
+    scale: Vector3<f64>,
+}
+
+impl NodePose {
+    /// Decomposes an affine transformation matrix without shearing
+    fn from_matrix(mat: &Matrix4<f64>) -> Self {
+        let scale = vec3(
+            mat.x.truncate().magnitude(),
+            mat.y.truncate().magnitude(),
+            mat.z.truncate().magnitude(),
+        );
+        let safe_div = |v: Vector4<f64>, s: f64| {
+            if s > f64::EPSILON {
+                v.truncate() / s
+            } else {
+                v.truncate()
+            }
+        };
+        let rot = Matrix3::from_cols(
+            safe_div(mat.x, scale.x),
+            safe_div(mat.y, scale.y),
+            safe_div(mat.z, scale.z),
+        );
+        Self {
+            pos: mat.w.truncate(),
+            rot: Quaternion::from(rot).normalize(),
+            scale,
+        }
+    }
+
+    fn to_matrix(self) -> Matrix4<f64> {
+        Matrix4::from_translation(self.pos)
+            * Matrix4::from(self.rot)
+            * Matrix4::from_nonuniform_scale(
+                self.scale.x,
+                self.scale.y,
+                self.scale.z,
+            )
     }
 }
 
+/// The pose of every node in the scene graph, in depth first order
+type Pose = Vec<NodePose>;
+
 /// Encapsulates transformation information of an `AiNode`.
 /// Essentially represents a node in the scene graph for the model
 pub struct AssimpNode {
     transformation: Matrix4<f64>,
+    /// `transformation` decomposed into a pose
+    rest_pose: NodePose,
     name: String,
     children: Vec<AssimpNode>,
 }
@@ -169,9 +224,11 @@ pub struct AssimpNode {
 impl AssimpNode {
     /// Creates a new scene heirarchy tree from a scene graph node and all its descendants
     pub fn new(node: &assimp::Node) -> Self {
+        let transformation = to_m4(*node.transformation());
         Self {
             name: node.name().to_owned(),
-            transformation: to_m4(*node.transformation()),
+            rest_pose: NodePose::from_matrix(&transformation),
+            transformation,
             children: node.child_iter().map(|c| Self::new(&c)).collect(),
         }
     }
@@ -181,8 +238,8 @@ impl AssimpNode {
 struct Animation {
     ticks_per_sec: f64,
     duration: f64,
-    root_node: Rc<AssimpNode>,
-    bone_map: Rc<HashMap<String, Bone>>,
+    root_node: Arc<AssimpNode>,
+    bone_map: Arc<HashMap<String, Bone>>,
     name: String,
     anim_bones: HashMap<String, BoneAnim>,
     root_inverse: Matrix4<f64>,
@@ -192,11 +249,11 @@ impl Animation {
     /// Requires there are no missing bones from `bone_map`
     pub fn new(
         anim: &assimp::Animation,
-        root_node: Rc<AssimpNode>,
-        bone_map: Rc<HashMap<String, Bone>>,
+        root_node: Arc<AssimpNode>,
+        bone_map: Arc<HashMap<String, Bone>>,
     ) -> Self {
         let mut used_bones = HashMap::<String, BoneAnim>::new();
-        println!("New animation named: `{}`", anim.name.as_ref());
+        debug!("New animation named: `{}`", anim.name.as_ref());
         for i in 0..anim.num_channels as usize {
             let node = anim.get_node_anim(i).unwrap();
             //let bone_info = bone_map.get((*node).node_name.as_ref()).unwrap();
@@ -220,20 +277,40 @@ impl Animation {
         }
     }
 
-    /// Plays the animations and gets the bone matrices
+    /// Gets the local pose of every node in the scene graph
     ///
     /// `dt` - seconds since animations has begun. If `dt > duration`
     /// animation loops to beginning
-    fn play(&self, dt: f64) -> Vec<Matrix4<f32>> {
+    fn sample(&self, dt: f64) -> Pose {
         let ticks = self.ticks_per_sec * dt;
         let iterations = (ticks / self.duration).floor() as i32;
         let ticks = ticks - f64::from(iterations) * self.duration;
 
+        let mut pose = Pose::new();
+        self.sample_node(ticks, &self.root_node, &mut pose);
+        pose
+    }
+
+    fn sample_node(&self, ticks: f64, ai_node: &AssimpNode, pose: &mut Pose) {
+        pose.push(
+            self.anim_bones
+                .get(&ai_node.name)
+                .map_or(ai_node.rest_pose, |bone| bone.get_pose(ticks)),
+        );
+        for child in &ai_node.children {
+            self.sample_node(ticks, child, pose);
+        }
+    }
+
+    /// Converts a pose of the scene graph into the final bone matrices
+    fn pose_to_bones(&self, pose: &[NodePose]) -> Vec<Matrix4<f32>> {
         let mut final_mats = Vec::<Matrix4<f32>>::new();
         final_mats.resize(self.bone_map.len(), Matrix4::from_scale(1.));
         let identity = Matrix4::from_scale(1f64);
+        let mut node_idx = 0;
         self.get_bone_transforms(
-            ticks,
+            pose,
+            &mut node_idx,
             &self.root_node,
             &identity,
             &mut final_mats,
@@ -243,39 +320,37 @@ impl Animation {
 
     /// Computes the bone transforms recursively done the node tree and stores them in `out_bone_matrices`
     ///
+    /// `pose` - the local pose of every node in depth first order
+    ///
+    /// `node_idx` - the index of `ai_node` in `pose`
+    ///
     /// `parent_transform` - the matrix to transfrom from parent space to world space
     ///
     /// `out_bone_matrices` - the vector storing final bone transformation matrices. Required to have size equal
     /// to the number of bones
-    ///
-    /// `anim_time` - the duration the animation has been running. Required to be between `0` and `duration`
     fn get_bone_transforms(
         &self,
-        anim_time: f64,
+        pose: &[NodePose],
+        node_idx: &mut usize,
         ai_node: &AssimpNode,
         parent_transform: &Matrix4<f64>,
         out_bone_matrices: &mut Vec<Matrix4<f32>>,
     ) {
-        let bone_transform = match self.anim_bones.get(&ai_node.name) {
-            Some(bone) => bone.get_bone_matrix(anim_time),
-            _ => ai_node.transformation,
-        };
+        let bone_transform = pose[*node_idx].to_matrix();
+        *node_idx += 1;
         let to_world_space = parent_transform * bone_transform;
 
-        match self.bone_map.get(&ai_node.name) {
-            Some(bone_info) => {
-                out_bone_matrices[bone_info.id as usize] = (self.root_inverse
-                    * to_world_space
-                    * bone_info.offset_matrix)
+        if let Some(bone_info) = self.bone_map.get(&ai_node.name) {
+            out_bone_matrices[bone_info.id as usize] =
+                (self.root_inverse * to_world_space * bone_info.offset_matrix)
                     .cast()
                     .unwrap();
-            }
-            None => (),
         }
 
-        for child in ai_node.children.iter().map(|c| &*c) {
+        for child in &ai_node.children {
             self.get_bone_transforms(
-                anim_time,
+                pose,
+                node_idx,
                 child,
                 &to_world_space,
                 out_bone_matrices,
@@ -290,80 +365,138 @@ impl Animation {
     }
 }
 
+/// The animations of a model. They don't use the GL context, so they can be
+/// read on any thread before the model is created
+pub struct Animations(Vec<Animation>);
+
+impl Animations {
+    /// Reads `anims`, which move the bones of `bone_map` in the scene graph
+    /// whose root is `root_node`
+    ///
+    /// Requires there are no missing bones from `bone_map`
+    pub fn new(
+        anims: assimp::scene::AnimationIter,
+        bone_map: HashMap<String, Bone>,
+        root_node: AssimpNode,
+    ) -> Self {
+        let bone_map = Arc::new(bone_map);
+        let root_node = Arc::new(root_node);
+        Self(
+            anims
+                .map(|x| {
+                    Animation::new(&x, root_node.clone(), bone_map.clone())
+                })
+                .collect(),
+        )
+    }
+}
+
+/// A clip being played by the animator
+#[derive(Copy, Clone)]
+struct Playback {
+    anim: usize,
+    start: std::time::Instant,
+    looping: bool,
+}
+
 /// An animator holds all the animations of a single model and handles playing and stopping them.
 /// Only one animation can play at a time
 pub struct Animator {
-    animations: Vec<Animation>,
-    cur_anim: Option<usize>,
-    anim_start: std::time::Instant,
-    play_loop: bool,
+    /// Animations are shared by every animator of the same model
+    animations: Rc<Vec<Animation>>,
+    current: Option<Playback>,
 }
 
 impl Animator {
-    pub fn new(
-        anims: assimp::scene::AnimationIter,
-        bone_map: &Rc<HashMap<String, Bone>>,
-        root_node: &Rc<AssimpNode>,
-    ) -> Self {
-        let total_anims: Vec<Animation> = anims
-            .map(|x| Animation::new(&x, root_node.clone(), bone_map.clone()))
-            .collect();
+    pub fn new(animations: Animations) -> Self {
+        Self::from_animations(Rc::new(animations.0))
+    }
+
+    const fn from_animations(animations: Rc<Vec<Animation>>) -> Self {
         Self {
-            cur_anim: None,
-            animations: total_anims,
-            anim_start: std::time::Instant::now(),
-            play_loop: true,
+            animations,
+            current: None,
         }
     }
 
+    /// Creates a new animator with the same animations as this one,
+    /// which is not playing any animation
+    pub fn share(&self) -> Self {
+        Self::from_animations(self.animations.clone())
+    }
+
+    /// `true` if the playback is a non-looping clip which has finished
+    fn is_finished(
+        &self,
+        playback: &Playback,
+        now: std::time::Instant,
+    ) -> bool {
+        !playback.looping
+            && self.animations[playback.anim]
+                .is_finished(now.duration_since(playback.start).as_secs_f64())
+    }
+
     /// Plays the current animation, if any, and gets the bone matrices
     /// If no animations is playing, returns `None`
     pub fn animate(
         &self,
         frame_time: std::time::Instant,
     ) -> Option<Vec<[[f32; 4]; 4]>> {
-        match self.cur_anim {
-            Some(cur_anim) => {
-                let anim_sec =
-                    frame_time.duration_since(self.anim_start).as_secs_f64();
-                if self.play_loop
-                    || !self.animations[cur_anim].is_finished(anim_sec)
-                {
-                    Some(
-                        self.animations[cur_anim]
-                            .play(anim_sec)
-                            .into_iter()
-                            .map(std::convert::Into::into)
-                            .collect(),
-                    )
-                } else {
-                    None
-                }
-            }
-            _ => None,
-        }
+        let current = self
+            .current
+            .filter(|cur| !self.is_finished(cur, frame_time))?;
+        let anim = &self.animations[current.anim];
+        let pose =
+            anim.sample(frame_time.duration_since(current.start).as_secs_f64());
+        Some(
+            anim.pose_to_bones(&pose)
+                .into_iter()
+                .map(std::convert::Into::into)
+                .collect(),
+        )
     }
 
     /// Starts an animation with the name `anim_name`. Panics if no animation with that name is found.
     /// Will interrupt itself if it is already playing and any other animation currently being played
     #[allow(dead_code)]
     pub fn start(&mut self, anim_name: &str, do_loop: bool) {
-        for (anim, idx) in self.animations.iter().zip(0..self.animations.len())
-        {
-            if anim.name == anim_name {
-                self.play_loop = do_loop;
-                self.cur_anim = Some(idx);
-                self.anim_start = std::time::Instant::now();
-                return;
-            }
-        }
-        panic!("Animation '{}' not found", anim_name);
+        let anim = self
+            .animations
+            .iter()
+            .position(|anim| anim.name == anim_name)
+            .unwrap_or_else(|| panic!("Animation '{}' not found", anim_name));
+        self.current = Some(Playback {
+            anim,
+            start: clock::now(),
+            looping: do_loop,
+        });
     }
 
     /// Stops any animation playing, resetting model position
     #[inline]
     #[allow(dead_code)]
     pub fn stop(&mut self) {
-        self.cur_anim = None;
+        self.current = None;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    fn assert_mat_eq(a: &Matrix4<f64>, b: &Matrix4<f64>) {
+        let (a, b): (&[f64; 16], &[f64; 16]) = (a.as_ref(), b.as_ref());
+        for (x, y) in a.iter().zip(b.iter()) {
+            assert_approx_eq!(x, y, 0.0001);
+        }
+    }
+
+    #[test]
+    fn pose_decompose_round_trip() {
+        let mat = Matrix4::from_translation(vec3(1., -2., 3.))
+            * Matrix4::from(Quaternion::from_angle_y(Deg(30f64)))
+            * Matrix4::from_nonuniform_scale(2., 1., 0.5);
+        assert_mat_eq(&NodePose::from_matrix(&mat).to_matrix(), &mat);
     }
 }
@@ -0,0 +1,399 @@
+use super::super::drawable::Vertex;
+use super::mesh::MeshGeometry;
+use cgmath::*;
+use std::collections::{HashMap, HashSet};
+
+/// Fraction of a level of detail's switching coverage by which the coverage
+/// must pass it before the level changes, so objects near the switching
+/// distance don't pop back and forth between levels
+pub const LOD_HYSTERESIS: f32 = 0.2;
+/// Fraction of the screen's height a model must cover to be rendered with
+/// full detail
+const FULL_DETAIL_COVERAGE: f32 = 0.15;
+/// Ratio between the switching coverages of consecutive levels of detail
+const LOD_COVERAGE_RATIO: f32 = 0.4;
+/// Largest fraction of the screen's height a model drawn by an impostor can
+/// cover
+const MAX_IMPOSTOR_COVERAGE: f32 = 0.02;
+/// Number of grid cells along the longest axis of a model for each
+/// generated level of detail
+const GENERATED_RESOLUTIONS: [f32; 2] = [10., 5.];
+/// Models with fewer triangles don't have levels of detail generated
+const MIN_LOD_TRIANGLES: usize = 256;
+/// Largest fraction of the triangles of the previous level a generated level
+/// can have. Levels which remove fewer are not worth switching to
+const MAX_LOD_RATIO: f32 = 0.75;
+
+/// A sphere bounding the geometry of a model, in model space
+#[derive(Copy, Clone)]
+pub struct Bounds {
+    pub center: Point3<f32>,
+    pub radius: f32,
+}
+
+impl Bounds {
+    /// Gets the bounds of `meshes`
+    pub fn of(meshes: &[MeshGeometry]) -> Self {
+        let vertices = || meshes.iter().flat_map(|m| m.vertices.iter());
+        let center = aabb(vertices())
+            .map_or_else(|| point3(0., 0., 0.), |(min, max)| min.midpoint(max));
+        let radius = vertices()
+            .map(|v| Point3::from(v.pos).distance(center))
+            .fold(0f32, f32::max);
+        Self { center, radius }
+    }
+}
+
+/// Gets the minimum and maximum corners of the box bounding `vertices`, or
+/// `None` if there are none
+fn aabb<'a>(
+    mut vertices: impl Iterator<Item = &'a Vertex>,
+) -> Option<(Point3<f32>, Point3<f32>)> {
+    let first = Point3::from(vertices.next()?.pos);
+    Some(vertices.fold((first, first), |(min, max), v| {
+        let p = Point3::from(v.pos);
+        (
+            point3(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)),
+            point3(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)),
+        )
+    }))
+}
+
+/// Gets the paths of the authored levels of detail of the model at `path`,
+/// from most to least detailed
+///
+/// Level `n` of `dir/model.obj` is `dir/model_lod[n].obj`, starting from `1`
+pub fn authored_paths(path: &str) -> Vec<String> {
+    let path = std::path::Path::new(path);
+    let (stem, ext) = match (
+        path.file_stem().and_then(std::ffi::OsStr::to_str),
+        path.extension().and_then(std::ffi::OsStr::to_str),
+    ) {
+        (Some(stem), Some(ext)) => (stem, ext),
+        _ => return Vec::new(),
+    };
+    (1..)
+        .map(|level| {
+            path.with_file_name(format!("{}_lod{}.{}", stem, level, ext))
+        })
+        .take_while(|lod| lod.exists())
+        .map(|lod| lod.to_string_lossy().into_owned())
+        .collect()
+}
+
+/// Gets the screen coverage each level of detail of a model with `levels`
+/// levels is used above, except the last, which is used for any coverage
+pub fn min_coverages(levels: usize) -> Vec<f32> {
+    std::iter::successors(Some(FULL_DETAIL_COVERAGE), |coverage| {
+        Some(coverage * LOD_COVERAGE_RATIO)
+    })
+    .take(levels.saturating_sub(1))
+    .collect()
+}
+
+/// Gets the screen coverage below which a model with `levels` levels of
+/// detail is drawn by an impostor instead of its least detailed level
+pub fn impostor_coverage(levels: usize) -> f32 {
+    min_coverages(levels + 1)
+        .last()
+        .map_or(MAX_IMPOSTOR_COVERAGE, |coverage| {
+            coverage.min(MAX_IMPOSTOR_COVERAGE)
+        })
+}
+
+/// Gets the fraction of the screen's height covered by a sphere of radius
+/// `radius` at a distance `dist` from the viewer
+///
+/// `proj_y_scale` - the y scale of the viewer's perspective projection,
+/// which is `proj[1][1]`
+pub fn screen_coverage(radius: f32, dist: f32, proj_y_scale: f32) -> f32 {
+    if dist <= radius {
+        f32::INFINITY
+    } else {
+        radius * proj_y_scale / dist
+    }
+}
+
+/// Gets the level of detail to render a model covering `coverage` of the
+/// screen's height with
+///
+/// `min_coverages` - the coverage each level is used above. See `min_coverages`
+///
+/// `current` - the level the model was last rendered with, or `None` if it
+/// wasn't. The level only changes once the coverage passes the switching
+/// coverage by `LOD_HYSTERESIS`
+pub fn select_level(
+    min_coverages: &[f32],
+    coverage: f32,
+    current: Option<usize>,
+) -> usize {
+    let level_at = |scale: f32| {
+        min_coverages
+            .iter()
+            .position(|min| coverage >= min * scale)
+            .unwrap_or(min_coverages.len())
+    };
+    match current {
+        None => level_at(1.),
+        Some(current) => {
+            let finer = level_at(1. + LOD_HYSTERESIS);
+            let coarser = level_at(1. - LOD_HYSTERESIS);
+            if finer < current {
+                finer
+            } else if coarser > current {
+                coarser
+            } else {
+                current
+            }
+        }
+    }
+}
+
+/// The vertices merged into one by decimation
+struct Cluster {
+    pos: Vector3<f32>,
+    normal: Vector3<f32>,
+    tangent: Vector3<f32>,
+    tex_coords: Vector2<f32>,
+    count: f32,
+    /// The first vertex of the cluster, whose bones the merged vertex uses
+    first: Vertex,
+}
+
+impl Cluster {
+    fn new(first: Vertex) -> Self {
+        Self {
+            pos: Vector3::zero(),
+            normal: Vector3::zero(),
+            tangent: Vector3::zero(),
+            tex_coords: Vector2::zero(),
+            count: 0.,
+            first,
+        }
+    }
+
+    fn add(&mut self, v: &Vertex) {
+        self.pos += Vector3::from(v.pos);
+        self.normal += Vector3::from(v.normal);
+        self.tangent += Vector3::from(v.tangent);
+        self.tex_coords += Vector2::from(v.tex_coords);
+        self.count += 1.;
+    }
+
+    /// Normalizes `sum`, or uses `fallback` if the vectors cancelled out
+    fn direction(sum: Vector3<f32>, fallback: [f32; 3]) -> [f32; 3] {
+        if sum.magnitude2() > f32::EPSILON {
+            sum.normalize().into()
+        } else {
+            fallback
+        }
+    }
+
+    /// Gets the vertex which replaces every vertex of the cluster
+    fn merged(&self) -> Vertex {
+        Vertex {
+            pos: (self.pos / self.count).into(),
+            normal: Self::direction(self.normal, self.first.normal),
+            tex_coords: (self.tex_coords / self.count).into(),
+            tangent: Self::direction(self.tangent, self.first.tangent),
+            bone_ids: self.first.bone_ids,
+            bone_weights: self.first.bone_weights,
+        }
+    }
+}
+
+/// Simplifies `geometry` by merging all of its vertices in the same cell of a
+/// grid, and removing the triangles which collapse
+///
+/// `origin` - the minimum corner of the grid
+///
+/// `cell_size` - the width of the cubes of the grid
+pub fn decimate(
+    geometry: &MeshGeometry,
+    origin: Point3<f32>,
+    cell_size: f32,
+) -> MeshGeometry {
+    let mut cells = HashMap::<[i32; 3], usize>::new();
+    let mut clusters = Vec::<Cluster>::new();
+    let mut cluster_of = Vec::with_capacity(geometry.vertices.len());
+    for v in &geometry.vertices {
+        let cell = (Point3::from(v.pos) - origin) / cell_size;
+        let cell = [
+            cell.x.floor() as i32,
+            cell.y.floor() as i32,
+            cell.z.floor() as i32,
+        ];
+        let idx = *cells.entry(cell).or_insert_with(|| {
+            clusters.push(Cluster::new(*v));
+            clusters.len() - 1
+        });
+        clusters[idx].add(v);
+        cluster_of.push(idx);
+    }
+
+    let mut seen = HashSet::new();
+    let mut vertex_of = vec![None; clusters.len()];
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    for tri in geometry.indices.chunks_exact(3) {
+        let (a, b, c) = (
+            cluster_of[tri[0] as usize],
+            cluster_of[tri[1] as usize],
+            cluster_of[tri[2] as usize],
+        );
+        // rotating the smallest index to the front keeps the winding, so
+        // only duplicates facing the same way are removed
+        let key = if a < b && a < c {
+            [a, b, c]
+        } else if b < c {
+            [b, c, a]
+        } else {
+            [c, a, b]
+        };
+        if a == b || b == c || a == c || !seen.insert(key) {
+            continue;
+        }
+        for cluster in key {
+            let idx = *vertex_of[cluster].get_or_insert_with(|| {
+                vertices.push(clusters[cluster].merged());
+                vertices.len() as u32 - 1
+            });
+            indices.push(idx);
+        }
+    }
+    MeshGeometry {
+        vertices,
+        indices,
+        mat_idx: geometry.mat_idx,
+    }
+}
+
+/// Gets the number of triangles in a model made of `meshes`
+fn triangles(meshes: &[MeshGeometry]) -> usize {
+    meshes.iter().map(MeshGeometry::triangles).sum()
+}
+
+/// Generates less detailed versions of a model made of `meshes` by
+/// decimating it with increasingly coarse grids
+///
+/// Returns the generated levels of detail, from most to least detailed,
+/// which doesn't include levels that would remove too few triangles
+pub fn generate(meshes: &[MeshGeometry]) -> Vec<Vec<MeshGeometry>> {
+    let mut lods = Vec::new();
+    let mut prev_triangles = triangles(meshes);
+    let (min, max) = match aabb(meshes.iter().flat_map(|m| m.vertices.iter())) {
+        Some(bounds) if prev_triangles >= MIN_LOD_TRIANGLES => bounds,
+        _ => return lods,
+    };
+    let extent = (max - min).x.max((max - min).y).max((max - min).z);
+    if extent <= f32::EPSILON {
+        return lods;
+    }
+    for resolution in GENERATED_RESOLUTIONS.iter() {
+        let lod: Vec<_> = meshes
+            .iter()
+            .map(|mesh| decimate(mesh, min, extent / resolution))
+            .collect();
+        let lod_triangles = triangles(&lod);
+        if lod_triangles == 0
+            || lod_triangles as f32 > prev_triangles as f32 * MAX_LOD_RATIO
+        {
+            break;
+        }
+        prev_triangles = lod_triangles;
+        lods.push(lod);
+    }
+    lods
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::graphics_engine::drawable::MAX_BONES_PER_VERTEX;
+    use assert_approx_eq::assert_approx_eq;
+
+    /// A flat square of `n` by `n` quads in the xz plane
+    fn grid(n: u32) -> MeshGeometry {
+        let mut vertices = Vec::new();
+        for z in 0..=n {
+            for x in 0..=n {
+                vertices.push(Vertex {
+                    pos: [x as f32, 0., z as f32],
+                    normal: [0., 1., 0.],
+                    tex_coords: [x as f32 / n as f32, z as f32 / n as f32],
+                    tangent: [1., 0., 0.],
+                    bone_ids: [-1; MAX_BONES_PER_VERTEX],
+                    bone_weights: [0.; MAX_BONES_PER_VERTEX],
+                });
+            }
+        }
+        let mut indices = Vec::new();
+        for z in 0..n {
+            for x in 0..n {
+                let corner = z * (n + 1) + x;
+                let above = corner + n + 1;
+                indices.extend_from_slice(&[corner, above, corner + 1]);
+                indices.extend_from_slice(&[corner + 1, above, above + 1]);
+            }
+        }
+        MeshGeometry {
+            vertices,
+            indices,
+            mat_idx: 0,
+        }
+    }
+
+    #[test]
+    fn decimation_removes_collapsed_triangles() {
+        let full = grid(16);
+        let lod = decimate(&full, point3(0., -0.5, 0.), 4.);
+        assert!(lod.triangles() > 0);
+        assert!(lod.triangles() < full.triangles() / 4);
+        assert!(lod.vertices.len() < full.vertices.len());
+        for tri in lod.indices.chunks_exact(3) {
+            assert!(tri.iter().all(|i| (*i as usize) < lod.vertices.len()));
+            assert!(tri[0] != tri[1] && tri[1] != tri[2] && tri[0] != tri[2]);
+        }
+        for v in &lod.vertices {
+            assert!(v.pos.iter().all(|x| (0. ..=16.).contains(x)));
+            assert_approx_eq!(Vector3::from(v.normal).magnitude(), 1.);
+        }
+
+        let lods = generate(&[full]);
+        assert!(!lods.is_empty());
+        assert!(lods.windows(2).all(|w| triangles(&w[1]) < triangles(&w[0])));
+        // too few triangles to be worth simplifying
+        assert!(generate(&[grid(4)]).is_empty());
+    }
+
+    #[test]
+    fn level_selection_has_hysteresis() {
+        let mins = min_coverages(3);
+        assert_eq!(mins.len(), 2);
+        assert!(mins[0] > mins[1]);
+        assert_eq!(select_level(&mins, mins[0] * 1.01, None), 0);
+        assert_eq!(select_level(&mins, mins[0] * 0.99, None), 1);
+        assert_eq!(select_level(&mins, mins[1] * 0.5, None), 2);
+
+        // small changes around a switching coverage keep the level
+        assert_eq!(select_level(&mins, mins[0] * 0.9, Some(0)), 0);
+        assert_eq!(select_level(&mins, mins[0] * 1.1, Some(1)), 1);
+        assert_eq!(select_level(&mins, mins[0] * 0.7, Some(0)), 1);
+        assert_eq!(select_level(&mins, mins[0] * 1.3, Some(1)), 0);
+        assert_eq!(select_level(&mins, mins[1] * 0.5, Some(0)), 2);
+
+        assert!(min_coverages(1).is_empty());
+        assert_eq!(select_level(&[], 0., Some(0)), 0);
+
+        assert!(impostor_coverage(3) < mins[1]);
+        assert!(impostor_coverage(1) <= MAX_IMPOSTOR_COVERAGE);
+    }
+
+    #[test]
+    fn coverage_falls_with_distance() {
+        let near = screen_coverage(1., 10., 2.);
+        assert_approx_eq!(near, 0.2);
+        assert_approx_eq!(screen_coverage(1., 20., 2.), near / 2.);
+        assert!(screen_coverage(1., 0.5, 2.).is_infinite());
+    }
+}
@@ -1,26 +1,28 @@
 use super::super::shader;
-use super::super::textures;
+use super::super::textures::DecodedImage;
 use crate::cg_support::ssbo;
+use glium::texture::{SrgbTexture2d, Texture2d};
 use std::collections::BTreeMap;
 use std::io::BufRead;
+use tracing::debug;
 
 /// Either a texture or constant factor
-enum TexOrConst {
-    Tex(glium::texture::Texture2d),
+enum TexOrConst<T> {
+    Tex(T),
     Fac(f32),
 }
 
-struct PBRData {
-    roughness: TexOrConst,
-    metalness: TexOrConst,
-    ao_tex: Option<glium::texture::Texture2d>,
+struct PBRData<T> {
+    roughness: TexOrConst<T>,
+    metalness: TexOrConst<T>,
+    ao_tex: Option<T>,
 }
 
 /// We can specify non-pbr textures in the pbr file in case
 /// assimp doesn't find any.
 struct ExtraTexData {
-    albedo_tex: Option<glium::texture::SrgbTexture2d>,
-    normal_tex: Option<glium::texture::Texture2d>,
+    albedo_tex: Option<DecodedImage>,
+    normal_tex: Option<DecodedImage>,
 }
 
 /// Reads pbr textures from an externam file names `[mat_name]-pbr.yml` that resides in
@@ -40,7 +42,7 @@ struct ExtraTexData {
 /// * `normal` - texture path [optional if read by assimp]
 fn get_pbr_data(dir: &str, mat_name: &str) -> Option<BTreeMap<String, String>> {
     let file = format!("{}{}-pbr.yml", dir, mat_name);
-    println!("{}", file);
+    debug!("{}", file);
     match std::fs::File::open(file) {
         Ok(file) => {
             let mut map = BTreeMap::<String, String>::new();
@@ -58,84 +60,65 @@ fn get_pbr_data(dir: &str, mat_name: &str) -> Option<BTreeMap<String, String>> {
     }
 }
 
-/// Loads the pbr textures for the material with name `mat_name` which has a home
+/// Decodes the pbr textures for the material with name `mat_name` which has a home
 /// directory of `dir`. Also returns an extra tex data which are optionally specified textures
 /// that don't have to go in the pbr file.
-#[allow(clippy::too_many_lines)]
-fn get_pbr_textures<F>(
+fn get_pbr_textures(
     dir: &str,
     mat_name: &str,
-    facade: &F,
-) -> (Option<PBRData>, Option<ExtraTexData>)
-where
-    F: glium::backend::Facade,
-{
-    match get_pbr_data(dir, mat_name) {
-        Some(tex_maps) => {
-            println!("{}", tex_maps["roughness"]);
-            println!("{}", tex_maps["metalness"]);
-            if tex_maps.contains_key("ao") {
-                println!("ao: {}", tex_maps["ao"]);
-            }
-            let rough_fac = tex_maps["roughness"].parse::<f32>();
-            let metal_fac = tex_maps["metalness"].parse::<f32>();
-            (
-                Some(PBRData {
-                    ao_tex: if tex_maps.contains_key("ao") {
-                        Some(textures::load_texture_2d(
-                            &format!("{}{}", dir, tex_maps["ao"]),
-                            facade,
-                        ))
-                    } else {
-                        None
-                    },
-                    roughness: match rough_fac {
-                        Ok(f) => TexOrConst::Fac(f),
-                        _ => TexOrConst::Tex(textures::load_texture_2d(
-                            &format!("{}{}", dir, tex_maps["roughness"]),
-                            facade,
-                        )),
-                    },
-                    metalness: match metal_fac {
-                        Ok(f) => TexOrConst::Fac(f),
-                        _ => TexOrConst::Tex(textures::load_texture_2d(
-                            &format!("{}{}", dir, tex_maps["metalness"]),
-                            facade,
-                        )),
-                    },
-                }),
-                Some(ExtraTexData {
-                    albedo_tex: if tex_maps.contains_key("albedo") {
-                        Some(textures::load_texture_srgb(
-                            &format!("{}{}", dir, tex_maps["albedo"]),
-                            facade,
-                        ))
-                    } else {
-                        None
-                    },
-                    normal_tex: if tex_maps.contains_key("normal") {
-                        Some(textures::load_texture_2d(
-                            &format!("{}{}", dir, tex_maps["normal"]),
-                            facade,
-                        ))
-                    } else {
-                        None
-                    },
-                }),
-            )
-        }
-        _ => (None, None),
-    }
+) -> Result<(Option<PBRData<DecodedImage>>, Option<ExtraTexData>), String> {
+    let tex_maps = match get_pbr_data(dir, mat_name) {
+        Some(tex_maps) => tex_maps,
+        None => return Ok((None, None)),
+    };
+    debug!("{}", tex_maps["roughness"]);
+    debug!("{}", tex_maps["metalness"]);
+    let load = |key: &str| {
+        tex_maps
+            .get(key)
+            .map(|path| DecodedImage::load(&format!("{}{}", dir, path)))
+            .transpose()
+    };
+    let tex_or_const = |key: &str| match tex_maps[key].parse::<f32>() {
+        Ok(f) => Ok(TexOrConst::Fac(f)),
+        _ => DecodedImage::load(&format!("{}{}", dir, tex_maps[key]))
+            .map(TexOrConst::Tex),
+    };
+    Ok((
+        Some(PBRData {
+            ao_tex: load("ao")?,
+            roughness: tex_or_const("roughness")?,
+            metalness: tex_or_const("metalness")?,
+        }),
+        Some(ExtraTexData {
+            albedo_tex: load("albedo")?,
+            normal_tex: load("normal")?,
+        }),
+    ))
 }
-/// Texture information for a mesh
+/// Roughness of materials without PBR data
+const DEFAULT_ROUGHNESS: f32 = 0.8;
+/// Metalness of materials without PBR data
+const DEFAULT_METALNESS: f32 = 0.;
+
+/// Texture information for a mesh, where `S` is the type of the `sRGB`
+/// textures and `L` is the type of the linear ones
 /// Currently, a material can only have 1 texture of each type
-pub struct Material {
-    diffuse_tex: Option<glium::texture::SrgbTexture2d>,
+pub struct MaterialData<S, L> {
+    diffuse_tex: Option<S>,
     name: String,
-    pbr_data: Option<PBRData>,
-    normal_tex: Option<glium::texture::Texture2d>,
-    emission_tex: Option<glium::texture::SrgbTexture2d>,
+    pbr_data: Option<PBRData<L>>,
+    normal_tex: Option<L>,
+    emission_tex: Option<S>,
 }
+
+/// A material whose textures are on the GPU
+pub type Material = MaterialData<SrgbTexture2d, Texture2d>;
+
+/// A material whose textures are decoded, but not uploaded to the GPU. Does
+/// not use the GL context, so it can be loaded on any thread
+pub type MaterialSource = MaterialData<DecodedImage, DecodedImage>;
+
 /// Gets a mutable nullptr
 const fn null<T>() -> *mut T {
     0 as *mut T
@@ -145,18 +128,15 @@ const fn null_c<T>() -> *const T {
     0 as *const T
 }
 
-impl Material {
-    /// Finds all textures of the specified type and loads them using the specified function
+impl MaterialSource {
+    /// Finds all textures of the specified type and decodes them
     ///
     /// `dir` - main directory of model where texture paths are relative to
-    ///
-    /// `load_func` - function taking the path to the texture as a String and returning the loaded texture
-    fn get_textures<T, G: Fn(String) -> T>(
+    fn get_textures(
         mat: &assimp_sys::AiMaterial,
         tex_type: assimp_sys::AiTextureType,
         dir: &str,
-        load_func: &G,
-    ) -> Vec<T> {
+    ) -> Result<Vec<DecodedImage>, String> {
         let mut path = assimp_sys::AiString::default();
         let tex_num = unsafe {
             assimp_sys::aiGetMaterialTextureCount(
@@ -164,7 +144,7 @@ impl Material {
                 tex_type,
             )
         };
-        let mut textures = Vec::<T>::new();
+        let mut textures = Vec::new();
         for i in 0..tex_num {
             unsafe {
                 assimp_sys::aiGetMaterialTexture(
@@ -185,10 +165,10 @@ impl Material {
                 dir,
                 String::from_utf8_lossy(&path.data[..path.length])
             );
-            println!("Assimp loaded: {}", tex);
-            textures.push(load_func(tex));
+            debug!("Assimp loaded: {}", tex);
+            textures.push(DecodedImage::load(&tex)?);
         }
-        textures
+        Ok(textures)
     }
     /// Gets a material property with the key `property` as an ascii string
     /// Non alphanumeric/punctuation ascii characters are stripped
@@ -218,50 +198,30 @@ impl Material {
         }
         None
     }
-    /// Creates a material from an Assimp material
+    /// Reads a material from an Assimp material
     ///
     /// `dir` - the directory of the model file where textures are relative to
-    #[allow(clippy::too_many_lines)]
-    pub fn new<F: glium::backend::Facade>(
+    ///
+    /// # Errors
+    /// Fails if the material has no name or a texture cannot be decoded
+    pub fn new(
         mat: &assimp_sys::AiMaterial,
         dir: &str,
-        ctx: &F,
-    ) -> Self {
-        let load_srgb = |path: String| textures::load_texture_srgb(&path, ctx);
-        let load_rgb = |path: String| textures::load_texture_2d(&path, ctx);
-        let mut diffuse = Self::get_textures(
-            mat,
-            assimp_sys::AiTextureType::Diffuse,
-            dir,
-            &load_srgb,
-        );
-        let mut emissive = Self::get_textures(
-            mat,
-            assimp_sys::AiTextureType::Emissive,
-            dir,
-            &load_srgb,
-        );
-        let mut normal = Self::get_textures(
-            mat,
-            assimp_sys::AiTextureType::Normals,
-            dir,
-            &load_rgb,
-        );
+    ) -> Result<Self, String> {
+        use assimp_sys::AiTextureType;
+        let mut diffuse = Self::get_textures(mat, AiTextureType::Diffuse, dir)?;
+        let mut emissive =
+            Self::get_textures(mat, AiTextureType::Emissive, dir)?;
+        let mut normal = Self::get_textures(mat, AiTextureType::Normals, dir)?;
         normal.append(&mut Self::get_textures(
             mat,
-            assimp_sys::AiTextureType::Height,
-            dir,
-            &load_rgb,
-        ));
-        let mut ao = Self::get_textures(
-            mat,
-            assimp_sys::AiTextureType::Lightmap,
+            AiTextureType::Height,
             dir,
-            &load_rgb,
-        );
-        let name =
-            Self::get_property(mat, "?mat.name").expect("No material name!");
-        let (pbr, extras) = get_pbr_textures(dir, &name, ctx);
+        )?);
+        let mut ao = Self::get_textures(mat, AiTextureType::Lightmap, dir)?;
+        let name = Self::get_property(mat, "?mat.name")
+            .ok_or_else(|| "No material name!".to_owned())?;
+        let (pbr, extras) = get_pbr_textures(dir, &name)?;
         let pbr = pbr.map(|mut pbr| {
             if pbr.ao_tex.is_none() && !ao.is_empty() {
                 pbr.ao_tex = Some(ao.swap_remove(0));
@@ -276,8 +236,12 @@ impl Material {
             diffuse.push(albedo_tex);
             normal.push(normal_tex);
         }
-        Self {
-            diffuse_tex: Some(diffuse.swap_remove(0)),
+        Ok(Self {
+            diffuse_tex: if diffuse.is_empty() {
+                None
+            } else {
+                Some(diffuse.swap_remove(0))
+            },
             name,
             pbr_data: pbr,
             normal_tex: if normal.is_empty() {
@@ -290,44 +254,129 @@ impl Material {
             } else {
                 Some(emissive.swap_remove(0))
             },
-        }
+        })
     }
 
     /// For some reason Assimp is having trouble loading mtl data from obj files
-    /// Creates a material from the tobj material loaded from an mtl file
+    /// Reads a material from the tobj material loaded from an mtl file
     ///
     /// `dir` - the directory of the model file where textures are relative to
-    pub fn from_mtl<F: glium::backend::Facade>(
-        mat: &tobj::Material,
-        dir: &str,
-        ctx: &F,
-    ) -> Self {
-        Self {
-            diffuse_tex: if mat.diffuse_texture.is_empty() {
-                None
-            } else {
-                Some(textures::load_texture_srgb(
-                    &format!("{}{}", dir, mat.diffuse_texture),
-                    ctx,
-                ))
-            },
-            pbr_data: get_pbr_textures(dir, &mat.name, ctx).0,
-            normal_tex: if mat.normal_texture.is_empty() {
-                None
+    ///
+    /// # Errors
+    /// Fails if a texture cannot be decoded
+    pub fn from_mtl(mat: &tobj::Material, dir: &str) -> Result<Self, String> {
+        let load = |path: &str| {
+            if path.is_empty() {
+                Ok(None)
             } else {
-                Some(textures::load_texture_2d(
-                    &format!("{}{}", dir, mat.normal_texture),
-                    ctx,
-                ))
-            },
-            emission_tex: mat.unknown_param.get("map_Ke").map(|x| {
-                println!("Ke: {}", x);
-                textures::load_texture_srgb(&format!("{}{}", dir, x), ctx)
-            }),
+                DecodedImage::load(&format!("{}{}", dir, path)).map(Some)
+            }
+        };
+        Ok(Self {
+            diffuse_tex: load(&mat.diffuse_texture)?,
+            pbr_data: get_pbr_textures(dir, &mat.name)?.0,
+            normal_tex: load(&mat.normal_texture)?,
+            emission_tex: mat
+                .unknown_param
+                .get("map_Ke")
+                .map(|x| {
+                    debug!("Ke: {}", x);
+                    DecodedImage::load(&format!("{}{}", dir, x))
+                })
+                .transpose()?,
             name: mat.name.clone(),
+        })
+    }
+
+    /// Uploads the textures of the material to the GPU
+    ///
+    /// # Errors
+    /// Fails if a texture cannot be created
+    pub fn upload<F: glium::backend::Facade>(
+        self,
+        ctx: &F,
+    ) -> Result<Material, String> {
+        let srgb = |tex: Option<DecodedImage>| {
+            tex.map(|tex| tex.upload_srgb(ctx)).transpose()
+        };
+        let linear = |tex: Option<DecodedImage>| {
+            tex.map(|tex| tex.upload_2d(ctx)).transpose()
+        };
+        let tex_or_const = |val: TexOrConst<DecodedImage>| match val {
+            TexOrConst::Tex(tex) => tex.upload_2d(ctx).map(TexOrConst::Tex),
+            TexOrConst::Fac(f) => Ok(TexOrConst::Fac(f)),
+        };
+        Ok(Material {
+            diffuse_tex: srgb(self.diffuse_tex)?,
+            pbr_data: match self.pbr_data {
+                Some(pbr) => Some(PBRData {
+                    roughness: tex_or_const(pbr.roughness)?,
+                    metalness: tex_or_const(pbr.metalness)?,
+                    ao_tex: linear(pbr.ao_tex)?,
+                }),
+                None => None,
+            },
+            normal_tex: linear(self.normal_tex)?,
+            emission_tex: srgb(self.emission_tex)?,
+            name: self.name,
+        })
+    }
+
+    /// Gets the amount of bytes of texture data that will be uploaded
+    #[must_use]
+    pub fn upload_size(&self) -> usize {
+        let size = |tex: &Option<DecodedImage>| {
+            tex.as_ref().map_or(0, |tex| tex.data.len())
+        };
+        let tex_or_const = |val: &TexOrConst<DecodedImage>| match val {
+            TexOrConst::Tex(tex) => tex.data.len(),
+            TexOrConst::Fac(_) => 0,
+        };
+        size(&self.diffuse_tex)
+            + size(&self.normal_tex)
+            + size(&self.emission_tex)
+            + self.pbr_data.as_ref().map_or(0, |pbr| {
+                tex_or_const(&pbr.roughness)
+                    + tex_or_const(&pbr.metalness)
+                    + size(&pbr.ao_tex)
+            })
+    }
+}
+
+impl<S, L> MaterialData<S, L> {
+    /// Checks the material for missing textures or data which will be
+    /// replaced by fallbacks when rendering
+    ///
+    /// Returns a description of each problem found
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.name == "Laser" {
+            return problems;
         }
+        if self.diffuse_tex.is_none() {
+            problems.push(format!(
+                "Material '{}' has no albedo map, using white",
+                self.name
+            ));
+        }
+        if self.normal_tex.is_none() {
+            problems.push(format!(
+                "Material '{}' has no normal map, using a flat normal map",
+                self.name
+            ));
+        }
+        if self.pbr_data.is_none() {
+            problems.push(format!(
+                "Material '{}' has no PBR file '{}-pbr.yml', using a roughness \
+                of {} and metalness of {}",
+                self.name, self.name, DEFAULT_ROUGHNESS, DEFAULT_METALNESS
+            ));
+        }
+        problems
     }
+}
 
+impl Material {
     /// Converts the material to shader uniform arguments
     ///
     /// `instancing` - if instanced rendering is being used
@@ -343,53 +392,48 @@ impl Material {
     ) -> shader::UniformInfo {
         match &self.name[..] {
             "Laser" => shader::UniformInfo::Laser,
-            _ if self.pbr_data.is_some() => {
-                shader::UniformInfo::Pbr(shader::PBRData {
-                    diffuse_tex: self.diffuse_tex.as_ref().unwrap(),
-                    model: model.unwrap_or_else(|| {
-                        cgmath::Matrix4::from_scale(1f32).into()
-                    }),
-                    roughness_map: self.pbr_data.as_ref().and_then(|data| {
-                        match &data.roughness {
-                            TexOrConst::Fac(_) => None,
-                            TexOrConst::Tex(t) => Some(t),
-                        }
-                    }),
-                    metallic_map: self.pbr_data.as_ref().and_then(|data| {
-                        match &data.metalness {
-                            TexOrConst::Fac(_) => None,
-                            TexOrConst::Tex(t) => Some(t),
-                        }
-                    }),
-                    normal_map: self.normal_tex.as_ref(),
-                    emission_map: self.emission_tex.as_ref(),
-                    ao_map: self
-                        .pbr_data
-                        .as_ref()
-                        .and_then(|data| data.ao_tex.as_ref()),
-                    instancing,
-                    bone_mats: bones,
-                    trans_data,
-                    emission_strength,
-                    roughness_fac: self
-                        .pbr_data
-                        .as_ref()
-                        .map(|data| match &data.roughness {
-                            TexOrConst::Fac(f) => *f,
-                            TexOrConst::Tex(_) => -2.0,
-                        })
-                        .unwrap(),
-                    metallic_fac: self
-                        .pbr_data
-                        .as_ref()
-                        .map(|data| match &data.metalness {
-                            TexOrConst::Fac(f) => *f,
-                            TexOrConst::Tex(_) => -2.,
-                        })
-                        .unwrap(),
-                })
-            }
-            x => panic!("Unimplemented texture with name: {}", x),
+            _ => shader::UniformInfo::Pbr(shader::PBRData {
+                diffuse_tex: self.diffuse_tex.as_ref(),
+                model: model.unwrap_or_else(|| {
+                    cgmath::Matrix4::from_scale(1f32).into()
+                }),
+                roughness_map: self.pbr_data.as_ref().and_then(|data| {
+                    match &data.roughness {
+                        TexOrConst::Fac(_) => None,
+                        TexOrConst::Tex(t) => Some(t),
+                    }
+                }),
+                metallic_map: self.pbr_data.as_ref().and_then(
+                    |data| match &data.metalness {
+                        TexOrConst::Fac(_) => None,
+                        TexOrConst::Tex(t) => Some(t),
+                    },
+                ),
+                normal_map: self.normal_tex.as_ref(),
+                emission_map: self.emission_tex.as_ref(),
+                ao_map: self
+                    .pbr_data
+                    .as_ref()
+                    .and_then(|data| data.ao_tex.as_ref()),
+                instancing,
+                bone_mats: bones,
+                trans_data,
+                emission_strength,
+                roughness_fac: self.pbr_data.as_ref().map_or(
+                    DEFAULT_ROUGHNESS,
+                    |data| match &data.roughness {
+                        TexOrConst::Fac(f) => *f,
+                        TexOrConst::Tex(_) => -2.0,
+                    },
+                ),
+                metallic_fac: self.pbr_data.as_ref().map_or(
+                    DEFAULT_METALNESS,
+                    |data| match &data.metalness {
+                        TexOrConst::Fac(f) => *f,
+                        TexOrConst::Tex(_) => -2.,
+                    },
+                ),
+            }),
         }
     }
 }
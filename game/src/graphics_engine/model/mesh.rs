@@ -32,7 +32,16 @@ pub struct Mesh {
     mat_idx: usize,
 }
 
-impl Mesh {
+/// The vertices and indices of a mesh before they're uploaded to the GPU
+#[derive(Clone)]
+pub struct MeshGeometry {
+    pub vertices: Vec<Vertex>,
+    /// Every 3 indices is a triangle
+    pub indices: Vec<u32>,
+    pub mat_idx: usize,
+}
+
+impl MeshGeometry {
     /// Gets a vector indexable by mesh vertex index which returns a vector of
     /// tuples of corresponding scene bone ids and bone weights
     ///
@@ -111,14 +120,13 @@ impl Mesh {
             )
         }
     }
-    /// Creates a new mesh
+    /// Reads the geometry of `mesh`
     ///
     /// `bone_map` - map of already loaded bones by other meshes in the model.
     /// Will be updated if this mesh contains new bones
-    pub fn new<F: glium::backend::Facade>(
+    pub fn new(
         mesh: &assimp::Mesh,
         bone_map: &mut HashMap<String, Bone>,
-        ctx: &F,
     ) -> Self {
         let mut vertices = Vec::<Vertex>::new();
         let mut indices = Vec::<u32>::new();
@@ -150,14 +158,32 @@ impl Mesh {
                 }
             }
         }
-        let (vbo, ebo) = get_vbo_ebo(&vertices, &indices, ctx);
         Self {
+            vertices,
+            indices,
+            mat_idx: (*mesh).material_index as usize,
+        }
+    }
+
+    /// Uploads the geometry to the GPU
+    #[must_use]
+    pub fn upload<F: glium::backend::Facade>(&self, ctx: &F) -> Mesh {
+        let (vbo, ebo) = get_vbo_ebo(&self.vertices, &self.indices, ctx);
+        Mesh {
             vbo,
             ebo,
-            mat_idx: (*mesh).material_index as usize,
+            mat_idx: self.mat_idx,
         }
     }
 
+    /// Gets the number of triangles in the geometry
+    #[must_use]
+    pub fn triangles(&self) -> usize {
+        self.indices.len() / 3
+    }
+}
+
+impl Mesh {
     /// Gets the uniform, vertex information, and indices for this mesh
     ///
     /// `model` - the model matrix for this mesh or `None` to
@@ -4,12 +4,13 @@ extern crate assimp;
 extern crate assimp_sys;
 extern crate tobj;
 mod animation;
+mod lod;
 mod material;
 mod mesh;
 #[allow(clippy::module_inception)]
 mod model;
 pub use animation::Animator;
-pub use model::Model;
+pub use model::{Model, ModelSource};
 
 /// Assimp `Vector3D` to `f32` array
 #[inline]
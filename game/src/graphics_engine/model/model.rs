@@ -1,16 +1,21 @@
+use super::super::cache::AssetCache;
+use super::super::clock;
 use super::super::drawable::*;
+use super::super::impostor;
 use super::super::instancing;
 use super::super::shader;
 use super::super::textures;
-use super::animation::{Animator, AssimpNode, Bone};
-use super::material::Material;
-use super::mesh::Mesh;
+use super::animation::{Animations, Animator, AssimpNode, Bone};
+use super::lod;
+use super::material::{Material, MaterialSource};
+use super::mesh::{Mesh, MeshGeometry};
 use crate::cg_support::ssbo;
 use assimp::*;
 use cgmath::*;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
+use tracing::{debug, warn};
 
 /// A model is geometry loaded from the filesystem
 /// Each model must have a main obj file with a material file at the specified path
@@ -29,57 +34,103 @@ use std::rc::Rc;
 ///
 /// * **Lasers** - Materials with the name "Laser" are lasers. These are objects that are simply colored
 /// with one uniform color and do not use textures
+///
+/// # Levels of Detail
+///
+/// Each copy of a model is rendered with less detail the less of the screen it covers. Levels of detail
+/// can be authored as `[model_name]_lod1.obj`, `[model_name]_lod2.obj`, etc. in the same directory as the
+/// main model file, from most to least detailed, and must use the same materials as the main model. Otherwise,
+/// they are generated by simplifying the model when it's loaded, unless it has few triangles. Animated models
+/// always have full detail. The collision mesh of an object is loaded separately, and so always has full detail
+///
+/// Instanced models can also enable impostors with `with_impostors`. Then copies which cover less of the screen
+/// than the least detailed level is meant for aren't drawn with geometry. Instead, nearby copies are grouped into
+/// clusters, and each cluster is drawn as a billboard showing an image of it
 pub struct Model {
-    meshes: Vec<Mesh>,
-    materials: Vec<Material>,
+    data: Rc<ModelData>,
     animator: Animator,
     bone_buffer: Option<ssbo::Ssbo<[[f32; 4]; 4]>>,
-    instances: instancing::InstanceBuffer<instancing::InstancePosition>,
+    /// The instance buffer of each level of detail
+    instances: Vec<instancing::InstanceBuffer<instancing::InstancePosition>>,
+    /// The level of detail each copy of the model was last chosen to be
+    /// rendered with, indexed like the model matrices it's rendered with
+    lod_levels: Vec<usize>,
+    impostors: Option<Box<impostor::Impostors>>,
+    /// The instance buffer of the copies in the impostor image being captured
+    capture_instances: instancing::InstanceBuffer<instancing::InstancePosition>,
     instancing: bool,
     transparency: Option<shader::TransparencyData>,
     pub emissive_strength: Rc<RefCell<f32>>,
 }
 
-impl Model {
-    fn process_node<F: glium::backend::Facade>(
+/// The geometry, materials, and animations loaded from a model file,
+/// which are shared between every model loaded from the same file
+pub struct ModelData {
+    /// The meshes of each level of detail, from most to least detailed
+    lods: Vec<Vec<Mesh>>,
+    /// The screen coverage above which each level of detail is used, except
+    /// the last. See `lod::min_coverages`
+    lod_coverages: Vec<f32>,
+    bounds: lod::Bounds,
+    materials: Vec<Material>,
+    animator: Animator,
+    bone_count: usize,
+}
+
+std::thread_local! {
+    static MODEL_CACHE: RefCell<AssetCache<ModelData>> =
+        RefCell::new(AssetCache::new());
+}
+
+/// The geometry, materials, and animations of a model file which have been
+/// read and decoded, but not uploaded to the GPU. Does not use the GL
+/// context, so it can be loaded on any thread
+pub struct ModelSource {
+    /// The geometry of each level of detail, from most to least detailed
+    lods: Vec<Vec<MeshGeometry>>,
+    bounds: lod::Bounds,
+    materials: Vec<MaterialSource>,
+    animations: Animations,
+    bone_count: usize,
+}
+
+impl ModelSource {
+    fn process_node(
         node: &assimp::Node,
         scene: &Scene,
         bone_map: &mut HashMap<String, Bone>,
-        ctx: &F,
-    ) -> Vec<Mesh> {
-        let mut meshes = Vec::<Mesh>::new();
+    ) -> Vec<MeshGeometry> {
+        let mut meshes = Vec::<MeshGeometry>::new();
         for i in 0..node.num_meshes() {
             let mesh = scene.mesh(i as usize).unwrap();
-            meshes.push(Mesh::new(&mesh, bone_map, ctx));
+            meshes.push(MeshGeometry::new(&mesh, bone_map));
         }
         for n in node.child_iter() {
-            meshes.append(&mut Self::process_node(&n, scene, bone_map, ctx));
+            meshes.append(&mut Self::process_node(&n, scene, bone_map));
         }
         meshes
     }
 
     /// Use assimp to load all scene materials
-    fn process_mats<F: glium::backend::Facade>(
+    fn process_mats(
         scene: &Scene,
         dir: &str,
-        ctx: &F,
-    ) -> Vec<Material> {
+    ) -> Result<Vec<MaterialSource>, String> {
         scene
             .material_iter()
-            .map(|x| Material::new(&*x, dir, ctx))
+            .map(|x| MaterialSource::new(&*x, dir))
             .collect()
     }
 
     /// Assimp is being weird with mtl files. If we load an obj file, use tobj to load
     /// its corresponding material file
-    fn process_obj_mats<F: glium::backend::Facade>(
-        path: &str,
-        ctx: &F,
-    ) -> Vec<Material> {
+    fn process_obj_mats(path: &str) -> Result<Vec<MaterialSource>, String> {
         let dir = textures::dir_stem(path);
-        let (mats, _) = tobj::load_mtl(path.replace(".obj", ".mtl")).unwrap();
+        let mtl = path.replace(".obj", ".mtl");
+        let (mats, _) = tobj::load_mtl(&mtl)
+            .map_err(|e| format!("Could not load '{}': {}", mtl, e))?;
         mats.iter()
-            .map(|x| Material::from_mtl(&*x, &dir, ctx))
+            .map(|x| MaterialSource::from_mtl(&*x, &dir))
             .collect()
     }
 
@@ -93,7 +144,7 @@ impl Model {
             for i in 0..anim.num_channels as usize {
                 let channel = unsafe { &**anim.channels.add(i) };
                 if bone_map.get(channel.node_name.as_ref()).is_none() {
-                    println!("Missing bone!");
+                    warn!("Missing bone!");
                     let bone_id = bone_map.len() as i32;
                     bone_map.insert(
                         channel.node_name.as_ref().to_owned(),
@@ -119,11 +170,10 @@ impl Model {
     ///
     /// PBR textures are specified in a YAML file with the `-pbr.yml` file ending. They should be named
     /// the same as their corresponding materials
-    fn process_materials<F: glium::backend::Facade>(
+    fn process_materials(
         path: &str,
         scene: &Scene,
-        ctx: &F,
-    ) -> Vec<Material> {
+    ) -> Result<Vec<MaterialSource>, String> {
         let backup_mtl = format!(
             "{}{}.mtl",
             textures::dir_stem(path),
@@ -133,15 +183,16 @@ impl Model {
                 .unwrap()
         );
         if path.contains(".obj") {
-            Self::process_obj_mats(path, ctx)
+            Self::process_obj_mats(path)
         } else if std::path::Path::new(&backup_mtl).exists() {
-            Self::process_obj_mats(&backup_mtl, ctx)
+            Self::process_obj_mats(&backup_mtl)
         } else {
-            Self::process_mats(scene, &textures::dir_stem(path), ctx)
+            Self::process_mats(scene, &textures::dir_stem(path))
         }
     }
 
-    pub fn new<F: glium::backend::Facade>(path: &str, ctx: &F) -> Self {
+    /// Creates the importer used to load model files
+    fn importer() -> Importer {
         let mut importer = Importer::new();
         importer.join_identical_vertices(true);
         importer.triangulate(true);
@@ -150,46 +201,222 @@ impl Model {
         importer.calc_tangent_space(|mut tan_space_args| {
             tan_space_args.enable = true;
         });
-        let scene = importer.read_file(path).unwrap();
-        assert!(!scene.is_incomplete());
-        println!("Loaded model");
+        importer
+    }
+
+    /// Reads the scene of the model file at `path`
+    fn read_scene<'a>(
+        importer: &'a Importer,
+        path: &str,
+    ) -> Result<Scene<'a>, String> {
+        let scene = importer
+            .read_file(path)
+            .map_err(|e| format!("Could not load model '{}': {}", path, e))?;
+        if scene.is_incomplete() {
+            return Err(format!("Model '{}' is incomplete", path));
+        }
+        Ok(scene)
+    }
+
+    /// Gets the geometry of each level of detail of the model at `path`,
+    /// from most to least detailed
+    ///
+    /// `full` - the geometry of the model with full detail
+    ///
+    /// `animated` - whether the model has bones, in which case it only has
+    /// full detail since the bones of each level would have to match
+    fn load_lods(
+        path: &str,
+        full: Vec<MeshGeometry>,
+        animated: bool,
+    ) -> Result<Vec<Vec<MeshGeometry>>, String> {
+        if animated {
+            return Ok(vec![full]);
+        }
+        let authored = lod::authored_paths(path);
+        let mut lods = if authored.is_empty() {
+            lod::generate(&full)
+        } else {
+            let importer = Self::importer();
+            let mut lods = Vec::new();
+            for lod_path in authored {
+                let scene = Self::read_scene(&importer, &lod_path)?;
+                let mut bone_map = HashMap::<String, Bone>::new();
+                lods.push(Self::process_node(
+                    &scene.root_node(),
+                    &scene,
+                    &mut bone_map,
+                ));
+            }
+            lods
+        };
+        lods.insert(0, full);
+        Ok(lods)
+    }
+
+    /// Reads the model file at `path`, along with its levels of detail and
+    /// the textures of its materials
+    ///
+    /// # Errors
+    /// Fails if the model, its materials or their textures cannot be read
+    pub fn load(path: &str) -> Result<Self, String> {
+        let importer = Self::importer();
+        let scene = Self::read_scene(&importer, path)?;
+        debug!("Loaded model");
         let mut bone_map = HashMap::<String, Bone>::new();
         let root_node = AssimpNode::new(&scene.root_node());
-        let meshes =
-            Self::process_node(&scene.root_node(), &scene, &mut bone_map, ctx);
-        let materials = Self::process_materials(path, &scene, ctx);
+        let geometry =
+            Self::process_node(&scene.root_node(), &scene, &mut bone_map);
+        let materials = Self::process_materials(path, &scene)?;
+        for problem in materials.iter().flat_map(MaterialSource::validate) {
+            warn!("'{}': {}", path, problem);
+        }
         bone_map = Self::load_missing_bones(&scene, bone_map);
-        let bone_buffer = if bone_map.is_empty() {
+        let bone_count = bone_map.len();
+        let animations =
+            Animations::new(scene.animation_iter(), bone_map, root_node);
+        let bounds = lod::Bounds::of(&geometry);
+        Ok(Self {
+            lods: Self::load_lods(path, geometry, bone_count > 0)?,
+            bounds,
+            materials,
+            animations,
+            bone_count,
+        })
+    }
+
+    /// Gets the amount of bytes that will be uploaded to the GPU
+    #[must_use]
+    pub fn upload_size(&self) -> usize {
+        let geometry: usize = self
+            .lods
+            .iter()
+            .flatten()
+            .map(|mesh| {
+                mesh.vertices.len() * std::mem::size_of::<Vertex>()
+                    + mesh.indices.len() * std::mem::size_of::<u32>()
+            })
+            .sum();
+        geometry
+            + self
+                .materials
+                .iter()
+                .map(MaterialSource::upload_size)
+                .sum::<usize>()
+    }
+
+    /// Uploads the geometry and textures of the model to the GPU
+    fn upload<F: glium::backend::Facade>(
+        self,
+        ctx: &F,
+    ) -> Result<ModelData, String> {
+        let materials = self
+            .materials
+            .into_iter()
+            .map(|mat| mat.upload(ctx))
+            .collect::<Result<_, _>>()?;
+        Ok(ModelData {
+            lod_coverages: lod::min_coverages(self.lods.len()),
+            bounds: self.bounds,
+            lods: self
+                .lods
+                .iter()
+                .map(|lod| lod.iter().map(|mesh| mesh.upload(ctx)).collect())
+                .collect(),
+            materials,
+            animator: Animator::new(self.animations),
+            bone_count: self.bone_count,
+        })
+    }
+}
+
+impl Model {
+    /// Loads the model at `path`, sharing the geometry and textures with
+    /// every other model loaded from the same path
+    ///
+    /// Panics if the model cannot be loaded
+    pub fn new<F: glium::backend::Facade>(path: &str, ctx: &F) -> Self {
+        let data = MODEL_CACHE.with(|cache| {
+            cache.borrow_mut().get_or_load(path, || {
+                ModelSource::load(path)
+                    .and_then(|source| source.upload(ctx))
+                    .unwrap_or_else(|e| panic!("{}", e))
+            })
+        });
+        let bone_buffer = if data.bone_count == 0 {
             None
         } else {
             Some(ssbo::Ssbo::<[[f32; 4]; 4]>::static_alloc_dyn(
-                bone_map.len(),
+                data.bone_count,
                 None,
             ))
         };
-        let animator = Animator::new(
-            scene.animation_iter(),
-            &Rc::new(bone_map),
-            &Rc::new(root_node),
-        );
         Self {
-            meshes,
-            materials,
-            animator,
+            animator: data.animator.share(),
+            data,
             bone_buffer,
-            instances: instancing::InstanceBuffer::new(),
+            instances: data
+                .lods
+                .iter()
+                .map(|_| instancing::InstanceBuffer::new())
+                .collect(),
+            lod_levels: Vec::new(),
+            impostors: None,
+            capture_instances: instancing::InstanceBuffer::new(),
             instancing: false,
             transparency: None,
             emissive_strength: Rc::new(RefCell::new(1.)),
         }
     }
 
+    /// Uploads `source`, the model read from `path`, and shares it with
+    /// every model later loaded from `path`. Nothing is uploaded if the
+    /// model at `path` is already loaded
+    ///
+    /// # Errors
+    /// Fails if the model cannot be uploaded
+    pub fn cache_source<F: glium::backend::Facade>(
+        path: &str,
+        source: ModelSource,
+        ctx: &F,
+    ) -> Result<(), String> {
+        if MODEL_CACHE.with(|cache| cache.borrow().contains(path)) {
+            return Ok(());
+        }
+        let data = source.upload(ctx)?;
+        MODEL_CACHE.with(|cache| cache.borrow_mut().get_or_load(path, || data));
+        Ok(())
+    }
+
+    /// Evicts cached model data which has not been used by any model for
+    /// the maximum idle time
+    pub fn evict_unused(now: std::time::Instant) {
+        MODEL_CACHE.with(|cache| cache.borrow_mut().evict_unused(now));
+    }
+
+    /// Sets the time cached model data can go unused before it is evicted
+    #[allow(dead_code)]
+    pub fn set_cache_idle_time(idle_time: std::time::Duration) {
+        MODEL_CACHE.with(|cache| cache.borrow_mut().set_max_idle(idle_time));
+    }
+
     /// Enables model instancing
     pub const fn with_instancing(mut self) -> Self {
         self.instancing = true;
         self
     }
 
+    /// Draws far away copies of the model with impostors
+    ///
+    /// Requires that this model is instanced, which is enabled by `with_instancing()`
+    pub fn with_impostors<F: glium::backend::Facade>(
+        mut self,
+        ctx: &F,
+    ) -> Self {
+        self.impostors = Some(Box::new(impostor::Impostors::new(ctx)));
+        self
+    }
+
     /// Enables model to have refractive transparency
     ///
     /// `refraction_idx`
@@ -226,16 +453,17 @@ impl Model {
         VertexHolder<'a>,
         glium::index::IndicesSource<'a>,
     )> {
-        let bones = self.animator.animate(std::time::Instant::now());
+        let bones = self.animator.animate(clock::now());
         if let (Some(mats), Some(buf)) = (&bones, self.bone_buffer.as_mut()) {
             buf.update(mats);
         }
         let mut v = Vec::new();
         let bones = self.bone_buffer.as_ref();
-        for mesh in &self.meshes {
+        let level = self.lod_level(0);
+        for mesh in &self.data.lods[level] {
             v.push(mesh.render_args(
                 Some(model),
-                &self.materials,
+                &self.data.materials,
                 bones,
                 self.transparency.as_ref(),
                 *self.emissive_strength.borrow(),
@@ -258,26 +486,46 @@ impl Model {
         glium::index::IndicesSource<'a>,
     )> {
         let mut v = Vec::new();
-        if !positions.is_empty() {
-            {
-                let ctx = super::super::get_active_ctx();
-                let ctx = ctx.ctx.borrow();
-                self.instances.update_buffer(
-                    &instancing::model_mats_to_vertex(positions),
-                    &*ctx,
-                );
+        let mut groups = vec![Vec::new(); self.data.lods.len()];
+        let mut far_idx = 0;
+        for (i, pos) in positions.iter().enumerate() {
+            if self.is_far(i) {
+                far_idx += 1;
+                if self
+                    .impostors
+                    .as_ref()
+                    .map_or(false, |imp| imp.covers(far_idx - 1))
+                {
+                    continue;
+                }
+            }
+            groups[self.lod_level(i)].push(*pos);
+        }
+        {
+            let ctx = super::super::get_active_ctx();
+            let ctx = ctx.ctx.borrow();
+            for (buffer, group) in self.instances.iter_mut().zip(&groups) {
+                if !group.is_empty() {
+                    buffer.update_buffer(
+                        &instancing::model_mats_to_vertex(group),
+                        &*ctx,
+                    );
+                }
+            }
+        }
+        for ((meshes, buffer), group) in
+            self.data.lods.iter().zip(&self.instances).zip(&groups)
+        {
+            if group.is_empty() {
+                continue;
             }
             let data: glium::vertex::VerticesSource<'a> = From::from(
-                self.instances
-                    .get_stored_buffer()
-                    .unwrap()
-                    .per_instance()
-                    .unwrap(),
+                buffer.get_stored_buffer().unwrap().per_instance().unwrap(),
             );
-            for mesh in &self.meshes {
+            for mesh in meshes {
                 let (uniform, vertices, indices) = mesh.render_args(
                     None,
-                    &self.materials,
+                    &self.data.materials,
                     None,
                     self.transparency.as_ref(),
                     *self.emissive_strength.borrow(),
@@ -285,9 +533,29 @@ impl Model {
                 v.push((uniform, vertices.append(data.clone()), indices));
             }
         }
+        if let Some(args) =
+            self.impostors.as_ref().and_then(|imp| imp.render_args())
+        {
+            v.push(args);
+        }
         v
     }
 
+    /// `true` if the copy of this model rendered with the model matrix at
+    /// index `idx` is far enough away to be drawn by an impostor
+    fn is_far(&self, idx: usize) -> bool {
+        self.impostors.is_some()
+            && self.lod_levels.get(idx) == Some(&self.data.lods.len())
+    }
+
+    /// Gets the level of detail the copy of this model rendered with the
+    /// model matrix at index `idx` should use
+    fn lod_level(&self, idx: usize) -> usize {
+        self.lod_levels
+            .get(idx)
+            .map_or(0, |level| (*level).min(self.data.lods.len() - 1))
+    }
+
     #[allow(dead_code)]
     pub fn get_animator(&mut self) -> &mut Animator {
         &mut self.animator
@@ -315,4 +583,156 @@ impl Drawable for Model {
     fn transparency(&self) -> Option<f32> {
         self.transparency.as_ref().map(|x| *x.trans_fac.borrow())
     }
+
+    fn select_lod(
+        &mut self,
+        positions: &[[[f32; 4]; 4]],
+        viewer: &shader::ViewerData,
+    ) {
+        if self.data.lods.len() < 2 && self.impostors.is_none() {
+            return;
+        }
+        let cam_pos = Point3::from(viewer.cam_pos);
+        let bounds = self.data.bounds;
+        let mut min_coverages = self.data.lod_coverages.clone();
+        if self.impostors.is_some() {
+            // the level after the least detailed one is drawn by impostors
+            min_coverages.push(lod::impostor_coverage(self.data.lods.len()));
+        }
+        self.lod_levels.truncate(positions.len());
+        for (i, mat) in positions.iter().enumerate() {
+            let mat = Matrix4::from(*mat);
+            let scale = mat
+                .x
+                .truncate()
+                .magnitude()
+                .max(mat.y.truncate().magnitude())
+                .max(mat.z.truncate().magnitude());
+            let coverage = lod::screen_coverage(
+                bounds.radius * scale,
+                mat.transform_point(bounds.center).distance(cam_pos),
+                viewer.proj[1][1],
+            );
+            let level = lod::select_level(
+                &min_coverages,
+                coverage,
+                self.lod_levels.get(i).copied(),
+            );
+            if let Some(current) = self.lod_levels.get_mut(i) {
+                *current = level;
+            } else {
+                self.lod_levels.push(level);
+            }
+        }
+    }
+
+    fn prepare(
+        &mut self,
+        positions: &[[[f32; 4]; 4]],
+        scene_data: &shader::SceneData,
+        cache: &shader::PipelineCache,
+        shader: &shader::ShaderManager,
+    ) {
+        if self.impostors.is_none() {
+            return;
+        }
+        let bounds = self.data.bounds;
+        let far_mats: Vec<_> = (0..positions.len())
+            .filter(|i| self.is_far(*i))
+            .map(|i| positions[i])
+            .collect();
+        let far: Vec<_> = far_mats
+            .iter()
+            .map(|mat| {
+                let mat = Matrix4::from(*mat);
+                let scale = mat
+                    .x
+                    .truncate()
+                    .magnitude()
+                    .max(mat.y.truncate().magnitude())
+                    .max(mat.z.truncate().magnitude());
+                impostor::Sphere {
+                    center: mat.transform_point(bounds.center),
+                    radius: bounds.radius * scale,
+                }
+            })
+            .collect();
+        let impostors = self.impostors.as_mut().unwrap();
+        let captures = impostors.update(
+            &far,
+            &scene_data.viewer,
+            std::time::Instant::now(),
+        );
+        for capture in captures {
+            let members: Vec<_> =
+                capture.members.iter().map(|i| far_mats[*i]).collect();
+            {
+                let ctx = super::super::get_active_ctx();
+                let ctx = ctx.ctx.borrow();
+                self.capture_instances.update_buffer(
+                    &instancing::model_mats_to_vertex(&members),
+                    &*ctx,
+                );
+            }
+            impostors.capture(
+                &capture,
+                &mut ImpostorCapture {
+                    meshes: self.data.lods.last().unwrap(),
+                    materials: &self.data.materials,
+                    instances: &self.capture_instances,
+                    emissive_strength: *self.emissive_strength.borrow(),
+                },
+                &members,
+                scene_data,
+                cache,
+                shader,
+            );
+        }
+        impostors.update_billboards();
+    }
+}
+
+/// The least detailed level of a model, drawn at the copies in the image of
+/// an impostor
+struct ImpostorCapture<'b> {
+    meshes: &'b [Mesh],
+    materials: &'b Vec<Material>,
+    instances: &'b instancing::InstanceBuffer<instancing::InstancePosition>,
+    emissive_strength: f32,
+}
+
+impl<'b> Drawable for ImpostorCapture<'b> {
+    fn render_args<'a>(
+        &'a mut self,
+        _positions: &[[[f32; 4]; 4]],
+    ) -> Vec<(
+        shader::UniformInfo,
+        VertexHolder<'a>,
+        glium::index::IndicesSource<'a>,
+    )> {
+        let data: glium::vertex::VerticesSource<'a> = From::from(
+            self.instances
+                .get_stored_buffer()
+                .unwrap()
+                .per_instance()
+                .unwrap(),
+        );
+        self.meshes
+            .iter()
+            .map(|mesh| {
+                let (uniform, vertices, indices) = mesh.render_args(
+                    None,
+                    self.materials,
+                    None,
+                    None,
+                    self.emissive_strength,
+                );
+                (uniform, vertices.append(data.clone()), indices)
+            })
+            .collect()
+    }
+
+    fn transparency(&self) -> Option<f32> {
+        None
+    }
 }
@@ -0,0 +1,197 @@
+use super::super::clock;
+use super::super::drawable::*;
+use super::super::entity::{AbstractEntity, RenderOrder};
+use super::super::instancing::InstanceBuffer;
+use super::super::shader;
+use super::trail::TrailVertex;
+use crate::cg_support::Transformation;
+use cgmath::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Instant;
+
+/// Length of the segments the beam is divided into
+const SEGMENT_LEN: f64 = 2.;
+/// Most segments a beam is divided into
+const MAX_SEGMENTS: u32 = 256;
+/// Largest `trail_t` of the beam, so the dimmest part of it is still visible
+const MAX_FADE: f32 = 0.7;
+
+/// A glowing beam between two points, such as a tractor beam
+///
+/// The beam is drawn with the trail shader as a straight camera-facing strip,
+/// with pulses of brightness and width that travel along it from its end to
+/// its start
+pub struct Beam {
+    /// Start and end of the beam, or `None` if it isn't shown
+    ends: Option<(Point3<f64>, Point3<f64>)>,
+    /// Distance between consecutive pulses
+    pulse_spacing: f64,
+    /// Speed the pulses travel at
+    pulse_speed: f64,
+    start: Instant,
+    args: shader::TrailData,
+    vertices: InstanceBuffer<TrailVertex>,
+    indices: glium::index::NoIndices,
+}
+
+impl Beam {
+    pub fn new() -> Self {
+        Self {
+            ends: None,
+            pulse_spacing: 12.,
+            pulse_speed: 30.,
+            start: clock::now(),
+            args: shader::TrailData {
+                color: [0.4, 1., 0.6, 0.8],
+                emission: 4.,
+                width: 1.5,
+                width_falloff: 0.5,
+                alpha_falloff: 1.,
+            },
+            vertices: InstanceBuffer::new(),
+            indices: glium::index::NoIndices(
+                glium::index::PrimitiveType::TriangleStrip,
+            ),
+        }
+    }
+
+    /// Sets the color of the beam, in linear space, and the factor it's
+    /// multiplied by
+    #[must_use]
+    #[allow(dead_code)]
+    pub const fn color(mut self, color: [f32; 4], emission: f32) -> Self {
+        self.args.color = color;
+        self.args.emission = emission;
+        self
+    }
+
+    /// Sets the width of the brightest parts of the beam
+    #[must_use]
+    #[allow(dead_code)]
+    pub const fn width(mut self, width: f32) -> Self {
+        self.args.width = width;
+        self
+    }
+
+    /// Shows the beam from the start to the end of `ends`, or hides it if
+    /// `ends` is `None`
+    pub fn set_ends(&mut self, ends: Option<(Point3<f64>, Point3<f64>)>) {
+        self.ends = ends;
+    }
+
+    /// Gets the vertices of the triangle strip along the beam `time`
+    /// seconds after it was created
+    fn strip_vertices(&self, time: f64) -> Vec<TrailVertex> {
+        let (start, end) = match self.ends {
+            Some(ends) => ends,
+            None => return Vec::new(),
+        };
+        let len = start.distance(end);
+        if len < f64::EPSILON {
+            return Vec::new();
+        }
+        let dir = (end - start) / len;
+        let segments =
+            ((len / SEGMENT_LEN).ceil() as u32).clamp(1, MAX_SEGMENTS);
+        let mut verts = Vec::with_capacity(segments as usize * 2 + 2);
+        for i in 0..=segments {
+            let dist = len * f64::from(i) / f64::from(segments);
+            let phase = (dist + time * self.pulse_speed) / self.pulse_spacing;
+            let pulse = (phase * std::f64::consts::PI * 2.).cos() as f32;
+            let t = MAX_FADE * (1. - pulse) * 0.5;
+            for side in [-1f32, 1f32] {
+                verts.push(TrailVertex {
+                    trail_pos: (start + dir * dist).cast().unwrap().into(),
+                    trail_dir: dir.cast().unwrap().into(),
+                    trail_side: side,
+                    trail_t: t,
+                });
+            }
+        }
+        verts
+    }
+}
+
+impl Default for Beam {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drawable for Beam {
+    fn render_args<'a>(
+        &'a mut self,
+        _: &[[[f32; 4]; 4]],
+    ) -> Vec<(
+        shader::UniformInfo,
+        VertexHolder<'a>,
+        glium::index::IndicesSource<'a>,
+    )> {
+        let verts = self.strip_vertices(
+            clock::now().duration_since(self.start).as_secs_f64(),
+        );
+        if verts.is_empty() {
+            return Vec::new();
+        }
+        {
+            let ctx = super::super::get_active_ctx();
+            let ctx = ctx.ctx.borrow();
+            self.vertices.update_buffer(&verts, &*ctx);
+        }
+        vec![(
+            shader::UniformInfo::Trail(self.args),
+            VertexHolder::new(VertexSourceData::Single(From::from(
+                self.vertices.get_stored_buffer().unwrap(),
+            ))),
+            From::from(&self.indices),
+        )]
+    }
+
+    fn transparency(&self) -> Option<f32> {
+        None
+    }
+}
+
+impl AbstractEntity for Beam {
+    fn transformations(&self) -> Option<&[Rc<RefCell<dyn Transformation>>]> {
+        None
+    }
+
+    fn drawable(&mut self) -> &mut dyn Drawable {
+        self
+    }
+
+    fn should_render(&self, pass: shader::RenderPassType) -> bool {
+        pass == shader::RenderPassType::Visual && self.ends.is_some()
+    }
+
+    fn render_order(&self) -> RenderOrder {
+        RenderOrder::Last
+    }
+
+    fn get_id(&self) -> usize {
+        self as *const Self as usize
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn beam_spans_its_ends_with_travelling_pulses() {
+        let mut beam = Beam::new();
+        assert!(beam.strip_vertices(0.).is_empty());
+        beam.set_ends(Some((point3(0., 0., 0.), point3(0., 0., 20.))));
+        let verts = beam.strip_vertices(0.);
+        assert_eq!(verts.len(), 22);
+        assert_eq!(verts[0].trail_pos, [0., 0., 0.]);
+        assert_eq!(verts[21].trail_pos, [0., 0., 20.]);
+        assert_eq!(verts[0].trail_dir, [0., 0., 1.]);
+        // a pulse starts at the start of the beam and moves towards it
+        assert!(verts[0].trail_t.abs() < 0.0001);
+        let later = beam.strip_vertices(0.1);
+        assert!(later[0].trail_t > 0.);
+    }
+}
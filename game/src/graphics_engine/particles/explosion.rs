@@ -0,0 +1,271 @@
+use super::super::instancing::{BillboardAttributes, InstanceBuffer};
+use super::super::shader;
+use super::Emitter;
+use cgmath::*;
+use rand::Rng;
+use std::time::Duration;
+
+/// Billboards that make up a fireball
+const FIREBALL_PUFFS: usize = 14;
+/// Seconds a fireball burns for
+const FIREBALL_LIFETIME: f64 = 1.6;
+/// Latest, in seconds after a fireball starts, that one of its puffs appears
+const MAX_PUFF_DELAY: f64 = 0.25;
+/// Seconds the light of a fireball's flash lasts
+const FLASH_LIFETIME: f64 = 0.7;
+/// Luminance of the flash of a fireball of size `1` when it's brightest
+const FLASH_LUMINANCE: f64 = 40.;
+/// Seconds a shockwave expands for
+const SHOCKWAVE_LIFETIME: f64 = 0.8;
+
+/// Gets the color of a fireball `t` of the way through its life
+///
+/// The fireball starts white hot, cools to orange, then to a dark red as it
+/// fades away
+fn fireball_color(t: f64) -> Vector4<f32> {
+    let white = vec3(1., 0.95, 0.8);
+    let orange = vec3(1., 0.5, 0.1);
+    let red = vec3(0.4, 0.05, 0.02);
+    let t = t.clamp(0., 1.);
+    let rgb = if t < 0.2 {
+        white.lerp(orange, t / 0.2)
+    } else {
+        orange.lerp(red, (t - 0.2) / 0.8)
+    };
+    let alpha = (1. - t) * (1. - t);
+    rgb.extend(alpha).cast().unwrap()
+}
+
+/// Gets the fraction of its final radius a shockwave has expanded to `t` of
+/// the way through its life. It expands quickly then slows down
+fn shockwave_expansion(t: f64) -> f64 {
+    let t = t.clamp(0., 1.);
+    1. - (1. - t).powi(3)
+}
+
+struct Puff {
+    /// Offset from the center of the fireball
+    offset: Vector3<f64>,
+    vel: Vector3<f64>,
+    rot: f64,
+    rot_vel: f64,
+    /// Scale of the puff when the fireball has fully grown
+    scale: f64,
+    /// Seconds after the fireball starts that the puff appears
+    delay: f64,
+}
+
+/// A billboard animation of a ball of fire that grows, cools, and fades. The
+/// fireball flashes a point light that fades out quickly
+pub struct Fireball {
+    center: Point3<f64>,
+    size: f64,
+    puffs: Vec<Puff>,
+    /// Seconds since the fireball started
+    age: f64,
+    billboards: Vec<BillboardAttributes>,
+    instances: InstanceBuffer<BillboardAttributes>,
+}
+
+impl Fireball {
+    /// Creates a fireball at `center` whose puffs grow to about `size`
+    pub fn new<F: glium::backend::Facade>(
+        center: Point3<f64>,
+        size: f64,
+        facade: &F,
+    ) -> Self {
+        let mut rng = rand::thread_rng();
+        let puffs = (0..FIREBALL_PUFFS)
+            .map(|_| {
+                let dir = vec3(
+                    rng.gen_range(-1. ..1.),
+                    rng.gen_range(-1. ..1.),
+                    rng.gen_range(-1. ..1f64),
+                );
+                Puff {
+                    offset: dir * size * 0.3,
+                    vel: dir * size * rng.gen_range(0.3..0.8),
+                    rot: rng.gen_range(0. ..std::f64::consts::PI * 2.),
+                    rot_vel: rng.gen_range(-1. ..1.),
+                    scale: size * rng.gen_range(0.6..1.2),
+                    delay: rng.gen_range(0. ..MAX_PUFF_DELAY),
+                }
+            })
+            .collect();
+        Self {
+            center,
+            size,
+            puffs,
+            age: 0.,
+            billboards: Vec::new(),
+            instances: InstanceBuffer::new_sized(FIREBALL_PUFFS, facade),
+        }
+    }
+}
+
+impl Emitter for Fireball {
+    fn emit(&mut self, dt: Duration) {
+        self.age += dt.as_secs_f64();
+        let (age, center) = (self.age, self.center);
+        let life = FIREBALL_LIFETIME - MAX_PUFF_DELAY;
+        self.billboards = self
+            .puffs
+            .iter()
+            .filter(|p| age >= p.delay)
+            .filter_map(|p| {
+                let t = (age - p.delay) / life;
+                if t >= 1. {
+                    return None;
+                }
+                let elapsed = age - p.delay;
+                let pos = center + p.offset + p.vel * elapsed;
+                let scale = (p.scale * (0.3 + 0.7 * t.sqrt())) as f32;
+                Some(BillboardAttributes {
+                    instance_pos_rot: [
+                        pos.x as f32,
+                        pos.y as f32,
+                        pos.z as f32,
+                        (p.rot + p.rot_vel * elapsed) as f32,
+                    ],
+                    instance_scale: [scale, scale],
+                    instance_color: fireball_color(t).into(),
+                })
+            })
+            .collect();
+        self.instances
+            .update_no_grow(&self.billboards, unsafe { std::mem::zeroed() });
+    }
+
+    fn expired(&self) -> bool {
+        self.age >= FIREBALL_LIFETIME
+    }
+
+    fn lights(&self) -> Option<Vec<shader::LightData>> {
+        if self.age >= FLASH_LIFETIME {
+            return None;
+        }
+        let fade = 1. - self.age / FLASH_LIFETIME;
+        Some(vec![shader::LightData::point_light(
+            self.center.cast().unwrap(),
+            (FLASH_LUMINANCE * self.size * fade * fade) as f32,
+            fireball_color(self.age / FIREBALL_LIFETIME).truncate(),
+        )])
+    }
+
+    fn instance_data(&self) -> glium::vertex::VerticesSource<'_> {
+        From::from(
+            self.instances
+                .get_stored_buffer()
+                .unwrap()
+                .per_instance()
+                .unwrap(),
+        )
+    }
+
+    fn blend_mode(&self) -> shader::BlendMode {
+        shader::BlendMode::Additive
+    }
+
+    fn billboards(&self) -> Option<&[BillboardAttributes]> {
+        Some(&self.billboards)
+    }
+}
+
+/// A ring that expands from an explosion to the edge of its blast and fades
+/// away as it does
+pub struct Shockwave {
+    center: Point3<f64>,
+    radius: f64,
+    /// Seconds since the shockwave started
+    age: f64,
+    billboards: Vec<BillboardAttributes>,
+    instances: InstanceBuffer<BillboardAttributes>,
+}
+
+impl Shockwave {
+    /// Creates a shockwave at `center` that expands to `radius`
+    pub fn new<F: glium::backend::Facade>(
+        center: Point3<f64>,
+        radius: f64,
+        facade: &F,
+    ) -> Self {
+        Self {
+            center,
+            radius,
+            age: 0.,
+            billboards: Vec::new(),
+            instances: InstanceBuffer::new_sized(1, facade),
+        }
+    }
+}
+
+impl Emitter for Shockwave {
+    fn emit(&mut self, dt: Duration) {
+        self.age += dt.as_secs_f64();
+        let t = self.age / SHOCKWAVE_LIFETIME;
+        self.billboards.clear();
+        if t < 1. {
+            let scale = (self.radius * shockwave_expansion(t)) as f32;
+            let fade = (1. - t) as f32;
+            self.billboards.push(BillboardAttributes {
+                instance_pos_rot: [
+                    self.center.x as f32,
+                    self.center.y as f32,
+                    self.center.z as f32,
+                    0.,
+                ],
+                instance_scale: [scale, scale],
+                instance_color: [0.8 * fade, 0.85 * fade, fade, fade],
+            });
+        }
+        self.instances
+            .update_no_grow(&self.billboards, unsafe { std::mem::zeroed() });
+    }
+
+    fn expired(&self) -> bool {
+        self.age >= SHOCKWAVE_LIFETIME
+    }
+
+    fn lights(&self) -> Option<Vec<shader::LightData>> {
+        None
+    }
+
+    fn instance_data(&self) -> glium::vertex::VerticesSource<'_> {
+        From::from(
+            self.instances
+                .get_stored_buffer()
+                .unwrap()
+                .per_instance()
+                .unwrap(),
+        )
+    }
+
+    fn blend_mode(&self) -> shader::BlendMode {
+        shader::BlendMode::Additive
+    }
+
+    fn billboards(&self) -> Option<&[BillboardAttributes]> {
+        Some(&self.billboards)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fireball_cools_and_shockwave_slows() {
+        let start = fireball_color(0.);
+        let mid = fireball_color(0.5);
+        let end = fireball_color(1.);
+        // cools from white to red
+        assert!(start.z > mid.z && start.y > mid.y);
+        assert!(mid.x > end.x);
+        assert!((start.w - 1.).abs() < f32::EPSILON);
+        assert!(end.w.abs() < f32::EPSILON);
+        assert!(shockwave_expansion(0.).abs() < f64::EPSILON);
+        assert!((shockwave_expansion(1.) - 1.).abs() < f64::EPSILON);
+        // covers most of the distance in the first half of its life
+        assert!(shockwave_expansion(0.5) > 0.8);
+    }
+}
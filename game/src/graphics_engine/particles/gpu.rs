@@ -0,0 +1,204 @@
+use super::super::clock;
+use super::super::instancing::GpuParticle;
+use super::super::shader;
+use super::Emitter;
+use crate::cg_support::ssbo;
+use cgmath::*;
+use std::time::{Duration, Instant};
+
+/// Amount of particles simulated by each work group of the compute shader
+const WORK_GROUP_SIZE: u32 = 64;
+
+/// Gets the amount of work groups needed to simulate `particle_num` particles
+const fn work_groups(particle_num: u32) -> u32 {
+    (particle_num + WORK_GROUP_SIZE - 1) / WORK_GROUP_SIZE
+}
+
+/// A particle emitter whose particles are emitted and moved by a compute
+/// shader. The particles never leave the GPU, so much larger effects are
+/// possible than with a `ParticleEmitter`
+///
+/// Each particle is emitted from the emitter's origin in a random direction
+/// within a cone, and moves according to its velocity and a constant
+/// acceleration. Dead particles are replaced while the emitter is active
+///
+/// GPU particles cannot be light sources
+#[allow(clippy::module_name_repetitions)]
+pub struct GpuParticleEmitter {
+    particles: ssbo::Ssbo<GpuParticle>,
+    params: shader::ParticleSimData,
+    blend: shader::BlendMode,
+    /// The instant that the emitter stops emitting particles.
+    /// This is not necessarily the time all particles are no longer visible
+    emitter_end: Option<Instant>,
+}
+
+impl GpuParticleEmitter {
+    /// Creates an emitter of `particle_num` particles at `pos` that lasts
+    /// forever
+    pub fn new(pos: Point3<f64>, particle_num: u32) -> Self {
+        let particles =
+            ssbo::Ssbo::static_alloc_dyn(particle_num as usize, None);
+        // a zeroed particle has an age equal to its lifetime, so it's dead
+        particles.zero_bytes();
+        Self {
+            particles,
+            params: shader::ParticleSimData {
+                dt: 0.,
+                seed: 0,
+                emitting: true,
+                particle_count: particle_num,
+                origin: pos.cast().unwrap().into(),
+                dir: [0., 1., 0.],
+                spread: std::f32::consts::PI,
+                speed: [1., 1.],
+                lifetime: [1., 1.],
+                scale: [1., 1.],
+                start_color: [0.5, 0.5, 0.5, 1.],
+                end_color: [0.5, 0.5, 0.5, 1.],
+                accel: [0., 0., 0.],
+            },
+            blend: shader::BlendMode::Alpha,
+            emitter_end: None,
+        }
+    }
+
+    /// Sets how the particles are blended. GPU particles are never sorted, so
+    /// alpha blended particles may draw in the wrong order
+    #[must_use]
+    #[allow(dead_code)]
+    pub const fn with_blend_mode(mut self, blend: shader::BlendMode) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    /// Sets the time from now that the emitter will stop generating particles
+    #[must_use]
+    pub fn lifetime(mut self, lifetime: Duration) -> Self {
+        self.emitter_end = Some(clock::now() + lifetime);
+        self
+    }
+
+    /// Emits particles within a cone around `dir`
+    ///
+    /// `spread` - half angle of the cone
+    #[must_use]
+    pub fn direction(mut self, dir: Vector3<f64>, spread: Rad<f64>) -> Self {
+        self.params.dir = dir.cast().unwrap().into();
+        self.params.spread = spread.0 as f32;
+        self
+    }
+
+    /// Sets the range of speeds particles are emitted with
+    #[must_use]
+    pub fn speed(mut self, min: f64, max: f64) -> Self {
+        self.params.speed = [min as f32, max as f32];
+        self
+    }
+
+    /// Sets the range of lifetimes of each particle
+    #[must_use]
+    pub fn particle_lifetime(mut self, min: Duration, max: Duration) -> Self {
+        self.params.lifetime = [min.as_secs_f32(), max.as_secs_f32()];
+        self
+    }
+
+    /// Sets the range of uniform scales of each particle
+    #[must_use]
+    pub fn scale(mut self, min: f64, max: f64) -> Self {
+        self.params.scale = [min as f32, max as f32];
+        self
+    }
+
+    /// Sets the color particles are emitted with, and the color they fade to
+    /// by the end of their lifetime
+    #[must_use]
+    pub fn color(mut self, start: Vector4<f32>, end: Vector4<f32>) -> Self {
+        self.params.start_color = start.into();
+        self.params.end_color = end.into();
+        self
+    }
+
+    /// Sets the constant acceleration applied to every particle
+    #[must_use]
+    #[allow(dead_code)]
+    pub fn acceleration(mut self, accel: Vector3<f64>) -> Self {
+        self.params.accel = accel.cast().unwrap().into();
+        self
+    }
+
+    /// `true` if the emitter is still replacing dead particles at `now`
+    fn emitting(&self, now: Instant) -> bool {
+        self.emitter_end.map_or(true, |end| now < end)
+    }
+
+    /// The longest amount of time a particle can live for
+    fn max_particle_lifetime(&self) -> Duration {
+        Duration::from_secs_f32(
+            self.params.lifetime[0].max(self.params.lifetime[1]).max(0.),
+        )
+    }
+}
+
+impl Emitter for GpuParticleEmitter {
+    fn emit(&mut self, dt: Duration) {
+        self.params.dt = dt.as_secs_f32();
+        self.params.seed = rand::random();
+        self.params.emitting = self.emitting(clock::now());
+        self.particles.bind(8);
+        let ctx = super::super::get_active_ctx();
+        ctx.shader.execute_compute(
+            work_groups(self.params.particle_count),
+            1,
+            1,
+            &shader::UniformInfo::ParticleSim(self.params),
+            None,
+        );
+        unsafe {
+            gl::MemoryBarrier(gl::SHADER_STORAGE_BARRIER_BIT);
+        }
+    }
+
+    /// The particles aren't read back from the GPU, so the emitter is
+    /// considered expired once every particle it could have emitted has
+    /// outlived the maximum particle lifetime
+    fn expired(&self) -> bool {
+        self.emitter_end.map_or(false, |end| {
+            clock::now() > end + self.max_particle_lifetime()
+        })
+    }
+
+    fn lights(&self) -> Option<Vec<shader::LightData>> {
+        None
+    }
+
+    /// Gets one empty instance per particle. The particle data is read from
+    /// the particle buffer by the vertex shader
+    fn instance_data(&self) -> glium::vertex::VerticesSource<'_> {
+        From::from(glium::vertex::EmptyInstanceAttributes {
+            len: self.params.particle_count as usize,
+        })
+    }
+
+    fn gpu_particles(&self) -> Option<&ssbo::Ssbo<GpuParticle>> {
+        Some(&self.particles)
+    }
+
+    fn blend_mode(&self) -> shader::BlendMode {
+        self.blend
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn work_groups_cover_all_particles() {
+        assert_eq!(work_groups(0), 0);
+        assert_eq!(work_groups(1), 1);
+        assert_eq!(work_groups(64), 1);
+        assert_eq!(work_groups(65), 2);
+        assert_eq!(work_groups(1000), 16);
+    }
+}
@@ -1,12 +1,41 @@
+mod beam;
+mod explosion;
+mod gpu;
 mod particle;
 mod system;
+mod thruster;
+mod trail;
 use super::instancing;
 use super::shader;
-use crate::cg_support::node;
+use crate::cg_support::{node, ssbo};
+use std::cell::Cell;
 use std::time::Duration;
 
+pub use beam::Beam;
+pub use explosion::{Fireball, Shockwave};
+pub use gpu::GpuParticleEmitter;
 pub use particle::{Particle, ParticleEmitter};
 pub use system::ParticleSystem;
+pub use thruster::{ExhaustCones, ExhaustEmitter, Nozzle};
+pub use trail::TrailEmitter;
+
+std::thread_local! {
+    static DENSITY: Cell<f32> = Cell::new(1.);
+}
+
+/// Sets the fraction of the particles of each effect which are emitted, so
+/// effects created afterwards have fewer particles. Clamped between `0`
+/// and `1`
+pub fn set_density(density: f32) {
+    DENSITY.with(|d| d.set(density.clamp(0., 1.)));
+}
+
+/// Scales `particle_num` by the particle density, keeping at least one
+/// particle
+fn scaled_count(particle_num: u32) -> u32 {
+    let density = f64::from(DENSITY.with(Cell::get));
+    ((f64::from(particle_num) * density).round() as u32).max(1)
+}
 
 pub trait Emitter {
     /// Emits particles and moves them according to the change in time since
@@ -22,6 +51,27 @@ pub trait Emitter {
     fn lights(&self) -> Option<Vec<shader::LightData>>;
 
     fn instance_data(&self) -> glium::vertex::VerticesSource;
+
+    /// Gets the buffer of particles simulated on the GPU, or `None` if the
+    /// particles of this emitter are passed as instance attributes
+    fn gpu_particles(&self) -> Option<&ssbo::Ssbo<instancing::GpuParticle>> {
+        None
+    }
+
+    /// Gets how the particles of this emitter are blended
+    fn blend_mode(&self) -> shader::BlendMode {
+        shader::BlendMode::Alpha
+    }
+
+    /// Gets the instance data of every particle, or `None` if the particles
+    /// aren't available on the CPU
+    ///
+    /// Alpha blended particles that are available on the CPU are sorted back
+    /// to front with the particles of all other emitters. Otherwise, the
+    /// particles are drawn unsorted with `instance_data`
+    fn billboards(&self) -> Option<&[instancing::BillboardAttributes]> {
+        None
+    }
 }
 
 /*
@@ -63,10 +113,13 @@ pub fn dust_emitter<F : glium::backend::Facade>(facade: &F, pos: cgmath::Point3<
 ///
 /// `path` - texture path for particle
 ///
-/// `particle_num` - number of particles
+/// `particle_num` - number of particles, before it is scaled by the particle
+/// density
 ///
 /// `are_lights` - true if all particles are light sources
 ///
+/// `blend` - how the particles are blended
+///
 /// `particle_gen` - generator for new particles
 ///
 /// `particle_step` - callback function each time particles are drawn
@@ -74,6 +127,7 @@ pub fn dust_emitter<F : glium::backend::Facade>(facade: &F, pos: cgmath::Point3<
 pub fn simple_emitter<G, S, F>(
     pos: cgmath::Point3<f64>,
     are_lights: bool,
+    blend: shader::BlendMode,
     particle_num: u32,
     emitter_lifetime: Option<std::time::Duration>,
     facade: &F,
@@ -86,50 +140,56 @@ where
     F: glium::backend::Facade,
 {
     use cgmath::*;
-    use std::time::Instant;
-    Box::new(ParticleEmitter::new(
-        node::Node::default().pos(pos),
-        emitter_lifetime,
-        particle_num,
-        facade,
-        particle_gen,
-        |particle| {
-            Instant::now().duration_since(particle.birth) > particle.lifetime
-        },
-        move |particle, dt| {
-            if let Some(step) = particle_step.as_mut() {
-                step(particle, dt);
-            }
-            particle.transform.translate(particle.vel * dt);
-            particle.transform.rotate_world(particle.rot_vel);
-        },
-        |particle| {
-            let pos = particle.transform.local_pos();
-            let rot = particle.transform.local_rot().s;
-            let scale = particle.transform.local_scale();
-            instancing::BillboardAttributes {
-                instance_color: particle.color.into(),
-                instance_pos_rot: vec4(pos.x, pos.y, pos.z, rot)
-                    .cast()
-                    .unwrap()
-                    .into(),
-                instance_scale: [scale.x as f32, scale.y as f32],
-            }
-        },
-        move |particle| {
-            if !are_lights {
-                return None;
-            }
-            let pt =
-                particle.transform.mat().transform_point(point3(0., 0., 0.));
-            let c = vec3(particle.color.x, particle.color.y, particle.color.z);
-            Some(shader::LightData::point_light(
-                pt.cast().unwrap(),
-                c.magnitude() * 3.,
-                c,
-            ))
-        },
-    ))
+    Box::new(
+        ParticleEmitter::new(
+            node::Node::default().pos(pos),
+            emitter_lifetime,
+            scaled_count(particle_num),
+            facade,
+            particle_gen,
+            |particle| {
+                super::clock::now().duration_since(particle.birth)
+                    > particle.lifetime
+            },
+            move |particle, dt| {
+                if let Some(step) = particle_step.as_mut() {
+                    step(particle, dt);
+                }
+                particle.transform.translate(particle.vel * dt);
+                particle.transform.rotate_world(particle.rot_vel);
+            },
+            |particle| {
+                let pos = particle.transform.local_pos();
+                let rot = particle.transform.local_rot().s;
+                let scale = particle.transform.local_scale();
+                instancing::BillboardAttributes {
+                    instance_color: particle.color.into(),
+                    instance_pos_rot: vec4(pos.x, pos.y, pos.z, rot)
+                        .cast()
+                        .unwrap()
+                        .into(),
+                    instance_scale: [scale.x as f32, scale.y as f32],
+                }
+            },
+            move |particle| {
+                if !are_lights {
+                    return None;
+                }
+                let pt = particle
+                    .transform
+                    .mat()
+                    .transform_point(point3(0., 0., 0.));
+                let c =
+                    vec3(particle.color.x, particle.color.y, particle.color.z);
+                Some(shader::LightData::point_light(
+                    pt.cast().unwrap(),
+                    c.magnitude() * 3.,
+                    c,
+                ))
+            },
+        )
+        .with_blend_mode(blend),
+    )
 }
 use cgmath::*;
 
@@ -147,6 +207,7 @@ pub fn laser_hit_emitter<F: glium::backend::Facade>(
     simple_emitter(
         body_pos,
         true,
+        shader::BlendMode::Additive,
         rnd.gen_range(10..50),
         Some(std::time::Duration::from_millis(10)),
         facade,
@@ -181,40 +242,36 @@ pub fn asteroid_hit_emitter<F: glium::backend::Facade>(
     body_pos: Point3<f64>,
     body_normal: Vector3<f64>,
     relative_velocity: Vector3<f64>,
-    facade: &F,
+    _facade: &F,
 ) -> Box<dyn Emitter> {
-    use rand::Rng;
-    let d = body_normal.dot(body_pos.to_vec());
-    let z = (d - body_normal.x) / body_normal.z; // z coord of point with x = 1, y = 0 on the plane
-    let v = vec3(1., 0., z) - body_pos.to_vec(); // line on the plane
-                                                 //let mut rnd = rand::thread_rng();
-    simple_emitter(
-        body_pos,
-        false,
-        20,
-        Some(std::time::Duration::from_millis(8)),
-        facade,
-        move |origin| {
-            let mut rnd = rand::thread_rng();
-            let mag = relative_velocity.magnitude() / 10.;
-            let phi = rnd.gen_range(0. ..std::f64::consts::PI / 5.);
-            let theta = rnd.gen_range(0. ..2. * std::f64::consts::PI);
-            let z = body_normal.cross(v);
-            let vel = v.normalize() * f64::cos(theta) * f64::sin(phi)
-                + body_normal.normalize() * f64::sin(theta) * f64::cos(phi)
-                + z.normalize() * f64::cos(phi);
-            // randomly select a vector on a unit sphere with the normal as the zenith
-            let origin_pos = origin.transform_point(point3(0., 0., 0.));
-            Particle::new(
-                origin_pos,
-                node::Node::default()
-                    .pos(origin_pos)
-                    .u_scale(rnd.gen_range(0.6..1.2)),
+    let speed = relative_velocity.magnitude() / 10.;
+    let color = vec4(0.421_875, 0.2265_625, 0.0468_75, 0.5);
+    Box::new(
+        GpuParticleEmitter::new(body_pos, scaled_count(256))
+            .lifetime(Duration::from_millis(8))
+            .direction(body_normal, Rad(std::f64::consts::PI / 5.))
+            .speed(speed * 0.5, speed)
+            .particle_lifetime(
+                Duration::from_millis(300),
+                Duration::from_millis(1000),
             )
-            .color(vec4(0.421_875, 0.2265_625, 0.0468_75, 0.5))
-            .lifetime(Duration::from_millis(rnd.gen_range(300..1000)))
-            .vel(vel.normalize() * mag)
-        },
-        Some(|_: &mut Particle, _| ()),
+            .scale(0.6, 1.2)
+            .color(color, vec4(color.x, color.y, color.z, 0.)),
+    )
+}
+
+/// Gets the burst of debris of an object being destroyed at `pos`
+pub fn explosion_emitter(pos: Point3<f64>) -> Box<dyn Emitter> {
+    let color = vec4(1., 0.55, 0.2, 0.9);
+    Box::new(
+        GpuParticleEmitter::new(pos, scaled_count(512))
+            .lifetime(Duration::from_millis(16))
+            .speed(5., 20.)
+            .particle_lifetime(
+                Duration::from_millis(500),
+                Duration::from_millis(1500),
+            )
+            .scale(0.8, 2.)
+            .color(color, vec4(0.3, 0.3, 0.3, 0.)),
     )
 }
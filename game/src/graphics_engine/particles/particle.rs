@@ -1,3 +1,4 @@
+use super::super::clock;
 use super::super::instancing::*;
 use super::super::shader;
 use super::Emitter;
@@ -24,7 +25,7 @@ impl Particle {
         particle_transform: Node,
     ) -> Self {
         Self {
-            birth: std::time::Instant::now(),
+            birth: clock::now(),
             transform: particle_transform,
             origin: emitter_location,
             color: vec4(0.5, 0.5, 0.5, 1.0),
@@ -61,13 +62,12 @@ impl Particle {
 }
 
 #[allow(clippy::module_name_repetitions)]
-pub struct ParticleEmitter<I, S, D, Ia, G, Lg>
+pub struct ParticleEmitter<I, S, D, G, Lg>
 where
     I: Fn(&Node) -> Particle,
     S: FnMut(&mut Particle, f64),
     D: Fn(&Particle) -> bool,
-    Ia: glium::Vertex + Copy,
-    G: Fn(&Particle) -> Ia,
+    G: Fn(&Particle) -> BillboardAttributes,
     Lg: Fn(&Particle) -> Option<shader::LightData>,
 {
     pos: Node,
@@ -84,18 +84,22 @@ where
     /// This is not necessarily the time all particles are no longer visible
     emitter_end: Option<Instant>,
     particles: VecDeque<Particle>,
-    instances: InstanceBuffer<Ia>,
+    /// Instance data of the particles, from the last call to `emit`
+    billboards: Vec<BillboardAttributes>,
+    /// Only updated for additive blended emitters. Alpha blended particles
+    /// are sorted and uploaded by the particle system
+    instances: InstanceBuffer<BillboardAttributes>,
+    blend: shader::BlendMode,
     /// Function that turns a particle into a light source
     light_getter: Lg,
 }
 
-impl<I, S, D, Ia, G, Lg> ParticleEmitter<I, S, D, Ia, G, Lg>
+impl<I, S, D, G, Lg> ParticleEmitter<I, S, D, G, Lg>
 where
     I: Fn(&Node) -> Particle,
     S: FnMut(&mut Particle, f64),
     D: Fn(&Particle) -> bool,
-    Ia: glium::Vertex + Copy,
-    G: Fn(&Particle) -> Ia,
+    G: Fn(&Particle) -> BillboardAttributes,
     Lg: Fn(&Particle) -> Option<shader::LightData>,
 {
     /// Creates a new particle emitter
@@ -125,32 +129,44 @@ where
     ) -> Self {
         Self {
             pos,
-            emitter_end: lifetime.map(|duration| Instant::now() + duration),
+            emitter_end: lifetime.map(|duration| clock::now() + duration),
             num_particles: num,
             instances: InstanceBuffer::new_sized(num as usize, facade),
             gen_particle: particle_generator,
             dead_particle: particle_killer,
             step_particle: particle_stepper,
             particles: VecDeque::new(),
+            billboards: Vec::new(),
             particle_data: particle_getter,
+            blend: shader::BlendMode::Alpha,
             light_getter,
         }
     }
 
+    /// Sets how the particles are blended
+    #[must_use]
+    pub fn with_blend_mode(mut self, blend: shader::BlendMode) -> Self {
+        self.blend = blend;
+        self
+    }
+
     fn update_instance_buffer(&mut self) {
-        let v: Vec<Ia> = self
+        self.billboards = self
             .particles
             .iter()
             .map(|x| (self.particle_data)(x))
             .collect();
-        self.instances
-            .update_no_grow(&v, unsafe { std::mem::zeroed() });
+        if self.blend == shader::BlendMode::Additive {
+            self.instances.update_no_grow(&self.billboards, unsafe {
+                std::mem::zeroed()
+            });
+        }
     }
 
     /// If `particles.len() < num_partices`, generates new particles
     fn replenish_particles(&mut self) {
         if self.emitter_end.is_none()
-            || Instant::now() < self.emitter_end.unwrap()
+            || clock::now() < self.emitter_end.unwrap()
         {
             for _ in 0..self.num_particles as usize - self.particles.len() {
                 self.particles.push_back((self.gen_particle)(&self.pos));
@@ -159,13 +175,12 @@ where
     }
 }
 
-impl<I, S, D, Ia, G, Lg> Emitter for ParticleEmitter<I, S, D, Ia, G, Lg>
+impl<I, S, D, G, Lg> Emitter for ParticleEmitter<I, S, D, G, Lg>
 where
     I: Fn(&Node) -> Particle,
     S: FnMut(&mut Particle, f64),
     D: Fn(&Particle) -> bool,
-    Ia: glium::Vertex + Copy,
-    G: Fn(&Particle) -> Ia,
+    G: Fn(&Particle) -> BillboardAttributes,
     Lg: Fn(&Particle) -> Option<shader::LightData>,
 {
     fn emit(&mut self, dt: Duration) {
@@ -186,7 +201,7 @@ where
     fn expired(&self) -> bool {
         self.particles.is_empty()
             && self.emitter_end.is_some()
-            && Instant::now() > self.emitter_end.unwrap()
+            && clock::now() > self.emitter_end.unwrap()
     }
 
     fn lights(&self) -> Option<Vec<shader::LightData>> {
@@ -213,4 +228,12 @@ where
                 .unwrap(),
         )
     }
+
+    fn blend_mode(&self) -> shader::BlendMode {
+        self.blend
+    }
+
+    fn billboards(&self) -> Option<&[BillboardAttributes]> {
+        Some(&self.billboards)
+    }
 }
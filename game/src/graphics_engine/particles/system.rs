@@ -1,17 +1,54 @@
 use super::super::drawable::{Drawable, VertexHolder};
 use super::super::entity::{AbstractEntity, RenderOrder};
+use super::super::instancing::{BillboardAttributes, InstanceBuffer};
 use super::super::shader;
 use super::Emitter;
 use crate::cg_support::Transformation;
+use cgmath::*;
 use std::cell::RefCell;
 use std::collections::VecDeque;
 use std::rc::Rc;
 
+/// Sorts `particles`, tagged with the index of the drawable they are drawn
+/// with, from farthest to closest to `view_pos`
+///
+/// Returns runs of consecutive particles that use the same drawable, so each
+/// run can be drawn with one draw call
+fn sort_back_to_front(
+    view_pos: Point3<f32>,
+    mut particles: Vec<(usize, BillboardAttributes)>,
+) -> Vec<(usize, Vec<BillboardAttributes>)> {
+    let dist2 = |p: &BillboardAttributes| {
+        let [x, y, z, _] = p.instance_pos_rot;
+        view_pos.distance2(point3(x, y, z))
+    };
+    particles.sort_by(|(_, a), (_, b)| {
+        dist2(b)
+            .partial_cmp(&dist2(a))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    let mut runs: Vec<(usize, Vec<BillboardAttributes>)> = Vec::new();
+    for (draw_idx, particle) in particles {
+        match runs.last_mut() {
+            Some((idx, run)) if *idx == draw_idx => run.push(particle),
+            _ => runs.push((draw_idx, vec![particle])),
+        }
+    }
+    runs
+}
+
 /// A collection of particle emitters
+///
+/// Alpha blended particles of all emitters are sorted back to front when
+/// `sort_particles` is called. Additive blended and GPU particles are drawn
+/// unsorted, after the alpha blended particles
 #[allow(clippy::module_name_repetitions)]
 pub struct ParticleSystem {
     emitters: VecDeque<(Box<dyn Emitter>, usize)>,
     drawables: Vec<Box<dyn Drawable>>,
+    /// Runs of sorted alpha blended particles and the index of the drawable
+    /// they are drawn with
+    sorted_runs: Vec<(usize, InstanceBuffer<BillboardAttributes>)>,
 }
 
 impl ParticleSystem {
@@ -19,6 +56,7 @@ impl ParticleSystem {
         Self {
             emitters: VecDeque::new(),
             drawables: Vec::new(),
+            sorted_runs: Vec::new(),
         }
     }
 
@@ -79,6 +117,32 @@ impl ParticleSystem {
         });
     }
 
+    /// Sorts the alpha blended particles of all emitters back to front from
+    /// `view_pos`. Should be called after `emit` each frame
+    pub fn sort_particles(&mut self, view_pos: Point3<f32>) {
+        let particles = self
+            .emitters
+            .iter()
+            .filter(|(e, _)| e.blend_mode() == shader::BlendMode::Alpha)
+            .filter_map(|(e, idx)| e.billboards().map(|b| (b, *idx)))
+            .flat_map(|(billboards, idx)| {
+                billboards.iter().map(move |b| (idx, *b))
+            })
+            .collect();
+        let runs = sort_back_to_front(view_pos, particles);
+        self.sorted_runs.truncate(runs.len());
+        self.sorted_runs
+            .resize_with(runs.len(), || (0, InstanceBuffer::new()));
+        let ctx = super::super::get_active_ctx();
+        let ctx = ctx.ctx.borrow();
+        for ((draw_idx, buffer), (run_idx, run)) in
+            self.sorted_runs.iter_mut().zip(runs)
+        {
+            *draw_idx = run_idx;
+            buffer.update_buffer(&run, &*ctx);
+        }
+    }
+
     pub fn lights(&self) -> Option<Vec<shader::LightData>> {
         let mut lights = Vec::new();
         for (e, _) in &self.emitters {
@@ -103,12 +167,38 @@ impl Drawable for ParticleSystem {
         VertexHolder<'a>,
         glium::index::IndicesSource<'a>,
     )> {
+        use shader::{BlendMode, UniformInfo};
         let mut v = Vec::new();
         let drawables = self.drawables.as_mut_ptr();
+        for (draw_idx, instances) in &self.sorted_runs {
+            if let Some(buffer) = instances.get_stored_buffer() {
+                let (u, vh, i) = unsafe { &mut *drawables.add(*draw_idx) }
+                    .render_args(p)
+                    .swap_remove(0);
+                v.push((
+                    u,
+                    vh.append(From::from(buffer.per_instance().unwrap())),
+                    i,
+                ));
+            }
+        }
         for (e, draw_idx) in &mut self.emitters {
+            let blend = e.blend_mode();
+            if blend == BlendMode::Alpha && e.billboards().is_some() {
+                continue;
+            }
             let (u, vh, i) = unsafe { &mut *drawables.add(*draw_idx) }
                 .render_args(p)
                 .swap_remove(0);
+            let u = match (u, e.gpu_particles()) {
+                (UniformInfo::Billboard(tex, density, _), Some(buf)) => {
+                    UniformInfo::GpuBillboard(tex, density, blend, buf)
+                }
+                (UniformInfo::Billboard(tex, density, _), None) => {
+                    UniformInfo::Billboard(tex, density, blend)
+                }
+                (u, _) => u,
+            };
             v.push((u, vh.append(e.instance_data()), i));
         }
         v
@@ -136,3 +226,48 @@ impl AbstractEntity for ParticleSystem {
         self as *const Self as usize
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn particle_at(z: f32) -> BillboardAttributes {
+        BillboardAttributes {
+            instance_pos_rot: [0., 0., z, 0.],
+            instance_scale: [1., 1.],
+            instance_color: [1., 1., 1., 1.],
+        }
+    }
+
+    #[test]
+    fn particles_sort_back_to_front() {
+        let particles = vec![
+            (0, particle_at(1.)),
+            (1, particle_at(5.)),
+            (0, particle_at(-3.)),
+            (0, particle_at(4.)),
+            (1, particle_at(2.)),
+        ];
+        let runs = sort_back_to_front(point3(0., 0., 0.), particles);
+        let order: Vec<_> = runs
+            .iter()
+            .map(|(idx, run)| {
+                (
+                    *idx,
+                    run.iter()
+                        .map(|p| p.instance_pos_rot[2])
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            order,
+            vec![
+                (1, vec![5.]),
+                (0, vec![4., -3.]),
+                (1, vec![2.]),
+                (0, vec![1.])
+            ]
+        );
+    }
+}
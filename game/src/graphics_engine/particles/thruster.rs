@@ -0,0 +1,345 @@
+use super::super::drawable::*;
+use super::super::entity::{AbstractEntity, RenderOrder};
+use super::super::instancing::{BillboardAttributes, InstanceBuffer};
+use super::super::shader;
+use super::trail::TrailVertex;
+use super::Emitter;
+use crate::cg_support::{node, Transformation};
+use cgmath::*;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::{Rc, Weak};
+use std::time::Duration;
+
+/// Points along the center line of each exhaust cone
+const CONE_SAMPLES: u32 = 4;
+/// Most exhaust particles an emitter has alive at once
+const MAX_EXHAUST_PARTICLES: u32 = 48;
+/// Particles emitted per second by a nozzle firing at full intensity
+const EXHAUST_RATE: f64 = 120.;
+/// Seconds an exhaust particle lives for
+const EXHAUST_LIFETIME: f64 = 0.3;
+/// Speed exhaust particles leave a nozzle firing at full intensity with,
+/// relative to the nozzle
+const EXHAUST_SPEED: f64 = 30.;
+
+/// The nozzle of a thruster, whose exhaust leaves along the local `+z` axis
+/// of its node
+pub struct Nozzle {
+    /// Transform of the nozzle, which is usually a child of the ship's node
+    pub node: Rc<RefCell<node::Node>>,
+    /// How hard the thruster fires, from `0` when it's off. Values above `1`
+    /// are boosted
+    pub intensity: f64,
+    /// Length of the exhaust cone at full intensity
+    pub length: f64,
+}
+
+impl Nozzle {
+    /// Gets the world space position of the nozzle and the direction its
+    /// exhaust leaves in
+    fn exhaust(&self) -> (Point3<f64>, Vector3<f64>) {
+        let node = self.node.borrow();
+        let dir = node.transform_vec(vec3(0., 0., 1.));
+        let dir = if dir.magnitude2() > f64::EPSILON {
+            dir.normalize()
+        } else {
+            dir
+        };
+        (node.transform_point(point3(0., 0., 0.)), dir)
+    }
+}
+
+/// The glowing exhaust cones of a ship's thrusters
+///
+/// Each firing nozzle is drawn with the trail shader as a camera-facing
+/// strip that tapers from the nozzle to the tip of its cone. Weaker
+/// thrusters have shorter, narrower and dimmer cones
+pub struct ExhaustCones {
+    nozzles: Vec<Rc<RefCell<Nozzle>>>,
+    args: shader::TrailData,
+    vertices: InstanceBuffer<TrailVertex>,
+    indices: glium::index::NoIndices,
+}
+
+impl ExhaustCones {
+    pub fn new(nozzles: Vec<Rc<RefCell<Nozzle>>>) -> Self {
+        Self {
+            nozzles,
+            args: shader::TrailData {
+                color: [0.3, 0.7, 1., 0.9],
+                emission: 8.,
+                width: 1.2,
+                width_falloff: 1.,
+                alpha_falloff: 1.5,
+            },
+            vertices: InstanceBuffer::new(),
+            indices: glium::index::NoIndices(
+                glium::index::PrimitiveType::TriangleStrip,
+            ),
+        }
+    }
+
+    /// Replaces the nozzles the cones are drawn from
+    pub fn set_nozzles(&mut self, nozzles: Vec<Rc<RefCell<Nozzle>>>) {
+        self.nozzles = nozzles;
+    }
+
+    /// Gets the vertices of a triangle strip of every firing nozzle's cone.
+    /// Consecutive cones are joined by degenerate triangles
+    fn strip_vertices(&self) -> Vec<TrailVertex> {
+        let mut verts = Vec::new();
+        for nozzle in &self.nozzles {
+            let nozzle = nozzle.borrow();
+            if nozzle.intensity <= f64::EPSILON {
+                continue;
+            }
+            let (pos, dir) = nozzle.exhaust();
+            let len = nozzle.length * nozzle.intensity;
+            // weaker cones start further along the falloff, so they're
+            // narrower and dimmer
+            let start_t = (1. - nozzle.intensity).max(0.);
+            let cone = (0..CONE_SAMPLES).flat_map(|i| {
+                let f = f64::from(i) / f64::from(CONE_SAMPLES - 1);
+                let t = (start_t + (1. - start_t) * f) as f32;
+                [-1f32, 1f32].map(|side| TrailVertex {
+                    trail_pos: (pos + dir * len * f).cast().unwrap().into(),
+                    trail_dir: dir.cast().unwrap().into(),
+                    trail_side: side,
+                    trail_t: t,
+                })
+            });
+            let first = verts.len();
+            verts.extend(cone);
+            if first > 0 {
+                let (prev, next) = (verts[first - 1], verts[first]);
+                verts.splice(first..first, [prev, next]);
+            }
+        }
+        verts
+    }
+}
+
+impl Drawable for ExhaustCones {
+    fn render_args<'a>(
+        &'a mut self,
+        _: &[[[f32; 4]; 4]],
+    ) -> Vec<(
+        shader::UniformInfo,
+        VertexHolder<'a>,
+        glium::index::IndicesSource<'a>,
+    )> {
+        let verts = self.strip_vertices();
+        if verts.is_empty() {
+            return Vec::new();
+        }
+        {
+            let ctx = super::super::get_active_ctx();
+            let ctx = ctx.ctx.borrow();
+            self.vertices.update_buffer(&verts, &*ctx);
+        }
+        vec![(
+            shader::UniformInfo::Trail(self.args),
+            VertexHolder::new(VertexSourceData::Single(From::from(
+                self.vertices.get_stored_buffer().unwrap(),
+            ))),
+            From::from(&self.indices),
+        )]
+    }
+
+    fn transparency(&self) -> Option<f32> {
+        None
+    }
+}
+
+impl AbstractEntity for ExhaustCones {
+    fn transformations(&self) -> Option<&[Rc<RefCell<dyn Transformation>>]> {
+        None
+    }
+
+    fn drawable(&mut self) -> &mut dyn Drawable {
+        self
+    }
+
+    fn should_render(&self, pass: shader::RenderPassType) -> bool {
+        pass == shader::RenderPassType::Visual
+    }
+
+    fn render_order(&self) -> RenderOrder {
+        RenderOrder::Last
+    }
+
+    fn get_id(&self) -> usize {
+        self as *const Self as usize
+    }
+}
+
+struct ExhaustParticle {
+    pos: Point3<f64>,
+    vel: Vector3<f64>,
+    /// Seconds since the particle was emitted
+    age: f64,
+}
+
+/// Emits the exhaust particles of a nozzle, at a rate and speed that scale
+/// with how hard the nozzle fires
+///
+/// The emitter expires once its nozzle is dropped and its last particles
+/// have died
+pub struct ExhaustEmitter {
+    nozzle: Weak<RefCell<Nozzle>>,
+    particles: VecDeque<ExhaustParticle>,
+    /// Fraction of a particle left over from the previous frames
+    pending: f64,
+    /// World space position of the nozzle last frame, or `None` before the
+    /// first frame
+    last_pos: Option<Point3<f64>>,
+    color: Vector4<f32>,
+    billboards: Vec<BillboardAttributes>,
+    instances: InstanceBuffer<BillboardAttributes>,
+}
+
+impl ExhaustEmitter {
+    pub fn new<F: glium::backend::Facade>(
+        nozzle: &Rc<RefCell<Nozzle>>,
+        facade: &F,
+    ) -> Self {
+        Self {
+            nozzle: Rc::downgrade(nozzle),
+            particles: VecDeque::new(),
+            pending: 0.,
+            last_pos: None,
+            color: vec4(0.3, 0.6, 1., 0.8),
+            billboards: Vec::new(),
+            instances: InstanceBuffer::new_sized(
+                MAX_EXHAUST_PARTICLES as usize,
+                facade,
+            ),
+        }
+    }
+
+    /// Emits the particles of the nozzle firing for `dt` seconds
+    fn emit_from(&mut self, nozzle: &Nozzle, dt: f64) {
+        let (pos, dir) = nozzle.exhaust();
+        let nozzle_vel = match self.last_pos {
+            Some(last) if dt > f64::EPSILON => (pos - last) / dt,
+            _ => vec3(0., 0., 0.),
+        };
+        self.last_pos = Some(pos);
+        let intensity = nozzle.intensity.max(0.);
+        self.pending += EXHAUST_RATE * intensity * dt;
+        let room = MAX_EXHAUST_PARTICLES as usize - self.particles.len();
+        let count = (self.pending.floor() as usize).min(room);
+        self.pending = self.pending.fract();
+        let mut rnd = rand::thread_rng();
+        for _ in 0..count {
+            use rand::Rng;
+            let spread = vec3(
+                rnd.gen_range(-0.15..0.15),
+                rnd.gen_range(-0.15..0.15),
+                rnd.gen_range(-0.15..0.15),
+            );
+            self.particles.push_back(ExhaustParticle {
+                pos,
+                vel: nozzle_vel
+                    + (dir + spread) * EXHAUST_SPEED * intensity.min(1.5),
+                age: rnd.gen_range(0. ..EXHAUST_LIFETIME * 0.3),
+            });
+        }
+    }
+}
+
+impl Emitter for ExhaustEmitter {
+    fn emit(&mut self, dt: Duration) {
+        let dt = dt.as_secs_f64();
+        for p in &mut self.particles {
+            p.age += dt;
+            p.pos += p.vel * dt;
+        }
+        self.particles.retain(|p| p.age < EXHAUST_LIFETIME);
+        if let Some(nozzle) = self.nozzle.upgrade() {
+            self.emit_from(&nozzle.borrow(), dt);
+        }
+        let color = self.color;
+        self.billboards = self
+            .particles
+            .iter()
+            .map(|p| {
+                let life = (1. - p.age / EXHAUST_LIFETIME) as f32;
+                let scale = (0.3 + 0.5 * p.age / EXHAUST_LIFETIME) as f32;
+                BillboardAttributes {
+                    instance_pos_rot: [
+                        p.pos.x as f32,
+                        p.pos.y as f32,
+                        p.pos.z as f32,
+                        0.,
+                    ],
+                    instance_scale: [scale, scale],
+                    instance_color: (color * life).into(),
+                }
+            })
+            .collect();
+        self.instances
+            .update_no_grow(&self.billboards, unsafe { std::mem::zeroed() });
+    }
+
+    fn expired(&self) -> bool {
+        self.nozzle.upgrade().is_none() && self.particles.is_empty()
+    }
+
+    fn lights(&self) -> Option<Vec<shader::LightData>> {
+        None
+    }
+
+    fn instance_data(&self) -> glium::vertex::VerticesSource<'_> {
+        From::from(
+            self.instances
+                .get_stored_buffer()
+                .unwrap()
+                .per_instance()
+                .unwrap(),
+        )
+    }
+
+    fn blend_mode(&self) -> shader::BlendMode {
+        shader::BlendMode::Additive
+    }
+
+    fn billboards(&self) -> Option<&[BillboardAttributes]> {
+        Some(&self.billboards)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn nozzle(intensity: f64) -> Rc<RefCell<Nozzle>> {
+        Rc::new(RefCell::new(Nozzle {
+            node: Rc::new(RefCell::new(
+                node::Node::default().pos(point3(0., 0., -3.)),
+            )),
+            intensity,
+            length: 4.,
+        }))
+    }
+
+    #[test]
+    fn cones_of_firing_nozzles_are_joined() {
+        let firing = nozzle(1.);
+        let cones = ExhaustCones::new(vec![nozzle(0.), firing.clone()]);
+        let verts = cones.strip_vertices();
+        assert_eq!(verts.len(), CONE_SAMPLES as usize * 2);
+        assert_eq!(verts[0].trail_pos, [0., 0., -3.]);
+        assert_eq!(verts[verts.len() - 1].trail_pos, [0., 0., 1.]);
+        assert!(verts[0].trail_t.abs() < f32::EPSILON);
+
+        let cones = ExhaustCones::new(vec![firing, nozzle(0.5)]);
+        let verts = cones.strip_vertices();
+        // two degenerate vertices join the cones
+        assert_eq!(verts.len(), CONE_SAMPLES as usize * 4 + 2);
+        let second = CONE_SAMPLES as usize * 2 + 2;
+        assert!((verts[second].trail_t - 0.5).abs() < f32::EPSILON);
+        assert_eq!(verts[verts.len() - 1].trail_pos, [0., 0., -1.]);
+    }
+}
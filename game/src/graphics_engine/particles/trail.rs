@@ -0,0 +1,303 @@
+use super::super::clock;
+use super::super::drawable::*;
+use super::super::entity::{AbstractEntity, RenderOrder};
+use super::super::instancing::InstanceBuffer;
+use super::super::shader;
+use crate::cg_support::{node, Transformation};
+use cgmath::*;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+#[derive(Copy, Clone)]
+pub struct TrailVertex {
+    /// Point on the center line of the trail
+    pub trail_pos: [f32; 3],
+    /// Direction of the trail at `trail_pos`
+    pub trail_dir: [f32; 3],
+    /// `-1` or `1` depending on which side of the center line the vertex is
+    pub trail_side: f32,
+    /// `0` at the head of the trail and `1` at the tail
+    pub trail_t: f32,
+}
+
+glium::implement_vertex!(
+    TrailVertex,
+    trail_pos,
+    trail_dir,
+    trail_side,
+    trail_t
+);
+
+/// A point the attachment passed through
+struct TrailSample {
+    pos: Point3<f64>,
+    time: Instant,
+}
+
+/// A glowing ribbon left behind a moving node, such as an engine trail
+///
+/// The trail samples the position of its attachment over time and is drawn
+/// as a camera-facing triangle strip whose width and opacity fall off from
+/// the head to the tail. The trail is emissive so it is picked up by bloom
+#[allow(clippy::module_name_repetitions)]
+pub struct TrailEmitter {
+    attachment: Rc<RefCell<node::Node>>,
+    /// Point in the attachment's local space the trail is emitted from
+    offset: Point3<f64>,
+    /// Samples ordered from newest to oldest
+    samples: VecDeque<TrailSample>,
+    /// How long a sampled point stays in the trail
+    duration: Duration,
+    sample_interval: Duration,
+    /// Distance between consecutive samples above which the trail is
+    /// restarted, so teleporting doesn't draw a trail across the map
+    max_segment_len: f64,
+    args: shader::TrailData,
+    vertices: InstanceBuffer<TrailVertex>,
+    indices: glium::index::NoIndices,
+}
+
+impl TrailEmitter {
+    /// Creates a trail emitted from the origin of `attachment`
+    pub fn new(attachment: Rc<RefCell<node::Node>>) -> Self {
+        Self {
+            attachment,
+            offset: point3(0., 0., 0.),
+            samples: VecDeque::new(),
+            duration: Duration::from_millis(600),
+            sample_interval: Duration::from_millis(16),
+            max_segment_len: 50.,
+            args: shader::TrailData {
+                color: [0.1, 0.6, 1., 1.],
+                emission: 6.,
+                width: 1.,
+                width_falloff: 1.,
+                alpha_falloff: 2.,
+            },
+            vertices: InstanceBuffer::new(),
+            indices: glium::index::NoIndices(
+                glium::index::PrimitiveType::TriangleStrip,
+            ),
+        }
+    }
+
+    /// Sets the point, relative to the attachment, the trail is emitted from
+    #[must_use]
+    pub const fn offset(mut self, offset: Point3<f64>) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Moves the point, relative to the attachment, the trail is emitted from
+    pub fn set_offset(&mut self, offset: Point3<f64>) {
+        self.offset = offset;
+    }
+
+    /// Changes the width of the head of the trail and the emissive strength
+    /// of the trail
+    pub fn set_style(&mut self, width: f32, emission: f32) {
+        self.args.width = width;
+        self.args.emission = emission;
+    }
+
+    /// Sets how long it takes the tail of the trail to catch up to a point
+    /// the head passed through
+    #[must_use]
+    #[allow(dead_code)]
+    pub const fn duration(mut self, duration: Duration) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    /// Sets the width of the head of the trail
+    #[must_use]
+    pub const fn width(mut self, width: f32) -> Self {
+        self.args.width = width;
+        self
+    }
+
+    /// Sets the exponents of the width and opacity falloff along the length
+    /// of the trail. An exponent of `1` is a linear falloff
+    #[must_use]
+    #[allow(dead_code)]
+    pub const fn falloff(mut self, width_exp: f32, alpha_exp: f32) -> Self {
+        self.args.width_falloff = width_exp;
+        self.args.alpha_falloff = alpha_exp;
+        self
+    }
+
+    /// Sets the color of the trail, in linear space, and the factor it's
+    /// multiplied by
+    #[must_use]
+    pub const fn color(mut self, color: [f32; 4], emission: f32) -> Self {
+        self.args.color = color;
+        self.args.emission = emission;
+        self
+    }
+
+    /// Records the current position of the attachment and removes samples
+    /// older than the trail's duration
+    ///
+    /// The head of the trail always follows the attachment, but a new point is
+    /// only added once every sample interval
+    fn sample(&mut self, now: Instant) {
+        let pos = self.attachment.borrow().mat().transform_point(self.offset);
+        if self.samples.front().map_or(false, |head| {
+            head.pos.distance2(pos)
+                > self.max_segment_len * self.max_segment_len
+        }) {
+            self.samples.clear();
+        }
+        let sample = TrailSample { pos, time: now };
+        match self.samples.get(1) {
+            Some(prev)
+                if now.saturating_duration_since(prev.time)
+                    < self.sample_interval =>
+            {
+                self.samples[0] = sample;
+            }
+            _ => self.samples.push_front(sample),
+        }
+        let duration = self.duration;
+        while self.samples.len() > 1
+            && self.samples.back().map_or(false, |tail| {
+                now.saturating_duration_since(tail.time) > duration
+            })
+        {
+            self.samples.pop_back();
+        }
+    }
+
+    /// Gets the vertices of the triangle strip along the sampled points
+    fn strip_vertices(&self, now: Instant) -> Vec<TrailVertex> {
+        if self.samples.len() < 2 {
+            return Vec::new();
+        }
+        let last = self.samples.len() - 1;
+        let mut verts = Vec::with_capacity(self.samples.len() * 2);
+        for (i, sample) in self.samples.iter().enumerate() {
+            let ahead = &self.samples[i.saturating_sub(1)];
+            let behind = &self.samples[(i + 1).min(last)];
+            let dir = ahead.pos - behind.pos;
+            let dir = if dir.magnitude2() > f64::EPSILON {
+                dir.normalize()
+            } else {
+                vec3(0., 0., 0.)
+            };
+            let t = (now.saturating_duration_since(sample.time).as_secs_f64()
+                / self.duration.as_secs_f64())
+            .min(1.);
+            for side in [-1f32, 1f32] {
+                verts.push(TrailVertex {
+                    trail_pos: sample.pos.cast().unwrap().into(),
+                    trail_dir: dir.cast().unwrap().into(),
+                    trail_side: side,
+                    trail_t: t as f32,
+                });
+            }
+        }
+        verts
+    }
+}
+
+impl Drawable for TrailEmitter {
+    fn render_args<'a>(
+        &'a mut self,
+        _: &[[[f32; 4]; 4]],
+    ) -> Vec<(
+        shader::UniformInfo,
+        VertexHolder<'a>,
+        glium::index::IndicesSource<'a>,
+    )> {
+        let now = clock::now();
+        self.sample(now);
+        let verts = self.strip_vertices(now);
+        if verts.is_empty() {
+            return Vec::new();
+        }
+        {
+            let ctx = super::super::get_active_ctx();
+            let ctx = ctx.ctx.borrow();
+            self.vertices.update_buffer(&verts, &*ctx);
+        }
+        vec![(
+            shader::UniformInfo::Trail(self.args),
+            VertexHolder::new(VertexSourceData::Single(From::from(
+                self.vertices.get_stored_buffer().unwrap(),
+            ))),
+            From::from(&self.indices),
+        )]
+    }
+
+    fn transparency(&self) -> Option<f32> {
+        None
+    }
+}
+
+impl AbstractEntity for TrailEmitter {
+    fn transformations(&self) -> Option<&[Rc<RefCell<dyn Transformation>>]> {
+        None
+    }
+
+    fn drawable(&mut self) -> &mut dyn Drawable {
+        self
+    }
+
+    fn should_render(&self, pass: shader::RenderPassType) -> bool {
+        pass == shader::RenderPassType::Visual
+    }
+
+    fn render_order(&self) -> RenderOrder {
+        RenderOrder::Last
+    }
+
+    fn get_id(&self) -> usize {
+        self as *const Self as usize
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn trail_follows_attachment() {
+        let node = Rc::new(RefCell::new(node::Node::default()));
+        let mut trail = TrailEmitter::new(node.clone())
+            .offset(point3(0., 0., -1.))
+            .duration(Duration::from_millis(100));
+        let start = Instant::now();
+        for i in 0..20u32 {
+            node.borrow_mut().set_pos(point3(f64::from(i), 0., 0.));
+            trail.sample(start + Duration::from_millis(u64::from(i) * 10));
+        }
+        assert_eq!(trail.samples[0].pos, point3(19., 0., -1.));
+        // only samples younger than the duration remain
+        assert_eq!(trail.samples.len(), 11);
+
+        let verts = trail.strip_vertices(start + Duration::from_millis(190));
+        assert_eq!(verts.len(), 22);
+        assert_eq!(verts[0].trail_side, -1.);
+        assert_eq!(verts[1].trail_side, 1.);
+        assert_eq!(verts[0].trail_dir, [1., 0., 0.]);
+        assert!(verts[0].trail_t.abs() < 0.0001);
+        assert!((verts[21].trail_t - 1.).abs() < 0.0001);
+    }
+
+    #[test]
+    fn trail_restarts_after_teleport() {
+        let node = Rc::new(RefCell::new(node::Node::default()));
+        let mut trail = TrailEmitter::new(node.clone());
+        let start = Instant::now();
+        trail.sample(start);
+        trail.sample(start + Duration::from_millis(20));
+        node.borrow_mut().set_pos(point3(1000., 0., 0.));
+        trail.sample(start + Duration::from_millis(40));
+        assert_eq!(trail.samples.len(), 1);
+        assert!(trail
+            .strip_vertices(start + Duration::from_millis(40))
+            .is_empty());
+    }
+}
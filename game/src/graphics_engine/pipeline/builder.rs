@@ -0,0 +1,294 @@
+use super::*;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+enum StageKind {
+    Target,
+    Process,
+}
+
+/// A stage added to a `PipelineBuilder`, which is used to connect the stage
+/// to other stages of the same builder
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct StageHandle {
+    kind: StageKind,
+    index: usize,
+}
+
+/// Builds a `RenderPass` from stages connected by their handles, which is
+/// checked to be a valid pipeline when it's built
+///
+/// Unlike the edges passed to `Pipeline::new`, handles don't depend on the
+/// order the stages are added in. Every render target with no inputs is a
+/// starting stage of the pipeline
+pub struct PipelineBuilder {
+    name: &'static str,
+    targets: Vec<(&'static str, Box<dyn RenderTarget>)>,
+    processes: Vec<(&'static str, Box<dyn TextureProcessor>)>,
+    /// `(from, to, input slot of to)`
+    edges: Vec<(StageHandle, StageHandle, usize)>,
+}
+
+impl PipelineBuilder {
+    /// Creates a builder for a pass whose stages are profiled under `name`
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            targets: Vec::new(),
+            processes: Vec::new(),
+            edges: Vec::new(),
+        }
+    }
+
+    /// Adds the render target `target` named `name`
+    pub fn add_target(
+        &mut self,
+        name: &'static str,
+        target: Box<dyn RenderTarget>,
+    ) -> StageHandle {
+        self.targets.push((name, target));
+        StageHandle {
+            kind: StageKind::Target,
+            index: self.targets.len() - 1,
+        }
+    }
+
+    /// Adds the texture processor `process` named `name`
+    pub fn add_process(
+        &mut self,
+        name: &'static str,
+        process: Box<dyn TextureProcessor>,
+    ) -> StageHandle {
+        self.processes.push((name, process));
+        StageHandle {
+            kind: StageKind::Process,
+            index: self.processes.len() - 1,
+        }
+    }
+
+    /// Sends the output of `from` to input `input_slot` of `to`
+    ///
+    /// Stages which store their output in the pipeline cache instead of
+    /// outputting a texture may be connected to an input that's already
+    /// connected, to run them before `to`
+    pub fn connect(
+        &mut self,
+        from: StageHandle,
+        to: StageHandle,
+        input_slot: usize,
+    ) -> &mut Self {
+        self.edges.push((from, to, input_slot));
+        self
+    }
+
+    /// Gets the id of `stage` in the render pass
+    fn id(&self, stage: StageHandle) -> Result<usize, PipelineError> {
+        match stage.kind {
+            StageKind::Target if stage.index < self.targets.len() => {
+                Ok(stage.index)
+            }
+            StageKind::Process if stage.index < self.processes.len() => {
+                Ok(self.targets.len() + stage.index)
+            }
+            _ => Err(PipelineError::InvalidHandle(stage)),
+        }
+    }
+
+    /// Gets the names of the stages in the order of their ids
+    fn stage_names(&self) -> Vec<&'static str> {
+        self.targets
+            .iter()
+            .map(|(name, _)| *name)
+            .chain(self.processes.iter().map(|(name, _)| *name))
+            .collect()
+    }
+
+    /// Checks that the stages form a DAG whose inputs are all connected,
+    /// and gets the pipeline of the stages
+    fn pipeline(&self) -> Result<Pipeline, PipelineError> {
+        let names = self.stage_names();
+        let mut has_inputs = vec![false; names.len()];
+        let mut edges = Vec::new();
+        for (from, to, slot) in &self.edges {
+            let (from, to) = (self.id(*from)?, self.id(*to)?);
+            has_inputs[to] = true;
+            edges.push((from as u16, (to as u16, *slot)));
+        }
+        if let Some(stage) =
+            (self.targets.len()..names.len()).find(|stage| !has_inputs[*stage])
+        {
+            return Err(PipelineError::NoInputs(names[stage].to_owned()));
+        }
+        let starts = (0..self.targets.len())
+            .filter(|stage| !has_inputs[*stage])
+            .map(|stage| stage as u16)
+            .collect();
+        Pipeline::with_names(starts, edges, &names)
+    }
+
+    /// Builds the render pass
+    ///
+    /// # Errors
+    /// Fails if a handle isn't from this builder, if the stages form a
+    /// cycle, if a texture processor has no inputs, or if a stage has an
+    /// unconnected input before a connected one
+    pub fn build(self) -> Result<RenderPass, PipelineError> {
+        let pipeline = self.pipeline()?;
+        let names = self.stage_names();
+        let targets = self.targets.into_iter().map(|(_, t)| t).collect();
+        let processes = self.processes.into_iter().map(|(_, p)| p).collect();
+        Ok(RenderPass::new(targets, processes, pipeline)
+            .with_name(self.name)
+            .with_stage_names(names))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use texture_processor::ToCacheProcessor;
+
+    /// A render target which never draws anything
+    struct NullTarget;
+
+    impl RenderTarget for NullTarget {
+        fn draw(
+            &mut self,
+            _: &dyn Viewer,
+            _: Option<Vec<&TextureType>>,
+            _: &mut PipelineCache,
+            _: &mut dyn FnMut(
+                &mut framebuffer::SimpleFrameBuffer,
+                &dyn Viewer,
+                RenderPassType,
+                &PipelineCache,
+                TargetType,
+                &Option<Vec<&TextureType>>,
+            ),
+        ) -> Option<TextureType> {
+            None
+        }
+
+        fn type_of(&self) -> TargetType {
+            TargetType::Depth
+        }
+    }
+
+    /// A texture processor which never outputs anything
+    struct NullProcess;
+
+    impl TextureProcessor for NullProcess {
+        fn process<'a>(
+            &mut self,
+            _: Option<Vec<&'a TextureType>>,
+            _: &shader::ShaderManager,
+            _: &mut PipelineCache<'a>,
+            _: Option<&shader::SceneData>,
+        ) -> Option<TextureType> {
+            None
+        }
+    }
+
+    fn process() -> Box<dyn TextureProcessor> {
+        Box::new(NullProcess)
+    }
+
+    #[test]
+    fn builds_in_topological_order() {
+        let mut builder = PipelineBuilder::new("test");
+        // processes are added before the targets they depend on
+        let compose = builder.add_process("compose", process());
+        let blur = builder.add_process("blur", process());
+        let depth = builder.add_target("depth", Box::new(NullTarget));
+        let msaa = builder.add_target("msaa", Box::new(NullTarget));
+        builder
+            .connect(depth, msaa, 0)
+            .connect(msaa, blur, 0)
+            .connect(msaa, compose, 0)
+            .connect(blur, compose, 1);
+        let pipeline = builder.pipeline().unwrap();
+        assert_eq!(pipeline.starts, vec![0]);
+        let order = pipeline.order();
+        let pos = |id| order.iter().position(|stage| *stage == id).unwrap();
+        // targets come first, then processes in the order they were added
+        assert!(pos(0) < pos(1));
+        assert!(pos(1) < pos(3));
+        assert!(pos(3) < pos(2));
+        assert_eq!(
+            builder.stage_names(),
+            vec!["depth", "msaa", "compose", "blur"]
+        );
+        assert!(builder.build().is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_pipelines() {
+        let mut builder = PipelineBuilder::new("test");
+        let msaa = builder.add_target("msaa", Box::new(NullTarget));
+        let blur = builder.add_process("blur", process());
+        let bloom = builder.add_process("bloom", process());
+        builder.connect(msaa, blur, 0).connect(blur, bloom, 0);
+        let unused = builder.add_process("unused", process());
+        assert_eq!(
+            builder.pipeline().err(),
+            Some(PipelineError::NoInputs("unused".to_owned()))
+        );
+
+        builder.connect(msaa, unused, 1);
+        assert_eq!(
+            builder.pipeline().err(),
+            Some(PipelineError::MissingInput {
+                stage: "unused".to_owned(),
+                slot: 0
+            })
+        );
+
+        builder.connect(msaa, unused, 0).connect(msaa, unused, 0);
+        assert_eq!(
+            builder.pipeline().err(),
+            Some(PipelineError::DuplicateInput {
+                stage: "unused".to_owned(),
+                slot: 0,
+                edges: 2
+            })
+        );
+
+        let mut builder = PipelineBuilder::new("test");
+        let msaa = builder.add_target("msaa", Box::new(NullTarget));
+        let blur = builder.add_process("blur", process());
+        let bloom = builder.add_process("bloom", process());
+        builder
+            .connect(msaa, blur, 0)
+            .connect(blur, bloom, 0)
+            .connect(bloom, blur, 1);
+        assert_eq!(
+            builder.pipeline().err(),
+            Some(PipelineError::Cycle(
+                ["blur", "bloom", "blur"].map(str::to_owned).to_vec()
+            ))
+        );
+
+        let mut other = PipelineBuilder::new("other");
+        other.connect(msaa, blur, 0);
+        assert_eq!(
+            other.pipeline().err(),
+            Some(PipelineError::InvalidHandle(msaa))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Cache slot `cam_depth` is written by multiple")]
+    fn rejects_duplicate_cache_writes() {
+        let mut builder = PipelineBuilder::new("test");
+        let depth = builder.add_target("depth", Box::new(NullTarget));
+        let first = builder.add_process(
+            "first",
+            Box::new(ToCacheProcessor::for_slot(shader::CAM_DEPTH)),
+        );
+        let second = builder.add_process(
+            "second",
+            Box::new(ToCacheProcessor::for_slot(shader::CAM_DEPTH)),
+        );
+        builder.connect(depth, first, 0).connect(depth, second, 0);
+        let _pass = builder.build();
+    }
+}
@@ -1,3 +1,4 @@
+mod builder;
 mod render_pass;
 pub mod render_target;
 pub mod texture_processor;
@@ -6,9 +7,9 @@ use super::shader;
 use glium::*;
 use shader::PipelineCache;
 use shader::RenderPassType;
-use std::collections::HashMap;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
 
+pub use builder::*;
 pub use render_pass::*;
 
 /// Either a `T` or `&T`
@@ -33,6 +34,8 @@ pub enum StageArgs {
     Cascade([[f32; 4]; 4], f32),
     Object(u32),
     Compositor([[f32; 3]; 3]),
+    /// Args - probe position, probe radius
+    Probe([f32; 3], f32),
 }
 
 /// The type of texture returned by a pipeline stage
@@ -100,13 +103,69 @@ pub trait TextureProcessor {
         cache: &mut PipelineCache<'a>,
         data: Option<&shader::SceneData>,
     ) -> Option<TextureType>;
+
+    /// Gets the names of the pipeline cache slots the processor writes to.
+    /// No two stages of a render pass may write to the same slot
+    fn cache_writes(&self) -> Vec<&'static str> {
+        Vec::new()
+    }
+}
+
+/// Why a pipeline is invalid
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum PipelineError {
+    /// A handle didn't refer to a stage of the builder it was used with
+    InvalidHandle(StageHandle),
+    /// The stages form a cycle, which starts and ends with the same stage
+    Cycle(Vec<String>),
+    /// A stage had an input connected after `slot`, but nothing connected
+    /// to `slot`
+    MissingInput { stage: String, slot: usize },
+    /// Input `slot` of a stage had `edges` edges connected to it
+    DuplicateInput {
+        stage: String,
+        slot: usize,
+        edges: usize,
+    },
+    /// A texture processor had nothing connected to its inputs
+    NoInputs(String),
 }
 
+impl std::fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::InvalidHandle(handle) => {
+                write!(f, "Stage {:?} is not part of the pipeline", handle)
+            }
+            Self::Cycle(stages) => {
+                write!(f, "Stages form a cycle: {}", stages.join(" -> "))
+            }
+            Self::MissingInput { stage, slot } => write!(
+                f,
+                "Input {} of stage '{}' is not connected, but a later input is",
+                slot, stage
+            ),
+            Self::DuplicateInput { stage, slot, edges } => write!(
+                f,
+                "Input {} of stage '{}' has {} edges connected to it",
+                slot, stage, edges
+            ),
+            Self::NoInputs(stage) => {
+                write!(f, "Texture processor '{}' has no inputs", stage)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PipelineError {}
+
 /// A pipeline is a connected DAG with start nodes. Pipeline stores the indices of
 /// transformations in a `RenderPass`
 pub struct Pipeline {
     pub starts: Vec<u16>,
     pub adj_list: HashMap<u16, Vec<(u16, usize)>>,
+    /// The nodes reachable from the start nodes, in topological order
+    order: Vec<u16>,
 }
 
 impl Pipeline {
@@ -119,12 +178,85 @@ impl Pipeline {
     ///
     /// `edges` - a set of edges `(u, (v, idx))` that indicates a directed edge from `u` to `v`. Where
     /// `u` and `v` are indexes of nodes. `idx` is the index of `v`s input list that the output from `u` will
-    /// be sent to. Requires that all consecutive inputs are used.
-    pub fn new(starts: Vec<u16>, edges: Vec<(u16, (u16, usize))>) -> Self {
-        Self {
+    /// be sent to. Every input of `v` up to its last connected input must have exactly one edge.
+    ///
+    /// Prefer `PipelineBuilder`, which doesn't depend on the ids of the nodes
+    ///
+    /// # Errors
+    /// Fails if the nodes reachable from `starts` form a cycle, or if an
+    /// input of a node doesn't have exactly one edge
+    pub fn new(
+        starts: Vec<u16>,
+        edges: Vec<(u16, (u16, usize))>,
+    ) -> Result<Self, PipelineError> {
+        Self::with_names(starts, edges, &[])
+    }
+
+    /// Creates a new pipeline like `new`, where errors describe the nodes by
+    /// `names`, which are in the order of the node ids. Nodes without a name
+    /// are described by their id
+    ///
+    /// # Errors
+    /// See `new`
+    pub fn with_names(
+        starts: Vec<u16>,
+        edges: Vec<(u16, (u16, usize))>,
+        names: &[&str],
+    ) -> Result<Self, PipelineError> {
+        let name = |node: u16| {
+            names
+                .get(node as usize)
+                .map_or_else(|| node.to_string(), |name| (*name).to_owned())
+        };
+        Self::check_inputs(&edges, name)?;
+        let adj_list = Self::to_adj_list(edges);
+        let order = Self::topo_order(&starts, &adj_list).map_err(|cycle| {
+            PipelineError::Cycle(cycle.into_iter().map(name).collect())
+        })?;
+        Ok(Self {
             starts,
-            adj_list: Self::to_adj_list(edges),
+            adj_list,
+            order,
+        })
+    }
+
+    /// Checks that every input of each node up to its last connected input
+    /// has exactly one edge in `edges`
+    ///
+    /// `name` - gets the name of a node to describe it by
+    fn check_inputs(
+        edges: &[(u16, (u16, usize))],
+        name: impl Fn(u16) -> String,
+    ) -> Result<(), PipelineError> {
+        let mut inputs = BTreeMap::<u16, Vec<usize>>::new();
+        for (_, (node, slot)) in edges {
+            let counts = inputs.entry(*node).or_default();
+            if counts.len() <= *slot {
+                counts.resize(*slot + 1, 0);
+            }
+            counts[*slot] += 1;
         }
+        for (node, counts) in inputs {
+            for (slot, edges) in counts.into_iter().enumerate() {
+                match edges {
+                    0 => {
+                        return Err(PipelineError::MissingInput {
+                            stage: name(node),
+                            slot,
+                        })
+                    }
+                    1 => (),
+                    edges => {
+                        return Err(PipelineError::DuplicateInput {
+                            stage: name(node),
+                            slot,
+                            edges,
+                        })
+                    }
+                }
+            }
+        }
+        Ok(())
     }
 
     /// Creates an adjacency list for the graph defined by the edge set `edges`
@@ -142,38 +274,84 @@ impl Pipeline {
         }
         adj_list
     }
-    /// Topologically sorts the graph starting from `node`
-    /// # Arguments
-    /// `node` - starting node
-    ///
-    /// `order` - the reverse topological order. Results are stored here
-    ///
-    /// `discovered` - the set of all nodes that have been discovered
-    fn topo_sort(
-        &self,
+
+    /// Gets the nodes adjacent to `node`
+    fn neighbors(
+        adj_list: &HashMap<u16, Vec<(u16, usize)>>,
         node: u16,
-        order: &mut Vec<u16>,
-        discovered: &mut HashSet<u16>,
-    ) {
-        if let Some(neighbors) = self.adj_list.get(&node) {
-            for (ns, _) in neighbors {
-                if discovered.get(ns).is_none() {
-                    discovered.insert(*ns);
-                    self.topo_sort(*ns, order, discovered);
+    ) -> impl Iterator<Item = u16> + '_ {
+        adj_list.get(&node).into_iter().flatten().map(|(v, _)| *v)
+    }
+
+    /// Sorts the nodes reachable from `starts` in topological order with
+    /// Kahn's algorithm
+    ///
+    /// Returns the nodes of a cycle, starting and ending with the same node,
+    /// if the reachable nodes aren't a DAG
+    fn topo_order(
+        starts: &[u16],
+        adj_list: &HashMap<u16, Vec<(u16, usize)>>,
+    ) -> Result<Vec<u16>, Vec<u16>> {
+        let mut reachable = BTreeSet::<u16>::new();
+        let mut stack = starts.to_vec();
+        while let Some(node) = stack.pop() {
+            if reachable.insert(node) {
+                stack.extend(Self::neighbors(adj_list, node));
+            }
+        }
+        let mut in_degree: BTreeMap<u16, usize> =
+            reachable.iter().map(|node| (*node, 0)).collect();
+        for node in &reachable {
+            for v in Self::neighbors(adj_list, *node) {
+                *in_degree.entry(v).or_default() += 1;
+            }
+        }
+        let mut ready: VecDeque<u16> = in_degree
+            .iter()
+            .filter(|(_, degree)| **degree == 0)
+            .map(|(node, _)| *node)
+            .collect();
+        let mut order = Vec::with_capacity(reachable.len());
+        while let Some(node) = ready.pop_front() {
+            order.push(node);
+            for v in Self::neighbors(adj_list, node) {
+                let degree = in_degree.entry(v).or_default();
+                *degree -= 1;
+                if *degree == 0 {
+                    ready.push_back(v);
                 }
             }
         }
-        order.push(node);
+        if order.len() == reachable.len() {
+            return Ok(order);
+        }
+        // every node left has an edge from another node left, so following
+        // those edges backwards must eventually revisit a node
+        let left: Vec<u16> = in_degree
+            .iter()
+            .filter(|(_, degree)| **degree > 0)
+            .map(|(node, _)| *node)
+            .collect();
+        let mut path = vec![left[0]];
+        loop {
+            let node = path[path.len() - 1];
+            let prev = *left
+                .iter()
+                .find(|u| Self::neighbors(adj_list, **u).any(|v| v == node))
+                .expect("Node left by Kahn's algorithm has no edge to it");
+            if let Some(start) = path.iter().position(|n| *n == prev) {
+                let mut cycle = vec![prev];
+                cycle.extend(path[start + 1..].iter().rev());
+                cycle.push(prev);
+                return Err(cycle);
+            }
+            path.push(prev);
+        }
     }
 
-    /// Gets the topological order of the pipeline
-    fn topo_order(&self) -> Vec<u16> {
-        let mut order = Vec::<u16>::new();
-        let mut discovered = HashSet::<u16>::new();
-        for start in &self.starts {
-            self.topo_sort(*start, &mut order, &mut discovered);
-        }
-        order.iter().rev().copied().collect()
+    /// Gets the nodes reachable from the start nodes in topological order
+    pub fn order(&self) -> &[u16] {
+        &self.order
     }
 }
 
@@ -194,6 +372,19 @@ macro_rules! pipeline_map_and_list {
     }};
 }
 
+/// Creates the pipeline of a `pipeline!`, starting from the first render
+/// target, and panics with a description of the problem if it's invalid
+macro_rules! pipeline_checked {
+    ($adj_list:expr, [$($target:ident),+], [$($process:ident),*]) => {
+        Pipeline::with_names(
+            vec![0],
+            $adj_list,
+            &[$(stringify!($target),)* $(stringify!($process),)*],
+        )
+        .unwrap_or_else(|e| panic!("Invalid pipeline: {}", e))
+    };
+}
+
 /// Constructs a pipeline using a DSL
 ///
 /// Expects `[<render_targets>], [<texture_processors>], <stage_a> -> <stage_b>.<b_in>`
@@ -209,13 +400,16 @@ macro_rules! pipeline {
     ([$($target:ident),+], [$($process:ident),*], $($stage_a:ident -> $stage_b:ident.$b_in:expr),*) => {{
             let (_, adj_list) = pipeline_map_and_list!([$($target),+], [$($process),*], $($stage_a -> $stage_b.$b_in),*);
             RenderPass::new(vec![$($target),*], vec![$($process),*],
-                Pipeline::new(vec![0], adj_list))
+                pipeline_checked!(adj_list, [$($target),+], [$($process),*]))
+                .with_stage_names(vec![$(stringify!($target),)* $(stringify!($process),)*])
     }};
     ([$($target:ident),+], [$($process:ident),*], $($stage_a:ident -> $stage_b:ident.$b_in:expr),*,
         {$($($conditional_stage:ident)|+ if $condition:expr),*}) => {{
         let (map, adj_list) = pipeline_map_and_list!([$($target),+], [$($process),*], $($stage_a -> $stage_b.$b_in),*);
         RenderPass::new(vec![$($target),*], vec![$($process),*],
-            Pipeline::new(vec![0], adj_list)).with_active_pred(Box::new(move |stage| {
+            pipeline_checked!(adj_list, [$($target),+], [$($process),*]))
+            .with_stage_names(vec![$(stringify!($target),)* $(stringify!($process),)*])
+            .with_active_pred(Box::new(move |stage| {
                 match stage {
                     $(
                         x if ($(x == map[stringify!($conditional_stage)] ||)* false) && $condition => true,
@@ -226,3 +420,26 @@ macro_rules! pipeline {
             }))
     }};
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sorts_long_pipelines() {
+        let edges = (0..u16::MAX - 1).map(|u| (u, (u + 1, 0))).collect();
+        let pipeline = Pipeline::new(vec![0], edges).unwrap();
+        assert!(pipeline.order().iter().copied().eq(0..u16::MAX));
+    }
+
+    #[test]
+    fn reports_cycles_by_id() {
+        let edges = vec![(0, (1, 0)), (1, (2, 0)), (2, (1, 1))];
+        assert_eq!(
+            Pipeline::new(vec![0], edges).err(),
+            Some(PipelineError::Cycle(
+                ["1", "2", "1"].map(str::to_owned).to_vec()
+            ))
+        );
+    }
+}
@@ -1,6 +1,7 @@
 use super::super::drawable::*;
 use super::shader;
 use super::*;
+use crate::profiler;
 use glium::*;
 use shader::PipelineCache;
 use std::collections::HashMap;
@@ -13,6 +14,11 @@ pub struct RenderPass {
     topo_order: Vec<u16>,
     pipeline: Pipeline,
     active_func: Option<Box<dyn Fn(u16) -> bool>>,
+    name: &'static str,
+    stage_names: Vec<&'static str>,
+    /// The `<pass>/<stage>` name of each stage, which its GPU time is
+    /// profiled under
+    labels: Vec<String>,
 }
 
 impl RenderPass {
@@ -20,18 +26,58 @@ impl RenderPass {
     ///
     /// The first `[0, targets.len())` ids refer to render targets. Then the next `[targets.len(), processes.len())` ids refer
     /// to processes. Therefore, the pipeline must contain nodes from `0` to `targets.len() + processes.len()` with the upper bound being exclusive
+    ///
+    /// Panics if two processes write to the same pipeline cache slot
     pub fn new(
         targets: Vec<Box<dyn RenderTarget>>,
         processes: Vec<Box<dyn TextureProcessor>>,
         pipeline: Pipeline,
     ) -> Self {
-        Self {
+        let mut written = std::collections::HashSet::new();
+        for slot in processes.iter().flat_map(|p| p.cache_writes()) {
+            assert!(
+                written.insert(slot),
+                "Cache slot `{}` is written by multiple stages",
+                slot
+            );
+        }
+        let mut pass = Self {
             targets,
             processes,
-            topo_order: pipeline.topo_order(),
+            topo_order: pipeline.order().to_vec(),
             pipeline,
             active_func: None,
-        }
+            name: "pass",
+            stage_names: Vec::new(),
+            labels: Vec::new(),
+        };
+        pass.update_labels();
+        pass
+    }
+
+    /// Sets the name the stages of the pass are profiled under
+    pub fn with_name(mut self, name: &'static str) -> Self {
+        self.name = name;
+        self.update_labels();
+        self
+    }
+
+    /// Names the stages of the pass, in the order of their ids. Stages
+    /// without a name are profiled under their id
+    pub fn with_stage_names(mut self, names: Vec<&'static str>) -> Self {
+        self.stage_names = names;
+        self.update_labels();
+        self
+    }
+
+    fn update_labels(&mut self) {
+        let stages = self.targets.len() + self.processes.len();
+        self.labels = (0..stages)
+            .map(|id| match self.stage_names.get(id) {
+                Some(stage) => format!("{}/{}", self.name, stage),
+                None => format!("{}/{}", self.name, id),
+            })
+            .collect();
     }
 
     /// Sets the conditional stage predicate to the render pass.
@@ -151,6 +197,7 @@ impl RenderPass {
                 let inputs =
                     Self::get_inputs(&registers, &saved_textures, *node);
                 let idx_ptr = self.targets.as_mut_ptr();
+                let _timer = profiler::gpu_scope(&self.labels[unode]);
                 unsafe {
                     let elem = idx_ptr.add(index);
                     stage_out_tex =
@@ -163,6 +210,7 @@ impl RenderPass {
                     Self::get_inputs(&registers, &saved_textures, *node);
                 let idx_ptr = self.processes.as_mut_ptr();
                 let sd = sdata.borrow();
+                let _timer = profiler::gpu_scope(&self.labels[unode]);
                 unsafe {
                     // need to use pointers because compiler can't know that we're borrowing
                     // different elements of the vector
@@ -4,22 +4,27 @@ use super::super::drawable::*;
 use super::shader;
 use super::*;
 use crate::cg_support::ssbo;
+use crate::collisions;
 use glium::Surface;
 use glium::*;
 use shader::PipelineCache;
 use shader::RenderPassType;
+use std::cell::RefCell;
 use std::pin::*;
+use std::rc::Rc;
 
 /// `RenderTarget` which renders to an MSAA color and depth buffer
 ///
 /// ### Output
 /// 2D RGBA F16 texture with multisampling already resolved
 pub struct MsaaRenderTarget {
-    fbo: framebuffer::SimpleFrameBuffer<'static>,
-    _tex: Pin<Box<texture::Texture2dMultisample>>,
-    _depth_tex: Pin<Box<texture::DepthTexture2dMultisample>>,
-    out_fbo: framebuffer::SimpleFrameBuffer<'static>,
-    out_tex: Pin<Box<texture::Texture2d>>,
+    tex: texture::Texture2dMultisample,
+    depth_tex: texture::DepthTexture2dMultisample,
+    out_tex: texture::Texture2d,
+    /// Context the framebuffers are created with. Glium caches the
+    /// framebuffer objects for a set of attachments, so creating them every
+    /// draw is cheap
+    ctx: std::rc::Rc<backend::Context>,
     width: u32,
     height: u32,
 }
@@ -31,14 +36,8 @@ impl MsaaRenderTarget {
         height: u32,
         facade: &F,
     ) -> Self {
-        let depth_tex = Box::pin(
-            glium::texture::DepthTexture2dMultisample::empty(
-                facade, width, height, samples,
-            )
-            .unwrap(),
-        );
-        let tex = Box::pin(
-            glium::texture::Texture2dMultisample::empty_with_format(
+        Self {
+            tex: glium::texture::Texture2dMultisample::empty_with_format(
                 facade,
                 glium::texture::UncompressedFloatFormat::F16F16F16F16,
                 glium::texture::MipmapsOption::NoMipmap,
@@ -47,9 +46,11 @@ impl MsaaRenderTarget {
                 samples,
             )
             .unwrap(),
-        );
-        let out_tex = Box::pin(
-            glium::texture::Texture2d::empty_with_format(
+            depth_tex: glium::texture::DepthTexture2dMultisample::empty(
+                facade, width, height, samples,
+            )
+            .unwrap(),
+            out_tex: glium::texture::Texture2d::empty_with_format(
                 facade,
                 glium::texture::UncompressedFloatFormat::F16F16F16F16,
                 glium::texture::MipmapsOption::NoMipmap,
@@ -57,26 +58,9 @@ impl MsaaRenderTarget {
                 height,
             )
             .unwrap(),
-        );
-        let ms_tex = std::ptr::addr_of!(*tex);
-        let rbo_ptr = std::ptr::addr_of!(*depth_tex);
-        let out_ptr = std::ptr::addr_of!(*out_tex);
-        unsafe {
-            Self {
-                fbo: glium::framebuffer::SimpleFrameBuffer::with_depth_buffer(
-                    facade, &*ms_tex, &*rbo_ptr,
-                )
-                .unwrap(),
-                out_fbo: glium::framebuffer::SimpleFrameBuffer::new(
-                    facade, &*out_ptr,
-                )
-                .unwrap(),
-                _tex: tex,
-                _depth_tex: depth_tex,
-                out_tex,
-                width,
-                height,
-            }
+            ctx: facade.get_context().clone(),
+            width,
+            height,
         }
     }
 }
@@ -96,22 +80,31 @@ impl RenderTarget for MsaaRenderTarget {
             &Option<Vec<&TextureType>>,
         ),
     ) -> Option<TextureType> {
+        let mut fbo = framebuffer::SimpleFrameBuffer::with_depth_buffer(
+            &self.ctx,
+            &self.tex,
+            &self.depth_tex,
+        )
+        .unwrap();
         func(
-            &mut self.fbo,
+            &mut fbo,
             viewer,
             RenderPassType::Visual,
             cache,
             TargetType::Msaa,
             &pipeline_inputs,
         );
+        let out_fbo =
+            framebuffer::SimpleFrameBuffer::new(&self.ctx, &self.out_tex)
+                .unwrap();
         let dst_target = glium::BlitTarget {
             left: 0,
             bottom: 0,
             width: self.width as i32,
             height: self.height as i32,
         };
-        self.fbo.blit_whole_color_to(
-            &self.out_fbo,
+        fbo.blit_whole_color_to(
+            &out_fbo,
             &dst_target,
             glium::uniforms::MagnifySamplerFilter::Linear,
         );
@@ -353,8 +346,17 @@ impl CubemapRenderBase {
     ///
     /// `func` - callable to render a single face of a cubemap. Passed a cube face and camera
     fn bind_views(&self) -> camera::StaticCamera {
+        self.bind_faces([true; 6])
+    }
+
+    /// Binds the views of the faces of the cubemap in `faces`. Every
+    /// primitive is clipped from the faces which aren't, so they keep their
+    /// contents
+    fn bind_faces(&self, faces: [bool; 6]) -> camera::StaticCamera {
         use super::super::camera::*;
         use cgmath::*;
+        // maps every vertex behind the clip volume
+        let clip_all = Matrix4::from_diagonal(vec4(0., 0., 0., -1f32));
         let mut cam = PerspectiveCamera {
             cam: (self.get_view_pos)(),
             aspect: 1f32,
@@ -365,51 +367,87 @@ impl CubemapRenderBase {
             up: cgmath::vec3(0., 1., 0.),
         };
         let target_faces = Self::get_target_up();
-        for ((target, up), mat_dst) in target_faces
+        for (((target, up), mat_dst), draw) in target_faces
             .iter()
             .zip(self.view_matrices.map_write().as_slice().iter_mut())
+            .zip(faces)
         {
             let target: (f32, f32, f32) =
                 (target.to_vec() + cam.cam.to_vec()).into();
             cam.target = std::convert::From::from(target);
             cam.up = *up;
-            *mat_dst = (cam.proj_mat() * cam.view_mat()).into();
+            *mat_dst = if draw {
+                (cam.proj_mat() * cam.view_mat()).into()
+            } else {
+                clip_all.into()
+            };
         }
         self.view_matrices.bind(5);
         //println!("{:?}", cam.proj_mat() * cam.view_mat());
         StaticCamera::from(&cam)
     }
+
+    /// Determines if any part of the sphere at `center` with `radius` is
+    /// in view of each face of the cubemap when it's rendered from
+    /// `view_pos`
+    fn faces_seeing(
+        &self,
+        view_pos: cgmath::Point3<f32>,
+        center: cgmath::Point3<f32>,
+        radius: f32,
+    ) -> [bool; 6] {
+        use cgmath::*;
+        let offset = center - view_pos;
+        let mut faces = [false; 6];
+        if offset.magnitude() - radius > self.view_dist {
+            return faces;
+        }
+        // the frustum of a face is bounded by planes at 45 degrees between
+        // the face's direction and each of the other axes
+        let slack = radius * std::f32::consts::SQRT_2;
+        for ((target, up), seen) in
+            Self::get_target_up().iter().zip(faces.iter_mut())
+        {
+            let forward = offset.dot(target.to_vec()) + slack;
+            let right = target.to_vec().cross(*up);
+            *seen = forward >= offset.dot(*up).abs()
+                && forward >= offset.dot(right).abs();
+        }
+        faces
+    }
 }
 
-/// `RenderTarget` which renders to a cubemap with perspective. Can assume that `draw()` ignores its viewer argument
-/// and that its called once per face
-///
-/// ### Output
-/// F16 RGB cubemap
-pub struct CubemapRenderTarget {
-    cubemap: CubemapRenderBase,
-    cbo_tex: Pin<Box<texture::Cubemap>>,
-    _depth_buffer: Pin<Box<texture::DepthCubemap>>,
-    _size: u32,
-    pass_type: RenderPassType,
-    get_trans_id: Option<Box<dyn Fn() -> u32>>,
+/// How often a `CubemapRenderTarget` renders the faces of its cubemap
+pub enum CubemapRefresh {
+    /// Every face is rendered every `n` frames
+    Interval(u32),
+    /// A face is rendered when a body it can see changes, when the cubemap
+    /// moves further than `max_move` from where every face was last
+    /// rendered, or once the face is `max_age` frames old
+    ///
+    /// Only changes to the bodies of the collision tree `changes` is
+    /// subscribed to are seen, so `max_age` bounds how long other changes,
+    /// such as particles and lights, take to show up
+    OnChange {
+        changes: Rc<RefCell<collisions::ObstacleSubscription>>,
+        max_move: f32,
+        max_age: u32,
+    },
+}
+
+/// The cubemap a `CubemapRenderTarget` renders to, and the framebuffer that
+/// renders to every face of it at once
+struct CubemapBuffers {
+    // the framebuffer refers to the textures, so it's dropped first
     fbo: framebuffer::SimpleFrameBuffer<'static>,
+    color: Pin<Box<texture::Cubemap>>,
+    depth: Pin<Box<texture::DepthCubemap>>,
+    size: u32,
 }
 
-impl CubemapRenderTarget {
-    /// Creates a new `CubemapRenderTarget`. The cubemap is a F16 RGB texture with no mipmapping
-    /// `view_dist` - the view distance for the viewer when rendering to a cubemap
-    ///
-    /// `size` - the square side length of each texture face in the cubemap
-    ///
-    /// `view_pos` - the position in the scene the cubemap is rendered from
-    pub fn new<F: glium::backend::Facade>(
-        size: u32,
-        view_dist: f32,
-        get_view_pos: Box<dyn Fn() -> cgmath::Point3<f32>>,
-        facade: &F,
-    ) -> Self {
-        let depth_buffer = Box::pin(
+impl CubemapBuffers {
+    fn new<F: glium::backend::Facade>(size: u32, facade: &F) -> Self {
+        let depth = Box::pin(
             texture::DepthCubemap::empty_with_format(
                 facade,
                 texture::DepthFormat::I24,
@@ -418,7 +456,7 @@ impl CubemapRenderTarget {
             )
             .unwrap(),
         );
-        let cbo_tex = Box::pin(
+        let color = Box::pin(
             texture::Cubemap::empty_with_format(
                 facade,
                 texture::UncompressedFloatFormat::F16F16F16,
@@ -427,8 +465,8 @@ impl CubemapRenderTarget {
             )
             .unwrap(),
         );
-        let color_ptr = std::ptr::addr_of!(*cbo_tex);
-        let depth_ptr = std::ptr::addr_of!(*depth_buffer);
+        let color_ptr = std::ptr::addr_of!(*color);
+        let depth_ptr = std::ptr::addr_of!(*depth);
         let fbo = unsafe {
             framebuffer::SimpleFrameBuffer::with_depth_buffer(
                 facade,
@@ -438,16 +476,147 @@ impl CubemapRenderTarget {
             .unwrap()
         };
         Self {
-            _size: size,
+            fbo,
+            color,
+            depth,
+            size,
+        }
+    }
+
+    /// Clears the faces in `faces`
+    fn clear(&mut self, faces: [bool; 6]) {
+        use texture::CubeLayer::*;
+        if faces.iter().all(|face| *face) {
+            self.fbo.clear_color_and_depth((0., 0., 0., 1.), 1.);
+            return;
+        }
+        let ctx = super::super::get_active_ctx();
+        let layers = [
+            PositiveX, NegativeX, PositiveY, NegativeY, PositiveZ, NegativeZ,
+        ];
+        for (layer, _) in layers.iter().zip(faces).filter(|(_, face)| *face) {
+            framebuffer::SimpleFrameBuffer::with_depth_buffer(
+                &*ctx.ctx.borrow(),
+                self.color.main_level().image(*layer),
+                self.depth.main_level().image(*layer),
+            )
+            .unwrap()
+            .clear_color_and_depth((0., 0., 0., 1.), 1.);
+        }
+    }
+}
+
+/// `RenderTarget` which renders to a cubemap with perspective. Can assume that `draw()` ignores its viewer argument
+/// and that its called once per face
+///
+/// ### Output
+/// F16 RGB cubemap
+pub struct CubemapRenderTarget {
+    cubemap: CubemapRenderBase,
+    buffers: CubemapBuffers,
+    pass_type: RenderPassType,
+    get_trans_id: Option<Box<dyn Fn() -> u32>>,
+    refresh: CubemapRefresh,
+    /// Frames since each face was rendered, or `None` if it never was
+    face_ages: [Option<u32>; 6],
+    /// Where every face was last rendered from
+    rendered_from: Option<cgmath::Point3<f32>>,
+}
+
+impl CubemapRenderTarget {
+    /// Creates a new `CubemapRenderTarget`. The cubemap is a F16 RGB texture with no mipmapping
+    /// `view_dist` - the view distance for the viewer when rendering to a cubemap
+    ///
+    /// `size` - the square side length of each texture face in the cubemap
+    ///
+    /// `view_pos` - the position in the scene the cubemap is rendered from
+    pub fn new<F: glium::backend::Facade>(
+        size: u32,
+        view_dist: f32,
+        get_view_pos: Box<dyn Fn() -> cgmath::Point3<f32>>,
+        facade: &F,
+    ) -> Self {
+        Self {
             cubemap: CubemapRenderBase::new(view_dist, get_view_pos),
-            cbo_tex,
-            _depth_buffer: depth_buffer,
+            buffers: CubemapBuffers::new(size, facade),
             pass_type: RenderPassType::LayeredVisual,
             get_trans_id: None,
-            fbo,
+            refresh: CubemapRefresh::Interval(1),
+            face_ages: [None; 6],
+            rendered_from: None,
         }
     }
 
+    /// Sets how often the faces of the cubemap are rendered. Faces reuse
+    /// their last render until they're rendered again
+    pub fn with_refresh(mut self, refresh: CubemapRefresh) -> Self {
+        self.refresh = refresh;
+        self
+    }
+
+    /// Scales the size of each face of the cubemap by `scale`
+    pub fn with_resolution_scale<F: glium::backend::Facade>(
+        mut self,
+        scale: f32,
+        facade: &F,
+    ) -> Self {
+        let size = ((self.buffers.size as f32 * scale).round() as u32).max(1);
+        if size != self.buffers.size {
+            self.buffers = CubemapBuffers::new(size, facade);
+        }
+        self
+    }
+
+    /// Determines which faces should be rendered this frame
+    fn due_faces(&mut self) -> [bool; 6] {
+        use cgmath::MetricSpace;
+        let view_pos = (self.cubemap.get_view_pos)();
+        let mut due = [false; 6];
+        match &self.refresh {
+            CubemapRefresh::Interval(n) => {
+                let age = self.face_ages[0];
+                due = [age.map_or(true, |age| age + 1 >= *n); 6];
+            }
+            CubemapRefresh::OnChange {
+                changes,
+                max_move,
+                max_age,
+            } => {
+                let mut changes = changes.borrow_mut();
+                // bodies that changed while nothing was rendered are lost
+                let moved = changes.take_overflowed()
+                    || self
+                        .rendered_from
+                        .map_or(true, |pos| pos.distance(view_pos) > *max_move);
+                for event in changes.drain() {
+                    for center in
+                        std::iter::once(event.center).chain(event.previous)
+                    {
+                        let seen = self.cubemap.faces_seeing(
+                            view_pos,
+                            center.cast().unwrap(),
+                            event.radius as f32,
+                        );
+                        for (due, seen) in due.iter_mut().zip(seen) {
+                            *due |= seen;
+                        }
+                    }
+                }
+                for (due, age) in due.iter_mut().zip(self.face_ages) {
+                    *due |=
+                        moved || age.map_or(true, |age| age + 1 >= *max_age);
+                }
+            }
+        }
+        for (age, due) in self.face_ages.iter_mut().zip(due) {
+            *age = if due { Some(0) } else { age.map(|age| age + 1) };
+        }
+        if due.iter().all(|face| *face) {
+            self.rendered_from = Some(view_pos);
+        }
+        due
+    }
+
     /// Sets the render pass type of this Render Target
     pub const fn with_pass(mut self, pass: RenderPassType) -> Self {
         self.pass_type = pass;
@@ -479,17 +648,20 @@ impl RenderTarget for CubemapRenderTarget {
             &Option<Vec<&TextureType>>,
         ),
     ) -> Option<TextureType> {
-        let cam_base = self.cubemap.bind_views();
-        self.fbo.clear_color_and_depth((0., 0., 0., 1.), 1.);
-        func(
-            &mut self.fbo,
-            &cam_base,
-            self.pass_type,
-            cache,
-            TargetType::Cubemap,
-            &pipeline_inputs,
-        );
-        let tex = TextureType::TexCube(Ref(&self.cbo_tex));
+        let faces = self.due_faces();
+        if faces.iter().any(|face| *face) {
+            let cam_base = self.cubemap.bind_faces(faces);
+            self.buffers.clear(faces);
+            func(
+                &mut self.buffers.fbo,
+                &cam_base,
+                self.pass_type,
+                cache,
+                TargetType::Cubemap,
+                &pipeline_inputs,
+            );
+        }
+        let tex = TextureType::TexCube(Ref(&self.buffers.color));
         if let Some(get_id) = &self.get_trans_id {
             Some(TextureType::WithArg(
                 Box::new(tex),
@@ -601,6 +773,180 @@ impl RenderTarget for MipCubemapRenderTarget {
     }
 }
 
+/// How often a reflection probe is captured
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[allow(dead_code)]
+pub enum ProbeUpdate {
+    /// Captured once on the first frame and reused afterwards
+    Baked,
+    /// Recaptured every `n` frames
+    Interval(u32),
+}
+
+/// A reflection probe capturing the scene around a fixed position
+struct ReflectionProbe {
+    pos: cgmath::Point3<f32>,
+    radius: f32,
+    update: ProbeUpdate,
+    frames_since_capture: Option<u32>,
+    cubemap: CubemapRenderBase,
+    env_map: texture::Cubemap,
+    depth_map: texture::DepthCubemap,
+}
+
+impl ReflectionProbe {
+    /// Determines if the probe should be captured this frame
+    fn due_for_capture(&mut self) -> bool {
+        let due = match (self.update, self.frames_since_capture) {
+            (_, None) => true,
+            (ProbeUpdate::Interval(n), Some(frames)) => frames + 1 >= n,
+            (ProbeUpdate::Baked, Some(_)) => false,
+        };
+        self.frames_since_capture = if due {
+            Some(0)
+        } else {
+            self.frames_since_capture.map(|frames| frames + 1)
+        };
+        due
+    }
+}
+
+/// `RenderTarget` which captures reflection probes at fixed positions in the scene.
+/// Like `MipCubemapRenderTarget`, `draw()` ignores its viewer argument and is called once
+/// per face, per mipmap level of each probe that is due to be captured
+///
+/// ### Output
+/// `Multi` of the RGB F16 mipmapped cubemap of every probe, each `WithArg` the position
+/// and radius of its probe. `None` if there are no probes
+pub struct ProbeRenderTarget {
+    probes: Vec<ReflectionProbe>,
+    size: u32,
+    mip_levels: u32,
+    view_dist: f32,
+}
+
+impl ProbeRenderTarget {
+    /// Creates a new `ProbeRenderTarget` with no probes
+    ///
+    /// `size` - the square side length of each face of a probe's cubemap at mipmap level 0
+    ///
+    /// `mip_levels` - the amount of mipmaps of each probe's cubemap
+    ///
+    /// `view_dist` - the view distance when capturing a probe
+    pub const fn new(size: u32, mip_levels: u32, view_dist: f32) -> Self {
+        Self {
+            probes: Vec::new(),
+            size,
+            mip_levels,
+            view_dist,
+        }
+    }
+
+    /// Adds a probe to be captured by this target
+    ///
+    /// `pos` - the position the probe is captured from
+    ///
+    /// `radius` - the distance from `pos` at which the probe no longer affects objects
+    ///
+    /// `update` - how often the probe is captured
+    pub fn with_probe<F: glium::backend::Facade>(
+        mut self,
+        pos: cgmath::Point3<f32>,
+        radius: f32,
+        update: ProbeUpdate,
+        facade: &F,
+    ) -> Self {
+        let mipmaps = texture::MipmapsOption::AutoGeneratedMipmapsMax(
+            self.mip_levels - 1,
+        );
+        self.probes.push(ReflectionProbe {
+            pos,
+            radius,
+            update,
+            frames_since_capture: None,
+            cubemap: CubemapRenderBase::new(
+                self.view_dist,
+                Box::new(move || pos),
+            ),
+            env_map: texture::Cubemap::empty_with_format(
+                facade,
+                texture::UncompressedFloatFormat::F16F16F16,
+                mipmaps,
+                self.size,
+            )
+            .unwrap(),
+            depth_map: texture::DepthCubemap::empty_with_format(
+                facade,
+                texture::DepthFormat::I24,
+                mipmaps,
+                self.size,
+            )
+            .unwrap(),
+        });
+        self
+    }
+}
+
+impl RenderTarget for ProbeRenderTarget {
+    fn draw(
+        &mut self,
+        _: &dyn Viewer,
+        pipeline_inputs: Option<Vec<&TextureType>>,
+        cache: &mut PipelineCache,
+        func: &mut dyn FnMut(
+            &mut framebuffer::SimpleFrameBuffer,
+            &dyn Viewer,
+            RenderPassType,
+            &PipelineCache,
+            TargetType,
+            &Option<Vec<&TextureType>>,
+        ),
+    ) -> Option<TextureType> {
+        if self.probes.is_empty() {
+            return None;
+        }
+        let ctx = super::super::get_active_ctx();
+        for probe in &mut self.probes {
+            if !probe.due_for_capture() {
+                continue;
+            }
+            let cam_base = probe.cubemap.bind_views();
+            for mip_level in 0..self.mip_levels {
+                let mut fbo =
+                    framebuffer::SimpleFrameBuffer::with_depth_buffer(
+                        &*ctx.ctx.borrow(),
+                        probe.env_map.mipmap(mip_level).unwrap(),
+                        probe.depth_map.mipmap(mip_level).unwrap(),
+                    )
+                    .unwrap();
+                func(
+                    &mut fbo,
+                    &cam_base,
+                    RenderPassType::LayeredVisual,
+                    cache,
+                    TargetType::Mipcube,
+                    &pipeline_inputs,
+                );
+            }
+        }
+        Some(TextureType::Multi(
+            self.probes
+                .iter()
+                .map(|probe| {
+                    TextureType::WithArg(
+                        Box::new(TextureType::TexCube(Ref(&probe.env_map))),
+                        StageArgs::Probe(probe.pos.into(), probe.radius),
+                    )
+                })
+                .collect(),
+        ))
+    }
+
+    fn type_of(&self) -> TargetType {
+        TargetType::Mipcube
+    }
+}
+
 /// A `RenderTarget` decorator wich supplies the target with an arbitrary view
 /// on draw
 pub struct CustomViewRenderTargetDecorator<
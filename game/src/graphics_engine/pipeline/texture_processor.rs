@@ -47,8 +47,9 @@ fn get_rect_vbo_ebo<F: glium::backend::Facade>(
 /// ### Outputs
 /// 2D RGBA F16 texture
 pub struct ExtractBrightProcessor {
-    bright_color_tex: Pin<Box<glium::texture::Texture2d>>,
-    bright_color_fbo: framebuffer::SimpleFrameBuffer<'static>,
+    bright_color_tex: glium::texture::Texture2d,
+    /// Context the framebuffer is created with each time the processor runs
+    ctx: std::rc::Rc<backend::Context>,
     vbo: VertexBuffer<Vertex>,
     ebo: IndexBuffer<u16>,
 }
@@ -59,8 +60,9 @@ impl ExtractBrightProcessor {
         width: u32,
         height: u32,
     ) -> Self {
-        let bright_color_tex = Box::pin(
-            glium::texture::Texture2d::empty_with_format(
+        let (vbo, ebo) = get_rect_vbo_ebo(facade);
+        Self {
+            bright_color_tex: glium::texture::Texture2d::empty_with_format(
                 facade,
                 glium::texture::UncompressedFloatFormat::F16F16F16F16,
                 glium::texture::MipmapsOption::NoMipmap,
@@ -68,19 +70,9 @@ impl ExtractBrightProcessor {
                 height,
             )
             .unwrap(),
-        );
-        let (vbo, ebo) = get_rect_vbo_ebo(facade);
-        unsafe {
-            let tex_ptr = std::ptr::addr_of!(*bright_color_tex);
-            Self {
-                bright_color_tex,
-                bright_color_fbo: glium::framebuffer::SimpleFrameBuffer::new(
-                    facade, &*tex_ptr,
-                )
-                .unwrap(),
-                ebo,
-                vbo,
-            }
+            ctx: facade.get_context().clone(),
+            vbo,
+            ebo,
         }
     }
 }
@@ -103,7 +95,11 @@ impl TextureProcessor for ExtractBrightProcessor {
                 shader.use_shader(&data, sd, Some(pc));
             match uniform {
                 shader::UniformType::ExtractBright(uniform) => {
-                    let fbo = &mut self.bright_color_fbo;
+                    let mut fbo = glium::framebuffer::SimpleFrameBuffer::new(
+                        &self.ctx,
+                        &self.bright_color_tex,
+                    )
+                    .unwrap();
                     fbo.clear_color(0., 0., 0., 1.);
                     fbo.draw(&self.vbo, &self.ebo, program, &uniform, &params)
                         .unwrap();
@@ -124,8 +120,9 @@ impl TextureProcessor for ExtractBrightProcessor {
 /// ### Outputs
 /// 2D RGBA F16 Texture
 pub struct SepConvProcessor {
-    ping_pong_tex: [Pin<Box<texture::Texture2d>>; 2],
-    ping_pong_fbo: [framebuffer::SimpleFrameBuffer<'static>; 2],
+    ping_pong_tex: [texture::Texture2d; 2],
+    /// Context the framebuffers are created with each time the processor runs
+    ctx: std::rc::Rc<backend::Context>,
     iterations: usize,
     ebo: IndexBuffer<u16>,
     vbo: VertexBuffer<Vertex>,
@@ -140,58 +137,30 @@ impl SepConvProcessor {
         iterations: usize,
         facade: &F,
     ) -> Self {
-        use std::mem::MaybeUninit;
-        const UNINIT_TEX: MaybeUninit<Pin<Box<texture::Texture2d>>> =
-            MaybeUninit::uninit();
-        const UNINIT_FBO: MaybeUninit<framebuffer::SimpleFrameBuffer<'static>> =
-            MaybeUninit::uninit();
-        let mut ping_pong_tex = [UNINIT_TEX; 2];
-        let mut ping_pong_fbo = [UNINIT_FBO; 2];
         let (vbo, ebo) = get_rect_vbo_ebo(facade);
-        for i in 0..2 {
-            let tex_box = Box::pin(
-                glium::texture::Texture2d::empty_with_format(
-                    facade,
-                    glium::texture::UncompressedFloatFormat::F16F16F16F16,
-                    glium::texture::MipmapsOption::NoMipmap,
-                    width,
-                    height,
-                )
-                .unwrap(),
-            );
-            let tex_ptr = std::ptr::addr_of!(*tex_box);
-            ping_pong_tex[i].write(tex_box);
-            unsafe {
-                ping_pong_fbo[i].write(
-                    glium::framebuffer::SimpleFrameBuffer::new(
-                        facade, &*tex_ptr,
-                    )
-                    .unwrap(),
-                );
-            }
-        }
-        unsafe {
-            Self {
-                iterations,
-                ping_pong_fbo: std::mem::transmute::<
-                    _,
-                    [framebuffer::SimpleFrameBuffer<'static>; 2],
-                >(ping_pong_fbo),
-                ping_pong_tex: std::mem::transmute::<
-                    _,
-                    [Pin<Box<texture::Texture2d>>; 2],
-                >(ping_pong_tex),
-                vbo,
-                ebo,
-            }
+        let make_tex = || {
+            glium::texture::Texture2d::empty_with_format(
+                facade,
+                glium::texture::UncompressedFloatFormat::F16F16F16F16,
+                glium::texture::MipmapsOption::NoMipmap,
+                width,
+                height,
+            )
+            .unwrap()
+        };
+        Self {
+            ping_pong_tex: [make_tex(), make_tex()],
+            ctx: facade.get_context().clone(),
+            iterations,
+            vbo,
+            ebo,
         }
     }
 
     fn pass(
-        dst: &mut framebuffer::SimpleFrameBuffer,
+        &self,
+        dst: &texture::Texture2d,
         source: &texture::Texture2d,
-        vbo: &VertexBuffer<Vertex>,
-        ebo: &IndexBuffer<u16>,
         iteration: usize,
         shaders: &shader::ShaderManager,
     ) {
@@ -202,7 +171,11 @@ impl SepConvProcessor {
         let (program, params, uniform) = shaders.use_shader(&data, None, None);
         match uniform {
             shader::UniformType::SepConv(uniform) => {
-                dst.draw(vbo, ebo, program, &uniform, &params).unwrap();
+                let mut fbo =
+                    glium::framebuffer::SimpleFrameBuffer::new(&self.ctx, dst)
+                        .unwrap();
+                fbo.draw(&self.vbo, &self.ebo, program, &uniform, &params)
+                    .unwrap();
             }
             _ => panic!("Invalid uniform type returned for RenderTarget"),
         }
@@ -219,21 +192,17 @@ impl TextureProcessor for SepConvProcessor {
     ) -> Option<TextureType> {
         if let TextureType::Tex2d(source) = source.unwrap()[0] {
             let source = source.to_ref();
-            Self::pass(
-                &mut self.ping_pong_fbo[0],
-                source,
-                &self.vbo,
-                &self.ebo,
-                0,
-                shader,
-            );
+            self.pass(&self.ping_pong_tex[0], source, 0, shader);
             for i in 1..self.iterations {
-                let tex = &*self.ping_pong_tex[(i - 1) % 2];
-                let dst = &mut self.ping_pong_fbo[i % 2];
-                Self::pass(dst, tex, &self.vbo, &self.ebo, i, shader);
+                self.pass(
+                    &self.ping_pong_tex[i % 2],
+                    &self.ping_pong_tex[(i - 1) % 2],
+                    i,
+                    shader,
+                );
             }
             Some(TextureType::Tex2d(Ref(
-                &*self.ping_pong_tex[(self.iterations - 1) % 2]
+                &self.ping_pong_tex[(self.iterations - 1) % 2]
             )))
         } else {
             panic!("Invalid source type for separable convolution");
@@ -709,84 +678,83 @@ impl TextureProcessor for CullLightProcessor {
         }
     }
 }
-/// Texture processor that stores its inputs in `PipelineCache` to be used as
-/// shader uniform inputs for subsequent stages
-pub struct ToCacheProcessor {}
+/// Texture processor for assigning lights to clusters of the view frustum,
+/// which handles many more lights than `CullLightProcessor` since the
+/// lights of a tile are split among the depth slices of the tile
+/// Results are stored in a shared shader storage buffer
+///
+/// ### Inputs
+/// Ignored, so the processor can replace a `CullLightProcessor`
+/// ### Outputs
+/// None (results stored in SSBO owned by this processor)
+/// ### Mutators
+/// Saves the clusters to `PipelineCache`'s `light_clusters` param
+pub struct ClusterLightProcessor {
+    clusters: shader::LightClusters,
+    visible_light_buffer: ssbo::Ssbo<i32>,
+}
+
+impl ClusterLightProcessor {
+    /// Must match `MAX_LIGHTS_PER_CLUSTER` in the shaders
+    const MAX_LIGHTS: u32 = 256;
+    /// Must match `GROUP_SIZE` in the cluster compute shader
+    const GROUP_SIZE: u32 = 4;
+
+    /// `tile_size` - width and height of the screen tile of a cluster
+    ///
+    /// `slices` - number of clusters along the depth of the view frustum
+    pub fn new(width: u32, height: u32, tile_size: u32, slices: u32) -> Self {
+        let clusters =
+            shader::LightClusters::new(width, height, tile_size, slices);
+        Self {
+            clusters,
+            visible_light_buffer: ssbo::Ssbo::<i32>::static_empty(
+                clusters.total() * Self::MAX_LIGHTS,
+            ),
+        }
+    }
+}
 
-impl ToCacheProcessor {
-    pub const fn new() -> Self {
-        Self {}
+impl TextureProcessor for ClusterLightProcessor {
+    fn process(
+        &mut self,
+        _: Option<Vec<&TextureType>>,
+        shader: &shader::ShaderManager,
+        cache: &mut PipelineCache,
+        data: Option<&shader::SceneData>,
+    ) -> Option<TextureType> {
+        let clusters = self.clusters.with_proj(&data.unwrap().viewer.proj);
+        let [x, y, z] = clusters
+            .count
+            .map(|count| (count + Self::GROUP_SIZE - 1) / Self::GROUP_SIZE);
+        self.visible_light_buffer.bind(1);
+        cache.tiles_x = Some(clusters.count[0]);
+        cache.light_clusters = Some(clusters);
+        shader.execute_compute(
+            x,
+            y,
+            z,
+            &shader::UniformInfo::LightCluster(clusters),
+            data,
+        );
+        None
     }
+}
 
-    #[allow(clippy::too_many_lines)]
-    fn cascade_maps_to_cache<'b>(
-        input: Vec<&'b TextureType>,
-        cache: &mut PipelineCache<'b>,
-    ) {
-        use std::mem::MaybeUninit;
-        let mut depth_texs = Vec::<&'b glium::texture::DepthTexture2d>::new();
-        let mut trans_depths = Vec::new();
-        let mut mats: [MaybeUninit<[[f32; 4]; 4]>; 5] =
-            unsafe { MaybeUninit::uninit().assume_init() };
-        let mut fars: [MaybeUninit<f32>; 4] =
-            unsafe { MaybeUninit::uninit().assume_init() };
-        for (tex, i) in input.into_iter().zip(0..3) {
-            match tex {
-                TextureType::WithArg(b, StageArgs::Cascade(mat, far)) => {
-                    mats[i].write(*mat);
-                    fars[i].write(*far);
-                    match &**b {
-                        TextureType::Depth2d(tex) => {
-                            //depth_texs[i].write(glium::texture::TextureHandle::new(tex, &sb));
-                            depth_texs.push(tex.to_ref());
-                        }
-                        TextureType::Multi(texes) if texes.len() == 3 => {
-                            match (&texes[0], &texes[1], &texes[2]) {
-                                (
-                                    TextureType::Depth2d(opaque_depth),
-                                    TextureType::Depth2d(trans_depth),
-                                    TextureType::Tex2d(trans_fac),
-                                ) => {
-                                    depth_texs.push(opaque_depth.to_ref());
-                                    trans_depths.push((
-                                        trans_depth.to_ref(),
-                                        trans_fac.to_ref(),
-                                    ));
-                                }
-                                _ => panic!("Unexpected input"),
-                            }
-                        }
-                        _ => panic!("Unimplemented"),
-                    }
-                }
-                _ => panic!("Unimplemented"),
-            }
-        }
-        fars[3].write(1f32);
-        mats[3].write(cgmath::Matrix4::<f32>::from_scale(1f32).into());
-        mats[4].write(cgmath::Matrix4::<f32>::from_scale(1f32).into());
-        cache.cascade_maps = Some(depth_texs);
-        if !trans_depths.is_empty() {
-            cache.trans_cascade_maps = Some(trans_depths);
-        }
-        unsafe {
-            let ctx = super::super::get_active_ctx();
-            cache.cascade_ubo = glium::uniforms::UniformBuffer::persistent(
-                &*ctx.ctx.borrow(),
-                shader::CascadeUniform {
-                    //depth_maps: std::mem::transmute::<_, [glium::texture::TextureHandle<'b>; 5]>(depth_texs),
-                    far_planes: std::mem::transmute::<_, [f32; 4]>(fars),
-                    viewproj_mats: std::mem::transmute::<_, [[[f32; 4]; 4]; 5]>(
-                        mats,
-                    ),
-                },
-            )
-            .ok();
-        }
+/// Texture processor that stores its inputs in a slot of the `PipelineCache`
+/// to be used as shader uniform inputs for subsequent stages
+pub struct ToCacheProcessor<T: shader::CacheValue> {
+    slot: shader::CacheSlot<T>,
+}
+
+impl<T: shader::CacheValue> ToCacheProcessor<T> {
+    /// Creates a processor which writes its inputs to `slot`
+    pub const fn for_slot(slot: shader::CacheSlot<T>) -> Self {
+        Self { slot }
     }
 }
 
-impl TextureProcessor for ToCacheProcessor {
+impl<T: shader::CacheValue> TextureProcessor for ToCacheProcessor<T> {
     fn process<'b>(
         &mut self,
         input: Option<Vec<&'b TextureType>>,
@@ -795,43 +763,16 @@ impl TextureProcessor for ToCacheProcessor {
         _: Option<&shader::SceneData>,
     ) -> Option<TextureType> {
         if let Some(input) = input {
-            if input.len() == 1 {
-                match input.get(0) {
-                    Some(TextureType::WithArg(b, StageArgs::Object(i))) => {
-                        if let TextureType::TexCube(cbo) = &**b {
-                            cache.obj_cubemaps.insert(*i, cbo.to_ref());
-                        }
-                    }
-                    Some(TextureType::Depth2d(tex)) => {
-                        cache.cam_depth = Some(tex.to_ref());
-                    }
-                    _ => (),
-                }
-                None
-            } else {
-                let mut is_cascade = true;
-                for i in &input {
-                    if let TextureType::WithArg(b, StageArgs::Cascade(..)) = i {
-                        if let TextureType::Depth2d(_) = &**b {
-                        } else if let TextureType::Multi(_) = &**b {
-                        } else {
-                            is_cascade = false;
-                            break;
-                        }
-                    } else {
-                        is_cascade = false;
-                        break;
-                    }
-                }
-                if is_cascade {
-                    Self::cascade_maps_to_cache(input, cache);
-                    None
-                } else {
-                    panic!("Unrecognized cache input")
-                }
-            }
-        } else {
-            None
+            assert!(
+                cache.write(self.slot, input),
+                "Unexpected inputs for cache slot `{}`",
+                self.slot.name()
+            );
         }
+        None
+    }
+
+    fn cache_writes(&self) -> Vec<&'static str> {
+        vec![self.slot.name()]
     }
 }
@@ -0,0 +1,107 @@
+use super::drawable::*;
+use super::shader;
+use std::time::Instant;
+
+const QUAD_VERTS: [VertexSimple; 4] = [
+    VertexSimple {
+        pos: [1., 1., 0.],
+        tex_coords: [1., 1.],
+    },
+    VertexSimple {
+        pos: [-1., 1., 0.],
+        tex_coords: [0., 1.],
+    },
+    VertexSimple {
+        pos: [-1., -1., 0.],
+        tex_coords: [0., 0.],
+    },
+    VertexSimple {
+        pos: [1., -1., 0.],
+        tex_coords: [1., 0.],
+    },
+];
+
+const QUAD_INDICES: [u8; 6] = [0, 1, 2, 2, 3, 0];
+
+/// The glowing, swirling faces of wormhole portals
+///
+/// Each portal is a disk of radius `1` in the XY plane of its location,
+/// facing `+z`, so locations should be scaled by the radius of the portal
+/// and rotated to face the way the portal does. Portals are blended
+/// additively and don't write depth, so they can be drawn in any order
+pub struct Portal {
+    vbo: glium::VertexBuffer<VertexSimple>,
+    ebo: glium::IndexBuffer<u8>,
+    color: [f32; 4],
+    start: Instant,
+}
+
+impl Portal {
+    pub fn new<F: glium::backend::Facade>(facade: &F) -> Self {
+        Self {
+            vbo: glium::VertexBuffer::immutable(facade, &QUAD_VERTS).unwrap(),
+            ebo: glium::IndexBuffer::immutable(
+                facade,
+                glium::index::PrimitiveType::TrianglesList,
+                &QUAD_INDICES,
+            )
+            .unwrap(),
+            color: [0.3, 0.6, 1.0, 0.9],
+            start: Instant::now(),
+        }
+    }
+
+    /// Sets the color of the portals, in linear space
+    #[must_use]
+    #[allow(dead_code)]
+    pub const fn color(mut self, color: [f32; 4]) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Creates the entity of portals at every location in `locations`
+    pub fn into_entity(
+        self,
+        locations: &[crate::cg_support::node::Node],
+    ) -> super::entity::Entity {
+        use super::entity;
+        entity::EntityBuilder::new(self)
+            .with_pass(shader::RenderPassType::Visual)
+            .render_order(entity::RenderOrder::Last)
+            .at_all(locations)
+            .build()
+    }
+}
+
+impl Drawable for Portal {
+    fn render_args<'a>(
+        &'a mut self,
+        positions: &[[[f32; 4]; 4]],
+    ) -> Vec<(
+        shader::UniformInfo,
+        VertexHolder<'a>,
+        glium::index::IndicesSource<'a>,
+    )> {
+        let time = self.start.elapsed().as_secs_f32();
+        positions
+            .iter()
+            .map(|model| {
+                (
+                    shader::UniformInfo::Portal(shader::PortalData {
+                        model: *model,
+                        color: self.color,
+                        time,
+                    }),
+                    VertexHolder::new(VertexSourceData::Single(From::from(
+                        &self.vbo,
+                    ))),
+                    From::from(&self.ebo),
+                )
+            })
+            .collect()
+    }
+
+    fn transparency(&self) -> Option<f32> {
+        None
+    }
+}
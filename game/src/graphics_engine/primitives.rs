@@ -5,7 +5,9 @@ use super::shader;
 use crate::node;
 use cgmath::*;
 use glium::*;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
+use std::rc::Rc;
 use VertexPos as Vertex;
 
 const LINE_VERTS: [Vertex; 2] =
@@ -13,11 +15,56 @@ const LINE_VERTS: [Vertex; 2] =
 
 const LINE_INDICES: [u8; 2] = [0, 1];
 
+/// Segments a sagging line is drawn with
+const SAG_SEGMENTS: usize = 16;
+
 /// Encapsulates information to draw a line
 pub struct LineData {
     pub start: node::Node,
     pub end: node::Node,
     pub color: [f32; 4],
+    /// Length of the rope the line is drawn as, or `None` to always draw a
+    /// straight line. A rope longer than the distance between its ends sags
+    /// into a curve
+    pub length: Option<Rc<Cell<f64>>>,
+}
+
+impl LineData {
+    /// Gets the points the line is drawn through, from its start to its end
+    fn points(&self) -> Vec<Point3<f64>> {
+        let (start, end) = (self.start.get_pos(), self.end.get_pos());
+        self.length
+            .as_ref()
+            .map_or_else(|| vec![start, end], |len| sag(start, end, len.get()))
+    }
+}
+
+/// Gets the points along a rope of `length` hung from `start` to `end`. A
+/// rope longer than the distance between its ends sags downwards in a
+/// parabola, which approximates a catenary, so that its length is about
+/// `length`
+fn sag(start: Point3<f64>, end: Point3<f64>, length: f64) -> Vec<Point3<f64>> {
+    let chord = end - start;
+    let dist = chord.magnitude();
+    if length <= dist || dist < f64::EPSILON {
+        return vec![start, end];
+    }
+    // a shallow parabola sagging by `depth` is about
+    // `dist + 8 * depth^2 / (3 * dist)` long
+    let depth = (3. * dist * (length - dist) / 8.).sqrt();
+    let dir = chord / dist;
+    let down = vec3(0., -1., 0.) - dir * -dir.y;
+    let down = if down.magnitude2() < 1e-6 {
+        dir.cross(vec3(1., 0., 0.)).normalize()
+    } else {
+        down.normalize()
+    };
+    (0..=SAG_SEGMENTS)
+        .map(|i| {
+            let t = i as f64 / SAG_SEGMENTS as f64;
+            start + chord * t + down * (4. * depth * t * (1. - t))
+        })
+        .collect()
 }
 
 /// A collection of lines
@@ -85,13 +132,19 @@ impl Drawable for Lines {
             {
                 let ctx = super::super::get_active_ctx();
                 let ctx = ctx.ctx.borrow();
+                // each segment of a line is an instance
                 let vals: Vec<LineAttributes> = self
                     .lines
                     .values()
-                    .map(|v| LineAttributes {
-                        start_pos: pt_to_gl_v4(v.start.get_pos()),
-                        end_pos: pt_to_gl_v4(v.end.get_pos()),
-                        color: v.color,
+                    .flat_map(|v| {
+                        v.points()
+                            .windows(2)
+                            .map(|seg| LineAttributes {
+                                start_pos: pt_to_gl_v4(seg[0]),
+                                end_pos: pt_to_gl_v4(seg[1]),
+                                color: v.color,
+                            })
+                            .collect::<Vec<_>>()
                     })
                     .collect();
                 self.instances.update_buffer(&vals, &*ctx);
@@ -121,8 +174,6 @@ impl Drawable for Lines {
 }
 
 use crate::cg_support::Transformation;
-use std::cell::RefCell;
-use std::rc::Rc;
 
 impl AbstractEntity for Lines {
     fn transformations(&self) -> Option<&[Rc<RefCell<dyn Transformation>>]> {
@@ -150,3 +201,23 @@ impl AbstractEntity for Lines {
         self as *const _ as usize
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn slack_lines_sag_to_their_length() {
+        let (start, end) = (point3(0., 0., 0.), point3(100., 0., 0.));
+        assert_eq!(sag(start, end, 90.).len(), 2);
+        let points = sag(start, end, 110.);
+        assert_eq!(points.len(), SAG_SEGMENTS + 1);
+        assert!(points[0].distance(start) < 1e-9);
+        assert!(points[SAG_SEGMENTS].distance(end) < 1e-9);
+        let middle = points[SAG_SEGMENTS / 2];
+        assert!(middle.y < -10.);
+        assert!((middle.x - 50.).abs() < 1e-9);
+        let length: f64 = points.windows(2).map(|p| p[0].distance(p[1])).sum();
+        assert!((length - 110.).abs() < 2.);
+    }
+}
@@ -18,18 +18,201 @@ pub trait AbstractScene {
 
     /// Sets the lights used for this scene
     fn set_lights(&mut self, lights: &[shader::LightData]);
+
+    /// Sets whether the scene should render onto the screen or return its
+    /// result as a texture. Scenes which cannot render onto the screen
+    /// ignore this
+    fn set_render_to_screen(&mut self, _to_screen: bool) {}
+
+    /// Rebuilds the render targets of the scene for a screen of the given
+    /// size. Scenes which don't depend on the screen size ignore this
+    fn resize(&mut self, _width: u32, _height: u32, _ctx: &glium::Display) {}
+}
+
+/// A group of entities of a scene. Layers are the render order of a scene,
+/// they're rendered in the order they're declared, and the entities of a
+/// layer are rendered in the order they were added
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Layer {
+    /// Skyboxes, which everything else is drawn over
+    Background,
+    World,
+    /// Particles and other transparent effects, which are blended over the
+    /// world
+    Effects,
+}
+
+impl Layer {
+    /// Gets the layer of an entity with the render order `order`
+    pub const fn of_order(order: entity::RenderOrder) -> Self {
+        match order {
+            entity::RenderOrder::First => Self::Background,
+            entity::RenderOrder::Unordered => Self::World,
+            entity::RenderOrder::Last => Self::Effects,
+        }
+    }
+}
+
+/// Identifies an entity added to a scene
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct EntityHandle(u64);
+
+struct SceneEntity {
+    handle: EntityHandle,
+    layer: Layer,
+    tags: Vec<&'static str>,
+    entity: Rc<RefCell<dyn AbstractEntity>>,
+}
+
+impl SceneEntity {
+    fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| *t == tag)
+    }
+}
+
+/// The entities of a scene, which are kept in render order
+#[derive(Default)]
+pub struct SceneEntities {
+    /// Sorted by layer and then by handle, since handles increase as
+    /// entities are added
+    entities: Vec<SceneEntity>,
+    next_handle: u64,
+}
+
+impl SceneEntities {
+    /// Adds `entity` to the layer of its render order
+    pub fn add(
+        &mut self,
+        entity: Rc<RefCell<dyn AbstractEntity>>,
+    ) -> EntityHandle {
+        let layer = Layer::of_order(entity.borrow().render_order());
+        self.add_to(layer, entity, &[])
+    }
+
+    /// Adds `entity` to `layer`, after the entities already in the layer,
+    /// with the tags `tags`
+    pub fn add_to(
+        &mut self,
+        layer: Layer,
+        entity: Rc<RefCell<dyn AbstractEntity>>,
+        tags: &[&'static str],
+    ) -> EntityHandle {
+        let handle = EntityHandle(self.next_handle);
+        self.next_handle += 1;
+        let idx = self.entities.partition_point(|e| e.layer <= layer);
+        self.entities.insert(
+            idx,
+            SceneEntity {
+                handle,
+                layer,
+                tags: tags.to_vec(),
+                entity,
+            },
+        );
+        handle
+    }
+
+    /// Removes the entity of `handle`, returning it if it was in the scene
+    #[allow(dead_code)]
+    pub fn remove(
+        &mut self,
+        handle: EntityHandle,
+    ) -> Option<Rc<RefCell<dyn AbstractEntity>>> {
+        let idx = self.entities.iter().position(|e| e.handle == handle)?;
+        Some(self.entities.remove(idx).entity)
+    }
+
+    /// Removes every entity tagged with `tag`
+    ///
+    /// Returns the number of entities removed
+    #[allow(dead_code)]
+    pub fn remove_tagged(&mut self, tag: &str) -> usize {
+        let count = self.entities.len();
+        self.entities.retain(|e| !e.has_tag(tag));
+        count - self.entities.len()
+    }
+
+    /// Gets the entity of `handle`, if it's in the scene
+    #[allow(dead_code)]
+    pub fn get(
+        &self,
+        handle: EntityHandle,
+    ) -> Option<&Rc<RefCell<dyn AbstractEntity>>> {
+        self.entities
+            .iter()
+            .find(|e| e.handle == handle)
+            .map(|e| &e.entity)
+    }
+
+    /// Tags the entity of `handle` with `tag`
+    ///
+    /// Returns `false` if the entity isn't in the scene
+    #[allow(dead_code)]
+    pub fn tag(&mut self, handle: EntityHandle, tag: &'static str) -> bool {
+        self.entities
+            .iter_mut()
+            .find(|e| e.handle == handle)
+            .map(|e| {
+                if !e.tags.contains(&tag) {
+                    e.tags.push(tag);
+                }
+            })
+            .is_some()
+    }
+
+    /// Gets the handles of the entities tagged with `tag`, in render order
+    #[allow(dead_code)]
+    pub fn tagged<'a>(
+        &'a self,
+        tag: &'a str,
+    ) -> impl Iterator<Item = EntityHandle> + 'a {
+        self.entities
+            .iter()
+            .filter(move |e| e.has_tag(tag))
+            .map(|e| e.handle)
+    }
+
+    /// Gets the entities of `layer`, in render order
+    #[allow(dead_code)]
+    pub fn layer(
+        &self,
+        layer: Layer,
+    ) -> impl Iterator<Item = &Rc<RefCell<dyn AbstractEntity>>> {
+        self.entities
+            .iter()
+            .filter(move |e| e.layer == layer)
+            .map(|e| &e.entity)
+    }
+
+    /// Gets every entity, in render order
+    pub fn iter(
+        &self,
+    ) -> impl Iterator<Item = &Rc<RefCell<dyn AbstractEntity>>> {
+        self.entities.iter().map(|e| &e.entity)
+    }
+
+    pub fn clear(&mut self) {
+        self.entities.clear();
+    }
 }
 
+/// Builds the render pass of a scene for a screen of the given size
+type PassBuilder =
+    Box<dyn FnMut(u32, u32, &glium::Display) -> pipeline::RenderPass>;
+
 /// A Scene manages the scene parameters and
 /// strings together multiple render passes
 pub struct Scene {
-    ibl_maps: Option<shader::PbrMaps>,
+    /// Shared by every scene which renders the same world
+    ibl_maps: Option<Rc<shader::PbrMaps>>,
     lights: Option<ssbo::Ssbo<shader::LightData>>,
     main_light_dir: Option<cgmath::Vector3<f32>>,
-    entities: Vec<Rc<RefCell<dyn AbstractEntity>>>,
+    entities: SceneEntities,
     pass: Option<pipeline::RenderPass>,
     viewer: Rc<RefCell<dyn Viewer>>,
     bg_color: (f32, f32, f32, f32),
+    /// Rebuilds the render pass when the screen is resized
+    resize_pass: Option<PassBuilder>,
 }
 
 impl Scene {
@@ -41,10 +224,11 @@ impl Scene {
             ibl_maps: None,
             lights: Some(ssbo::Ssbo::dynamic(None)),
             main_light_dir: None,
-            entities: Vec::new(),
+            entities: SceneEntities::default(),
             pass: Some(pass),
             viewer,
             bg_color: (0., 0., 0., 0.),
+            resize_pass: None,
         }
     }
 
@@ -56,10 +240,11 @@ impl Scene {
             ibl_maps: None,
             lights: None,
             main_light_dir: None,
-            entities: Vec::new(),
+            entities: SceneEntities::default(),
             pass: Some(pass),
             viewer,
             bg_color: (0., 0., 0., 0.),
+            resize_pass: None,
         }
     }
 
@@ -69,16 +254,29 @@ impl Scene {
         self
     }
 
+    /// Rebuilds the render pass with `make_pass` whenever the screen is
+    /// resized
+    #[must_use]
+    pub fn on_resize<
+        F: FnMut(u32, u32, &glium::Display) -> pipeline::RenderPass + 'static,
+    >(
+        mut self,
+        make_pass: F,
+    ) -> Self {
+        self.resize_pass = Some(Box::new(make_pass));
+        self
+    }
+
     fn get_scene_data<'a>(
         viewer: shader::ViewerData,
         pass: shader::RenderPassType,
-        ibl_maps: &'a Option<shader::PbrMaps>,
+        ibl_maps: &'a Option<Rc<shader::PbrMaps>>,
         lights: &'a Option<ssbo::Ssbo<shader::LightData>>,
         light_dir: &'a Option<cgmath::Vector3<f32>>,
     ) -> shader::SceneData<'a> {
         shader::SceneData {
             viewer,
-            ibl_maps: ibl_maps.as_ref(),
+            ibl_maps: ibl_maps.as_deref(),
             lights: lights.as_ref(),
             pass_type: pass,
             light_pos: light_dir.map(std::convert::Into::into),
@@ -88,7 +286,7 @@ impl Scene {
     #[allow(clippy::too_many_arguments)]
     /// Renders the transparency pass for `obj`
     fn render_transparency(
-        entities: &[Rc<RefCell<dyn AbstractEntity>>],
+        entities: &SceneEntities,
         obj: usize,
         viewer: &dyn Viewer,
         scene_data: &shader::SceneData,
@@ -103,7 +301,7 @@ impl Scene {
         let mut firsts = Vec::new();
         let mut lasts = Vec::new();
         let view_mat = viewer.view_mat().into_transform();
-        for entity in entities {
+        for entity in entities.iter() {
             if entity.borrow().get_id() != obj
                 && entity
                     .borrow()
@@ -151,7 +349,7 @@ impl Scene {
 
     #[allow(clippy::too_many_arguments)]
     fn render_entities(
-        entities: &[Rc<RefCell<dyn AbstractEntity>>],
+        entities: &SceneEntities,
         viewer: &dyn Viewer,
         scene_data: &shader::SceneData,
         pass: shader::RenderPassType,
@@ -166,7 +364,7 @@ impl Scene {
                 );
             }
             typ => {
-                for entity in entities {
+                for entity in entities.iter() {
                     if entity.borrow().should_render(typ) {
                         let mut entity = entity.borrow_mut();
                         entity::render_entity(
@@ -182,7 +380,7 @@ impl Scene {
         }
     }
 
-    pub fn set_ibl_maps(&mut self, maps: shader::PbrMaps) {
+    pub fn set_ibl_maps(&mut self, maps: Rc<shader::PbrMaps>) {
         self.ibl_maps = Some(maps);
     }
 
@@ -190,27 +388,46 @@ impl Scene {
         self.main_light_dir = Some(dir_light);
     }
 
+    /// Replaces the entities of the scene, putting each entity in the layer
+    /// of its render order
     pub fn set_entities(
         &mut self,
         entities: Vec<Rc<RefCell<dyn AbstractEntity>>>,
     ) {
-        use entity::RenderOrder as RO;
-        use std::cmp::Ordering;
-        self.entities = entities;
-        self.entities.sort_by(|a, b| {
-            match (a.borrow().render_order(), b.borrow().render_order()) {
-                (RO::First, RO::First)
-                | (RO::Last, RO::Last)
-                | (RO::Unordered, RO::Unordered) => Ordering::Equal,
-                (RO::First, _) | (_, RO::Last) => Ordering::Less,
-                (RO::Last, _) | (_, RO::First) => Ordering::Greater,
-            }
-        });
+        self.entities.clear();
+        for entity in entities {
+            self.entities.add(entity);
+        }
     }
 
+    /// Adds `entity` to the layer of its render order
     #[allow(dead_code)]
-    pub fn add_entity(&mut self, entity: Rc<RefCell<dyn AbstractEntity>>) {
-        self.entities.push(entity);
+    pub fn add_entity(
+        &mut self,
+        entity: Rc<RefCell<dyn AbstractEntity>>,
+    ) -> EntityHandle {
+        self.entities.add(entity)
+    }
+
+    /// Gets the entities of the scene, to find, add or remove entities
+    #[allow(dead_code)]
+    pub fn entities_mut(&mut self) -> &mut SceneEntities {
+        &mut self.entities
+    }
+
+    /// Replaces the render pass of the scene, such as when the render
+    /// targets need to be rebuilt with different settings
+    pub fn set_render_pass(&mut self, pass: pipeline::RenderPass) {
+        self.pass = Some(pass);
+    }
+
+    /// Replaces the viewer the scene is rendered from, returning the
+    /// previous viewer
+    pub fn set_viewer(
+        &mut self,
+        viewer: Rc<RefCell<dyn Viewer>>,
+    ) -> Rc<RefCell<dyn Viewer>> {
+        std::mem::replace(&mut self.viewer, viewer)
     }
 }
 /// Generates an ibl from an hdr and skybox
@@ -218,17 +435,78 @@ impl Scene {
 /// `hdr_path` - the path to the hdr diffuse ibl image
 ///
 /// `bg_skybox` - the skybox storing the texture to generate the specular ibl from
-#[allow(clippy::too_many_lines)]
 pub fn gen_ibl_from_hdr<F: glium::backend::Facade>(
     hdr_path: &str,
     bg_skybox: &mut cubes::Skybox,
     shader_manager: &shader::ShaderManager,
     facade: &F,
 ) -> shader::PbrMaps {
-    use super::{camera, drawable};
-    use pipeline::*;
     let cbo =
         cubes::gen_cubemap_from_sphere(hdr_path, 1024, shader_manager, facade);
+    gen_ibl(cbo, bg_skybox, shader_manager, facade)
+}
+
+/// Generates an ibl from a skybox alone, such as a procedurally generated one
+///
+/// The diffuse ibl is the skybox prefiltered with full roughness, which
+/// approximates the cosine weighted irradiance of the sky
+///
+/// `bg_skybox` - the skybox storing the texture to generate the ibl from
+pub fn gen_ibl_from_sky<F: glium::backend::Facade>(
+    bg_skybox: &mut cubes::Skybox,
+    shader_manager: &shader::ShaderManager,
+    facade: &F,
+) -> shader::PbrMaps {
+    use super::{camera, drawable};
+    use pipeline::*;
+    let cam = camera::PerspectiveCamera::default(1.);
+    let mut rt = render_target::MipCubemapRenderTarget::new(
+        32,
+        1,
+        10.,
+        Box::new(|| cgmath::point3(0., 0., 0.)),
+    );
+    let mut cache = shader::PipelineCache::default();
+    bg_skybox.set_mip_progress(Some(1.));
+    let res = rt.draw(
+        &cam,
+        None,
+        &mut cache,
+        &mut |fbo, viewer, _, cache, _, _| {
+            let mut sd = drawable::default_scene_data(viewer);
+            sd.pass_type = shader::RenderPassType::LayeredVisual;
+            drawable::render_drawable(
+                bg_skybox,
+                None,
+                fbo,
+                &sd,
+                cache,
+                shader_manager,
+            );
+        },
+    );
+    bg_skybox.set_mip_progress(None);
+    if let Some(TextureType::TexCube(Ownership::Own(diffuse))) = res {
+        gen_ibl(diffuse, bg_skybox, shader_manager, facade)
+    } else {
+        panic!("Unexpected return from generating the diffuse ibl")
+    }
+}
+
+/// Generates the specular ibl and brdf lookup table of an ibl
+///
+/// `diffuse_ibl` - the diffuse ibl cubemap
+///
+/// `bg_skybox` - the skybox storing the texture to generate the specular ibl from
+#[allow(clippy::too_many_lines)]
+fn gen_ibl<F: glium::backend::Facade>(
+    diffuse_ibl: glium::texture::Cubemap,
+    bg_skybox: &mut cubes::Skybox,
+    shader_manager: &shader::ShaderManager,
+    facade: &F,
+) -> shader::PbrMaps {
+    use super::{camera, drawable};
+    use pipeline::*;
     let cam = camera::PerspectiveCamera::default(1.);
     let mip_levels = 5;
     let pos_func = || cgmath::point3(0., 0., 0.);
@@ -271,7 +549,7 @@ pub fn gen_ibl_from_hdr<F: glium::backend::Facade>(
             Some(TextureType::TexCube(Ownership::Own(spec))),
             Some(TextureType::Tex2d(Ownership::Own(brdf))),
         ) => shader::PbrMaps {
-            diffuse_ibl: cbo,
+            diffuse_ibl,
             spec_ibl: spec,
             brdf_lut: brdf,
         },
@@ -329,6 +607,36 @@ impl AbstractScene for Scene {
             self.lights = Some(ssbo::Ssbo::dynamic(Some(lights)));
         }
     }
+
+    fn resize(&mut self, width: u32, height: u32, ctx: &glium::Display) {
+        if let Some(make_pass) = self.resize_pass.as_mut() {
+            self.pass = Some(make_pass(width, height, ctx));
+        }
+    }
+}
+
+/// Allows a scene to be shared by a compositor and code which modifies the
+/// scene at runtime
+impl<S: AbstractScene + ?Sized> AbstractScene for Rc<RefCell<S>> {
+    fn render(
+        &mut self,
+        inputs: Option<&Vec<pipeline::TextureType>>,
+        shader: &shader::ShaderManager,
+    ) -> Option<pipeline::TextureType> {
+        self.borrow_mut().render(inputs, shader)
+    }
+
+    fn set_lights(&mut self, lights: &[shader::LightData]) {
+        self.borrow_mut().set_lights(lights);
+    }
+
+    fn set_render_to_screen(&mut self, to_screen: bool) {
+        self.borrow_mut().set_render_to_screen(to_screen);
+    }
+
+    fn resize(&mut self, width: u32, height: u32, ctx: &glium::Display) {
+        self.borrow_mut().resize(width, height, ctx);
+    }
 }
 
 pub type SceneModelPair = (Box<dyn AbstractScene>, Option<Matrix3<f32>>);
@@ -347,6 +655,7 @@ pub struct CompositorScene<
     compositor: CompositorProcessor,
     blitter: BlitTextureProcessor<S, SHolder, GetSHolder, CleanSHolder>,
     viewer: Rc<RefCell<dyn Viewer>>,
+    to_screen: bool,
 }
 
 /// Creates a new `CompositorScene` which blends together all output textures
@@ -404,6 +713,7 @@ pub fn compositor_scene_new<F: backend::Facade>(
             },
         ),
         viewer,
+        to_screen: true,
     }
 }
 
@@ -455,6 +765,13 @@ impl<
                 Some(&sd),
             )
         {
+            if !self.to_screen {
+                return Some(tex);
+            }
+            if let TextureType::Tex2d(frame) = &tex {
+                // captured before the blit, at the size it was rendered at
+                super::capture::on_frame(frame.to_ref());
+            }
             self.blitter.process(
                 Some(vec![&tex]),
                 shader,
@@ -473,4 +790,221 @@ impl<
             scene.set_lights(lights);
         }
     }
+
+    fn set_render_to_screen(&mut self, to_screen: bool) {
+        self.to_screen = to_screen;
+    }
+
+    fn resize(&mut self, width: u32, height: u32, ctx: &glium::Display) {
+        self.compositor =
+            CompositorProcessor::new(width, height, BlendFn::Overlay, ctx);
+        for (scene, _) in &mut self.scenes {
+            scene.resize(width, height, ctx);
+        }
+    }
+}
+
+/// A region of the screen which a scene is composited into, in fractions of
+/// the screen size from the bottom left corner
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Viewport {
+    pub left: f32,
+    pub bottom: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Viewport {
+    /// The whole screen
+    pub const FULL: Self = Self {
+        left: 0.,
+        bottom: 0.,
+        width: 1.,
+        height: 1.,
+    };
+
+    /// Splits the screen into `count` viewports side by side, ordered from
+    /// left to right
+    pub fn split_screen(count: u32) -> Vec<Self> {
+        let count = count.max(1);
+        let width = 1. / count as f32;
+        (0..count)
+            .map(|i| Self {
+                left: i as f32 * width,
+                bottom: 0.,
+                width,
+                height: 1.,
+            })
+            .collect()
+    }
+
+    /// Gets the model matrix which composites a scene's output into this
+    /// viewport. The matrix maps the texture coordinates of the screen to
+    /// the texture coordinates of the scene's output
+    pub fn transform(&self) -> Matrix3<f32> {
+        Matrix3::from_nonuniform_scale(1. / self.width, 1. / self.height)
+            * Matrix3::from_translation(vec2(-self.left, -self.bottom))
+    }
+
+    /// Gets the size of this viewport in pixels on a screen of the given
+    /// size
+    pub fn size(&self, screen_width: u32, screen_height: u32) -> (u32, u32) {
+        let scale =
+            |len: u32, frac: f32| ((len as f32 * frac).round() as u32).max(1);
+        (
+            scale(screen_width, self.width),
+            scale(screen_height, self.height),
+        )
+    }
+
+    /// Gets the aspect ratio of this viewport on a screen of the given size
+    pub fn aspect(&self, screen_width: u32, screen_height: u32) -> f32 {
+        let (width, height) = self.size(screen_width, screen_height);
+        width as f32 / height as f32
+    }
+
+    /// Converts `ndc`, in the normalized device coordinates of this
+    /// viewport, to the normalized device coordinates of the screen
+    pub fn to_screen_ndc(&self, ndc: Point2<f64>) -> Point2<f64> {
+        let to_screen = |ndc: f64, start: f32, len: f32| {
+            2. * (f64::from(start) + f64::from(len) * (ndc + 1.) / 2.) - 1.
+        };
+        point2(
+            to_screen(ndc.x, self.left, self.width),
+            to_screen(ndc.y, self.bottom, self.height),
+        )
+    }
+
+    /// Converts `ndc`, in the normalized device coordinates of the screen,
+    /// to the normalized device coordinates of this viewport
+    pub fn to_viewport_ndc(&self, ndc: Point2<f64>) -> Point2<f64> {
+        let from_screen = |ndc: f64, start: f32, len: f32| {
+            2. * ((ndc + 1.) / 2. - f64::from(start)) / f64::from(len) - 1.
+        };
+        point2(
+            from_screen(ndc.x, self.left, self.width),
+            from_screen(ndc.y, self.bottom, self.height),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// An entity which is never rendered and has nothing to draw
+    struct NullEntity(entity::RenderOrder);
+
+    impl AbstractEntity for NullEntity {
+        fn transformations(
+            &self,
+        ) -> Option<&[Rc<RefCell<dyn crate::cg_support::Transformation>>]>
+        {
+            None
+        }
+
+        fn drawable(&mut self) -> &mut dyn Drawable {
+            self
+        }
+
+        fn should_render(&self, _: shader::RenderPassType) -> bool {
+            false
+        }
+
+        fn render_order(&self) -> entity::RenderOrder {
+            self.0
+        }
+
+        fn get_id(&self) -> usize {
+            self as *const _ as usize
+        }
+    }
+
+    impl Drawable for NullEntity {
+        fn render_args<'a>(
+            &'a mut self,
+            _positions: &[[[f32; 4]; 4]],
+        ) -> Vec<(
+            shader::UniformInfo,
+            VertexHolder<'a>,
+            glium::index::IndicesSource<'a>,
+        )> {
+            Vec::new()
+        }
+
+        fn transparency(&self) -> Option<f32> {
+            None
+        }
+    }
+
+    fn null_entity(
+        order: entity::RenderOrder,
+    ) -> Rc<RefCell<dyn AbstractEntity>> {
+        Rc::new(RefCell::new(NullEntity(order)))
+    }
+
+    #[test]
+    fn entities_are_kept_in_render_order() {
+        use entity::RenderOrder::*;
+        let mut entities = SceneEntities::default();
+        let particles = entities.add(null_entity(Last));
+        let ship = entities.add(null_entity(Unordered));
+        let skybox = entities.add(null_entity(First));
+        let hud = entities.add_to(
+            Layer::Effects,
+            null_entity(Unordered),
+            &["temporary"],
+        );
+        let asteroid = entities.add_to(
+            Layer::World,
+            null_entity(Unordered),
+            &["temporary"],
+        );
+        let order: Vec<_> =
+            entities.entities.iter().map(|e| e.handle).collect();
+        assert_eq!(order, vec![skybox, ship, asteroid, particles, hud]);
+        assert_eq!(entities.layer(Layer::World).count(), 2);
+
+        assert!(entities.tag(ship, "player"));
+        assert_eq!(entities.tagged("player").collect::<Vec<_>>(), vec![ship]);
+        assert_eq!(
+            entities.tagged("temporary").collect::<Vec<_>>(),
+            vec![asteroid, hud]
+        );
+        assert_eq!(entities.remove_tagged("temporary"), 2);
+        assert!(entities.get(hud).is_none());
+
+        assert!(entities.remove(particles).is_some());
+        assert!(entities.remove(particles).is_none());
+        assert!(!entities.tag(particles, "player"));
+        assert_eq!(entities.iter().count(), 2);
+    }
+
+    #[test]
+    fn split_screen_transforms() {
+        let viewports = Viewport::split_screen(2);
+        assert_eq!(viewports.len(), 2);
+        // the corners of each viewport map to the corners of its scene
+        for (viewport, left) in viewports.iter().zip([0f32, 0.5]) {
+            let mat = viewport.transform();
+            let bottom_left = mat * vec3(left, 0., 1.);
+            let top_right = mat * vec3(left + 0.5, 1., 1.);
+            assert!(bottom_left.truncate().distance(vec2(0., 0.)) < 1e-5);
+            assert!(top_right.truncate().distance(vec2(1., 1.)) < 1e-5);
+        }
+        assert_eq!(viewports[1].size(1920, 1080), (960, 1080));
+        assert_eq!(Viewport::FULL.transform(), Matrix3::identity());
+    }
+
+    #[test]
+    fn viewport_ndc_to_screen() {
+        let right = Viewport::split_screen(2)[1];
+        let center = right.to_screen_ndc(point2(0., 0.));
+        assert!(center.distance(point2(0.5, 0.)) < 1e-9);
+        let corner = right.to_screen_ndc(point2(-1., 1.));
+        assert!(corner.distance(point2(0., 1.)) < 1e-9);
+        let pt = point2(0.3, -0.6);
+        let round_trip = right.to_viewport_ndc(right.to_screen_ndc(pt));
+        assert!(round_trip.distance(pt) < 1e-9);
+    }
 }
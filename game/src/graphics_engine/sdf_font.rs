@@ -0,0 +1,529 @@
+use std::cmp::Reverse;
+use std::path::Path;
+
+/// Pixels per em glyphs are drawn at in the atlas, which matches the
+/// pre-baked fonts
+const FONT_SIZE: f32 = 32.;
+/// Distance, in atlas pixels, the field extends past the outline of each
+/// glyph. The field is `0.5` on the outline and fades to `0` and `1` this
+/// far outside and inside it
+const SPREAD: u32 = 12;
+/// Factor glyphs are rasterized larger by, so the distances are measured
+/// more finely than the atlas resolution
+const SUPERSAMPLING: u32 = 4;
+/// Width of the atlas, which grows taller to fit the glyphs
+const ATLAS_WIDTH: u32 = 512;
+/// Most glyphs put in an atlas. Fonts with more characters only have their
+/// first characters by code point in the atlas
+const MAX_GLYPHS: usize = 1024;
+/// Squared distance of pixels with nothing to measure the distance to
+const FAR: f64 = 1e12;
+
+/// A glyph rasterized at `SUPERSAMPLING` times the size of the atlas
+struct RasterGlyph {
+    c: char,
+    /// Coverage of each pixel of the glyph, by row from the top
+    coverage: Vec<u8>,
+    width: u32,
+    height: u32,
+    /// Offset of the left of the glyph from the pen position
+    xmin: i32,
+    /// Offset of the bottom of the glyph above the baseline
+    ymin: i32,
+    /// Distance the pen moves after the glyph
+    advance: f32,
+}
+
+/// A glyph's distance field and where it's put in the atlas, in atlas
+/// pixels
+struct SdfGlyph {
+    c: char,
+    /// Alpha of each pixel of the field, by row from the top
+    field: Vec<u8>,
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    xoffset: i32,
+    /// Offset of the top of the field below the top of the line
+    yoffset: i32,
+    xadvance: i32,
+}
+
+/// `true` if `path` is a font outline file, which is drawn from an atlas
+/// generated for it
+pub fn is_outline_font(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map_or(false, |ext| {
+            ext.eq_ignore_ascii_case("ttf") || ext.eq_ignore_ascii_case("otf")
+        })
+}
+
+/// Gets the path of the `fnt` file of the signed distance field atlas
+/// generated for the font outline file at `path`
+///
+/// The atlas is cached beside the font as `<name>.sdf.fnt` and
+/// `<name>.sdf.png`, and is only generated again once the font is newer
+/// than the cache
+///
+/// # Errors
+/// Fails if the font cannot be read or parsed, or the atlas cannot be
+/// saved
+pub fn cached_atlas(path: &str) -> Result<String, String> {
+    let stem = path
+        .rfind('.')
+        .map_or(path, |ext| path.split_at(ext).0)
+        .to_owned();
+    let (fnt_path, png_path) =
+        (format!("{}.sdf.fnt", stem), format!("{}.sdf.png", stem));
+    let modified = |path: &str| {
+        std::fs::metadata(path)
+            .and_then(|meta| meta.modified())
+            .ok()
+    };
+    let fresh = match (modified(path), modified(&fnt_path)) {
+        (Some(font), Some(cache)) => {
+            cache >= font && Path::new(&png_path).exists()
+        }
+        _ => false,
+    };
+    if !fresh {
+        let data = std::fs::read(path)
+            .map_err(|e| format!("Could not open font '{}': {}", path, e))?;
+        let png_name = Path::new(&png_path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| format!("Invalid font path '{}'", path))?;
+        let (desc, atlas) = generate(&data, png_name)?;
+        atlas
+            .save(&png_path)
+            .map_err(|e| format!("Could not save '{}': {}", png_path, e))?;
+        std::fs::write(&fnt_path, desc)
+            .map_err(|e| format!("Could not save '{}': {}", fnt_path, e))?;
+    }
+    Ok(fnt_path)
+}
+
+/// Generates the signed distance field atlas of the font outline file
+/// `data`, whose texture will be saved as `png_name`
+///
+/// Returns the contents of the atlas' `fnt` file and its texture
+fn generate(
+    data: &[u8],
+    png_name: &str,
+) -> Result<(String, image::RgbaImage), String> {
+    let font =
+        fontdue::Font::from_bytes(data, fontdue::FontSettings::default())
+            .map_err(|e| format!("Could not parse font: {}", e))?;
+    let mut chars: Vec<_> = font
+        .chars()
+        .keys()
+        .copied()
+        .filter(|c| !c.is_control())
+        .collect();
+    chars.sort_unstable();
+    chars.truncate(MAX_GLYPHS);
+    let (line_height, base) = font
+        .horizontal_line_metrics(FONT_SIZE)
+        .map_or((FONT_SIZE * 1.2, FONT_SIZE), |line| {
+            (line.new_line_size, line.ascent)
+        });
+    let mut glyphs: Vec<_> = chars
+        .iter()
+        .map(|c| {
+            let (metrics, coverage) =
+                font.rasterize(*c, FONT_SIZE * SUPERSAMPLING as f32);
+            sdf_glyph(
+                &RasterGlyph {
+                    c: *c,
+                    coverage,
+                    width: metrics.width as u32,
+                    height: metrics.height as u32,
+                    xmin: metrics.xmin,
+                    ymin: metrics.ymin,
+                    advance: metrics.advance_width,
+                },
+                base.round() as i32,
+            )
+        })
+        .collect();
+    let kernings: Vec<_> = chars
+        .iter()
+        .flat_map(|first| chars.iter().map(move |second| (*first, *second)))
+        .filter_map(|(first, second)| {
+            let amount =
+                font.horizontal_kern(first, second, FONT_SIZE)?.round() as i32;
+            if amount == 0 {
+                None
+            } else {
+                Some((first, second, amount))
+            }
+        })
+        .collect();
+    let height = pack(&mut glyphs);
+    let desc = describe(
+        font.name().unwrap_or("Unknown"),
+        &glyphs,
+        &kernings,
+        (line_height.round() as i32, base.round() as i32),
+        (ATLAS_WIDTH, height),
+        png_name,
+    );
+    Ok((desc, draw_atlas(&glyphs, height)))
+}
+
+/// Computes the distance field of `glyph`, whose baseline is `base` pixels
+/// below the top of the line
+fn sdf_glyph(glyph: &RasterGlyph, base: i32) -> SdfGlyph {
+    let xadvance = (glyph.advance / SUPERSAMPLING as f32).round() as i32;
+    if glyph.width == 0 || glyph.height == 0 {
+        return SdfGlyph {
+            c: glyph.c,
+            field: Vec::new(),
+            width: 0,
+            height: 0,
+            x: 0,
+            y: 0,
+            xoffset: -(SPREAD as i32),
+            yoffset: 0,
+            xadvance,
+        };
+    }
+    let pad = SPREAD * SUPERSAMPLING;
+    // the supersampled field is padded to a whole number of atlas pixels
+    let width = (glyph.width + 2 * pad + SUPERSAMPLING - 1) / SUPERSAMPLING;
+    let height = (glyph.height + 2 * pad + SUPERSAMPLING - 1) / SUPERSAMPLING;
+    let (hi_width, hi_height) = (
+        (width * SUPERSAMPLING) as usize,
+        (height * SUPERSAMPLING) as usize,
+    );
+    let mut coverage = vec![0; hi_width * hi_height];
+    for (y, row) in glyph.coverage.chunks(glyph.width as usize).enumerate() {
+        let start = (y + pad as usize) * hi_width + pad as usize;
+        coverage[start..start + row.len()].copy_from_slice(row);
+    }
+    let distances = signed_distances(&coverage, hi_width, hi_height);
+    let field = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let half = SUPERSAMPLING / 2;
+            let idx = ((y * SUPERSAMPLING + half) as usize) * hi_width
+                + (x * SUPERSAMPLING + half) as usize;
+            let dist = distances[idx] / f64::from(SUPERSAMPLING);
+            let alpha = (0.5 + dist / f64::from(2 * SPREAD)).clamp(0., 1.);
+            (alpha * 255.).round() as u8
+        })
+        .collect();
+    let top = glyph.ymin + glyph.height as i32;
+    SdfGlyph {
+        c: glyph.c,
+        field,
+        width,
+        height,
+        x: 0,
+        y: 0,
+        xoffset: (glyph.xmin as f32 / SUPERSAMPLING as f32).round() as i32
+            - SPREAD as i32,
+        yoffset: base
+            - (top as f32 / SUPERSAMPLING as f32).round() as i32
+            - SPREAD as i32,
+        xadvance,
+    }
+}
+
+/// Computes the signed distance, in pixels, from the center of each pixel
+/// of the `width` by `height` coverage bitmap to the outline of the shape it
+/// covers. Distances are positive inside the shape
+fn signed_distances(coverage: &[u8], width: usize, height: usize) -> Vec<f64> {
+    let inside: Vec<_> = coverage.iter().map(|c| *c >= 128).collect();
+    let mut to_inside: Vec<_> =
+        inside.iter().map(|i| if *i { 0. } else { FAR }).collect();
+    let mut to_outside: Vec<_> =
+        inside.iter().map(|i| if *i { FAR } else { 0. }).collect();
+    distance_transform(&mut to_inside, width, height);
+    distance_transform(&mut to_outside, width, height);
+    // the outline is half a pixel from the centers of the pixels beside it
+    inside
+        .iter()
+        .zip(to_inside.iter().zip(&to_outside))
+        .map(|(inside, (to_inside, to_outside))| {
+            if *inside {
+                to_outside.sqrt() - 0.5
+            } else {
+                0.5 - to_inside.sqrt()
+            }
+        })
+        .collect()
+}
+
+/// Replaces each cell of the `width` by `height` grid with the squared
+/// distance to the nearest cell which is `0`, where cells which aren't `0`
+/// are `FAR`
+///
+/// Uses the linear time distance transform of Felzenszwalb and
+/// Huttenlocher, which transforms the columns and then the rows
+fn distance_transform(grid: &mut [f64], width: usize, height: usize) {
+    let mut column = vec![0.; height];
+    for x in 0..width {
+        for (y, cell) in column.iter_mut().enumerate() {
+            *cell = grid[y * width + x];
+        }
+        for (y, dist) in distance_transform_1d(&column).into_iter().enumerate()
+        {
+            grid[y * width + x] = dist;
+        }
+    }
+    for row in grid.chunks_mut(width) {
+        let dists = distance_transform_1d(row);
+        row.copy_from_slice(&dists);
+    }
+}
+
+/// Gets the squared distance transform of the sampled function `f`, which
+/// is the lower envelope of the parabolas rooted at each sample
+fn distance_transform_1d(f: &[f64]) -> Vec<f64> {
+    let n = f.len();
+    // roots of the parabolas of the envelope, and where each begins
+    let mut roots = vec![0_usize; n];
+    let mut bounds = vec![0.; n + 1];
+    let mut k = 0;
+    bounds[0] = f64::NEG_INFINITY;
+    bounds[1] = f64::INFINITY;
+    let intersect = |q: usize, p: usize| {
+        let (qf, pf) = (q as f64, p as f64);
+        (qf.mul_add(qf, f[q]) - pf.mul_add(pf, f[p])) / (2. * (qf - pf))
+    };
+    for q in 1..n {
+        let mut s = intersect(q, roots[k]);
+        while s <= bounds[k] {
+            k -= 1;
+            s = intersect(q, roots[k]);
+        }
+        k += 1;
+        roots[k] = q;
+        bounds[k] = s;
+        bounds[k + 1] = f64::INFINITY;
+    }
+    k = 0;
+    (0..n)
+        .map(|q| {
+            while bounds[k + 1] < q as f64 {
+                k += 1;
+            }
+            let offset = q as f64 - roots[k] as f64;
+            offset.mul_add(offset, f[roots[k]])
+        })
+        .collect()
+}
+
+/// Places the glyphs in rows across the atlas, tallest first
+///
+/// Returns the height of the atlas, which is a power of two
+fn pack(glyphs: &mut [SdfGlyph]) -> u32 {
+    glyphs.sort_by_key(|glyph| Reverse(glyph.height));
+    let (mut x, mut y, mut row_height) = (0, 0, 0);
+    for glyph in glyphs.iter_mut().filter(|glyph| glyph.width > 0) {
+        if x + glyph.width > ATLAS_WIDTH {
+            x = 0;
+            y += row_height;
+            row_height = 0;
+        }
+        glyph.x = x;
+        glyph.y = y;
+        x += glyph.width;
+        row_height = row_height.max(glyph.height);
+    }
+    (y + row_height).next_power_of_two()
+}
+
+/// Draws the fields of the packed glyphs into an atlas of `height`. The
+/// field is stored in the alpha channel
+fn draw_atlas(glyphs: &[SdfGlyph], height: u32) -> image::RgbaImage {
+    let mut atlas = image::RgbaImage::new(ATLAS_WIDTH, height);
+    for glyph in glyphs.iter().filter(|glyph| glyph.width > 0) {
+        let rows = glyph.field.chunks(glyph.width as usize);
+        for (y, row) in (glyph.y..).zip(rows) {
+            for (x, alpha) in (glyph.x..).zip(row) {
+                atlas.put_pixel(x, y, image::Rgba([255, 255, 255, *alpha]));
+            }
+        }
+    }
+    atlas
+}
+
+/// Writes the `fnt` file of an atlas in the text format of `BMFont`
+///
+/// `line` - the line height and the distance from the top of the line to
+/// the baseline
+///
+/// `size` - the width and height of the atlas texture, `png_name`
+fn describe(
+    face: &str,
+    glyphs: &[SdfGlyph],
+    kernings: &[(char, char, i32)],
+    line: (i32, i32),
+    size: (u32, u32),
+    png_name: &str,
+) -> String {
+    use std::fmt::Write;
+    let mut desc = format!(
+        "info face=\"{}\" size={} bold=0 italic=0 charset=\"\" unicode=1 \
+         stretchH=100 smooth=1 aa=1 padding={spread},{spread},{spread},{spread} \
+         spacing=0,0\n\
+         common lineHeight={} base={} scaleW={} scaleH={} pages=1 packed=0\n\
+         page id=0 file=\"{}\"\n\
+         chars count={}\n",
+        face.replace('"', ""),
+        FONT_SIZE,
+        line.0,
+        line.1,
+        size.0,
+        size.1,
+        png_name,
+        glyphs.len(),
+        spread = SPREAD
+    );
+    for g in glyphs {
+        // writing to a string can't fail
+        let _ = writeln!(
+            desc,
+            "char id={} x={} y={} width={} height={} xoffset={} yoffset={} \
+             xadvance={} page=0 chnl=0",
+            u32::from(g.c),
+            g.x,
+            g.y,
+            g.width,
+            g.height,
+            g.xoffset,
+            g.yoffset,
+            g.xadvance
+        );
+    }
+    let _ = writeln!(desc, "kernings count={}", kernings.len());
+    for (first, second, amount) in kernings {
+        let _ = writeln!(
+            desc,
+            "kerning first={} second={} amount={}",
+            u32::from(*first),
+            u32::from(*second),
+            amount
+        );
+    }
+    desc
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::graphics_engine::text::FontDesc;
+
+    /// Gets a glyph covering a `size` by `size` square sitting on the
+    /// baseline
+    fn square(c: char, size: u32) -> RasterGlyph {
+        RasterGlyph {
+            c,
+            coverage: vec![255; (size * size) as usize],
+            width: size,
+            height: size,
+            xmin: 0,
+            ymin: 0,
+            advance: (size + 8) as f32,
+        }
+    }
+
+    #[test]
+    fn distances_are_signed_from_the_outline() {
+        // a 4x4 square in the middle of a 10x10 bitmap
+        let coverage: Vec<_> = (0..100)
+            .map(|i| {
+                if (3..7).contains(&(i % 10)) && (3..7).contains(&(i / 10)) {
+                    255
+                } else {
+                    0
+                }
+            })
+            .collect();
+        let dists = signed_distances(&coverage, 10, 10);
+        assert!((dists[3 * 10 + 3] - 0.5).abs() < 1e-9);
+        assert!((dists[4 * 10 + 4] - 1.5).abs() < 1e-9);
+        assert!((dists[4 * 10 + 2] + 0.5).abs() < 1e-9);
+        assert!((dists[4 * 10] + 2.5).abs() < 1e-9);
+        // diagonally out from the corner
+        assert!((dists[0] - (0.5 - 18_f64.sqrt())).abs() < 1e-9);
+    }
+
+    #[test]
+    fn glyph_fields_fade_from_the_outline() {
+        let size = 16 * SUPERSAMPLING;
+        let glyph = sdf_glyph(&square('a', size), 30);
+        assert_eq!(glyph.width, 16 + 2 * SPREAD);
+        assert_eq!(glyph.height, 16 + 2 * SPREAD);
+        assert_eq!(glyph.xadvance, 18);
+        assert_eq!(glyph.xoffset, -(SPREAD as i32));
+        assert_eq!(glyph.yoffset, 30 - 16 - SPREAD as i32);
+        let alpha =
+            |x: u32, y: u32| glyph.field[(y * glyph.width + x) as usize];
+        let center = SPREAD + 8;
+        assert!(alpha(center, center) > 200);
+        assert_eq!(alpha(0, 0), 0);
+        // the outline is half way
+        let edge = alpha(SPREAD, center);
+        assert!((120..=140).contains(&edge));
+        assert!(alpha(SPREAD - 4, center) < edge);
+        assert!(alpha(SPREAD + 4, center) > edge);
+        // whitespace has no field
+        let mut space = square(' ', 0);
+        space.advance = 36.;
+        let space = sdf_glyph(&space, 30);
+        assert!(space.field.is_empty());
+        assert_eq!(space.xadvance, 9);
+    }
+
+    #[test]
+    fn atlases_describe_their_glyphs() {
+        let mut glyphs: Vec<_> = ('A'..='Z')
+            .map(|c| sdf_glyph(&square(c, 8 * SUPERSAMPLING), 30))
+            .chain(std::iter::once(sdf_glyph(&square(' ', 0), 30)))
+            .collect();
+        let height = pack(&mut glyphs);
+        assert!(height.is_power_of_two());
+        // no glyphs overlap or leave the atlas
+        let placed: Vec<_> = glyphs.iter().filter(|g| g.width > 0).collect();
+        for (i, a) in placed.iter().enumerate() {
+            assert!(a.x + a.width <= ATLAS_WIDTH && a.y + a.height <= height);
+            for b in &placed[i + 1..] {
+                assert!(
+                    a.x + a.width <= b.x
+                        || b.x + b.width <= a.x
+                        || a.y + a.height <= b.y
+                        || b.y + b.height <= a.y
+                );
+            }
+        }
+        let desc = describe(
+            "Test",
+            &glyphs,
+            &[('A', 'V', -2)],
+            (38, 30),
+            (ATLAS_WIDTH, height),
+            "Test-Regular.sdf.png",
+        );
+        let parsed = FontDesc::parse(&desc, "assets/fonts/Test.sdf.fnt");
+        assert!(parsed.tex_path.ends_with("/Test-Regular.sdf.png"));
+        let atlas = draw_atlas(&glyphs, height);
+        assert_eq!(atlas.dimensions(), (ATLAS_WIDTH, height));
+        let a = glyphs.iter().find(|g| g.c == 'A').unwrap();
+        let center = (a.x + a.width / 2, a.y + a.height / 2);
+        assert!(atlas.get_pixel(center.0, center.1)[3] > 128);
+        assert_eq!(atlas.get_pixel(a.x, a.y)[3], 0);
+    }
+
+    #[test]
+    fn outline_fonts_are_recognized() {
+        assert!(is_outline_font("assets/fonts/Noto Sans.ttf"));
+        assert!(is_outline_font("Font.OTF"));
+        assert!(!is_outline_font("assets/fonts/SignedDistanceArial.fnt"));
+    }
+}
@@ -1,4 +1,6 @@
 #![allow(clippy::transmute_ptr_to_ptr)]
+use super::pipeline::{StageArgs, TextureType};
+use super::textures;
 use crate::cg_support::ssbo;
 use cgmath::*;
 use glium::implement_uniform_block;
@@ -18,14 +20,19 @@ enum ShaderType {
     PrefilterHdrShader,
     GenLutShader,
     CullLightsCompute,
+    ClusterLightsCompute,
     DepthShader,
     DepthInstancedShader,
     PbrInstancedShader,
     PbrAnim,
     DepthAnim,
     TriIntersectionCompute,
+    ParticleSimCompute,
     CollisionDebug,
     Billboard,
+    GpuBillboard,
+    AdditiveBillboard,
+    AdditiveGpuBillboard,
     ParallelPbr,
     ParallelInstancePbr,
     ParallelAnimPbr,
@@ -33,11 +40,30 @@ enum ShaderType {
     ParallelSky,
     ParallelEqRect,
     ParallelPrefilter,
+    ParallelProceduralSky,
     Cloud,
     Line,
     Text,
     Minimap,
     Icon,
+    Decal,
+    Trail,
+    Impostor,
+    Atmosphere,
+    Radar,
+    HitIndicator,
+    Portal,
+}
+
+/// How particles are blended with what is behind them
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum BlendMode {
+    /// Standard alpha blending, which requires particles to be drawn back to
+    /// front
+    Alpha,
+    /// The color of particles is added to what is behind them, so they can be
+    /// drawn in any order. Good for fire, sparks, and other glowing effects
+    Additive,
 }
 
 /// The type of objects that should be rendered to a render target
@@ -90,7 +116,7 @@ impl ShaderType {
         match self {
             Pbr | PbrInstancedShader | DepthShader | DepthInstancedShader
             | Laser | PbrAnim | DepthAnim | ParallelInstancePbr
-            | ParallelLaser | ParallelPbr => {
+            | ParallelLaser | ParallelPbr | Impostor => {
                 glium::DrawParameters {
                     depth: glium::Depth {
                         test: DepthTest::IfLess,
@@ -112,18 +138,87 @@ impl ShaderType {
                 line_width: Some(2.),
                 ..Default::default()
             },
-            Billboard | Minimap | Icon => glium::DrawParameters {
-                blend: glium::Blend::alpha_blending(),
+            Billboard | GpuBillboard | Minimap | Icon => {
+                glium::DrawParameters {
+                    blend: glium::Blend::alpha_blending(),
+                    backface_culling: glium::BackfaceCullingMode::CullClockwise,
+                    ..Default::default()
+                }
+            }
+            AdditiveBillboard | AdditiveGpuBillboard => glium::DrawParameters {
+                blend: glium::Blend {
+                    color: BlendingFunction::Addition {
+                        source: LinearBlendingFactor::SourceAlpha,
+                        destination: LinearBlendingFactor::One,
+                    },
+                    alpha: BlendingFunction::Addition {
+                        source: LinearBlendingFactor::Zero,
+                        destination: LinearBlendingFactor::One,
+                    },
+                    constant_value: (0., 0., 0., 0.),
+                },
                 backface_culling: glium::BackfaceCullingMode::CullClockwise,
                 ..Default::default()
             },
-            Cloud => glium::DrawParameters {
+            Cloud | Decal => glium::DrawParameters {
                 blend: glium::Blend::alpha_blending(),
                 backface_culling:
                     glium::BackfaceCullingMode::CullCounterClockwise,
                 ..Default::default()
             },
-            Text => glium::DrawParameters {
+            Atmosphere => glium::DrawParameters {
+                // the scattered light is premultiplied by its opacity
+                blend: glium::Blend {
+                    color: BlendingFunction::Addition {
+                        source: LinearBlendingFactor::One,
+                        destination: LinearBlendingFactor::OneMinusSourceAlpha,
+                    },
+                    alpha: BlendingFunction::Addition {
+                        source: LinearBlendingFactor::Zero,
+                        destination: LinearBlendingFactor::One,
+                    },
+                    constant_value: (0., 0., 0., 0.),
+                },
+                backface_culling:
+                    glium::BackfaceCullingMode::CullCounterClockwise,
+                ..Default::default()
+            },
+            // glowing lines, so overlapping lines don't need to be sorted
+            Radar => glium::DrawParameters {
+                blend: glium::Blend {
+                    color: BlendingFunction::Addition {
+                        source: LinearBlendingFactor::SourceAlpha,
+                        destination: LinearBlendingFactor::One,
+                    },
+                    alpha: BlendingFunction::Addition {
+                        source: LinearBlendingFactor::Zero,
+                        destination: LinearBlendingFactor::One,
+                    },
+                    constant_value: (0., 0., 0., 0.),
+                },
+                line_width: Some(1.5),
+                ..Default::default()
+            },
+            Trail | Portal => glium::DrawParameters {
+                depth: glium::Depth {
+                    test: DepthTest::IfLess,
+                    write: false,
+                    ..Default::default()
+                },
+                blend: glium::Blend {
+                    color: BlendingFunction::Addition {
+                        source: LinearBlendingFactor::SourceAlpha,
+                        destination: LinearBlendingFactor::One,
+                    },
+                    alpha: BlendingFunction::Addition {
+                        source: LinearBlendingFactor::Zero,
+                        destination: LinearBlendingFactor::One,
+                    },
+                    constant_value: (0., 0., 0., 0.),
+                },
+                ..Default::default()
+            },
+            Text | HitIndicator => glium::DrawParameters {
                 blend: glium::Blend::alpha_blending(),
                 //polygon_mode: glium::PolygonMode::Line,
                 ..Default::default()
@@ -194,6 +289,24 @@ impl LightData {
             _mode: 2,
         }
     }
+
+    pub const fn luminance(&self) -> f32 {
+        self._luminance
+    }
+
+    /// Gets the point halfway along the light
+    pub fn center(&self) -> Point3<f32> {
+        Point3::from(self._light_start).midpoint(Point3::from(self._light_end))
+    }
+
+    /// Gets this light with its luminance scaled by `factor`
+    #[must_use]
+    pub fn scaled(self, factor: f32) -> Self {
+        Self {
+            _luminance: self._luminance * factor,
+            ..self
+        }
+    }
 }
 
 /// The `ShaderManager` stores all shaders and all draw parameters for each shader
@@ -206,6 +319,13 @@ pub struct ShaderManager {
     empty_2d: glium::texture::Texture2d,
     empty_cube: glium::texture::Cubemap,
     empty_depth: glium::texture::DepthTexture2d,
+    /// Fallback for missing albedo maps
+    white_srgb: glium::texture::SrgbTexture2d,
+    /// Fallback for missing roughness maps
+    white_2d: glium::texture::Texture2d,
+    /// Fallback for missing normal maps, a normal pointing straight out of
+    /// the surface in tangent space
+    flat_normal: glium::texture::Texture2d,
 }
 
 /// Precomputed integrals for PBR environment maps
@@ -215,6 +335,7 @@ pub struct PbrMaps {
     pub brdf_lut: glium::texture::Texture2d,
 }
 
+#[derive(Copy, Clone)]
 pub struct ViewerData {
     pub viewproj: [[f32; 4]; 4],
     pub view: [[f32; 4]; 4],
@@ -249,7 +370,7 @@ impl Default for TransparencyData {
 /// Shader inputs for PBR shader
 pub struct PBRData<'a> {
     pub model: [[f32; 4]; 4],
-    pub diffuse_tex: &'a glium::texture::SrgbTexture2d,
+    pub diffuse_tex: Option<&'a glium::texture::SrgbTexture2d>,
     pub roughness_map: Option<&'a glium::texture::Texture2d>,
     pub metallic_map: Option<&'a glium::texture::Texture2d>,
     pub normal_map: Option<&'a glium::texture::Texture2d>,
@@ -284,16 +405,156 @@ pub struct PrefilterHdrEnvData<'a> {
     pub env_map: &'a glium::texture::Cubemap,
     pub roughness: f32,
 }
+/// A layer of nebula in a procedural sky, laid out like the shader expects
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct NebulaLayerData {
+    /// The color of the dense parts of the layer, and the radius of the
+    /// shell of the noise volume the layer is sampled from
+    pub inner_color_shell: [f32; 4],
+    /// The color of the thin parts of the layer, and the brightness of the
+    /// layer
+    pub outer_color_intensity: [f32; 4],
+}
+/// Shader inputs for generating a procedural sky
+pub struct ProceduralSkyData<'a> {
+    pub nebula_noise: &'a glium::texture::Texture3d,
+    pub nebula_layers: &'a ssbo::Ssbo<NebulaLayerData>,
+    pub seed: u32,
+    /// Chance that a cell of the star grid has a star
+    pub star_density: f32,
+    pub star_brightness: f32,
+    /// Angle covered by a texel of the cubemap face being rendered, in radians
+    pub texel_angle: f32,
+}
 /// Compute shader inputs for light culling
 pub struct LightCullData<'a> {
     pub depth_tex: &'a glium::texture::DepthTexture2d,
     pub scr_width: u32,
     pub scr_height: u32,
 }
+/// A 3D grid of clusters dividing the view frustum, which lights are
+/// assigned to. Each cluster is a screen tile between two depths. The depth
+/// is sliced exponentially, so clusters get longer with their distance like
+/// the footprint of a pixel does
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LightClusters {
+    /// Number of clusters along the width, height, and depth of the frustum
+    pub count: [u32; 3],
+    /// Width and height of the screen tile of a cluster, in pixels
+    pub tile_size: u32,
+    pub screen_size: [u32; 2],
+    /// Near and far plane of the frustum
+    pub depth_range: [f32; 2],
+}
+
+impl LightClusters {
+    /// Creates clusters covering a `width` by `height` screen with tiles of
+    /// `tile_size` pixels and `slices` depth slices
+    pub const fn new(
+        width: u32,
+        height: u32,
+        tile_size: u32,
+        slices: u32,
+    ) -> Self {
+        Self {
+            count: [
+                (width + tile_size - 1) / tile_size,
+                (height + tile_size - 1) / tile_size,
+                slices,
+            ],
+            tile_size,
+            screen_size: [width, height],
+            depth_range: [0., 0.],
+        }
+    }
+
+    /// Gets the clusters spanning the frustum of the perspective
+    /// projection `proj`
+    pub fn with_proj(self, proj: &[[f32; 4]; 4]) -> Self {
+        // the depth terms of a perspective matrix are
+        // `(far + near) / (near - far)` and `2 * far * near / (near - far)`
+        let (a, b) = (proj[2][2], proj[3][2]);
+        Self {
+            depth_range: [b / (a - 1.), b / (a + 1.)],
+            ..self
+        }
+    }
+
+    /// Gets the total number of clusters
+    #[must_use]
+    pub const fn total(&self) -> u32 {
+        self.count[0] * self.count[1] * self.count[2]
+    }
+}
+
 /// Data for cloud rendering
 pub struct CloudData<'a> {
     pub volume: &'a glium::texture::Texture3d,
     pub model: [[f32; 4]; 4],
+    /// Rotation of the volume's density around its center
+    pub anim_rot: [[f32; 3]; 3],
+}
+/// Data for rendering the atmosphere of a planet
+pub struct AtmosphereData {
+    pub model: [[f32; 4]; 4],
+    /// Radius of the surface of the planet, in model space
+    pub planet_radius: f32,
+    /// Radius of the top of the atmosphere, in model space
+    pub atmosphere_radius: f32,
+    /// Scale of the scattering and absorption of the atmosphere
+    pub density: f32,
+    pub sun_intensity: f32,
+}
+/// Compute shader inputs for simulating GPU particles
+///
+/// Ranges are `[min, max]` and randomly chosen per particle
+#[derive(Clone, Copy, Debug)]
+pub struct ParticleSimData {
+    /// Elapsed time since the last simulation step, in seconds
+    pub dt: f32,
+    /// Random seed for this step
+    pub seed: u32,
+    /// `true` if dead particles should be respawned
+    pub emitting: bool,
+    pub particle_count: u32,
+    pub origin: [f32; 3],
+    /// Center of the cone new particles are emitted in
+    pub dir: [f32; 3],
+    /// Half angle of the emission cone, in radians
+    pub spread: f32,
+    pub speed: [f32; 2],
+    /// Lifetime range, in seconds
+    pub lifetime: [f32; 2],
+    pub scale: [f32; 2],
+    pub start_color: [f32; 4],
+    /// Color a particle fades to by the end of its lifetime
+    pub end_color: [f32; 4],
+    pub accel: [f32; 3],
+}
+/// Data for trail rendering
+#[derive(Clone, Copy, Debug)]
+pub struct TrailData {
+    /// Color of the head of the trail, in linear space
+    pub color: [f32; 4],
+    /// Multiplier of `color` so the trail is bright enough to bloom
+    pub emission: f32,
+    /// Width of the head of the trail
+    pub width: f32,
+    /// Exponent of the width's falloff from the head to the tail
+    pub width_falloff: f32,
+    /// Exponent of the opacity's falloff from the head to the tail
+    pub alpha_falloff: f32,
+}
+/// Data for rendering the face of a wormhole's portal
+#[derive(Clone, Copy, Debug)]
+pub struct PortalData {
+    /// Transformation of the unit quad in the XY plane to the portal's face
+    pub model: [[f32; 4]; 4],
+    /// Color of the portal, in linear space
+    pub color: [f32; 4],
+    /// Seconds since the portal was created, which animates its swirl
+    pub time: f32,
 }
 #[derive(Clone, Copy)]
 #[repr(C)]
@@ -370,15 +631,308 @@ impl<'s, T: Uniforms, R: Uniforms> Uniforms for UniformsStruct<'s, T, R> {
 pub struct PipelineCache<'a> {
     pub cascade_ubo: Option<glium::uniforms::UniformBuffer<CascadeUniform>>,
     pub tiles_x: Option<u32>,
-    pub cascade_maps: Option<Vec<&'a glium::texture::DepthTexture2d>>,
-    pub trans_cascade_maps: Option<
-        Vec<(
-            &'a glium::texture::DepthTexture2d,
-            &'a glium::texture::Texture2d,
-        )>,
-    >,
-    pub obj_cubemaps: HashMap<u32, &'a glium::texture::Cubemap>,
-    pub cam_depth: Option<&'a glium::texture::DepthTexture2d>,
+    /// The clusters the lights were assigned to, or `None` if they were
+    /// culled per screen tile
+    pub light_clusters: Option<LightClusters>,
+    /// Stage outputs written to each slot, keyed by the name of the slot
+    slots: HashMap<&'static str, Vec<&'a TextureType<'a>>>,
+}
+
+impl<'a> PipelineCache<'a> {
+    /// Stores `outputs` in `slot`, replacing anything already stored there
+    ///
+    /// Returns `false`, leaving the cache unchanged, if `outputs` can't be
+    /// read as the kind of value stored in `slot`
+    pub fn write<T: CacheValue>(
+        &mut self,
+        slot: CacheSlot<T>,
+        outputs: Vec<&'a TextureType<'a>>,
+    ) -> bool {
+        match T::read(&outputs) {
+            Some(value) => {
+                T::on_write(&value, self);
+                self.slots.insert(slot.name(), outputs);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Gets the value stored in `slot`, or `None` if nothing was written
+    /// to it yet
+    pub fn get<T: CacheValue>(
+        &self,
+        slot: CacheSlot<T>,
+    ) -> Option<T::Value<'a>> {
+        self.slots
+            .get(slot.name())
+            .and_then(|outputs| T::read(outputs))
+    }
+
+    /// Gets the environment maps and weights of the two reflection probes
+    /// with the most influence over an object at `pos`, strongest first
+    pub fn nearest_probes(
+        &self,
+        pos: [f32; 3],
+    ) -> [Option<(&'a glium::texture::Cubemap, f32)>; 2] {
+        let mut best: [Option<(&'a glium::texture::Cubemap, f32)>; 2] =
+            [None, None];
+        for probe in self.get(PROBES).unwrap_or_default() {
+            let weight = probe.weight(pos);
+            if weight <= 0. {
+                continue;
+            }
+            if best[0].map_or(true, |(_, w)| weight > w) {
+                best[1] = best[0];
+                best[0] = Some((probe.env_map, weight));
+            } else if best[1].map_or(true, |(_, w)| weight > w) {
+                best[1] = Some((probe.env_map, weight));
+            }
+        }
+        best
+    }
+}
+
+/// A kind of value stored in the pipeline cache, which is read from the
+/// outputs of the stage that wrote it
+pub trait CacheValue {
+    type Value<'a>;
+
+    /// Reads the value from the stage outputs written to its slot, or `None`
+    /// if the outputs aren't this kind of value
+    fn read<'a>(outputs: &[&'a TextureType<'a>]) -> Option<Self::Value<'a>>;
+
+    /// Called when `value` is written to `cache`, to store anything else
+    /// that's derived from it
+    fn on_write<'a>(_value: &Self::Value<'a>, _cache: &mut PipelineCache<'a>) {}
+}
+
+/// Key of a value of kind `T` in the pipeline cache
+///
+/// Slots are identified by their name, so every slot needs a unique one
+pub struct CacheSlot<T: CacheValue> {
+    name: &'static str,
+    value: std::marker::PhantomData<fn() -> T>,
+}
+
+impl<T: CacheValue> Clone for CacheSlot<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: CacheValue> Copy for CacheSlot<T> {}
+
+impl<T: CacheValue> CacheSlot<T> {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            value: std::marker::PhantomData,
+        }
+    }
+
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+/// Depth of the scene from the camera
+pub const CAM_DEPTH: CacheSlot<DepthMap> = CacheSlot::new("cam_depth");
+/// Shadow maps of the directional light
+pub const CASCADES: CacheSlot<Cascades> = CacheSlot::new("cascades");
+/// Cubemaps rendered from translucent objects, for refraction
+pub const OBJECT_CUBEMAPS: CacheSlot<ObjectCubemaps> =
+    CacheSlot::new("object_cubemaps");
+/// Reflection probes of the scene
+pub const PROBES: CacheSlot<Probes> = CacheSlot::new("probes");
+
+/// A single depth texture
+pub struct DepthMap;
+
+impl CacheValue for DepthMap {
+    type Value<'a> = &'a glium::texture::DepthTexture2d;
+
+    fn read<'a>(outputs: &[&'a TextureType<'a>]) -> Option<Self::Value<'a>> {
+        match outputs {
+            [TextureType::Depth2d(tex)] => Some(tex.to_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// Cubemaps with the id of the object each was rendered from
+pub struct ObjectCubemaps;
+
+impl CacheValue for ObjectCubemaps {
+    type Value<'a> = Vec<(u32, &'a glium::texture::Cubemap)>;
+
+    fn read<'a>(outputs: &[&'a TextureType<'a>]) -> Option<Self::Value<'a>> {
+        outputs
+            .iter()
+            .map(|out| match out {
+                TextureType::WithArg(tex, StageArgs::Object(id)) => {
+                    match &**tex {
+                        TextureType::TexCube(cbo) => Some((*id, cbo.to_ref())),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// The reflection probes output by a single stage
+pub struct Probes;
+
+impl CacheValue for Probes {
+    type Value<'a> = Vec<ProbeRef<'a>>;
+
+    fn read<'a>(outputs: &[&'a TextureType<'a>]) -> Option<Self::Value<'a>> {
+        match outputs {
+            [TextureType::Multi(probes)] => probes
+                .iter()
+                .map(|probe| match probe {
+                    TextureType::WithArg(
+                        tex,
+                        StageArgs::Probe(pos, radius),
+                    ) => match &**tex {
+                        TextureType::TexCube(cbo) => Some(ProbeRef {
+                            pos: *pos,
+                            radius: *radius,
+                            env_map: cbo.to_ref(),
+                        }),
+                        _ => None,
+                    },
+                    _ => None,
+                })
+                .collect(),
+            _ => None,
+        }
+    }
+}
+
+/// Maximum number of shadow cascades the pbr shader samples
+const MAX_CASCADES: usize = 3;
+
+/// Shadow map cascades, nearest first
+pub struct Cascades;
+
+pub struct CascadeMaps<'a> {
+    pub depth_maps: Vec<&'a glium::texture::DepthTexture2d>,
+    /// The depth and transparency factor of translucent objects in each
+    /// cascade, or empty if the cascades don't render translucent objects
+    pub trans_maps: Vec<(
+        &'a glium::texture::DepthTexture2d,
+        &'a glium::texture::Texture2d,
+    )>,
+    /// Light space viewproj matrix of each cascade
+    pub viewprojs: Vec<[[f32; 4]; 4]>,
+    /// Far plane of each cascade in view space
+    pub far_planes: Vec<f32>,
+}
+
+impl CacheValue for Cascades {
+    type Value<'a> = CascadeMaps<'a>;
+
+    fn read<'a>(outputs: &[&'a TextureType<'a>]) -> Option<Self::Value<'a>> {
+        if outputs.is_empty() || outputs.len() > MAX_CASCADES {
+            return None;
+        }
+        let mut maps = CascadeMaps {
+            depth_maps: Vec::new(),
+            trans_maps: Vec::new(),
+            viewprojs: Vec::new(),
+            far_planes: Vec::new(),
+        };
+        for out in outputs {
+            let (tex, viewproj, far) = match out {
+                TextureType::WithArg(tex, StageArgs::Cascade(mat, far)) => {
+                    (tex, mat, far)
+                }
+                _ => return None,
+            };
+            match &**tex {
+                TextureType::Depth2d(depth) => {
+                    maps.depth_maps.push(depth.to_ref());
+                }
+                TextureType::Multi(texes) => match texes.as_slice() {
+                    [TextureType::Depth2d(opaque_depth), TextureType::Depth2d(trans_depth), TextureType::Tex2d(trans_fac)] =>
+                    {
+                        maps.depth_maps.push(opaque_depth.to_ref());
+                        maps.trans_maps
+                            .push((trans_depth.to_ref(), trans_fac.to_ref()));
+                    }
+                    _ => return None,
+                },
+                _ => return None,
+            }
+            maps.viewprojs.push(*viewproj);
+            maps.far_planes.push(*far);
+        }
+        if maps.trans_maps.is_empty()
+            || maps.trans_maps.len() == maps.depth_maps.len()
+        {
+            Some(maps)
+        } else {
+            None
+        }
+    }
+
+    fn on_write<'a>(maps: &CascadeMaps<'a>, cache: &mut PipelineCache<'a>) {
+        let mut uniform = CascadeUniform {
+            far_planes: [0.; 4],
+            viewproj_mats: [Matrix4::<f32>::from_scale(1.).into(); 5],
+        };
+        for (i, (viewproj, far)) in
+            maps.viewprojs.iter().zip(&maps.far_planes).enumerate()
+        {
+            uniform.viewproj_mats[i] = *viewproj;
+            uniform.far_planes[i] = *far;
+        }
+        let ctx = super::get_active_ctx();
+        cache.cascade_ubo = glium::uniforms::UniformBuffer::persistent(
+            &*ctx.ctx.borrow(),
+            uniform,
+        )
+        .ok();
+    }
+}
+
+/// A reflection probe captured by an earlier stage of the render pass
+pub struct ProbeRef<'a> {
+    pub pos: [f32; 3],
+    /// Distance from `pos` at which the probe stops affecting objects
+    pub radius: f32,
+    /// Mipmapped capture of the scene around the probe
+    pub env_map: &'a glium::texture::Cubemap,
+}
+
+impl<'a> ProbeRef<'a> {
+    /// Gets the influence of this probe on an object at `pos`. Falls off
+    /// linearly from `1` at the center of the probe to `0` at its radius
+    pub fn weight(&self, pos: [f32; 3]) -> f32 {
+        let dist = self
+            .pos
+            .iter()
+            .zip(pos.iter())
+            .map(|(a, b)| (a - b) * (a - b))
+            .sum::<f32>()
+            .sqrt();
+        (1. - dist / self.radius).max(0.)
+    }
+}
+
+/// Data for drawing the directions the player was hit from
+pub struct HitIndicatorData {
+    /// Each column is two arcs around the center of the screen, as
+    /// `angle, intensity` pairs. Angles are counterclockwise from the right
+    /// of the screen, and arcs with an intensity of `0` aren't drawn
+    pub arcs: [[f32; 4]; 4],
+    /// Strength of the red flash around the edges of the screen
+    pub vignette: f32,
+    /// Width of the screen divided by its height
+    pub aspect: f32,
 }
 
 pub struct MinimapData<'a> {
@@ -394,14 +948,25 @@ pub enum UniformInfo<'a> {
     SepConv(SepConvData<'a>),
     ExtractBright(ExtractBrightData<'a>),
     PrefilterHdrEnv(PrefilterHdrEnvData<'a>),
+    ProceduralSky(ProceduralSkyData<'a>),
     GenLut,
     Laser,
     TriangleCollisions,
     LightCull(LightCullData<'a>),
+    LightCluster(LightClusters),
+    ParticleSim(ParticleSimData),
     /// Arg - model matrix
     CollisionDebug([[f32; 4]; 4]),
-    /// Args - billboard texture, spherical billboard density
-    Billboard(&'a glium::texture::SrgbTexture2d, f32),
+    /// Args - billboard texture, spherical billboard density, blend mode
+    Billboard(&'a glium::texture::SrgbTexture2d, f32, BlendMode),
+    /// Args - billboard texture, spherical billboard density, blend mode,
+    /// particle buffer
+    GpuBillboard(
+        &'a glium::texture::SrgbTexture2d,
+        f32,
+        BlendMode,
+        &'a ssbo::Ssbo<super::instancing::GpuParticle>,
+    ),
     Cloud(CloudData<'a>),
     Line,
     /// Args - SDF texture, `[tex_width, tex_height]`
@@ -409,6 +974,16 @@ pub enum UniformInfo<'a> {
     Minimap(MinimapData<'a>),
     /// Args - Icon texture, model matrix
     Icon(&'a glium::texture::SrgbTexture2d, [[f32; 4]; 4]),
+    /// Args - Decal texture, decal color
+    Decal(&'a glium::texture::SrgbTexture2d, [f32; 4]),
+    Trail(TrailData),
+    /// Args - the images of the impostors
+    Impostor(&'a glium::texture::Texture2dArray),
+    Atmosphere(AtmosphereData),
+    /// Args - transformation from radar space to clip space
+    Radar([[f32; 4]; 4]),
+    HitIndicator(HitIndicatorData),
+    Portal(PortalData),
 }
 
 impl<'a> std::fmt::Debug for UniformInfo<'a> {
@@ -422,17 +997,28 @@ impl<'a> std::fmt::Debug for UniformInfo<'a> {
             SepConv(_) => "Sep Conv",
             ExtractBright(_) => "Extract Bright",
             PrefilterHdrEnv(_) => "Prefilter HDR",
+            ProceduralSky(_) => "Procedural sky",
             GenLut => "Gen BRDF Lut",
             Laser => "Laser",
             TriangleCollisions => "Compute triangle",
             LightCull(_) => "Compute light cull",
+            LightCluster(_) => "Compute light cluster",
+            ParticleSim(_) => "Compute particle sim",
             CollisionDebug(_) => "Collision debug",
-            Billboard(_, _) => "Billboard",
+            Billboard(_, _, _) => "Billboard",
+            GpuBillboard(_, _, _, _) => "GPU Billboard",
             Cloud(_) => "Cloud",
             Line => "Line",
             Text(_, _) => "Text",
             Minimap(_) => "Minimap",
             Icon(_, _) => "Icon",
+            Decal(_, _) => "Decal",
+            Trail(_) => "Trail",
+            Impostor(_) => "Impostor",
+            Atmosphere(_) => "Atmosphere",
+            Radar(_) => "Radar",
+            HitIndicator(_) => "HitIndicator",
+            Portal(_) => "Portal",
         };
         f.write_str(name)
     }
@@ -506,10 +1092,28 @@ impl<'a> UniformInfo<'a> {
             (Skybox(_), LayeredVisual | Transparent(_)) => {
                 ShaderType::ParallelSky
             }
-            (Billboard(_, _), Visual) => ShaderType::Billboard,
+            (Billboard(_, _, BlendMode::Alpha), Visual) => {
+                ShaderType::Billboard
+            }
+            (Billboard(_, _, BlendMode::Additive), Visual) => {
+                ShaderType::AdditiveBillboard
+            }
+            (GpuBillboard(_, _, BlendMode::Alpha, _), Visual) => {
+                ShaderType::GpuBillboard
+            }
+            (GpuBillboard(_, _, BlendMode::Additive, _), Visual) => {
+                ShaderType::AdditiveGpuBillboard
+            }
             (Text(_, _), Visual) => ShaderType::Text,
             (Minimap(_), Visual) => ShaderType::Minimap,
             (Icon(_, _), Visual) => ShaderType::Icon,
+            (Decal(_, _), Visual) => ShaderType::Decal,
+            (Trail(_), Visual) => ShaderType::Trail,
+            (Impostor(_), Visual) => ShaderType::Impostor,
+            (Atmosphere(_), Visual) => ShaderType::Atmosphere,
+            (Radar(_), Visual) => ShaderType::Radar,
+            (HitIndicator(_), Visual) => ShaderType::HitIndicator,
+            (Portal(_), Visual) => ShaderType::Portal,
 
             // tex processors
             (Composite(_), Visual) => ShaderType::CompositeShader,
@@ -519,11 +1123,16 @@ impl<'a> UniformInfo<'a> {
             (PrefilterHdrEnv(_), LayeredVisual) => {
                 ShaderType::ParallelPrefilter
             }
+            (ProceduralSky(_), LayeredVisual) => {
+                ShaderType::ParallelProceduralSky
+            }
             (GenLut, Visual) => ShaderType::GenLutShader,
 
             // compute shaders
             (LightCull(_), Visual) => ShaderType::CullLightsCompute,
+            (LightCluster(_), Visual) => ShaderType::ClusterLightsCompute,
             (TriangleCollisions, Visual) => ShaderType::TriIntersectionCompute,
+            (ParticleSim(_), Visual) => ShaderType::ParticleSimCompute,
             (typ, pass) => panic!(
                 "Unknown shader-pass combination ({:?}, {:?})",
                 typ, pass
@@ -543,6 +1152,10 @@ pub enum UniformType<'a> {
         UniformsStruct<'static, UniformsStorage<'a,
         glium::uniforms::Sampler<'a, glium::texture::Cubemap>, glium::uniforms::UniformsStorage<'a, f32,
         glium::uniforms::UniformsStorage<'a, f32, glium::uniforms::EmptyUniforms>>>,
+        UniformsStruct<'static, UniformsStorage<'a, f32, UniformsStorage<'a, f32,
+        UniformsStorage<'a, Sampler<'a, glium::texture::Cubemap>,
+        UniformsStorage<'a, Sampler<'a, glium::texture::Cubemap>, EmptyUniforms>>>>,
+        UniformsStorage<'a, [f32; 2], UniformsStorage<'a, [i32; 4],
         glium::uniforms::UniformsStorage<'a, [f32; 3], glium::uniforms::UniformsStorage<'a, [[f32; 4]; 4],
         glium::uniforms::UniformsStorage<'a, &'a glium::uniforms::UniformBuffer<CascadeUniform>,
         glium::uniforms::UniformsStorage<'a, i32, glium::uniforms::UniformsStorage<'a, bool,
@@ -556,7 +1169,7 @@ pub enum UniformType<'a> {
         glium::uniforms::UniformsStorage<'a, glium::uniforms::Sampler<'a, glium::Texture2d>,
         glium::uniforms::UniformsStorage<'a, glium::uniforms::Sampler<'a, glium::texture::SrgbTexture2d>,
         glium::uniforms::UniformsStorage<'a, [[f32; 4]; 4], glium::uniforms::UniformsStorage<'a, [[f32; 4]; 4],
-        UniformsStorage<'a, f32, UniformsStorage< 'a, f32, UniformsStorage<'a, f32, glium::uniforms::EmptyUniforms>>>>>>>>>>>>>>>>>>>>>>>>),
+        UniformsStorage<'a, f32, UniformsStorage< 'a, f32, UniformsStorage<'a, f32, glium::uniforms::EmptyUniforms>>>>>>>>>>>>>>>>>>>>>>>>>>>),
     EqRect(UniformsStorage<'a, Sampler<'a, glium::texture::Texture2d>, UniformsStorage<'a, [[f32; 4]; 4], UniformsStorage<'a, [[f32; 4]; 4], EmptyUniforms>>>),
     ExtractBright(UniformsStorage<'a, Sampler<'a, glium::texture::Texture2d>, UniformsStorage<'a, [[f32; 4]; 4], EmptyUniforms>>),
     Composite(UniformsArray<'static, Sampler<'a, glium::texture::Texture2d>, UniformsArray<'static, [[f32; 3]; 3], UniformsStorage<'a, (&'a str, glium::program::ShaderStage), UniformsStorage<'a, [[f32; 4]; 4],
@@ -564,20 +1177,38 @@ pub enum UniformType<'a> {
     SepConv(UniformsStorage<'a, bool, UniformsStorage<'a, Sampler<'a, glium::texture::Texture2d>, UniformsStorage<'a, [[f32; 4]; 4], EmptyUniforms>>>),
     PrefilterHdrEnv(UniformsStorage<'a, f32, UniformsStorage<'a, Sampler<'a, glium::texture::Cubemap>, UniformsStorage<'a, [[f32; 4]; 4],
         UniformsStorage<'a, [[f32; 4]; 4], EmptyUniforms>>>>),
+    ProceduralSky(UniformsStorage<'a, f32, UniformsStorage<'a, f32, UniformsStorage<'a, f32, UniformsStorage<'a, u32,
+        UniformsStorage<'a, Sampler<'a, glium::texture::Texture3d>, UniformsStorage<'a, [[f32; 4]; 4],
+        UniformsStorage<'a, [[f32; 4]; 4], EmptyUniforms>>>>>>>),
     BrdfLut(UniformsStorage<'a, [[f32; 4]; 4], EmptyUniforms>),
     Depth(UniformsStorage<'a, f32, UniformsStorage<'a, [[f32; 4]; 4], UniformsStorage<'a, [[f32; 4]; 4], EmptyUniforms>>>),
     Color(UniformsStorage<'a, [f32; 4], UniformsStorage<'a, [[f32; 4]; 4], UniformsStorage<'a, [[f32; 4]; 4], EmptyUniforms>>>),
     Billboard(UniformsStorage<'a, f32, UniformsStorage<'a, Sampler<'a, glium::texture::DepthTexture2d>, UniformsStorage<'a, Sampler<'a, glium::texture::SrgbTexture2d>,
         UniformsStorage<'a, [[f32; 4]; 4], UniformsStorage<'a, [[f32; 4]; 4], EmptyUniforms>>>>>),
-    Cloud(UniformsStorage<'a, Sampler<'a, glium::texture::DepthTexture2d>, UniformsStorage<'a, [[f32; 4]; 4],
+    Cloud(UniformsStorage<'a, [[f32; 3]; 3], UniformsStorage<'a, Sampler<'a, glium::texture::DepthTexture2d>, UniformsStorage<'a, [[f32; 4]; 4],
         UniformsStorage<'a, [[f32; 4]; 4], UniformsStorage<'a, i32,
         UniformsStorage<'a, Sampler<'a, glium::texture::Texture3d>, UniformsStorage<'a, [f32; 3],
-        UniformsStorage<'a, [f32; 3], UniformsStorage<'a, [[f32; 4]; 4], UniformsStorage<'a, [[f32; 4]; 4], EmptyUniforms>>>>>>>>>),
+        UniformsStorage<'a, [f32; 3], UniformsStorage<'a, [[f32; 4]; 4], UniformsStorage<'a, [[f32; 4]; 4], EmptyUniforms>>>>>>>>>>),
     Line(UniformsStorage<'a, [[f32; 4]; 4], EmptyUniforms>),
+    Radar(UniformsStorage<'a, [[f32; 4]; 4], EmptyUniforms>),
+    HitIndicator(UniformsStorage<'a, f32, UniformsStorage<'a, f32, UniformsStorage<'a, [[f32; 4]; 4], EmptyUniforms>>>),
     Text(UniformsStorage<'a, Sampler<'a, glium::texture::Texture2d>, UniformsStorage<'a, [f32; 2], UniformsStorage<'a, [[f32; 4]; 4],
         EmptyUniforms>>>),
     Minimap(UniformsArray<'static, Sampler<'a, glium::texture::Texture2d>, EmptyUniforms>),
     Icon(UniformsStorage<'a, Sampler<'a, glium::texture::SrgbTexture2d>, UniformsStorage<'a, [[f32; 4]; 4], EmptyUniforms>>), 
+    Decal(UniformsStorage<'a, [f32; 4], UniformsStorage<'a, Sampler<'a, glium::texture::DepthTexture2d>,
+        UniformsStorage<'a, Sampler<'a, glium::texture::SrgbTexture2d>, UniformsStorage<'a, [[f32; 4]; 4],
+        UniformsStorage<'a, [[f32; 4]; 4], EmptyUniforms>>>>>),
+    Trail(UniformsStorage<'a, f32, UniformsStorage<'a, f32, UniformsStorage<'a, f32, UniformsStorage<'a, f32,
+        UniformsStorage<'a, [f32; 4], UniformsStorage<'a, [f32; 3], UniformsStorage<'a, [[f32; 4]; 4], EmptyUniforms>>>>>>>),
+    Impostor(UniformsStorage<'a, Sampler<'a, glium::texture::Texture2dArray>, UniformsStorage<'a, [[f32; 4]; 4],
+        UniformsStorage<'a, [[f32; 4]; 4], EmptyUniforms>>>),
+    Atmosphere(UniformsStorage<'a, f32, UniformsStorage<'a, f32, UniformsStorage<'a, f32, UniformsStorage<'a, f32,
+        UniformsStorage<'a, Sampler<'a, glium::texture::DepthTexture2d>, UniformsStorage<'a, [f32; 3],
+        UniformsStorage<'a, [f32; 3], UniformsStorage<'a, [[f32; 4]; 4], UniformsStorage<'a, [[f32; 4]; 4],
+        UniformsStorage<'a, [[f32; 4]; 4], EmptyUniforms>>>>>>>>>>),
+    Portal(UniformsStorage<'a, f32, UniformsStorage<'a, [f32; 4], UniformsStorage<'a, [[f32; 4]; 4],
+        UniformsStorage<'a, [[f32; 4]; 4], EmptyUniforms>>>>),
 }
 /// Samples a texture with `LinearMipmapLinear` minification, repeat wrapping, and linear magnification
 macro_rules! sample_mip_repeat {
@@ -768,6 +1399,24 @@ impl ShaderManager {
             "shaders/billboard.fs"
         )
         .unwrap();
+        let gpu_billboard = load_shader_source!(
+            facade,
+            "shaders/gpuBillboard.vs",
+            "shaders/billboard.fs"
+        )
+        .unwrap();
+        let additive_billboard = load_shader_source!(
+            facade,
+            "shaders/billboard.vs",
+            "shaders/billboard.fs"
+        )
+        .unwrap();
+        let additive_gpu_billboard = load_shader_source!(
+            facade,
+            "shaders/gpuBillboard.vs",
+            "shaders/billboard.fs"
+        )
+        .unwrap();
         let parallel_pbr = load_shader_source!(
             facade,
             "shaders/pbr.vs",
@@ -817,9 +1466,22 @@ impl ShaderManager {
             "shaders/parallelSky.gs"
         )
         .unwrap();
+        let parallel_procedural_sky = load_shader_source!(
+            facade,
+            "shaders/sky.vs",
+            "shaders/proceduralSky.fs",
+            "shaders/parallelSky.gs"
+        )
+        .unwrap();
         let cloud_shader =
             load_shader_source!(facade, "shaders/cloud.vs", "shaders/cloud.fs")
                 .unwrap();
+        let atmosphere_shader = load_shader_source!(
+            facade,
+            "shaders/atmosphere.vs",
+            "shaders/atmosphere.fs"
+        )
+        .unwrap();
         let line_shader =
             load_shader_source!(facade, "shaders/line.vs", "shaders/line.fs")
                 .unwrap();
@@ -832,19 +1494,56 @@ impl ShaderManager {
             "shaders/minimap.fs"
         )
         .unwrap();
+        let radar_shader =
+            load_shader_source!(facade, "shaders/radar.vs", "shaders/line.fs")
+                .unwrap();
         let icon_shader =
             load_shader_source!(facade, "shaders/icon.vs", "shaders/icon.fs")
                 .unwrap();
+        let hit_indicator_shader = load_shader_source!(
+            facade,
+            "shaders/hitIndicator.vs",
+            "shaders/hitIndicator.fs"
+        )
+        .unwrap();
+        let decal_shader =
+            load_shader_source!(facade, "shaders/decal.vs", "shaders/decal.fs")
+                .unwrap();
+        let trail_shader =
+            load_shader_source!(facade, "shaders/trail.vs", "shaders/trail.fs")
+                .unwrap();
+        let portal_shader = load_shader_source!(
+            facade,
+            "shaders/portal.vs",
+            "shaders/portal.fs"
+        )
+        .unwrap();
+        let impostor_shader = load_shader_source!(
+            facade,
+            "shaders/billboard.vs",
+            "shaders/impostor.fs"
+        )
+        .unwrap();
         let light_cull = glium::program::ComputeShader::from_source(
             facade,
             include_str!("shaders/lightCull.comp"),
         )
         .unwrap();
+        let light_cluster = glium::program::ComputeShader::from_source(
+            facade,
+            include_str!("shaders/lightCluster.comp"),
+        )
+        .unwrap();
         let triangle_test = glium::program::ComputeShader::from_source(
             facade,
             include_str!("shaders/triTriCollision.comp"),
         )
         .unwrap();
+        let particle_sim = glium::program::ComputeShader::from_source(
+            facade,
+            include_str!("shaders/particleSim.comp"),
+        )
+        .unwrap();
         let mut shaders = HashMap::<ShaderType, glium::Program>::new();
         shaders.insert(ShaderType::Laser, laser_shader);
         shaders.insert(ShaderType::Skybox, skybox_shader);
@@ -862,6 +1561,10 @@ impl ShaderManager {
         shaders.insert(ShaderType::DepthAnim, depth_anim);
         shaders.insert(ShaderType::CollisionDebug, debug);
         shaders.insert(ShaderType::Billboard, billboard);
+        shaders.insert(ShaderType::GpuBillboard, gpu_billboard);
+        shaders.insert(ShaderType::AdditiveBillboard, additive_billboard);
+        shaders
+            .insert(ShaderType::AdditiveGpuBillboard, additive_gpu_billboard);
         shaders.insert(ShaderType::ParallelPbr, parallel_pbr);
         shaders.insert(ShaderType::ParallelLaser, parallel_laser);
         shaders.insert(ShaderType::ParallelInstancePbr, parallel_instance_pbr);
@@ -869,16 +1572,27 @@ impl ShaderManager {
         shaders.insert(ShaderType::ParallelEqRect, parallel_eq_rect);
         shaders.insert(ShaderType::ParallelAnimPbr, parallel_anim_pbr);
         shaders.insert(ShaderType::ParallelPrefilter, parallel_prefilter);
+        shaders
+            .insert(ShaderType::ParallelProceduralSky, parallel_procedural_sky);
         shaders.insert(ShaderType::Cloud, cloud_shader);
         shaders.insert(ShaderType::Line, line_shader);
         shaders.insert(ShaderType::Text, text_shader);
         shaders.insert(ShaderType::Minimap, minimap_shader);
+        shaders.insert(ShaderType::Radar, radar_shader);
+        shaders.insert(ShaderType::HitIndicator, hit_indicator_shader);
         shaders.insert(ShaderType::Icon, icon_shader);
+        shaders.insert(ShaderType::Decal, decal_shader);
+        shaders.insert(ShaderType::Trail, trail_shader);
+        shaders.insert(ShaderType::Portal, portal_shader);
+        shaders.insert(ShaderType::Impostor, impostor_shader);
+        shaders.insert(ShaderType::Atmosphere, atmosphere_shader);
         let mut compute_shaders =
             HashMap::<ShaderType, glium::program::ComputeShader>::new();
         compute_shaders.insert(ShaderType::CullLightsCompute, light_cull);
+        compute_shaders.insert(ShaderType::ClusterLightsCompute, light_cluster);
         compute_shaders
             .insert(ShaderType::TriIntersectionCompute, triangle_test);
+        compute_shaders.insert(ShaderType::ParticleSimCompute, particle_sim);
         Self {
             shaders,
             compute_shaders,
@@ -888,6 +1602,15 @@ impl ShaderManager {
             empty_cube: glium::texture::Cubemap::empty(facade, 0).unwrap(),
             empty_depth: glium::texture::DepthTexture2d::empty(facade, 0, 0)
                 .unwrap(),
+            white_srgb: textures::solid_texture_srgb(
+                [255, 255, 255, 255],
+                facade,
+            ),
+            white_2d: textures::solid_texture_2d([255, 255, 255, 255], facade),
+            flat_normal: textures::solid_texture_2d(
+                [128, 128, 255, 255],
+                facade,
+            ),
         }
     }
 
@@ -949,7 +1672,7 @@ impl ShaderManager {
                 let sd = scene_data.unwrap();
                 sd.lights.unwrap().bind(0);
                 let cache = cache.unwrap();
-                let maps = cache.cascade_maps.as_ref().unwrap();
+                let cascades = cache.get(CASCADES).unwrap();
                 // NOTE: requires the compute shader's SSBO for visible indices is still bound
                 if typ == ShaderType::PbrAnim {
                     bone_mats.unwrap().bind(4);
@@ -957,27 +1680,39 @@ impl ShaderManager {
                 let default = TransparencyData::default();
                 let trans_data = if pass_tp == Visual { trans_data.unwrap_or(&default) }
                 else { &default };
+                let [probe_a, probe_b] = cache.nearest_probes([model[3][0], model[3][1], model[3][2]]);
                 UniformType::Pbr(UniformsArray { name: "cascadeDepthMaps",
-                vals: maps.iter().map(|x|
+                vals: cascades.depth_maps.iter().map(|x|
                     sample_nearest_border!(*x)).collect::<Vec<Sampler<'b, glium::texture::DepthTexture2d>>>(),
                 rest: UniformsArray { name: "cascadeTransMaps",
-                vals: cache.trans_cascade_maps.as_ref().map_or_else(
-                    || vec![sample_nearest_border!(self.empty_depth)],
-                    |v| {
-                        v.iter().map(|(d, _)| sample_nearest_border!(*d))
-                        .collect::<Vec<Sampler<'b, glium::texture::DepthTexture2d>>>()}),
+                vals: if cascades.trans_maps.is_empty() {
+                    vec![sample_nearest_border!(self.empty_depth)]
+                } else {
+                    cascades.trans_maps.iter().map(|(d, _)| sample_nearest_border!(*d))
+                    .collect::<Vec<Sampler<'b, glium::texture::DepthTexture2d>>>()
+                },
                 rest: UniformsArray { name: "cascadeTransFacs",
-                vals: cache.trans_cascade_maps.as_ref().map_or_else(
-                    || vec![sample_nearest_border!(self.empty_2d)],
-                    |v| {
-                    v.iter().map(|(_, c)| sample_nearest_border!(*c))
-                    .collect::<Vec<Sampler<'b, glium::texture::Texture2d>>>()}),
+                vals: if cascades.trans_maps.is_empty() {
+                    vec![sample_nearest_border!(self.empty_2d)]
+                } else {
+                    cascades.trans_maps.iter().map(|(_, c)| sample_nearest_border!(*c))
+                    .collect::<Vec<Sampler<'b, glium::texture::Texture2d>>>()
+                },
                 rest: UniformsStruct { name: "transparencyData", 
                 data: glium::uniform! {
                     trans_fac: *trans_data.trans_fac.borrow(),
                     refraction_idx: trans_data.refraction_idx,
                     tex:
-                        sample_linear_clamp!(cache.obj_cubemaps.get(&trans_data.object_id).unwrap_or(&&self.empty_cube)),
+                        sample_linear_clamp!(cache.get(OBJECT_CUBEMAPS).unwrap_or_default().into_iter()
+                            .find(|(id, _)| *id == trans_data.object_id)
+                            .map_or(&self.empty_cube, |(_, tex)| tex)),
+                },
+                rest: UniformsStruct { name: "probeData",
+                data: glium::uniform! {
+                    env_a: sample_mip_clamp!(probe_a.map_or(&self.empty_cube, |(tex, _)| tex)),
+                    env_b: sample_mip_clamp!(probe_b.map_or(&self.empty_cube, |(tex, _)| tex)),
+                    weight_a: probe_a.map_or(0., |(_, weight)| weight),
+                    weight_b: probe_b.map_or(0., |(_, weight)| weight),
                 },
                 rest: glium::uniform! {
                     roughness_fac: *roughness_fac,
@@ -985,9 +1720,9 @@ impl ShaderManager {
                     emission_strength: *emission_strength,
                     viewproj: sd.viewer.viewproj,
                     model: *model,
-                    albedo_map: sample_mip_repeat!(diffuse_tex),
-                    roughness_map: sample_mip_repeat!(roughness_map.unwrap_or(&self.empty_2d)),
-                    normal_map: sample_mip_repeat!(normal_map.unwrap()),
+                    albedo_map: sample_mip_repeat!(diffuse_tex.unwrap_or(&self.white_srgb)),
+                    roughness_map: sample_mip_repeat!(roughness_map.unwrap_or(&self.white_2d)),
+                    normal_map: sample_mip_repeat!(normal_map.unwrap_or(&self.flat_normal)),
                     metallic_map: sample_mip_repeat!(metallic_map.unwrap_or(&self.empty_2d)),
                     cam_pos: sd.viewer.cam_pos,
                     emission_map: sample_mip_repeat!(emission_map.unwrap_or(&self.empty_srgb)),
@@ -1000,7 +1735,11 @@ impl ShaderManager {
                     CascadeUniform: cache.cascade_ubo.as_ref().unwrap(),     
                     view: sd.viewer.view,
                     dir_light_dir: sd.light_pos.unwrap(),
-            }}
+                    cluster_grid: cache.light_clusters.map_or([0; 4], |c| {
+                        [c.count[0] as i32, c.count[1] as i32, c.count[2] as i32, c.tile_size as i32]
+                    }),
+                    cluster_depth: cache.light_clusters.map_or([0.; 2], |c| c.depth_range),
+            }}}
                     }
                 }})
             },
@@ -1040,6 +1779,20 @@ impl ShaderManager {
                 env_map: sample_linear_clamp!(env_map),
                 roughness: *roughness,
             }),
+            (ProceduralSky(ProceduralSkyData {
+                nebula_noise, nebula_layers, seed, star_density, star_brightness, texel_angle }), _)
+            => {
+                nebula_layers.bind(9);
+                UniformType::ProceduralSky(glium::uniform! {
+                    view: scene_data.unwrap().viewer.view,
+                    proj: scene_data.unwrap().viewer.proj,
+                    nebula_noise: sample_linear_clamp!(nebula_noise),
+                    seed: *seed,
+                    star_density: *star_density,
+                    star_brightness: *star_brightness,
+                    texel_angle: *texel_angle,
+                })
+            }
             (GenLut, _) => UniformType::BrdfLut(glium::uniform! {
                 model: cgmath::Matrix4::from_scale(1f32).into(),
             }),
@@ -1056,14 +1809,24 @@ impl ShaderManager {
                 model: *model,
                 color: [1.0, 0.0, 0.0, 1.0],
             }),
-            (Billboard(tex, density), Visual) => UniformType::Billboard(glium::uniform! {
+            (Billboard(tex, density, _), Visual) => UniformType::Billboard(glium::uniform! {
                 view: scene_data.unwrap().viewer.view,
                 proj: scene_data.unwrap().viewer.proj,
                 tex: sample_mip_repeat!(tex),
-                cam_depth: sample_linear_clamp!(cache.unwrap().cam_depth.unwrap()),
+                cam_depth: sample_linear_clamp!(cache.unwrap().get(CAM_DEPTH).unwrap()),
                 particle_density: *density,
             }),
-            (Cloud(CloudData{volume, model}), Visual) => UniformType::Cloud(glium::uniform! {
+            (GpuBillboard(tex, density, _, particles), Visual) => {
+                particles.bind(8);
+                UniformType::Billboard(glium::uniform! {
+                    view: scene_data.unwrap().viewer.view,
+                    proj: scene_data.unwrap().viewer.proj,
+                    tex: sample_mip_repeat!(tex),
+                    cam_depth: sample_linear_clamp!(cache.unwrap().get(CAM_DEPTH).unwrap()),
+                    particle_density: *density,
+                })
+            },
+            (Cloud(CloudData{volume, model, anim_rot}), Visual) => UniformType::Cloud(glium::uniform! {
                 viewproj: scene_data.unwrap().viewer.viewproj,
                 model: *model,
                 light_dir: scene_data.unwrap().light_pos.unwrap_or([1f32, 0., 0.]),
@@ -1072,7 +1835,8 @@ impl ShaderManager {
                 tile_num_x: cache.as_ref().map(|x| x.tiles_x).unwrap().unwrap() as i32,
                 view: scene_data.unwrap().viewer.view,
                 proj: scene_data.unwrap().viewer.proj,
-                cam_depth: sample_linear_clamp!(cache.unwrap().cam_depth.unwrap()),
+                cam_depth: sample_linear_clamp!(cache.unwrap().get(CAM_DEPTH).unwrap()),
+                anim_rot: *anim_rot,
             }),
             (Line, Visual | Transparent(_)) => UniformType::Line(glium::uniform! {
                 viewproj: scene_data.unwrap().viewer.viewproj,
@@ -1082,6 +1846,15 @@ impl ShaderManager {
                 tex_width_height: [tex_width_height[0] as f32, tex_width_height[1] as f32],
                 tex: sample_mip_clamp!(tex),
             }),
+            (Radar(transform), Visual) => UniformType::Radar(glium::uniform! {
+                transform: *transform,
+            }),
+            (HitIndicator(HitIndicatorData { arcs, vignette, aspect }), Visual) =>
+                UniformType::HitIndicator(glium::uniform! {
+                    arcs: *arcs,
+                    vignette: *vignette,
+                    aspect: *aspect,
+                }),
             (Minimap(MinimapData{ textures }), Visual) => UniformType::Minimap(UniformsArray {
                 name: "textures",
                 vals: textures.iter().map(|t| sample_linear_border!(t)).collect(),
@@ -1091,6 +1864,52 @@ impl ShaderManager {
                 model: *model,
                 tex: sample_linear_clamp!(texture),
             }),
+            (Decal(texture, color), Visual) => {
+                let viewproj = Matrix4::from(scene_data.unwrap().viewer.viewproj);
+                UniformType::Decal(glium::uniform! {
+                    viewproj: scene_data.unwrap().viewer.viewproj,
+                    inv_viewproj: Into::<[[f32; 4]; 4]>::into(viewproj.invert().unwrap()),
+                    decal_tex: sample_mip_clamp!(texture),
+                    cam_depth: sample_linear_clamp!(cache.unwrap().get(CAM_DEPTH).unwrap()),
+                    decal_color: *color,
+                })
+            },
+            (Trail(TrailData { color, emission, width, width_falloff, alpha_falloff }), Visual) =>
+                UniformType::Trail(glium::uniform! {
+                    viewproj: scene_data.unwrap().viewer.viewproj,
+                    cam_pos: scene_data.unwrap().viewer.cam_pos,
+                    color: *color,
+                    emission: *emission,
+                    width: *width,
+                    width_falloff: *width_falloff,
+                    alpha_falloff: *alpha_falloff,
+                }),
+            (Portal(PortalData { model, color, time }), Visual) =>
+                UniformType::Portal(glium::uniform! {
+                    viewproj: scene_data.unwrap().viewer.viewproj,
+                    model: *model,
+                    color: *color,
+                    time: *time,
+                }),
+            (Impostor(images), Visual) => UniformType::Impostor(glium::uniform! {
+                view: scene_data.unwrap().viewer.view,
+                proj: scene_data.unwrap().viewer.proj,
+                impostors: sample_linear_clamp!(images),
+            }),
+            (Atmosphere(AtmosphereData {
+                model, planet_radius, atmosphere_radius, density, sun_intensity }), Visual)
+            => UniformType::Atmosphere(glium::uniform! {
+                viewproj: scene_data.unwrap().viewer.viewproj,
+                model: *model,
+                proj: scene_data.unwrap().viewer.proj,
+                cam_pos: scene_data.unwrap().viewer.cam_pos,
+                light_dir: scene_data.unwrap().light_pos.unwrap_or([1f32, 0., 0.]),
+                cam_depth: sample_linear_clamp!(cache.unwrap().get(CAM_DEPTH).unwrap()),
+                planet_radius: *planet_radius,
+                atmosphere_radius: *atmosphere_radius,
+                density: *density,
+                sun_intensity: *sun_intensity,
+            }),
             (data, pass) =>
                 panic!("Invalid shader/shader data combination with shader (Args: `{:?}` '{:?}') during pass '{:?}'", data, typ, pass),
         };
@@ -1127,6 +1946,29 @@ impl ShaderManager {
                 scene_data.lights.unwrap().bind(0);
                 compute.execute(uniform, x, y, z);
             }
+            UniformInfo::LightCluster(clusters) => {
+                let scene_data = scene_data.unwrap();
+                let inv_proj =
+                    Matrix4::from(scene_data.viewer.proj).invert().unwrap();
+                let [x_count, y_count, z_count] = clusters.count;
+                let uniform = glium::uniform! {
+                    view: scene_data.viewer.view,
+                    inv_proj: Into::<[[f32; 4]; 4]>::into(inv_proj),
+                    cluster_count: [x_count as i32, y_count as i32, z_count as i32],
+                    tile_size: clusters.tile_size as i32,
+                    screen_size: [
+                        clusters.screen_size[0] as i32,
+                        clusters.screen_size[1] as i32,
+                    ],
+                    depth_range: clusters.depth_range,
+                };
+                let compute = self
+                    .compute_shaders
+                    .get(&ShaderType::ClusterLightsCompute)
+                    .unwrap();
+                scene_data.lights.unwrap().bind(0);
+                compute.execute(uniform, x, y, z);
+            }
             UniformInfo::TriangleCollisions => {
                 let compute = self
                     .compute_shaders
@@ -1134,7 +1976,47 @@ impl ShaderManager {
                     .unwrap();
                 compute.execute(EmptyUniforms, x, y, z);
             }
+            UniformInfo::ParticleSim(data) => {
+                let uniform = glium::uniform! {
+                    dt: data.dt,
+                    seed: data.seed,
+                    emitting: data.emitting,
+                    particle_count: data.particle_count,
+                    origin: data.origin,
+                    dir: data.dir,
+                    spread: data.spread,
+                    speed: data.speed,
+                    lifetime: data.lifetime,
+                    scale: data.scale,
+                    start_color: data.start_color,
+                    end_color: data.end_color,
+                    accel: data.accel,
+                };
+                let compute = self
+                    .compute_shaders
+                    .get(&ShaderType::ParticleSimCompute)
+                    .unwrap();
+                compute.execute(uniform, x, y, z);
+            }
             _ => panic!("Unknown compute shader args"),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn light_clusters_cover_the_frustum() {
+        let clusters = LightClusters::new(1920, 1080, 64, 24);
+        assert_eq!(clusters.count, [30, 17, 24]);
+        assert_eq!(clusters.total(), 30 * 17 * 24);
+        let proj: [[f32; 4]; 4] =
+            perspective(Deg(60f32), 16. / 9., 0.1, 1000.).into();
+        let [near, far] = clusters.with_proj(&proj).depth_range;
+        assert_approx_eq!(near, 0.1, 1e-4);
+        assert_approx_eq!(far, 1000., 5.);
+    }
+}
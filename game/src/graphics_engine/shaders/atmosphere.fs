@@ -0,0 +1,151 @@
+#version 430 core
+in vec3 world_pos;
+
+uniform mat4 model;
+uniform mat4 proj;
+uniform vec3 cam_pos;
+// direction towards the sun
+uniform vec3 light_dir;
+uniform sampler2D cam_depth;
+// radii of the surface of the planet and the top of its atmosphere, in
+// model space
+uniform float planet_radius;
+uniform float atmosphere_radius;
+// scales the scattering and absorption of the atmosphere
+uniform float density;
+uniform float sun_intensity;
+
+out vec4 frag_color;
+
+const float PI = 3.14159265358979323846264338327950288;
+#define VIEW_STEPS 16
+#define LIGHT_STEPS 8
+
+// Scattering coefficients per depth of atmosphere, so the look of an
+// atmosphere doesn't depend on its size. Based on earth's, whose
+// atmosphere is about 100km deep
+const vec3 RAYLEIGH_SCATTERING = vec3(0.58, 1.35, 3.31);
+const float MIE_SCATTERING = 2.1;
+const float MIE_EXTINCTION = MIE_SCATTERING * 1.11;
+// heights over which the density of each kind of particle falls by a
+// factor of e, as fractions of the depth of the atmosphere
+const float RAYLEIGH_HEIGHT = 0.08;
+const float MIE_HEIGHT = 0.012;
+// anisotropy of mie scattering, which mostly scatters forwards
+const float MIE_G = 0.76;
+
+/// Gets the distances along the ray from `origin` in the direction `dir`
+/// to where it enters and exits the sphere at `center` of radius `radius`.
+/// The entry is after the exit if the ray misses
+vec2 raySphere(vec3 origin, vec3 dir, vec3 center, float radius) {
+    vec3 oc = origin - center;
+    float b = dot(oc, dir);
+    float c = dot(oc, oc) - radius * radius;
+    float disc = b * b - c;
+    if (disc < 0.0) {
+        return vec2(1.0, -1.0);
+    }
+    float s = sqrt(disc);
+    return vec2(-b - s, -b + s);
+}
+
+/// Gets the distance from the camera to the opaque surface seen through
+/// the fragment, which the atmosphere in front of it is composited over
+float sceneDistance() {
+    vec2 uv = gl_FragCoord.xy / vec2(textureSize(cam_depth, 0));
+    float depth = texture(cam_depth, uv).r;
+    if (depth >= 1.0) {
+        // nothing was drawn, so the view ray passes through the atmosphere
+        return 3.402823466e+38;
+    }
+    vec4 view_pos = inverse(proj) * vec4(vec3(uv, depth) * 2.0 - 1.0, 1.0);
+    return length(view_pos.xyz / view_pos.w);
+}
+
+/// Gets the rayleigh and mie particle densities at `pt`
+///
+/// `thickness` - the depth of the atmosphere in world space
+vec2 densityAt(vec3 pt, vec3 center, float inner, float thickness) {
+    float height = max(length(pt - center) - inner, 0.0) / thickness;
+    return exp(-height / vec2(RAYLEIGH_HEIGHT, MIE_HEIGHT));
+}
+
+/// Gets the optical depth of the rayleigh and mie particles between `pt`
+/// and the sun in `optical_depth`. Returns `false` if the planet is in the way
+bool lightOpticalDepth(vec3 pt, vec3 sun, vec3 center, float inner,
+    float outer, out vec2 optical_depth)
+{
+    optical_depth = vec2(0.0);
+    if (raySphere(pt, sun, center, inner).x > 0.0) {
+        return false;
+    }
+    float step_size = raySphere(pt, sun, center, outer).y / float(LIGHT_STEPS);
+    for (int i = 0; i < LIGHT_STEPS; ++i) {
+        vec3 sample_pt = pt + sun * step_size * (float(i) + 0.5);
+        optical_depth += densityAt(sample_pt, center, inner, outer - inner)
+            * step_size;
+    }
+    return true;
+}
+
+float rayleighPhase(float cos_theta) {
+    return 3.0 / (16.0 * PI) * (1.0 + cos_theta * cos_theta);
+}
+
+/// Cornette-Shanks approximation of mie scattering
+float miePhase(float cos_theta) {
+    float g2 = MIE_G * MIE_G;
+    return 3.0 / (8.0 * PI) * ((1.0 - g2) * (1.0 + cos_theta * cos_theta))
+        / ((2.0 + g2) * pow(1.0 + g2 - 2.0 * MIE_G * cos_theta, 1.5));
+}
+
+/// Single scattering of the sun's light along the view ray through the
+/// atmosphere, up to the scene's depth
+void main() {
+    vec3 center = model[3].xyz;
+    float scale = length(model[0].xyz);
+    float inner = planet_radius * scale;
+    float outer = atmosphere_radius * scale;
+    float thickness = outer - inner;
+    vec3 dir = normalize(world_pos - cam_pos);
+
+    vec2 atmosphere = raySphere(cam_pos, dir, center, outer);
+    float start = max(atmosphere.x, 0.0);
+    float end = min(atmosphere.y, sceneDistance());
+    vec2 ground = raySphere(cam_pos, dir, center, inner);
+    if (ground.x > 0.0) {
+        end = min(end, ground.x);
+    }
+    if (end <= start) {
+        discard;
+    }
+
+    vec3 rayleigh_coeff = RAYLEIGH_SCATTERING * density / thickness;
+    float mie_coeff = MIE_SCATTERING * density / thickness;
+    float mie_extinction = MIE_EXTINCTION * density / thickness;
+    vec3 sun = normalize(light_dir);
+    float step_size = (end - start) / float(VIEW_STEPS);
+    vec2 view_depth = vec2(0.0);
+    vec3 rayleigh = vec3(0.0);
+    vec3 mie = vec3(0.0);
+    for (int i = 0; i < VIEW_STEPS; ++i) {
+        vec3 pt = cam_pos + dir * (start + step_size * (float(i) + 0.5));
+        vec2 step_depth = densityAt(pt, center, inner, thickness) * step_size;
+        view_depth += step_depth;
+        vec2 light_depth;
+        if (lightOpticalDepth(pt, sun, center, inner, outer, light_depth)) {
+            vec2 total = view_depth + light_depth;
+            vec3 attenuation =
+                exp(-(rayleigh_coeff * total.x + mie_extinction * total.y));
+            rayleigh += step_depth.x * attenuation;
+            mie += step_depth.y * attenuation;
+        }
+    }
+    float cos_theta = dot(dir, sun);
+    vec3 scattered = sun_intensity * (rayleigh * rayleigh_coeff
+        * rayleighPhase(cos_theta) + mie * mie_coeff * miePhase(cos_theta));
+    vec3 transmittance =
+        exp(-(rayleigh_coeff * view_depth.x + mie_extinction * view_depth.y));
+    // premultiplied, so the scene behind is dimmed by the transmittance
+    frag_color = vec4(scattered, 1.0 - dot(transmittance, vec3(1.0 / 3.0)));
+}
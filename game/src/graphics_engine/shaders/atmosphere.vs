@@ -0,0 +1,16 @@
+#version 430 core
+layout (location = 0) in vec3 pos;
+
+uniform mat4 model;
+uniform mat4 viewproj;
+// radius of the top of the atmosphere, in model space
+uniform float atmosphere_radius;
+
+out vec3 world_pos;
+
+void main() {
+    // the unit cube is scaled to bound the atmosphere
+    vec4 world = model * vec4(pos * atmosphere_radius, 1.0);
+    world_pos = world.xyz;
+    gl_Position = viewproj * world;
+}
@@ -15,6 +15,8 @@ uniform mat4 viewproj;
 uniform mat4 view;
 uniform mat4 proj;
 uniform int tile_num_x;
+// rotates the density of the volume around its center
+uniform mat3 anim_rot;
 
 const float EPS = 0.00001;
 const vec3 scattering_coeff = 15 * vec3(0.25, 0.5, 1.0);
@@ -68,7 +70,8 @@ vec2 getNearFarT(vec3 dir, vec3 origin, out bool miss) {
     return vec2(near, far);
 }
 float densityAt(vec3 pt) {
-    float density = texture(volume, pt).r;
+    vec3 anim_pt = anim_rot * (pt - vec3(0.5)) + vec3(0.5);
+    float density = texture(volume, anim_pt).r;
     return smoothstep(0.05, 0.85, density);
 }
 
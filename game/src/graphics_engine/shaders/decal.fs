@@ -0,0 +1,27 @@
+#version 430 core
+
+flat in mat4 inv_model;
+flat in float alpha;
+
+uniform sampler2D cam_depth;
+uniform sampler2D decal_tex;
+uniform mat4 inv_viewproj;
+uniform vec4 decal_color;
+
+out vec4 frag_color;
+
+void main() {
+    // reconstruct the world position of the opaque surface behind this fragment
+    vec2 screen_uv = gl_FragCoord.xy / vec2(textureSize(cam_depth, 0));
+    float depth = texture(cam_depth, screen_uv).r;
+    vec4 world_pos = inv_viewproj * vec4(vec3(screen_uv, depth) * 2.0 - 1.0, 1.0);
+    vec3 local_pos = (inv_model * vec4(world_pos.xyz / world_pos.w, 1.0)).xyz;
+
+    // the decal is projected along its local z axis through the -1 to 1 box
+    if (any(greaterThan(abs(local_pos), vec3(1.0)))) {
+        discard;
+    }
+    float mask = texture(decal_tex, local_pos.xy * 0.5 + 0.5).a;
+    float fade = 1.0 - abs(local_pos.z);
+    frag_color = vec4(decal_color.rgb, decal_color.a * mask * fade * alpha);
+}
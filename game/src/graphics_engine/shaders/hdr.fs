@@ -39,12 +39,20 @@ float textureLinearize(sampler2D tex, vec2 tex_coords) {
     return (2.0 * near) / (far + near - z * (far - near));
 }
 
+bool inBounds(vec2 tex_coords) {
+    return tex_coords.x >= 0 && tex_coords.x <= 1.0 
+        && tex_coords.y >= 0 && tex_coords.y <= 1;
+}
+
 void main() {
-    frag_color = texture(textures[0], (models[0] * vec3(f_tex_coords, 1.0)).xy);
+    // the first texture may only cover part of the screen, such as one
+    // viewport of a split screen
+    vec2 base_coords = (models[0] * vec3(f_tex_coords, 1.0)).xy;
+    frag_color = inBounds(base_coords) ? texture(textures[0], base_coords)
+        : vec4(0.0, 0.0, 0.0, 1.0);
     for (uint i = 1; i < tex_count; ++i) {
         vec2 tex_coords = (models[i] * vec3(f_tex_coords, 1.0)).xy;
-        if (tex_coords.x >= 0 && tex_coords.x <= 1.0 
-            && tex_coords.y >= 0 && tex_coords.y <= 1)
+        if (inBounds(tex_coords))
         {
             frag_color = 
                 blend_function(texture(textures[i], tex_coords), frag_color);
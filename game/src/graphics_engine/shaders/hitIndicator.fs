@@ -0,0 +1,46 @@
+#version 430 core
+
+in vec2 ndc;
+
+// each column is two arcs as (angle, intensity) pairs
+uniform mat4 arcs;
+uniform float vignette;
+uniform float aspect;
+
+out vec4 frag_color;
+
+const float PI = 3.14159265358979323846264338327950288;
+// radius and thickness of the ring the arcs lie on, as fractions of half
+// the screen height
+const float ARC_RADIUS = 0.35;
+const float ARC_WIDTH = 0.025;
+// half the angle spanned by an arc
+const float ARC_HALF_ANGLE = 0.4;
+const vec3 HIT_COLOR = vec3(1.0, 0.1, 0.05);
+
+/// Gets the opacity of an arc pointing at `angle` at the polar coordinate
+/// `theta`, on the ring of arcs
+float arcAlpha(float theta, float angle, float intensity) {
+    float diff = abs(mod(theta - angle + PI, 2.0 * PI) - PI);
+    return intensity * (1.0 - smoothstep(ARC_HALF_ANGLE * 0.6,
+        ARC_HALF_ANGLE, diff));
+}
+
+void main() {
+    // circular in screen space
+    vec2 p = vec2(ndc.x * aspect, ndc.y);
+    float radius = length(p);
+    float theta = atan(p.y, p.x);
+    float ring = 1.0 - smoothstep(ARC_WIDTH * 0.5, ARC_WIDTH,
+        abs(radius - ARC_RADIUS));
+    float alpha = 0.0;
+    if (ring > 0.0) {
+        for (int i = 0; i < 4; ++i) {
+            alpha = max(alpha, arcAlpha(theta, arcs[i].x, arcs[i].y));
+            alpha = max(alpha, arcAlpha(theta, arcs[i].z, arcs[i].w));
+        }
+        alpha *= ring;
+    }
+    float edge = smoothstep(0.6, 1.4, length(ndc));
+    frag_color = vec4(HIT_COLOR, max(alpha, vignette * edge));
+}
@@ -0,0 +1,9 @@
+#version 430 core
+layout (location = 0) in vec3 pos;
+
+out vec2 ndc;
+
+void main() {
+    ndc = pos.xy;
+    gl_Position = vec4(pos.xy, 0.0, 1.0);
+}
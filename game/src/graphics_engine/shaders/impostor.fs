@@ -0,0 +1,17 @@
+#version 430 core
+in vec2 tcoords;
+// rgb - tint of the image, a - layer of the image
+in vec4 color;
+
+uniform sampler2DArray impostors;
+
+out vec4 frag_color;
+
+void main() {
+    vec4 image = texture(impostors, vec3(tcoords, color.a));
+    // the image is cleared to transparent around the objects in it
+    if (image.a < 0.5) {
+        discard;
+    }
+    frag_color = vec4(image.rgb * color.rgb, 1.0);
+}
@@ -3,14 +3,54 @@
 in FragData {
     vec2 tex_coords;
     flat vec4 color;
-    flat uint tex_idx;
+    flat uint icon;
 } fragData;
 
 uniform sampler2D textures[3];
 
 out vec4 frag_color;
 
+// icons after the textures are shapes drawn here, pointing up
+#define ICON_DIAMOND 3u
+#define ICON_TRIANGLE 4u
+#define ICON_ARROW 5u
+#define ICON_RING 6u
+
+/// Gets the signed distance from `p` to the edge of an isoceles triangle
+/// pointing up, with its tip at `tip` and its base at `base` of half width
+/// `half_width`. Negative inside the triangle
+float triangle(vec2 p, float tip, float base, float half_width) {
+    float edge = normalize(vec2(tip - base, half_width)).x;
+    float slope = abs(p.x) - half_width * (tip - p.y) / (tip - base);
+    return max(slope * edge, base - p.y);
+}
+
+/// Gets the signed distance from `p` in `[-1, 1]` to the edge of the shape
+/// of `icon`. Negative inside the shape
+float shapeDistance(uint icon, vec2 p) {
+    switch (icon) {
+        case ICON_DIAMOND:
+            return (abs(p.x) + abs(p.y) - 0.8) * 0.7071;
+        case ICON_TRIANGLE:
+            return triangle(p, 0.9, -0.7, 0.7);
+        case ICON_ARROW:
+            return triangle(p, 0.9, -0.7, 0.45);
+        case ICON_RING:
+            return abs(length(p) - 0.8) - 0.12;
+        default:
+            return 1.0;
+    }
+}
+
 void main() {
-    frag_color = texture(textures[fragData.tex_idx], fragData.tex_coords).r * 
-        fragData.color;
-}
\ No newline at end of file
+    if (fragData.icon < 3u) {
+        frag_color = texture(textures[fragData.icon], fragData.tex_coords).r *
+            fragData.color;
+    } else {
+        float dist = shapeDistance(fragData.icon,
+            fragData.tex_coords * 2.0 - 1.0);
+        float aa = max(fwidth(dist), 1e-4);
+        float coverage = 1.0 - smoothstep(-aa, aa, dist);
+        frag_color = vec4(fragData.color.rgb, fragData.color.a * coverage);
+    }
+}
@@ -11,7 +11,7 @@ layout (location = 7) in vec4 instance_model_col3;
 out FragData {
     vec2 tex_coords;
     flat vec4 color;
-    flat uint tex_idx;
+    flat uint icon;
 } fragData;
 
 void main() {
@@ -20,6 +20,6 @@ void main() {
 
     fragData.tex_coords = tex_coords;
     fragData.color = color;
-    fragData.tex_idx = tex_idx;
+    fragData.icon = tex_idx;
     gl_Position = model * vec4(pos, 1.0);
 }
\ No newline at end of file
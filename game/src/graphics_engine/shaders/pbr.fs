@@ -31,6 +31,13 @@ uniform sampler2D brdf_lut;
 
 uniform int tile_num_x;
 #define MAX_LIGHTS_PER_TILE 1024
+#define MAX_LIGHTS_PER_CLUSTER 256
+
+// (clusters along x, clusters along y, depth slices, tile size in pixels)
+// of the light clusters, or all 0 if lights are culled per screen tile
+uniform ivec4 cluster_grid;
+// near and far planes of the light clusters
+uniform vec2 cluster_depth;
 
 const float max_reflection_mips = 4.0; // we use 5 mip maps (0 to 4)
 
@@ -51,6 +58,16 @@ struct TransparencyData {
 
 uniform TransparencyData transparencyData;
 
+// The two reflection probes closest to the object
+struct ProbeData {
+    samplerCube env_a;
+    samplerCube env_b;
+    float weight_a;
+    float weight_b;
+};
+
+uniform ProbeData probeData;
+
 layout(std430, binding = 0) readonly buffer LightBuffer {
     uint light_num;
     LightData lights[];
@@ -375,19 +392,45 @@ float lightAPrime(LightData light, float roughness, float dist) {
     }
 }
 
+/// Gets the offset of the list of lights which may affect this fragment in
+/// the visible light buffer, and the maximum length of the list
+uint lightListOffset(out int max_lights) {
+    ivec2 location = ivec2(gl_FragCoord.xy);
+    if (cluster_grid.z == 0) {
+        ivec2 tileId = location / ivec2(16, 16);
+        max_lights = MAX_LIGHTS_PER_TILE;
+        return uint(tileId.y * tile_num_x + tileId.x) * MAX_LIGHTS_PER_TILE;
+    }
+    ivec2 tile = min(location / cluster_grid.w, cluster_grid.xy - 1);
+    // slices are exponentially distributed between the near and far plane
+    float depth = max(-(view * vec4(f_in.frag_pos, 1.0)).z, cluster_depth.x);
+    int slice = int(log(depth / cluster_depth.x) /
+        log(cluster_depth.y / cluster_depth.x) * float(cluster_grid.z));
+    slice = min(slice, cluster_grid.z - 1);
+    max_lights = MAX_LIGHTS_PER_CLUSTER;
+    return uint((slice * cluster_grid.y + tile.y) * cluster_grid.x + tile.x)
+        * MAX_LIGHTS_PER_CLUSTER;
+}
+
+/// Gets the distance from the fragment to the closest point of `light`
+float distToLight(LightData light) {
+    vec3 segment = light.end - light.start;
+    float t = clamp(dot(f_in.frag_pos - light.start, segment) /
+        max(dot(segment, segment), 0.00001), 0.0, 1.0);
+    return length(light.start + segment * t - f_in.frag_pos);
+}
+
 /// Computes the direct radiance from an array of light sources
 /// `R` - reflection vector
 vec3 directRadiance(vec3 norm, vec3 view_dir, vec3 f0, float roughness, 
     float metallic, vec3 albedo, vec3 R) 
 {
-    ivec2 location = ivec2(gl_FragCoord.xy);
-    ivec2 tileId = location / ivec2(16, 16);
-    uint workGroupIndex = tileId.y * tile_num_x + tileId.x;
-    uint offset = workGroupIndex * MAX_LIGHTS_PER_TILE;
+    int max_lights;
+    uint offset = lightListOffset(max_lights);
 
     vec3 radiance_out = vec3(0);
 
-    for(int i = 0; i < MAX_LIGHTS_PER_TILE && visibleLightBuffer.indices[offset + i] != -1; ++i) {
+    for(int i = 0; i < max_lights && visibleLightBuffer.indices[offset + i] != -1; ++i) {
         // using point lights, so we know where the light is coming from 
         // so not exactly integrating over total area
 
@@ -396,6 +439,12 @@ vec3 directRadiance(vec3 norm, vec3 view_dir, vec3 f0, float roughness,
         // so we find the closest point on the mesh to our reflection ray
 
         LightData light = lights[visibleLightBuffer.indices[offset + i]];
+        // the list holds the lights which may reach any fragment of the tile
+        // or cluster, so skip the shading of the lights that can't reach
+        // this one
+        if (distToLight(light) > light.radius * light.luminance) {
+            continue;
+        }
 
         float light_radius = light.radius;
         float luminance = light.luminance;
@@ -458,6 +507,18 @@ vec3 applyTransparency(vec3 frag_color, vec3 view_dir, vec3 norm) {
     }
 }
 
+// Blends a color sampled from the global IBL maps with the reflection probes
+// affecting this object
+vec3 applyProbes(vec3 global_color, vec3 dir, float lod) {
+    float total_weight = probeData.weight_a + probeData.weight_b;
+    if (total_weight < 0.0001) {
+        return global_color;
+    }
+    vec3 probe_color = (textureLod(probeData.env_a, dir, lod).rgb * probeData.weight_a
+        + textureLod(probeData.env_b, dir, lod).rgb * probeData.weight_b) / total_weight;
+    return mix(global_color, probe_color, max(probeData.weight_a, probeData.weight_b));
+}
+
 void main() {
     vec3 albedo = texture(albedo_map, f_in.tex_coords).rgb; // load textures using SRGB so no need to gamma correct
     vec3 emission = texture(emission_map, f_in.tex_coords).rgb;
@@ -471,7 +532,9 @@ void main() {
     vec3 view_dir = normalize(cam_pos - f_in.frag_pos);
     vec3 ref = reflect(-view_dir, norm);
 
-    vec3 prefilter_color = textureLod(prefilter_map, ref, roughness * max_reflection_mips).rgb;
+    vec3 prefilter_color = applyProbes(
+        textureLod(prefilter_map, ref, roughness * max_reflection_mips).rgb,
+        ref, roughness * max_reflection_mips);
 
     vec3 f0 = getF0(albedo, metallic);
 
@@ -483,7 +546,9 @@ void main() {
     vec3 kd = 1.0 - ks;
     kd *= 1.0 - metallic;
 
-    vec3 irradiance = texture(irradiance_map, norm).rgb;
+    // probes have no convolved irradiance map, so use their blurriest mip instead
+    vec3 irradiance = applyProbes(texture(irradiance_map, norm).rgb, norm,
+        max_reflection_mips);
     // irradiance map is precomputed integral of light intensity over hemisphere
     vec3 diffuse = irradiance * albedo;
     vec3 specular = prefilter_color * (ks * env_brdf.x + env_brdf.y);
@@ -0,0 +1,27 @@
+#version 430 core
+in vec2 disk_pos;
+
+uniform vec4 color;
+// seconds since the portal was created
+uniform float time;
+
+out vec4 frag_color;
+
+const float PI = 3.14159265;
+const float ARMS = 3.0;
+
+void main() {
+    float r = length(disk_pos);
+    if (r > 1.0) {
+        discard;
+    }
+    float angle = atan(disk_pos.y, disk_pos.x);
+    // spiral arms that wind tighter towards the center and turn over time
+    float swirl = sin(ARMS * angle + 8.0 * sqrt(r) - time * 2.5);
+    swirl = swirl * 0.5 + 0.5;
+    // bright rim and a dark throat, fading out past the rim
+    float rim = smoothstep(0.75, 0.95, r) * (1.0 - smoothstep(0.95, 1.0, r));
+    float body = (1.0 - r) * swirl;
+    float alpha = color.a * clamp(body + rim * 2.0, 0.0, 1.0);
+    frag_color = vec4(color.rgb * (1.0 + 3.0 * rim), alpha);
+}
@@ -0,0 +1,14 @@
+#version 430 core
+layout (location = 0) in vec3 pos;
+layout (location = 1) in vec2 tex_coords;
+
+uniform mat4 model;
+uniform mat4 viewproj;
+
+// position on the face of the portal, from -1 to 1
+out vec2 disk_pos;
+
+void main() {
+    disk_pos = tex_coords * 2.0 - 1.0;
+    gl_Position = viewproj * model * vec4(pos, 1.0);
+}
@@ -0,0 +1,111 @@
+#version 430 core
+
+in FragData {
+    vec3 tex_coords;
+} f_in;
+
+struct NebulaLayer {
+    // rgb - color of the dense parts of the layer, a - radius of the shell
+    // of the noise volume the layer is sampled from
+    vec4 inner_color_shell;
+    // rgb - color of the thin parts of the layer, a - brightness of the layer
+    vec4 outer_color_intensity;
+};
+
+layout(std430, binding = 9) readonly buffer NebulaLayers {
+    uint layer_count;
+    NebulaLayer layers[];
+};
+
+uniform sampler3D nebula_noise;
+uniform uint seed;
+// chance that a cell of the star grid has a star
+uniform float star_density;
+uniform float star_brightness;
+// angle covered by a texel of the face being rendered, in radians
+uniform float texel_angle;
+
+out vec4 frag_color;
+
+// cells of the star grid per unit of direction
+#define STAR_CELLS 200.0
+// angular radius of a star, in radians
+#define STAR_RADIUS 0.0015
+
+/// PCG hash, see "Hash Functions for GPU Rendering" (Jarzynski and Olano)
+uvec3 hash(uvec3 v) {
+    v = v * 1664525u + 1013904223u;
+    v.x += v.y * v.z;
+    v.y += v.z * v.x;
+    v.z += v.x * v.y;
+    v ^= v >> 16u;
+    v.x += v.y * v.z;
+    v.y += v.z * v.x;
+    v.z += v.x * v.y;
+    return v;
+}
+
+/// Gets three random numbers between 0 and 1 for the star grid cell `cell`
+vec3 random(ivec3 cell) {
+    return vec3(hash(uvec3(cell) ^ uvec3(seed))) / float(0xffffffffu);
+}
+
+/// Gets the light of the stars around the direction `dir`
+///
+/// Stars are placed randomly within the cells of a 3D grid, so they don't
+/// depend on the faces of the cubemap
+vec3 stars(vec3 dir) {
+    vec3 pos = dir * STAR_CELLS;
+    ivec3 base = ivec3(floor(pos));
+    // stars smaller than a texel are spread over it, keeping their energy
+    float radius = max(STAR_RADIUS, texel_angle);
+    float energy = (STAR_RADIUS * STAR_RADIUS) / (radius * radius);
+    vec3 light = vec3(0.0);
+    for (int x = -1; x <= 1; ++x) {
+        for (int y = -1; y <= 1; ++y) {
+            for (int z = -1; z <= 1; ++z) {
+                ivec3 cell = base + ivec3(x, y, z);
+                vec3 rand = random(cell);
+                vec3 star_pos = vec3(cell) + random(cell.zxy);
+                // only stars near the sphere the directions lie on are used,
+                // so every star is within the cells around the
+                // directions it's seen in
+                if (rand.x >= star_density ||
+                    abs(length(star_pos) - STAR_CELLS) > 0.5)
+                {
+                    continue;
+                }
+                vec3 star_dir = normalize(star_pos);
+                float angle = length(dir - star_dir);
+                float falloff = exp(-angle * angle / (radius * radius));
+                // most stars are dim, and their colors range from
+                // orange to blue white
+                float brightness = pow(rand.y, 8.0) * star_brightness;
+                vec3 color = mix(vec3(1.0, 0.7, 0.45), vec3(0.75, 0.85, 1.0), rand.z);
+                light += color * brightness * falloff * energy;
+            }
+        }
+    }
+    return light;
+}
+
+/// Gets the light of the nebula layers in the direction `dir`
+vec3 nebula(vec3 dir) {
+    vec3 light = vec3(0.0);
+    for (uint i = 0; i < layer_count; ++i) {
+        float shell = layers[i].inner_color_shell.a;
+        // each layer is offset so layers with the same shell differ
+        vec3 offset = fract(vec3(0.37, 0.71, 0.13) * float(i)) - 0.5;
+        vec3 coords = clamp(dir * shell * 0.5 + offset * (1.0 - shell), -0.5, 0.5) + 0.5;
+        float density = texture(nebula_noise, coords).r;
+        vec3 color = mix(layers[i].outer_color_intensity.rgb,
+            layers[i].inner_color_shell.rgb, density);
+        light += color * density * density * layers[i].outer_color_intensity.a;
+    }
+    return light;
+}
+
+void main() {
+    vec3 dir = normalize(f_in.tex_coords);
+    frag_color = vec4(nebula(dir) + stars(dir), 1.0);
+}
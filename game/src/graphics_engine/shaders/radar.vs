@@ -0,0 +1,18 @@
+#version 430 core
+layout (location = 0) in vec3 pos;
+layout (location = 1) in vec4 start_pos;
+layout (location = 2) in vec4 end_pos;
+layout (location = 3) in vec4 color;
+
+// radar space to clip space
+uniform mat4 transform;
+
+out vec4 line_color;
+
+void main() {
+    // pos.x is 0 for start pos, 1 for end pos
+    vec3 radar_pos = mix(start_pos.xyz, end_pos.xyz, pos.x);
+
+    gl_Position = transform * vec4(radar_pos, 1.0);
+    line_color = color;
+}
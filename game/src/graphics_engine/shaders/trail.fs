@@ -0,0 +1,16 @@
+#version 430 core
+in float t;
+in float side;
+
+uniform vec4 color;
+uniform float emission;
+uniform float alpha_falloff;
+
+out vec4 frag_color;
+
+void main() {
+    // soften the edges of the strip
+    float edge = 1.0 - side * side;
+    float alpha = color.a * pow(1.0 - t, alpha_falloff) * edge;
+    frag_color = vec4(color.rgb * emission, alpha);
+}
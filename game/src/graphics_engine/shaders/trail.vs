@@ -0,0 +1,26 @@
+#version 430 core
+layout (location = 0) in vec3 trail_pos;
+layout (location = 1) in vec3 trail_dir;
+layout (location = 2) in float trail_side;
+layout (location = 3) in float trail_t;
+
+uniform mat4 viewproj;
+uniform vec3 cam_pos;
+uniform float width;
+uniform float width_falloff;
+
+out float t;
+out float side;
+
+void main() {
+    // expand the center line perpendicular to both the trail and the view
+    // ray so the strip always faces the camera
+    vec3 offset = cross(trail_dir, cam_pos - trail_pos);
+    float len = length(offset);
+    offset = len > 0.00001 ? offset / len : vec3(0.0);
+    float half_width = width * 0.5 * pow(1.0 - trail_t, width_falloff);
+
+    t = trail_t;
+    side = trail_side;
+    gl_Position = viewproj * vec4(trail_pos + offset * trail_side * half_width, 1.0);
+}
@@ -0,0 +1,255 @@
+use super::cubes::{self, CUBE_INDICES, CUBE_VERTS};
+use super::drawable::*;
+use super::pipeline::*;
+use super::{camera, shader, volume};
+use crate::cg_support::ssbo;
+use std::cell::Cell;
+
+/// Side length of the noise volume nebula layers are sampled from
+const NEBULA_NOISE_SIZE: u32 = 64;
+
+/// A layer of nebula clouds across the sky, colored by a gradient from
+/// its thin parts to its dense parts
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct NebulaLayer {
+    /// The color of the dense parts of the layer
+    pub inner_color: [f32; 3],
+    /// The color of the thin parts of the layer
+    pub outer_color: [f32; 3],
+    /// The radius of the shell of the noise volume the layer is sampled
+    /// from, between `0` and `1`. Smaller shells have larger clouds
+    pub shell: f32,
+    pub intensity: f32,
+}
+
+impl NebulaLayer {
+    const fn data(&self) -> shader::NebulaLayerData {
+        let [ir, ig, ib] = self.inner_color;
+        let [or, og, ob] = self.outer_color;
+        shader::NebulaLayerData {
+            inner_color_shell: [ir, ig, ib, self.shell],
+            outer_color_intensity: [or, og, ob, self.intensity],
+        }
+    }
+}
+
+/// Generates a skybox of stars and nebulas instead of loading one
+pub struct ProceduralSky {
+    seed: u32,
+    star_density: f32,
+    star_brightness: f32,
+    nebulas: Vec<NebulaLayer>,
+    size: u32,
+    mip_levels: u32,
+}
+
+impl ProceduralSky {
+    /// Creates a sky with stars and no nebulas
+    ///
+    /// `seed` - the seed of the random placement of the stars and the
+    /// nebula noise
+    pub const fn new(seed: u32) -> Self {
+        Self {
+            seed,
+            star_density: 0.05,
+            star_brightness: 40.,
+            nebulas: Vec::new(),
+            size: 1024,
+            mip_levels: 5,
+        }
+    }
+
+    /// A sky of dim stars with purple and blue nebulas
+    pub fn space(seed: u32) -> Self {
+        Self::new(seed)
+            .nebula(NebulaLayer {
+                inner_color: [0.5, 0.15, 0.6],
+                outer_color: [0.05, 0.02, 0.15],
+                shell: 0.5,
+                intensity: 0.6,
+            })
+            .nebula(NebulaLayer {
+                inner_color: [0.2, 0.45, 0.8],
+                outer_color: [0.02, 0.05, 0.1],
+                shell: 0.9,
+                intensity: 0.3,
+            })
+    }
+
+    /// Sets the stars of the sky
+    ///
+    /// `density` - chance, from `0` to `1`, that a cell of the star grid
+    /// has a star. There are about 500,000 cells across the sky
+    ///
+    /// `brightness` - the brightness of the brightest stars
+    #[must_use]
+    #[allow(dead_code)]
+    pub const fn stars(mut self, density: f32, brightness: f32) -> Self {
+        self.star_density = density;
+        self.star_brightness = brightness;
+        self
+    }
+
+    /// Adds a layer of nebula. Layers are added together
+    #[must_use]
+    pub fn nebula(mut self, layer: NebulaLayer) -> Self {
+        self.nebulas.push(layer);
+        self
+    }
+
+    /// Sets the side length of each face of the generated cubemap, and its
+    /// amount of mipmaps
+    #[must_use]
+    #[allow(dead_code)]
+    pub const fn size(mut self, size: u32, mip_levels: u32) -> Self {
+        self.size = size;
+        self.mip_levels = mip_levels;
+        self
+    }
+
+    /// Renders the sky into a cubemap and gets the skybox showing it
+    pub fn generate<F: glium::backend::Facade>(
+        &self,
+        shader_manager: &shader::ShaderManager,
+        facade: &F,
+    ) -> cubes::Skybox {
+        let layers: Vec<_> =
+            self.nebulas.iter().map(NebulaLayer::data).collect();
+        let mut generator = SkyGenerator {
+            vbo: glium::VertexBuffer::new(facade, &CUBE_VERTS).unwrap(),
+            ebo: glium::IndexBuffer::new(
+                facade,
+                glium::index::PrimitiveType::TrianglesList,
+                &CUBE_INDICES,
+            )
+            .unwrap(),
+            noise: volume::VolumeGenerator::new(
+                NEBULA_NOISE_SIZE,
+                NEBULA_NOISE_SIZE,
+                NEBULA_NOISE_SIZE,
+            )
+            .layer(
+                volume::NoiseLayer::new(volume::NoiseKind::Perlin, 3.)
+                    .octaves(5, 0.55, 2.),
+            )
+            .remap(volume::DensityCurve::Smoothstep(0.45, 0.85))
+            .seed(self.seed)
+            .generate(facade),
+            layers: ssbo::Ssbo::dynamic(Some(layers.as_slice())),
+            seed: self.seed,
+            star_density: self.star_density,
+            star_brightness: self.star_brightness,
+            texel_angle: 0.,
+        };
+        let cam = camera::PerspectiveCamera::default(1.);
+        let mut rt = render_target::MipCubemapRenderTarget::new(
+            self.size,
+            self.mip_levels,
+            10.,
+            Box::new(|| cgmath::point3(0., 0., 0.)),
+        );
+        let mip_level = Cell::new(0);
+        let mut cache = shader::PipelineCache::default();
+        let res = rt.draw(
+            &cam,
+            None,
+            &mut cache,
+            &mut |fbo, viewer, _, cache, _, _| {
+                generator.texel_angle = texel_angle(self.size, mip_level.get());
+                let mut sd = default_scene_data(viewer);
+                sd.pass_type = shader::RenderPassType::LayeredVisual;
+                render_drawable(
+                    &mut generator,
+                    None,
+                    fbo,
+                    &sd,
+                    cache,
+                    shader_manager,
+                );
+                mip_level.set(mip_level.get() + 1);
+            },
+        );
+        if let Some(TextureType::TexCube(Ownership::Own(sky))) = res {
+            cubes::Skybox::new(cubes::SkyboxTex::Cube(sky), facade)
+        } else {
+            panic!("Unexpected return from generating the sky")
+        }
+    }
+}
+
+/// Gets the angle covered by a texel at the center of a face of a cubemap
+/// with faces of side length `size`, at mipmap level `mip_level`
+fn texel_angle(size: u32, mip_level: u32) -> f32 {
+    let texels = (size >> mip_level).max(1);
+    // a face spans from -1 to 1 at a distance of 1 from the center
+    (2. / texels as f32).atan()
+}
+
+/// Draws the stars and nebulas of a procedural sky onto a cube around the
+/// viewer
+struct SkyGenerator {
+    vbo: glium::VertexBuffer<VertexPos>,
+    ebo: glium::IndexBuffer<u16>,
+    noise: glium::texture::Texture3d,
+    layers: ssbo::Ssbo<shader::NebulaLayerData>,
+    seed: u32,
+    star_density: f32,
+    star_brightness: f32,
+    /// Angle covered by a texel of the mipmap level being rendered
+    texel_angle: f32,
+}
+
+impl Drawable for SkyGenerator {
+    fn render_args<'a>(
+        &'a mut self,
+        _: &[[[f32; 4]; 4]],
+    ) -> Vec<(
+        shader::UniformInfo,
+        VertexHolder<'a>,
+        glium::index::IndicesSource<'a>,
+    )> {
+        vec![(
+            shader::UniformInfo::ProceduralSky(shader::ProceduralSkyData {
+                nebula_noise: &self.noise,
+                nebula_layers: &self.layers,
+                seed: self.seed,
+                star_density: self.star_density,
+                star_brightness: self.star_brightness,
+                texel_angle: self.texel_angle,
+            }),
+            VertexHolder::new(VertexSourceData::Single(From::from(&self.vbo))),
+            From::from(&self.ebo),
+        )]
+    }
+
+    fn transparency(&self) -> Option<f32> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn texels_grow_with_mip_level() {
+        assert_approx_eq!(texel_angle(2, 0), std::f32::consts::FRAC_PI_4);
+        assert!(texel_angle(1024, 1) > texel_angle(1024, 0));
+        // levels past the smallest have one texel per face
+        assert_approx_eq!(texel_angle(1024, 12), texel_angle(1024, 10));
+    }
+
+    #[test]
+    fn nebula_layers_are_packed_for_the_shader() {
+        let data = NebulaLayer {
+            inner_color: [1., 0.5, 0.25],
+            outer_color: [0.1, 0.2, 0.3],
+            shell: 0.7,
+            intensity: 2.,
+        }
+        .data();
+        assert_eq!(data.inner_color_shell, [1., 0.5, 0.25, 0.7]);
+        assert_eq!(data.outer_color_intensity, [0.1, 0.2, 0.3, 2.]);
+    }
+}
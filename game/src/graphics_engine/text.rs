@@ -1,3 +1,4 @@
+use super::assets::AssetHandle;
 use super::drawable::*;
 use super::entity::*;
 use super::instancing::*;
@@ -8,8 +9,6 @@ use glium::*;
 use regex::*;
 use std::cell::RefCell;
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::Read;
 use std::rc::Rc;
 use VertexSimple as Vertex;
 
@@ -45,20 +44,23 @@ struct Glyph {
 }
 
 pub struct Font {
-    glyphs: HashMap<u8, Glyph>,
+    glyphs: HashMap<char, Glyph>,
     /// Map of horizontal spacing offsets when the second character follows the first
-    kernings: HashMap<u8, HashMap<u8, i32>>,
+    kernings: HashMap<char, HashMap<char, i32>>,
     sdf: glium::texture::Texture2d,
     _line_height: i32,
     img_width: i32,
     img_height: i32,
+    /// Fonts the glyphs missing from this font are drawn with, in the order
+    /// they're searched
+    fallbacks: Vec<Rc<Font>>,
 }
 
 impl Font {
     fn parse_line_to_glyph(
         line: &str,
         param_regex: &Regex,
-    ) -> Option<(u8, Glyph)> {
+    ) -> Option<(char, Glyph)> {
         let mut x: Option<i32> = None;
         let mut y: Option<i32> = None;
         let mut height: Option<i32> = None;
@@ -91,28 +93,30 @@ impl Font {
                 _ => (),
             }
         }
-        char_id.map(|char_id| {
-            (
-                char_id as u8,
-                Glyph {
-                    x: x.unwrap(),
-                    y: y.unwrap(),
-                    height: height.unwrap(),
-                    width: width.unwrap(),
-                    advance: advance.unwrap(),
-                    _xoff: xoff.unwrap(),
-                    _yoff: yoff.unwrap(),
-                },
-            )
-        })
+        char_id
+            .and_then(|char_id| char::from_u32(char_id as u32))
+            .map(|c| {
+                (
+                    c,
+                    Glyph {
+                        x: x.unwrap(),
+                        y: y.unwrap(),
+                        height: height.unwrap(),
+                        width: width.unwrap(),
+                        advance: advance.unwrap(),
+                        _xoff: xoff.unwrap(),
+                        _yoff: yoff.unwrap(),
+                    },
+                )
+            })
     }
 
     fn parse_line_to_kerning(
         line: &str,
         regex: &Regex,
-    ) -> Option<(u8, (u8, i32))> {
-        let mut first: Option<u8> = None;
-        let mut second: Option<u8> = None;
+    ) -> Option<(char, (char, i32))> {
+        let mut first: Option<char> = None;
+        let mut second: Option<char> = None;
         let mut amount: Option<i32> = None;
         for cap in regex.captures_iter(line.trim()) {
             let val = cap
@@ -127,8 +131,8 @@ impl Font {
                 .as_str();
 
             match key {
-                "first" => first = Some(val as u8),
-                "second" => second = Some(val as u8),
+                "first" => first = char::from_u32(val as u32),
+                "second" => second = char::from_u32(val as u32),
                 "amount" => amount = Some(val),
                 _ => (),
             }
@@ -156,7 +160,7 @@ impl Font {
         let line_height = get_integral_field("lineHeight");
         let width = get_integral_field("scaleW");
         let height = get_integral_field("scaleH");
-        let tex_path = Regex::new(r#"file="([a-zA-Z\.]+)""#)
+        let tex_path = Regex::new(r#"file="([^"]+)""#)
             .unwrap()
             .captures(header)
             .expect("No matching file pattern found")
@@ -166,32 +170,90 @@ impl Font {
         (line_height, width, height, tex_path.to_owned())
     }
 
-    /// `path` - path to the `fnt` file which contains the textual metadata
-    pub fn new<F: backend::Facade>(path: &str, f: &F) -> Self {
+    /// Creates a font from its parsed metadata and its loaded signed distance
+    /// field texture
+    pub fn from_desc(desc: FontDesc, sdf: glium::texture::Texture2d) -> Self {
+        Self {
+            glyphs: desc.glyphs,
+            kernings: desc.kernings,
+            sdf,
+            _line_height: desc.line_height,
+            img_width: desc.img_width,
+            img_height: desc.img_height,
+            fallbacks: Vec::new(),
+        }
+    }
+
+    /// Adds a font to draw the glyphs this font is missing with, such as the
+    /// glyphs of non-Latin scripts. Fallbacks are searched in the order
+    /// they're added
+    #[must_use]
+    pub fn with_fallback(mut self, fallback: Rc<Self>) -> Self {
+        self.fallbacks.push(fallback);
+        self
+    }
+
+    /// Gets the glyph of `c` and the index of the font it's drawn with, where
+    /// `0` is this font and the fallbacks follow in order
+    fn find_glyph(&self, c: char) -> Option<(usize, &Glyph)> {
+        self.glyphs.get(&c).map(|g| (0, g)).or_else(|| {
+            self.fallbacks
+                .iter()
+                .zip(1..)
+                .find_map(|(font, idx)| font.glyphs.get(&c).map(|g| (idx, g)))
+        })
+    }
+
+    /// Gets the offset between `first` and `second` when they're drawn next
+    /// to each other with the font at `font_idx`, as returned by
+    /// `find_glyph`
+    fn kerning(&self, font_idx: usize, first: char, second: char) -> i32 {
+        let font = match font_idx {
+            0 => self,
+            idx => &self.fallbacks[idx - 1],
+        };
+        font.kernings
+            .get(&first)
+            .map_or(0, |offsets| offsets.get(&second).copied().unwrap_or(0))
+    }
+}
+
+/// The textual metadata of a font, which does not depend on
+/// the GL context and can be parsed on any thread
+pub struct FontDesc {
+    glyphs: HashMap<char, Glyph>,
+    kernings: HashMap<char, HashMap<char, i32>>,
+    line_height: i32,
+    img_width: i32,
+    img_height: i32,
+    /// Path to the signed distance field texture of the font
+    pub tex_path: String,
+}
+
+impl FontDesc {
+    /// Parses the contents of a `fnt` file
+    ///
+    /// `path` - path to the `fnt` file, which the texture path is relative to
+    pub fn parse(data: &str, path: &str) -> Self {
         let dir = dir_stem(path);
-        let mut file = File::open(path)
-            .unwrap_or_else(|_| panic!("Could not open font file: {}", path));
-        let mut data = String::new();
-        file.read_to_string(&mut data)
-            .expect("Could not read from font file");
         let (header, content) =
             data.split_at(data.find("chars count").unwrap());
         let (char_data, kerning_data) =
             content.split_at(content.find("kernings count").unwrap());
         let (line_height, img_width, img_height, tex_path) =
-            Self::read_from_header(header);
+            Font::read_from_header(header);
 
         let rg_param = Regex::new(r#"([a-z][a-z\s]*)=(-?[0-9]+)"#).unwrap();
         let mut glyphs = HashMap::new();
         let mut kernings = HashMap::new();
         for line in char_data.split('\n') {
-            if let Some((k, v)) = Self::parse_line_to_glyph(line, &rg_param) {
+            if let Some((k, v)) = Font::parse_line_to_glyph(line, &rg_param) {
                 glyphs.insert(k, v);
             }
         }
         for line in kerning_data.split('\n') {
             if let Some((first, (second, amount))) =
-                Self::parse_line_to_kerning(line, &rg_param)
+                Font::parse_line_to_kerning(line, &rg_param)
             {
                 kernings
                     .entry(first)
@@ -199,32 +261,54 @@ impl Font {
                     .insert(second, amount);
             }
         }
-
-        let sdf = load_texture_2d(&format!("{}/{}", dir, tex_path), f);
         Self {
             glyphs,
             kernings,
-            sdf,
-            _line_height: line_height,
+            line_height,
             img_width,
             img_height,
+            tex_path: format!("{}/{}", dir, tex_path),
         }
     }
 }
 
-pub struct Text {
-    vertices: VertexBuffer<Vertex>,
-    indicies: IndexBuffer<u32>,
+/// The glyphs of a text object which are drawn with one font's texture
+struct GlyphBatch {
+    font: Rc<Font>,
     instances: InstanceBuffer<TextAttributes>,
     instance_pos: InstanceBuffer<InstancePosition>,
-    font: Rc<Font>,
     attribs: Vec<TextAttributes>,
     positions: Vec<Node>,
+}
+
+impl GlyphBatch {
+    const fn new(font: Rc<Font>) -> Self {
+        Self {
+            font,
+            instances: InstanceBuffer::new(),
+            instance_pos: InstanceBuffer::new(),
+            attribs: Vec::new(),
+            positions: Vec::new(),
+        }
+    }
+}
+
+pub struct Text {
+    vertices: VertexBuffer<Vertex>,
+    indicies: IndexBuffer<u32>,
+    font: Rc<Font>,
+    /// The glyphs drawn with the font, followed by the glyphs drawn with
+    /// each of its fallbacks
+    batches: Vec<GlyphBatch>,
     dirty: bool,
 }
 
 impl Text {
     pub fn new<F: backend::Facade>(font: Rc<Font>, facade: &F) -> Self {
+        let batches = std::iter::once(font.clone())
+            .chain(font.fallbacks.iter().cloned())
+            .map(GlyphBatch::new)
+            .collect();
         Self {
             vertices: VertexBuffer::new(facade, &RECT_VERTS).unwrap(),
             indicies: IndexBuffer::new(
@@ -233,16 +317,16 @@ impl Text {
                 &RECT_INDICES,
             )
             .unwrap(),
-            instances: InstanceBuffer::new(),
-            instance_pos: InstanceBuffer::new(),
             font,
-            attribs: Vec::new(),
-            positions: Vec::new(),
+            batches,
             dirty: false,
         }
     }
 
     /// Adds an instance of text with the given string, position/scaling, and color
+    ///
+    /// Characters missing from the font are drawn with the first of its
+    /// fallbacks which has them, or skipped if none do
     pub fn add_text(
         &mut self,
         txt: &str,
@@ -252,16 +336,15 @@ impl Text {
         use cgmath::*;
         let mut last_x = 0;
         let fnt = self.font.clone();
-        let mut last_char: u8 = 0;
-        for (g, c) in txt
-            .as_bytes()
-            .iter()
-            .filter_map(|c| fnt.glyphs.get(c).map(|g| (g, c)))
+        let mut last_char = None;
+        for (c, (font_idx, g)) in txt
+            .chars()
+            .filter_map(|c| fnt.find_glyph(c).map(|g| (c, g)))
         {
-            let offset = fnt
-                .kernings
-                .get(&last_char)
-                .map_or(0, |offsets| offsets.get(c).copied().unwrap_or(0));
+            // kerning only applies between glyphs of the same font
+            let offset = last_char
+                .filter(|(_, last_idx)| *last_idx == font_idx)
+                .map_or(0, |(last, _)| fnt.kerning(font_idx, last, c));
             let pt = pos.borrow().transform_pt(point3(
                 f64::from(last_x + offset),
                 0.,
@@ -269,12 +352,13 @@ impl Text {
             ));
             let p = Node::default().parent(pos.clone()).pos(pt);
             last_x += g.advance.min(9);
-            self.positions.push(p);
-            self.attribs.push(TextAttributes {
+            let batch = &mut self.batches[font_idx];
+            batch.positions.push(p);
+            batch.attribs.push(TextAttributes {
                 x_y_width_height: [g.x, g.y, g.width, g.height],
                 color,
             });
-            last_char = *c;
+            last_char = Some((c, font_idx));
         }
         self.dirty = true;
     }
@@ -282,8 +366,10 @@ impl Text {
     /// Removes all text from this text object
     #[inline]
     pub fn clear_text(&mut self) {
-        self.attribs.clear();
-        self.positions.clear();
+        for batch in &mut self.batches {
+            batch.attribs.clear();
+            batch.positions.clear();
+        }
         self.dirty = true;
     }
 }
@@ -297,51 +383,61 @@ impl Drawable for Text {
         VertexHolder<'a>,
         glium::index::IndicesSource<'a>,
     )> {
-        if self.attribs.is_empty() || self.positions.is_empty() {
-            return Vec::new();
-        }
         if self.dirty {
             // TODO: will not work if we want to move text after adding it
             // by changing the parent's transformation node
             let ctx = super::super::get_active_ctx();
             let ctx = ctx.ctx.borrow();
-            self.instances.update_buffer(&self.attribs, &*ctx);
-            let ps: Vec<_> = self
-                .positions
-                .iter()
-                .map(|x| mat_to_instance_pos(&x.mat()))
-                .collect();
-            self.instance_pos.update_buffer(&ps, &*ctx);
+            // empty batches aren't drawn, so their buffers aren't updated
+            for batch in
+                self.batches.iter_mut().filter(|b| !b.attribs.is_empty())
+            {
+                batch.instances.update_buffer(&batch.attribs, &*ctx);
+                let ps: Vec<_> = batch
+                    .positions
+                    .iter()
+                    .map(|x| mat_to_instance_pos(&x.mat()))
+                    .collect();
+                batch.instance_pos.update_buffer(&ps, &*ctx);
+            }
             self.dirty = false;
         }
-
-        let attribs: glium::vertex::VerticesSource<'a> = From::from(
-            self.instances
-                .get_stored_buffer()
-                .unwrap()
-                .per_instance()
-                .unwrap(),
-        );
-        let locs: glium::vertex::VerticesSource<'a> = From::from(
-            self.instance_pos
-                .get_stored_buffer()
-                .unwrap()
-                .per_instance()
-                .unwrap(),
-        );
-        let vertices = VertexHolder::new(VertexSourceData::Single(From::from(
-            &self.vertices,
-        )))
-        .append(locs)
-        .append(attribs);
-        vec![(
-            shader::UniformInfo::Text(
-                &self.font.sdf,
-                [self.font.img_width, self.font.img_height],
-            ),
-            vertices,
-            From::from(&self.indicies),
-        )]
+        let this: &'a Self = self;
+        this.batches
+            .iter()
+            .filter(|batch| !batch.attribs.is_empty())
+            .map(|batch| {
+                let attribs: glium::vertex::VerticesSource<'a> = From::from(
+                    batch
+                        .instances
+                        .get_stored_buffer()
+                        .unwrap()
+                        .per_instance()
+                        .unwrap(),
+                );
+                let locs: glium::vertex::VerticesSource<'a> = From::from(
+                    batch
+                        .instance_pos
+                        .get_stored_buffer()
+                        .unwrap()
+                        .per_instance()
+                        .unwrap(),
+                );
+                let vertices = VertexHolder::new(VertexSourceData::Single(
+                    From::from(&this.vertices),
+                ))
+                .append(locs)
+                .append(attribs);
+                (
+                    shader::UniformInfo::Text(
+                        &batch.font.sdf,
+                        [batch.font.img_width, batch.font.img_height],
+                    ),
+                    vertices,
+                    From::from(&this.indicies),
+                )
+            })
+            .collect()
     }
 
     fn transparency(&self) -> Option<f32> {
@@ -374,12 +470,34 @@ impl AbstractEntity for Text {
 pub struct Icon {
     vertices: VertexBuffer<Vertex>,
     indicies: IndexBuffer<u32>,
-    texture: glium::texture::SrgbTexture2d,
+    texture: Rc<glium::texture::SrgbTexture2d>,
+    /// The texture being loaded, which replaces `texture` once it is loaded
+    pending: Option<AssetHandle<glium::texture::SrgbTexture2d>>,
 }
 
 impl Icon {
     pub fn new<F: backend::Facade>(tex_path: &str, f: &F) -> Self {
-        let tex = load_texture_srgb(tex_path, f);
+        let tex = load_texture_srgb_shared(tex_path, f);
+        Self::with_texture(tex, None, f)
+    }
+
+    /// Creates an icon from a texture which may still be loading. Until
+    /// the texture is loaded, the icon displays the handle's placeholder
+    ///
+    /// Requires the handle has a placeholder
+    pub fn from_handle<F: backend::Facade>(
+        tex: AssetHandle<glium::texture::SrgbTexture2d>,
+        f: &F,
+    ) -> Self {
+        let current = tex.get().expect("Icon texture has no placeholder");
+        Self::with_texture(current, Some(tex), f)
+    }
+
+    fn with_texture<F: backend::Facade>(
+        texture: Rc<glium::texture::SrgbTexture2d>,
+        pending: Option<AssetHandle<glium::texture::SrgbTexture2d>>,
+        f: &F,
+    ) -> Self {
         Self {
             vertices: VertexBuffer::new(f, &RECT_VERTS).unwrap(),
             indicies: IndexBuffer::new(
@@ -388,7 +506,8 @@ impl Icon {
                 &RECT_INDICES,
             )
             .unwrap(),
-            texture: tex,
+            texture,
+            pending,
         }
     }
 }
@@ -402,9 +521,17 @@ impl Drawable for Icon {
         VertexHolder<'a>,
         glium::index::IndicesSource<'a>,
     )> {
+        if let Some(handle) = &self.pending {
+            if handle.is_loaded() || handle.error().is_some() {
+                if let Some(tex) = handle.get() {
+                    self.texture = tex;
+                }
+                self.pending = None;
+            }
+        }
         let verts = &self.vertices;
         let indicies = &self.indicies;
-        let tex = &self.texture;
+        let tex = &*self.texture;
         positions
             .iter()
             .map(|pos| {
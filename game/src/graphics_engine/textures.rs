@@ -1,4 +1,12 @@
+use super::cache::AssetCache;
 use glium::Surface;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+std::thread_local! {
+    static SRGB_CACHE: RefCell<AssetCache<glium::texture::SrgbTexture2d>> =
+        RefCell::new(AssetCache::new());
+}
 
 /// Loads a 16 bit RGB texture
 fn load_hdr(path: &str) -> glium::texture::RawImage2d<u16> {
@@ -36,6 +44,83 @@ fn load_img(path: &str, rev: bool) -> glium::texture::RawImage2d<u8> {
     }
 }
 
+/// Decodes an image from `path` into 8bit RGBA pixels without reversing it.
+/// Does not use the GL context, so it can be called from any thread
+///
+/// Returns the pixel data and the dimensions of the image
+///
+/// # Errors
+/// Fails if the file cannot be opened or decoded
+pub fn decode_rgba(path: &str) -> Result<(Vec<u8>, (u32, u32)), String> {
+    let f = std::fs::File::open(path)
+        .map_err(|e| format!("Could not load '{}': {}", path, e))?;
+    let format = image::ImageFormat::from_path(path)
+        .map_err(|e| format!("Unknown image format '{}': {}", path, e))?;
+    let img = image::load(std::io::BufReader::new(f), format)
+        .map_err(|e| format!("Could not decode '{}': {}", path, e))?
+        .to_rgba8();
+    let dims = img.dimensions();
+    Ok((img.into_raw(), dims))
+}
+
+/// The 8bit RGBA pixels of an image which has been decoded, but not yet
+/// uploaded to the GPU
+pub struct DecodedImage {
+    pub data: Vec<u8>,
+    pub dims: (u32, u32),
+}
+
+impl DecodedImage {
+    /// Decodes the image at `path` without reversing it. Does not use the GL
+    /// context, so it can be called from any thread
+    ///
+    /// # Errors
+    /// Fails if the file cannot be opened or decoded
+    pub fn load(path: &str) -> Result<Self, String> {
+        decode_rgba(path).map(|(data, dims)| Self { data, dims })
+    }
+
+    fn reversed(&self) -> glium::texture::RawImage2d<u8> {
+        glium::texture::RawImage2d::from_raw_rgba_reversed(
+            &self.data, self.dims,
+        )
+    }
+
+    /// Uploads the image as an `sRGB` texture with mipmaps. The image is
+    /// reversed on upload
+    ///
+    /// # Errors
+    /// Fails if the texture cannot be created
+    pub fn upload_srgb<F: glium::backend::Facade>(
+        &self,
+        facade: &F,
+    ) -> Result<glium::texture::SrgbTexture2d, String> {
+        glium::texture::SrgbTexture2d::with_mipmaps(
+            facade,
+            self.reversed(),
+            glium::texture::MipmapsOption::AutoGeneratedMipmaps,
+        )
+        .map_err(|e| e.to_string())
+    }
+
+    /// Uploads the image as a texture with mipmaps. The image is reversed on
+    /// upload
+    ///
+    /// # Errors
+    /// Fails if the texture cannot be created
+    pub fn upload_2d<F: glium::backend::Facade>(
+        &self,
+        facade: &F,
+    ) -> Result<glium::texture::Texture2d, String> {
+        glium::Texture2d::with_mipmaps(
+            facade,
+            self.reversed(),
+            glium::texture::MipmapsOption::AutoGeneratedMipmaps,
+        )
+        .map_err(|e| e.to_string())
+    }
+}
+
 /// Loads an `sRGB` texture as 8bit RGBA with mipmaps from `path`. The image is reversed on load
 /// This will convert whatever texture is loaded from `sRGB` to linear color space
 pub fn load_texture_srgb<F: glium::backend::Facade>(
@@ -51,6 +136,30 @@ pub fn load_texture_srgb<F: glium::backend::Facade>(
     .unwrap()
 }
 
+/// Loads an `sRGB` texture the same way as `load_texture_srgb`, but shares
+/// the texture with every other user of the texture at `path`
+pub fn load_texture_srgb_shared<F: glium::backend::Facade>(
+    path: &str,
+    facade: &F,
+) -> Rc<glium::texture::SrgbTexture2d> {
+    SRGB_CACHE.with(|cache| {
+        cache
+            .borrow_mut()
+            .get_or_load(path, || load_texture_srgb(path, facade))
+    })
+}
+
+/// Evicts shared textures which have not been used for the maximum idle time
+pub fn evict_unused(now: std::time::Instant) {
+    SRGB_CACHE.with(|cache| cache.borrow_mut().evict_unused(now));
+}
+
+/// Sets the time a shared texture can go unused before it is evicted
+#[allow(dead_code)]
+pub fn set_cache_idle_time(idle_time: std::time::Duration) {
+    SRGB_CACHE.with(|cache| cache.borrow_mut().set_max_idle(idle_time));
+}
+
 /// Loads a texture as 8bit RGBA with mipmaps. Image is reversed
 pub fn load_texture_2d<F: glium::backend::Facade>(
     path: &str,
@@ -74,6 +183,30 @@ pub fn load_texture_2d_rev<F : glium::backend::Facade>(path: &str, facade: &F)
         glium::texture::MipmapsOption::AutoGeneratedMipmaps).unwrap()
 }*/
 
+/// Creates a 1x1 texture of a single 8bit RGBA color
+pub fn solid_texture_2d<F: glium::backend::Facade>(
+    color: [u8; 4],
+    facade: &F,
+) -> glium::texture::Texture2d {
+    glium::texture::Texture2d::new(
+        facade,
+        glium::texture::RawImage2d::from_raw_rgba(color.to_vec(), (1, 1)),
+    )
+    .unwrap()
+}
+
+/// Creates a 1x1 `sRGB` texture of a single 8bit RGBA color
+pub fn solid_texture_srgb<F: glium::backend::Facade>(
+    color: [u8; 4],
+    facade: &F,
+) -> glium::texture::SrgbTexture2d {
+    glium::texture::SrgbTexture2d::new(
+        facade,
+        glium::texture::RawImage2d::from_raw_rgba(color.to_vec(), (1, 1)),
+    )
+    .unwrap()
+}
+
 /// Loads a texture with 16bit RGB with mipmaps. Image is reversed
 pub fn load_texture_hdr<F: glium::backend::Facade>(
     path: &str,
@@ -147,48 +280,3 @@ where
     }
     cubemap
 }
-
-pub fn gen_cloud_noise_vol<F: glium::backend::Facade>(
-    width: u32,
-    height: u32,
-    depth: u32,
-    facade: &F,
-) -> glium::texture::Texture3d {
-    use cgmath::*;
-    use noise::*;
-    let mut data = Vec::new();
-    let voxels_per_slice = width * height;
-    //let fbm = Fbm::new();
-    let perlin = Perlin::new();
-
-    let scale = 0.1f64;
-    let remap = |x| (x + 1.0) * 0.5;
-    for idx in 0..width * height * depth {
-        let x = f64::from(idx % width);
-        let y = (f64::from(idx % voxels_per_slice) / f64::from(width)).floor();
-        let z = (f64::from(idx) / f64::from(voxels_per_slice)).floor();
-
-        let pt = cgmath::vec3(
-            x / f64::from(width),
-            y / f64::from(height),
-            z / f64::from(depth),
-        );
-
-        let /*mut*/ noise = remap(perlin.get([x * scale, y * scale, z * scale]));
-        /*noise *= remap(fbm.get([ pt.x * 100., pt.y * 100., pt.z * 100. ]));
-        noise *= remap(fbm.get([pt.x * 10., pt.y * 10., pt.z * 10.]));
-        noise *= 4.0;*/
-
-        let dist = (1.0 - (pt - vec3(0.5, 0.5, 0.5)).magnitude()).max(0.0);
-
-        data.push((noise * dist * dist * 255.).round() as u8);
-    }
-    let img = glium::texture::RawImage3d {
-        data: std::borrow::Cow::Owned(data),
-        width,
-        height,
-        depth,
-        format: glium::texture::ClientFormat::U8,
-    };
-    glium::texture::Texture3d::new(facade, img).unwrap()
-}
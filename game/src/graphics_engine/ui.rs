@@ -0,0 +1,738 @@
+use super::assets::AssetHandle;
+use super::entity::*;
+use super::shader;
+use super::text::{Font, Icon, Text};
+use crate::cg_support::node::Node;
+use cgmath::*;
+use glium::texture::SrgbTexture2d;
+use glutin::event::{ElementState, MouseButton, WindowEvent};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// The point of a container that a widget is placed relative to
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[allow(dead_code)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    /// Gets the normalized position of the anchor within a rectangle where
+    /// `(0, 0)` is the bottom left corner and `(1, 1)` is the top right
+    const fn factors(self) -> (f64, f64) {
+        use Anchor::*;
+        match self {
+            TopLeft => (0., 1.),
+            Top => (0.5, 1.),
+            TopRight => (1., 1.),
+            Left => (0., 0.5),
+            Center => (0.5, 0.5),
+            Right => (1., 0.5),
+            BottomLeft => (0., 0.),
+            Bottom => (0.5, 0.),
+            BottomRight => (1., 0.),
+        }
+    }
+}
+
+/// An axis aligned rectangle in normalized device coordinates
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct UiRect {
+    /// Center of the rectangle
+    pub center: Point2<f64>,
+    /// Half the width and height of the rectangle
+    pub half_size: Vector2<f64>,
+}
+
+impl UiRect {
+    /// The rectangle covering the entire screen
+    pub const fn screen() -> Self {
+        Self {
+            center: Point2::new(0., 0.),
+            half_size: Vector2::new(1., 1.),
+        }
+    }
+
+    /// `true` if `pt` (in NDC) is inside this rectangle
+    pub fn contains(&self, pt: Point2<f64>) -> bool {
+        (pt.x - self.center.x).abs() <= self.half_size.x
+            && (pt.y - self.center.y).abs() <= self.half_size.y
+    }
+
+    /// Gets the position of the given anchor of this rectangle
+    fn anchor_pt(&self, anchor: Anchor) -> Point2<f64> {
+        let (fx, fy) = anchor.factors();
+        point2(
+            (fx * 2.0 - 1.0).mul_add(self.half_size.x, self.center.x),
+            (fy * 2.0 - 1.0).mul_add(self.half_size.y, self.center.y),
+        )
+    }
+
+    /// Gets a node which transforms the unit square (`-1` to `1`) onto this
+    /// rectangle
+    fn to_node(self, depth: f64) -> Node {
+        Node::default()
+            .pos(point3(self.center.x, self.center.y, depth))
+            .scale(vec3(self.half_size.x, self.half_size.y, 1.))
+    }
+}
+
+/// Describes where a widget is placed within its parent
+///
+/// Offsets and sizes are in pixels so that widgets keep their size and
+/// distance from the screen edges when the window is resized
+#[derive(Copy, Clone, Debug)]
+pub struct Layout {
+    anchor: Anchor,
+    offset: Vector2<f64>,
+    size: Vector2<f64>,
+}
+
+impl Layout {
+    /// Creates a new layout anchored at `anchor` of the widget's parent. The
+    /// same point of the widget is placed at that anchor.
+    pub const fn new(anchor: Anchor) -> Self {
+        Self {
+            anchor,
+            offset: Vector2::new(0., 0.),
+            size: Vector2::new(0., 0.),
+        }
+    }
+
+    /// Sets the offset, in pixels, of the widget from its anchor. Positive
+    /// offsets move the widget towards the center of its parent. If the anchor
+    /// is centered along an axis, positive offsets move the widget right or up
+    #[must_use]
+    pub const fn offset(mut self, x: f64, y: f64) -> Self {
+        self.offset = Vector2::new(x, y);
+        self
+    }
+
+    /// Sets the width and height of the widget, in pixels
+    #[must_use]
+    pub const fn size(mut self, width: f64, height: f64) -> Self {
+        self.size = Vector2::new(width, height);
+        self
+    }
+
+    /// Computes the screen space rectangle of a widget with this layout
+    ///
+    /// `parent` - the rectangle of the widget's container
+    ///
+    /// `screen` - the width and height of the screen in pixels
+    pub fn resolve(&self, parent: &UiRect, screen: (u32, u32)) -> UiRect {
+        let px_to_ndc = vec2(
+            2. / f64::from(screen.0.max(1)),
+            2. / f64::from(screen.1.max(1)),
+        );
+        let half_size =
+            vec2(self.size.x * px_to_ndc.x, self.size.y * px_to_ndc.y) / 2.;
+        let (fx, fy) = self.anchor.factors();
+        // direction towards the center of the parent
+        let inward = vec2(1. - fx * 2., 1. - fy * 2.);
+        let anchor = parent.anchor_pt(self.anchor);
+        let offset = vec2(
+            self.offset.x * px_to_ndc.x * offset_dir(inward.x),
+            self.offset.y * px_to_ndc.y * offset_dir(inward.y),
+        );
+        UiRect {
+            center: anchor
+                + offset
+                + vec2(inward.x * half_size.x, inward.y * half_size.y),
+            half_size,
+        }
+    }
+}
+
+/// Gets the direction a positive offset moves a widget along an axis, given
+/// the direction towards the center of the parent along that axis
+fn offset_dir(inward: f64) -> f64 {
+    if inward.abs() < f64::EPSILON {
+        1.
+    } else {
+        inward.signum()
+    }
+}
+
+/// A retained mode UI element
+pub trait Widget {
+    /// Recomputes the position of this widget and its children
+    ///
+    /// `parent` - the screen space rectangle of the parent container
+    ///
+    /// `screen` - the size of the screen in pixels
+    fn layout(&mut self, parent: &UiRect, screen: (u32, u32));
+
+    /// Gets the screen space rectangle of this widget, computed during the last
+    /// layout
+    fn bounds(&self) -> UiRect;
+
+    /// Handles a mouse click at `pt` (NDC). Returns `true` if the click was
+    /// consumed by this widget
+    fn on_click(&mut self, _pt: Point2<f64>) -> bool {
+        false
+    }
+
+    /// Gets the entities that must be rendered to display this widget
+    fn entities(&self) -> Vec<Rc<RefCell<dyn AbstractEntity>>>;
+}
+
+/// A container which positions its children relative to itself
+pub struct Panel {
+    layout: Layout,
+    rect: UiRect,
+    children: Vec<Rc<RefCell<dyn Widget>>>,
+}
+
+impl Panel {
+    pub fn new(layout: Layout) -> Self {
+        Self {
+            layout,
+            rect: UiRect::screen(),
+            children: Vec::new(),
+        }
+    }
+
+    /// Adds a child widget to this panel
+    #[must_use]
+    pub fn with_child(mut self, child: Rc<RefCell<dyn Widget>>) -> Self {
+        self.children.push(child);
+        self
+    }
+
+    pub fn add_child(&mut self, child: Rc<RefCell<dyn Widget>>) {
+        self.children.push(child);
+    }
+}
+
+impl Widget for Panel {
+    fn layout(&mut self, parent: &UiRect, screen: (u32, u32)) {
+        self.rect = self.layout.resolve(parent, screen);
+        for child in &self.children {
+            child.borrow_mut().layout(&self.rect, screen);
+        }
+    }
+
+    fn bounds(&self) -> UiRect {
+        self.rect
+    }
+
+    fn on_click(&mut self, pt: Point2<f64>) -> bool {
+        self.rect.contains(pt)
+            && self
+                .children
+                .iter()
+                .any(|child| child.borrow_mut().on_click(pt))
+    }
+
+    fn entities(&self) -> Vec<Rc<RefCell<dyn AbstractEntity>>> {
+        self.children
+            .iter()
+            .flat_map(|child| child.borrow().entities())
+            .collect()
+    }
+}
+
+/// A widget that displays a single texture stretched over its area
+pub struct ImagePanel {
+    layout: Layout,
+    rect: UiRect,
+    transform: Rc<RefCell<Node>>,
+    entity: Rc<RefCell<Entity>>,
+}
+
+impl ImagePanel {
+    pub fn new<F: glium::backend::Facade>(
+        tex_path: &str,
+        layout: Layout,
+        facade: &F,
+    ) -> Self {
+        Self::from_icon(Icon::new(tex_path, facade), layout)
+    }
+
+    /// Creates an image panel whose texture may still be loading
+    pub fn from_handle<F: glium::backend::Facade>(
+        tex: AssetHandle<SrgbTexture2d>,
+        layout: Layout,
+        facade: &F,
+    ) -> Self {
+        Self::from_icon(Icon::from_handle(tex, facade), layout)
+    }
+
+    fn from_icon(icon: Icon, layout: Layout) -> Self {
+        let transform = Rc::new(RefCell::new(Node::default()));
+        let entity = EntityBuilder::new(icon)
+            .with_pass(shader::RenderPassType::Visual)
+            .build();
+        let entity = Rc::new(RefCell::new(entity));
+        entity.borrow_mut().locations.push(transform.clone());
+        Self {
+            layout,
+            rect: UiRect::screen(),
+            transform,
+            entity,
+        }
+    }
+}
+
+impl Widget for ImagePanel {
+    fn layout(&mut self, parent: &UiRect, screen: (u32, u32)) {
+        self.rect = self.layout.resolve(parent, screen);
+        *self.transform.borrow_mut() = self.rect.to_node(0.);
+    }
+
+    fn bounds(&self) -> UiRect {
+        self.rect
+    }
+
+    fn entities(&self) -> Vec<Rc<RefCell<dyn AbstractEntity>>> {
+        vec![self.entity.clone()]
+    }
+}
+
+/// An image centered on a point of the screen, such as a reticle that
+/// follows an object in the world. The marker is hidden until it's given a
+/// position
+pub struct Marker {
+    image: ImagePanel,
+    /// Text displayed below the marker
+    label: Option<Label>,
+    /// Center of the marker in NDC
+    pos: Option<Point2<f64>>,
+    screen: (u32, u32),
+}
+
+impl Marker {
+    /// Creates a new marker which is `width` by `height` pixels
+    pub fn new<F: glium::backend::Facade>(
+        tex_path: &str,
+        width: f64,
+        height: f64,
+        facade: &F,
+    ) -> Self {
+        Self {
+            image: ImagePanel::new(
+                tex_path,
+                Layout::new(Anchor::Center).size(width, height),
+                facade,
+            ),
+            label: None,
+            pos: None,
+            screen: (1, 1),
+        }
+    }
+
+    /// Adds a label below the marker which moves with it
+    ///
+    /// `height` - height of the label's text, in pixels
+    #[must_use]
+    pub fn with_label<F: glium::backend::Facade>(
+        mut self,
+        font: Rc<Font>,
+        height: f64,
+        color: [f32; 4],
+        facade: &F,
+    ) -> Self {
+        self.label = Some(Label::new(
+            font,
+            Layout::new(Anchor::Bottom)
+                .offset(0., -height * 1.2)
+                .size(height * 6., height),
+            color,
+            facade,
+        ));
+        self
+    }
+
+    /// Changes the text of the marker's label. Does nothing if the marker
+    /// has no label
+    pub fn set_label_text(&mut self, txt: &str) {
+        if let Some(label) = &mut self.label {
+            label.set_text(txt);
+        }
+    }
+
+    /// Resizes the marker to `width` by `height` pixels
+    pub fn set_size(&mut self, width: f64, height: f64) {
+        self.image.layout = self.image.layout.size(width, height);
+        self.update_transform();
+    }
+
+    /// Moves the center of the marker to `pos` (NDC), or hides the marker if
+    /// `pos` is `None`
+    pub fn set_position(&mut self, pos: Option<Point2<f64>>) {
+        self.pos = pos;
+        self.update_transform();
+    }
+
+    fn update_transform(&mut self) {
+        let size = self.image.layout.resolve(&UiRect::screen(), self.screen);
+        self.image.rect = UiRect {
+            center: self.pos.unwrap_or(size.center),
+            half_size: size.half_size,
+        };
+        *self.image.transform.borrow_mut() = if self.pos.is_some() {
+            self.image.rect.to_node(0.)
+        } else {
+            Node::default().scale(vec3(0., 0., 0.))
+        };
+        if let Some(label) = &mut self.label {
+            if self.pos.is_some() {
+                label.layout(&self.image.rect, self.screen);
+            } else if label.rect.half_size.y > 0. {
+                // a zero height label has no visible text
+                label.rect.half_size = vec2(0., 0.);
+                label.rebuild();
+            }
+        }
+    }
+}
+
+impl Widget for Marker {
+    fn layout(&mut self, _: &UiRect, screen: (u32, u32)) {
+        self.screen = screen;
+        self.update_transform();
+    }
+
+    fn bounds(&self) -> UiRect {
+        self.image.rect
+    }
+
+    fn entities(&self) -> Vec<Rc<RefCell<dyn AbstractEntity>>> {
+        self.image
+            .entities()
+            .into_iter()
+            .chain(self.label.iter().flat_map(|label| label.entities()))
+            .collect()
+    }
+}
+
+/// A single line of text. The text is scaled to the height of the label
+/// and starts at the left edge of the label
+pub struct Label {
+    layout: Layout,
+    rect: UiRect,
+    text: Rc<RefCell<Text>>,
+    string: String,
+    color: [f32; 4],
+}
+
+impl Label {
+    pub fn new<F: glium::backend::Facade>(
+        font: Rc<Font>,
+        layout: Layout,
+        color: [f32; 4],
+        facade: &F,
+    ) -> Self {
+        Self {
+            layout,
+            rect: UiRect::screen(),
+            text: Rc::new(RefCell::new(Text::new(font, facade))),
+            string: String::new(),
+            color,
+        }
+    }
+
+    /// Changes the displayed text. Does nothing if the text is unchanged
+    pub fn set_text(&mut self, txt: &str) {
+        if self.string != txt {
+            self.string = txt.to_owned();
+            self.rebuild();
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn set_color(&mut self, color: [f32; 4]) {
+        self.color = color;
+        self.rebuild();
+    }
+
+    /// Regenerates the text glyphs from the current string and layout
+    fn rebuild(&mut self) {
+        let mut text = self.text.borrow_mut();
+        text.clear_text();
+        let origin = self.rect.anchor_pt(Anchor::Left);
+        text.add_text(
+            &self.string,
+            &Rc::new(RefCell::new(
+                Node::default()
+                    .u_scale(self.rect.half_size.y * 2.)
+                    .pos(point3(origin.x, origin.y, 0.1)),
+            )),
+            self.color,
+        );
+    }
+}
+
+impl Widget for Label {
+    fn layout(&mut self, parent: &UiRect, screen: (u32, u32)) {
+        self.rect = self.layout.resolve(parent, screen);
+        self.rebuild();
+    }
+
+    fn bounds(&self) -> UiRect {
+        self.rect
+    }
+
+    fn entities(&self) -> Vec<Rc<RefCell<dyn AbstractEntity>>> {
+        vec![self.text.clone()]
+    }
+}
+
+/// A clickable image with an optional label
+pub struct Button<'a> {
+    image: ImagePanel,
+    label: Option<Label>,
+    on_click: Box<dyn FnMut() + 'a>,
+}
+
+impl<'a> Button<'a> {
+    /// Creates a new button
+    ///
+    /// `on_click` - callback invoked when the button is clicked
+    pub fn new<F: glium::backend::Facade, C: FnMut() + 'a>(
+        tex_path: &str,
+        layout: Layout,
+        on_click: C,
+        facade: &F,
+    ) -> Self {
+        Self {
+            image: ImagePanel::new(tex_path, layout, facade),
+            label: None,
+            on_click: Box::new(on_click),
+        }
+    }
+
+    /// Adds a label which is centered vertically over the button
+    #[must_use]
+    pub fn with_label<F: glium::backend::Facade>(
+        mut self,
+        font: Rc<Font>,
+        txt: &str,
+        color: [f32; 4],
+        facade: &F,
+    ) -> Self {
+        let size = self.image.layout.size;
+        let mut label = Label::new(
+            font,
+            Layout::new(Anchor::Left)
+                .offset(size.y * 0.25, 0.)
+                .size(size.x - size.y * 0.5, size.y * 0.5),
+            color,
+            facade,
+        );
+        label.set_text(txt);
+        self.label = Some(label);
+        self
+    }
+
+    /// Changes the text of the button's label. Does nothing if the button
+    /// has no label
+    pub fn set_label_text(&mut self, txt: &str) {
+        if let Some(label) = &mut self.label {
+            label.set_text(txt);
+        }
+    }
+}
+
+impl<'a> Widget for Button<'a> {
+    fn layout(&mut self, parent: &UiRect, screen: (u32, u32)) {
+        self.image.layout(parent, screen);
+        if let Some(label) = &mut self.label {
+            label.layout(&self.image.rect, screen);
+        }
+    }
+
+    fn bounds(&self) -> UiRect {
+        self.image.rect
+    }
+
+    fn on_click(&mut self, pt: Point2<f64>) -> bool {
+        if self.image.rect.contains(pt) {
+            (self.on_click)();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn entities(&self) -> Vec<Rc<RefCell<dyn AbstractEntity>>> {
+        self.image
+            .entities()
+            .into_iter()
+            .chain(self.label.iter().flat_map(|label| label.entities()))
+            .collect()
+    }
+}
+
+/// The root of a widget hierarchy which covers the entire screen
+///
+/// Tracks the screen size and cursor position from window events
+/// to lay out and dispatch clicks to its widgets
+pub struct UiRoot<'a> {
+    widgets: Vec<Rc<RefCell<dyn Widget + 'a>>>,
+    screen: (u32, u32),
+    cursor: Point2<f64>,
+}
+
+impl<'a> UiRoot<'a> {
+    pub fn new(screen_width: u32, screen_height: u32) -> Self {
+        Self {
+            widgets: Vec::new(),
+            screen: (screen_width, screen_height),
+            cursor: point2(0., 0.),
+        }
+    }
+
+    /// Adds a widget to the root and lays it out
+    pub fn add<W: Widget + 'a>(&mut self, widget: W) -> Rc<RefCell<W>> {
+        let widget = Rc::new(RefCell::new(widget));
+        widget.borrow_mut().layout(&UiRect::screen(), self.screen);
+        self.widgets.push(widget.clone());
+        widget
+    }
+
+    /// Recomputes the layout of all widgets for a new screen size
+    pub fn resize(&mut self, screen_width: u32, screen_height: u32) {
+        self.screen = (screen_width, screen_height);
+        for widget in &self.widgets {
+            widget.borrow_mut().layout(&UiRect::screen(), self.screen);
+        }
+    }
+
+    /// Gets the position of the cursor in the normalized device coordinates
+    /// of the screen
+    pub const fn cursor(&self) -> Point2<f64> {
+        self.cursor
+    }
+
+    /// Dispatches a click at the current cursor position
+    ///
+    /// Returns `true` if any widget consumed the click
+    pub fn click(&mut self) -> bool {
+        let pt = self.cursor;
+        self.widgets
+            .iter()
+            .rev()
+            .any(|widget| widget.borrow_mut().on_click(pt))
+    }
+
+    /// Handles window events to track the cursor, clicks, and resizing
+    ///
+    /// Returns `true` if the event was consumed by the UI
+    pub fn on_window_event(&mut self, ev: &WindowEvent) -> bool {
+        match ev {
+            WindowEvent::Resized(size) if size.height != 0 => {
+                self.resize(size.width, size.height);
+                false
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor = point2(
+                    position.x / f64::from(self.screen.0.max(1)) * 2. - 1.,
+                    1. - position.y / f64::from(self.screen.1.max(1)) * 2.,
+                );
+                false
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } => self.click(),
+            _ => false,
+        }
+    }
+
+    /// Gets all entities required to render the UI
+    pub fn entities(&self) -> Vec<Rc<RefCell<dyn AbstractEntity>>> {
+        self.widgets
+            .iter()
+            .flat_map(|widget| widget.borrow().entities())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A 200 by 100 pixel screen, where a pixel is `0.01` wide and `0.02`
+    /// tall in NDC
+    const SCREEN: (u32, u32) = (200, 100);
+
+    fn assert_rect_near(rect: UiRect, center: (f64, f64), half: (f64, f64)) {
+        let near = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        assert!(
+            near(rect.center.x, center.0)
+                && near(rect.center.y, center.1)
+                && near(rect.half_size.x, half.0)
+                && near(rect.half_size.y, half.1),
+            "{:?} is not centered at {:?} with half size {:?}",
+            rect,
+            center,
+            half
+        );
+    }
+
+    #[test]
+    fn widgets_are_placed_inside_each_anchor() {
+        use Anchor::*;
+        let expected = [
+            (TopLeft, (-0.9, 0.9)),
+            (Top, (0., 0.9)),
+            (TopRight, (0.9, 0.9)),
+            (Left, (-0.9, 0.)),
+            (Center, (0., 0.)),
+            (Right, (0.9, 0.)),
+            (BottomLeft, (-0.9, -0.9)),
+            (Bottom, (0., -0.9)),
+            (BottomRight, (0.9, -0.9)),
+        ];
+        for (anchor, center) in expected {
+            let rect = Layout::new(anchor)
+                .size(20., 10.)
+                .resolve(&UiRect::screen(), SCREEN);
+            assert_rect_near(rect, center, (0.1, 0.1));
+        }
+        let parent = UiRect {
+            center: point2(0.5, 0.5),
+            half_size: vec2(0.5, 0.5),
+        };
+        let rect = Layout::new(TopLeft).size(20., 10.).resolve(&parent, SCREEN);
+        assert_rect_near(rect, (0.1, 0.9), (0.1, 0.1));
+    }
+
+    #[test]
+    fn offsets_move_widgets_inward() {
+        let resolve = |anchor| {
+            Layout::new(anchor)
+                .size(20., 10.)
+                .offset(10., 5.)
+                .resolve(&UiRect::screen(), SCREEN)
+        };
+        assert_rect_near(resolve(Anchor::TopRight), (0.8, 0.8), (0.1, 0.1));
+        assert_rect_near(resolve(Anchor::BottomLeft), (-0.8, -0.8), (0.1, 0.1));
+        // centered axes are offset right or up
+        assert_rect_near(resolve(Anchor::Center), (0.1, 0.1), (0.1, 0.1));
+        assert_rect_near(resolve(Anchor::Bottom), (0.1, -0.8), (0.1, 0.1));
+    }
+
+    #[test]
+    fn widgets_keep_their_pixel_size_when_resized() {
+        let layout =
+            Layout::new(Anchor::TopRight).size(20., 10.).offset(10., 5.);
+        let small = layout.resolve(&UiRect::screen(), SCREEN);
+        assert_rect_near(small, (0.8, 0.8), (0.1, 0.1));
+        // twice the pixels makes the widget and its offset half as large in
+        // NDC, so it stays 10 pixels from the edge
+        let large = layout.resolve(&UiRect::screen(), (400, 200));
+        assert_rect_near(large, (0.9, 0.9), (0.05, 0.05));
+    }
+}
@@ -0,0 +1,336 @@
+use noise::{NoiseFn, Perlin, Seedable};
+
+/// The basis function of a noise layer
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[allow(dead_code)]
+pub enum NoiseKind {
+    /// Smooth gradient noise, good for wispy detail
+    Perlin,
+    /// Inverted cellular noise, good for billowy, puffy shapes
+    Worley,
+}
+
+/// A single layer of fractal noise in a volume
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct NoiseLayer {
+    pub kind: NoiseKind,
+    /// Frequency of the first octave, in cycles across the volume
+    pub frequency: f64,
+    pub octaves: u32,
+    /// Amplitude multiplier from one octave to the next
+    pub persistence: f64,
+    /// Frequency multiplier from one octave to the next
+    pub lacunarity: f64,
+    /// Contribution of this layer relative to the other layers
+    pub weight: f64,
+}
+
+impl NoiseLayer {
+    /// Creates a layer with a single octave of noise
+    pub const fn new(kind: NoiseKind, frequency: f64) -> Self {
+        Self {
+            kind,
+            frequency,
+            octaves: 1,
+            persistence: 0.5,
+            lacunarity: 2.,
+            weight: 1.,
+        }
+    }
+
+    /// Sets the amount of octaves, where each octave has `persistence` times
+    /// the amplitude and `lacunarity` times the frequency of the previous one
+    #[must_use]
+    pub const fn octaves(
+        mut self,
+        octaves: u32,
+        persistence: f64,
+        lacunarity: f64,
+    ) -> Self {
+        self.octaves = octaves;
+        self.persistence = persistence;
+        self.lacunarity = lacunarity;
+        self
+    }
+
+    #[must_use]
+    pub const fn weight(mut self, weight: f64) -> Self {
+        self.weight = weight;
+        self
+    }
+}
+
+/// A curve that remaps the combined noise, which is between `0` and `1`,
+/// to a density, also between `0` and `1`
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[allow(dead_code)]
+pub enum DensityCurve {
+    Linear,
+    /// Smoothly remaps `low .. high` to `0 .. 1`, clamping values outside
+    /// the range. Raising `low` carves empty space between clouds
+    Smoothstep(f64, f64),
+    /// Raises the noise to a power. Exponents above `1` thin out the volume
+    Power(f64),
+}
+
+impl DensityCurve {
+    pub fn apply(self, x: f64) -> f64 {
+        match self {
+            Self::Linear => x,
+            Self::Smoothstep(low, high) => {
+                let t = ((x - low) / (high - low)).clamp(0., 1.);
+                t * t * (3. - 2. * t)
+            }
+            Self::Power(exp) => x.powf(exp),
+        }
+    }
+}
+
+/// Generates 3D density volumes from layered noise, for use by volumetric
+/// clouds and nebulas
+pub struct VolumeGenerator {
+    size: (u32, u32, u32),
+    layers: Vec<NoiseLayer>,
+    curve: DensityCurve,
+    spherical_falloff: bool,
+    seed: u32,
+}
+
+impl VolumeGenerator {
+    /// Creates a generator for a volume of `width x height x depth` voxels
+    /// with no noise layers
+    pub const fn new(width: u32, height: u32, depth: u32) -> Self {
+        Self {
+            size: (width, height, depth),
+            layers: Vec::new(),
+            curve: DensityCurve::Linear,
+            spherical_falloff: false,
+            seed: 0,
+        }
+    }
+
+    /// A cube volume of side length `size` with billowy worley shapes broken
+    /// up by perlin detail, fading out towards the edges of the volume
+    pub fn nebula(size: u32) -> Self {
+        Self::new(size, size, size)
+            .layer(
+                NoiseLayer::new(NoiseKind::Worley, 4.)
+                    .octaves(3, 0.5, 2.)
+                    .weight(0.65),
+            )
+            .layer(
+                NoiseLayer::new(NoiseKind::Perlin, 8.)
+                    .octaves(4, 0.5, 2.)
+                    .weight(0.35),
+            )
+            .remap(DensityCurve::Smoothstep(0.35, 0.8))
+            .spherical_falloff(true)
+    }
+
+    /// Adds a layer of noise. The density of the volume is the weighted
+    /// average of all layers
+    #[must_use]
+    pub fn layer(mut self, layer: NoiseLayer) -> Self {
+        self.layers.push(layer);
+        self
+    }
+
+    /// Sets the curve applied to the combined noise of all layers
+    #[must_use]
+    pub const fn remap(mut self, curve: DensityCurve) -> Self {
+        self.curve = curve;
+        self
+    }
+
+    /// If `true`, the density falls off to `0` at a distance of `0.5` from the
+    /// center of the volume so the volume has no visible edges
+    #[must_use]
+    pub const fn spherical_falloff(mut self, falloff: bool) -> Self {
+        self.spherical_falloff = falloff;
+        self
+    }
+
+    #[must_use]
+    pub const fn seed(mut self, seed: u32) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Gets the value of a layer at `pt`, between `0` and `1`
+    fn sample_layer(
+        &self,
+        perlin: &Perlin,
+        layer: &NoiseLayer,
+        pt: [f64; 3],
+    ) -> f64 {
+        let mut freq = layer.frequency;
+        let mut amplitude = 1.;
+        let mut total = 0.;
+        let mut max_total = 0.;
+        for octave in 0..layer.octaves {
+            let p = [pt[0] * freq, pt[1] * freq, pt[2] * freq];
+            let val = match layer.kind {
+                NoiseKind::Perlin => (perlin.get(p) + 1.) * 0.5,
+                NoiseKind::Worley => {
+                    1. - worley(p, self.seed.wrapping_add(octave))
+                }
+            };
+            total += val * amplitude;
+            max_total += amplitude;
+            amplitude *= layer.persistence;
+            freq *= layer.lacunarity;
+        }
+        if max_total > 0. {
+            (total / max_total).clamp(0., 1.)
+        } else {
+            0.
+        }
+    }
+
+    /// Gets the density at `pt`, where each component of `pt` is between
+    /// `0` and `1`
+    pub fn density_at(&self, pt: [f64; 3]) -> f64 {
+        let perlin = Perlin::new().set_seed(self.seed);
+        self.density_with(&perlin, pt)
+    }
+
+    fn density_with(&self, perlin: &Perlin, pt: [f64; 3]) -> f64 {
+        let total_weight: f64 = self.layers.iter().map(|l| l.weight).sum();
+        if total_weight <= 0. {
+            return 0.;
+        }
+        let noise = self
+            .layers
+            .iter()
+            .map(|layer| self.sample_layer(perlin, layer, pt) * layer.weight)
+            .sum::<f64>()
+            / total_weight;
+        let density = self.curve.apply(noise);
+        if self.spherical_falloff {
+            let dist =
+                pt.iter().map(|x| (x - 0.5) * (x - 0.5)).sum::<f64>().sqrt();
+            let falloff = (1. - dist * 2.).max(0.);
+            density * falloff * falloff
+        } else {
+            density
+        }
+    }
+
+    /// Generates the density of every voxel, stored in x, then y, then z order
+    pub fn generate_data(&self) -> Vec<u8> {
+        let perlin = Perlin::new().set_seed(self.seed);
+        let (width, height, depth) = self.size;
+        let mut data = Vec::with_capacity((width * height * depth) as usize);
+        for z in 0..depth {
+            for y in 0..height {
+                for x in 0..width {
+                    let pt = [
+                        (f64::from(x) + 0.5) / f64::from(width),
+                        (f64::from(y) + 0.5) / f64::from(height),
+                        (f64::from(z) + 0.5) / f64::from(depth),
+                    ];
+                    let density = self.density_with(&perlin, pt);
+                    data.push((density * 255.).round() as u8);
+                }
+            }
+        }
+        data
+    }
+
+    /// Generates the volume as a single channel texture
+    pub fn generate<F: glium::backend::Facade>(
+        &self,
+        facade: &F,
+    ) -> glium::texture::Texture3d {
+        let (width, height, depth) = self.size;
+        let img = glium::texture::RawImage3d {
+            data: std::borrow::Cow::Owned(self.generate_data()),
+            width,
+            height,
+            depth,
+            format: glium::texture::ClientFormat::U8,
+        };
+        glium::texture::Texture3d::new(facade, img).unwrap()
+    }
+}
+
+/// Hashes a cell coordinate to a pseudo-random point within the cell, with
+/// each component between `0` and `1`
+fn cell_point(cell: [i64; 3], seed: u32) -> [f64; 3] {
+    let mut h = u64::from(seed).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    let mut out = [0.; 3];
+    for (c, o) in cell.iter().zip(out.iter_mut()) {
+        h ^= (*c as u64).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        h = (h ^ (h >> 31)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        h ^= h >> 29;
+        *o = (h >> 11) as f64 / (1u64 << 53) as f64;
+    }
+    out
+}
+
+/// Gets the distance from `pt` to the closest feature point, clamped to `1`
+fn worley(pt: [f64; 3], seed: u32) -> f64 {
+    let base = [
+        pt[0].floor() as i64,
+        pt[1].floor() as i64,
+        pt[2].floor() as i64,
+    ];
+    let mut min_dist = f64::MAX;
+    for dx in -1..=1 {
+        for dy in -1..=1 {
+            for dz in -1..=1 {
+                let cell = [base[0] + dx, base[1] + dy, base[2] + dz];
+                let feature = cell_point(cell, seed);
+                let dist = (0..3)
+                    .map(|i| {
+                        let d = cell[i] as f64 + feature[i] - pt[i];
+                        d * d
+                    })
+                    .sum::<f64>();
+                min_dist = min_dist.min(dist);
+            }
+        }
+    }
+    min_dist.sqrt().min(1.)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn density_curves() {
+        let smooth = DensityCurve::Smoothstep(0.2, 0.6);
+        assert_approx_eq!(DensityCurve::Linear.apply(0.3), 0.3);
+        assert_approx_eq!(smooth.apply(0.1), 0.);
+        assert_approx_eq!(smooth.apply(0.7), 1.);
+        assert_approx_eq!(smooth.apply(0.4), 0.5);
+        assert_approx_eq!(DensityCurve::Power(2.).apply(0.5), 0.25);
+    }
+
+    #[test]
+    fn worley_is_bounded_and_zero_at_features() {
+        let feature = cell_point([2, -1, 3], 7);
+        let pt = [2. + feature[0], -1. + feature[1], 3. + feature[2]];
+        assert!(worley(pt, 7) < 0.0001);
+        for i in 0..50 {
+            let x = f64::from(i) * 0.37;
+            let val = worley([x, x * 0.5, -x], 7);
+            assert!((0. ..=1.).contains(&val));
+        }
+    }
+
+    #[test]
+    fn volume_falls_off_at_edges() {
+        let gen = VolumeGenerator::nebula(8).seed(3);
+        let data = gen.generate_data();
+        assert_eq!(data.len(), 8 * 8 * 8);
+        assert_approx_eq!(gen.density_at([0., 0., 0.]), 0.);
+        assert_approx_eq!(gen.density_at([0.5, 0.5, 1.]), 0.);
+        assert!(VolumeGenerator::new(4, 4, 4)
+            .generate_data()
+            .iter()
+            .all(|x| *x == 0));
+    }
+}
@@ -1,14 +1,18 @@
+use super::assets::AssetManager;
 use super::scene::AbstractScene;
 use super::shader;
+use crate::profiler;
 use glium::Display;
+use glutin::dpi::PhysicalSize;
 use glutin::event::{Event, WindowEvent};
 use glutin::event_loop::{ControlFlow, EventLoop};
 use glutin::platform::run_return::EventLoopExtRunReturn;
-use glutin::window::WindowBuilder;
-use glutin::ContextBuilder;
-use std::cell::{RefCell, RefMut};
+use glutin::window::{Fullscreen, WindowBuilder};
+use glutin::{ContextBuilder, NotCurrent};
+use std::cell::{Cell, RefCell, RefMut};
 use std::rc::Rc;
 use std::time::{Duration, Instant};
+use tracing::warn;
 
 pub struct SceneManager {
     scenes: std::collections::HashMap<
@@ -16,6 +20,23 @@ pub struct SceneManager {
         Box<RefCell<dyn AbstractScene>>,
     >,
     active_scene: Option<&'static str>,
+    /// Scenes pushed on top of the active scene, the last is the topmost
+    overlays: Vec<&'static str>,
+}
+
+/// Renders a stack of scenes from bottom to top, passing the output of each
+/// scene as the input to the scene above it
+///
+/// Requires every scene but the last is rendering to a texture
+fn render_stack(
+    scenes: &mut [RefMut<dyn AbstractScene + 'static>],
+    inputs: Option<&Vec<super::pipeline::TextureType>>,
+    shader: &shader::ShaderManager,
+) {
+    if let Some((bottom, rest)) = scenes.split_first_mut() {
+        let out = bottom.render(inputs, shader).map(|tex| vec![tex]);
+        render_stack(rest, out.as_ref(), shader);
+    }
 }
 
 impl SceneManager {
@@ -23,12 +44,14 @@ impl SceneManager {
         Self {
             scenes: std::collections::HashMap::new(),
             active_scene: None,
+            overlays: Vec::new(),
         }
     }
 
-    /// Sets the active scene to `scene`
+    /// Sets the active scene to `scene`, removing any pushed scenes
     /// Requires that `scene` is a name of a scene managed by this manager
     pub fn change_scene(&mut self, scene: &'static str) -> &mut Self {
+        while self.pop_scene().is_some() {}
         self.active_scene = Some(scene);
         self
     }
@@ -43,20 +66,82 @@ impl SceneManager {
         self
     }
 
+    /// Pushes `scene` on top of the current scene. The scenes below it
+    /// continue to be rendered, underneath `scene`, but only the topmost
+    /// scene is passed to the draw callback
+    ///
+    /// Requires that `scene` is a name of a scene managed by this manager
+    /// and is not already on the stack
+    pub fn push_scene(&mut self, scene: &'static str) -> &mut Self {
+        assert!(
+            self.active_scene != Some(scene) && !self.overlays.contains(&scene),
+            "Scene '{}' is already on the scene stack",
+            scene
+        );
+        if let Some(top) = self.overlays.last().copied().or(self.active_scene) {
+            self.scenes[top].borrow_mut().set_render_to_screen(false);
+        }
+        self.overlays.push(scene);
+        self
+    }
+
+    /// Removes the topmost pushed scene, returning its name
+    ///
+    /// Returns `None` if there are no pushed scenes
+    pub fn pop_scene(&mut self) -> Option<&'static str> {
+        let popped = self.overlays.pop();
+        if popped.is_some() {
+            if let Some(top) =
+                self.overlays.last().copied().or(self.active_scene)
+            {
+                self.scenes[top].borrow_mut().set_render_to_screen(true);
+            }
+        }
+        popped
+    }
+
+    /// Gets the name of the topmost scene
+    pub fn top_scene_name(&self) -> Option<&'static str> {
+        self.overlays.last().copied().or(self.active_scene)
+    }
+
+    /// Gets the topmost scene, which is the only one that receives updates
     pub fn get_active_scene(
         &self,
     ) -> Option<RefMut<dyn AbstractScene + 'static>> {
-        self.active_scene
+        self.top_scene_name()
             .map(|x| self.scenes[x].as_ref().borrow_mut())
     }
+
+    /// Rebuilds the render targets of every scene for a screen of the given
+    /// size
+    fn resize(&self, width: u32, height: u32, ctx: &Display) {
+        for scene in self.scenes.values() {
+            scene.borrow_mut().resize(width, height, ctx);
+        }
+    }
+
+    /// Renders the scene stack, bottom first
+    fn render(&self, shader: &shader::ShaderManager) {
+        let mut stack: Vec<_> = self
+            .active_scene
+            .iter()
+            .chain(self.overlays.iter())
+            .map(|x| self.scenes[x].as_ref().borrow_mut())
+            .collect();
+        render_stack(&mut stack, None, shader);
+    }
 }
 
 pub struct WindowCallbacks<'a> {
     input_cb: Option<
         &'a mut dyn FnMut(glutin::event::DeviceEvent, RefMut<SceneManager>),
     >,
-    resize_cb: Option<&'a mut dyn FnMut(glutin::dpi::PhysicalSize<u32>)>,
+    resize_cb: Option<&'a mut dyn FnMut(PhysicalSize<u32>)>,
     draw_cb: Option<&'a mut dyn FnMut(Duration, RefMut<dyn AbstractScene>)>,
+    window_event_cb:
+        Option<&'a mut dyn FnMut(&WindowEvent, RefMut<SceneManager>)>,
+    update_cb: Option<&'a mut dyn FnMut(RefMut<SceneManager>)>,
 }
 
 impl<'a> WindowCallbacks<'a> {
@@ -65,6 +150,8 @@ impl<'a> WindowCallbacks<'a> {
             input_cb: None,
             resize_cb: None,
             draw_cb: None,
+            window_event_cb: None,
+            update_cb: None,
         }
     }
 
@@ -79,9 +166,11 @@ impl<'a> WindowCallbacks<'a> {
         self
     }
 
+    /// Sets a handler which is called once per frame when the window was
+    /// resized since the last frame, after the scenes were resized
     pub fn with_resize_handler(
         mut self,
-        on_resize: &'a mut dyn FnMut(glutin::dpi::PhysicalSize<u32>),
+        on_resize: &'a mut dyn FnMut(PhysicalSize<u32>),
     ) -> Self {
         self.resize_cb = Some(on_resize);
         self
@@ -94,65 +183,181 @@ impl<'a> WindowCallbacks<'a> {
         self.draw_cb = Some(on_draw);
         self
     }
+
+    /// Sets a handler which receives every window event before the window
+    /// processes it. Used for events relative to the window such as the
+    /// cursor position
+    pub fn with_window_event_handler(
+        mut self,
+        on_event: &'a mut dyn FnMut(&WindowEvent, RefMut<SceneManager>),
+    ) -> Self {
+        self.window_event_cb = Some(on_event);
+        self
+    }
+
+    /// Sets a handler which is called once per frame, before the scenes are
+    /// rendered. Used to change the displayed scenes
+    pub fn with_update_handler(
+        mut self,
+        on_update: &'a mut dyn FnMut(RefMut<SceneManager>),
+    ) -> Self {
+        self.update_cb = Some(on_update);
+        self
+    }
 }
 
 pub struct Window {
     wnd_ctx: Rc<RefCell<Display>>,
     e_loop: RefCell<EventLoop<()>>,
     scenes: RefCell<SceneManager>,
+    assets: RefCell<AssetManager>,
     pub shaders: Rc<shader::ShaderManager>,
+    title: Option<&'static str>,
+    msaa: Option<u16>,
+    depth_bits: Option<u8>,
+    vsync: Cell<bool>,
+    /// Vsync setting the context is recreated with after the current frame
+    pending_vsync: Cell<Option<bool>>,
 }
 
+/// Amount of threads used to load assets in the background
+const ASSET_WORKERS: usize = 2;
+
 impl Window {
     fn from_builder(builder: WindowMaker) -> Self {
         let e_loop = builder.e_loop.unwrap_or_else(EventLoop::new);
-        let mut window_builder = WindowBuilder::new()
-            .with_decorations(true)
-            .with_inner_size(glium::glutin::dpi::PhysicalSize::<u32> {
-                width: builder.width,
-                height: builder.height,
-            })
-            .with_visible(builder.visible);
-        if let Some(title) = builder.title {
-            window_builder = window_builder.with_title(title);
-        }
-        let mut wnd_ctx = ContextBuilder::new().with_srgb(true);
-        if let Some(depth) = builder.depth_bits {
-            wnd_ctx = wnd_ctx.with_depth_buffer(depth);
-        }
-        if let Some(msaa) = builder.msaa {
-            wnd_ctx = wnd_ctx.with_multisampling(msaa);
-        }
+        let window_builder = Self::window_builder(
+            builder.title,
+            PhysicalSize::new(builder.width, builder.height),
+            builder.fullscreen,
+        )
+        .with_visible(builder.visible);
+        let wnd_ctx = Self::context_builder(
+            builder.depth_bits,
+            builder.msaa,
+            builder.vsync,
+        );
         let wnd_ctx = Rc::new(RefCell::new(
             Display::new(window_builder, wnd_ctx, &e_loop).unwrap(),
         ));
         let shaders = Rc::new(shader::ShaderManager::init(&*wnd_ctx.borrow()));
         gl::load_with(|s| wnd_ctx.borrow().gl_window().get_proc_address(s));
         super::set_active_ctx(wnd_ctx.clone(), shaders.clone());
+        let assets =
+            RefCell::new(AssetManager::new(ASSET_WORKERS, &*wnd_ctx.borrow()));
 
         Self {
             e_loop: RefCell::new(e_loop),
+            assets,
             shaders,
             wnd_ctx,
             scenes: RefCell::new(SceneManager::new()),
+            title: builder.title,
+            msaa: builder.msaa,
+            depth_bits: builder.depth_bits,
+            vsync: Cell::new(builder.vsync),
+            pending_vsync: Cell::new(None),
+        }
+    }
+
+    fn window_builder(
+        title: Option<&'static str>,
+        size: PhysicalSize<u32>,
+        fullscreen: bool,
+    ) -> WindowBuilder {
+        let mut window_builder = WindowBuilder::new()
+            .with_decorations(true)
+            .with_inner_size(size)
+            .with_fullscreen(fullscreen.then(|| Fullscreen::Borderless(None)));
+        if let Some(title) = title {
+            window_builder = window_builder.with_title(title);
+        }
+        window_builder
+    }
+
+    fn context_builder(
+        depth_bits: Option<u8>,
+        msaa: Option<u16>,
+        vsync: bool,
+    ) -> ContextBuilder<'static, NotCurrent> {
+        let mut wnd_ctx =
+            ContextBuilder::new().with_srgb(true).with_vsync(vsync);
+        if let Some(depth) = depth_bits {
+            wnd_ctx = wnd_ctx.with_depth_buffer(depth);
+        }
+        if let Some(msaa) = msaa {
+            wnd_ctx = wnd_ctx.with_multisampling(msaa);
+        }
+        wnd_ctx
+    }
+
+    /// Recreates the window and its context with the vsync setting `vsync`.
+    /// The new context shares the old one's textures and buffers, so the
+    /// scenes and assets are kept
+    ///
+    /// Requires the event loop isn't running
+    fn rebuild(&self, vsync: bool) {
+        let ctx = self.wnd_ctx.borrow();
+        let (size, fullscreen) = {
+            let gl_window = ctx.gl_window();
+            let window = gl_window.window();
+            (window.inner_size(), window.fullscreen().is_some())
+        };
+        let window_builder = Self::window_builder(self.title, size, fullscreen);
+        let context_builder =
+            Self::context_builder(self.depth_bits, self.msaa, vsync);
+        match ctx.rebuild(
+            window_builder,
+            context_builder,
+            &*self.e_loop.borrow(),
+        ) {
+            Ok(()) => {
+                gl::load_with(|s| ctx.gl_window().get_proc_address(s));
+                self.vsync.set(vsync);
+            }
+            Err(e) => warn!("Could not change vsync: {}", e),
         }
     }
 
     /// Runs the main loop and blocks until the window is closed
     pub fn main_loop(&self, mut callbacks: WindowCallbacks) {
-        let shaders = self.shaders.clone();
         let mut last_time = Instant::now();
+        // the event loop stops whenever the context must be recreated, and
+        // is restarted with the new context
+        loop {
+            self.run_events(&mut callbacks, &mut last_time);
+            match self.pending_vsync.take() {
+                Some(vsync) => self.rebuild(vsync),
+                None => break,
+            }
+        }
+    }
+
+    /// Runs the event loop until the window is closed or the context must be
+    /// recreated
+    fn run_events(
+        &self,
+        callbacks: &mut WindowCallbacks,
+        last_time: &mut Instant,
+    ) {
+        let shaders = self.shaders.clone();
+        let mut new_size = None;
         self.e_loop.borrow_mut().run_return(|ev, _, control| {
             match ev {
-                Event::WindowEvent { event, .. } => match event {
-                    WindowEvent::CloseRequested => *control = ControlFlow::Exit,
-                    WindowEvent::Resized(new_size) => {
-                        if let Some(resize) = callbacks.resize_cb.as_mut() {
-                            resize(new_size);
+                Event::WindowEvent { event, .. } => {
+                    if let Some(cb) = callbacks.window_event_cb.as_mut() {
+                        cb(&event, self.scenes.borrow_mut());
+                    }
+                    match event {
+                        WindowEvent::CloseRequested => {
+                            *control = ControlFlow::Exit;
                         }
+                        // the render targets are rebuilt once per frame, as
+                        // many resizes can arrive while the window is dragged
+                        WindowEvent::Resized(size) => new_size = Some(size),
+                        _ => (),
                     }
-                    _ => (),
-                },
+                }
                 Event::DeviceEvent { event, .. }
                     if callbacks.input_cb.is_some() =>
                 {
@@ -163,27 +368,100 @@ impl Window {
                 }
                 Event::MainEventsCleared => {
                     let now = Instant::now();
-                    let dt = now.duration_since(last_time);
-                    last_time = now;
+                    let dt = now.duration_since(*last_time);
+                    *last_time = now;
+                    profiler::next_frame();
+                    let _frame =
+                        tracing::trace_span!("frame", dt = ?dt).entered();
 
-                    if let Some(mut active_scene) =
-                        self.scenes.borrow().get_active_scene()
+                    if let Some(size) = new_size.take() {
+                        // minimized windows have no area to render to
+                        if size.width > 0 && size.height > 0 {
+                            self.scenes.borrow().resize(
+                                size.width,
+                                size.height,
+                                &*self.wnd_ctx.borrow(),
+                            );
+                        }
+                        if let Some(resize) = callbacks.resize_cb.as_mut() {
+                            resize(size);
+                        }
+                    }
+                    self.assets
+                        .borrow_mut()
+                        .process_uploads(&*self.wnd_ctx.borrow());
+                    super::evict_unused_assets();
+                    if let Some(cb) = callbacks.update_cb.as_mut() {
+                        let _timer = profiler::scope("update");
+                        cb(self.scenes.borrow_mut());
+                    }
                     {
-                        (&mut *active_scene).render(None, &*shaders);
+                        let _timer = profiler::scope("render");
+                        self.scenes.borrow().render(&*shaders);
                     }
 
                     if let (Some(cb), Some(scene)) = (
                         &mut callbacks.draw_cb.as_mut(),
                         self.scenes.borrow().get_active_scene(),
                     ) {
+                        let _timer = profiler::scope("draw");
                         cb(dt, scene);
                     }
+                    if self.pending_vsync.get().is_some() {
+                        *control = ControlFlow::Exit;
+                    }
                 }
                 _ => (),
             };
         });
     }
 
+    /// Switches between borderless fullscreen on the current monitor and a
+    /// window. The scenes are resized once the window has changed size
+    pub fn set_fullscreen(&self, fullscreen: bool) {
+        self.wnd_ctx
+            .borrow()
+            .gl_window()
+            .window()
+            .set_fullscreen(fullscreen.then(|| Fullscreen::Borderless(None)));
+    }
+
+    /// `true` if the window is fullscreen
+    pub fn is_fullscreen(&self) -> bool {
+        self.wnd_ctx
+            .borrow()
+            .gl_window()
+            .window()
+            .fullscreen()
+            .is_some()
+    }
+
+    /// Resizes the window to `width` by `height` pixels, leaving fullscreen
+    /// if the window is fullscreen. The scenes are resized once the window
+    /// has changed size
+    pub fn set_size(&self, width: u32, height: u32) {
+        let ctx = self.wnd_ctx.borrow();
+        let gl_window = ctx.gl_window();
+        let window = gl_window.window();
+        window.set_fullscreen(None);
+        window.set_inner_size(PhysicalSize::new(width, height));
+    }
+
+    /// Turns vsync on or off. Changing it recreates the window and its
+    /// context after the current frame
+    pub fn set_vsync(&self, vsync: bool) {
+        if vsync == self.vsync.get() {
+            self.pending_vsync.set(None);
+        } else {
+            self.pending_vsync.set(Some(vsync));
+        }
+    }
+
+    /// `true` if vsync is on, or will be once the context is recreated
+    pub fn vsync(&self) -> bool {
+        self.pending_vsync.get().unwrap_or_else(|| self.vsync.get())
+    }
+
     pub fn scene_manager(&mut self) -> RefMut<SceneManager> {
         self.scenes.borrow_mut()
     }
@@ -191,6 +469,12 @@ impl Window {
     pub fn ctx(&self) -> std::cell::Ref<glium::Display> {
         self.wnd_ctx.borrow()
     }
+
+    /// Gets the asset manager, which loads assets in the background and
+    /// uploads them while the main loop is running
+    pub fn assets(&self) -> RefMut<AssetManager> {
+        self.assets.borrow_mut()
+    }
 }
 
 impl Drop for Window {
@@ -204,6 +488,10 @@ pub struct WindowMaker {
     height: u32,
     title: Option<&'static str>,
     visible: bool,
+    /// Covers the current monitor with a borderless window
+    fullscreen: bool,
+    /// Waits for the vertical blank before presenting each frame
+    vsync: bool,
     msaa: Option<u16>,
     depth_bits: Option<u8>,
     e_loop: Option<EventLoop<()>>,
@@ -217,6 +505,8 @@ impl WindowMaker {
             height,
             title: None,
             visible: true,
+            fullscreen: false,
+            vsync: false,
             msaa: None,
             depth_bits: None,
             e_loop: None,
@@ -235,6 +525,18 @@ impl WindowMaker {
         self
     }
 
+    #[must_use]
+    pub const fn fullscreen(mut self, fullscreen: bool) -> Self {
+        self.fullscreen = fullscreen;
+        self
+    }
+
+    #[must_use]
+    pub const fn vsync(mut self, vsync: bool) -> Self {
+        self.vsync = vsync;
+        self
+    }
+
     #[must_use]
     pub const fn msaa(mut self, samples: u16) -> Self {
         self.msaa = Some(samples);
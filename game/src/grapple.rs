@@ -0,0 +1,169 @@
+use crate::cg_support::node::Node;
+use crate::controls::Reel;
+use crate::physics::{Tether, TetherData, TetherMode};
+use cgmath::*;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
+
+/// Point, in a ship's local space, that its grappling line is attached to
+pub const SHIP_ATTACH: Point3<f64> = Point3::new(0., 0., 8.);
+/// Rate the length of the line changes at while reeling in or out
+const REEL_SPEED: f64 = 30.;
+/// Shortest length the line can be reeled in to
+const MIN_LENGTH: f64 = 10.;
+/// Longest length the line can be reeled out to
+const MAX_LENGTH: f64 = 600.;
+/// Tension, per unit of the ship's mass, above which the line snaps
+const BREAK_TENSION: f64 = 1500.;
+
+/// A grappling line from a ship to the point its hook hit on another object
+///
+/// The line is a tether constraint in the simulation whose length can be
+/// reeled in and out. Releasing the line leaves both objects with the
+/// velocities the constraint gave them
+pub struct Grapple {
+    tether: Rc<RefCell<TetherData>>,
+    /// Length of the line, shared with the line drawn between its ends
+    rope_length: Rc<Cell<f64>>,
+    ship_mass: f64,
+}
+
+impl Grapple {
+    /// Attaches a line from `ship` to `hit_point`, in world space, on
+    /// `target`. The line starts as long as the distance between its ends,
+    /// and pulls them together as `mode` once it's stretched
+    ///
+    /// Returns the grapple and the constraint to add to the simulation
+    pub fn attach<T>(
+        ship: &Rc<RefCell<Node>>,
+        ship_mass: f64,
+        target: &Rc<RefCell<Node>>,
+        hit_point: Point3<f64>,
+        mode: TetherMode,
+    ) -> (Self, Tether<T>) {
+        let attach_a = target
+            .borrow()
+            .mat()
+            .invert()
+            .map_or(hit_point, |inv| inv.transform_point(hit_point));
+        let ship_point = ship.borrow().transform_point(SHIP_ATTACH);
+        let length =
+            ship_point.distance(hit_point).clamp(MIN_LENGTH, MAX_LENGTH);
+        let tether = Rc::new(RefCell::new(TetherData {
+            a: Rc::downgrade(target),
+            attach_a,
+            b: Rc::downgrade(ship),
+            attach_b: SHIP_ATTACH,
+            length,
+            mode,
+            tension: 0.,
+            released: false,
+        }));
+        (
+            Self {
+                tether: tether.clone(),
+                rope_length: Rc::new(Cell::new(length)),
+                ship_mass,
+            },
+            Tether::new(tether),
+        )
+    }
+
+    /// Changes the length of the line by reeling it in or out for `dt`
+    pub fn reel(&self, reel: Reel, dt: Duration) {
+        let change = match reel {
+            Reel::In => -REEL_SPEED,
+            Reel::Out => REEL_SPEED,
+            Reel::Hold => return,
+        } * dt.as_secs_f64();
+        let mut tether = self.tether.borrow_mut();
+        tether.length = (tether.length + change).clamp(MIN_LENGTH, MAX_LENGTH);
+        self.rope_length.set(tether.length);
+    }
+
+    #[allow(dead_code)]
+    pub fn length(&self) -> f64 {
+        self.tether.borrow().length
+    }
+
+    /// Gets the length of the line, which is updated as it's reeled in and
+    /// out
+    pub fn rope_length(&self) -> Rc<Cell<f64>> {
+        self.rope_length.clone()
+    }
+
+    /// Gets the position, in the hooked object's local space, that the line
+    /// is attached to
+    pub fn target_attach(&self) -> Point3<f64> {
+        self.tether.borrow().attach_a
+    }
+
+    /// `true` if the tension during the last step was too much for the line
+    pub fn is_overloaded(&self) -> bool {
+        self.tether.borrow().tension > BREAK_TENSION * self.ship_mass
+    }
+
+    /// Detaches the line, so it no longer affects either object
+    pub fn release(&self) {
+        self.tether.borrow_mut().released = true;
+    }
+
+    /// `true` if the line still connects the ship to the hooked object
+    pub fn is_attached(&self) -> bool {
+        let tether = self.tether.borrow();
+        !tether.released && tether.attach_points().is_some()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    fn attach() -> (Grapple, Rc<RefCell<Node>>, Rc<RefCell<Node>>) {
+        let ship = Rc::new(RefCell::new(Node::default()));
+        let target =
+            Rc::new(RefCell::new(Node::default().pos(point3(0., 0., 108.))));
+        let (grapple, _) = Grapple::attach::<()>(
+            &ship,
+            2.,
+            &target,
+            point3(0., 0., 100.),
+            TetherMode::Spring,
+        );
+        (grapple, ship, target)
+    }
+
+    #[test]
+    fn line_attaches_at_hit_point_and_reels() {
+        let (grapple, _ship, _target) = attach();
+        assert_approx_eq!(grapple.length(), 92.);
+        assert_approx_eq!(grapple.target_attach().z, -8.);
+        grapple.reel(Reel::In, Duration::from_secs(1));
+        assert_approx_eq!(grapple.length(), 92. - REEL_SPEED);
+        assert_approx_eq!(grapple.rope_length().get(), 92. - REEL_SPEED);
+        grapple.reel(Reel::Hold, Duration::from_secs(1));
+        assert_approx_eq!(grapple.length(), 92. - REEL_SPEED);
+        grapple.reel(Reel::In, Duration::from_secs(60));
+        assert_approx_eq!(grapple.length(), MIN_LENGTH);
+        grapple.reel(Reel::Out, Duration::from_secs(60));
+        assert_approx_eq!(grapple.length(), MAX_LENGTH);
+    }
+
+    #[test]
+    fn line_snaps_under_tension_and_detaches() {
+        let (grapple, _ship, _target) = attach();
+        assert!(grapple.is_attached());
+        assert!(!grapple.is_overloaded());
+        grapple.tether.borrow_mut().tension = BREAK_TENSION * 3.;
+        assert!(grapple.is_overloaded());
+        grapple.release();
+        assert!(!grapple.is_attached());
+
+        // the line detaches once the hooked object is removed
+        let (grapple, _ship, target) = attach();
+        drop(target);
+        assert!(!grapple.is_attached());
+    }
+}
@@ -0,0 +1,209 @@
+use super::damage::Damage;
+use super::drawable::*;
+use super::minimap::{RECT_INDICES, RECT_VERTS};
+use crate::cg_support::Transformation;
+use crate::graphics_engine::{camera, entity, shader};
+use cgmath::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+/// How long it takes for a damage arc to fade out
+const ARC_LIFETIME: Duration = Duration::from_secs(1);
+/// How long it takes for the vignette flash to fade out
+const FLASH_LIFETIME: Duration = Duration::from_millis(400);
+/// Damage from a single hit that flashes the vignette at full strength
+const FULL_FLASH_DAMAGE: f64 = 30.;
+/// Most arcs that can be shown at once
+const MAX_ARCS: usize = 8;
+
+/// An arc pointing towards where the player was hit from
+struct DamageArc {
+    /// Angle counterclockwise from the right of the screen
+    angle: f64,
+    /// Opacity of the arc when it was created
+    intensity: f64,
+    age: Duration,
+}
+
+/// HUD feedback for the player taking damage
+///
+/// Each hit shows an arc around the center of the screen pointing towards
+/// where the hit came from, and flashes the edges of the screen red by an
+/// amount scaled by the damage
+pub struct HitIndicator {
+    arcs: Vec<DamageArc>,
+    /// Strength of the vignette flash
+    flash: f64,
+    aspect: f32,
+    vertices: glium::VertexBuffer<VertexSimple>,
+    indices: glium::IndexBuffer<u8>,
+}
+
+/// Gets the angle on the screen, counterclockwise from the right, of the
+/// world space direction `dir` when viewed by `cam`
+///
+/// Returns `None` if `dir` is parallel to the view direction
+fn screen_angle(
+    dir: Vector3<f64>,
+    cam: &camera::PerspectiveCamera,
+) -> Option<f64> {
+    let forward = (cam.target - cam.cam).cast::<f64>().unwrap().normalize();
+    let right = forward.cross(cam.up.cast().unwrap()).normalize();
+    let up = right.cross(forward);
+    let (x, y) = (dir.dot(right), dir.dot(up));
+    if x.abs() < f64::EPSILON && y.abs() < f64::EPSILON {
+        None
+    } else {
+        Some(y.atan2(x))
+    }
+}
+
+impl HitIndicator {
+    pub fn new<F: glium::backend::Facade>(facade: &F) -> Self {
+        Self {
+            arcs: Vec::new(),
+            flash: 0.,
+            aspect: 1.,
+            vertices: glium::VertexBuffer::immutable(facade, &RECT_VERTS)
+                .unwrap(),
+            indices: glium::IndexBuffer::immutable(
+                facade,
+                glium::index::PrimitiveType::TrianglesList,
+                &RECT_INDICES,
+            )
+            .unwrap(),
+        }
+    }
+
+    /// Shows that the player, whose center is at `center`, took `damage`
+    /// while viewed by `cam`
+    ///
+    /// The arc points in the opposite direction of the momentum the hit
+    /// transferred, or towards where the player was hit if it transferred
+    /// none
+    pub fn on_hit(
+        &mut self,
+        damage: &Damage,
+        center: Point3<f64>,
+        cam: &camera::PerspectiveCamera,
+    ) {
+        let amount = damage.multiplied();
+        let from = if damage.impulse.magnitude2() > f64::EPSILON {
+            -damage.impulse
+        } else {
+            damage.pos - center
+        };
+        if let Some(angle) = screen_angle(from, cam) {
+            if self.arcs.len() == MAX_ARCS {
+                self.arcs.remove(0);
+            }
+            self.arcs.push(DamageArc {
+                angle,
+                intensity: (amount / FULL_FLASH_DAMAGE).clamp(0.4, 1.),
+                age: Duration::default(),
+            });
+        }
+        self.flash = self.flash.max((amount / FULL_FLASH_DAMAGE).min(1.));
+        self.aspect = cam.aspect;
+    }
+
+    /// Fades out the arcs and flash by `dt`
+    pub fn update(&mut self, dt: Duration) {
+        for arc in &mut self.arcs {
+            arc.age += dt;
+        }
+        self.arcs.retain(|arc| arc.age < ARC_LIFETIME);
+        self.flash = dt
+            .as_secs_f64()
+            .mul_add(-1. / FLASH_LIFETIME.as_secs_f64(), self.flash)
+            .max(0.);
+    }
+
+    /// Gets the `angle, intensity` pairs of the arcs, packed two to a column
+    fn packed_arcs(&self) -> [[f32; 4]; 4] {
+        let mut packed = [[0f32; 4]; 4];
+        for (i, arc) in self.arcs.iter().enumerate() {
+            let fade = 1. - arc.age.as_secs_f64() / ARC_LIFETIME.as_secs_f64();
+            let col = &mut packed[i / 2];
+            col[i % 2 * 2] = arc.angle as f32;
+            col[i % 2 * 2 + 1] = (arc.intensity * fade) as f32;
+        }
+        packed
+    }
+}
+
+impl Drawable for HitIndicator {
+    fn render_args<'a>(
+        &'a mut self,
+        _positions: &[[[f32; 4]; 4]],
+    ) -> Vec<(
+        shader::UniformInfo,
+        VertexHolder<'a>,
+        glium::index::IndicesSource<'a>,
+    )> {
+        if self.arcs.is_empty() && self.flash <= 0. {
+            return Vec::new();
+        }
+        vec![(
+            shader::UniformInfo::HitIndicator(shader::HitIndicatorData {
+                arcs: self.packed_arcs(),
+                vignette: self.flash as f32,
+                aspect: self.aspect,
+            }),
+            VertexHolder::new(VertexSourceData::Single(From::from(
+                &self.vertices,
+            ))),
+            From::from(&self.indices),
+        )]
+    }
+
+    fn transparency(&self) -> Option<f32> {
+        None
+    }
+}
+
+impl entity::AbstractEntity for HitIndicator {
+    fn transformations(&self) -> Option<&[Rc<RefCell<dyn Transformation>>]> {
+        None
+    }
+
+    fn drawable(&mut self) -> &mut dyn Drawable {
+        self
+    }
+
+    fn should_render(&self, pass: shader::RenderPassType) -> bool {
+        matches!(pass, shader::RenderPassType::Visual)
+    }
+
+    fn render_order(&self) -> entity::RenderOrder {
+        entity::RenderOrder::Last
+    }
+
+    fn get_id(&self) -> usize {
+        self as *const _ as usize
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn hits_point_towards_their_source_on_screen() {
+        let cam = camera::PerspectiveCamera {
+            cam: point3(0., 0., 0.),
+            target: point3(0., 0., -1.),
+            up: vec3(0., 1., 0.),
+            ..camera::PerspectiveCamera::default(1.)
+        };
+        let right = screen_angle(vec3(1., 0., 0.), &cam).unwrap();
+        assert_approx_eq!(right, 0.);
+        let above = screen_angle(vec3(0., 1., 1.), &cam).unwrap();
+        assert_approx_eq!(above, std::f64::consts::FRAC_PI_2);
+        let left_behind = screen_angle(vec3(-1., 0., 5.), &cam).unwrap();
+        assert_approx_eq!(left_behind.abs(), std::f64::consts::PI);
+        assert!(screen_angle(vec3(0., 0., -3.), &cam).is_none());
+    }
+}
@@ -0,0 +1,344 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::Display;
+use tracing::warn;
+
+/// Language whose strings are used for keys missing from other languages
+pub const DEFAULT_LANGUAGE: &str = "en";
+/// Directory of the string tables, which are named `<language>.lang`
+const LANGUAGE_DIR: &str = "assets/lang";
+/// Strings of the default language, which are built into the game so there
+/// is always something to fall back on
+const DEFAULT_STRINGS: &str = include_str!("../assets/lang/en.lang");
+
+/// Looks up the string of a key in the current language, falling back to
+/// English and then to the key itself
+///
+/// Arguments after the key fill the `{}` and `{<index>}` placeholders of
+/// the string, such as `tr!("hud.respawning", secs, lives)`
+macro_rules! tr {
+    ($key:expr) => {
+        $crate::localization::lookup($key)
+    };
+    ($key:expr, $($arg:expr),+ $(,)?) => {
+        $crate::localization::format(
+            &$crate::localization::lookup($key),
+            &[$(&$arg as &dyn std::fmt::Display),+],
+        )
+    };
+}
+
+/// The strings of one language, keyed by their identifiers
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct StringTable {
+    strings: HashMap<String, String>,
+}
+
+impl StringTable {
+    /// Parses the contents of a language file
+    ///
+    /// The file is made of `key = "value"` lines. Empty lines and lines
+    /// beginning with `#` are ignored. Values may contain the escapes `\"`,
+    /// `\\` and `\n`
+    ///
+    /// # Errors
+    /// Fails if a line is malformed or a key is repeated
+    pub fn parse(data: &str) -> Result<Self, String> {
+        let mut strings = HashMap::new();
+        for line in data
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        {
+            let (key, val) = line
+                .split_once('=')
+                .map(|(k, v)| (k.trim(), v.trim()))
+                .ok_or_else(|| format!("Malformed string: \"{}\"", line))?;
+            let val = val
+                .strip_prefix('"')
+                .and_then(|val| val.strip_suffix('"'))
+                .ok_or_else(|| format!("Unquoted string: {}", val))?;
+            if strings.insert(key.to_owned(), unescape(val)?).is_some() {
+                return Err(format!("Repeated key \"{}\"", key));
+            }
+        }
+        Ok(Self { strings })
+    }
+
+    /// Loads the string table of `language` from the language directory
+    ///
+    /// # Errors
+    /// Fails if the file cannot be read or parsed
+    pub fn load(language: &str) -> Result<Self, Box<dyn Error>> {
+        if language == DEFAULT_LANGUAGE {
+            return Ok(Self::parse(DEFAULT_STRINGS)?);
+        }
+        let path = format!("{}/{}.lang", LANGUAGE_DIR, language);
+        Ok(Self::parse(&std::fs::read_to_string(path)?)?)
+    }
+
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.strings.get(key).map(String::as_str)
+    }
+}
+
+/// Replaces the escapes of a quoted string with the characters they stand
+/// for
+fn unescape(val: &str) -> Result<String, String> {
+    let mut result = String::with_capacity(val.len());
+    let mut chars = val.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => result.push('\n'),
+                Some(c @ ('"' | '\\')) => result.push(c),
+                _ => return Err(format!("Invalid escape in {}", val)),
+            },
+            '"' => return Err(format!("Unescaped quote in {}", val)),
+            c => result.push(c),
+        }
+    }
+    Ok(result)
+}
+
+/// The language strings are looked up in
+struct Locale {
+    language: String,
+    strings: StringTable,
+    defaults: StringTable,
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        let defaults = StringTable::parse(DEFAULT_STRINGS)
+            .expect("Invalid default language file");
+        Self {
+            language: DEFAULT_LANGUAGE.to_owned(),
+            strings: defaults.clone(),
+            defaults,
+        }
+    }
+}
+
+thread_local! {
+    static LOCALE: RefCell<Locale> = RefCell::new(Locale::default());
+}
+
+/// Switches the strings looked up by `tr!` to those of `language`. Text
+/// which was already shown keeps its old language until it's set again
+///
+/// # Errors
+/// Fails if the language's file cannot be loaded, leaving the language
+/// unchanged
+pub fn set_language(language: &str) -> Result<(), Box<dyn Error>> {
+    let strings = StringTable::load(language)?;
+    LOCALE.with(|locale| {
+        let mut locale = locale.borrow_mut();
+        locale.language = language.to_owned();
+        locale.strings = strings;
+    });
+    Ok(())
+}
+
+/// Gets the language strings are looked up in
+#[must_use]
+pub fn language() -> String {
+    LOCALE.with(|locale| locale.borrow().language.clone())
+}
+
+/// Gets the languages which have a file in the language directory, sorted
+/// by name. The default language is always available
+#[must_use]
+pub fn languages() -> Vec<String> {
+    let mut languages: Vec<_> = std::fs::read_dir(LANGUAGE_DIR)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? == "lang" {
+                Some(path.file_stem()?.to_str()?.to_owned())
+            } else {
+                None
+            }
+        })
+        .chain(std::iter::once(DEFAULT_LANGUAGE.to_owned()))
+        .collect();
+    languages.sort();
+    languages.dedup();
+    languages
+}
+
+/// Gets the language after `language` in `languages`, wrapping around to
+/// the first
+#[must_use]
+pub fn next_language(language: &str, languages: &[String]) -> String {
+    languages
+        .iter()
+        .position(|l| l == language)
+        .and_then(|idx| languages.get(idx + 1))
+        .or_else(|| languages.first())
+        .map_or_else(|| DEFAULT_LANGUAGE.to_owned(), Clone::clone)
+}
+
+/// Gets the string of `key` in the current language. Keys missing from the
+/// language use the default language's string, and keys missing from both
+/// are returned as is
+#[must_use]
+pub fn lookup(key: &str) -> String {
+    LOCALE.with(|locale| {
+        let locale = locale.borrow();
+        locale
+            .strings
+            .get(key)
+            .or_else(|| locale.defaults.get(key))
+            .unwrap_or(key)
+            .to_owned()
+    })
+}
+
+/// Fills the placeholders of `template` with `args`. `{}` is replaced by the
+/// argument after the one used by the last placeholder, and `{<index>}` by
+/// the argument at `index`, so translations can reorder them. `{{` and `}}`
+/// are a literal brace, and placeholders without an argument are kept
+#[must_use]
+pub fn format(template: &str, args: &[&dyn Display]) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut next_arg = 0;
+    let mut rest = template;
+    while let Some(start) = rest.find(|c: char| c == '{' || c == '}') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if rest.starts_with("{{") || rest.starts_with("}}") {
+            result.push_str(&rest[..1]);
+            rest = &rest[2..];
+            continue;
+        }
+        let arg =
+            rest.find('}')
+                .filter(|_| rest.starts_with('{'))
+                .and_then(|end| {
+                    let idx = match &rest[1..end] {
+                        "" => next_arg,
+                        idx => idx.parse().ok()?,
+                    };
+                    args.get(idx).map(|arg| (idx, arg, end))
+                });
+        if let Some((idx, arg, end)) = arg {
+            result.push_str(&arg.to_string());
+            next_arg = idx + 1;
+            rest = &rest[end + 1..];
+        } else {
+            result.push_str(&rest[..1]);
+            rest = &rest[1..];
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Gets the name of `language` in that language, or its code if its file
+/// cannot be loaded or doesn't name it
+#[must_use]
+pub fn language_name(language: &str) -> String {
+    StringTable::load(language)
+        .ok()
+        .and_then(|strings| strings.get("language.name").map(str::to_owned))
+        .unwrap_or_else(|| language.to_owned())
+}
+
+/// Gets the paths of the fonts that every available language uses for the
+/// glyphs missing from the default font, such as the glyphs of non-Latin
+/// scripts. They are named by the `font.fallback` key of a language file
+///
+/// Fonts which don't exist are left out
+#[must_use]
+pub fn fallback_fonts() -> Vec<String> {
+    let mut fonts: Vec<_> = languages()
+        .iter()
+        .filter_map(|language| StringTable::load(language).ok())
+        .filter_map(|strings| strings.get("font.fallback").map(str::to_owned))
+        .filter(|path| {
+            let exists = std::path::Path::new(path).exists();
+            if !exists {
+                warn!("Missing fallback font: {}", path);
+            }
+            exists
+        })
+        .collect();
+    fonts.dedup();
+    fonts
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn string_tables_parse() {
+        let table = StringTable::parse(
+            "# comment\n\
+            \n\
+            menu.play = \"Play\"\n\
+              menu.quit=\"Quit \\\"now\\\"\\\\\"",
+        )
+        .unwrap();
+        assert_eq!(table.get("menu.play"), Some("Play"));
+        assert_eq!(table.get("menu.quit"), Some("Quit \"now\"\\"));
+        assert_eq!(table.get("menu.back"), None);
+        assert!(StringTable::parse("menu.play \"Play\"").is_err());
+        assert!(StringTable::parse("menu.play = Play").is_err());
+        assert!(StringTable::parse("a = \"\\t\"").is_err());
+        assert!(StringTable::parse("a = \"x\"\na = \"y\"").is_err());
+    }
+
+    #[test]
+    fn default_language_has_every_string() {
+        let defaults = StringTable::load(DEFAULT_LANGUAGE).unwrap();
+        assert_eq!(defaults.get("menu.play"), Some("Play"));
+        assert!(defaults.get("language.name").is_some());
+        assert_eq!(lookup("menu.settings"), "Settings");
+        // unknown keys, such as ship names, are shown as they are
+        assert_eq!(lookup("Fighter"), "Fighter");
+        assert_eq!(
+            tr!("hud.respawning", 3, 2),
+            "Respawning in 3 (2 lives left)"
+        );
+    }
+
+    #[test]
+    fn missing_strings_fall_back_to_english() {
+        LOCALE.with(|locale| {
+            let mut locale = locale.borrow_mut();
+            locale.language = "test".to_owned();
+            locale.strings =
+                StringTable::parse("menu.play = \"Jugar\"").unwrap();
+        });
+        assert_eq!(language(), "test");
+        assert_eq!(tr!("menu.play"), "Jugar");
+        assert_eq!(tr!("menu.quit"), "Quit");
+        assert!(set_language("not-a-language").is_err());
+        assert_eq!(language(), "test");
+        set_language(DEFAULT_LANGUAGE).unwrap();
+        assert_eq!(tr!("menu.play"), "Play");
+    }
+
+    #[test]
+    fn placeholders_are_filled_in_order_or_by_index() {
+        assert_eq!(format("{} of {}", &[&1, &"two"]), "1 of two");
+        assert_eq!(format("{1} before {0}", &[&"a", &"b"]), "b before a");
+        assert_eq!(format("{1}, {}", &[&"a", &"b", &"c"]), "b, c");
+        assert_eq!(format("{{}} {}", &[&7]), "{} 7");
+        assert_eq!(format("{} {} {x} }", &[&7]), "7 {} {x} }");
+    }
+
+    #[test]
+    fn languages_cycle() {
+        let languages = vec!["de".to_owned(), "en".to_owned()];
+        assert_eq!(next_language("de", &languages), "en");
+        assert_eq!(next_language("en", &languages), "de");
+        assert_eq!(next_language("fr", &languages), "de");
+        assert!(languages().contains(&DEFAULT_LANGUAGE.to_owned()));
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{reload, EnvFilter, Layer, Registry};
+
+/// Levels logged before the configured filter is applied
+const DEFAULT_FILTER: &str = "info";
+/// Time a warning or error is shown on the log overlay
+const OVERLAY_TIME: Duration = Duration::from_secs(8);
+/// Most warnings and errors kept for the log overlay
+const OVERLAY_CAPACITY: usize = 16;
+/// Most events of any level kept for crash reports
+const RECENT_CAPACITY: usize = 100;
+
+/// A warning or error kept for the log overlay
+struct OverlayEntry {
+    time: Instant,
+    level: Level,
+    message: String,
+}
+
+lazy_static! {
+    /// The latest warnings and errors, oldest first. Shared as events are
+    /// logged from every thread
+    static ref OVERLAY: Mutex<VecDeque<OverlayEntry>> =
+        Mutex::new(VecDeque::new());
+    /// The latest events which passed the filter, oldest first
+    static ref RECENT: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+}
+
+/// Formats the message and fields of an event into one line
+#[derive(Default)]
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        use std::fmt::Write;
+        if field.name() == "message" {
+            let _ = write!(self.0, "{:?}", value);
+        } else {
+            let _ = write!(self.0, " {}={:?}", field.name(), value);
+        }
+    }
+}
+
+/// Keeps the latest events which pass the filter for crash reports, and the
+/// warnings and errors among them for the log overlay
+struct RecentLayer;
+
+impl<S: Subscriber> Layer<S> for RecentLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let level = *metadata.level();
+        let mut message = MessageVisitor::default();
+        event.record(&mut message);
+        {
+            let mut recent =
+                RECENT.lock().unwrap_or_else(PoisonError::into_inner);
+            if recent.len() == RECENT_CAPACITY {
+                recent.pop_front();
+            }
+            recent.push_back(format!(
+                "{} {}: {}",
+                level,
+                metadata.target(),
+                message.0
+            ));
+        }
+        // more verbose levels are greater
+        if level > Level::WARN {
+            return;
+        }
+        let mut overlay =
+            OVERLAY.lock().unwrap_or_else(PoisonError::into_inner);
+        if overlay.len() == OVERLAY_CAPACITY {
+            overlay.pop_front();
+        }
+        overlay.push_back(OverlayEntry {
+            time: Instant::now(),
+            level,
+            message: message.0,
+        });
+    }
+}
+
+/// Changes which modules are logged at which levels
+pub struct LogFilter(reload::Handle<EnvFilter, Registry>);
+
+impl LogFilter {
+    /// Sets the levels logged to the directives of `filter`, such as
+    /// `warn,oort_client::controls=debug`. The `RUST_LOG` environment
+    /// variable takes precedence over `filter` when it's set
+    ///
+    /// # Errors
+    /// Fails if the directives cannot be parsed
+    pub fn set(&self, filter: &str) -> Result<(), Box<dyn Error>> {
+        let filter = EnvFilter::try_from_default_env()
+            .or_else(|_| EnvFilter::try_new(filter))?;
+        self.0.reload(filter)?;
+        Ok(())
+    }
+}
+
+/// Logs the events of every thread to stdout, with the time they happened
+/// and the spans they happened in. Spans are logged as they close, along
+/// with the time spent in them
+///
+/// Info and more severe events are logged until the filter is set with the
+/// returned handle
+///
+/// # Errors
+/// Fails if a logger was already set
+pub fn init() -> Result<LogFilter, Box<dyn Error>> {
+    let (filter, handle) = reload::Layer::new(EnvFilter::new(DEFAULT_FILTER));
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_span_events(FmtSpan::CLOSE)
+                .with_thread_names(true),
+        )
+        .with(RecentLayer)
+        .try_init()?;
+    Ok(LogFilter(handle))
+}
+
+/// Gets the warnings and errors logged within `OVERLAY_TIME` of `now`,
+/// oldest first. At most `max_lines` of the latest are returned
+#[must_use]
+pub fn overlay_lines(now: Instant, max_lines: usize) -> Vec<String> {
+    let mut overlay = OVERLAY.lock().unwrap_or_else(PoisonError::into_inner);
+    while overlay
+        .front()
+        .map_or(false, |entry| now.duration_since(entry.time) > OVERLAY_TIME)
+    {
+        overlay.pop_front();
+    }
+    overlay
+        .iter()
+        .skip(overlay.len().saturating_sub(max_lines))
+        .map(|entry| format!("{}: {}", entry.level, entry.message))
+        .collect()
+}
+
+/// Gets the latest events of any level which passed the filter, oldest first
+#[must_use]
+pub fn recent_lines() -> Vec<String> {
+    RECENT
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn recent_events_are_kept() {
+        let subscriber = tracing_subscriber::registry().with(RecentLayer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("spawned wave");
+            tracing::warn!(count = 3, "low on ids");
+            tracing::error!("connection lost");
+        });
+        let now = Instant::now();
+        let lines = overlay_lines(now, 1);
+        assert_eq!(lines, vec!["ERROR: connection lost"]);
+        assert_eq!(
+            overlay_lines(now, 5),
+            vec!["WARN: low on ids count=3", "ERROR: connection lost"]
+        );
+        assert!(overlay_lines(now + OVERLAY_TIME * 2, 5).is_empty());
+        assert!(recent_lines().ends_with(&[
+            "INFO oort_client::logging::test: spawned wave".to_owned(),
+            "WARN oort_client::logging::test: low on ids count=3".to_owned(),
+            "ERROR oort_client::logging::test: connection lost".to_owned(),
+        ]));
+    }
+}
@@ -16,62 +16,151 @@ extern crate glium;
 extern crate static_assertions;
 #[macro_use]
 extern crate lazy_static;
+mod audio;
+mod bot;
 mod cg_support;
+mod client_config;
 #[macro_use]
 mod graphics_engine;
+#[macro_use]
+mod localization;
 mod collisions;
+mod console;
 mod controls;
+mod crash;
+mod damage;
+mod debris;
+mod energy;
+mod event_bus;
+mod explosion;
+mod fragmentation;
 mod game;
 mod game_mediator;
+mod game_state;
+mod governor;
+mod grapple;
+mod hit_indicator;
+mod logging;
 mod minimap;
+mod mission;
 mod object;
 mod physics;
+mod pickup;
 mod player;
+mod profiler;
+mod radar;
+mod respawn;
+mod scoring;
+mod settings;
+mod ship;
+mod station;
+mod tactical;
+mod thrusters;
+mod time_scale;
+mod tractor;
+mod wormhole;
 extern crate gl;
 use graphics_engine::window::*;
+use tracing::{error, info, warn};
 
 use cgmath::*;
 use game_mediator::*;
+use game_state::{GameState, GameStateMachine};
 use graphics_engine::pipeline::*;
 use graphics_engine::*;
-use shared_types::game_controller::{GameController, LocalGameController};
+use shared_types::game_controller::{
+    GameController, LocalGameController, SurvivalGameController,
+};
+use shared_types::game_map::{AsteroidMap, Map, ProceduralMap};
+use shared_types::map_file::FileMap;
+use shared_types::survival;
 
 use cg_support::node;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
 
-use crate::controls::get_std_ai_controller;
+use crate::client_config::MapChoice;
+use crate::controls::{get_guard_ai_controller, get_squad_ai_controller};
 
 fn get_cascade_target(
-    width: u32,
-    height: u32,
+    map_size: u32,
     user: Rc<RefCell<player::Player>>,
     near: f32,
     far: f32,
 ) -> Box<dyn RenderTarget> {
     Box::new(render_target::CustomViewRenderTargetDecorator::new(
-        render_target::DepthRenderTarget::new_cascade(width, height, true),
+        render_target::DepthRenderTarget::new_cascade(map_size, map_size, true),
         move |_| {
-            user.borrow().get_cam().get_cascade(
+            camera::get_cascade(
+                &*user.borrow(),
                 vec3(-120., 120., 0.),
                 near,
                 far,
-                2048,
+                map_size,
             )
         },
     ))
 }
+
+/// Gets the render target capturing the reflection probes spread throughout
+/// the asteroid field, which surrounds the planet at the origin
+fn get_probe_target(
+    view_dist: f32,
+    wnd_ctx: &glium::Display,
+) -> Box<dyn RenderTarget> {
+    let probe_positions = [
+        point3(300., 0., 0.),
+        point3(-300., 0., 0.),
+        point3(0., 300., 0.),
+        point3(0., -300., 0.),
+        point3(0., 0., 300.),
+        point3(0., 0., -300.),
+    ];
+    Box::new(probe_positions.iter().fold(
+        render_target::ProbeRenderTarget::new(128, 5, view_dist),
+        |target, pos| {
+            target.with_probe(
+                *pos,
+                250.,
+                render_target::ProbeUpdate::Baked,
+                wnd_ctx,
+            )
+        },
+    ))
+}
+
+/// Gets how often the faces of the translucency cubemap are rendered
+fn translucency_refresh(
+    settings: &settings::GraphicsSettings,
+    obstacles: &collisions::CollisionTree,
+) -> render_target::CubemapRefresh {
+    if settings.translucency_on_change {
+        // bodies smaller than a ship barely show up in the cubemap
+        let changes = obstacles.subscribe(1., 1.);
+        changes.borrow_mut().set_max_events(1024);
+        render_target::CubemapRefresh::OnChange {
+            changes,
+            max_move: 2.,
+            max_age: settings.translucency_refresh,
+        }
+    } else {
+        render_target::CubemapRefresh::Interval(settings.translucency_refresh)
+    }
+}
+
 #[allow(clippy::too_many_lines)]
 fn get_main_render_pass(
-    render_width: u32,
-    render_height: u32,
+    settings: &settings::GraphicsSettings,
     user: Rc<RefCell<player::Player>>,
+    obstacles: &collisions::CollisionTree,
     wnd_ctx: &glium::Display,
 ) -> RenderPass {
     use graphics_engine::drawable::Viewer;
     use pipeline::*;
+    let (render_width, render_height) =
+        (settings.render_width, settings.render_height);
     let msaa = Box::new(render_target::MsaaRenderTarget::new(
-        8,
+        settings.msaa_samples,
         render_width,
         render_height,
         wnd_ctx,
@@ -84,7 +173,7 @@ fn get_main_render_pass(
     let blur = Box::new(texture_processor::SepConvProcessor::new(
         render_width,
         render_height,
-        10,
+        settings.bloom_iterations,
         wnd_ctx,
     ));
     let compose = Box::new(texture_processor::CompositorProcessor::new(
@@ -98,17 +187,31 @@ fn get_main_render_pass(
         render_height,
         false,
     ));
-    let cull_lights = Box::new(texture_processor::CullLightProcessor::new(
-        render_width,
-        render_height,
-        16,
+    let cull_lights: Box<dyn TextureProcessor> = match settings.light_culling {
+        settings::LightCulling::Tiled => {
+            Box::new(texture_processor::CullLightProcessor::new(
+                render_width,
+                render_height,
+                16,
+            ))
+        }
+        settings::LightCulling::Clustered => {
+            Box::new(texture_processor::ClusterLightProcessor::new(
+                render_width,
+                render_height,
+                64,
+                24,
+            ))
+        }
+    };
+    let to_cache = Box::new(texture_processor::ToCacheProcessor::for_slot(
+        shader::CASCADES,
     ));
-    let to_cache = Box::new(texture_processor::ToCacheProcessor::new());
 
     let user_clone = user.clone();
     let translucency = Box::new(
         render_target::CubemapRenderTarget::new(
-            1024,
+            settings.translucency_map_size,
             user.borrow().view_dist().1,
             Box::new(move || {
                 user_clone
@@ -122,24 +225,41 @@ fn get_main_render_pass(
             }),
             wnd_ctx,
         )
+        .with_resolution_scale(settings.translucency_scale, wnd_ctx)
+        .with_refresh(translucency_refresh(settings, obstacles))
         .with_trans_getter(Box::new(|| 0))
         .with_pass(shader::RenderPassType::Transparent(
             user.borrow().get_entity_id(),
         )),
     );
-    let trans_to_cache = Box::new(texture_processor::ToCacheProcessor::new());
-    let cam_depth_to_cache =
-        Box::new(texture_processor::ToCacheProcessor::new());
+    let trans_to_cache = Box::new(
+        texture_processor::ToCacheProcessor::for_slot(shader::OBJECT_CUBEMAPS),
+    );
+    let probes = get_probe_target(user.borrow().view_dist().1, wnd_ctx);
+    let probes_to_cache = Box::new(
+        texture_processor::ToCacheProcessor::for_slot(shader::PROBES),
+    );
+    let cam_depth_to_cache = Box::new(
+        texture_processor::ToCacheProcessor::for_slot(shader::CAM_DEPTH),
+    );
 
+    let shadow_size = settings.shadow_map_size;
+    let shadow_near = 0.1;
+    let splits = camera::practical_splits(
+        shadow_near,
+        settings.shadow_distance,
+        3,
+        settings.cascade_split_lambda,
+    );
     let render_cascade_1 =
-        get_cascade_target(2048, 2048, user.clone(), 0.1, 40.);
+        get_cascade_target(shadow_size, user.clone(), shadow_near, splits[0]);
     let render_cascade_2 =
-        get_cascade_target(2048, 2048, user.clone(), 40., 200.);
+        get_cascade_target(shadow_size, user.clone(), splits[0], splits[1]);
     let render_cascade_3 =
-        get_cascade_target(2048, 2048, user.clone(), 200., 600.);
+        get_cascade_target(shadow_size, user.clone(), splits[1], splits[2]);
 
-    pipeline! ([depth_render, msaa, render_cascade_1, render_cascade_2, render_cascade_3, translucency],
-        [cull_lights, eb, blur, compose, to_cache, trans_to_cache, cam_depth_to_cache],
+    pipeline! ([depth_render, msaa, render_cascade_1, render_cascade_2, render_cascade_3, translucency, probes],
+        [cull_lights, eb, blur, compose, to_cache, trans_to_cache, cam_depth_to_cache, probes_to_cache],
 
         depth_render -> cull_lights.0,
         depth_render -> cam_depth_to_cache.0,
@@ -153,10 +273,14 @@ fn get_main_render_pass(
         render_cascade_2 -> to_cache.1,
         render_cascade_3 -> to_cache.2,
 
-        to_cache -> msaa.0,
+        to_cache -> msaa.1,
         to_cache -> translucency.0,
         translucency -> trans_to_cache.0,
-        trans_to_cache -> msaa.1,
+        trans_to_cache -> msaa.2,
+        to_cache -> probes.0,
+        probes -> probes_to_cache.0,
+        probes_to_cache -> msaa.3,
+        probes_to_cache -> translucency.1,
 
         msaa -> eb.0,
         eb -> blur.0,
@@ -165,6 +289,62 @@ fn get_main_render_pass(
 
         { trans_to_cache | translucency if user.borrow().trans_fac() > f32::EPSILON }
     )
+    .with_name("main")
+}
+
+/// Gets the settings of the render pass of a player's viewport, whose render
+/// targets are sized to the viewport instead of the whole screen
+fn viewport_settings(
+    settings: &settings::GraphicsSettings,
+    viewport: &scene::Viewport,
+) -> settings::GraphicsSettings {
+    let (render_width, render_height) =
+        viewport.size(settings.render_width, settings.render_height);
+    settings::GraphicsSettings {
+        render_width,
+        render_height,
+        ..settings.clone()
+    }
+}
+
+/// The scene a player sees the game through, the player, and the region of
+/// the screen the scene is shown in
+type PlayerView = (
+    Rc<RefCell<scene::Scene>>,
+    Rc<RefCell<player::Player>>,
+    scene::Viewport,
+);
+
+/// Rebuilds the render pass of each player's viewport with `settings`
+fn rebuild_viewports(
+    settings: &settings::GraphicsSettings,
+    views: &[PlayerView],
+    obstacles: &collisions::CollisionTree,
+    wnd_ctx: &glium::Display,
+) {
+    for (scene, player, viewport) in views {
+        scene.borrow_mut().set_render_pass(get_main_render_pass(
+            &viewport_settings(settings, viewport),
+            player.clone(),
+            obstacles,
+            wnd_ctx,
+        ));
+    }
+}
+
+/// Rebuilds the render passes with `settings` scaled down to the tier of
+/// `governor`, and sets the particle density of new effects
+fn apply_graphics_settings(
+    settings: &settings::GraphicsSettings,
+    governor: &governor::FrameGovernor,
+    views: &[PlayerView],
+    obstacles: &collisions::CollisionTree,
+    wnd_ctx: &glium::Display,
+) {
+    let settings = governor.apply(settings);
+    crash::set_context("Graphics settings", settings.to_string());
+    particles::set_density(settings.particle_density);
+    rebuild_viewports(&settings, views, obstacles, wnd_ctx);
 }
 
 fn get_ui_render_pass(
@@ -183,215 +363,2784 @@ fn get_ui_render_pass(
     pipeline::RenderPass::new(
         vec![msaa],
         Vec::new(),
-        pipeline::Pipeline::new(vec![0], Vec::new()),
+        pipeline::Pipeline::new(vec![0], Vec::new())
+            .expect("Invalid UI pipeline"),
     )
+    .with_name("ui")
+    .with_stage_names(vec!["msaa"])
 }
 
-// Long function for ad-hoc testing
-// TODO: refactor
-#[allow(clippy::too_many_lines)]
-fn main() {
-    let render_width = 1920;
-    let render_height = 1080;
+/// Builds the pause menu, which is composited over the scene beneath it
+///
+/// `actions` - the key of the label of each button above the quit button,
+/// from top to bottom, and the function called when it's clicked
+///
+/// Returns the pause menu scene, the UI of the menu, and the relabels of its
+/// buttons
+fn get_pause_menu(
+    screen_width: Rc<RefCell<u32>>,
+    screen_height: Rc<RefCell<u32>>,
+    font: &Rc<text::Font>,
+    actions: Vec<(&str, Box<dyn FnMut()>)>,
+    wnd_ctx: &glium::Display,
+) -> (
+    Box<RefCell<dyn scene::AbstractScene>>,
+    ui::UiRoot<'static>,
+    Vec<Relabel>,
+) {
+    let (width, height) = (*screen_width.borrow(), *screen_height.borrow());
+    let mut menu = ui::UiRoot::new(width, height);
+    let mut relabels = Vec::new();
+    // the column of buttons is centered, with the quit button below the
+    // actions
+    let top = 50. * actions.len() as f64;
+    let rows = actions.len() as u32;
+    for ((key, on_click), row) in actions.into_iter().zip(0_u32..) {
+        let button = menu.add(
+            ui::Button::new(
+                "assets/particles/window_01.png",
+                ui::Layout::new(ui::Anchor::Center)
+                    .offset(0., f64::from(row).mul_add(-100., top))
+                    .size(320., 80.),
+                on_click,
+                wnd_ctx,
+            )
+            .with_label(
+                font.clone(),
+                &tr!(key),
+                [1., 1., 1., 1.],
+                wnd_ctx,
+            ),
+        );
+        relabels.push(relabel_button(button, key));
+    }
+    let quit = menu.add(
+        ui::Button::new(
+            "assets/particles/window_01.png",
+            ui::Layout::new(ui::Anchor::Center)
+                .offset(0., f64::from(rows).mul_add(-100., top))
+                .size(320., 80.),
+            || std::process::exit(0),
+            wnd_ctx,
+        )
+        .with_label(
+            font.clone(),
+            &tr!("menu.quit"),
+            [1., 1., 1., 1.],
+            wnd_ctx,
+        ),
+    );
+    relabels.push(relabel_button(quit, "menu.quit"));
+    (
+        get_menu_scene(screen_width, screen_height, &menu, 0.5, wnd_ctx),
+        menu,
+        relabels,
+    )
+}
+
+/// Builds the settings menu, which is composited over the scene beneath it
+///
+/// `options` - the initial label of each button, from top to bottom, and the
+/// function called when it's clicked
+///
+/// Returns the settings menu scene, the UI of the menu, the button of each
+/// option so that their labels can show the current settings, and the
+/// relabel of the title
+#[allow(clippy::type_complexity)]
+fn get_settings_menu(
+    screen_width: Rc<RefCell<u32>>,
+    screen_height: Rc<RefCell<u32>>,
+    font: &Rc<text::Font>,
+    options: Vec<(String, Box<dyn FnMut()>)>,
+    wnd_ctx: &glium::Display,
+) -> (
+    Box<RefCell<dyn scene::AbstractScene>>,
+    ui::UiRoot<'static>,
+    Vec<Rc<RefCell<ui::Button<'static>>>>,
+    Relabel,
+) {
+    let mut menu =
+        ui::UiRoot::new(*screen_width.borrow(), *screen_height.borrow());
+    let mut title_label = ui::Label::new(
+        font.clone(),
+        ui::Layout::new(ui::Anchor::Center)
+            .offset(0., 200.)
+            .size(480., 80.),
+        [1., 1., 1., 1.],
+        wnd_ctx,
+    );
+    title_label.set_text(&tr!("menu.settings"));
+    let title = relabel_title(menu.add(title_label), "menu.settings");
+    let buttons = options
+        .into_iter()
+        .zip(0_u32..)
+        .map(|((label, on_click), row)| {
+            menu.add(
+                ui::Button::new(
+                    "assets/particles/window_01.png",
+                    ui::Layout::new(ui::Anchor::Center)
+                        .offset(0., f64::from(row).mul_add(-100., 60.))
+                        .size(320., 80.),
+                    on_click,
+                    wnd_ctx,
+                )
+                .with_label(
+                    font.clone(),
+                    &label,
+                    [1., 1., 1., 1.],
+                    wnd_ctx,
+                ),
+            )
+        })
+        .collect();
+    (
+        get_menu_scene(screen_width, screen_height, &menu, 0.5, wnd_ctx),
+        menu,
+        buttons,
+        title,
+    )
+}
+
+/// Gets the labels of the quality, fullscreen, resolution, vsync, language
+/// and back buttons of the settings menu in the current language
+fn settings_labels(
+    quality: settings::Quality,
+    client: &client_config::ClientConfig,
+) -> [String; 6] {
+    let on_off = |on| tr!(if on { "settings.on" } else { "settings.off" });
+    [
+        tr!("settings.quality", tr!(&format!("quality.{}", quality))),
+        tr!("settings.fullscreen", on_off(client.fullscreen)),
+        tr!("settings.resolution", client.width, client.height),
+        tr!("settings.vsync", on_off(client.vsync)),
+        tr!(
+            "settings.language",
+            localization::language_name(&localization::language())
+        ),
+        tr!("menu.back"),
+    ]
+}
+
+/// Sets the text of a menu's title or button in the current language
+type Relabel = Box<dyn Fn()>;
+
+/// Gets the relabel which sets the label of `button` to the string of `key`
+fn relabel_button(
+    button: Rc<RefCell<ui::Button<'static>>>,
+    key: &str,
+) -> Relabel {
+    let key = key.to_owned();
+    Box::new(move || button.borrow_mut().set_label_text(&tr!(&key)))
+}
+
+/// Gets the relabel which sets the text of `title` to the string of `key`
+fn relabel_title(title: Rc<RefCell<ui::Label>>, key: &str) -> Relabel {
+    let key = key.to_owned();
+    Box::new(move || title.borrow_mut().set_text(&tr!(&key)))
+}
+
+/// Switches the UI text to the language after the current one, relabels the
+/// menus with `relabels`, and saves the language to the client configuration
+/// at `config_path`
+fn switch_language(
+    client: &mut client_config::ClientConfig,
+    config_path: &str,
+    relabels: &[Relabel],
+) {
+    let language = localization::next_language(
+        &localization::language(),
+        &localization::languages(),
+    );
+    if let Err(e) = localization::set_language(&language) {
+        warn!("Failed to load language {}: {}", language, e);
+        return;
+    }
+    for relabel in relabels {
+        relabel();
+    }
+    client.language = language;
+    if let Err(e) = client.save(config_path) {
+        error!("Failed to save client configuration: {}", e);
+    }
+}
+
+/// Applies the display options clicked in the settings menu to the window,
+/// and saves them to the client configuration at `config_path`
+///
+/// `clicked` - set when the fullscreen, resolution and vsync buttons are
+/// clicked, and cleared once their options are applied
+///
+/// Returns `true` if any option changed
+fn on_display_clicked(
+    wnd: &Window,
+    client: &mut client_config::ClientConfig,
+    config_path: &str,
+    clicked: [&Cell<bool>; 3],
+) -> bool {
+    let [fullscreen, resolution, vsync] = clicked.map(Cell::take);
+    if fullscreen {
+        client.fullscreen = !wnd.is_fullscreen();
+        wnd.set_fullscreen(client.fullscreen);
+    }
+    if resolution {
+        let (width, height) =
+            client_config::next_resolution(client.width, client.height);
+        client.width = width;
+        client.height = height;
+        client.fullscreen = false;
+        wnd.set_size(width, height);
+    }
+    if vsync {
+        client.vsync = !wnd.vsync();
+        wnd.set_vsync(client.vsync);
+    }
+    let changed = fullscreen || resolution || vsync;
+    if changed {
+        if let Err(e) = client.save(config_path) {
+            error!("Failed to save client configuration: {}", e);
+        }
+    }
+    changed
+}
+
+/// Creates a scene for 2D UI covering the screen, whose render targets and
+/// camera are rebuilt when the screen is resized
+fn get_ui_scene(
+    width: u32,
+    height: u32,
+    wnd_ctx: &glium::Display,
+) -> scene::Scene {
+    let camera = Rc::new(RefCell::new(camera::Camera2D::new(width, height)));
+    scene::Scene::new_no_lights(
+        get_ui_render_pass(width, height, wnd_ctx),
+        camera.clone(),
+    )
+    .on_resize(move |width, height, ctx| {
+        camera.borrow_mut().resize(width, height);
+        get_ui_render_pass(width, height, ctx)
+    })
+}
+
+/// Creates a scene which displays `menu` over a black background with an
+/// opacity of `bg_alpha`
+fn get_menu_scene(
+    screen_width: Rc<RefCell<u32>>,
+    screen_height: Rc<RefCell<u32>>,
+    menu: &ui::UiRoot,
+    bg_alpha: f32,
+    wnd_ctx: &glium::Display,
+) -> Box<RefCell<dyn scene::AbstractScene>> {
+    let (width, height) = (*screen_width.borrow(), *screen_height.borrow());
+    let mut menu_scene =
+        get_ui_scene(width, height, wnd_ctx).bg((0., 0., 0., bg_alpha));
+    menu_scene.set_entities(menu.entities());
+    let compositor = scene::compositor_scene_new(
+        screen_width,
+        screen_height,
+        Rc::new(RefCell::new(camera::Camera2D::new(width, height))),
+        vec![(Box::new(menu_scene), None)],
+        wnd_ctx,
+    );
+    Box::new(RefCell::new(compositor))
+}
+
+/// Builds a menu with a title above a column of buttons
+///
+/// `title` - the key of the title's text
+///
+/// `buttons` - the key of the label of each button, from top to bottom, and
+/// the function called when it's clicked. Labels without a localized string,
+/// such as ship names, are shown as they are
+///
+/// Returns the menu scene, the UI of the menu, and the relabels of its title
+/// and buttons
+fn get_menu(
+    screen_width: Rc<RefCell<u32>>,
+    screen_height: Rc<RefCell<u32>>,
+    font: &Rc<text::Font>,
+    title: &str,
+    buttons: Vec<(&str, Box<dyn FnMut()>)>,
+    bg_alpha: f32,
+    wnd_ctx: &glium::Display,
+) -> (
+    Box<RefCell<dyn scene::AbstractScene>>,
+    ui::UiRoot<'static>,
+    Vec<Relabel>,
+) {
+    let mut menu =
+        ui::UiRoot::new(*screen_width.borrow(), *screen_height.borrow());
+    let mut title_label = ui::Label::new(
+        font.clone(),
+        ui::Layout::new(ui::Anchor::Center)
+            .offset(0., 200.)
+            .size(480., 80.),
+        [1., 1., 1., 1.],
+        wnd_ctx,
+    );
+    title_label.set_text(&tr!(title));
+    let mut relabels = vec![relabel_title(menu.add(title_label), title)];
+    for ((key, on_click), row) in buttons.into_iter().zip(0_u32..) {
+        let button = menu.add(
+            ui::Button::new(
+                "assets/particles/window_01.png",
+                ui::Layout::new(ui::Anchor::Center)
+                    .offset(0., f64::from(row).mul_add(-100., 60.))
+                    .size(320., 80.),
+                on_click,
+                wnd_ctx,
+            )
+            .with_label(
+                font.clone(),
+                &tr!(key),
+                [1., 1., 1., 1.],
+                wnd_ctx,
+            ),
+        );
+        relabels.push(relabel_button(button, key));
+    }
+    (
+        get_menu_scene(screen_width, screen_height, &menu, bg_alpha, wnd_ctx),
+        menu,
+        relabels,
+    )
+}
+
+/// Pauses the game if it's being played, resumes it if it's paused, or goes
+/// back to the pause menu from the settings menu
+fn toggle_pause(states: &mut GameStateMachine) {
+    match states.state() {
+        GameState::Playing | GameState::Settings => {
+            states.request(GameState::Paused);
+        }
+        GameState::Paused => {
+            states.request(GameState::Playing);
+        }
+        _ => (),
+    }
+}
+
+/// Passes `ev` to the menu shown in `state`. Clicks are only passed to the
+/// shown menu, but every other event is passed to all menus so that they
+/// track the cursor and screen size while hidden
+///
+/// Returns `true` if any menu consumed the event
+fn dispatch_menu_event(
+    ev: &glutin::event::WindowEvent,
+    state: GameState,
+    menus: &[(GameState, &RefCell<ui::UiRoot>)],
+) -> bool {
+    use glutin::event::WindowEvent;
+    menus.iter().fold(false, |consumed, (menu_state, menu)| {
+        if *menu_state == state || !matches!(ev, WindowEvent::MouseInput { .. })
+        {
+            menu.borrow_mut().on_window_event(ev) || consumed
+        } else {
+            consumed
+        }
+    })
+}
+
+/// Moves the reticle to where player 1's lasers are headed, the lead
+/// marker to where player 1 should aim to hit the target it's locked on to,
+/// and the target bracket around the locked target
+///
+/// `viewport` - region of the screen player 1 is shown in
+///
+/// `viewport_height` - height of player 1's viewport in pixels
+fn update_aim_markers<M: GameMediator>(
+    game: &game::Game<M>,
+    reticle: &RefCell<ui::Marker>,
+    lead_marker: &RefCell<ui::Marker>,
+    target_bracket: &RefCell<ui::Marker>,
+    viewport: &scene::Viewport,
+    viewport_height: u32,
+) {
+    use graphics_engine::drawable::project_to_ndc;
+    /// Distance in front of the player the reticle is projected from
+    const RETICLE_DIST: f64 = 200.;
+    /// Smallest size of the target bracket, in pixels
+    const MIN_BRACKET_SIZE: f64 = 32.;
+    let player = game.player_1();
+    let player = player.borrow();
+    let project = |pt| {
+        project_to_ndc(&*player, pt).map(|ndc| viewport.to_screen_ndc(ndc))
+    };
+    reticle
+        .borrow_mut()
+        .set_position(project(game.aim_point(RETICLE_DIST)));
+    lead_marker
+        .borrow_mut()
+        .set_position(game.target_lead().and_then(project));
 
-    let mut wnd = WindowMaker::new(render_width, render_height)
+    let mut bracket = target_bracket.borrow_mut();
+    let target = game.locked_target().and_then(|target| {
+        let (center, radius) = {
+            let target = target.borrow();
+            let body = &target.get_rigid_body().base;
+            body.collider
+                .as_ref()
+                .map_or((body.center(), 1.), |c| c.bounding_sphere())
+        };
+        let up = player.get_cam().up.cast::<f64>().unwrap();
+        let center_ndc = project_to_ndc(&*player, center)?;
+        // the projected radius is the distance to the top of the bounding
+        // sphere on screen
+        let top_ndc = project_to_ndc(&*player, center + up * radius)
+            .unwrap_or(center_ndc);
+        let size = (center_ndc.distance(top_ndc) * f64::from(viewport_height))
+            .max(MIN_BRACKET_SIZE);
+        Some((center, viewport.to_screen_ndc(center_ndc), size))
+    });
+    if let Some((center, center_ndc, size)) = target {
+        bracket.set_size(size, size);
+        bracket.set_label_text(&format!(
+            "{}",
+            center
+                .distance(player.get_rigid_body().base.center())
+                .round() as u64
+        ));
+        bracket.set_position(Some(center_ndc));
+    } else {
+        bracket.set_position(None);
+    }
+}
+
+/// Shows the damage dealt by `event` on the hit indicator if it was dealt to
+/// player 1
+fn on_player_damaged<M: GameMediator>(
+    game: &game::Game<M>,
+    event: &event_bus::DamageEvent,
+    indicator: &RefCell<hit_indicator::HitIndicator>,
+) {
+    let player = game.player_1();
+    let player = player.borrow();
+    if event.damage.target == player.get_rigid_body().metadata {
+        indicator.borrow_mut().on_hit(
+            &event.damage,
+            player.get_rigid_body().base.center(),
+            &player.get_cam(),
+        );
+    }
+}
+
+/// Fills the minimap or the radar, whichever the player chose to show, with
+/// the bodies in the game and the locked target
+fn update_maps<M: GameMediator>(
+    game: &game::Game<M>,
+    controls: &mut controls::PlayerControls,
+    minimap: &mut minimap::Minimap,
+    radar: &mut radar::Radar,
+) {
+    let show_radar = controls.is_radar_shown();
+    minimap.set_shown(!show_radar);
+    radar.set_shown(show_radar);
+    minimap.zoom(controls.take_minimap_zoom());
+    let target = game
+        .locked_target()
+        .map(|target| target.borrow().get_rigid_body().base.center());
+    if show_radar {
+        radar.clear_items();
+        game.get_mediator().iter_bodies(|bods| {
+            for bod in bods {
+                radar.add_item(bod);
+            }
+        });
+        game.iter_opponents(|bod| radar.add_item(bod));
+        if let Some(target) = target {
+            radar.add_target(target);
+        }
+    } else {
+        minimap.clear_items();
+        game.get_mediator().iter_bodies(|bods| {
+            for bod in bods {
+                minimap.add_item(bod);
+            }
+        });
+        game.iter_opponents(|bod| minimap.add_item(bod));
+        if let Some(target) = target {
+            minimap.add_target(target);
+        }
+    }
+}
+
+/// Shows the tactical map while the player chose to, except during
+/// cutscenes, and fills it with the bodies in the game and the autopilot's
+/// waypoint. The game is slowed down while the map is shown
+fn update_tactical_map<M: GameMediator>(
+    game: &game::Game<M>,
+    controls: &controls::PlayerControls,
+    map: &RefCell<tactical::TacticalMap>,
+    main_scene: &RefCell<scene::Scene>,
+    in_cutscene: bool,
+) {
+    let mut map = map.borrow_mut();
+    if controls.is_tactical_map_shown() && !in_cutscene {
+        map.show(main_scene);
+    } else {
+        map.hide(main_scene);
+    }
+    game.slow_for_tactical_map(map.is_shown());
+    if map.is_shown() {
+        map.clear_items();
+        game.get_mediator().iter_bodies(|bods| {
+            for bod in bods {
+                map.add_item(bod);
+            }
+        });
+        game.iter_opponents(|bod| map.add_item(bod));
+        if let Some(waypoint) = controls.waypoint() {
+            map.add_waypoint(waypoint);
+        }
+    }
+}
+
+/// Engages the autopilot where player 1 asked for it, flying to its locked
+/// target or down the station's approach corridor. Does nothing if there's
+/// no target or player 1 can't dock
+fn engage_autopilot<M: GameMediator>(
+    game: &game::Game<M>,
+    controls: &RefCell<controls::PlayerControls>,
+) {
+    let mut controls = controls.borrow_mut();
+    let destination = match controls.take_autopilot_request() {
+        Some(controls::AutopilotRequest::LockedTarget) => {
+            game.locked_target().map(|target| {
+                controls::Destination::Target(target.borrow().get_node())
+            })
+        }
+        Some(controls::AutopilotRequest::Dock) => {
+            game.docking_approach().map(controls::Destination::Docking)
+        }
+        None => None,
+    };
+    if destination.is_some() {
+        controls.set_destination(destination);
+    }
+}
+
+/// Left clicking the tactical map sends player 1 on autopilot to the
+/// clicked point, at player 1's altitude. Right clicking stops the
+/// autopilot
+///
+/// `cursor` - position of the cursor in the normalized device coordinates of
+/// player 1's viewport
+fn on_tactical_map_click<M: GameMediator>(
+    ev: &glutin::event::WindowEvent,
+    game: &game::Game<M>,
+    controls: &mut controls::PlayerControls,
+    area: &tactical::MapArea,
+    cursor: Point2<f64>,
+) {
+    use glutin::event::{ElementState, MouseButton, WindowEvent};
+    match ev {
+        WindowEvent::MouseInput {
+            state: ElementState::Pressed,
+            button: MouseButton::Left,
+            ..
+        } => {
+            let altitude =
+                game.player_1().borrow().get_node().borrow().get_pos().y;
+            controls.set_waypoint(Some(area.to_world(cursor, altitude)));
+        }
+        WindowEvent::MouseInput {
+            state: ElementState::Pressed,
+            button: MouseButton::Right,
+            ..
+        } => controls.set_waypoint(None),
+        _ => (),
+    }
+}
+
+/// Shows the current objective, announces the latest mission event, and
+/// moves the objective marker to the location of the current objective
+///
+/// In survival games, the current wave is shown instead of the objective and
+/// wave events are announced too
+///
+/// `announcement_time` - seconds left before the announcement is cleared
+///
+/// `viewport` - region of the screen player 1 is shown in
+fn update_mission_hud<M: GameMediator>(
+    game: &game::Game<M>,
+    objective_label: &RefCell<ui::Label>,
+    announcement: &RefCell<ui::Label>,
+    announcement_time: &Cell<f64>,
+    objective_marker: &RefCell<ui::Marker>,
+    viewport: &scene::Viewport,
+    dt: std::time::Duration,
+) {
+    use graphics_engine::drawable::project_to_ndc;
+    /// Seconds a mission event is announced for
+    const ANNOUNCEMENT_SECS: f64 = 3.;
+    {
+        let mut mission = game.mission();
+        let waves = game.wave_hud_text();
+        let objective = if waves.is_empty() {
+            mission.hud_text()
+        } else {
+            waves
+        };
+        objective_label.borrow_mut().set_text(&objective);
+        let event = mission.take_events().last().map(ToString::to_string);
+        if let Some(event) = game
+            .take_wave_events()
+            .last()
+            .map(ToString::to_string)
+            .or(event)
+        {
+            announcement.borrow_mut().set_text(&event);
+            announcement_time.set(ANNOUNCEMENT_SECS);
+        } else if announcement_time.get() > 0. {
+            announcement_time.set(announcement_time.get() - dt.as_secs_f64());
+            if announcement_time.get() <= 0. {
+                announcement.borrow_mut().set_text("");
+            }
+        }
+    }
+    let player = game.player_1();
+    objective_marker.borrow_mut().set_position(
+        game.objective_marker()
+            .and_then(|pos| project_to_ndc(&*player.borrow(), pos))
+            .map(|ndc| viewport.to_screen_ndc(ndc)),
+    );
+}
+
+/// F3 toggles the profiler and F4 dumps the frame times it kept
+fn on_profiler_key(key: glutin::event::VirtualKeyCode) {
+    use glutin::event::VirtualKeyCode;
+    match key {
+        VirtualKeyCode::F3 => profiler::set_enabled(!profiler::is_enabled()),
+        VirtualKeyCode::F4 => match profiler::dump_csv(profiler::CSV_PATH) {
+            Ok(()) => info!("Saved frame times to {}", profiler::CSV_PATH),
+            Err(e) => error!("Failed to save frame times: {}", e),
+        },
+        _ => (),
+    }
+}
+
+/// F12 saves a screenshot and F11 starts or stops recording the frames
+fn on_capture_key(
+    key: glutin::event::VirtualKeyCode,
+    video: &capture::VideoSettings,
+) {
+    use glutin::event::VirtualKeyCode;
+    match key {
+        VirtualKeyCode::F12 => capture::request_screenshot(),
+        VirtualKeyCode::F11 if capture::is_recording() => {
+            capture::stop_recording();
+        }
+        VirtualKeyCode::F11 => match capture::start_recording(video) {
+            Ok(()) => info!("Recording {:?}", video.duration),
+            Err(e) => error!("Failed to start recording: {}", e),
+        },
+        _ => (),
+    }
+}
+
+/// Lines of the profiler overlay
+const PROFILER_LINES: usize = 10;
+
+/// Shows the frame and stage times on the lines of the profiler overlay, or
+/// clears them if the profiler is off
+fn update_profiler_hud(lines: &[Rc<RefCell<ui::Label>>]) {
+    let text = if profiler::is_enabled() {
+        profiler::overlay_lines(lines.len())
+    } else {
+        Vec::new()
+    };
+    for (i, line) in lines.iter().enumerate() {
+        line.borrow_mut()
+            .set_text(text.get(i).map_or("", String::as_str));
+    }
+}
+
+/// Lines of the overlay of recent warnings and errors
+const LOG_LINES: usize = 5;
+
+/// Shows the latest warnings and errors on the lines of the log overlay,
+/// newest at the bottom, or clears them if the overlay is off
+fn update_log_hud(lines: &[Rc<RefCell<ui::Label>>], enabled: bool) {
+    let text = if enabled {
+        logging::overlay_lines(std::time::Instant::now(), lines.len())
+    } else {
+        Vec::new()
+    };
+    // the lines are filled from the bottom
+    let blank = lines.len().saturating_sub(text.len());
+    for (i, line) in lines.iter().enumerate() {
+        line.borrow_mut().set_text(
+            i.checked_sub(blank)
+                .and_then(|i| text.get(i))
+                .map_or("", String::as_str),
+        );
+    }
+}
+
+/// Lines of output the developer console shows
+const CONSOLE_LINES: usize = 12;
+
+/// Gets the developer console, with commands to outline the colliders,
+/// spawn asteroids, change the time scale and profile the frames
+///
+/// `debug_draw` - set by the console when the colliders are outlined
+fn get_console<'a, M: GameMediator>(
+    game: &'a RefCell<game::Game<M>>,
+    debug_draw: &'a Cell<bool>,
+) -> console::Console<'a> {
+    use console::{parse_arg, parse_toggle};
+    let on_off = |on| if on { "on" } else { "off" };
+    let mut console = console::Console::new();
+    console.register("debug_draw", "[on|off]", move |args| {
+        debug_draw.set(parse_toggle(args, debug_draw.get())?);
+        Ok(format!("Collider outlines: {}", on_off(debug_draw.get())))
+    });
+    console.register("spawn_asteroid", "[scale] [distance]", move |args| {
+        let scale = parse_arg(args, 0, 0.3)?;
+        let dist = parse_arg(args, 1, 60.)?;
+        if scale <= 0. {
+            return Err("The scale must be positive".to_owned());
+        }
+        game.borrow()
+            .spawn_asteroid(scale, dist)
+            .map(|_| format!("Spawned an asteroid {} ahead", dist))
+            .ok_or_else(|| "No object ids left".to_owned())
+    });
+    console.register("time_scale", "<scale> [seconds]", move |args| {
+        let scale: f64 = parse_arg(args, 0, -1.)?;
+        let secs: f64 = parse_arg(args, 1, 0.)?;
+        if scale <= 0. || secs < 0. {
+            return Err("Expected a positive scale".to_owned());
+        }
+        game.borrow()
+            .set_time_scale(scale, std::time::Duration::from_secs_f64(secs));
+        Ok(format!("Time scale: {}", scale))
+    });
+    console.register("profile", "[on|off|stats|dump [path]]", move |args| {
+        match args {
+            ["stats"] if profiler::is_enabled() => {
+                Ok(profiler::overlay_lines(PROFILER_LINES).join("\n"))
+            }
+            ["stats"] => Err("The profiler is off".to_owned()),
+            ["dump", path @ ..] => {
+                let path = path.first().copied().unwrap_or(profiler::CSV_PATH);
+                profiler::dump_csv(path)
+                    .map(|()| format!("Saved frame times to {}", path))
+                    .map_err(|e| e.to_string())
+            }
+            _ => {
+                profiler::set_enabled(parse_toggle(
+                    args,
+                    profiler::is_enabled(),
+                )?);
+                Ok(format!("Profiler: {}", on_off(profiler::is_enabled())))
+            }
+        }
+    });
+    console
+}
+
+/// Shows the output and the line being typed of `console` on `lines`, the
+/// last of which is the line being typed, or clears them if it's closed
+fn update_console_hud(
+    console: &console::Console,
+    lines: &[Rc<RefCell<ui::Label>>],
+) {
+    let (input, output) = lines.split_last().unwrap();
+    if !console.is_open() {
+        for line in lines {
+            line.borrow_mut().set_text("");
+        }
+        return;
+    }
+    let text: Vec<_> = console.output(output.len()).collect();
+    for (i, line) in output.iter().enumerate() {
+        line.borrow_mut()
+            .set_text(text.get(i).copied().unwrap_or_default());
+    }
+    input
+        .borrow_mut()
+        .set_text(&format!("> {}_", console.input()));
+}
+
+/// Outlines the bounding sphere of every collider in `tree` with a box of
+/// `boxes` if `enabled`, or hides the boxes
+fn update_collider_boxes(
+    boxes: &RefCell<entity::Entity>,
+    tree: &collisions::CollisionTree,
+    enabled: bool,
+) {
+    let mut boxes = boxes.borrow_mut();
+    boxes.locations.clear();
+    if enabled {
+        for obj in tree.get_all_objects() {
+            let (center, radius) = obj.bounding_sphere();
+            boxes.locations.push(Rc::new(RefCell::new(
+                node::Node::default().pos(center).u_scale(radius),
+            )));
+        }
+    }
+}
+
+/// Adds the result of the session tracked by `score` to the leaderboard
+/// file
+fn record_score(score: &scoring::Score) {
+    let mut leaderboard =
+        scoring::Leaderboard::load_or_default(scoring::LEADERBOARD_PATH);
+    let stats = score.stats();
+    info!(
+        "Scored {} with {:.0}% accuracy in {:.0}s, destroying {} asteroids \
+        and {} ships, dealing {:.0} damage and taking {:.0}",
+        score.points(),
+        stats.accuracy() * 100.,
+        stats.time_alive.as_secs_f64(),
+        stats.asteroids_destroyed,
+        stats.ships_destroyed,
+        stats.damage_dealt,
+        stats.damage_taken
+    );
+    if let Some(rank) = leaderboard.insert(score.entry()) {
+        info!("New high score! Rank {}", rank + 1);
+    }
+    if let Err(e) = leaderboard.save(scoring::LEADERBOARD_PATH) {
+        error!("Failed to save leaderboard: {}", e);
+    }
+}
+
+/// Path of the file the local session is saved to from the pause menu
+const SAVE_PATH: &str = "saves/quicksave.sav";
+
+/// Saves the session `game` is playing on the map with the seed `map_seed`
+fn save_session<M: GameMediator>(game: &game::Game<M>, map_seed: Option<u64>) {
+    match game.save_game(map_seed).save(SAVE_PATH) {
+        Ok(()) => info!("Saved game to {}", SAVE_PATH),
+        Err(e) => error!("Failed to save game: {}", e),
+    }
+}
+
+/// Loads the saved session into `game`, unless it was saved on a map other
+/// than the map with the seed `map_seed`
+///
+/// Returns `true` if the session was loaded
+fn load_session<M: GameMediator>(
+    game: &game::Game<M>,
+    map_seed: Option<u64>,
+) -> bool {
+    match shared_types::save::SaveGame::from_file(SAVE_PATH) {
+        Ok(save) if save.map_seed == map_seed => {
+            game.load_game(&save);
+            true
+        }
+        Ok(_) => {
+            warn!("Cannot load a game saved on a different map");
+            false
+        }
+        Err(e) => {
+            error!("Failed to load game: {}", e);
+            false
+        }
+    }
+}
+
+/// Gets the intro flyover, which sweeps past the planet at the center of the
+/// asteroid field before settling behind player 1
+fn intro_flyover(player: &player::Player) -> cinematic::CameraPath {
+    use cinematic::Keyframe;
+    let up = vec3(0., 1., 0.);
+    let planet = point3(0., 0., 0.);
+    let ship = player.root().borrow().local_pos();
+    let behind = ship - player.forward() * 25. + up * 15.;
+    cinematic::CameraPath::new(vec![
+        Keyframe::looking_at(0., point3(-700., 300., -700.), planet, up),
+        Keyframe::looking_at(4., point3(0., 150., -800.), planet, up),
+        Keyframe::looking_at(8., ship + vec3(150., 60., -250.), ship, up),
+        Keyframe::looking_at(11., behind, ship, up),
+    ])
+}
+
+/// Starts recording the player's inputs to a file if the game was started
+/// with `--record <path>`, or plays back a recording if it was started with
+/// `--replay <path>`
+fn apply_input_args(controls: &RefCell<controls::PlayerControls>) {
+    let args: Vec<String> = std::env::args().collect();
+    for arg in args.windows(2) {
+        match arg[0].as_str() {
+            "--record" => {
+                if let Err(e) = controls.borrow_mut().start_recording(&arg[1]) {
+                    error!("Could not record inputs: {}", e);
+                }
+            }
+            "--replay" => {
+                if let Err(e) = controls.borrow_mut().start_playback(&arg[1]) {
+                    error!("Could not replay inputs: {}", e);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Loads the map chosen on the command line, or the asteroid map if none was
+/// chosen or the chosen map file can't be loaded
+fn load_map(choice: Option<&MapChoice>) -> Box<dyn Map> {
+    match choice {
+        Some(MapChoice::Procedural(seed)) => {
+            Box::new(ProceduralMap::new(*seed))
+        }
+        Some(MapChoice::File(name)) => match FileMap::by_name(name) {
+            Ok(map) => Box::new(map),
+            Err(e) => {
+                error!("Could not load map {}: {}", name, e);
+                Box::new(AsteroidMap {})
+            }
+        },
+        None => Box::new(AsteroidMap {}),
+    }
+}
+
+/// Gets `count` laser lights spread within `220` units of `center`, so the
+/// light culling can be profiled with many lights on screen
+fn benchmark_lights(
+    count: usize,
+    center: Point3<f32>,
+) -> Vec<shader::LightData> {
+    // golden angle spiral, so the lights are spread evenly in every direction
+    let golden_angle = std::f32::consts::PI * (3. - 5f32.sqrt());
+    (0..count)
+        .map(|i| {
+            let t = (i as f32 + 0.5) / count as f32;
+            let dir = {
+                let z = 1. - 2. * t;
+                let r = (1. - z * z).sqrt();
+                let theta = golden_angle * i as f32;
+                vec3(r * theta.cos(), r * theta.sin(), z)
+            };
+            let pos = center + dir * (20. + 200. * ((i * 7) % 11) as f32 / 10.);
+            shader::LightData::tube_light(
+                pos + dir.cross(Vector3::unit_y()) * 3.,
+                pos - dir.cross(Vector3::unit_y()) * 3.,
+                1.5,
+                80.,
+                vec3(0.5451, 0., 0.5451),
+            )
+        })
+        .collect()
+}
+
+/// Gets the settings of the headless bots to run instead of the game if the
+/// game was started with `--bots <count>`. The bots connect to the configured
+/// server, are named after the configured player, and play for
+/// `--duration <secs>`, or until the process is killed
+fn bot_args(client: &client_config::ClientConfig) -> Option<bot::BotConfig> {
+    let args: Vec<String> = std::env::args().collect();
+    let arg = |name: &str| {
+        args.windows(2)
+            .find(|arg| arg[0] == name)
+            .map(|arg| arg[1].as_str())
+    };
+    let count = match arg("--bots")?.parse() {
+        Ok(count) => count,
+        Err(e) => {
+            error!("Invalid bot count: {}", e);
+            return None;
+        }
+    };
+    let duration = match arg("--duration").map(str::parse::<u64>) {
+        Some(Ok(secs)) => Some(std::time::Duration::from_secs(secs)),
+        Some(Err(e)) => {
+            error!("Invalid bot duration: {}", e);
+            return None;
+        }
+        None => None,
+    };
+    Some(bot::BotConfig {
+        count,
+        server: (client.server.ip(), client.server.port()),
+        name: client.player_name.clone(),
+        duration,
+    })
+}
+
+/// Where player 2 starts a split screen game, beside player 1
+const PLAYER_2_START: Point3<f64> = Point3::new(60., 0., 0.);
+
+/// Creates player 2 of a split screen game, who flies `ship` with the
+/// gamepad
+fn make_player_2(
+    controller: &mut LocalGameController,
+    aspect: f32,
+    ship: &ship::ShipDef,
+    wnd_ctx: &glium::Display,
+) -> (player::Player, Rc<RefCell<controls::PlayerControls>>) {
+    let controls =
+        Rc::new(RefCell::new(controls::PlayerControls::new().with_gamepad()));
+    let player = player::Player::new(
+        model::Model::new(&ship.model, wnd_ctx),
+        aspect,
+        ship,
+        controller.alloc_obj(),
+        controls.clone(),
+    );
+    player.get_node().borrow_mut().set_pos(PLAYER_2_START);
+    (player, controls)
+}
+
+/// Distance from the origin to the edges of the battle area, which the
+/// simulation's collision tree and the tactical map cover
+const BATTLE_AREA_SIZE: f64 = 1500.;
+
+/// Path of the definition of the ship flown by the AI
+const AI_SHIP_PATH: &str = "assets/Ships/raider.ship";
+
+/// Creates the enemies of the mission, which fly `ship`
+fn make_enemies(
+    controller: &mut LocalGameController,
+    aspect: f32,
+    ship: &ship::ShipDef,
+    wnd_ctx: &glium::Display,
+) -> Vec<player::Player> {
+    let squad = Rc::new(RefCell::new(controls::Squad::new(30.)));
+    let mut enemies: Vec<_> = [
+        (controls::AiProfile::normal(), point3(400., 100., 100.)),
+        (controls::AiProfile::easy(), point3(430., 100., 70.)),
+        (controls::AiProfile::ace(), point3(370., 100., 70.)),
+    ]
+    .iter()
+    .map(|&(profile, pos)| {
+        let enemy = player::Player::new(
+            model::Model::new(&ship.model, wnd_ctx),
+            aspect,
+            ship,
+            controller.alloc_obj(),
+            get_squad_ai_controller(profile, &squad),
+        );
+        enemy.get_node().borrow_mut().set_pos(pos);
+        enemy
+    })
+    .collect();
+    // a sentry which patrols around its post and attacks intruders
+    let post = point3(-200., 50., -200.);
+    let sentry = player::Player::new(
+        model::Model::new(&ship.model, wnd_ctx),
+        aspect,
+        ship,
+        controller.alloc_obj(),
+        get_guard_ai_controller(
+            controls::AiProfile::normal(),
+            post,
+            250.,
+            vec![
+                post + vec3(100., 0., 0.),
+                post + vec3(0., 0., 100.),
+                post + vec3(-100., 0., 0.),
+                post + vec3(0., 0., -100.),
+            ],
+        ),
+    );
+    sentry.get_node().borrow_mut().set_pos(post);
+    enemies.push(sentry);
+    enemies
+}
+
+/// Creates the ships that are sent at the player in waves in a survival
+/// game, which fly `ship`. Each difficulty has enough ships for the largest
+/// wave
+fn make_reserves(
+    controller: &mut LocalGameController,
+    aspect: f32,
+    ship: &ship::ShipDef,
+    wnd_ctx: &glium::Display,
+) -> Vec<(player::Player, survival::Difficulty)> {
+    use survival::Difficulty;
+    let squad = Rc::new(RefCell::new(controls::Squad::new(30.)));
+    [Difficulty::Easy, Difficulty::Normal, Difficulty::Ace]
+        .iter()
+        .flat_map(|&difficulty| {
+            (0..survival::MAX_PER_DIFFICULTY).map(move |_| difficulty)
+        })
+        .map(|difficulty| {
+            let profile = match difficulty {
+                Difficulty::Easy => controls::AiProfile::easy(),
+                Difficulty::Normal => controls::AiProfile::normal(),
+                Difficulty::Ace => controls::AiProfile::ace(),
+            };
+            let enemy = player::Player::new(
+                model::Model::new(&ship.model, wnd_ctx),
+                aspect,
+                ship,
+                controller.alloc_obj(),
+                get_squad_ai_controller(profile, &squad),
+            );
+            (enemy, difficulty)
+        })
+        .collect()
+}
+
+/// The local game, once the lighting has been taken out of its mediator
+type LocalGame = game::Game<LocalGameMediator<NoLightingAvailable>>;
+
+/// The local game before the lighting is taken out of its mediator
+type LitLocalGame = game::Game<LocalGameMediator<HasLightingAvailable>>;
+
+/// A scene and the name it's shown by
+type NamedScene = (&'static str, Box<RefCell<dyn scene::AbstractScene>>);
+
+/// The settings of the client, which are changed in the settings menu
+struct ClientSettings {
+    client: RefCell<client_config::ClientConfig>,
+    /// Where changes to the client configuration are saved
+    client_path: String,
+    /// Shared by the resize handler and the settings menu
+    graphics: RefCell<settings::GraphicsSettings>,
+    /// Lowers the quality when frames take too long, if there's a target
+    /// frame rate
+    governor: RefCell<governor::FrameGovernor>,
+    video: capture::VideoSettings,
+}
+
+impl ClientSettings {
+    fn new(client: client_config::ClientConfig, client_path: String) -> Self {
+        let mut graphics = settings::GraphicsSettings::load_or_default(
+            settings::SETTINGS_PATH,
+        );
+        // the render targets match the window
+        graphics.render_width = client.width;
+        graphics.render_height = client.height;
+        particles::set_density(graphics.particle_density);
+        // the light benchmark is profiled to compare the light culling modes
+        profiler::set_enabled(
+            client.launch.light_benchmark > 0 || client.launch.profile,
+        );
+        Self {
+            governor: RefCell::new(governor::FrameGovernor::new(
+                graphics.target_fps,
+            )),
+            video: client.video_settings(),
+            graphics: RefCell::new(graphics),
+            client: RefCell::new(client),
+            client_path,
+        }
+    }
+
+    /// Gets the size of the render targets in pixels
+    fn render_size(&self) -> (u32, u32) {
+        let graphics = self.graphics.borrow();
+        (graphics.render_width, graphics.render_height)
+    }
+}
+
+/// Opens the window, and records the GPU and graphics settings in crash
+/// reports
+fn make_window(settings: &ClientSettings) -> Window {
+    let client = settings.client.borrow();
+    let wnd = WindowMaker::new(client.width, client.height)
         .title("Space Fight")
         .depth_buffer(24)
+        .fullscreen(client.fullscreen)
+        .vsync(client.vsync)
         .build();
+    {
+        let ctx = wnd.ctx();
+        crash::set_context(
+            "GPU",
+            format!(
+                "{}\n{}\nOpenGL {}",
+                ctx.get_opengl_vendor_string(),
+                ctx.get_opengl_renderer_string(),
+                ctx.get_opengl_version_string()
+            ),
+        );
+    }
+    crash::set_context(
+        "Graphics settings",
+        settings.graphics.borrow().to_string(),
+    );
+    wnd
+}
 
-    let mut controller = LocalGameController::new(
-        &shared_types::game_controller::AsteroidMap {},
+/// Loads the models of the objects and of `ships`, and the HUD font
+///
+/// The models and fonts used from the start are read by the asset workers at
+/// the same time, instead of one after another as they're first used
+fn preload_assets<'a>(
+    wnd: &Window,
+    ships: impl Iterator<Item = &'a ship::ShipDef>,
+) -> Rc<text::Font> {
+    for path in game_mediator::OBJECT_MODELS
+        .iter()
+        .copied()
+        .chain(ships.map(|ship| ship.model.as_str()))
+    {
+        wnd.assets().preload_model(path);
+    }
+    // glyphs the HUD font is missing are drawn with the fonts the languages
+    // fall back on
+    let font = wnd.assets().load_font(
+        "assets/fonts/SignedDistanceArial.fnt",
+        &localization::fallback_fonts(),
     );
-    let player_controls =
-        Rc::new(RefCell::new(controls::PlayerControls::new()));
-    let player = player::Player::new(
-        model::Model::new("assets/Ships/StarSparrow01.obj", &*wnd.ctx()),
-        render_width as f32 / render_height as f32,
-        "assets/Ships/StarSparrow01.obj",
+    wnd.assets().finish_loading(&*wnd.ctx());
+    font.get()
+        .unwrap_or_else(|| panic!("{}", font.error().unwrap_or_default()))
+}
+
+/// The players who aren't controlled by the AI
+struct Players {
+    /// The controls of player 1
+    controls: Rc<RefCell<controls::PlayerControls>>,
+    /// Player 2 of a split screen game, and their controls
+    player_2: Option<(
+        Rc<RefCell<player::Player>>,
+        Rc<RefCell<controls::PlayerControls>>,
+    )>,
+    /// The region of the screen each player is shown in
+    viewports: Vec<scene::Viewport>,
+}
+
+/// Creates player 1, who flies `ship`, and player 2 if a split screen game
+/// was chosen
+///
+/// Split screen games add player 2, who plays against player 1 with the
+/// gamepad on the right half of the screen. The HUD only shows player 1
+fn make_players(
+    settings: &ClientSettings,
+    controller: &mut LocalGameController,
+    ship: &ship::ShipDef,
+    wnd_ctx: &glium::Display,
+) -> (player::Player, Players) {
+    let client = settings.client.borrow();
+    let graphics = settings.graphics.borrow();
+    let split_screen = client.launch.split_screen;
+    let viewports = if split_screen {
+        scene::Viewport::split_screen(2)
+    } else {
+        vec![scene::Viewport::FULL]
+    };
+    let controls = {
+        let controls =
+            controls::PlayerControls::new().with_bindings(client.input_map());
+        Rc::new(RefCell::new(if split_screen {
+            controls
+        } else {
+            controls.with_gamepad()
+        }))
+    };
+    apply_input_args(&controls);
+    let mut player = player::Player::new(
+        model::Model::new(&ship.model, wnd_ctx),
+        viewports[0].aspect(graphics.render_width, graphics.render_height),
+        ship,
         controller.get_player_stats().pid,
-        player_controls.clone(),
+        controls.clone(),
     );
+    *player.camera_effects() = camera_effects::CameraEffects::new()
+        .intensity(f64::from(graphics.screen_shake))
+        .max_fov_offset(f64::from(graphics.speed_fov));
+    let player_2 = split_screen.then(|| {
+        let aspect =
+            viewports[1].aspect(graphics.render_width, graphics.render_height);
+        let (player, controls) =
+            make_player_2(controller, aspect, ship, wnd_ctx);
+        (Rc::new(RefCell::new(player)), controls)
+    });
+    let players = Players {
+        controls,
+        player_2,
+        viewports,
+    };
+    (player, players)
+}
 
-    let enemy_controls = get_std_ai_controller();
-    let enemy = player::Player::new(
-        model::Model::new("assets/Ships/StarSparrow02.obj", &*wnd.ctx()),
-        render_width as f32 / render_height as f32,
-        "assets/Ships/StarSparrow02.obj",
-        controller.alloc_obj(),
-        enemy_controls.clone(),
-    );
-    enemy
-        .get_node()
-        .borrow_mut()
-        .set_pos(point3(400., 100., 100.));
+/// Creates the mediator of a local game on `controller`'s map
+///
+/// Survival games send waves of enemies at the player instead of playing the
+/// mission
+fn make_mediator(
+    survival: bool,
+    controller: LocalGameController,
+    wnd: &Window,
+) -> LocalGameMediator<HasLightingAvailable> {
+    let ctx = wnd.ctx();
+    if survival {
+        LocalGameMediator::<HasLightingAvailable>::new(
+            &wnd.shaders,
+            &*ctx,
+            SurvivalGameController::new(controller),
+        )
+    } else {
+        LocalGameMediator::<HasLightingAvailable>::new(
+            &wnd.shaders,
+            &*ctx,
+            controller,
+        )
+    }
+}
 
-    let mediator = LocalGameMediator::<HasLightingAvailable>::new(
-        &wnd.shaders,
-        &*wnd.ctx(),
-        controller,
+/// Plays the music, and the sound effects of `game`, if there's an audio
+/// device
+fn start_audio(game: &mut LitLocalGame) {
+    match audio::AudioEngine::new() {
+        Ok(mut audio) => {
+            if let Err(e) = audio.play_music(game::MUSIC_TRACK) {
+                warn!("Could not play music: {}", e);
+            }
+            game.set_audio(audio);
+        }
+        Err(e) => warn!("Audio disabled: {}", e),
+    }
+}
+
+/// Creates the game on `controller`'s map, which player 1 plays as `player`
+/// against the enemies, who fly `ai_ship`
+fn make_game(
+    client: &client_config::ClientConfig,
+    mut controller: LocalGameController,
+    player: player::Player,
+    player_2: Option<Rc<RefCell<player::Player>>>,
+    ai_ship: &ship::ShipDef,
+    wnd: &Window,
+) -> LitLocalGame {
+    let survival = client.launch.survival;
+    let aspect = player.aspect;
+    let (enemies, reserves) = {
+        let ctx = wnd.ctx();
+        if survival {
+            (
+                Vec::new(),
+                make_reserves(&mut controller, aspect, ai_ship, &ctx),
+            )
+        } else {
+            (
+                make_enemies(&mut controller, aspect, ai_ship, &ctx),
+                Vec::new(),
+            )
+        }
+    };
+    let mission = mission::Mission::new(if survival {
+        Vec::new()
+    } else {
+        controller.get_mission().to_vec()
+    });
+    let pickups = pickup::Pickups::new(
+        controller.get_pickups().cloned().unwrap_or_default(),
     );
-    let mut game = game::Game::new(mediator, player);
-    game.add_character(Rc::new(RefCell::new(enemy)));
+    let mut game =
+        game::Game::new(make_mediator(survival, controller, wnd), player);
+    game.set_mission(mission);
+    game.set_pickups(pickups);
+    if let Some(player_2) = player_2 {
+        game.add_character(player_2);
+    }
+    for enemy in enemies {
+        game.add_character(Rc::new(RefCell::new(enemy)));
+    }
+    for (enemy, difficulty) in reserves {
+        game.add_reserve(Rc::new(RefCell::new(enemy)), difficulty);
+    }
+    start_audio(&mut game);
     // TODO: enemy AI doesn't terminate
+    game
+}
 
-    let mut main_scene = scene::Scene::new(
-        get_main_render_pass(
-            render_width,
-            render_height,
-            game.player_1(),
-            &*wnd.ctx(),
-        ),
-        game.player_1(),
-    );
+/// Creates the scene each player sees `game` through, and takes the lighting
+/// out of `game` to light them
+///
+/// Every player sees the game through their own scene, which shares the
+/// entities and environment maps of the other players' scenes
+fn make_views(
+    game: LitLocalGame,
+    players: &Players,
+    graphics_settings: &settings::GraphicsSettings,
+    obstacles: &collisions::CollisionTree,
+    wnd_ctx: &glium::Display,
+) -> (LocalGame, Vec<PlayerView>) {
+    let views: Vec<PlayerView> = std::iter::once(game.player_1())
+        .chain(players.player_2.iter().map(|(player, _)| player.clone()))
+        .zip(&players.viewports)
+        .map(|(player, viewport)| {
+            let scene = scene::Scene::new(
+                get_main_render_pass(
+                    &viewport_settings(graphics_settings, viewport),
+                    player.clone(),
+                    obstacles,
+                    wnd_ctx,
+                ),
+                player.clone(),
+            );
+            (Rc::new(RefCell::new(scene)), player, *viewport)
+        })
+        .collect();
     let (ibl, ldir, game) = game.get_lighting();
-    main_scene.set_ibl_maps(ibl);
-    main_scene.set_light_dir(ldir);
-
-    let mut ui_scene = scene::Scene::new_no_lights(
-        get_ui_render_pass(render_width, render_height, &*wnd.ctx()),
-        Rc::new(RefCell::new(camera::Camera2D::new(
-            render_width,
-            render_height,
-        ))),
+    let ibl = Rc::new(ibl);
+    for (scene, _, _) in &views {
+        scene.borrow_mut().set_ibl_maps(ibl.clone());
+        scene.borrow_mut().set_light_dir(ldir);
+    }
+    (game, views)
+}
+
+/// A match which hasn't started yet
+struct Match {
+    game: LocalGame,
+    simulation: physics::Simulation<'static, 'static, object::ObjectData>,
+    /// The scene each player sees the match through
+    views: Vec<PlayerView>,
+    players: Players,
+    /// The seed of the map, if it's procedural
+    map_seed: Option<u64>,
+}
+
+/// Creates the match on the map chosen on the command line, in which player 1
+/// flies `ship` and the enemies fly `ai_ship`
+fn make_match(
+    settings: &ClientSettings,
+    ship: &ship::ShipDef,
+    ai_ship: &ship::ShipDef,
+    wnd: &Window,
+) -> Match {
+    let map = load_map(settings.client.borrow().launch.map.as_ref());
+    let map_seed = map.seed();
+    // objects of every type are loaded with the map, so their collision
+    // meshes are loaded up front instead of while the first ones spawn
+    collisions::preload_meshes(object::collision_meshes());
+    let mut controller = LocalGameController::new(map);
+    let (player, players) =
+        make_players(settings, &mut controller, ship, &wnd.ctx());
+    let game = make_game(
+        &settings.client.borrow(),
+        controller,
+        player,
+        players.player_2.as_ref().map(|(player, _)| player.clone()),
+        ai_ship,
+        wnd,
+    );
+    // the simulation is made before the render passes, which watch the
+    // bodies of its collision tree for changes
+    let simulation = physics::Simulation::<object::ObjectData>::new(
+        point3(0., 0., 0.),
+        BATTLE_AREA_SIZE,
     );
+    let (game, views) = make_views(
+        game,
+        &players,
+        &settings.graphics.borrow(),
+        simulation.get_collision_tree(),
+        &wnd.ctx(),
+    );
+    Match {
+        game,
+        simulation,
+        views,
+        players,
+        map_seed,
+    }
+}
 
-    let mut map_scene = scene::Scene::new_no_lights(
-        get_ui_render_pass(render_width, render_height, &*wnd.ctx()),
-        Rc::new(RefCell::new(camera::Camera2D::new(
-            render_width,
-            render_height,
-        ))),
-    )
-    .bg((0., 0., 0., 0.6));
-    let map = minimap::Minimap::new(
-        game.player_1().borrow().root().clone(),
-        3000.,
-        &*wnd.ctx(),
+/// The HUD shown over player 1's view
+struct Hud {
+    root: RefCell<ui::UiRoot<'static>>,
+    shield: Rc<RefCell<ui::Label>>,
+    energy: Rc<RefCell<ui::Label>>,
+    reticle: Rc<RefCell<ui::Marker>>,
+    lead_marker: Rc<RefCell<ui::Marker>>,
+    target_bracket: Rc<RefCell<ui::Marker>>,
+    objective: Rc<RefCell<ui::Label>>,
+    announcement: Rc<RefCell<ui::Label>>,
+    /// How long the announcement has been shown, in seconds
+    announcement_time: Cell<f64>,
+    respawn: Rc<RefCell<ui::Label>>,
+    score: Rc<RefCell<ui::Label>>,
+    net: Rc<RefCell<ui::Label>>,
+    quality: Rc<RefCell<ui::Label>>,
+    profiler_lines: Vec<Rc<RefCell<ui::Label>>>,
+    log_lines: Vec<Rc<RefCell<ui::Label>>>,
+    console_lines: Vec<Rc<RefCell<ui::Label>>>,
+    objective_marker: Rc<RefCell<ui::Marker>>,
+    hit_indicator: Rc<RefCell<hit_indicator::HitIndicator>>,
+}
+
+impl Hud {
+    /// Creates the HUD of a `screen` sized in pixels, whose hit indicator
+    /// shows the damage dealt to player 1 in `game`
+    fn new(
+        game: &mut LocalGame,
+        font: &Rc<text::Font>,
+        (width, height): (u32, u32),
+        wnd: &Window,
+    ) -> Self {
+        let mut root = ui::UiRoot::new(width, height);
+        let [shield, energy] = add_status_panel(&mut root, font, wnd);
+        let ctx = wnd.ctx();
+        let [reticle, lead_marker, target_bracket] =
+            add_aim_markers(&mut root, font, &ctx);
+        let [objective, announcement, respawn, score, net, quality] =
+            add_match_labels(&mut root, font, &ctx);
+        let [profiler_lines, log_lines, console_lines] =
+            add_overlay_lines(&mut root, font, &ctx);
+        let objective_marker = root.add(ui::Marker::new(
+            "assets/particles/circle_05.png",
+            32.,
+            32.,
+            &*ctx,
+        ));
+        let hit_indicator =
+            Rc::new(RefCell::new(hit_indicator::HitIndicator::new(&*ctx)));
+        game.subscribe({
+            let hit_indicator = hit_indicator.clone();
+            move |game, event: &event_bus::DamageEvent| {
+                on_player_damaged(game, event, &hit_indicator);
+            }
+        });
+        Self {
+            root: RefCell::new(root),
+            shield,
+            energy,
+            reticle,
+            lead_marker,
+            target_bracket,
+            objective,
+            announcement,
+            announcement_time: Cell::new(0.),
+            respawn,
+            score,
+            net,
+            quality,
+            profiler_lines,
+            log_lines,
+            console_lines,
+            objective_marker,
+            hit_indicator,
+        }
+    }
+
+    /// Creates the scene the HUD is shown in
+    fn scene(
+        &self,
+        (width, height): (u32, u32),
+        wnd_ctx: &glium::Display,
+    ) -> scene::Scene {
+        let mut scene = get_ui_scene(width, height, wnd_ctx);
+        let mut entities = self.root.borrow().entities();
+        entities.push(self.hit_indicator.clone());
+        scene.set_entities(entities);
+        scene
+    }
+
+    /// Shows player 1's status, the score, the network stats and the tier
+    /// `governor` lowered the quality to
+    fn update_labels(
+        &self,
+        game: &LocalGame,
+        governor: &governor::FrameGovernor,
+    ) {
+        {
+            let player = game.player_1();
+            let player = player.borrow();
+            // shield and hull
+            self.shield.borrow_mut().set_text(&format!(
+                "{} | {}",
+                player.shield().round() as u64,
+                player.hull().round() as u64
+            ));
+            self.respawn.borrow_mut().set_text(
+                &player.respawn_countdown().map_or_else(
+                    String::new,
+                    |remaining| {
+                        tr!(
+                            "hud.respawning",
+                            remaining.as_secs_f64().ceil() as u64,
+                            game.lives()
+                        )
+                    },
+                ),
+            );
+            // energy and power distribution
+            self.energy.borrow_mut().set_text(&format!(
+                "{} {}",
+                player.energy().round() as u64,
+                player.power_distribution()
+            ));
+        }
+        self.score
+            .borrow_mut()
+            .set_text(&game.score().borrow().hud_text());
+        self.net.borrow_mut().set_text(&game.net_hud_text());
+        self.quality.borrow_mut().set_text(&governor.hud_text());
+    }
+
+    /// Shows the profiler, the log and the console, if they're on
+    fn update_overlays(&self, console: &console::Console, log_overlay: bool) {
+        update_profiler_hud(&self.profiler_lines);
+        update_log_hud(&self.log_lines, log_overlay);
+        update_console_hud(console, &self.console_lines);
+    }
+
+    /// Moves the markers over player 1's view, and shows the progress of the
+    /// mission
+    ///
+    /// `viewport` - region of the screen player 1 is shown in
+    ///
+    /// `screen` - size of the screen in pixels
+    fn update_markers(
+        &self,
+        game: &LocalGame,
+        viewport: &scene::Viewport,
+        (width, height): (u32, u32),
+        dt: std::time::Duration,
+    ) {
+        update_aim_markers(
+            game,
+            &self.reticle,
+            &self.lead_marker,
+            &self.target_bracket,
+            viewport,
+            viewport.size(width, height).1,
+        );
+        update_mission_hud(
+            game,
+            &self.objective,
+            &self.announcement,
+            &self.announcement_time,
+            &self.objective_marker,
+            viewport,
+            dt,
+        );
+    }
+}
+
+/// Adds the panel showing player 1's shield and hull, and their energy and
+/// power distribution, to the HUD
+///
+/// Returns the labels of the shield and the energy
+fn add_status_panel(
+    root: &mut ui::UiRoot<'static>,
+    font: &Rc<text::Font>,
+    wnd: &Window,
+) -> [Rc<RefCell<ui::Label>>; 2] {
+    let ctx = wnd.ctx();
+    let label = |offset: f64, width: f64, color: [f32; 4]| {
+        Rc::new(RefCell::new(ui::Label::new(
+            font.clone(),
+            ui::Layout::new(ui::Anchor::TopLeft)
+                .offset(60., offset)
+                .size(width, 40.),
+            color,
+            &*ctx,
+        )))
+    };
+    let icon = |path: &str, offset: f64| {
+        Rc::new(RefCell::new(ui::ImagePanel::from_handle(
+            wnd.assets().load_texture_srgb(path),
+            ui::Layout::new(ui::Anchor::TopLeft)
+                .offset(0., offset)
+                .size(54., 54.),
+            &*ctx,
+        )))
+    };
+    let shield = label(0., 140., [0., 0., 1., 1.]);
+    let energy = label(54., 180., [1., 1., 0., 1.]);
+    root.add(
+        ui::Panel::new(
+            ui::Layout::new(ui::Anchor::TopLeft)
+                .offset(48., 27.)
+                .size(200., 108.),
+        )
+        .with_child(icon("assets/icons/bubble-shield.png", 0.))
+        .with_child(icon("assets/icons/electric.png", 54.))
+        .with_child(shield.clone())
+        .with_child(energy.clone()),
     );
-    let minimap = Rc::new(RefCell::new(map));
-    map_scene.set_entities(vec![minimap.clone()]);
+    [shield, energy]
+}
 
-    let stat_text = Rc::new(RefCell::new(graphics_engine::text::Text::new(
-        Rc::new(text::Font::new(
-            "assets/fonts/SignedDistanceArial.fnt",
-            &*wnd.ctx(),
+/// Adds the reticle, the lead marker and the target bracket to the HUD
+fn add_aim_markers(
+    root: &mut ui::UiRoot<'static>,
+    font: &Rc<text::Font>,
+    wnd_ctx: &glium::Display,
+) -> [Rc<RefCell<ui::Marker>>; 3] {
+    [
+        root.add(ui::Marker::new(
+            "assets/particles/circle_05.png",
+            48.,
+            48.,
+            wnd_ctx,
+        )),
+        root.add(ui::Marker::new(
+            "assets/particles/circle_02.png",
+            24.,
+            24.,
+            wnd_ctx,
         )),
-        &*wnd.ctx(),
-    )));
+        root.add(
+            ui::Marker::new(
+                "assets/icons/target-bracket.png",
+                64.,
+                64.,
+                wnd_ctx,
+            )
+            .with_label(
+                font.clone(),
+                20.,
+                [1., 0.2, 0.2, 1.],
+                wnd_ctx,
+            ),
+        ),
+    ]
+}
 
-    let power_icon = entity::EntityBuilder::new(text::Icon::new(
-        "assets/icons/electric.png",
-        &*wnd.ctx(),
-    ))
-    .at(node::Node::default()
-        .u_scale(0.05)
-        .pos(point3(-0.9, 0.8, 0.0)))
-    .with_pass(shader::RenderPassType::Visual)
-    .build();
-    let shield_icon = entity::EntityBuilder::new(text::Icon::new(
-        "assets/icons/bubble-shield.png",
-        &*wnd.ctx(),
-    ))
-    .at(node::Node::default()
-        .u_scale(0.05)
-        .pos(point3(-0.9, 0.9, 0.0)))
-    .with_pass(shader::RenderPassType::Visual)
-    .build();
-    ui_scene.set_entities(vec![
-        stat_text.clone(),
-        Rc::new(RefCell::new(power_icon)),
-        Rc::new(RefCell::new(shield_icon)),
-    ]);
-
-    // skybox must be rendered first, particles must be rendered last
+/// Adds the labels of the objective, the announcements, the respawn
+/// countdown, the score, the network stats and the quality tier to the HUD
+fn add_match_labels(
+    root: &mut ui::UiRoot<'static>,
+    font: &Rc<text::Font>,
+    wnd_ctx: &glium::Display,
+) -> [Rc<RefCell<ui::Label>>; 6] {
+    const WHITE: [f32; 4] = [1., 1., 1., 1.];
+    const GREY: [f32; 4] = [0.7, 0.7, 0.7, 1.];
+    [
+        (ui::Anchor::Top, (0., 20.), (640., 40.), WHITE),
+        (ui::Anchor::Top, (0., 70.), (640., 40.), [1., 0.8, 0.2, 1.]),
+        (ui::Anchor::Center, (0., 120.), (480., 40.), WHITE),
+        (ui::Anchor::TopRight, (40., 27.), (240., 40.), WHITE),
+        (ui::Anchor::BottomRight, (40., 20.), (480., 30.), GREY),
+        // shows the tier the quality was lowered to, if it's lowered at all
+        (ui::Anchor::BottomRight, (40., 50.), (480., 30.), GREY),
+    ]
+    .map(|(anchor, (x, y), (width, height), color)| {
+        root.add(ui::Label::new(
+            font.clone(),
+            ui::Layout::new(anchor).offset(x, y).size(width, height),
+            color,
+            wnd_ctx,
+        ))
+    })
+}
+
+/// Adds `count` lines of text to the HUD, each laid out by `layout` from its
+/// index
+fn add_hud_lines(
+    root: &mut ui::UiRoot<'static>,
+    font: &Rc<text::Font>,
+    count: usize,
+    layout: impl Fn(f64) -> ui::Layout,
+    color: [f32; 4],
+    wnd_ctx: &glium::Display,
+) -> Vec<Rc<RefCell<ui::Label>>> {
+    (0..count)
+        .map(|i| {
+            root.add(ui::Label::new(
+                font.clone(),
+                layout(i as f64),
+                color,
+                wnd_ctx,
+            ))
+        })
+        .collect()
+}
+
+/// Adds the lines of the profiler, the log and the console to the HUD
+fn add_overlay_lines(
+    root: &mut ui::UiRoot<'static>,
+    font: &Rc<text::Font>,
+    wnd_ctx: &glium::Display,
+) -> [Vec<Rc<RefCell<ui::Label>>>; 3] {
+    [
+        // F3 shows the profiler, whose overlay has a line per stage
+        add_hud_lines(
+            root,
+            font,
+            PROFILER_LINES,
+            |i| {
+                ui::Layout::new(ui::Anchor::TopLeft)
+                    .offset(20., i.mul_add(28., 160.))
+                    .size(420., 26.)
+            },
+            [0., 1., 0., 1.],
+            wnd_ctx,
+        ),
+        // recent warnings and errors, if the log overlay is on
+        add_hud_lines(
+            root,
+            font,
+            LOG_LINES,
+            |i| {
+                ui::Layout::new(ui::Anchor::BottomLeft)
+                    .offset(20., (LOG_LINES as f64 - 1. - i).mul_add(26., 20.))
+                    .size(900., 24.)
+            },
+            [1., 0.5, 0.3, 1.],
+            wnd_ctx,
+        ),
+        // ` drops the developer console down, whose last line is being typed
+        add_hud_lines(
+            root,
+            font,
+            CONSOLE_LINES + 1,
+            |i| {
+                ui::Layout::new(ui::Anchor::Top)
+                    .offset(0., i.mul_add(26., 20.))
+                    .size(1200., 24.)
+            },
+            [1., 1., 0.6, 1.],
+            wnd_ctx,
+        ),
+    ]
+}
+
+/// The minimap, the radar and the tactical map, which follow player 1
+struct Maps {
+    minimap: Rc<RefCell<minimap::Minimap>>,
+    radar: Rc<RefCell<radar::Radar>>,
+    tactical: Rc<RefCell<tactical::TacticalMap>>,
+}
+
+impl Maps {
+    /// Creates the maps of player 1 in `game`, whose view has an aspect ratio
+    /// of `aspect`
+    ///
+    /// Returns the maps, the scene of the minimap and radar, and the scene of
+    /// the tactical map
+    fn new(
+        game: &LocalGame,
+        aspect: f32,
+        (width, height): (u32, u32),
+        wnd_ctx: &glium::Display,
+    ) -> (Self, scene::Scene, scene::Scene) {
+        let root = game.player_1().borrow().root().clone();
+        let minimap =
+            Rc::new(RefCell::new(minimap::Minimap::new(root.clone(), wnd_ctx)));
+        let radar = Rc::new(RefCell::new(radar::Radar::new(
+            root.clone(),
+            3000.,
+            wnd_ctx,
+        )));
+        let mut map_scene =
+            get_ui_scene(width, height, wnd_ctx).bg((0., 0., 0., 0.6));
+        map_scene.set_entities(vec![minimap.clone(), radar.clone()]);
+        let tactical = Rc::new(RefCell::new(tactical::TacticalMap::new(
+            tactical::MapArea {
+                center: point3(0., 0., 0.),
+                half_size: BATTLE_AREA_SIZE,
+                aspect: f64::from(aspect),
+            },
+            root,
+            wnd_ctx,
+        )));
+        let mut tactical_scene = get_ui_scene(width, height, wnd_ctx);
+        tactical_scene.set_entities(vec![tactical.clone()]);
+        let maps = Self {
+            minimap,
+            radar,
+            tactical,
+        };
+        (maps, map_scene, tactical_scene)
+    }
+}
+
+/// Shows the entities of `game` in the scene of each player
+///
+/// Returns the entity the console's debug_draw command outlines the colliders
+/// with
+fn add_game_entities(
+    game: &LocalGame,
+    views: &[PlayerView],
+    wnd_ctx: &glium::Display,
+) -> Rc<RefCell<entity::Entity>> {
     // TODO: roll players into mediator to avoid this explicit adding of players
     let mut entities = game.get_mediator().get_entities();
     entities.append(&mut game.get_player_entities());
-    main_scene.set_entities(entities);
+    let collider_boxes = Rc::new(RefCell::new(
+        entity::EntityBuilder::new(cubes::DebugCube::new(wnd_ctx))
+            .with_pass(shader::RenderPassType::Visual)
+            .build(),
+    ));
+    entities.push(collider_boxes.clone());
+    for (scene, _, _) in views {
+        scene.borrow_mut().set_entities(entities.clone());
+    }
+    collider_boxes
+}
 
+/// Creates the scene which composites the view of each player with the
+/// tactical map, the HUD and the minimap
+fn make_compositor(
+    views: &[PlayerView],
+    tactical_scene: scene::Scene,
+    ui_scene: scene::Scene,
+    map_scene: scene::Scene,
+    (screen_width, screen_height): (Rc<RefCell<u32>>, Rc<RefCell<u32>>),
+    wnd_ctx: &glium::Display,
+) -> Box<RefCell<dyn scene::AbstractScene>> {
     let map_screen_location = Matrix3::from_translation(vec2(-2.0f32, 0.0))
         * Matrix3::from_scale(3.0f32);
-    let screen_width = Rc::new(RefCell::new(render_width));
-    let screen_height = Rc::new(RefCell::new(render_height));
-    let compositor_scene = scene::compositor_scene_new(
-        screen_width.clone(),
-        screen_height.clone(),
-        Rc::new(RefCell::new(camera::Camera2D::new(
-            render_width,
-            render_height,
-        ))),
-        vec![
-            (Box::new(main_scene), None),
-            (Box::new(ui_scene), None),
-            (Box::new(map_scene), Some(map_screen_location)),
-        ],
-        &*wnd.ctx(),
-    );
-    wnd.scene_manager()
-        .insert_scene("main", Box::new(RefCell::new(compositor_scene)))
-        .change_scene("main");
+    let mut composited: Vec<scene::SceneModelPair> = views
+        .iter()
+        .map(|(scene, _, viewport)| {
+            let scene: Box<dyn scene::AbstractScene> = Box::new(scene.clone());
+            (scene, Some(viewport.transform()))
+        })
+        .collect();
+    // the tactical map's icons are drawn over player 1's view
+    composited.push((Box::new(tactical_scene), Some(views[0].2.transform())));
+    composited.push((Box::new(ui_scene), None));
+    composited.push((Box::new(map_scene), Some(map_screen_location)));
+    let camera =
+        camera::Camera2D::new(*screen_width.borrow(), *screen_height.borrow());
+    Box::new(RefCell::new(scene::compositor_scene_new(
+        screen_width,
+        screen_height,
+        Rc::new(RefCell::new(camera)),
+        composited,
+        wnd_ctx,
+    )))
+}
 
-    let game = RefCell::new(game);
+/// Set when the buttons of the menus are clicked, until the clicks are
+/// handled
+#[derive(Default)]
+struct MenuClicks {
+    resume: Rc<Cell<bool>>,
+    save: Rc<Cell<bool>>,
+    load: Rc<Cell<bool>>,
+    settings: Rc<Cell<bool>>,
+    quality: Rc<Cell<bool>>,
+    fullscreen: Rc<Cell<bool>>,
+    resolution: Rc<Cell<bool>>,
+    vsync: Rc<Cell<bool>>,
+    language: Rc<Cell<bool>>,
+    settings_back: Rc<Cell<bool>>,
+    play: Rc<Cell<bool>>,
+    /// The index of the chosen ship
+    ship: Rc<Cell<Option<usize>>>,
+    back: Rc<Cell<bool>>,
+    restart: Rc<Cell<bool>>,
+    to_menu: Rc<Cell<bool>>,
+    repair: Rc<Cell<bool>>,
+    rearm: Rc<Cell<bool>>,
+    undock: Rc<Cell<bool>>,
+}
 
-    let sim = RefCell::new(
-        physics::Simulation::<object::ObjectData>::new(point3(0., 0., 0.), 1500.)
-            .with_do_resolve(game::Game::<LocalGameMediator<NoLightingAvailable>>::should_resolve)
-            .with_on_hit(|a, b, hit| game.borrow().on_hit(a, b, hit)),
-    );
+/// Gets the function a button calls when it's clicked, which sets `clicked`
+fn on_click(clicked: &Rc<Cell<bool>>) -> Box<dyn FnMut()> {
+    let clicked = clicked.clone();
+    Box::new(move || clicked.set(true))
+}
+
+/// Gets the buttons of the ship select menu, which choose the ship at their
+/// index in `ships`, followed by the back button
+fn ship_buttons<'a>(
+    ships: &'a [ship::ShipDef],
+    clicks: &MenuClicks,
+) -> Vec<(&'a str, Box<dyn FnMut()>)> {
+    let mut buttons: Vec<(&str, Box<dyn FnMut()>)> = ships
+        .iter()
+        .enumerate()
+        .map(|(idx, ship)| {
+            let chosen = clicks.ship.clone();
+            let choose: Box<dyn FnMut()> =
+                Box::new(move || chosen.set(Some(idx)));
+            (ship.name.as_str(), choose)
+        })
+        .collect();
+    buttons.push(("menu.back", on_click(&clicks.back)));
+    buttons
+}
+
+/// Builds menus, keeping the scene of each and the relabels of their titles
+/// and buttons
+struct MenuBuilder<'a> {
+    screen_width: Rc<RefCell<u32>>,
+    screen_height: Rc<RefCell<u32>>,
+    font: &'a Rc<text::Font>,
+    wnd_ctx: &'a glium::Display,
+    scenes: Vec<NamedScene>,
+    relabels: Vec<Relabel>,
+}
+
+impl<'a> MenuBuilder<'a> {
+    fn new(
+        font: &'a Rc<text::Font>,
+        (screen_width, screen_height): (Rc<RefCell<u32>>, Rc<RefCell<u32>>),
+        wnd_ctx: &'a glium::Display,
+    ) -> Self {
+        Self {
+            screen_width,
+            screen_height,
+            font,
+            wnd_ctx,
+            scenes: Vec::new(),
+            relabels: Vec::new(),
+        }
+    }
+
+    /// Builds a menu with [`get_menu`], whose scene is shown by `name`
+    fn menu(
+        &mut self,
+        name: &'static str,
+        title: &str,
+        buttons: Vec<(&str, Box<dyn FnMut()>)>,
+        bg_alpha: f32,
+    ) -> RefCell<ui::UiRoot<'static>> {
+        let (scene, menu, relabels) = get_menu(
+            self.screen_width.clone(),
+            self.screen_height.clone(),
+            self.font,
+            title,
+            buttons,
+            bg_alpha,
+            self.wnd_ctx,
+        );
+        self.scenes.push((name, scene));
+        self.relabels.extend(relabels);
+        RefCell::new(menu)
+    }
+
+    /// Builds the pause menu, whose buttons set the clicks of `clicks`
+    fn pause_menu(
+        &mut self,
+        clicks: &MenuClicks,
+    ) -> RefCell<ui::UiRoot<'static>> {
+        let (scene, menu, relabels) = get_pause_menu(
+            self.screen_width.clone(),
+            self.screen_height.clone(),
+            self.font,
+            vec![
+                ("menu.resume", on_click(&clicks.resume)),
+                ("menu.save", on_click(&clicks.save)),
+                ("menu.load", on_click(&clicks.load)),
+                ("menu.settings", on_click(&clicks.settings)),
+            ],
+            self.wnd_ctx,
+        );
+        self.scenes.push(("pause", scene));
+        self.relabels.extend(relabels);
+        RefCell::new(menu)
+    }
+
+    /// Builds the settings menu, whose buttons set the clicks of `clicks`
+    ///
+    /// `labels` - the initial label of each button, from top to bottom
+    ///
+    /// Returns the menu and the button of each option
+    fn settings_menu(
+        &mut self,
+        clicks: &MenuClicks,
+        labels: [String; 6],
+    ) -> (
+        RefCell<ui::UiRoot<'static>>,
+        Vec<Rc<RefCell<ui::Button<'static>>>>,
+    ) {
+        let [quality, fullscreen, resolution, vsync, language, back] = labels;
+        let (scene, menu, buttons, title) = get_settings_menu(
+            self.screen_width.clone(),
+            self.screen_height.clone(),
+            self.font,
+            vec![
+                (quality, on_click(&clicks.quality)),
+                (fullscreen, on_click(&clicks.fullscreen)),
+                (resolution, on_click(&clicks.resolution)),
+                (vsync, on_click(&clicks.vsync)),
+                (language, on_click(&clicks.language)),
+                (back, on_click(&clicks.settings_back)),
+            ],
+            self.wnd_ctx,
+        );
+        self.scenes.push(("settings", scene));
+        self.relabels.push(title);
+        (RefCell::new(menu), buttons)
+    }
+}
+
+/// The menus shown instead of a match or over it
+struct Menus {
+    pause: RefCell<ui::UiRoot<'static>>,
+    settings: RefCell<ui::UiRoot<'static>>,
+    main: RefCell<ui::UiRoot<'static>>,
+    ship: RefCell<ui::UiRoot<'static>>,
+    game_over: RefCell<ui::UiRoot<'static>>,
+    dock: RefCell<ui::UiRoot<'static>>,
+    /// The buttons of the settings menu, whose labels show the current
+    /// settings
+    settings_buttons: Vec<Rc<RefCell<ui::Button<'static>>>>,
+    /// Relabel the titles and buttons of every menu in the current language
+    relabels: Vec<Relabel>,
+}
+
+impl Menus {
+    /// Builds the menus, whose buttons set the clicks of `clicks`
+    ///
+    /// `settings_labels` - the initial labels of the buttons of the settings
+    /// menu
+    ///
+    /// Returns the menus and the scene of each menu
+    fn new(
+        clicks: &MenuClicks,
+        ships: &[ship::ShipDef],
+        settings_labels: [String; 6],
+        font: &Rc<text::Font>,
+        screen: (Rc<RefCell<u32>>, Rc<RefCell<u32>>),
+        wnd_ctx: &glium::Display,
+    ) -> (Self, Vec<NamedScene>) {
+        let mut builder = MenuBuilder::new(font, screen, wnd_ctx);
+        let pause = builder.pause_menu(clicks);
+        let (settings, settings_buttons) =
+            builder.settings_menu(clicks, settings_labels);
+        let quit: Box<dyn FnMut()> = Box::new(|| std::process::exit(0));
+        let main = builder.menu(
+            "menu",
+            "menu.title",
+            vec![("menu.play", on_click(&clicks.play)), ("menu.quit", quit)],
+            1.,
+        );
+        let ship = builder.menu(
+            "ship_select",
+            "menu.choose_ship",
+            ship_buttons(ships, clicks),
+            1.,
+        );
+        builder.menu("loading", "menu.loading", Vec::new(), 1.);
+        let game_over = builder.menu(
+            "game_over",
+            "menu.game_over",
+            vec![
+                ("menu.restart", on_click(&clicks.restart)),
+                ("menu.main_menu", on_click(&clicks.to_menu)),
+            ],
+            0.5,
+        );
+        let dock = builder.menu(
+            "dock",
+            "menu.station",
+            vec![
+                ("menu.repair", on_click(&clicks.repair)),
+                ("menu.rearm", on_click(&clicks.rearm)),
+                ("menu.undock", on_click(&clicks.undock)),
+            ],
+            0.3,
+        );
+        let menus = Self {
+            pause,
+            settings,
+            main,
+            ship,
+            game_over,
+            dock,
+            settings_buttons,
+            relabels: builder.relabels,
+        };
+        (menus, builder.scenes)
+    }
+
+    /// Passes `ev` to the menus with [`dispatch_menu_event`]
+    ///
+    /// Returns `true` if any menu consumed the event
+    fn on_window_event(
+        &self,
+        ev: &glutin::event::WindowEvent,
+        state: GameState,
+    ) -> bool {
+        dispatch_menu_event(
+            ev,
+            state,
+            &[
+                (GameState::MainMenu, &self.main),
+                (GameState::ShipSelect, &self.ship),
+                (GameState::Paused, &self.pause),
+                (GameState::Settings, &self.settings),
+                (GameState::GameOver, &self.game_over),
+                (GameState::Docked, &self.dock),
+            ],
+        )
+    }
+}
+
+/// Everything shown over or instead of the players' views
+struct Interface {
+    hud: Hud,
+    maps: Maps,
+    menus: Menus,
+    clicks: MenuClicks,
+    cinematic_cam: Rc<RefCell<cinematic::CinematicCamera>>,
+    /// The player takes control once the intro finishes or is skipped
+    in_cutscene: Rc<Cell<bool>>,
+    /// The console's debug_draw command outlines the colliders
+    collider_boxes: Rc<RefCell<entity::Entity>>,
+    screen_width: Rc<RefCell<u32>>,
+    screen_height: Rc<RefCell<u32>>,
+}
+
+impl Interface {
+    /// Creates the interface of `new_match`, in which the ship select menu
+    /// chooses from `ships`
+    ///
+    /// Returns the interface and the scene of each menu and of the match
+    fn new(
+        new_match: &mut Match,
+        settings: &ClientSettings,
+        ships: &[ship::ShipDef],
+        font: &Rc<text::Font>,
+        wnd: &Window,
+    ) -> (Self, Vec<NamedScene>) {
+        let size = settings.render_size();
+        let aspect = new_match.players.viewports[0].aspect(size.0, size.1);
+        let (maps, map_scene, tactical_scene) =
+            Maps::new(&new_match.game, aspect, size, &wnd.ctx());
+        let hud = Hud::new(&mut new_match.game, font, size, wnd);
+        let collider_boxes =
+            add_game_entities(&new_match.game, &new_match.views, &wnd.ctx());
+        let screen_width = Rc::new(RefCell::new(size.0));
+        let screen_height = Rc::new(RefCell::new(size.1));
+        let screen = (screen_width.clone(), screen_height.clone());
+        let compositor = make_compositor(
+            &new_match.views,
+            tactical_scene,
+            hud.scene(size, &wnd.ctx()),
+            map_scene,
+            screen.clone(),
+            &wnd.ctx(),
+        );
+        let clicks = MenuClicks::default();
+        let labels = settings_labels(
+            settings.graphics.borrow().quality,
+            &settings.client.borrow(),
+        );
+        let (menus, mut scenes) =
+            Menus::new(&clicks, ships, labels, font, screen, &wnd.ctx());
+        scenes.push(("main", compositor));
+        let cinematic_cam = cinematic::CinematicCamera::new(
+            aspect,
+            new_match.game.player_1().borrow().view_dist().1,
+        );
+        let ui = Self {
+            hud,
+            maps,
+            menus,
+            clicks,
+            cinematic_cam: Rc::new(RefCell::new(cinematic_cam)),
+            in_cutscene: Rc::new(Cell::new(false)),
+            collider_boxes,
+            screen_width,
+            screen_height,
+        };
+        (ui, scenes)
+    }
+
+    /// Gets the size of the screen in pixels
+    fn screen_size(&self) -> (u32, u32) {
+        (*self.screen_width.borrow(), *self.screen_height.borrow())
+    }
+}
+
+/// Gets the hook which plays the intro at the start of every match
+fn play_intro(
+    ui: &Interface,
+    main_scene: Rc<RefCell<scene::Scene>>,
+    player: Rc<RefCell<player::Player>>,
+) -> impl FnMut(GameState) {
+    let cinematic_cam = ui.cinematic_cam.clone();
+    let in_cutscene = ui.in_cutscene.clone();
+    move |prev| {
+        if prev == GameState::Loading {
+            cinematic_cam.borrow_mut().skip();
+            in_cutscene.set(true);
+            let in_cutscene = in_cutscene.clone();
+            cinematic::CinematicCamera::play_in_scene(
+                &cinematic_cam,
+                &main_scene,
+                intro_flyover(&*player.borrow()),
+                move || in_cutscene.set(false),
+            );
+        }
+    }
+}
+
+/// Gets the hook which hides the tactical map and releases the mouse when a
+/// match is paused or left
+fn leave_match(
+    ui: &Interface,
+    players: &Players,
+    main_scene: Rc<RefCell<scene::Scene>>,
+) -> impl FnMut(GameState) {
+    let tactical_map = ui.maps.tactical.clone();
+    let player_controls = players.controls.clone();
+    let player_2_controls = players
+        .player_2
+        .as_ref()
+        .map(|(_, controls)| controls.clone());
+    move |next| {
+        // the map is shown again after a pause, but is closed when the match
+        // is left, so it can't take the viewer from the intro of the next
+        // match
+        tactical_map.borrow_mut().hide(&main_scene);
+        if next != GameState::Paused {
+            player_controls.borrow_mut().close_tactical_map();
+        }
+        player_controls.borrow_mut().release_mouse();
+        if let Some(controls) = &player_2_controls {
+            controls.borrow_mut().release_mouse();
+        }
+    }
+}
+
+/// Gets the state machine of `new_match`, which starts at the main menu
+fn make_state_machine(new_match: &Match, ui: &Interface) -> GameStateMachine {
+    let main_scene = &new_match.views[0].0;
+    let score = new_match.game.score();
+    GameStateMachine::new(GameState::MainMenu)
+        .with_scene(GameState::MainMenu, "menu")
+        .with_scene(GameState::ShipSelect, "ship_select")
+        .with_scene(GameState::Loading, "loading")
+        .with_scene(GameState::Playing, "main")
+        .with_overlay(GameState::Paused, "main", "pause")
+        .with_overlay(GameState::Settings, "main", "settings")
+        .with_overlay(GameState::GameOver, "main", "game_over")
+        .with_overlay(GameState::Docked, "main", "dock")
+        .on_enter(
+            GameState::Playing,
+            play_intro(ui, main_scene.clone(), new_match.game.player_1()),
+        )
+        .on_exit(
+            GameState::Playing,
+            leave_match(ui, &new_match.players, main_scene.clone()),
+        )
+        .on_enter(GameState::GameOver, move |_| record_score(&score.borrow()))
+}
 
-    // TODO: factor out HUD updates
-    let mut draw_cb =
-        |dt, mut scene: std::cell::RefMut<dyn scene::AbstractScene>| {
-            minimap.borrow_mut().clear_items();
-            game.borrow().get_mediator().iter_bodies(|bods| {
-                for bod in bods {
-                    minimap.borrow_mut().add_item(bod);
+/// Adds `scenes` to the window, and shows the scene of the current state of
+/// `states`, which becomes the state machine of `game`
+fn show_scenes(
+    game: &mut LocalGame,
+    states: GameStateMachine,
+    scenes: Vec<NamedScene>,
+    wnd: &mut Window,
+) {
+    let mut scene_manager = wnd.scene_manager();
+    for (name, scene) in scenes {
+        scene_manager.insert_scene(name, scene);
+    }
+    states.show(&mut scene_manager);
+    game.set_state_machine(states);
+}
+
+/// The state shared by the callbacks of the game loop
+struct Client<'a> {
+    wnd: &'a Window,
+    game: &'a RefCell<LocalGame>,
+    sim: RefCell<physics::Simulation<'a, 'a, object::ObjectData>>,
+    console: RefCell<console::Console<'a>>,
+    debug_draw: &'a Cell<bool>,
+    settings: ClientSettings,
+    views: Vec<PlayerView>,
+    player_controls: Rc<RefCell<controls::PlayerControls>>,
+    ui: Interface,
+    ships: Vec<ship::ShipDef>,
+    /// The chosen ship is flown once its model has loaded
+    pending_ship: Cell<Option<usize>>,
+    /// The seed of the map, if it's procedural
+    map_seed: Option<u64>,
+}
+
+impl Client<'_> {
+    /// Runs the game loop until the window is closed
+    fn run(&self) {
+        let mut update_cb = |mut scenes: std::cell::RefMut<SceneManager>| {
+            self.on_update(&mut scenes);
+        };
+        let mut draw_cb =
+            |dt, mut scene: std::cell::RefMut<dyn scene::AbstractScene>| {
+                self.on_draw(dt, &mut *scene);
+            };
+        let mut controller_cb =
+            |ev: glutin::event::DeviceEvent,
+             _: std::cell::RefMut<SceneManager>| {
+                self.on_device_event(&ev);
+            };
+        let mut resize_cb = |new_size: glutin::dpi::PhysicalSize<u32>| {
+            self.on_resize(new_size);
+        };
+        let mut window_event_cb =
+            |ev: &glutin::event::WindowEvent,
+             _: std::cell::RefMut<SceneManager>| {
+                self.on_window_event(ev);
+            };
+        let cbs = WindowCallbacks::new()
+            .with_update_handler(&mut update_cb)
+            .with_draw_handler(&mut draw_cb)
+            .with_input_handler(&mut controller_cb)
+            .with_resize_handler(&mut resize_cb)
+            .with_window_event_handler(&mut window_event_cb);
+        info!("Start game loop");
+        self.wnd.main_loop(cbs);
+    }
+
+    /// Rebuilds the render passes of the players' views from the graphics
+    /// settings, lowered to the governor's tier
+    fn apply_graphics_settings(&self) {
+        apply_graphics_settings(
+            &self.settings.graphics.borrow(),
+            &self.settings.governor.borrow(),
+            &self.views,
+            self.sim.borrow().get_collision_tree(),
+            &*self.wnd.ctx(),
+        );
+    }
+
+    /// Simulates and draws a frame of the match, if it's being simulated
+    fn on_draw(
+        &self,
+        dt: std::time::Duration,
+        scene: &mut dyn scene::AbstractScene,
+    ) {
+        if !self.game.borrow().state().is_simulating() {
+            return;
+        }
+        self.settings.governor.borrow_mut().record(dt);
+        // during playback, frames are simulated with the recorded dt
+        let dt = self.player_controls.borrow_mut().begin_frame(dt);
+        self.ui.cinematic_cam.borrow_mut().update(dt);
+        self.update_maps();
+        self.ui.hud.update_labels(
+            &self.game.borrow(),
+            &self.settings.governor.borrow(),
+        );
+        // older hits fade before this frame's hits are shown
+        self.ui.hud.hit_indicator.borrow_mut().update(dt);
+        self.game
+            .borrow()
+            .on_draw(&mut self.sim.borrow_mut(), dt, scene);
+        let light_benchmark =
+            self.settings.client.borrow().launch.light_benchmark;
+        if light_benchmark > 0 {
+            let game = self.game.borrow();
+            let mut lights = game.get_mediator().get_lights();
+            lights.append(&mut benchmark_lights(
+                light_benchmark,
+                game.player_1().borrow().cam_pos(),
+            ));
+            scene.set_lights(&lights);
+        }
+        self.ui.hud.update_overlays(
+            &self.console.borrow(),
+            self.settings.client.borrow().log_overlay,
+        );
+        update_collider_boxes(
+            &self.ui.collider_boxes,
+            self.sim.borrow().get_collision_tree(),
+            self.debug_draw.get(),
+        );
+        // will call on_hit, so cannot mutably borrow game
+        self.ui.hud.update_markers(
+            &self.game.borrow(),
+            &self.views[0].2,
+            self.ui.screen_size(),
+            dt,
+        );
+    }
+
+    /// Updates the minimap, the radar and the tactical map, and engages the
+    /// autopilot of player 1 if they chose a destination
+    fn update_maps(&self) {
+        let game = self.game.borrow();
+        let maps = &self.ui.maps;
+        update_maps(
+            &*game,
+            &mut *self.player_controls.borrow_mut(),
+            &mut *maps.minimap.borrow_mut(),
+            &mut *maps.radar.borrow_mut(),
+        );
+        engage_autopilot(&*game, &self.player_controls);
+        update_tactical_map(
+            &*game,
+            &*self.player_controls.borrow(),
+            &maps.tactical,
+            &self.views[0].0,
+            self.ui.in_cutscene.get(),
+        );
+    }
+
+    /// Pauses the game, profiles it or captures it, skips the intro, or
+    /// passes `ev` to the controls of player 1
+    fn on_device_event(&self, ev: &glutin::event::DeviceEvent) {
+        use glutin::event::*;
+        // the keys are typed into the console while it's open
+        if self.console.borrow().is_open() {
+            return;
+        }
+        if let DeviceEvent::Key(KeyboardInput {
+            virtual_keycode: Some(VirtualKeyCode::P),
+            state: ElementState::Pressed,
+            ..
+        }) = ev
+        {
+            toggle_pause(&mut self.game.borrow().state_machine());
+        } else if let DeviceEvent::Key(KeyboardInput {
+            virtual_keycode:
+                Some(key @ (VirtualKeyCode::F3 | VirtualKeyCode::F4)),
+            state: ElementState::Pressed,
+            ..
+        }) = ev
+        {
+            on_profiler_key(*key);
+        } else if let DeviceEvent::Key(KeyboardInput {
+            virtual_keycode:
+                Some(key @ (VirtualKeyCode::F11 | VirtualKeyCode::F12)),
+            state: ElementState::Pressed,
+            ..
+        }) = ev
+        {
+            on_capture_key(*key, &self.settings.video);
+        } else if self.game.borrow().state() == GameState::Playing {
+            if self.ui.in_cutscene.get() {
+                if let DeviceEvent::Key(KeyboardInput {
+                    virtual_keycode:
+                        Some(VirtualKeyCode::Space | VirtualKeyCode::Return),
+                    state: ElementState::Pressed,
+                    ..
+                }) = ev
+                {
+                    self.ui.cinematic_cam.borrow_mut().skip();
                 }
-            });
-            stat_text.borrow_mut().clear_text();
-            stat_text.borrow_mut().add_text(
-                &format!(
-                    "{}",
-                    game.borrow().player_1().borrow().shield().round() as u64
-                ),
-                &Rc::new(RefCell::new(
-                    node::Node::default()
-                        .u_scale(0.07)
-                        .pos(point3(-0.78, 0.85, 0.1)),
-                )),
-                [0., 0., 1., 1.],
+            } else {
+                self.player_controls.borrow_mut().on_input(ev);
+            }
+        }
+    }
+
+    /// Resizes the players' views, the maps, the menus and the render
+    /// targets to match the window
+    fn on_resize(&self, new_size: glutin::dpi::PhysicalSize<u32>) {
+        if new_size.width == 0 || new_size.height == 0 {
+            return;
+        }
+        for (_, player, viewport) in &self.views {
+            player.borrow_mut().aspect =
+                viewport.aspect(new_size.width, new_size.height);
+        }
+        let aspect = self.views[0].2.aspect(new_size.width, new_size.height);
+        self.ui.cinematic_cam.borrow_mut().aspect = aspect;
+        self.ui.maps.tactical.borrow_mut().set_aspect(aspect);
+        *self.ui.screen_width.borrow_mut() = new_size.width;
+        *self.ui.screen_height.borrow_mut() = new_size.height;
+        // the main render targets are rebuilt to match the window
+        {
+            let mut graphics = self.settings.graphics.borrow_mut();
+            graphics.render_width = new_size.width;
+            graphics.render_height = new_size.height;
+        }
+        self.apply_graphics_settings();
+    }
+
+    /// Passes `ev` to the HUD, the console, the tactical map and the menus
+    fn on_window_event(&self, ev: &glutin::event::WindowEvent) {
+        self.ui.hud.root.borrow_mut().on_window_event(ev);
+        let state = self.game.borrow().state();
+        if state == GameState::Playing
+            && self.console.borrow_mut().on_window_event(ev)
+        {
+            return;
+        }
+        let tactical_map = &self.ui.maps.tactical;
+        if state == GameState::Playing && tactical_map.borrow().is_shown() {
+            on_tactical_map_click(
+                ev,
+                &*self.game.borrow(),
+                &mut *self.player_controls.borrow_mut(),
+                tactical_map.borrow().area(),
+                self.views[0]
+                    .2
+                    .to_viewport_ndc(self.ui.hud.root.borrow().cursor()),
             );
-            stat_text.borrow_mut().add_text(
-                &format!(
-                    "{}",
-                    game.borrow().player_1().borrow().energy().round() as u64
-                ),
-                &Rc::new(RefCell::new(
-                    node::Node::default()
-                        .u_scale(0.07)
-                        .pos(point3(-0.78, 0.75, 0.1)),
-                )),
-                [1., 1., 0., 1.],
+        }
+        if self.ui.menus.on_window_event(ev, state) {
+            self.on_menu_clicks();
+            self.on_settings_clicks();
+        }
+    }
+
+    /// Handles the clicks of the buttons of every menu but the settings menu
+    fn on_menu_clicks(&self) {
+        let game = self.game.borrow();
+        let clicks = &self.ui.clicks;
+        if clicks.repair.take() {
+            game.service_player_1(station::Service::Repair);
+        }
+        if clicks.rearm.take() {
+            game.service_player_1(station::Service::Rearm);
+        }
+        if clicks.undock.take() {
+            game.undock();
+        }
+        let chosen = clicks.ship.take();
+        if let Some(idx) = chosen {
+            self.wnd.assets().preload_model(&self.ships[idx].model);
+            self.pending_ship.set(Some(idx));
+        }
+        if clicks.save.take() {
+            save_session(&*game, self.map_seed);
+        }
+        let loaded = clicks.load.take() && load_session(&*game, self.map_seed);
+        let requests = [
+            (clicks.resume.take() || loaded, GameState::Playing),
+            (clicks.play.take(), GameState::ShipSelect),
+            (
+                chosen.is_some() || clicks.restart.take(),
+                GameState::Loading,
+            ),
+            (clicks.back.take(), GameState::MainMenu),
+            (clicks.settings.take(), GameState::Settings),
+            (clicks.settings_back.take(), GameState::Paused),
+            (clicks.to_menu.take(), GameState::MainMenu),
+        ];
+        let mut states = game.state_machine();
+        for (clicked, state) in requests {
+            if clicked {
+                states.request(state);
+            }
+        }
+    }
+
+    /// Handles the clicks of the buttons of the settings menu, whose labels
+    /// are updated to show the new settings
+    fn on_settings_clicks(&self) {
+        let clicks = &self.ui.clicks;
+        let quality_changed = clicks.quality.take();
+        if quality_changed {
+            {
+                let mut graphics = self.settings.graphics.borrow_mut();
+                *graphics = graphics.with_quality(graphics.quality.next());
+                if let Err(e) = graphics.save(settings::SETTINGS_PATH) {
+                    error!("Failed to save graphics settings: {}", e);
+                }
+            }
+            self.apply_graphics_settings();
+        }
+        let mut client = self.settings.client.borrow_mut();
+        let client_path = &self.settings.client_path;
+        let display_changed = on_display_clicked(
+            self.wnd,
+            &mut client,
+            client_path,
+            [&clicks.fullscreen, &clicks.resolution, &clicks.vsync],
+        );
+        let language_changed = clicks.language.take();
+        if language_changed {
+            switch_language(&mut client, client_path, &self.ui.menus.relabels);
+        }
+        if quality_changed || display_changed || language_changed {
+            let labels = settings_labels(
+                self.settings.graphics.borrow().quality,
+                &client,
             );
-            game.borrow()
-                .on_draw(&mut sim.borrow_mut(), dt, &mut *scene);
-            // will call on_hit, so cannot mutably borrow game
-        };
-    let mut controller_cb = |ev, _: std::cell::RefMut<SceneManager>| {
-        (&mut *player_controls.borrow_mut()).on_input(&ev);
-    };
-    let mut resize_cb = |new_size: glutin::dpi::PhysicalSize<u32>| {
-        if new_size.height != 0 {
-            game.borrow().player_1().borrow_mut().aspect =
-                new_size.width as f32 / new_size.height as f32;
-            *screen_width.borrow_mut() = new_size.width;
-            *screen_height.borrow_mut() = new_size.height;
+            for (button, label) in
+                self.ui.menus.settings_buttons.iter().zip(&labels)
+            {
+                button.borrow_mut().set_label_text(label);
+            }
         }
+    }
+
+    /// Starts the match once its assets have loaded, rebuilds the render
+    /// passes if the governor changed the quality, and shows the scenes of
+    /// the current state
+    fn on_update(&self, scenes: &mut SceneManager) {
+        let game = self.game.borrow();
+        if game.state() == GameState::Loading && self.wnd.assets().is_idle() {
+            self.start_match(&game);
+        }
+        // the render passes aren't in use between frames
+        if self.settings.governor.borrow_mut().take_changed() {
+            self.apply_graphics_settings();
+        }
+        game.state_machine().update(scenes);
+    }
+
+    /// Starts a match, in which player 1 flies the chosen ship
+    fn start_match(&self, game: &LocalGame) {
+        // meshes the last match left unused, such as that of a ship
+        // player 1 no longer flies, are dropped. Those of every object
+        // type are kept loaded
+        let evicted = collisions::evict_unused_meshes();
+        info!("Evicted {} unused collision meshes", evicted);
+        collisions::preload_meshes(object::collision_meshes());
+        if let Some(ship) = self.pending_ship.take().map(|idx| &self.ships[idx])
+        {
+            game.player_1().borrow_mut().set_ship(
+                ship,
+                model::Model::new(&ship.model, &*self.wnd.ctx()),
+            );
+        }
+        game.mission().restart();
+        game.score().borrow_mut().reset();
+        game.reset_lives();
+        game.set_time_scale(1., std::time::Duration::default());
+        game.state_machine().request(GameState::Playing);
+    }
+}
+
+/// Opens the window and plays the game until the window is closed
+///
+/// `client_path` - where changes to `client` are saved
+fn run_game(client: client_config::ClientConfig, client_path: String) {
+    let settings = ClientSettings::new(client, client_path);
+    let mut wnd = make_window(&settings);
+    let ships = ship::ShipDef::load_all(ship::SHIPS_DIR);
+    let ai_ship = ship::ShipDef::load_or_default(AI_SHIP_PATH);
+    let font =
+        preload_assets(&wnd, ships.iter().chain(std::iter::once(&ai_ship)));
+    // player 1 flies the first ship until another is chosen on the ship
+    // select screen
+    let mut new_match = make_match(&settings, &ships[0], &ai_ship, &wnd);
+    let (ui, scenes) =
+        Interface::new(&mut new_match, &settings, &ships, &font, &wnd);
+    let states = make_state_machine(&new_match, &ui);
+    show_scenes(&mut new_match.game, states, scenes, &mut wnd);
+
+    let game = RefCell::new(new_match.game);
+    let debug_draw = Cell::new(false);
+    let simulation = new_match
+        .simulation
+        .with_do_resolve(LocalGame::should_resolve)
+        .with_on_hit(|a, b, hit| game.borrow().on_hit(a, b, hit));
+    let client = Client {
+        wnd: &wnd,
+        game: &game,
+        sim: RefCell::new(simulation),
+        console: RefCell::new(get_console(&game, &debug_draw)),
+        debug_draw: &debug_draw,
+        settings,
+        views: new_match.views,
+        player_controls: new_match.players.controls,
+        ui,
+        ships,
+        pending_ship: Cell::new(None),
+        map_seed: new_match.map_seed,
     };
-    let cbs = WindowCallbacks::new()
-        .with_draw_handler(&mut draw_cb)
-        .with_input_handler(&mut controller_cb)
-        .with_resize_handler(&mut resize_cb);
-    println!("Start game loop");
-    wnd.main_loop(cbs);
+    client.run();
+}
+
+fn main() {
+    let log_filter = logging::init()
+        .map_err(|e| eprintln!("Logging disabled: {}", e))
+        .ok();
+    crash::install_hook();
+    let args: Vec<String> = std::env::args().collect();
+    let config_path = client_config::config_path(&args).to_owned();
+    let client = client_config::parse_args(args.iter().cloned())
+        .unwrap_or_else(|e| {
+            warn!("Ignoring command line options: {}", e);
+            client_config::ClientConfig::load_or_default(&config_path)
+        });
+    if let Some(Err(e)) = log_filter.map(|f| f.set(&client.log_filter)) {
+        warn!("Invalid log filter \"{}\": {}", client.log_filter, e);
+    }
+    crash::set_context("Client configuration", client.to_string());
+    if let Err(e) = localization::set_language(&client.language) {
+        warn!("Using the default language: {}", e);
+    }
+    // bots are headless, so they run without the window
+    if let Some(config) = bot_args(&client) {
+        bot::run_bots(&config);
+        return;
+    }
+    run_game(client, config_path);
 }
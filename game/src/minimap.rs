@@ -9,7 +9,7 @@ use VertexSimple as Vertex;
 
 // TODO: consolidate with Billboard.rs
 
-const RECT_VERTS: [Vertex; 4] = [
+pub const RECT_VERTS: [Vertex; 4] = [
     Vertex {
         pos: [1., 1., 0.],
         tex_coords: [1., 1.],
@@ -28,51 +28,82 @@ const RECT_VERTS: [Vertex; 4] = [
     },
 ];
 
-const RECT_INDICES: [u8; 6] = [0, 1, 3, 3, 1, 2];
+pub const RECT_INDICES: [u8; 6] = [0, 1, 3, 3, 1, 2];
 
-struct MinimapBlip {
-    color: [f32; 4],
-    tex_index: usize,
-    pos: node::Node,
+/// Distances from the center that the minimap can see at each zoom level,
+/// from most zoomed in to most zoomed out
+const ZOOM_LEVELS: [f64; 4] = [750., 1500., 3000., 6000.];
+/// Zoom level the minimap starts at
+const DEFAULT_ZOOM: usize = 2;
+/// Distance from the center of the minimap to the indicators on its edge,
+/// where `1` is the edge of the map
+const EDGE_DIST: f64 = 0.9;
+
+/// The icon of a blip. The first icons are the minimap's textures, and the
+/// rest are shapes drawn by the minimap shader. Shapes point up
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Icon {
+    Circle = 0,
+    Trace,
+    Star,
+    Diamond,
+    Triangle,
+    Arrow,
+    Ring,
 }
 
-pub struct Minimap {
+/// Loads the textures of the icons drawn from textures, in the order of
+/// `Icon`
+pub fn load_icon_textures<F: glium::backend::Facade>(
+    facade: &F,
+) -> [glium::texture::Texture2d; 3] {
+    [
+        textures::load_texture_2d("assets/particles/circle_05.png", facade),
+        textures::load_texture_2d("assets/particles/trace_02.png", facade),
+        textures::load_texture_2d("assets/particles/star_07.png", facade),
+    ]
+}
+
+/// Gets the color and icon of objects of type `typ`, or `None` if objects
+/// of that type aren't shown on maps
+pub const fn icon_of(typ: object::ObjectType) -> Option<([f32; 4], Icon)> {
+    use object::ObjectType::*;
+    match typ {
+        Asteroid => Some(([0.517, 0.282, 0.082, 1.0], Icon::Circle)),
+        Planet => Some(([0.3, 0.55, 1.0, 1.0], Icon::Ring)),
+        Laser => Some(([0.5451, 0.0, 0.5451, 1.0], Icon::Trace)),
+        Ship => Some(([1.0, 0.2, 0.1, 1.0], Icon::Triangle)),
+        Pickup => Some(([0.2, 0.9, 1.0, 1.0], Icon::Diamond)),
+        Station => Some(([0.9, 0.9, 0.9, 1.0], Icon::Star)),
+        _ => None,
+    }
+}
+
+/// Color of the icon of the player at the center of maps
+pub const CENTER_COLOR: [f32; 4] = [0.0, 1.0, 0.0, 1.0];
+/// Color of the locked target on maps
+pub const TARGET_COLOR: [f32; 4] = [1.0, 0.9, 0.2, 1.0];
+
+/// An icon on a map. Its transform is in the space of the map
+pub struct MinimapBlip {
+    pub color: [f32; 4],
+    pub icon: Icon,
+    pub pos: node::Node,
+}
+
+/// The buffers that draw the icons of a map with one instanced draw call
+pub struct IconBatch {
     textures: [glium::texture::Texture2d; 3],
-    center: Rc<RefCell<node::Node>>,
-    view_dist: f64,
-    blips: Vec<MinimapBlip>,
     vertices: glium::VertexBuffer<Vertex>,
     indicies: glium::IndexBuffer<u8>,
     instances: instancing::InstanceBuffer<instancing::ParticleAttributes>,
     instance_pos: instancing::InstanceBuffer<instancing::InstancePosition>,
 }
 
-impl Minimap {
-    /// Creates a new minimap that is centered on `center` and can see `view_dist`
-    /// away from `center` in all directions
-    pub fn new<F: glium::backend::Facade>(
-        center: Rc<RefCell<node::Node>>,
-        view_dist: f64,
-        facade: &F,
-    ) -> Self {
+impl IconBatch {
+    pub fn new<F: glium::backend::Facade>(facade: &F) -> Self {
         Self {
-            center,
-            view_dist,
-            textures: [
-                textures::load_texture_2d(
-                    "assets/particles/circle_05.png",
-                    facade,
-                ),
-                textures::load_texture_2d(
-                    "assets/particles/trace_02.png",
-                    facade,
-                ),
-                textures::load_texture_2d(
-                    "assets/particles/star_07.png",
-                    facade,
-                ),
-            ],
-            blips: Vec::new(),
+            textures: load_icon_textures(facade),
             vertices: glium::VertexBuffer::immutable(facade, &RECT_VERTS)
                 .unwrap(),
             indicies: glium::IndexBuffer::immutable(
@@ -86,69 +117,33 @@ impl Minimap {
         }
     }
 
-    /// Adds `body` to the minimap. Does nothing if `body` should not be shown
-    pub fn add_item(&mut self, body: &physics::RigidBody<object::ObjectData>) {
-        use cgmath::*;
-        use object::ObjectType::*;
-        let (color, tex_index, scale) = match body.metadata.0 {
-            Asteroid => (
-                [0.517f32, 0.282, 0.082, 1.0],
-                0usize,
-                (body.base.extents().unwrap_or(0.) / self.view_dist).max(0.05),
-            ),
-            Laser => ([0.5451f32, 0.0, 0.5451, 1.0], 1usize, 0.1),
-            Ship => ([0.960f32, 0.623, 0.141, 1.0], 2usize, 0.1),
-            _ => return,
-        };
-        let center_inv = self.center.borrow().mat().invert().unwrap();
-        let minimap_pos =
-            center_inv.transform_point(body.base.center()) / self.view_dist;
-        let pos: [f64; 3] = minimap_pos.into();
-        self.blips.push(MinimapBlip {
-            color,
-            tex_index,
-            pos: node::Node::default().pos(pos.into()).u_scale(scale),
-        });
-    }
-
-    /// Removes all items on the minimap
-    pub fn clear_items(&mut self) {
-        self.blips.clear();
-        // add center icon (self)
-        self.blips.push(MinimapBlip {
-            color: [0.0, 1.0, 0.0, 1.0],
-            tex_index: 2,
-            pos: node::Node::default().u_scale(0.1),
-        });
-    }
-}
-
-impl Drawable for Minimap {
-    fn render_args<'a>(
+    /// Gets the render arguments which draw `blips`, after transforming
+    /// them from the space of the map to normalized device coordinates by
+    /// `to_ndc`
+    pub fn render_args<'a>(
         &'a mut self,
-        _positions: &[[[f32; 4]; 4]],
+        blips: &[MinimapBlip],
+        to_ndc: cgmath::Matrix4<f64>,
     ) -> Vec<(
         shader::UniformInfo,
         VertexHolder<'a>,
         glium::index::IndicesSource<'a>,
     )> {
         use glium::*;
-        if self.blips.is_empty() {
+        if blips.is_empty() {
             return Vec::new();
         };
-        let attribs: Vec<_> = self
-            .blips
+        let attribs: Vec<_> = blips
             .iter()
             .map(|b| instancing::ParticleAttributes {
                 color: b.color,
-                tex_idx: b.tex_index as u32,
+                tex_idx: b.icon as u32,
             })
             .collect();
 
-        let positions: Vec<[[f32; 4]; 4]> = self
-            .blips
+        let positions: Vec<[[f32; 4]; 4]> = blips
             .iter()
-            .map(|b| b.pos.mat().cast().unwrap().into())
+            .map(|b| (to_ndc * b.pos.mat()).cast().unwrap().into())
             .collect();
         let positions = instancing::model_mats_to_vertex(&positions);
 
@@ -184,6 +179,187 @@ impl Drawable for Minimap {
         .append(inst_pos);
         vec![(uniform, v, From::from(&self.indicies))]
     }
+}
+
+/// A 2D map of the bodies around the player, looking along the player's
+/// forward axis
+///
+/// Hostile ships point in the direction they're heading, the locked target
+/// is shown by an arrow on the edge of the map when it's out of range, and
+/// an indicator on the edge of the map points towards world up
+pub struct Minimap {
+    center: Rc<RefCell<node::Node>>,
+    zoom: usize,
+    shown: bool,
+    blips: Vec<MinimapBlip>,
+    icons: IconBatch,
+}
+
+impl Minimap {
+    /// Creates a new minimap that is centered on `center`
+    pub fn new<F: glium::backend::Facade>(
+        center: Rc<RefCell<node::Node>>,
+        facade: &F,
+    ) -> Self {
+        Self {
+            center,
+            zoom: DEFAULT_ZOOM,
+            shown: true,
+            blips: Vec::new(),
+            icons: IconBatch::new(facade),
+        }
+    }
+
+    /// Gets the distance the minimap can see away from its center in all
+    /// directions
+    fn view_dist(&self) -> f64 {
+        ZOOM_LEVELS[self.zoom]
+    }
+
+    /// Shows or hides the minimap
+    pub fn set_shown(&mut self, shown: bool) {
+        self.shown = shown;
+    }
+
+    /// Zooms in by `steps` zoom levels, or out if `steps` is negative.
+    /// The zoom stops at the closest and farthest levels
+    pub fn zoom(&mut self, steps: i32) {
+        let level = self.zoom as i64 - i64::from(steps);
+        self.zoom = level.clamp(0, ZOOM_LEVELS.len() as i64 - 1) as usize;
+    }
+
+    /// Converts the world space `pt` to the space of the minimap, where the
+    /// edges of the map are at `-1` and `1`
+    fn to_map(&self, pt: cgmath::Point3<f64>) -> cgmath::Point3<f64> {
+        use cgmath::*;
+        let center_inv = self.center.borrow().mat().invert().unwrap();
+        center_inv.transform_point(pt) / self.view_dist()
+    }
+
+    /// Gets the rotation around the view axis of the map that turns an
+    /// icon pointing up to point along the world space direction `dir`
+    ///
+    /// Returns `None` if `dir` is parallel to the view axis
+    fn map_rotation(
+        &self,
+        dir: cgmath::Vector3<f64>,
+    ) -> Option<cgmath::Quaternion<f64>> {
+        use cgmath::*;
+        let center_inv = self.center.borrow().mat().invert().unwrap();
+        let dir = center_inv.transform_vector(dir);
+        if dir.x.abs() < f64::EPSILON && dir.y.abs() < f64::EPSILON {
+            None
+        } else {
+            Some(Quaternion::from_angle_z(Rad((-dir.x).atan2(dir.y))))
+        }
+    }
+
+    /// Gets the transform of an icon of size `scale` on the edge of the map
+    /// in the direction of the map space `dir`, pointing outwards
+    fn edge_blip(dir: cgmath::Vector2<f64>, scale: f64) -> node::Node {
+        use cgmath::*;
+        // the map is square, so the edge is where the longest component of
+        // `dir` reaches it
+        let edge = dir / dir.x.abs().max(dir.y.abs()) * EDGE_DIST;
+        node::Node::default()
+            .pos(point3(edge.x, edge.y, 0.))
+            .rot(Quaternion::from_angle_z(Rad((-dir.x).atan2(dir.y))))
+            .u_scale(scale)
+    }
+
+    /// Adds `body` to the minimap. Does nothing if `body` should not be shown
+    pub fn add_item(&mut self, body: &physics::RigidBody<object::ObjectData>) {
+        use cgmath::*;
+        use object::ObjectType::*;
+        if Rc::ptr_eq(&body.base.transform, &self.center) {
+            // the center is drawn by `clear_items`
+            return;
+        }
+        let (color, icon) = match icon_of(body.metadata.0) {
+            Some(style) => style,
+            None => return,
+        };
+        let size = body.base.extents().unwrap_or(0.) / self.view_dist();
+        let scale = match body.metadata.0 {
+            Asteroid => size.max(0.05),
+            Planet => size.max(0.1),
+            Laser => 0.1,
+            Ship => 0.07,
+            _ => 0.05,
+        };
+        let pos: [f64; 3] = self.to_map(body.base.center()).into();
+        let mut pos = node::Node::default().pos(pos.into()).u_scale(scale);
+        if icon == Icon::Triangle {
+            let forward =
+                body.base.transform.borrow().transform_vec(vec3(0., 0., 1.));
+            if let Some(rot) = self.map_rotation(forward) {
+                pos = pos.rot(rot);
+            }
+        }
+        self.blips.push(MinimapBlip { color, icon, pos });
+    }
+
+    /// Marks `target` as the locked target. If the target is out of range
+    /// of the minimap, an arrow pointing towards it is shown on the
+    /// edge of the map
+    ///
+    /// Should be called after `clear_items`
+    pub fn add_target(&mut self, target: cgmath::Point3<f64>) {
+        use cgmath::*;
+        let pos = self.to_map(target);
+        let dir = vec2(pos.x, pos.y);
+        if dir.x.abs().max(dir.y.abs()) < EDGE_DIST && pos.z.abs() < 1. {
+            self.blips.push(MinimapBlip {
+                color: TARGET_COLOR,
+                icon: Icon::Ring,
+                pos: node::Node::default().pos(pos).u_scale(0.1),
+            });
+        } else if dir.magnitude2() > f64::EPSILON {
+            self.blips.push(MinimapBlip {
+                color: TARGET_COLOR,
+                icon: Icon::Arrow,
+                pos: Self::edge_blip(dir, 0.06),
+            });
+        }
+    }
+
+    /// Removes all items on the minimap
+    pub fn clear_items(&mut self) {
+        use cgmath::*;
+        self.blips.clear();
+        // add center icon (self)
+        self.blips.push(MinimapBlip {
+            color: CENTER_COLOR,
+            icon: Icon::Star,
+            pos: node::Node::default().u_scale(0.1),
+        });
+        // orientation indicator pointing towards world up
+        let center_inv = self.center.borrow().mat().invert().unwrap();
+        let up = center_inv.transform_vector(vec3(0., 1., 0.));
+        let up = vec2(up.x, up.y);
+        if up.magnitude2() > f64::EPSILON {
+            self.blips.push(MinimapBlip {
+                color: [1.0, 1.0, 1.0, 0.8],
+                icon: Icon::Triangle,
+                pos: Self::edge_blip(up, 0.04),
+            });
+        }
+    }
+}
+
+impl Drawable for Minimap {
+    fn render_args<'a>(
+        &'a mut self,
+        _positions: &[[[f32; 4]; 4]],
+    ) -> Vec<(
+        shader::UniformInfo,
+        VertexHolder<'a>,
+        glium::index::IndicesSource<'a>,
+    )> {
+        use cgmath::SquareMatrix;
+        self.icons
+            .render_args(&self.blips, cgmath::Matrix4::identity())
+    }
 
     fn transparency(&self) -> Option<f32> {
         None
@@ -200,7 +376,7 @@ impl entity::AbstractEntity for Minimap {
     }
 
     fn should_render(&self, pass: shader::RenderPassType) -> bool {
-        matches!(pass, shader::RenderPassType::Visual)
+        self.shown && matches!(pass, shader::RenderPassType::Visual)
     }
 
     fn render_order(&self) -> entity::RenderOrder {
@@ -0,0 +1,364 @@
+use cgmath::*;
+use shared_types::mission::{Objective, ObjectiveDef};
+use shared_types::save::MissionState;
+use shared_types::{ObjectId, ObjectType};
+use std::collections::HashSet;
+use std::time::Duration;
+
+/// Whether a mission has been won or lost
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MissionStatus {
+    InProgress,
+    Completed,
+    Failed,
+}
+
+/// A change in the progress of a mission
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum MissionEvent {
+    /// The objective with the given description became the current
+    /// objective
+    ObjectiveStarted(String),
+    ObjectiveCompleted(String),
+    /// The objective with the given description can no longer be
+    /// completed, failing the mission
+    ObjectiveFailed(String),
+    /// The last objective was completed
+    MissionCompleted,
+}
+
+impl std::fmt::Display for MissionEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::ObjectiveStarted(desc) => {
+                write!(f, "{}", tr!("mission.objective_started", desc))
+            }
+            Self::ObjectiveCompleted(desc) => {
+                write!(f, "{}", tr!("mission.objective_completed", desc))
+            }
+            Self::ObjectiveFailed(desc) => {
+                write!(f, "{}", tr!("mission.objective_failed", desc))
+            }
+            Self::MissionCompleted => write!(f, "{}", tr!("mission.complete")),
+        }
+    }
+}
+
+/// Tracks the player's progress through a sequence of objectives
+///
+/// Objectives are completed one at a time, in order. The mission is
+/// completed once the last objective is completed and fails as soon as any
+/// objective fails. A mission without objectives is never completed
+pub struct Mission {
+    objectives: Vec<ObjectiveDef>,
+    /// Index of the current objective
+    current: usize,
+    /// Asteroids destroyed since the current objective started
+    asteroids_destroyed: u32,
+    /// Time since the current objective started
+    elapsed: Duration,
+    /// Distance between player 1 and the waypoint of the current objective
+    /// as of the last update
+    waypoint_dist: Option<f64>,
+    /// Every object destroyed since the mission started
+    destroyed: HashSet<ObjectId>,
+    status: MissionStatus,
+    events: Vec<MissionEvent>,
+}
+
+impl Mission {
+    /// Creates a mission which starts at the first of `objectives`
+    pub fn new(objectives: Vec<ObjectiveDef>) -> Self {
+        let mut mission = Self {
+            objectives,
+            current: 0,
+            asteroids_destroyed: 0,
+            elapsed: Duration::default(),
+            waypoint_dist: None,
+            destroyed: HashSet::new(),
+            status: MissionStatus::InProgress,
+            events: Vec::new(),
+        };
+        mission.restart();
+        mission
+    }
+
+    /// Starts the mission over from its first objective, discarding any
+    /// events that haven't been taken
+    pub fn restart(&mut self) {
+        self.events.clear();
+        self.start_objective(0);
+        self.destroyed.clear();
+        self.status = MissionStatus::InProgress;
+    }
+
+    /// Gets the progress through the mission to save with the game
+    pub fn save_state(&self) -> MissionState {
+        MissionState {
+            current: self.current as u32,
+            asteroids_destroyed: self.asteroids_destroyed,
+            elapsed: self.elapsed,
+            destroyed: self.destroyed.iter().copied().collect(),
+            completed: self.status == MissionStatus::Completed,
+            failed: self.status == MissionStatus::Failed,
+        }
+    }
+
+    /// Restores the progress through the mission of a saved game, and
+    /// announces the objective it was saved on
+    pub fn load_state(&mut self, state: &MissionState) {
+        self.events.clear();
+        self.start_objective(state.current as usize);
+        self.asteroids_destroyed = state.asteroids_destroyed;
+        self.elapsed = state.elapsed;
+        self.destroyed = state.destroyed.iter().copied().collect();
+        self.status = if state.failed {
+            MissionStatus::Failed
+        } else if state.completed {
+            MissionStatus::Completed
+        } else {
+            MissionStatus::InProgress
+        };
+    }
+
+    /// Gets the objective the player is working on, or `None` if the
+    /// mission is over or has no objectives
+    pub fn current(&self) -> Option<&ObjectiveDef> {
+        if self.status == MissionStatus::InProgress {
+            self.objectives.get(self.current)
+        } else {
+            None
+        }
+    }
+
+    pub const fn status(&self) -> MissionStatus {
+        self.status
+    }
+
+    /// Takes the events that occurred since the last call, in the order they
+    /// occurred
+    pub fn take_events(&mut self) -> Vec<MissionEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Gets the point the current objective directs the player to, if any
+    pub fn waypoint(&self) -> Option<Point3<f64>> {
+        match self.current().map(|def| &def.objective) {
+            Some(Objective::ReachWaypoint { pos, .. }) => Some((*pos).into()),
+            _ => None,
+        }
+    }
+
+    /// Gets the object the current objective asks the player to protect, if
+    /// any
+    pub fn protected(&self) -> Option<ObjectId> {
+        match self.current().map(|def| &def.objective) {
+            Some(Objective::Protect { target, .. }) => Some(*target),
+            _ => None,
+        }
+    }
+
+    /// Gets the text describing the current objective and its progress, or
+    /// the outcome of the mission if it's over
+    ///
+    /// Returns an empty string if the mission has no objectives
+    pub fn hud_text(&self) -> String {
+        match self.status {
+            MissionStatus::Completed => return tr!("mission.complete"),
+            MissionStatus::Failed => return tr!("mission.failed"),
+            MissionStatus::InProgress => (),
+        }
+        let def = match self.objectives.get(self.current) {
+            Some(def) => def,
+            None => return String::new(),
+        };
+        let remaining = |duration: Duration| {
+            duration.saturating_sub(self.elapsed).as_secs_f64().ceil() as u64
+        };
+        match def.objective {
+            Objective::DestroyAsteroids(count) => format!(
+                "{} ({}/{})",
+                def.description, self.asteroids_destroyed, count
+            ),
+            Objective::Survive(duration)
+            | Objective::Protect { duration, .. } => {
+                format!("{} ({}s)", def.description, remaining(duration))
+            }
+            Objective::ReachWaypoint { .. } => self.waypoint_dist.map_or_else(
+                || def.description.clone(),
+                |dist| format!("{} ({})", def.description, dist.round() as u64),
+            ),
+        }
+    }
+
+    /// Advances the current objective by one frame
+    ///
+    /// `player_pos` - the position of player 1
+    ///
+    /// `destroyed` - the ids and types of the objects destroyed during the
+    /// frame
+    pub fn update(
+        &mut self,
+        dt: Duration,
+        player_pos: Point3<f64>,
+        destroyed: &[(ObjectId, ObjectType)],
+    ) {
+        self.destroyed.extend(destroyed.iter().map(|(id, _)| *id));
+        let objective = match self.current() {
+            Some(def) => def.objective.clone(),
+            None => return,
+        };
+        self.elapsed += dt;
+        let completed = match objective {
+            Objective::DestroyAsteroids(count) => {
+                self.asteroids_destroyed += destroyed
+                    .iter()
+                    .filter(|(_, typ)| *typ == ObjectType::Asteroid)
+                    .count() as u32;
+                self.asteroids_destroyed >= count
+            }
+            Objective::Survive(duration) => self.elapsed >= duration,
+            Objective::ReachWaypoint { pos, radius } => {
+                let dist = player_pos.distance(pos.into());
+                self.waypoint_dist = Some(dist);
+                dist <= radius
+            }
+            Objective::Protect { target, duration } => {
+                if self.destroyed.contains(&target) {
+                    self.fail();
+                    return;
+                }
+                self.elapsed >= duration
+            }
+        };
+        if completed {
+            self.complete();
+        }
+    }
+
+    /// Resets the progress tracking for the objective at `idx` and makes it
+    /// the current objective
+    fn start_objective(&mut self, idx: usize) {
+        self.current = idx;
+        self.asteroids_destroyed = 0;
+        self.elapsed = Duration::default();
+        self.waypoint_dist = None;
+        if let Some(def) = self.objectives.get(idx) {
+            self.events
+                .push(MissionEvent::ObjectiveStarted(def.description.clone()));
+        }
+    }
+
+    fn complete(&mut self) {
+        let desc = self.objectives[self.current].description.clone();
+        self.events.push(MissionEvent::ObjectiveCompleted(desc));
+        if self.current + 1 < self.objectives.len() {
+            self.start_objective(self.current + 1);
+        } else {
+            self.status = MissionStatus::Completed;
+            self.events.push(MissionEvent::MissionCompleted);
+        }
+    }
+
+    fn fail(&mut self) {
+        let desc = self.objectives[self.current].description.clone();
+        self.events.push(MissionEvent::ObjectiveFailed(desc));
+        self.status = MissionStatus::Failed;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(500);
+
+    fn mission() -> Mission {
+        Mission::new(vec![
+            ObjectiveDef::new(
+                "Reach",
+                Objective::ReachWaypoint {
+                    pos: [100., 0., 0.],
+                    radius: 10.,
+                },
+            ),
+            ObjectiveDef::new("Destroy", Objective::DestroyAsteroids(2)),
+            ObjectiveDef::new(
+                "Protect",
+                Objective::Protect {
+                    target: ObjectId::new(7),
+                    duration: Duration::from_secs(1),
+                },
+            ),
+        ])
+    }
+
+    #[test]
+    fn objectives_complete_in_order() {
+        let mut mission = mission();
+        let origin = point3(0., 0., 0.);
+        let asteroid = (ObjectId::new(1), ObjectType::Asteroid);
+        let ship = (ObjectId::new(2), ObjectType::Ship);
+        mission.update(FRAME, origin, &[]);
+        assert_eq!(mission.hud_text(), "Reach (100)");
+        assert_eq!(mission.waypoint(), Some(point3(100., 0., 0.)));
+        // destroying asteroids doesn't count before the objective starts
+        mission.update(FRAME, point3(95., 0., 0.), &[asteroid]);
+        assert_eq!(mission.hud_text(), "Destroy (0/2)");
+        mission.update(FRAME, origin, &[asteroid, ship]);
+        assert_eq!(mission.hud_text(), "Destroy (1/2)");
+        mission.update(FRAME, origin, &[asteroid]);
+        assert_eq!(mission.protected(), Some(ObjectId::new(7)));
+        mission.update(FRAME, origin, &[]);
+        assert_eq!(mission.hud_text(), "Protect (1s)");
+        mission.update(FRAME, origin, &[]);
+        assert_eq!(mission.status(), MissionStatus::Completed);
+        assert!(mission.current().is_none());
+        let events = mission.take_events();
+        assert_eq!(events.len(), 7);
+        assert_eq!(events.last(), Some(&MissionEvent::MissionCompleted));
+        assert!(mission.take_events().is_empty());
+    }
+
+    #[test]
+    fn destroying_protected_object_fails_mission() {
+        let mut mission = mission();
+        let target = (ObjectId::new(7), ObjectType::Asteroid);
+        let asteroid = (ObjectId::new(1), ObjectType::Asteroid);
+        let origin = point3(0., 0., 0.);
+        mission.update(FRAME, point3(100., 0., 0.), &[target]);
+        mission.update(FRAME, origin, &[asteroid, asteroid]);
+        mission.update(FRAME, origin, &[]);
+        // the target was destroyed before the protect objective started
+        assert_eq!(mission.status(), MissionStatus::Failed);
+        assert_eq!(
+            mission.take_events().last(),
+            Some(&MissionEvent::ObjectiveFailed("Protect".to_owned()))
+        );
+        mission.restart();
+        assert_eq!(mission.status(), MissionStatus::InProgress);
+        assert_eq!(mission.hud_text(), "Reach");
+        assert!(Mission::new(Vec::new()).hud_text().is_empty());
+    }
+
+    #[test]
+    fn loaded_mission_resumes_objective() {
+        let mut saved = mission();
+        let target = (ObjectId::new(7), ObjectType::Asteroid);
+        let asteroid = (ObjectId::new(1), ObjectType::Asteroid);
+        saved.update(FRAME, point3(100., 0., 0.), &[target]);
+        saved.update(FRAME, point3(0., 0., 0.), &[asteroid]);
+        let state = saved.save_state();
+        let mut loaded = mission();
+        loaded.load_state(&state);
+        assert_eq!(loaded.hud_text(), "Destroy (1/2)");
+        assert_eq!(
+            loaded.take_events(),
+            vec![MissionEvent::ObjectiveStarted("Destroy".to_owned())]
+        );
+        loaded.update(FRAME, point3(0., 0., 0.), &[asteroid]);
+        // the target was destroyed before the game was saved
+        loaded.update(FRAME, point3(0., 0., 0.), &[]);
+        assert_eq!(loaded.status(), MissionStatus::Failed);
+    }
+}
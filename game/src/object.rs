@@ -1,40 +1,57 @@
 use crate::cg_support::node;
 use crate::collisions;
+use crate::collisions::{CollisionLod, TreeStopCriteria};
 use crate::graphics_engine::entity::*;
+use crate::graphics_engine::model;
 use crate::graphics_engine::shader;
 use crate::physics::*;
-use crate::{collisions::TreeStopCriteria, graphics_engine::model};
 pub use shared_types::{ObjectId, ObjectType};
 use std::cell::RefCell;
+use std::convert::TryFrom;
 use std::rc::Rc;
 
 pub type ObjectData = (ObjectType, ObjectId);
 
+/// Gets the level of detail of the bvh of the collision mesh of objects of
+/// type `typ`
+pub const fn collision_lod(typ: ObjectType) -> CollisionLod {
+    match typ {
+        // large meshes that everything else runs into
+        ObjectType::Planet | ObjectType::Station => CollisionLod::Fine,
+        // boxes and beams, which have too few triangles to split
+        ObjectType::Laser
+        | ObjectType::Hook
+        | ObjectType::Pickup
+        | ObjectType::Debris => CollisionLod::Hull,
+        _ => CollisionLod::Coarse,
+    }
+}
+
 /// Gets the collision mesh path, bvh tree stop criteria, and the density of the object type
 pub fn col_data_of_obj_type(
     typ: ObjectType,
 ) -> Option<(&'static str, TreeStopCriteria, f64)> {
-    match typ {
-        ObjectType::Asteroid => Some((
-            "assets/asteroid1/Asteroid.obj",
-            TreeStopCriteria::default(),
-            2.71,
-        )),
-        ObjectType::Planet => Some((
-            "assets/planet/planet1.obj",
-            TreeStopCriteria::default(),
-            10.,
-        )),
-        ObjectType::Laser | ObjectType::Hook => {
-            Some(("assets/laser2.obj", TreeStopCriteria::default(), 0.5))
-        }
-        ObjectType::Ship => Some((
-            "assets/Ships/StarSparrow01.obj",
-            TreeStopCriteria::default(),
-            0.88,
-        )),
-        _ => None,
-    }
+    let (path, density) = match typ {
+        ObjectType::Asteroid => ("assets/asteroid1/Asteroid.obj", 2.71),
+        ObjectType::Planet => ("assets/planet/planet1.obj", 10.),
+        ObjectType::Laser | ObjectType::Hook => ("assets/laser2.obj", 0.5),
+        ObjectType::Ship => ("assets/Ships/StarSparrow01.obj", 0.88),
+        ObjectType::Pickup => ("assets/default_cube.obj", 1.),
+        ObjectType::Station => ("assets/station/station_hull.obj", 10.),
+        ObjectType::Debris => ("assets/default_cube.obj", 0.3),
+        _ => return None,
+    };
+    Some((path, collision_lod(typ).stop_criteria(), density))
+}
+
+/// Gets the collision mesh path and bvh tree stop criteria of every object
+/// type with a collision mesh
+pub fn collision_meshes(
+) -> impl Iterator<Item = (&'static str, TreeStopCriteria)> {
+    (0..=u8::MAX)
+        .map_while(|typ| ObjectType::try_from(typ).ok())
+        .filter_map(col_data_of_obj_type)
+        .map(|(path, bvh_stop, _)| (path, bvh_stop))
 }
 
 /// A game object that only stores a model
@@ -0,0 +1,130 @@
+//! Benchmark of a full simulation step. It's ignored by default, run it with
+//! `cargo test --release bench -- --ignored --nocapture --test-threads=1`
+//!
+//! Needs a GL context, since collisions between triangles are tested on the
+//! GPU
+
+use super::*;
+use crate::collisions::CollisionObject;
+use crate::graphics_engine::{set_active_ctx, shader};
+use crate::object::{col_data_of_obj_type, ObjectData};
+use cgmath::*;
+use criterion::Criterion;
+use rand::{Rng, SeedableRng};
+use serial_test::serial;
+use shared_types::{ObjectId, ObjectType};
+use std::time::Duration;
+
+/// Half width of the scene, small enough that bodies regularly collide
+const SCENE_SIZE: f64 = 500.;
+
+#[cfg(unix)]
+fn get_event_loop() -> glutin::event_loop::EventLoop<()> {
+    use glutin::platform::unix::EventLoopExtUnix;
+    glutin::event_loop::EventLoop::new_any_thread()
+}
+
+#[cfg(windows)]
+fn get_event_loop() -> glutin::event_loop::EventLoop<()> {
+    use glutin::platform::windows::EventLoopExtWindows;
+    glutin::event_loop::EventLoop::new_any_thread()
+}
+
+/// Creates a context whose window is never shown and makes it the active
+/// context
+fn init() -> (Rc<RefCell<glium::Display>>, Rc<shader::ShaderManager>) {
+    use glutin::window::WindowBuilder;
+    use glutin::ContextBuilder;
+    let e_loop = get_event_loop();
+    let window_builder = WindowBuilder::new()
+        .with_visible(false)
+        .with_inner_size(glium::glutin::dpi::PhysicalSize::<u32> {
+            width: 128,
+            height: 128,
+        });
+    let wnd_ctx =
+        glium::Display::new(window_builder, ContextBuilder::new(), &e_loop)
+            .unwrap();
+    gl::load_with(|s| wnd_ctx.gl_window().get_proc_address(s));
+    let shader = Rc::new(shader::ShaderManager::init(&wnd_ctx));
+    let ctx = Rc::new(RefCell::new(wnd_ctx));
+    set_active_ctx(ctx.clone(), shader.clone());
+    (ctx, shader)
+}
+
+/// Creates `count` bodies of type `typ` at random positions, moving at up to
+/// `speed`
+fn bodies(
+    typ: ObjectType,
+    count: u32,
+    body_type: BodyType,
+    speed: f64,
+    rng: &mut impl Rng,
+) -> Vec<RigidBody<ObjectData>> {
+    let (path, stop, density) = col_data_of_obj_type(typ).unwrap();
+    let prototype = CollisionObject::prototype(path, stop);
+    let mut rand_vec = |max: f64| {
+        vec3(
+            rng.gen_range(-max..=max),
+            rng.gen_range(-max..=max),
+            rng.gen_range(-max..=max),
+        )
+    };
+    (0..count)
+        .map(|id| {
+            let node = node::Node::default()
+                .pos(Point3::from_vec(rand_vec(SCENE_SIZE)));
+            let node = Rc::new(RefCell::new(node));
+            let collider = CollisionObject::from(node.clone(), &prototype);
+            let mut body = RigidBody::new(
+                node,
+                Some(collider),
+                body_type,
+                (typ, ObjectId::new(id)),
+            )
+            .with_density(density);
+            body.base.velocity = rand_vec(speed);
+            body
+        })
+        .collect()
+}
+
+#[test]
+#[ignore]
+#[serial]
+fn simulation_step_bench() {
+    let (_ctx, _shader) = init();
+    let mut rng = rand::rngs::StdRng::seed_from_u64(4377);
+    // roughly the makeup of a busy match
+    let mut objects: Vec<_> = [
+        (ObjectType::Station, 2, BodyType::Static, 0.),
+        (ObjectType::Asteroid, 400, BodyType::Dynamic, 5.),
+        (ObjectType::Ship, 20, BodyType::Controlled, 40.),
+        (ObjectType::Laser, 200, BodyType::Dynamic, 200.),
+    ]
+    .iter()
+    .flat_map(|&(typ, count, body_type, speed)| {
+        bodies(typ, count, body_type, speed, &mut rng)
+    })
+    .collect();
+    let mut sim = Simulation::<ObjectData>::new(point3(0., 0., 0.), SCENE_SIZE);
+    let dt = Duration::from_millis(16);
+    let mut c = Criterion::default()
+        .sample_size(20)
+        .warm_up_time(Duration::from_secs(1));
+    c.bench_function("simulation_step", |b| {
+        b.iter(|| {
+            let resolvers = {
+                let objects: Vec<_> = objects.iter().collect();
+                sim.calc_resolvers(&objects, &[], dt)
+            };
+            let mut objects: Vec<_> = objects.iter_mut().collect();
+            Simulation::<ObjectData>::apply_resolvers(
+                &mut objects,
+                &resolvers,
+                dt,
+            );
+        });
+    });
+    c.final_summary();
+}
@@ -2,6 +2,7 @@ use crate::node;
 use cgmath::*;
 use std::cell::RefCell;
 use std::rc::{Rc, Weak};
+use std::time::Duration;
 
 #[derive(PartialEq, Eq)]
 pub enum RestoringMode {
@@ -119,3 +120,81 @@ impl super::Forcer for Centripetal {
         }
     }
 }
+
+/// A blast that pushes bodies away from its center, such as the shockwave of
+/// an explosion
+///
+/// The push falls off linearly from the center to `radius`, and is spread
+/// evenly over `duration`, so it should be applied for that long
+pub struct RadialImpulse {
+    pub center: Point3<f64>,
+    /// Momentum given to a body at the center over the whole blast
+    pub impulse: f64,
+    /// Largest change in speed the blast gives a body at the center, so
+    /// light bodies aren't flung away
+    pub max_speed: f64,
+    /// Distance from the center past which bodies are unaffected
+    pub radius: f64,
+    pub duration: Duration,
+}
+
+impl super::Forcer for RadialImpulse {
+    fn get_force(
+        &self,
+        body: &super::BaseRigidBody,
+    ) -> Option<(Point3<f64>, Vector3<f64>)> {
+        let center = body.center();
+        let r = center - self.center;
+        let dist = r.magnitude();
+        if dist >= self.radius || dist < f64::EPSILON {
+            return None;
+        }
+        let falloff = 1. - dist / self.radius;
+        let impulse = self.impulse.min(body.mass * self.max_speed) * falloff;
+        let secs = self.duration.as_secs_f64().max(f64::EPSILON);
+        Some((center, r / dist * impulse / secs))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{BaseRigidBody, Forcer};
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    fn body_at(pos: Point3<f64>, mass: f64) -> BaseRigidBody {
+        let mut body = BaseRigidBody::new(Rc::new(RefCell::new(
+            node::Node::default().pos(pos),
+        )));
+        body.mass = mass;
+        body
+    }
+
+    #[test]
+    fn radial_impulse_falls_off_from_center() {
+        let blast = RadialImpulse {
+            center: point3(0., 0., 0.),
+            impulse: 100.,
+            max_speed: 10.,
+            radius: 20.,
+            duration: Duration::from_millis(500),
+        };
+        let (pt, force) =
+            blast.get_force(&body_at(point3(0., 5., 0.), 50.)).unwrap();
+        assert_eq!(pt, point3(0., 5., 0.));
+        assert_approx_eq!(force.y, 150.);
+        assert_approx_eq!(force.x, 0.);
+        let (_, force) = blast
+            .get_force(&body_at(point3(-15., 0., 0.), 50.))
+            .unwrap();
+        assert_approx_eq!(force.x, -50.);
+        // light bodies get no more than the max change in speed
+        let (_, force) =
+            blast.get_force(&body_at(point3(-15., 0., 0.), 2.)).unwrap();
+        assert_approx_eq!(force.x, -10.);
+        assert!(blast
+            .get_force(&body_at(point3(0., 0., 20.), 50.))
+            .is_none());
+        assert!(blast.get_force(&body_at(point3(0., 0., 0.), 50.)).is_none());
+    }
+}
@@ -1,3 +1,5 @@
+#[cfg(test)]
+mod bench;
 mod forces;
 mod rigid_body;
 mod simulation;
@@ -7,8 +9,8 @@ use cgmath::*;
 pub use forces::*;
 pub use rigid_body::*;
 pub use simulation::Simulation;
-use std::cell::RefCell;
-use std::rc::Weak;
+use std::cell::{Cell, RefCell};
+use std::rc::{Rc, Weak};
 
 /// Data to resolve a collision
 /// Sum of all collision resolving forces for a single object
@@ -138,11 +140,20 @@ pub trait Manipulator<T> {
         body_indices: &std::collections::HashMap<*const node::Node, u32>,
         dt: std::time::Duration,
     );
+
+    /// `false` once the manipulator no longer affects any bodies, so it can
+    /// be removed
+    fn is_active(&self) -> bool {
+        true
+    }
 }
 
 /// Abstracts a force under a Manipulator interface
 pub struct ForceManipulator<T> {
     forces: Vec<Box<dyn Forcer>>,
+    /// How much longer the forces are applied for, or `None` if they're
+    /// applied forever
+    remaining: Option<Cell<std::time::Duration>>,
     _m: std::marker::PhantomData<T>,
 }
 
@@ -152,18 +163,26 @@ impl<T> ForceManipulator<T> {
     pub fn new(forces: Vec<Box<dyn Forcer>>) -> Self {
         Self {
             forces,
+            remaining: None,
             _m: std::marker::PhantomData {},
         }
     }
 
     /// Creates a new manipulator from a single force
-    #[allow(unused)]
     pub fn new_single(force: Box<dyn Forcer>) -> Self {
         Self {
             forces: vec![force],
+            remaining: None,
             _m: std::marker::PhantomData {},
         }
     }
+
+    /// Stops applying the forces after `lifetime`, at which point the
+    /// manipulator is inactive
+    pub fn lifetime(mut self, lifetime: std::time::Duration) -> Self {
+        self.remaining = Some(Cell::new(lifetime));
+        self
+    }
 }
 
 /// Gets the difference in linear and angular velocity due to the force
@@ -191,6 +210,14 @@ impl<T> Manipulator<T> for ForceManipulator<T> {
         _body_indices: &std::collections::HashMap<*const node::Node, u32>,
         dt: std::time::Duration,
     ) {
+        let dt = self.remaining.as_ref().map_or(dt, |remaining| {
+            let left = remaining.get();
+            remaining.set(left.saturating_sub(dt));
+            dt.min(left)
+        });
+        if dt.is_zero() {
+            return;
+        }
         let dt = dt.as_secs_f64();
         let len = bodies.len();
         for (bod, idx) in bodies
@@ -207,8 +234,59 @@ impl<T> Manipulator<T> for ForceManipulator<T> {
             }
         }
     }
+
+    fn is_active(&self) -> bool {
+        self.remaining
+            .as_ref()
+            .map_or(true, |remaining| !remaining.get().is_zero())
+    }
+}
+
+/// Fraction of the distance a rigid tether is stretched past its length
+/// that is corrected each step
+const TETHER_CORRECTION: f64 = 0.2;
+/// Natural frequency, in radians per second, of a springy tether
+const TETHER_FREQUENCY: f64 = 6.;
+
+/// How a tether pulls its ends back together once it's stretched past its
+/// length
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum TetherMode {
+    /// The ends are held within the length by a distance constraint, whose
+    /// drift is corrected with Baumgarte stabilization
+    Rigid,
+    /// The stretch is pulled back by a critically damped spring, which eases
+    /// a towed body into following without bouncing it back and forth
+    Spring,
 }
 
+/// Gets the impulse, on the reduced mass of the ends of a tether, that pulls
+/// them together when the tether is stretched `stretch` past its length
+/// while the ends separate at `separating`
+fn tether_impulse(
+    mode: TetherMode,
+    stretch: f64,
+    separating: f64,
+    reduced_mass: f64,
+    dt: f64,
+) -> f64 {
+    let change = match mode {
+        TetherMode::Rigid => {
+            TETHER_CORRECTION.mul_add(stretch / dt, separating)
+        }
+        TetherMode::Spring => {
+            // the spring is integrated implicitly with a damping ratio of 1,
+            // so it stays stable for stiff springs and long time steps
+            let w = TETHER_FREQUENCY;
+            let damping = separating * w * w.mul_add(dt, 2.);
+            dt * (w * w).mul_add(stretch, damping) / w.mul_add(dt, 1.).powi(2)
+        }
+    };
+    change.max(0.) * reduced_mass
+}
+
+/// The state of a tether shared between the tether constraint and the
+/// game
 pub struct TetherData {
     pub a: Weak<RefCell<node::Node>>,
     /// Local space
@@ -216,16 +294,42 @@ pub struct TetherData {
     pub b: Weak<RefCell<node::Node>>,
     /// Local space
     pub attach_b: Point3<f64>,
+    /// Maximum distance between the attachment points
     pub length: f64,
+    pub mode: TetherMode,
+    /// Force the tether pulled its ends together with during the last step
+    pub tension: f64,
+    /// `true` once the tether no longer connects its ends
+    pub released: bool,
+}
+
+impl TetherData {
+    /// Gets the world space positions of both attachment points, or `None`
+    /// if either end no longer exists
+    pub fn attach_points(&self) -> Option<(Point3<f64>, Point3<f64>)> {
+        if let (Some(a), Some(b)) = (self.a.upgrade(), self.b.upgrade()) {
+            Some((
+                a.borrow().transform_point(self.attach_a),
+                b.borrow().transform_point(self.attach_b),
+            ))
+        } else {
+            None
+        }
+    }
 }
 
+/// A constraint that pulls the attachment points of two bodies back within
+/// the tether's length of each other, rigidly or with a spring
+///
+/// The bodies are pulled together by equal and opposite impulses, so the
+/// constraint conserves momentum
 pub struct Tether<T> {
-    pub data: TetherData,
+    pub data: Rc<RefCell<TetherData>>,
     _m: std::marker::PhantomData<T>,
 }
 
 impl<T> Tether<T> {
-    pub const fn new(data: TetherData) -> Self {
+    pub const fn new(data: Rc<RefCell<TetherData>>) -> Self {
         Self {
             data,
             _m: std::marker::PhantomData {},
@@ -235,95 +339,222 @@ impl<T> Tether<T> {
     /// `true` if the tether is taught (at or beyond its maximum length)
     #[allow(unused)]
     pub fn is_taught(&self) -> bool {
-        if let (Some(a), Some(b)) =
-            (self.data.a.upgrade(), self.data.b.upgrade())
+        let t = self.data.borrow();
+        t.attach_points()
+            .map_or(false, |(a, b)| (a - b).magnitude() > t.length)
+    }
+}
+
+/// Gets the inverse mass of `body`, which is `0` for static bodies
+fn inverse_mass(body: &BaseRigidBody) -> f64 {
+    if body.body_type == BodyType::Static || body.mass <= f64::EPSILON {
+        0.
+    } else {
+        1. / body.mass
+    }
+}
+
+impl<T> Manipulator<T> for Tether<T> {
+    fn affect_bodies(
+        &self,
+        objs: &[&RigidBody<T>],
+        resolvers: &mut [CollisionResolution],
+        body_indices: &std::collections::HashMap<*const node::Node, u32>,
+        dt: std::time::Duration,
+    ) {
+        let mut t = self.data.borrow_mut();
+        t.tension = 0.;
+        if t.released {
+            return;
+        }
+        if let (Some(a), Some(b), Some((attach_a, attach_b))) =
+            (t.a.upgrade(), t.b.upgrade(), t.attach_points())
         {
-            (a.borrow().transform_point(self.data.attach_a)
-                - b.borrow().transform_point(self.data.attach_b))
-            .magnitude()
-                > self.data.length
+            let a_idx = body_indices.get(&(a.as_ptr() as *const _));
+            let b_idx = body_indices.get(&(b.as_ptr() as *const _));
+            if let (Some(a_idx), Some(b_idx)) = (a_idx, b_idx) {
+                let (a_idx, b_idx) = (*a_idx as usize, *b_idx as usize);
+                let a_to_b = attach_b - attach_a;
+                let dist = a_to_b.magnitude();
+                let dt = dt.as_secs_f64();
+                if dist <= t.length || dist < f64::EPSILON || dt <= 0. {
+                    return;
+                }
+                let norm = a_to_b / dist;
+                let (body_a, body_b) = (&objs[a_idx].base, &objs[b_idx].base);
+                let (inv_a, inv_b) =
+                    (inverse_mass(body_a), inverse_mass(body_b));
+                if inv_a + inv_b <= f64::EPSILON {
+                    return;
+                }
+                let separating = (body_b.velocity - body_a.velocity).dot(norm);
+                let impulse = tether_impulse(
+                    t.mode,
+                    dist - t.length,
+                    separating,
+                    1. / (inv_a + inv_b),
+                    dt,
+                );
+                resolvers[a_idx].add_vel_change(norm * impulse * inv_a, None);
+                resolvers[b_idx].add_vel_change(norm * -impulse * inv_b, None);
+                t.tension = impulse / dt;
+            }
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        let t = self.data.borrow();
+        !t.released && t.attach_points().is_some()
+    }
+}
+
+/// The state of a spring joint shared between the joint constraint and the
+/// game
+pub struct SpringJointData {
+    pub a: Weak<RefCell<node::Node>>,
+    /// Local space
+    pub attach_a: Point3<f64>,
+    pub b: Weak<RefCell<node::Node>>,
+    /// Local space
+    pub attach_b: Point3<f64>,
+    /// Distance between the attachment points the spring rests at
+    pub rest_length: f64,
+    /// Acceleration, per unit of stretch, the spring pulls the ends
+    /// together with. Scaled by the masses of the ends so that light and
+    /// heavy bodies respond alike
+    pub stiffness: f64,
+    /// Acceleration, per unit of the speed the ends separate at, resisting
+    /// the separation
+    pub damping: f64,
+    /// `true` once the joint no longer connects its ends
+    pub released: bool,
+}
+
+impl SpringJointData {
+    /// Gets the world space positions of both attachment points, or `None`
+    /// if either end no longer exists
+    pub fn attach_points(&self) -> Option<(Point3<f64>, Point3<f64>)> {
+        if let (Some(a), Some(b)) = (self.a.upgrade(), self.b.upgrade()) {
+            Some((
+                a.borrow().transform_point(self.attach_a),
+                b.borrow().transform_point(self.attach_b),
+            ))
         } else {
-            false
+            None
         }
     }
 }
 
-/// Projection coefficient of `v` projected onto `u`
+/// A damped spring that pulls or pushes the attachment points of two bodies
+/// towards its rest length
 ///
-/// The vector would be this coefficient times `u`
-fn project_onto(v: Vector3<f64>, u: Vector3<f64>) -> f64 {
-    v.dot(u) / u.dot(u)
+/// Like a tether, the bodies are moved by equal and opposite impulses
+pub struct SpringJoint<T> {
+    pub data: Rc<RefCell<SpringJointData>>,
+    _m: std::marker::PhantomData<T>,
 }
 
-/// Gets the r vector, the projection of `body_a`'s velocity onto that vector,
-/// the projection of `body_b`'s velocity onto that vector and the total mass
-/// of both bodies
-fn get_r_projections_mass<T>(
-    body_a: &RigidBody<T>,
-    body_b: &RigidBody<T>,
-    tether_length: f64,
-) -> Option<(Vector3<f64>, f64, f64, f64)> {
-    /*let attach_a_world = body_a.base.transform.borrow()
-        .transform_point(t.attach_a);
-    let attach_b_world = body_b.base.transform.borrow()
-        .transform_point(t.attach_b);*/
-    let a_to_b = body_b.base.center() - body_a.base.center();
-    if a_to_b.magnitude() < tether_length {
-        None
-    } else {
-        let a_to_b = a_to_b.normalize();
-        let t_a = project_onto(body_a.base.velocity, a_to_b);
-        let t_b = project_onto(body_b.base.velocity, a_to_b);
-        Some((a_to_b, t_a, t_b, body_a.base.mass + body_b.base.mass))
+impl<T> SpringJoint<T> {
+    pub const fn new(data: Rc<RefCell<SpringJointData>>) -> Self {
+        Self {
+            data,
+            _m: std::marker::PhantomData {},
+        }
     }
 }
 
-impl<T> Manipulator<T> for Tether<T> {
+impl<T> Manipulator<T> for SpringJoint<T> {
     fn affect_bodies(
         &self,
         objs: &[&RigidBody<T>],
         resolvers: &mut [CollisionResolution],
         body_indices: &std::collections::HashMap<*const node::Node, u32>,
-        _dt: std::time::Duration,
+        dt: std::time::Duration,
     ) {
-        let t = &self.data;
-        if let (Some(a), Some(b)) = (t.a.upgrade(), t.b.upgrade()) {
+        let s = self.data.borrow();
+        if s.released {
+            return;
+        }
+        if let (Some(a), Some(b), Some((attach_a, attach_b))) =
+            (s.a.upgrade(), s.b.upgrade(), s.attach_points())
+        {
             let a_idx = body_indices.get(&(a.as_ptr() as *const _));
             let b_idx = body_indices.get(&(b.as_ptr() as *const _));
             if let (Some(a_idx), Some(b_idx)) = (a_idx, b_idx) {
-                let a_idx = *a_idx as usize;
-                let b_idx = *b_idx as usize;
-                if let Some((a_to_b, t_a, t_b, total_mass)) =
-                    get_r_projections_mass(objs[a_idx], objs[b_idx], t.length)
-                {
-                    let mut total_parallel_p = vec3(0., 0., 0.);
-                    let calc_momentum =
-                    |body: &RigidBody<T>,
-                     t,
-                     resolver: &mut CollisionResolution| {
-                        let v = t * a_to_b;
-                        resolver.add_vel_change(v * -1., None);
-                        v * body.base.mass
-                    };
-                    if t_a < 0. {
-                        total_parallel_p += calc_momentum(
-                            objs[a_idx],
-                            t_a,
-                            &mut resolvers[a_idx],
-                        );
-                    }
-                    if t_b > 0. {
-                        total_parallel_p += calc_momentum(
-                            objs[b_idx],
-                            t_b,
-                            &mut resolvers[b_idx],
-                        );
-                    }
-                    total_parallel_p /= total_mass;
-                    resolvers[a_idx].add_vel_change(total_parallel_p, None);
-                    resolvers[b_idx].add_vel_change(total_parallel_p, None);
+                let (a_idx, b_idx) = (*a_idx as usize, *b_idx as usize);
+                let a_to_b = attach_b - attach_a;
+                let dist = a_to_b.magnitude();
+                if dist < f64::EPSILON {
+                    return;
+                }
+                let norm = a_to_b / dist;
+                let (body_a, body_b) = (&objs[a_idx].base, &objs[b_idx].base);
+                let (inv_a, inv_b) =
+                    (inverse_mass(body_a), inverse_mass(body_b));
+                if inv_a + inv_b <= f64::EPSILON {
+                    return;
                 }
+                let separating = (body_b.velocity - body_a.velocity).dot(norm);
+                let accel = s.stiffness * (dist - s.rest_length)
+                    + s.damping * separating;
+                // impulse on the reduced mass of the ends
+                let impulse = accel * dt.as_secs_f64() / (inv_a + inv_b);
+                resolvers[a_idx].add_vel_change(norm * impulse * inv_a, None);
+                resolvers[b_idx].add_vel_change(norm * -impulse * inv_b, None);
+            }
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        let s = self.data.borrow();
+        !s.released && s.attach_points().is_some()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn rigid_tethers_stop_separation_and_correct_stretch() {
+        assert_approx_eq!(
+            tether_impulse(TetherMode::Rigid, 0., 2., 3., 0.1),
+            6.
+        );
+        assert_approx_eq!(
+            tether_impulse(TetherMode::Rigid, 1., 0., 1., 0.1),
+            2.
+        );
+        // ends moving together aren't pushed apart
+        assert_approx_eq!(
+            tether_impulse(TetherMode::Rigid, 0., -2., 1., 0.1),
+            0.
+        );
+    }
+
+    #[test]
+    fn spring_tethers_settle_without_bouncing() {
+        let dt = 1. / 60.;
+        let (mut stretch, mut separating) = (2., 0.);
+        for _ in 0..120 {
+            if stretch > 0. {
+                separating -= tether_impulse(
+                    TetherMode::Spring,
+                    stretch,
+                    separating,
+                    1.,
+                    dt,
+                );
             }
+            let next = separating.mul_add(dt, stretch);
+            assert!(next <= stretch + f64::EPSILON);
+            assert!(next > -0.01);
+            stretch = next;
         }
+        assert!(stretch < 0.01);
+        // even a long step doesn't overshoot
+        let impulse = tether_impulse(TetherMode::Spring, 1., 0., 1., 1.);
+        assert!(impulse < 1.);
     }
 }
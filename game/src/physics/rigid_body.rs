@@ -120,6 +120,9 @@ pub struct BaseRigidBody {
     pub collider: Option<collisions::CollisionObject>,
     pub body_type: BodyType,
     pub mass: f64,
+    /// Factor the moment of inertia computed from the collision mesh is
+    /// scaled by
+    pub inertia_fac: f64,
     shared_body_ptr: usize,
 }
 
@@ -167,7 +170,9 @@ impl BaseRigidBody {
 
     /// Gets the moment of inertia tensor
     pub fn moment_inertia(&self) -> Matrix3<f64> {
-        self.mass * unsafe { self.get_shared_body() }.inertial_tensor
+        self.mass
+            * self.inertia_fac
+            * unsafe { self.get_shared_body() }.inertial_tensor
     }
 
     /// Gets the body's collision method
@@ -185,6 +190,7 @@ impl BaseRigidBody {
             collider: None,
             body_type: BodyType::Controlled,
             mass: 0.,
+            inertia_fac: 1.,
             shared_body_ptr: 0,
         }
     }
@@ -211,6 +217,7 @@ impl<T> RigidBody<T> {
             base: BaseRigidBody {
                 transform,
                 mass,
+                inertia_fac: 1.,
                 shared_body_ptr: SharedRigidBody::get_ptr_id(&collider),
                 collider,
                 velocity: vec3(0., 0., 0.),
@@ -226,4 +233,12 @@ impl<T> RigidBody<T> {
         self.base.density(density);
         self
     }
+
+    /// Sets the mass of this body, and scales the moment of inertia computed
+    /// from its collision mesh by `inertia_fac`
+    pub fn with_mass(mut self, mass: f64, inertia_fac: f64) -> Self {
+        self.base.mass = mass;
+        self.base.inertia_fac = inertia_fac;
+        self
+    }
 }
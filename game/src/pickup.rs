@@ -0,0 +1,195 @@
+use crate::cg_support::node::Node;
+use crate::game_mediator::GameMediator;
+use crate::object::{ObjectId, ObjectType};
+use crate::player::Player;
+use cgmath::*;
+use shared_types::pickup::{PickupKind, SpawnTable};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Shield restored by a shield cell
+const SHIELD_CELL_AMOUNT: f64 = 40.;
+/// Energy restored by an energy cell
+const ENERGY_CELL_AMOUNT: f64 = 50.;
+/// How long a weapon mod boosts the fire rate for
+const WEAPON_MOD_DURATION: Duration = Duration::from_secs(10);
+/// Uniform scale of a pickup's model
+const PICKUP_SCALE: f64 = 2.;
+/// Distance a pickup floats above and below its spawn point
+const BOB_HEIGHT: f64 = 1.5;
+/// Radians per second of a pickup's floating motion
+const BOB_RATE: f64 = 2.;
+/// Angle a pickup spins through per second
+const SPIN_RATE: Deg<f64> = Deg(90.);
+
+/// Applies the effect of a pickup of type `kind` to the player who
+/// collected it
+pub fn apply_effect(kind: PickupKind, player: &mut Player) {
+    match kind {
+        PickupKind::ShieldCell => player.restore_shield(SHIELD_CELL_AMOUNT),
+        PickupKind::EnergyCell => player.change_energy(ENERGY_CELL_AMOUNT),
+        PickupKind::WeaponMod => player.boost_fire_rate(WEAPON_MOD_DURATION),
+    }
+}
+
+/// Gets the position and rotation of a pickup spawned at `anchor`, `time`
+/// seconds into its idle animation
+fn idle_pose(anchor: Point3<f64>, time: f64) -> (Point3<f64>, Quaternion<f64>) {
+    let pos = anchor + vec3(0., (time * BOB_RATE).sin() * BOB_HEIGHT, 0.);
+    let rot = Quaternion::from_axis_angle(vec3(0., 1., 0.), SPIN_RATE * time);
+    (pos, rot)
+}
+
+/// A pickup in the world
+struct ActivePickup {
+    kind: PickupKind,
+    /// Index of the spawn point the pickup was spawned at
+    point: usize,
+}
+
+/// Spawns the pickups of a spawn table, animates them, and respawns them
+/// after they're collected
+pub struct Pickups {
+    table: SpawnTable,
+    active: HashMap<ObjectId, ActivePickup>,
+    /// The spawn points without a pickup, and the time left before a pickup
+    /// is spawned at each of them
+    respawns: Vec<(usize, Duration)>,
+    /// Time since the pickups were created
+    time: Duration,
+}
+
+impl Pickups {
+    /// Creates pickups which spawn at every point of `table` on the first
+    /// update
+    pub fn new(table: SpawnTable) -> Self {
+        let respawns = (0..table.points.len())
+            .map(|point| (point, Duration::default()))
+            .collect();
+        Self {
+            table,
+            active: HashMap::new(),
+            respawns,
+            time: Duration::default(),
+        }
+    }
+
+    /// Spawns pickups at the spawn points whose respawn time is up and moves
+    /// every pickup through its idle animation
+    pub fn update<M: GameMediator>(&mut self, dt: Duration, mediator: &mut M) {
+        for point in self.tick(dt) {
+            let kind = match self.table.choose(rand::random()) {
+                Some(kind) => kind,
+                None => continue,
+            };
+            let transform = Node::default()
+                .pos(self.table.points[point].into())
+                .u_scale(PICKUP_SCALE);
+            if let Some(id) = mediator.add_pickup(transform) {
+                self.active.insert(id, ActivePickup { kind, point });
+            } else {
+                self.respawns.push((point, self.table.respawn_time));
+            }
+        }
+        let (active, points) = (&self.active, &self.table.points);
+        let time = self.time.as_secs_f64();
+        mediator.update_bodies(|bodies| {
+            for body in bodies.filter(|b| b.metadata.0 == ObjectType::Pickup) {
+                if let Some(pickup) = active.get(&body.metadata.1) {
+                    // offset each pickup's animation so they aren't in sync
+                    let (pos, rot) = idle_pose(
+                        points[pickup.point].into(),
+                        time + pickup.point as f64,
+                    );
+                    let mut transform = body.base.transform.borrow_mut();
+                    transform.set_pos(pos);
+                    transform.set_rot(rot);
+                }
+            }
+        });
+    }
+
+    /// Collects the pickup with the id `id`, scheduling a new pickup to be
+    /// spawned at its spawn point
+    ///
+    /// Returns the kind of the pickup, or `None` if it was already collected
+    /// or isn't a pickup
+    pub fn collect(&mut self, id: ObjectId) -> Option<PickupKind> {
+        let pickup = self.active.remove(&id)?;
+        self.respawns.push((pickup.point, self.table.respawn_time));
+        Some(pickup.kind)
+    }
+
+    /// Forgets every pickup, which must have been removed from the world,
+    /// so pickups are spawned at every spawn point on the next update
+    pub fn reset(&mut self) {
+        *self = Self::new(std::mem::take(&mut self.table));
+    }
+
+    /// Advances the respawn timers by `dt`
+    ///
+    /// Returns the spawn points whose respawn time is up
+    fn tick(&mut self, dt: Duration) -> Vec<usize> {
+        self.time += dt;
+        for (_, remaining) in &mut self.respawns {
+            *remaining = remaining.saturating_sub(dt);
+        }
+        let (due, waiting): (Vec<_>, Vec<_>) =
+            std::mem::take(&mut self.respawns)
+                .into_iter()
+                .partition(|(_, remaining)| *remaining == Duration::default());
+        self.respawns = waiting;
+        due.into_iter().map(|(point, _)| point).collect()
+    }
+}
+
+impl Default for Pickups {
+    /// Creates pickups without any spawn points
+    fn default() -> Self {
+        Self::new(SpawnTable::default())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn collected_pickups_respawn() {
+        let mut pickups = Pickups::new(SpawnTable {
+            points: vec![[0., 0., 0.], [10., 0., 0.]],
+            kinds: vec![(PickupKind::EnergyCell, 1)],
+            respawn_time: Duration::from_secs(2),
+        });
+        assert_eq!(pickups.tick(Duration::from_millis(16)), vec![0, 1]);
+        assert!(pickups.tick(Duration::from_millis(16)).is_empty());
+        pickups.active.insert(
+            ObjectId::new(3),
+            ActivePickup {
+                kind: PickupKind::EnergyCell,
+                point: 1,
+            },
+        );
+        assert_eq!(
+            pickups.collect(ObjectId::new(3)),
+            Some(PickupKind::EnergyCell)
+        );
+        // a pickup can only be collected once
+        assert_eq!(pickups.collect(ObjectId::new(3)), None);
+        assert!(pickups.tick(Duration::from_secs(1)).is_empty());
+        assert_eq!(pickups.tick(Duration::from_secs(1)), vec![1]);
+    }
+
+    #[test]
+    fn pickups_float_around_spawn_point() {
+        let anchor = point3(5., 5., 5.);
+        for time in [0., 0.4, 1.3, 2.9] {
+            let (pos, rot) = idle_pose(anchor, time);
+            assert!(pos.distance(anchor) <= BOB_HEIGHT);
+            assert_approx_eq!(pos.x, anchor.x);
+            assert_approx_eq!(rot.magnitude(), 1.);
+        }
+        assert!(idle_pose(anchor, 0.5).1 != idle_pose(anchor, 1.).1);
+    }
+}
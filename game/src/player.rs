@@ -4,60 +4,135 @@ use crate::camera;
 use crate::cg_support::node::*;
 use crate::collisions;
 use crate::controls::MovementControl;
+use crate::damage::Health;
+use crate::energy::{self, EnergySystem, PowerDistribution, Subsystem};
+use crate::graphics_engine::camera_effects::CameraEffects;
 use crate::graphics_engine::entity::AbstractEntity;
-use crate::graphics_engine::{drawable, entity, shader};
+use crate::graphics_engine::{drawable, entity, particles, shader};
 use crate::model::Model;
 use crate::physics;
+use crate::respawn::{self, DeathCamera, LifeState};
+use crate::ship::ShipDef;
+use crate::thrusters::{self, Thrusters};
 use drawable::Viewer;
+use shared_types::save::ShipState;
+use shared_types::survival::ShopItem;
 use std::cell::RefCell;
 use std::rc::Rc;
+use tracing::debug;
 
 use cgmath::*;
 
 const FAR_PLANE: f32 = 1000.;
 
-const ENERGY_PER_SEC: f64 = 1.;
+/// Field of view of the player's camera, before it's widened by speed
+const FOV: f32 = 60.;
 
-/// Changes a player's statistic that is bounded between 0 and 100
-#[inline]
-fn change_stat(stat: f64, change: f64) -> f64 {
-    (stat + change).max(0.).min(100.)
-}
+/// Transparency factor above which the ship's cloak is drawing power
+const CLOAKED_FAC: f32 = 0.5;
+
+/// Minimum seconds between shots
+const SHOT_INTERVAL: f64 = 0.2;
+
+/// Factor the fire rate is multiplied by while it's boosted
+const FIRE_BOOST_FAC: f64 = 3.;
+
+/// Emissive strength of the engine while idle
+const IDLE_EMISSION: f32 = 2.5;
+
+/// Emissive strength of the engine while thrusting
+const THRUST_EMISSION: f32 = 4.;
+
+/// Factor the engines' thrust, or an AI's velocity, is multiplied by while
+/// the afterburner is on
+pub const AFTERBURNER_FAC: f64 = 2.5;
+
+/// Emissive strength of the engine while the afterburner is on
+const AFTERBURNER_EMISSION: f32 = 9.;
+
+/// Width and emissive strength of the engine trail
+const TRAIL_STYLE: (f32, f32) = (0.8, 6.);
+
+/// Width and emissive strength of the engine trail while the afterburner is
+/// on
+const AFTERBURNER_TRAIL_STYLE: (f32, f32) = (1.6, 14.);
+
+/// How quickly flight assist damps the part of the ship's velocity that
+/// isn't along its heading, per second
+const FLIGHT_ASSIST_RATE: f64 = 1.5;
+
+/// Extra emissive strength of a ship at the peak of its shield shimmer
+const SHIMMER_EMISSION: f32 = 6.;
+
+/// Where benched players wait, out of the way of the players in play
+const BENCH_POS: Point3<f64> = Point3::new(0., -5000., 0.);
+
+/// Fraction of the angle between the ship and the tracked target that the
+/// camera turns towards the target. Less than `1` so the ship stays in view
+const CAM_TRACKING_STRENGTH: f64 = 0.35;
+
+/// How quickly the camera blends towards or away from a tracked target, per
+/// second
+const CAM_TRACKING_RATE: f64 = 3.;
 
 /// The player is the combination of the player's entity and the player's camera
 pub struct Player {
-    cam: Node,
+    cam: camera::ChaseCamera,
     entity: Rc<RefCell<entity::Entity>>,
+    engine_trail: Rc<RefCell<particles::TrailEmitter>>,
+    /// Exhaust of the engine and maneuvering thrusters
+    thrusters: Thrusters,
     controller: Rc<RefCell<dyn MovementControl>>,
     pub aspect: f32,
     body: physics::RigidBody<object::ObjectData>,
     // The transparency factor as read by the graphics engine
     pub inv_fac: Rc<RefCell<f32>>,
     em_fac: Rc<RefCell<f32>>,
-    energy: f64,
-    shield: f64,
+    energy: EnergySystem,
+    health: Health,
+    /// The ship the player flies
+    ship: ShipDef,
+    /// Index of the gun hardpoint the next shot is fired from
+    next_gun: usize,
+    afterburning: bool,
+    /// Point the camera softly turns towards
+    look_target: Point3<f64>,
+    /// `true` if the camera should be turning towards the look target
+    tracking: bool,
+    /// How far the camera has turned towards the look target, from `0` to `1`
+    look_blend: f64,
+    /// Seconds before the player can fire again
+    shot_cooldown: f64,
+    /// Seconds left of the player's boosted fire rate
+    fire_boost: f64,
+    life: LifeState,
+    /// `true` if the player respawns after dying, instead of being benched
+    respawns: bool,
+    /// Camera the player views its wreck from while it's dead
+    death_cam: Option<DeathCamera>,
+    /// Shake, kicks, and FOV widening applied on top of the camera's view
+    effects: CameraEffects,
 }
 
 impl Player {
     /// Creates a new player
     ///
-    /// `model` - the player model
+    /// `model` - the player model, which is the model of `ship`
     ///
     /// `view_aspect` - the screen aspect ratio to control the player perspective camera
     ///
-    /// `c_str` - collision string, the path of the collision mesh
+    /// `ship` - the ship the player flies
     pub fn new(
         model: Model,
         view_aspect: f32,
-        c_str: &str,
+        ship: &ShipDef,
         id: object::ObjectId,
         controller: Rc<RefCell<dyn MovementControl>>,
     ) -> Self {
         let root_node = Rc::new(RefCell::new(
             Node::default().pos(point3(200., 100., 100.)),
         ));
-        let mut cam = Node::new(Some(point3(0., 15., -25.)), None, None, None);
-        cam.set_parent(root_node.clone());
+        let cam = camera::ChaseCamera::new(vec3(0., 15., -25.));
         let mut model = model.with_transparency(0.99, 0);
         let inv_fac = model.trans_fac();
         let s = Self {
@@ -75,64 +150,117 @@ impl Player {
                 ],
                 order: entity::RenderOrder::Unordered,
             })),
-            body: physics::RigidBody::new(
-                root_node.clone(),
-                Some(collisions::CollisionObject::new(
-                    root_node,
-                    c_str,
-                    collisions::TreeStopCriteria::default(),
-                )),
-                physics::BodyType::Controlled,
-                (object::ObjectType::Ship, id),
-            )
-            .with_density(0.88),
+            engine_trail: Rc::new(RefCell::new(
+                particles::TrailEmitter::new(root_node.clone())
+                    .offset(ship.engine)
+                    .width(TRAIL_STYLE.0),
+            )),
+            thrusters: Thrusters::new(root_node.clone(), ship),
+            body: Self::body_of(root_node, ship, id),
             inv_fac,
-            energy: 100.,
-            shield: 100.,
+            energy: EnergySystem::new(ship.energy, ship.energy_recharge),
+            health: Health::new(ship.armor),
+            ship: ship.clone(),
+            next_gun: 0,
+            afterburning: false,
             controller,
+            look_target: point3(0., 0., 0.),
+            tracking: false,
+            look_blend: 0.,
+            shot_cooldown: 0.,
+            fire_boost: 0.,
+            life: LifeState::Alive,
+            respawns: true,
+            death_cam: None,
+            effects: CameraEffects::new(),
         };
-        println!(
+        debug!(
             "Player geom id: {}",
             s.body.base.collider.as_ref().unwrap().geometry_id()
         );
         s
     }
 
+    /// Creates the rigid body of `ship`, whose transform is `root`
+    fn body_of(
+        root: Rc<RefCell<Node>>,
+        ship: &ShipDef,
+        id: object::ObjectId,
+    ) -> physics::RigidBody<object::ObjectData> {
+        physics::RigidBody::new(
+            root.clone(),
+            Some(collisions::CollisionObject::new(
+                root,
+                &ship.collision,
+                object::collision_lod(object::ObjectType::Ship).into(),
+            )),
+            physics::BodyType::Controlled,
+            (object::ObjectType::Ship, id),
+        )
+        .with_mass(ship.mass, ship.inertia)
+    }
+
+    /// Swaps the ship the player flies for `ship`, whose model is `model`,
+    /// with full health and energy. The player keeps its place, camera,
+    /// and id
+    pub fn set_ship(&mut self, ship: &ShipDef, model: Model) {
+        let mut model = model.with_transparency(0.99, 0);
+        self.inv_fac = model.trans_fac();
+        self.em_fac = model.emissive_strength.clone();
+        self.entity.borrow_mut().geometry = Box::new(model);
+        self.engine_trail.borrow_mut().set_offset(ship.engine);
+        self.thrusters.set_ship(ship);
+        if let Some(collider) = &self.body.base.collider {
+            collisions::CollisionTree::remove(collider);
+        }
+        let (velocity, rot_vel) =
+            (self.body.base.velocity, self.body.base.rot_vel);
+        self.body = Self::body_of(self.get_node(), ship, self.body.metadata.1);
+        self.body.base.velocity = velocity;
+        self.body.base.rot_vel = rot_vel;
+        self.energy = EnergySystem::new(ship.energy, ship.energy_recharge);
+        self.health = Health::new(ship.armor);
+        self.ship = ship.clone();
+        self.next_gun = 0;
+    }
+
+    /// Gets the definition of the ship the player flies
+    pub const fn ship(&self) -> &ShipDef {
+        &self.ship
+    }
+
     /// Updates the players' forces based on the input controls and returns the rigid body
     pub fn update_rigid_body(
         &mut self,
         dt: std::time::Duration,
     ) -> &mut physics::RigidBody<object::ObjectData> {
         use cgmath::*;
+        if self.life.update(dt) {
+            self.respawn();
+        }
+        if self.life.is_dead() {
+            if let Some(cam) = &mut self.death_cam {
+                cam.update(dt);
+            }
+            self.body.base.velocity = vec3(0., 0., 0.);
+            self.body.base.rot_vel = vec3(0., 0., 0.);
+            self.thrusters
+                .update(vec3(0., 0., 0.), vec3(0., 0., 0.), false);
+            return &mut self.body;
+        }
         {
             let model: cgmath::Matrix4<f64> =
                 std::convert::From::from(&*self.body.base.transform.borrow());
             let forward = model.transform_vector(cgmath::vec3(0., 0., 1.));
             let dt_sec = dt.as_secs_f64();
-            let energy_cost = 10. * dt_sec;
-            self.body.base.velocity += match self
-                .controller
-                .borrow()
-                .get_movement()
-            {
-                controls::Movement::Forward if self.energy >= energy_cost => {
-                    *self.em_fac.borrow_mut() = 4.;
-                    self.energy = change_stat(self.energy, -energy_cost);
-                    forward
-                }
-                controls::Movement::Backwards if self.energy >= energy_cost => {
-                    *self.em_fac.borrow_mut() = 4.;
-                    self.energy = change_stat(self.energy, -energy_cost);
-                    -forward
-                }
-                _ => {
-                    *self.em_fac.borrow_mut() = 2.5;
-                    vec3(0., 0., 0.)
-                }
-            };
-            self.energy = change_stat(self.energy, ENERGY_PER_SEC * dt_sec);
-            self.shield =
-                change_stat(self.shield, ENERGY_PER_SEC / 3. * dt_sec);
+            if self.controller.borrow_mut().take_cycle_power() {
+                self.energy.cycle_distribution();
+            }
+            let thrusting = self.apply_thrust(forward, dt_sec);
+            self.energy.update(dt);
+            self.update_powered_systems(dt);
+            self.shot_cooldown = (self.shot_cooldown - dt_sec).max(0.);
+            self.fire_boost = (self.fire_boost - dt_sec).max(0.);
             if let Some(rot) = self.controller.borrow().get_snapped_rot() {
                 self.get_node().borrow_mut().set_rot(rot.into());
             } else {
@@ -140,12 +268,135 @@ impl Player {
                     self.controller.borrow().get_pitch(),
                     0.,
                     self.controller.borrow().get_roll(),
-                ) / 10000.;
+                ) * self.ship.turn_rate;
+            }
+            self.update_thrusters(thrusting);
+            if let Some(shimmer) = self.life.shimmer() {
+                *self.em_fac.borrow_mut() += SHIMMER_EMISSION * shimmer as f32;
             }
         }
         &mut self.body
     }
 
+    /// Thrusts the ship along `forward` and boosts it with the afterburner
+    /// as the controller asks and the energy allows, then damps the ship's
+    /// drift if flight assist is on. AI ships aren't thrusted, since their
+    /// velocity is set by their actions, but show their afterburner
+    ///
+    /// Returns `true` if the engines thrusted
+    fn apply_thrust(&mut self, forward: Vector3<f64>, dt_sec: f64) -> bool {
+        let (movement, afterburner, assisted, is_ai) = {
+            let controller = self.controller.borrow();
+            (
+                controller.get_movement(),
+                controller.is_afterburning(),
+                controller.is_flight_assisted(),
+                controller.is_ai(),
+            )
+        };
+        let energy_cost = energy::THRUST_COST * dt_sec;
+        let thrusting = movement != controls::Movement::Stopped
+            && self.energy.draw(energy_cost);
+        self.afterburning = if is_ai {
+            afterburner
+        } else {
+            afterburner
+                && thrusting
+                && movement == controls::Movement::Forward
+                && self.energy.draw(energy::AFTERBURNER_COST * dt_sec)
+        };
+        let boost = if self.afterburning {
+            AFTERBURNER_FAC
+        } else {
+            1.
+        };
+        let thrust = forward
+            * (self.energy.factor(Subsystem::Engines)
+                * boost
+                * self.ship.thrust
+                / self.ship.mass);
+        if thrusting {
+            self.body.base.velocity += match movement {
+                controls::Movement::Backwards => -thrust,
+                _ => thrust,
+            };
+        }
+        if assisted && !is_ai {
+            let along = forward * self.body.base.velocity.dot(forward);
+            let drift = self.body.base.velocity - along;
+            let t = 1. - (-FLIGHT_ASSIST_RATE * dt_sec).exp();
+            self.body.base.velocity -= drift * t;
+        }
+        *self.em_fac.borrow_mut() = if self.afterburning {
+            AFTERBURNER_EMISSION
+        } else if thrusting {
+            THRUST_EMISSION
+        } else {
+            IDLE_EMISSION
+        };
+        let (width, emission) = if self.afterburning {
+            AFTERBURNER_TRAIL_STYLE
+        } else {
+            TRAIL_STYLE
+        };
+        self.engine_trail.borrow_mut().set_style(width, emission);
+        self.effects.set_boosting(self.afterburning);
+        thrusting
+    }
+
+    /// Fires the thrusters that move the ship the way its controller asks.
+    /// AI ships fire their thrusters along the velocity set by their actions
+    ///
+    /// `thrusting` - `true` if the engines thrusted this update
+    fn update_thrusters(&mut self, thrusting: bool) {
+        let (movement, pitch, roll, snapped, is_ai) = {
+            let controller = self.controller.borrow();
+            (
+                controller.get_movement(),
+                controller.get_pitch(),
+                controller.get_roll(),
+                controller.get_snapped_rot().is_some(),
+                controller.is_ai(),
+            )
+        };
+        let linear = if is_ai {
+            let rot = self.body.base.transform.borrow().local_rot();
+            rot.conjugate() * self.body.base.velocity
+                / thrusters::AI_FULL_THRUST_SPEED
+        } else if thrusting {
+            match movement {
+                controls::Movement::Backwards => vec3(0., 0., -1.),
+                _ => vec3(0., 0., 1.),
+            }
+        } else {
+            vec3(0., 0., 0.)
+        };
+        let angular = if snapped {
+            vec3(0., 0., 0.)
+        } else {
+            vec3(pitch, 0., roll) * self.ship.turn_rate
+                / thrusters::FULL_TURN_SPEED
+        };
+        self.thrusters.update(linear, angular, self.afterburning);
+    }
+
+    /// Spends energy recharging the shield, at a rate depending on the power
+    /// distribution, and keeping the cloak up. The cloak drops once there
+    /// isn't enough energy for it
+    fn update_powered_systems(&mut self, dt: std::time::Duration) {
+        let recharged = self.health.update_powered(
+            dt,
+            self.energy.factor(Subsystem::Shields),
+            self.energy.charge() / energy::SHIELD_COST,
+        );
+        self.energy.drain(recharged * energy::SHIELD_COST);
+        if *self.inv_fac.borrow() > CLOAKED_FAC
+            && !self.energy.draw(energy::CLOAK_COST * dt.as_secs_f64())
+        {
+            self.controller.borrow_mut().on_cloak_depleted();
+        }
+    }
+
     pub const fn get_rigid_body(
         &self,
     ) -> &physics::RigidBody<object::ObjectData> {
@@ -169,25 +420,101 @@ impl Player {
     /// Constructs a new perspective camera so that it has the exact
     /// same view as the player's camera
     pub fn get_cam(&self) -> camera::PerspectiveCamera {
+        let (_, target, up) = self.view();
         camera::PerspectiveCamera {
-            fov_deg: 60.,
+            fov_deg: self.effects.fov(FOV),
             aspect: self.aspect,
             near: 0.1,
             far: FAR_PLANE,
             cam: self.cam_pos(),
-            up: self
-                .cam
-                .transform_vec(vec3(0., 1., 0.))
-                .cast::<f32>()
-                .unwrap(),
-            target: self
-                .body
-                .base
-                .transform
-                .borrow()
-                .local_pos()
-                .cast::<f32>()
-                .unwrap(),
+            up: up.cast::<f32>().unwrap(),
+            target: target.cast::<f32>().unwrap(),
+        }
+    }
+
+    /// Gets the position, target, and up direction of the camera, which is
+    /// the death camera while the player is dead, with the camera effects
+    /// applied
+    fn view(&self) -> (Point3<f64>, Point3<f64>, Vector3<f64>) {
+        let (pos, target, up) = self.death_cam.as_ref().map_or_else(
+            || (self.cam.pos(), self.look_point(), self.cam.up()),
+            |cam| (cam.pos(), cam.target(), vec3(0., 1., 0.)),
+        );
+        let (target, up) = self.effects.apply(pos, target, up);
+        (pos, target, up)
+    }
+
+    /// Gets the effects applied on top of the player's camera
+    pub fn camera_effects(&mut self) -> &mut CameraEffects {
+        &mut self.effects
+    }
+
+    /// Places the camera behind the ship on its next update instead of
+    /// springing it there, such as after the ship goes through a wormhole
+    pub fn snap_camera(&mut self) {
+        self.cam.snap();
+    }
+
+    /// Sets the point the camera softly turns towards, or turns the camera
+    /// back to the ship if `target` is `None`
+    ///
+    /// The camera only turns towards the target if the player's controller
+    /// has camera tracking enabled
+    pub fn set_look_target(&mut self, target: Option<Point3<f64>>) {
+        self.tracking = target.is_some();
+        if let Some(target) = target {
+            self.look_target = target;
+        }
+    }
+
+    /// Moves the chase camera behind the ship, keeping it out of the objects
+    /// in `scene`, and blends the camera towards the look target if it's
+    /// being tracked, or back towards the ship otherwise. Also advances the
+    /// camera effects
+    pub fn update_camera(
+        &mut self,
+        scene: &collisions::CollisionTree,
+        dt: std::time::Duration,
+    ) {
+        let free_look = self.controller.borrow_mut().take_free_look();
+        self.cam.free_look(free_look);
+        let model: Matrix4<f64> =
+            std::convert::From::from(&*self.body.base.transform.borrow());
+        let ship: Vec<_> = self.body.base.collider.iter().collect();
+        self.cam.update(&model, dt, scene, &ship);
+
+        let goal =
+            if self.tracking && self.controller.borrow().is_camera_tracking() {
+                1.
+            } else {
+                0.
+            };
+        let t = 1. - (-CAM_TRACKING_RATE * dt.as_secs_f64()).exp();
+        self.look_blend += (goal - self.look_blend) * t;
+        self.effects.update(dt, self.body.base.velocity.magnitude());
+    }
+
+    /// Gets the point the camera looks at, which is the ship turned partially
+    /// towards the look target
+    fn look_point(&self) -> Point3<f64> {
+        let ship = self.body.base.transform.borrow().local_pos();
+        if self.look_blend <= f64::EPSILON {
+            return ship;
+        }
+        let cam = self.cam.pos();
+        let to_ship = ship - cam;
+        let to_target = self.look_target - cam;
+        if to_target.magnitude2() <= f64::EPSILON {
+            return ship;
+        }
+        let dir = to_ship.normalize().lerp(
+            to_target.normalize(),
+            self.look_blend * CAM_TRACKING_STRENGTH,
+        );
+        if dir.magnitude2() <= f64::EPSILON {
+            ship
+        } else {
+            cam + dir.normalize() * to_ship.magnitude()
         }
     }
 
@@ -196,6 +523,26 @@ impl Player {
         self.entity.clone()
     }
 
+    /// Gets the trail left behind the player's engine
+    #[inline]
+    pub fn engine_trail(&self) -> Rc<RefCell<particles::TrailEmitter>> {
+        self.engine_trail.clone()
+    }
+
+    /// Gets the exhaust cones of the player's thrusters
+    #[inline]
+    pub fn thruster_cones(&self) -> Rc<RefCell<particles::ExhaustCones>> {
+        self.thrusters.cones()
+    }
+
+    /// Takes the thruster nozzles that particle emitters need to be made for,
+    /// which are the nozzles added since the last call
+    pub fn take_unemitted_nozzles(
+        &mut self,
+    ) -> Vec<Rc<RefCell<particles::Nozzle>>> {
+        self.thrusters.take_unemitted()
+    }
+
     /// Gets the ship transform/player root node
     #[inline]
     pub const fn root(&self) -> &Rc<RefCell<Node>> {
@@ -211,12 +558,22 @@ impl Player {
 
     #[inline]
     pub const fn energy(&self) -> f64 {
-        self.energy
+        self.energy.charge()
+    }
+
+    #[inline]
+    pub const fn power_distribution(&self) -> PowerDistribution {
+        self.energy.distribution()
     }
 
     #[inline]
     pub const fn shield(&self) -> f64 {
-        self.shield
+        self.health.shield()
+    }
+
+    #[inline]
+    pub const fn hull(&self) -> f64 {
+        self.health.hull()
     }
 
     #[inline]
@@ -224,26 +581,211 @@ impl Player {
         if self.controller.borrow().is_ai() {
             return;
         }
-        self.energy = change_stat(self.energy, delta);
+        if delta >= 0. {
+            self.energy.restore(delta);
+        } else {
+            self.energy.drain(-delta);
+        }
+    }
+
+    /// Takes `amount` energy if the player has at least that much
+    ///
+    /// Returns `true` if the energy was taken
+    pub fn draw_energy(&mut self, amount: f64) -> bool {
+        self.energy.draw(amount)
+    }
+
+    /// Restores up to `amount` of the player's shield
+    #[inline]
+    pub fn restore_shield(&mut self, amount: f64) {
+        self.health.restore_shield(amount);
+    }
+
+    /// Deals `damage` to the player's shield and then its hull. If the hull
+    /// is depleted, the player dies and respawns after the respawn delay.
+    /// Dead and invulnerable players can't be damaged
+    ///
+    /// Returns `true` if the player died
+    pub fn apply_damage(&mut self, damage: f64) -> bool {
+        if !self.life.can_take_damage() {
+            return false;
+        }
+        let died = self.health.apply_damage(damage);
+        if died {
+            self.die();
+        }
+        died
+    }
+
+    /// Hides and stops the player, and switches to the death camera until
+    /// the player respawns. Players that don't respawn are benched instead
+    fn die(&mut self) {
+        self.controller.borrow_mut().on_death();
+        if self.respawns {
+            self.life = LifeState::Dead(respawn::RESPAWN_DELAY);
+            self.death_cam =
+                Some(DeathCamera::new(self.body.base.center(), self.cam.pos()));
+        } else {
+            self.life = LifeState::Benched;
+            self.get_node().borrow_mut().set_pos(BENCH_POS);
+        }
+        self.entity.borrow_mut().locations.clear();
+        self.body.base.velocity = vec3(0., 0., 0.);
+        self.body.base.rot_vel = vec3(0., 0., 0.);
+    }
+
+    /// Takes the player out of play until it's spawned with `spawn_at`.
+    /// From then on, the player is benched again whenever it dies instead of
+    /// respawning
+    pub fn bench(&mut self) {
+        self.respawns = false;
+        self.die();
+    }
+
+    /// Puts a benched player back in play at `pos`, invulnerable for a while
+    pub fn spawn_at(&mut self, pos: Point3<f64>) {
+        self.get_node().borrow_mut().set_pos(pos);
+        self.respawn();
+        self.life = LifeState::Invulnerable(respawn::INVULNERABLE_TIME);
+    }
+
+    #[inline]
+    pub const fn is_benched(&self) -> bool {
+        matches!(self.life, LifeState::Benched)
+    }
+
+    /// Restores the player's shield and hull to full
+    pub fn repair(&mut self) {
+        self.health.reset();
+    }
+
+    /// Refills the player's energy, which its weapons fire with, and lets it
+    /// fire straight away
+    pub fn rearm(&mut self) {
+        self.energy.refill();
+        self.shot_cooldown = 0.;
+    }
+
+    /// Gets the state of the player's ship to save with the game
+    pub fn save_state(&self) -> ShipState {
+        ShipState {
+            body: to_remote_object(
+                &self.body.base.transform.borrow(),
+                &self.body.base.velocity,
+                &self.body.base.rot_vel,
+                self.body.metadata.0,
+                self.body.metadata.1,
+            ),
+            shield: self.shield(),
+            hull: self.hull(),
+            energy: self.energy(),
+            benched: self.is_benched(),
+        }
+    }
+
+    /// Puts the player's ship back in the state it was saved in. A player
+    /// that was dead when the game was saved comes back with full health
+    pub fn load_state(&mut self, state: &ShipState) {
+        if state.benched {
+            if !self.is_benched() {
+                self.bench();
+            }
+            return;
+        }
+        let (saved, vel, rot_vel, _, _) = from_remote_object(&state.body);
+        {
+            let mut transform = self.body.base.transform.borrow_mut();
+            transform.set_pos(saved.local_pos());
+            transform.set_rot(saved.local_rot());
+        }
+        self.respawn();
+        self.life = LifeState::Alive;
+        self.body.base.velocity = vel;
+        self.body.base.rot_vel = rot_vel;
+        if state.hull > 0. {
+            self.health.set(state.shield, state.hull);
+        }
+        self.energy.drain(self.energy.charge() - state.energy);
+    }
+
+    /// Puts the player back in play with full health and energy, and makes
+    /// it invulnerable for a while
+    fn respawn(&mut self) {
+        self.death_cam = None;
+        self.entity.borrow_mut().locations =
+            vec![self.body.base.transform.clone()];
+        self.health.reset();
+        self.energy.refill();
+        self.shot_cooldown = 0.;
+        self.fire_boost = 0.;
+        self.body.base.velocity = vec3(0., 0., 0.);
+        self.cam.snap();
+    }
+
+    /// Moves the dead player to the point it will respawn at
+    pub fn set_respawn_point(&mut self, pos: Point3<f64>) {
+        self.get_node().borrow_mut().set_pos(pos);
     }
 
     #[inline]
-    pub fn change_shield(&mut self, delta: f64) {
-        self.shield = change_stat(self.shield, delta);
-        if self.shield <= f64::EPSILON {
-            self.controller.borrow_mut().on_death();
-            self.get_node().borrow_mut().set_pos(
-                point3(
-                    rand::random::<f64>() - 0.5,
-                    rand::random::<f64>() - 0.5,
-                    rand::random::<f64>() - 0.5,
-                ) * 200.0,
-            );
-            self.shield = 100.;
-            self.energy = 100.;
+    pub const fn is_dead(&self) -> bool {
+        self.life.is_dead()
+    }
+
+    /// Gets the time left before the player respawns, or `None` if the
+    /// player is alive
+    pub const fn respawn_countdown(&self) -> Option<std::time::Duration> {
+        match self.life {
+            LifeState::Dead(remaining) => Some(remaining),
+            _ => None,
         }
     }
 
+    /// `true` if the player is alive and enough time has passed since its
+    /// last shot for it to fire again
+    #[inline]
+    pub fn can_fire(&self) -> bool {
+        self.shot_cooldown <= 0. && !self.is_dead()
+    }
+
+    /// Gets the world space position of the gun the player fires from next
+    pub fn muzzle(&self) -> Point3<f64> {
+        let gun = self.ship.guns[self.next_gun % self.ship.guns.len()];
+        self.root().borrow().transform_point(gun)
+    }
+
+    /// Gets the world space position of the gun the player fires from next,
+    /// and moves on to the gun after it
+    pub fn next_muzzle(&mut self) -> Point3<f64> {
+        let muzzle = self.muzzle();
+        self.next_gun = (self.next_gun + 1) % self.ship.guns.len();
+        muzzle
+    }
+
+    /// Gets the world space position the player's tractor beam is emitted
+    /// from
+    pub fn beam_origin(&self) -> Point3<f64> {
+        self.root().borrow().transform_point(self.ship.beam)
+    }
+
+    /// Starts the wait before the player can fire again, which is shorter
+    /// while the fire rate is boosted or weapons are given priority
+    #[inline]
+    pub fn start_shot_cooldown(&mut self) {
+        let interval = if self.fire_boost > 0. {
+            SHOT_INTERVAL / FIRE_BOOST_FAC
+        } else {
+            SHOT_INTERVAL
+        };
+        self.shot_cooldown = interval / self.energy.factor(Subsystem::Weapons);
+    }
+
+    /// Boosts the player's fire rate for `duration`, or for the rest of the
+    /// current boost if it's longer
+    pub fn boost_fire_rate(&mut self, duration: std::time::Duration) {
+        self.fire_boost = self.fire_boost.max(duration.as_secs_f64());
+    }
+
     /// Gets the player's entity id
     pub fn get_entity_id(&self) -> usize {
         self.entity.borrow().get_id()
@@ -259,6 +801,38 @@ impl Player {
         self.controller.borrow_mut().transition_action_state();
     }
 
+    /// See `controls::PlayerController::take_cycle_target`
+    pub fn take_cycle_target(&self) -> bool {
+        self.controller.borrow_mut().take_cycle_target()
+    }
+
+    /// See `controls::PlayerController::take_purchase`
+    pub fn take_purchase(&self) -> Option<ShopItem> {
+        self.controller.borrow_mut().take_purchase()
+    }
+
+    /// See `controls::PlayerController::on_docking`
+    pub fn on_docking(&self) {
+        self.controller.borrow_mut().on_docking();
+    }
+
+    /// See `controls::PlayerController::get_reel`
+    pub fn get_reel(&self) -> controls::Reel {
+        self.controller.borrow().get_reel()
+    }
+
+    /// See `controls::PlayerController::is_beam_held`
+    pub fn is_beam_held(&self) -> bool {
+        self.controller.borrow().is_beam_held()
+    }
+
+    /// See `controls::PlayerController::observe_projectiles`
+    pub fn observe_projectiles(&self, projectiles: &[controls::Projectile]) {
+        self.controller
+            .borrow_mut()
+            .observe_projectiles(projectiles);
+    }
+
     /// See `controls::PlayerController::on_frame_update`
     pub fn on_controller_tick<'a, T>(
         &'a self,
@@ -284,7 +858,7 @@ impl Player {
 impl drawable::Viewer for Player {
     fn proj_mat(&self) -> cgmath::Matrix4<f32> {
         cgmath::perspective(
-            cgmath::Deg::<f32>(60f32),
+            cgmath::Deg::<f32>(self.effects.fov(FOV)),
             self.aspect,
             0.1,
             FAR_PLANE,
@@ -292,18 +866,16 @@ impl drawable::Viewer for Player {
     }
 
     fn cam_pos(&self) -> cgmath::Point3<f32> {
-        self.cam.transform_point(point3(0., 0., 0.)).cast().unwrap()
+        self.view().0.cast().unwrap()
     }
 
     fn view_mat(&self) -> Matrix4<f32> {
-        let cam_pos = self.cam_pos();
-        let view_pos = self.body.base.transform.borrow().local_pos();
-        let up = self
-            .cam
-            .transform_vec(vec3(0., 1., 0.))
-            .cast::<f32>()
-            .unwrap();
-        Matrix4::look_at_rh(cam_pos, view_pos.cast::<f32>().unwrap(), up)
+        let (cam_pos, view_pos, up) = self.view();
+        Matrix4::look_at_rh(
+            cam_pos.cast::<f32>().unwrap(),
+            view_pos.cast::<f32>().unwrap(),
+            up.cast::<f32>().unwrap(),
+        )
     }
 
     fn view_dist(&self) -> (f32, f32) {
@@ -0,0 +1,555 @@
+use gl::types::GLuint;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+/// Default location the frame times are dumped to
+pub const CSV_PATH: &str = "profile.csv";
+/// Number of frames whose times are kept, and dumped
+const HISTORY_LEN: usize = 600;
+/// Number of frames the times displayed on the overlay are averaged over
+const OVERLAY_FRAMES: usize = 60;
+
+/// Where the time of a stage was measured
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Clock {
+    Cpu,
+    /// Measured with timer queries, so the time is how long the GPU took to
+    /// execute the commands of the stage
+    Gpu,
+}
+
+impl Display for Clock {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Cpu => write!(f, "cpu"),
+            Self::Gpu => write!(f, "gpu"),
+        }
+    }
+}
+
+/// The times of the stages of a frame
+#[derive(Clone, Default, Debug)]
+struct FrameTimes {
+    frame: u64,
+    /// Time from the start of the frame to the start of the next one
+    total: Duration,
+    /// The total time of each stage in the order the stages first ran. A
+    /// stage which ran several times during the frame is the sum of its runs
+    stages: Vec<(Clock, String, Duration)>,
+    /// The last value of each counter during the frame
+    counts: Vec<(&'static str, u64)>,
+}
+
+impl FrameTimes {
+    fn add(&mut self, clock: Clock, name: &str, time: Duration) {
+        match self
+            .stages
+            .iter_mut()
+            .find(|(c, n, _)| *c == clock && n.as_str() == name)
+        {
+            Some((.., total)) => *total += time,
+            None => self.stages.push((clock, name.to_owned(), time)),
+        }
+    }
+
+    fn set_count(&mut self, name: &'static str, value: u64) {
+        match self.counts.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, count)) => *count = value,
+            None => self.counts.push((name, value)),
+        }
+    }
+
+    fn get_count(&self, name: &str) -> Option<u64> {
+        self.counts
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, count)| *count)
+    }
+
+    fn get(&self, clock: Clock, name: &str) -> Option<Duration> {
+        self.stages
+            .iter()
+            .find(|(c, n, _)| *c == clock && n.as_str() == name)
+            .map(|(.., time)| *time)
+    }
+}
+
+/// A pair of timestamp queries around a stage run on the GPU
+struct GpuQuery {
+    /// The frame the stage ran in
+    frame: u64,
+    name: String,
+    start: GLuint,
+    end: GLuint,
+}
+
+/// Timer queries which were issued but whose results weren't read yet
+///
+/// The GPU runs behind the CPU, so the results are read once they become
+/// available instead of stalling until they are. Query objects are reused
+/// once their results are read
+#[derive(Default)]
+struct GpuTimers {
+    pending: Vec<GpuQuery>,
+    free: Vec<GLuint>,
+}
+
+impl GpuTimers {
+    /// Records the time the GPU reaches this point in the command stream
+    ///
+    /// Returns the query which will hold the time
+    fn timestamp(&mut self) -> GLuint {
+        let query = self.free.pop().unwrap_or_else(|| {
+            let mut query = 0;
+            unsafe { gl::GenQueries(1, &mut query) };
+            query
+        });
+        unsafe { gl::QueryCounter(query, gl::TIMESTAMP) };
+        query
+    }
+
+    /// Gets the `(frame, stage, time)` of each query whose results are
+    /// available, without waiting for the others
+    fn collect(&mut self) -> Vec<(u64, String, Duration)> {
+        let Self { pending, free } = self;
+        let mut done = Vec::new();
+        pending.retain(|query| {
+            let mut available = 0;
+            unsafe {
+                gl::GetQueryObjectiv(
+                    query.end,
+                    gl::QUERY_RESULT_AVAILABLE,
+                    &mut available,
+                );
+            }
+            if available == 0 {
+                return true;
+            }
+            let (mut start, mut end) = (0, 0);
+            unsafe {
+                gl::GetQueryObjectui64v(
+                    query.start,
+                    gl::QUERY_RESULT,
+                    &mut start,
+                );
+                gl::GetQueryObjectui64v(query.end, gl::QUERY_RESULT, &mut end);
+            }
+            done.push((
+                query.frame,
+                query.name.clone(),
+                Duration::from_nanos(end.saturating_sub(start)),
+            ));
+            free.extend([query.start, query.end]);
+            false
+        });
+        done
+    }
+}
+
+/// Measures how long each stage of a frame takes on the CPU and the GPU
+///
+/// Keeps the times of the last `HISTORY_LEN` frames
+#[derive(Default)]
+struct Profiler {
+    enabled: bool,
+    /// The frame being measured
+    frame: FrameTimes,
+    frame_start: Option<Instant>,
+    history: VecDeque<FrameTimes>,
+    gpu: GpuTimers,
+}
+
+impl Profiler {
+    /// Ends the current frame at `now`, starting the next one
+    fn next_frame(&mut self, now: Instant) {
+        if let Some(start) = self.frame_start {
+            let next = FrameTimes {
+                frame: self.frame.frame + 1,
+                ..FrameTimes::default()
+            };
+            let mut done = std::mem::replace(&mut self.frame, next);
+            done.total = now.saturating_duration_since(start);
+            self.history.push_back(done);
+            while self.history.len() > HISTORY_LEN {
+                self.history.pop_front();
+            }
+        }
+        self.frame_start = Some(now);
+        if !self.gpu.pending.is_empty() {
+            for (frame, name, time) in self.gpu.collect() {
+                self.record_gpu(frame, &name, time);
+            }
+        }
+    }
+
+    /// Adds the GPU time of a stage to `frame`, which already ended. The
+    /// time is dropped if the frame is no longer kept
+    fn record_gpu(&mut self, frame: u64, name: &str, time: Duration) {
+        if let Some(times) = self.history.iter_mut().find(|f| f.frame == frame)
+        {
+            times.add(Clock::Gpu, name, time);
+        }
+    }
+
+    /// Clears the recorded times, so a profile doesn't include frames from
+    /// before it was enabled
+    fn reset(&mut self) {
+        let frame = self.frame.frame + 1;
+        self.frame = FrameTimes {
+            frame,
+            ..FrameTimes::default()
+        };
+        self.frame_start = None;
+        self.history.clear();
+    }
+
+    /// Gets the lines of the overlay. The first line is the frame time, then
+    /// the counters of the last frame if there are any, and the rest are the
+    /// times of the slowest stages. Times are averaged over the last
+    /// `OVERLAY_FRAMES` frames
+    ///
+    /// `max_lines` - the maximum number of lines
+    fn overlay_lines(&self, max_lines: usize) -> Vec<String> {
+        let frames: Vec<_> =
+            self.history.iter().rev().take(OVERLAY_FRAMES).collect();
+        if frames.is_empty() || max_lines == 0 {
+            return Vec::new();
+        }
+        let count = frames.len() as f64;
+        let mut stages: Vec<(Clock, &str, f64)> = Vec::new();
+        for (clock, name, time) in frames.iter().flat_map(|f| &f.stages) {
+            let ms = time.as_secs_f64() * 1000. / count;
+            match stages
+                .iter_mut()
+                .find(|(c, n, _)| *c == *clock && *n == name.as_str())
+            {
+                Some((.., total)) => *total += ms,
+                None => stages.push((*clock, name.as_str(), ms)),
+            }
+        }
+        stages.sort_by(|(.., a), (.., b)| b.total_cmp(a));
+        let frame_ms =
+            frames.iter().map(|f| f.total.as_secs_f64()).sum::<f64>() * 1000.
+                / count;
+        let fps = if frame_ms > 0. { 1000. / frame_ms } else { 0. };
+        let counts = frames[0]
+            .counts
+            .iter()
+            .map(|(name, count)| format!("{} {}", name, count))
+            .collect::<Vec<_>>()
+            .join(", ");
+        std::iter::once(format!("frame {:.2} ms ({:.0} fps)", frame_ms, fps))
+            .chain(Some(counts).filter(|counts| !counts.is_empty()))
+            .chain(stages.iter().map(|(clock, name, ms)| {
+                format!("{} {} {:.2} ms", clock, name, ms)
+            }))
+            .take(max_lines)
+            .collect()
+    }
+
+    /// Writes the kept frames as CSV, with a row per frame, a column of
+    /// milliseconds per stage and a column per counter. The columns are
+    /// named `<clock>:<stage>` and `count:<counter>`, and a stage which
+    /// didn't run or a counter which wasn't set during a frame is empty
+    fn write_csv(&self, mut out: impl Write) -> std::io::Result<()> {
+        let mut columns: Vec<(Clock, &str)> = Vec::new();
+        for (clock, name, _) in self.history.iter().flat_map(|f| &f.stages) {
+            if !columns
+                .iter()
+                .any(|(c, n)| c == clock && *n == name.as_str())
+            {
+                columns.push((*clock, name.as_str()));
+            }
+        }
+        let mut counters: Vec<&str> = Vec::new();
+        for (name, _) in self.history.iter().flat_map(|f| &f.counts) {
+            if !counters.contains(name) {
+                counters.push(name);
+            }
+        }
+        write!(out, "frame,total")?;
+        for (clock, name) in &columns {
+            write!(out, ",{}:{}", clock, name)?;
+        }
+        for name in &counters {
+            write!(out, ",count:{}", name)?;
+        }
+        writeln!(out)?;
+        for frame in &self.history {
+            write!(
+                out,
+                "{},{:.3}",
+                frame.frame,
+                frame.total.as_secs_f64() * 1000.
+            )?;
+            for (clock, name) in &columns {
+                write!(out, ",")?;
+                if let Some(time) = frame.get(*clock, name) {
+                    write!(out, "{:.3}", time.as_secs_f64() * 1000.)?;
+                }
+            }
+            for name in &counters {
+                write!(out, ",")?;
+                if let Some(count) = frame.get_count(name) {
+                    write!(out, "{}", count)?;
+                }
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
+
+thread_local! {
+    static PROFILER: RefCell<Profiler> = RefCell::new(Profiler::default());
+}
+
+/// Turns profiling on or off. Profiling is off by default, and costs
+/// nothing but a check while it's off
+pub fn set_enabled(enabled: bool) {
+    PROFILER.with(|p| {
+        let mut p = p.borrow_mut();
+        if enabled && !p.enabled {
+            p.reset();
+        }
+        p.enabled = enabled;
+    });
+}
+
+/// `true` if the profiler is measuring frames
+#[must_use]
+pub fn is_enabled() -> bool {
+    PROFILER.with(|p| p.borrow().enabled)
+}
+
+/// Ends the current frame and starts the next one. Must be called once at
+/// the start of every frame
+pub fn next_frame() {
+    PROFILER.with(|p| {
+        let mut p = p.borrow_mut();
+        if p.enabled {
+            p.next_frame(Instant::now());
+        }
+    });
+}
+
+/// Sets the counter `name` of the current frame to `value`, such as the
+/// number of lights drawn
+pub fn count(name: &'static str, value: u64) {
+    PROFILER.with(|p| {
+        let mut p = p.borrow_mut();
+        if p.enabled {
+            p.frame.set_count(name, value);
+        }
+    });
+}
+
+/// Measures the CPU time of a stage until the scope is dropped
+#[must_use]
+pub struct CpuScope {
+    name: &'static str,
+    /// `None` if profiling is off
+    start: Option<Instant>,
+    /// Logs the stage as a trace span, which is exited when the scope is
+    /// dropped
+    _span: tracing::span::EnteredSpan,
+}
+
+impl Drop for CpuScope {
+    fn drop(&mut self) {
+        if let Some(start) = self.start {
+            let time = start.elapsed();
+            PROFILER.with(|p| {
+                p.borrow_mut().frame.add(Clock::Cpu, self.name, time)
+            });
+        }
+    }
+}
+
+/// Starts measuring the CPU time of the stage `name`, which is recorded when
+/// the returned scope is dropped. The stage is also a `stage` span of the
+/// log, whether or not profiling is on
+pub fn scope(name: &'static str) -> CpuScope {
+    CpuScope {
+        name,
+        start: if is_enabled() {
+            Some(Instant::now())
+        } else {
+            None
+        },
+        _span: tracing::trace_span!("stage", name).entered(),
+    }
+}
+
+/// Measures the GPU time of the commands issued until the scope is dropped
+#[must_use]
+pub struct GpuScope {
+    /// The stage and its start query, or `None` if profiling is off
+    start: Option<(String, GLuint)>,
+}
+
+impl Drop for GpuScope {
+    fn drop(&mut self) {
+        if let Some((name, start)) = self.start.take() {
+            PROFILER.with(|p| {
+                let mut p = p.borrow_mut();
+                let end = p.gpu.timestamp();
+                let frame = p.frame.frame;
+                p.gpu.pending.push(GpuQuery {
+                    frame,
+                    name,
+                    start,
+                    end,
+                });
+            });
+        }
+    }
+}
+
+/// Starts measuring the GPU time of the stage `name`. The time is recorded
+/// once the GPU finishes the commands issued before the returned scope is
+/// dropped, which is usually a few frames later
+///
+/// Requires a current OpenGL context with the `gl` functions loaded
+pub fn gpu_scope(name: &str) -> GpuScope {
+    GpuScope {
+        start: PROFILER.with(|p| {
+            let mut p = p.borrow_mut();
+            if p.enabled {
+                Some((name.to_owned(), p.gpu.timestamp()))
+            } else {
+                None
+            }
+        }),
+    }
+}
+
+/// Gets the lines of the profiler overlay, which shows the average frame
+/// time and the times of the slowest stages
+///
+/// `max_lines` - the maximum number of lines
+#[must_use]
+pub fn overlay_lines(max_lines: usize) -> Vec<String> {
+    PROFILER.with(|p| p.borrow().overlay_lines(max_lines))
+}
+
+/// Writes the times of the kept frames to the CSV file at `path`
+///
+/// # Errors
+/// Fails if the file cannot be written
+pub fn dump_csv(path: &str) -> std::io::Result<()> {
+    let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    PROFILER.with(|p| p.borrow().write_csv(file))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    /// Records frames which each take `10 ms` and run `sim` on the CPU
+    /// twice, then ends them
+    fn profile_frames(profiler: &mut Profiler, frames: u32) {
+        let start = Instant::now();
+        for i in 0..frames {
+            profiler.next_frame(start + ms(10) * i);
+            profiler.frame.add(Clock::Cpu, "sim", ms(2));
+            profiler.frame.add(Clock::Cpu, "sim", ms(1));
+        }
+        profiler.next_frame(start + ms(10) * frames);
+    }
+
+    #[test]
+    fn stage_times_are_summed_per_frame() {
+        let mut profiler = Profiler::default();
+        profile_frames(&mut profiler, 3);
+        assert_eq!(profiler.history.len(), 3);
+        for (frame, number) in profiler.history.iter().zip(0..) {
+            assert_eq!(frame.frame, number);
+            assert_eq!(frame.total, ms(10));
+            assert_eq!(frame.get(Clock::Cpu, "sim"), Some(ms(3)));
+            assert_eq!(frame.get(Clock::Gpu, "sim"), None);
+        }
+        // gpu times arrive later but belong to the frame they ran in
+        profiler.record_gpu(1, "main/msaa", ms(4));
+        assert_eq!(
+            profiler.history[1].get(Clock::Gpu, "main/msaa"),
+            Some(ms(4))
+        );
+        assert_eq!(profiler.history[0].get(Clock::Gpu, "main/msaa"), None);
+    }
+
+    #[test]
+    fn history_is_bounded() {
+        let mut profiler = Profiler::default();
+        profile_frames(&mut profiler, HISTORY_LEN as u32 + 5);
+        assert_eq!(profiler.history.len(), HISTORY_LEN);
+        assert_eq!(profiler.history[0].frame, 5);
+        // times of frames which are no longer kept are dropped
+        profiler.record_gpu(0, "main/msaa", ms(4));
+        assert!(profiler
+            .history
+            .iter()
+            .all(|f| f.get(Clock::Gpu, "main/msaa").is_none()));
+    }
+
+    #[test]
+    fn overlay_shows_slowest_stages() {
+        let mut profiler = Profiler::default();
+        assert!(profiler.overlay_lines(4).is_empty());
+        profile_frames(&mut profiler, 2);
+        profiler.record_gpu(0, "main/msaa", ms(8));
+        profiler.record_gpu(1, "main/msaa", ms(4));
+        profiler.record_gpu(1, "ui/msaa", ms(1));
+        assert_eq!(
+            profiler.overlay_lines(3),
+            vec![
+                "frame 10.00 ms (100 fps)".to_owned(),
+                "gpu main/msaa 6.00 ms".to_owned(),
+                "cpu sim 3.00 ms".to_owned(),
+            ]
+        );
+    }
+
+    #[test]
+    fn counters_are_shown_and_dumped() {
+        let mut profiler = Profiler::default();
+        let start = Instant::now();
+        profiler.next_frame(start);
+        profiler.frame.set_count("lights", 20);
+        profiler.frame.set_count("lights", 30);
+        profiler.next_frame(start + ms(10));
+        profiler.frame.set_count("dropped", 2);
+        profiler.next_frame(start + ms(20));
+        assert_eq!(profiler.overlay_lines(4)[1], "dropped 2");
+        let mut csv = Vec::new();
+        profiler.write_csv(&mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "frame,total,count:lights,count:dropped\n\
+             0,10.000,30,\n\
+             1,10.000,,2\n"
+        );
+    }
+
+    #[test]
+    fn csv_has_a_column_per_stage() {
+        let mut profiler = Profiler::default();
+        profile_frames(&mut profiler, 2);
+        profiler.record_gpu(1, "main/msaa", ms(4));
+        let mut csv = Vec::new();
+        profiler.write_csv(&mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "frame,total,cpu:sim,gpu:main/msaa\n\
+             0,10.000,3.000,\n\
+             1,10.000,3.000,4.000\n"
+        );
+    }
+}
@@ -0,0 +1,336 @@
+use super::drawable::*;
+use super::minimap::{self, Icon};
+use super::object;
+use super::physics;
+use crate::cg_support::{node, Transformation};
+use crate::graphics_engine::{entity, instancing, shader};
+use cgmath::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const LINE_VERTS: [VertexPos; 2] = [
+    VertexPos { pos: [0., 0., 0.] },
+    VertexPos { pos: [1., 0., 0.] },
+];
+
+const LINE_INDICES: [u8; 2] = [0, 1];
+
+/// Number of line segments in each circle of the wireframe sphere
+const CIRCLE_SEGMENTS: u32 = 48;
+/// Angle the radar is tilted towards the viewer, so the equatorial plane
+/// can be seen from above
+const TILT: Deg<f64> = Deg(25.);
+/// Radius of the sphere in clip space
+const SPHERE_SCALE: f64 = 0.85;
+/// Opacity of contacts at the edge of the radar's range
+const MIN_ALPHA: f32 = 0.2;
+const WIREFRAME_COLOR: [f32; 4] = [0.1, 0.8, 1.0, 0.35];
+
+struct RadarBlip {
+    color: [f32; 4],
+    icon: Icon,
+    /// Position in radar space, where the sphere has a radius of `1`
+    pos: Point3<f64>,
+    scale: f64,
+}
+
+/// A holographic sphere showing the contacts around the player in 3D
+///
+/// Radar space is the local space of the player scaled so that the
+/// radar's range is `1`, so contacts are positioned by their direction
+/// from the player's orientation. Each contact has a stalk down to the
+/// player's equatorial plane to show how far above or below the player it
+/// is, and contacts fade out as they get further away
+pub struct Radar {
+    textures: [glium::texture::Texture2d; 3],
+    center: Rc<RefCell<node::Node>>,
+    range: f64,
+    shown: bool,
+    /// Line segments of the wireframe sphere
+    wireframe: Vec<instancing::LineAttributes>,
+    blips: Vec<RadarBlip>,
+    line_vertices: glium::VertexBuffer<VertexPos>,
+    line_indices: glium::IndexBuffer<u8>,
+    lines: instancing::InstanceBuffer<instancing::LineAttributes>,
+    vertices: glium::VertexBuffer<VertexSimple>,
+    indices: glium::IndexBuffer<u8>,
+    instances: instancing::InstanceBuffer<instancing::ParticleAttributes>,
+    instance_pos: instancing::InstanceBuffer<instancing::InstancePosition>,
+}
+
+/// Gets the segments of a circle of radius `radius` parallel to the
+/// equatorial plane at height `y`
+fn latitude(y: f64, radius: f64) -> Vec<instancing::LineAttributes> {
+    circle(|t| point3(radius * t.cos(), y, radius * t.sin()))
+}
+
+/// Gets the segments of a great circle through the poles, rotated `angle`
+/// around the vertical axis
+fn meridian(angle: Rad<f64>) -> Vec<instancing::LineAttributes> {
+    let (sin, cos) = angle.sin_cos();
+    circle(|t| point3(t.cos() * cos, t.sin(), t.cos() * sin))
+}
+
+/// Gets the line segments of the closed curve `pt`, parameterized from `0`
+/// to `2 pi`
+fn circle<F: Fn(f64) -> Point3<f64>>(pt: F) -> Vec<instancing::LineAttributes> {
+    let step = std::f64::consts::PI * 2. / f64::from(CIRCLE_SEGMENTS);
+    (0..CIRCLE_SEGMENTS)
+        .map(|i| {
+            line(
+                pt(f64::from(i) * step),
+                pt(f64::from(i + 1) * step),
+                WIREFRAME_COLOR,
+            )
+        })
+        .collect()
+}
+
+fn line(
+    start: Point3<f64>,
+    end: Point3<f64>,
+    color: [f32; 4],
+) -> instancing::LineAttributes {
+    let (start, end): (Point3<f32>, Point3<f32>) =
+        (start.cast().unwrap(), end.cast().unwrap());
+    instancing::LineAttributes {
+        start_pos: start.to_homogeneous().into(),
+        end_pos: end.to_homogeneous().into(),
+        color,
+    }
+}
+
+impl Radar {
+    /// Creates a new radar centered on `center` that shows contacts up to
+    /// `range` away from `center`
+    pub fn new<F: glium::backend::Facade>(
+        center: Rc<RefCell<node::Node>>,
+        range: f64,
+        facade: &F,
+    ) -> Self {
+        let mut wireframe = latitude(0., 1.);
+        for y in [-0.5, 0.5] {
+            wireframe.append(&mut latitude(y, 0.75f64.sqrt()));
+        }
+        for i in 0..4 {
+            wireframe.append(&mut meridian(Deg(45. * f64::from(i)).into()));
+        }
+        Self {
+            textures: minimap::load_icon_textures(facade),
+            center,
+            range,
+            shown: false,
+            wireframe,
+            blips: Vec::new(),
+            line_vertices: glium::VertexBuffer::immutable(facade, &LINE_VERTS)
+                .unwrap(),
+            line_indices: glium::IndexBuffer::immutable(
+                facade,
+                glium::index::PrimitiveType::LinesList,
+                &LINE_INDICES,
+            )
+            .unwrap(),
+            lines: instancing::InstanceBuffer::new(),
+            vertices: glium::VertexBuffer::immutable(
+                facade,
+                &minimap::RECT_VERTS,
+            )
+            .unwrap(),
+            indices: glium::IndexBuffer::immutable(
+                facade,
+                glium::index::PrimitiveType::TrianglesList,
+                &minimap::RECT_INDICES,
+            )
+            .unwrap(),
+            instances: instancing::InstanceBuffer::new(),
+            instance_pos: instancing::InstanceBuffer::new(),
+        }
+    }
+
+    /// Shows or hides the radar
+    pub fn set_shown(&mut self, shown: bool) {
+        self.shown = shown;
+    }
+
+    /// Gets the transformation from radar space to clip space, which views
+    /// the sphere from behind and slightly above the player
+    fn radar_to_clip() -> Matrix4<f64> {
+        // forward (+z) is into the screen, and depth is kept in [-1, 1]
+        Matrix4::from_nonuniform_scale(SPHERE_SCALE, SPHERE_SCALE, 0.5)
+            * Matrix4::from_angle_x(-TILT)
+    }
+
+    /// Adds `body` to the radar. Does nothing if `body` is out of range or
+    /// should not be shown
+    pub fn add_item(&mut self, body: &physics::RigidBody<object::ObjectData>) {
+        if Rc::ptr_eq(&body.base.transform, &self.center) {
+            return;
+        }
+        if let Some((color, icon)) = minimap::icon_of(body.metadata.0) {
+            self.add_contact(body.base.center(), color, icon);
+        }
+    }
+
+    /// Marks `target` as the locked target. Does nothing if `target` is out
+    /// of range
+    ///
+    /// Should be called after `clear_items`
+    pub fn add_target(&mut self, target: Point3<f64>) {
+        self.add_contact(target, minimap::TARGET_COLOR, Icon::Ring);
+    }
+
+    /// Adds a contact at the world space `pos` if it is in range, fading
+    /// its color by its distance from the center
+    fn add_contact(&mut self, pos: Point3<f64>, color: [f32; 4], icon: Icon) {
+        let center_inv = self.center.borrow().mat().invert().unwrap();
+        let pos = center_inv.transform_point(pos) / self.range;
+        let dist = pos.to_vec().magnitude();
+        if dist > 1. {
+            return;
+        }
+        let alpha = (1. - dist as f32).mul_add(1. - MIN_ALPHA, MIN_ALPHA);
+        self.blips.push(RadarBlip {
+            color: [color[0], color[1], color[2], color[3] * alpha],
+            icon,
+            pos,
+            scale: 0.04,
+        });
+    }
+
+    /// Removes all contacts on the radar
+    pub fn clear_items(&mut self) {
+        self.blips.clear();
+        self.blips.push(RadarBlip {
+            color: minimap::CENTER_COLOR,
+            icon: Icon::Star,
+            pos: point3(0., 0., 0.),
+            scale: 0.06,
+        });
+    }
+}
+
+impl Drawable for Radar {
+    fn render_args<'a>(
+        &'a mut self,
+        _positions: &[[[f32; 4]; 4]],
+    ) -> Vec<(
+        shader::UniformInfo,
+        VertexHolder<'a>,
+        glium::index::IndicesSource<'a>,
+    )> {
+        use glium::*;
+        if self.blips.is_empty() {
+            return Vec::new();
+        };
+        let to_clip = Self::radar_to_clip();
+        let stalks = self.blips.iter().map(|b| {
+            let color = [b.color[0], b.color[1], b.color[2], b.color[3] * 0.6];
+            line(b.pos, point3(b.pos.x, 0., b.pos.z), color)
+        });
+        let lines: Vec<_> =
+            self.wireframe.iter().copied().chain(stalks).collect();
+        let attribs: Vec<_> = self
+            .blips
+            .iter()
+            .map(|b| instancing::ParticleAttributes {
+                color: b.color,
+                tex_idx: b.icon as u32,
+            })
+            .collect();
+        // blips always face the screen, so they're placed in clip space
+        let positions: Vec<[[f32; 4]; 4]> = self
+            .blips
+            .iter()
+            .map(|b| {
+                let pos = to_clip.transform_point(b.pos);
+                (Matrix4::from_translation(pos.to_vec())
+                    * Matrix4::from_scale(b.scale))
+                .cast()
+                .unwrap()
+                .into()
+            })
+            .collect();
+        let positions = instancing::model_mats_to_vertex(&positions);
+
+        {
+            let ctx = crate::graphics_engine::get_active_ctx();
+            let facade = ctx.ctx.borrow();
+            self.lines.update_buffer(&lines, &*facade);
+            self.instances.update_buffer(&attribs, &*facade);
+            self.instance_pos.update_buffer(&positions, &*facade);
+        }
+
+        let line_attribs: vertex::VerticesSource<'a> = From::from(
+            self.lines
+                .get_stored_buffer()
+                .unwrap()
+                .per_instance()
+                .unwrap(),
+        );
+        let inst_attribs: vertex::VerticesSource<'a> = From::from(
+            self.instances
+                .get_stored_buffer()
+                .unwrap()
+                .per_instance()
+                .unwrap(),
+        );
+        let inst_pos: vertex::VerticesSource<'a> = From::from(
+            self.instance_pos
+                .get_stored_buffer()
+                .unwrap()
+                .per_instance()
+                .unwrap(),
+        );
+        vec![
+            (
+                shader::UniformInfo::Radar(to_clip.cast().unwrap().into()),
+                VertexHolder::new(VertexSourceData::Single(From::from(
+                    &self.line_vertices,
+                )))
+                .append(line_attribs),
+                From::from(&self.line_indices),
+            ),
+            (
+                shader::UniformInfo::Minimap(shader::MinimapData {
+                    textures: [
+                        &self.textures[0],
+                        &self.textures[1],
+                        &self.textures[2],
+                    ],
+                }),
+                VertexHolder::new(VertexSourceData::Single(From::from(
+                    &self.vertices,
+                )))
+                .append(inst_attribs)
+                .append(inst_pos),
+                From::from(&self.indices),
+            ),
+        ]
+    }
+
+    fn transparency(&self) -> Option<f32> {
+        None
+    }
+}
+
+impl entity::AbstractEntity for Radar {
+    fn transformations(&self) -> Option<&[Rc<RefCell<dyn Transformation>>]> {
+        None
+    }
+
+    fn drawable(&mut self) -> &mut dyn Drawable {
+        self
+    }
+
+    fn should_render(&self, pass: shader::RenderPassType) -> bool {
+        self.shown && matches!(pass, shader::RenderPassType::Visual)
+    }
+
+    fn render_order(&self) -> entity::RenderOrder {
+        entity::RenderOrder::Unordered
+    }
+
+    fn get_id(&self) -> usize {
+        self as *const _ as usize
+    }
+}
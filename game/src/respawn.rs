@@ -0,0 +1,222 @@
+use crate::collisions::{CollisionObject, CollisionTree};
+use cgmath::*;
+use rand::Rng;
+use std::time::Duration;
+use tracing::warn;
+
+/// Time a destroyed ship waits before it respawns
+pub const RESPAWN_DELAY: Duration = Duration::from_secs(4);
+/// Time after respawning that a ship can't be damaged
+pub const INVULNERABLE_TIME: Duration = Duration::from_secs(3);
+/// Radius of the sphere around a spawn point which must be clear of objects
+pub const SPAWN_CLEARANCE: f64 = 30.;
+/// Minimum and maximum distance from the center of the map that ships
+/// respawn at
+const SPAWN_DIST: (f64, f64) = (150., 500.);
+/// Spawn points tried before settling for one that isn't clear
+const SPAWN_ATTEMPTS: u32 = 32;
+/// Factor the distance between the camera and a destroyed ship is
+/// multiplied by as the death camera pulls away from the wreck
+const DEATH_CAM_PULLBACK: f64 = 2.5;
+/// Angle the death camera orbits around the wreck per second
+const DEATH_CAM_ORBIT_RATE: Deg<f64> = Deg(20.);
+/// Radians per second of the shield shimmer of an invulnerable ship
+const SHIMMER_RATE: f64 = 18.;
+
+/// Whether a ship is in play
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum LifeState {
+    Alive,
+    /// Destroyed and waiting to respawn, with the time left before the ship
+    /// respawns
+    Dead(Duration),
+    /// Recently respawned and can't be damaged, with the time left before
+    /// the ship can be damaged again
+    Invulnerable(Duration),
+    /// Out of play until it's spawned, such as an enemy waiting for its
+    /// wave. Benched ships don't respawn
+    Benched,
+}
+
+impl LifeState {
+    /// Advances the state by `dt`. Dead ships respawn once their respawn
+    /// delay is up, and are invulnerable for a while afterwards
+    ///
+    /// Returns `true` if the ship respawned
+    pub fn update(&mut self, dt: Duration) -> bool {
+        match *self {
+            Self::Dead(remaining) if remaining <= dt => {
+                *self = Self::Invulnerable(INVULNERABLE_TIME);
+                true
+            }
+            Self::Dead(remaining) => {
+                *self = Self::Dead(remaining - dt);
+                false
+            }
+            Self::Invulnerable(remaining) if remaining <= dt => {
+                *self = Self::Alive;
+                false
+            }
+            Self::Invulnerable(remaining) => {
+                *self = Self::Invulnerable(remaining - dt);
+                false
+            }
+            Self::Alive | Self::Benched => false,
+        }
+    }
+
+    /// `true` if the ship is out of play
+    pub const fn is_dead(self) -> bool {
+        matches!(self, Self::Dead(_) | Self::Benched)
+    }
+
+    pub const fn can_take_damage(self) -> bool {
+        matches!(self, Self::Alive)
+    }
+
+    /// Gets the brightness of the shield shimmer, from `0` to `1`, or `None`
+    /// if the ship isn't invulnerable
+    pub fn shimmer(self) -> Option<f64> {
+        match self {
+            Self::Invulnerable(remaining) => Some(
+                (remaining.as_secs_f64() * SHIMMER_RATE)
+                    .sin()
+                    .mul_add(0.5, 0.5),
+            ),
+            _ => None,
+        }
+    }
+}
+
+/// A camera which slowly pulls away from and orbits around the wreck of a
+/// destroyed ship
+pub struct DeathCamera {
+    wreck: Point3<f64>,
+    /// Position of the camera relative to the wreck when it was destroyed
+    offset: Vector3<f64>,
+    /// Time since the ship was destroyed
+    elapsed: Duration,
+}
+
+impl DeathCamera {
+    /// Creates a camera looking at the wreck at `wreck` from `cam_pos`, the
+    /// position of the camera when the ship was destroyed
+    pub fn new(wreck: Point3<f64>, cam_pos: Point3<f64>) -> Self {
+        let offset = cam_pos - wreck;
+        Self {
+            wreck,
+            offset: if offset.magnitude2() > f64::EPSILON {
+                offset
+            } else {
+                vec3(0., 15., -25.)
+            },
+            elapsed: Duration::default(),
+        }
+    }
+
+    pub fn update(&mut self, dt: Duration) {
+        self.elapsed += dt;
+    }
+
+    /// Gets the position of the camera
+    pub fn pos(&self) -> Point3<f64> {
+        let t =
+            (self.elapsed.as_secs_f64() / RESPAWN_DELAY.as_secs_f64()).min(1.);
+        let pullback = (DEATH_CAM_PULLBACK - 1.).mul_add(t, 1.);
+        let orbit = Basis3::from_angle_y(
+            DEATH_CAM_ORBIT_RATE * self.elapsed.as_secs_f64(),
+        );
+        self.wreck + orbit.rotate_vector(self.offset * pullback)
+    }
+
+    /// Gets the point the camera looks at
+    pub const fn target(&self) -> Point3<f64> {
+        self.wreck
+    }
+}
+
+/// `true` if no object in `tree` other than the objects in `ignore` has a
+/// bounding sphere within `radius` of `center`
+pub fn is_clear(
+    tree: &CollisionTree,
+    center: Point3<f64>,
+    radius: f64,
+    ignore: &[&CollisionObject],
+) -> bool {
+    tree.test_for_collisions(center, radius).iter().all(|obj| {
+        let (obj_center, obj_radius) = obj.bounding_sphere();
+        ignore.iter().any(|ignored| *ignored == obj)
+            || obj_center.distance(center) > obj_radius + radius
+    })
+}
+
+/// Finds a point to respawn a ship at which is clear of every object in
+/// `tree` other than the objects in `ignore` for at least `clearance`. If
+/// no clear point is found, the last point tried is used
+pub fn find_spawn_point<R: Rng>(
+    tree: &CollisionTree,
+    clearance: f64,
+    ignore: &[&CollisionObject],
+    rng: &mut R,
+) -> Point3<f64> {
+    let mut point = point3(SPAWN_DIST.1, 0., 0.);
+    for _ in 0..SPAWN_ATTEMPTS {
+        let (theta, z) = (
+            rng.gen_range(0. ..std::f64::consts::TAU),
+            rng.gen_range(-1. ..1_f64),
+        );
+        let r = z.mul_add(-z, 1.).sqrt();
+        let dir = vec3(r * theta.cos(), r * theta.sin(), z);
+        point =
+            Point3::from_vec(dir * rng.gen_range(SPAWN_DIST.0..SPAWN_DIST.1));
+        if is_clear(tree, point, clearance, ignore) {
+            return point;
+        }
+    }
+    warn!("No clear spawn point found");
+    point
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn dead_ships_respawn_invulnerable() {
+        let second = Duration::from_secs(1);
+        let mut life = LifeState::Dead(RESPAWN_DELAY);
+        assert!(!life.can_take_damage());
+        assert!(!life.update(RESPAWN_DELAY - second));
+        assert!(life.is_dead());
+        assert!(life.shimmer().is_none());
+        assert!(life.update(second));
+        assert_eq!(life, LifeState::Invulnerable(INVULNERABLE_TIME));
+        assert!(!life.can_take_damage());
+        let shimmer = life.shimmer().unwrap();
+        assert!((0. ..=1.).contains(&shimmer));
+        assert!(!life.update(INVULNERABLE_TIME));
+        assert_eq!(life, LifeState::Alive);
+        assert!(life.can_take_damage());
+        assert!(!life.update(second));
+
+        let mut life = LifeState::Benched;
+        assert!(!life.update(RESPAWN_DELAY));
+        assert!(life.is_dead());
+    }
+
+    #[test]
+    fn death_camera_pulls_back_around_wreck() {
+        let wreck = point3(10., 0., 0.);
+        let mut cam = DeathCamera::new(wreck, point3(10., 0., -20.));
+        assert_approx_eq!(cam.pos().distance(wreck), 20.);
+        cam.update(RESPAWN_DELAY);
+        assert_approx_eq!(cam.pos().distance(wreck), 20. * DEATH_CAM_PULLBACK);
+        // the camera orbits in the horizontal plane
+        assert_approx_eq!(cam.pos().y, 0.);
+        assert!(cam.pos().z > -20. * DEATH_CAM_PULLBACK);
+        cam.update(RESPAWN_DELAY);
+        assert_approx_eq!(cam.pos().distance(wreck), 20. * DEATH_CAM_PULLBACK);
+        assert_eq!(cam.target(), wreck);
+    }
+}
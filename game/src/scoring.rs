@@ -0,0 +1,392 @@
+use crate::object::{ObjectId, ObjectType};
+use shared_types::save::ScoreState;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt::Display;
+use std::time::Duration;
+use tracing::warn;
+
+/// Default location of the leaderboard file
+pub const LEADERBOARD_PATH: &str = "leaderboard.txt";
+/// Number of scores kept on the leaderboard
+const LEADERBOARD_SIZE: usize = 10;
+/// Maximum time between kills for them to be part of the same combo
+const COMBO_WINDOW: Duration = Duration::from_secs(3);
+/// Largest factor the points of a kill can be multiplied by
+const MAX_MULTIPLIER: u32 = 5;
+
+/// Gets the points awarded for destroying an object of type `typ`, before
+/// the combo multiplier is applied
+pub const fn kill_points(typ: ObjectType) -> u64 {
+    match typ {
+        ObjectType::Asteroid => 100,
+        ObjectType::Ship => 500,
+        _ => 0,
+    }
+}
+
+/// Statistics of player 1 over a session
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct SessionStats {
+    pub shots_fired: u32,
+    /// Shots that damaged something
+    pub shots_hit: u32,
+    pub damage_dealt: f64,
+    pub damage_taken: f64,
+    pub time_alive: Duration,
+    pub asteroids_destroyed: u32,
+    pub ships_destroyed: u32,
+}
+
+impl SessionStats {
+    /// Gets the fraction of shots that hit, or `0` if no shots were fired
+    pub fn accuracy(&self) -> f64 {
+        if self.shots_fired == 0 {
+            0.
+        } else {
+            f64::from(self.shots_hit) / f64::from(self.shots_fired)
+        }
+    }
+}
+
+/// Tracks the points and statistics of player 1 over a session
+///
+/// Kills made within the combo window of the previous kill build up a
+/// combo, and the points of each kill are multiplied by the length of the
+/// combo
+#[derive(Default)]
+pub struct Score {
+    points: u64,
+    /// Kills in the current combo
+    combo: u32,
+    /// Time since the last kill
+    since_kill: Duration,
+    stats: SessionStats,
+    /// Ids of the lasers fired by player 1 that haven't hit anything
+    lasers: HashSet<ObjectId>,
+}
+
+impl Score {
+    /// Clears the points and statistics for a new session
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    pub const fn points(&self) -> u64 {
+        self.points
+    }
+
+    pub const fn stats(&self) -> &SessionStats {
+        &self.stats
+    }
+
+    /// Gets the points and statistics to save with the game
+    pub const fn save_state(&self) -> ScoreState {
+        ScoreState {
+            points: self.points,
+            shots_fired: self.stats.shots_fired,
+            shots_hit: self.stats.shots_hit,
+            damage_dealt: self.stats.damage_dealt,
+            damage_taken: self.stats.damage_taken,
+            time_alive: self.stats.time_alive,
+            asteroids_destroyed: self.stats.asteroids_destroyed,
+            ships_destroyed: self.stats.ships_destroyed,
+        }
+    }
+
+    /// Restores the points and statistics of a saved game. The combo isn't
+    /// saved, so it starts over
+    pub fn load_state(&mut self, state: &ScoreState) {
+        *self = Self {
+            points: state.points,
+            stats: SessionStats {
+                shots_fired: state.shots_fired,
+                shots_hit: state.shots_hit,
+                damage_dealt: state.damage_dealt,
+                damage_taken: state.damage_taken,
+                time_alive: state.time_alive,
+                asteroids_destroyed: state.asteroids_destroyed,
+                ships_destroyed: state.ships_destroyed,
+            },
+            ..Self::default()
+        };
+    }
+
+    /// Gets the factor the points of the current combo are multiplied by
+    pub fn multiplier(&self) -> u32 {
+        self.combo.clamp(1, MAX_MULTIPLIER)
+    }
+
+    /// Records player 1 firing the laser with the id `laser`
+    pub fn record_shot(&mut self, laser: ObjectId) {
+        self.stats.shots_fired += 1;
+        self.lasers.insert(laser);
+    }
+
+    /// Records the laser with the id `laser` damaging an object
+    ///
+    /// Returns `true` if the laser was fired by player 1 and hadn't already
+    /// hit something
+    pub fn record_hit(&mut self, laser: ObjectId) -> bool {
+        let hit = self.lasers.remove(&laser);
+        if hit {
+            self.stats.shots_hit += 1;
+        }
+        hit
+    }
+
+    /// Records player 1 dealing `amount` damage
+    pub fn record_damage_dealt(&mut self, amount: f64) {
+        self.stats.damage_dealt += amount;
+    }
+
+    /// Records player 1 taking `amount` damage
+    pub fn record_damage_taken(&mut self, amount: f64) {
+        self.stats.damage_taken += amount;
+    }
+
+    /// Records player 1 destroying an object of type `typ`, extending the
+    /// combo if the kill was made within the combo window
+    ///
+    /// Returns the points awarded for the kill
+    pub fn record_kill(&mut self, typ: ObjectType) -> u64 {
+        match typ {
+            ObjectType::Asteroid => self.stats.asteroids_destroyed += 1,
+            ObjectType::Ship => self.stats.ships_destroyed += 1,
+            _ => (),
+        }
+        if self.combo > 0 && self.since_kill <= COMBO_WINDOW {
+            self.combo += 1;
+        } else {
+            self.combo = 1;
+        }
+        self.since_kill = Duration::default();
+        let points = kill_points(typ) * u64::from(self.multiplier());
+        self.points += points;
+        points
+    }
+
+    /// Advances the session by `dt`, ending the combo once the combo window
+    /// has passed without a kill
+    pub fn update(&mut self, dt: Duration) {
+        self.stats.time_alive += dt;
+        self.since_kill += dt;
+        if self.since_kill > COMBO_WINDOW {
+            self.combo = 0;
+        }
+    }
+
+    /// Gets the text showing the points and the multiplier of the current
+    /// combo, if there is one
+    pub fn hud_text(&self) -> String {
+        if self.combo > 1 {
+            format!("{} (x{})", self.points, self.multiplier())
+        } else {
+            format!("{}", self.points)
+        }
+    }
+
+    /// Gets the leaderboard entry for the session
+    pub fn entry(&self) -> LeaderboardEntry {
+        LeaderboardEntry {
+            points: self.points,
+            accuracy: self.stats.accuracy(),
+            time_alive: self.stats.time_alive,
+        }
+    }
+}
+
+/// A session's result on the leaderboard
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct LeaderboardEntry {
+    pub points: u64,
+    /// Fraction of shots that hit
+    pub accuracy: f64,
+    pub time_alive: Duration,
+}
+
+/// The best scores of every session played locally, from best to worst
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Leaderboard {
+    entries: Vec<LeaderboardEntry>,
+}
+
+impl Leaderboard {
+    /// Parses a leaderboard from the contents of a leaderboard file
+    ///
+    /// The file has a line for every entry holding its points, accuracy, and
+    /// seconds alive, separated by spaces. Empty lines and lines beginning
+    /// with `#` are ignored
+    ///
+    /// # Errors
+    /// Fails if a line is malformed or a value cannot be parsed
+    pub fn parse(leaderboard: &str) -> Result<Self, Box<dyn Error>> {
+        let mut board = Self::default();
+        for line in leaderboard
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        {
+            let fields: Vec<_> = line.split_whitespace().collect();
+            if let [points, accuracy, time] = fields.as_slice() {
+                board.insert(LeaderboardEntry {
+                    points: points.parse()?,
+                    accuracy: accuracy.parse()?,
+                    time_alive: Duration::from_secs_f64(time.parse()?),
+                });
+            } else {
+                return Err(format!(
+                    "Malformed leaderboard entry: \"{}\"",
+                    line
+                )
+                .into());
+            }
+        }
+        Ok(board)
+    }
+
+    /// Loads the leaderboard from the file at `path`
+    ///
+    /// # Errors
+    /// Fails if the file cannot be read or parsed
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn Error>> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Loads the leaderboard from `path`, or an empty leaderboard if the file
+    /// does not exist or cannot be parsed
+    pub fn load_or_default(path: &str) -> Self {
+        Self::from_file(path).unwrap_or_else(|e| {
+            warn!("Starting a new leaderboard: {}", e);
+            Self::default()
+        })
+    }
+
+    /// Saves the leaderboard to `path`
+    ///
+    /// # Errors
+    /// Fails if the file cannot be written
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        std::fs::write(path, self.to_string())
+    }
+
+    #[allow(dead_code)]
+    pub fn entries(&self) -> &[LeaderboardEntry] {
+        &self.entries
+    }
+
+    /// Adds `entry` to the leaderboard, dropping the worst entry if the
+    /// leaderboard is full. Entries with equal points are ranked by when
+    /// they were added
+    ///
+    /// Returns the rank of the entry, starting at `0`, or `None` if it
+    /// didn't make the leaderboard
+    pub fn insert(&mut self, entry: LeaderboardEntry) -> Option<usize> {
+        let rank = self
+            .entries
+            .iter()
+            .position(|e| e.points < entry.points)
+            .unwrap_or(self.entries.len());
+        if rank >= LEADERBOARD_SIZE {
+            return None;
+        }
+        self.entries.insert(rank, entry);
+        self.entries.truncate(LEADERBOARD_SIZE);
+        Some(rank)
+    }
+}
+
+impl Display for Leaderboard {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "# Oort leaderboard\n# points accuracy seconds_alive")?;
+        for entry in &self.entries {
+            writeln!(
+                f,
+                "{} {} {}",
+                entry.points,
+                entry.accuracy,
+                entry.time_alive.as_secs_f64()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn rapid_kills_build_combo() {
+        let mut score = Score::default();
+        assert_eq!(score.record_kill(ObjectType::Asteroid), 100);
+        score.update(Duration::from_secs(1));
+        assert_eq!(score.record_kill(ObjectType::Asteroid), 200);
+        assert_eq!(score.record_kill(ObjectType::Ship), 1500);
+        assert_eq!(score.hud_text(), "1800 (x3)");
+        // the combo ends once the window passes without a kill
+        score.update(Duration::from_secs(4));
+        assert_eq!(score.multiplier(), 1);
+        assert_eq!(score.record_kill(ObjectType::Asteroid), 100);
+        assert_eq!(score.points(), 1900);
+        assert_eq!(score.stats().asteroids_destroyed, 3);
+        assert_eq!(score.stats().ships_destroyed, 1);
+        for _ in 0..10 {
+            score.record_kill(ObjectType::Asteroid);
+        }
+        assert_eq!(score.multiplier(), MAX_MULTIPLIER);
+    }
+
+    #[test]
+    fn shots_track_accuracy() {
+        let mut score = Score::default();
+        assert_approx_eq!(score.stats().accuracy(), 0.);
+        for id in 0..4 {
+            score.record_shot(ObjectId::new(id));
+        }
+        assert!(score.record_hit(ObjectId::new(1)));
+        // a laser only hits once, and other lasers weren't fired by player 1
+        assert!(!score.record_hit(ObjectId::new(1)));
+        assert!(!score.record_hit(ObjectId::new(9)));
+        assert_approx_eq!(score.stats().accuracy(), 0.25);
+    }
+
+    #[test]
+    fn loaded_score_keeps_stats_but_not_combo() {
+        let mut score = Score::default();
+        score.record_shot(ObjectId::new(0));
+        score.record_kill(ObjectType::Asteroid);
+        score.record_kill(ObjectType::Asteroid);
+        score.update(Duration::from_secs(2));
+        let mut loaded = Score::default();
+        loaded.load_state(&score.save_state());
+        assert_eq!(loaded.points(), score.points());
+        assert_eq!(loaded.stats(), score.stats());
+        assert_eq!(loaded.multiplier(), 1);
+        // lasers fired before the save can't hit anything after it
+        assert!(!loaded.record_hit(ObjectId::new(0)));
+    }
+
+    #[test]
+    fn leaderboard_keeps_best_scores() {
+        let entry = |points| LeaderboardEntry {
+            points,
+            accuracy: 0.5,
+            time_alive: Duration::from_millis(62_500),
+        };
+        let mut board = Leaderboard::default();
+        for points in 1..=LEADERBOARD_SIZE as u64 {
+            board.insert(entry(points * 100));
+        }
+        assert_eq!(board.entries()[0].points, 1000);
+        assert_eq!(board.insert(entry(50)), None);
+        assert_eq!(board.insert(entry(950)), Some(1));
+        assert_eq!(board.entries().len(), LEADERBOARD_SIZE);
+        assert_eq!(board.entries().last().unwrap().points, 200);
+        let parsed = Leaderboard::parse(&board.to_string()).unwrap();
+        assert_eq!(board, parsed);
+        assert!(Leaderboard::parse("100 0.5").is_err());
+        assert!(Leaderboard::parse("lots 0.5 10").is_err());
+    }
+}
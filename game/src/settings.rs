@@ -0,0 +1,387 @@
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt::Display;
+use tracing::warn;
+
+/// Default location of the graphics settings file
+pub const SETTINGS_PATH: &str = "graphics.cfg";
+
+/// Quality presets for the graphics settings
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Quality {
+    Low,
+    Medium,
+    High,
+}
+
+impl Quality {
+    /// Gets the next higher preset, wrapping around to `Low` after `High`
+    pub const fn next(self) -> Self {
+        match self {
+            Self::Low => Self::Medium,
+            Self::Medium => Self::High,
+            Self::High => Self::Low,
+        }
+    }
+}
+
+impl TryFrom<&str> for Quality {
+    type Error = String;
+    fn try_from(val: &str) -> Result<Self, Self::Error> {
+        match val.to_lowercase().as_str() {
+            "low" => Ok(Self::Low),
+            "medium" => Ok(Self::Medium),
+            "high" => Ok(Self::High),
+            _ => Err(format!("Invalid quality preset: {}", val)),
+        }
+    }
+}
+
+impl Display for Quality {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Low => write!(f, "low"),
+            Self::Medium => write!(f, "medium"),
+            Self::High => write!(f, "high"),
+        }
+    }
+}
+
+/// How the lights affecting each fragment are found
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum LightCulling {
+    /// Lights are culled per screen tile, against the depth range of the
+    /// tile (forward+)
+    Tiled,
+    /// Lights are assigned to clusters which split each screen tile into
+    /// depth slices, so a fragment only shades the lights near its depth
+    Clustered,
+}
+
+impl TryFrom<&str> for LightCulling {
+    type Error = String;
+    fn try_from(val: &str) -> Result<Self, Self::Error> {
+        match val.to_lowercase().as_str() {
+            "tiled" => Ok(Self::Tiled),
+            "clustered" => Ok(Self::Clustered),
+            _ => Err(format!("Invalid light culling: {}", val)),
+        }
+    }
+}
+
+impl Display for LightCulling {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Tiled => write!(f, "tiled"),
+            Self::Clustered => write!(f, "clustered"),
+        }
+    }
+}
+
+/// Settings which control the quality and cost of rendering
+#[derive(Clone, PartialEq, Debug)]
+pub struct GraphicsSettings {
+    /// The preset the settings were derived from
+    pub quality: Quality,
+    /// Width of the main render targets
+    pub render_width: u32,
+    /// Height of the main render targets
+    pub render_height: u32,
+    /// Samples of the main multisampled render target
+    pub msaa_samples: u32,
+    /// Width and height of each shadow cascade
+    pub shadow_map_size: u32,
+    /// Amount of separable convolution iterations used for bloom
+    pub bloom_iterations: usize,
+    /// Size of each face of the translucency cubemap
+    pub translucency_map_size: u32,
+    /// Fraction of `translucency_map_size` the translucency cubemap is
+    /// rendered at
+    pub translucency_scale: f32,
+    /// Frames between renders of the translucency cubemap, or the most
+    /// frames a face goes without being rendered if faces are only rendered
+    /// when they change
+    pub translucency_refresh: u32,
+    /// `true` if a face of the translucency cubemap is only rendered when
+    /// the bodies it can see change
+    pub translucency_on_change: bool,
+    /// Fraction of the particles of each effect which are emitted
+    pub particle_density: f32,
+    /// How the lights affecting each fragment are found
+    pub light_culling: LightCulling,
+    /// Distance from the camera that shadows are rendered within
+    pub shadow_distance: f32,
+    /// Weight of the logarithmic splits of the shadow cascades, from `0`
+    /// for cascades of equal length to `1` for cascades of equal texel
+    /// density on screen
+    pub cascade_split_lambda: f32,
+    /// Strength of the camera shake and kicks, from `0` for none
+    pub screen_shake: f32,
+    /// Degrees the field of view widens by at high speed
+    pub speed_fov: f32,
+    /// Frame rate the quality is lowered to hold, or `0` to always render
+    /// at the chosen quality
+    pub target_fps: u32,
+}
+
+impl GraphicsSettings {
+    /// Gets the settings for a quality preset at the given resolution
+    pub const fn preset(quality: Quality, width: u32, height: u32) -> Self {
+        let (msaa_samples, shadow_map_size, bloom_iterations, trans_size) =
+            match quality {
+                Quality::Low => (2, 1024, 4, 256),
+                Quality::Medium => (4, 2048, 6, 512),
+                Quality::High => (8, 2048, 10, 1024),
+            };
+        let (translucency_refresh, particle_density) = match quality {
+            Quality::Low => (2, 0.5),
+            Quality::Medium | Quality::High => (1, 1.),
+        };
+        Self {
+            quality,
+            render_width: width,
+            render_height: height,
+            msaa_samples,
+            shadow_map_size,
+            bloom_iterations,
+            translucency_map_size: trans_size,
+            translucency_scale: 1.,
+            translucency_refresh,
+            translucency_on_change: false,
+            particle_density,
+            light_culling: LightCulling::Tiled,
+            shadow_distance: 600.,
+            cascade_split_lambda: 0.75,
+            screen_shake: 1.,
+            speed_fov: 10.,
+            target_fps: 0,
+        }
+    }
+
+    /// Parses settings from the contents of a config file
+    ///
+    /// The config file is a list of `key = value` lines. Empty lines and lines
+    /// beginning with `#` are ignored. If a `quality` key is specified, it is
+    /// applied before any other keys, so individual settings can override
+    /// the preset
+    ///
+    /// # Errors
+    /// Fails if there is an unknown key, a line is malformed, or a value
+    /// cannot be parsed
+    pub fn parse(config: &str) -> Result<Self, Box<dyn Error>> {
+        let pairs = config
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                line.split_once('=')
+                    .map(|(k, v)| (k.trim(), v.trim()))
+                    .ok_or_else(|| format!("Malformed setting: \"{}\"", line))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut settings = Self::default();
+        if let Some((_, quality)) = pairs.iter().find(|(k, _)| *k == "quality")
+        {
+            settings = Self::preset(
+                Quality::try_from(*quality)?,
+                settings.render_width,
+                settings.render_height,
+            );
+        }
+        for (key, val) in pairs {
+            match key {
+                "quality" => (),
+                "width" => settings.render_width = val.parse()?,
+                "height" => settings.render_height = val.parse()?,
+                "msaa" => settings.msaa_samples = val.parse()?,
+                "shadow_map_size" => settings.shadow_map_size = val.parse()?,
+                "bloom_iterations" => {
+                    settings.bloom_iterations = val.parse()?
+                }
+                "translucency_map_size" => {
+                    settings.translucency_map_size = val.parse()?;
+                }
+                "translucency_scale" => {
+                    settings.translucency_scale = val.parse()?;
+                }
+                "translucency_refresh" => {
+                    settings.translucency_refresh = val.parse()?;
+                }
+                "translucency_on_change" => {
+                    settings.translucency_on_change = val.parse()?;
+                }
+                "particle_density" => {
+                    settings.particle_density = val.parse()?;
+                }
+                "light_culling" => {
+                    settings.light_culling = LightCulling::try_from(val)?;
+                }
+                "shadow_distance" => settings.shadow_distance = val.parse()?,
+                "cascade_split_lambda" => {
+                    settings.cascade_split_lambda = val.parse()?;
+                }
+                "screen_shake" => settings.screen_shake = val.parse()?,
+                "speed_fov" => settings.speed_fov = val.parse()?,
+                "target_fps" => settings.target_fps = val.parse()?,
+                _ => return Err(format!("Unknown setting \"{}\"", key).into()),
+            }
+        }
+        Ok(settings)
+    }
+
+    /// Loads the settings from the file at `path`
+    ///
+    /// # Errors
+    /// Fails if the file cannot be read or parsed
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn Error>> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Loads the settings from `path`, or the default settings if the file
+    /// does not exist or cannot be parsed
+    pub fn load_or_default(path: &str) -> Self {
+        Self::from_file(path).unwrap_or_else(|e| {
+            warn!("Using default graphics settings: {}", e);
+            Self::default()
+        })
+    }
+
+    /// Saves the settings to `path`
+    ///
+    /// # Errors
+    /// Fails if the file cannot be written
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        std::fs::write(path, self.to_string())
+    }
+
+    /// Changes the quality preset, keeping the current resolution and the
+    /// settings which don't depend on the quality
+    #[must_use]
+    pub const fn with_quality(&self, quality: Quality) -> Self {
+        Self {
+            light_culling: self.light_culling,
+            shadow_distance: self.shadow_distance,
+            cascade_split_lambda: self.cascade_split_lambda,
+            screen_shake: self.screen_shake,
+            speed_fov: self.speed_fov,
+            translucency_on_change: self.translucency_on_change,
+            target_fps: self.target_fps,
+            ..Self::preset(quality, self.render_width, self.render_height)
+        }
+    }
+}
+
+impl Default for GraphicsSettings {
+    fn default() -> Self {
+        Self::preset(Quality::High, 1920, 1080)
+    }
+}
+
+impl Display for GraphicsSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "# Graphics settings\n\
+            quality = {}\n\
+            width = {}\n\
+            height = {}\n\
+            msaa = {}\n\
+            shadow_map_size = {}\n\
+            bloom_iterations = {}\n\
+            translucency_map_size = {}\n\
+            translucency_scale = {}\n\
+            translucency_refresh = {}\n\
+            translucency_on_change = {}\n\
+            particle_density = {}\n\
+            light_culling = {}\n\
+            shadow_distance = {}\n\
+            cascade_split_lambda = {}\n\
+            screen_shake = {}\n\
+            speed_fov = {}\n\
+            target_fps = {}\n",
+            self.quality,
+            self.render_width,
+            self.render_height,
+            self.msaa_samples,
+            self.shadow_map_size,
+            self.bloom_iterations,
+            self.translucency_map_size,
+            self.translucency_scale,
+            self.translucency_refresh,
+            self.translucency_on_change,
+            self.particle_density,
+            self.light_culling,
+            self.shadow_distance,
+            self.cascade_split_lambda,
+            self.screen_shake,
+            self.speed_fov,
+            self.target_fps
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn settings_round_trip() {
+        let settings = GraphicsSettings {
+            light_culling: LightCulling::Clustered,
+            cascade_split_lambda: 0.6,
+            screen_shake: 0.5,
+            translucency_scale: 0.5,
+            translucency_on_change: true,
+            particle_density: 0.25,
+            target_fps: 60,
+            ..GraphicsSettings::preset(Quality::Medium, 800, 600)
+        };
+        let parsed = GraphicsSettings::parse(&settings.to_string()).unwrap();
+        assert_eq!(settings, parsed);
+    }
+
+    #[test]
+    fn settings_override_preset() {
+        let settings = GraphicsSettings::parse(
+            "# comment\n\
+            msaa = 8\n\
+            \n\
+            quality = low\n\
+            bloom_iterations = 12\n\
+            light_culling = clustered",
+        )
+        .unwrap();
+        assert_eq!(settings.quality, Quality::Low);
+        assert_eq!(settings.msaa_samples, 8);
+        assert_eq!(settings.bloom_iterations, 12);
+        assert_eq!(settings.shadow_map_size, 1024);
+        assert_eq!(settings.light_culling, LightCulling::Clustered);
+    }
+
+    #[test]
+    fn settings_invalid() {
+        assert!(GraphicsSettings::parse("msaa 4").is_err());
+        assert!(GraphicsSettings::parse("msaa = four").is_err());
+        assert!(GraphicsSettings::parse("fov = 90").is_err());
+        assert!(GraphicsSettings::parse("quality = ultra").is_err());
+        assert!(GraphicsSettings::parse("light_culling = deferred").is_err());
+    }
+
+    #[test]
+    fn quality_keeps_other_settings() {
+        let settings = GraphicsSettings::parse(
+            "light_culling = clustered\n\
+            shadow_distance = 300\n\
+            speed_fov = 0\n\
+            target_fps = 30",
+        )
+        .unwrap()
+        .with_quality(Quality::Low);
+        assert_eq!(settings.msaa_samples, 2);
+        assert_eq!(settings.light_culling, LightCulling::Clustered);
+        assert_approx_eq!(settings.shadow_distance, 300.);
+        assert_approx_eq!(settings.speed_fov, 0.);
+        assert_eq!(settings.target_fps, 30);
+    }
+}
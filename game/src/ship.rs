@@ -0,0 +1,302 @@
+use crate::damage::Armor;
+use cgmath::*;
+use std::convert::TryInto;
+use std::error::Error;
+use std::time::Duration;
+use tracing::warn;
+
+/// Directory the ship definitions are loaded from
+pub const SHIPS_DIR: &str = "assets/Ships";
+/// Extension of ship definition files
+const SHIP_EXT: &str = "ship";
+
+/// The model, flight model, stats, and hardpoints of a kind of ship
+#[derive(Clone, PartialEq, Debug)]
+pub struct ShipDef {
+    /// Name of the ship shown on the ship select screen
+    pub name: String,
+    /// Path of the ship's model
+    pub model: String,
+    /// Path of the ship's collision mesh
+    pub collision: String,
+    pub mass: f64,
+    /// Factor the moment of inertia computed from the collision mesh is
+    /// scaled by
+    pub inertia: f64,
+    /// Momentum the engines add to the ship each update at full power
+    pub thrust: f64,
+    /// Angular velocity of the ship per unit of pitch and roll input
+    pub turn_rate: f64,
+    /// Most energy the ship can store
+    pub energy: f64,
+    /// Energy recharged per second
+    pub energy_recharge: f64,
+    pub armor: Armor,
+    /// Points, in the ship's local space, that the ship's lasers are fired
+    /// from in turn
+    pub guns: Vec<Point3<f64>>,
+    /// Point, in the ship's local space, that the tractor beam is emitted
+    /// from
+    pub beam: Point3<f64>,
+    /// Point, in the ship's local space, that the engine trail is emitted
+    /// from
+    pub engine: Point3<f64>,
+    /// The maneuvering thrusters that fire as the ship turns and thrusts
+    pub thrusters: Vec<ThrusterDef>,
+}
+
+/// A maneuvering thruster of a ship
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct ThrusterDef {
+    /// Point, in the ship's local space, of the thruster's nozzle
+    pub pos: Point3<f64>,
+    /// Normalized direction, in the ship's local space, that the exhaust
+    /// leaves the nozzle in. The thruster pushes the ship the opposite way
+    pub exhaust: Vector3<f64>,
+}
+
+impl ThrusterDef {
+    const fn new(pos: Point3<f64>, exhaust: Vector3<f64>) -> Self {
+        Self { pos, exhaust }
+    }
+}
+
+/// Parses `N` numbers separated by whitespace
+fn parse_numbers<const N: usize>(
+    val: &str,
+) -> Result<[f64; N], Box<dyn Error>> {
+    let nums = val
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<Vec<f64>, _>>()?;
+    nums.try_into()
+        .map_err(|_| format!("Expected {} numbers: \"{}\"", N, val).into())
+}
+
+/// Parses a point given as `x y z`
+fn parse_point(val: &str) -> Result<Point3<f64>, Box<dyn Error>> {
+    parse_numbers(val).map(Point3::from)
+}
+
+/// Parses a thruster given as `x y z dx dy dz`, the position of its nozzle
+/// followed by the direction of its exhaust
+fn parse_thruster(val: &str) -> Result<ThrusterDef, Box<dyn Error>> {
+    let [x, y, z, dx, dy, dz] = parse_numbers(val)?;
+    let exhaust = vec3(dx, dy, dz);
+    if exhaust.magnitude2() <= f64::EPSILON {
+        return Err(format!("Thruster has no exhaust: \"{}\"", val).into());
+    }
+    Ok(ThrusterDef::new(point3(x, y, z), exhaust.normalize()))
+}
+
+impl ShipDef {
+    /// Parses a ship from the contents of a ship definition file
+    ///
+    /// The file is a list of `key = value` lines. Empty lines and lines
+    /// beginning with `#` are ignored. Keys that aren't given keep the
+    /// values of the default ship. The keys are:
+    ///
+    /// * `name`, `model`, `collision` - the name of the ship and the paths
+    /// of its model and collision mesh
+    /// * `mass`, `inertia`, `thrust`, `turn_rate` - the flight model
+    /// * `energy`, `energy_recharge` - the energy capacity and recharge rate
+    /// * `shield`, `hull` - the most shield and hull points
+    /// * `shield_recharge`, `shield_delay` - the shield points recharged per
+    /// second, and the seconds without damage before the shield recharges
+    /// * `gun` - a laser hardpoint, as `x y z`. May be repeated
+    /// * `beam`, `engine` - the tractor beam and engine hardpoints, as
+    /// `x y z`
+    /// * `thruster` - a maneuvering thruster, as `x y z dx dy dz` where
+    /// `dx dy dz` is the direction of its exhaust. May be repeated
+    ///
+    /// # Errors
+    /// Fails if there is an unknown key, a line is malformed, a value cannot
+    /// be parsed, or the mass isn't positive
+    pub fn parse(def: &str) -> Result<Self, Box<dyn Error>> {
+        let mut ship = Self::default();
+        let mut guns = Vec::new();
+        let mut thrusters = Vec::new();
+        for line in def
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        {
+            let (key, val) = line
+                .split_once('=')
+                .map(|(k, v)| (k.trim(), v.trim()))
+                .ok_or_else(|| format!("Malformed ship line: \"{}\"", line))?;
+            match key {
+                "name" => ship.name = val.to_owned(),
+                "model" => ship.model = val.to_owned(),
+                "collision" => ship.collision = val.to_owned(),
+                "mass" => ship.mass = val.parse()?,
+                "inertia" => ship.inertia = val.parse()?,
+                "thrust" => ship.thrust = val.parse()?,
+                "turn_rate" => ship.turn_rate = val.parse()?,
+                "energy" => ship.energy = val.parse()?,
+                "energy_recharge" => ship.energy_recharge = val.parse()?,
+                "shield" => ship.armor.max_shield = val.parse()?,
+                "hull" => ship.armor.max_hull = val.parse()?,
+                "shield_recharge" => ship.armor.recharge_rate = val.parse()?,
+                "shield_delay" => {
+                    ship.armor.recharge_delay =
+                        Duration::from_secs_f64(val.parse()?);
+                }
+                "gun" => guns.push(parse_point(val)?),
+                "beam" => ship.beam = parse_point(val)?,
+                "engine" => ship.engine = parse_point(val)?,
+                "thruster" => thrusters.push(parse_thruster(val)?),
+                _ => return Err(format!("Unknown ship key \"{}\"", key).into()),
+            }
+        }
+        if !guns.is_empty() {
+            ship.guns = guns;
+        }
+        if !thrusters.is_empty() {
+            ship.thrusters = thrusters;
+        }
+        if ship.mass <= 0. {
+            return Err("A ship's mass must be positive".into());
+        }
+        Ok(ship)
+    }
+
+    /// Loads the ship defined in the file at `path`
+    ///
+    /// # Errors
+    /// Fails if the file cannot be read or parsed
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn Error>> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Loads the ship defined in `path`, or the default ship if the file
+    /// does not exist or cannot be parsed
+    pub fn load_or_default(path: &str) -> Self {
+        Self::from_file(path).unwrap_or_else(|e| {
+            warn!("Using the default ship instead of {}: {}", path, e);
+            Self::default()
+        })
+    }
+
+    /// Loads every ship defined in `dir`, ordered by file name. Files that
+    /// can't be parsed are skipped
+    ///
+    /// Returns the default ship if there are no ships in `dir`
+    pub fn load_all(dir: &str) -> Vec<Self> {
+        let mut paths: Vec<_> = std::fs::read_dir(dir)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok().map(|e| e.path()))
+                    .filter(|path| {
+                        path.extension().map_or(false, |ext| ext == SHIP_EXT)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        paths.sort();
+        let ships: Vec<_> = paths
+            .iter()
+            .filter_map(|path| {
+                let path = path.to_string_lossy();
+                Self::from_file(&path)
+                    .map_err(|e| warn!("Skipping ship {}: {}", path, e))
+                    .ok()
+            })
+            .collect();
+        if ships.is_empty() {
+            vec![Self::default()]
+        } else {
+            ships
+        }
+    }
+}
+
+impl Default for ShipDef {
+    fn default() -> Self {
+        Self {
+            name: "Sparrow".to_owned(),
+            model: "assets/Ships/StarSparrow01.obj".to_owned(),
+            collision: "assets/Ships/StarSparrow01.obj".to_owned(),
+            mass: 420.,
+            inertia: 1.,
+            thrust: 420.,
+            turn_rate: 0.0001,
+            energy: 100.,
+            energy_recharge: 5.,
+            armor: Armor {
+                max_shield: 100.,
+                max_hull: 100.,
+                recharge_delay: Duration::from_secs(3),
+                recharge_rate: 15.,
+            },
+            guns: vec![point3(0., 0., 10.)],
+            beam: point3(0., 0., 10.),
+            engine: point3(0., 0.3, -3.),
+            thrusters: vec![
+                ThrusterDef::new(point3(6., 0., -1.), vec3(0., 1., 0.)),
+                ThrusterDef::new(point3(6., 0., -1.), vec3(0., -1., 0.)),
+                ThrusterDef::new(point3(-6., 0., -1.), vec3(0., 1., 0.)),
+                ThrusterDef::new(point3(-6., 0., -1.), vec3(0., -1., 0.)),
+                ThrusterDef::new(point3(0., 0., 8.), vec3(0., 1., 0.)),
+                ThrusterDef::new(point3(0., 0., 8.), vec3(0., -1., 0.)),
+                ThrusterDef::new(point3(0., 0., 8.), vec3(0., 0., 1.)),
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ships_override_the_default_ship() {
+        let ship = ShipDef::parse(
+            "# a light ship\n\
+            name = Dart\n\
+            \n\
+            mass = 200\n\
+            shield = 60\n\
+            shield_delay = 1.5\n\
+            gun = -3 0 8\n\
+            gun = 3 0 8\n\
+            thruster = 0 0 8 0 2 0",
+        )
+        .unwrap();
+        assert_eq!(ship.name, "Dart");
+        assert_eq!(ship.mass, 200.);
+        assert_eq!(ship.armor.max_shield, 60.);
+        assert_eq!(ship.armor.recharge_delay, Duration::from_millis(1500));
+        assert_eq!(ship.guns, vec![point3(-3., 0., 8.), point3(3., 0., 8.)]);
+        assert_eq!(
+            ship.thrusters,
+            vec![ThrusterDef::new(point3(0., 0., 8.), vec3(0., 1., 0.))]
+        );
+        assert_eq!(ship.model, ShipDef::default().model);
+        assert_eq!(ship.thrust, ShipDef::default().thrust);
+    }
+
+    #[test]
+    fn malformed_ships_are_rejected() {
+        assert!(ShipDef::parse("mass = heavy").is_err());
+        assert!(ShipDef::parse("mass = 0").is_err());
+        assert!(ShipDef::parse("gun = 1 2").is_err());
+        assert!(ShipDef::parse("thruster = 1 2 3 0 0 0").is_err());
+        assert!(ShipDef::parse("wings = 2").is_err());
+        assert!(ShipDef::parse("name").is_err());
+    }
+
+    #[test]
+    fn bundled_ships_parse() {
+        let raider =
+            ShipDef::parse(include_str!("../assets/Ships/raider.ship"))
+                .unwrap();
+        assert_eq!(raider.guns.len(), 2);
+        assert_eq!(raider.thrusters.len(), 7);
+        let sparrow =
+            ShipDef::parse(include_str!("../assets/Ships/sparrow.ship"))
+                .unwrap();
+        assert_eq!(sparrow.model, ShipDef::default().model);
+        assert_eq!(sparrow.thrusters, ShipDef::default().thrusters);
+    }
+}
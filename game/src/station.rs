@@ -0,0 +1,359 @@
+use crate::cg_support::node::Node;
+use crate::graphics_engine::cinematic::{CameraPath, Keyframe};
+use crate::physics::BaseRigidBody;
+use cgmath::*;
+use std::time::Duration;
+
+/// Point, in the station's local space, that docked ships are held at, just
+/// off the mouth of its bay
+const BERTH: Point3<f64> = Point3::new(0., 0., 55.);
+/// Distance along the station's local `+z` axis that its approach corridor
+/// starts at
+const CORRIDOR_START: f64 = 80.;
+/// Distance along the station's local `+z` axis that its approach corridor
+/// ends at
+const CORRIDOR_END: f64 = 200.;
+/// Radius of the approach corridor
+const CORRIDOR_RADIUS: f64 = 25.;
+/// Fastest a ship can be moving in the approach corridor to dock
+pub const MAX_DOCKING_SPEED: f64 = 20.;
+/// Length of the docking and undocking paths, in seconds
+const DOCKING_TIME: f64 = 4.;
+
+/// A service a station provides to the ships docked with it
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Service {
+    /// Restores a ship's shield and hull
+    Repair,
+    /// Refills a ship's energy
+    Rearm,
+}
+
+/// The docking bay of a station, which ships dock with by flying slowly
+/// towards the station down the approach corridor in front of the bay
+pub struct DockingPort {
+    /// Transform of the station, which is immobile
+    station: Node,
+}
+
+impl DockingPort {
+    pub const fn new(station: Node) -> Self {
+        Self { station }
+    }
+
+    /// Gets the direction the station's bay faces, in world space
+    fn axis(&self) -> Vector3<f64> {
+        self.station.local_rot().rotate_vector(vec3(0., 0., 1.))
+    }
+
+    /// Gets the up direction of the station, in world space
+    fn up(&self) -> Vector3<f64> {
+        self.station.local_rot().rotate_vector(vec3(0., 1., 0.))
+    }
+
+    /// Gets the world space position of the point `dist` along the
+    /// station's approach axis
+    fn on_axis(&self, dist: f64) -> Point3<f64> {
+        self.station.transform_point(point3(0., 0., dist))
+    }
+
+    /// `true` if a ship at `pos` moving at `velocity` is in the approach
+    /// corridor, heading towards the station slowly enough to dock
+    pub fn can_dock(&self, pos: Point3<f64>, velocity: Vector3<f64>) -> bool {
+        let local = match self.station.mat().invert() {
+            Some(inv) => inv.transform_point(pos),
+            None => return false,
+        };
+        (CORRIDOR_START..=CORRIDOR_END).contains(&local.z)
+            && vec2(local.x, local.y).magnitude() <= CORRIDOR_RADIUS
+            && velocity.dot(self.axis()) < 0.
+            && velocity.magnitude() <= MAX_DOCKING_SPEED
+    }
+
+    /// Gets the points a ship flies through to dock with the station, in
+    /// order: just off the mouth of the approach corridor, then halfway down
+    /// it
+    pub fn approach_route(&self) -> Vec<Point3<f64>> {
+        vec![
+            self.on_axis(CORRIDOR_END + CORRIDOR_RADIUS),
+            self.on_axis((CORRIDOR_START + CORRIDOR_END) * 0.5),
+        ]
+    }
+
+    /// Gets the pose of a ship docked with the station, which faces the
+    /// station's bay, `time` seconds into a path
+    fn berth(&self, time: f64) -> Keyframe {
+        let berth = self.station.transform_point(BERTH);
+        Keyframe::looking_at(time, berth, berth - self.axis(), self.up())
+    }
+
+    /// Gets the path from a ship at `pos` with the rotation `rot` to the
+    /// berth, which lines the ship up with the bay at the start of the
+    /// corridor before pulling it in
+    fn docking_path(
+        &self,
+        pos: Point3<f64>,
+        rot: Quaternion<f64>,
+    ) -> CameraPath {
+        let approach = self.on_axis(CORRIDOR_START);
+        CameraPath::new(vec![
+            Keyframe { time: 0., pos, rot },
+            Keyframe::looking_at(
+                DOCKING_TIME * 0.6,
+                approach,
+                approach - self.axis(),
+                self.up(),
+            ),
+            self.berth(DOCKING_TIME),
+        ])
+    }
+
+    /// Gets the path from the berth to just past the end of the corridor,
+    /// which turns the ship around to face away from the station
+    fn undocking_path(&self) -> CameraPath {
+        let turn = self.on_axis((CORRIDOR_START + CORRIDOR_END) * 0.5);
+        let exit = self.on_axis(CORRIDOR_END + 20.);
+        let side = self.up().cross(self.axis());
+        CameraPath::new(vec![
+            self.berth(0.),
+            Keyframe::looking_at(
+                DOCKING_TIME * 0.5,
+                turn,
+                turn + side,
+                self.up(),
+            ),
+            Keyframe::looking_at(
+                DOCKING_TIME,
+                exit,
+                exit + self.axis(),
+                self.up(),
+            ),
+        ])
+    }
+}
+
+/// A change in a ship's docking
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DockingEvent {
+    /// The ship entered the corridor and started moving to the berth
+    Started,
+    /// The ship reached the berth
+    Docked,
+    /// The ship left the corridor and is back in flight
+    Undocked,
+}
+
+enum DockingState {
+    Flying,
+    /// Following a docking or undocking path, `elapsed` seconds along it
+    Moving {
+        path: CameraPath,
+        elapsed: f64,
+        docking: bool,
+    },
+    Docked,
+}
+
+/// Docks a ship with a station
+///
+/// A ship that flies slowly enough down the station's approach corridor is
+/// taken out of the player's control and moved along a spline to the
+/// station's berth, where it's held until it undocks. Undocking moves the
+/// ship back out past the end of the corridor and returns it to flight
+pub struct Docking {
+    port: Option<DockingPort>,
+    state: DockingState,
+}
+
+impl Docking {
+    /// Creates the docking of a ship with `port`, or a docking that never
+    /// docks if the map has no station
+    pub const fn new(port: Option<DockingPort>) -> Self {
+        Self {
+            port,
+            state: DockingState::Flying,
+        }
+    }
+
+    /// `true` if the ship is flown by its player
+    pub const fn is_flying(&self) -> bool {
+        matches!(self.state, DockingState::Flying)
+    }
+
+    /// `true` if the ship is held at the berth
+    pub const fn is_docked(&self) -> bool {
+        matches!(self.state, DockingState::Docked)
+    }
+
+    /// Gets the route a flying ship takes down the approach corridor to
+    /// dock, or `None` if there's no station or the ship isn't flying
+    pub fn approach_route(&self) -> Option<Vec<Point3<f64>>> {
+        if self.is_flying() {
+            self.port.as_ref().map(DockingPort::approach_route)
+        } else {
+            None
+        }
+    }
+
+    /// Starts docking `ship` if it's entered the approach corridor, moves it
+    /// `dt` along its docking or undocking path, and holds it still while
+    /// it's docked
+    ///
+    /// Returns the change in the docking, if any
+    pub fn update(
+        &mut self,
+        dt: Duration,
+        ship: &mut BaseRigidBody,
+    ) -> Option<DockingEvent> {
+        let port = self.port.as_ref()?;
+        let mut event = None;
+        match &mut self.state {
+            DockingState::Flying => {
+                if !port.can_dock(ship.center(), ship.velocity) {
+                    return None;
+                }
+                let path = {
+                    let transform = ship.transform.borrow();
+                    port.docking_path(
+                        transform.get_pos(),
+                        transform.local_rot(),
+                    )
+                };
+                self.state = DockingState::Moving {
+                    path,
+                    elapsed: 0.,
+                    docking: true,
+                };
+                event = Some(DockingEvent::Started);
+            }
+            DockingState::Moving { elapsed, .. } => {
+                *elapsed += dt.as_secs_f64();
+            }
+            DockingState::Docked => (),
+        }
+        self.hold(ship);
+        if let DockingState::Moving {
+            path,
+            elapsed,
+            docking,
+        } = &self.state
+        {
+            if *elapsed >= path.duration() {
+                let docking = *docking;
+                self.state = if docking {
+                    DockingState::Docked
+                } else {
+                    DockingState::Flying
+                };
+                event = Some(if docking {
+                    DockingEvent::Docked
+                } else {
+                    DockingEvent::Undocked
+                });
+            }
+        }
+        event
+    }
+
+    /// Moves `ship` to its place on its path or at the berth, and stops it
+    fn hold(&self, ship: &mut BaseRigidBody) {
+        let (pos, rot) = match (&self.state, &self.port) {
+            (DockingState::Moving { path, elapsed, .. }, _) => {
+                path.sample(*elapsed)
+            }
+            (DockingState::Docked, Some(port)) => {
+                let berth = port.berth(0.);
+                (berth.pos, berth.rot)
+            }
+            _ => return,
+        };
+        {
+            let mut transform = ship.transform.borrow_mut();
+            transform.set_pos(pos);
+            transform.set_rot(rot);
+        }
+        ship.velocity = vec3(0., 0., 0.);
+        ship.rot_vel = vec3(0., 0., 0.);
+    }
+
+    /// Returns the ship to flight wherever it is, such as when it's
+    /// destroyed while docking
+    pub fn abort(&mut self) {
+        self.state = DockingState::Flying;
+    }
+
+    /// Starts moving a docked ship out of the station
+    ///
+    /// Returns `true` if the ship was docked
+    pub fn undock(&mut self) -> bool {
+        match (&self.state, &self.port) {
+            (DockingState::Docked, Some(port)) => {
+                self.state = DockingState::Moving {
+                    path: port.undocking_path(),
+                    elapsed: 0.,
+                    docking: false,
+                };
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn port() -> DockingPort {
+        DockingPort::new(Node::default().pos(point3(100., 0., 0.)))
+    }
+
+    #[test]
+    fn ships_dock_from_the_corridor_when_slow() {
+        let port = port();
+        let slow = vec3(0., 0., -5.);
+        assert!(port.can_dock(point3(100., 5., 150.), slow));
+        assert!(!port.can_dock(point3(100., 5., 150.), slow * 10.));
+        assert!(!port.can_dock(point3(100., 5., 150.), -slow));
+        assert!(!port.can_dock(point3(100., 50., 150.), slow));
+        assert!(!port.can_dock(point3(100., 0., CORRIDOR_END + 10.), slow));
+        // the approach route leads from outside the corridor into it
+        let route = port.approach_route();
+        assert!(!port.can_dock(route[0], slow));
+        assert!(port.can_dock(route[1], slow));
+    }
+
+    #[test]
+    fn ships_dock_and_undock_along_paths() {
+        let node =
+            Rc::new(RefCell::new(Node::default().pos(point3(100., 0., 150.))));
+        let mut ship = BaseRigidBody::new(node.clone());
+        ship.velocity = vec3(0., 0., -5.);
+        let mut docking = Docking::new(Some(port()));
+        let step = Duration::from_secs_f64(DOCKING_TIME);
+        assert_eq!(
+            docking.update(Duration::default(), &mut ship),
+            Some(DockingEvent::Started)
+        );
+        assert!(!docking.is_flying());
+        assert_eq!(ship.velocity, vec3(0., 0., 0.));
+        assert!(!docking.undock());
+
+        assert_eq!(docking.update(step, &mut ship), Some(DockingEvent::Docked));
+        assert!(docking.is_docked());
+        let berth = node.borrow().local_pos();
+        assert!(berth.distance(point3(100., 0., BERTH.z)) < 0.001);
+
+        assert!(docking.undock());
+        assert_eq!(
+            docking.update(step, &mut ship),
+            Some(DockingEvent::Undocked)
+        );
+        assert!(docking.is_flying());
+        // the ship is left outside the corridor, so it doesn't dock again
+        assert!(node.borrow().local_pos().z > CORRIDOR_END);
+        assert_eq!(docking.update(step, &mut ship), None);
+    }
+}
@@ -0,0 +1,286 @@
+use super::drawable::*;
+use super::minimap::{self, Icon, IconBatch, MinimapBlip};
+use super::object;
+use super::physics;
+use crate::cg_support::{node, Transformation};
+use crate::graphics_engine::camera::OrthoCamera;
+use crate::graphics_engine::{entity, scene, shader};
+use cgmath::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// The area seen by a tactical map, looking straight down along the world's
+/// `-y` axis with `x` to the right and `-z` up the screen
+///
+/// Map space is the area scaled so its edges are at `-1` and `1`. The area
+/// is square, but is widened to fill a viewport of aspect ratio `aspect`
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct MapArea {
+    pub center: Point3<f64>,
+    /// Distance from the center to the edges of the area
+    pub half_size: f64,
+    pub aspect: f64,
+}
+
+impl MapArea {
+    /// Gets the orthographic camera looking down on the area, which sees
+    /// from the top to the bottom of the cube around the area
+    pub fn camera(&self) -> OrthoCamera {
+        let size = self.half_size * 2.;
+        let pos = self.center + vec3(0., size, 0.);
+        OrthoCamera::new(
+            (size * self.aspect) as f32,
+            size as f32,
+            self.half_size as f32,
+            (self.half_size * 3.) as f32,
+            pos.cast().unwrap(),
+            Some(self.center.cast().unwrap()),
+            Some(vec3(0., 0., -1.)),
+        )
+    }
+
+    /// Converts the world space `pt` to map space
+    fn to_map(&self, pt: Point3<f64>) -> Point3<f64> {
+        let offset = (pt - self.center) / self.half_size;
+        point3(offset.x, -offset.z, 0.)
+    }
+
+    /// Gets the rotation that turns an icon pointing up the map to point
+    /// along the world space direction `dir`
+    ///
+    /// Returns `None` if `dir` is vertical
+    fn map_rotation(dir: Vector3<f64>) -> Option<Quaternion<f64>> {
+        let dir = vec2(dir.x, -dir.z);
+        if dir.magnitude2() < f64::EPSILON {
+            None
+        } else {
+            Some(Quaternion::from_angle_z(Rad((-dir.x).atan2(dir.y))))
+        }
+    }
+
+    /// Gets the transformation from map space to the normalized device
+    /// coordinates of the viewport
+    fn map_to_ndc(&self) -> Matrix4<f64> {
+        Matrix4::from_nonuniform_scale(1. / self.aspect, 1., 1.)
+    }
+
+    /// Converts `ndc`, in the normalized device coordinates of the viewport,
+    /// to the point in the area at the height `altitude`. Points beyond the
+    /// edges of the area are moved to the closest edge
+    pub fn to_world(&self, ndc: Point2<f64>, altitude: f64) -> Point3<f64> {
+        let x = (ndc.x * self.aspect).clamp(-1., 1.);
+        let y = ndc.y.clamp(-1., 1.);
+        point3(
+            self.center.x + x * self.half_size,
+            altitude,
+            self.center.z - y * self.half_size,
+        )
+    }
+}
+
+/// A top-down map of the battle area, which the main scene is viewed
+/// through while player 1 picks where to fly
+///
+/// While the map is shown, the main scene is rendered from an orthographic
+/// camera above the area, and the map marks the bodies in the scene with
+/// the icons of the minimap
+pub struct TacticalMap {
+    area: MapArea,
+    camera: Rc<RefCell<OrthoCamera>>,
+    /// Viewer of the scene before the map was shown, while the map is shown
+    prev_viewer: Option<Rc<RefCell<dyn Viewer>>>,
+    player: Rc<RefCell<node::Node>>,
+    blips: Vec<MinimapBlip>,
+    icons: IconBatch,
+}
+
+impl TacticalMap {
+    /// Creates a hidden map of `area` which marks `player` as player 1
+    pub fn new<F: glium::backend::Facade>(
+        area: MapArea,
+        player: Rc<RefCell<node::Node>>,
+        facade: &F,
+    ) -> Self {
+        Self {
+            area,
+            camera: Rc::new(RefCell::new(area.camera())),
+            prev_viewer: None,
+            player,
+            blips: Vec::new(),
+            icons: IconBatch::new(facade),
+        }
+    }
+
+    pub const fn area(&self) -> &MapArea {
+        &self.area
+    }
+
+    /// Sets the aspect ratio of the viewport the map is shown in
+    pub fn set_aspect(&mut self, aspect: f32) {
+        self.area.aspect = f64::from(aspect);
+        *self.camera.borrow_mut() = self.area.camera();
+    }
+
+    pub const fn is_shown(&self) -> bool {
+        self.prev_viewer.is_some()
+    }
+
+    /// Shows the map by rendering `scene` from above the area. Does nothing
+    /// if the map is already shown
+    pub fn show(&mut self, scene: &RefCell<scene::Scene>) {
+        if self.prev_viewer.is_none() {
+            self.prev_viewer =
+                Some(scene.borrow_mut().set_viewer(self.camera.clone()));
+        }
+    }
+
+    /// Hides the map, returning `scene` to the viewer it had before the map
+    /// was shown
+    pub fn hide(&mut self, scene: &RefCell<scene::Scene>) {
+        if let Some(viewer) = self.prev_viewer.take() {
+            scene.borrow_mut().set_viewer(viewer);
+        }
+    }
+
+    /// Removes all bodies from the map, leaving player 1
+    pub fn clear_items(&mut self) {
+        self.blips.clear();
+        let player = self.player.borrow();
+        let mut pos = node::Node::default()
+            .pos(self.area.to_map(player.get_pos()))
+            .u_scale(0.035);
+        if let Some(rot) =
+            MapArea::map_rotation(player.transform_vec(vec3(0., 0., 1.)))
+        {
+            pos = pos.rot(rot);
+        }
+        self.blips.push(MinimapBlip {
+            color: minimap::CENTER_COLOR,
+            icon: Icon::Triangle,
+            pos,
+        });
+    }
+
+    /// Adds `body` to the map. Does nothing if `body` should not be shown
+    pub fn add_item(&mut self, body: &physics::RigidBody<object::ObjectData>) {
+        use object::ObjectType::*;
+        if Rc::ptr_eq(&body.base.transform, &self.player) {
+            return;
+        }
+        let (color, icon) = match minimap::icon_of(body.metadata.0) {
+            Some(style) => style,
+            None => return,
+        };
+        let size = body.base.extents().unwrap_or(0.) / self.area.half_size;
+        let scale = match body.metadata.0 {
+            Asteroid => size.max(0.008),
+            Planet => size.max(0.02),
+            Ship => 0.025,
+            _ => 0.012,
+        };
+        let mut pos = node::Node::default()
+            .pos(self.area.to_map(body.base.center()))
+            .u_scale(scale);
+        if icon == Icon::Triangle {
+            let forward =
+                body.base.transform.borrow().transform_vec(vec3(0., 0., 1.));
+            if let Some(rot) = MapArea::map_rotation(forward) {
+                pos = pos.rot(rot);
+            }
+        }
+        self.blips.push(MinimapBlip { color, icon, pos });
+    }
+
+    /// Marks the waypoint the autopilot is flying player 1 to
+    pub fn add_waypoint(&mut self, waypoint: Point3<f64>) {
+        self.blips.push(MinimapBlip {
+            color: minimap::TARGET_COLOR,
+            icon: Icon::Ring,
+            pos: node::Node::default()
+                .pos(self.area.to_map(waypoint))
+                .u_scale(0.03),
+        });
+    }
+}
+
+impl Drawable for TacticalMap {
+    fn render_args<'a>(
+        &'a mut self,
+        _positions: &[[[f32; 4]; 4]],
+    ) -> Vec<(
+        shader::UniformInfo,
+        VertexHolder<'a>,
+        glium::index::IndicesSource<'a>,
+    )> {
+        self.icons.render_args(&self.blips, self.area.map_to_ndc())
+    }
+
+    fn transparency(&self) -> Option<f32> {
+        None
+    }
+}
+
+impl entity::AbstractEntity for TacticalMap {
+    fn transformations(&self) -> Option<&[Rc<RefCell<dyn Transformation>>]> {
+        None
+    }
+
+    fn drawable(&mut self) -> &mut dyn Drawable {
+        self
+    }
+
+    fn should_render(&self, pass: shader::RenderPassType) -> bool {
+        self.is_shown() && matches!(pass, shader::RenderPassType::Visual)
+    }
+
+    fn render_order(&self) -> entity::RenderOrder {
+        entity::RenderOrder::Unordered
+    }
+
+    fn get_id(&self) -> usize {
+        self as *const _ as usize
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn clicks_on_the_map_match_the_camera() {
+        let area = MapArea {
+            center: point3(100., 0., -50.),
+            half_size: 1000.,
+            aspect: 2.,
+        };
+        let pt = point3(600., 30., -550.);
+        let map = area.to_map(pt);
+        assert_approx_eq!(map.x, 0.5);
+        assert_approx_eq!(map.y, 0.5);
+        // the icons are drawn where the camera sees the point
+        let ndc = area.map_to_ndc().transform_point(map);
+        let cam = area.camera();
+        let seen = (cam.proj_mat() * cam.view_mat())
+            .transform_point(pt.cast::<f32>().unwrap());
+        assert_approx_eq!(f64::from(seen.x), ndc.x, 1e-5);
+        assert_approx_eq!(f64::from(seen.y), ndc.y, 1e-5);
+        assert!(seen.z.abs() < 1.);
+
+        let clicked = area.to_world(point2(ndc.x, ndc.y), 30.);
+        assert!(clicked.distance(pt) < 1e-6);
+        // clicks beside the area are moved into it
+        let edge = area.to_world(point2(0.9, 0.), 0.);
+        assert_approx_eq!(edge.x, 1100.);
+        assert_approx_eq!(edge.z, -50.);
+    }
+
+    #[test]
+    fn icons_point_along_the_heading() {
+        let up = MapArea::map_rotation(vec3(0., 0., -1.)).unwrap();
+        assert_approx_eq!((up * vec3(0., 1., 0.)).y, 1.);
+        let right = MapArea::map_rotation(vec3(1., 0., 0.)).unwrap();
+        assert_approx_eq!((right * vec3(0., 1., 0.)).x, 1.);
+        assert!(MapArea::map_rotation(vec3(0., 1., 0.)).is_none());
+    }
+}
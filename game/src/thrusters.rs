@@ -0,0 +1,205 @@
+use crate::cg_support::node::Node;
+use crate::graphics_engine::particles::{ExhaustCones, Nozzle};
+use crate::ship::{ShipDef, ThrusterDef};
+use cgmath::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Rotational velocity, as set by a ship's pitch and roll, at which its
+/// maneuvering thrusters fire at full intensity
+pub const FULL_TURN_SPEED: f64 = 0.0003;
+
+/// Speed at which an AI ship's thrusters fire at full intensity
+pub const AI_FULL_THRUST_SPEED: f64 = 40.;
+
+/// Intensity of the main engine while the afterburner is on
+const AFTERBURNER_INTENSITY: f64 = 1.6;
+
+/// Length of the main engine's exhaust cone at full intensity
+const ENGINE_CONE_LEN: f64 = 4.;
+
+/// Length of a maneuvering thruster's exhaust cone at full intensity
+const RCS_CONE_LEN: f64 = 1.5;
+
+/// Gets how hard the maneuvering thruster `thruster` fires when the ship is
+/// asked to thrust along `linear` and turn about `angular`, both in the
+/// ship's local space with a length of at most `1`
+///
+/// A thruster fires by how much it pushes the ship along `linear`, plus how
+/// much it turns the ship about `angular`, up to an intensity of `1`
+pub fn thruster_intensity(
+    thruster: &ThrusterDef,
+    linear: Vector3<f64>,
+    angular: Vector3<f64>,
+) -> f64 {
+    let force = -thruster.exhaust;
+    let push = force.dot(linear).max(0.);
+    let torque = thruster.pos.to_vec().cross(force);
+    let turn = if torque.magnitude2() > f64::EPSILON {
+        torque.normalize().dot(angular).max(0.)
+    } else {
+        0.
+    };
+    (push + turn).min(1.)
+}
+
+/// Shortens `v` to a length of `1` if it's longer
+fn clamp_unit(v: Vector3<f64>) -> Vector3<f64> {
+    if v.magnitude2() > 1. {
+        v.normalize()
+    } else {
+        v
+    }
+}
+
+/// Creates a nozzle at `pos`, in the local space of `root`, whose exhaust
+/// leaves along `exhaust`
+fn nozzle_at(
+    root: &Rc<RefCell<Node>>,
+    pos: Point3<f64>,
+    exhaust: Vector3<f64>,
+    length: f64,
+) -> Rc<RefCell<Nozzle>> {
+    let rot = Quaternion::from_arc(vec3(0., 0., 1.), exhaust, None);
+    let node = Rc::new(RefCell::new(Node::default().pos(pos).rot(rot)));
+    Node::attach(root, &node);
+    Rc::new(RefCell::new(Nozzle {
+        node,
+        intensity: 0.,
+        length,
+    }))
+}
+
+/// The main engine and maneuvering thrusters of a ship, whose exhaust
+/// reacts to how the ship is asked to move
+pub struct Thrusters {
+    root: Rc<RefCell<Node>>,
+    engine: Rc<RefCell<Nozzle>>,
+    rcs: Vec<(ThrusterDef, Rc<RefCell<Nozzle>>)>,
+    cones: Rc<RefCell<ExhaustCones>>,
+    /// Nozzles that haven't had particle emitters made for them yet
+    unemitted: Vec<Rc<RefCell<Nozzle>>>,
+}
+
+impl Thrusters {
+    /// Creates the thrusters of `ship`, attached to the ship's `root` node
+    pub fn new(root: Rc<RefCell<Node>>, ship: &ShipDef) -> Self {
+        let (engine, rcs) = Self::nozzles_of(&root, ship);
+        let nozzles = Self::all_nozzles(&engine, &rcs);
+        Self {
+            root,
+            engine,
+            rcs,
+            cones: Rc::new(RefCell::new(ExhaustCones::new(nozzles.clone()))),
+            unemitted: nozzles,
+        }
+    }
+
+    /// Creates the nozzles of the main engine and maneuvering thrusters of
+    /// `ship`
+    fn nozzles_of(
+        root: &Rc<RefCell<Node>>,
+        ship: &ShipDef,
+    ) -> (Rc<RefCell<Nozzle>>, Vec<(ThrusterDef, Rc<RefCell<Nozzle>>)>) {
+        let engine =
+            nozzle_at(root, ship.engine, vec3(0., 0., -1.), ENGINE_CONE_LEN);
+        let rcs = ship
+            .thrusters
+            .iter()
+            .map(|def| {
+                (*def, nozzle_at(root, def.pos, def.exhaust, RCS_CONE_LEN))
+            })
+            .collect();
+        (engine, rcs)
+    }
+
+    fn all_nozzles(
+        engine: &Rc<RefCell<Nozzle>>,
+        rcs: &[(ThrusterDef, Rc<RefCell<Nozzle>>)],
+    ) -> Vec<Rc<RefCell<Nozzle>>> {
+        std::iter::once(engine.clone())
+            .chain(rcs.iter().map(|(_, nozzle)| nozzle.clone()))
+            .collect()
+    }
+
+    /// Replaces the thrusters with those of `ship`. The particles of the old
+    /// thrusters die out on their own
+    pub fn set_ship(&mut self, ship: &ShipDef) {
+        let (engine, rcs) = Self::nozzles_of(&self.root, ship);
+        let nozzles = Self::all_nozzles(&engine, &rcs);
+        self.engine = engine;
+        self.rcs = rcs;
+        self.cones.borrow_mut().set_nozzles(nozzles.clone());
+        self.unemitted = nozzles;
+    }
+
+    /// Fires the thrusters for the ship being asked to thrust along `linear`
+    /// and turn about `angular`, both in the ship's local space. Inputs
+    /// longer than `1` are treated as having a length of `1`
+    ///
+    /// `boost` - `true` if the afterburner is on
+    pub fn update(
+        &mut self,
+        linear: Vector3<f64>,
+        angular: Vector3<f64>,
+        boost: bool,
+    ) {
+        let (linear, angular) = (clamp_unit(linear), clamp_unit(angular));
+        let boost = if boost { AFTERBURNER_INTENSITY } else { 1. };
+        self.engine.borrow_mut().intensity = linear.z.max(0.) * boost;
+        for (def, nozzle) in &self.rcs {
+            nozzle.borrow_mut().intensity =
+                thruster_intensity(def, linear, angular);
+        }
+    }
+
+    /// Gets the exhaust cones of the thrusters
+    pub fn cones(&self) -> Rc<RefCell<ExhaustCones>> {
+        self.cones.clone()
+    }
+
+    /// Takes the nozzles that particle emitters need to be made for
+    pub fn take_unemitted(&mut self) -> Vec<Rc<RefCell<Nozzle>>> {
+        std::mem::take(&mut self.unemitted)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn thrusters_fire_with_the_input_they_help() {
+        let nose = ThrusterDef {
+            pos: point3(0., 0., 8.),
+            exhaust: vec3(0., 1., 0.),
+        };
+        let retro = ThrusterDef {
+            pos: point3(0., 0., 8.),
+            exhaust: vec3(0., 0., 1.),
+        };
+        let zero = vec3(0., 0., 0.);
+        // exhausting up turns the nose down, about +x
+        assert!(
+            (thruster_intensity(&nose, zero, vec3(1., 0., 0.)) - 1.).abs()
+                < f64::EPSILON
+        );
+        assert!(thruster_intensity(&nose, zero, vec3(-1., 0., 0.)) < 0.01);
+        assert!(
+            (thruster_intensity(&nose, vec3(0., -0.5, 0.), zero) - 0.5).abs()
+                < f64::EPSILON
+        );
+        assert!(
+            (thruster_intensity(&retro, vec3(0., 0., -1.), zero) - 1.).abs()
+                < f64::EPSILON
+        );
+        assert!(thruster_intensity(&retro, vec3(0., 0., 1.), zero) < 0.01);
+        assert!(thruster_intensity(&retro, zero, vec3(1., 0., 1.)) < 0.01);
+        assert!(
+            (thruster_intensity(&nose, vec3(0., -1., 0.), vec3(1., 0., 0.))
+                - 1.)
+                .abs()
+                < f64::EPSILON
+        );
+    }
+}
@@ -0,0 +1,127 @@
+use std::time::Duration;
+
+/// Real time it takes to slow down into, and speed back up out of, slow
+/// motion
+const SLOW_MOTION_RAMP: Duration = Duration::from_millis(300);
+
+/// How fast the game runs relative to real time, which can be ramped
+/// smoothly to a new scale for effects like slow motion
+///
+/// Ramps and holds are timed in real time, so they take as long no matter
+/// how slow the game is running
+pub struct TimeScale {
+    scale: f64,
+    /// Scale at the start of the current ramp
+    from: f64,
+    target: f64,
+    /// Real time the current ramp takes
+    ramp: Duration,
+    /// Real time since the current ramp started
+    elapsed: Duration,
+    /// Real time left before the scale ramps back to `1`, if it will
+    restore_in: Option<Duration>,
+}
+
+impl Default for TimeScale {
+    fn default() -> Self {
+        Self {
+            scale: 1.,
+            from: 1.,
+            target: 1.,
+            ramp: Duration::default(),
+            elapsed: Duration::default(),
+            restore_in: None,
+        }
+    }
+}
+
+impl TimeScale {
+    /// Gets the current time scale, where `1` is real time
+    pub const fn scale(&self) -> f64 {
+        self.scale
+    }
+
+    /// Smoothly changes the time scale from its current value to `target`
+    /// over the real time `over`. Negative targets are treated as `0`
+    ///
+    /// Cancels the end of any slow motion
+    pub fn set_time_scale(&mut self, target: f64, over: Duration) {
+        self.from = self.scale;
+        self.target = target.max(0.);
+        self.ramp = over;
+        self.elapsed = Duration::default();
+        self.restore_in = None;
+        if over.is_zero() {
+            self.scale = self.target;
+        }
+    }
+
+    /// Slows the game down to `scale` for the real time `hold`, then speeds
+    /// it back up to real time
+    pub fn slow_motion(&mut self, scale: f64, hold: Duration) {
+        self.set_time_scale(scale, SLOW_MOTION_RAMP);
+        self.restore_in = Some(SLOW_MOTION_RAMP + hold);
+    }
+
+    /// Advances the ramp of the time scale by the real time `dt`
+    ///
+    /// Returns the game time that passed during `dt`
+    pub fn update(&mut self, dt: Duration) -> Duration {
+        let start_scale = self.scale;
+        if let Some(left) = self.restore_in {
+            if left <= dt {
+                self.set_time_scale(1., SLOW_MOTION_RAMP);
+            } else {
+                self.restore_in = Some(left - dt);
+            }
+        }
+        self.elapsed += dt;
+        self.scale = if self.elapsed >= self.ramp {
+            self.target
+        } else {
+            let t = self.elapsed.as_secs_f64() / self.ramp.as_secs_f64();
+            let t = t * t * (3. - 2. * t);
+            self.from + (self.target - self.from) * t
+        };
+        dt.mul_f64((start_scale + self.scale) / 2.)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn time_scale_ramps_smoothly() {
+        let mut time = TimeScale::default();
+        assert_eq!(time.update(Duration::from_secs(1)), Duration::from_secs(1));
+        time.set_time_scale(0.5, Duration::from_secs(1));
+        time.update(Duration::from_millis(500));
+        assert_approx_eq!(time.scale(), 0.75);
+        time.update(Duration::from_millis(250));
+        assert!(time.scale() > 0.5 && time.scale() < 0.6);
+        time.update(Duration::from_millis(500));
+        assert_approx_eq!(time.scale(), 0.5);
+        assert_eq!(
+            time.update(Duration::from_secs(1)),
+            Duration::from_millis(500)
+        );
+        time.set_time_scale(2., Duration::default());
+        assert_approx_eq!(time.scale(), 2.);
+    }
+
+    #[test]
+    fn slow_motion_returns_to_real_time() {
+        let mut time = TimeScale::default();
+        time.slow_motion(0.25, Duration::from_secs(1));
+        time.update(SLOW_MOTION_RAMP);
+        assert_approx_eq!(time.scale(), 0.25);
+        time.update(Duration::from_millis(900));
+        assert_approx_eq!(time.scale(), 0.25);
+        time.update(Duration::from_millis(200));
+        assert!(time.scale() > 0.25);
+        time.update(SLOW_MOTION_RAMP);
+        assert_approx_eq!(time.scale(), 1.);
+    }
+}
@@ -0,0 +1,144 @@
+use crate::cg_support::node::Node;
+use crate::physics::{SpringJoint, SpringJointData};
+use cgmath::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Farthest a target can be from the beam's origin to be locked on to
+const BEAM_RANGE: f64 = 150.;
+/// Distance from the beam's origin past which a held target is released
+const BREAK_RANGE: f64 = 200.;
+/// Largest angle between a ship's forward direction and the direction to a
+/// target that can be locked on to
+const BEAM_ARC: Deg<f64> = Deg(20.);
+/// Distance from the beam's origin the beam pulls a target to
+const HOLD_DIST: f64 = 40.;
+/// Acceleration, per unit the target is away from the hold distance, the
+/// beam pulls with
+const STIFFNESS: f64 = 4.;
+/// Damping of the beam, critically damped for `STIFFNESS`
+const DAMPING: f64 = 4.;
+
+/// `true` if a target at `target` can be locked on to by the tractor beam
+/// of a ship whose beam is emitted from `origin` in the direction `forward`
+pub fn in_beam(
+    origin: Point3<f64>,
+    forward: Vector3<f64>,
+    target: Point3<f64>,
+) -> bool {
+    let to_target = target - origin;
+    let dist = to_target.magnitude();
+    dist <= BEAM_RANGE
+        && (dist < f64::EPSILON
+            || forward.normalize().dot(to_target / dist)
+                >= Rad::from(BEAM_ARC).cos())
+}
+
+/// A tractor beam holding a target in front of a ship
+///
+/// The beam is a damped spring in the simulation between the ship and the
+/// center of the target, which tows the target along behind the beam.
+/// Releasing the beam leaves both objects with the velocities the spring
+/// gave them
+pub struct TractorBeam {
+    joint: Rc<RefCell<SpringJointData>>,
+}
+
+impl TractorBeam {
+    /// Locks the beam of `ship`, emitted from the point `origin` in the
+    /// ship's local space, on to `target`, whose center is at the world space
+    /// point `target_center`
+    ///
+    /// Returns the beam and the constraint to add to the simulation
+    pub fn lock<T>(
+        ship: &Rc<RefCell<Node>>,
+        origin: Point3<f64>,
+        target: &Rc<RefCell<Node>>,
+        target_center: Point3<f64>,
+    ) -> (Self, SpringJoint<T>) {
+        let attach_a =
+            target.borrow().mat().invert().map_or(target_center, |inv| {
+                inv.transform_point(target_center)
+            });
+        let joint = Rc::new(RefCell::new(SpringJointData {
+            a: Rc::downgrade(target),
+            attach_a,
+            b: Rc::downgrade(ship),
+            attach_b: origin,
+            rest_length: HOLD_DIST,
+            stiffness: STIFFNESS,
+            damping: DAMPING,
+            released: false,
+        }));
+        (
+            Self {
+                joint: joint.clone(),
+            },
+            SpringJoint::new(joint),
+        )
+    }
+
+    /// Gets the world space positions of the beam's origin on the ship and
+    /// of the target, or `None` if either no longer exists
+    pub fn ends(&self) -> Option<(Point3<f64>, Point3<f64>)> {
+        self.joint
+            .borrow()
+            .attach_points()
+            .map(|(target, ship)| (ship, target))
+    }
+
+    /// `true` if the beam still holds its target within range
+    pub fn is_attached(&self) -> bool {
+        !self.joint.borrow().released
+            && self.ends().map_or(false, |(origin, target)| {
+                origin.distance(target) <= BREAK_RANGE
+            })
+    }
+
+    /// Lets go of the target, so the beam no longer affects either object
+    pub fn release(&self) {
+        self.joint.borrow_mut().released = true;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn targets_must_be_in_range_and_in_front() {
+        let origin = point3(0., 0., 0.);
+        let forward = vec3(0., 0., 1.);
+        assert!(in_beam(origin, forward, point3(0., 10., 100.)));
+        assert!(!in_beam(origin, forward, point3(0., 0., BEAM_RANGE + 1.)));
+        assert!(!in_beam(origin, forward, point3(0., 100., 100.)));
+        assert!(!in_beam(origin, forward, point3(0., 0., -50.)));
+    }
+
+    #[test]
+    fn beam_breaks_when_target_leaves_range() {
+        let ship = Rc::new(RefCell::new(Node::default()));
+        let target =
+            Rc::new(RefCell::new(Node::default().pos(point3(0., 0., 100.))));
+        let (beam, _) = TractorBeam::lock::<()>(
+            &ship,
+            point3(0., 0., 10.),
+            &target,
+            point3(0., 0., 100.),
+        );
+        let (origin, held) = beam.ends().unwrap();
+        assert_eq!(origin, point3(0., 0., 10.));
+        assert_eq!(held, point3(0., 0., 100.));
+        assert!(beam.is_attached());
+        target
+            .borrow_mut()
+            .set_pos(point3(0., 0., BREAK_RANGE + 20.));
+        assert!(!beam.is_attached());
+
+        target.borrow_mut().set_pos(point3(0., 0., 100.));
+        beam.release();
+        assert!(!beam.is_attached());
+        drop(target);
+        assert!(beam.ends().is_none());
+    }
+}
@@ -0,0 +1,166 @@
+use crate::cg_support::node::Node;
+use crate::physics::BaseRigidBody;
+use cgmath::*;
+use shared_types::wormhole::{TeleportClaim, Wormhole, TELEPORT_COOLDOWN};
+use shared_types::ObjectId;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::time::Duration;
+
+/// Watches for bodies entering the map's wormholes and moves the bodies
+/// whose teleports are confirmed
+///
+/// A body that entered a portal can't enter another one until its cooldown
+/// is up, so a body isn't claimed again while its teleport is waiting to be
+/// confirmed
+pub struct Wormholes {
+    wormholes: Vec<Wormhole>,
+    /// Time left before each body can enter a wormhole again
+    cooldowns: HashMap<ObjectId, Duration>,
+}
+
+impl Wormholes {
+    pub fn new(wormholes: Vec<Wormhole>) -> Self {
+        Self {
+            wormholes,
+            cooldowns: HashMap::new(),
+        }
+    }
+
+    /// Counts down the cooldowns by `dt`
+    pub fn update(&mut self, dt: Duration) {
+        for left in self.cooldowns.values_mut() {
+            *left = left.saturating_sub(dt);
+        }
+        self.cooldowns.retain(|_, left| !left.is_zero());
+    }
+
+    /// Gets the claim for the body `id` at `pos` moving at `velocity` if it
+    /// entered a portal, and starts its cooldown
+    ///
+    /// Returns `None` if the body didn't enter a portal or is cooling down
+    pub fn check(
+        &mut self,
+        id: ObjectId,
+        pos: Point3<f64>,
+        velocity: Vector3<f64>,
+    ) -> Option<TeleportClaim> {
+        if self.cooldowns.contains_key(&id) {
+            return None;
+        }
+        let (wormhole, entry) =
+            self.wormholes
+                .iter()
+                .enumerate()
+                .find_map(|(idx, wormhole)| {
+                    wormhole.entered(pos, velocity).map(|entry| (idx, entry))
+                })?;
+        self.cooldowns.insert(id, TELEPORT_COOLDOWN);
+        Some(TeleportClaim {
+            body: id,
+            wormhole: u8::try_from(wormhole).ok()?,
+            entry: u8::try_from(entry).ok()?,
+        })
+    }
+
+    /// Moves `body` through the wormhole of the confirmed `claim`. Does
+    /// nothing if the claim's wormhole doesn't exist
+    pub fn teleport(&self, claim: &TeleportClaim, body: &mut BaseRigidBody) {
+        let entry = usize::from(claim.entry);
+        if let Some(wormhole) = self
+            .wormholes
+            .get(usize::from(claim.wormhole))
+            .filter(|_| entry < 2)
+        {
+            let center = body.center();
+            let traversal = wormhole.traverse(entry, center, body.velocity);
+            let mut transform = body.transform.borrow_mut();
+            let rot = traversal.rotation * transform.local_rot();
+            transform.translate(traversal.pos - center);
+            transform.set_rot(rot);
+            body.velocity = traversal.velocity;
+            body.rot_vel = traversal.rotation.rotate_vector(body.rot_vel);
+        }
+    }
+}
+
+/// Gets the locations of the faces of every portal of `wormholes`, which
+/// are disks in the XY plane scaled to the radius of the portal
+pub fn portal_locations(wormholes: &[Wormhole]) -> Vec<Node> {
+    wormholes
+        .iter()
+        .flat_map(|wormhole| {
+            wormhole.ends.iter().map(move |end| {
+                Node::default()
+                    .pos(end.pos.into())
+                    .rot(Quaternion::from_arc(
+                        vec3(0., 0., 1.),
+                        end.normal.into(),
+                        None,
+                    ))
+                    .u_scale(wormhole.radius)
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+    use shared_types::wormhole::Portal;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn wormholes() -> Wormholes {
+        Wormholes::new(vec![Wormhole {
+            ends: [
+                Portal::new(point3(0., 0., 100.), vec3(0., 0., -1.)),
+                Portal::new(point3(0., 0., -100.), vec3(0., 0., 1.)),
+            ],
+            radius: 10.,
+        }])
+    }
+
+    #[test]
+    fn bodies_entering_portals_are_claimed_once_per_cooldown() {
+        let mut wormholes = wormholes();
+        let id = ObjectId::new(3);
+        assert!(wormholes
+            .check(id, point3(0., 0., 50.), vec3(0., 0., 10.))
+            .is_none());
+        let claim = wormholes
+            .check(id, point3(0., 0., 95.), vec3(0., 0., 10.))
+            .unwrap();
+        assert_eq!(claim.wormhole, 0);
+        assert_eq!(claim.entry, 0);
+        assert!(wormholes
+            .check(id, point3(0., 0., 95.), vec3(0., 0., 10.))
+            .is_none());
+        wormholes.update(TELEPORT_COOLDOWN);
+        assert!(wormholes
+            .check(id, point3(0., 0., 95.), vec3(0., 0., 10.))
+            .is_some());
+    }
+
+    #[test]
+    fn teleported_bodies_leave_the_other_portal() {
+        let wormholes = wormholes();
+        let node =
+            Rc::new(RefCell::new(Node::default().pos(point3(2., 0., 95.))));
+        let mut body = BaseRigidBody::new(node.clone());
+        body.velocity = vec3(0., 0., 10.);
+        let claim = TeleportClaim {
+            body: ObjectId::new(3),
+            wormhole: 0,
+            entry: 0,
+        };
+        wormholes.teleport(&claim, &mut body);
+        let pos = node.borrow().local_pos();
+        // the body comes out past the exit portal's sensor, still moving
+        // away from the portal
+        assert!(pos.z > -90. && pos.z < -50.);
+        assert_approx_eq!(pos.x.abs(), 2.);
+        assert_approx_eq!(body.velocity.z, 10.);
+    }
+}
@@ -0,0 +1,257 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::error;
+
+/// Time the listening threads sleep between checking for connections
+const ACCEPT_SLEEP: Duration = Duration::from_millis(50);
+/// Longest time a client of the metrics endpoint has to send its request
+const HTTP_TIMEOUT: Duration = Duration::from_secs(2);
+/// Largest HTTP request that's read
+const MAX_REQUEST_SIZE: u64 = 4096;
+/// Highest number of times per second the server can update its lobbies
+pub const MAX_TICK_RATE: u32 = 1000;
+
+pub const HELP: &str = "Commands:\n\
+    \tlist: lists the connected clients\n\
+    \tkick <address | name>: disconnects a client\n\
+    \tmap <map>: changes the map for the players who log in from now on\n\
+    \ttickrate <hz>: sets how often the server updates its lobbies\n\
+    \tmetrics: shows the latest metrics\n\
+    \thelp: shows this message";
+
+/// A command sent to the server from the admin console
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AdminCommand {
+    List,
+    /// Disconnects the client with the address or username
+    Kick(String),
+    /// Changes the map to the map of the name, which is parsed like the
+    /// `--map` argument
+    Map(String),
+    TickRate(u32),
+    Metrics,
+    Help,
+}
+
+impl TryFrom<&str> for AdminCommand {
+    type Error = String;
+    fn try_from(line: &str) -> Result<Self, Self::Error> {
+        let mut words = line.split_whitespace();
+        let cmd = words.next().unwrap_or_default();
+        let arg = words.next();
+        if words.next().is_some() {
+            return Err(format!("Too many arguments to {}", cmd));
+        }
+        match (cmd, arg) {
+            ("list", None) => Ok(Self::List),
+            ("kick", Some(client)) => Ok(Self::Kick(client.to_owned())),
+            ("map", Some(map)) => Ok(Self::Map(map.to_owned())),
+            ("tickrate", Some(rate)) => match rate.parse() {
+                Ok(rate) if (1..=MAX_TICK_RATE).contains(&rate) => {
+                    Ok(Self::TickRate(rate))
+                }
+                _ => Err(format!("Invalid tick rate: {}", rate)),
+            },
+            ("metrics", None) => Ok(Self::Metrics),
+            ("help", None) => Ok(Self::Help),
+            ("kick" | "map" | "tickrate", None) => {
+                Err(format!("{} requires an argument", cmd))
+            }
+            _ => Err(format!("Unknown command \"{}\". Try help", line.trim())),
+        }
+    }
+}
+
+/// A command from the console, and where to send its result
+pub struct Request {
+    pub command: AdminCommand,
+    reply: Sender<String>,
+}
+
+impl Request {
+    /// Sends the result of the command to whoever sent it
+    pub fn reply(self, result: String) {
+        // the console may have disconnected since
+        std::mem::drop(self.reply.send(result));
+    }
+}
+
+/// Sends the command on each line of `input` to the server, writing the
+/// results to `output`, until `input` ends or the server stops
+fn run_console(
+    input: impl BufRead,
+    mut output: impl Write,
+    requests: &Sender<Request>,
+) {
+    for line in input.lines() {
+        let line = match line {
+            Ok(line) if line.trim().is_empty() => continue,
+            Ok(line) => line,
+            Err(_) => return,
+        };
+        let result = match AdminCommand::try_from(line.as_str()) {
+            Ok(command) => {
+                let (reply, result) = mpsc::channel();
+                if requests.send(Request { command, reply }).is_err() {
+                    return;
+                }
+                match result.recv() {
+                    Ok(result) => result,
+                    Err(_) => return,
+                }
+            }
+            Err(error) => error,
+        };
+        if writeln!(output, "{}", result).is_err() {
+            return;
+        }
+    }
+}
+
+/// Calls `on_connect` with each connection to `listener` until `stop` is set
+fn accept_until(
+    listener: &TcpListener,
+    stop: &AtomicBool,
+    mut on_connect: impl FnMut(TcpStream),
+) {
+    while !stop.load(Ordering::SeqCst) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                if stream.set_nonblocking(false).is_ok() {
+                    on_connect(stream);
+                }
+            }
+            Err(_) => std::thread::sleep(ACCEPT_SLEEP),
+        }
+    }
+}
+
+/// Binds a listener on the local machine, so the admin interface can't be
+/// reached from other machines
+fn bind_local(port: u16) -> std::io::Result<TcpListener> {
+    let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], port)))?;
+    listener.set_nonblocking(true)?;
+    Ok(listener)
+}
+
+/// Answers the HTTP request on `stream` with `page` if it's for `/metrics`
+fn serve_metrics(
+    mut stream: TcpStream,
+    page: &Mutex<String>,
+) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(HTTP_TIMEOUT))?;
+    let mut request_line = String::new();
+    BufReader::new((&stream).take(MAX_REQUEST_SIZE))
+        .read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let response = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => {
+            let body = page.lock().map(|page| page.clone()).unwrap_or_default();
+            format!(
+                "HTTP/1.1 200 OK\r\n\
+                Content-Type: text/plain; version=0.0.4\r\n\
+                Content-Length: {}\r\n\
+                Connection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+        }
+        _ => "HTTP/1.1 404 Not Found\r\n\
+            Content-Length: 0\r\n\
+            Connection: close\r\n\r\n"
+            .to_owned(),
+    };
+    stream.write_all(response.as_bytes())
+}
+
+/// The admin interface of the server, which is a command console on stdin
+/// and on a local TCP port, and a metrics endpoint for Prometheus
+///
+/// The console and endpoint are served from their own threads. Commands are
+/// queued for the server to run between messages, and the endpoint serves
+/// the latest metrics the server published. The threads stop taking
+/// connections once `stop` is set
+pub struct Admin {
+    requests: Receiver<Request>,
+    /// The page served at `/metrics`, if the endpoint is enabled
+    metrics_page: Option<Arc<Mutex<String>>>,
+}
+
+impl Admin {
+    /// Starts the console on stdin if `stdin` is `true`, the console on
+    /// `console_port` and the metrics endpoint on `metrics_port`, if they
+    /// are set
+    ///
+    /// # Errors
+    /// Fails if a port cannot be bound
+    pub fn spawn(
+        stdin: bool,
+        console_port: Option<u16>,
+        metrics_port: Option<u16>,
+        stop: &Arc<AtomicBool>,
+    ) -> std::io::Result<Self> {
+        let (sender, requests) = mpsc::channel();
+        if stdin {
+            let sender = sender.clone();
+            std::thread::spawn(move || {
+                run_console(
+                    std::io::stdin().lock(),
+                    std::io::stdout(),
+                    &sender,
+                );
+            });
+        }
+        if let Some(port) = console_port {
+            let listener = bind_local(port)?;
+            let stop = stop.clone();
+            std::thread::spawn(move || {
+                accept_until(&listener, &stop, |stream| {
+                    let sender = sender.clone();
+                    std::thread::spawn(move || {
+                        if let Ok(input) = stream.try_clone() {
+                            run_console(BufReader::new(input), stream, &sender);
+                        }
+                    });
+                });
+            });
+        }
+        let metrics_page = match metrics_port {
+            Some(port) => {
+                let listener = bind_local(port)?;
+                let page = Arc::new(Mutex::new(String::new()));
+                let (stop, served) = (stop.clone(), page.clone());
+                std::thread::spawn(move || {
+                    accept_until(&listener, &stop, |stream| {
+                        if let Err(error) = serve_metrics(stream, &served) {
+                            error!("Error serving metrics: {}", error);
+                        }
+                    });
+                });
+                Some(page)
+            }
+            None => None,
+        };
+        Ok(Self {
+            requests,
+            metrics_page,
+        })
+    }
+
+    /// Gets the commands sent since the last call
+    pub fn pending(&self) -> Vec<Request> {
+        self.requests.try_iter().collect()
+    }
+
+    /// Sets the page the metrics endpoint serves
+    pub fn publish_metrics(&self, page: String) {
+        if let Some(Ok(mut served)) =
+            self.metrics_page.as_ref().map(|served| served.lock())
+        {
+            *served = page;
+        }
+    }
+}
@@ -1,28 +1,64 @@
+use crate::{admin, interest, lobby, metrics};
 use core::fmt::Display;
-use shared_types::game_map;
+use shared_types::{game_map, map_file};
 use std::env;
 use std::error::Error;
+use std::time::Duration;
 
 pub const DEFAULT_PORT: u16 = 33200;
 
 #[derive(Debug)]
 pub enum MapType {
     AsteroidMap,
+    /// A procedural map generated from the seed
+    Procedural(u64),
+    /// A map loaded from the map file with the name
+    File(String),
 }
 
 impl MapType {
-    pub fn get_game_map(&self) -> Box<dyn game_map::Map> {
-        match self {
+    /// Creates the map
+    ///
+    /// # Errors
+    /// Fails if the map is loaded from a file which cannot be read or parsed
+    pub fn get_game_map(
+        &self,
+    ) -> Result<Box<dyn game_map::Map>, Box<dyn Error>> {
+        let map: Box<dyn game_map::Map> = match self {
             Self::AsteroidMap => Box::new(game_map::AsteroidMap {}),
-        }
+            Self::Procedural(seed) => {
+                Box::new(game_map::ProceduralMap::new(*seed))
+            }
+            Self::File(name) => Box::new(map_file::FileMap::by_name(name)?),
+        };
+        Ok(map)
     }
 }
 
+/// Gets a seed from the current time
+fn random_seed() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |time| time.as_nanos() as u64)
+}
+
+/// Parses `asteroid`, `procedural` for a procedural map with a random seed,
+/// `procedural:<seed>`, or the name of a file in the maps directory
 impl TryFrom<&str> for MapType {
     type Error = String;
     fn try_from(val: &str) -> Result<Self, Self::Error> {
-        match val {
-            "asteroid" => Ok(Self::AsteroidMap),
+        match val.split_once(':') {
+            None if val == "asteroid" => Ok(Self::AsteroidMap),
+            None if val == "procedural" => Ok(Self::Procedural(random_seed())),
+            Some(("procedural", seed)) => seed
+                .parse()
+                .map(Self::Procedural)
+                .map_err(|_| format!("Invalid map seed: {}", seed)),
+            None if std::path::Path::new(&map_file::FileMap::path_of(val))
+                .is_file() =>
+            {
+                Ok(Self::File(val.to_owned()))
+            }
             _ => Err(format!("Invalid map type: {}", val)),
         }
     }
@@ -34,6 +70,36 @@ pub const DEFAULT_MAP: MapType = MapType::AsteroidMap;
 pub struct ServerConfiguration {
     pub port: u16,
     pub map: MapType,
+    /// Distance from a client's ship that objects are sent within
+    pub interest_radius: f64,
+    /// Number of updates sent between updates with every object
+    pub refresh_interval: u32,
+    /// Time between every player in a lobby becoming ready and their match
+    /// starting
+    pub countdown: Duration,
+    pub match_length: Duration,
+    /// `true` if messages are compressed for clients that support it
+    pub compression: bool,
+    /// `true` if clients must start a session before anything else
+    pub secure: bool,
+    /// Number of times per second the lobbies are updated
+    pub tick_rate: u32,
+    /// Time between the summaries of the server's metrics
+    pub metrics_interval: Duration,
+    /// Port of the Prometheus metrics endpoint, if it's enabled
+    pub metrics_port: Option<u16>,
+    /// Port of the admin console, if it's enabled
+    pub admin_port: Option<u16>,
+    /// `true` if the admin console reads commands from stdin
+    pub console: bool,
+    /// Directory the profiles of the players are saved in, if they're saved
+    pub profiles_dir: Option<String>,
+    /// Bytes per second each client may be sent, or `None` if unlimited.
+    /// Updates beyond the cap are held back, but nothing else is
+    pub client_bandwidth: Option<u32>,
+    /// Levels logged for each module, such as `warn,oort_server=debug`.
+    /// The `RUST_LOG` environment variable takes precedence
+    pub log_filter: String,
 }
 
 impl Default for ServerConfiguration {
@@ -41,6 +107,20 @@ impl Default for ServerConfiguration {
         Self {
             port: DEFAULT_PORT,
             map: DEFAULT_MAP,
+            interest_radius: interest::DEFAULT_INTEREST_RADIUS,
+            refresh_interval: interest::DEFAULT_REFRESH_INTERVAL,
+            countdown: lobby::DEFAULT_COUNTDOWN,
+            match_length: lobby::DEFAULT_MATCH_LENGTH,
+            compression: true,
+            secure: false,
+            tick_rate: lobby::DEFAULT_TICK_RATE,
+            metrics_interval: metrics::DEFAULT_METRICS_INTERVAL,
+            metrics_port: None,
+            admin_port: None,
+            console: false,
+            profiles_dir: None,
+            client_bandwidth: None,
+            log_filter: "info".to_owned(),
         }
     }
 }
@@ -52,8 +132,37 @@ impl Display for ServerConfiguration {
             "ServerConfiguration {{\n\
                 \tport: {},\n\
                 \tmap: {:?},\n\
+                \tinterest_radius: {},\n\
+                \trefresh_interval: {},\n\
+                \tcountdown: {:?},\n\
+                \tmatch_length: {:?},\n\
+                \tcompression: {},\n\
+                \tsecure: {},\n\
+                \ttick_rate: {},\n\
+                \tmetrics_interval: {:?},\n\
+                \tmetrics_port: {:?},\n\
+                \tadmin_port: {:?},\n\
+                \tconsole: {},\n\
+                \tprofiles_dir: {:?},\n\
+                \tclient_bandwidth: {:?},\n\
+                \tlog_filter: {:?},\n\
             }}",
-            self.port, self.map
+            self.port,
+            self.map,
+            self.interest_radius,
+            self.refresh_interval,
+            self.countdown,
+            self.match_length,
+            self.compression,
+            self.secure,
+            self.tick_rate,
+            self.metrics_interval,
+            self.metrics_port,
+            self.admin_port,
+            self.console,
+            self.profiles_dir,
+            self.client_bandwidth,
+            self.log_filter
         )
     }
 }
@@ -74,6 +183,97 @@ fn parse_args_helper(
             config.map = MapType::try_from(map_name.as_str())?;
             parse_args_helper(args, config)
         }
+        Some(x) if x == "-r" || x == "--radius" => {
+            let radius = args.next().ok_or("--radius requires an argument")?;
+            config.interest_radius = radius.parse::<f64>()?;
+            if config.interest_radius <= 0. {
+                return Err("--radius must be positive".into());
+            }
+            parse_args_helper(args, config)
+        }
+        Some(x) if x == "--refresh" => {
+            let interval =
+                args.next().ok_or("--refresh requires an argument")?;
+            config.refresh_interval = interval.parse::<u32>()?;
+            parse_args_helper(args, config)
+        }
+        Some(x) if x == "--countdown" => {
+            let secs = args.next().ok_or("--countdown requires an argument")?;
+            config.countdown = Duration::from_secs(secs.parse::<u64>()?);
+            parse_args_helper(args, config)
+        }
+        Some(x) if x == "--match-length" => {
+            let secs =
+                args.next().ok_or("--match-length requires an argument")?;
+            config.match_length = Duration::from_secs(secs.parse::<u64>()?);
+            if config.match_length.is_zero() {
+                return Err("--match-length must be positive".into());
+            }
+            parse_args_helper(args, config)
+        }
+        Some(x) if x == "--no-compression" => {
+            config.compression = false;
+            parse_args_helper(args, config)
+        }
+        Some(x) if x == "--secure" => {
+            config.secure = true;
+            parse_args_helper(args, config)
+        }
+        Some(x) if x == "--tick-rate" => {
+            let rate = args.next().ok_or("--tick-rate requires an argument")?;
+            config.tick_rate = rate.parse::<u32>()?;
+            if !(1..=admin::MAX_TICK_RATE).contains(&config.tick_rate) {
+                return Err(format!(
+                    "--tick-rate must be between 1 and {}",
+                    admin::MAX_TICK_RATE
+                ))?;
+            }
+            parse_args_helper(args, config)
+        }
+        Some(x) if x == "--metrics-interval" => {
+            let secs = args
+                .next()
+                .ok_or("--metrics-interval requires an argument")?;
+            config.metrics_interval = Duration::from_secs(secs.parse::<u64>()?);
+            if config.metrics_interval.is_zero() {
+                return Err("--metrics-interval must be positive".into());
+            }
+            parse_args_helper(args, config)
+        }
+        Some(x) if x == "--metrics-port" => {
+            let port =
+                args.next().ok_or("--metrics-port requires an argument")?;
+            config.metrics_port = Some(port.parse::<u16>()?);
+            parse_args_helper(args, config)
+        }
+        Some(x) if x == "--admin-port" => {
+            let port =
+                args.next().ok_or("--admin-port requires an argument")?;
+            config.admin_port = Some(port.parse::<u16>()?);
+            parse_args_helper(args, config)
+        }
+        Some(x) if x == "--console" => {
+            config.console = true;
+            parse_args_helper(args, config)
+        }
+        Some(x) if x == "--profiles" => {
+            let dir = args.next().ok_or("--profiles requires an argument")?;
+            config.profiles_dir = Some(dir);
+            parse_args_helper(args, config)
+        }
+        Some(x) if x == "--bandwidth" => {
+            let rate = args.next().ok_or("--bandwidth requires an argument")?;
+            config.client_bandwidth = Some(rate.parse::<u32>()?);
+            if config.client_bandwidth == Some(0) {
+                return Err("--bandwidth must be positive".into());
+            }
+            parse_args_helper(args, config)
+        }
+        Some(x) if x == "--log" => {
+            config.log_filter =
+                args.next().ok_or("--log requires an argument")?;
+            parse_args_helper(args, config)
+        }
         Some(x) => Err(format!("Unknown argument \"{}\"", x))?,
     }
 }
@@ -0,0 +1,137 @@
+use crate::interest::object_pos;
+use shared_types::{HitClaim, ObjectId, ObjectType, RemoteObject};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Farthest a shooter can be from its target when the target is hit
+pub const MAX_HIT_RANGE: f64 = 1500.;
+/// Farthest a hit point can be from the center of its target. Generous,
+/// since the server's positions lag behind the clients'
+const HIT_POINT_TOLERANCE: f64 = 60.;
+/// Window the hits of a shooter are counted in
+const HIT_WINDOW: Duration = Duration::from_secs(1);
+/// Most hits a shooter can claim in `HIT_WINDOW`. Slightly more than the
+/// fastest boosted fire rate, since hits can bunch up when lasers take
+/// different times to reach their targets
+const MAX_HITS_PER_WINDOW: usize = 30;
+/// Radius of an obstacle per unit of its scale. Smaller than the objects'
+/// actual radii so lasers skimming an obstacle aren't wrongly blocked
+const ASTEROID_RADIUS_FAC: f64 = 8.;
+const PLANET_RADIUS_FAC: f64 = 9.;
+const STATION_RADIUS_FAC: f64 = 30.;
+
+/// Gets the radius of the sphere that blocks lasers around `obj`, or
+/// `None` if lasers pass through it
+fn obstacle_radius(obj: &RemoteObject) -> Option<f64> {
+    let scale = obj.mat[2][0];
+    match obj.typ {
+        ObjectType::Asteroid => Some(scale * ASTEROID_RADIUS_FAC),
+        ObjectType::Planet => Some(scale * PLANET_RADIUS_FAC),
+        ObjectType::Station => Some(scale * STATION_RADIUS_FAC),
+        _ => None,
+    }
+}
+
+pub fn distance(a: [f64; 3], b: [f64; 3]) -> f64 {
+    (0..3).map(|i| (a[i] - b[i]).powi(2)).sum::<f64>().sqrt()
+}
+
+/// `true` if the segment from `start` to `end` passes through the sphere of
+/// radius `radius` at `center`
+fn segment_hits_sphere(
+    start: [f64; 3],
+    end: [f64; 3],
+    center: [f64; 3],
+    radius: f64,
+) -> bool {
+    let dir = [end[0] - start[0], end[1] - start[1], end[2] - start[2]];
+    let len2: f64 = dir.iter().map(|d| d * d).sum();
+    let t = if len2 > 0. {
+        ((0..3).map(|i| (center[i] - start[i]) * dir[i]).sum::<f64>() / len2)
+            .clamp(0., 1.)
+    } else {
+        0.
+    };
+    let closest = [
+        dir[0].mul_add(t, start[0]),
+        dir[1].mul_add(t, start[1]),
+        dir[2].mul_add(t, start[2]),
+    ];
+    distance(closest, center) < radius
+}
+
+/// Checks the hits clients claim against the server's view of the game,
+/// so a client can't claim hits its ships couldn't have made
+#[derive(Default)]
+pub struct HitValidator {
+    /// Times of the hits accepted from each shooter that are in the
+    /// current window
+    recent_hits: HashMap<ObjectId, VecDeque<Instant>>,
+}
+
+impl HitValidator {
+    /// Checks that `claim` is plausible. The shooter must be one of
+    /// `own_objects`, the objects of the claiming client, and the target
+    /// must be one of `world`, the other objects in the game. The target
+    /// must be in range, the hit point must be on the target, no obstacle
+    /// in `world` may block the shot, and the shooter can't hit more often
+    /// than it can fire
+    ///
+    /// Accepted hits count towards the shooter's fire rate
+    ///
+    /// # Errors
+    /// Returns the reason the hit was rejected
+    pub fn validate<'a>(
+        &mut self,
+        claim: &HitClaim,
+        own_objects: &[RemoteObject],
+        world: impl Iterator<Item = &'a RemoteObject> + Clone,
+        now: Instant,
+    ) -> Result<(), String> {
+        let shooter = own_objects
+            .iter()
+            .find(|obj| obj.id == claim.shooter)
+            .ok_or("shooter isn't owned by the client")?;
+        let target = world
+            .clone()
+            .find(|obj| obj.id == claim.target)
+            .ok_or("target doesn't exist")?;
+        let (shooter_pos, target_pos) =
+            (object_pos(shooter), object_pos(target));
+        if distance(shooter_pos, target_pos) > MAX_HIT_RANGE {
+            return Err("target is out of range".to_owned());
+        }
+        let target_radius = obstacle_radius(target).unwrap_or(0.);
+        if distance(claim.hit_point, target_pos)
+            > target_radius + HIT_POINT_TOLERANCE
+        {
+            return Err("hit point isn't on the target".to_owned());
+        }
+        let blocked = world
+            .filter(|obj| obj.id != target.id)
+            .filter_map(|obj| Some((object_pos(obj), obstacle_radius(obj)?)))
+            .any(|(center, radius)| {
+                segment_hits_sphere(
+                    shooter_pos,
+                    claim.hit_point,
+                    center,
+                    radius,
+                )
+            });
+        if blocked {
+            return Err("shot is blocked".to_owned());
+        }
+        let hits = self.recent_hits.entry(claim.shooter).or_default();
+        while hits
+            .front()
+            .map_or(false, |time| now.duration_since(*time) > HIT_WINDOW)
+        {
+            hits.pop_front();
+        }
+        if hits.len() >= MAX_HITS_PER_WINDOW {
+            return Err("shooter is hitting faster than it can fire".to_owned());
+        }
+        hits.push_back(now);
+        Ok(())
+    }
+}
@@ -0,0 +1,101 @@
+use shared_types::{ObjectType, RemoteObject};
+use std::collections::HashMap;
+
+/// Default distance from a client's ship that objects are sent within
+pub const DEFAULT_INTEREST_RADIUS: f64 = 1000.;
+/// Default number of updates sent to a client between full refreshes, which
+/// include every object regardless of distance
+pub const DEFAULT_REFRESH_INTERVAL: u32 = 60;
+
+type Cell = (i64, i64, i64);
+
+/// Gets the position of `obj`
+pub const fn object_pos(obj: &RemoteObject) -> [f64; 3] {
+    [obj.mat[1][0], obj.mat[1][1], obj.mat[1][2]]
+}
+
+/// Gets the position of a client's ship from its objects. This is the
+/// object with the client's id, or the client's first ship if it has none
+pub fn client_pos(
+    objects: &[RemoteObject],
+    id: shared_types::ObjectId,
+) -> Option<[f64; 3]> {
+    objects
+        .iter()
+        .find(|obj| obj.id == id)
+        .or_else(|| objects.iter().find(|obj| obj.typ == ObjectType::Ship))
+        .map(object_pos)
+}
+
+/// A uniform grid of cubic cells holding the objects of each client, for
+/// finding the objects near a point without checking every object
+pub struct InterestGrid<K> {
+    cell_size: f64,
+    /// Objects in each cell with the client that owns them. Empty cells
+    /// are removed
+    cells: HashMap<Cell, Vec<(K, RemoteObject)>>,
+}
+
+impl<K: Copy + Eq> InterestGrid<K> {
+    pub fn new(cell_size: f64) -> Self {
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+        }
+    }
+
+    fn cell_of(&self, pos: [f64; 3]) -> Cell {
+        let [x, y, z] = pos.map(|c| (c / self.cell_size).floor() as i64);
+        (x, y, z)
+    }
+
+    /// Adds `objects`, owned by `owner`
+    pub fn insert(&mut self, owner: K, objects: &[RemoteObject]) {
+        for obj in objects {
+            let cell = self.cell_of(object_pos(obj));
+            self.cells.entry(cell).or_default().push((owner, *obj));
+        }
+    }
+
+    /// Removes the objects of `owner` from the cells `objects` are in.
+    /// `objects` should be the objects last inserted for `owner`
+    pub fn remove(&mut self, owner: K, objects: &[RemoteObject]) {
+        for obj in objects {
+            let cell = self.cell_of(object_pos(obj));
+            if let Some(contents) = self.cells.get_mut(&cell) {
+                contents.retain(|(obj_owner, _)| *obj_owner != owner);
+                if contents.is_empty() {
+                    self.cells.remove(&cell);
+                }
+            }
+        }
+    }
+
+    /// Gets the objects within `radius` of `pos` that aren't owned by
+    /// `exclude`
+    pub fn query(
+        &self,
+        pos: [f64; 3],
+        radius: f64,
+        exclude: K,
+    ) -> Vec<RemoteObject> {
+        let reach = (radius / self.cell_size).ceil() as i64;
+        let (cx, cy, cz) = self.cell_of(pos);
+        let neighborhood = (-reach..=reach).flat_map(move |x| {
+            (-reach..=reach).flat_map(move |y| {
+                (-reach..=reach).map(move |z| (cx + x, cy + y, cz + z))
+            })
+        });
+        neighborhood
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .filter(|(owner, obj)| {
+                let obj_pos = object_pos(obj);
+                let dist2: f64 =
+                    (0..3).map(|i| (obj_pos[i] - pos[i]).powi(2)).sum();
+                *owner != exclude && dist2 <= radius * radius
+            })
+            .map(|(_, obj)| *obj)
+            .collect()
+    }
+}
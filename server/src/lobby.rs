@@ -0,0 +1,325 @@
+use shared_types::lobby::*;
+use shared_types::{ObjectId, ServerCommandType};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// Default time between every player becoming ready and the match starting
+pub const DEFAULT_COUNTDOWN: Duration = Duration::from_secs(5);
+/// Default length of a match
+pub const DEFAULT_MATCH_LENGTH: Duration = Duration::from_secs(300);
+/// Default number of times per second the server updates the lobbies
+pub const DEFAULT_TICK_RATE: u32 = 10;
+
+/// Messages to send to clients
+pub type Outgoing = Vec<(SocketAddr, ServerCommandType)>;
+
+enum Phase {
+    Waiting,
+    Countdown { ends: Instant },
+    InMatch { ends: Instant },
+}
+
+struct Member {
+    addr: SocketAddr,
+    player: LobbyPlayer,
+    /// Hits confirmed for the member in the current match
+    score: u32,
+}
+
+struct Lobby {
+    name: String,
+    members: Vec<Member>,
+    phase: Phase,
+}
+
+impl Lobby {
+    fn new(name: String) -> Self {
+        Self {
+            name,
+            members: Vec::new(),
+            phase: Phase::Waiting,
+        }
+    }
+
+    fn info(&self, now: Instant) -> LobbyInfo {
+        LobbyInfo {
+            name: self.name.clone(),
+            phase: match self.phase {
+                Phase::Waiting => LobbyPhase::Waiting,
+                Phase::Countdown { ends } => {
+                    LobbyPhase::Countdown(ends.saturating_duration_since(now))
+                }
+                Phase::InMatch { .. } => LobbyPhase::InMatch,
+            },
+            players: self.members.iter().map(|m| m.player.clone()).collect(),
+        }
+    }
+
+    /// Gets the messages sending `cmd` to every member
+    fn broadcast(&self, cmd: &ServerCommandType) -> Outgoing {
+        self.members.iter().map(|m| (m.addr, cmd.clone())).collect()
+    }
+
+    /// Gets the messages sending the lobby's state to every member
+    fn broadcast_state(&self, now: Instant) -> Outgoing {
+        self.broadcast(&ServerCommandType::LobbyState(self.info(now)))
+    }
+
+    /// Starts the countdown if every player is ready, and cancels it if not
+    fn check_ready(&mut self, now: Instant, countdown: Duration) {
+        let all_ready = self.members.len() >= MIN_PLAYERS
+            && self.members.iter().all(|m| m.player.ready);
+        match self.phase {
+            Phase::Waiting if all_ready => {
+                self.phase = Phase::Countdown {
+                    ends: now + countdown,
+                };
+            }
+            Phase::Countdown { .. } if !all_ready => {
+                self.phase = Phase::Waiting;
+            }
+            _ => (),
+        }
+    }
+
+    /// Starts the match, assigning the members to the spawn points in the
+    /// order they joined
+    fn start_match(
+        &mut self,
+        now: Instant,
+        match_length: Duration,
+        map_seed: Option<u64>,
+        spawn_points: &[[f64; 3]],
+    ) -> Outgoing {
+        self.phase = Phase::InMatch {
+            ends: now + match_length,
+        };
+        for member in &mut self.members {
+            member.score = 0;
+        }
+        let spawns = self
+            .members
+            .iter()
+            .zip(spawn_points.iter().cycle())
+            .map(|(m, pos)| (m.player.pid, *pos))
+            .collect();
+        let start =
+            ServerCommandType::MatchStart(MatchStart { map_seed, spawns });
+        let mut out = self.broadcast(&start);
+        out.append(&mut self.broadcast_state(now));
+        out
+    }
+
+    /// Ends the match, sending the results to every member. Every player
+    /// must ready up again for the next match
+    fn end_match(&mut self, now: Instant) -> Outgoing {
+        self.phase = Phase::Waiting;
+        let results = MatchResults::new(
+            self.members
+                .iter()
+                .map(|m| (m.player.pid, m.score))
+                .collect(),
+        );
+        for member in &mut self.members {
+            member.player.ready = false;
+        }
+        let mut out = self.broadcast(&ServerCommandType::MatchEnd(results));
+        out.append(&mut self.broadcast_state(now));
+        out
+    }
+}
+
+/// Every lobby on the server, and the lifecycle of their matches
+///
+/// Players join a lobby by name and ready up. Once there are enough players
+/// and every player is ready, a countdown starts, which is cancelled if a
+/// player stops being ready or leaves. When the countdown ends the match
+/// starts, and it ends once its time runs out or too few players remain.
+/// After a match, the lobby waits for its players to ready up again
+pub struct Lobbies {
+    lobbies: HashMap<String, Lobby>,
+    /// Name of the lobby each client is in
+    membership: HashMap<SocketAddr, String>,
+    countdown: Duration,
+    match_length: Duration,
+}
+
+impl Lobbies {
+    pub fn new(countdown: Duration, match_length: Duration) -> Self {
+        Self {
+            lobbies: HashMap::new(),
+            membership: HashMap::new(),
+            countdown,
+            match_length,
+        }
+    }
+
+    /// Gets the state of the lobby named `name`, if it exists
+    pub fn info(&self, name: &str, now: Instant) -> Option<LobbyInfo> {
+        self.lobbies.get(name).map(|lobby| lobby.info(now))
+    }
+
+    /// Gets the name of the lobby `addr` is in
+    pub fn lobby_of(&self, addr: &SocketAddr) -> Option<&str> {
+        self.membership.get(addr).map(String::as_str)
+    }
+
+    /// `true` if `addr` is playing a match
+    pub fn in_match(&self, addr: &SocketAddr) -> bool {
+        self.membership
+            .get(addr)
+            .and_then(|name| self.lobbies.get(name))
+            .map_or(false, |lobby| matches!(lobby.phase, Phase::InMatch { .. }))
+    }
+
+    /// Adds `addr`, the client of player `pid` named `username`, to the lobby
+    /// named `name`, creating the lobby if it doesn't exist. The client
+    /// leaves its current lobby first
+    ///
+    /// Returns the messages announcing the change to the members of both
+    /// lobbies
+    ///
+    /// # Errors
+    /// Fails if the lobby is full or playing a match
+    pub fn join(
+        &mut self,
+        addr: SocketAddr,
+        pid: ObjectId,
+        username: &str,
+        name: &str,
+        now: Instant,
+    ) -> Result<Outgoing, String> {
+        if self.lobby_of(&addr) == Some(name) {
+            return Ok(self.lobbies[name].broadcast_state(now));
+        }
+        if let Some(lobby) = self.lobbies.get(name) {
+            if matches!(lobby.phase, Phase::InMatch { .. }) {
+                return Err(format!("lobby {} is playing a match", name));
+            }
+            if lobby.members.len() >= MAX_PLAYERS {
+                return Err(format!("lobby {} is full", name));
+            }
+        }
+        let mut out = self.leave(&addr, now);
+        let lobby = self
+            .lobbies
+            .entry(name.to_owned())
+            .or_insert_with(|| Lobby::new(name.to_owned()));
+        lobby.members.push(Member {
+            addr,
+            player: LobbyPlayer {
+                pid,
+                name: username.to_owned(),
+                ready: false,
+            },
+            score: 0,
+        });
+        // the new player isn't ready
+        lobby.check_ready(now, self.countdown);
+        out.append(&mut lobby.broadcast_state(now));
+        self.membership.insert(addr, name.to_owned());
+        Ok(out)
+    }
+
+    /// Removes `addr` from its lobby, ending the lobby's match if too few
+    /// players remain and removing the lobby once it's empty
+    ///
+    /// Returns the messages announcing the change to the remaining members
+    /// and the lobby's final state to `addr`
+    pub fn leave(&mut self, addr: &SocketAddr, now: Instant) -> Outgoing {
+        let lobby = match self
+            .membership
+            .remove(addr)
+            .and_then(|name| self.lobbies.get_mut(&name))
+        {
+            Some(lobby) => lobby,
+            None => return Vec::new(),
+        };
+        lobby.members.retain(|m| m.addr != *addr);
+        let mut out = match lobby.phase {
+            Phase::InMatch { .. } if lobby.members.len() < MIN_PLAYERS => {
+                lobby.end_match(now)
+            }
+            _ => {
+                lobby.check_ready(now, self.countdown);
+                lobby.broadcast_state(now)
+            }
+        };
+        out.push((*addr, ServerCommandType::LobbyState(lobby.info(now))));
+        if lobby.members.is_empty() {
+            let name = lobby.name.clone();
+            self.lobbies.remove(&name);
+        }
+        out
+    }
+
+    /// Sets whether `addr` is ready for its lobby's next match
+    ///
+    /// Returns the messages announcing the change to the lobby's members
+    ///
+    /// # Errors
+    /// Fails if `addr` isn't in a lobby, or its lobby is playing a match
+    pub fn set_ready(
+        &mut self,
+        addr: &SocketAddr,
+        ready: bool,
+        now: Instant,
+    ) -> Result<Outgoing, String> {
+        let lobby = self
+            .membership
+            .get(addr)
+            .and_then(|name| self.lobbies.get_mut(name))
+            .ok_or("client isn't in a lobby")?;
+        if matches!(lobby.phase, Phase::InMatch { .. }) {
+            return Err("lobby is playing a match".to_owned());
+        }
+        if let Some(member) = lobby.members.iter_mut().find(|m| m.addr == *addr)
+        {
+            member.player.ready = ready;
+        }
+        lobby.check_ready(now, self.countdown);
+        Ok(lobby.broadcast_state(now))
+    }
+
+    /// Scores a confirmed hit for `addr` if it's playing a match
+    pub fn record_hit(&mut self, addr: &SocketAddr) {
+        if !self.in_match(addr) {
+            return;
+        }
+        if let Some(member) = self
+            .membership
+            .get(addr)
+            .and_then(|name| self.lobbies.get_mut(name))
+            .and_then(|lobby| {
+                lobby.members.iter_mut().find(|m| m.addr == *addr)
+            })
+        {
+            member.score += 1;
+        }
+    }
+
+    /// Starts the matches whose countdowns have finished, on the map with
+    /// `map_seed` and `spawn_points`, and ends the matches whose time has run
+    /// out
+    ///
+    /// Returns the messages announcing the starts and ends
+    pub fn update(
+        &mut self,
+        now: Instant,
+        map_seed: Option<u64>,
+        spawn_points: &[[f64; 3]],
+    ) -> Outgoing {
+        let match_length = self.match_length;
+        self.lobbies
+            .values_mut()
+            .flat_map(|lobby| match lobby.phase {
+                Phase::Countdown { ends } if ends <= now => {
+                    lobby.start_match(now, match_length, map_seed, spawn_points)
+                }
+                Phase::InMatch { ends } if ends <= now => lobby.end_match(now),
+                _ => Vec::new(),
+            })
+            .collect()
+    }
+}
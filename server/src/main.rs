@@ -11,18 +11,31 @@
     clippy::module_name_repetitions
 )]
 #![allow(dead_code)]
+mod admin;
 mod argument_parser;
+mod hits;
+mod interest;
+mod lobby;
+mod metrics;
+mod ownership;
+mod profiles;
+mod teleports;
+mod throttle;
 use std::net::*;
 use std::ops::Deref;
 
 use argument_parser::ServerConfiguration;
+use interest::InterestGrid;
 use shared_types::*;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
+use std::panic::{self, AssertUnwindSafe};
 use std::sync::{
     atomic::{AtomicBool, Ordering},
     Arc,
 };
+use std::time::{Duration, Instant};
+use tracing::{debug_span, error, info, trace_span, warn};
 
 // Maybe the server should just be a headless client
 
@@ -40,9 +53,6 @@ enum ClientState {
     WaitingForRequest,
 }
 
-/// Requires usage from one thread only
-static mut OBJ_ID: ObjectId = ObjectId::new(0);
-
 /// The data for each client
 struct ClientData {
     state: ClientState,
@@ -50,16 +60,24 @@ struct ClientData {
     id: ObjectId,
     last_msg_id: u32,
     client_objects: Vec<RemoteObject>,
+    /// Updates left before the client is sent every object again
+    updates_until_refresh: u32,
+    /// `true` if the client is sent compressed messages
+    compression: bool,
+    profile: profile::Profile,
 }
 
 impl ClientData {
-    const fn new(id: ObjectId) -> Self {
+    fn new(id: ObjectId) -> Self {
         Self {
             state: ClientState::WaitingForRequest,
             username: String::new(),
             id,
             last_msg_id: 0,
             client_objects: Vec::new(),
+            updates_until_refresh: 0,
+            compression: false,
+            profile: profile::Profile::default(),
         }
     }
 }
@@ -69,7 +87,35 @@ struct ServerState {
     users: HashMap<SocketAddr, ClientData>,
     server_objects: Vec<RemoteObject>,
     server_lighting: game_map::GlobalLightingInfo,
-    last_obj_id: ObjectId,
+    /// Seed of the map, if clients generate it themselves
+    map_seed: Option<u64>,
+    spawn_points: Vec<[f64; 3]>,
+    /// Hands out the ids of the objects clients create
+    ids: id_list::IdAllocator,
+    /// The objects of every client, partitioned by their location
+    interest: InterestGrid<SocketAddr>,
+    /// Distance from a client's ship that objects are sent within
+    interest_radius: f64,
+    refresh_interval: u32,
+    /// `true` if messages are compressed for clients that support it
+    compression: bool,
+    hits: hits::HitValidator,
+    wormholes: Vec<wormhole::Wormhole>,
+    teleports: teleports::TeleportValidator,
+    lobbies: lobby::Lobbies,
+    /// The session of each client that started one
+    sessions: HashMap<SocketAddr, session::Session>,
+    /// `true` if clients must start a session before anything else
+    secure: bool,
+    /// Clients disconnected by an admin, whose messages are dropped
+    kicked: HashSet<SocketAddr>,
+    /// Time between updates of the lobbies
+    tick_interval: Duration,
+    metrics: metrics::Metrics,
+    profiles: profiles::ProfileStore,
+    ownership: ownership::Ownership,
+    /// Limits the bytes per second sent to each client
+    throttle: throttle::Throttle,
 }
 
 impl ServerState {
@@ -85,60 +131,646 @@ impl ServerState {
             .collect()
     }
 
-    fn new<Dm: Deref<Target = dyn game_map::Map>>(map: Dm) -> Self {
+    /// Gets the objects of other clients that `requesting_client` should
+    /// be sent, which are the objects near its ship. Every so often, and
+    /// whenever the client's ship can't be found, every object is sent
+    /// instead
+    fn get_visible_objects(
+        &mut self,
+        requesting_client: &SocketAddr,
+    ) -> Vec<RemoteObject> {
+        let pos = match self.users.get_mut(requesting_client) {
+            Some(client) if client.updates_until_refresh > 0 => {
+                client.updates_until_refresh -= 1;
+                interest::client_pos(&client.client_objects, client.id)
+            }
+            Some(client) => {
+                client.updates_until_refresh = self.refresh_interval;
+                None
+            }
+            None => None,
+        };
+        pos.map_or_else(
+            || self.get_all_objects(requesting_client),
+            |pos| {
+                self.interest.query(
+                    pos,
+                    self.interest_radius,
+                    *requesting_client,
+                )
+            },
+        )
+    }
+
+    /// Checks the hit `claim` made by `claimer` against the objects of every
+    /// client and the server's objects
+    ///
+    /// # Errors
+    /// Returns the reason the hit was rejected
+    fn validate_hit(
+        &mut self,
+        claimer: &SocketAddr,
+        claim: &HitClaim,
+    ) -> Result<(), String> {
+        let own_objects = self
+            .users
+            .get(claimer)
+            .map_or(&[][..], |client| client.client_objects.as_slice());
+        let world = self
+            .users
+            .iter()
+            .filter(|(client_addr, _)| claimer != *client_addr)
+            .flat_map(|(_, client_data)| client_data.client_objects.iter())
+            .chain(self.server_objects.iter());
+        self.hits
+            .validate(claim, own_objects, world, std::time::Instant::now())
+    }
+
+    /// Checks a teleport claimed by `claimer` against the client's objects
+    /// and the map's wormholes
+    ///
+    /// # Errors
+    /// Returns the reason the teleport was rejected
+    fn validate_teleport(
+        &mut self,
+        claimer: &SocketAddr,
+        claim: &wormhole::TeleportClaim,
+    ) -> Result<(), String> {
+        let own_objects = self
+            .users
+            .get(claimer)
+            .map_or(&[][..], |client| client.client_objects.as_slice());
+        self.teleports.validate(
+            claim,
+            own_objects,
+            &self.wormholes,
+            std::time::Instant::now(),
+        )
+    }
+
+    /// Gets the session of `client` if it's still in use. Messages from
+    /// clients whose session is idle aren't sealed, so they can start a new
+    /// session
+    fn active_session(&self, client: &SocketAddr) -> Option<&session::Session> {
+        let now = std::time::Instant::now();
+        self.sessions
+            .get(client)
+            .filter(|session| !session.is_idle(now))
+    }
+
+    /// Disconnects `client`, removing its objects and taking it out of its
+    /// lobby. The client's messages are dropped from then on
+    ///
+    /// Returns the messages announcing the change to the client's lobby
+    fn kick(&mut self, client: &SocketAddr) -> lobby::Outgoing {
+        let mut out = self.lobbies.leave(client, Instant::now());
+        out.append(&mut self.ownership.remove_client(client));
+        out.retain(|(addr, _)| addr != client);
+        if let Some(client_data) = self.users.remove(client) {
+            self.interest.remove(*client, &client_data.client_objects);
+            self.teleports.remove(&client_data.client_objects);
+        }
+        self.sessions.remove(client);
+        self.throttle.remove(client);
+        self.kicked.insert(*client);
+        out
+    }
+
+    /// Switches to `map`. Players already logged in keep playing the map
+    /// they were sent at login
+    fn set_map(&mut self, map: &dyn game_map::Map) {
+        self.server_objects = map.initial_objects();
+        self.server_lighting = map.lighting_info();
+        self.map_seed = map.seed();
+        self.spawn_points = map.spawn_points();
+        self.wormholes = map.wormholes();
+        // ids already handed out stay reserved
+        if let Some(last) = self.server_objects.last() {
+            self.ids.reserve_through(last.id);
+        }
+    }
+
+    /// Reserves a block of `n` ids for `client`, which is empty if every id
+    /// was already handed out
+    fn reserve_ids(
+        &mut self,
+        n: u32,
+        client: SocketAddr,
+    ) -> (ObjectId, ObjectId) {
+        if let Some(ids) = self.ids.reserve(n) {
+            self.ownership.assign(ids, client);
+            ids
+        } else {
+            warn!("Out of object ids to give {}", client);
+            (ObjectId::default(), ObjectId::default())
+        }
+    }
+
+    /// Summarizes the metrics of the interval that just ended, in which
+    /// packets were reassembled as counted by `reassembly`
+    fn summarize_metrics(
+        &mut self,
+        now: Instant,
+        reassembly: BufferStats,
+    ) -> &metrics::Summary {
+        let client_objects = self
+            .users
+            .values()
+            .map(|client| client.client_objects.len())
+            .sum();
+        self.metrics.summarize(
+            now,
+            self.users.len(),
+            client_objects,
+            self.server_objects.len(),
+            reassembly,
+        )
+    }
+
+    /// Replaces the objects of `client` with `objects`
+    fn set_client_objects(
+        &mut self,
+        client: &SocketAddr,
+        objects: Vec<RemoteObject>,
+    ) {
+        let client_data = self.users.get_mut(client).unwrap();
+        self.interest.remove(*client, &client_data.client_objects);
+        self.interest.insert(*client, &objects);
+        client_data.client_objects = objects;
+    }
+
+    fn new<Dm: Deref<Target = dyn game_map::Map>>(
+        map: Dm,
+        config: &ServerConfiguration,
+    ) -> Self {
+        let server_objects = map.initial_objects();
+        // ids of the map's objects are never handed out
+        let ids = id_list::IdAllocator::after(
+            server_objects.last().map(|obj| obj.id),
+        );
         Self {
             users: HashMap::default(),
-            server_objects: map.initial_objects(),
+            server_objects,
             server_lighting: map.lighting_info(),
-            last_obj_id: ObjectId::default(),
+            map_seed: map.seed(),
+            spawn_points: map.spawn_points(),
+            ids,
+            // objects within the radius are at most one cell away
+            interest: InterestGrid::new(config.interest_radius),
+            interest_radius: config.interest_radius,
+            refresh_interval: config.refresh_interval,
+            compression: config.compression,
+            hits: hits::HitValidator::default(),
+            wormholes: map.wormholes(),
+            teleports: teleports::TeleportValidator::default(),
+            lobbies: lobby::Lobbies::new(config.countdown, config.match_length),
+            sessions: HashMap::new(),
+            secure: config.secure,
+            kicked: HashSet::new(),
+            tick_interval: Duration::from_secs(1) / config.tick_rate,
+            metrics: metrics::Metrics::new(
+                config.metrics_interval,
+                Instant::now(),
+            ),
+            profiles: profiles::ProfileStore::new(
+                config.profiles_dir.as_deref(),
+            ),
+            ownership: ownership::Ownership::default(),
+            throttle: throttle::Throttle::new(config.client_bandwidth),
         }
     }
 }
-/// Gets the response to a login client request
+/// Gets the response to a login client request. Messages are compressed for
+/// the client from then on if both the client and server support it
 fn login_response(
     username: String,
+    compression: bool,
     user_addr: &SocketAddr,
     state: &mut ServerState,
 ) -> ServerCommandType {
+    // players are spread between the spawn points in the order they joined
+    let spawn_pos = state
+        .spawn_points
+        .iter()
+        .cycle()
+        .nth(state.users.len().saturating_sub(1))
+        .copied()
+        .unwrap_or_default();
     let user_state = state.users.get_mut(user_addr).unwrap();
+    user_state.profile = state.profiles.load(&username);
     user_state.username = username;
-    let starting_id = state.last_obj_id;
-    state.last_obj_id = state.last_obj_id.incr(1024);
+    user_state.compression = compression && state.compression;
+    let starting_ids = state.reserve_ids(id_list::ID_BLOCK_SIZE, *user_addr);
     ServerCommandType::ReturnLogin(LoginInfo {
         pid: user_state.id,
         lighting: state.server_lighting.clone(),
-        spawn_pos: [0., 0., 0.],
-        starting_ids: (starting_id, state.last_obj_id),
+        spawn_pos,
+        starting_ids,
+        map_seed: state.map_seed,
+        compression: user_state.compression,
+        profile: user_state.profile.clone(),
     })
 }
-/// Gets the response to a client object update request
+/// Gets the response to a client object update request. Objects the client
+/// doesn't own are dropped from the update
 fn update_response(
-    objects: Vec<RemoteObject>,
+    mut objects: Vec<RemoteObject>,
     user_addr: &SocketAddr,
     state: &mut ServerState,
 ) -> ServerCommandType {
-    let user_state = state.users.get_mut(user_addr).unwrap();
-    user_state.client_objects = objects;
-    ServerCommandType::Update(state.get_all_objects(user_addr))
+    objects.retain(|obj| state.ownership.may_update(user_addr, obj.id));
+    state.set_client_objects(user_addr, objects);
+    ServerCommandType::Update(state.get_visible_objects(user_addr))
+}
+
+/// Gets the response to a client's hit claim. Plausible hits are confirmed
+/// to every client, and clients whose claims are rejected are sent the
+/// objects near them to correct their view of the game
+fn hit_response(
+    claim: HitClaim,
+    user_addr: &SocketAddr,
+    socket: &UdpSocket,
+    state: &mut ServerState,
+) -> ServerCommandType {
+    let result = if state.lobbies.lobby_of(user_addr).is_some()
+        && !state.lobbies.in_match(user_addr)
+    {
+        Err("the client's match hasn't started".to_owned())
+    } else {
+        state.validate_hit(user_addr, &claim)
+    };
+    if let Err(reason) = result {
+        warn!("Rejected hit claimed by {}: {}", user_addr, reason);
+        return ServerCommandType::Update(state.get_visible_objects(user_addr));
+    }
+    state.lobbies.record_hit(user_addr);
+    let confirmation = ServerCommandType::ConfirmHit(claim);
+    let others: Vec<_> = state
+        .users
+        .keys()
+        .filter(|addr| *addr != user_addr)
+        .copied()
+        .collect();
+    for addr in others {
+        let msg_id = get_last_msg_id(state, addr);
+        send_to_client(socket, &addr, &confirmation, msg_id, state);
+    }
+    confirmation
+}
+
+/// Gets the response to a teleport claimed by a client. Plausible teleports
+/// are confirmed to the client, and clients whose claims are rejected are
+/// sent the objects near them to correct their view of the game
+fn teleport_response(
+    claim: wormhole::TeleportClaim,
+    user_addr: &SocketAddr,
+    state: &mut ServerState,
+) -> ServerCommandType {
+    if let Err(reason) = state.validate_teleport(user_addr, &claim) {
+        warn!("Rejected teleport claimed by {}: {}", user_addr, reason);
+        return ServerCommandType::Update(state.get_visible_objects(user_addr));
+    }
+    ServerCommandType::ConfirmTeleport(claim)
+}
+
+/// Sends `cmd` to the client at `addr`, compressed if the client accepted
+/// compressed messages at login and sealed if the client started a session
+///
+/// Updates are held back if the client is over its send budget
+fn send_to_client(
+    socket: &UdpSocket,
+    addr: &SocketAddr,
+    cmd: &ServerCommandType,
+    msg_id: MsgId,
+    state: &mut ServerState,
+) {
+    let now = Instant::now();
+    if !state.throttle.admit(*addr, cmd, msg_id, now) {
+        return;
+    }
+    let compress = state
+        .users
+        .get(addr)
+        .map_or(false, |client| client.compression);
+    match send_data_with(
+        socket,
+        addr,
+        cmd,
+        msg_id,
+        compress,
+        state.sessions.get(addr),
+    ) {
+        Ok(bytes) => state.throttle.record_sent(*addr, bytes, now),
+        Err(error) => error!("Error sending data: {}", error),
+    }
+}
+
+/// Sends the updates that were held back from clients whose send budgets
+/// have refilled
+fn send_deferred(socket: &UdpSocket, state: &mut ServerState) {
+    for (addr, cmd, msg_id) in state.throttle.take_due(Instant::now()) {
+        send_to_client(socket, &addr, &cmd, msg_id, state);
+    }
+}
+
+/// Answers a client's handshake with the server's public key, and starts
+/// the session every later message to and from the client is sealed with
+///
+/// The answer isn't sealed, since the client can't open it until it has the
+/// server's key. A session can only be replaced once it's idle, so a spoofed
+/// handshake can't take over a client's session
+fn handshake_response(
+    client_key: [u8; session::PUBLIC_KEY_SIZE],
+    user_addr: &SocketAddr,
+    msg_id: MsgId,
+    socket: &UdpSocket,
+    state: &mut ServerState,
+) {
+    let pending = state.active_session(user_addr).map(|session| {
+        (
+            session.is_established(),
+            session.peer_key(),
+            session.public_key(),
+        )
+    });
+    match pending {
+        Some((true, ..)) => {
+            warn!("Rejected handshake from {}: session in use", user_addr);
+            return;
+        }
+        // the client didn't receive the reply, so it's sent again without
+        // being sealed. The session is only replaced if the client started
+        // a new handshake
+        Some((false, peer_key, public_key)) if peer_key == client_key => {
+            let session = state.sessions.remove(user_addr);
+            let response = ServerCommandType::Handshake(public_key);
+            send_to_client(socket, user_addr, &response, msg_id, state);
+            state.sessions.extend(session.map(|s| (*user_addr, s)));
+            return;
+        }
+        _ => (),
+    }
+    state.sessions.remove(user_addr);
+    let handshake = session::Handshake::new();
+    let response = ServerCommandType::Handshake(handshake.public_key());
+    send_to_client(socket, user_addr, &response, msg_id, state);
+    state.sessions.insert(
+        *user_addr,
+        handshake.finish(client_key, session::Role::Server),
+    );
+}
+
+/// Sends each message of `out` to its client. The results of the matches
+/// that ended are recorded in the profiles of their players, who are sent
+/// their new profiles
+fn send_all(out: lobby::Outgoing, socket: &UdpSocket, state: &mut ServerState) {
+    for (addr, cmd) in out {
+        let profile = match &cmd {
+            ServerCommandType::MatchEnd(results) => {
+                record_results(results, &addr, state)
+            }
+            _ => None,
+        };
+        for cmd in std::iter::once(cmd).chain(profile) {
+            let msg_id = get_last_msg_id(state, addr);
+            send_to_client(socket, &addr, &cmd, msg_id, state);
+        }
+    }
+}
+
+/// Records the match with `results` in the profile of the player at
+/// `user_addr`, and saves it
+///
+/// Returns the message sending the player their new profile
+fn record_results(
+    results: &lobby::MatchResults,
+    user_addr: &SocketAddr,
+    state: &mut ServerState,
+) -> Option<ServerCommandType> {
+    let user = state.users.get_mut(user_addr)?;
+    let hits = results
+        .scores
+        .iter()
+        .find(|(pid, _)| *pid == user.id)
+        .map_or(0, |(_, score)| *score);
+    user.profile
+        .record_match(hits, results.winner() == Some(user.id));
+    state.profiles.save(&user.username, &user.profile);
+    Some(ServerCommandType::Profile(user.profile.clone()))
+}
+
+/// Gets the response to a client's request to replace its loadouts, which
+/// is the client's profile whether or not the request was accepted. The
+/// loadouts can't change during a match
+fn profile_response(
+    loadouts: Vec<profile::Loadout>,
+    user_addr: &SocketAddr,
+    state: &mut ServerState,
+) -> ServerCommandType {
+    let in_match = state.lobbies.in_match(user_addr);
+    let user = state.users.get_mut(user_addr).unwrap();
+    let result = if in_match {
+        Err("the client is playing a match".to_owned())
+    } else if user.username.is_empty() {
+        Err("the client hasn't logged in".to_owned())
+    } else {
+        user.profile.set_loadouts(loadouts)
+    };
+    match result {
+        Ok(()) => state.profiles.save(&user.username, &user.profile),
+        Err(reason) => {
+            warn!("Rejected profile update from {}: {}", user_addr, reason);
+        }
+    }
+    ServerCommandType::Profile(user.profile.clone())
+}
+
+/// Gets the response to a client's lobby request. The client is sent the
+/// new state of its lobby along with the other members, so only a rejected
+/// request has a response of its own, which is the state of the lobby the
+/// client tried to join, if any
+fn lobby_response(
+    msg: ClientCommandType,
+    user_addr: &SocketAddr,
+    socket: &UdpSocket,
+    state: &mut ServerState,
+) -> Option<ServerCommandType> {
+    let now = std::time::Instant::now();
+    let (result, lobby_name) = match msg {
+        ClientCommandType::JoinLobby(name) => {
+            let user = &state.users[user_addr];
+            let result = state.lobbies.join(
+                *user_addr,
+                user.id,
+                &user.username,
+                &name,
+                now,
+            );
+            (result, Some(name))
+        }
+        ClientCommandType::SetReady(ready) => {
+            (state.lobbies.set_ready(user_addr, ready, now), None)
+        }
+        ClientCommandType::LeaveLobby => {
+            (Ok(state.lobbies.leave(user_addr, now)), None)
+        }
+        _ => unreachable!("Not a lobby request"),
+    };
+    match result {
+        Ok(out) => {
+            send_all(out, socket, state);
+            None
+        }
+        Err(reason) => {
+            warn!("Rejected lobby request from {}: {}", user_addr, reason);
+            lobby_name
+                .and_then(|name| state.lobbies.info(&name, now))
+                .map(ServerCommandType::LobbyState)
+        }
+    }
+}
+
+/// Starts and ends the matches of the lobbies that are due
+fn update_lobbies(socket: &UdpSocket, state: &mut ServerState) {
+    let out = state.lobbies.update(
+        std::time::Instant::now(),
+        state.map_seed,
+        &state.spawn_points,
+    );
+    send_all(out, socket, state);
+}
+
+/// Gets the line describing each connected client
+fn list_clients(state: &ServerState) -> String {
+    let mut lines: Vec<_> = state
+        .users
+        .iter()
+        .map(|(addr, client)| {
+            format!(
+                "{} id: {}, name: \"{}\", objects: {}, lobby: {}",
+                addr,
+                client.id.as_underlying_type(),
+                client.username,
+                client.client_objects.len(),
+                state.lobbies.lobby_of(addr).unwrap_or("none")
+            )
+        })
+        .collect();
+    lines.sort();
+    if lines.is_empty() {
+        "No clients connected".to_owned()
+    } else {
+        lines.join("\n")
+    }
+}
+
+/// Runs `command` from the admin console, getting its result
+fn run_admin_command(
+    command: admin::AdminCommand,
+    socket: &UdpSocket,
+    state: &mut ServerState,
+) -> String {
+    use admin::AdminCommand::*;
+    match command {
+        List => list_clients(state),
+        Kick(client) => {
+            let addr = client.parse::<SocketAddr>().ok().or_else(|| {
+                state
+                    .users
+                    .iter()
+                    .find(|(_, data)| data.username == client)
+                    .map(|(addr, _)| *addr)
+            });
+            match addr {
+                Some(addr) if state.users.contains_key(&addr) => {
+                    let out = state.kick(&addr);
+                    send_all(out, socket, state);
+                    format!("Kicked {}", addr)
+                }
+                _ => format!("No client {}", client),
+            }
+        }
+        Map(name) => {
+            match argument_parser::MapType::try_from(name.as_str())
+                .map_err(Box::<dyn Error>::from)
+                .and_then(|map| map.get_game_map())
+            {
+                Ok(map) => {
+                    state.set_map(&*map);
+                    format!("Changed the map to {}", name)
+                }
+                Err(error) => format!("Could not change the map: {}", error),
+            }
+        }
+        TickRate(rate) => {
+            state.tick_interval = Duration::from_secs(1) / rate;
+            format!("Set the tick rate to {} Hz", rate)
+        }
+        Metrics => state.metrics.latest().to_string(),
+        Help => admin::HELP.to_owned(),
+    }
+}
+
+/// Hands off the objects that are closer to another client's ship than to
+/// their owner's, and abandons the transfers that timed out
+fn update_ownership(socket: &UdpSocket, state: &mut ServerState) {
+    let now = Instant::now();
+    let ships: Vec<_> = state
+        .users
+        .iter()
+        .filter_map(|(addr, client)| {
+            interest::client_pos(&client.client_objects, client.id)
+                .map(|pos| (*addr, pos))
+        })
+        .collect();
+    let objects = state.users.iter().flat_map(|(addr, client)| {
+        client.client_objects.iter().map(move |obj| (*addr, obj))
+    });
+    let mut out = state.ownership.follow_proximity(objects, &ships, now);
+    out.append(&mut state.ownership.expire(now));
+    send_all(out, socket, state);
+}
+
+/// Updates the lobbies and the owners of objects, sends the updates held
+/// back by the throttle, runs the commands sent from the admin console, and summarizes the metrics once their interval
+/// is over. `reassembly` counts what happened to the received packets
+fn tick(
+    socket: &UdpSocket,
+    admin: &admin::Admin,
+    state: &mut ServerState,
+    reassembly: BufferStats,
+) {
+    let _span = trace_span!("tick").entered();
+    update_lobbies(socket, state);
+    update_ownership(socket, state);
+    send_deferred(socket, state);
+    for request in admin.pending() {
+        let result = run_admin_command(request.command.clone(), socket, state);
+        request.reply(result);
+    }
+    let now = Instant::now();
+    if state.metrics.is_due(now) {
+        let summary = state.summarize_metrics(now, reassembly);
+        info!("Metrics: {}", summary);
+        admin.publish_metrics(summary.to_prometheus());
+    }
 }
 
 /// Gets the response to a client id allocation request
 fn id_fetch_response(
     alloc_size: u32,
+    user_addr: &SocketAddr,
     state: &mut ServerState,
 ) -> ServerCommandType {
-    let starting_id = state.last_obj_id;
-    state.last_obj_id = state.last_obj_id.incr(alloc_size);
-    ServerCommandType::ReturnIds((starting_id, state.last_obj_id))
+    ServerCommandType::ReturnIds(state.reserve_ids(alloc_size, *user_addr))
 }
 
 /// Gets the last message id sent to the client, and increments it
 /// for the next message
 fn get_last_msg_id(state: &mut ServerState, user_addr: SocketAddr) -> u32 {
-    let mut user_state = state
-        .users
-        .entry(user_addr)
-        .or_insert_with(|| ClientData::new(state.last_obj_id.consume()));
+    let mut user_state = state.users.entry(user_addr).or_insert_with(|| {
+        ClientData::new(state.ids.next_id().unwrap_or_default())
+    });
     let last_msg_id = user_state.last_msg_id;
     user_state.last_msg_id += 1;
     last_msg_id
@@ -149,52 +781,153 @@ fn respond_to_msg(
     msg: ClientCommandType,
     socket: &UdpSocket,
     addr: SocketAddr,
-    mut state: ServerState,
-) -> ServerState {
+    state: &mut ServerState,
+) {
     use ClientCommandType::*;
-    let last_msg_id = get_last_msg_id(&mut state, addr);
+    let _span = debug_span!("message", client = %addr).entered();
+    if state.kicked.contains(&addr) {
+        return;
+    }
+    // only a handshake can be unsealed once a client has had a session.
+    // Sealed messages establish the session as they're opened
+    let unsealed = state
+        .active_session(&addr)
+        .map_or(true, |session| !session.is_established());
+    if unsealed
+        && (state.secure || state.sessions.contains_key(&addr))
+        && !matches!(msg, Handshake(_))
+    {
+        warn!("Dropped unsealed message from {}", addr);
+        return;
+    }
+    let last_msg_id = get_last_msg_id(state, addr);
     let response = match msg {
-        Login(username) => login_response(username, &addr, &mut state),
-        Update(objects) => update_response(objects, &addr, &mut state),
+        Login(username, compression) => {
+            Some(login_response(username, compression, &addr, state))
+        }
+        Update(objects) => Some(update_response(objects, &addr, state)),
         UpdateReadOnly(objects) => {
-            update_response(objects.to_vec(), &addr, &mut state)
+            Some(update_response(objects.to_vec(), &addr, state))
+        }
+        GetIds(alloc_size) => Some(id_fetch_response(alloc_size, &addr, state)),
+        ClaimHit(claim) => Some(hit_response(claim, &addr, socket, state)),
+        ClaimTeleport(claim) => Some(teleport_response(claim, &addr, state)),
+        Ping(token) => Some(ServerCommandType::Pong(token)),
+        Handshake(client_key) => {
+            handshake_response(client_key, &addr, last_msg_id, socket, state);
+            None
+        }
+        msg @ (JoinLobby(_) | SetReady(_) | LeaveLobby) => {
+            lobby_response(msg, &addr, socket, state)
+        }
+        UpdateProfile(loadouts) => {
+            Some(profile_response(loadouts, &addr, state))
+        }
+        RequestOwnership(id) => {
+            let now = Instant::now();
+            let out = state.ownership.request(id, addr, now);
+            send_all(out, socket, state);
+            None
+        }
+        ReleaseOwnership(id, released) => {
+            let now = Instant::now();
+            let out = state.ownership.release(id, addr, released, now);
+            send_all(out, socket, state);
+            None
+        }
+        AckOwnership(id) => {
+            state.ownership.ack(id, addr);
+            None
         }
-        GetIds(alloc_size) => id_fetch_response(alloc_size, &mut state),
     };
-    if let Err(error) = send_data(socket, &addr, &response, last_msg_id) {
-        println!("Error sending data: {}", error);
+    if let Some(response) = response {
+        send_to_client(socket, &addr, &response, last_msg_id, state);
     }
-    state
 }
 
 fn run_game_server(
     config: &ServerConfiguration,
     stop_token: &Arc<AtomicBool>,
 ) -> Result<(), Box<dyn Error>> {
-    use std::time::Duration;
     let socket = UdpSocket::bind(("127.0.0.1", config.port))?;
-    socket.set_read_timeout(Some(Duration::from_secs(3)))?;
+    let admin = admin::Admin::spawn(
+        config.console,
+        config.admin_port,
+        config.metrics_port,
+        stop_token,
+    )?;
     let mut data: ClientBuffer<ClientCommandType> = ClientBuffer::new();
-    let mut state = ServerState::new(config.map.get_game_map());
+    let mut state = ServerState::new(config.map.get_game_map()?, config);
+    let mut next_tick = Instant::now();
     while !stop_token.load(Ordering::SeqCst) {
-        state = match recv_data(&socket, &mut data) {
-            Ok(Some((cmd, src))) => {
-                clear_old_messages(
-                    &mut data,
-                    std::time::Duration::from_secs(10),
+        // the server ticks whenever a read times out, or is due during a
+        // stream of messages
+        socket.set_read_timeout(Some(state.tick_interval))?;
+        let received = recv_data_secure(&socket, &mut data, |addr| {
+            state.active_session(addr)
+        });
+        if let Ok(Some((cmd, src))) = received {
+            let start = Instant::now();
+            let responded = panic::catch_unwind(AssertUnwindSafe(|| {
+                respond_to_msg(cmd, &socket, src, &mut state);
+            }));
+            // the state of the client whose message caused the panic may
+            // be inconsistent, so it's disconnected
+            if let Err(payload) = responded {
+                error!(
+                    "Disconnecting {} after its message panicked: {}",
+                    src,
+                    crash::panic_message(&*payload)
                 );
-                respond_to_msg(cmd, &socket, src, state)
+                let out = state.kick(&src);
+                send_all(out, &socket, &mut state);
             }
-            _ => state,
+            state.metrics.record(start.elapsed());
+        }
+        if Instant::now() >= next_tick {
+            let ticked = panic::catch_unwind(AssertUnwindSafe(|| {
+                tick(&socket, &admin, &mut state, data.stats());
+            }));
+            if let Err(payload) = ticked {
+                error!(
+                    "Skipped the rest of a tick which panicked: {}",
+                    crash::panic_message(&*payload)
+                );
+            }
+            next_tick = Instant::now() + state.tick_interval;
         }
     }
     Ok(())
 }
 
+/// Logs the events of every thread to stdout at the levels of `filter`,
+/// unless `RUST_LOG` is set
+fn init_logging(filter: &str) -> Result<(), Box<dyn Error>> {
+    use tracing_subscriber::util::SubscriberInitExt;
+    use tracing_subscriber::EnvFilter;
+    let filter = EnvFilter::try_from_default_env()
+        .or_else(|_| EnvFilter::try_new(filter))?;
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .finish()
+        .try_init()?;
+    Ok(())
+}
+
+/// Logs a crash report of each panic, which the server recovers from where
+/// possible, instead of printing it
+fn install_panic_hook() {
+    panic::set_hook(Box::new(|info| {
+        error!("{}", crash::report(info, &[]));
+    }));
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
     let config = argument_parser::parse_args(std::env::args())?;
+    init_logging(&config.log_filter)?;
+    install_panic_hook();
 
-    println!("Starting server with config:\n{}", config);
+    info!("Starting server with config:\n{}", config);
 
     run_game_server(&config, &Arc::new(AtomicBool::new(false)))
 }
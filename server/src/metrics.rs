@@ -0,0 +1,187 @@
+use core::fmt::Display;
+use shared_types::remote::BufferStats;
+use std::time::{Duration, Instant};
+
+/// Default time between the summaries of the server's metrics
+pub const DEFAULT_METRICS_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The state of the server over one interval of its metrics
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Summary {
+    pub players: usize,
+    pub messages_per_sec: f64,
+    /// Average time the server took to respond to a message
+    pub avg_response_time: Duration,
+    /// Number of objects the clients have sent
+    pub client_objects: usize,
+    /// Number of objects the map spawned
+    pub server_objects: usize,
+    /// Messages handled since the server started
+    pub total_messages: u64,
+    /// What happened to the packets received since the server started
+    pub reassembly: BufferStats,
+}
+
+impl Summary {
+    /// Formats the summary in the Prometheus text format
+    #[must_use]
+    pub fn to_prometheus(&self) -> String {
+        let metrics = [
+            ("players", "gauge", "Players connected", self.players as f64),
+            (
+                "messages_per_second",
+                "gauge",
+                "Messages handled per second",
+                self.messages_per_sec,
+            ),
+            (
+                "response_seconds",
+                "gauge",
+                "Average time taken to respond to a message",
+                self.avg_response_time.as_secs_f64(),
+            ),
+            (
+                "client_objects",
+                "gauge",
+                "Objects sent by clients",
+                self.client_objects as f64,
+            ),
+            (
+                "server_objects",
+                "gauge",
+                "Objects spawned by the map",
+                self.server_objects as f64,
+            ),
+            (
+                "messages_total",
+                "counter",
+                "Messages handled since the server started",
+                self.total_messages as f64,
+            ),
+            (
+                "messages_expired_total",
+                "counter",
+                "Incomplete messages dropped for not receiving packets",
+                self.reassembly.expired as f64,
+            ),
+            (
+                "messages_rejected_total",
+                "counter",
+                "Incomplete messages dropped for invalid packets",
+                self.reassembly.rejected as f64,
+            ),
+            (
+                "packets_out_of_order_total",
+                "counter",
+                "Packets received after a later packet of their message",
+                self.reassembly.out_of_order as f64,
+            ),
+        ];
+        metrics
+            .iter()
+            .map(|(name, typ, help, value)| {
+                format!(
+                    "# HELP oort_{0} {1}\n\
+                    # TYPE oort_{0} {2}\n\
+                    oort_{0} {3}\n",
+                    name, help, typ, value
+                )
+            })
+            .collect()
+    }
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "players: {}, messages/s: {:.1}, response time: {:?}, \
+            client objects: {}, server objects: {}, packets: {}",
+            self.players,
+            self.messages_per_sec,
+            self.avg_response_time,
+            self.client_objects,
+            self.server_objects,
+            self.reassembly
+        )
+    }
+}
+
+/// Measures the messages the server handles, summarizing them once per
+/// interval
+pub struct Metrics {
+    interval: Duration,
+    interval_start: Instant,
+    /// Messages handled in the current interval
+    messages: u64,
+    /// Total time taken to respond to the messages of the current interval
+    response_time: Duration,
+    total_messages: u64,
+    latest: Summary,
+}
+
+impl Metrics {
+    pub fn new(interval: Duration, now: Instant) -> Self {
+        Self {
+            interval,
+            interval_start: now,
+            messages: 0,
+            response_time: Duration::ZERO,
+            total_messages: 0,
+            latest: Summary::default(),
+        }
+    }
+
+    /// Records a message which took `response_time` to respond to
+    pub fn record(&mut self, response_time: Duration) {
+        self.messages += 1;
+        self.total_messages += 1;
+        self.response_time += response_time;
+    }
+
+    /// Summarizes the current interval, in which the server has `players`
+    /// and the objects counted by `client_objects` and `server_objects`,
+    /// and has reassembled packets as counted by `reassembly`, and starts
+    /// the next one
+    pub fn summarize(
+        &mut self,
+        now: Instant,
+        players: usize,
+        client_objects: usize,
+        server_objects: usize,
+        reassembly: BufferStats,
+    ) -> &Summary {
+        let elapsed = now.saturating_duration_since(self.interval_start);
+        self.latest = Summary {
+            players,
+            messages_per_sec: if elapsed.is_zero() {
+                0.
+            } else {
+                self.messages as f64 / elapsed.as_secs_f64()
+            },
+            avg_response_time: if self.messages == 0 {
+                Duration::ZERO
+            } else {
+                self.response_time.div_f64(self.messages as f64)
+            },
+            client_objects,
+            server_objects,
+            total_messages: self.total_messages,
+            reassembly,
+        };
+        self.interval_start = now;
+        self.messages = 0;
+        self.response_time = Duration::ZERO;
+        &self.latest
+    }
+
+    /// `true` if the current interval is over at `now`
+    pub fn is_due(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.interval_start) >= self.interval
+    }
+
+    /// Gets the summary of the last interval
+    pub const fn latest(&self) -> &Summary {
+        &self.latest
+    }
+}
@@ -0,0 +1,307 @@
+use crate::hits::distance;
+use crate::interest::object_pos;
+use crate::lobby::Outgoing;
+use shared_types::{ObjectId, ObjectType, RemoteObject, ServerCommandType};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// Longest time each step of a transfer can take before it's abandoned
+pub const HANDOFF_TIMEOUT: Duration = Duration::from_secs(2);
+/// An object is handed off to the client whose ship is closest to it once
+/// that ship is closer than this fraction of the distance to the owner's
+/// ship. Keeps objects from bouncing between clients at similar distances
+pub const HANDOFF_RATIO: f64 = 0.5;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Stage {
+    /// Waiting for the owner to stop simulating the object
+    AwaitingRelease,
+    /// Waiting for the new owner to confirm it's simulating the object
+    AwaitingAck,
+}
+
+/// An object moving from one owner to another
+#[derive(Copy, Clone, Debug)]
+struct Transfer {
+    /// The previous owner, or `None` if the server owned the object
+    from: Option<SocketAddr>,
+    to: SocketAddr,
+    stage: Stage,
+    /// When the current stage started
+    since: Instant,
+}
+
+/// `true` if the authority over `obj` can move between clients. Ships and
+/// lasers are always simulated by the client that created them
+pub const fn is_transferable(obj: &RemoteObject) -> bool {
+    !matches!(obj.typ, ObjectType::Ship | ObjectType::Laser)
+}
+
+/// Which client simulates each object
+///
+/// A client owns the objects with the ids it was handed out, unless they
+/// were transferred. Objects whose ids weren't handed out to a client, like
+/// the map's, are owned by the server. The server doesn't simulate them, so
+/// they're granted to the first client that asks
+///
+/// A transfer is a handshake. The requester asks the server for the object,
+/// the server asks the owner to release it, the owner releases or refuses
+/// it, and the server grants the object to the requester, which
+/// acknowledges taking it over. The transfer only takes effect once it's
+/// acknowledged, and it's abandoned if a step times out
+#[derive(Default)]
+pub struct Ownership {
+    /// Id ranges `[start, end)` handed out to each client, ordered by their
+    /// starts. The ranges of clients that disconnected are owned by the
+    /// server
+    ranges: Vec<(u32, u32, Option<SocketAddr>)>,
+    /// Owners of the objects which were transferred away from the clients
+    /// their ids were handed out to. `None` is the server
+    transferred: HashMap<ObjectId, Option<SocketAddr>>,
+    pending: HashMap<ObjectId, Transfer>,
+}
+
+impl Ownership {
+    /// Records that the ids `[ids.0, ids.1)` were handed out to `client`
+    pub fn assign(&mut self, ids: (ObjectId, ObjectId), client: SocketAddr) {
+        let (start, end) =
+            (ids.0.as_underlying_type(), ids.1.as_underlying_type());
+        let i = self.ranges.partition_point(|(s, ..)| *s < start);
+        self.ranges.insert(i, (start, end, Some(client)));
+    }
+
+    /// Gets the owner of `id`, or `None` if the server owns it
+    pub fn owner_of(&self, id: ObjectId) -> Option<SocketAddr> {
+        if let Some(owner) = self.transferred.get(&id) {
+            return *owner;
+        }
+        let id = id.as_underlying_type();
+        let i = self.ranges.partition_point(|(start, ..)| *start <= id);
+        i.checked_sub(1)
+            .map(|i| self.ranges[i])
+            .filter(|(_, end, _)| id < *end)
+            .and_then(|(.., client)| client)
+    }
+
+    /// `true` if `client` may send updates of `id`. Objects owned by the
+    /// server can be updated by any client
+    pub fn may_update(&self, client: &SocketAddr, id: ObjectId) -> bool {
+        self.owner_of(id).map_or(true, |owner| owner == *client)
+    }
+
+    /// Makes `owner` the owner of `id`
+    fn set_owner(&mut self, id: ObjectId, owner: Option<SocketAddr>) {
+        self.transferred.remove(&id);
+        if self.owner_of(id) != owner {
+            self.transferred.insert(id, owner);
+        }
+    }
+
+    /// Starts the transfer of `id` to `to`, asking its owner to release it
+    /// or granting it right away if the server owns it
+    fn start(
+        &mut self,
+        id: ObjectId,
+        to: SocketAddr,
+        now: Instant,
+    ) -> Outgoing {
+        let from = self.owner_of(id);
+        let stage = if from.is_some() {
+            Stage::AwaitingRelease
+        } else {
+            Stage::AwaitingAck
+        };
+        self.pending.insert(
+            id,
+            Transfer {
+                from,
+                to,
+                stage,
+                since: now,
+            },
+        );
+        match from {
+            Some(from) => vec![(from, ServerCommandType::HandoffRequest(id))],
+            None => vec![(to, ServerCommandType::OwnershipGranted(id))],
+        }
+    }
+
+    /// Handles the request of `requester` for the authority over `id`
+    ///
+    /// Returns the messages of the next step of the transfer
+    pub fn request(
+        &mut self,
+        id: ObjectId,
+        requester: SocketAddr,
+        now: Instant,
+    ) -> Outgoing {
+        if self.owner_of(id) == Some(requester) {
+            return vec![(requester, ServerCommandType::OwnershipGranted(id))];
+        }
+        match self.pending.get(&id) {
+            // the request was already received
+            Some(transfer) if transfer.to == requester => Vec::new(),
+            Some(_) => {
+                vec![(requester, ServerCommandType::OwnershipDenied(id))]
+            }
+            None => self.start(id, requester, now),
+        }
+    }
+
+    /// Handles the answer of `owner` to the request to hand off `id`
+    ///
+    /// Returns the message telling the requester whether it got the object
+    pub fn release(
+        &mut self,
+        id: ObjectId,
+        owner: SocketAddr,
+        released: bool,
+        now: Instant,
+    ) -> Outgoing {
+        let transfer = match self.pending.get_mut(&id) {
+            Some(transfer)
+                if transfer.stage == Stage::AwaitingRelease
+                    && transfer.from == Some(owner) =>
+            {
+                transfer
+            }
+            _ => return Vec::new(),
+        };
+        let to = transfer.to;
+        if released {
+            transfer.stage = Stage::AwaitingAck;
+            transfer.since = now;
+            vec![(to, ServerCommandType::OwnershipGranted(id))]
+        } else {
+            self.pending.remove(&id);
+            vec![(to, ServerCommandType::OwnershipDenied(id))]
+        }
+    }
+
+    /// Handles `client` acknowledging that it took over `id`, which
+    /// completes the transfer
+    pub fn ack(&mut self, id: ObjectId, client: SocketAddr) {
+        let acked = self.pending.get(&id).map_or(false, |transfer| {
+            transfer.stage == Stage::AwaitingAck && transfer.to == client
+        });
+        if acked {
+            self.pending.remove(&id);
+            self.set_owner(id, Some(client));
+        }
+    }
+
+    /// Abandons the transfers whose current step took longer than
+    /// `HANDOFF_TIMEOUT`. An object which was released but never
+    /// acknowledged is granted back to its previous owner
+    ///
+    /// Returns the messages telling the clients of the abandoned transfers
+    pub fn expire(&mut self, now: Instant) -> Outgoing {
+        let expired: Vec<_> = self
+            .pending
+            .iter()
+            .filter(|(_, t)| {
+                now.saturating_duration_since(t.since) > HANDOFF_TIMEOUT
+            })
+            .map(|(id, t)| (*id, *t))
+            .collect();
+        let mut out = Vec::new();
+        for (id, transfer) in expired {
+            self.pending.remove(&id);
+            out.push((transfer.to, ServerCommandType::OwnershipDenied(id)));
+            if let (Stage::AwaitingAck, Some(from)) =
+                (transfer.stage, transfer.from)
+            {
+                out.push((from, ServerCommandType::OwnershipGranted(id)));
+            }
+        }
+        out
+    }
+
+    /// Starts handing off each transferable object in `objects`, with the
+    /// client that sent it, to the client whose ship in `ships` is much
+    /// closer to it than its owner's ship
+    ///
+    /// Returns the messages asking the owners to release the objects
+    pub fn follow_proximity<'a>(
+        &mut self,
+        objects: impl Iterator<Item = (SocketAddr, &'a RemoteObject)>,
+        ships: &[(SocketAddr, [f64; 3])],
+        now: Instant,
+    ) -> Outgoing {
+        let mut out = Vec::new();
+        for (sender, obj) in objects {
+            if !is_transferable(obj)
+                || self.pending.contains_key(&obj.id)
+                || self.owner_of(obj.id) != Some(sender)
+            {
+                continue;
+            }
+            let pos = object_pos(obj);
+            let owner_dist = ships
+                .iter()
+                .find(|(client, _)| *client == sender)
+                .map_or(f64::INFINITY, |(_, ship)| distance(*ship, pos));
+            let closest = ships
+                .iter()
+                .filter(|(client, _)| *client != sender)
+                .map(|(client, ship)| (*client, distance(*ship, pos)))
+                .min_by(|(_, a), (_, b)| a.total_cmp(b));
+            if let Some((client, dist)) = closest {
+                if dist < owner_dist * HANDOFF_RATIO {
+                    out.append(&mut self.start(obj.id, client, now));
+                }
+            }
+        }
+        out
+    }
+
+    /// Forgets `client`, which disconnected. Its objects return to the
+    /// server, and its transfers are abandoned
+    ///
+    /// Returns the messages telling the other clients of its transfers
+    pub fn remove_client(&mut self, client: &SocketAddr) -> Outgoing {
+        for owner in self.transferred.values_mut() {
+            if *owner == Some(*client) {
+                *owner = None;
+            }
+        }
+        // the ids stay handed out, so they aren't handed out again
+        for (.., owner) in &mut self.ranges {
+            if *owner == Some(*client) {
+                *owner = None;
+            }
+        }
+        let mut out = Vec::new();
+        self.pending.retain(|id, transfer| {
+            match (transfer.stage, transfer.from) {
+                _ if transfer.to == *client => {
+                    if let (Stage::AwaitingAck, Some(from)) =
+                        (transfer.stage, transfer.from)
+                    {
+                        out.push((
+                            from,
+                            ServerCommandType::OwnershipGranted(*id),
+                        ));
+                    }
+                    false
+                }
+                (Stage::AwaitingRelease, Some(from)) if from == *client => {
+                    out.push((
+                        transfer.to,
+                        ServerCommandType::OwnershipDenied(*id),
+                    ));
+                    false
+                }
+                // the object was already released, so the transfer can
+                // still be acknowledged
+                (Stage::AwaitingAck, Some(from)) if from == *client => {
+                    transfer.from = None;
+                    true
+                }
+                _ => true,
+            }
+        });
+        out
+    }
+}
@@ -0,0 +1,96 @@
+use shared_types::profile::Profile;
+use std::path::PathBuf;
+use tracing::error;
+
+/// Extension of profile files
+const PROFILE_EXT: &str = "profile";
+/// Extension a profile file that cannot be parsed is renamed to, so it isn't
+/// overwritten and can be recovered by hand
+const CORRUPT_EXT: &str = "corrupt";
+
+/// Gets the name of the profile file of `username`. Characters which may not
+/// be allowed in file names are escaped as `%` followed by their hex value,
+/// so different usernames never share a file
+fn file_name(username: &str) -> String {
+    username
+        .bytes()
+        .map(|byte| {
+            if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' {
+                char::from(byte).to_string()
+            } else {
+                format!("%{:02X}", byte)
+            }
+        })
+        .collect()
+}
+
+/// The profiles of the players, saved in a file per username in a directory
+///
+/// Without a directory, every player gets a new profile at login which isn't
+/// saved
+pub struct ProfileStore {
+    dir: Option<PathBuf>,
+}
+
+impl ProfileStore {
+    pub fn new(dir: Option<&str>) -> Self {
+        Self {
+            dir: dir.map(PathBuf::from),
+        }
+    }
+
+    /// Gets the path of the profile file of `username`, or `None` if the
+    /// profile isn't saved
+    fn path_of(&self, username: &str) -> Option<PathBuf> {
+        self.dir
+            .as_ref()
+            .filter(|_| !username.is_empty())
+            .map(|dir| {
+                dir.join(file_name(username)).with_extension(PROFILE_EXT)
+            })
+    }
+
+    /// Loads the profile of `username`, or a new profile if the player has
+    /// none. A profile file which cannot be parsed is moved aside, and the
+    /// player starts over with a new profile
+    pub fn load(&self, username: &str) -> Profile {
+        let path = match self.path_of(username) {
+            Some(path) if path.is_file() => path,
+            _ => return Profile::default(),
+        };
+        match std::fs::read_to_string(&path)
+            .map_err(Into::into)
+            .and_then(|file| Profile::parse(&file))
+        {
+            Ok(profile) => profile,
+            Err(error) => {
+                error!("Profile of {} is corrupt: {}", username, error);
+                let corrupt = path.with_extension(CORRUPT_EXT);
+                if let Err(error) = std::fs::rename(&path, &corrupt) {
+                    error!("Could not move corrupt profile: {}", error);
+                }
+                Profile::default()
+            }
+        }
+    }
+
+    /// Saves `profile` as the profile of `username`
+    ///
+    /// The profile is written to a temporary file which then replaces the
+    /// old profile, so a crash while saving can't corrupt it
+    pub fn save(&self, username: &str, profile: &Profile) {
+        let path = match self.path_of(username) {
+            Some(path) => path,
+            None => return,
+        };
+        let temp = path.with_extension("tmp");
+        let result = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(&temp, profile.to_string()))
+            .and_then(|_| std::fs::rename(&temp, &path));
+        if let Err(error) = result {
+            error!("Could not save profile of {}: {}", username, error);
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use crate::hits::distance;
+use crate::interest::object_pos;
+use shared_types::wormhole::{TeleportClaim, Wormhole, TELEPORT_COOLDOWN};
+use shared_types::{ObjectId, RemoteObject};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Farthest a body can be from the edge of a portal's sensor when it enters
+/// the portal. Generous, since the server's positions lag behind the
+/// clients'
+const PORTAL_TOLERANCE: f64 = 40.;
+/// How much sooner than the cooldown a body's next teleport may be claimed,
+/// since claims can arrive closer together than they were sent
+const COOLDOWN_SLACK: Duration = Duration::from_millis(300);
+
+/// Checks the teleports clients claim against the server's view of the
+/// game, so a client can't teleport its bodies wherever it likes
+#[derive(Default)]
+pub struct TeleportValidator {
+    /// Time of the last accepted teleport of each body whose cooldown
+    /// isn't up yet
+    last_teleports: HashMap<ObjectId, Instant>,
+}
+
+impl TeleportValidator {
+    /// Checks that `claim` is plausible. The body must be one of
+    /// `own_objects`, the objects of the claiming client, the portal it
+    /// entered must be one of `wormholes`, the body must be at that portal,
+    /// and the body can't go through a wormhole again before its cooldown
+    /// is up
+    ///
+    /// Accepted teleports restart the body's cooldown. Bodies whose
+    /// cooldowns are up by `now` are forgotten
+    ///
+    /// # Errors
+    /// Returns the reason the teleport was rejected
+    pub fn validate(
+        &mut self,
+        claim: &TeleportClaim,
+        own_objects: &[RemoteObject],
+        wormholes: &[Wormhole],
+        now: Instant,
+    ) -> Result<(), String> {
+        self.last_teleports.retain(|_, last| {
+            now.duration_since(*last) + COOLDOWN_SLACK < TELEPORT_COOLDOWN
+        });
+        let body = own_objects
+            .iter()
+            .find(|obj| obj.id == claim.body)
+            .ok_or("body isn't owned by the client")?;
+        let wormhole = wormholes
+            .get(usize::from(claim.wormhole))
+            .ok_or("wormhole doesn't exist")?;
+        let portal = wormhole
+            .ends
+            .get(usize::from(claim.entry))
+            .ok_or("portal doesn't exist")?;
+        if distance(object_pos(body), portal.pos)
+            > wormhole.radius + PORTAL_TOLERANCE
+        {
+            return Err("body isn't at the portal".to_owned());
+        }
+        if self.last_teleports.contains_key(&claim.body) {
+            return Err("body's teleport is cooling down".to_owned());
+        }
+        self.last_teleports.insert(claim.body, now);
+        Ok(())
+    }
+
+    /// Forgets the cooldowns of `objects`, which have left the game
+    pub fn remove(&mut self, objects: &[RemoteObject]) {
+        for obj in objects {
+            self.last_teleports.remove(&obj.id);
+        }
+    }
+
+    /// Number of bodies whose cooldowns are being tracked
+    #[cfg(test)]
+    pub fn tracked_bodies(&self) -> usize {
+        self.last_teleports.len()
+    }
+}
@@ -11,7 +11,6 @@ extern crate cgmath;
 extern crate serial_test;
 #[cfg(test)]
 use super::ServerConfiguration;
-use std::collections::HashMap;
 
 #[serial_test::serial]
 #[test]
@@ -61,7 +60,7 @@ fn login() {
             let client_addr = ("127.0.0.1", DEFAULT_PORT + id);
             let server_addr = ("127.0.0.1", DEFAULT_PORT);
             let sock = UdpSocket::bind(client_addr).unwrap();
-            let cmd = ClientCommandType::Login(format!("Client_{}", id));
+            let cmd = ClientCommandType::Login(format!("Client_{}", id), false);
             std::mem::drop(send_data(&sock, server_addr, &cmd, 0));
             //println!("Client {} sent", id);
             let mut data: ClientBuffer<ServerCommandType> =
@@ -104,7 +103,10 @@ fn login_important() {
             let server_addr = ("127.0.0.1", DEFAULT_PORT);
             let sock = UdpSocket::bind(client_addr).unwrap();
             sock.connect(server_addr).unwrap();
-            let cmd = ClientCommandType::Login(format!("Client_{}", id));
+            // even clients ask for compression
+            let compression = id % 2 == 0;
+            let cmd =
+                ClientCommandType::Login(format!("Client_{}", id), compression);
             let mut data: ClientBuffer<ServerCommandType> =
                 ClientBuffer::default();
             if let Ok(resp) = send_important(
@@ -115,7 +117,8 @@ fn login_important() {
                 &ImportantArguments::default(),
             ) {
                 #[allow(irrefutable_let_patterns)]
-                if let ServerCommandType::ReturnLogin(_) = resp {
+                if let ServerCommandType::ReturnLogin(login) = resp {
+                    assert_eq!(login.compression, compression);
                 } else {
                     panic!("Unexpected response: {:?}", resp);
                 }
@@ -163,7 +166,8 @@ fn update_important() {
                 ObjectType::Ship,
                 ObjectId::new(u32::from(id)),
             )]);
-            let mut data: ClientBuffer<ServerCommandType> = HashMap::default();
+            let mut data: ClientBuffer<ServerCommandType> =
+                ClientBuffer::default();
             if let Ok(resp) = send_important(
                 &sock,
                 &cmd,
@@ -205,3 +209,542 @@ fn update_important() {
     done.store(true, Ordering::SeqCst);
     server.join().unwrap();
 }
+
+fn ship_at(x: f64, id: u32) -> RemoteObject {
+    use cgmath::*;
+    node::to_remote_object(
+        &node::Node::default().pos(point3(x, 0., 0.)),
+        &vec3(0., 0., 0.),
+        &vec3(0., 0., 0.),
+        ObjectType::Ship,
+        ObjectId::new(id),
+    )
+}
+
+#[test]
+fn interest_grid_finds_nearby_objects() {
+    use crate::interest::InterestGrid;
+    let mut grid = InterestGrid::new(100.);
+    grid.insert(1, &[ship_at(0., 1), ship_at(-250., 2)]);
+    grid.insert(2, &[ship_at(90., 3), ship_at(150., 4), ship_at(5000., 5)]);
+    let mut near: Vec<_> = grid
+        .query([0., 0., 0.], 160., 0)
+        .iter()
+        .map(|obj| obj.id.as_underlying_type())
+        .collect();
+    near.sort_unstable();
+    assert_eq!(near, vec![1, 3, 4]);
+
+    // a client isn't sent its own objects
+    let near = grid.query([0., 0., 0.], 160., 1);
+    assert_eq!(near.len(), 2);
+}
+
+#[test]
+fn interest_grid_moves_updated_objects() {
+    use crate::interest::{client_pos, InterestGrid};
+    let mut grid = InterestGrid::new(100.);
+    let old = [ship_at(0., 1)];
+    grid.insert(1, &old);
+    let new = [ship_at(1000., 1)];
+    grid.remove(1, &old);
+    grid.insert(1, &new);
+    assert!(grid.query([0., 0., 0.], 100., 0).is_empty());
+    assert_eq!(grid.query([1000., 0., 0.], 100., 0).len(), 1);
+    assert_eq!(client_pos(&new, ObjectId::new(7)), Some([1000., 0., 0.]));
+}
+
+fn asteroid_at(x: f64, scale: f64, id: u32) -> RemoteObject {
+    use cgmath::*;
+    node::to_remote_object(
+        &node::Node::default().pos(point3(x, 0., 0.)).u_scale(scale),
+        &vec3(0., 0., 0.),
+        &vec3(0., 0., 0.),
+        ObjectType::Asteroid,
+        ObjectId::new(id),
+    )
+}
+
+#[test]
+fn hit_validation_checks_range_and_line_of_sight() {
+    use crate::hits::{HitValidator, MAX_HIT_RANGE};
+    let mut hits = HitValidator::default();
+    let shooter = [ship_at(0., 1)];
+    let mut check =
+        |target, x, own: &[RemoteObject], world: &[RemoteObject]| {
+            let claim = HitClaim {
+                shooter: ObjectId::new(1),
+                target: ObjectId::new(target),
+                hit_point: [x, 0., 0.],
+            };
+            hits.validate(&claim, own, world.iter(), std::time::Instant::now())
+                .is_ok()
+        };
+    let world = [ship_at(300., 2), asteroid_at(-200., 2., 3)];
+    assert!(check(2, 298., &shooter, &world));
+    // the client must own the shooter, and the target must exist
+    assert!(!check(2, 298., &world, &world));
+    assert!(!check(9, 298., &shooter, &world));
+    // the hit point must be on the target
+    assert!(!check(2, 100., &shooter, &world));
+    // the asteroid can be hit, but blocks shots at what's behind it
+    assert!(check(3, -190., &shooter, &world));
+    let behind = [ship_at(-400., 4), asteroid_at(-200., 2., 3)];
+    assert!(!check(4, -400., &shooter, &behind));
+    let far = [ship_at(MAX_HIT_RANGE + 10., 5)];
+    assert!(!check(5, MAX_HIT_RANGE + 10., &shooter, &far));
+}
+
+#[test]
+fn hit_validation_limits_fire_rate() {
+    use crate::hits::HitValidator;
+    let mut hits = HitValidator::default();
+    let now = std::time::Instant::now();
+    let shooter = [ship_at(0., 1)];
+    let world = [ship_at(100., 2)];
+    let claim = HitClaim {
+        shooter: ObjectId::new(1),
+        target: ObjectId::new(2),
+        hit_point: [100., 0., 0.],
+    };
+    let accepted = (0..100)
+        .filter(|_| hits.validate(&claim, &shooter, world.iter(), now).is_ok())
+        .count();
+    assert!(accepted > 0 && accepted < 100);
+    // the limit resets once the earlier hits are old enough
+    let later = now + std::time::Duration::from_secs(2);
+    assert!(hits.validate(&claim, &shooter, world.iter(), later).is_ok());
+}
+
+#[test]
+fn teleport_validation_checks_portal_and_cooldown() {
+    use crate::teleports::TeleportValidator;
+    use cgmath::{point3, vec3};
+    use wormhole::{Portal, TeleportClaim, Wormhole, TELEPORT_COOLDOWN};
+    let mut teleports = TeleportValidator::default();
+    let wormholes = [Wormhole {
+        ends: [
+            Portal::new(point3(0., 0., 0.), vec3(1., 0., 0.)),
+            Portal::new(point3(1000., 0., 0.), vec3(-1., 0., 0.)),
+        ],
+        radius: 15.,
+    }];
+    let own = [ship_at(10., 1)];
+    let now = std::time::Instant::now();
+    let mut check = |body, wormhole, entry, own: &[RemoteObject], time| {
+        let claim = TeleportClaim {
+            body: ObjectId::new(body),
+            wormhole,
+            entry,
+        };
+        teleports.validate(&claim, own, &wormholes, time).is_ok()
+    };
+    // the body must be owned and at the portal it entered
+    assert!(!check(2, 0, 0, &own, now));
+    assert!(!check(1, 0, 1, &own, now));
+    assert!(!check(1, 1, 0, &own, now));
+    assert!(!check(1, 0, 2, &own, now));
+    assert!(check(1, 0, 0, &own, now));
+    // and can't go through again until its cooldown is up
+    let soon = now + TELEPORT_COOLDOWN / 4;
+    assert!(!check(1, 0, 0, &own, soon));
+    let later = now + TELEPORT_COOLDOWN;
+    assert!(check(1, 0, 0, &own, later));
+}
+
+#[test]
+fn teleport_validation_forgets_expired_and_removed_bodies() {
+    use crate::teleports::TeleportValidator;
+    use cgmath::{point3, vec3};
+    use wormhole::{Portal, TeleportClaim, Wormhole, TELEPORT_COOLDOWN};
+    let mut teleports = TeleportValidator::default();
+    let wormholes = [Wormhole {
+        ends: [
+            Portal::new(point3(0., 0., 0.), vec3(1., 0., 0.)),
+            Portal::new(point3(1000., 0., 0.), vec3(-1., 0., 0.)),
+        ],
+        radius: 15.,
+    }];
+    let own = [ship_at(10., 1), ship_at(5., 2)];
+    let now = std::time::Instant::now();
+    let claim = |body| TeleportClaim {
+        body: ObjectId::new(body),
+        wormhole: 0,
+        entry: 0,
+    };
+    assert!(teleports.validate(&claim(1), &own, &wormholes, now).is_ok());
+    assert!(teleports.validate(&claim(2), &own, &wormholes, now).is_ok());
+    assert_eq!(teleports.tracked_bodies(), 2);
+    // bodies that leave the game are forgotten
+    teleports.remove(&own[1..]);
+    assert_eq!(teleports.tracked_bodies(), 1);
+    // as are bodies whose cooldown is up, even if the claim is rejected
+    let later = now + TELEPORT_COOLDOWN;
+    assert!(teleports
+        .validate(&claim(3), &own, &wormholes, later)
+        .is_err());
+    assert_eq!(teleports.tracked_bodies(), 0);
+}
+
+fn lobby_addr(port: u16) -> std::net::SocketAddr {
+    std::net::SocketAddr::from(([127, 0, 0, 1], port))
+}
+
+#[test]
+fn lobby_countdown_waits_for_every_player() {
+    use crate::lobby::Lobbies;
+    use std::time::{Duration, Instant};
+    let mut lobbies =
+        Lobbies::new(Duration::from_secs(5), Duration::from_secs(60));
+    let now = Instant::now();
+    let (a, b) = (lobby_addr(1), lobby_addr(2));
+    lobbies
+        .join(a, ObjectId::new(1), "a", "lobby", now)
+        .unwrap();
+    // a lone player can't start a match
+    lobbies.set_ready(&a, true, now).unwrap();
+    let phase = |lobbies: &Lobbies| lobbies.info("lobby", now).unwrap().phase;
+    assert_eq!(phase(&lobbies), lobby::LobbyPhase::Waiting);
+
+    let out = lobbies
+        .join(b, ObjectId::new(2), "b", "lobby", now)
+        .unwrap();
+    assert_eq!(out.len(), 2);
+    lobbies.set_ready(&b, true, now).unwrap();
+    assert!(matches!(phase(&lobbies), lobby::LobbyPhase::Countdown(_)));
+    // unreadying cancels the countdown
+    lobbies.set_ready(&a, false, now).unwrap();
+    assert_eq!(phase(&lobbies), lobby::LobbyPhase::Waiting);
+    lobbies.set_ready(&a, true, now).unwrap();
+    assert!(lobbies.update(now, None, &[[0.; 3]]).is_empty());
+    assert!(!lobbies.in_match(&a));
+
+    let later = now + Duration::from_secs(6);
+    let out = lobbies.update(later, Some(7), &[[1., 2., 3.], [4., 5., 6.]]);
+    let start = out.iter().find_map(|(addr, cmd)| match cmd {
+        ServerCommandType::MatchStart(start) if *addr == b => Some(start),
+        _ => None,
+    });
+    assert_eq!(
+        start.unwrap(),
+        &lobby::MatchStart {
+            map_seed: Some(7),
+            spawns: vec![
+                (ObjectId::new(1), [1., 2., 3.]),
+                (ObjectId::new(2), [4., 5., 6.])
+            ],
+        }
+    );
+    assert!(lobbies.in_match(&a) && lobbies.in_match(&b));
+    // players can't join or ready up during a match
+    let c = lobby_addr(3);
+    assert!(lobbies
+        .join(c, ObjectId::new(3), "c", "lobby", later)
+        .is_err());
+    assert!(lobbies.set_ready(&a, false, later).is_err());
+}
+
+#[test]
+fn lobby_match_ends_with_results() {
+    use crate::lobby::Lobbies;
+    use std::time::{Duration, Instant};
+    let mut lobbies = Lobbies::new(Duration::ZERO, Duration::from_secs(60));
+    let now = Instant::now();
+    let (a, b, c) = (lobby_addr(1), lobby_addr(2), lobby_addr(3));
+    for (addr, id) in [(a, 1), (b, 2), (c, 3)] {
+        lobbies
+            .join(addr, ObjectId::new(id), "", "lobby", now)
+            .unwrap();
+        lobbies.set_ready(&addr, true, now).unwrap();
+    }
+    lobbies.update(now, None, &[[0.; 3]]);
+    lobbies.record_hit(&b);
+    lobbies.record_hit(&b);
+    lobbies.record_hit(&c);
+
+    let results = |out: &crate::lobby::Outgoing| {
+        out.iter()
+            .find_map(|(_, cmd)| match cmd {
+                ServerCommandType::MatchEnd(results) => Some(results.clone()),
+                _ => None,
+            })
+            .unwrap()
+    };
+    // the match goes on while enough players remain
+    let out = lobbies.leave(&a, now);
+    assert!(out
+        .iter()
+        .all(|(_, cmd)| !matches!(cmd, ServerCommandType::MatchEnd(_))));
+    let out = lobbies.update(now + Duration::from_secs(61), None, &[]);
+    let results = results(&out);
+    assert_eq!(
+        results.scores,
+        vec![(ObjectId::new(2), 2), (ObjectId::new(3), 1)]
+    );
+    assert_eq!(results.winner(), Some(ObjectId::new(2)));
+    // players ready up again for the next match
+    let info = lobbies.info("lobby", now).unwrap();
+    assert_eq!(info.phase, lobby::LobbyPhase::Waiting);
+    assert!(info.players.iter().all(|player| !player.ready));
+
+    // the lobby is removed once everyone leaves
+    lobbies.leave(&b, now);
+    lobbies.leave(&c, now);
+    assert!(lobbies.info("lobby", now).is_none());
+}
+
+#[test]
+fn admin_commands_parse() {
+    use crate::admin::AdminCommand;
+    let parse = |line| AdminCommand::try_from(line);
+    assert_eq!(parse("list"), Ok(AdminCommand::List));
+    assert_eq!(
+        parse("  kick   127.0.0.1:5000 "),
+        Ok(AdminCommand::Kick("127.0.0.1:5000".to_owned()))
+    );
+    assert_eq!(
+        parse("map procedural:7"),
+        Ok(AdminCommand::Map("procedural:7".to_owned()))
+    );
+    assert_eq!(parse("tickrate 30"), Ok(AdminCommand::TickRate(30)));
+    assert!(parse("tickrate 0").is_err());
+    assert!(parse("tickrate fast").is_err());
+    assert!(parse("kick").is_err());
+    assert!(parse("list everyone").is_err());
+    assert!(parse("restart").is_err());
+}
+
+#[test]
+fn metrics_summarize_each_interval() {
+    use crate::metrics::Metrics;
+    use std::time::{Duration, Instant};
+    let now = Instant::now();
+    let mut metrics = Metrics::new(Duration::from_secs(2), now);
+    metrics.record(Duration::from_millis(1));
+    metrics.record(Duration::from_millis(3));
+    assert!(!metrics.is_due(now + Duration::from_secs(1)));
+    let later = now + Duration::from_secs(2);
+    assert!(metrics.is_due(later));
+    let summary = metrics
+        .summarize(later, 3, 10, 20, remote::BufferStats::default())
+        .clone();
+    assert!((summary.messages_per_sec - 1.).abs() < 1e-9);
+    assert_eq!(summary.avg_response_time, Duration::from_millis(2));
+    assert_eq!(summary.players, 3);
+    let text = summary.to_prometheus();
+    assert!(text.contains("# TYPE oort_players gauge\noort_players 3\n"));
+    assert!(text.contains("oort_messages_total 2\n"));
+
+    // the next interval starts empty, but the total carries over
+    let summary = metrics.summarize(
+        later + Duration::from_secs(2),
+        3,
+        10,
+        20,
+        remote::BufferStats::default(),
+    );
+    assert_eq!(summary.avg_response_time, Duration::ZERO);
+    assert_eq!(summary.total_messages, 2);
+    assert_eq!(metrics.latest().total_messages, 2);
+}
+
+#[test]
+fn profile_store_recovers_from_corrupt_files() {
+    use crate::profiles::ProfileStore;
+    let dir = std::env::temp_dir()
+        .join(format!("oort-profiles-{}", std::process::id()));
+    let store = ProfileStore::new(dir.to_str());
+    assert_eq!(store.load("pilot"), profile::Profile::default());
+
+    let mut saved = profile::Profile::default();
+    saved.record_match(4, true);
+    store.save("pilot", &saved);
+    store.save("../pilot", &profile::Profile::default());
+    assert_eq!(store.load("pilot"), saved);
+    assert_eq!(store.load("../pilot"), profile::Profile::default());
+
+    std::fs::write(dir.join("pilot.profile"), "wins = \u{fffd}").unwrap();
+    assert_eq!(store.load("pilot"), profile::Profile::default());
+    // the corrupt file is kept aside instead of being overwritten
+    assert!(dir.join("pilot.corrupt").is_file());
+    assert!(!dir.join("pilot.profile").exists());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn ownership_transfers_after_release_and_ack() {
+    use crate::ownership::{Ownership, HANDOFF_TIMEOUT};
+    use std::time::Instant;
+    let mut owners = Ownership::default();
+    let (a, b, c) = (lobby_addr(1), lobby_addr(2), lobby_addr(3));
+    owners.assign((ObjectId::new(100), ObjectId::new(200)), a);
+    owners.assign((ObjectId::new(200), ObjectId::new(300)), b);
+    let id = ObjectId::new(150);
+    assert_eq!(owners.owner_of(id), Some(a));
+    assert_eq!(owners.owner_of(ObjectId::new(250)), Some(b));
+    // ids that weren't handed out belong to the server
+    assert_eq!(owners.owner_of(ObjectId::new(5)), None);
+    assert!(owners.may_update(&c, ObjectId::new(5)));
+
+    let now = Instant::now();
+    let out = owners.request(id, b, now);
+    assert_eq!(out, vec![(a, ServerCommandType::HandoffRequest(id))]);
+    // the object is taken while the transfer is in progress
+    assert_eq!(
+        owners.request(id, c, now),
+        vec![(c, ServerCommandType::OwnershipDenied(id))]
+    );
+    let out = owners.release(id, a, true, now);
+    assert_eq!(out, vec![(b, ServerCommandType::OwnershipGranted(id))]);
+    assert_eq!(owners.owner_of(id), Some(a));
+    owners.ack(id, b);
+    assert_eq!(owners.owner_of(id), Some(b));
+    assert!(!owners.may_update(&a, id));
+
+    // a refused handoff leaves the owner as is
+    owners.request(id, c, now);
+    let out = owners.release(id, b, false, now);
+    assert_eq!(out, vec![(c, ServerCommandType::OwnershipDenied(id))]);
+    assert_eq!(owners.owner_of(id), Some(b));
+
+    // a grant that isn't acknowledged returns to the previous owner
+    owners.request(id, a, now);
+    owners.release(id, b, true, now);
+    let later = now + HANDOFF_TIMEOUT * 2;
+    let out = owners.expire(later);
+    assert!(out.contains(&(a, ServerCommandType::OwnershipDenied(id))));
+    assert!(out.contains(&(b, ServerCommandType::OwnershipGranted(id))));
+    owners.ack(id, a);
+    assert_eq!(owners.owner_of(id), Some(b));
+
+    // the objects of clients that leave return to the server
+    owners.remove_client(&b);
+    assert_eq!(owners.owner_of(id), None);
+    assert_eq!(owners.owner_of(ObjectId::new(250)), None);
+    assert_eq!(owners.owner_of(ObjectId::new(120)), Some(a));
+}
+
+#[test]
+fn ownership_follows_proximity() {
+    use crate::ownership::Ownership;
+    use std::time::Instant;
+    let mut owners = Ownership::default();
+    let (a, b) = (lobby_addr(1), lobby_addr(2));
+    owners.assign((ObjectId::new(100), ObjectId::new(200)), a);
+    let rock = asteroid_at(1000., 1., 150);
+    let laser = RemoteObject {
+        typ: ObjectType::Laser,
+        ..asteroid_at(1000., 1., 151)
+    };
+    let now = Instant::now();
+    let near_owner = [(a, [900., 0., 0.]), (b, [1060., 0., 0.])];
+    let objects = [(a, &rock), (a, &laser)];
+    assert!(owners
+        .follow_proximity(objects.into_iter(), &near_owner, now)
+        .is_empty());
+    let near_other = [(a, [0., 0., 0.]), (b, [1050., 0., 0.])];
+    let out = owners.follow_proximity(objects.into_iter(), &near_other, now);
+    // only the asteroid is handed off, since lasers stay with their shooter
+    assert_eq!(out, vec![(a, ServerCommandType::HandoffRequest(rock.id))]);
+    owners.release(rock.id, a, true, now);
+    owners.ack(rock.id, b);
+    assert_eq!(owners.owner_of(rock.id), Some(b));
+}
+
+#[test]
+fn throttle_holds_back_updates_over_budget() {
+    use crate::throttle::Throttle;
+    use std::net::SocketAddr;
+    use std::time::{Duration, Instant};
+    let now = Instant::now();
+    let addr = SocketAddr::from(([127, 0, 0, 1], 5000));
+    let update = ServerCommandType::Update(Vec::new());
+    let mut throttle = Throttle::new(Some(1000));
+    assert!(throttle.admit(addr, &update, 1, now));
+    throttle.record_sent(addr, 1500, now);
+    // critical messages are sent even when the client is over its budget
+    assert!(throttle.admit(addr, &ServerCommandType::Pong(1), 2, now));
+    assert!(!throttle.admit(addr, &update, 3, now));
+    assert!(!throttle.admit(addr, &update, 4, now));
+    assert_eq!(throttle.coalesced(), 1);
+    assert!(throttle.take_due(now).is_empty());
+
+    let later = now + Duration::from_millis(600);
+    assert_eq!(throttle.take_due(later), vec![(addr, update.clone(), 4)]);
+    assert!(throttle.take_due(later).is_empty());
+
+    let mut unlimited = Throttle::new(None);
+    unlimited.record_sent(addr, 1_000_000, now);
+    assert!(unlimited.admit(addr, &update, 1, now));
+}
+
+/// Relays datagrams between a client and the server at `server_port` until
+/// `done` is set, dropping the datagrams of message `lost` of those the
+/// server sends, counting from 0
+fn lossy_relay(
+    relay: &UdpSocket,
+    server_port: u16,
+    lost: usize,
+    done: &AtomicBool,
+) {
+    let upstream = UdpSocket::bind("127.0.0.1:0").unwrap();
+    upstream.connect(("127.0.0.1", server_port)).unwrap();
+    relay.set_nonblocking(true).unwrap();
+    upstream.set_nonblocking(true).unwrap();
+    let mut client = None;
+    let mut msg_ids = Vec::new();
+    let mut buf = [0; 2048];
+    while !done.load(Ordering::SeqCst) {
+        if let Ok((amt, src)) = relay.recv_from(&mut buf) {
+            client = Some(src);
+            upstream.send(&buf[..amt]).unwrap();
+        }
+        if let (Ok(amt), Some(client)) = (upstream.recv(&mut buf), client) {
+            // bytes 2 to 5 of a datagram are the id of its message
+            let msg_id = buf[2..6].to_vec();
+            if !msg_ids.contains(&msg_id) {
+                msg_ids.push(msg_id.clone());
+            }
+            if msg_ids.get(lost) != Some(&msg_id) {
+                relay.send_to(&buf[..amt], client).unwrap();
+            }
+        }
+        thread::sleep(std::time::Duration::from_millis(1));
+    }
+}
+
+#[serial_test::serial]
+#[test]
+fn connecting_survives_lost_responses() {
+    // the replies to the handshake and to the login
+    for lost in 0..2 {
+        let done = Arc::new(AtomicBool::new(false));
+        let server = thread::spawn({
+            let done = done.clone();
+            move || {
+                std::mem::drop(run_game_server(
+                    &ServerConfiguration::default(),
+                    &done,
+                ));
+            }
+        });
+        let relay_port = DEFAULT_PORT + 20;
+        let relay_sock = UdpSocket::bind(("127.0.0.1", relay_port)).unwrap();
+        let relay = thread::spawn({
+            let done = done.clone();
+            move || lossy_relay(&relay_sock, DEFAULT_PORT, lost, &done)
+        });
+        let client = game_controller::RemoteGameController::new(
+            "Client_1",
+            ("127.0.0.1".parse().unwrap(), relay_port),
+        );
+        done.store(true, Ordering::SeqCst);
+        relay.join().unwrap();
+        server.join().unwrap();
+        assert!(
+            client.is_ok(),
+            "Failed to connect after losing reply {}",
+            lost
+        );
+    }
+}
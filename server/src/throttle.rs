@@ -0,0 +1,169 @@
+use shared_types::{MsgId, ServerCommandType};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Instant;
+
+/// Seconds of its rate a client's budget can save up while nothing is sent
+const BURST_SECS: f64 = 1.;
+
+/// Bytes that may be sent to a client, which refill at a fixed rate
+///
+/// The budget may be overdrawn by the last message sent, so a message is
+/// never split or held back just because it's larger than what's left
+struct SendBudget {
+    /// Bytes per second
+    rate: f64,
+    balance: f64,
+    last_refill: Instant,
+}
+
+impl SendBudget {
+    /// Creates a full budget which refills by `rate` bytes per second
+    fn new(rate: u32, now: Instant) -> Self {
+        let rate = f64::from(rate);
+        Self {
+            rate,
+            balance: rate * BURST_SECS,
+            last_refill: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.balance = self
+            .rate
+            .mul_add(elapsed.as_secs_f64(), self.balance)
+            .min(self.rate * BURST_SECS);
+        self.last_refill = now;
+    }
+
+    /// `true` if anything may be sent at `now`
+    fn has_budget(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        self.balance > 0.
+    }
+
+    fn spend(&mut self, bytes: usize) {
+        self.balance -= bytes as f64;
+    }
+}
+
+/// `true` if `cmd` may be held back or replaced when a client is over its
+/// budget. Updates are, since the next update makes them stale anyway, but
+/// logins, ids, confirmations and everything else the client waits on
+/// aren't
+pub const fn is_droppable(cmd: &ServerCommandType) -> bool {
+    matches!(cmd, ServerCommandType::Update(_))
+}
+
+struct ClientThrottle {
+    budget: SendBudget,
+    /// The latest update held back because the client was over its budget,
+    /// and the id of the message it answers
+    deferred: Option<(ServerCommandType, MsgId)>,
+}
+
+/// Limits the bytes per second sent to each client, so a client on a slow
+/// connection isn't flooded with updates
+///
+/// Critical messages are always sent, but count against the budget. An
+/// update to a client that's over its budget is held back until the budget
+/// refills, and replaced by any newer update before then
+pub struct Throttle {
+    /// Bytes per second each client may be sent, or `None` if unlimited
+    rate: Option<u32>,
+    clients: HashMap<SocketAddr, ClientThrottle>,
+    /// Updates that were replaced by a newer update before they were sent
+    coalesced: u64,
+}
+
+impl Throttle {
+    pub fn new(rate: Option<u32>) -> Self {
+        Self {
+            rate,
+            clients: HashMap::new(),
+            coalesced: 0,
+        }
+    }
+
+    fn client(
+        &mut self,
+        addr: SocketAddr,
+        now: Instant,
+    ) -> Option<&mut ClientThrottle> {
+        let rate = self.rate?;
+        Some(self.clients.entry(addr).or_insert_with(|| ClientThrottle {
+            budget: SendBudget::new(rate, now),
+            deferred: None,
+        }))
+    }
+
+    /// Determines if `cmd`, the answer to message `msg_id`, may be sent to
+    /// `addr` at `now`. If it can't be, it's held back until `take_due`
+    /// returns it, replacing the update held back for the client before
+    pub fn admit(
+        &mut self,
+        addr: SocketAddr,
+        cmd: &ServerCommandType,
+        msg_id: MsgId,
+        now: Instant,
+    ) -> bool {
+        let client = match self.client(addr, now) {
+            Some(client) => client,
+            None => return true,
+        };
+        let admitted = !is_droppable(cmd) || client.budget.has_budget(now);
+        let replaced = if admitted && !is_droppable(cmd) {
+            None
+        } else if admitted {
+            client.deferred.take()
+        } else {
+            client.deferred.replace((cmd.clone(), msg_id))
+        };
+        self.coalesced += u64::from(replaced.is_some());
+        admitted
+    }
+
+    /// Records that `bytes` were sent to `addr`
+    pub fn record_sent(
+        &mut self,
+        addr: SocketAddr,
+        bytes: usize,
+        now: Instant,
+    ) {
+        if let Some(client) = self.client(addr, now) {
+            client.budget.spend(bytes);
+        }
+    }
+
+    /// Takes the held back updates of the clients whose budgets have
+    /// refilled at `now`, along with their clients and message ids
+    pub fn take_due(
+        &mut self,
+        now: Instant,
+    ) -> Vec<(SocketAddr, ServerCommandType, MsgId)> {
+        self.clients
+            .iter_mut()
+            .filter(|(_, client)| client.deferred.is_some())
+            .filter_map(|(addr, client)| {
+                if client.budget.has_budget(now) {
+                    let (cmd, msg_id) = client.deferred.take()?;
+                    Some((*addr, cmd, msg_id))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Forgets the budget and held back update of `addr`
+    pub fn remove(&mut self, addr: &SocketAddr) {
+        self.clients.remove(addr);
+    }
+
+    /// Number of updates that were replaced by a newer update before they
+    /// were sent
+    pub const fn coalesced(&self) -> u64 {
+        self.coalesced
+    }
+}
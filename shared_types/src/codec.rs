@@ -0,0 +1,452 @@
+use super::ObjectId;
+use std::error::Error;
+use std::fmt::Display;
+
+/// Why a value could not be encoded
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum EncodeError {
+    /// A string, list or message is `len` long, but at most `max` fits into
+    /// its length prefix or chunks
+    TooLong { len: usize, max: usize },
+}
+
+impl Display for EncodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::TooLong { len, max } => {
+                write!(f, "Length {} is longer than the maximum {}", len, max)
+            }
+        }
+    }
+}
+
+impl Error for EncodeError {}
+
+/// Why a message could not be decoded
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum DecodeError {
+    /// A field needed `needed` bytes, but the message only had `left` left
+    Truncated { needed: usize, left: usize },
+    /// The message had this many bytes left after its last field
+    TrailingBytes(usize),
+    /// A string was not valid UTF-8
+    InvalidUtf8,
+    /// A byte that tells `what` the data is had an unknown `value`
+    Unknown { what: &'static str, value: u8 },
+    /// The chunks of the message were malformed, or its compressed data was
+    /// corrupt
+    Malformed(String),
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Truncated { needed, left } => write!(
+                f,
+                "Message too short: needed {} bytes with {} left",
+                needed, left
+            ),
+            Self::TrailingBytes(left) => {
+                write!(f, "Message too long: {} bytes left", left)
+            }
+            Self::InvalidUtf8 => write!(f, "Invalid UTF-8 string"),
+            Self::Unknown { what, value } => {
+                write!(f, "Unknown {} with value '{}'", what, value)
+            }
+            Self::Malformed(reason) => {
+                write!(f, "Malformed message: {}", reason)
+            }
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+/// A value that can be written to a message
+pub trait Encode {
+    /// Writes the value to the end of `writer`
+    ///
+    /// # Errors
+    /// Fails if the value is too long for its length prefix
+    fn encode(&self, writer: &mut Writer) -> Result<(), EncodeError>;
+}
+
+/// A value that can be read from a message
+pub trait Decode: Sized {
+    /// Reads the value from the front of `reader`
+    ///
+    /// # Errors
+    /// Fails if `reader` is too short or its bytes aren't a valid value
+    fn decode(reader: &mut Reader) -> Result<Self, DecodeError>;
+}
+
+/// Builds the data of a message from big endian values
+#[derive(Default)]
+pub struct Writer {
+    data: Vec<u8>,
+}
+
+impl Writer {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes `val` to the end of the data
+    ///
+    /// # Errors
+    /// Fails if `val` cannot be encoded
+    pub fn put<T: Encode + ?Sized>(
+        &mut self,
+        val: &T,
+    ) -> Result<&mut Self, EncodeError> {
+        val.encode(self)?;
+        Ok(self)
+    }
+
+    /// Writes each of `vals` without a count in front, so they can only be
+    /// read back with `Reader::get_rest`
+    ///
+    /// # Errors
+    /// Fails if a value cannot be encoded
+    pub fn put_each<T: Encode>(
+        &mut self,
+        vals: &[T],
+    ) -> Result<&mut Self, EncodeError> {
+        for val in vals {
+            val.encode(self)?;
+        }
+        Ok(self)
+    }
+
+    /// Writes `bytes` as they are
+    pub fn bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        self.data.extend_from_slice(bytes);
+        self
+    }
+
+    /// Writes the one byte length prefix of a string or list
+    ///
+    /// # Errors
+    /// Fails if `len` doesn't fit into a byte
+    pub fn len_prefix(&mut self, len: usize) -> Result<&mut Self, EncodeError> {
+        let len = u8::try_from(len).map_err(|_| EncodeError::TooLong {
+            len,
+            max: u8::MAX.into(),
+        })?;
+        Ok(self.bytes(&[len]))
+    }
+
+    /// Gets the written data
+    #[must_use]
+    pub fn finish(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+/// Reads big endian values from the front of the data of a message
+///
+/// Every read is bounds checked, so malformed data fails to decode instead
+/// of panicking
+pub struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    #[must_use]
+    pub const fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    /// `true` if every byte was read
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Reads the next `len` bytes
+    ///
+    /// # Errors
+    /// Fails if there are fewer than `len` bytes left
+    pub fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if self.data.len() < len {
+            return Err(DecodeError::Truncated {
+                needed: len,
+                left: self.data.len(),
+            });
+        }
+        let (front, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(front)
+    }
+
+    /// Reads the next `N` bytes
+    ///
+    /// # Errors
+    /// Fails if there are fewer than `N` bytes left
+    pub fn take_array<const N: usize>(
+        &mut self,
+    ) -> Result<[u8; N], DecodeError> {
+        let mut bytes = [0; N];
+        bytes.copy_from_slice(self.take(N)?);
+        Ok(bytes)
+    }
+
+    /// Reads the next value
+    ///
+    /// # Errors
+    /// Fails if the value cannot be decoded
+    pub fn get<T: Decode>(&mut self) -> Result<T, DecodeError> {
+        T::decode(self)
+    }
+
+    /// Reads values until the end of the data, which were written with
+    /// `Writer::put_each`
+    ///
+    /// # Errors
+    /// Fails if a value cannot be decoded, which is the case if the last
+    /// value is cut off
+    pub fn get_rest<T: Decode>(&mut self) -> Result<Vec<T>, DecodeError> {
+        let mut vals = Vec::new();
+        while !self.is_empty() {
+            vals.push(T::decode(self)?);
+        }
+        Ok(vals)
+    }
+
+    /// Reads a one byte length prefix
+    ///
+    /// # Errors
+    /// Fails if there's no byte left
+    pub fn len_prefix(&mut self) -> Result<usize, DecodeError> {
+        Ok(self.take_array::<1>()?[0].into())
+    }
+
+    /// Checks that every byte was read
+    ///
+    /// # Errors
+    /// Fails if there is data left to read
+    pub fn finish(self) -> Result<(), DecodeError> {
+        if self.data.is_empty() {
+            Ok(())
+        } else {
+            Err(DecodeError::TrailingBytes(self.data.len()))
+        }
+    }
+}
+
+/// Implements `Encode` and `Decode` for numbers as their big endian bytes
+macro_rules! impl_codec_for_numbers {
+    ($($num:ty),*) => {
+        $(
+            impl Encode for $num {
+                fn encode(&self, writer: &mut Writer) -> Result<(), EncodeError> {
+                    writer.bytes(&self.to_be_bytes());
+                    Ok(())
+                }
+            }
+
+            impl Decode for $num {
+                fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+                    Ok(Self::from_be_bytes(reader.take_array()?))
+                }
+            }
+        )*
+    };
+}
+
+impl_codec_for_numbers!(u8, u32, u64, f32, f64);
+
+/// Implements `Encode` and `Decode` for a struct by encoding each of the
+/// listed fields in order. Every field must implement `Encode` and `Decode`,
+/// and every field of the struct must be listed
+///
+/// ```ignore
+/// impl_codec!(HitClaim { shooter, target, hit_point });
+/// ```
+macro_rules! impl_codec {
+    ($name:ty { $($field:ident),* $(,)? }) => {
+        impl $crate::codec::Encode for $name {
+            fn encode(
+                &self,
+                writer: &mut $crate::codec::Writer,
+            ) -> Result<(), $crate::codec::EncodeError> {
+                $(writer.put(&self.$field)?;)*
+                Ok(())
+            }
+        }
+
+        impl $crate::codec::Decode for $name {
+            fn decode(
+                reader: &mut $crate::codec::Reader,
+            ) -> Result<Self, $crate::codec::DecodeError> {
+                // struct fields are evaluated in the order they're written
+                Ok(Self {
+                    $($field: reader.get()?,)*
+                })
+            }
+        }
+    };
+}
+
+pub(crate) use impl_codec;
+
+impl Encode for bool {
+    fn encode(&self, writer: &mut Writer) -> Result<(), EncodeError> {
+        writer.bytes(&[u8::from(*self)]);
+        Ok(())
+    }
+}
+
+impl Decode for bool {
+    fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(reader.get::<u8>()? != 0)
+    }
+}
+
+impl Encode for ObjectId {
+    fn encode(&self, writer: &mut Writer) -> Result<(), EncodeError> {
+        writer.bytes(&self.to_be_bytes());
+        Ok(())
+    }
+}
+
+impl Decode for ObjectId {
+    fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(Self::from_be_bytes(reader.take_array()?))
+    }
+}
+
+/// Strings are prefixed by their length in one byte
+impl Encode for str {
+    fn encode(&self, writer: &mut Writer) -> Result<(), EncodeError> {
+        writer.len_prefix(self.len())?.bytes(self.as_bytes());
+        Ok(())
+    }
+}
+
+impl Encode for String {
+    fn encode(&self, writer: &mut Writer) -> Result<(), EncodeError> {
+        self.as_str().encode(writer)
+    }
+}
+
+impl Decode for String {
+    fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let len = reader.len_prefix()?;
+        std::str::from_utf8(reader.take(len)?)
+            .map(str::to_owned)
+            .map_err(|_| DecodeError::InvalidUtf8)
+    }
+}
+
+/// Lists are prefixed by their number of items in one byte
+impl<T: Encode> Encode for [T] {
+    fn encode(&self, writer: &mut Writer) -> Result<(), EncodeError> {
+        writer.len_prefix(self.len())?.put_each(self)?;
+        Ok(())
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode(&self, writer: &mut Writer) -> Result<(), EncodeError> {
+        self.as_slice().encode(writer)
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        (0..reader.len_prefix()?).map(|_| reader.get()).collect()
+    }
+}
+
+/// Arrays have no length prefix, since their length is known
+impl<T: Encode, const N: usize> Encode for [T; N] {
+    fn encode(&self, writer: &mut Writer) -> Result<(), EncodeError> {
+        writer.put_each(self)?;
+        Ok(())
+    }
+}
+
+impl<T: Decode, const N: usize> Decode for [T; N] {
+    fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        (0..N)
+            .map(|_| reader.get())
+            .collect::<Result<Vec<_>, _>>()?
+            .try_into()
+            .map_err(|_| DecodeError::Malformed("Array length".to_owned()))
+    }
+}
+
+/// Options are prefixed by a byte which is `1` if there is a value
+impl<T: Encode> Encode for Option<T> {
+    fn encode(&self, writer: &mut Writer) -> Result<(), EncodeError> {
+        writer.put(&self.is_some())?;
+        if let Some(val) = self {
+            writer.put(val)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        match reader.get::<u8>()? {
+            0 => Ok(None),
+            1 => Ok(Some(reader.get()?)),
+            value => Err(DecodeError::Unknown {
+                what: "option",
+                value,
+            }),
+        }
+    }
+}
+
+impl<A: Encode, B: Encode> Encode for (A, B) {
+    fn encode(&self, writer: &mut Writer) -> Result<(), EncodeError> {
+        writer.put(&self.0)?.put(&self.1)?;
+        Ok(())
+    }
+}
+
+impl<A: Decode, B: Decode> Decode for (A, B) {
+    fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        Ok((reader.get()?, reader.get()?))
+    }
+}
+
+impl<T: Encode> Encode for cgmath::Vector3<T> {
+    fn encode(&self, writer: &mut Writer) -> Result<(), EncodeError> {
+        writer.put(&self.x)?.put(&self.y)?.put(&self.z)?;
+        Ok(())
+    }
+}
+
+impl<T: Decode> Decode for cgmath::Vector3<T> {
+    fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(Self::new(reader.get()?, reader.get()?, reader.get()?))
+    }
+}
+
+/// Durations are their whole seconds followed by their nanoseconds
+impl Encode for std::time::Duration {
+    fn encode(&self, writer: &mut Writer) -> Result<(), EncodeError> {
+        writer.put(&self.as_secs())?.put(&self.subsec_nanos())?;
+        Ok(())
+    }
+}
+
+impl Decode for std::time::Duration {
+    fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let (secs, nanos): (u64, u32) = (reader.get()?, reader.get()?);
+        // more nanoseconds than a second would carry into the seconds, which
+        // can overflow
+        if nanos >= 1_000_000_000 {
+            return Err(DecodeError::Malformed(format!(
+                "Duration with {} nanoseconds",
+                nanos
+            )));
+        }
+        Ok(Self::new(secs, nanos))
+    }
+}
@@ -0,0 +1,164 @@
+use std::error::Error;
+
+/// Shortest match that is encoded
+const MIN_MATCH: usize = 4;
+/// Size of the table of previous positions is `2^HASH_LOG`
+const HASH_LOG: u32 = 12;
+/// Farthest back a match can be
+const MAX_OFFSET: usize = 0xFFFF;
+/// The last match must start at least this many bytes before the end
+const MF_LIMIT: usize = 12;
+/// The last bytes are always literals
+const LAST_LITERALS: usize = 5;
+/// Value of a 4 bit length which is continued in extra bytes
+const LENGTH_CONTINUES: usize = 15;
+
+fn hash(seq: u32) -> usize {
+    (seq.wrapping_mul(2_654_435_761) >> (32 - HASH_LOG)) as usize
+}
+
+fn read_u32(data: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap())
+}
+
+/// Writes the part of a length that doesn't fit into its 4 bits
+fn write_extra_length(out: &mut Vec<u8>, mut len: usize) {
+    while len >= 255 {
+        out.push(255);
+        len -= 255;
+    }
+    out.push(len as u8);
+}
+
+/// Writes a sequence of `literals` followed by a match of `(offset, length)`,
+/// if any
+fn write_sequence(
+    out: &mut Vec<u8>,
+    literals: &[u8],
+    matched: Option<(usize, usize)>,
+) {
+    let match_len = matched.map_or(0, |(_, len)| len - MIN_MATCH);
+    out.push(
+        (literals.len().min(LENGTH_CONTINUES) << 4
+            | match_len.min(LENGTH_CONTINUES)) as u8,
+    );
+    if literals.len() >= LENGTH_CONTINUES {
+        write_extra_length(out, literals.len() - LENGTH_CONTINUES);
+    }
+    out.extend_from_slice(literals);
+    if let Some((offset, _)) = matched {
+        out.extend_from_slice(&(offset as u16).to_le_bytes());
+        if match_len >= LENGTH_CONTINUES {
+            write_extra_length(out, match_len - LENGTH_CONTINUES);
+        }
+    }
+}
+
+/// Compresses `data` into an LZ4 block
+///
+/// A block is a list of sequences. Each sequence is a token byte holding the
+/// number of literals and the length of the match that follow in its upper and
+/// lower 4 bits, the literal bytes, and a match, which is a 2 byte little
+/// endian offset back into the decompressed data. Lengths that don't fit into
+/// their 4 bits are continued in extra bytes. The last sequence has literals
+/// only
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() / 2);
+    let mut last_seen = vec![usize::MAX; 1 << HASH_LOG];
+    let (mut anchor, mut pos) = (0, 0);
+    while pos + MF_LIMIT < data.len() {
+        let seq = read_u32(data, pos);
+        let candidate = std::mem::replace(&mut last_seen[hash(seq)], pos);
+        if candidate != usize::MAX
+            && pos - candidate <= MAX_OFFSET
+            && read_u32(data, candidate) == seq
+        {
+            let mut len = MIN_MATCH;
+            while pos + len < data.len() - LAST_LITERALS
+                && data[candidate + len] == data[pos + len]
+            {
+                len += 1;
+            }
+            write_sequence(
+                &mut out,
+                &data[anchor..pos],
+                Some((pos - candidate, len)),
+            );
+            pos += len;
+            anchor = pos;
+        } else {
+            pos += 1;
+        }
+    }
+    write_sequence(&mut out, &data[anchor..], None);
+    out
+}
+
+/// Reads a length whose 4 bits are `nibble` and whose extra bytes, if any,
+/// start at `pos`
+fn read_length(
+    data: &[u8],
+    pos: &mut usize,
+    nibble: usize,
+) -> Result<usize, Box<dyn Error>> {
+    let mut len = nibble;
+    if nibble == LENGTH_CONTINUES {
+        loop {
+            let byte = *data.get(*pos).ok_or("Truncated length")?;
+            *pos += 1;
+            len += byte as usize;
+            if byte != 255 {
+                break;
+            }
+        }
+    }
+    Ok(len)
+}
+
+/// Decompresses the LZ4 block `data`, which decompresses to `size` bytes
+///
+/// # Errors
+/// Fails if `data` is malformed or doesn't decompress to `size` bytes
+pub fn decompress(data: &[u8], size: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut out = Vec::with_capacity(size);
+    let mut pos = 0;
+    loop {
+        let token = *data.get(pos).ok_or("Truncated sequence")?;
+        pos += 1;
+        let literal_len = read_length(data, &mut pos, (token >> 4) as usize)?;
+        if out.len() + literal_len > size {
+            return Err("Decompressed data too long")?;
+        }
+        let literals = data
+            .get(pos..pos + literal_len)
+            .ok_or("Truncated literals")?;
+        out.extend_from_slice(literals);
+        pos += literal_len;
+        if pos == data.len() {
+            break;
+        }
+        let offset = u16::from_le_bytes(
+            data.get(pos..pos + 2)
+                .ok_or("Truncated offset")?
+                .try_into()?,
+        ) as usize;
+        pos += 2;
+        let match_len =
+            read_length(data, &mut pos, (token & 0xF) as usize)? + MIN_MATCH;
+        if offset == 0 || offset > out.len() {
+            return Err("Invalid match offset")?;
+        }
+        if out.len() + match_len > size {
+            return Err("Decompressed data too long")?;
+        }
+        // copied a byte at a time, since a match can overlap itself
+        for _ in 0..match_len {
+            out.push(out[out.len() - offset]);
+        }
+    }
+    if out.len() == size {
+        Ok(out)
+    } else {
+        Err("Decompressed data too short")?
+    }
+}
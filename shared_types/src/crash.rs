@@ -0,0 +1,69 @@
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::fmt::{Display, Write};
+use std::panic::PanicHookInfo;
+
+/// Gets the message a panic was started with, if it was a string
+#[must_use]
+pub fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("Box<dyn Any>")
+}
+
+/// Describes the panic `info` of the current thread, with its backtrace
+/// followed by each of the `sections`, which are titles and their contents
+#[must_use]
+pub fn report(info: &PanicHookInfo, sections: &[(&str, &str)]) -> String {
+    let thread = std::thread::current();
+    let mut headline = format!(
+        "Thread '{}' panicked: {}",
+        thread.name().unwrap_or("<unnamed>"),
+        panic_message(info.payload())
+    );
+    if let Some(location) = info.location() {
+        let _ = write!(headline, "\nat {}", location);
+    }
+    format_report(&headline, &Backtrace::force_capture(), sections)
+}
+
+fn format_report(
+    headline: &str,
+    backtrace: &dyn Display,
+    sections: &[(&str, &str)],
+) -> String {
+    let mut report = format!("{}\n\n[Backtrace]\n{}\n", headline, backtrace);
+    for (title, contents) in sections {
+        let _ = write!(report, "\n[{}]\n{}\n", title, contents.trim_end());
+    }
+    report
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn panics_are_reported() {
+        let payload = std::panic::catch_unwind(|| {
+            panic!("missing asset {}", 7);
+        })
+        .unwrap_err();
+        assert_eq!(panic_message(&*payload), "missing asset 7");
+        assert_eq!(panic_message(&"static"), "static");
+        assert_eq!(panic_message(&5), "Box<dyn Any>");
+        assert_eq!(
+            format_report(
+                "Thread 'main' panicked: missing asset 7",
+                &"0: main",
+                &[("Settings", "msaa = 4\n"), ("Log", "")],
+            ),
+            "Thread 'main' panicked: missing asset 7\n\n\
+            [Backtrace]\n0: main\n\n\
+            [Settings]\nmsaa = 4\n\n\
+            [Log]\n\n"
+        );
+    }
+}
@@ -3,6 +3,12 @@ use super::*;
 pub use game_map::*;
 use std::collections::{HashMap, VecDeque};
 use std::net::IpAddr;
+use std::time::{Duration, Instant};
+use tracing::{trace_span, warn};
+
+/// Longest a remote controller waits for more messages from the server when
+/// it syncs
+const SYNC_RECV_TIMEOUT: Duration = Duration::from_millis(1);
 
 pub struct GameStats {}
 
@@ -34,29 +40,88 @@ pub trait GameController {
     /// Returns `None` if there are no requested ids received
     fn get_requested_ids(&mut self) -> Option<(ObjectId, ObjectId)>;
 
+    /// Stops ids up to and including `id` from being handed out by later
+    /// requests, such as when objects loaded from a save already use them.
+    /// Only local games can be loaded, so other controllers ignore it
+    fn reserve_ids_through(&mut self, _id: ObjectId) {}
+
+    /// Moves the ids received since the last call into `ids`, and requests
+    /// another block of ids once `ids` is running low
+    fn refill_ids(&mut self, ids: &mut id_list::IdList) {
+        while let Some(range) = self.get_requested_ids() {
+            ids.add_ids(range);
+        }
+        if ids.should_request(Instant::now()) {
+            self.request_n_ids(id_list::ID_BLOCK_SIZE);
+        }
+    }
+
     /// Called every loop to update state
     fn sync(&mut self);
 
     fn get_lighting_info(&self) -> &GlobalLightingInfo;
+
+    /// Gets the objectives of the mission played on the map, in the order
+    /// they must be completed
+    fn get_mission(&self) -> &[mission::ObjectiveDef];
+
+    /// Gets where pickups are spawned on the map, or `None` if there are no
+    /// pickups
+    fn get_pickups(&self) -> Option<&pickup::SpawnTable>;
+
+    /// Gets the waves of enemies sent at the player, or `None` if the game
+    /// mode doesn't have waves
+    fn get_waves(&mut self) -> Option<&mut survival::Waves> {
+        None
+    }
+
+    /// Claims that a laser hit. The hit is applied once it's confirmed
+    fn claim_hit(&mut self, claim: HitClaim);
+
+    /// Takes the hits that were confirmed since the last call
+    fn take_confirmed_hits(&mut self) -> Vec<HitClaim>;
+
+    /// Gets the wormholes of the map
+    fn get_wormholes(&self) -> &[wormhole::Wormhole];
+
+    /// Claims that a body entered a wormhole. The body is teleported once
+    /// it's confirmed
+    fn claim_teleport(&mut self, claim: wormhole::TeleportClaim);
+
+    /// Takes the teleports that were confirmed since the last call
+    fn take_confirmed_teleports(&mut self) -> Vec<wormhole::TeleportClaim>;
+
+    /// Gets the measurements of the connection to the server, or `None` if
+    /// the game isn't networked
+    fn get_net_stats(&self) -> Option<remote::NetSummary> {
+        None
+    }
 }
 
 pub struct LocalGameController {
-    last_id: ObjectId,
+    ids: id_list::IdAllocator,
     objects: Vec<RemoteObject>,
     indices: HashMap<ObjectId, usize>,
     start_time: std::time::Instant,
     requested_ids: std::collections::VecDeque<(ObjectId, ObjectId)>,
     lighting: GlobalLightingInfo,
     player: PlayerStats,
+    mission: Vec<mission::ObjectiveDef>,
+    pickups: Option<pickup::SpawnTable>,
+    confirmed_hits: Vec<HitClaim>,
+    wormholes: Vec<wormhole::Wormhole>,
+    confirmed_teleports: Vec<wormhole::TeleportClaim>,
 }
 
 impl LocalGameController {
-    pub fn new<M: Map, Dm: std::ops::Deref<Target = M>>(map: Dm) -> Self {
+    pub fn new<M: Map + ?Sized, Dm: std::ops::Deref<Target = M>>(
+        map: Dm,
+    ) -> Self {
         let objs = map.initial_objects();
         let indices = (0..objs.len()).map(|i| (objs[i].id, i)).collect();
         let player_id = objs.last().map(|o| o.id.next()).unwrap_or_default();
         Self {
-            last_id: player_id.next(),
+            ids: id_list::IdAllocator::after(Some(player_id)),
             objects: objs,
             start_time: std::time::Instant::now(),
             indices,
@@ -64,15 +129,21 @@ impl LocalGameController {
             lighting: map.lighting_info(),
             player: PlayerStats {
                 pid: player_id,
-                spawn_pos: cgmath::point3(300., 100., 0.),
+                spawn_pos: map
+                    .spawn_points()
+                    .first()
+                    .map_or(cgmath::point3(0., 0., 0.), |&pos| pos.into()),
             },
+            mission: map.mission(),
+            pickups: map.pickups(),
+            confirmed_hits: Vec::new(),
+            wormholes: map.wormholes(),
+            confirmed_teleports: Vec::new(),
         }
     }
 
     pub fn alloc_obj(&mut self) -> ObjectId {
-        let id = self.last_id;
-        self.last_id = self.last_id.next();
-        id
+        self.ids.next_id().expect("Out of object ids")
     }
 }
 
@@ -162,20 +233,168 @@ impl GameController for LocalGameController {
     }
 
     fn request_n_ids(&mut self, n: u32) {
-        let id = self.last_id;
-        self.last_id = self.last_id.incr(n);
-        self.requested_ids.push_back((id, self.last_id));
+        if let Some(ids) = self.ids.reserve(n) {
+            self.requested_ids.push_back(ids);
+        }
     }
 
     fn get_requested_ids(&mut self) -> Option<(ObjectId, ObjectId)> {
         self.requested_ids.pop_front()
     }
 
+    fn reserve_ids_through(&mut self, id: ObjectId) {
+        self.ids.reserve_through(id);
+    }
+
     fn sync(&mut self) {}
 
     fn get_lighting_info(&self) -> &GlobalLightingInfo {
         &self.lighting
     }
+
+    fn get_mission(&self) -> &[mission::ObjectiveDef] {
+        &self.mission
+    }
+
+    fn get_pickups(&self) -> Option<&pickup::SpawnTable> {
+        self.pickups.as_ref()
+    }
+
+    /// Local games are authoritative, so every claim is confirmed
+    fn claim_hit(&mut self, claim: HitClaim) {
+        self.confirmed_hits.push(claim);
+    }
+
+    fn take_confirmed_hits(&mut self) -> Vec<HitClaim> {
+        std::mem::take(&mut self.confirmed_hits)
+    }
+
+    fn get_wormholes(&self) -> &[wormhole::Wormhole] {
+        &self.wormholes
+    }
+
+    /// Local games are authoritative, so every claim is confirmed
+    fn claim_teleport(&mut self, claim: wormhole::TeleportClaim) {
+        self.confirmed_teleports.push(claim);
+    }
+
+    fn take_confirmed_teleports(&mut self) -> Vec<wormhole::TeleportClaim> {
+        std::mem::take(&mut self.confirmed_teleports)
+    }
+}
+
+/// A local game where the player survives timed waves of enemies instead of
+/// playing the map's mission
+pub struct SurvivalGameController {
+    local: LocalGameController,
+    waves: survival::Waves,
+}
+
+impl SurvivalGameController {
+    /// Creates a survival game from the objects and ids of `local`
+    #[must_use]
+    pub fn new(local: LocalGameController) -> Self {
+        Self {
+            local,
+            waves: survival::Waves::default(),
+        }
+    }
+}
+
+impl GameController for SurvivalGameController {
+    fn get_game_objects(&self) -> &[RemoteObject] {
+        self.local.get_game_objects()
+    }
+
+    fn get_game_time(&self) -> std::time::Duration {
+        self.local.get_game_time()
+    }
+
+    fn get_game_stats(&self) -> &GameStats {
+        self.local.get_game_stats()
+    }
+
+    fn get_player_stats(&self) -> &PlayerStats {
+        self.local.get_player_stats()
+    }
+
+    fn set_objects(&mut self, objects: &[RemoteObject]) {
+        self.local.set_objects(objects);
+    }
+
+    fn update_objects(&mut self, updates: &[RemoteObjectUpdate]) {
+        self.local.update_objects(updates);
+    }
+
+    fn remove_objects(&mut self, ids: &[ObjectId]) {
+        self.local.remove_objects(ids);
+    }
+
+    fn request_n_ids(&mut self, n: u32) {
+        self.local.request_n_ids(n);
+    }
+
+    fn get_requested_ids(&mut self) -> Option<(ObjectId, ObjectId)> {
+        self.local.get_requested_ids()
+    }
+
+    fn reserve_ids_through(&mut self, id: ObjectId) {
+        self.local.reserve_ids_through(id);
+    }
+
+    fn sync(&mut self) {
+        self.local.sync();
+    }
+
+    fn get_lighting_info(&self) -> &GlobalLightingInfo {
+        self.local.get_lighting_info()
+    }
+
+    /// Survival games have no mission, the player plays until they run out
+    /// of lives
+    fn get_mission(&self) -> &[mission::ObjectiveDef] {
+        &[]
+    }
+
+    fn get_pickups(&self) -> Option<&pickup::SpawnTable> {
+        self.local.get_pickups()
+    }
+
+    fn get_waves(&mut self) -> Option<&mut survival::Waves> {
+        Some(&mut self.waves)
+    }
+
+    fn claim_hit(&mut self, claim: HitClaim) {
+        self.local.claim_hit(claim);
+    }
+
+    fn take_confirmed_hits(&mut self) -> Vec<HitClaim> {
+        self.local.take_confirmed_hits()
+    }
+
+    fn get_wormholes(&self) -> &[wormhole::Wormhole] {
+        self.local.get_wormholes()
+    }
+
+    fn claim_teleport(&mut self, claim: wormhole::TeleportClaim) {
+        self.local.claim_teleport(claim);
+    }
+
+    fn take_confirmed_teleports(&mut self) -> Vec<wormhole::TeleportClaim> {
+        self.local.take_confirmed_teleports()
+    }
+}
+
+/// A change in the objects a client simulates, sent by the server
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum OwnershipEvent {
+    /// Another client should take over the object. Answer with
+    /// `release_ownership`
+    HandoffRequested(ObjectId),
+    /// The client now simulates the object
+    Granted(ObjectId),
+    /// The client doesn't, or no longer, simulates the object
+    Denied(ObjectId),
 }
 
 #[allow(unused)]
@@ -191,33 +410,102 @@ pub struct RemoteGameController {
     peer: (IpAddr, u16),
     msg_buffer: ClientBuffer<ServerCommandType>,
     last_out_id: MsgId,
+    /// Hits confirmed by the server which haven't been taken yet
+    confirmed_hits: Vec<HitClaim>,
+    wormholes: Vec<wormhole::Wormhole>,
+    /// Teleports confirmed by the server which haven't been taken yet
+    confirmed_teleports: Vec<wormhole::TeleportClaim>,
+    /// Last state of the lobby the client is in, if it has joined one
+    lobby: Option<lobby::LobbyInfo>,
+    match_start: Option<lobby::MatchStart>,
+    match_results: Option<lobby::MatchResults>,
+    /// The player's progression, as last sent by the server
+    profile: profile::Profile,
+    /// Ownership changes which haven't been taken yet
+    ownership_events: Vec<OwnershipEvent>,
+    /// `true` if the server accepted sending compressed messages
+    compression: bool,
+    net_stats: remote::NetStats,
+    /// Seals everything sent to and opens everything received from the
+    /// server
+    session: session::Session,
 }
 
+/// Times each message sent while connecting is tried before giving up
+const CONNECT_TRIES: usize = 3;
+
 impl RemoteGameController {
-    fn login(
-        username: &str,
+    /// Sends `cmd` to the server until it responds, at most `CONNECT_TRIES`
+    /// times, and gets the response
+    ///
+    /// Each try is a new message, since a session drops messages it already
+    /// received
+    fn send_connecting(
         sock: &UdpSocket,
+        cmd: &ClientCommandType,
         last_out_id: &mut MsgId,
         received_msgs: &mut ClientBuffer<ServerCommandType>,
-    ) -> Result<LoginInfo, Box<dyn Error>> {
-        let mut trials = 0;
-        while trials < 3 {
-            match remote::send_important(
+        args: &ImportantArguments,
+    ) -> Result<ServerCommandType, Box<dyn Error>> {
+        let mut result = Err("The server did not respond".into());
+        for _ in 0..CONNECT_TRIES {
+            result = remote::send_important(
                 sock,
-                &ClientCommandType::Login(username.to_owned()),
+                cmd,
                 *last_out_id,
                 received_msgs,
-                &ImportantArguments::default(),
-            ) {
-                Ok(ServerCommandType::ReturnLogin(login)) => {
-                    *last_out_id = last_out_id.wrapping_add(1);
-                    return Ok(login);
-                }
-                Err(_) => trials += 1,
-                Ok(_) => panic!("Unexpected response"),
+                args,
+            );
+            *last_out_id = last_out_id.wrapping_add(1);
+            if result.is_ok() {
+                break;
             }
         }
-        Err("Could not receive data")?
+        result
+    }
+
+    /// Starts a session with the server, which every later message is sent
+    /// in
+    fn handshake(
+        sock: &UdpSocket,
+        last_out_id: &mut MsgId,
+        received_msgs: &mut ClientBuffer<ServerCommandType>,
+    ) -> Result<session::Session, Box<dyn Error>> {
+        let handshake = session::Handshake::new();
+        match Self::send_connecting(
+            sock,
+            &ClientCommandType::Handshake(handshake.public_key()),
+            last_out_id,
+            received_msgs,
+            &ImportantArguments::default(),
+        )? {
+            ServerCommandType::Handshake(server_key) => {
+                Ok(handshake.finish(server_key, session::Role::Client))
+            }
+            _ => Err("Unexpected response from the server".into()),
+        }
+    }
+
+    fn login(
+        username: &str,
+        sock: &UdpSocket,
+        last_out_id: &mut MsgId,
+        received_msgs: &mut ClientBuffer<ServerCommandType>,
+        session: &session::Session,
+    ) -> Result<LoginInfo, Box<dyn Error>> {
+        match Self::send_connecting(
+            sock,
+            &ClientCommandType::Login(username.to_owned(), true),
+            last_out_id,
+            received_msgs,
+            &ImportantArguments {
+                session: Some(session),
+                ..ImportantArguments::default()
+            },
+        )? {
+            ServerCommandType::ReturnLogin(login) => Ok(login),
+            _ => Err("Unexpected response from the server".into()),
+        }
     }
 
     fn get_initial_objects(
@@ -225,32 +513,27 @@ impl RemoteGameController {
         player: RemoteObject,
         last_out_id: &mut MsgId,
         received_msgs: &mut ClientBuffer<ServerCommandType>,
+        args: &ImportantArguments,
     ) -> Result<RemoteObjectMapPair, Box<dyn Error>> {
-        let mut trials = 0;
-        let mut objects = vec![player];
-        let mut indices = HashMap::new();
-        indices.insert(player.id, 0);
-        while trials < 3 {
-            match remote::send_important(
-                sock,
-                &ClientCommandType::Update(vec![player]),
-                *last_out_id,
-                received_msgs,
-                &ImportantArguments::default(),
-            ) {
-                Ok(ServerCommandType::Update(objs)) => {
-                    for (obj, idx) in objs.into_iter().zip(1..) {
-                        indices.insert(obj.id, idx);
-                        objects.push(obj);
-                    }
-                    *last_out_id = last_out_id.wrapping_add(1);
-                    return Ok((objects, indices));
+        match Self::send_connecting(
+            sock,
+            &ClientCommandType::Update(vec![player]),
+            last_out_id,
+            received_msgs,
+            args,
+        )? {
+            ServerCommandType::Update(objs) => {
+                let mut objects = vec![player];
+                let mut indices = HashMap::new();
+                indices.insert(player.id, 0);
+                for (obj, idx) in objs.into_iter().zip(1..) {
+                    indices.insert(obj.id, idx);
+                    objects.push(obj);
                 }
-                Err(_) => trials += 1,
-                Ok(_) => panic!("Unexpected response"),
+                Ok((objects, indices))
             }
+            _ => Err("Unexpected response from the server".into()),
         }
-        Err("Could not receive data")?
     }
 
     /// Creates a new `RemoteGameController` and connects to the server
@@ -260,13 +543,25 @@ impl RemoteGameController {
         username: &str,
         server: (IpAddr, u16),
     ) -> Result<Self, Box<dyn Error>> {
-        let sock = UdpSocket::bind(&server)?;
+        let local: IpAddr = if server.0.is_ipv4() {
+            std::net::Ipv4Addr::UNSPECIFIED.into()
+        } else {
+            std::net::Ipv6Addr::UNSPECIFIED.into()
+        };
+        let sock = UdpSocket::bind((local, 0))?;
         sock.connect(&server)?;
         let mut last_out_id = 0 as MsgId;
         let mut recieved_msgs = ClientBuffer::<ServerCommandType>::new();
         let mut available_ids = id_list::IdList::new();
-        let login_info =
-            Self::login(username, &sock, &mut last_out_id, &mut recieved_msgs)?;
+        let session =
+            Self::handshake(&sock, &mut last_out_id, &mut recieved_msgs)?;
+        let login_info = Self::login(
+            username,
+            &sock,
+            &mut last_out_id,
+            &mut recieved_msgs,
+            &session,
+        )?;
         available_ids.add_ids(login_info.starting_ids);
         let player = node::to_remote_object(
             &node::Node::default().pos(From::from(login_info.spawn_pos)),
@@ -280,11 +575,24 @@ impl RemoteGameController {
             player,
             &mut last_out_id,
             &mut recieved_msgs,
+            &ImportantArguments {
+                compress: login_info.compression,
+                session: Some(&session),
+                ..ImportantArguments::default()
+            },
         )?;
+        // seeded maps are generated locally instead of being sent
+        // TODO: receive the wormholes of other maps from the server
+        let (game_objects, wormholes) =
+            login_info.map_seed.map_or_else(Default::default, |seed| {
+                let map = game_map::ProceduralMap::new(seed);
+                (map.initial_objects(), map.wormholes())
+            });
+        sock.set_read_timeout(Some(SYNC_RECV_TIMEOUT))?;
         Ok(Self {
             server_objects,
             client_objects: vec![],
-            game_objects: vec![],
+            game_objects,
             indices,
             available_ids,
             lighting: login_info.lighting,
@@ -296,51 +604,202 @@ impl RemoteGameController {
             peer: server,
             msg_buffer: recieved_msgs,
             last_out_id,
+            confirmed_hits: Vec::new(),
+            wormholes,
+            confirmed_teleports: Vec::new(),
+            lobby: None,
+            match_start: None,
+            match_results: None,
+            profile: login_info.profile,
+            ownership_events: Vec::new(),
+            compression: login_info.compression,
+            net_stats: remote::NetStats::new(),
+            session,
         })
     }
 
-    /// Requests `n` ids from the server
-    fn get_ids_from_server(&mut self, n: u32) {
-        match remote::send_important(
+    /// Sends `cmd` to the server without waiting for a response
+    fn send_command(&mut self, cmd: &ClientCommandType) {
+        match remote::send_data_with(
             &self.sock,
-            &ClientCommandType::GetIds(n),
+            self.peer,
+            cmd,
             self.last_out_id,
-            &mut self.msg_buffer,
-            &ImportantArguments::default(),
-        )
-        .expect("Could not send ID request")
-        {
-            ServerCommandType::ReturnIds(ids) => {
-                self.last_out_id = self.last_out_id.wrapping_add(1);
-                self.available_ids.add_ids(ids);
-            }
-            _ => panic!("Unexpected response"),
+            self.compression,
+            Some(&self.session),
+        ) {
+            Ok(bytes) => self.net_stats.record_sent(bytes, Instant::now()),
+            Err(e) => warn!("Could not send command: {}", e),
         }
+        self.last_out_id = self.last_out_id.wrapping_add(1);
+    }
+
+    /// Joins the lobby named `name`, leaving the current lobby. The lobby's
+    /// state is available once the server responds
+    pub fn join_lobby(&mut self, name: &str) {
+        self.send_command(&ClientCommandType::JoinLobby(name.to_owned()));
+    }
+
+    /// Sets whether the player is ready for the next match of their lobby
+    pub fn set_ready(&mut self, ready: bool) {
+        self.send_command(&ClientCommandType::SetReady(ready));
+    }
+
+    pub fn leave_lobby(&mut self) {
+        self.send_command(&ClientCommandType::LeaveLobby);
+        self.lobby = None;
+    }
+
+    /// Gets the last state of the player's lobby received from the server,
+    /// or `None` if the player isn't in a lobby
+    #[must_use]
+    pub const fn lobby(&self) -> Option<&lobby::LobbyInfo> {
+        self.lobby.as_ref()
+    }
+
+    /// Gets the setup of the match that started since this was last called
+    pub fn take_match_start(&mut self) -> Option<lobby::MatchStart> {
+        self.match_start.take()
+    }
+
+    /// Gets the results of the match that ended since this was last called
+    pub fn take_match_results(&mut self) -> Option<lobby::MatchResults> {
+        self.match_results.take()
+    }
+
+    /// Gets the player's progression, which is updated whenever a match ends
+    #[must_use]
+    pub const fn profile(&self) -> &profile::Profile {
+        &self.profile
+    }
+
+    /// Asks the server to replace the player's loadouts with `loadouts`.
+    /// The server only accepts the change outside of a match, and responds
+    /// with the player's profile
+    ///
+    /// # Errors
+    /// Fails if a loadout is invalid or uses a ship that isn't unlocked
+    pub fn update_loadouts(
+        &mut self,
+        loadouts: Vec<profile::Loadout>,
+    ) -> Result<(), String> {
+        // checked against a copy, so the profile only changes once the
+        // server accepts
+        self.profile.clone().set_loadouts(loadouts.clone())?;
+        self.send_command(&ClientCommandType::UpdateProfile(loadouts));
+        Ok(())
+    }
+
+    /// Asks the server for authority over simulating the object `id`. The
+    /// server answers with a `Granted` or `Denied` event once the object's
+    /// owner has handed it off
+    pub fn request_ownership(&mut self, id: ObjectId) {
+        self.send_command(&ClientCommandType::RequestOwnership(id));
+    }
+
+    /// Answers a `HandoffRequested` event for `id`. If `released` is `true`,
+    /// the client must stop sending the object, since the server ignores
+    /// updates of objects the client doesn't own
+    pub fn release_ownership(&mut self, id: ObjectId, released: bool) {
+        self.send_command(&ClientCommandType::ReleaseOwnership(id, released));
+    }
+
+    /// Gets the ownership changes the server sent since this was last called
+    pub fn take_ownership_events(&mut self) -> Vec<OwnershipEvent> {
+        std::mem::take(&mut self.ownership_events)
+    }
+
+    /// Gets the objects the server sent in its last update, which are the
+    /// objects of the other clients near the player
+    #[must_use]
+    pub fn server_objects(&self) -> &[RemoteObject] {
+        &self.server_objects
     }
 
     fn send_update(&mut self) -> Result<(), Box<dyn Error>> {
         if self.client_objects.is_empty() {
             return Ok(());
         }
-        let out = remote::send_data(
+        let out = remote::send_data_with(
             &self.sock,
             self.peer,
             &ClientCommandType::UpdateReadOnly(&self.client_objects),
             self.last_out_id,
+            self.compression,
+            Some(&self.session),
         );
         self.last_out_id = self.last_out_id.wrapping_add(1);
-        out
+        let bytes = out?;
+        self.net_stats.record_sent(bytes, Instant::now());
+        Ok(())
+    }
+
+    /// Handles every message the server sent since the last call
+    fn get_responses(&mut self) {
+        loop {
+            let stats = &mut self.net_stats;
+            match remote::recv_data_recorded(
+                &self.sock,
+                &mut self.msg_buffer,
+                Some(&self.session),
+                |_, bytes| stats.record_received(bytes, Instant::now()),
+            ) {
+                Ok(Some((response, _))) => self.handle_response(response),
+                Ok(None) => (),
+                Err(_) => break,
+            }
+        }
     }
 
-    fn get_response(&mut self) {
-        if let Ok(Some(response)) =
-            remote::recv_data(&self.sock, &mut self.msg_buffer)
-        {
-            match response {
-                (ServerCommandType::Update(objs), _) => {
-                    self.server_objects = objs;
-                }
-                _ => panic!("Unexpected response"),
+    fn handle_response(&mut self, response: ServerCommandType) {
+        let _span = trace_span!("response").entered();
+        match response {
+            ServerCommandType::Update(objs) => {
+                self.server_objects = objs;
+            }
+            ServerCommandType::ReturnIds(ids) => {
+                self.available_ids.add_ids(ids);
+            }
+            ServerCommandType::ConfirmHit(hit) => {
+                self.confirmed_hits.push(hit);
+            }
+            ServerCommandType::ConfirmTeleport(teleport) => {
+                self.confirmed_teleports.push(teleport);
+            }
+            ServerCommandType::LobbyState(lobby) => {
+                let joined =
+                    lobby.players.iter().any(|p| p.pid == self.player.pid);
+                self.lobby = if joined { Some(lobby) } else { None };
+            }
+            ServerCommandType::MatchStart(start) => {
+                self.match_start = Some(start);
+            }
+            ServerCommandType::MatchEnd(results) => {
+                self.match_results = Some(results);
+            }
+            ServerCommandType::Pong(token) => {
+                self.net_stats.on_pong(token, Instant::now());
+            }
+            ServerCommandType::Profile(profile) => {
+                self.profile = profile;
+            }
+            ServerCommandType::HandoffRequest(id) => {
+                self.ownership_events
+                    .push(OwnershipEvent::HandoffRequested(id));
+            }
+            ServerCommandType::OwnershipGranted(id) => {
+                // acknowledged right away, so the server commits the
+                // transfer before the client's next update
+                self.send_command(&ClientCommandType::AckOwnership(id));
+                self.ownership_events.push(OwnershipEvent::Granted(id));
+            }
+            ServerCommandType::OwnershipDenied(id) => {
+                self.ownership_events.push(OwnershipEvent::Denied(id));
+            }
+            // a late response to a request that was already retried
+            ServerCommandType::ReturnLogin(_)
+            | ServerCommandType::Handshake(_) => {
+                warn!("Unexpected response");
             }
         }
     }
@@ -375,19 +834,70 @@ impl GameController for RemoteGameController {
         todo!()
     }
 
+    /// Requests `n` ids from the server, which are available once the
+    /// server responds
     fn request_n_ids(&mut self, n: u32) {
-        self.get_ids_from_server(n);
+        self.send_command(&ClientCommandType::GetIds(n));
     }
 
     fn get_requested_ids(&mut self) -> Option<(ObjectId, ObjectId)> {
         self.available_ids.pop_front()
     }
 
+    /// Sends the client's objects to the server, pings the server if a ping
+    /// is due, and handles the server's responses
     fn sync(&mut self) {
-        self.get_response();
+        let _span = trace_span!("sync").entered();
+        if let Err(e) = self.send_update() {
+            warn!("Could not send update: {}", e);
+        }
+        if let Some(token) = self.net_stats.ping_due(Instant::now()) {
+            self.send_command(&ClientCommandType::Ping(token));
+        }
+        self.get_responses();
     }
 
     fn get_lighting_info(&self) -> &GlobalLightingInfo {
         &self.lighting
     }
+
+    fn get_mission(&self) -> &[mission::ObjectiveDef] {
+        // TODO: receive the mission from the server
+        &[]
+    }
+
+    fn get_pickups(&self) -> Option<&pickup::SpawnTable> {
+        // TODO: receive the pickups from the server
+        None
+    }
+
+    /// Sends the claim to the server, which broadcasts it to every client if
+    /// it's plausible
+    fn claim_hit(&mut self, claim: HitClaim) {
+        self.send_command(&ClientCommandType::ClaimHit(claim));
+    }
+
+    fn take_confirmed_hits(&mut self) -> Vec<HitClaim> {
+        std::mem::take(&mut self.confirmed_hits)
+    }
+
+    fn get_wormholes(&self) -> &[wormhole::Wormhole] {
+        &self.wormholes
+    }
+
+    /// Sends the claim to the server, which confirms it if it's plausible
+    fn claim_teleport(&mut self, claim: wormhole::TeleportClaim) {
+        self.send_command(&ClientCommandType::ClaimTeleport(claim));
+    }
+
+    fn take_confirmed_teleports(&mut self) -> Vec<wormhole::TeleportClaim> {
+        std::mem::take(&mut self.confirmed_teleports)
+    }
+
+    fn get_net_stats(&self) -> Option<remote::NetSummary> {
+        Some(remote::NetSummary {
+            reassembly: self.msg_buffer.stats(),
+            ..self.net_stats.summary(Instant::now())
+        })
+    }
 }
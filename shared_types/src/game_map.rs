@@ -2,6 +2,7 @@ use super::*;
 use crate::node::{to_remote_object, Node};
 use cgmath::*;
 use rand;
+use rand::{Rng, SeedableRng};
 use std::ops::Range;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -17,6 +18,35 @@ pub trait Map {
     fn initial_objects(&self) -> Vec<RemoteObject>;
 
     fn lighting_info(&self) -> GlobalLightingInfo;
+
+    /// Gets the objectives of the map's mission in the order they must be
+    /// completed. Maps without a mission are played freely
+    fn mission(&self) -> Vec<mission::ObjectiveDef> {
+        Vec::new()
+    }
+
+    /// Gets where pickups are spawned on the map, or `None` if the map has
+    /// no pickups
+    fn pickups(&self) -> Option<pickup::SpawnTable> {
+        None
+    }
+
+    /// Gets the wormholes of the map
+    fn wormholes(&self) -> Vec<wormhole::Wormhole> {
+        Vec::new()
+    }
+
+    /// Gets the seed the map is generated from, or `None` if the map isn't
+    /// procedurally generated. Clients generate seeded maps themselves
+    /// instead of receiving the map's objects
+    fn seed(&self) -> Option<u64> {
+        None
+    }
+
+    /// Gets the points players spawn at
+    fn spawn_points(&self) -> Vec<[f64; 3]> {
+        vec![[300., 100., 0.]]
+    }
 }
 
 pub struct AsteroidMap {}
@@ -101,12 +131,33 @@ impl Map for AsteroidMap {
             0. ..PI,
             0.002..0.8,
             100,
+            |t| {
+                // the station's approach is kept clear
+                if t.get_pos().distance(STATION_POS) > STATION_CLEARANCE {
+                    vec.push(to_remote_object(
+                        &t,
+                        &vec3(0., 0., 0.),
+                        &vec3(0., 0., 0.),
+                        ObjectType::Asteroid,
+                        ids,
+                    ));
+                    ids = ids.incr(1);
+                }
+            },
+        );
+        Self::randomize_spherical(
+            point3(0., 0., 0.),
+            250. ..550.,
+            0. ..2. * PI,
+            0. ..PI,
+            40. ..120.,
+            8,
             |t| {
                 vec.push(to_remote_object(
                     &t,
                     &vec3(0., 0., 0.),
                     &vec3(0., 0., 0.),
-                    ObjectType::Asteroid,
+                    ObjectType::Cloud,
                     ids,
                 ));
                 ids = ids.incr(1);
@@ -119,6 +170,19 @@ impl Map for AsteroidMap {
             ObjectType::Planet,
             ids,
         ));
+        ids = ids.incr(1);
+        // the station's docking bay faces away from the planet
+        vec.push(to_remote_object(
+            &Node::default().pos(STATION_POS).rot(Quaternion::from_arc(
+                vec3(0., 0., 1.),
+                STATION_POS.to_vec().normalize(),
+                None,
+            )),
+            &vec3(0., 0., 0.),
+            &vec3(0., 0., 0.),
+            ObjectType::Station,
+            ids,
+        ));
         vec
     }
 
@@ -129,4 +193,306 @@ impl Map for AsteroidMap {
             dir_light: vec3(-2_396.839_8, -1_668.553, 3_637.501).normalize(),
         }
     }
+
+    fn mission(&self) -> Vec<mission::ObjectiveDef> {
+        use mission::{Objective, ObjectiveDef};
+        use std::time::Duration;
+        vec![
+            ObjectiveDef::new(
+                "Fly to the far side of the planet",
+                Objective::ReachWaypoint {
+                    pos: [-300., 0., 0.],
+                    radius: 80.,
+                },
+            ),
+            // the first asteroid of the map
+            ObjectiveDef::new(
+                "Protect the marked asteroid",
+                Objective::Protect {
+                    target: ObjectId::default(),
+                    duration: Duration::from_secs(45),
+                },
+            ),
+            ObjectiveDef::new(
+                "Destroy 5 asteroids",
+                Objective::DestroyAsteroids(5),
+            ),
+            ObjectiveDef::new(
+                "Survive the enemy squadron",
+                Objective::Survive(Duration::from_secs(60)),
+            ),
+        ]
+    }
+
+    fn pickups(&self) -> Option<pickup::SpawnTable> {
+        use pickup::PickupKind::*;
+        use std::f64::consts::PI;
+        let mut points = Vec::new();
+        Self::randomize_spherical(
+            point3(0., 0., 0.),
+            150. ..450.,
+            0. ..2. * PI,
+            0. ..PI,
+            // the scale of the spawn points is ignored
+            0.5..1.,
+            12,
+            |t| points.push(t.get_pos().into()),
+        );
+        Some(pickup::SpawnTable {
+            points,
+            kinds: vec![(ShieldCell, 3), (EnergyCell, 3), (WeaponMod, 1)],
+            respawn_time: std::time::Duration::from_secs(30),
+        })
+    }
+
+    /// A wormhole between opposite sides of the field, which cuts across
+    /// the planet
+    fn wormholes(&self) -> Vec<wormhole::Wormhole> {
+        use wormhole::{Portal, Wormhole};
+        vec![Wormhole {
+            ends: [
+                Portal::new(point3(0., 0., 650.), vec3(0., 0., -1.)),
+                Portal::new(point3(0., 0., -650.), vec3(0., 0., 1.)),
+            ],
+            radius: WORMHOLE_RADIUS,
+        }]
+    }
+}
+
+/// Position of the station on the asteroid map
+const STATION_POS: Point3<f64> = Point3::new(350., -60., 300.);
+/// Distance from the station that no asteroids are placed within on the
+/// asteroid map
+const STATION_CLEARANCE: f64 = 250.;
+/// Radius of the sensor of the wormholes on the built in maps
+const WORMHOLE_RADIUS: f64 = 15.;
+/// Number of wormholes in a procedural map
+const WORMHOLE_COUNT: usize = 2;
+
+/// Radius of the sphere around each spawn point that is kept clear
+pub const SPAWN_ZONE_RADIUS: f64 = 80.;
+/// Distance from the center of a procedural map that objects are placed
+/// within
+const FIELD_RADIUS: f64 = 650.;
+/// Number of asteroid clusters a procedural map tries to place
+const CLUSTER_COUNT: usize = 8;
+/// Minimum distance between the centers of asteroid clusters
+const CLUSTER_SPACING: f64 = 220.;
+/// Radius of an asteroid cluster
+const CLUSTER_RADIUS: f64 = 110.;
+/// Candidate points tried for each cluster before giving up on it
+const CLUSTER_ATTEMPTS: u32 = 30;
+/// Average number of asteroids in a cluster
+const ASTEROIDS_PER_CLUSTER: f64 = 14.;
+/// Number of nebulae in a procedural map
+const NEBULA_COUNT: usize = 6;
+/// Scale of the planet, whose radius is roughly 10 times its scale
+const PLANET_SCALE: f64 = 10.;
+
+/// An asteroid field generated from a seed. The same seed always generates
+/// the same map
+///
+/// Asteroids are grouped into clusters spread out by Poisson-disk sampling,
+/// each with its own density, and the area around each spawn point is kept
+/// clear
+pub struct ProceduralMap {
+    seed: u64,
+}
+
+impl ProceduralMap {
+    #[must_use]
+    pub const fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    /// `true` if a sphere of radius `radius` at `pos` doesn't overlap any
+    /// spawn zone
+    fn clear_of_spawns(pos: Point3<f64>, radius: f64) -> bool {
+        SPAWN_POINTS.iter().all(|spawn| {
+            pos.distance(Point3::from(*spawn)) > SPAWN_ZONE_RADIUS + radius
+        })
+    }
+
+    /// Gets a uniformly distributed random point in a ball of radius `radius`
+    /// around `center`. `falloff` above `1` concentrates points towards the
+    /// center
+    fn point_in_ball<R: Rng>(
+        rng: &mut R,
+        center: Point3<f64>,
+        radius: f64,
+        falloff: f64,
+    ) -> Point3<f64> {
+        use std::f64::consts::PI;
+        let theta = rng.gen_range(0. ..2. * PI);
+        let cos_phi: f64 = rng.gen_range(-1. ..1.);
+        let sin_phi = (1. - cos_phi * cos_phi).sqrt();
+        let dist = radius * rng.gen::<f64>().powf(falloff / 3.);
+        center
+            + vec3(sin_phi * theta.cos(), sin_phi * theta.sin(), cos_phi) * dist
+    }
+
+    fn random_rotation<R: Rng>(rng: &mut R) -> Quaternion<f64> {
+        let axis = vec3(
+            rng.gen_range(-1. ..1.),
+            rng.gen_range(-1. ..1.),
+            rng.gen_range(-1. ..1.),
+        );
+        if axis.magnitude2() < 1e-6 {
+            return Quaternion::from_sv(1., vec3(0., 0., 0.));
+        }
+        Quaternion::from_axis_angle(
+            axis.normalize(),
+            Deg(rng.gen_range(0. ..360.)),
+        )
+        .normalize()
+    }
+
+    /// Places the centers of the asteroid clusters at least
+    /// `CLUSTER_SPACING` apart by dart throwing, skipping clusters with no
+    /// room left for them
+    fn cluster_centers<R: Rng>(
+        rng: &mut R,
+        planet: Point3<f64>,
+    ) -> Vec<Point3<f64>> {
+        let mut centers: Vec<Point3<f64>> = Vec::new();
+        for _ in 0..CLUSTER_COUNT {
+            let center = (0..CLUSTER_ATTEMPTS)
+                .map(|_| {
+                    Self::point_in_ball(
+                        rng,
+                        point3(0., 0., 0.),
+                        FIELD_RADIUS,
+                        1.,
+                    )
+                })
+                .find(|pos| {
+                    centers.iter().all(|c| c.distance(*pos) > CLUSTER_SPACING)
+                        && pos.distance(planet) > CLUSTER_SPACING
+                        && Self::clear_of_spawns(*pos, CLUSTER_RADIUS * 0.5)
+                });
+            centers.extend(center);
+        }
+        centers
+    }
+
+    /// Gets the position of the planet, near the center of the map
+    fn planet_pos<R: Rng>(rng: &mut R) -> Point3<f64> {
+        loop {
+            let pos = Self::point_in_ball(rng, point3(0., 0., 0.), 150., 1.);
+            if Self::clear_of_spawns(pos, PLANET_SCALE * 10.) {
+                return pos;
+            }
+        }
+    }
+}
+
+/// Points players spawn at on procedural maps
+const SPAWN_POINTS: [[f64; 3]; 4] = [
+    [300., 100., 0.],
+    [-300., 100., 0.],
+    [0., 100., 300.],
+    [0., 100., -300.],
+];
+
+impl Map for ProceduralMap {
+    fn initial_objects(&self) -> Vec<RemoteObject> {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(self.seed);
+        let mut vec = Vec::new();
+        let mut ids = ObjectId::default();
+        let mut push = |node: &Node, typ| {
+            vec.push(to_remote_object(
+                node,
+                &vec3(0., 0., 0.),
+                &vec3(0., 0., 0.),
+                typ,
+                ids,
+            ));
+            ids = ids.incr(1);
+        };
+        let planet = Self::planet_pos(&mut rng);
+        for center in Self::cluster_centers(&mut rng, planet) {
+            let density: f64 = rng.gen_range(0.5..1.5);
+            let count = (ASTEROIDS_PER_CLUSTER * density).round() as usize;
+            for _ in 0..count {
+                let pos =
+                    Self::point_in_ball(&mut rng, center, CLUSTER_RADIUS, 2.);
+                let scale = rng.gen_range(0.002..0.8);
+                let rot = Self::random_rotation(&mut rng);
+                // asteroids in spawn zones are dropped rather than moved,
+                // so the rest of the map is unaffected
+                if Self::clear_of_spawns(pos, scale * 10.) {
+                    push(
+                        &Node::default().pos(pos).u_scale(scale).rot(rot),
+                        ObjectType::Asteroid,
+                    );
+                }
+            }
+        }
+        for _ in 0..NEBULA_COUNT {
+            let pos = Self::point_in_ball(
+                &mut rng,
+                point3(0., 0., 0.),
+                FIELD_RADIUS,
+                1.,
+            );
+            let scale = rng.gen_range(40. ..120.);
+            push(&Node::default().pos(pos).u_scale(scale), ObjectType::Cloud);
+        }
+        push(
+            &Node::default().pos(planet).u_scale(PLANET_SCALE),
+            ObjectType::Planet,
+        );
+        vec
+    }
+
+    fn lighting_info(&self) -> GlobalLightingInfo {
+        AsteroidMap {}.lighting_info()
+    }
+
+    fn pickups(&self) -> Option<pickup::SpawnTable> {
+        use pickup::PickupKind::*;
+        // pickups use their own stream so they don't shift the objects
+        let mut rng =
+            rand::rngs::StdRng::seed_from_u64(self.seed.wrapping_add(1));
+        let points = (0..12)
+            .map(|_| {
+                Self::point_in_ball(&mut rng, point3(0., 0., 0.), 450., 1.)
+                    .into()
+            })
+            .collect();
+        Some(pickup::SpawnTable {
+            points,
+            kinds: vec![(ShieldCell, 3), (EnergyCell, 3), (WeaponMod, 1)],
+            respawn_time: std::time::Duration::from_secs(30),
+        })
+    }
+
+    /// Wormholes join random points at the edge of the field, facing
+    /// inwards
+    fn wormholes(&self) -> Vec<wormhole::Wormhole> {
+        use wormhole::{Portal, Wormhole};
+        // wormholes use their own stream so they don't shift the objects
+        let mut rng =
+            rand::rngs::StdRng::seed_from_u64(self.seed.wrapping_add(2));
+        let mut portal = || {
+            let dir = Self::point_in_ball(&mut rng, point3(0., 0., 0.), 1., 0.)
+                .to_vec()
+                .normalize();
+            Portal::new(Point3::from_vec(dir * FIELD_RADIUS), -dir)
+        };
+        (0..WORMHOLE_COUNT)
+            .map(|_| Wormhole {
+                ends: [portal(), portal()],
+                radius: WORMHOLE_RADIUS,
+            })
+            .collect()
+    }
+
+    fn seed(&self) -> Option<u64> {
+        Some(self.seed)
+    }
+
+    fn spawn_points(&self) -> Vec<[f64; 3]> {
+        SPAWN_POINTS.to_vec()
+    }
 }
@@ -1,5 +1,15 @@
 use super::*;
 use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Amount of ids requested at once
+pub const ID_BLOCK_SIZE: u32 = 1024;
+/// More ids are requested once fewer than this many are left
+pub const MIN_IDS: usize = 64;
+/// Time after which ids are requested again if they weren't received
+const ID_REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+/// Amount of distinct object ids
+const ID_COUNT: u64 = ObjectIdType::MAX as u64 + 1;
 
 /// A data type that caches allocated object IDs to use to create new objects
 pub struct IdList {
@@ -8,6 +18,8 @@ pub struct IdList {
     /// Invariant: prev_id is `None` or in between the first and last
     /// ids of the front range in the list
     prev_id: Option<ObjectId>,
+    /// When more ids were last requested, if they weren't received yet
+    requested: Option<Instant>,
 }
 
 impl IdList {
@@ -17,6 +29,7 @@ impl IdList {
         Self {
             ids: VecDeque::new(),
             prev_id: None,
+            requested: None,
         }
     }
 
@@ -43,9 +56,45 @@ impl IdList {
         next_id
     }
 
-    /// Adds a range of allocated ids to the list
+    /// Adds a range of allocated ids to the list. Empty ranges are ignored
     pub fn add_ids(&mut self, range: (ObjectId, ObjectId)) {
-        self.ids.push_back(range);
+        self.requested = None;
+        if range.0 != range.1 {
+            self.ids.push_back(range);
+        }
+    }
+
+    /// Checks if more ids should be requested at `now`, which is when fewer
+    /// than `MIN_IDS` are left and no request is awaiting a response. If
+    /// `true`, the request is assumed to be sent
+    pub fn should_request(&mut self, now: Instant) -> bool {
+        let awaiting = self.requested.map_or(false, |requested| {
+            now.saturating_duration_since(requested) < ID_REQUEST_TIMEOUT
+        });
+        if self.remaining() < MIN_IDS && !awaiting {
+            self.requested = Some(now);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Discards every available id up to and including `id`, such as when
+    /// objects loaded from a save already use them
+    pub fn skip_through(&mut self, id: ObjectId) {
+        while let Some((begin, end)) = self.ids.front_mut() {
+            if u64::from(end.id) > u64::from(id.id) + 1 {
+                if begin.id <= id.id {
+                    *begin = id.next();
+                    if self.prev_id.map_or(false, |prev| prev.id <= id.id) {
+                        self.prev_id = None;
+                    }
+                }
+                return;
+            }
+            self.ids.pop_front();
+            self.prev_id = None;
+        }
     }
 
     pub fn pop_front(&mut self) -> Option<(ObjectId, ObjectId)> {
@@ -91,6 +140,85 @@ impl Default for IdList {
     }
 }
 
+/// Hands out blocks of object ids which never overlap
+///
+/// Ids are handed out in increasing order, wrapping around past the largest
+/// id to the smallest. An id that was handed out may still be in use, so the
+/// allocator never hands out an id twice. Once it runs out of ids it's
+/// exhausted, instead of wrapping around into ids that were handed out
+pub struct IdAllocator {
+    /// Next id to hand out
+    next: ObjectId,
+    /// Amount of ids left to hand out, starting at `next`
+    remaining: u64,
+}
+
+impl IdAllocator {
+    /// Creates an allocator which hands out the ids from `first` up to but
+    /// not including `end`, wrapping around if `end` is before `first`.
+    /// Every id is handed out if `first` and `end` are the same
+    #[must_use]
+    pub fn new(first: ObjectId, end: ObjectId) -> Self {
+        let remaining = match u64::from(end.id.wrapping_sub(first.id)) {
+            0 => ID_COUNT,
+            len => len,
+        };
+        Self {
+            next: first,
+            remaining,
+        }
+    }
+
+    /// Creates an allocator which hands out the ids after `last`, such as
+    /// the id of the last object of a map, up to the largest id. Every id is
+    /// handed out if `last` is `None`
+    #[must_use]
+    pub fn after(last: Option<ObjectId>) -> Self {
+        let first = last.map_or(0, |last| u64::from(last.id) + 1);
+        Self {
+            next: ObjectId::new(first as ObjectIdType),
+            remaining: ID_COUNT - first,
+        }
+    }
+
+    /// Reserves a block of `n` ids
+    ///
+    /// Returns the first id of the block and the id after its last, which
+    /// is less than the first if the block wraps around, or `None` if fewer
+    /// than `n` ids are left
+    pub fn reserve(&mut self, n: u32) -> Option<(ObjectId, ObjectId)> {
+        if u64::from(n) > self.remaining {
+            return None;
+        }
+        let first = self.next;
+        self.next = first.incr(n);
+        self.remaining -= u64::from(n);
+        Some((first, self.next))
+    }
+
+    /// Reserves a single id, or returns `None` if the allocator is exhausted
+    pub fn next_id(&mut self) -> Option<ObjectId> {
+        self.reserve(1).map(|(id, _)| id)
+    }
+
+    /// Stops the ids up to and including `id` from being handed out, such
+    /// as when objects loaded from elsewhere already use them. Does nothing
+    /// if `id` can't be handed out anyway
+    pub fn reserve_through(&mut self, id: ObjectId) {
+        let skipped = u64::from(id.id.wrapping_sub(self.next.id)) + 1;
+        if skipped <= self.remaining {
+            self.next = id.next();
+            self.remaining -= skipped;
+        }
+    }
+
+    /// Gets the amount of ids left to hand out
+    #[must_use]
+    pub const fn remaining(&self) -> u64 {
+        self.remaining
+    }
+}
+
 #[test]
 fn id_list_add_remove_cnt() {
     let mut lst = IdList::new();
@@ -115,3 +243,82 @@ fn id_list_add_remove_cnt() {
             + (5 as ObjectIdType).wrapping_sub(ObjectIdType::MAX - 10)
     );
 }
+
+#[test]
+fn id_list_skip_through() {
+    let mut lst = IdList::new();
+    lst.add_ids((ObjectId::new(10), ObjectId::new(20)));
+    lst.add_ids((ObjectId::new(30), ObjectId::new(40)));
+    assert_eq!(lst.next_id(), Some(ObjectId::new(10)));
+    lst.skip_through(ObjectId::new(14));
+    assert_eq!(lst.next_id(), Some(ObjectId::new(15)));
+    lst.skip_through(ObjectId::new(25));
+    assert_eq!(lst.next_id(), Some(ObjectId::new(30)));
+    lst.skip_through(ObjectId::new(2));
+    assert_eq!(lst.next_id(), Some(ObjectId::new(31)));
+    lst.skip_through(ObjectId::new(39));
+    assert_eq!(lst.next_id(), None);
+}
+
+#[test]
+fn id_list_requests_ids_when_low() {
+    let start = Instant::now();
+    let mut lst = IdList::new();
+    assert!(lst.should_request(start));
+    // the request is still awaiting a response
+    assert!(!lst.should_request(start + Duration::from_millis(500)));
+    assert!(lst.should_request(start + ID_REQUEST_TIMEOUT));
+    lst.add_ids((ObjectId::new(0), ObjectId::new(ID_BLOCK_SIZE)));
+    assert!(!lst.should_request(start + ID_REQUEST_TIMEOUT));
+    for _ in 0..=ID_BLOCK_SIZE as usize - MIN_IDS {
+        lst.next_id();
+    }
+    assert!(lst.should_request(start + ID_REQUEST_TIMEOUT));
+    // empty ranges don't add ids
+    lst.add_ids((ObjectId::new(5), ObjectId::new(5)));
+    assert_eq!(lst.remaining(), MIN_IDS - 1);
+}
+
+#[test]
+fn id_allocator_reserves_disjoint_blocks() {
+    let mut ids = IdAllocator::after(Some(ObjectId::new(9)));
+    assert_eq!(
+        ids.reserve(ID_BLOCK_SIZE),
+        Some((ObjectId::new(10), ObjectId::new(1034)))
+    );
+    assert_eq!(ids.next_id(), Some(ObjectId::new(1034)));
+    ids.reserve_through(ObjectId::new(2000));
+    assert_eq!(ids.next_id(), Some(ObjectId::new(2001)));
+    // ids that were already handed out stay handed out
+    ids.reserve_through(ObjectId::new(20));
+    assert_eq!(ids.next_id(), Some(ObjectId::new(2002)));
+    assert_eq!(ids.remaining(), ID_COUNT - 2003);
+    assert_eq!(IdAllocator::after(None).remaining(), ID_COUNT);
+    let mut last = IdAllocator::after(Some(ObjectId::new(ObjectIdType::MAX)));
+    assert_eq!(last.next_id(), None);
+}
+
+#[test]
+fn id_allocator_wraps_around_without_collisions() {
+    let mut ids = IdAllocator::new(
+        ObjectId::new(ObjectIdType::MAX - 9),
+        ObjectId::new(20),
+    );
+    assert_eq!(ids.remaining(), 30);
+    assert_eq!(
+        ids.reserve(15),
+        Some((ObjectId::new(ObjectIdType::MAX - 9), ObjectId::new(5)))
+    );
+    let mut lst = IdList::new();
+    lst.add_ids(ids.reserve(15).unwrap());
+    assert_eq!(lst.remaining(), 15);
+    assert_eq!(lst.next_id(), Some(ObjectId::new(5)));
+    // exhausted once it would wrap into ids it handed out
+    assert_eq!(ids.reserve(1), None);
+    assert_eq!(ids.next_id(), None);
+
+    let mut all = IdAllocator::new(ObjectId::new(7), ObjectId::new(7));
+    assert_eq!(all.remaining(), ID_COUNT);
+    all.reserve_through(ObjectId::new(6));
+    assert_eq!(all.remaining(), 0);
+}
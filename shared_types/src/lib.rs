@@ -37,6 +37,9 @@ pub type CommandId = u8;
 pub type MsgId = u32;
 pub type ChunkedMsg = BTreeMap<PacketNum, Vec<u8>>;
 
+pub mod codec;
+mod compression;
+pub mod crash;
 mod serializeable;
 pub use serializeable::Serializeable;
 
@@ -47,6 +50,15 @@ pub use remote::*;
 pub mod game_controller;
 pub mod game_map;
 pub mod id_list;
+pub mod lobby;
+pub mod map_file;
+pub mod mission;
+pub mod pickup;
+pub mod profile;
+pub mod save;
+pub mod session;
+pub mod survival;
+pub mod wormhole;
 
 #[cfg(test)]
 mod test;
@@ -65,9 +77,12 @@ mod test;
  The footer is \n
 
  The cmd_id is the type of command being sent. Unique among every command.
+    Its highest bit is set if the data is compressed.
  The msg_id is a unique id for the message as determined by the sender,
     in big endian byte order.
  The packet_number is the order of the chunked packet in the message
+ The data is encoded by `codec`. Numbers are big endian, and strings and
+    lists are prefixed by their length in one byte.
 */
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
@@ -80,6 +95,9 @@ pub enum ObjectType {
     Hook,
     Planet,
     Cloud,
+    Pickup,
+    Station,
+    Debris,
 }
 
 impl TryFrom<u8> for ObjectType {
@@ -91,7 +109,11 @@ impl TryFrom<u8> for ObjectType {
             2 => Ok(Self::Asteroid),
             3 => Ok(Self::Skybox),
             4 => Ok(Self::Hook),
-            5 => Ok(Self::Cloud),
+            5 => Ok(Self::Planet),
+            6 => Ok(Self::Cloud),
+            7 => Ok(Self::Pickup),
+            8 => Ok(Self::Station),
+            9 => Ok(Self::Debris),
             _ => {
                 Err(format!("Invalid object type byte representation: {}", val))
             }
@@ -106,7 +128,7 @@ impl ObjectType {
     /// Undefined behavior if this condition is not met
     ///
     /// # Safety
-    /// `val` must be between 0 and 5 inclusive
+    /// `val` must be between 0 and 9 inclusive
     #[must_use]
     pub unsafe fn from_unchecked(val: u8) -> Self {
         std::mem::transmute(val)
@@ -198,13 +220,6 @@ pub struct RemoteObject {
     pub typ: ObjectType,
 }
 
-/// The packed size for a `RemoteObject`
-///
-/// This is the amount of bytes sent over the network for a `RemoteObject`
-const REMOTE_OBJECT_SIZE: usize = std::mem::size_of::<ObjData>()
-    + std::mem::size_of::<ObjectId>()
-    + std::mem::size_of::<ObjectType>();
-
 #[derive(Copy, Clone, Debug)]
 pub struct RemoteObjectUpdate {
     pub delta_vel: [f64; 3],
@@ -243,26 +258,69 @@ impl Eq for RemoteObject {}
 /// A command that is sent from the client to the server
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub enum ClientCommandType<'a> {
-    Login(String),
+    /// Logs in with the username, and whether the client can receive
+    /// compressed messages
+    Login(String, bool),
     Update(Vec<RemoteObject>),
     UpdateReadOnly(&'a [RemoteObject]),
     GetIds(u32),
+    /// Claims that a laser hit, which the server checks before confirming
+    ClaimHit(HitClaim),
+    /// Claims that one of the client's bodies entered a wormhole, which the
+    /// server checks before confirming
+    ClaimTeleport(wormhole::TeleportClaim),
+    /// Joins the lobby with the given name, creating it if it doesn't exist,
+    /// and leaves the client's current lobby
+    JoinLobby(String),
+    /// Sets whether the client is ready for the lobby's next match
+    SetReady(bool),
+    LeaveLobby,
+    /// Asks the server to respond with a `Pong` with the same token, to
+    /// measure the round-trip time
+    Ping(u32),
+    /// Starts a session with the client's public key, which the server
+    /// answers with its own. Sent before logging in
+    Handshake([u8; session::PUBLIC_KEY_SIZE]),
+    /// Replaces the player's loadouts, which the server answers with the
+    /// player's profile. Only accepted outside of a match
+    UpdateProfile(Vec<profile::Loadout>),
+    /// Asks for authority over simulating the object, such as when the
+    /// client starts towing it
+    RequestOwnership(ObjectId),
+    /// Answers a `HandoffRequest` for the object, `true` if the client
+    /// stopped simulating it so another client can take over
+    ReleaseOwnership(ObjectId, bool),
+    /// Confirms that the client took over simulating the object it was
+    /// granted
+    AckOwnership(ObjectId),
+}
+
+/// A claim that a laser fired by `shooter` hit `target`
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct HitClaim {
+    pub shooter: ObjectId,
+    pub target: ObjectId,
+    /// Point the target was hit at, in world space
+    pub hit_point: [f64; 3],
 }
 
+impl Eq for HitClaim {}
+
 #[derive(Clone, PartialEq, Debug)]
 pub struct LoginInfo {
     pub pid: ObjectId,
     pub lighting: game_map::GlobalLightingInfo,
     pub spawn_pos: [f64; 3],
     pub starting_ids: (ObjectId, ObjectId),
+    /// Seed of the map if it's procedurally generated, in which case the
+    /// client generates the map's objects itself
+    pub map_seed: Option<u64>,
+    /// `true` if the server and client send each other compressed messages
+    pub compression: bool,
+    /// The progression the server saved for the player's username
+    pub profile: profile::Profile,
 }
 
-const LOGIN_MIN_SIZE: usize = std::mem::size_of::<ObjectId>()
-    + std::mem::size_of::<[f32; 3]>()
-    + 2 * 2
-    + std::mem::size_of::<[f64; 3]>()
-    + std::mem::size_of::<(ObjectId, ObjectId)>();
-
 impl Eq for LoginInfo {}
 
 /// Commands sent from the server to the client
@@ -271,4 +329,28 @@ pub enum ServerCommandType {
     ReturnLogin(LoginInfo),
     Update(Vec<RemoteObject>),
     ReturnIds((ObjectId, ObjectId)),
+    /// A hit claimed by a client which the server found plausible, and which
+    /// every client should apply
+    ConfirmHit(HitClaim),
+    /// A teleport claimed by the client which the server found plausible,
+    /// and which the client should apply
+    ConfirmTeleport(wormhole::TeleportClaim),
+    /// The state of the client's lobby, sent whenever it changes
+    LobbyState(lobby::LobbyInfo),
+    MatchStart(lobby::MatchStart),
+    MatchEnd(lobby::MatchResults),
+    /// The response to a `Ping` with its token
+    Pong(u32),
+    /// The server's public key for the session the client started
+    Handshake([u8; session::PUBLIC_KEY_SIZE]),
+    /// The player's profile, sent when it changes
+    Profile(profile::Profile),
+    /// Asks the owner of the object to hand it off to another client
+    HandoffRequest(ObjectId),
+    /// The client now simulates the object, and must answer with an
+    /// `AckOwnership`
+    OwnershipGranted(ObjectId),
+    /// The client's request for the object was refused, or the object was
+    /// taken back after the client didn't acknowledge it in time
+    OwnershipDenied(ObjectId),
 }
@@ -0,0 +1,86 @@
+use super::ObjectId;
+use std::time::Duration;
+
+/// Fewest players a match can be played with
+pub const MIN_PLAYERS: usize = 2;
+/// Most players a lobby can hold
+pub const MAX_PLAYERS: usize = 16;
+
+/// The part of the match lifecycle a lobby is in
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum LobbyPhase {
+    /// Waiting for enough players to join and every player to be ready
+    Waiting,
+    /// Every player is ready, and the match starts once the time left runs
+    /// out
+    Countdown(Duration),
+    /// A match is being played
+    InMatch,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct LobbyPlayer {
+    pub pid: ObjectId,
+    pub name: String,
+    pub ready: bool,
+}
+
+/// A named group of players who play matches together
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct LobbyInfo {
+    pub name: String,
+    pub phase: LobbyPhase,
+    /// The players in the lobby, in the order they joined
+    pub players: Vec<LobbyPlayer>,
+}
+
+impl LobbyInfo {
+    /// `true` if there are enough players for a match and every player is
+    /// ready
+    #[must_use]
+    pub fn all_ready(&self) -> bool {
+        self.players.len() >= MIN_PLAYERS
+            && self.players.iter().all(|player| player.ready)
+    }
+}
+
+/// The setup of a match, which every player in the lobby is sent when it
+/// starts
+#[derive(Clone, PartialEq, Debug)]
+pub struct MatchStart {
+    /// Seed of the map the match is played on, if it's procedurally
+    /// generated
+    pub map_seed: Option<u64>,
+    /// The point each player spawns at
+    pub spawns: Vec<(ObjectId, [f64; 3])>,
+}
+
+impl Eq for MatchStart {}
+
+/// The results of a match, which every player in the lobby is sent when it
+/// ends
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MatchResults {
+    /// The score of each player, from highest to lowest
+    pub scores: Vec<(ObjectId, u32)>,
+}
+
+impl MatchResults {
+    /// Creates the results from the scores of the players in any order
+    #[must_use]
+    pub fn new(mut scores: Vec<(ObjectId, u32)>) -> Self {
+        scores.sort_by(|(_, a), (_, b)| b.cmp(a));
+        Self { scores }
+    }
+
+    /// Gets the player with the highest score, or `None` if there's a tie
+    /// for first or no players
+    #[must_use]
+    pub fn winner(&self) -> Option<ObjectId> {
+        match self.scores[..] {
+            [(_, first), (_, second), ..] if first == second => None,
+            [(pid, _), ..] => Some(pid),
+            [] => None,
+        }
+    }
+}
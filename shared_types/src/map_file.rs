@@ -0,0 +1,201 @@
+use super::*;
+use crate::game_map::{GlobalLightingInfo, Map};
+use crate::node::{to_remote_object, Node};
+use cgmath::*;
+use std::error::Error;
+
+/// Directory map files are looked up in by name
+pub const MAPS_DIR: &str = "assets/maps";
+
+/// A map loaded from a map file
+#[derive(Clone, Debug)]
+pub struct FileMap {
+    objects: Vec<RemoteObject>,
+    lighting: GlobalLightingInfo,
+    spawn_points: Vec<[f64; 3]>,
+    wormholes: Vec<wormhole::Wormhole>,
+}
+
+/// Parses `N` whitespace separated floats
+fn parse_floats<const N: usize>(val: &str) -> Result<[f64; N], Box<dyn Error>> {
+    val.split_whitespace()
+        .map(str::parse)
+        .collect::<Result<Vec<f64>, _>>()?
+        .try_into()
+        .map_err(|_| format!("Expected {} numbers: \"{}\"", N, val).into())
+}
+
+/// Parses the transform of an object, which is its position and scale,
+/// optionally followed by the axis and degrees it's rotated by
+fn parse_transform(val: &str) -> Result<Node, Box<dyn Error>> {
+    let nums = val
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<Vec<f64>, _>>()?;
+    match nums[..] {
+        [x, y, z, scale] => {
+            Ok(Node::default().pos(point3(x, y, z)).u_scale(scale))
+        }
+        [x, y, z, scale, ax, ay, az, degrees]
+            if vec3(ax, ay, az).magnitude2() > f64::EPSILON =>
+        {
+            let rot = Quaternion::from_axis_angle(
+                vec3(ax, ay, az).normalize(),
+                Deg(degrees),
+            );
+            Ok(Node::default().pos(point3(x, y, z)).u_scale(scale).rot(rot))
+        }
+        _ => Err(format!("Invalid object transform: \"{}\"", val).into()),
+    }
+}
+
+/// Parses a wormhole, which is the position and facing of each of its
+/// portals followed by the radius of their sensors
+fn parse_wormhole(val: &str) -> Result<wormhole::Wormhole, Box<dyn Error>> {
+    use wormhole::{Portal, Wormhole};
+    let [ax, ay, az, anx, any, anz, bx, by, bz, bnx, bny, bnz, radius] =
+        parse_floats::<13>(val)?;
+    let (a_normal, b_normal) = (vec3(anx, any, anz), vec3(bnx, bny, bnz));
+    if a_normal.magnitude2() <= f64::EPSILON
+        || b_normal.magnitude2() <= f64::EPSILON
+    {
+        return Err(format!("Invalid wormhole facing: \"{}\"", val).into());
+    }
+    Ok(Wormhole {
+        ends: [
+            Portal::new(point3(ax, ay, az), a_normal),
+            Portal::new(point3(bx, by, bz), b_normal),
+        ],
+        radius,
+    })
+}
+
+impl FileMap {
+    /// Parses a map from the contents of a map file
+    ///
+    /// The map file is a list of `key = value` lines. Empty lines and lines
+    /// beginning with `#` are ignored. The keys are:
+    ///
+    /// * `skybox`, `hdr` - paths of the skybox and its lighting map
+    /// * `light` - direction of the directional light, as `x y z`
+    /// * `spawn` - a spawn point, as `x y z`. May be repeated
+    /// * `wormhole` - a wormhole, as the `x y z` position and `x y z` facing
+    /// of each of its portals followed by the radius of their sensors. May
+    /// be repeated
+    /// * `asteroid`, `planet`, `cloud`, `station` - an object, as
+    /// `x y z scale`, optionally followed by the `x y z degrees` axis-angle
+    /// of its rotation. May be repeated
+    ///
+    /// `skybox`, `hdr` and `light` are required. If there are no spawn points,
+    /// the default spawn point of a map is used
+    ///
+    /// # Errors
+    /// Fails if there is an unknown or missing key, a line is malformed, or a
+    /// value cannot be parsed
+    pub fn parse(map: &str) -> Result<Self, Box<dyn Error>> {
+        let mut objects = Vec::new();
+        let mut spawn_points = Vec::new();
+        let mut wormholes = Vec::new();
+        let (mut skybox, mut hdr, mut light) = (None, None, None);
+        let mut id = ObjectId::default();
+        for line in map
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        {
+            let (key, val) = line
+                .split_once('=')
+                .map(|(k, v)| (k.trim(), v.trim()))
+                .ok_or_else(|| format!("Malformed map line: \"{}\"", line))?;
+            let typ = match key {
+                "skybox" => {
+                    skybox = Some(val.to_owned());
+                    continue;
+                }
+                "hdr" => {
+                    hdr = Some(val.to_owned());
+                    continue;
+                }
+                "light" => {
+                    light = Some(Vector3::from(parse_floats::<3>(val)?));
+                    continue;
+                }
+                "spawn" => {
+                    spawn_points.push(parse_floats::<3>(val)?);
+                    continue;
+                }
+                "wormhole" => {
+                    wormholes.push(parse_wormhole(val)?);
+                    continue;
+                }
+                "asteroid" => ObjectType::Asteroid,
+                "planet" => ObjectType::Planet,
+                "cloud" => ObjectType::Cloud,
+                "station" => ObjectType::Station,
+                _ => return Err(format!("Unknown map key \"{}\"", key).into()),
+            };
+            objects.push(to_remote_object(
+                &parse_transform(val)?,
+                &vec3(0., 0., 0.),
+                &vec3(0., 0., 0.),
+                typ,
+                id.consume(),
+            ));
+        }
+        let light: Vector3<f64> = light.ok_or("Map is missing `light`")?;
+        Ok(Self {
+            objects,
+            lighting: GlobalLightingInfo {
+                skybox: skybox.ok_or("Map is missing `skybox`")?,
+                hdr: hdr.ok_or("Map is missing `hdr`")?,
+                dir_light: light.normalize().cast().ok_or("Invalid `light`")?,
+            },
+            spawn_points,
+            wormholes,
+        })
+    }
+
+    /// Loads the map from the file at `path`
+    ///
+    /// # Errors
+    /// Fails if the file cannot be read or parsed
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn Error>> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Loads the map named `name` from the maps directory
+    ///
+    /// # Errors
+    /// Fails if the file cannot be read or parsed
+    pub fn by_name(name: &str) -> Result<Self, Box<dyn Error>> {
+        Self::from_file(&Self::path_of(name))
+    }
+
+    /// Gets the path of the file of the map named `name`
+    #[must_use]
+    pub fn path_of(name: &str) -> String {
+        format!("{}/{}.map", MAPS_DIR, name)
+    }
+}
+
+impl Map for FileMap {
+    fn initial_objects(&self) -> Vec<RemoteObject> {
+        self.objects.clone()
+    }
+
+    fn lighting_info(&self) -> GlobalLightingInfo {
+        self.lighting.clone()
+    }
+
+    fn wormholes(&self) -> Vec<wormhole::Wormhole> {
+        self.wormholes.clone()
+    }
+
+    fn spawn_points(&self) -> Vec<[f64; 3]> {
+        if self.spawn_points.is_empty() {
+            game_map::AsteroidMap {}.spawn_points()
+        } else {
+            self.spawn_points.clone()
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use super::ObjectId;
+use std::time::Duration;
+
+/// A goal the player must complete
+#[derive(Clone, Debug, PartialEq)]
+pub enum Objective {
+    /// Destroy the given number of asteroids
+    DestroyAsteroids(u32),
+    /// Stay alive for the given amount of time
+    Survive(Duration),
+    /// Fly within `radius` of `pos`
+    ReachWaypoint { pos: [f64; 3], radius: f64 },
+    /// Keep the object with the id `target` from being destroyed for
+    /// `duration`
+    Protect {
+        target: ObjectId,
+        duration: Duration,
+    },
+}
+
+/// An objective and the text which describes it to the player
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObjectiveDef {
+    pub description: String,
+    pub objective: Objective,
+}
+
+impl ObjectiveDef {
+    #[must_use]
+    pub fn new(description: &str, objective: Objective) -> Self {
+        Self {
+            description: description.to_owned(),
+            objective,
+        }
+    }
+}
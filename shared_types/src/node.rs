@@ -1,7 +1,7 @@
 //use std::collections::BTreeMap;
 use cgmath::*;
 use std::cell::{Cell, RefCell};
-use std::rc::Rc;
+use std::rc::{Rc, Weak};
 
 /// A node in a transformation heirarchy with a position, rotation, scale, and anchor point
 /// that can have a parent. The node represents the transformation from the local coordinate space to
@@ -26,7 +26,12 @@ pub struct Node {
     orientation: Quaternion<f64>,
     anchor: Point3<f64>,
     parent: Option<Rc<RefCell<Node>>>,
-    /// None iff invalidated
+    /// Nodes attached to this one with `Node::attach`. Children that were
+    /// dropped or moved to another parent since are skipped
+    children: Vec<Weak<RefCell<Node>>>,
+    /// The transformation to the parent's space. None iff invalidated
+    local: Cell<Option<Matrix4<f64>>>,
+    /// The transformation to world space. None iff invalidated
     transform: Cell<Option<Matrix4<f64>>>,
     /// The version number of `parent`'s `transform` that was used to compute `transform`
     last_parent_ver: Cell<u64>,
@@ -34,17 +39,28 @@ pub struct Node {
     trans_ver: Cell<u64>,
 }
 
+#[cfg(test)]
+std::thread_local! {
+    /// Amount of matrix multiplications done by nodes on this thread
+    static MAT_MULS: Cell<u64> = Cell::new(0);
+}
+
+/// Computes `T(anchor) * T(pos) * R(orientation) * S(scale) * T(-anchor)`
+/// without multiplying any matrices, since a point `x` is mapped to
+/// `anchor + pos + RS(x - anchor)`
 fn transform_matrix(
     anchor: &Point3<f64>,
     pos: &Point3<f64>,
     orientation: &Quaternion<f64>,
     scale: &Vector3<f64>,
 ) -> Matrix4<f64> {
-    Matrix4::from_translation(anchor.to_vec())
-        * Matrix4::from_translation(pos.to_vec())
-        * Matrix4::from(*orientation)
-        * Matrix4::from_nonuniform_scale(scale.x, scale.y, scale.z)
-        * Matrix4::from_translation(anchor.to_vec() * -1f64)
+    let rot = Matrix3::from(*orientation);
+    let rot_scale =
+        Matrix3::from_cols(rot.x * scale.x, rot.y * scale.y, rot.z * scale.z);
+    let offset = anchor.to_vec() + pos.to_vec() - rot_scale * anchor.to_vec();
+    let mut mat = Matrix4::from(rot_scale);
+    mat.w = offset.extend(1.);
+    mat
 }
 
 impl Node {
@@ -80,18 +96,16 @@ impl Node {
             Some(pt) => pt,
             None => point3(0., 0., 0.),
         };
+        let local = transform_matrix(&anchor, &pos, &orientation, &scale);
         Self {
-            transform: Cell::new(Some(transform_matrix(
-                &anchor,
-                &pos,
-                &orientation,
-                &scale,
-            ))),
+            local: Cell::new(Some(local)),
+            transform: Cell::new(Some(local)),
             pos,
             scale,
             orientation,
             anchor,
             parent: None,
+            children: Vec::new(),
             last_parent_ver: Cell::new(0),
             trans_ver: Cell::new(0),
         }
@@ -157,18 +171,32 @@ impl Node {
         self
     }
 
+    /// Makes `child` a child of `parent`, which also lets it be visited by
+    /// `descendants` of `parent`
+    pub fn attach(parent: &Rc<RefCell<Self>>, child: &Rc<RefCell<Self>>) {
+        child.borrow_mut().set_parent(parent.clone());
+        let mut parent = parent.borrow_mut();
+        parent.children.retain(|child| child.strong_count() > 0);
+        parent.children.push(Rc::downgrade(child));
+    }
+
+    /// Gets an iterator over the nodes attached below `node`, depth first
+    /// with every node before its children
+    #[must_use]
+    pub fn descendants(node: &Rc<RefCell<Self>>) -> Descendants {
+        let mut descendants = Descendants { stack: Vec::new() };
+        descendants.push_children(node);
+        descendants
+    }
+
     /// Gets the transformation matrix
     /// # Panics
     /// Panics if another thread is using the node's matrix
     #[inline]
     pub fn mat(&self) -> Matrix4<f64> {
-        if self.needs_to_recompute() {
-            self.update_matrix()
-        } else {
-            let t = self.transform.take();
-            let m = t.unwrap();
-            self.transform.set(t);
-            m
+        match self.transform.get() {
+            Some(mat) if !self.needs_to_recompute() => mat,
+            _ => self.update_matrix(),
         }
     }
 
@@ -192,76 +220,91 @@ impl Node {
     #[inline]
     pub fn set_pos(&mut self, pos: Point3<f64>) {
         self.pos = pos;
-        self.transform.set(None);
+        self.invalidate();
     }
 
     /// Sets the nonuniform scale factors
     #[inline]
     pub fn set_scale(&mut self, scale: Vector3<f64>) {
         self.scale = scale;
-        self.transform.set(None);
+        self.invalidate();
     }
 
     /// Sets the uniform scale factor
     #[inline]
     pub fn set_u_scale(&mut self, scale: f64) {
         self.scale = vec3(scale, scale, scale);
-        self.transform.set(None);
+        self.invalidate();
     }
 
     /// Sets the orientation
     #[inline]
     pub fn set_rot(&mut self, rot: Quaternion<f64>) {
         self.orientation = rot;
-        self.transform.set(None);
+        self.invalidate();
     }
 
     /// Sets the anchor shift
     #[inline]
     pub fn set_anchor(&mut self, anchor: Point3<f64>) {
         self.anchor = anchor;
+        self.invalidate();
+    }
+
+    /// Invalidates the cached local and world transformation matrices
+    #[inline]
+    fn invalidate(&mut self) {
+        self.local.set(None);
         self.transform.set(None);
     }
 
     /// Returns `true` if we need to recompute the cached transformation matrix
+    ///
+    /// The world matrix is stale if it was invalidated, or if an ancestor's
+    /// world matrix changed since it was computed
     fn needs_to_recompute(&self) -> bool {
-        let t = self.transform.take();
-        let local_recompute = t.is_none();
-        self.transform.set(t);
-        let last_par_ver = self.last_parent_ver.take();
-        local_recompute
+        self.transform.get().is_none()
             || self.parent.as_ref().map_or(false, |parent| {
-                let ver = parent.borrow().trans_ver.take();
-                let recompute =
-                    last_par_ver != ver || parent.borrow().needs_to_recompute();
-                parent.borrow().trans_ver.set(ver);
-                recompute
+                let parent = parent.borrow();
+                self.last_parent_ver.get() != parent.trans_ver.get()
+                    || parent.needs_to_recompute()
             })
     }
 
+    /// Gets the transformation to the parent's space, recomputing it if it
+    /// was invalidated
+    fn local_mat(&self) -> Matrix4<f64> {
+        self.local.get().unwrap_or_else(|| {
+            let mat = transform_matrix(
+                &self.anchor,
+                &self.pos,
+                &self.orientation,
+                &self.scale,
+            );
+            self.local.set(Some(mat));
+            mat
+        })
+    }
+
     /// Updates `self.transform` and returns the new matrix
+    ///
+    /// Only the parent's matrix is multiplied with the cached local matrix,
+    /// so a node whose ancestors moved costs one matrix multiplication
     fn update_matrix(&self) -> Matrix4<f64> {
-        let mat = transform_matrix(
-            &self.anchor,
-            &self.pos,
-            &self.orientation,
-            &self.scale,
-        );
+        let mat = self.local_mat();
         let t_prime = match &self.parent {
             Some(node) => {
-                let parent = node.borrow().mat();
-                parent * mat
+                let parent = node.borrow();
+                let parent_mat = parent.mat();
+                self.last_parent_ver.set(parent.trans_ver.get());
+                #[cfg(test)]
+                MAT_MULS.with(|muls| muls.set(muls.get() + 1));
+                parent_mat * mat
             }
             None => mat,
         };
         self.transform.set(Some(t_prime));
-        self.last_parent_ver
-            .set(self.parent.as_ref().map_or(0, |parent| {
-                let t = parent.borrow().trans_ver.take();
-                parent.borrow().trans_ver.set(t);
-                t
-            }));
-        self.trans_ver.set(self.trans_ver.take().wrapping_add(1));
+        self.trans_ver.set(self.trans_ver.get().wrapping_add(1));
         t_prime
     }
 
@@ -289,7 +332,7 @@ impl Node {
     #[inline]
     pub fn rotate_world(&mut self, rot: Quaternion<f64>) {
         self.orientation = self.orientation * rot;
-        self.transform.set(None);
+        self.invalidate();
     }
 
     /// Rotate the node by `rot`, which is respect to the node's local space
@@ -298,14 +341,14 @@ impl Node {
     #[inline]
     pub fn rotate_local(&mut self, rot: Quaternion<f64>) {
         self.orientation = rot * self.orientation;
-        self.transform.set(None);
+        self.invalidate();
     }
 
     /// Translates the node by `translation` units in world space
     #[inline]
     pub fn translate(&mut self, translation: Vector3<f64>) {
         self.pos += translation;
-        self.transform.set(None);
+        self.invalidate();
     }
 
     #[inline]
@@ -322,6 +365,44 @@ impl Node {
     }
 }
 
+/// Iterator over the descendants of a node, from `Node::descendants`
+pub struct Descendants {
+    /// Nodes left to visit, with the next one last
+    stack: Vec<Rc<RefCell<Node>>>,
+}
+
+impl Descendants {
+    /// Adds the children still attached to `node` to the nodes to visit,
+    /// so that the first child is visited next
+    fn push_children(&mut self, node: &Rc<RefCell<Node>>) {
+        let start = self.stack.len();
+        self.stack.extend(
+            node.borrow()
+                .children
+                .iter()
+                .filter_map(Weak::upgrade)
+                .filter(|child| {
+                    child
+                        .borrow()
+                        .parent
+                        .as_ref()
+                        .map_or(false, |parent| Rc::ptr_eq(parent, node))
+                }),
+        );
+        self.stack[start..].reverse();
+    }
+}
+
+impl Iterator for Descendants {
+    type Item = Rc<RefCell<Node>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.push_children(&node);
+        Some(node)
+    }
+}
+
 impl From<&'_ Node> for Matrix4<f64> {
     fn from(node: &'_ Node) -> Self {
         node.mat()
@@ -407,6 +488,88 @@ mod test {
         assert_eq!(p, point3(14., 3., 0.));
     }
 
+    #[test]
+    fn descendants_are_visited_depth_first() {
+        let ship = Rc::new(RefCell::new(Node::default()));
+        let turret = Rc::new(RefCell::new(Node::default()));
+        let barrel = Rc::new(RefCell::new(Node::default()));
+        let engine = Rc::new(RefCell::new(Node::default()));
+        Node::attach(&ship, &turret);
+        Node::attach(&turret, &barrel);
+        Node::attach(&ship, &engine);
+        let visited: Vec<_> = Node::descendants(&ship).collect();
+        assert_eq!(visited.len(), 3);
+        assert!(Rc::ptr_eq(&visited[0], &turret));
+        assert!(Rc::ptr_eq(&visited[1], &barrel));
+        assert!(Rc::ptr_eq(&visited[2], &engine));
+
+        // moved and dropped children are skipped
+        barrel.borrow_mut().set_parent(engine.clone());
+        drop(visited);
+        drop(engine);
+        let visited: Vec<_> = Node::descendants(&ship).collect();
+        assert_eq!(visited.len(), 1);
+        assert!(Rc::ptr_eq(&visited[0], &turret));
+    }
+
+    /// Counts the matrix multiplications done by nodes during `frame`
+    fn mat_muls<F: FnOnce()>(frame: F) -> u64 {
+        let start = MAT_MULS.with(Cell::get);
+        frame();
+        MAT_MULS.with(Cell::get) - start
+    }
+
+    #[test]
+    fn cached_matrices_save_multiplications() {
+        // every moving asteroid used to cost 4 multiplications per frame,
+        // and 4 more every other time its matrix was read
+        let mut asteroids: Vec<_> = (0..500)
+            .map(|i| Node::default().pos(point3(f64::from(i), 0., 0.)))
+            .collect();
+        let muls = mat_muls(|| {
+            for asteroid in &mut asteroids {
+                asteroid.translate(vec3(1., 0., 0.));
+                asteroid.mat();
+                asteroid.mat();
+            }
+        });
+        assert_eq!(muls, 0);
+
+        let ship = Rc::new(RefCell::new(Node::default()));
+        let turret =
+            Rc::new(RefCell::new(Node::default().pos(point3(0., 1., 0.))));
+        let barrel =
+            Rc::new(RefCell::new(Node::default().pos(point3(0., 0., 2.))));
+        Node::attach(&ship, &turret);
+        Node::attach(&turret, &barrel);
+        barrel.borrow().mat();
+        // a moving ship recomputes the turret and barrel once each
+        let muls = mat_muls(|| {
+            ship.borrow_mut().translate(vec3(1., 0., 0.));
+            barrel.borrow().mat();
+            turret.borrow().mat();
+            barrel.borrow().mat();
+        });
+        assert_eq!(muls, 2);
+        // an aiming barrel doesn't recompute the turret
+        let muls = mat_muls(|| {
+            barrel
+                .borrow_mut()
+                .rotate_local(Euler::new(Deg(0.), Deg(30f64), Deg(0.)).into());
+            barrel.borrow().mat();
+            turret.borrow().mat();
+        });
+        assert_eq!(muls, 1);
+        // reading the matrix again doesn't recompute anything
+        assert_eq!(
+            mat_muls(|| {
+                barrel.borrow().mat();
+            }),
+            0
+        );
+        assert_eq!(barrel.borrow().get_pos(), point3(1., 1., 2.));
+    }
+
     #[test]
     fn scale_test() {
         let n = Node::new(None, None, Some(vec3(2., 2., 1.)), None);
@@ -0,0 +1,55 @@
+use std::time::Duration;
+
+/// The effect a pickup has on the player that collects it
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum PickupKind {
+    /// Restores the player's shield
+    ShieldCell,
+    /// Restores the player's energy
+    EnergyCell,
+    /// Temporarily increases how quickly the player can fire
+    WeaponMod,
+}
+
+/// The pickups spawned on a map and where they are spawned
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct SpawnTable {
+    /// Points pickups are spawned at. Each point holds at most one pickup
+    /// at a time
+    pub points: Vec<[f64; 3]>,
+    /// The kinds of pickups that can spawn, each with its weight. The chance
+    /// of a kind being spawned is its weight divided by the total weight
+    pub kinds: Vec<(PickupKind, u32)>,
+    /// Time after a pickup is collected before another is spawned at its
+    /// point
+    pub respawn_time: Duration,
+}
+
+impl SpawnTable {
+    /// Chooses the kind of pickup to spawn
+    ///
+    /// `roll` - a number from `0` to `1`, where each kind takes up a part of
+    /// the range proportional to its weight
+    ///
+    /// Returns `None` if no kind has a positive weight
+    #[must_use]
+    pub fn choose(&self, roll: f64) -> Option<PickupKind> {
+        let total: u32 = self.kinds.iter().map(|(_, weight)| weight).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut remaining = (roll.clamp(0., 1.) * f64::from(total)) as u32;
+        for (kind, weight) in &self.kinds {
+            if remaining < *weight {
+                return Some(*kind);
+            }
+            remaining -= weight;
+        }
+        // `roll` is 1
+        self.kinds
+            .iter()
+            .rev()
+            .find(|(_, weight)| *weight > 0)
+            .map(|(kind, _)| *kind)
+    }
+}
@@ -0,0 +1,221 @@
+use std::error::Error;
+use std::fmt::Display;
+
+/// The ship every player starts with
+pub const DEFAULT_SHIP: &str = "StarSparrow";
+/// Most loadouts a player can save
+pub const MAX_LOADOUTS: usize = 8;
+/// Most pieces of gear a loadout can hold
+pub const MAX_GEAR: usize = 8;
+/// Longest name of a loadout, ship or piece of gear
+pub const MAX_NAME_LEN: usize = 32;
+
+/// The ships players can unlock, and the wins each ship is unlocked at
+pub const SHIP_UNLOCKS: [(&str, u32); 1] = [(DEFAULT_SHIP, 0)];
+
+/// The record of every match a player has played
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct CareerStats {
+    pub matches: u32,
+    pub wins: u32,
+    /// Confirmed hits over every match
+    pub hits: u32,
+}
+
+/// A named ship and the gear it's flown with
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Loadout {
+    pub name: String,
+    pub ship: String,
+    pub gear: Vec<String>,
+}
+
+/// `true` if `name` can name a loadout, ship or piece of gear, which is the
+/// case if it's short and has no whitespace
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && !name.chars().any(char::is_whitespace)
+}
+
+impl Loadout {
+    /// Checks that every name in the loadout is valid, and that the loadout
+    /// isn't too big
+    ///
+    /// # Errors
+    /// Returns the reason the loadout is invalid
+    pub fn validate(&self) -> Result<(), String> {
+        if self.gear.len() > MAX_GEAR {
+            return Err(format!("Loadout {} has too much gear", self.name));
+        }
+        std::iter::once(&self.name)
+            .chain(std::iter::once(&self.ship))
+            .chain(self.gear.iter())
+            .find(|name| !is_valid_name(name))
+            .map_or(Ok(()), |name| Err(format!("Invalid name \"{}\"", name)))
+    }
+}
+
+/// Parses a loadout from `name ship gear...`
+impl TryFrom<&str> for Loadout {
+    type Error = String;
+    fn try_from(val: &str) -> Result<Self, Self::Error> {
+        let mut words = val.split_whitespace().map(str::to_owned);
+        match (words.next(), words.next()) {
+            (Some(name), Some(ship)) => {
+                let loadout = Self {
+                    name,
+                    ship,
+                    gear: words.collect(),
+                };
+                loadout.validate()?;
+                Ok(loadout)
+            }
+            _ => Err(format!("Invalid loadout: \"{}\"", val)),
+        }
+    }
+}
+
+impl Display for Loadout {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} {}", self.name, self.ship)?;
+        for gear in &self.gear {
+            write!(f, " {}", gear)?;
+        }
+        Ok(())
+    }
+}
+
+/// The progression of a player, which the server keeps between logins
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Profile {
+    pub stats: CareerStats,
+    pub unlocked_ships: Vec<String>,
+    pub loadouts: Vec<Loadout>,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        let mut profile = Self {
+            stats: CareerStats::default(),
+            unlocked_ships: Vec::new(),
+            loadouts: Vec::new(),
+        };
+        profile.unlock_ships();
+        profile
+    }
+}
+
+impl Profile {
+    /// Unlocks every ship the player has won enough matches for
+    fn unlock_ships(&mut self) {
+        for (ship, wins) in SHIP_UNLOCKS {
+            if self.stats.wins >= wins
+                && !self.unlocked_ships.iter().any(|s| s == ship)
+            {
+                self.unlocked_ships.push(ship.to_owned());
+            }
+        }
+    }
+
+    /// Records a match the player finished with `hits` confirmed hits,
+    /// unlocking the ships they've earned
+    pub fn record_match(&mut self, hits: u32, won: bool) {
+        self.stats.matches = self.stats.matches.saturating_add(1);
+        self.stats.hits = self.stats.hits.saturating_add(hits);
+        if won {
+            self.stats.wins = self.stats.wins.saturating_add(1);
+        }
+        self.unlock_ships();
+    }
+
+    /// Replaces the player's loadouts with `loadouts`
+    ///
+    /// # Errors
+    /// Fails if there are too many loadouts, a loadout is invalid, or a
+    /// loadout uses a ship that isn't unlocked
+    pub fn set_loadouts(
+        &mut self,
+        loadouts: Vec<Loadout>,
+    ) -> Result<(), String> {
+        if loadouts.len() > MAX_LOADOUTS {
+            return Err("Too many loadouts".to_owned());
+        }
+        for loadout in &loadouts {
+            loadout.validate()?;
+            if !self.unlocked_ships.contains(&loadout.ship) {
+                return Err(format!("Ship {} isn't unlocked", loadout.ship));
+            }
+        }
+        self.loadouts = loadouts;
+        Ok(())
+    }
+
+    /// Parses a profile from the contents of a profile file
+    ///
+    /// The profile file is a list of `key = value` lines. Empty lines and
+    /// lines beginning with `#` are ignored. The keys are `matches`, `wins`
+    /// and `hits`, along with `ship`, an unlocked ship, and `loadout`, a
+    /// loadout as `name ship gear...`, which may be repeated
+    ///
+    /// # Errors
+    /// Fails if there is an unknown key, a line is malformed, or a value
+    /// cannot be parsed
+    pub fn parse(file: &str) -> Result<Self, Box<dyn Error>> {
+        let mut profile = Self {
+            stats: CareerStats::default(),
+            unlocked_ships: Vec::new(),
+            loadouts: Vec::new(),
+        };
+        for line in file
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        {
+            let (key, val) = line
+                .split_once('=')
+                .map(|(k, v)| (k.trim(), v.trim()))
+                .ok_or_else(|| format!("Malformed line: \"{}\"", line))?;
+            match key {
+                "matches" => profile.stats.matches = val.parse()?,
+                "wins" => profile.stats.wins = val.parse()?,
+                "hits" => profile.stats.hits = val.parse()?,
+                "ship" if is_valid_name(val) => {
+                    if !profile.unlocked_ships.iter().any(|s| s == val) {
+                        profile.unlocked_ships.push(val.to_owned());
+                    }
+                }
+                "loadout" => profile.loadouts.push(Loadout::try_from(val)?),
+                _ => return Err(format!("Invalid line: \"{}\"", line).into()),
+            }
+        }
+        // ships may have been added to the unlocks since the file was saved
+        profile.unlock_ships();
+        if profile.loadouts.len() > MAX_LOADOUTS
+            || profile.unlocked_ships.len() > std::u8::MAX.into()
+        {
+            return Err("Profile too large")?;
+        }
+        Ok(profile)
+    }
+}
+
+impl Display for Profile {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "# Player profile\n\
+            matches = {}\n\
+            wins = {}\n\
+            hits = {}\n",
+            self.stats.matches, self.stats.wins, self.stats.hits
+        )?;
+        for ship in &self.unlocked_ships {
+            writeln!(f, "ship = {}", ship)?;
+        }
+        for loadout in &self.loadouts {
+            writeln!(f, "loadout = {}", loadout)?;
+        }
+        Ok(())
+    }
+}
@@ -1,4 +1,175 @@
 use super::*;
+use session::Session;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+pub mod conditioner;
+
+/// How often clients ping the server
+pub const PING_INTERVAL: Duration = Duration::from_secs(1);
+/// Pings that aren't answered within this time are counted as lost
+pub const PING_TIMEOUT: Duration = Duration::from_secs(2);
+/// Number of recent pings packet loss is measured over
+const LOSS_SAMPLES: usize = 20;
+/// Window bandwidth is measured over
+const BANDWIDTH_WINDOW: Duration = Duration::from_secs(2);
+
+/// A snapshot of the measurements of `NetStats`
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct NetSummary {
+    /// Smoothed round-trip time, or `None` before the first ping is answered
+    pub rtt: Option<Duration>,
+    /// Fraction of recent pings that were lost, between `0` and `1`
+    pub packet_loss: f64,
+    /// Bytes sent per second
+    pub upload: f64,
+    /// Bytes received per second
+    pub download: f64,
+    /// What happened to the packets received from the peer
+    pub reassembly: BufferStats,
+}
+
+impl std::fmt::Display for NetSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.rtt {
+            Some(rtt) => write!(f, "RTT {} ms", rtt.as_millis())?,
+            None => write!(f, "RTT -")?,
+        }
+        write!(
+            f,
+            " | Loss {}% | Up {:.1} KB/s | Down {:.1} KB/s | Dropped {}",
+            (self.packet_loss * 100.).round() as u32,
+            self.upload / 1024.,
+            self.download / 1024.,
+            self.reassembly.expired + self.reassembly.rejected
+        )
+    }
+}
+
+/// Measures the round-trip time, packet loss and bandwidth of a connection
+///
+/// The round-trip time and packet loss are measured with pings, which are
+/// sent when `ping_due` returns a token and answered by `on_pong`
+#[derive(Default)]
+pub struct NetStats {
+    next_token: u32,
+    last_ping: Option<Instant>,
+    /// Tokens of unanswered pings and when they were sent, oldest first
+    pending_pings: VecDeque<(u32, Instant)>,
+    rtt: Option<Duration>,
+    /// Whether each recent ping was answered, oldest first
+    ping_outcomes: VecDeque<bool>,
+    /// Times and sizes of the packets sent in the bandwidth window
+    sent: VecDeque<(Instant, usize)>,
+    received: VecDeque<(Instant, usize)>,
+}
+
+/// Removes the samples older than the bandwidth window from `samples`
+fn trim_samples(samples: &mut VecDeque<(Instant, usize)>, now: Instant) {
+    while samples.front().map_or(false, |(time, _)| {
+        now.saturating_duration_since(*time) > BANDWIDTH_WINDOW
+    }) {
+        samples.pop_front();
+    }
+}
+
+/// Gets the bytes per second of `samples` in the bandwidth window
+fn bandwidth(samples: &VecDeque<(Instant, usize)>, now: Instant) -> f64 {
+    let bytes: usize = samples
+        .iter()
+        .filter(|(time, _)| {
+            now.saturating_duration_since(*time) <= BANDWIDTH_WINDOW
+        })
+        .map(|(_, bytes)| bytes)
+        .sum();
+    bytes as f64 / BANDWIDTH_WINDOW.as_secs_f64()
+}
+
+impl NetStats {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gets the token of a new ping to send if one is due at `now`
+    pub fn ping_due(&mut self, now: Instant) -> Option<u32> {
+        self.expire_pings(now);
+        if self.last_ping.map_or(false, |last| {
+            now.saturating_duration_since(last) < PING_INTERVAL
+        }) {
+            return None;
+        }
+        let token = self.next_token;
+        self.next_token = self.next_token.wrapping_add(1);
+        self.last_ping = Some(now);
+        self.pending_pings.push_back((token, now));
+        Some(token)
+    }
+
+    /// Records the answer to the ping with `token` received at `now`.
+    /// Answers to lost or unknown pings are ignored
+    pub fn on_pong(&mut self, token: u32, now: Instant) {
+        self.expire_pings(now);
+        if let Some(idx) =
+            self.pending_pings.iter().position(|(t, _)| *t == token)
+        {
+            let (_, sent) = self.pending_pings.remove(idx).unwrap();
+            let sample = now.saturating_duration_since(sent);
+            // smoothed like TCP's round-trip time estimate
+            self.rtt =
+                Some(self.rtt.map_or(sample, |rtt| (rtt * 7 + sample) / 8));
+            self.record_ping_outcome(true);
+        }
+    }
+
+    /// Counts the pings that have gone unanswered for too long as lost
+    fn expire_pings(&mut self, now: Instant) {
+        while self.pending_pings.front().map_or(false, |(_, sent)| {
+            now.saturating_duration_since(*sent) > PING_TIMEOUT
+        }) {
+            self.pending_pings.pop_front();
+            self.record_ping_outcome(false);
+        }
+    }
+
+    fn record_ping_outcome(&mut self, answered: bool) {
+        self.ping_outcomes.push_back(answered);
+        if self.ping_outcomes.len() > LOSS_SAMPLES {
+            self.ping_outcomes.pop_front();
+        }
+    }
+
+    /// Records sending a packet of `bytes` bytes at `now`
+    pub fn record_sent(&mut self, bytes: usize, now: Instant) {
+        self.sent.push_back((now, bytes));
+        trim_samples(&mut self.sent, now);
+    }
+
+    /// Records receiving a packet of `bytes` bytes at `now`
+    pub fn record_received(&mut self, bytes: usize, now: Instant) {
+        self.received.push_back((now, bytes));
+        trim_samples(&mut self.received, now);
+    }
+
+    /// Gets the measurements at `now`. The reassembly stats are left empty,
+    /// since they are kept by the buffer the packets are received into
+    #[must_use]
+    pub fn summary(&self, now: Instant) -> NetSummary {
+        let lost = self.ping_outcomes.iter().filter(|answered| !**answered);
+        NetSummary {
+            rtt: self.rtt,
+            packet_loss: if self.ping_outcomes.is_empty() {
+                0.
+            } else {
+                lost.count() as f64 / self.ping_outcomes.len() as f64
+            },
+            upload: bandwidth(&self.sent, now),
+            download: bandwidth(&self.received, now),
+            reassembly: BufferStats::default(),
+        }
+    }
+}
+
 /// Adds the string `"END"` to the end of the data
 /// Adds the end delimiter to the last packet if it fits, otherwise creates a new packet
 ///
@@ -53,11 +224,48 @@ pub fn send_data<T: Serializeable, S: ToSocketAddrs>(
     data: &T,
     msg_id: MsgId,
 ) -> Result<(), Box<dyn Error>> {
-    let chunks = add_end_chunk(data.serialize(msg_id)?);
-    for (_, chunk) in chunks {
-        sock.send_to(&chunk, &addr)?;
+    send_data_with(sock, addr, data, msg_id, false, None).map(|_| ())
+}
+
+/// Same as `send_data`, except the data is compressed if `compress` is `true`
+/// and that makes it smaller, and each datagram is sealed by `session`, if
+/// any
+///
+/// Requires the receiver to be able to receive compressed messages if
+/// `compress` is `true`
+///
+/// Returns the number of bytes sent
+/// # Errors
+/// Returns an error of the data cannot be serialized or the socket cannot be sent to
+pub fn send_data_with<T: Serializeable, S: ToSocketAddrs>(
+    sock: &UdpSocket,
+    addr: S,
+    data: &T,
+    msg_id: MsgId,
+    compress: bool,
+    session: Option<&Session>,
+) -> Result<usize, Box<dyn Error>> {
+    let chunks = add_end_chunk(data.serialize_with(msg_id, compress)?);
+    let mut bytes = 0;
+    for (_, chunk) in seal_chunks(chunks, session)? {
+        bytes += sock.send_to(&chunk, &addr)?;
+    }
+    Ok(bytes)
+}
+
+/// Seals each chunk with `session`, or leaves them as they are if there's no
+/// session
+fn seal_chunks(
+    chunks: ChunkedMsg,
+    session: Option<&Session>,
+) -> Result<ChunkedMsg, Box<dyn Error>> {
+    match session {
+        Some(session) => chunks
+            .into_iter()
+            .map(|(pkt_num, chunk)| Ok((pkt_num, session.seal(&chunk)?)))
+            .collect(),
+        None => Ok(chunks),
     }
-    Ok(())
 }
 
 /// A serizeable message that's fully or partially received from the socket
@@ -83,8 +291,9 @@ impl<T: Serializeable> RemoteData<T> {
         }
     }
 
-    /// Determines if a buffered message contains the END token and is not missing packets
-    /// or the data is already ready
+    /// Determines if a buffered message contains the END token where
+    /// `remove_end_chunk` expects it and is not missing packets or the data is
+    /// already ready
     pub fn is_ready(&self) -> bool {
         use RemoteData::*;
         match self {
@@ -92,7 +301,10 @@ impl<T: Serializeable> RemoteData<T> {
             Buffering(msg) => msg.iter().rev().next().map_or(
                 false,
                 |(last_pack_num, last_chunk)| {
-                    last_chunk.windows(3).rev().any(|x| x == b"END")
+                    (last_chunk.ends_with(b"END")
+                        || last_chunk
+                            .get(CHUNK_TITLE_SIZE..CHUNK_TITLE_SIZE + 3)
+                            .map_or(false, |x| x == b"END"))
                         && *last_pack_num as usize == msg.len() - 1
                 },
             ),
@@ -114,7 +326,11 @@ impl<T: Serializeable> RemoteData<T> {
                 {
                     e.insert(packet);
                     let this = Buffering(msg);
-                    Ok(this.to_ready()?)
+                    if this.is_ready() {
+                        this.to_ready()
+                    } else {
+                        Ok(this)
+                    }
                 } else {
                     Err("Duplicate packet")?
                 }
@@ -175,7 +391,9 @@ impl<T: Serializeable> From<RemoteData<T>> for TimestampedRemoteData<T> {
 ///
 /// Requires `chunk` is a well-formed data chunk
 #[inline]
-fn get_cmd_ids_and_nums(chunk: &[u8]) -> (CommandId, MsgId, PacketNum) {
+pub(crate) fn get_cmd_ids_and_nums(
+    chunk: &[u8],
+) -> (CommandId, MsgId, PacketNum) {
     (
         chunk[CMD_ID_INDEX],
         u32::from_be_bytes(
@@ -186,10 +404,103 @@ fn get_cmd_ids_and_nums(chunk: &[u8]) -> (CommandId, MsgId, PacketNum) {
 }
 
 pub type ClientData<T> = TimestampedRemoteData<T>;
-pub type ClientBuffer<T> = std::collections::HashMap<
-    SocketAddr,
-    BTreeMap<(CommandId, MsgId), ClientData<T>>,
->;
+
+/// Time a buffering message may go without receiving a packet before it's
+/// dropped
+pub const DEFAULT_MESSAGE_TTL: Duration = Duration::from_secs(10);
+
+/// Counts of what happened to the packets added to a `ClientBuffer`
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct BufferStats {
+    /// Messages whose packets all arrived
+    pub completed: u64,
+    /// Incomplete messages dropped because they went longer than the TTL
+    /// without receiving a packet
+    pub expired: u64,
+    /// Incomplete messages dropped because a packet of theirs was a
+    /// duplicate or the complete message could not be deserialized
+    pub rejected: u64,
+    /// Packets which arrived after a packet with a higher number of the
+    /// same message
+    pub out_of_order: u64,
+}
+
+impl std::fmt::Display for BufferStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "completed: {}, expired: {}, rejected: {}, out of order: {}",
+            self.completed, self.expired, self.rejected, self.out_of_order
+        )
+    }
+}
+
+/// Reassembles the packets received from each sender into messages
+///
+/// Buffering messages which go longer than the buffer's TTL without
+/// receiving a packet are dropped whenever a packet is added
+pub struct ClientBuffer<T: Serializeable> {
+    messages: std::collections::HashMap<
+        SocketAddr,
+        BTreeMap<(CommandId, MsgId), ClientData<T>>,
+    >,
+    ttl: Duration,
+    stats: BufferStats,
+}
+
+impl<T: Serializeable> Default for ClientBuffer<T> {
+    fn default() -> Self {
+        Self {
+            messages: std::collections::HashMap::new(),
+            ttl: DEFAULT_MESSAGE_TTL,
+            stats: BufferStats::default(),
+        }
+    }
+}
+
+impl<T: Serializeable> ClientBuffer<T> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the time a buffering message may go without receiving a packet
+    #[must_use]
+    pub const fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Gets the counts of what happened to the packets added so far
+    #[must_use]
+    pub const fn stats(&self) -> BufferStats {
+        self.stats
+    }
+
+    /// Number of messages still waiting on packets
+    #[must_use]
+    pub fn pending(&self) -> usize {
+        self.messages.values().map(BTreeMap::len).sum()
+    }
+
+    /// Drops the buffering messages which haven't received a packet since
+    /// `ttl` before `now`
+    pub fn expire(&mut self, now: Instant) {
+        let ttl = self.ttl;
+        let mut expired = 0;
+        for client_data in self.messages.values_mut() {
+            client_data.retain(|_, msg| {
+                let alive =
+                    now.saturating_duration_since(msg.last_access) <= ttl;
+                expired += u64::from(!alive);
+                alive
+            });
+        }
+        self.messages
+            .retain(|_, client_data| !client_data.is_empty());
+        self.stats.expired += expired;
+    }
+}
 
 /// Receives a single packet from `socket`. If the packet is well-formed,
 /// adds the packet to a buffering command. If the packet completes a buffering
@@ -212,37 +523,94 @@ where
         static BUF : Rc<RefCell<[u8; MAX_DATAGRAM_SIZE]>> = Rc::new(RefCell::new([0; MAX_DATAGRAM_SIZE]))
     );
     if let Ok((amt, src)) = BUF.with(recv_func) {
-        if amt > CHUNK_METADATA_SIZE {
-            let msg = BUF.with(Clone::clone);
-            let msg = msg.borrow();
-            let (cmd_id, msg_id, _pn) = get_cmd_ids_and_nums(&*msg);
-            let client_data = data.entry(src).or_insert(BTreeMap::new());
-            let id = (cmd_id, msg_id);
-
-            if let Ok(new_data) = client_data
-                .remove(&id)
-                .unwrap_or_default()
-                .data
-                .add_packet(msg[..amt].to_vec())
-            {
-                match new_data {
-                    new_data @ RemoteData::Buffering(_) => {
-                        client_data.insert(id, new_data.into());
-                        Ok(None)
-                    }
-                    RemoteData::Ready(data) => Ok(Some((data, src))),
-                }
-            } else {
-                Err("Could not add packet to buffering command")?
-            }
-        } else {
-            Err("Packet too small")?
-        }
+        let msg = BUF.with(Clone::clone);
+        let msg = msg.borrow();
+        buffer_packet(data, &msg[..amt], src)
     } else {
         Err("Failed to receive data")?
     }
 }
 
+/// Adds `packet`, received from `src`, to its buffering command. If the
+/// packet completes the buffering command, removes the buffering command and
+/// returns the deserialized command along with `src`
+///
+/// The packet is dropped if it is malformed, and the buffering command is
+/// dropped if the complete message cannot be deserialized
+pub(crate) fn buffer_packet<T: Serializeable>(
+    data: &mut ClientBuffer<T>,
+    packet: &[u8],
+    src: SocketAddr,
+) -> Result<Option<(T, SocketAddr)>, Box<dyn Error>> {
+    if packet.len() <= CHUNK_METADATA_SIZE {
+        return Err("Packet too small")?;
+    }
+    data.expire(Instant::now());
+    let (cmd_id, msg_id, pn) = get_cmd_ids_and_nums(packet);
+    let client_data = data.messages.entry(src).or_insert_with(BTreeMap::new);
+    let id = (cmd_id, msg_id);
+    let msg = client_data.remove(&id).unwrap_or_default().data;
+    if let RemoteData::Buffering(chunks) = &msg {
+        if chunks.keys().next_back().map_or(false, |last| *last > pn) {
+            data.stats.out_of_order += 1;
+        }
+    }
+
+    match msg.add_packet(packet.to_vec()) {
+        Ok(new_data @ RemoteData::Buffering(_)) => {
+            client_data.insert(id, new_data.into());
+            Ok(None)
+        }
+        Ok(RemoteData::Ready(msg)) => {
+            data.stats.completed += 1;
+            Ok(Some((msg, src)))
+        }
+        Err(_) => {
+            data.stats.rejected += 1;
+            Err("Could not add packet to buffering command")?
+        }
+    }
+}
+
+/// Receives a datagram with `recv` into `buf`, opening it with the session
+/// `session_of` gets for its sender if it's sealed
+///
+/// Fails if the datagram cannot be opened, if it's sealed but its sender has
+/// no session, or if it isn't sealed but its session doesn't accept that.
+/// See `Session::accepts_unsealed`
+fn recv_opened<'s>(
+    buf: &RefCell<[u8; MAX_DATAGRAM_SIZE]>,
+    recv: impl FnOnce(&mut [u8]) -> std::io::Result<(usize, SocketAddr)>,
+    session_of: impl FnOnce(&SocketAddr) -> Option<&'s Session>,
+) -> std::io::Result<(usize, SocketAddr)> {
+    let mut datagram = [0; MAX_DATAGRAM_SIZE + session::SEAL_OVERHEAD];
+    let (amt, src) = recv(&mut datagram)?;
+    let datagram = &datagram[..amt];
+    let chunk = match session_of(&src) {
+        Some(session)
+            if session.accepts_unsealed() && !session::is_sealed(datagram) =>
+        {
+            Ok(datagram.to_vec())
+        }
+        Some(session) => session.open(datagram).map_err(|e| e.to_string()),
+        None if session::is_sealed(datagram) => {
+            Err("Sealed datagram from a peer without a session".to_owned())
+        }
+        None => Ok(datagram.to_vec()),
+    }
+    .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    let mut buf = buf.borrow_mut();
+    buf.get_mut(..chunk.len())
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Datagram too large",
+            )
+        })?
+        .copy_from_slice(&chunk);
+    Ok((chunk.len(), src))
+}
+
 /// Receives a single packet from `socket`. If the packet is well-formed,
 /// adds the packet to a buffering command. If the packet completes a buffering
 /// command, removes the buffering command and returns the deserialized command
@@ -262,20 +630,73 @@ pub fn recv_data<T: Serializeable>(
     recv_data_helper(data, |buf| socket.recv_from(&mut *buf.borrow_mut()))
 }
 
-/// Same as `recv_data` except only receives packets from the connected peer
+/// Same as `recv_data`, except sealed packets are opened with the session
+/// `session_of` gets for their sender. Packets that aren't sealed are only
+/// accepted from senders without a session, or whose session accepts them
+/// # Errors
+/// Returns an error if the packet is too small, malformed, cannot be opened,
+/// or the socket read fails
+pub fn recv_data_secure<'s, T: Serializeable>(
+    socket: &UdpSocket,
+    data: &mut ClientBuffer<T>,
+    session_of: impl Fn(&SocketAddr) -> Option<&'s Session>,
+) -> Result<Option<(T, SocketAddr)>, Box<dyn Error>> {
+    recv_data_helper(data, |buf| {
+        recv_opened(buf, |buf| socket.recv_from(buf), &session_of)
+    })
+}
+
+/// Same as `recv_data`, except `record` is called with the sender and size of
+/// the packet that's received, if any, and packets are opened with `session`
+/// if there is one
+/// # Errors
+/// Returns an error if the packet is too small, malformed, cannot be opened,
+/// or the socket read fails
+pub fn recv_data_recorded<T: Serializeable, F: FnOnce(SocketAddr, usize)>(
+    socket: &UdpSocket,
+    data: &mut ClientBuffer<T>,
+    session: Option<&Session>,
+    record: F,
+) -> Result<Option<(T, SocketAddr)>, Box<dyn Error>> {
+    let received = std::cell::Cell::new(None);
+    let res = recv_data_helper(data, |buf| {
+        recv_opened(
+            buf,
+            |buf| {
+                let res = socket.recv_from(buf);
+                if let Ok((amt, src)) = res {
+                    received.set(Some((src, amt)));
+                }
+                res
+            },
+            |_| session,
+        )
+    });
+    if let Some((src, amt)) = received.get() {
+        record(src, amt);
+    }
+    res
+}
+
+/// Same as `recv_data` except only receives packets from the connected peer,
+/// which are opened with `session` if there is one
 ///
 /// Requires `socket` is a connected socket
 /// # Errors
-/// Returns an error if the packet is too small, malformed, or the socket read fails
+/// Returns an error if the packet is too small, malformed, cannot be opened,
+/// or the socket read fails
 pub fn recv_data_filtered<T: Serializeable>(
     socket: &UdpSocket,
     data: &mut ClientBuffer<T>,
+    session: Option<&Session>,
 ) -> Result<Option<T>, Box<dyn Error>> {
     let dummy_addr: SocketAddr = SocketAddr::from(([0, 0, 0, 0], 0));
     match recv_data_helper(data, |buf| {
-        socket
-            .recv(&mut *buf.borrow_mut())
-            .map(|res| (res, dummy_addr))
+        recv_opened(
+            buf,
+            |buf| socket.recv(buf).map(|res| (res, dummy_addr)),
+            |_| session,
+        )
     }) {
         Ok(Some((data, _))) => Ok(Some(data)),
         Ok(None) => Ok(None),
@@ -283,35 +704,20 @@ pub fn recv_data_filtered<T: Serializeable>(
     }
 }
 
-/// Removes any buffering message whose last access time is older than `timeout`
-pub fn clear_old_messages<T: Serializeable>(
-    data: &mut ClientBuffer<T>,
-    timeout: std::time::Duration,
-) {
-    let now = std::time::Instant::now();
-    for (_, client_data) in data.iter_mut() {
-        let mut dead_ids = Vec::new();
-        for (id, client_data) in client_data.iter() {
-            if now.duration_since(client_data.last_access) > timeout {
-                dead_ids.push(*id);
-            }
-        }
-        for dead_id in dead_ids {
-            client_data.remove(&dead_id);
-        }
-    }
-}
-
 /// Arguments for `send_important`
-pub struct ImportantArguments {
+pub struct ImportantArguments<'a> {
     pub max_recv_tries: u32,
     pub max_send_tries: u32,
     pub trial_recv_timeout: std::time::Duration,
     pub trial_send_timeout: std::time::Duration,
     pub total_send_attempts: u32,
+    /// Compresses the sent data if that makes it smaller
+    pub compress: bool,
+    /// The session the sent and received data is sealed with, if any
+    pub session: Option<&'a Session>,
 }
 
-impl Default for ImportantArguments {
+impl Default for ImportantArguments<'_> {
     fn default() -> Self {
         Self {
             max_recv_tries: 5,
@@ -319,6 +725,8 @@ impl Default for ImportantArguments {
             total_send_attempts: 5,
             trial_recv_timeout: std::time::Duration::from_secs(2),
             trial_send_timeout: std::time::Duration::from_millis(300),
+            compress: false,
+            session: None,
         }
     }
 }
@@ -331,7 +739,7 @@ fn send_with_retries(
     sock: &UdpSocket,
 ) -> Result<(), Box<dyn Error>> {
     let mut total_send_attempts = 0;
-    for (_, chunk) in chunks {
+    for (_, chunk) in seal_chunks(chunks, args.session)? {
         let mut send_attempts = 0;
         while sock.send(&chunk).is_err() {
             if send_attempts >= args.max_send_tries
@@ -357,7 +765,7 @@ fn recv_with_retries<R: Serializeable>(
     let old_timeout = sock.read_timeout();
     std::mem::drop(sock.set_read_timeout(Some(args.trial_recv_timeout)));
     loop {
-        match recv_data_filtered(sock, recv_data) {
+        match recv_data_filtered(sock, recv_data, args.session) {
             Err(_) if recv_attempts < args.max_recv_tries => recv_attempts += 1,
             Err(_) => {
                 std::mem::drop(
@@ -391,7 +799,8 @@ pub fn send_important<S: Serializeable, R: Serializeable>(
     recv_data: &mut ClientBuffer<R>,
     args: &ImportantArguments,
 ) -> Result<R, Box<dyn Error>> {
-    let chunks = add_end_chunk(send_data.serialize(send_msg_id)?);
+    let chunks =
+        add_end_chunk(send_data.serialize_with(send_msg_id, args.compress)?);
     send_with_retries(chunks, args, sock)?;
     recv_with_retries(sock, args, recv_data)
     // TODO: This assumes that the message was received by the peer successfully.
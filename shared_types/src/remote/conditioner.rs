@@ -0,0 +1,190 @@
+use rand::{Rng, SeedableRng};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use std::time::{Duration, Instant};
+
+/// Largest packet that's forwarded
+const MAX_PACKET_SIZE: usize = 2048;
+/// Time the forwarding thread sleeps when there's nothing to do
+const IDLE_SLEEP: Duration = Duration::from_millis(1);
+
+/// The network conditions a `NetworkConditioner` simulates
+#[derive(Copy, Clone, Debug, Default)]
+pub struct NetworkConditions {
+    latency: Duration,
+    jitter: Duration,
+    loss: f64,
+    reorder: f64,
+    seed: u64,
+}
+
+impl NetworkConditions {
+    /// Delay added to every packet, in each direction
+    #[must_use]
+    pub const fn latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Most random delay added to a packet on top of the latency
+    #[must_use]
+    pub const fn jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Chance of dropping a packet, between `0` and `1`
+    #[must_use]
+    pub fn loss(mut self, loss: f64) -> Self {
+        self.loss = loss.clamp(0., 1.);
+        self
+    }
+
+    /// Chance of holding back a packet until after the packets sent after it,
+    /// between `0` and `1`
+    #[must_use]
+    pub fn reorder(mut self, reorder: f64) -> Self {
+        self.reorder = reorder.clamp(0., 1.);
+        self
+    }
+
+    /// Seed of the random choices, so runs can be repeated
+    #[must_use]
+    pub const fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Gets the delay of the next packet, or `None` if it's dropped
+    fn delay(&self, rng: &mut impl Rng) -> Option<Duration> {
+        if rng.gen_bool(self.loss) {
+            return None;
+        }
+        let mut delay = self.latency + self.jitter.mul_f64(rng.gen::<f64>());
+        if rng.gen_bool(self.reorder) {
+            // long enough for the packets behind it to overtake it
+            delay += self.jitter + Duration::from_millis(10);
+        }
+        Some(delay)
+    }
+}
+
+/// A packet waiting to be forwarded
+struct Delayed {
+    deliver_at: Instant,
+    to_server: bool,
+    data: Vec<u8>,
+}
+
+/// A UDP proxy that forwards packets between one client and a server under
+/// simulated adverse network conditions. For testing the protocol locally
+/// only
+///
+/// The client sends to the conditioner's address instead of the server's.
+/// Packets from the server are forwarded to whoever last sent to the
+/// conditioner. Forwarding stops when the conditioner is dropped
+pub struct NetworkConditioner {
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl NetworkConditioner {
+    /// Starts forwarding the packets sent to `listen` to `server` and back
+    /// under `conditions`
+    ///
+    /// # Errors
+    /// Fails if the sockets cannot be bound or connected
+    pub fn spawn<L: ToSocketAddrs, S: ToSocketAddrs>(
+        listen: L,
+        server: S,
+        conditions: NetworkConditions,
+    ) -> std::io::Result<Self> {
+        let client_side = UdpSocket::bind(listen)?;
+        let server_side = UdpSocket::bind(("127.0.0.1", 0))?;
+        server_side.connect(server)?;
+        client_side.set_nonblocking(true)?;
+        server_side.set_nonblocking(true)?;
+        let addr = client_side.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let thread = std::thread::spawn(move || {
+            forward(&client_side, &server_side, conditions, &thread_stop);
+        });
+        Ok(Self {
+            addr,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Gets the address clients should send to
+    #[must_use]
+    pub const fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for NetworkConditioner {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            std::mem::drop(thread.join());
+        }
+    }
+}
+
+/// Forwards packets between the client and server until `stop` is set
+fn forward(
+    client_side: &UdpSocket,
+    server_side: &UdpSocket,
+    conditions: NetworkConditions,
+    stop: &AtomicBool,
+) {
+    let mut rng = rand::rngs::StdRng::seed_from_u64(conditions.seed);
+    let mut client = None;
+    let mut pending: Vec<Delayed> = Vec::new();
+    let mut buf = [0_u8; MAX_PACKET_SIZE];
+    while !stop.load(Ordering::SeqCst) {
+        let mut idle = true;
+        while let Ok((amt, src)) = client_side.recv_from(&mut buf) {
+            client = Some(src);
+            idle = false;
+            if let Some(delay) = conditions.delay(&mut rng) {
+                pending.push(Delayed {
+                    deliver_at: Instant::now() + delay,
+                    to_server: true,
+                    data: buf[..amt].to_vec(),
+                });
+            }
+        }
+        while let Ok(amt) = server_side.recv(&mut buf) {
+            idle = false;
+            if let Some(delay) = conditions.delay(&mut rng) {
+                pending.push(Delayed {
+                    deliver_at: Instant::now() + delay,
+                    to_server: false,
+                    data: buf[..amt].to_vec(),
+                });
+            }
+        }
+        let now = Instant::now();
+        pending.sort_by_key(|packet| packet.deliver_at);
+        let due = pending.partition_point(|packet| packet.deliver_at <= now);
+        for packet in pending.drain(..due) {
+            idle = false;
+            // packets that fail to send are lost, like on a real network
+            if packet.to_server {
+                std::mem::drop(server_side.send(&packet.data));
+            } else if let Some(client) = client {
+                std::mem::drop(client_side.send_to(&packet.data, client));
+            }
+        }
+        if idle {
+            std::thread::sleep(IDLE_SLEEP);
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use super::codec::{
+    impl_codec, Decode, DecodeError, Encode, EncodeError, Reader, Writer,
+};
+use super::{ObjectId, RemoteObject};
+use std::error::Error;
+use std::path::Path;
+use std::time::Duration;
+
+/// Bytes every save file starts with
+const MAGIC: [u8; 4] = *b"OSAV";
+/// Version of the save format, which must be bumped whenever it changes
+pub const SAVE_VERSION: u32 = 1;
+
+/// The state of a ship when the game was saved
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct ShipState {
+    /// Transform, velocities and id of the ship
+    pub body: RemoteObject,
+    pub shield: f64,
+    pub hull: f64,
+    pub energy: f64,
+    /// `true` if the ship was out of play, and stays out until it's spawned
+    pub benched: bool,
+}
+
+/// Player 1's points and statistics when the game was saved
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct ScoreState {
+    pub points: u64,
+    pub shots_fired: u32,
+    pub shots_hit: u32,
+    pub damage_dealt: f64,
+    pub damage_taken: f64,
+    pub time_alive: Duration,
+    pub asteroids_destroyed: u32,
+    pub ships_destroyed: u32,
+}
+
+/// Progress through the mission when the game was saved
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct MissionState {
+    /// Index of the current objective
+    pub current: u32,
+    /// Asteroids destroyed since the current objective started
+    pub asteroids_destroyed: u32,
+    /// Time since the current objective started
+    pub elapsed: Duration,
+    /// Every object destroyed since the mission started
+    pub destroyed: Vec<ObjectId>,
+    pub completed: bool,
+    pub failed: bool,
+}
+
+/// A snapshot of a local game session
+///
+/// Only what changes during a session is saved. Everything else, such as
+/// the lighting, clouds and wormholes, comes from the map, so a save must be
+/// loaded on the map it was saved on
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct SaveGame {
+    /// Seed of the map if it's procedurally generated
+    pub map_seed: Option<u64>,
+    /// Every body that isn't a ship, projectile, pickup or debris
+    pub bodies: Vec<RemoteObject>,
+    pub ships: Vec<ShipState>,
+    pub lives: u32,
+    pub score: ScoreState,
+    pub mission: MissionState,
+}
+
+impl_codec!(ShipState {
+    body,
+    shield,
+    hull,
+    energy,
+    benched
+});
+impl_codec!(ScoreState {
+    points,
+    shots_fired,
+    shots_hit,
+    damage_dealt,
+    damage_taken,
+    time_alive,
+    asteroids_destroyed,
+    ships_destroyed,
+});
+
+/// Writes `vals` prefixed by their number in four bytes, since saves hold
+/// more objects than fit in the one byte prefix of a message
+fn put_long_list<T: Encode>(
+    writer: &mut Writer,
+    vals: &[T],
+) -> Result<(), EncodeError> {
+    let len = u32::try_from(vals.len()).map_err(|_| EncodeError::TooLong {
+        len: vals.len(),
+        max: u32::MAX as usize,
+    })?;
+    writer.put(&len)?.put_each(vals)?;
+    Ok(())
+}
+
+/// Reads a list written with `put_long_list`
+fn get_long_list<T: Decode>(
+    reader: &mut Reader,
+) -> Result<Vec<T>, DecodeError> {
+    let len = reader.get::<u32>()?;
+    (0..len).map(|_| reader.get()).collect()
+}
+
+impl Encode for MissionState {
+    fn encode(&self, writer: &mut Writer) -> Result<(), EncodeError> {
+        writer
+            .put(&self.current)?
+            .put(&self.asteroids_destroyed)?
+            .put(&self.elapsed)?;
+        put_long_list(writer, &self.destroyed)?;
+        writer.put(&self.completed)?.put(&self.failed)?;
+        Ok(())
+    }
+}
+
+impl Decode for MissionState {
+    fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(Self {
+            current: reader.get()?,
+            asteroids_destroyed: reader.get()?,
+            elapsed: reader.get()?,
+            destroyed: get_long_list(reader)?,
+            completed: reader.get()?,
+            failed: reader.get()?,
+        })
+    }
+}
+
+impl Encode for SaveGame {
+    fn encode(&self, writer: &mut Writer) -> Result<(), EncodeError> {
+        writer.put(&self.map_seed)?;
+        put_long_list(writer, &self.bodies)?;
+        put_long_list(writer, &self.ships)?;
+        writer
+            .put(&self.lives)?
+            .put(&self.score)?
+            .put(&self.mission)?;
+        Ok(())
+    }
+}
+
+impl Decode for SaveGame {
+    fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(Self {
+            map_seed: reader.get()?,
+            bodies: get_long_list(reader)?,
+            ships: get_long_list(reader)?,
+            lives: reader.get()?,
+            score: reader.get()?,
+            mission: reader.get()?,
+        })
+    }
+}
+
+impl SaveGame {
+    /// Encodes the save, after the magic bytes and the version of the save
+    /// format
+    ///
+    /// # Errors
+    /// Fails if the save has more objects than fit in a list
+    pub fn to_bytes(&self) -> Result<Vec<u8>, EncodeError> {
+        let mut writer = Writer::new();
+        writer.bytes(&MAGIC).put(&SAVE_VERSION)?.put(self)?;
+        Ok(writer.finish())
+    }
+
+    /// Decodes a save encoded by `to_bytes`
+    ///
+    /// # Errors
+    /// Fails if `bytes` isn't a save, if it was saved with a different
+    /// version of the save format, or if it's corrupt
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader::new(bytes);
+        if reader.take_array::<4>()? != MAGIC {
+            return Err(DecodeError::Malformed("Not a save file".to_owned()));
+        }
+        let version = reader.get::<u32>()?;
+        if version != SAVE_VERSION {
+            return Err(DecodeError::Malformed(format!(
+                "Save version {} is not supported, expected {}",
+                version, SAVE_VERSION
+            )));
+        }
+        let save = reader.get()?;
+        reader.finish()?;
+        Ok(save)
+    }
+
+    /// Loads the save at `path`
+    ///
+    /// # Errors
+    /// Fails if the file cannot be read or isn't a valid save
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        Ok(Self::from_bytes(&std::fs::read(path)?)?)
+    }
+
+    /// Writes the save to `path`, creating its directory if needed
+    ///
+    /// The save is written to a temporary file which then replaces the old
+    /// save, so a crash while saving can't corrupt it
+    ///
+    /// # Errors
+    /// Fails if the save cannot be encoded or written
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref();
+        let bytes = self.to_bytes()?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let temp = path.with_extension("tmp");
+        std::fs::write(&temp, bytes)?;
+        std::fs::rename(&temp, path)?;
+        Ok(())
+    }
+}
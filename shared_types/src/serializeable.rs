@@ -1,4 +1,7 @@
 use super::*;
+use crate::codec::{
+    impl_codec, Decode, DecodeError, Encode, EncodeError, Reader, Writer,
+};
 
 /// A type that can be converted to a network message
 pub trait Serializeable {
@@ -10,7 +13,22 @@ pub trait Serializeable {
     /// # Errors
     /// Fails if the object is not well-formed or violates invariants for the particular
     ///     implementor of the trait.
-    fn serialize(&self, msg_id: MsgId) -> Result<ChunkedMsg, Box<dyn Error>>;
+    fn serialize(&self, msg_id: MsgId) -> Result<ChunkedMsg, Box<dyn Error>> {
+        self.serialize_with(msg_id, false)
+    }
+
+    /// Same as `serialize`, except if `compress` is `true` the data is
+    /// compressed when that makes it smaller. Compressed messages are
+    /// marked by `COMPRESSED_FLAG` in their command id
+    ///
+    /// # Errors
+    /// Fails if the object is not well-formed or violates invariants for the particular
+    ///     implementor of the trait.
+    fn serialize_with(
+        &self,
+        msg_id: MsgId,
+        compress: bool,
+    ) -> Result<ChunkedMsg, Box<dyn Error>>;
 
     /// Converts a chunked message without the `END` token into the object
     ///
@@ -28,12 +46,79 @@ pub trait Serializeable {
 const LOGIN_ID: u8 = b'L';
 const UPDATE_OBJS_ID: u8 = b'U';
 const ID_FETCH_ID: u8 = b'I';
+const HIT_ID: u8 = b'H';
+const LOBBY_ID: u8 = b'J';
+const READY_ID: u8 = b'R';
+const LEAVE_LOBBY_ID: u8 = b'X';
+const MATCH_START_ID: u8 = b'S';
+const MATCH_END_ID: u8 = b'E';
+const PING_ID: u8 = b'P';
+const HANDSHAKE_ID: u8 = b'K';
+const PROFILE_ID: u8 = b'F';
+const OWNERSHIP_ID: u8 = b'O';
+const TELEPORT_ID: u8 = b'T';
+
+/// Bit of the command id that is set when the data of a message is
+/// compressed. Every command id is ASCII, so the bit is otherwise unused
+pub const COMPRESSED_FLAG: CommandId = 0x80;
+/// Largest data of a message, which fits into packets `0` to `254` so the
+/// `END` token always fits in the last packet. Compressed data cannot
+/// decompress to more than this either
+const MAX_PAYLOAD_SIZE: usize = 255 * (MAX_DATAGRAM_SIZE - CHUNK_METADATA_SIZE);
+
+/// Converts the data of a command into chunks like `chunk_serialized_data`.
+/// If `compress` is `true` and compressing the data makes it smaller, the
+/// compressed data, prefixed by its decompressed size, is chunked instead
+///
+/// Fails if the data is larger than `MAX_PAYLOAD_SIZE`
+fn chunk_payload(
+    cmd_id: CommandId,
+    data: Vec<u8>,
+    msg_id: MsgId,
+    compress: bool,
+) -> Result<ChunkedMsg, EncodeError> {
+    if data.len() > MAX_PAYLOAD_SIZE {
+        return Err(EncodeError::TooLong {
+            len: data.len(),
+            max: MAX_PAYLOAD_SIZE,
+        });
+    }
+    if compress {
+        let compressed = compression::compress(&data);
+        if compressed.len() + 4 < data.len() {
+            let payload = (data.len() as u32)
+                .to_be_bytes()
+                .into_iter()
+                .chain(compressed);
+            return Ok(chunk_serialized_data(
+                cmd_id | COMPRESSED_FLAG,
+                payload,
+                msg_id,
+            ));
+        }
+    }
+    Ok(chunk_serialized_data(cmd_id, data.into_iter(), msg_id))
+}
+
+/// Decompresses data chunked by `chunk_payload`
+fn decompress_payload(data: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    let mut reader = Reader::new(data);
+    let size = reader.get::<u32>()? as usize;
+    if size > MAX_PAYLOAD_SIZE {
+        return Err(DecodeError::Malformed(
+            "Decompressed data too large".to_owned(),
+        ));
+    }
+    compression::decompress(&data[4..], size)
+        .map_err(|e| DecodeError::Malformed(e.to_string()))
+}
 
 /// Converts a command into chunks of `MAX_DATAGRAM_SIZE` bytes.
 ///
 /// `cmd_id` - the command type id
 ///
-/// Panics if the data cannot fit into `255` of `MAX_DATAGRAM_SIZE` sized chunks (~260 KB)
+/// Requires the data fits into `255` of `MAX_DATAGRAM_SIZE` sized chunks
+/// (~260 KB), which `chunk_payload` checks
 /// If `data` serializes to an empty message, returns
 /// one packet containing a header and footer only
 fn chunk_serialized_data<T>(
@@ -60,7 +145,7 @@ where
                 .chain(CHUNK_FOOTER.into_iter())
                 .collect();
             let res = (packet_num, v);
-            packet_num += 1; // Will panic on overflows (More than 255 packets)
+            packet_num = packet_num.wrapping_add(1);
             res
         })
         .collect();
@@ -88,14 +173,14 @@ where
 fn check_option_equals<T>(
     opt: &mut Option<T>,
     val: T,
-) -> Result<(), Box<dyn Error>>
+) -> Result<(), DecodeError>
 where
     T: PartialEq + std::fmt::Debug,
 {
     match opt {
-        Some(opt_val) if *opt_val != val => {
-            Err(format!("Option mismatch: {:?} != {:?}", opt_val, val).into())
-        }
+        Some(opt_val) if *opt_val != val => Err(DecodeError::Malformed(
+            format!("Option mismatch: {:?} != {:?}", opt_val, val),
+        )),
         None => {
             *opt = Some(val);
             Ok(())
@@ -110,284 +195,381 @@ where
 ///
 /// Returns the combined payload data and the command type id. The payload does not
 /// contain the header, or footer (this includes the command id and packet number)
+/// Compressed payloads are decompressed, and their command id is returned without
+/// the `COMPRESSED_FLAG`
 ///
-/// Fails if the chunks are not in order, not all for the same command, or do not
-/// contain the title and footer
+/// Fails if there are no chunks, or the chunks are not in order, not all for the
+/// same command, or do not contain the title and footer
 fn dechunk_serialized_data(
     chunks: ChunkedMsg,
-) -> Result<(Vec<u8>, CommandId, MsgId), Box<dyn Error>> {
+) -> Result<(Vec<u8>, CommandId, MsgId), DecodeError> {
     let mut res = Vec::new();
     let mut last_cmd_id: Option<CommandId> = None;
     let mut last_msg_id: Option<MsgId> = None;
     for (expected_packet_num, (_, chunk)) in (0_u8..).zip(chunks.into_iter()) {
         let chunk_len = chunk.len();
         if chunk_len < CHUNK_METADATA_SIZE {
-            return Err(format!("Chunk too short: {}", chunk.len()).into());
+            return Err(DecodeError::Malformed(format!(
+                "Chunk too short: {}",
+                chunk.len()
+            )));
         }
         if chunk[0..CHUNK_HEADER_SIZE] != CHUNK_HEADER {
-            return Err(format!(
+            return Err(DecodeError::Malformed(format!(
                 "Invalid chunk title: {:?}",
                 &chunk[0..CHUNK_TITLE_SIZE]
-            )
-            .into());
+            )));
         }
         if chunk[chunk_len - CHUNK_FOOTER_SIZE..] != CHUNK_FOOTER {
-            return Err(format!(
+            return Err(DecodeError::Malformed(format!(
                 "Invalid chunk footer: {:?}",
                 &chunk[chunk_len - CHUNK_FOOTER_SIZE..]
-            )
-            .into());
+            )));
         }
-        let cmd_id = chunk[CMD_ID_INDEX];
-        let msg_id = MsgId::from_be_bytes(
-            chunk[MSG_ID_INDEX..MSG_ID_INDEX + 4].try_into()?,
-        );
-        check_option_equals(&mut last_cmd_id, cmd_id)?;
-        check_option_equals(&mut last_msg_id, msg_id)?;
-        if expected_packet_num != chunk[PKT_NM_INDEX] {
-            return Err("Chunk packet numbers are not in order")?;
+        let mut title = Reader::new(&chunk[CMD_ID_INDEX..CHUNK_TITLE_SIZE]);
+        check_option_equals(&mut last_cmd_id, title.get::<CommandId>()?)?;
+        check_option_equals(&mut last_msg_id, title.get::<MsgId>()?)?;
+        if expected_packet_num != title.get::<PacketNum>()? {
+            return Err(DecodeError::Malformed(
+                "Chunk packet numbers are not in order".to_owned(),
+            ));
         }
         res.extend_from_slice(
             &chunk[CHUNK_TITLE_SIZE..chunk_len - CHUNK_FOOTER_SIZE],
         );
     }
-    Ok((res, last_cmd_id.unwrap(), last_msg_id.unwrap()))
-}
-
-fn serialize_objects(objects: &[RemoteObject]) -> (Vec<u8>, u8) {
-    (
-        objects
-            .iter()
-            .flat_map(|obj| {
-                obj.mat
-                    .iter()
-                    .flatten()
-                    .flat_map(|flt| flt.to_be_bytes())
-                    .chain(obj.id.to_be_bytes())
-                    .chain([obj.typ as u8])
-            })
-            .collect(),
-        UPDATE_OBJS_ID,
-    )
-}
-
-fn deserialize_update(
-    data: Vec<u8>,
-) -> Result<Vec<RemoteObject>, Box<dyn Error>> {
-    const MAT_SIZE: usize = std::mem::size_of::<ObjData>();
-    #[allow(clippy::if_not_else)]
-    if data.len() % REMOTE_OBJECT_SIZE != 0 {
-        Err("Invalid update length")?
+    let (cmd_id, msg_id) = last_cmd_id
+        .zip(last_msg_id)
+        .ok_or_else(|| DecodeError::Malformed("No chunks".to_owned()))?;
+    if cmd_id & COMPRESSED_FLAG == 0 {
+        Ok((res, cmd_id, msg_id))
     } else {
-        let objs = data
-            .into_iter()
-            .chunks(REMOTE_OBJECT_SIZE)
-            .into_iter()
-            .map(|chunk| {
-                let vec: Vec<_> = chunk.collect();
-                let floats = vec
-                    .iter()
-                    .copied()
-                    .take(MAT_SIZE)
-                    .chunks(std::mem::size_of::<f64>())
-                    .into_iter()
-                    .map(|flt| {
-                        let flt_bytes: Vec<u8> = flt.collect();
-                        match flt_bytes.try_into() {
-                            Ok(flt) => Ok(f64::from_be_bytes(flt)),
-                            Err(_) => Err("Could not parse bytes to f64"),
-                        }
-                    })
-                    .collect::<Result<Vec<f64>, _>>()?;
-
-                let mat: ObjData = floats
-                    .chunks(4)
-                    .into_iter()
-                    .map(|row| {
-                        if row.len() != 4 {
-                            Err("Invalid matrix row length")
-                        } else {
-                            Ok([row[0], row[1], row[2], row[3]])
-                        }
-                    })
-                    .collect::<Result<Vec<_>, _>>()?
-                    .try_into()
-                    .map_err(|_| "Invalid # matrix rows")?;
-                let id = ObjectId::from_be_bytes(
-                    vec[MAT_SIZE..MAT_SIZE + 4].try_into()?,
-                );
-                let typ = vec[MAT_SIZE + 4].try_into()?;
-                Ok(RemoteObject { mat, id, typ })
-            })
-            .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
-        Ok(objs)
+        Ok((decompress_payload(&res)?, cmd_id & !COMPRESSED_FLAG, msg_id))
     }
 }
 
-fn serialize_login(login: &LoginInfo) -> (Vec<u8>, u8) {
-    assert!(login.lighting.hdr.len() <= std::u8::MAX.into());
-    assert!(login.lighting.skybox.len() <= std::u8::MAX.into());
-    let data: Vec<_> = login
-        .pid
-        .to_be_bytes()
-        .into_iter()
-        .chain(login.spawn_pos[0].to_be_bytes().into_iter())
-        .chain(login.spawn_pos[1].to_be_bytes().into_iter())
-        .chain(login.spawn_pos[2].to_be_bytes().into_iter())
-        .chain(login.lighting.dir_light.x.to_be_bytes().into_iter())
-        .chain(login.lighting.dir_light.y.to_be_bytes().into_iter())
-        .chain(login.lighting.dir_light.z.to_be_bytes().into_iter())
-        .chain(login.starting_ids.0.to_be_bytes().into_iter())
-        .chain(login.starting_ids.1.to_be_bytes().into_iter())
-        .chain(std::iter::once(login.lighting.hdr.len() as u8))
-        .chain(login.lighting.hdr.as_bytes().iter().copied())
-        .chain(std::iter::once(login.lighting.skybox.len() as u8))
-        .chain(login.lighting.skybox.as_bytes().iter().copied())
-        .collect();
-    (data, LOGIN_ID)
+impl Encode for ObjectType {
+    fn encode(&self, writer: &mut Writer) -> Result<(), EncodeError> {
+        writer.put(&(*self as u8))?;
+        Ok(())
+    }
 }
 
-fn deserialize_login(data: &[u8]) -> Result<LoginInfo, Box<dyn Error>> {
-    if data.len() < LOGIN_MIN_SIZE {
-        return Err("Login too short")?;
+impl Decode for ObjectType {
+    fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let value = reader.get::<u8>()?;
+        Self::try_from(value).map_err(|_| DecodeError::Unknown {
+            what: "object type",
+            value,
+        })
     }
-    let pid = ObjectId::from_be_bytes(data[0..4].try_into()?);
-    let spawn_pos: [f64; 3] = [
-        f64::from_be_bytes(data[4..12].try_into()?),
-        f64::from_be_bytes(data[12..20].try_into()?),
-        f64::from_be_bytes(data[20..28].try_into()?),
-    ];
-    let light_dir: [f32; 3] = [
-        f32::from_be_bytes(data[28..32].try_into()?),
-        f32::from_be_bytes(data[32..36].try_into()?),
-        f32::from_be_bytes(data[36..40].try_into()?),
-    ];
-    let starting_id = ObjectId::from_be_bytes(data[40..44].try_into()?);
-    let ending_id = ObjectId::from_be_bytes(data[44..48].try_into()?);
-    let hdr_len = data[48] as usize;
-    let hdr = std::str::from_utf8(&data[49..49 + hdr_len])?.to_string();
-    let skybox_len = data[49 + hdr_len] as usize;
-    let skybox =
-        std::str::from_utf8(&data[50 + hdr_len..50 + hdr_len + skybox_len])?
-            .to_string();
-    Ok(LoginInfo {
-        pid,
-        spawn_pos,
-        lighting: game_map::GlobalLightingInfo {
-            dir_light: From::from(light_dir),
-            hdr,
-            skybox,
-        },
-        starting_ids: (starting_id, ending_id),
-    })
 }
 
-fn serialize_id_range(ids: (ObjectId, ObjectId)) -> (Vec<u8>, u8) {
-    let data: Vec<_> = ids
-        .0
-        .to_be_bytes()
-        .into_iter()
-        .chain(ids.1.to_be_bytes().into_iter())
-        .collect();
-    (data, ID_FETCH_ID)
-}
+impl_codec!(RemoteObject { mat, id, typ });
+impl_codec!(HitClaim {
+    shooter,
+    target,
+    hit_point
+});
+impl_codec!(wormhole::TeleportClaim {
+    body,
+    wormhole,
+    entry
+});
+impl_codec!(game_map::GlobalLightingInfo {
+    dir_light,
+    hdr,
+    skybox
+});
+impl_codec!(profile::CareerStats {
+    matches,
+    wins,
+    hits
+});
+impl_codec!(profile::Loadout { name, ship, gear });
+impl_codec!(profile::Profile {
+    stats,
+    unlocked_ships,
+    loadouts
+});
+impl_codec!(LoginInfo {
+    pid,
+    spawn_pos,
+    lighting,
+    starting_ids,
+    map_seed,
+    compression,
+    profile,
+});
+impl_codec!(lobby::LobbyPlayer { pid, ready, name });
+impl_codec!(lobby::LobbyInfo {
+    name,
+    phase,
+    players
+});
+impl_codec!(lobby::MatchStart { map_seed, spawns });
+impl_codec!(lobby::MatchResults { scores });
 
-fn deserialize_id_range(
-    data: &[u8],
-) -> Result<(ObjectId, ObjectId), Box<dyn Error>> {
-    if data.len() != 8 {
-        return Err("Invalid ID range size")?;
+/// Lobby phases are a byte telling which phase it is, followed by the
+/// milliseconds left if it's a countdown
+impl Encode for lobby::LobbyPhase {
+    fn encode(&self, writer: &mut Writer) -> Result<(), EncodeError> {
+        match self {
+            Self::Waiting => writer.put(&0_u8)?,
+            Self::Countdown(left) => writer
+                .put(&1_u8)?
+                .put(&u64::try_from(left.as_millis()).unwrap_or(u64::MAX))?,
+            Self::InMatch => writer.put(&2_u8)?,
+        };
+        Ok(())
     }
-    let start = ObjectId::from_be_bytes(data[0..4].try_into()?);
-    let end = ObjectId::from_be_bytes(data[4..8].try_into()?);
-    Ok((start, end))
 }
 
-fn serialize_id_request(id_amount: u32) -> (Vec<u8>, u8) {
-    (id_amount.to_be_bytes().to_vec(), ID_FETCH_ID)
+impl Decode for lobby::LobbyPhase {
+    fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        match reader.get::<u8>()? {
+            0 => Ok(Self::Waiting),
+            1 => Ok(Self::Countdown(std::time::Duration::from_millis(
+                reader.get()?,
+            ))),
+            2 => Ok(Self::InMatch),
+            value => Err(DecodeError::Unknown {
+                what: "lobby phase",
+                value,
+            }),
+        }
+    }
 }
 
-fn deserialize_id_request(data: &[u8]) -> Result<u32, Box<dyn Error>> {
-    if data.len() != 4 {
-        return Err("Invalid ID request size")?;
-    }
-    Ok(u32::from_be_bytes(data[0..4].try_into()?))
+/// Kinds of ownership messages, which share `OWNERSHIP_ID`
+const REQUEST_OWNERSHIP: u8 = 0;
+const RELEASE_OWNERSHIP: u8 = 1;
+const REFUSE_OWNERSHIP: u8 = 2;
+const ACK_OWNERSHIP: u8 = 3;
+const HANDOFF_REQUEST: u8 = 4;
+const OWNERSHIP_GRANTED: u8 = 5;
+const OWNERSHIP_DENIED: u8 = 6;
+
+/// Writes an ownership message of `kind` about `id`
+///
+/// Returns the command id of ownership messages
+fn put_ownership(
+    writer: &mut Writer,
+    kind: u8,
+    id: ObjectId,
+) -> Result<CommandId, EncodeError> {
+    writer.put(&kind)?.put(&id)?;
+    Ok(OWNERSHIP_ID)
 }
 
 impl<'a> Serializeable for ClientCommandType<'a> {
-    fn serialize(&self, msg_id: MsgId) -> Result<ChunkedMsg, Box<dyn Error>> {
-        let (data, cmd_id) = match self {
-            ClientCommandType::Login(name) => {
-                if name.len() > 256 {
-                    return Err("Login name too long")?;
-                }
-                let mut data = vec![name.len() as u8];
-                data.extend(name.bytes());
-                (data, LOGIN_ID)
+    fn serialize_with(
+        &self,
+        msg_id: MsgId,
+        compress: bool,
+    ) -> Result<ChunkedMsg, Box<dyn Error>> {
+        let mut writer = Writer::new();
+        let cmd_id = match self {
+            ClientCommandType::Login(name, compression) => {
+                writer.put(name)?.put(compression)?;
+                LOGIN_ID
+            }
+            ClientCommandType::Update(objects) => {
+                writer.put_each(objects)?;
+                UPDATE_OBJS_ID
             }
-            ClientCommandType::Update(objects) => serialize_objects(objects),
             ClientCommandType::UpdateReadOnly(objects) => {
-                serialize_objects(objects)
+                writer.put_each(objects)?;
+                UPDATE_OBJS_ID
+            }
+            ClientCommandType::GetIds(amount) => {
+                writer.put(amount)?;
+                ID_FETCH_ID
+            }
+            ClientCommandType::ClaimHit(hit) => {
+                writer.put(hit)?;
+                HIT_ID
+            }
+            ClientCommandType::ClaimTeleport(teleport) => {
+                writer.put(teleport)?;
+                TELEPORT_ID
+            }
+            ClientCommandType::JoinLobby(name) => {
+                writer.put(name)?;
+                LOBBY_ID
+            }
+            ClientCommandType::SetReady(ready) => {
+                writer.put(ready)?;
+                READY_ID
+            }
+            ClientCommandType::LeaveLobby => LEAVE_LOBBY_ID,
+            ClientCommandType::Ping(token) => {
+                writer.put(token)?;
+                PING_ID
+            }
+            ClientCommandType::Handshake(key) => {
+                writer.put(key)?;
+                HANDSHAKE_ID
+            }
+            ClientCommandType::UpdateProfile(loadouts) => {
+                writer.put(loadouts)?;
+                PROFILE_ID
+            }
+            ClientCommandType::RequestOwnership(id) => {
+                put_ownership(&mut writer, REQUEST_OWNERSHIP, *id)?
+            }
+            ClientCommandType::ReleaseOwnership(id, true) => {
+                put_ownership(&mut writer, RELEASE_OWNERSHIP, *id)?
+            }
+            ClientCommandType::ReleaseOwnership(id, false) => {
+                put_ownership(&mut writer, REFUSE_OWNERSHIP, *id)?
+            }
+            ClientCommandType::AckOwnership(id) => {
+                put_ownership(&mut writer, ACK_OWNERSHIP, *id)?
             }
-            ClientCommandType::GetIds(amount) => serialize_id_request(*amount),
         };
 
-        Ok(chunk_serialized_data(cmd_id, data.into_iter(), msg_id))
+        Ok(chunk_payload(cmd_id, writer.finish(), msg_id, compress)?)
     }
 
     fn deserialize(
         chunks: ChunkedMsg,
     ) -> Result<(Self, MsgId), Box<dyn Error>> {
         let (data, cmd_id, msg_id) = dechunk_serialized_data(chunks)?;
-        match cmd_id {
-            LOGIN_ID => {
-                if data.is_empty() {
-                    return Err("Login command too short")?;
+        let mut reader = Reader::new(&data);
+        let cmd = match cmd_id {
+            LOGIN_ID => Self::Login(reader.get()?, reader.get()?),
+            UPDATE_OBJS_ID => Self::Update(reader.get_rest()?),
+            ID_FETCH_ID => Self::GetIds(reader.get()?),
+            HIT_ID => Self::ClaimHit(reader.get()?),
+            TELEPORT_ID => Self::ClaimTeleport(reader.get()?),
+            LOBBY_ID => Self::JoinLobby(reader.get()?),
+            READY_ID => Self::SetReady(reader.get()?),
+            LEAVE_LOBBY_ID => Self::LeaveLobby,
+            PING_ID => Self::Ping(reader.get()?),
+            HANDSHAKE_ID => Self::Handshake(reader.get()?),
+            PROFILE_ID => Self::UpdateProfile(reader.get()?),
+            OWNERSHIP_ID => match reader.get()? {
+                REQUEST_OWNERSHIP => Self::RequestOwnership(reader.get()?),
+                RELEASE_OWNERSHIP => {
+                    Self::ReleaseOwnership(reader.get()?, true)
                 }
-                let name_len = data[0] as usize;
-                if data.len() < 1 + name_len {
-                    return Err("Login command too short")?;
+                REFUSE_OWNERSHIP => {
+                    Self::ReleaseOwnership(reader.get()?, false)
                 }
-                let name = std::str::from_utf8(&data[1..])?;
-                Ok((Self::Login(name.to_string()), msg_id))
-            }
-            UPDATE_OBJS_ID => {
-                Ok((Self::Update(deserialize_update(data)?), msg_id))
-            }
-            ID_FETCH_ID => {
-                Ok((Self::GetIds(deserialize_id_request(&data)?), msg_id))
-            }
-            x => Err(format!("Unknown command with value '{}'", x))?,
-        }
+                ACK_OWNERSHIP => Self::AckOwnership(reader.get()?),
+                value => Err(DecodeError::Unknown {
+                    what: "ownership message",
+                    value,
+                })?,
+            },
+            value => Err(DecodeError::Unknown {
+                what: "command",
+                value,
+            })?,
+        };
+        reader.finish()?;
+        Ok((cmd, msg_id))
     }
 }
 
 impl Serializeable for ServerCommandType {
-    fn serialize(&self, msg_id: MsgId) -> Result<ChunkedMsg, Box<dyn Error>> {
-        let (data, cmd_id) = match self {
-            ServerCommandType::ReturnLogin(login) => serialize_login(login),
-            ServerCommandType::Update(objects) => serialize_objects(objects),
-            ServerCommandType::ReturnIds(ids) => serialize_id_range(*ids),
+    fn serialize_with(
+        &self,
+        msg_id: MsgId,
+        compress: bool,
+    ) -> Result<ChunkedMsg, Box<dyn Error>> {
+        let mut writer = Writer::new();
+        let cmd_id = match self {
+            ServerCommandType::ReturnLogin(login) => {
+                writer.put(login)?;
+                LOGIN_ID
+            }
+            ServerCommandType::Update(objects) => {
+                writer.put_each(objects)?;
+                UPDATE_OBJS_ID
+            }
+            ServerCommandType::ReturnIds(ids) => {
+                writer.put(ids)?;
+                ID_FETCH_ID
+            }
+            ServerCommandType::ConfirmHit(hit) => {
+                writer.put(hit)?;
+                HIT_ID
+            }
+            ServerCommandType::ConfirmTeleport(teleport) => {
+                writer.put(teleport)?;
+                TELEPORT_ID
+            }
+            ServerCommandType::LobbyState(lobby) => {
+                writer.put(lobby)?;
+                LOBBY_ID
+            }
+            ServerCommandType::MatchStart(start) => {
+                writer.put(start)?;
+                MATCH_START_ID
+            }
+            ServerCommandType::MatchEnd(results) => {
+                writer.put(results)?;
+                MATCH_END_ID
+            }
+            ServerCommandType::Pong(token) => {
+                writer.put(token)?;
+                PING_ID
+            }
+            ServerCommandType::Handshake(key) => {
+                writer.put(key)?;
+                HANDSHAKE_ID
+            }
+            ServerCommandType::Profile(profile) => {
+                writer.put(profile)?;
+                PROFILE_ID
+            }
+            ServerCommandType::HandoffRequest(id) => {
+                put_ownership(&mut writer, HANDOFF_REQUEST, *id)?
+            }
+            ServerCommandType::OwnershipGranted(id) => {
+                put_ownership(&mut writer, OWNERSHIP_GRANTED, *id)?
+            }
+            ServerCommandType::OwnershipDenied(id) => {
+                put_ownership(&mut writer, OWNERSHIP_DENIED, *id)?
+            }
         };
-        Ok(chunk_serialized_data(cmd_id, data.into_iter(), msg_id))
+        Ok(chunk_payload(cmd_id, writer.finish(), msg_id, compress)?)
     }
 
     fn deserialize(
         chunks: ChunkedMsg,
     ) -> Result<(Self, MsgId), Box<dyn Error>> {
         let (data, cmd_id, msg_id) = dechunk_serialized_data(chunks)?;
-        match cmd_id {
-            LOGIN_ID => {
-                let login = deserialize_login(&data)?;
-                Ok((Self::ReturnLogin(login), msg_id))
-            }
-            UPDATE_OBJS_ID => {
-                Ok((Self::Update(deserialize_update(data)?), msg_id))
-            }
-            ID_FETCH_ID => {
-                Ok((Self::ReturnIds(deserialize_id_range(&data)?), msg_id))
-            }
-            x => Err(format!("Unknown command with value '{}'", x))?,
-        }
+        let mut reader = Reader::new(&data);
+        let cmd = match cmd_id {
+            LOGIN_ID => Self::ReturnLogin(reader.get()?),
+            UPDATE_OBJS_ID => Self::Update(reader.get_rest()?),
+            ID_FETCH_ID => Self::ReturnIds(reader.get()?),
+            HIT_ID => Self::ConfirmHit(reader.get()?),
+            TELEPORT_ID => Self::ConfirmTeleport(reader.get()?),
+            LOBBY_ID => Self::LobbyState(reader.get()?),
+            MATCH_START_ID => Self::MatchStart(reader.get()?),
+            MATCH_END_ID => Self::MatchEnd(reader.get()?),
+            PING_ID => Self::Pong(reader.get()?),
+            HANDSHAKE_ID => Self::Handshake(reader.get()?),
+            PROFILE_ID => Self::Profile(reader.get()?),
+            OWNERSHIP_ID => match reader.get()? {
+                HANDOFF_REQUEST => Self::HandoffRequest(reader.get()?),
+                OWNERSHIP_GRANTED => Self::OwnershipGranted(reader.get()?),
+                OWNERSHIP_DENIED => Self::OwnershipDenied(reader.get()?),
+                value => Err(DecodeError::Unknown {
+                    what: "ownership message",
+                    value,
+                })?,
+            },
+            value => Err(DecodeError::Unknown {
+                what: "command",
+                value,
+            })?,
+        };
+        reader.finish()?;
+        Ok((cmd, msg_id))
     }
 }
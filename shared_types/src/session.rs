@@ -0,0 +1,272 @@
+use super::*;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use x25519_dalek::{EphemeralSecret, PublicKey};
+
+pub const PUBLIC_KEY_SIZE: usize = 32;
+/// Bytes a sealed datagram is longer than the datagram it seals
+pub const SEAL_OVERHEAD: usize = 16;
+/// Time a session must be unused before a new handshake can replace it
+pub const SESSION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Header of a sealed datagram, in place of the header of a chunk
+const SEALED_HEADER: u8 = b'E';
+/// Labels the keys derived for the session
+const KEY_INFO: &[u8] = b"ProjectOort session keys";
+/// Number of message ids before the newest one whose packets are still
+/// accepted
+const REPLAY_WINDOW: MsgId = 1024;
+
+/// The side of the handshake a session is for, which determines the keys it
+/// sends and receives with
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Role {
+    Client,
+    Server,
+}
+
+/// One side of a key exchange whose peer hasn't answered yet
+///
+/// Each side sends the other its public key, and both derive the same keys
+/// from the shared secret. The keys aren't signed, so the handshake doesn't
+/// prove who the peer is, but once it's done nobody else can send datagrams
+/// on behalf of either side
+pub struct Handshake {
+    secret: EphemeralSecret,
+    public: PublicKey,
+}
+
+impl Handshake {
+    /// Creates a new key pair for the handshake
+    #[must_use]
+    pub fn new() -> Self {
+        let secret = EphemeralSecret::random_from_rng(rand::rngs::OsRng);
+        let public = PublicKey::from(&secret);
+        Self { secret, public }
+    }
+
+    /// Gets the key to send to the peer
+    #[must_use]
+    pub fn public_key(&self) -> [u8; PUBLIC_KEY_SIZE] {
+        self.public.to_bytes()
+    }
+
+    /// Finishes the handshake with the public key of the peer, creating the
+    /// session of the side `role`
+    #[must_use]
+    pub fn finish(
+        self,
+        peer_key: [u8; PUBLIC_KEY_SIZE],
+        role: Role,
+    ) -> Session {
+        let peer = PublicKey::from(peer_key);
+        let shared = self.secret.diffie_hellman(&peer);
+        let (client, server) = match role {
+            Role::Client => (self.public, peer),
+            Role::Server => (peer, self.public),
+        };
+        let salt: Vec<u8> = client
+            .as_bytes()
+            .iter()
+            .chain(server.as_bytes())
+            .copied()
+            .collect();
+        let mut keys = [0_u8; 64];
+        hkdf::Hkdf::<sha2::Sha256>::new(Some(&salt[..]), shared.as_bytes())
+            .expand(KEY_INFO, &mut keys)
+            .expect("64 bytes is a valid output length");
+        let (to_server, to_client) = keys.split_at(32);
+        let (send, recv) = match role {
+            Role::Client => (to_server, to_client),
+            Role::Server => (to_client, to_server),
+        };
+        Session {
+            send: ChaCha20Poly1305::new(Key::from_slice(send)),
+            recv: ChaCha20Poly1305::new(Key::from_slice(recv)),
+            replay: RefCell::new(ReplayWindow::default()),
+            last_used: Cell::new(Instant::now()),
+            established: Cell::new(false),
+            role,
+            public_key: self.public.to_bytes(),
+            peer_key,
+        }
+    }
+}
+
+impl Default for Handshake {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The packets received from each message of a session, so packets that are
+/// sent again can be dropped
+#[derive(Default)]
+struct ReplayWindow {
+    newest: Option<MsgId>,
+    /// Bit set of the packet numbers received of each message in the window
+    seen: HashMap<MsgId, [u64; 4]>,
+}
+
+impl ReplayWindow {
+    /// Records packet `pkt_num` of message `msg_id`
+    ///
+    /// Returns `false` if the packet was already received or its message is
+    /// too old for the window
+    fn record(&mut self, msg_id: MsgId, pkt_num: PacketNum) -> bool {
+        if self.newest.map_or(false, |newest| {
+            msg_id.saturating_add(REPLAY_WINDOW) < newest
+        }) {
+            return false;
+        }
+        let seen = self.seen.entry(msg_id).or_default();
+        let (word, bit) = (pkt_num as usize / 64, 1 << (pkt_num % 64));
+        if seen[word] & bit != 0 {
+            return false;
+        }
+        seen[word] |= bit;
+        if self.newest.map_or(true, |newest| msg_id > newest) {
+            self.newest = Some(msg_id);
+            self.seen
+                .retain(|id, _| id.saturating_add(REPLAY_WINDOW) >= msg_id);
+        }
+        true
+    }
+}
+
+/// Gets the nonce of the datagram with the title `title`. A message id and
+/// packet number are never reused by a sender, and each side of the session
+/// sends with its own key, so no nonce is used twice with the same key
+fn nonce(title: &[u8]) -> [u8; 12] {
+    let mut nonce = [0; 12];
+    nonce[..4].copy_from_slice(&title[MSG_ID_INDEX..MSG_ID_INDEX + 4]);
+    nonce[4] = title[PKT_NM_INDEX];
+    nonce
+}
+
+/// `true` if `datagram` is sealed by a session
+#[must_use]
+pub fn is_sealed(datagram: &[u8]) -> bool {
+    datagram.first() == Some(&SEALED_HEADER)
+}
+
+/// The keys of an established session, which encrypt and authenticate the
+/// datagrams sent between the client and server
+///
+/// A sealed datagram keeps the title of its chunk so it can be buffered, with
+/// its header replaced, and the title is authenticated along with the rest of
+/// the datagram. Replayed datagrams are rejected using their message ids
+pub struct Session {
+    send: ChaCha20Poly1305,
+    recv: ChaCha20Poly1305,
+    replay: RefCell<ReplayWindow>,
+    last_used: Cell<Instant>,
+    /// `true` once a datagram from the peer has been opened, which shows the
+    /// peer received its side of the handshake
+    established: Cell<bool>,
+    role: Role,
+    /// Key this side sent in the handshake
+    public_key: [u8; PUBLIC_KEY_SIZE],
+    /// Key the peer sent in the handshake
+    peer_key: [u8; PUBLIC_KEY_SIZE],
+}
+
+impl Session {
+    /// Encrypts and authenticates the chunk `datagram`
+    ///
+    /// # Errors
+    /// Fails if `datagram` isn't a chunk
+    pub fn seal(&self, datagram: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        if datagram.len() < CHUNK_TITLE_SIZE
+            || datagram[..CHUNK_HEADER_SIZE] != CHUNK_HEADER
+        {
+            return Err("Only chunks can be sealed")?;
+        }
+        let mut sealed = datagram[..CHUNK_TITLE_SIZE].to_vec();
+        sealed[0] = SEALED_HEADER;
+        let ciphertext = self
+            .send
+            .encrypt(
+                Nonce::from_slice(&nonce(&sealed)),
+                Payload {
+                    msg: &datagram[CHUNK_TITLE_SIZE..],
+                    aad: &sealed,
+                },
+            )
+            .map_err(|_| "Could not seal datagram")?;
+        sealed.extend(ciphertext);
+        Ok(sealed)
+    }
+
+    /// Decrypts the sealed `datagram`, getting the chunk it seals
+    ///
+    /// # Errors
+    /// Fails if `datagram` isn't sealed, wasn't sealed by the peer, was
+    /// changed, or was already received
+    pub fn open(&self, datagram: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        if !is_sealed(datagram)
+            || datagram.len() < CHUNK_TITLE_SIZE + SEAL_OVERHEAD
+        {
+            return Err("Datagram isn't sealed")?;
+        }
+        let title = &datagram[..CHUNK_TITLE_SIZE];
+        let plaintext = self
+            .recv
+            .decrypt(
+                Nonce::from_slice(&nonce(title)),
+                Payload {
+                    msg: &datagram[CHUNK_TITLE_SIZE..],
+                    aad: title,
+                },
+            )
+            .map_err(|_| "Datagram failed authentication")?;
+        let (_, msg_id, pkt_num) = remote::get_cmd_ids_and_nums(title);
+        if !self.replay.borrow_mut().record(msg_id, pkt_num) {
+            return Err("Replayed datagram")?;
+        }
+        self.last_used.set(Instant::now());
+        self.established.set(true);
+        let mut chunk = title.to_vec();
+        chunk[..CHUNK_HEADER_SIZE].copy_from_slice(&CHUNK_HEADER);
+        chunk.extend(plaintext);
+        Ok(chunk)
+    }
+
+    /// `true` if nothing has been received in the session for
+    /// `SESSION_TIMEOUT`
+    #[must_use]
+    pub fn is_idle(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.last_used.get()) > SESSION_TIMEOUT
+    }
+
+    /// `true` if a datagram from the peer has been opened. Until then, the
+    /// peer may not have received the reply to its handshake
+    #[must_use]
+    pub fn is_established(&self) -> bool {
+        self.established.get()
+    }
+
+    /// `true` if datagrams which aren't sealed are accepted from the peer.
+    /// Only servers accept them, until the session is established, since the
+    /// reply to the client's handshake may have been lost and the client
+    /// sends the handshake again
+    #[must_use]
+    pub fn accepts_unsealed(&self) -> bool {
+        self.role == Role::Server && !self.is_established()
+    }
+
+    /// Gets the key this side sent in the handshake
+    #[must_use]
+    pub const fn public_key(&self) -> [u8; PUBLIC_KEY_SIZE] {
+        self.public_key
+    }
+
+    /// Gets the key the peer sent in the handshake
+    #[must_use]
+    pub const fn peer_key(&self) -> [u8; PUBLIC_KEY_SIZE] {
+        self.peer_key
+    }
+}
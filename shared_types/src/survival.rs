@@ -0,0 +1,237 @@
+use std::time::Duration;
+
+/// Pause before the first wave and between waves
+pub const INTERMISSION: Duration = Duration::from_secs(20);
+/// Time the player has to destroy a wave before the next wave arrives
+pub const WAVE_TIME: Duration = Duration::from_secs(90);
+/// Largest number of enemies of each difficulty in a wave
+pub const MAX_PER_DIFFICULTY: u32 = 4;
+
+/// How skilled an enemy is
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Ace,
+}
+
+impl Difficulty {
+    /// Gets the credits awarded for destroying an enemy of this difficulty
+    #[must_use]
+    pub const fn bounty(self) -> u32 {
+        match self {
+            Self::Easy => 50,
+            Self::Normal => 100,
+            Self::Ace => 200,
+        }
+    }
+}
+
+/// Something the player can buy between waves
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum ShopItem {
+    /// Restores the player's shield and hull
+    Repair,
+    ExtraLife,
+    /// Temporarily increases how quickly the player can fire
+    FireBoost,
+}
+
+impl ShopItem {
+    #[must_use]
+    pub const fn cost(self) -> u32 {
+        match self {
+            Self::Repair => 150,
+            Self::ExtraLife => 500,
+            Self::FireBoost => 200,
+        }
+    }
+}
+
+/// The enemies of a wave
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Wave {
+    /// Number of the wave, starting at `1`
+    pub number: u32,
+    pub easy: u32,
+    pub normal: u32,
+    pub ace: u32,
+}
+
+impl Wave {
+    /// Gets the `number`th wave. Every wave has one more enemy than the last,
+    /// and later waves have more skilled enemies
+    #[must_use]
+    pub fn new(number: u32) -> Self {
+        let total = (number + 2).min(MAX_PER_DIFFICULTY * 3);
+        let ace = (number.saturating_sub(3) / 2).min(MAX_PER_DIFFICULTY);
+        let normal = (number / 2).min(MAX_PER_DIFFICULTY).min(total - ace);
+        Self {
+            number,
+            easy: (total - ace - normal).min(MAX_PER_DIFFICULTY),
+            normal,
+            ace,
+        }
+    }
+
+    /// Gets the number of enemies of difficulty `difficulty` in the wave
+    #[must_use]
+    pub const fn count(&self, difficulty: Difficulty) -> u32 {
+        match difficulty {
+            Difficulty::Easy => self.easy,
+            Difficulty::Normal => self.normal,
+            Difficulty::Ace => self.ace,
+        }
+    }
+}
+
+/// The part of the survival game being played
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum WavePhase {
+    /// Pause before the next wave, during which the player can shop, with
+    /// the time left before the wave arrives
+    Intermission(Duration),
+    /// A wave is attacking. The next wave arrives once `time_left` runs out,
+    /// even if enemies remain
+    Attack { remaining: u32, time_left: Duration },
+}
+
+/// Something that happened to the waves
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum WaveEvent {
+    /// The wave arrived, and its enemies should be spawned
+    Started(Wave),
+    /// Every enemy was destroyed, with the number of the cleared wave
+    Cleared(u32),
+}
+
+impl std::fmt::Display for WaveEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Started(wave) => write!(f, "Wave {} incoming", wave.number),
+            Self::Cleared(wave) => write!(f, "Wave {} cleared", wave),
+        }
+    }
+}
+
+/// Sends timed waves of enemies at the player, and keeps the credits the
+/// player earns by destroying them
+#[derive(Clone, Debug)]
+pub struct Waves {
+    /// Number of the last wave that arrived
+    wave: u32,
+    phase: WavePhase,
+    credits: u32,
+}
+
+impl Default for Waves {
+    fn default() -> Self {
+        Self {
+            wave: 0,
+            phase: WavePhase::Intermission(INTERMISSION),
+            credits: 0,
+        }
+    }
+}
+
+impl Waves {
+    /// Gets the number of the last wave that arrived, or `0` before the
+    /// first wave
+    #[must_use]
+    pub const fn wave(&self) -> u32 {
+        self.wave
+    }
+
+    #[must_use]
+    pub const fn phase(&self) -> WavePhase {
+        self.phase
+    }
+
+    #[must_use]
+    pub const fn credits(&self) -> u32 {
+        self.credits
+    }
+
+    /// Advances the waves by `dt`
+    ///
+    /// Returns `WaveEvent::Started` when the next wave arrives
+    pub fn update(&mut self, dt: Duration) -> Option<WaveEvent> {
+        match self.phase {
+            WavePhase::Intermission(left) if left <= dt => {
+                Some(self.next_wave(0))
+            }
+            WavePhase::Intermission(left) => {
+                self.phase = WavePhase::Intermission(left - dt);
+                None
+            }
+            WavePhase::Attack {
+                remaining,
+                time_left,
+            } if time_left <= dt => Some(self.next_wave(remaining)),
+            WavePhase::Attack {
+                remaining,
+                time_left,
+            } => {
+                self.phase = WavePhase::Attack {
+                    remaining,
+                    time_left: time_left - dt,
+                };
+                None
+            }
+        }
+    }
+
+    /// Starts the next wave, with `remaining` enemies left from the last
+    /// wave
+    fn next_wave(&mut self, remaining: u32) -> WaveEvent {
+        self.wave += 1;
+        self.phase = WavePhase::Attack {
+            remaining,
+            time_left: WAVE_TIME,
+        };
+        WaveEvent::Started(Wave::new(self.wave))
+    }
+
+    /// Records `count` enemies of the current wave being spawned
+    pub fn enemies_spawned(&mut self, count: u32) {
+        if let WavePhase::Attack { remaining, .. } = &mut self.phase {
+            *remaining += count;
+        }
+    }
+
+    /// Records an enemy of difficulty `difficulty` being destroyed and
+    /// awards its bounty. Once every enemy is destroyed, the player is
+    /// awarded a bonus and the next intermission starts
+    ///
+    /// Returns `WaveEvent::Cleared` if the enemy was the last one
+    pub fn enemy_destroyed(
+        &mut self,
+        difficulty: Difficulty,
+    ) -> Option<WaveEvent> {
+        self.credits += difficulty.bounty();
+        match &mut self.phase {
+            WavePhase::Attack { remaining, .. } if *remaining > 1 => {
+                *remaining -= 1;
+                None
+            }
+            WavePhase::Attack { .. } => {
+                self.credits += self.wave * 100;
+                self.phase = WavePhase::Intermission(INTERMISSION);
+                Some(WaveEvent::Cleared(self.wave))
+            }
+            WavePhase::Intermission(_) => None,
+        }
+    }
+
+    /// Buys `item` if the player is between waves and can afford it
+    ///
+    /// Returns `true` if the item was bought
+    pub fn buy(&mut self, item: ShopItem) -> bool {
+        let can_buy = matches!(self.phase, WavePhase::Intermission(_))
+            && self.credits >= item.cost();
+        if can_buy {
+            self.credits -= item.cost();
+        }
+        can_buy
+    }
+}
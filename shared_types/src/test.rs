@@ -3,7 +3,7 @@ use super::*;
 
 #[test]
 fn cct_serialize_deserialize() {
-    let cct = ClientCommandType::Login("test".to_string());
+    let cct = ClientCommandType::Login("test".to_string(), true);
     let msg_id = 0x22458;
     let chunks = cct.serialize(msg_id).unwrap();
     let cct2 = ClientCommandType::deserialize(chunks).unwrap();
@@ -52,11 +52,26 @@ fn sct_serialize_deserialize() {
         pid: ObjectId::new(475893),
         starting_ids: (ObjectId::new(0x123456), ObjectId::new(0x12345679)),
         spawn_pos: [0.0, 1.0, -2.0],
+        map_seed: Some(0x0123_4567_89AB_CDEF),
+        compression: true,
         lighting: game_map::GlobalLightingInfo {
             dir_light: cgmath::vec3(10.1f32, -20.2, 30.3),
             hdr: String::from("assets/hdr/hdr.hdr"),
             skybox: String::from("assets/skybox.jpg"),
         },
+        profile: profile::Profile {
+            stats: profile::CareerStats {
+                matches: 12,
+                wins: 5,
+                hits: 340,
+            },
+            unlocked_ships: vec![profile::DEFAULT_SHIP.to_owned()],
+            loadouts: vec![profile::Loadout {
+                name: "sniper".to_owned(),
+                ship: profile::DEFAULT_SHIP.to_owned(),
+                gear: vec!["scope".to_owned(), "shield".to_owned()],
+            }],
+        },
     });
     let msg_id = 0x2A458;
     let chunks = sct.serialize(msg_id).unwrap();
@@ -125,22 +140,967 @@ fn sct_serialize_deserialize() {
     assert_eq!((sct, msg_id), sct2);
 }
 
+#[test]
+fn hit_serialize_deserialize() {
+    let hit = HitClaim {
+        shooter: ObjectId::new(0x1234),
+        target: ObjectId::new(0x5678),
+        hit_point: [1.5, -20.25, 300.],
+    };
+    let cct = ClientCommandType::ClaimHit(hit);
+    let cct2 = ClientCommandType::deserialize(cct.serialize(0x22).unwrap());
+    assert_eq!((cct, 0x22), cct2.unwrap());
+
+    let sct = ServerCommandType::ConfirmHit(hit);
+    let sct2 = ServerCommandType::deserialize(sct.serialize(0x23).unwrap());
+    assert_eq!((sct, 0x23), sct2.unwrap());
+}
+
+#[test]
+fn teleport_serialize_deserialize() {
+    let teleport = wormhole::TeleportClaim {
+        body: ObjectId::new(0x4321),
+        wormhole: 3,
+        entry: 1,
+    };
+    let cct = ClientCommandType::ClaimTeleport(teleport);
+    let cct2 = ClientCommandType::deserialize(cct.serialize(0x24).unwrap());
+    assert_eq!((cct, 0x24), cct2.unwrap());
+
+    let sct = ServerCommandType::ConfirmTeleport(teleport);
+    let sct2 = ServerCommandType::deserialize(sct.serialize(0x25).unwrap());
+    assert_eq!((sct, 0x25), sct2.unwrap());
+}
+
+#[test]
+fn wormholes_keep_motion_relative_to_portals() {
+    use cgmath::*;
+    use wormhole::{Portal, Wormhole};
+    let wormhole = Wormhole {
+        ends: [
+            Portal::new(point3(0., 0., 0.), vec3(0., 0., 1.)),
+            Portal::new(point3(500., 0., 0.), vec3(1., 0., 0.)),
+        ],
+        radius: 10.,
+    };
+    // only bodies moving into a portal's face enter it
+    assert_eq!(
+        wormhole.entered(point3(2., 0., 3.), vec3(0., 0., -5.)),
+        Some(0)
+    );
+    assert_eq!(wormhole.entered(point3(2., 0., 3.), vec3(0., 0., 5.)), None);
+    assert_eq!(
+        wormhole.entered(point3(0., 0., 30.), vec3(0., 0., -5.)),
+        None
+    );
+    assert_eq!(
+        wormhole.entered(point3(505., 0., 0.), vec3(-1., 0., 0.)),
+        Some(1)
+    );
+
+    let traversal = wormhole.traverse(0, point3(0., 2., 3.), vec3(1., 0., -5.));
+    // entering along -z leaves along +x
+    assert!(traversal.velocity.x > 4.9);
+    assert!((traversal.velocity.magnitude() - 26f64.sqrt()).abs() < 1e-9);
+    assert!((traversal.pos.y - 2.).abs() < 1e-9);
+    // the body is left outside of the exit's sensor, moving away from it
+    assert_eq!(wormhole.entered(traversal.pos, traversal.velocity), None);
+    assert!(traversal.pos.distance(point3(500., 0., 0.)) > wormhole.radius);
+    let forward = traversal.rotation.rotate_vector(vec3(0., 0., -1.));
+    assert!((forward - vec3(1., 0., 0.)).magnitude() < 1e-9);
+}
+
+#[test]
+fn lobby_serialize_deserialize() {
+    for cct in [
+        ClientCommandType::JoinLobby("lobby".to_string()),
+        ClientCommandType::SetReady(true),
+        ClientCommandType::LeaveLobby,
+    ] {
+        let cct2 = ClientCommandType::deserialize(cct.serialize(0x30).unwrap());
+        assert_eq!((cct, 0x30), cct2.unwrap());
+    }
+    let pid = ObjectId::new(0x1234);
+    let lobby = lobby::LobbyInfo {
+        name: "lobby".to_string(),
+        phase: lobby::LobbyPhase::Countdown(std::time::Duration::from_millis(
+            2500,
+        )),
+        players: vec![lobby::LobbyPlayer {
+            pid,
+            name: "player".to_string(),
+            ready: true,
+        }],
+    };
+    for sct in [
+        ServerCommandType::LobbyState(lobby),
+        ServerCommandType::MatchStart(lobby::MatchStart {
+            map_seed: Some(0xDEADBEEF),
+            spawns: vec![(pid, [300., 100., -5.5])],
+        }),
+        ServerCommandType::MatchEnd(lobby::MatchResults::new(vec![
+            (pid, 3),
+            (ObjectId::new(0x5678), 7),
+        ])),
+    ] {
+        let sct2 = ServerCommandType::deserialize(sct.serialize(0x31).unwrap());
+        assert_eq!((sct, 0x31), sct2.unwrap());
+    }
+}
+
 #[test]
 fn add_rem_end() {
     let cmd = ServerCommandType::ReturnLogin(LoginInfo {
         pid: ObjectId::new(475893),
         starting_ids: (ObjectId::new(0x12345678), ObjectId::new(0x12345679)),
         spawn_pos: [0.0, 1.0, -2.0],
+        map_seed: None,
+        compression: false,
         lighting: game_map::GlobalLightingInfo {
             dir_light: cgmath::vec3(10.1f32, -20.2, 30.3),
             hdr: String::from("assets/hdr/hdr.hdr"),
             skybox: String::from("assets/skybox.jpg"),
         },
+        profile: profile::Profile::default(),
     });
     let msg = cmd.serialize(0x2A458).unwrap();
     assert_eq!(remove_end_chunk(add_end_chunk(msg.clone())).unwrap(), msg);
 
-    let cmd = ClientCommandType::Login("HellloThereIAmGroot".to_string());
+    let cmd =
+        ClientCommandType::Login("HellloThereIAmGroot".to_string(), false);
     let msg = cmd.serialize(0x2A458).unwrap();
     assert_eq!(remove_end_chunk(add_end_chunk(msg.clone())).unwrap(), msg);
 }
+
+#[test]
+fn object_type_byte_conversion() {
+    for typ in [
+        ObjectType::Laser,
+        ObjectType::Planet,
+        ObjectType::Cloud,
+        ObjectType::Pickup,
+        ObjectType::Station,
+        ObjectType::Debris,
+    ] {
+        assert_eq!(ObjectType::try_from(typ as u8), Ok(typ));
+    }
+    assert!(ObjectType::try_from(10).is_err());
+}
+
+#[test]
+fn spawn_table_weights() {
+    use pickup::PickupKind::*;
+    let table = pickup::SpawnTable {
+        points: Vec::new(),
+        kinds: vec![(ShieldCell, 1), (WeaponMod, 0), (EnergyCell, 3)],
+        respawn_time: std::time::Duration::default(),
+    };
+    assert_eq!(table.choose(0.), Some(ShieldCell));
+    assert_eq!(table.choose(0.2), Some(ShieldCell));
+    assert_eq!(table.choose(0.25), Some(EnergyCell));
+    assert_eq!(table.choose(1.), Some(EnergyCell));
+    assert_eq!(pickup::SpawnTable::default().choose(0.5), None);
+}
+
+#[test]
+fn waves_escalate() {
+    use survival::*;
+    let first = Wave::new(1);
+    assert_eq!((first.easy, first.normal, first.ace), (3, 0, 0));
+    let fifth = Wave::new(5);
+    assert_eq!((fifth.easy, fifth.normal, fifth.ace), (4, 2, 1));
+    let last = Wave::new(100);
+    assert_eq!(last.count(Difficulty::Ace), MAX_PER_DIFFICULTY);
+    assert_eq!(last.count(Difficulty::Easy), MAX_PER_DIFFICULTY);
+}
+
+#[test]
+fn waves_alternate_with_intermissions() {
+    use survival::*;
+    let mut waves = Waves::default();
+    assert!(!waves.buy(ShopItem::Repair));
+    assert_eq!(
+        waves.update(INTERMISSION),
+        Some(WaveEvent::Started(Wave::new(1)))
+    );
+    waves.enemies_spawned(2);
+    assert_eq!(waves.enemy_destroyed(Difficulty::Easy), None);
+    // shopping is closed during a wave
+    assert!(!waves.buy(ShopItem::Repair));
+    assert_eq!(
+        waves.enemy_destroyed(Difficulty::Ace),
+        Some(WaveEvent::Cleared(1))
+    );
+    assert_eq!(waves.credits(), 350);
+    assert!(waves.buy(ShopItem::FireBoost));
+    assert!(!waves.buy(ShopItem::ExtraLife));
+    assert_eq!(waves.credits(), 150);
+
+    // the next wave arrives once the time runs out, even if enemies remain
+    waves.update(INTERMISSION);
+    waves.enemies_spawned(3);
+    assert_eq!(
+        waves.update(WAVE_TIME),
+        Some(WaveEvent::Started(Wave::new(3)))
+    );
+    assert_eq!(
+        waves.phase(),
+        WavePhase::Attack {
+            remaining: 3,
+            time_left: WAVE_TIME
+        }
+    );
+}
+
+#[test]
+fn procedural_maps_are_deterministic() {
+    use game_map::{Map, ProceduralMap};
+    let objects = ProceduralMap::new(42).initial_objects();
+    assert!(objects.iter().any(|o| o.typ == ObjectType::Asteroid));
+    assert_eq!(
+        objects
+            .iter()
+            .filter(|o| o.typ == ObjectType::Planet)
+            .count(),
+        1
+    );
+    assert_eq!(objects, ProceduralMap::new(42).initial_objects());
+    assert_ne!(objects, ProceduralMap::new(43).initial_objects());
+    assert_eq!(ProceduralMap::new(42).seed(), Some(42));
+}
+
+#[test]
+fn procedural_maps_keep_spawn_zones_clear() {
+    use game_map::{Map, ProceduralMap, SPAWN_ZONE_RADIUS};
+    for seed in 0..20 {
+        let map = ProceduralMap::new(seed);
+        for obj in map
+            .initial_objects()
+            .iter()
+            .filter(|o| o.typ != ObjectType::Cloud)
+        {
+            let pos = obj.mat[1];
+            for spawn in map.spawn_points() {
+                let dist2: f64 =
+                    (0..3).map(|i| (pos[i] - spawn[i]).powi(2)).sum();
+                assert!(dist2.sqrt() > SPAWN_ZONE_RADIUS);
+            }
+        }
+    }
+}
+
+#[test]
+fn map_file_parse() {
+    use game_map::Map;
+    let map = map_file::FileMap::parse(
+        "# a small arena
+        skybox = assets/skybox.jpg
+        hdr = assets/hdr/hdr.hdr
+        light = 0 -2 0
+
+        spawn = 100 0 0
+        spawn = -100 0 0
+        planet = 0 0 0 10
+        asteroid = 0 50 200 0.5 0 1 0 90
+        cloud = 0 0 -300 80
+        wormhole = 0 0 400 0 0 -1 0 0 -400 0 0 2 20",
+    )
+    .unwrap();
+    let objects = map.initial_objects();
+    assert_eq!(objects.len(), 3);
+    assert_eq!(objects[1].typ, ObjectType::Asteroid);
+    assert_eq!(objects[1].mat[1][..3], [0., 50., 200.]);
+    assert_eq!(objects[2].id, ObjectId::new(2));
+    assert_eq!(map.spawn_points(), vec![[100., 0., 0.], [-100., 0., 0.]]);
+    let lighting = map.lighting_info();
+    assert_eq!(lighting.skybox, "assets/skybox.jpg");
+    assert_eq!(lighting.dir_light, cgmath::vec3(0., -1., 0.));
+    let wormholes = map.wormholes();
+    assert_eq!(wormholes.len(), 1);
+    assert_eq!(wormholes[0].ends[1].normal, [0., 0., 1.]);
+}
+
+#[test]
+fn map_file_rejects_malformed_maps() {
+    use map_file::FileMap;
+    let lighting = "skybox = a.jpg\nhdr = a.hdr\nlight = 0 1 0\n";
+    assert!(FileMap::parse(lighting).is_ok());
+    assert!(FileMap::parse("skybox = a.jpg\nhdr = a.hdr").is_err());
+    assert!(FileMap::parse(&format!("{}asteroid = 0 0 0", lighting)).is_err());
+    assert!(FileMap::parse(&format!("{}ship = 0 0 0 1", lighting)).is_err());
+    assert!(FileMap::parse(&format!("{}planet 0 0 0 1", lighting)).is_err());
+    assert!(FileMap::parse(&format!("{}wormhole = 0 0 0 1", lighting)).is_err());
+    let facing = "wormhole = 0 0 0 0 0 0 9 9 9 0 1 0 10";
+    assert!(FileMap::parse(&format!("{}{}", lighting, facing)).is_err());
+}
+
+#[test]
+fn compression_round_trip() {
+    let repetitive: Vec<u8> = (0..5000).map(|i| (i % 7) as u8).collect();
+    let mixed: Vec<u8> = (0_u32..5000)
+        .map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8)
+        .collect();
+    for data in [&[][..], b"short", &repetitive, &mixed] {
+        let compressed = compression::compress(data);
+        let decompressed = compression::decompress(&compressed, data.len());
+        assert_eq!(decompressed.unwrap(), data);
+    }
+    assert!(compression::compress(&repetitive).len() < 100);
+
+    let compressed = compression::compress(&repetitive);
+    assert!(compression::decompress(&compressed, 4999).is_err());
+    assert!(compression::decompress(&compressed, 5001).is_err());
+    let truncated = &compressed[..compressed.len() / 2];
+    assert!(compression::decompress(truncated, 5000).is_err());
+}
+
+/// Checks compression at least halves the packets a typical 200 object
+/// update is sent in. Most of each object is zeros or repeated
+#[test]
+fn compression_halves_update_packets() {
+    use cgmath::*;
+    use rand::{Rng, SeedableRng};
+    let mut rng = rand::rngs::StdRng::seed_from_u64(200);
+    let mut id = ObjectId::default();
+    let objects: Vec<_> = (0..200)
+        .map(|i| {
+            let pos = point3(
+                rng.gen_range(-2000. ..2000.),
+                rng.gen_range(-2000. ..2000.),
+                rng.gen_range(-2000. ..2000.),
+            );
+            let (typ, scale, vel) = if i % 4 == 0 {
+                (
+                    ObjectType::Ship,
+                    1.,
+                    vec3(rng.gen_range(-50. ..50.), 0., 0.),
+                )
+            } else {
+                (
+                    ObjectType::Asteroid,
+                    rng.gen_range(1. ..4.),
+                    vec3(0., 0., 0.),
+                )
+            };
+            node::to_remote_object(
+                &node::Node::default().pos(pos).u_scale(scale),
+                &vel,
+                &vec3(0., 0., 0.),
+                typ,
+                id.consume(),
+            )
+        })
+        .collect();
+    let cmd = ServerCommandType::Update(objects);
+    let plain = add_end_chunk(cmd.serialize(0x40).unwrap());
+    let compressed = add_end_chunk(cmd.serialize_with(0x40, true).unwrap());
+    assert!(
+        compressed.len() * 2 <= plain.len(),
+        "{} packets compressed, {} uncompressed",
+        compressed.len(),
+        plain.len()
+    );
+    assert!(compressed
+        .values()
+        .all(|chunk| chunk.len() <= MAX_DATAGRAM_SIZE));
+
+    let compressed = remove_end_chunk(compressed).unwrap();
+    let cmd2 = ServerCommandType::deserialize(compressed).unwrap();
+    assert_eq!((cmd, 0x40), cmd2);
+}
+
+#[test]
+fn compression_is_skipped_when_it_does_not_help() {
+    let cmd = ClientCommandType::GetIds(1024);
+    assert_eq!(
+        cmd.serialize_with(0x41, true).unwrap(),
+        cmd.serialize(0x41).unwrap()
+    );
+}
+
+#[test]
+fn net_stats_measure_rtt_loss_and_bandwidth() {
+    use std::time::{Duration, Instant};
+    let cct = ClientCommandType::Ping(0x1234);
+    let cct2 = ClientCommandType::deserialize(cct.serialize(0x50).unwrap());
+    assert_eq!((cct, 0x50), cct2.unwrap());
+    let sct = ServerCommandType::Pong(0x1234);
+    let sct2 = ServerCommandType::deserialize(sct.serialize(0x51).unwrap());
+    assert_eq!((sct, 0x51), sct2.unwrap());
+
+    let mut stats = NetStats::new();
+    let start = Instant::now();
+    let first = stats.ping_due(start).unwrap();
+    // pings are spaced out
+    assert!(stats.ping_due(start + Duration::from_millis(10)).is_none());
+    stats.on_pong(first, start + Duration::from_millis(80));
+    assert_eq!(stats.summary(start).rtt, Some(Duration::from_millis(80)));
+
+    // an unanswered ping is lost once it times out
+    let second = start + PING_INTERVAL;
+    stats.ping_due(second).unwrap();
+    let third = second + PING_TIMEOUT + Duration::from_millis(1);
+    let token = stats.ping_due(third).unwrap();
+    stats.on_pong(token, third + Duration::from_millis(40));
+    let summary = stats.summary(third);
+    assert!((summary.packet_loss - 1. / 3.).abs() < 1e-9);
+    assert_eq!(summary.rtt, Some(Duration::from_millis(75)));
+
+    stats.record_sent(1000, third);
+    stats.record_received(4000, third);
+    stats.record_received(4000, third - Duration::from_secs(10));
+    let summary = stats.summary(third);
+    assert!(summary.upload > 0. && summary.download == 4. * summary.upload);
+}
+
+#[test]
+fn network_conditioner_delays_and_drops_packets() {
+    use remote::conditioner::{NetworkConditioner, NetworkConditions};
+    use std::time::{Duration, Instant};
+    let echo = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let echo_addr = echo.local_addr().unwrap();
+    echo.set_read_timeout(Some(Duration::from_millis(500)))
+        .unwrap();
+    let echo_thread = std::thread::spawn(move || {
+        let mut buf = [0; 64];
+        while let Ok((amt, src)) = echo.recv_from(&mut buf) {
+            echo.send_to(&buf[..amt], src).unwrap();
+        }
+    });
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_millis(300)))
+        .unwrap();
+
+    let delayed = NetworkConditioner::spawn(
+        "127.0.0.1:0",
+        echo_addr,
+        NetworkConditions::default().latency(Duration::from_millis(40)),
+    )
+    .unwrap();
+    let sent = Instant::now();
+    client.send_to(b"ping", delayed.local_addr()).unwrap();
+    let mut buf = [0; 64];
+    let amt = client.recv(&mut buf).unwrap();
+    assert_eq!(&buf[..amt], b"ping");
+    // delayed on the way there and back
+    assert!(sent.elapsed() >= Duration::from_millis(80));
+    std::mem::drop(delayed);
+
+    let lossy = NetworkConditioner::spawn(
+        "127.0.0.1:0",
+        echo_addr,
+        NetworkConditions::default().loss(1.),
+    )
+    .unwrap();
+    client.send_to(b"ping", lossy.local_addr()).unwrap();
+    assert!(client.recv(&mut buf).is_err());
+    std::mem::drop(lossy);
+    echo_thread.join().unwrap();
+}
+
+#[test]
+fn sessions_reject_forged_and_replayed_datagrams() {
+    use session::*;
+    let (client, server) = (Handshake::new(), Handshake::new());
+    let (client_key, server_key) = (client.public_key(), server.public_key());
+    let cct = ClientCommandType::Handshake(client_key);
+    let cct2 = ClientCommandType::deserialize(cct.serialize(0x60).unwrap());
+    assert_eq!((cct, 0x60), cct2.unwrap());
+    let sct = ServerCommandType::Handshake(server_key);
+    let sct2 = ServerCommandType::deserialize(sct.serialize(0x61).unwrap());
+    assert_eq!((sct, 0x61), sct2.unwrap());
+    let client = client.finish(server_key, Role::Client);
+    let server = server.finish(client_key, Role::Server);
+    let seal = |cmd: &ClientCommandType, msg_id| {
+        add_end_chunk(cmd.serialize(msg_id).unwrap())
+            .values()
+            .map(|chunk| client.seal(chunk).unwrap())
+            .collect::<Vec<_>>()
+    };
+
+    let cmd = ClientCommandType::Login("Player".to_owned(), true);
+    let sealed = seal(&cmd, 7);
+    assert!(sealed.iter().all(|datagram| is_sealed(datagram)));
+    let opened: ChunkedMsg = sealed
+        .iter()
+        .map(|datagram| server.open(datagram).unwrap())
+        .map(|chunk| (chunk[PKT_NM_INDEX], chunk))
+        .collect();
+    assert_eq!(opened, add_end_chunk(cmd.serialize(7).unwrap()));
+    let (opened, msg_id) =
+        ClientCommandType::deserialize(remove_end_chunk(opened).unwrap())
+            .unwrap();
+    assert_eq!((opened, msg_id), (cmd, 7));
+    assert!(server.open(&sealed[0]).is_err());
+
+    // changing the data or the title fails authentication, without using
+    // up the packet
+    let sealed = seal(&ClientCommandType::Ping(1), 8);
+    let mut forged = sealed[0].clone();
+    *forged.last_mut().unwrap() ^= 1;
+    assert!(server.open(&forged).is_err());
+    let mut forged = sealed[0].clone();
+    forged[MSG_ID_INDEX + 3] = 9;
+    assert!(server.open(&forged).is_err());
+    // each side sends with its own key
+    assert!(client.open(&sealed[0]).is_err());
+    assert!(server.open(&sealed[0]).is_ok());
+
+    // messages far older than the newest one are dropped
+    server
+        .open(&seal(&ClientCommandType::Ping(2), 5000)[0])
+        .unwrap();
+    assert!(server
+        .open(&seal(&ClientCommandType::Ping(3), 10)[0])
+        .is_err());
+    let plain =
+        add_end_chunk(ClientCommandType::Ping(4).serialize(5001).unwrap());
+    assert!(server.open(&plain[&0]).is_err());
+}
+
+#[test]
+fn profiles_persist_and_gate_loadouts() {
+    use profile::*;
+    let loadout = |ship: &str| Loadout {
+        name: "main".to_owned(),
+        ship: ship.to_owned(),
+        gear: vec!["shield".to_owned()],
+    };
+    let mut profile = Profile::default();
+    assert_eq!(profile.unlocked_ships, vec![DEFAULT_SHIP.to_owned()]);
+    profile.set_loadouts(vec![loadout(DEFAULT_SHIP)]).unwrap();
+    assert!(profile.set_loadouts(vec![loadout("Locked")]).is_err());
+    assert_eq!(profile.loadouts, vec![loadout(DEFAULT_SHIP)]);
+    profile.record_match(7, true);
+    profile.record_match(2, false);
+    assert_eq!(
+        profile.stats,
+        CareerStats {
+            matches: 2,
+            wins: 1,
+            hits: 9
+        }
+    );
+
+    let saved = Profile::parse(&profile.to_string()).unwrap();
+    assert_eq!(saved, profile);
+    assert!(Profile::parse("wins = many").is_err());
+    assert!(Profile::parse("loadout = nameonly").is_err());
+    assert!(Profile::parse("\0\0garbage").is_err());
+
+    for cct in [
+        ClientCommandType::UpdateProfile(vec![loadout(DEFAULT_SHIP)]),
+        ClientCommandType::UpdateProfile(Vec::new()),
+    ] {
+        let cct2 = ClientCommandType::deserialize(cct.serialize(0x40).unwrap());
+        assert_eq!((cct, 0x40), cct2.unwrap());
+    }
+    let sct = ServerCommandType::Profile(profile);
+    let sct2 = ServerCommandType::deserialize(sct.serialize(0x41).unwrap());
+    assert_eq!((sct, 0x41), sct2.unwrap());
+}
+
+#[test]
+fn ownership_serialize_deserialize() {
+    let id = ObjectId::new(0xABCD);
+    for cct in [
+        ClientCommandType::RequestOwnership(id),
+        ClientCommandType::ReleaseOwnership(id, true),
+        ClientCommandType::ReleaseOwnership(id, false),
+        ClientCommandType::AckOwnership(id),
+    ] {
+        let cct2 = ClientCommandType::deserialize(cct.serialize(0x50).unwrap());
+        assert_eq!((cct, 0x50), cct2.unwrap());
+    }
+    for sct in [
+        ServerCommandType::HandoffRequest(id),
+        ServerCommandType::OwnershipGranted(id),
+        ServerCommandType::OwnershipDenied(id),
+    ] {
+        let sct2 = ServerCommandType::deserialize(sct.serialize(0x51).unwrap());
+        assert_eq!((sct, 0x51), sct2.unwrap());
+    }
+}
+
+#[test]
+fn codec_reports_errors_instead_of_panicking() {
+    use codec::*;
+    use rand::SeedableRng;
+    assert_eq!(
+        Reader::new(&[0, 1]).get::<u32>(),
+        Err(DecodeError::Truncated { needed: 4, left: 2 })
+    );
+    let mut reader = Reader::new(&[1, 2]);
+    assert_eq!(reader.get::<u8>(), Ok(1));
+    assert_eq!(reader.finish(), Err(DecodeError::TrailingBytes(1)));
+    assert_eq!(
+        Reader::new(&[2, 0]).get::<Option<u8>>(),
+        Err(DecodeError::Unknown {
+            what: "option",
+            value: 2
+        })
+    );
+    assert_eq!(
+        Reader::new(&[2, 0xFF, 0xFE]).get::<String>(),
+        Err(DecodeError::InvalidUtf8)
+    );
+    assert_eq!(
+        Reader::new(&[3, b'a']).get::<String>(),
+        Err(DecodeError::Truncated { needed: 3, left: 1 })
+    );
+
+    let long_name = "a".repeat(256);
+    assert_eq!(
+        Writer::new().put(&long_name).err(),
+        Some(EncodeError::TooLong { len: 256, max: 255 })
+    );
+    assert!(ClientCommandType::Login(long_name, false)
+        .serialize(0x70)
+        .is_err());
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    let objects = fuzz_objects(&mut rng, 2000);
+    assert!(ServerCommandType::Update(objects).serialize(0x71).is_err());
+}
+
+#[test]
+fn codec_rejects_malformed_durations() {
+    use codec::*;
+    use std::time::Duration;
+    let time = Duration::new(u64::MAX, 999_999_999);
+    let mut writer = Writer::new();
+    writer.put(&time).unwrap();
+    let data = writer.finish();
+    assert_eq!(Reader::new(&data).get::<Duration>(), Ok(time));
+    // the most seconds, and a big endian count of 1000000000 nanoseconds
+    let data = [[0xFF; 8].as_slice(), &[0x3B, 0x9A, 0xCA, 0x00]].concat();
+    assert_eq!(
+        Reader::new(&data).get::<Duration>(),
+        Err(DecodeError::Malformed(
+            "Duration with 1000000000 nanoseconds".to_owned()
+        ))
+    );
+}
+
+/// Creates `count` objects with random positions
+fn fuzz_objects(rng: &mut rand::rngs::StdRng, count: u32) -> Vec<RemoteObject> {
+    use rand::Rng;
+    (0..count)
+        .map(|i| RemoteObject {
+            mat: [[0.; 4]; 5].map(|row| row.map(|_| rng.gen_range(-1e4..1e4))),
+            id: ObjectId::new(i),
+            typ: ObjectType::try_from(rng.gen_range(0..8_u8)).unwrap(),
+        })
+        .collect()
+}
+
+fn fuzz_client_commands(
+    rng: &mut rand::rngs::StdRng,
+) -> Vec<ClientCommandType<'static>> {
+    let id = ObjectId::new(0x4321);
+    vec![
+        ClientCommandType::Login("fuzz".to_owned(), true),
+        ClientCommandType::Update(fuzz_objects(rng, 1)),
+        ClientCommandType::Update(fuzz_objects(rng, 40)),
+        ClientCommandType::GetIds(64),
+        ClientCommandType::ClaimHit(HitClaim {
+            shooter: id,
+            target: id.next(),
+            hit_point: [1., 2., 3.],
+        }),
+        ClientCommandType::ClaimTeleport(wormhole::TeleportClaim {
+            body: id,
+            wormhole: 0,
+            entry: 1,
+        }),
+        ClientCommandType::JoinLobby("lobby".to_owned()),
+        ClientCommandType::SetReady(true),
+        ClientCommandType::LeaveLobby,
+        ClientCommandType::Ping(9),
+        ClientCommandType::Handshake([7; session::PUBLIC_KEY_SIZE]),
+        ClientCommandType::UpdateProfile(vec![profile::Loadout {
+            name: "brawler".to_owned(),
+            ship: profile::DEFAULT_SHIP.to_owned(),
+            gear: vec!["hook".to_owned(), "shield".to_owned()],
+        }]),
+        ClientCommandType::ReleaseOwnership(id, false),
+    ]
+}
+
+fn fuzz_server_commands(
+    rng: &mut rand::rngs::StdRng,
+) -> Vec<ServerCommandType> {
+    let pid = ObjectId::new(0x1234);
+    let mut profile = profile::Profile::default();
+    profile.record_match(5, true);
+    vec![
+        ServerCommandType::ReturnLogin(LoginInfo {
+            pid,
+            starting_ids: (ObjectId::new(0x100), ObjectId::new(0x200)),
+            spawn_pos: [0., 1., -2.],
+            map_seed: Some(12),
+            compression: true,
+            lighting: game_map::GlobalLightingInfo {
+                dir_light: cgmath::vec3(1., -2., 3.),
+                hdr: "assets/hdr/hdr.hdr".to_owned(),
+                skybox: "assets/skybox.jpg".to_owned(),
+            },
+            profile,
+        }),
+        ServerCommandType::Update(fuzz_objects(rng, 40)),
+        ServerCommandType::ReturnIds((pid, pid.incr(10))),
+        ServerCommandType::LobbyState(lobby::LobbyInfo {
+            name: "lobby".to_owned(),
+            phase: lobby::LobbyPhase::Countdown(
+                std::time::Duration::from_secs(3),
+            ),
+            players: vec![lobby::LobbyPlayer {
+                pid,
+                name: "player".to_owned(),
+                ready: false,
+            }],
+        }),
+        ServerCommandType::MatchStart(lobby::MatchStart {
+            map_seed: None,
+            spawns: vec![(pid, [4., 5., 6.])],
+        }),
+        ServerCommandType::MatchEnd(lobby::MatchResults::new(vec![(pid, 2)])),
+        ServerCommandType::Pong(9),
+        ServerCommandType::OwnershipGranted(pid),
+    ]
+}
+
+/// Feeds `datagrams` through the reassembly of received packets, returning
+/// the commands they complete
+fn reassemble<T: Serializeable>(datagrams: &[Vec<u8>]) -> Vec<T> {
+    let src = SocketAddr::from(([127, 0, 0, 1], 4000));
+    let mut buffer = ClientBuffer::default();
+    datagrams
+        .iter()
+        .filter_map(|datagram| buffer_packet(&mut buffer, datagram, src).ok())
+        .flatten()
+        .map(|(cmd, _)| cmd)
+        .collect()
+}
+
+/// Mangles the datagrams of a message like a hostile network or peer might,
+/// by shuffling, cutting off, corrupting, dropping, duplicating or replacing
+/// them
+fn mangle(rng: &mut rand::rngs::StdRng, datagrams: &mut Vec<Vec<u8>>) {
+    use rand::seq::SliceRandom;
+    use rand::Rng;
+    datagrams.shuffle(rng);
+    let i = rng.gen_range(0..datagrams.len());
+    match rng.gen_range(0..5) {
+        0 => {
+            let len = rng.gen_range(0..=datagrams[i].len());
+            datagrams[i].truncate(len);
+        }
+        1 => {
+            for _ in 0..rng.gen_range(1..4) {
+                let byte = rng.gen_range(0..datagrams[i].len());
+                datagrams[i][byte] ^= rng.gen_range(1..=u8::MAX);
+            }
+        }
+        2 => {
+            datagrams.remove(i);
+        }
+        3 => datagrams.push(datagrams[i].clone()),
+        _ => {
+            // keeps the title, so the datagram reaches deserialization
+            let len = rng.gen_range(CHUNK_TITLE_SIZE..MAX_DATAGRAM_SIZE);
+            datagrams[i].resize(len, 0);
+            rng.fill(&mut datagrams[i][CHUNK_TITLE_SIZE..]);
+        }
+    }
+}
+
+/// Feeds shuffled and mangled datagrams through reassembly and
+/// deserialization, which must never panic. Shuffled datagrams that weren't
+/// mangled must be reassembled into the original command
+#[test]
+fn fuzzed_datagrams_never_panic() {
+    use rand::{Rng, SeedableRng};
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0xF022);
+    let clients = fuzz_client_commands(&mut rng);
+    let servers = fuzz_server_commands(&mut rng);
+    for round in 0..2000 {
+        let compress = rng.gen_bool(0.5);
+        let (cmd_idx, from_client) = if round % 2 == 0 {
+            (rng.gen_range(0..clients.len()), true)
+        } else {
+            (rng.gen_range(0..servers.len()), false)
+        };
+        let chunks = if from_client {
+            clients[cmd_idx].serialize_with(round, compress)
+        } else {
+            servers[cmd_idx].serialize_with(round, compress)
+        };
+        let mut datagrams: Vec<_> =
+            add_end_chunk(chunks.unwrap()).into_values().collect();
+        let mangled = round % 3 != 0;
+        if mangled {
+            mangle(&mut rng, &mut datagrams);
+        } else {
+            use rand::seq::SliceRandom;
+            datagrams.shuffle(&mut rng);
+        }
+
+        let received_clients = reassemble::<ClientCommandType>(&datagrams);
+        let received_servers = reassemble::<ServerCommandType>(&datagrams);
+        if !mangled && from_client {
+            assert_eq!(received_clients, [clients[cmd_idx].clone()]);
+        } else if !mangled {
+            assert_eq!(received_servers, [servers[cmd_idx].clone()]);
+        }
+    }
+
+    for _ in 0..2000 {
+        let len = rng.gen_range(0..MAX_DATAGRAM_SIZE);
+        let mut datagram = vec![0; len];
+        rng.fill(&mut datagram[..]);
+        if rng.gen_bool(0.5) && len > 0 {
+            datagram[0] = CHUNK_HEADER[0];
+        }
+        reassemble::<ClientCommandType>(&[datagram.clone()]);
+        reassemble::<ServerCommandType>(&[datagram]);
+    }
+}
+
+#[test]
+fn client_buffer_expires_messages_and_counts_packets() {
+    use rand::SeedableRng;
+    use std::time::{Duration, Instant};
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0xB0F);
+    let cmd = ServerCommandType::Update(fuzz_objects(&mut rng, 40));
+    let datagrams = |msg_id| -> Vec<_> {
+        add_end_chunk(cmd.serialize(msg_id).unwrap())
+            .into_values()
+            .collect()
+    };
+    let src = SocketAddr::from(([127, 0, 0, 1], 4000));
+    let mut buffer = ClientBuffer::<ServerCommandType>::new();
+
+    // every packet after the last one arrives out of order
+    let reversed: Vec<_> = datagrams(1).into_iter().rev().collect();
+    assert!(reversed.len() > 2);
+    let received: Vec<_> = reversed
+        .iter()
+        .filter_map(|datagram| buffer_packet(&mut buffer, datagram, src).ok())
+        .flatten()
+        .collect();
+    assert_eq!(received.len(), 1);
+    assert_eq!(buffer.stats().completed, 1);
+    assert_eq!(buffer.stats().out_of_order, reversed.len() as u64 - 1);
+
+    let first = &datagrams(2)[0];
+    assert!(buffer_packet(&mut buffer, first, src).unwrap().is_none());
+    assert!(buffer_packet(&mut buffer, first, src).is_err());
+    assert_eq!(buffer.stats().rejected, 1);
+    assert_eq!(buffer.pending(), 0);
+
+    assert!(buffer_packet(&mut buffer, first, src).unwrap().is_none());
+    buffer.expire(Instant::now());
+    assert_eq!(buffer.pending(), 1);
+    buffer
+        .expire(Instant::now() + DEFAULT_MESSAGE_TTL + Duration::from_secs(1));
+    assert_eq!(buffer.pending(), 0);
+    assert_eq!(
+        buffer.stats(),
+        BufferStats {
+            completed: 1,
+            expired: 1,
+            rejected: 1,
+            out_of_order: reversed.len() as u64 - 1,
+        }
+    );
+}
+
+/// Every message cut off before its end fails to deserialize. Updates are
+/// left out, since an update cut off between objects is a smaller update
+#[test]
+fn truncated_messages_fail_to_deserialize() {
+    use rand::SeedableRng;
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0x7C);
+    let chunked: Vec<_> = fuzz_client_commands(&mut rng)
+        .iter()
+        .filter(|cmd| !matches!(cmd, ClientCommandType::Update(_)))
+        .map(|cmd| (cmd.serialize(1).unwrap(), true))
+        .chain(
+            fuzz_server_commands(&mut rng)
+                .iter()
+                .filter(|cmd| !matches!(cmd, ServerCommandType::Update(_)))
+                .map(|cmd| (cmd.serialize(1).unwrap(), false)),
+        )
+        .collect();
+    for (chunks, from_client) in chunked {
+        assert_eq!(chunks.len(), 1);
+        let chunk = &chunks[&0];
+        let data = &chunk[CHUNK_TITLE_SIZE..chunk.len() - CHUNK_FOOTER_SIZE];
+        for len in 0..data.len() {
+            let truncated: Vec<_> = chunk[..CHUNK_TITLE_SIZE]
+                .iter()
+                .chain(&data[..len])
+                .chain(&CHUNK_FOOTER)
+                .copied()
+                .collect();
+            let truncated = BTreeMap::from([(0, truncated)]);
+            if from_client {
+                assert!(ClientCommandType::deserialize(truncated).is_err());
+            } else {
+                assert!(ServerCommandType::deserialize(truncated).is_err());
+            }
+        }
+    }
+}
+
+#[test]
+fn save_round_trip_and_version_check() {
+    use cgmath::*;
+    use save::*;
+    let body = |id, typ| {
+        node::to_remote_object(
+            &node::Node::default().pos(point3(1., -2., 300.)),
+            &vec3(0.5, 0., -4.),
+            &vec3(0., 1., 0.),
+            typ,
+            ObjectId::new(id),
+        )
+    };
+    let save = SaveGame {
+        map_seed: Some(0xdead_beef),
+        // more bodies than fit in the one byte prefix of a message list
+        bodies: (0..300).map(|id| body(id, ObjectType::Asteroid)).collect(),
+        ships: vec![ShipState {
+            body: body(400, ObjectType::Ship),
+            shield: 20.,
+            hull: 55.5,
+            energy: 80.,
+            benched: false,
+        }],
+        lives: 2,
+        score: ScoreState {
+            points: 1250,
+            shots_fired: 40,
+            shots_hit: 12,
+            time_alive: std::time::Duration::from_millis(65_432),
+            ..ScoreState::default()
+        },
+        mission: MissionState {
+            current: 1,
+            asteroids_destroyed: 3,
+            elapsed: std::time::Duration::from_secs(12),
+            destroyed: vec![ObjectId::new(7), ObjectId::new(9)],
+            completed: false,
+            failed: false,
+        },
+    };
+    let bytes = save.to_bytes().unwrap();
+    assert_eq!(SaveGame::from_bytes(&bytes).unwrap(), save);
+
+    let mut newer = bytes.clone();
+    newer[7] += 1;
+    assert!(SaveGame::from_bytes(&newer).is_err());
+    assert!(SaveGame::from_bytes(&bytes[1..]).is_err());
+    assert!(SaveGame::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+}
@@ -0,0 +1,103 @@
+use super::ObjectId;
+use cgmath::*;
+use std::time::Duration;
+
+/// Time after a body goes through a wormhole before it can go through one
+/// again
+pub const TELEPORT_COOLDOWN: Duration = Duration::from_millis(1500);
+/// Distance past the edge of the exit portal's sensor that bodies are
+/// placed at, so they don't immediately enter it
+const EXIT_CLEARANCE: f64 = 5.;
+
+/// One end of a wormhole
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Portal {
+    pub pos: [f64; 3],
+    /// Direction the portal faces. Bodies enter the portal moving against
+    /// its facing and leave the partner portal moving along its facing
+    pub normal: [f64; 3],
+}
+
+impl Portal {
+    #[must_use]
+    pub fn new(pos: Point3<f64>, normal: Vector3<f64>) -> Self {
+        Self {
+            pos: pos.into(),
+            normal: normal.normalize().into(),
+        }
+    }
+
+    fn normal(&self) -> Vector3<f64> {
+        Vector3::from(self.normal).normalize()
+    }
+}
+
+/// A pair of portals, where bodies that enter one portal leave the other
+#[derive(Clone, Debug, PartialEq)]
+pub struct Wormhole {
+    pub ends: [Portal; 2],
+    /// Radius of the sphere around each portal that bodies enter it in
+    pub radius: f64,
+}
+
+/// Where a body that went through a wormhole ends up
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Traversal {
+    pub pos: Point3<f64>,
+    pub velocity: Vector3<f64>,
+    /// Rotation applied to the body's orientation, in world space
+    pub rotation: Quaternion<f64>,
+}
+
+impl Wormhole {
+    /// Gets the index of the portal a body at `pos` moving at `velocity` is
+    /// entering, or `None` if it isn't entering either portal
+    #[must_use]
+    pub fn entered(
+        &self,
+        pos: Point3<f64>,
+        velocity: Vector3<f64>,
+    ) -> Option<usize> {
+        self.ends.iter().position(|end| {
+            pos.distance(Point3::from(end.pos)) <= self.radius
+                && velocity.dot(end.normal()) < 0.
+        })
+    }
+
+    /// Moves a body at `pos` moving at `velocity` that entered the portal
+    /// `entry` to the other portal
+    ///
+    /// The body keeps its offset from the center of the portal across the
+    /// portal's face, and its velocity and orientation are turned by the
+    /// rotation between the portals, so it leaves the other portal moving
+    /// the way it entered relative to the portal it entered
+    #[must_use]
+    pub fn traverse(
+        &self,
+        entry: usize,
+        pos: Point3<f64>,
+        velocity: Vector3<f64>,
+    ) -> Traversal {
+        let (from, to) = (&self.ends[entry], &self.ends[1 - entry]);
+        let normal = to.normal();
+        let rotation = Quaternion::from_arc(-from.normal(), normal, None);
+        let offset = rotation.rotate_vector(pos - Point3::from(from.pos));
+        let across = offset - normal * offset.dot(normal);
+        Traversal {
+            pos: Point3::from(to.pos)
+                + across
+                + normal * (self.radius + EXIT_CLEARANCE),
+            velocity: rotation.rotate_vector(velocity),
+            rotation,
+        }
+    }
+}
+
+/// A claim that the body with the id `body` entered the portal `entry` of
+/// the map's wormhole at index `wormhole`
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct TeleportClaim {
+    pub body: ObjectId,
+    pub wormhole: u8,
+    pub entry: u8,
+}